@@ -0,0 +1,44 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::cmp::Ordering;
+
+/// Orders the keys of a [`BTree`](crate::BTree).
+///
+/// The name of the comparator is stored in the tree, and reopening a tree with a comparator of
+/// another name fails, since its keys would no longer be found in the order they were stored.
+/// Only the first 32 bytes of the name are stored.
+pub trait KeyComparator: std::fmt::Debug + Send + Sync + 'static {
+    /// Name identifying the order, stored in the tree.
+    fn name(&self) -> &str;
+
+    /// Compare two keys.
+    fn compare(&self, left: &[u8], right: &[u8]) -> Ordering;
+}
+
+/// Orders keys lexicographically by their bytes.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BytewiseComparator;
+
+impl KeyComparator for BytewiseComparator {
+    fn name(&self) -> &'static str {
+        "minql.bytewise"
+    }
+
+    fn compare(&self, left: &[u8], right: &[u8]) -> Ordering {
+        left.cmp(right)
+    }
+}
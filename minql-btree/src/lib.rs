@@ -0,0 +1,71 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! B+Tree Index
+//!
+//! A disk backed [`BTree`] mapping byte string keys to values in the order of a configurable
+//! [`KeyComparator`], with range scans over linked leaves. Pages are cached in a
+//! [`BufferPool`] and every change is logged to a [`WriteAheadLog`] first, so splits and merges
+//! survive a crash.
+//!
+//! ```rust
+//! use minql_btree::{BTree, BTreeOptions};
+//! use minql_vfs::{FileSystem, MemoryFileSystem, PagedFile, WalOptions, WriteAheadLog};
+//! use std::ops::Bound;
+//!
+//! let fs = MemoryFileSystem::new();
+//! let file = PagedFile::new(fs.create_file("/index.db").unwrap(), 4096).unwrap();
+//! let wal = WriteAheadLog::open(fs.clone(), "/index.wal", WalOptions::new()).unwrap();
+//! let mut tree = BTree::open(file, wal, BTreeOptions::new()).unwrap();
+//!
+//! tree.insert(b"apple", b"red").unwrap();
+//! tree.insert(b"banana", b"yellow").unwrap();
+//! tree.insert(b"cherry", b"red").unwrap();
+//! assert_eq!(tree.get(b"banana").unwrap(), Some(b"yellow".to_vec()));
+//!
+//! let keys = tree
+//!     .range(Bound::Excluded(b"apple"), Bound::Unbounded)
+//!     .map(|entry| entry.unwrap().0)
+//!     .collect::<Vec<_>>();
+//! assert_eq!(keys, vec![b"banana".to_vec(), b"cherry".to_vec()]);
+//! ```
+//!
+//! [`BufferPool`]: minql_vfs::BufferPool
+//! [`WriteAheadLog`]: minql_vfs::WriteAheadLog
+
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod comparator;
+mod node;
+mod result;
+mod tree;
+
+pub use self::comparator::{BytewiseComparator, KeyComparator};
+pub use self::result::{BTreeError, BTreeResult};
+pub use self::tree::{BTree, BTreeOptions, BTreeRange};
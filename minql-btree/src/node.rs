@@ -0,0 +1,443 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::KeyComparator;
+use minql_vfs::{Lsn, PageId};
+use std::cmp::Ordering;
+
+/// Magic bytes at the start of the meta page of every tree.
+const META_MAGIC: &[u8; 8] = b"MQLBTREE";
+
+/// Version of the tree file format.
+const META_VERSION: u32 = 1;
+
+/// Longest comparator name stored in the meta page.
+pub(crate) const MAX_COMPARATOR_NAME: usize = 32;
+
+/// Bytes of the kind, entry count and link leading every node.
+pub(crate) const NODE_HEADER: usize = 11;
+
+const KIND_LEAF: u8 = 1;
+const KIND_INTERNAL: u8 = 2;
+const KIND_FREE: u8 = 3;
+
+/// Contents of the meta page, the first page of every tree.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Meta {
+    /// Name of the comparator ordering the keys
+    pub comparator: String,
+    /// Page of the root node
+    pub root: PageId,
+    /// First page of the free list, or zero if it's empty
+    pub free: PageId,
+    /// Number of entries in the tree
+    pub count: u64,
+    /// Position in the log replay starts from
+    pub checkpoint: Lsn,
+}
+
+impl Meta {
+    pub fn encode(&self, data: &mut [u8]) {
+        let name = comparator_name(&self.comparator);
+        data.fill(0);
+        data[..8].copy_from_slice(META_MAGIC);
+        data[8..12].copy_from_slice(&META_VERSION.to_le_bytes());
+        data[12] = u8::try_from(name.len()).expect("Comparator Name");
+        data[13..13 + name.len()].copy_from_slice(name.as_bytes());
+        data[48..56].copy_from_slice(&self.root.to_le_bytes());
+        data[56..64].copy_from_slice(&self.free.to_le_bytes());
+        data[64..72].copy_from_slice(&self.count.to_le_bytes());
+        data[72..80].copy_from_slice(&self.checkpoint.segment.to_le_bytes());
+        data[80..88].copy_from_slice(&self.checkpoint.offset.to_le_bytes());
+    }
+
+    /// Decode a meta page, or `None` if the page doesn't hold one.
+    pub fn decode(data: &[u8]) -> Option<Meta> {
+        if data.get(..8)? != META_MAGIC || read_u32(data, 8)? != META_VERSION {
+            return None;
+        }
+        let len = usize::from(*data.get(12)?);
+        let comparator = std::str::from_utf8(data.get(13..13 + len)?).ok()?;
+        Some(Meta {
+            comparator: comparator.to_string(),
+            root: read_u64(data, 48)?,
+            free: read_u64(data, 56)?,
+            count: read_u64(data, 64)?,
+            checkpoint: Lsn {
+                segment: read_u64(data, 72)?,
+                offset: read_u64(data, 80)?,
+            },
+        })
+    }
+}
+
+/// A decoded tree page.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Node {
+    /// Sorted entries, linked to the next leaf in key order, or zero for the last leaf.
+    Leaf {
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+        next: PageId,
+    },
+    /// Separator keys between children, where `children[i]` holds the keys at least
+    /// `keys[i - 1]` and below `keys[i]`.
+    Internal {
+        keys: Vec<Vec<u8>>,
+        children: Vec<PageId>,
+    },
+    /// A page on the free list, linked to the next free page or zero.
+    Free { next: PageId },
+}
+
+/// Outcome of rebalancing an underfull node with its sibling.
+pub(crate) enum Rebalanced {
+    /// Both nodes fit in one, the left.
+    Merged(Node),
+    /// Entries were shared evenly, with a new separator between the nodes.
+    Shared(Node, Vec<u8>, Node),
+}
+
+impl Node {
+    /// Bytes the node takes when encoded.
+    pub fn size(&self) -> usize {
+        NODE_HEADER
+            + match self {
+                Node::Leaf { entries, .. } => entries
+                    .iter()
+                    .map(|(key, value)| leaf_entry_size(key, value))
+                    .sum(),
+                Node::Internal { keys, .. } => {
+                    keys.iter().map(|key| internal_entry_size(key)).sum()
+                }
+                Node::Free { .. } => 0,
+            }
+    }
+
+    pub fn encode(&self, data: &mut [u8]) {
+        data.fill(0);
+        let (kind, count, link) = match self {
+            Node::Leaf { entries, next } => (KIND_LEAF, entries.len(), *next),
+            Node::Internal { keys, children } => (KIND_INTERNAL, keys.len(), children[0]),
+            Node::Free { next } => (KIND_FREE, 0, *next),
+        };
+        data[0] = kind;
+        data[1..3].copy_from_slice(&to_u16(count).to_le_bytes());
+        data[3..11].copy_from_slice(&link.to_le_bytes());
+        let mut offset = NODE_HEADER;
+        let mut put = |bytes: &[u8]| {
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+            offset += bytes.len();
+        };
+        match self {
+            Node::Leaf { entries, .. } => {
+                for (key, value) in entries {
+                    put(&to_u16(key.len()).to_le_bytes());
+                    put(&to_u16(value.len()).to_le_bytes());
+                    put(key);
+                    put(value);
+                }
+            }
+            Node::Internal { keys, children } => {
+                for (key, child) in keys.iter().zip(&children[1..]) {
+                    put(&to_u16(key.len()).to_le_bytes());
+                    put(key);
+                    put(&child.to_le_bytes());
+                }
+            }
+            Node::Free { .. } => {}
+        }
+    }
+
+    /// Decode a node, or `None` if the page doesn't hold one.
+    pub fn decode(data: &[u8]) -> Option<Node> {
+        let count = usize::from(read_u16(data, 1)?);
+        let link = read_u64(data, 3)?;
+        let mut offset = NODE_HEADER;
+        let mut take = |len: usize| {
+            let bytes = data.get(offset..offset + len)?;
+            offset += len;
+            Some(bytes)
+        };
+        match *data.first()? {
+            KIND_LEAF => {
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let key_len = usize::from(u16::from_le_bytes(take(2)?.try_into().ok()?));
+                    let value_len = usize::from(u16::from_le_bytes(take(2)?.try_into().ok()?));
+                    entries.push((take(key_len)?.to_vec(), take(value_len)?.to_vec()));
+                }
+                Some(Node::Leaf {
+                    entries,
+                    next: link,
+                })
+            }
+            KIND_INTERNAL => {
+                let mut keys = Vec::with_capacity(count);
+                let mut children = Vec::with_capacity(count + 1);
+                children.push(link);
+                for _ in 0..count {
+                    let key_len = usize::from(u16::from_le_bytes(take(2)?.try_into().ok()?));
+                    keys.push(take(key_len)?.to_vec());
+                    children.push(u64::from_le_bytes(take(8)?.try_into().ok()?));
+                }
+                Some(Node::Internal { keys, children })
+            }
+            KIND_FREE => Some(Node::Free { next: link }),
+            _ => None,
+        }
+    }
+
+    /// Split an overfull node in two by size, returning the left half, the separator, and the
+    /// right half. A left leaf is linked to the right leaf at `right_id`.
+    pub fn split(self, right_id: PageId) -> (Node, Vec<u8>, Node) {
+        match self {
+            Node::Leaf { mut entries, next } => {
+                let sizes = entries
+                    .iter()
+                    .map(|(key, value)| leaf_entry_size(key, value));
+                let middle = halfway(&sizes.collect::<Vec<_>>(), 1);
+                let right = entries.split_off(middle);
+                let separator = right[0].0.clone();
+                (
+                    Node::Leaf {
+                        entries,
+                        next: right_id,
+                    },
+                    separator,
+                    Node::Leaf {
+                        entries: right,
+                        next,
+                    },
+                )
+            }
+            Node::Internal {
+                mut keys,
+                mut children,
+            } => {
+                let sizes = keys.iter().map(|key| internal_entry_size(key));
+                let middle = halfway(&sizes.collect::<Vec<_>>(), 2);
+                let right_keys = keys.split_off(middle + 1);
+                let separator = keys.pop().expect("Separator Key");
+                let right_children = children.split_off(middle + 1);
+                (
+                    Node::Internal { keys, children },
+                    separator,
+                    Node::Internal {
+                        keys: right_keys,
+                        children: right_children,
+                    },
+                )
+            }
+            Node::Free { .. } => unreachable!("free pages are never split"),
+        }
+    }
+
+    /// Merge an underfull node with its right sibling at `right_id`, separated by `separator` in
+    /// their parent, if both fit in `capacity` bytes, or otherwise share their entries evenly.
+    pub fn rebalance(
+        left: Node,
+        separator: Vec<u8>,
+        right: Node,
+        right_id: PageId,
+        capacity: usize,
+    ) -> Rebalanced {
+        let combined = match (left, right) {
+            (
+                Node::Leaf {
+                    entries: mut left, ..
+                },
+                Node::Leaf {
+                    entries: right,
+                    next,
+                },
+            ) => {
+                left.extend(right);
+                Node::Leaf {
+                    entries: left,
+                    next,
+                }
+            }
+            (
+                Node::Internal {
+                    keys: mut left_keys,
+                    children: mut left_children,
+                },
+                Node::Internal { keys, children },
+            ) => {
+                left_keys.push(separator);
+                left_keys.extend(keys);
+                left_children.extend(children);
+                Node::Internal {
+                    keys: left_keys,
+                    children: left_children,
+                }
+            }
+            _ => unreachable!("siblings are always the same kind"),
+        };
+        if combined.size() <= capacity {
+            return Rebalanced::Merged(combined);
+        }
+        let (left, separator, right) = combined.split(right_id);
+        Rebalanced::Shared(left, separator, right)
+    }
+}
+
+/// Index of the child of an internal node to search for `key`.
+pub(crate) fn child_index(comparator: &dyn KeyComparator, keys: &[Vec<u8>], key: &[u8]) -> usize {
+    keys.partition_point(|separator| comparator.compare(separator, key) != Ordering::Greater)
+}
+
+/// Bytes an entry takes in a leaf.
+pub(crate) fn leaf_entry_size(key: &[u8], value: &[u8]) -> usize {
+    4 + key.len() + value.len()
+}
+
+/// Bytes a separator key and its child take in an internal node.
+fn internal_entry_size(key: &[u8]) -> usize {
+    10 + key.len()
+}
+
+/// Truncate a comparator name to what the meta page stores.
+pub(crate) fn comparator_name(name: &str) -> &str {
+    let mut end = name.len().min(MAX_COMPARATOR_NAME);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+/// Index splitting entries of `sizes` in half by size, leaving at least one entry on the left
+/// and `right` entries from the index onwards.
+fn halfway(sizes: &[usize], right: usize) -> usize {
+    let total: usize = sizes.iter().sum();
+    let mut left = 0;
+    let mut index = 0;
+    while index < sizes.len() && left + sizes[index] <= total / 2 {
+        left += sizes[index];
+        index += 1;
+    }
+    index.clamp(1, sizes.len().saturating_sub(right).max(1))
+}
+
+fn to_u16(value: usize) -> u16 {
+    u16::try_from(value).expect("Node Field")
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Meta, Node, Rebalanced};
+    use minql_vfs::Lsn;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_node_encoding() {
+        let mut data = vec![0; 128];
+        let meta = Meta {
+            comparator: "minql.bytewise".to_string(),
+            root: 3,
+            free: 7,
+            count: 42,
+            checkpoint: Lsn {
+                segment: 1,
+                offset: 512,
+            },
+        };
+        meta.encode(&mut data);
+        assert_eq!(Meta::decode(&data), Some(meta));
+        assert_eq!(Node::decode(&data), None);
+
+        let entries = (0..6_u8)
+            .map(|index| (vec![index; 4], vec![index; 8]))
+            .collect::<Vec<_>>();
+        let leaf = Node::Leaf {
+            entries: entries.clone(),
+            next: 9,
+        };
+        assert_eq!(leaf.size(), 11 + 6 * 16);
+        leaf.encode(&mut data);
+        assert_eq!(Node::decode(&data), Some(leaf.clone()));
+
+        // Splitting halves the entries, linking the left leaf to the right
+        let (left, separator, right) = leaf.split(5);
+        assert_eq!(separator, vec![3; 4]);
+        assert_eq!(
+            left,
+            Node::Leaf {
+                entries: entries[..3].to_vec(),
+                next: 5,
+            }
+        );
+        assert_eq!(
+            right,
+            Node::Leaf {
+                entries: entries[3..].to_vec(),
+                next: 9,
+            }
+        );
+        let Rebalanced::Merged(merged) = Node::rebalance(left, separator, right, 5, 128) else {
+            panic!("leaves should merge");
+        };
+        assert_eq!(
+            merged,
+            Node::Leaf {
+                entries: entries.clone(),
+                next: 9,
+            }
+        );
+
+        // Splitting an internal node moves its middle key up
+        let internal = Node::Internal {
+            keys: (1..6_u8).map(|index| vec![index; 4]).collect(),
+            children: (10..16).collect(),
+        };
+        internal.encode(&mut data);
+        assert_eq!(Node::decode(&data), Some(internal.clone()));
+        let (left, separator, right) = internal.split(0);
+        assert_eq!(separator, vec![3; 4]);
+        assert_eq!(
+            left,
+            Node::Internal {
+                keys: vec![vec![1; 4], vec![2; 4]],
+                children: vec![10, 11, 12],
+            }
+        );
+        assert_eq!(
+            right,
+            Node::Internal {
+                keys: vec![vec![4; 4], vec![5; 4]],
+                children: vec![13, 14, 15],
+            }
+        );
+    }
+}
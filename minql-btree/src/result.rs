@@ -0,0 +1,70 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use minql_vfs::{FileSystemError, Lsn, PageId};
+
+/// Result Type for B+Trees
+pub type BTreeResult<T> = Result<T, BTreeError>;
+
+/// Error Type for B+Trees
+#[derive(Debug)]
+pub enum BTreeError {
+    /// Key and value are too large to store in a node
+    EntryTooLarge {
+        /// Combined size of the key and value in bytes
+        size: usize,
+        /// Largest combined size a node holds
+        max: usize,
+    },
+    /// Page size is too small for a node, or too large for its offsets
+    InvalidPageSize(usize),
+    /// File isn't a B+tree
+    InvalidHeader,
+    /// Tree was created with a different key order
+    ComparatorMismatch {
+        /// Name of the comparator stored in the tree
+        stored: String,
+        /// Name of the comparator it was opened with
+        given: String,
+    },
+    /// Page contents are inconsistent
+    CorruptPage(PageId),
+    /// Log record can't be replayed
+    CorruptLog(Lsn),
+    /// Error of the underlying `FileSystem`
+    FileSystem(FileSystemError),
+}
+
+impl std::fmt::Display for BTreeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for BTreeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BTreeError::FileSystem(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<FileSystemError> for BTreeError {
+    fn from(err: FileSystemError) -> Self {
+        BTreeError::FileSystem(err)
+    }
+}
@@ -0,0 +1,779 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::node::{child_index, comparator_name, Meta, Node, Rebalanced, NODE_HEADER};
+use crate::{BTreeError, BTreeResult, BytewiseComparator, KeyComparator};
+use minql_vfs::{
+    BufferPool, FileHandle, FileSystem, FileSystemError, LruPolicy, Lsn, Page, PageId, PagedFile,
+    WriteAheadLog,
+};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Smallest page payload holding a node with a useful fanout.
+const MIN_PAYLOAD: usize = 128;
+
+/// Deepest a tree may be before its pages are taken to form a cycle.
+const MAX_DEPTH: usize = 64;
+
+/// Configuration of a [`BTree`].
+#[derive(Clone, Debug)]
+pub struct BTreeOptions {
+    cache_size: usize,
+    comparator: Arc<dyn KeyComparator>,
+}
+
+impl BTreeOptions {
+    /// Default bytes of pages cached in memory.
+    pub const DEFAULT_CACHE_SIZE: usize = 4 * 1024 * 1024;
+
+    /// Create the default options, caching 4 MiB of pages and ordering keys by their bytes.
+    #[must_use]
+    pub fn new() -> BTreeOptions {
+        BTreeOptions {
+            cache_size: Self::DEFAULT_CACHE_SIZE,
+            comparator: Arc::new(BytewiseComparator),
+        }
+    }
+
+    /// Set the bytes of pages cached in the buffer pool.
+    #[must_use]
+    pub fn with_cache_size(mut self, cache_size: usize) -> BTreeOptions {
+        self.cache_size = cache_size;
+        self
+    }
+
+    /// Set the comparator ordering keys.
+    #[must_use]
+    pub fn with_comparator<C: KeyComparator>(mut self, comparator: C) -> BTreeOptions {
+        self.comparator = Arc::new(comparator);
+        self
+    }
+
+    /// Bytes of pages cached in the buffer pool.
+    #[must_use]
+    pub fn cache_size(&self) -> usize {
+        self.cache_size
+    }
+
+    /// Comparator ordering keys.
+    #[must_use]
+    pub fn comparator(&self) -> &dyn KeyComparator {
+        &*self.comparator
+    }
+}
+
+impl Default for BTreeOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// B+Tree
+///
+/// An ordered map of byte string keys to byte string values stored in the pages of a
+/// [`PagedFile`], cached by a [`BufferPool`]. Values live in the leaves, which are linked in key
+/// order for range scans, while internal nodes hold separator keys. Keys are ordered by the
+/// [`KeyComparator`] of the [`BTreeOptions`], whose name is stored in the tree.
+///
+/// Every change, including the splits and merges it causes, is written to the
+/// [`WriteAheadLog`] as the full images of the pages it touches and synced before any page is
+/// changed in the pool. Opening a tree replays the log from the last checkpoint, so a crash
+/// never leaves a split or merge half done. [`BTree::checkpoint`] writes every cached page
+/// back to the file and trims the log.
+///
+/// The first page of the file holds the root, the free list of pages released by merges, and
+/// the position of the last checkpoint.
+#[derive(Debug)]
+pub struct BTree<H: FileHandle, F: FileSystem> {
+    pool: BufferPool<H>,
+    wal: WriteAheadLog<F>,
+    comparator: Arc<dyn KeyComparator>,
+    meta: Meta,
+    payload: usize,
+}
+
+impl<H: FileHandle, F: FileSystem> BTree<H, F> {
+    /// Open the tree stored in `file`, creating it if the file is empty, after replaying any
+    /// changes in `wal` not yet written to the file.
+    pub fn open(
+        mut file: PagedFile<H>,
+        wal: WriteAheadLog<F>,
+        options: BTreeOptions,
+    ) -> BTreeResult<BTree<H, F>> {
+        let payload = file.payload_size();
+        if !(MIN_PAYLOAD..=usize::from(u16::MAX)).contains(&payload) {
+            return Err(BTreeError::InvalidPageSize(file.page_size()));
+        }
+        recover(&mut file, &wal)?;
+        // A zeroed meta page is left by a crash before the tree was first created.
+        let fresh = if file.page_count()? == 0 {
+            file.extend(1)?;
+            true
+        } else {
+            file.read_page(0)?.data().iter().all(|byte| *byte == 0)
+        };
+
+        let given = comparator_name(options.comparator.name()).to_string();
+        let mut tree = BTree {
+            pool: BufferPool::new(file, options.cache_size, LruPolicy::new()),
+            wal,
+            comparator: options.comparator,
+            meta: Meta {
+                comparator: given.clone(),
+                root: 0,
+                free: 0,
+                count: 0,
+                checkpoint: Lsn::default(),
+            },
+            payload,
+        };
+        if fresh {
+            let mut batch = tree.batch();
+            let root = batch.allocate()?;
+            batch.put(
+                root,
+                Node::Leaf {
+                    entries: Vec::new(),
+                    next: 0,
+                },
+            );
+            batch.meta.root = root;
+            let Batch { nodes, meta, .. } = batch;
+            tree.commit(nodes, meta)?;
+        } else {
+            let meta =
+                Meta::decode(tree.pool.pin(0)?.read().data()).ok_or(BTreeError::InvalidHeader)?;
+            if meta.comparator != given {
+                return Err(BTreeError::ComparatorMismatch {
+                    stored: meta.comparator,
+                    given,
+                });
+            }
+            tree.meta = meta;
+        }
+        Ok(tree)
+    }
+
+    /// Number of entries in the tree.
+    #[must_use]
+    pub fn len(&self) -> u64 {
+        self.meta.count
+    }
+
+    /// Check if the tree holds no entries.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.meta.count == 0
+    }
+
+    /// Largest combined size of a key and value.
+    #[must_use]
+    pub fn max_entry_size(&self) -> usize {
+        (self.payload - NODE_HEADER) / 4 - 4
+    }
+
+    /// Get the value of a key.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn get(&self, key: &[u8]) -> BTreeResult<Option<Vec<u8>>> {
+        let batch = self.batch();
+        let (_, _, mut entries, _) = self.descend(&batch, Some(key))?;
+        Ok(self
+            .search(&entries, key)
+            .ok()
+            .map(|index| entries.swap_remove(index).1))
+    }
+
+    /// Insert or replace the value of a key, returning the value it replaced.
+    #[tracing::instrument(level = "trace", skip(self, value))]
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> BTreeResult<Option<Vec<u8>>> {
+        let size = key.len() + value.len();
+        let max = self.max_entry_size();
+        if size > max {
+            return Err(BTreeError::EntryTooLarge { size, max });
+        }
+        let mut batch = self.batch();
+        let (mut path, mut id, mut entries, next) = self.descend(&batch, Some(key))?;
+        let previous = match self.search(&entries, key) {
+            Ok(index) => Some(std::mem::replace(&mut entries[index].1, value.to_vec())),
+            Err(index) => {
+                entries.insert(index, (key.to_vec(), value.to_vec()));
+                batch.meta.count += 1;
+                None
+            }
+        };
+
+        // Split overfull nodes up the path, growing a new root if the old one splits.
+        let mut node = Node::Leaf { entries, next };
+        while node.size() > self.payload {
+            let right_id = batch.allocate()?;
+            tracing::trace!(page = id, right = right_id, "Splitting node");
+            let (left, separator, right) = node.split(right_id);
+            batch.put(id, left);
+            batch.put(right_id, right);
+            node = if let Some((parent, index)) = path.pop() {
+                let Node::Internal {
+                    mut keys,
+                    mut children,
+                } = batch.node(parent)?
+                else {
+                    return Err(BTreeError::CorruptPage(parent));
+                };
+                keys.insert(index, separator);
+                children.insert(index + 1, right_id);
+                id = parent;
+                Node::Internal { keys, children }
+            } else {
+                let children = vec![id, right_id];
+                id = batch.allocate()?;
+                batch.meta.root = id;
+                Node::Internal {
+                    keys: vec![separator],
+                    children,
+                }
+            };
+        }
+        batch.put(id, node);
+        let Batch { nodes, meta, .. } = batch;
+        self.commit(nodes, meta)?;
+        Ok(previous)
+    }
+
+    /// Remove a key, returning its value.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn remove(&mut self, key: &[u8]) -> BTreeResult<Option<Vec<u8>>> {
+        let mut batch = self.batch();
+        let (mut path, mut id, mut entries, next) = self.descend(&batch, Some(key))?;
+        let Ok(index) = self.search(&entries, key) else {
+            return Ok(None);
+        };
+        let (_, previous) = entries.remove(index);
+        batch.meta.count -= 1;
+
+        // Rebalance underfull nodes up the path, shrinking the root once it has a single child.
+        let mut node = Node::Leaf { entries, next };
+        loop {
+            let Some((parent, index)) = path.pop() else {
+                match node {
+                    Node::Internal { keys, children } if keys.is_empty() => {
+                        batch.meta.root = children[0];
+                        batch.free(id);
+                    }
+                    node => batch.put(id, node),
+                }
+                break;
+            };
+            if node.size() >= self.payload / 4 {
+                batch.put(id, node);
+                break;
+            }
+            let Node::Internal {
+                mut keys,
+                mut children,
+            } = batch.node(parent)?
+            else {
+                return Err(BTreeError::CorruptPage(parent));
+            };
+            if children.len() < 2 {
+                return Err(BTreeError::CorruptPage(parent));
+            }
+            let left_index = index.min(children.len() - 2);
+            let (left_id, right_id) = (children[left_index], children[left_index + 1]);
+            let original = node.clone();
+            let (left, right) = if left_index == index {
+                (node, batch.node(right_id)?)
+            } else {
+                (batch.node(left_id)?, node)
+            };
+            let separator = keys[left_index].clone();
+            let shared = match Node::rebalance(left, separator, right, right_id, self.payload) {
+                Rebalanced::Merged(merged) => {
+                    tracing::trace!(page = left_id, right = right_id, "Merging nodes");
+                    batch.put(left_id, merged);
+                    batch.free(right_id);
+                    keys.remove(left_index);
+                    children.remove(left_index + 1);
+                    None
+                }
+                Rebalanced::Shared(left, separator, right) => {
+                    keys[left_index] = separator;
+                    Some((left, right))
+                }
+            };
+            node = Node::Internal { keys, children };
+            if let Some((left, right)) = shared {
+                if node.size() > self.payload {
+                    // A longer separator would overflow the parent, so the node stays underfull.
+                    batch.put(id, original);
+                    break;
+                }
+                batch.put(left_id, left);
+                batch.put(right_id, right);
+            }
+            id = parent;
+        }
+        let Batch { nodes, meta, .. } = batch;
+        self.commit(nodes, meta)?;
+        Ok(Some(previous))
+    }
+
+    /// Iterate over the entries with keys between `start` and `end`, in key order.
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> BTreeRange<'_, H, F> {
+        BTreeRange {
+            tree: self,
+            start: Some(start.map(<[u8]>::to_vec)),
+            end: end.map(<[u8]>::to_vec),
+            entries: VecDeque::new(),
+            next: 0,
+            done: false,
+        }
+    }
+
+    /// Iterate over every entry in key order.
+    pub fn iter(&self) -> BTreeRange<'_, H, F> {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Write every cached page back to the file and record the position in the log that
+    /// recovery starts from, removing log segments no longer needed.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn checkpoint(&mut self) -> BTreeResult<()> {
+        self.pool.flush_all()?;
+        let mut meta = self.meta.clone();
+        meta.checkpoint = self.wal.next_lsn();
+        // The meta page is logged like any other change, so a torn write of it is recovered.
+        self.commit(BTreeMap::new(), meta)?;
+        self.pool.flush_all()?;
+        self.wal.remove_segments_before(self.meta.checkpoint)?;
+        Ok(())
+    }
+
+    /// Checkpoint the tree and return its file and log.
+    pub fn into_parts(mut self) -> BTreeResult<(PagedFile<H>, WriteAheadLog<F>)> {
+        self.checkpoint()?;
+        Ok((self.pool.into_inner()?, self.wal))
+    }
+
+    fn batch(&self) -> Batch<'_, H> {
+        Batch {
+            pool: &self.pool,
+            nodes: BTreeMap::new(),
+            meta: self.meta.clone(),
+        }
+    }
+
+    /// Find the leaf for `key`, or the first leaf if `None`, returning the path of internal
+    /// nodes and the index of the child taken in each, the leaf, and its contents.
+    #[allow(clippy::type_complexity)]
+    fn descend(
+        &self,
+        batch: &Batch<'_, H>,
+        key: Option<&[u8]>,
+    ) -> BTreeResult<(
+        Vec<(PageId, usize)>,
+        PageId,
+        Vec<(Vec<u8>, Vec<u8>)>,
+        PageId,
+    )> {
+        let mut path = Vec::new();
+        let mut id = batch.meta.root;
+        while path.len() < MAX_DEPTH {
+            match batch.node(id)? {
+                Node::Internal { keys, children } => {
+                    let index = key.map_or(0, |key| child_index(&*self.comparator, &keys, key));
+                    path.push((id, index));
+                    id = children[index];
+                }
+                Node::Leaf { entries, next } => return Ok((path, id, entries, next)),
+                Node::Free { .. } => break,
+            }
+        }
+        Err(BTreeError::CorruptPage(id))
+    }
+
+    fn search(&self, entries: &[(Vec<u8>, Vec<u8>)], key: &[u8]) -> Result<usize, usize> {
+        entries.binary_search_by(|(entry, _)| self.comparator.compare(entry, key))
+    }
+
+    /// Log the page images of a change, then apply them to the pool.
+    fn commit(&mut self, nodes: BTreeMap<PageId, Node>, meta: Meta) -> BTreeResult<()> {
+        let mut images = Vec::with_capacity(nodes.len() + 1);
+        let mut page = Page::new(self.payload);
+        meta.encode(page.data_mut());
+        images.push((0, page));
+        for (id, node) in nodes {
+            let mut page = Page::new(self.payload);
+            node.encode(page.data_mut());
+            images.push((id, page));
+        }
+        let mut record = Vec::with_capacity(images.len() * (8 + self.payload));
+        for (id, page) in &images {
+            record.extend_from_slice(&id.to_le_bytes());
+            record.extend_from_slice(page.data());
+        }
+        self.wal.append(&record)?;
+        self.wal.sync()?;
+        for (id, page) in images {
+            self.pool
+                .pin(id)?
+                .write()
+                .data_mut()
+                .copy_from_slice(page.data());
+        }
+        self.meta = meta;
+        Ok(())
+    }
+}
+
+/// Pages changed by an operation on a [`BTree`], applied together once it completes.
+struct Batch<'a, H: FileHandle> {
+    pool: &'a BufferPool<H>,
+    nodes: BTreeMap<PageId, Node>,
+    meta: Meta,
+}
+
+impl<H: FileHandle> Batch<'_, H> {
+    fn node(&self, id: PageId) -> BTreeResult<Node> {
+        match self.nodes.get(&id) {
+            Some(node) => Ok(node.clone()),
+            None => read_node(self.pool, id),
+        }
+    }
+
+    fn put(&mut self, id: PageId, node: Node) {
+        self.nodes.insert(id, node);
+    }
+
+    /// Take a page from the free list, or append one to the file.
+    fn allocate(&mut self) -> BTreeResult<PageId> {
+        if self.meta.free == 0 {
+            return Ok(self.pool.new_page()?.id());
+        }
+        let id = self.meta.free;
+        match self.node(id)? {
+            Node::Free { next } => {
+                self.meta.free = next;
+                Ok(id)
+            }
+            _ => Err(BTreeError::CorruptPage(id)),
+        }
+    }
+
+    /// Return a page to the free list.
+    fn free(&mut self, id: PageId) {
+        let next = self.meta.free;
+        self.put(id, Node::Free { next });
+        self.meta.free = id;
+    }
+}
+
+impl<'a, H: FileHandle, F: FileSystem> IntoIterator for &'a BTree<H, F> {
+    type Item = BTreeResult<(Vec<u8>, Vec<u8>)>;
+    type IntoIter = BTreeRange<'a, H, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over a range of entries of a [`BTree`], returned by [`BTree::range`].
+#[derive(Debug)]
+pub struct BTreeRange<'a, H: FileHandle, F: FileSystem> {
+    tree: &'a BTree<H, F>,
+    /// Start of the range, until the first leaf is found
+    start: Option<Bound<Vec<u8>>>,
+    end: Bound<Vec<u8>>,
+    /// Entries of the current leaf not yet returned
+    entries: VecDeque<(Vec<u8>, Vec<u8>)>,
+    /// Next leaf, or zero after the last
+    next: PageId,
+    done: bool,
+}
+
+impl<H: FileHandle, F: FileSystem> BTreeRange<'_, H, F> {
+    /// Load the leaf holding the start of the range, skipping the entries before it.
+    fn seek(&mut self, start: &Bound<Vec<u8>>) -> BTreeResult<()> {
+        let key = match start {
+            Bound::Included(key) | Bound::Excluded(key) => Some(key.as_slice()),
+            Bound::Unbounded => None,
+        };
+        let (_, _, entries, next) = self.tree.descend(&self.tree.batch(), key)?;
+        let comparator = &*self.tree.comparator;
+        self.entries = entries
+            .into_iter()
+            .filter(|(key, _)| match start {
+                Bound::Included(start) => comparator.compare(key, start) != Ordering::Less,
+                Bound::Excluded(start) => comparator.compare(key, start) == Ordering::Greater,
+                Bound::Unbounded => true,
+            })
+            .collect();
+        self.next = next;
+        Ok(())
+    }
+
+    fn next_leaf(&mut self) -> BTreeResult<()> {
+        match read_node(&self.tree.pool, self.next)? {
+            Node::Leaf { entries, next } => {
+                self.entries = entries.into();
+                self.next = next;
+                Ok(())
+            }
+            _ => Err(BTreeError::CorruptPage(self.next)),
+        }
+    }
+}
+
+impl<H: FileHandle, F: FileSystem> Iterator for BTreeRange<'_, H, F> {
+    type Item = BTreeResult<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let loaded = match self.start.take() {
+                Some(start) => self.seek(&start),
+                None if self.entries.is_empty() && self.next != 0 => self.next_leaf(),
+                None => Ok(()),
+            };
+            if let Err(err) = loaded {
+                self.done = true;
+                return Some(Err(err));
+            }
+            let Some((key, value)) = self.entries.pop_front() else {
+                if self.next == 0 {
+                    self.done = true;
+                }
+                continue;
+            };
+            let past = match &self.end {
+                Bound::Included(end) => {
+                    self.tree.comparator.compare(&key, end) == Ordering::Greater
+                }
+                Bound::Excluded(end) => self.tree.comparator.compare(&key, end) != Ordering::Less,
+                Bound::Unbounded => false,
+            };
+            if past {
+                self.done = true;
+                return None;
+            }
+            return Some(Ok((key, value)));
+        }
+        None
+    }
+}
+
+fn read_node<H: FileHandle>(pool: &BufferPool<H>, id: PageId) -> BTreeResult<Node> {
+    // The meta page is never a node, and zero ends every link.
+    if id == 0 {
+        return Err(BTreeError::CorruptPage(id));
+    }
+    let page = pool.pin(id)?;
+    let node = Node::decode(page.read().data()).ok_or(BTreeError::CorruptPage(id));
+    node
+}
+
+/// Write the page images logged since the last checkpoint to `file`.
+fn recover<H: FileHandle, F: FileSystem>(
+    file: &mut PagedFile<H>,
+    wal: &WriteAheadLog<F>,
+) -> BTreeResult<()> {
+    let checkpoint = if file.page_count()? == 0 {
+        Lsn::default()
+    } else {
+        match file.read_page(0) {
+            Ok(page) => Meta::decode(page.data()).map_or(Lsn::default(), |meta| meta.checkpoint),
+            // A meta page torn while checkpointing is restored from the start of the log.
+            Err(FileSystemError::CorruptData { .. }) => Lsn::default(),
+            Err(err) => return Err(err.into()),
+        }
+    };
+    let image_size = 8 + file.payload_size();
+    let mut replayed = 0;
+    for record in wal.iter(checkpoint) {
+        let (lsn, record) = record?;
+        if record.is_empty() || record.len() % image_size != 0 {
+            return Err(BTreeError::CorruptLog(lsn));
+        }
+        for image in record.chunks(image_size) {
+            let id = u64::from_le_bytes(image[..8].try_into().expect("Page Id"));
+            let count = file.page_count()?;
+            if id >= count {
+                file.extend(id + 1 - count)?;
+            }
+            file.write_page(id, &Page::from(image[8..].to_vec()))?;
+        }
+        replayed += 1;
+    }
+    if replayed > 0 {
+        tracing::debug!(replayed, ?checkpoint, "Replayed B+tree log");
+        file.sync()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BTree, BTreeError, BTreeOptions, BTreeResult, KeyComparator};
+    use minql_vfs::{FileSystem, MemoryFileSystem, PagedFile, WalOptions, WriteAheadLog};
+    use std::cmp::Ordering;
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+
+    type MemoryTree = BTree<<MemoryFileSystem as FileSystem>::FileHandle, MemoryFileSystem>;
+
+    #[derive(Debug)]
+    struct Reverse;
+
+    impl KeyComparator for Reverse {
+        fn name(&self) -> &'static str {
+            "test.reverse"
+        }
+
+        fn compare(&self, left: &[u8], right: &[u8]) -> Ordering {
+            right.cmp(left)
+        }
+    }
+
+    /// Open the tree with small pages and a cache of eight of them, so it splits and evicts
+    /// often.
+    fn open(fs: &MemoryFileSystem, options: BTreeOptions) -> BTreeResult<MemoryTree> {
+        let handle = if fs.exists("/tree.db").unwrap() {
+            fs.open_file("/tree.db")
+        } else {
+            fs.create_file("/tree.db")
+        };
+        let file = PagedFile::new(handle.unwrap(), 256).unwrap();
+        let wal_options = WalOptions::new().with_segment_size(16 * 1024);
+        let wal = WriteAheadLog::open(fs.clone(), "/tree.wal", wal_options).unwrap();
+        BTree::open(file, wal, options.with_cache_size(8 * 256))
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key{index:04}").into_bytes()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_btree() {
+        let fs = MemoryFileSystem::new();
+        let mut tree = open(&fs, BTreeOptions::new()).unwrap();
+        assert!(tree.is_empty());
+        let mut expected = BTreeMap::new();
+        for index in 0..300 {
+            let key = key(index * 7 % 300);
+            let value = vec![u8::try_from(index % 251).unwrap(); index % 20];
+            assert_eq!(tree.insert(&key, &value).unwrap(), None);
+            expected.insert(key, value);
+        }
+        assert_eq!(tree.len(), 300);
+        assert_eq!(
+            tree.insert(&key(5), b"five").unwrap(),
+            expected.insert(key(5), b"five".to_vec())
+        );
+        assert_eq!(tree.get(&key(42)).unwrap().as_ref(), expected.get(&key(42)));
+        assert_eq!(tree.get(b"missing").unwrap(), None);
+        let entries = tree.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries, expected.clone().into_iter().collect::<Vec<_>>());
+        let (start, end) = (key(100), key(110));
+        let keys = tree
+            .range(Bound::Included(&start), Bound::Excluded(&end))
+            .map(|entry| entry.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, (100..110).map(key).collect::<Vec<_>>());
+        let value = vec![0; tree.max_entry_size()];
+        assert!(matches!(
+            tree.insert(b"big", &value),
+            Err(BTreeError::EntryTooLarge { .. })
+        ));
+
+        // Removing most entries merges nodes, and later inserts reuse their pages
+        for index in (0..300).filter(|index| index % 5 != 0) {
+            assert_eq!(
+                tree.remove(&key(index)).unwrap(),
+                expected.remove(&key(index))
+            );
+        }
+        assert_eq!(tree.remove(&key(1)).unwrap(), None);
+        assert_eq!(tree.len(), 60);
+        let entries = tree.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries, expected.clone().into_iter().collect::<Vec<_>>());
+        let (file, _) = tree.into_parts().unwrap();
+        let pages = file.page_count().unwrap();
+        drop(file);
+
+        let mut tree = open(&fs, BTreeOptions::new()).unwrap();
+        for index in 300..340 {
+            tree.insert(&key(index), b"again").unwrap();
+            expected.insert(key(index), b"again".to_vec());
+        }
+        let entries = (&tree).into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries, expected.into_iter().collect::<Vec<_>>());
+        let (file, _) = tree.into_parts().unwrap();
+        assert_eq!(file.page_count().unwrap(), pages);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_btree_recovery() {
+        let fs = MemoryFileSystem::new();
+        let mut tree = open(&fs, BTreeOptions::new()).unwrap();
+        for index in 0..200 {
+            tree.insert(&key(index), &key(index)).unwrap();
+        }
+        tree.checkpoint().unwrap();
+        for index in 0..150 {
+            tree.remove(&key(index)).unwrap();
+        }
+        for index in 200..260 {
+            tree.insert(&key(index), &key(index)).unwrap();
+        }
+
+        // Dropping the tree loses every page changed since the checkpoint and not yet evicted
+        drop(tree);
+        let tree = open(&fs, BTreeOptions::new()).unwrap();
+        assert_eq!(tree.len(), 110);
+        let entries = tree.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        let expected = (150..260).map(|index| (key(index), key(index)));
+        assert_eq!(entries, expected.collect::<Vec<_>>());
+        drop(tree);
+
+        assert!(matches!(
+            open(&fs, BTreeOptions::new().with_comparator(Reverse)),
+            Err(BTreeError::ComparatorMismatch { .. })
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_btree_comparator() {
+        let fs = MemoryFileSystem::new();
+        let mut tree = open(&fs, BTreeOptions::new().with_comparator(Reverse)).unwrap();
+        for index in 0..50 {
+            tree.insert(&key(index), b"").unwrap();
+        }
+        let keys = tree
+            .iter()
+            .map(|entry| entry.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, (0..50).rev().map(key).collect::<Vec<_>>());
+        let (start, end) = (key(30), key(20));
+        let keys = tree
+            .range(Bound::Excluded(&start), Bound::Included(&end))
+            .map(|entry| entry.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, (20..30).rev().map(key).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,626 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{
+    CatalogError, CatalogResult, CatalogSnapshot, Constraint, DatabaseSchema, IndexSchema,
+    TableSchema, TableStatistics,
+};
+use minql_vfs::{FileHandle, FileSystem, FileSystemError};
+use std::io::Write;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+
+/// Prefix of catalog file names, followed by their sequence number.
+const CATALOG_PREFIX: &str = "CATALOG-";
+
+/// Persistent catalog of databases, tables and indexes, kept in a system directory.
+///
+/// Readers take a [`CatalogSnapshot`], which never changes under them. DDL runs in a
+/// [`CatalogTransaction`], one at a time, and is written to a new catalog file on commit, so a
+/// crash leaves either the catalog before the transaction or after it.
+///
+/// ```rust
+/// use minql_catalog::{Catalog, ColumnSchema, TableSchema};
+/// use minql_lang::ast::DataType;
+/// use minql_vfs::MemoryFileSystem;
+///
+/// let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+/// let mut transaction = catalog.begin();
+/// transaction.create_database("sales", "mem:///data/sales").unwrap();
+/// let table = TableSchema::new("orders").with_column(ColumnSchema::new("id", DataType::BigInt));
+/// transaction.create_table("sales", table).unwrap();
+/// transaction.commit().unwrap();
+///
+/// let snapshot = catalog.snapshot();
+/// assert_eq!(snapshot.table("sales", "orders").unwrap().uri, "mem:///data/sales/t2");
+/// ```
+#[derive(Debug)]
+pub struct Catalog<F: FileSystem> {
+    fs: F,
+    directory: String,
+    /// Held by the open transaction, so DDL is serialized
+    writer: Mutex<()>,
+    current: RwLock<Arc<CatalogSnapshot>>,
+}
+
+impl<F: FileSystem> Catalog<F> {
+    /// Open the catalog kept in `directory`, creating an empty one if there is none.
+    #[tracing::instrument(level = "debug", skip(fs))]
+    pub fn open(fs: F, directory: &str) -> CatalogResult<Catalog<F>> {
+        let directory = directory.trim_end_matches('/').to_string();
+        fs.create_directory_all(&directory)?;
+        let snapshot = load_snapshot(&fs, &directory)?;
+        let catalog = Catalog {
+            fs,
+            directory,
+            writer: Mutex::new(()),
+            current: RwLock::new(Arc::new(snapshot)),
+        };
+        catalog.remove_orphans()?;
+        Ok(catalog)
+    }
+
+    /// Current state of the catalog.
+    #[must_use]
+    pub fn snapshot(&self) -> Arc<CatalogSnapshot> {
+        self.current.read().expect("Poisoned Lock").clone()
+    }
+
+    /// Begin a transaction, waiting for any other to finish.
+    #[must_use]
+    pub fn begin(&self) -> CatalogTransaction<'_, F> {
+        let writer = self.writer.lock().expect("Poisoned Lock");
+        let state = CatalogSnapshot::clone(&self.snapshot());
+        CatalogTransaction {
+            catalog: self,
+            _writer: writer,
+            state,
+            changed: false,
+        }
+    }
+
+    /// Write `snapshot` as the next catalog file, then remove the one before it.
+    fn save(&self, mut snapshot: CatalogSnapshot) -> CatalogResult<Arc<CatalogSnapshot>> {
+        let previous = self.snapshot().version;
+        snapshot.version = previous + 1;
+        let path = catalog_path(&self.directory, snapshot.version);
+        let mut handle = self.fs.create_file(&path)?;
+        handle
+            .write_all(snapshot.encode().as_bytes())
+            .map_err(FileSystemError::io_error)?;
+        handle.sync_all()?;
+        let snapshot = Arc::new(snapshot);
+        *self.current.write().expect("Poisoned Lock") = snapshot.clone();
+        match self
+            .fs
+            .remove_file(&catalog_path(&self.directory, previous))
+        {
+            Ok(()) | Err(FileSystemError::PathMissing) => Ok(snapshot),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Remove catalog files other than the current one, left behind by a crash.
+    fn remove_orphans(&self) -> CatalogResult<()> {
+        let version = self.snapshot().version;
+        for name in self.fs.list_directory(&self.directory)? {
+            if let Some(sequence) = name.strip_prefix(CATALOG_PREFIX) {
+                if sequence.parse() != Ok(version) {
+                    tracing::debug!(name, "Removing orphaned file");
+                    self.fs.remove_file(&format!("{}/{name}", self.directory))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Changes to a [`Catalog`], made visible together by [`commit`](CatalogTransaction::commit).
+///
+/// Dropping the transaction without committing discards its changes.
+#[derive(Debug)]
+pub struct CatalogTransaction<'a, F: FileSystem> {
+    catalog: &'a Catalog<F>,
+    _writer: MutexGuard<'a, ()>,
+    state: CatalogSnapshot,
+    changed: bool,
+}
+
+impl<F: FileSystem> CatalogTransaction<'_, F> {
+    /// State of the catalog including the changes of the transaction.
+    #[must_use]
+    pub fn state(&self) -> &CatalogSnapshot {
+        &self.state
+    }
+
+    /// Create a database whose tables and indexes are stored below `uri`.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn create_database(&mut self, name: &str, uri: &str) -> CatalogResult<Arc<DatabaseSchema>> {
+        if self.state.databases.contains_key(name) {
+            return Err(CatalogError::DatabaseExists(name.to_string()));
+        }
+        let database = Arc::new(DatabaseSchema {
+            id: self.next_id(),
+            name: name.to_string(),
+            uri: uri.trim_end_matches('/').to_string(),
+        });
+        self.state
+            .databases
+            .insert(name.to_string(), database.clone());
+        self.changed = true;
+        Ok(database)
+    }
+
+    /// Drop a database, which must have no tables left.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn drop_database(&mut self, name: &str) -> CatalogResult<Arc<DatabaseSchema>> {
+        self.database(name)?;
+        if self.state.tables(name).next().is_some() {
+            return Err(CatalogError::DatabaseNotEmpty(name.to_string()));
+        }
+        let database = self.state.databases.remove(name).expect("Database");
+        self.changed = true;
+        Ok(database)
+    }
+
+    /// Create a table in `database`, assigning its id and storage URI.
+    #[tracing::instrument(level = "debug", skip(self, table), fields(table = table.name))]
+    pub fn create_table(
+        &mut self,
+        database: &str,
+        mut table: TableSchema,
+    ) -> CatalogResult<Arc<TableSchema>> {
+        let uri = self.database(database)?.uri.clone();
+        if self.state.table(database, &table.name).is_some() {
+            return Err(CatalogError::TableExists(table.name));
+        }
+        table.validate()?;
+        for constraint in &table.constraints {
+            if let Constraint::ForeignKey {
+                columns,
+                table: foreign_table,
+                referred_columns,
+                ..
+            } = constraint
+            {
+                self.check_foreign_key(database, &table, columns, foreign_table, referred_columns)?;
+            }
+        }
+        table.id = self.next_id();
+        table.database = database.to_string();
+        table.uri = format!("{uri}/t{}", table.id);
+        let table = Arc::new(table);
+        self.state.tables.insert(table.id, table.clone());
+        self.state.rebuild();
+        self.changed = true;
+        Ok(table)
+    }
+
+    /// Drop a table and its indexes, returning them so their storage can be removed.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn drop_table(
+        &mut self,
+        database: &str,
+        name: &str,
+    ) -> CatalogResult<(Arc<TableSchema>, Vec<Arc<IndexSchema>>)> {
+        let table = self
+            .state
+            .table(database, name)
+            .ok_or_else(|| CatalogError::TableMissing(name.to_string()))?
+            .clone();
+        for other in self.state.tables(database) {
+            let refers = other.id != table.id
+                && other.constraints.iter().any(|constraint| {
+                    matches!(constraint, Constraint::ForeignKey { table, .. } if table == name)
+                });
+            if refers {
+                return Err(CatalogError::TableReferenced {
+                    table: name.to_string(),
+                    by: other.name.clone(),
+                });
+            }
+        }
+        let indexes = self.state.table_indexes(table.id).to_vec();
+        for index in &indexes {
+            self.state.indexes.remove(&index.id);
+        }
+        self.state.tables.remove(&table.id);
+        self.state.statistics.remove(&table.id);
+        self.state.rebuild();
+        self.changed = true;
+        Ok((table, indexes))
+    }
+
+    /// Create an index over the named columns of a table, assigning its id and storage URI.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn create_index(
+        &mut self,
+        database: &str,
+        name: &str,
+        table: &str,
+        columns: &[&str],
+        unique: bool,
+    ) -> CatalogResult<Arc<IndexSchema>> {
+        let uri = self.database(database)?.uri.clone();
+        if self.state.index(database, name).is_some() {
+            return Err(CatalogError::IndexExists(name.to_string()));
+        }
+        let table = self
+            .state
+            .table(database, table)
+            .ok_or_else(|| CatalogError::TableMissing(table.to_string()))?;
+        if columns.is_empty() {
+            return Err(CatalogError::InvalidSchema(format!(
+                "index {name} has no columns"
+            )));
+        }
+        let columns = columns
+            .iter()
+            .map(|&column| {
+                table
+                    .column_index(column)
+                    .ok_or_else(|| CatalogError::ColumnMissing(column.to_string()))
+            })
+            .collect::<CatalogResult<Vec<_>>>()?;
+        let table = table.id;
+        let id = self.next_id();
+        let index = Arc::new(IndexSchema {
+            id,
+            database: database.to_string(),
+            name: name.to_string(),
+            table,
+            columns,
+            unique,
+            uri: format!("{uri}/i{id}"),
+        });
+        self.state.indexes.insert(id, index.clone());
+        self.state.rebuild();
+        self.changed = true;
+        Ok(index)
+    }
+
+    /// Drop an index, returning it so its storage can be removed.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn drop_index(&mut self, database: &str, name: &str) -> CatalogResult<Arc<IndexSchema>> {
+        let id = self
+            .state
+            .index(database, name)
+            .ok_or_else(|| CatalogError::IndexMissing(name.to_string()))?
+            .id;
+        let index = self.state.indexes.remove(&id).expect("Index");
+        self.state.rebuild();
+        self.changed = true;
+        Ok(index)
+    }
+
+    /// Replace the statistics of a table with those gathered by `ANALYZE`.
+    #[tracing::instrument(level = "debug", skip(self, statistics), fields(rows = statistics.rows))]
+    pub fn set_statistics(
+        &mut self,
+        database: &str,
+        table: &str,
+        statistics: TableStatistics,
+    ) -> CatalogResult<Arc<TableStatistics>> {
+        let schema = self
+            .state
+            .table(database, table)
+            .ok_or_else(|| CatalogError::TableMissing(table.to_string()))?;
+        if statistics.columns.len() != schema.columns.len() {
+            return Err(CatalogError::InvalidStatistics(format!(
+                "{} columns of statistics for table {table} of {}",
+                statistics.columns.len(),
+                schema.columns.len()
+            )));
+        }
+        let id = schema.id;
+        let statistics = Arc::new(statistics);
+        self.state.statistics.insert(id, statistics.clone());
+        self.changed = true;
+        Ok(statistics)
+    }
+
+    /// Write the changes and make them visible to new snapshots.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn commit(self) -> CatalogResult<Arc<CatalogSnapshot>> {
+        if !self.changed {
+            return Ok(self.catalog.snapshot());
+        }
+        self.catalog.save(self.state)
+    }
+
+    /// Discard the changes.
+    pub fn rollback(self) {}
+
+    fn database(&self, name: &str) -> CatalogResult<&Arc<DatabaseSchema>> {
+        self.state
+            .database(name)
+            .ok_or_else(|| CatalogError::DatabaseMissing(name.to_string()))
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.state.next_id += 1;
+        self.state.next_id
+    }
+
+    /// Check a foreign key refers to columns of a table in the same database.
+    fn check_foreign_key(
+        &self,
+        database: &str,
+        table: &TableSchema,
+        columns: &[usize],
+        foreign_table: &str,
+        referred_columns: &[String],
+    ) -> CatalogResult<()> {
+        let foreign = if foreign_table == table.name {
+            table
+        } else {
+            self.state
+                .table(database, foreign_table)
+                .ok_or_else(|| CatalogError::TableMissing(foreign_table.to_string()))?
+        };
+        let count = if referred_columns.is_empty() {
+            foreign.primary_key.len()
+        } else {
+            for column in referred_columns {
+                if foreign.column_index(column).is_none() {
+                    return Err(CatalogError::ColumnMissing(column.clone()));
+                }
+            }
+            referred_columns.len()
+        };
+        if count != columns.len() {
+            return Err(CatalogError::InvalidSchema(format!(
+                "foreign key of {} has {} columns but refers to {count}",
+                table.name,
+                columns.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Path of the catalog file with sequence number `sequence`.
+fn catalog_path(directory: &str, sequence: u64) -> String {
+    format!("{directory}/{CATALOG_PREFIX}{sequence:06}")
+}
+
+/// Load the newest intact catalog file, skipping any torn by a crash.
+fn load_snapshot<F: FileSystem>(fs: &F, directory: &str) -> CatalogResult<CatalogSnapshot> {
+    let mut sequences = fs
+        .list_directory(directory)?
+        .iter()
+        .filter_map(|name| name.strip_prefix(CATALOG_PREFIX)?.parse::<u64>().ok())
+        .collect::<Vec<_>>();
+    sequences.sort_unstable();
+    for sequence in sequences.into_iter().rev() {
+        let text = fs.read(&catalog_path(directory, sequence))?;
+        if let Some(mut snapshot) = std::str::from_utf8(&text)
+            .ok()
+            .and_then(CatalogSnapshot::decode)
+        {
+            snapshot.version = sequence;
+            return Ok(snapshot);
+        }
+        tracing::warn!(sequence, "Skipping damaged catalog");
+    }
+    Ok(CatalogSnapshot::default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{catalog_path, Catalog};
+    use crate::{
+        CatalogError, ColumnSchema, Constraint, StatisticsCollector, TableSchema, TableStatistics,
+    };
+    use minql_lang::ast::DataType;
+    use minql_value::Value;
+    use minql_vfs::{FileSystem, MemoryFileSystem};
+
+    fn customers() -> TableSchema {
+        TableSchema::new("customers")
+            .with_column(ColumnSchema::new("id", DataType::BigInt).with_nullable(false))
+            .with_column(ColumnSchema::new("name", DataType::Text))
+            .with_primary_key(vec![0])
+    }
+
+    fn orders() -> TableSchema {
+        TableSchema::new("orders")
+            .with_column(ColumnSchema::new("id", DataType::BigInt))
+            .with_column(ColumnSchema::new("customer", DataType::BigInt))
+            .with_constraint(Constraint::ForeignKey {
+                name: None,
+                columns: vec![1],
+                table: "customers".to_string(),
+                referred_columns: Vec::new(),
+            })
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_catalog() {
+        let fs = MemoryFileSystem::new();
+        let catalog = Catalog::open(fs.clone(), "/system/").unwrap();
+        assert_eq!(catalog.snapshot().version(), 0);
+
+        let mut transaction = catalog.begin();
+        transaction
+            .create_database("sales", "mem:///data/sales/")
+            .unwrap();
+        transaction.create_table("sales", customers()).unwrap();
+        transaction.create_table("sales", orders()).unwrap();
+        transaction
+            .create_index("sales", "by_name", "customers", &["name"], false)
+            .unwrap();
+        assert!(transaction.state().table("sales", "orders").is_some());
+        assert!(catalog.snapshot().table("sales", "orders").is_none());
+        let snapshot = transaction.commit().unwrap();
+        assert_eq!(snapshot.version(), 1);
+        assert_eq!(fs.list_directory("/system").unwrap(), ["CATALOG-000001"]);
+
+        let customers = snapshot.table("sales", "customers").unwrap();
+        assert_eq!(customers.uri, "mem:///data/sales/t2");
+        assert_eq!(
+            snapshot.table_indexes(customers.id)[0].uri,
+            "mem:///data/sales/i4"
+        );
+        assert_eq!(snapshot.tables("sales").count(), 2);
+
+        let mut transaction = catalog.begin();
+        assert!(matches!(
+            transaction.create_database("sales", "mem:///other"),
+            Err(CatalogError::DatabaseExists(_))
+        ));
+        assert!(matches!(
+            transaction.create_table("sales", orders()),
+            Err(CatalogError::TableExists(_))
+        ));
+        assert!(matches!(
+            transaction.create_table("missing", orders()),
+            Err(CatalogError::DatabaseMissing(_))
+        ));
+        assert!(matches!(
+            transaction.create_index("sales", "by_name", "orders", &["id"], true),
+            Err(CatalogError::IndexExists(_))
+        ));
+        assert!(matches!(
+            transaction.create_index("sales", "i", "orders", &["total"], true),
+            Err(CatalogError::ColumnMissing(_))
+        ));
+        assert!(matches!(
+            transaction.drop_table("sales", "customers"),
+            Err(CatalogError::TableReferenced { by, .. }) if by == "orders"
+        ));
+        assert!(matches!(
+            transaction.drop_database("sales"),
+            Err(CatalogError::DatabaseNotEmpty(_))
+        ));
+        let mut missing = orders();
+        missing.name = "returns".to_string();
+        if let Constraint::ForeignKey { table, .. } = &mut missing.constraints[0] {
+            *table = "shipments".to_string();
+        }
+        assert!(matches!(
+            transaction.create_table("sales", missing),
+            Err(CatalogError::TableMissing(_))
+        ));
+        transaction.drop_table("sales", "orders").unwrap();
+        let (_, indexes) = transaction.drop_table("sales", "customers").unwrap();
+        assert_eq!(indexes.len(), 1);
+        transaction.rollback();
+        assert_eq!(catalog.snapshot().version(), 1);
+        assert!(catalog
+            .begin()
+            .commit()
+            .unwrap()
+            .table("sales", "orders")
+            .is_some());
+
+        let mut transaction = catalog.begin();
+        transaction.drop_table("sales", "orders").unwrap();
+        transaction.drop_index("sales", "by_name").unwrap();
+        transaction.commit().unwrap();
+        drop(catalog);
+
+        let catalog = Catalog::open(fs.clone(), "/system").unwrap();
+        let snapshot = catalog.snapshot();
+        assert_eq!(snapshot.version(), 2);
+        assert!(snapshot.table("sales", "orders").is_none());
+        assert!(snapshot.index("sales", "by_name").is_none());
+        let customers = snapshot.table("sales", "customers").unwrap();
+        assert_eq!(customers.primary_key, [0]);
+        assert!(snapshot.table_indexes(customers.id).is_empty());
+
+        let mut transaction = catalog.begin();
+        let table = transaction.create_table("sales", orders()).unwrap();
+        assert_eq!(table.id, 5);
+        transaction.commit().unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_catalog_recovery() {
+        let fs = MemoryFileSystem::new();
+        let catalog = Catalog::open(fs.clone(), "/system").unwrap();
+        let mut transaction = catalog.begin();
+        transaction
+            .create_database("sales", "mem:///sales")
+            .unwrap();
+        transaction.commit().unwrap();
+        drop(catalog);
+
+        let torn = fs.read(&catalog_path("/system", 1)).unwrap();
+        fs.write(&catalog_path("/system", 2), &torn[..torn.len() / 2])
+            .unwrap();
+        fs.write("/system/other", b"kept").unwrap();
+
+        let catalog = Catalog::open(fs.clone(), "/system").unwrap();
+        assert_eq!(catalog.snapshot().version(), 1);
+        assert!(catalog.snapshot().database("sales").is_some());
+        let mut names = fs.list_directory("/system").unwrap();
+        names.sort();
+        assert_eq!(names, ["CATALOG-000001", "other"]);
+
+        let mut transaction = catalog.begin();
+        transaction.drop_database("sales").unwrap();
+        assert_eq!(transaction.commit().unwrap().version(), 2);
+        assert!(Catalog::open(fs, "/system")
+            .unwrap()
+            .snapshot()
+            .database("sales")
+            .is_none());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_catalog_statistics() {
+        let fs = MemoryFileSystem::new();
+        let catalog = Catalog::open(fs.clone(), "/system").unwrap();
+        let mut transaction = catalog.begin();
+        transaction
+            .create_database("sales", "mem:///data/sales")
+            .unwrap();
+        let id = transaction.create_table("sales", customers()).unwrap().id;
+        transaction.commit().unwrap();
+
+        let mut collector = StatisticsCollector::new(2);
+        for id in 0..50i64 {
+            collector.add_row(&[Value::from(id), Value::from(format!("name {}", id % 5))]);
+        }
+        let statistics = collector.finish();
+        let mut transaction = catalog.begin();
+        assert!(matches!(
+            transaction.set_statistics("sales", "orders", statistics.clone()),
+            Err(CatalogError::TableMissing(_))
+        ));
+        assert!(matches!(
+            transaction.set_statistics("sales", "customers", TableStatistics::default()),
+            Err(CatalogError::InvalidStatistics(_))
+        ));
+        transaction
+            .set_statistics("sales", "customers", statistics.clone())
+            .unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(**catalog.snapshot().statistics(id).unwrap(), statistics);
+
+        drop(catalog);
+        let catalog = Catalog::open(fs.clone(), "/system").unwrap();
+        assert_eq!(**catalog.snapshot().statistics(id).unwrap(), statistics);
+
+        let mut transaction = catalog.begin();
+        transaction.drop_table("sales", "customers").unwrap();
+        let snapshot = transaction.commit().unwrap();
+        assert!(snapshot.statistics(id).is_none());
+    }
+}
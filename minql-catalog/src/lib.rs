@@ -0,0 +1,56 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Persistent Schema Catalog
+//!
+//! A [`Catalog`] records the databases, tables, columns, constraints and indexes of every
+//! database, and the URI where each keeps its storage. It is saved as text files in a system directory
+//! of any `FileSystem` from `minql-vfs`, rewritten whole by every committed
+//! [`CatalogTransaction`], and read through immutable [`CatalogSnapshot`]s keyed for name
+//! resolution by the planner.
+//!
+//! `ANALYZE` gathers [`TableStatistics`] of row counts, distinct values, ranges and histograms
+//! with a [`StatisticsCollector`], kept alongside each table's schema so the planner can
+//! estimate the selectivity of predicates and the size of joins.
+
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod catalog;
+mod result;
+mod schema;
+mod snapshot;
+mod statistics;
+
+pub use self::catalog::{Catalog, CatalogTransaction};
+pub use self::result::{CatalogError, CatalogResult};
+pub use self::schema::{ColumnSchema, Constraint, DatabaseSchema, IndexSchema, TableSchema};
+pub use self::snapshot::CatalogSnapshot;
+pub use self::statistics::{
+    ColumnStatistics, HistogramBucket, StatisticsCollector, TableStatistics,
+};
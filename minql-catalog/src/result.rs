@@ -0,0 +1,77 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use minql_vfs::FileSystemError;
+
+/// Result Type for the Catalog
+pub type CatalogResult<T> = Result<T, CatalogError>;
+
+/// Error Type for the Catalog
+#[derive(Debug)]
+pub enum CatalogError {
+    /// Database of the same name already exists
+    DatabaseExists(String),
+    /// Database doesn't exist
+    DatabaseMissing(String),
+    /// Database still has tables and can't be dropped
+    DatabaseNotEmpty(String),
+    /// Table of the same name already exists in the database
+    TableExists(String),
+    /// Table doesn't exist in the database
+    TableMissing(String),
+    /// Table is referred to by a foreign key of another table and can't be dropped
+    TableReferenced {
+        /// Table referred to
+        table: String,
+        /// Table holding the foreign key
+        by: String,
+    },
+    /// Index of the same name already exists in the database
+    IndexExists(String),
+    /// Index doesn't exist in the database
+    IndexMissing(String),
+    /// Column isn't part of the table
+    ColumnMissing(String),
+    /// Column is defined more than once
+    DuplicateColumn(String),
+    /// Schema is inconsistent, such as a table without columns or two primary keys
+    InvalidSchema(String),
+    /// Statistics don't match the columns of their table
+    InvalidStatistics(String),
+    /// Error of the underlying `FileSystem`
+    FileSystem(FileSystemError),
+}
+
+impl std::fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for CatalogError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CatalogError::FileSystem(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<FileSystemError> for CatalogError {
+    fn from(err: FileSystemError) -> Self {
+        CatalogError::FileSystem(err)
+    }
+}
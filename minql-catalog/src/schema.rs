@@ -0,0 +1,450 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{CatalogError, CatalogResult};
+use minql_lang::ast::{ColumnDef, ColumnOption, DataType, Expr, Ident, TableConstraint};
+use std::collections::HashSet;
+
+/// Database, a namespace of tables and indexes stored below a common URI.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DatabaseSchema {
+    /// Id, assigned by the catalog
+    pub id: u64,
+    /// Name, unique in the catalog
+    pub name: String,
+    /// URI under which the storage of its tables and indexes is kept
+    pub uri: String,
+}
+
+/// Column of a [`TableSchema`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColumnSchema {
+    /// Name, unique in the table
+    pub name: String,
+    /// Type of the values
+    pub data_type: DataType,
+    /// Whether the column accepts `NULL`
+    pub nullable: bool,
+    /// Value of the column when an insert leaves it out
+    pub default: Option<Expr>,
+}
+
+impl ColumnSchema {
+    /// Create a nullable column without a default.
+    #[must_use]
+    pub fn new(name: &str, data_type: DataType) -> ColumnSchema {
+        ColumnSchema {
+            name: name.to_string(),
+            data_type,
+            nullable: true,
+            default: None,
+        }
+    }
+
+    /// Set whether the column accepts `NULL`.
+    #[must_use]
+    pub fn with_nullable(mut self, nullable: bool) -> Self {
+        self.nullable = nullable;
+        self
+    }
+
+    /// Set the value of the column when an insert leaves it out.
+    #[must_use]
+    pub fn with_default(mut self, default: Expr) -> Self {
+        self.default = Some(default);
+        self
+    }
+}
+
+/// Constraint of a [`TableSchema`] besides its column types, nullability and primary key.
+///
+/// Columns of the table are referred to by position.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Constraint {
+    /// Values of the columns are distinct together
+    Unique {
+        /// Name of the constraint
+        name: Option<String>,
+        /// Positions of the columns
+        columns: Vec<usize>,
+    },
+    /// Every row meets a condition
+    Check {
+        /// Name of the constraint
+        name: Option<String>,
+        /// Condition over the columns of the row
+        expr: Expr,
+    },
+    /// Values of the columns are found in another table of the same database
+    ForeignKey {
+        /// Name of the constraint
+        name: Option<String>,
+        /// Positions of the columns referring
+        columns: Vec<usize>,
+        /// Name of the table referred to
+        table: String,
+        /// Names of the columns referred to, or its primary key if empty
+        referred_columns: Vec<String>,
+    },
+}
+
+/// Table, its columns and constraints, and where its rows are stored.
+///
+/// ```rust
+/// use minql_catalog::{ColumnSchema, TableSchema};
+/// use minql_lang::ast::DataType;
+///
+/// let table = TableSchema::new("users")
+///     .with_column(ColumnSchema::new("id", DataType::BigInt).with_nullable(false))
+///     .with_column(ColumnSchema::new("name", DataType::Text))
+///     .with_primary_key(vec![0]);
+/// assert_eq!(table.column_index("name"), Some(1));
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TableSchema {
+    /// Id, assigned by the catalog
+    pub id: u64,
+    /// Name of the database holding the table, assigned by the catalog
+    pub database: String,
+    /// Name, unique among the tables of the database
+    pub name: String,
+    /// URI of the storage of the rows, assigned by the catalog
+    pub uri: String,
+    /// Columns, in the order they were defined
+    pub columns: Vec<ColumnSchema>,
+    /// Positions of the columns of the primary key, empty if the table has none
+    pub primary_key: Vec<usize>,
+    /// Constraints besides the primary key
+    pub constraints: Vec<Constraint>,
+}
+
+impl TableSchema {
+    /// Create a table without columns.
+    #[must_use]
+    pub fn new(name: &str) -> TableSchema {
+        TableSchema {
+            id: 0,
+            database: String::new(),
+            name: name.to_string(),
+            uri: String::new(),
+            columns: Vec::new(),
+            primary_key: Vec::new(),
+            constraints: Vec::new(),
+        }
+    }
+
+    /// Add a column after the others.
+    #[must_use]
+    pub fn with_column(mut self, column: ColumnSchema) -> Self {
+        self.columns.push(column);
+        self
+    }
+
+    /// Set the positions of the columns of the primary key.
+    #[must_use]
+    pub fn with_primary_key(mut self, columns: Vec<usize>) -> Self {
+        self.primary_key = columns;
+        self
+    }
+
+    /// Add a constraint.
+    #[must_use]
+    pub fn with_constraint(mut self, constraint: Constraint) -> Self {
+        self.constraints.push(constraint);
+        self
+    }
+
+    /// Build a table from the columns and constraints of a `CREATE TABLE`.
+    ///
+    /// Names are normalized, and columns of the primary key are made `NOT NULL`.
+    pub fn from_ast(
+        name: &str,
+        columns: &[ColumnDef],
+        constraints: &[TableConstraint],
+    ) -> CatalogResult<TableSchema> {
+        let mut table = TableSchema::new(name);
+        let mut primary_key = None;
+        for (position, column) in columns.iter().enumerate() {
+            let mut schema = ColumnSchema::new(&column.name.normalized(), column.data_type);
+            for option in &column.options {
+                match option {
+                    ColumnOption::Null => schema.nullable = true,
+                    ColumnOption::NotNull => schema.nullable = false,
+                    ColumnOption::PrimaryKey => set_primary_key(&mut primary_key, vec![position])?,
+                    ColumnOption::Unique => table.constraints.push(Constraint::Unique {
+                        name: None,
+                        columns: vec![position],
+                    }),
+                    ColumnOption::Default(expr) => schema.default = Some(expr.clone()),
+                    ColumnOption::Check(expr) => table.constraints.push(Constraint::Check {
+                        name: None,
+                        expr: expr.clone(),
+                    }),
+                    ColumnOption::References {
+                        table: foreign_table,
+                        columns,
+                    } => table.constraints.push(Constraint::ForeignKey {
+                        name: None,
+                        columns: vec![position],
+                        table: object_name(foreign_table)?,
+                        referred_columns: columns.iter().map(Ident::normalized).collect(),
+                    }),
+                }
+            }
+            table.columns.push(schema);
+        }
+        for constraint in constraints {
+            match constraint {
+                TableConstraint::PrimaryKey { columns, .. } => {
+                    let columns = table.positions(columns)?;
+                    set_primary_key(&mut primary_key, columns)?;
+                }
+                TableConstraint::Unique { name, columns } => {
+                    let columns = table.positions(columns)?;
+                    table.constraints.push(Constraint::Unique {
+                        name: name.as_ref().map(Ident::normalized),
+                        columns,
+                    });
+                }
+                TableConstraint::ForeignKey {
+                    name,
+                    columns,
+                    foreign_table,
+                    referred_columns,
+                } => {
+                    let columns = table.positions(columns)?;
+                    table.constraints.push(Constraint::ForeignKey {
+                        name: name.as_ref().map(Ident::normalized),
+                        columns,
+                        table: object_name(foreign_table)?,
+                        referred_columns: referred_columns.iter().map(Ident::normalized).collect(),
+                    });
+                }
+                TableConstraint::Check { name, expr } => {
+                    table.constraints.push(Constraint::Check {
+                        name: name.as_ref().map(Ident::normalized),
+                        expr: expr.clone(),
+                    });
+                }
+            }
+        }
+        table.primary_key = primary_key.unwrap_or_default();
+        for &position in &table.primary_key {
+            table.columns[position].nullable = false;
+        }
+        table.validate()?;
+        Ok(table)
+    }
+
+    /// Position of the column named `name`.
+    #[must_use]
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name == name)
+    }
+
+    /// Column named `name`.
+    #[must_use]
+    pub fn column(&self, name: &str) -> Option<&ColumnSchema> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+
+    /// Check the table has columns, their names are distinct, and every position is in range.
+    pub(crate) fn validate(&self) -> CatalogResult<()> {
+        if self.columns.is_empty() {
+            return Err(CatalogError::InvalidSchema(format!(
+                "table {} has no columns",
+                self.name
+            )));
+        }
+        let mut names = HashSet::new();
+        for column in &self.columns {
+            if !names.insert(column.name.as_str()) {
+                return Err(CatalogError::DuplicateColumn(column.name.clone()));
+            }
+        }
+        let positions = self
+            .constraints
+            .iter()
+            .filter_map(|constraint| match constraint {
+                Constraint::Unique { columns, .. } | Constraint::ForeignKey { columns, .. } => {
+                    Some(columns)
+                }
+                Constraint::Check { .. } => None,
+            })
+            .chain(std::iter::once(&self.primary_key));
+        for columns in positions {
+            if let Some(position) = columns
+                .iter()
+                .find(|&&position| position >= self.columns.len())
+            {
+                return Err(CatalogError::ColumnMissing(position.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Positions of the named columns.
+    fn positions(&self, names: &[Ident]) -> CatalogResult<Vec<usize>> {
+        names
+            .iter()
+            .map(|name| {
+                let name = name.normalized();
+                self.column_index(&name)
+                    .ok_or(CatalogError::ColumnMissing(name))
+            })
+            .collect()
+    }
+}
+
+/// Index over columns of a table, and where its entries are stored.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IndexSchema {
+    /// Id, assigned by the catalog
+    pub id: u64,
+    /// Name of the database holding the index
+    pub database: String,
+    /// Name, unique among the indexes of the database
+    pub name: String,
+    /// Id of the table indexed
+    pub table: u64,
+    /// Positions of the columns indexed, in order
+    pub columns: Vec<usize>,
+    /// Whether indexed values must be distinct
+    pub unique: bool,
+    /// URI of the storage of the entries
+    pub uri: String,
+}
+
+/// Set the primary key, unless one was already declared.
+fn set_primary_key(primary_key: &mut Option<Vec<usize>>, columns: Vec<usize>) -> CatalogResult<()> {
+    if primary_key.is_some() {
+        return Err(CatalogError::InvalidSchema(
+            "multiple primary keys".to_string(),
+        ));
+    }
+    *primary_key = Some(columns);
+    Ok(())
+}
+
+/// Name of a table referred to, which must be in the same database.
+fn object_name(name: &[Ident]) -> CatalogResult<String> {
+    match name {
+        [table] => Ok(table.normalized()),
+        _ => Err(CatalogError::InvalidSchema(format!(
+            "foreign key refers to another database: {}",
+            name.iter()
+                .map(|ident| ident.value.as_str())
+                .collect::<Vec<_>>()
+                .join(".")
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ColumnSchema, Constraint, TableSchema};
+    use crate::CatalogError;
+    use minql_lang::ast::{DataType, Statement};
+    use minql_lang::Parser;
+
+    fn create(sql: &str) -> Result<TableSchema, CatalogError> {
+        match Parser::parse_statement(sql).unwrap() {
+            Statement::CreateTable {
+                name,
+                columns,
+                constraints,
+                ..
+            } => TableSchema::from_ast(&name[0].normalized(), &columns, &constraints),
+            statement => panic!("Not a CREATE TABLE: {statement}"),
+        }
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_from_ast() {
+        let table = create(
+            "CREATE TABLE Orders (id BIGINT, \"Customer\" INTEGER NOT NULL REFERENCES customers, \
+             total DECIMAL(10, 2) DEFAULT 0 CHECK (total >= 0), code VARCHAR(8) UNIQUE, \
+             CONSTRAINT pk PRIMARY KEY (ID), UNIQUE (\"Customer\", code))",
+        )
+        .unwrap();
+        assert_eq!(table.name, "orders");
+        let names = table
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["id", "Customer", "total", "code"]);
+        assert_eq!(table.primary_key, [0]);
+        assert!(!table.columns[0].nullable);
+        assert!(!table.columns[1].nullable);
+        assert!(table.columns[2].nullable);
+        assert_eq!(table.columns[2].default.as_ref().unwrap().to_string(), "0");
+        assert_eq!(
+            table.column("code").unwrap().data_type,
+            DataType::Varchar(Some(8))
+        );
+        assert_eq!(table.constraints.len(), 4);
+        assert_eq!(
+            table.constraints[0],
+            Constraint::ForeignKey {
+                name: None,
+                columns: vec![1],
+                table: "customers".to_string(),
+                referred_columns: Vec::new(),
+            }
+        );
+        assert!(
+            matches!(&table.constraints[1], Constraint::Check { name: None, expr } if expr.to_string() == "total >= 0")
+        );
+        assert_eq!(
+            table.constraints[3],
+            Constraint::Unique {
+                name: None,
+                columns: vec![1, 3],
+            }
+        );
+
+        assert!(matches!(
+            create("CREATE TABLE t (a INTEGER, A TEXT)"),
+            Err(CatalogError::DuplicateColumn(name)) if name == "a"
+        ));
+        assert!(matches!(
+            create("CREATE TABLE t (a INTEGER, UNIQUE (b))"),
+            Err(CatalogError::ColumnMissing(name)) if name == "b"
+        ));
+        assert!(matches!(
+            create("CREATE TABLE t (a INTEGER PRIMARY KEY, b TEXT, PRIMARY KEY (b))"),
+            Err(CatalogError::InvalidSchema(_))
+        ));
+        assert!(matches!(
+            create("CREATE TABLE t (a INTEGER REFERENCES other.t)"),
+            Err(CatalogError::InvalidSchema(_))
+        ));
+
+        let table = TableSchema::new("t")
+            .with_column(ColumnSchema::new("a", DataType::Integer))
+            .with_primary_key(vec![1]);
+        assert!(matches!(
+            table.validate(),
+            Err(CatalogError::ColumnMissing(_))
+        ));
+        assert!(matches!(
+            TableSchema::new("t").validate(),
+            Err(CatalogError::InvalidSchema(_))
+        ));
+    }
+}
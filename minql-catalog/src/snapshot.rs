@@ -0,0 +1,742 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{
+    ColumnSchema, ColumnStatistics, Constraint, DatabaseSchema, HistogramBucket, IndexSchema,
+    TableSchema, TableStatistics,
+};
+use minql_lang::ast::DataType;
+use minql_lang::Parser;
+use minql_value::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// First line of every catalog file.
+const CATALOG_HEADER: &str = "minql-catalog 1";
+
+/// Immutable state of a [`Catalog`](crate::Catalog) at one version.
+///
+/// Lookups are keyed by normalized name within a database, or by id, so a planner holding a
+/// snapshot resolves names without touching storage or seeing concurrent DDL.
+#[derive(Clone, Debug, Default)]
+pub struct CatalogSnapshot {
+    /// Sequence number of the catalog file the snapshot was read from or written to
+    pub(crate) version: u64,
+    /// Id of the next database, table or index created
+    pub(crate) next_id: u64,
+    pub(crate) databases: BTreeMap<String, Arc<DatabaseSchema>>,
+    pub(crate) tables: BTreeMap<u64, Arc<TableSchema>>,
+    pub(crate) indexes: BTreeMap<u64, Arc<IndexSchema>>,
+    /// Statistics of analyzed tables, by table id
+    pub(crate) statistics: BTreeMap<u64, Arc<TableStatistics>>,
+    /// Ids of tables by database then name
+    table_names: HashMap<String, HashMap<String, u64>>,
+    /// Ids of indexes by database then name
+    index_names: HashMap<String, HashMap<String, u64>>,
+    /// Indexes of each table, by id
+    table_indexes: HashMap<u64, Vec<Arc<IndexSchema>>>,
+}
+
+impl CatalogSnapshot {
+    /// Version of the catalog, increased by every committed change.
+    #[must_use]
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Database named `name`.
+    #[must_use]
+    pub fn database(&self, name: &str) -> Option<&Arc<DatabaseSchema>> {
+        self.databases.get(name)
+    }
+
+    /// All databases, by name.
+    pub fn databases(&self) -> impl Iterator<Item = &Arc<DatabaseSchema>> {
+        self.databases.values()
+    }
+
+    /// Table named `name` in `database`.
+    #[must_use]
+    pub fn table(&self, database: &str, name: &str) -> Option<&Arc<TableSchema>> {
+        let id = self.table_names.get(database)?.get(name)?;
+        self.tables.get(id)
+    }
+
+    /// Table with id `id`.
+    #[must_use]
+    pub fn table_by_id(&self, id: u64) -> Option<&Arc<TableSchema>> {
+        self.tables.get(&id)
+    }
+
+    /// Tables of `database`, by id.
+    pub fn tables<'a>(&'a self, database: &'a str) -> impl Iterator<Item = &'a Arc<TableSchema>> {
+        self.tables
+            .values()
+            .filter(move |table| table.database == database)
+    }
+
+    /// Index named `name` in `database`.
+    #[must_use]
+    pub fn index(&self, database: &str, name: &str) -> Option<&Arc<IndexSchema>> {
+        let id = self.index_names.get(database)?.get(name)?;
+        self.indexes.get(id)
+    }
+
+    /// Index with id `id`.
+    #[must_use]
+    pub fn index_by_id(&self, id: u64) -> Option<&Arc<IndexSchema>> {
+        self.indexes.get(&id)
+    }
+
+    /// Indexes of the table with id `table`, by id.
+    #[must_use]
+    pub fn table_indexes(&self, table: u64) -> &[Arc<IndexSchema>] {
+        self.table_indexes.get(&table).map_or(&[], Vec::as_slice)
+    }
+
+    /// Statistics of the table with id `table`, or `None` if it hasn't been analyzed.
+    #[must_use]
+    pub fn statistics(&self, table: u64) -> Option<&Arc<TableStatistics>> {
+        self.statistics.get(&table)
+    }
+
+    /// Rebuild the lookups from the databases, tables and indexes.
+    pub(crate) fn rebuild(&mut self) {
+        self.table_names.clear();
+        self.index_names.clear();
+        self.table_indexes.clear();
+        for table in self.tables.values() {
+            self.table_names
+                .entry(table.database.clone())
+                .or_default()
+                .insert(table.name.clone(), table.id);
+        }
+        for index in self.indexes.values() {
+            self.index_names
+                .entry(index.database.clone())
+                .or_default()
+                .insert(index.name.clone(), index.id);
+            self.table_indexes
+                .entry(index.table)
+                .or_default()
+                .push(index.clone());
+        }
+    }
+
+    /// Encode the snapshot as the text of a catalog file.
+    ///
+    /// Catalog files are a line per record, ended by a CRC32 of the lines before it so a torn
+    /// file is never mistaken for a complete one. Names, types and expressions are quoted SQL.
+    pub(crate) fn encode(&self) -> String {
+        let mut text = format!("{CATALOG_HEADER}\n");
+        writeln!(text, "next_id {}", self.next_id).expect("Write Catalog");
+        for database in self.databases.values() {
+            writeln!(
+                text,
+                "database {} {} {}",
+                database.id,
+                quote(&database.name),
+                quote(&database.uri)
+            )
+            .expect("Write Catalog");
+        }
+        for table in self.tables.values() {
+            encode_table(&mut text, table);
+        }
+        for index in self.indexes.values() {
+            writeln!(
+                text,
+                "index {} {} {} {} {} {}{}",
+                index.id,
+                index.table,
+                quote(&index.database),
+                quote(&index.name),
+                if index.unique { "unique" } else { "plain" },
+                quote(&index.uri),
+                positions(&index.columns)
+            )
+            .expect("Write Catalog");
+        }
+        for (table, statistics) in &self.statistics {
+            encode_statistics(&mut text, *table, statistics);
+        }
+        writeln!(text, "crc {:08x}", crc32fast::hash(text.as_bytes())).expect("Write Catalog");
+        text
+    }
+
+    /// Decode a catalog file, or `None` if it's damaged or incomplete.
+    pub(crate) fn decode(text: &str) -> Option<CatalogSnapshot> {
+        let body_end = text.trim_end_matches('\n').rfind('\n')? + 1;
+        let (body, checksum) = text.split_at(body_end);
+        let checksum = u32::from_str_radix(checksum.trim().strip_prefix("crc ")?, 16).ok()?;
+        if crc32fast::hash(body.as_bytes()) != checksum {
+            return None;
+        }
+        let mut lines = body.lines();
+        if lines.next()? != CATALOG_HEADER {
+            return None;
+        }
+        let mut snapshot = CatalogSnapshot::default();
+        let mut tables = BTreeMap::new();
+        let mut statistics = BTreeMap::new();
+        for line in lines {
+            let mut fields = Fields::split(line)?;
+            match fields.word()? {
+                "next_id" => snapshot.next_id = fields.number()?,
+                "database" => {
+                    let database = DatabaseSchema {
+                        id: fields.number()?,
+                        name: fields.quoted()?,
+                        uri: fields.quoted()?,
+                    };
+                    snapshot
+                        .databases
+                        .insert(database.name.clone(), Arc::new(database));
+                }
+                "table" => {
+                    let id = fields.number()?;
+                    let mut table = TableSchema::new("");
+                    table.id = id;
+                    table.database = fields.quoted()?;
+                    table.name = fields.quoted()?;
+                    table.uri = fields.quoted()?;
+                    tables.insert(id, table);
+                }
+                "index" => {
+                    let index = IndexSchema {
+                        id: fields.number()?,
+                        table: fields.number()?,
+                        database: fields.quoted()?,
+                        name: fields.quoted()?,
+                        unique: match fields.word()? {
+                            "unique" => true,
+                            "plain" => false,
+                            _ => return None,
+                        },
+                        uri: fields.quoted()?,
+                        columns: fields.numbers()?,
+                    };
+                    snapshot.indexes.insert(index.id, Arc::new(index));
+                }
+                kind @ ("statistics" | "column_statistics" | "bucket") => {
+                    decode_statistics(kind, &mut fields, &mut statistics)?;
+                }
+                kind => {
+                    let table = tables.get_mut(&fields.number()?)?;
+                    decode_table(kind, &mut fields, table)?;
+                }
+            }
+            fields.end()?;
+        }
+        snapshot.tables = tables
+            .into_iter()
+            .map(|(id, table)| (id, Arc::new(table)))
+            .collect();
+        snapshot.statistics = statistics
+            .into_iter()
+            .map(|(id, statistics)| (id, Arc::new(statistics)))
+            .collect();
+        snapshot.rebuild();
+        Some(snapshot)
+    }
+}
+
+/// Write the records of a table, its columns and constraints.
+fn encode_table(text: &mut String, table: &TableSchema) {
+    let id = table.id;
+    writeln!(
+        text,
+        "table {id} {} {} {}",
+        quote(&table.database),
+        quote(&table.name),
+        quote(&table.uri)
+    )
+    .expect("Write Catalog");
+    for column in &table.columns {
+        writeln!(
+            text,
+            "column {id} {} {} {} {}",
+            quote(&column.name),
+            quote(&column.data_type.to_string()),
+            if column.nullable { "null" } else { "not_null" },
+            column
+                .default
+                .as_ref()
+                .map_or_else(|| "-".to_string(), |expr| quote(&expr.to_string()))
+        )
+        .expect("Write Catalog");
+    }
+    if !table.primary_key.is_empty() {
+        writeln!(text, "primary_key {id}{}", positions(&table.primary_key)).expect("Write Catalog");
+    }
+    for constraint in &table.constraints {
+        match constraint {
+            Constraint::Unique { name, columns } => {
+                writeln!(
+                    text,
+                    "unique {id} {}{}",
+                    optional(name.as_deref()),
+                    positions(columns)
+                )
+            }
+            Constraint::Check { name, expr } => writeln!(
+                text,
+                "check {id} {} {}",
+                optional(name.as_deref()),
+                quote(&expr.to_string())
+            ),
+            Constraint::ForeignKey {
+                name,
+                columns,
+                table: foreign_table,
+                referred_columns,
+            } => writeln!(
+                text,
+                "foreign_key {id} {} {} {}{}{}",
+                optional(name.as_deref()),
+                quote(foreign_table),
+                columns.len(),
+                positions(columns),
+                referred_columns
+                    .iter()
+                    .fold(String::new(), |mut text, column| {
+                        write!(text, " {}", quote(column)).expect("Write Catalog");
+                        text
+                    })
+            ),
+        }
+        .expect("Write Catalog");
+    }
+}
+
+/// Decode a record of the columns or constraints of a table.
+fn decode_table(kind: &str, fields: &mut Fields<'_>, table: &mut TableSchema) -> Option<()> {
+    match kind {
+        "column" => {
+            let mut column = ColumnSchema::new(
+                &fields.quoted()?,
+                Parser::parse_data_type(&fields.quoted()?).ok()?,
+            );
+            column.nullable = match fields.word()? {
+                "null" => true,
+                "not_null" => false,
+                _ => return None,
+            };
+            column.default = match fields.optional().ok()? {
+                Some(expr) => Some(Parser::parse_expr(&expr).ok()?),
+                None => None,
+            };
+            table.columns.push(column);
+        }
+        "primary_key" => table.primary_key = fields.numbers()?,
+        "unique" => table.constraints.push(Constraint::Unique {
+            name: fields.optional().ok()?,
+            columns: fields.numbers()?,
+        }),
+        "check" => table.constraints.push(Constraint::Check {
+            name: fields.optional().ok()?,
+            expr: Parser::parse_expr(&fields.quoted()?).ok()?,
+        }),
+        "foreign_key" => {
+            let name = fields.optional().ok()?;
+            let foreign_table = fields.quoted()?;
+            let count = fields.number()?;
+            let columns = (0..count)
+                .map(|_| fields.number())
+                .collect::<Option<Vec<_>>>()?;
+            let mut referred_columns = Vec::new();
+            while !fields.is_empty() {
+                referred_columns.push(fields.quoted()?);
+            }
+            table.constraints.push(Constraint::ForeignKey {
+                name,
+                columns,
+                table: foreign_table,
+                referred_columns,
+            });
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Write the records of the statistics of a table, its columns and their histograms.
+fn encode_statistics(text: &mut String, table: u64, statistics: &TableStatistics) {
+    writeln!(text, "statistics {table} {}", statistics.rows).expect("Write Catalog");
+    for column in &statistics.columns {
+        writeln!(
+            text,
+            "column_statistics {table} {} {} {} {}",
+            column.distinct,
+            column.nulls,
+            encode_value(column.min.as_ref()),
+            encode_value(column.max.as_ref())
+        )
+        .expect("Write Catalog");
+        for bucket in &column.histogram {
+            writeln!(
+                text,
+                "bucket {table} {} {} {}",
+                bucket.rows,
+                bucket.distinct,
+                encode_value(Some(&bucket.upper))
+            )
+            .expect("Write Catalog");
+        }
+    }
+}
+
+/// Decode a record of the statistics of a table, its columns or their histograms.
+fn decode_statistics(
+    kind: &str,
+    fields: &mut Fields<'_>,
+    statistics: &mut BTreeMap<u64, TableStatistics>,
+) -> Option<()> {
+    let table = fields.number()?;
+    if kind == "statistics" {
+        let rows = fields.number()?;
+        statistics.insert(
+            table,
+            TableStatistics {
+                rows,
+                columns: Vec::new(),
+            },
+        );
+        return Some(());
+    }
+    let columns = &mut statistics.get_mut(&table)?.columns;
+    if kind == "column_statistics" {
+        columns.push(ColumnStatistics {
+            distinct: fields.number()?,
+            nulls: fields.number()?,
+            min: fields.value().ok()?,
+            max: fields.value().ok()?,
+            histogram: Vec::new(),
+        });
+    } else {
+        columns.last_mut()?.histogram.push(HistogramBucket {
+            rows: fields.number()?,
+            distinct: fields.number()?,
+            upper: fields.value().ok()??,
+        });
+    }
+    Some(())
+}
+
+/// A value as its quoted type and text, or `-` if there is none.
+///
+/// Blobs are written as hex digits, and every other value as it displays, which casting the
+/// text back to its type restores.
+fn encode_value(value: Option<&Value>) -> String {
+    match value {
+        Some(value) => match value.value_type() {
+            Some(value_type) => {
+                let text = match value {
+                    Value::Blob(bytes) => bytes.iter().fold(String::new(), |mut text, byte| {
+                        write!(text, "{byte:02x}").expect("Write Catalog");
+                        text
+                    }),
+                    value => value.to_string(),
+                };
+                format!("{} {}", quote(&value_type.to_string()), quote(&text))
+            }
+            None => "-".to_string(),
+        },
+        None => "-".to_string(),
+    }
+}
+
+/// Positions, each preceded by a space.
+fn positions(positions: &[usize]) -> String {
+    positions.iter().fold(String::new(), |mut text, position| {
+        write!(text, " {position}").expect("Write Catalog");
+        text
+    })
+}
+
+/// A quoted name, or `-` if there is none.
+fn optional(name: Option<&str>) -> String {
+    name.map_or_else(|| "-".to_string(), quote)
+}
+
+/// Quote text so it's read back as one field, whatever it holds.
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Field of a record, a bare word or quoted text.
+#[derive(Debug)]
+enum Field<'a> {
+    Word(&'a str),
+    Quoted(String),
+}
+
+/// Fields of a record, taken from the front.
+#[derive(Debug)]
+struct Fields<'a> {
+    fields: std::vec::IntoIter<Field<'a>>,
+}
+
+impl<'a> Fields<'a> {
+    /// Split a line into fields, or `None` if a quote isn't closed.
+    fn split(line: &'a str) -> Option<Fields<'a>> {
+        let mut fields = Vec::new();
+        let mut rest = line;
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let mut text = String::new();
+                let mut chars = quoted.char_indices();
+                let end = loop {
+                    match chars.next()? {
+                        (index, '"') => break index + 1,
+                        (_, '\\') => match chars.next()?.1 {
+                            'n' => text.push('\n'),
+                            'r' => text.push('\r'),
+                            c => text.push(c),
+                        },
+                        (_, c) => text.push(c),
+                    }
+                };
+                fields.push(Field::Quoted(text));
+                rest = &quoted[end..];
+            } else {
+                let end = rest.find(' ').unwrap_or(rest.len());
+                fields.push(Field::Word(&rest[..end]));
+                rest = &rest[end..];
+            }
+        }
+        Some(Fields {
+            fields: fields.into_iter(),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.fields.len() == 0
+    }
+
+    fn word(&mut self) -> Option<&'a str> {
+        match self.fields.next()? {
+            Field::Word(word) => Some(word),
+            Field::Quoted(_) => None,
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Option<T> {
+        self.word()?.parse().ok()
+    }
+
+    /// Numbers up to the end of the record.
+    fn numbers<T: std::str::FromStr>(&mut self) -> Option<Vec<T>> {
+        let mut numbers = Vec::new();
+        while !self.is_empty() {
+            numbers.push(self.number()?);
+        }
+        Some(numbers)
+    }
+
+    fn quoted(&mut self) -> Option<String> {
+        match self.fields.next()? {
+            Field::Quoted(text) => Some(text),
+            Field::Word(_) => None,
+        }
+    }
+
+    /// Quoted text, or `None` if the field is `-`.
+    fn optional(&mut self) -> Result<Option<String>, ()> {
+        match self.fields.next() {
+            Some(Field::Quoted(text)) => Ok(Some(text)),
+            Some(Field::Word("-")) => Ok(None),
+            _ => Err(()),
+        }
+    }
+
+    /// Value written by `encode_value`, or `None` if the field is `-`.
+    fn value(&mut self) -> Result<Option<Value>, ()> {
+        let Some(value_type) = self.optional()? else {
+            return Ok(None);
+        };
+        let text = self.quoted().ok_or(())?;
+        let value = match Parser::parse_data_type(&value_type).map_err(|_| ())? {
+            DataType::Blob => Value::Blob(
+                (0..text.len())
+                    .step_by(2)
+                    .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+                    .collect::<Option<_>>()
+                    .ok_or(())?,
+            ),
+            DataType::Text => Value::Text(text),
+            data_type => Value::Text(text).cast(data_type).map_err(|_| ())?,
+        };
+        Ok(Some(value))
+    }
+
+    /// Check every field was taken.
+    fn end(&self) -> Option<()> {
+        self.is_empty().then_some(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CatalogSnapshot;
+    use crate::{
+        ColumnSchema, ColumnStatistics, Constraint, DatabaseSchema, HistogramBucket, IndexSchema,
+        TableSchema, TableStatistics,
+    };
+    use minql_lang::ast::DataType;
+    use minql_lang::Parser;
+    use minql_value::{Decimal, Timestamp, Value};
+    use std::cmp::Ordering;
+    use std::sync::Arc;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_snapshot_encoding() {
+        let mut snapshot = CatalogSnapshot {
+            next_id: 4,
+            ..CatalogSnapshot::default()
+        };
+        snapshot.databases.insert(
+            "sales".to_string(),
+            Arc::new(DatabaseSchema {
+                id: 1,
+                name: "sales".to_string(),
+                uri: "mem:///data/sales".to_string(),
+            }),
+        );
+        let mut table = TableSchema::new("Odd \"Name\"\\\n")
+            .with_column(ColumnSchema::new("id", DataType::BigInt).with_nullable(false))
+            .with_column(
+                ColumnSchema::new("price", DataType::Decimal(Some((10, Some(2)))))
+                    .with_default(Parser::parse_expr("'it''s' || 1").unwrap()),
+            )
+            .with_primary_key(vec![0])
+            .with_constraint(Constraint::Unique {
+                name: Some("u".to_string()),
+                columns: vec![0, 1],
+            })
+            .with_constraint(Constraint::Check {
+                name: None,
+                expr: Parser::parse_expr("price > 0 AND id <> -1").unwrap(),
+            })
+            .with_constraint(Constraint::ForeignKey {
+                name: None,
+                columns: vec![1],
+                table: "prices".to_string(),
+                referred_columns: vec!["amount".to_string()],
+            });
+        table.id = 2;
+        table.database = "sales".to_string();
+        table.uri = "mem:///data/sales/t2".to_string();
+        snapshot.tables.insert(2, Arc::new(table));
+        snapshot.indexes.insert(
+            3,
+            Arc::new(IndexSchema {
+                id: 3,
+                database: "sales".to_string(),
+                name: "by price".to_string(),
+                table: 2,
+                columns: vec![1],
+                unique: false,
+                uri: "mem:///data/sales/i3".to_string(),
+            }),
+        );
+        snapshot.rebuild();
+
+        let text = snapshot.encode();
+        let decoded = CatalogSnapshot::decode(&text).unwrap();
+        assert_eq!(decoded.next_id, 4);
+        assert_eq!(decoded.databases, snapshot.databases);
+        assert_eq!(decoded.tables, snapshot.tables);
+        assert_eq!(decoded.indexes, snapshot.indexes);
+        assert_eq!(decoded.table("sales", "Odd \"Name\"\\\n").unwrap().id, 2);
+        assert_eq!(decoded.index("sales", "by price").unwrap().table, 2);
+        assert_eq!(decoded.table_indexes(2).len(), 1);
+        assert_eq!(decoded.encode(), text);
+
+        assert!(CatalogSnapshot::decode(&text[..text.len() - 4]).is_none());
+        assert!(CatalogSnapshot::decode(&text.replace("t2", "t3")).is_none());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_snapshot_statistics() {
+        let mut snapshot = CatalogSnapshot::default();
+        let bucket = |upper: Value| HistogramBucket {
+            upper,
+            rows: 2,
+            distinct: 1,
+        };
+        let values = [
+            Value::from(true),
+            Value::from(-3i16),
+            Value::from(7),
+            Value::from(i64::MIN),
+            Value::from(0.1f32),
+            Value::from(f64::NAN),
+            Value::from(Decimal::parse("-12.50").unwrap()),
+            Value::from(" it's \"odd\"\n"),
+            Value::from(vec![0, 0xab, 0xff]),
+            Value::from(Timestamp::parse("2024-02-29 12:34:56.789").unwrap()),
+        ];
+        snapshot.statistics.insert(
+            2,
+            Arc::new(TableStatistics {
+                rows: 20,
+                columns: vec![
+                    ColumnStatistics {
+                        distinct: 10,
+                        nulls: 0,
+                        min: Some(values[0].clone()),
+                        max: Some(values[9].clone()),
+                        histogram: values.iter().cloned().map(bucket).collect(),
+                    },
+                    ColumnStatistics {
+                        nulls: 20,
+                        ..ColumnStatistics::default()
+                    },
+                ],
+            }),
+        );
+        let decoded = CatalogSnapshot::decode(&snapshot.encode()).unwrap();
+        let statistics = decoded.statistics(2).unwrap();
+        assert_eq!(statistics.columns[0].histogram.len(), values.len());
+        for (bucket, value) in statistics.columns[0].histogram.iter().zip(&values) {
+            assert_eq!(bucket.upper.value_type(), value.value_type());
+            assert_eq!(bucket.upper.cmp(value), Ordering::Equal);
+        }
+        assert_eq!(statistics.columns[1].nulls, 20);
+        assert_eq!(statistics.columns[1].min, None);
+        assert_eq!(decoded.encode(), snapshot.encode());
+    }
+}
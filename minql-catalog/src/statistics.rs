@@ -0,0 +1,546 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use minql_lang::ast::DataType;
+use minql_value::Value;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Bound;
+
+/// Bits of a hash choosing its hyperloglog register.
+const REGISTER_BITS: u32 = 12;
+
+/// Number of hyperloglog registers, for a standard error of about 1.6%.
+const REGISTERS: usize = 1 << REGISTER_BITS;
+
+/// Selectivity of an equality on a column without statistics.
+const DEFAULT_EQUALITY_SELECTIVITY: f64 = 0.005;
+
+/// Selectivity of a range on a column without statistics.
+const DEFAULT_RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+
+/// Statistics of the rows of a table, gathered by `ANALYZE` with a [`StatisticsCollector`] and
+/// kept in the catalog for the planner to estimate cardinalities.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TableStatistics {
+    /// Number of rows
+    pub rows: u64,
+    /// Statistics of each column, by position
+    pub columns: Vec<ColumnStatistics>,
+}
+
+/// Statistics of the values of one column.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColumnStatistics {
+    /// Estimated number of distinct values other than `NULL`
+    pub distinct: u64,
+    /// Number of `NULL`s
+    pub nulls: u64,
+    /// Smallest value, or `None` if every value is `NULL`
+    pub min: Option<Value>,
+    /// Largest value, or `None` if every value is `NULL`
+    pub max: Option<Value>,
+    /// Equi-depth histogram of the values other than `NULL`, in order
+    pub histogram: Vec<HistogramBucket>,
+}
+
+/// Bucket of an equi-depth histogram, holding the values above the bucket before it up to
+/// `upper`.
+///
+/// Buckets hold about the same number of rows, except that a value is never split across two,
+/// so a frequent value ends a bucket of its own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistogramBucket {
+    /// Largest value in the bucket
+    pub upper: Value,
+    /// Estimated number of rows
+    pub rows: u64,
+    /// Estimated number of distinct values
+    pub distinct: u64,
+}
+
+impl TableStatistics {
+    /// Estimated fraction of rows where `column` is `NULL`.
+    #[must_use]
+    pub fn null_selectivity(&self, column: usize) -> f64 {
+        match self.columns.get(column) {
+            Some(statistics) => fraction(statistics.nulls, self.rows),
+            None => DEFAULT_EQUALITY_SELECTIVITY,
+        }
+    }
+
+    /// Estimated fraction of rows where `column` equals `value`.
+    #[must_use]
+    pub fn equality_selectivity(&self, column: usize, value: &Value) -> f64 {
+        let Some(statistics) = self.columns.get(column) else {
+            return DEFAULT_EQUALITY_SELECTIVITY;
+        };
+        let (Some(min), Some(max)) = (&statistics.min, &statistics.max) else {
+            return 0.0;
+        };
+        if value.is_null() || value < min || value > max {
+            return 0.0;
+        }
+        match statistics
+            .histogram
+            .iter()
+            .find(|bucket| value <= &bucket.upper)
+        {
+            Some(bucket) => fraction(bucket.rows, self.rows) / count(bucket.distinct.max(1)),
+            None => {
+                fraction(self.rows - statistics.nulls, self.rows)
+                    / count(statistics.distinct.max(1))
+            }
+        }
+    }
+
+    /// Estimated fraction of rows where `column` lies between `low` and `high`.
+    #[must_use]
+    pub fn range_selectivity(&self, column: usize, low: Bound<&Value>, high: Bound<&Value>) -> f64 {
+        let Some(statistics) = self.columns.get(column) else {
+            return DEFAULT_RANGE_SELECTIVITY;
+        };
+        let below = |bound: Bound<&Value>, upper: bool| match bound {
+            Bound::Unbounded => Some(if upper { 1.0 } else { 0.0 }),
+            Bound::Included(value) if value.is_null() => None,
+            Bound::Excluded(value) if value.is_null() => None,
+            Bound::Included(value) => Some(statistics.below(value, upper)),
+            Bound::Excluded(value) => Some(statistics.below(value, !upper)),
+        };
+        match (below(low, false), below(high, true)) {
+            (Some(low), Some(high)) => {
+                (high - low).clamp(0.0, 1.0) * fraction(self.rows - statistics.nulls, self.rows)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+impl ColumnStatistics {
+    /// Estimated fraction of the values other than `NULL` below `value`, or up to it if
+    /// `inclusive`.
+    fn below(&self, value: &Value, inclusive: bool) -> f64 {
+        let total: u64 = self.histogram.iter().map(|bucket| bucket.rows).sum();
+        if total == 0 {
+            // Without a histogram, values are taken as spread evenly over their range
+            return match (&self.min, &self.max) {
+                (Some(min), Some(max)) if value > max || (inclusive && value == max) => 1.0,
+                (Some(min), Some(max)) => position(min, max, value),
+                _ => DEFAULT_RANGE_SELECTIVITY,
+            };
+        }
+        let mut below = 0.0;
+        let mut start = self.min.as_ref();
+        for bucket in &self.histogram {
+            let rows = count(bucket.rows);
+            let per_value = rows / count(bucket.distinct.max(1));
+            match value.cmp(&bucket.upper) {
+                Ordering::Greater => below += rows,
+                Ordering::Equal => {
+                    below += if inclusive { rows } else { rows - per_value };
+                    break;
+                }
+                Ordering::Less => {
+                    if let Some(start) = start {
+                        below += (rows - per_value) * position(start, &bucket.upper, value);
+                    }
+                    break;
+                }
+            }
+            start = Some(&bucket.upper);
+        }
+        (below / count(total)).clamp(0.0, 1.0)
+    }
+}
+
+/// Gathers [`TableStatistics`] over every row of a table in one pass, as `ANALYZE` does.
+///
+/// Row counts, `NULL`s and the range of each column are exact. Distinct values are estimated
+/// with a hyperloglog sketch, and histograms are built from a uniform sample of the rows, so
+/// memory stays bounded however large the table is. Tables no larger than the sample get exact
+/// distinct counts and histograms.
+///
+/// ```rust
+/// use minql_catalog::StatisticsCollector;
+/// use minql_value::Value;
+///
+/// let mut collector = StatisticsCollector::new(2).with_buckets(4);
+/// for id in 0..100 {
+///     collector.add_row(&[Value::from(id), Value::from(id % 10)]);
+/// }
+/// let statistics = collector.finish();
+/// assert_eq!(statistics.rows, 100);
+/// assert_eq!(statistics.columns[1].distinct, 10);
+/// assert_eq!(statistics.columns[0].histogram.len(), 4);
+/// ```
+#[derive(Clone, Debug)]
+pub struct StatisticsCollector {
+    sample_size: usize,
+    buckets: usize,
+    rows: u64,
+    columns: Vec<ColumnCollector>,
+    /// Uniform sample of the rows so far, by reservoir sampling
+    sample: Vec<Vec<Value>>,
+    /// State of the xorshift generator choosing sampled rows, seeded alike on every run so
+    /// `ANALYZE` of the same rows gives the same statistics
+    random: u64,
+}
+
+/// Statistics of one column gathered so far.
+#[derive(Clone, Debug)]
+struct ColumnCollector {
+    nulls: u64,
+    min: Option<Value>,
+    max: Option<Value>,
+    /// hyperloglog registers, each the longest run of leading zeros seen plus one
+    registers: Vec<u8>,
+}
+
+impl StatisticsCollector {
+    /// Default number of rows sampled for histograms.
+    pub const DEFAULT_SAMPLE_SIZE: usize = 30_000;
+    /// Default number of histogram buckets per column.
+    pub const DEFAULT_BUCKETS: usize = 100;
+
+    /// Collector of statistics for rows of `columns` values.
+    #[must_use]
+    pub fn new(columns: usize) -> StatisticsCollector {
+        StatisticsCollector {
+            sample_size: Self::DEFAULT_SAMPLE_SIZE,
+            buckets: Self::DEFAULT_BUCKETS,
+            rows: 0,
+            columns: vec![
+                ColumnCollector {
+                    nulls: 0,
+                    min: None,
+                    max: None,
+                    registers: vec![0; REGISTERS],
+                };
+                columns
+            ],
+            sample: Vec::new(),
+            random: 0x9e37_79b9_7f4a_7c15,
+        }
+    }
+
+    /// Sample up to `sample_size` rows for histograms.
+    #[must_use]
+    pub fn with_sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = sample_size.max(1);
+        self
+    }
+
+    /// Build histograms of up to `buckets` buckets.
+    #[must_use]
+    pub fn with_buckets(mut self, buckets: usize) -> Self {
+        self.buckets = buckets.max(1);
+        self
+    }
+
+    /// Add a row of the table.
+    pub fn add_row(&mut self, row: &[Value]) {
+        assert_eq!(row.len(), self.columns.len(), "Row Width");
+        self.rows += 1;
+        for (column, value) in self.columns.iter_mut().zip(row) {
+            column.add(value);
+        }
+        if self.sample.len() < self.sample_size {
+            self.sample.push(row.to_vec());
+        } else {
+            self.random ^= self.random << 13;
+            self.random ^= self.random >> 7;
+            self.random ^= self.random << 17;
+            if let Ok(slot) = usize::try_from(self.random % self.rows) {
+                if slot < self.sample_size {
+                    self.sample[slot] = row.to_vec();
+                }
+            }
+        }
+    }
+
+    /// Statistics of the rows added.
+    #[must_use]
+    pub fn finish(self) -> TableStatistics {
+        let complete = self.sample.len() as u64 == self.rows;
+        let mut samples = vec![Vec::with_capacity(self.sample.len()); self.columns.len()];
+        for row in self.sample {
+            for (values, value) in samples.iter_mut().zip(row) {
+                if !value.is_null() {
+                    values.push(value);
+                }
+            }
+        }
+        let columns = self
+            .columns
+            .into_iter()
+            .zip(samples)
+            .map(|(column, mut values)| {
+                values.sort_unstable();
+                let values_rows = self.rows - column.nulls;
+                let in_sample = distinct(&values);
+                let estimate = if complete {
+                    in_sample
+                } else {
+                    column.estimate().clamp(in_sample, values_rows)
+                };
+                ColumnStatistics {
+                    distinct: estimate,
+                    nulls: column.nulls,
+                    min: column.min,
+                    max: column.max,
+                    histogram: histogram(&values, self.buckets, values_rows, estimate),
+                }
+            })
+            .collect();
+        TableStatistics {
+            rows: self.rows,
+            columns,
+        }
+    }
+}
+
+impl ColumnCollector {
+    fn add(&mut self, value: &Value) {
+        if value.is_null() {
+            self.nulls += 1;
+            return;
+        }
+        if self.min.as_ref().is_none_or(|min| value < min) {
+            self.min = Some(value.clone());
+        }
+        if self.max.as_ref().is_none_or(|max| value > max) {
+            self.max = Some(value.clone());
+        }
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let register = usize::try_from(hash >> (64 - REGISTER_BITS)).expect("Register");
+        let rank = u8::try_from(
+            (hash << REGISTER_BITS)
+                .leading_zeros()
+                .min(64 - REGISTER_BITS)
+                + 1,
+        )
+        .expect("Rank");
+        self.registers[register] = self.registers[register].max(rank);
+    }
+
+    /// hyperloglog estimate of the distinct values added, counted exactly by linear counting
+    /// while most registers are empty.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss,
+        clippy::naive_bytecount
+    )]
+    fn estimate(&self) -> u64 {
+        let registers = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / registers);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&rank| (-f64::from(rank)).exp2())
+            .sum();
+        let estimate = alpha * registers * registers / sum;
+        let empty = self.registers.iter().filter(|&&rank| rank == 0).count();
+        if estimate <= 2.5 * registers && empty > 0 {
+            (registers * (registers / empty as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+/// Equi-depth histogram of sorted `values` sampled from `rows` rows holding `estimate`
+/// distinct values, scaling the counts of the sample up to the table.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn histogram(values: &[Value], buckets: usize, rows: u64, estimate: u64) -> Vec<HistogramBucket> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let scale = rows as f64 / values.len() as f64;
+    let distinct_scale = estimate as f64 / distinct(values) as f64;
+    let scaled = |index: usize| (index as f64 * scale).round() as u64;
+    let depth = values.len().div_ceil(buckets);
+    let mut histogram = Vec::with_capacity(buckets);
+    let mut start = 0;
+    while start < values.len() {
+        let mut end = (start + depth).min(values.len());
+        while end < values.len() && values[end] == values[end - 1] {
+            end += 1;
+        }
+        let rows = scaled(end) - scaled(start);
+        let distinct = (distinct(&values[start..end]) as f64 * distinct_scale).round() as u64;
+        histogram.push(HistogramBucket {
+            upper: values[end - 1].clone(),
+            rows,
+            distinct: distinct.clamp(1, rows.max(1)),
+        });
+        start = end;
+    }
+    histogram
+}
+
+/// Number of distinct values in sorted `values`.
+fn distinct(values: &[Value]) -> u64 {
+    let changes = values.windows(2).filter(|pair| pair[0] != pair[1]).count();
+    u64::from(!values.is_empty()) + changes as u64
+}
+
+/// Position of `value` between `start` and `end`, from 0 to 1, interpolated for numbers and
+/// timestamps and taken as halfway for other types.
+fn position(start: &Value, end: &Value, value: &Value) -> f64 {
+    if value <= start {
+        return 0.0;
+    }
+    match (number(start), number(end), number(value)) {
+        (Some(start), Some(end), Some(value)) if end > start => {
+            ((value - start) / (end - start)).clamp(0.0, 1.0)
+        }
+        _ => 0.5,
+    }
+}
+
+/// Value of a number or timestamp on a line, for interpolation.
+#[allow(clippy::cast_precision_loss)]
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Timestamp(timestamp) => Some(timestamp.micros() as f64),
+        value if value.value_type()?.is_numeric() => match value.cast(DataType::Double).ok()? {
+            Value::Double(number) => Some(number),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `part` as a fraction of `whole`, or 0 if `whole` is.
+fn fraction(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        count(part) / count(whole)
+    }
+}
+
+/// Count as a float, for estimates.
+#[allow(clippy::cast_precision_loss)]
+fn count(count: u64) -> f64 {
+    count as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ColumnStatistics, StatisticsCollector, TableStatistics};
+    use minql_value::Value;
+    use std::ops::Bound;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_statistics_complete() {
+        let mut collector = StatisticsCollector::new(3).with_buckets(10);
+        for id in 0..1000 {
+            let status = if id % 10 == 0 { "open" } else { "closed" };
+            let note = if id % 4 == 0 {
+                Value::Null
+            } else {
+                Value::from("x")
+            };
+            collector.add_row(&[Value::from(id), Value::from(status), note]);
+        }
+        let statistics = collector.finish();
+        assert_eq!(statistics.rows, 1000);
+
+        let id = &statistics.columns[0];
+        assert_eq!(id.distinct, 1000);
+        assert_eq!(id.nulls, 0);
+        assert_eq!(id.min, Some(Value::from(0)));
+        assert_eq!(id.max, Some(Value::from(999)));
+        assert_eq!(id.histogram.len(), 10);
+        assert!(id.histogram.iter().all(|bucket| bucket.rows == 100));
+        assert_eq!(id.histogram[0].upper, Value::from(99));
+
+        let status = &statistics.columns[1];
+        assert_eq!(status.distinct, 2);
+        assert_eq!(status.histogram.len(), 2);
+        assert_eq!(status.histogram[0].upper, Value::from("closed"));
+        assert_eq!(status.histogram[0].rows, 900);
+
+        let uniform = TableStatistics {
+            rows: 100,
+            columns: vec![ColumnStatistics {
+                histogram: Vec::new(),
+                ..id.clone()
+            }],
+        };
+        let low = Value::from(100);
+        let range = uniform.range_selectivity(0, Bound::Included(&low), Bound::Unbounded);
+        assert!((range - 0.9).abs() < 0.01, "{range}");
+
+        let note = &statistics.columns[2];
+        assert_eq!((note.distinct, note.nulls), (1, 250));
+        assert!((statistics.null_selectivity(2) - 0.25).abs() < 1e-9);
+
+        let equal = statistics.equality_selectivity(1, &Value::from("open"));
+        assert!((equal - 0.1).abs() < 1e-9);
+        assert!((statistics.equality_selectivity(0, &Value::from(5)) - 0.001).abs() < 1e-9);
+        assert!(statistics.equality_selectivity(0, &Value::from(5000)).abs() < 1e-9);
+        assert!(statistics.equality_selectivity(0, &Value::Null).abs() < 1e-9);
+
+        let range = |low, high| statistics.range_selectivity(0, low, high);
+        let five = Value::from(500);
+        let quarter = Value::from(250);
+        assert!((range(Bound::Unbounded, Bound::Excluded(&five)) - 0.5).abs() < 0.01);
+        assert!((range(Bound::Included(&quarter), Bound::Excluded(&five)) - 0.25).abs() < 0.01);
+        assert!((range(Bound::Included(&five), Bound::Unbounded) - 0.5).abs() < 0.01);
+        assert!(range(Bound::Included(&five), Bound::Included(&quarter)).abs() < 1e-9);
+        assert!(range(Bound::Included(&Value::Null), Bound::Unbounded).abs() < 1e-9);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_statistics_sampled() {
+        let mut collector = StatisticsCollector::new(2)
+            .with_sample_size(2000)
+            .with_buckets(20);
+        for id in 0..100_000i64 {
+            collector.add_row(&[Value::from(id), Value::from(id % 500)]);
+        }
+        let statistics = collector.finish();
+        assert_eq!(statistics.rows, 100_000);
+        let id = &statistics.columns[0];
+        assert!(id.distinct.abs_diff(100_000) < 5000, "{}", id.distinct);
+        assert_eq!(id.max, Some(Value::from(99_999i64)));
+        assert_eq!(id.histogram.len(), 20);
+        assert_eq!(
+            id.histogram.iter().map(|bucket| bucket.rows).sum::<u64>(),
+            100_000
+        );
+        let modulo = &statistics.columns[1];
+        assert!(modulo.distinct.abs_diff(500) < 25, "{}", modulo.distinct);
+
+        let high = Value::from(25_000i64);
+        let range = statistics.range_selectivity(0, Bound::Unbounded, Bound::Excluded(&high));
+        assert!((range - 0.25).abs() < 0.05, "{range}");
+        let equal = statistics.equality_selectivity(1, &Value::from(7i64));
+        assert!((equal - 0.002).abs() < 0.001, "{equal}");
+    }
+}
@@ -0,0 +1,237 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::{ShellError, ShellResult};
+use minql_catalog::{Catalog, CatalogSnapshot};
+use minql_client::Client;
+use minql_exec::{Engine, ExecError, Executor, MemoryStorage, Response, Session};
+use minql_lang::Parser;
+use minql_uri::URI;
+use minql_value::Value;
+use minql_vfs::MemoryFileSystem;
+use std::sync::Arc;
+
+/// Database the shell opened, in this process or on a server.
+#[derive(Debug)]
+pub enum Connection {
+    /// Scratch database held in memory by a local engine, gone once the shell exits
+    Memory(Session<MemoryFileSystem>),
+    /// Database of a server speaking the Postgres protocol
+    Remote(Box<Client>),
+}
+
+/// Rows returned by a statement, or just its command tag if it returns none.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Output {
+    /// Names of the columns of the rows, empty if the statement returns no rows
+    pub columns: Vec<String>,
+    /// Values of the rows
+    pub rows: Vec<Vec<Value>>,
+    /// Command tag of the statement, such as `SELECT 2` or `CREATE TABLE`
+    pub command: String,
+}
+
+impl Connection {
+    /// Open a database by URI: `mem://[name]` for a scratch database in memory, named `main`
+    /// unless given, or `minql://`, `postgres://` and `postgresql://` for a database of a
+    /// server, as parsed by [`Config`](minql_client::Config).
+    pub fn open(uri: &str) -> ShellResult<Connection> {
+        let parsed = URI::parse(uri)
+            .ok()
+            .filter(|parsed| parsed.raw.len() == uri.len())
+            .ok_or_else(|| ShellError::Usage(format!("invalid database URI {uri:?}")))?;
+        match parsed.scheme.as_ref() {
+            "mem" => {
+                let path = parsed.path.to_string();
+                let host = parsed
+                    .authority
+                    .as_ref()
+                    .map(|authority| authority.hostinfo.raw())
+                    .unwrap_or_default();
+                let name = match (path.trim_matches('/'), host.as_str()) {
+                    ("", "") => "main",
+                    ("", host) => host,
+                    (path, _) => path,
+                };
+                Connection::memory(name)
+            }
+            "minql" | "postgres" | "postgresql" => {
+                Ok(Connection::Remote(Box::new(Client::open(uri)?)))
+            }
+            scheme => Err(ShellError::Usage(format!(
+                "unsupported database URI scheme {scheme:?}, expected mem or minql"
+            ))),
+        }
+    }
+
+    /// Open an empty scratch database in memory.
+    pub fn memory(name: &str) -> ShellResult<Connection> {
+        let catalog = Catalog::open(MemoryFileSystem::new(), "/system").map_err(ExecError::from)?;
+        let mut transaction = catalog.begin();
+        transaction
+            .create_database(name, &format!("mem:///data/{name}"))
+            .map_err(ExecError::from)?;
+        transaction.commit().map_err(ExecError::from)?;
+        let engine = Engine::new(catalog, Executor::new(Arc::new(MemoryStorage::new())));
+        Ok(Connection::Memory(Session::new(Arc::new(engine), name)?))
+    }
+
+    /// Name of the database open.
+    #[must_use]
+    pub fn database(&self) -> &str {
+        match self {
+            Connection::Memory(session) => session.database(),
+            Connection::Remote(client) => client.config().database(),
+        }
+    }
+
+    /// Catalog of the database, if it can be read from the shell.
+    pub fn catalog(&self) -> ShellResult<Arc<CatalogSnapshot>> {
+        match self {
+            Connection::Memory(session) => Ok(session.engine().catalog().snapshot()),
+            Connection::Remote(_) => Err(ShellError::Unsupported(
+                "the catalog of a server can't be listed yet".to_string(),
+            )),
+        }
+    }
+
+    /// Run a statement, returning every row it returns.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn run(&mut self, sql: &str) -> ShellResult<Output> {
+        match self {
+            Connection::Memory(session) => {
+                let statement = Parser::parse_statement(sql).map_err(ExecError::from)?;
+                let prepared = session.prepare(statement)?;
+                match session.execute(&prepared, Vec::new())? {
+                    Response::Rows { columns, cursor } => {
+                        let rows = cursor.collect::<Result<Vec<_>, _>>()?;
+                        let command = match prepared.command() {
+                            "SELECT" => format!("SELECT {}", rows.len()),
+                            command => command.to_string(),
+                        };
+                        Ok(Output {
+                            columns,
+                            rows,
+                            command,
+                        })
+                    }
+                    Response::Done { rows } => Ok(Output {
+                        command: match prepared.command() {
+                            "INSERT" => format!("INSERT 0 {rows}"),
+                            command => command.to_string(),
+                        },
+                        ..Output::default()
+                    }),
+                }
+            }
+            Connection::Remote(client) => {
+                let rows = client.query(sql, &[])?;
+                Ok(Output {
+                    columns: rows.columns().to_vec(),
+                    command: rows.command().to_string(),
+                    rows: rows.iter().map(|row| row.values().to_vec()).collect(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Connection;
+    use crate::ShellError;
+    use minql_catalog::Catalog;
+    use minql_exec::{Engine, Executor, MemoryStorage};
+    use minql_server::Server;
+    use minql_value::Value;
+    use minql_vfs::MemoryFileSystem;
+    use std::sync::Arc;
+
+    fn exercise(connection: &mut Connection) {
+        assert_eq!(
+            connection
+                .run("CREATE TABLE items (id BIGINT, kind TEXT)")
+                .unwrap()
+                .command,
+            "CREATE TABLE"
+        );
+        assert_eq!(
+            connection
+                .run("INSERT INTO items VALUES (1, 'tea'), (2, NULL);")
+                .unwrap()
+                .command,
+            "INSERT 0 2"
+        );
+        let output = connection
+            .run("SELECT id, kind FROM items ORDER BY id")
+            .unwrap();
+        assert_eq!(output.columns, ["id", "kind"]);
+        assert_eq!(output.command, "SELECT 2");
+        assert_eq!(output.rows.len(), 2);
+        assert_eq!(output.rows[1][1], Value::Null);
+        assert!(connection.run("SELECT * FROM missing").is_err());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_connection() {
+        let mut connection = Connection::open("mem://").unwrap();
+        assert_eq!(connection.database(), "main");
+        exercise(&mut connection);
+        let output = connection
+            .run("SELECT kind FROM items WHERE id = 1")
+            .unwrap();
+        assert_eq!(output.rows, [[Value::from("tea")]]);
+        assert_eq!(connection.catalog().unwrap().tables("main").count(), 1);
+
+        assert_eq!(
+            Connection::open("mem:///scratch").unwrap().database(),
+            "scratch"
+        );
+        assert_eq!(
+            Connection::open("mem://scratch").unwrap().database(),
+            "scratch"
+        );
+        for uri in ["file:///tmp/shop", "not a uri"] {
+            assert!(
+                matches!(Connection::open(uri), Err(ShellError::Usage(_))),
+                "{uri}"
+            );
+        }
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_remote_connection() {
+        let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+        let mut transaction = catalog.begin();
+        transaction
+            .create_database("shop", "mem:///data/shop")
+            .unwrap();
+        transaction.commit().unwrap();
+        let engine = Engine::new(catalog, Executor::new(Arc::new(MemoryStorage::new())));
+        let server = Server::bind(Arc::new(engine), "127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        std::thread::spawn(move || server.serve());
+
+        let mut connection = Connection::open(&format!("minql://127.0.0.1:{port}/shop")).unwrap();
+        assert_eq!(connection.database(), "shop");
+        exercise(&mut connection);
+        assert!(matches!(
+            connection.catalog(),
+            Err(ShellError::Unsupported(_))
+        ));
+    }
+}
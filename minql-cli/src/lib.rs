@@ -0,0 +1,55 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Interactive Shell
+//!
+//! The `minql` binary is a shell reading SQL statements and meta-commands from a terminal,
+//! with line editing and history, running them against a [`Connection`] to a scratch database
+//! in memory or to a database of a server, and writing the rows returned in an
+//! [`OutputFormat`]: aligned tables, vertical records, CSV or JSON.
+//!
+//! ```text
+//! minql [OPTIONS] [URI]
+//! ```
+//!
+//! The [`Shell`] behind it handles one line of input at a time, so it can be driven by other
+//! front ends as well.
+
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+// rustyline depends on several releases of windows-sys, none of which this crate can pick.
+#![allow(clippy::multiple_crate_versions)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod connection;
+mod output;
+mod result;
+mod shell;
+
+pub use self::connection::{Connection, Output};
+pub use self::output::OutputFormat;
+pub use self::result::{ShellError, ShellResult};
+pub use self::shell::{Control, Shell};
@@ -0,0 +1,179 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Command line entry point of the `minql` shell.
+
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+// rustyline depends on several releases of windows-sys, none of which this crate can pick.
+#![allow(clippy::multiple_crate_versions)]
+
+use minql_cli::{Control, OutputFormat, Shell, ShellError, ShellResult};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: minql [OPTIONS] [URI]
+
+Arguments:
+  [URI]                   database to open, mem://[name] (default) or minql://[user@]host[:port]/database
+
+Options:
+  -c, --command <SQL>     run the statements given and exit
+  -f, --file <PATH>       run the statements of a file and exit
+  -F, --format <FORMAT>   output format: table (default), vertical, csv or json
+  -h, --help              print this help
+";
+
+/// Options given on the command line.
+#[derive(Default)]
+struct Options {
+    uri: Option<String>,
+    command: Option<String>,
+    file: Option<PathBuf>,
+    format: OutputFormat,
+    help: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> ShellResult<Options> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| ShellError::Usage(format!("missing value of {name}")))
+            };
+            match arg.as_str() {
+                "-c" | "--command" => options.command = Some(value(&arg)?),
+                "-f" | "--file" => options.file = Some(PathBuf::from(value(&arg)?)),
+                "-F" | "--format" => options.format = value(&arg)?.parse()?,
+                "-h" | "--help" => options.help = true,
+                _ if arg.starts_with('-') => {
+                    return Err(ShellError::Usage(format!("unknown option {arg}")))
+                }
+                _ if options.uri.is_none() => options.uri = Some(arg),
+                _ => return Err(ShellError::Usage(format!("unexpected argument {arg}"))),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("ERROR: {err}");
+            if matches!(err, ShellError::Usage(_)) {
+                eprint!("\n{USAGE}");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> ShellResult<ExitCode> {
+    let options = Options::parse(std::env::args().skip(1))?;
+    if options.help {
+        print!("{USAGE}");
+        return Ok(ExitCode::SUCCESS);
+    }
+    let uri = options.uri.as_deref().unwrap_or("mem://");
+    let mut shell = Shell::open(uri)?.with_format(options.format);
+    let mut stdout = std::io::stdout().lock();
+    if let Some(sql) = options.command {
+        shell.script(&sql, &mut stdout)?;
+    } else if let Some(path) = options.file {
+        shell.script(&std::fs::read_to_string(path)?, &mut stdout)?;
+    } else if std::io::stdin().is_terminal() {
+        drop(stdout);
+        interactive(&mut shell)?;
+    } else {
+        return piped(&mut shell, &mut stdout);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Read lines from a terminal with line editing and history, until `\q` or end of input.
+fn interactive(shell: &mut Shell) -> ShellResult<()> {
+    let mut editor = DefaultEditor::new().map_err(editor_error)?;
+    let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".minql_history"));
+    if let Some(history) = &history {
+        // No history yet the first time the shell runs.
+        let _ = editor.load_history(history);
+    }
+    println!("minql {}, type \\? for help.", env!("CARGO_PKG_VERSION"));
+    loop {
+        match editor.readline(&shell.prompt()) {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    let _ = editor.add_history_entry(line.as_str());
+                }
+                let mut stdout = std::io::stdout().lock();
+                match shell.line(&line, &mut stdout) {
+                    Ok(Control::Continue) => {}
+                    Ok(Control::Quit) => break,
+                    Err(err) => eprintln!("ERROR: {err}"),
+                }
+                stdout.flush()?;
+            }
+            Err(ReadlineError::Interrupted) => shell.reset(),
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(editor_error(err)),
+        }
+    }
+    if let Some(history) = &history {
+        if let Err(err) = editor.save_history(history) {
+            tracing::warn!("unable to save history to {}: {err}", history.display());
+        }
+    }
+    Ok(())
+}
+
+/// Read lines piped into the shell, reporting errors and carrying on, and failing at the end
+/// if any statement did.
+fn piped(shell: &mut Shell, out: &mut impl Write) -> ShellResult<ExitCode> {
+    let mut code = ExitCode::SUCCESS;
+    for line in std::io::stdin().lock().lines() {
+        let result = shell.line(&line?, out);
+        match result {
+            Ok(Control::Continue) => continue,
+            Ok(Control::Quit) => return Ok(code),
+            Err(err) => eprintln!("ERROR: {err}"),
+        }
+        code = ExitCode::FAILURE;
+    }
+    if let Err(err) = shell.finish(out) {
+        eprintln!("ERROR: {err}");
+        code = ExitCode::FAILURE;
+    }
+    Ok(code)
+}
+
+fn editor_error(err: ReadlineError) -> ShellError {
+    match err {
+        ReadlineError::Io(err) => ShellError::Io(err),
+        err => ShellError::Io(std::io::Error::other(err)),
+    }
+}
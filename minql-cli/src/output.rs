@@ -0,0 +1,309 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::{Output, ShellError};
+use minql_value::Value;
+use std::io::Write;
+
+/// How the shell writes the rows returned by statements.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum OutputFormat {
+    /// Aligned columns under a header, followed by the number of rows
+    #[default]
+    Table,
+    /// Each row as a record of one line per column
+    Vertical,
+    /// Comma separated values under a header line, as in RFC 4180
+    Csv,
+    /// Array of one JSON object per row
+    Json,
+}
+
+impl OutputFormat {
+    /// Write the rows of an output, or its command tag if it has no columns, which only the
+    /// table and vertical formats write.
+    pub fn write(self, output: &Output, writer: &mut impl Write) -> std::io::Result<()> {
+        if output.columns.is_empty() {
+            if matches!(self, OutputFormat::Table | OutputFormat::Vertical) {
+                writeln!(writer, "{}", output.command)?;
+            }
+            return Ok(());
+        }
+        match self {
+            OutputFormat::Table => write_table(output, writer),
+            OutputFormat::Vertical => write_vertical(output, writer),
+            OutputFormat::Csv => write_csv(output, writer),
+            OutputFormat::Json => write_json(output, writer),
+        }
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = ShellError;
+
+    fn from_str(name: &str) -> Result<OutputFormat, ShellError> {
+        match name.to_ascii_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "vertical" => Ok(OutputFormat::Vertical),
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(ShellError::Usage(format!(
+                "unknown format {name:?}, expected table, vertical, csv or json"
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Table => "table",
+            OutputFormat::Vertical => "vertical",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+        })
+    }
+}
+
+/// Text of a value in the table and vertical formats, empty for `NULL`.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Number of rows, as written below a table.
+fn count(rows: usize) -> String {
+    match rows {
+        1 => "(1 row)".to_string(),
+        rows => format!("({rows} rows)"),
+    }
+}
+
+fn write_table(output: &Output, writer: &mut impl Write) -> std::io::Result<()> {
+    let cells: Vec<Vec<String>> = output
+        .rows
+        .iter()
+        .map(|row| row.iter().map(text).collect())
+        .collect();
+    let widths: Vec<usize> = output
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(position, column)| {
+            cells
+                .iter()
+                .filter_map(|row| row.get(position))
+                .map(|cell| width(cell))
+                .fold(width(column), usize::max)
+        })
+        .collect();
+    let line = |cells: &mut dyn Iterator<Item = &str>| {
+        cells
+            .zip(&widths)
+            .map(|(cell, &width)| format!(" {cell:width$} "))
+            .collect::<Vec<_>>()
+            .join("|")
+            .trim_end()
+            .to_string()
+    };
+    writeln!(
+        writer,
+        "{}",
+        line(&mut output.columns.iter().map(String::as_str))
+    )?;
+    let rule: Vec<String> = widths.iter().map(|width| "-".repeat(width + 2)).collect();
+    writeln!(writer, "{}", rule.join("+"))?;
+    for row in &cells {
+        writeln!(writer, "{}", line(&mut row.iter().map(String::as_str)))?;
+    }
+    writeln!(writer, "{}", count(output.rows.len()))
+}
+
+fn write_vertical(output: &Output, writer: &mut impl Write) -> std::io::Result<()> {
+    let width = output
+        .columns
+        .iter()
+        .map(|column| width(column))
+        .max()
+        .unwrap_or(0);
+    for (number, row) in output.rows.iter().enumerate() {
+        writeln!(writer, "-[ RECORD {} ]", number + 1)?;
+        for (column, value) in output.columns.iter().zip(row) {
+            let value = text(value);
+            writeln!(
+                writer,
+                "{}",
+                format!("{column:width$} | {value}").trim_end()
+            )?;
+        }
+    }
+    if output.rows.is_empty() {
+        writeln!(writer, "{}", count(0))?;
+    }
+    Ok(())
+}
+
+fn write_csv(output: &Output, writer: &mut impl Write) -> std::io::Result<()> {
+    let line = |fields: Vec<String>| {
+        fields
+            .iter()
+            .map(|field| {
+                if field.contains([',', '"', '\n', '\r']) {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    field.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    writeln!(writer, "{}", line(output.columns.clone()))?;
+    for row in &output.rows {
+        writeln!(writer, "{}", line(row.iter().map(text).collect()))?;
+    }
+    Ok(())
+}
+
+fn write_json(output: &Output, writer: &mut impl Write) -> std::io::Result<()> {
+    if output.rows.is_empty() {
+        return writeln!(writer, "[]");
+    }
+    writeln!(writer, "[")?;
+    for (number, row) in output.rows.iter().enumerate() {
+        let fields: Vec<String> = output
+            .columns
+            .iter()
+            .zip(row)
+            .map(|(column, value)| format!("{}: {}", json_string(column), json_value(value)))
+            .collect();
+        let separator = if number + 1 < output.rows.len() {
+            ","
+        } else {
+            ""
+        };
+        writeln!(writer, "  {{{}}}{separator}", fields.join(", "))?;
+    }
+    writeln!(writer, "]")
+}
+
+/// JSON of a value: numbers and booleans as themselves, and anything else as a string.
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Boolean(_)
+        | Value::SmallInt(_)
+        | Value::Integer(_)
+        | Value::BigInt(_)
+        | Value::Decimal(_) => value.to_string(),
+        Value::Real(number) if number.is_finite() => value.to_string(),
+        Value::Double(number) if number.is_finite() => value.to_string(),
+        value => json_string(&value.to_string()),
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for character in text.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            character if u32::from(character) < 0x20 => {
+                let code = u32::from(character);
+                json.push_str("\\u00");
+                json.extend(
+                    [code >> 4, code & 0xf]
+                        .map(|digit| char::from_digit(digit, 16).expect("Hex Digit")),
+                );
+            }
+            character => json.push(character),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod test {
+    use super::OutputFormat;
+    use crate::Output;
+    use minql_value::Value;
+
+    fn write(format: OutputFormat, output: &Output) -> String {
+        let mut text = Vec::new();
+        format.write(output, &mut text).unwrap();
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_output_formats() {
+        let output = Output {
+            columns: vec!["id".to_string(), "kind".to_string()],
+            rows: vec![
+                vec![Value::from(1), Value::from("green, \"tea\"")],
+                vec![Value::from(20), Value::Null],
+            ],
+            command: "SELECT 2".to_string(),
+        };
+        assert_eq!(
+            write(OutputFormat::Table, &output),
+            " id | kind\n\
+             ----+--------------\n \
+             1  | green, \"tea\"\n \
+             20 |\n\
+             (2 rows)\n"
+        );
+        assert_eq!(
+            write(OutputFormat::Vertical, &output),
+            "-[ RECORD 1 ]\nid   | 1\nkind | green, \"tea\"\n-[ RECORD 2 ]\nid   | 20\nkind |\n"
+        );
+        assert_eq!(
+            write(OutputFormat::Csv, &output),
+            "id,kind\n1,\"green, \"\"tea\"\"\"\n20,\n"
+        );
+        assert_eq!(
+            write(OutputFormat::Json, &output),
+            "[\n  {\"id\": 1, \"kind\": \"green, \\\"tea\\\"\"},\n  {\"id\": 20, \"kind\": null}\n]\n"
+        );
+
+        let empty = Output {
+            rows: Vec::new(),
+            ..output
+        };
+        assert!(write(OutputFormat::Table, &empty).ends_with("(0 rows)\n"));
+        assert_eq!(write(OutputFormat::Json, &empty), "[]\n");
+
+        let done = Output {
+            command: "INSERT 0 2".to_string(),
+            ..Output::default()
+        };
+        assert_eq!(write(OutputFormat::Table, &done), "INSERT 0 2\n");
+        assert_eq!(write(OutputFormat::Csv, &done), "");
+
+        assert_eq!("JSON".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+        assert_eq!(OutputFormat::Vertical.to_string(), "vertical");
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+}
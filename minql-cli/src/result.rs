@@ -0,0 +1,78 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use minql_client::ClientError;
+use minql_exec::ExecError;
+
+/// Result Type for the Shell
+pub type ShellResult<T> = Result<T, ShellError>;
+
+/// Error Type for the Shell
+#[derive(Debug)]
+pub enum ShellError {
+    /// Command line or meta-command is malformed
+    Usage(String),
+    /// Request can't be carried out over the connection open
+    Unsupported(String),
+    /// Error running a statement in a local session
+    Exec(ExecError),
+    /// Error running a statement on a server
+    Client(ClientError),
+    /// Error writing output
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for ShellError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShellError::Usage(message) | ShellError::Unsupported(message) => {
+                write!(f, "{message}")
+            }
+            ShellError::Client(ClientError::Server { code, message }) => {
+                write!(f, "{message} ({code})")
+            }
+            err => std::fmt::Debug::fmt(err, f),
+        }
+    }
+}
+
+impl std::error::Error for ShellError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShellError::Exec(err) => Some(err),
+            ShellError::Client(err) => Some(err),
+            ShellError::Io(err) => Some(err),
+            ShellError::Usage(_) | ShellError::Unsupported(_) => None,
+        }
+    }
+}
+
+impl From<ExecError> for ShellError {
+    fn from(err: ExecError) -> Self {
+        ShellError::Exec(err)
+    }
+}
+
+impl From<ClientError> for ShellError {
+    fn from(err: ClientError) -> Self {
+        ShellError::Client(err)
+    }
+}
+
+impl From<std::io::Error> for ShellError {
+    fn from(err: std::io::Error) -> Self {
+        ShellError::Io(err)
+    }
+}
@@ -0,0 +1,467 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::{Connection, Output, OutputFormat, ShellError, ShellResult};
+use minql_lang::{LangError, Lexer, TokenKind};
+use minql_value::Value;
+use std::io::Write;
+
+/// Meta-commands understood by the shell, as listed by `\?`.
+const HELP: &str = "\
+\\?                 show this help
+\\q                 quit
+\\c URI             open another database, mem://[name] or minql://[user@]host[:port]/database
+\\l                 list databases
+\\dt                list tables
+\\di                list indexes
+\\d TABLE           describe the columns of a table
+\\x                 toggle between the table and vertical formats
+\\format [FORMAT]   show or set the output format: table, vertical, csv or json
+";
+
+/// Interactive shell running the statements and meta-commands typed into it against a
+/// [`Connection`], one line at a time.
+///
+/// Statements may span several lines and run once ended by a semicolon; several may be given
+/// on one line. Lines starting with a backslash outside a statement are meta-commands, see
+/// `\?`.
+///
+/// ```rust
+/// use minql_cli::{Control, OutputFormat, Shell};
+///
+/// let mut shell = Shell::open("mem://scratch").unwrap().with_format(OutputFormat::Csv);
+/// let mut output = Vec::new();
+/// shell.line("CREATE TABLE items (id BIGINT, kind TEXT);", &mut output).unwrap();
+/// shell.line("INSERT INTO items VALUES (1, 'tea')", &mut output).unwrap();
+/// assert_eq!(shell.prompt(), "scratch-> ");
+/// shell.line(";", &mut output).unwrap();
+/// shell.line("SELECT kind FROM items;", &mut output).unwrap();
+/// assert_eq!(String::from_utf8(output).unwrap(), "kind\ntea\n");
+/// assert!(matches!(shell.line("\\q", &mut Vec::new()), Ok(Control::Quit)));
+/// ```
+#[derive(Debug)]
+pub struct Shell {
+    connection: Connection,
+    format: OutputFormat,
+    /// Text of a statement not yet ended by a semicolon
+    pending: String,
+}
+
+/// Whether the shell keeps reading lines after one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Control {
+    /// Read the next line
+    Continue,
+    /// Quit the shell
+    Quit,
+}
+
+impl Shell {
+    /// Open a shell on a database, see [`Connection::open`].
+    pub fn open(uri: &str) -> ShellResult<Shell> {
+        Ok(Shell::new(Connection::open(uri)?))
+    }
+
+    /// Create a shell on an open connection, writing rows as tables.
+    #[must_use]
+    pub fn new(connection: Connection) -> Shell {
+        Shell {
+            connection,
+            format: OutputFormat::default(),
+            pending: String::new(),
+        }
+    }
+
+    /// Write rows in `format`.
+    #[must_use]
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Format rows are written in.
+    #[must_use]
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Connection statements run on.
+    #[must_use]
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Prompt of the next line, `=>` after the name of the database, or `->` in the middle
+    /// of a statement.
+    #[must_use]
+    pub fn prompt(&self) -> String {
+        let marker = if self.pending.is_empty() { "=>" } else { "->" };
+        format!("{}{marker} ", self.connection.database())
+    }
+
+    /// Forget the statement being typed.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Handle a line typed in: run a meta-command, or every statement the line ends,
+    /// stopping at the first that fails.
+    pub fn line(&mut self, line: &str, out: &mut impl Write) -> ShellResult<Control> {
+        if self.pending.is_empty() {
+            if let Some(command) = line.trim().strip_prefix('\\') {
+                return self.meta(command, out);
+            }
+        } else {
+            self.pending.push('\n');
+        }
+        self.pending.push_str(line);
+        let (statements, rest) = split(&self.pending);
+        self.pending = rest;
+        for statement in statements {
+            if let Err(err) = self.statement(&statement, out) {
+                self.pending.clear();
+                return Err(err);
+            }
+        }
+        Ok(Control::Continue)
+    }
+
+    /// Run every statement of a script, including a last one not ended by a semicolon.
+    pub fn script(&mut self, sql: &str, out: &mut impl Write) -> ShellResult<()> {
+        self.reset();
+        self.line(sql, out)?;
+        self.finish(out)
+    }
+
+    /// Run the statement being typed, if any, as if ended by a semicolon.
+    pub fn finish(&mut self, out: &mut impl Write) -> ShellResult<()> {
+        let statement = std::mem::take(&mut self.pending);
+        if statement.is_empty() {
+            return Ok(());
+        }
+        self.statement(&statement, out)
+    }
+
+    fn statement(&mut self, sql: &str, out: &mut impl Write) -> ShellResult<()> {
+        let output = self.connection.run(sql)?;
+        self.format.write(&output, out)?;
+        Ok(())
+    }
+
+    /// Run a meta-command, given without its backslash.
+    #[tracing::instrument(level = "debug", skip(self, out))]
+    fn meta(&mut self, command: &str, out: &mut impl Write) -> ShellResult<Control> {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let argument = words.next();
+        if let Some(extra) = words.next() {
+            return Err(ShellError::Usage(format!(
+                "unexpected argument {extra:?} of \\{name}"
+            )));
+        }
+        match (name, argument) {
+            ("q" | "quit", None) => return Ok(Control::Quit),
+            ("?", None) => write!(out, "{HELP}")?,
+            ("c" | "connect", Some(uri)) => {
+                self.connection = Connection::open(uri)?;
+                writeln!(out, "Connected to database {}.", self.connection.database())?;
+            }
+            ("l", None) => self.list_databases(out)?,
+            ("dt" | "d", None) => self.list_tables(out)?,
+            ("di", None) => self.list_indexes(out)?,
+            ("d", Some(table)) => self.describe(table, out)?,
+            ("x", None) => {
+                self.format = match self.format {
+                    OutputFormat::Vertical => OutputFormat::Table,
+                    _ => OutputFormat::Vertical,
+                };
+                writeln!(out, "Output format is {}.", self.format)?;
+            }
+            ("format", None) => writeln!(out, "Output format is {}.", self.format)?,
+            ("format", Some(format)) => {
+                self.format = format.parse()?;
+                writeln!(out, "Output format is {}.", self.format)?;
+            }
+            _ => {
+                return Err(ShellError::Usage(format!(
+                    "invalid meta-command \\{command}, see \\? for help"
+                )))
+            }
+        }
+        Ok(Control::Continue)
+    }
+
+    fn list_databases(&self, out: &mut impl Write) -> ShellResult<()> {
+        let catalog = self.connection.catalog()?;
+        let rows = catalog
+            .databases()
+            .map(|database| {
+                vec![
+                    Value::from(database.name.as_str()),
+                    Value::from(database.uri.as_str()),
+                ]
+            })
+            .collect();
+        self.list(&["name", "uri"], rows, out)
+    }
+
+    fn list_tables(&self, out: &mut impl Write) -> ShellResult<()> {
+        let catalog = self.connection.catalog()?;
+        let mut rows: Vec<Vec<Value>> = catalog
+            .tables(self.connection.database())
+            .map(|table| {
+                let rows = catalog
+                    .statistics(table.id)
+                    .map(|statistics| i64::try_from(statistics.rows).unwrap_or(i64::MAX));
+                vec![
+                    Value::from(table.name.as_str()),
+                    Value::from(i64::try_from(table.columns.len()).unwrap_or(i64::MAX)),
+                    Value::from(rows),
+                ]
+            })
+            .collect();
+        rows.sort();
+        self.list(&["name", "columns", "analyzed rows"], rows, out)
+    }
+
+    fn list_indexes(&self, out: &mut impl Write) -> ShellResult<()> {
+        let catalog = self.connection.catalog()?;
+        let mut rows = Vec::new();
+        for table in catalog.tables(self.connection.database()) {
+            for index in catalog.table_indexes(table.id) {
+                let columns: Vec<&str> = index
+                    .columns
+                    .iter()
+                    .map(|&position| table.columns[position].name.as_str())
+                    .collect();
+                rows.push(vec![
+                    Value::from(index.name.as_str()),
+                    Value::from(table.name.as_str()),
+                    Value::from(columns.join(", ")),
+                    Value::from(index.unique),
+                ]);
+            }
+        }
+        rows.sort();
+        self.list(&["name", "table", "columns", "unique"], rows, out)
+    }
+
+    fn describe(&self, name: &str, out: &mut impl Write) -> ShellResult<()> {
+        let catalog = self.connection.catalog()?;
+        let table = catalog
+            .table(self.connection.database(), &name.to_lowercase())
+            .or_else(|| catalog.table(self.connection.database(), name))
+            .ok_or_else(|| ShellError::Usage(format!("no table {name:?}")))?;
+        let rows = table
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(position, column)| {
+                vec![
+                    Value::from(column.name.as_str()),
+                    Value::from(column.data_type.to_string()),
+                    Value::from(column.nullable),
+                    Value::from(column.default.as_ref().map(ToString::to_string)),
+                    Value::from(table.primary_key.contains(&position)),
+                ]
+            })
+            .collect();
+        self.list(
+            &["column", "type", "nullable", "default", "primary key"],
+            rows,
+            out,
+        )
+    }
+
+    /// Write rows listing the catalog in the format of the shell.
+    fn list(
+        &self,
+        columns: &[&str],
+        rows: Vec<Vec<Value>>,
+        out: &mut impl Write,
+    ) -> ShellResult<()> {
+        let output = Output {
+            columns: columns.iter().map(ToString::to_string).collect(),
+            command: format!("SELECT {}", rows.len()),
+            rows,
+        };
+        self.format.write(&output, out)?;
+        Ok(())
+    }
+}
+
+/// Split the statements ended by semicolons off the front of text, returning them and the
+/// text left, which is empty if it holds nothing but whitespace and comments.
+///
+/// Text the lexer can't read past is left whole unless it ends with a semicolon, in which
+/// case it is one statement the parser will report the error of.
+fn split(text: &str) -> (Vec<String>, String) {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut blank = true;
+    for token in Lexer::new(text) {
+        match token {
+            Ok(token) => match token.kind {
+                TokenKind::Semicolon => {
+                    if !blank {
+                        statements.push(text[start..token.span.end].to_string());
+                    }
+                    start = token.span.end;
+                    blank = true;
+                }
+                TokenKind::Comment(_) => {}
+                _ => blank = false,
+            },
+            Err(
+                LangError::UnterminatedString(_)
+                | LangError::UnterminatedIdentifier(_)
+                | LangError::UnterminatedComment(_),
+            ) => return (statements, text[start..].to_string()),
+            Err(_) => {
+                let rest = &text[start..];
+                if rest.trim_end().ends_with(';') {
+                    statements.push(rest.to_string());
+                    return (statements, String::new());
+                }
+                return (statements, rest.to_string());
+            }
+        }
+    }
+    let rest = if blank { "" } else { &text[start..] };
+    (statements, rest.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{split, Control, Shell};
+    use crate::{OutputFormat, ShellError};
+
+    fn run(shell: &mut Shell, lines: &[&str]) -> String {
+        let mut out = Vec::new();
+        for line in lines {
+            shell.line(line, &mut out).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_statement_splitting() {
+        assert_eq!(
+            split("SELECT 1; SELECT 2"),
+            (vec!["SELECT 1;".to_string()], " SELECT 2".to_string())
+        );
+        assert_eq!(
+            split("SELECT ';' ; -- done; really\n ;"),
+            (vec!["SELECT ';' ;".to_string()], String::new())
+        );
+        assert_eq!(
+            split("SELECT 'open;"),
+            (Vec::new(), "SELECT 'open;".to_string())
+        );
+        assert_eq!(
+            split("SELECT /* ; */ 1"),
+            (Vec::new(), "SELECT /* ; */ 1".to_string())
+        );
+        assert_eq!(
+            split("SELECT # ;"),
+            (vec!["SELECT # ;".to_string()], String::new())
+        );
+        assert_eq!(split("  -- nothing"), (Vec::new(), String::new()));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_shell_session() {
+        let mut shell = Shell::open("mem://").unwrap();
+        assert_eq!(shell.prompt(), "main=> ");
+        let out = run(
+            &mut shell,
+            &[
+                "CREATE TABLE items (",
+                "  id BIGINT PRIMARY KEY,",
+                "  kind TEXT NOT NULL DEFAULT 'tea'",
+                ");",
+                "CREATE UNIQUE INDEX items_kind ON items (kind);",
+                "INSERT INTO items VALUES (1, 'tea'), (2, 'cake'); SELECT kind",
+                "FROM items ORDER BY id;",
+            ],
+        );
+        assert_eq!(
+            out,
+            "CREATE TABLE\nCREATE INDEX\nINSERT 0 2\n kind\n------\n tea\n cake\n(2 rows)\n"
+        );
+
+        shell.line("SELECT", &mut Vec::new()).unwrap();
+        assert_eq!(shell.prompt(), "main-> ");
+        shell.reset();
+        assert_eq!(shell.prompt(), "main=> ");
+
+        let out = run(
+            &mut shell,
+            &["\\x", "SELECT id, kind FROM items WHERE id = 1;"],
+        );
+        assert_eq!(
+            out,
+            "Output format is vertical.\n-[ RECORD 1 ]\nid   | 1\nkind | tea\n"
+        );
+        let out = run(
+            &mut shell,
+            &["\\format csv", "\\d items", "\\dt", "\\di", "\\l"],
+        );
+        assert_eq!(
+            out,
+            "Output format is csv.\n\
+             column,type,nullable,default,primary key\n\
+             id,BIGINT,false,,true\n\
+             kind,TEXT,false,'tea',false\n\
+             name,columns,analyzed rows\n\
+             items,2,\n\
+             name,table,columns,unique\n\
+             items_kind,items,kind,true\n\
+             name,uri\n\
+             main,mem:///data/main\n"
+        );
+
+        let mut out = Vec::new();
+        assert!(matches!(
+            shell.line("SELECT * FROM missing; SELECT 1;", &mut out),
+            Err(ShellError::Exec(_))
+        ));
+        assert_eq!(shell.prompt(), "main=> ");
+        for command in [
+            "\\format yaml",
+            "\\frobnicate",
+            "\\d items extra",
+            "\\d missing",
+        ] {
+            assert!(
+                matches!(shell.line(command, &mut out), Err(ShellError::Usage(_))),
+                "{command}"
+            );
+        }
+        assert!(out.is_empty());
+
+        shell
+            .script("SELECT id FROM items WHERE id = 2", &mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "id\n2\n");
+
+        let out = run(&mut shell, &["\\c mem://other"]);
+        assert_eq!(out, "Connected to database other.\n");
+        assert_eq!(shell.format(), OutputFormat::Csv);
+        assert_eq!(shell.line("\\q", &mut Vec::new()).unwrap(), Control::Quit);
+    }
+}
@@ -0,0 +1,300 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::{Client, ClientError, ClientResult, Config, Rows, Statement};
+use minql_value::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Operation waiting for the worker of a client.
+type Job = Box<dyn FnOnce(&mut Client) + Send>;
+
+/// Asynchronous connection to a server, whose statements run on a worker thread owning a
+/// [`Client`].
+///
+/// The worker blocks on the connection rather than on the executor, so futures can be awaited
+/// on any executor, or driven to completion with `minql_vfs::block_on`. Statements run one at a
+/// time in the order they were issued, whether or not their futures are awaited in that order;
+/// clones of a client share its worker, which stops once every clone is dropped.
+///
+/// ```rust,no_run
+/// use minql_client::{AsyncClient, Config};
+/// use minql_value::Value;
+///
+/// # async fn example() -> minql_client::ClientResult<()> {
+/// let client = AsyncClient::connect(Config::parse("minql://localhost/shop")?).await?;
+/// let rows = client.query("SELECT kind FROM items WHERE id = $1", vec![Value::from(1)]).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct AsyncClient {
+    jobs: Sender<Job>,
+}
+
+impl AsyncClient {
+    /// Connect to a server and start a session as configured.
+    pub fn connect(config: Config) -> impl Future<Output = ClientResult<AsyncClient>> + Send {
+        let (task, done) = Task::new();
+        let (jobs, receiver) = mpsc::channel();
+        start_worker(move || match Client::connect(&config) {
+            Ok(client) => {
+                done.complete(Ok(AsyncClient { jobs }));
+                Some((client, receiver))
+            }
+            Err(err) => {
+                done.complete(Err(err));
+                None
+            }
+        });
+        task
+    }
+
+    /// Run statements separated by semicolons, see [`Client::batch`].
+    pub fn batch(&self, sql: &str) -> impl Future<Output = ClientResult<()>> + Send {
+        let sql = sql.to_string();
+        self.spawn(move |client| client.batch(&sql))
+    }
+
+    /// Run a statement returning rows, see [`Client::query`].
+    pub fn query(
+        &self,
+        sql: &str,
+        params: Vec<Value>,
+    ) -> impl Future<Output = ClientResult<Rows>> + Send {
+        let sql = sql.to_string();
+        self.spawn(move |client| client.query(&sql, &params))
+    }
+
+    /// Run a statement changing rows, see [`Client::execute`].
+    pub fn execute(
+        &self,
+        sql: &str,
+        params: Vec<Value>,
+    ) -> impl Future<Output = ClientResult<u64>> + Send {
+        let sql = sql.to_string();
+        self.spawn(move |client| client.execute(&sql, &params))
+    }
+
+    /// Prepare a statement, see [`Client::prepare`].
+    pub fn prepare(&self, sql: &str) -> impl Future<Output = ClientResult<Statement>> + Send {
+        let sql = sql.to_string();
+        self.spawn(move |client| client.prepare(&sql))
+    }
+
+    /// Run a prepared statement returning rows, see [`Client::query_prepared`].
+    pub fn query_prepared(
+        &self,
+        statement: Statement,
+        params: Vec<Value>,
+    ) -> impl Future<Output = ClientResult<Rows>> + Send {
+        self.spawn(move |client| client.query_prepared(&statement, &params))
+    }
+
+    /// Run a prepared statement changing rows, see [`Client::execute_prepared`].
+    pub fn execute_prepared(
+        &self,
+        statement: Statement,
+        params: Vec<Value>,
+    ) -> impl Future<Output = ClientResult<u64>> + Send {
+        self.spawn(move |client| client.execute_prepared(&statement, &params))
+    }
+
+    /// Queue an operation on the client behind those issued before it.
+    fn spawn<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&mut Client) -> ClientResult<T> + Send + 'static,
+    ) -> Task<T> {
+        let (task, done) = Task::new();
+        // A worker that stopped drops the operation, failing its task.
+        let _ = self
+            .jobs
+            .send(Box::new(move |client| done.complete(operation(client))));
+        task
+    }
+}
+
+impl From<Client> for AsyncClient {
+    fn from(client: Client) -> Self {
+        let (jobs, receiver) = mpsc::channel();
+        start_worker(move || Some((client, receiver)));
+        AsyncClient { jobs }
+    }
+}
+
+/// Start a worker thread running the jobs of the client `connect` opens, in the order they were
+/// sent, until every sender is dropped.
+fn start_worker(connect: impl FnOnce() -> Option<(Client, Receiver<Job>)> + Send + 'static) {
+    std::thread::Builder::new()
+        .name("minql-client".to_string())
+        .spawn(move || {
+            if let Some((mut client, jobs)) = connect() {
+                for job in jobs {
+                    job(&mut client);
+                }
+            }
+        })
+        .expect("Spawned Thread");
+}
+
+/// Future of an operation run by a worker, resolving to its output.
+struct Task<T> {
+    state: Arc<Mutex<TaskState<T>>>,
+}
+
+/// Completes a [`Task`], or fails it if dropped first.
+struct Completion<T> {
+    state: Arc<Mutex<TaskState<T>>>,
+    completed: bool,
+}
+
+struct TaskState<T> {
+    output: Option<ClientResult<T>>,
+    waker: Option<Waker>,
+}
+
+impl<T> Task<T> {
+    /// Create a task and the handle completing it.
+    fn new() -> (Task<T>, Completion<T>) {
+        let state = Arc::new(Mutex::new(TaskState {
+            output: None,
+            waker: None,
+        }));
+        let completion = Completion {
+            state: state.clone(),
+            completed: false,
+        };
+        (Task { state }, completion)
+    }
+}
+
+impl<T> Completion<T> {
+    /// Resolve the task to `output`.
+    fn complete(mut self, output: ClientResult<T>) {
+        self.finish(output);
+    }
+
+    fn finish(&mut self, output: ClientResult<T>) {
+        self.completed = true;
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        state.output = Some(output);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.finish(Err(ClientError::Io(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "client worker stopped",
+            ))));
+        }
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = ClientResult<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<ClientResult<T>> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        if let Some(output) = state.output.take() {
+            return Poll::Ready(output);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AsyncClient;
+    use crate::client::test::serve;
+    use minql_value::Value;
+    use minql_vfs::block_on;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_async_client() {
+        let config = serve();
+        block_on(async move {
+            let client = AsyncClient::connect(config).await.unwrap();
+            client
+                .batch("CREATE TABLE items (id BIGINT, kind TEXT)")
+                .await
+                .unwrap();
+            let inserts: Vec<_> = (0..4)
+                .map(|id| {
+                    client.execute(
+                        "INSERT INTO items VALUES ($1, $2)",
+                        vec![Value::from(id), Value::from(format!("kind {id}"))],
+                    )
+                })
+                .collect();
+            for insert in inserts {
+                assert_eq!(insert.await.unwrap(), 1);
+            }
+            let select = client
+                .prepare("SELECT kind FROM items WHERE id = $1")
+                .await
+                .unwrap();
+            let rows = client
+                .query_prepared(select, vec![Value::from(2)])
+                .await
+                .unwrap();
+            assert_eq!(rows[0].get::<String>(0).unwrap(), "kind 2");
+            let rows = client
+                .query("SELECT id FROM items", Vec::new())
+                .await
+                .unwrap();
+            assert_eq!(rows.len(), 4);
+            let err = client
+                .execute("DELETE FROM items", Vec::new())
+                .await
+                .unwrap_err();
+            assert_eq!(err.code(), Some("0A000"));
+        });
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_async_client_order() {
+        let config = serve();
+        block_on(async move {
+            let client = AsyncClient::connect(config).await.unwrap();
+            let create = client.batch("CREATE TABLE items (id BIGINT)");
+            let inserts: Vec<_> = (0..20)
+                .map(|id| client.execute("INSERT INTO items VALUES ($1)", vec![Value::from(id)]))
+                .collect();
+            let select = client.clone().query("SELECT id FROM items", Vec::new());
+            // Statements run in the order they were issued, not the order they're awaited
+            let rows = select.await.unwrap();
+            let ids = rows
+                .iter()
+                .map(|row| row.get::<i64>(0).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(ids, (0..20).collect::<Vec<_>>());
+            for insert in inserts.into_iter().rev() {
+                assert_eq!(insert.await.unwrap(), 1);
+            }
+            create.await.unwrap();
+        });
+    }
+}
@@ -0,0 +1,568 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::message::{Backend, Frontend};
+use crate::{ClientError, ClientResult, Config, Rows};
+use minql_value::Value;
+use std::collections::HashMap;
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+/// Connection to a server, running one statement at a time.
+///
+/// Statements given as text are sent by the extended query protocol, with the types of their
+/// parameters taken from the [`Value`]s bound to them. Statements made by
+/// [`prepare`](Client::prepare) are parsed once and run any number of times, but the server
+/// reads the text of their parameters without knowing their types, so numbers are read as
+/// `BIGINT` or `DECIMAL` and anything else as `TEXT`.
+///
+/// A client whose connection failed is [broken](Client::is_broken), and every later
+/// statement fails too.
+///
+/// ```rust,no_run
+/// use minql_client::Client;
+/// use minql_value::Value;
+///
+/// let mut client = Client::open("minql://tester@localhost/shop").unwrap();
+/// client.batch("CREATE TABLE items (id BIGINT, kind TEXT)").unwrap();
+/// let inserted = client
+///     .execute("INSERT INTO items VALUES ($1, $2)", &[Value::from(1), Value::from("tea")])
+///     .unwrap();
+/// assert_eq!(inserted, 1);
+/// for row in &client.query("SELECT id, kind FROM items", &[]).unwrap() {
+///     let id: i64 = row.get("id").unwrap();
+///     let kind: String = row.get("kind").unwrap();
+///     println!("{id} {kind}");
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Client {
+    config: Config,
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+    /// Parameters reported by the server, such as `server_version`
+    parameters: HashMap<String, String>,
+    /// Id and secret of the connection on the server
+    key: (u32, u32),
+    /// Number of statements prepared, naming the next
+    prepared: u64,
+    broken: bool,
+}
+
+/// Statement prepared on the server by [`Client::prepare`], run by
+/// [`Client::query_prepared`] and [`Client::execute_prepared`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Statement {
+    name: String,
+    parameters: usize,
+    columns: Vec<String>,
+}
+
+/// Messages sent by the server in answer to a series of messages.
+#[derive(Default)]
+struct Reply {
+    /// Names of the columns of the last rows described
+    columns: Vec<String>,
+    /// Rows returned
+    rows: Vec<Vec<Option<String>>>,
+    /// Command tag of the last statement that completed
+    command: String,
+    /// Number of rows changed or returned by the last statement that completed
+    changed: u64,
+    /// Types of the parameters of the statement described
+    parameters: Vec<u32>,
+}
+
+impl Client {
+    /// Connect to a server described by a connection URI, see [`Config`].
+    pub fn open(uri: &str) -> ClientResult<Client> {
+        Client::connect(&Config::parse(uri)?)
+    }
+
+    /// Connect to a server and start a session as configured.
+    #[tracing::instrument(level = "debug", skip(config), fields(host = config.host(), port = config.port()))]
+    pub fn connect(config: &Config) -> ClientResult<Client> {
+        let stream = match config.connect_timeout() {
+            Some(timeout) => {
+                let mut last = None;
+                let mut connected = None;
+                for address in (config.host(), config.port()).to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&address, timeout) {
+                        Ok(stream) => {
+                            connected = Some(stream);
+                            break;
+                        }
+                        Err(err) => last = Some(err),
+                    }
+                }
+                match (connected, last) {
+                    (Some(stream), _) => stream,
+                    (None, Some(err)) => return Err(err.into()),
+                    (None, None) => {
+                        return Err(ClientError::Config(format!(
+                            "no address for host {:?}",
+                            config.host()
+                        )))
+                    }
+                }
+            }
+            None => TcpStream::connect((config.host(), config.port()))?,
+        };
+        stream.set_nodelay(true)?;
+        let mut client = Client {
+            config: config.clone(),
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+            parameters: HashMap::new(),
+            key: (0, 0),
+            prepared: 0,
+            broken: false,
+        };
+        client.startup()?;
+        Ok(client)
+    }
+
+    /// Configuration the client connected with.
+    #[must_use]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Value of a parameter reported by the server, such as `server_version`.
+    #[must_use]
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters.get(name).map(String::as_str)
+    }
+
+    /// Id of the connection on the server.
+    #[must_use]
+    pub fn process_id(&self) -> u32 {
+        self.key.0
+    }
+
+    /// Whether the connection failed, so the client can't run statements any more.
+    #[must_use]
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Run one or more statements separated by semicolons, without parameters, stopping at
+    /// the first that fails.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn batch(&mut self, sql: &str) -> ClientResult<()> {
+        self.exchange(&[Frontend::Query(sql)])?;
+        Ok(())
+    }
+
+    /// Run a statement with the values of its parameters, returning the rows it returns.
+    #[tracing::instrument(level = "debug", skip(self, params))]
+    pub fn query(&mut self, sql: &str, params: &[Value]) -> ClientResult<Rows> {
+        let reply = self.run(sql, params)?;
+        Ok(Rows::new(reply.columns, reply.rows, reply.command))
+    }
+
+    /// Run a statement with the values of its parameters, returning the number of rows it
+    /// changed.
+    #[tracing::instrument(level = "debug", skip(self, params))]
+    pub fn execute(&mut self, sql: &str, params: &[Value]) -> ClientResult<u64> {
+        Ok(self.run(sql, params)?.changed)
+    }
+
+    /// Parse and plan a statement on the server, to be run any number of times.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn prepare(&mut self, sql: &str) -> ClientResult<Statement> {
+        let name = format!("minql_{}", self.prepared);
+        self.prepared += 1;
+        let reply = self.exchange(&[
+            Frontend::Parse {
+                name: &name,
+                query: sql,
+                types: &[],
+            },
+            Frontend::Describe {
+                kind: b'S',
+                name: &name,
+            },
+            Frontend::Sync,
+        ])?;
+        Ok(Statement {
+            name,
+            parameters: reply.parameters.len(),
+            columns: reply.columns,
+        })
+    }
+
+    /// Run a prepared statement with the values of its parameters, returning the rows it
+    /// returns.
+    pub fn query_prepared(
+        &mut self,
+        statement: &Statement,
+        params: &[Value],
+    ) -> ClientResult<Rows> {
+        let reply = self.portal(&statement.name, params)?;
+        Ok(Rows::new(reply.columns, reply.rows, reply.command))
+    }
+
+    /// Run a prepared statement with the values of its parameters, returning the number of
+    /// rows it changed.
+    pub fn execute_prepared(
+        &mut self,
+        statement: &Statement,
+        params: &[Value],
+    ) -> ClientResult<u64> {
+        Ok(self.portal(&statement.name, params)?.changed)
+    }
+
+    /// Release a prepared statement on the server.
+    pub fn close(&mut self, statement: &Statement) -> ClientResult<()> {
+        self.exchange(&[
+            Frontend::Close {
+                kind: b'S',
+                name: &statement.name,
+            },
+            Frontend::Sync,
+        ])?;
+        Ok(())
+    }
+
+    /// Send the startup message and read the answers of the server until it is ready.
+    fn startup(&mut self) -> ClientResult<()> {
+        let config = self.config.clone();
+        self.send(&[Frontend::Startup(&config.startup_parameters())])?;
+        loop {
+            match self.receive()? {
+                Backend::Authentication(0) | Backend::NoticeResponse => {}
+                Backend::Authentication(3) => {
+                    let password = config.password().ok_or_else(|| {
+                        ClientError::Config("server asked for a password".to_string())
+                    })?;
+                    self.send(&[Frontend::Password(password)])?;
+                }
+                Backend::Authentication(method) => {
+                    return Err(ClientError::Config(format!(
+                        "unsupported authentication method {method}"
+                    )))
+                }
+                Backend::ParameterStatus { name, value } => {
+                    self.parameters.insert(name, value);
+                }
+                Backend::KeyData { process, secret } => self.key = (process, secret),
+                Backend::ErrorResponse { code, message } => {
+                    self.broken = true;
+                    return Err(ClientError::Server { code, message });
+                }
+                Backend::ReadyForQuery => return Ok(()),
+                message => return Err(self.unexpected(&message)),
+            }
+        }
+    }
+
+    /// Parse a statement as the unnamed statement, declaring the types of its parameters,
+    /// and run it.
+    fn run(&mut self, sql: &str, params: &[Value]) -> ClientResult<Reply> {
+        let types: Vec<u32> = params.iter().map(oid).collect();
+        let (formats, values) = encode(params);
+        self.exchange(&[
+            Frontend::Parse {
+                name: "",
+                query: sql,
+                types: &types,
+            },
+            Frontend::Bind {
+                statement: "",
+                formats: &formats,
+                values: &values,
+            },
+            Frontend::Describe {
+                kind: b'P',
+                name: "",
+            },
+            Frontend::Execute,
+            Frontend::Sync,
+        ])
+    }
+
+    /// Bind a prepared statement into the unnamed portal and run it.
+    fn portal(&mut self, statement: &str, params: &[Value]) -> ClientResult<Reply> {
+        let (formats, values) = encode(params);
+        self.exchange(&[
+            Frontend::Bind {
+                statement,
+                formats: &formats,
+                values: &values,
+            },
+            Frontend::Describe {
+                kind: b'P',
+                name: "",
+            },
+            Frontend::Execute,
+            Frontend::Sync,
+        ])
+    }
+
+    /// Send messages and read the answers of the server until it is ready for the next,
+    /// failing with the first error it reported.
+    fn exchange(&mut self, messages: &[Frontend<'_>]) -> ClientResult<Reply> {
+        if self.broken {
+            return Err(ClientError::Protocol("connection is broken".to_string()));
+        }
+        self.send(messages)?;
+        let mut reply = Reply::default();
+        let mut error = None;
+        loop {
+            match self.receive()? {
+                Backend::ParseComplete
+                | Backend::BindComplete
+                | Backend::CloseComplete
+                | Backend::NoData
+                | Backend::EmptyQueryResponse
+                | Backend::NoticeResponse => {}
+                Backend::ParameterStatus { name, value } => {
+                    self.parameters.insert(name, value);
+                }
+                Backend::ParameterDescription(types) => reply.parameters = types,
+                Backend::RowDescription(columns) => reply.columns = columns,
+                Backend::DataRow(values) => reply.rows.push(values),
+                Backend::CommandComplete(tag) => {
+                    reply.changed = changed(&tag);
+                    reply.command = tag;
+                }
+                Backend::ErrorResponse { code, message } => {
+                    tracing::debug!(code, message, "Statement failed");
+                    error.get_or_insert(ClientError::Server { code, message });
+                }
+                Backend::ReadyForQuery => break,
+                message => return Err(self.unexpected(&message)),
+            }
+        }
+        match error {
+            Some(err) => Err(err),
+            None => Ok(reply),
+        }
+    }
+
+    fn send(&mut self, messages: &[Frontend<'_>]) -> ClientResult<()> {
+        let sent = messages
+            .iter()
+            .try_for_each(|message| message.write(&mut self.writer))
+            .and_then(|()| Ok(self.writer.flush()?));
+        self.broken |= sent.is_err();
+        sent
+    }
+
+    fn receive(&mut self) -> ClientResult<Backend> {
+        let received = Backend::read(&mut self.reader);
+        self.broken |= received.is_err();
+        received
+    }
+
+    /// Error of a message the server shouldn't have sent, breaking the connection.
+    fn unexpected(&mut self, message: &Backend) -> ClientError {
+        self.broken = true;
+        ClientError::Protocol(format!("unexpected message {message:?}"))
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        if !self.broken {
+            let _ = Frontend::Terminate.write(&mut self.writer);
+            let _ = self.writer.flush();
+        }
+    }
+}
+
+impl Statement {
+    /// Number of parameter values the statement needs.
+    #[must_use]
+    pub fn parameters(&self) -> usize {
+        self.parameters
+    }
+
+    /// Names of the columns of the rows the statement returns, empty if it returns none.
+    #[must_use]
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+}
+
+/// OID of the type of a parameter value, zero for `NULL` to leave it unspecified.
+fn oid(value: &Value) -> u32 {
+    match value {
+        Value::Null => 0,
+        Value::Boolean(_) => 16,
+        Value::Blob(_) => 17,
+        Value::BigInt(_) => 20,
+        Value::SmallInt(_) => 21,
+        Value::Integer(_) => 23,
+        Value::Text(_) => 25,
+        Value::Real(_) => 700,
+        Value::Double(_) => 701,
+        Value::Timestamp(_) => 1114,
+        Value::Decimal(_) => 1700,
+    }
+}
+
+/// Formats and encoded values of parameters: bytes of a `BLOB` in binary, and the text of
+/// anything else.
+fn encode(params: &[Value]) -> (Vec<i16>, Vec<Option<Vec<u8>>>) {
+    params
+        .iter()
+        .map(|value| match value {
+            Value::Null => (0, None),
+            Value::Blob(bytes) => (1, Some(bytes.clone())),
+            value => (0, Some(value.to_string().into_bytes())),
+        })
+        .unzip()
+}
+
+/// Number of rows counted by a command tag, its last word, or zero if it counts none.
+fn changed(tag: &str) -> u64 {
+    tag.rsplit(' ')
+        .next()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::Client;
+    use crate::{ClientError, Config};
+    use minql_catalog::Catalog;
+    use minql_exec::{Engine, Executor, MemoryStorage};
+    use minql_server::Server;
+    use minql_value::{Timestamp, Value};
+    use minql_vfs::MemoryFileSystem;
+    use std::sync::Arc;
+
+    /// Serve an engine with an empty `shop` database, returning how to connect to it.
+    pub(crate) fn serve() -> Config {
+        let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+        let mut transaction = catalog.begin();
+        transaction
+            .create_database("shop", "mem:///data/shop")
+            .unwrap();
+        transaction.commit().unwrap();
+        let engine = Engine::new(catalog, Executor::new(Arc::new(MemoryStorage::new())));
+        let server = Server::bind(Arc::new(engine), "127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+        std::thread::spawn(move || server.serve());
+        Config::new("127.0.0.1", address.port()).with_database("shop")
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_client_queries() {
+        let config = serve();
+        let uri = format!("minql://tester@127.0.0.1:{}/shop", config.port());
+        let mut client = Client::open(&uri).unwrap();
+        assert_eq!(client.parameter("server_encoding"), Some("UTF8"));
+        assert_ne!(client.process_id(), 0);
+
+        client
+            .batch(
+                "CREATE TABLE items (id BIGINT PRIMARY KEY, kind TEXT, price DECIMAL, \
+                 added TIMESTAMP, data BLOB, fresh BOOLEAN)",
+            )
+            .unwrap();
+        let added = Timestamp::parse("2024-05-06 07:08:09").unwrap();
+        let inserted = client
+            .execute(
+                "INSERT INTO items VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    Value::from(1),
+                    Value::from("tea"),
+                    Value::from(2.5),
+                    Value::from(added),
+                    Value::from(vec![0, 1, 255]),
+                    Value::from(true),
+                ],
+            )
+            .unwrap();
+        assert_eq!(inserted, 1);
+        client
+            .batch("INSERT INTO items (id, kind) VALUES (2, 'cake'); INSERT INTO items (id) VALUES (3)")
+            .unwrap();
+
+        let rows = client
+            .query(
+                "SELECT id, kind, price, added, data, fresh FROM items WHERE id < $1 ORDER BY id",
+                &[Value::from(3)],
+            )
+            .unwrap();
+        assert_eq!(
+            rows.columns(),
+            ["id", "kind", "price", "added", "data", "fresh"]
+        );
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows.command(), "SELECT 2");
+        let row = &rows[0];
+        assert_eq!(row.get::<i64>("id").unwrap(), 1);
+        assert_eq!(row.get::<String>("kind").unwrap(), "tea");
+        assert_eq!(row.get::<Value>("price").unwrap(), Value::from("2.5"));
+        assert_eq!(row.get::<Timestamp>("added").unwrap(), added);
+        assert_eq!(row.get::<Vec<u8>>("data").unwrap(), [0, 1, 255]);
+        assert!(row.get::<bool>("fresh").unwrap());
+        assert_eq!(rows[1].get::<Option<Timestamp>>("added").unwrap(), None);
+
+        let err = client.query("SELECT * FROM missing", &[]).unwrap_err();
+        assert_eq!(err.code(), Some("42P01"));
+        let err = client.batch("SELEC 1").unwrap_err();
+        assert_eq!(err.code(), Some("42601"));
+        assert!(!client.is_broken());
+        assert_eq!(
+            client
+                .query("SELECT id FROM items WHERE id = 3", &[])
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_prepared_statements() {
+        let mut client = Client::connect(&serve()).unwrap();
+        client
+            .batch("CREATE TABLE items (id BIGINT, kind TEXT)")
+            .unwrap();
+        let insert = client.prepare("INSERT INTO items VALUES ($1, $2)").unwrap();
+        assert_eq!(insert.parameters(), 2);
+        assert!(insert.columns().is_empty());
+        for (id, kind) in [(1, "tea"), (2, "cake"), (3, "bun")] {
+            let inserted = client
+                .execute_prepared(&insert, &[Value::from(id), Value::from(kind)])
+                .unwrap();
+            assert_eq!(inserted, 1);
+        }
+        let select = client
+            .prepare("SELECT kind FROM items WHERE id >= $1 ORDER BY id")
+            .unwrap();
+        assert_eq!(select.columns(), ["kind"]);
+        let rows = client.query_prepared(&select, &[Value::from(2)]).unwrap();
+        let kinds: Vec<String> = rows.iter().map(|row| row.get(0).unwrap()).collect();
+        assert_eq!(kinds, ["cake", "bun"]);
+        assert!(matches!(
+            client.query_prepared(&select, &[]),
+            Err(ClientError::Server { .. })
+        ));
+
+        client.close(&select).unwrap();
+        let err = client
+            .query_prepared(&select, &[Value::from(1)])
+            .unwrap_err();
+        assert_eq!(err.code(), Some("26000"));
+    }
+}
@@ -0,0 +1,82 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Native Client Driver
+//!
+//! A [`Client`] connects to a `minql-server`, or any server speaking version 3.0 of the
+//! Postgres frontend/backend protocol, as described by a [`Config`] parsed from a connection
+//! URI such as `minql://user@localhost:5432/shop`. It runs statements with parameters bound
+//! from [`Value`](minql_value::Value)s, returning [`Rows`] whose values are decoded into Rust
+//! types by [`Row::get`].
+//!
+//! An [`AsyncClient`] runs the statements of a client as futures of any executor, and a
+//! [`Pool`] lends out clients connected alike, connecting them as they are needed.
+//!
+//! ```rust
+//! use minql_catalog::Catalog;
+//! use minql_client::{Client, Config};
+//! use minql_exec::{Engine, Executor, MemoryStorage};
+//! use minql_server::Server;
+//! use minql_value::Value;
+//! use minql_vfs::MemoryFileSystem;
+//! use std::sync::Arc;
+//!
+//! let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+//! let mut transaction = catalog.begin();
+//! transaction.create_database("shop", "mem:///data/shop").unwrap();
+//! transaction.commit().unwrap();
+//! let engine = Engine::new(catalog, Executor::new(Arc::new(MemoryStorage::new())));
+//! let server = Server::bind(Arc::new(engine), "127.0.0.1:0").unwrap();
+//! let port = server.local_addr().unwrap().port();
+//! std::thread::spawn(move || server.serve());
+//!
+//! let mut client = Client::open(&format!("minql://tester@127.0.0.1:{port}/shop")).unwrap();
+//! client.batch("CREATE TABLE items (id BIGINT, kind TEXT)").unwrap();
+//! client
+//!     .execute("INSERT INTO items VALUES ($1, $2)", &[Value::from(1), Value::from("tea")])
+//!     .unwrap();
+//! let rows = client.query("SELECT kind FROM items WHERE id = $1", &[Value::from(1)]).unwrap();
+//! assert_eq!(rows[0].get::<String>("kind").unwrap(), "tea");
+//! ```
+
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod asynchronous;
+mod client;
+mod config;
+mod message;
+mod pool;
+mod result;
+mod row;
+
+pub use self::asynchronous::AsyncClient;
+pub use self::client::{Client, Statement};
+pub use self::config::{Config, DEFAULT_PORT};
+pub use self::pool::{Pool, PooledClient};
+pub use self::result::{ClientError, ClientResult};
+pub use self::row::{ColumnIndex, FromValue, Row, Rows};
@@ -0,0 +1,361 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::{ClientError, ClientResult};
+use std::io::{Read, Write};
+
+/// Version 3.0 of the protocol, the only one spoken.
+const PROTOCOL_VERSION: u32 = 0x0003_0000;
+
+/// Longest message accepted, counting its length but not its type.
+const MAX_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Message sent to the server.
+#[derive(Debug)]
+pub(crate) enum Frontend<'a> {
+    /// First message of a connection, with the parameters of the session
+    Startup(&'a [(&'a str, &'a str)]),
+    /// `p`, password asked for by the server, sent in the clear
+    Password(&'a str),
+    /// `Q`, statements run by the simple query protocol
+    Query(&'a str),
+    /// `P`, statement prepared under a name, empty for the unnamed statement
+    Parse {
+        /// Name of the statement
+        name: &'a str,
+        /// Text of the statement
+        query: &'a str,
+        /// Types of the parameters, zero where left unspecified
+        types: &'a [u32],
+    },
+    /// `B`, values of the parameters of a prepared statement bound into the unnamed portal
+    Bind {
+        /// Name of the prepared statement
+        statement: &'a str,
+        /// Formats of the parameters, `0` for text and `1` for binary
+        formats: &'a [i16],
+        /// Values of the parameters, `None` for `NULL`
+        values: &'a [Option<Vec<u8>>],
+    },
+    /// `D`, description of a prepared statement (`S`) or portal (`P`)
+    Describe {
+        /// `S` or `P`
+        kind: u8,
+        /// Name of the statement or portal
+        name: &'a str,
+    },
+    /// `E`, run the unnamed portal, returning every row
+    Execute,
+    /// `C`, close a prepared statement (`S`) or portal (`P`)
+    Close {
+        /// `S` or `P`
+        kind: u8,
+        /// Name of the statement or portal
+        name: &'a str,
+    },
+    /// `S`, end of a series of extended query messages
+    Sync,
+    /// `X`, end of the connection
+    Terminate,
+}
+
+/// Message sent by the server.
+#[derive(Debug)]
+pub(crate) enum Backend {
+    /// `R`, request to authenticate by a method, or `0` once authenticated
+    Authentication(u32),
+    /// `S`, value of a parameter of the server
+    ParameterStatus {
+        /// Name of the parameter
+        name: String,
+        /// Value of the parameter
+        value: String,
+    },
+    /// `K`, key queries of the connection are cancelled with
+    KeyData {
+        /// Id of the connection
+        process: u32,
+        /// Secret of the connection
+        secret: u32,
+    },
+    /// `Z`, ready for the next query
+    ReadyForQuery,
+    /// `T`, names of the columns of rows
+    RowDescription(Vec<String>),
+    /// `t`, types of the parameters of a prepared statement
+    ParameterDescription(Vec<u32>),
+    /// `n`, the statement or portal described returns no rows
+    NoData,
+    /// `D`, values of a row as text, `None` for `NULL`
+    DataRow(Vec<Option<String>>),
+    /// `C`, statement ran to completion, with its command tag
+    CommandComplete(String),
+    /// `I`, the query was empty
+    EmptyQueryResponse,
+    /// `1`
+    ParseComplete,
+    /// `2`
+    BindComplete,
+    /// `3`
+    CloseComplete,
+    /// `s`, the portal ran out of rows to return before its last row
+    PortalSuspended,
+    /// `N`, warning or notice, which is logged and otherwise ignored
+    NoticeResponse,
+    /// `E`, error with its SQLSTATE code
+    ErrorResponse {
+        /// SQLSTATE code
+        code: String,
+        /// Message of the error
+        message: String,
+    },
+}
+
+impl Frontend<'_> {
+    /// Write the message, its type byte followed by its length and body.
+    pub(crate) fn write(&self, writer: &mut impl Write) -> ClientResult<()> {
+        let mut body = Vec::new();
+        let kind = match self {
+            Frontend::Startup(parameters) => {
+                body.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+                for (name, value) in *parameters {
+                    put_string(&mut body, name);
+                    put_string(&mut body, value);
+                }
+                body.push(0);
+                writer.write_all(&length(body.len() + 4)?.to_be_bytes())?;
+                writer.write_all(&body)?;
+                return Ok(());
+            }
+            Frontend::Password(password) => {
+                put_string(&mut body, password);
+                b'p'
+            }
+            Frontend::Query(sql) => {
+                put_string(&mut body, sql);
+                b'Q'
+            }
+            Frontend::Parse { name, query, types } => {
+                put_string(&mut body, name);
+                put_string(&mut body, query);
+                put_count(&mut body, types.len())?;
+                for oid in *types {
+                    body.extend_from_slice(&oid.to_be_bytes());
+                }
+                b'P'
+            }
+            Frontend::Bind {
+                statement,
+                formats,
+                values,
+            } => {
+                put_string(&mut body, "");
+                put_string(&mut body, statement);
+                put_count(&mut body, formats.len())?;
+                for format in *formats {
+                    body.extend_from_slice(&format.to_be_bytes());
+                }
+                put_count(&mut body, values.len())?;
+                for value in *values {
+                    match value {
+                        Some(value) => {
+                            body.extend_from_slice(&length(value.len())?.to_be_bytes());
+                            body.extend_from_slice(value);
+                        }
+                        None => body.extend_from_slice(&(-1i32).to_be_bytes()),
+                    }
+                }
+                // Every column of the rows returned as text
+                body.extend_from_slice(&0u16.to_be_bytes());
+                b'B'
+            }
+            Frontend::Describe { kind, name } => {
+                body.push(*kind);
+                put_string(&mut body, name);
+                b'D'
+            }
+            Frontend::Execute => {
+                put_string(&mut body, "");
+                body.extend_from_slice(&0u32.to_be_bytes());
+                b'E'
+            }
+            Frontend::Close { kind, name } => {
+                body.push(*kind);
+                put_string(&mut body, name);
+                b'C'
+            }
+            Frontend::Sync => b'S',
+            Frontend::Terminate => b'X',
+        };
+        writer.write_all(&[kind])?;
+        writer.write_all(&length(body.len() + 4)?.to_be_bytes())?;
+        writer.write_all(&body)?;
+        Ok(())
+    }
+}
+
+impl Backend {
+    /// Read the next message of the server.
+    pub(crate) fn read(reader: &mut impl Read) -> ClientResult<Backend> {
+        let mut header = [0; 5];
+        reader.read_exact(&mut header)?;
+        let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+        let mut body = Body::read(reader, length)?;
+        let message = match header[0] {
+            b'R' => Backend::Authentication(body.u32()?),
+            b'S' => Backend::ParameterStatus {
+                name: body.string()?,
+                value: body.string()?,
+            },
+            b'K' => Backend::KeyData {
+                process: body.u32()?,
+                secret: body.u32()?,
+            },
+            b'Z' => Backend::ReadyForQuery,
+            b'T' => {
+                let columns = (0..body.u16()?)
+                    .map(|_| {
+                        let name = body.string()?;
+                        // Table, column, type, size, modifier and format of the column
+                        body.bytes(18)?;
+                        Ok(name)
+                    })
+                    .collect::<ClientResult<_>>()?;
+                Backend::RowDescription(columns)
+            }
+            b't' => Backend::ParameterDescription(
+                (0..body.u16()?)
+                    .map(|_| body.u32())
+                    .collect::<ClientResult<_>>()?,
+            ),
+            b'n' => Backend::NoData,
+            b'D' => {
+                let values = (0..body.u16()?)
+                    .map(|_| match body.u32()? {
+                        u32::MAX => Ok(None),
+                        length => {
+                            let bytes = body.bytes(length as usize)?.to_vec();
+                            String::from_utf8(bytes).map(Some).map_err(|_| {
+                                ClientError::Protocol("value is not UTF-8".to_string())
+                            })
+                        }
+                    })
+                    .collect::<ClientResult<_>>()?;
+                Backend::DataRow(values)
+            }
+            b'C' => Backend::CommandComplete(body.string()?),
+            b'I' => Backend::EmptyQueryResponse,
+            b'1' => Backend::ParseComplete,
+            b'2' => Backend::BindComplete,
+            b'3' => Backend::CloseComplete,
+            b's' => Backend::PortalSuspended,
+            b'N' => Backend::NoticeResponse,
+            b'E' => {
+                let (mut code, mut message) = (String::new(), String::new());
+                loop {
+                    match body.u8()? {
+                        0 => break,
+                        b'C' => code = body.string()?,
+                        b'M' => message = body.string()?,
+                        _ => {
+                            body.string()?;
+                        }
+                    }
+                }
+                Backend::ErrorResponse { code, message }
+            }
+            other => {
+                return Err(ClientError::Protocol(format!(
+                    "unexpected message type {:?}",
+                    char::from(other)
+                )))
+            }
+        };
+        Ok(message)
+    }
+}
+
+/// Body of a message being read.
+struct Body {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl Body {
+    /// Read the body of a message of a length, which counts the length itself.
+    fn read(reader: &mut impl Read, length: u32) -> ClientResult<Body> {
+        let length = (length as usize)
+            .checked_sub(4)
+            .filter(|&length| length <= MAX_LENGTH)
+            .ok_or_else(|| ClientError::Protocol(format!("invalid message length {length}")))?;
+        let mut data = vec![0; length];
+        reader.read_exact(&mut data)?;
+        Ok(Body { data, position: 0 })
+    }
+
+    fn bytes(&mut self, count: usize) -> ClientResult<&[u8]> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| ClientError::Protocol("message ended early".to_string()))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> ClientResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> ClientResult<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> ClientResult<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Null terminated string.
+    fn string(&mut self) -> ClientResult<String> {
+        let rest = &self.data[self.position..];
+        let end = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| ClientError::Protocol("unterminated string".to_string()))?;
+        let string = String::from_utf8(rest[..end].to_vec())
+            .map_err(|_| ClientError::Protocol("string is not UTF-8".to_string()))?;
+        self.position += end + 1;
+        Ok(string)
+    }
+}
+
+fn put_string(body: &mut Vec<u8>, string: &str) {
+    body.extend_from_slice(string.as_bytes());
+    body.push(0);
+}
+
+fn put_count(body: &mut Vec<u8>, count: usize) -> ClientResult<()> {
+    let count = u16::try_from(count)
+        .map_err(|_| ClientError::Protocol(format!("{count} fields in one message")))?;
+    body.extend_from_slice(&count.to_be_bytes());
+    Ok(())
+}
+
+fn length(length: usize) -> ClientResult<i32> {
+    i32::try_from(length).map_err(|_| ClientError::Protocol(format!("message of {length} bytes")))
+}
@@ -0,0 +1,229 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::{Client, ClientError, ClientResult, Config};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Pool of up to a number of [`Client`]s connected alike, lent out one at a time.
+///
+/// Clients are connected as they are first needed and returned to the pool when the
+/// [`PooledClient`] lending them is dropped, unless their connection broke. Once every client
+/// is lent out, [`get`](Pool::get) waits for one to be returned, failing with
+/// [`ClientError::PoolTimeout`] if given a timeout and none is returned in time.
+///
+/// ```rust,no_run
+/// use minql_client::{Config, Pool};
+/// use std::time::Duration;
+///
+/// let config = Config::parse("minql://localhost/shop").unwrap();
+/// let pool = Pool::new(config, 8).with_timeout(Duration::from_secs(5));
+/// let mut client = pool.get().unwrap();
+/// client.batch("ANALYZE").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Pool {
+    config: Config,
+    size: usize,
+    timeout: Option<Duration>,
+    state: Mutex<PoolState>,
+    /// Signalled when a client is returned or a connection slot is freed
+    returned: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    /// Clients connected and not lent out
+    idle: Vec<Client>,
+    /// Clients connected or connecting, lent out or not
+    open: usize,
+}
+
+/// Client lent out by a [`Pool`], returned to it when dropped.
+#[derive(Debug)]
+pub struct PooledClient<'pool> {
+    pool: &'pool Pool,
+    client: Option<Client>,
+}
+
+impl Pool {
+    /// Create a pool of up to `size` clients connected as configured, at least one.
+    #[must_use]
+    pub fn new(config: Config, size: usize) -> Pool {
+        Pool {
+            config,
+            size: size.max(1),
+            timeout: None,
+            state: Mutex::new(PoolState::default()),
+            returned: Condvar::new(),
+        }
+    }
+
+    /// Give up waiting for a client after `timeout`.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Configuration clients connect with.
+    #[must_use]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Most clients the pool connects.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of clients connected, lent out or not.
+    #[must_use]
+    pub fn open(&self) -> usize {
+        self.state.lock().expect("Poisoned Lock").open
+    }
+
+    /// Number of clients connected and waiting to be lent out.
+    #[must_use]
+    pub fn idle(&self) -> usize {
+        self.state.lock().expect("Poisoned Lock").idle.len()
+    }
+
+    /// Borrow an idle client, connecting a new one if there are none and the pool isn't
+    /// full, or waiting for one to be returned otherwise.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn get(&self) -> ClientResult<PooledClient<'_>> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        loop {
+            if let Some(client) = state.idle.pop() {
+                return Ok(self.lend(client));
+            }
+            if state.open < self.size {
+                state.open += 1;
+                drop(state);
+                return match Client::connect(&self.config) {
+                    Ok(client) => Ok(self.lend(client)),
+                    Err(err) => {
+                        self.release();
+                        Err(err)
+                    }
+                };
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(ClientError::PoolTimeout);
+                    }
+                    self.returned
+                        .wait_timeout(state, deadline - now)
+                        .expect("Poisoned Lock")
+                        .0
+                }
+                None => self.returned.wait(state).expect("Poisoned Lock"),
+            };
+        }
+    }
+
+    fn lend(&self, client: Client) -> PooledClient<'_> {
+        PooledClient {
+            pool: self,
+            client: Some(client),
+        }
+    }
+
+    /// Free the connection slot of a client that broke or never connected.
+    fn release(&self) {
+        self.state.lock().expect("Poisoned Lock").open -= 1;
+        self.returned.notify_one();
+    }
+}
+
+impl Deref for PooledClient<'_> {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().expect("Lent Client")
+    }
+}
+
+impl DerefMut for PooledClient<'_> {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().expect("Lent Client")
+    }
+}
+
+impl Drop for PooledClient<'_> {
+    fn drop(&mut self) {
+        let client = self.client.take().expect("Lent Client");
+        if client.is_broken() {
+            drop(client);
+            self.pool.release();
+        } else {
+            self.pool
+                .state
+                .lock()
+                .expect("Poisoned Lock")
+                .idle
+                .push(client);
+            self.pool.returned.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Pool;
+    use crate::client::test::serve;
+    use crate::{ClientError, Config};
+    use std::time::Duration;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_pool() {
+        let pool = Pool::new(serve(), 2).with_timeout(Duration::from_millis(50));
+        let mut first = pool.get().unwrap();
+        first.batch("CREATE TABLE items (id BIGINT)").unwrap();
+        let process = first.process_id();
+        let second = pool.get().unwrap();
+        assert_eq!((pool.open(), pool.idle()), (2, 0));
+        assert!(matches!(pool.get(), Err(ClientError::PoolTimeout)));
+
+        drop(first);
+        assert_eq!((pool.open(), pool.idle()), (2, 1));
+        let mut first = pool.get().unwrap();
+        assert_eq!(first.process_id(), process);
+        assert_eq!(
+            first.execute("INSERT INTO items VALUES (1)", &[]).unwrap(),
+            1
+        );
+
+        std::thread::scope(|scope| {
+            let waiting = scope.spawn(|| pool.get().map(|client| client.process_id()));
+            std::thread::sleep(Duration::from_millis(10));
+            drop(second);
+            assert!(waiting.join().unwrap().is_ok());
+        });
+        drop(first);
+        assert_eq!((pool.open(), pool.idle()), (2, 2));
+
+        let unreachable = Pool::new(Config::new("127.0.0.1", 1), 1);
+        assert!(matches!(unreachable.get(), Err(ClientError::Io(_))));
+        assert_eq!(unreachable.open(), 0);
+    }
+}
@@ -0,0 +1,92 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use minql_uri::URIError;
+use minql_value::ValueError;
+
+/// Result Type for the Client
+pub type ClientResult<T> = Result<T, ClientError>;
+
+/// Error Type for the Client
+#[derive(Debug)]
+pub enum ClientError {
+    /// Connection URI couldn't be parsed
+    Uri(URIError),
+    /// Connection URI or configuration is invalid
+    Config(String),
+    /// Server sent a message the protocol doesn't allow, breaking the connection
+    Protocol(String),
+    /// Server refused a statement, with the SQLSTATE code of the error
+    Server {
+        /// SQLSTATE code of the error
+        code: String,
+        /// Description of the error
+        message: String,
+    },
+    /// Value of a column couldn't be converted to the type asked for
+    Value(ValueError),
+    /// Column asked for doesn't exist, or holds `NULL` where a value was expected
+    Column(String),
+    /// No client of a pool became free before the pool timed out
+    PoolTimeout,
+    /// Error reading from or writing to the connection
+    Io(std::io::Error),
+}
+
+impl ClientError {
+    /// SQLSTATE code of an error reported by the server, if it is one.
+    #[must_use]
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            ClientError::Server { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Uri(err) => Some(err),
+            ClientError::Value(err) => Some(err),
+            ClientError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<URIError> for ClientError {
+    fn from(err: URIError) -> Self {
+        ClientError::Uri(err)
+    }
+}
+
+impl From<ValueError> for ClientError {
+    fn from(err: ValueError) -> Self {
+        ClientError::Value(err)
+    }
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(err: std::io::Error) -> Self {
+        ClientError::Io(err)
+    }
+}
@@ -0,0 +1,371 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::{ClientError, ClientResult};
+use minql_lang::ast::DataType;
+use minql_value::{Decimal, Timestamp, Value};
+use std::sync::Arc;
+
+/// Rows returned by a query, the names of their columns and the command tag of the query.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rows {
+    columns: Arc<[String]>,
+    records: Vec<Row>,
+    command: String,
+}
+
+/// Row returned by a query, whose values are decoded to Rust types by [`Row::get`].
+///
+/// Values are sent by the server as text, so each holds [`Value::Text`] or [`Value::Null`]
+/// until converted to the type asked for, as `CAST` does.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    columns: Arc<[String]>,
+    values: Vec<Value>,
+}
+
+/// Type a value of a column is decoded into by [`Row::get`].
+pub trait FromValue: Sized {
+    /// Decode a value, failing if it is `NULL` or doesn't convert to the type.
+    fn from_value(value: &Value) -> ClientResult<Self>;
+}
+
+/// Column of a [`Row`], by its position or its name.
+pub trait ColumnIndex: Copy + std::fmt::Debug {
+    /// Position of the column among `columns`, if it is one of them.
+    fn position(&self, columns: &[String]) -> Option<usize>;
+}
+
+impl Rows {
+    /// Rows of columns named `columns`, each row holding a value of each column, returned by
+    /// a statement completed with a command tag.
+    pub(crate) fn new(
+        columns: Vec<String>,
+        rows: Vec<Vec<Option<String>>>,
+        command: String,
+    ) -> Rows {
+        let columns: Arc<[String]> = columns.into();
+        let records = rows
+            .into_iter()
+            .map(|values| Row {
+                columns: columns.clone(),
+                values: values.into_iter().map(Value::from).collect(),
+            })
+            .collect();
+        Rows {
+            columns,
+            records,
+            command,
+        }
+    }
+
+    /// Names of the columns of the rows, in order.
+    #[must_use]
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Command tag the server completed the statement with, such as `SELECT 2` or
+    /// `CREATE TABLE`.
+    #[must_use]
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Number of rows.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Whether there are no rows.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Iterate over the rows.
+    pub fn iter(&self) -> std::slice::Iter<'_, Row> {
+        self.records.iter()
+    }
+}
+
+impl std::ops::Index<usize> for Rows {
+    type Output = Row;
+
+    fn index(&self, index: usize) -> &Row {
+        &self.records[index]
+    }
+}
+
+impl IntoIterator for Rows {
+    type Item = Row;
+    type IntoIter = std::vec::IntoIter<Row>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.records.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Rows {
+    type Item = &'a Row;
+    type IntoIter = std::slice::Iter<'a, Row>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.records.iter()
+    }
+}
+
+impl Row {
+    /// Names of the columns of the row, in order.
+    #[must_use]
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Values of the row, as sent by the server.
+    #[must_use]
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Number of values of the row.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether the row has no values.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Value of a column, by its position or name, decoded into a type.
+    ///
+    /// ```rust
+    /// # use minql_client::Rows;
+    /// # fn example(rows: &Rows) -> minql_client::ClientResult<()> {
+    /// for row in rows {
+    ///     let id: i64 = row.get(0)?;
+    ///     let kind: Option<String> = row.get("kind")?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get<T: FromValue>(&self, column: impl ColumnIndex) -> ClientResult<T> {
+        let position = column
+            .position(&self.columns)
+            .ok_or_else(|| ClientError::Column(format!("no column {column:?}")))?;
+        T::from_value(&self.values[position]).map_err(|err| match err {
+            ClientError::Column(message) => ClientError::Column(format!("{message} in {column:?}")),
+            err => err,
+        })
+    }
+}
+
+impl ColumnIndex for usize {
+    fn position(&self, columns: &[String]) -> Option<usize> {
+        (*self < columns.len()).then_some(*self)
+    }
+}
+
+impl ColumnIndex for &str {
+    fn position(&self, columns: &[String]) -> Option<usize> {
+        columns.iter().position(|column| column == self)
+    }
+}
+
+/// Value converted to a type, failing if it is `NULL`.
+fn cast(value: &Value, data_type: DataType) -> ClientResult<Value> {
+    if value.is_null() {
+        return Err(ClientError::Column("unexpected NULL".to_string()));
+    }
+    Ok(value.cast(data_type)?)
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> ClientResult<Value> {
+        Ok(value.clone())
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> ClientResult<Option<T>> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> ClientResult<bool> {
+        match cast(value, DataType::Boolean)? {
+            Value::Boolean(value) => Ok(value),
+            _ => unreachable!("cast to BOOLEAN"),
+        }
+    }
+}
+
+impl FromValue for i16 {
+    fn from_value(value: &Value) -> ClientResult<i16> {
+        match cast(value, DataType::SmallInt)? {
+            Value::SmallInt(value) => Ok(value),
+            _ => unreachable!("cast to SMALLINT"),
+        }
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: &Value) -> ClientResult<i32> {
+        match cast(value, DataType::Integer)? {
+            Value::Integer(value) => Ok(value),
+            _ => unreachable!("cast to INTEGER"),
+        }
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> ClientResult<i64> {
+        match cast(value, DataType::BigInt)? {
+            Value::BigInt(value) => Ok(value),
+            _ => unreachable!("cast to BIGINT"),
+        }
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: &Value) -> ClientResult<f32> {
+        match cast(value, DataType::Real)? {
+            Value::Real(value) => Ok(value),
+            _ => unreachable!("cast to REAL"),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> ClientResult<f64> {
+        match cast(value, DataType::Double)? {
+            Value::Double(value) => Ok(value),
+            _ => unreachable!("cast to DOUBLE"),
+        }
+    }
+}
+
+impl FromValue for Decimal {
+    fn from_value(value: &Value) -> ClientResult<Decimal> {
+        match cast(value, DataType::Decimal(None))? {
+            Value::Decimal(value) => Ok(value),
+            _ => unreachable!("cast to DECIMAL"),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> ClientResult<String> {
+        match cast(value, DataType::Text)? {
+            Value::Text(value) => Ok(value),
+            _ => unreachable!("cast to TEXT"),
+        }
+    }
+}
+
+impl FromValue for Timestamp {
+    fn from_value(value: &Value) -> ClientResult<Timestamp> {
+        match cast(value, DataType::Timestamp)? {
+            Value::Timestamp(value) => Ok(value),
+            _ => unreachable!("cast to TIMESTAMP"),
+        }
+    }
+}
+
+/// Bytes of a `BLOB`, which is sent as `\x` followed by the hex digits of its bytes.
+impl FromValue for Vec<u8> {
+    fn from_value(value: &Value) -> ClientResult<Vec<u8>> {
+        let text = match value {
+            Value::Blob(bytes) => return Ok(bytes.clone()),
+            Value::Text(text) => text,
+            value => return Err(ClientError::Column(format!("{value} is not a BLOB"))),
+        };
+        let invalid = || ClientError::Column(format!("{text:?} is not a BLOB"));
+        let digits = text.strip_prefix("\\x").ok_or_else(invalid)?;
+        if digits.len() % 2 != 0 {
+            return Err(invalid());
+        }
+        (0..digits.len())
+            .step_by(2)
+            .map(|start| u8::from_str_radix(&digits[start..start + 2], 16).map_err(|_| invalid()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Rows;
+    use crate::ClientError;
+    use minql_value::{Decimal, Timestamp, Value};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_row_decoding() {
+        let rows = Rows::new(
+            vec!["id".to_string(), "kind".to_string(), "data".to_string()],
+            vec![
+                vec![
+                    Some("7".to_string()),
+                    Some("tea".to_string()),
+                    Some("\\x0aff".to_string()),
+                ],
+                vec![
+                    Some("1.50".to_string()),
+                    None,
+                    Some("2024-01-02 03:04:05".to_string()),
+                ],
+            ],
+            "SELECT 2".to_string(),
+        );
+        assert_eq!(rows.columns(), ["id", "kind", "data"]);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows.command(), "SELECT 2");
+        let row = &rows[0];
+        assert_eq!(row.get::<i64>(0).unwrap(), 7);
+        assert_eq!(row.get::<i16>("id").unwrap(), 7);
+        assert!(row.get::<bool>("id").is_err());
+        assert_eq!(row.get::<String>("kind").unwrap(), "tea");
+        assert_eq!(row.get::<Vec<u8>>("data").unwrap(), [0x0a, 0xff]);
+        assert_eq!(row.get::<Value>(1).unwrap(), Value::from("tea"));
+        assert!(matches!(row.get::<i64>(3), Err(ClientError::Column(_))));
+        assert!(matches!(
+            row.get::<i64>("price"),
+            Err(ClientError::Column(_))
+        ));
+
+        let row = &rows[1];
+        assert_eq!(
+            row.get::<Decimal>(0).unwrap(),
+            Decimal::parse("1.50").unwrap()
+        );
+        assert!((row.get::<f64>(0).unwrap() - 1.5).abs() < f64::EPSILON);
+        assert_eq!(row.get::<i32>(0).unwrap(), 2);
+        assert_eq!(row.get::<Option<String>>(1).unwrap(), None);
+        assert!(matches!(row.get::<String>(1), Err(ClientError::Column(_))));
+        assert_eq!(
+            row.get::<Timestamp>(2).unwrap(),
+            Timestamp::parse("2024-01-02 03:04:05").unwrap()
+        );
+        assert!(row.get::<Vec<u8>>(2).is_err());
+    }
+}
@@ -0,0 +1,477 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::operator::{Node, Operator};
+use crate::spill::{partition, row_size, SpillReader, SpillSpace, SpillWriter, MAX_DEPTH};
+use crate::ExecResult;
+use minql_lang::ast::BinaryOperator;
+use minql_plan::{AggregateCall, AggregateFunction};
+use minql_value::{ScalarExpr, UdfAccumulator, Value, ValueType};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Values grouped by and the running aggregates of a group.
+type Group = (Vec<Value>, Vec<Accumulator>);
+
+/// Rows of the input grouped by the values of expressions in a hash table, returning each
+/// group's values followed by its aggregates once every row is read.
+///
+/// Once the groups exceed the memory budget, the groups held keep aggregating their rows,
+/// and the rows of other groups are split by the hash of their values into partitions
+/// written to temporary files, each aggregated in turn after the groups held are returned.
+/// Groups held are returned in the order their first rows were read.
+pub(crate) struct HashAggregateOperator {
+    input: Node,
+    group_by: Vec<ScalarExpr>,
+    aggregates: Vec<AggregateCall>,
+    spill: SpillSpace,
+    params: Arc<[Value]>,
+    groups: std::vec::IntoIter<Group>,
+    /// Partitions of rows of groups not yet aggregated, and the times they have been split
+    partitions: Vec<(SpillReader, u32)>,
+    spilled: u64,
+}
+
+/// Running value of an aggregate over the rows of a group.
+#[derive(Debug)]
+enum Accumulator {
+    Count(i64),
+    Sum(Option<Value>),
+    Min(Option<Value>),
+    Max(Option<Value>),
+    Avg(Option<Value>, i64),
+    Udf(UdfAccumulator),
+    /// Aggregate of only the arguments not seen before
+    Distinct(HashSet<Vec<Value>>, Box<Accumulator>),
+}
+
+impl HashAggregateOperator {
+    pub(crate) fn new(
+        input: Node,
+        group_by: Vec<ScalarExpr>,
+        aggregates: Vec<AggregateCall>,
+        spill: SpillSpace,
+    ) -> HashAggregateOperator {
+        HashAggregateOperator {
+            input,
+            group_by,
+            aggregates,
+            spill,
+            params: Arc::new([]),
+            groups: Vec::new().into_iter(),
+            partitions: Vec::new(),
+            spilled: 0,
+        }
+    }
+
+    /// Keep the partitions of rows of groups not held.
+    fn add_partitions(&mut self, split: Option<Vec<SpillWriter>>, depth: u32) -> ExecResult<()> {
+        for partition in split.into_iter().flatten() {
+            self.spilled += partition.rows();
+            if partition.rows() > 0 {
+                self.partitions.push((partition.finish()?, depth + 1));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Operator for HashAggregateOperator {
+    fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()> {
+        self.params = params.clone();
+        self.partitions.clear();
+        self.input.open(params)?;
+        let input = &mut self.input;
+        let (mut groups, split) = group(
+            &self.group_by,
+            &self.aggregates,
+            params,
+            &self.spill,
+            0,
+            || input.next(),
+        )?;
+        self.input.close();
+        if split.is_some() {
+            tracing::debug!(budget = self.spill.budget(), "Hash aggregate spilling");
+        }
+        self.add_partitions(split, 0)?;
+        if groups.is_empty() && self.group_by.is_empty() {
+            groups.push((Vec::new(), accumulators(&self.aggregates)));
+        }
+        self.groups = groups.into_iter();
+        Ok(())
+    }
+
+    fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        loop {
+            if let Some((mut row, accumulators)) = self.groups.next() {
+                for accumulator in accumulators {
+                    row.push(accumulator.finish()?);
+                }
+                return Ok(Some(row));
+            }
+            let Some((mut partition, depth)) = self.partitions.pop() else {
+                return Ok(None);
+            };
+            tracing::trace!(rows = partition.remaining(), depth, "Aggregating partition");
+            let (groups, split) = group(
+                &self.group_by,
+                &self.aggregates,
+                &self.params,
+                &self.spill,
+                depth,
+                || partition.next(),
+            )?;
+            self.add_partitions(split, depth)?;
+            self.groups = groups.into_iter();
+        }
+    }
+
+    fn close(&mut self) {
+        self.groups = Vec::new().into_iter();
+        self.partitions.clear();
+    }
+
+    fn children(&self) -> Vec<&Node> {
+        vec![&self.input]
+    }
+
+    fn spilled(&self) -> u64 {
+        self.spilled
+    }
+}
+
+/// Aggregate rows into groups held in memory, and once they exceed the memory budget, write
+/// the rows of groups not held to partitions returned besides.
+fn group(
+    group_by: &[ScalarExpr],
+    aggregates: &[AggregateCall],
+    params: &[Value],
+    spill: &SpillSpace,
+    depth: u32,
+    mut next: impl FnMut() -> ExecResult<Option<Vec<Value>>>,
+) -> ExecResult<(Vec<Group>, Option<Vec<SpillWriter>>)> {
+    let mut positions = HashMap::new();
+    let mut groups: Vec<Group> = Vec::new();
+    let mut split: Option<Vec<SpillWriter>> = None;
+    let mut bytes = 0;
+    while let Some(row) = next()? {
+        let key = group_by
+            .iter()
+            .map(|expr| expr.eval(&row, params))
+            .collect::<Result<Vec<_>, _>>()?;
+        let position = if let Some(&position) = positions.get(&key) {
+            position
+        } else if let Some(partitions) = &mut split {
+            partitions[partition(&key, depth)].write(&row)?;
+            continue;
+        } else {
+            bytes += 2 * row_size(&key) + aggregates.len() * std::mem::size_of::<Accumulator>();
+            positions.insert(key.clone(), groups.len());
+            groups.push((key, accumulators(aggregates)));
+            groups.len() - 1
+        };
+        let accumulators = &mut groups[position].1;
+        for (aggregate, accumulator) in aggregates.iter().zip(accumulators) {
+            let args = if aggregate.args.is_empty() {
+                vec![Value::Boolean(true)]
+            } else {
+                aggregate
+                    .args
+                    .iter()
+                    .map(|arg| arg.eval(&row, params))
+                    .collect::<Result<_, _>>()?
+            };
+            bytes += accumulator.add(args)?;
+        }
+        if split.is_none() && depth < MAX_DEPTH && spill.exceeded(bytes) {
+            split = Some(spill.partitions("hash-aggregate")?);
+        }
+    }
+    Ok((groups, split))
+}
+
+/// Accumulators of a new group.
+fn accumulators(aggregates: &[AggregateCall]) -> Vec<Accumulator> {
+    aggregates
+        .iter()
+        .map(|aggregate| {
+            let accumulator = Accumulator::new(&aggregate.function);
+            if aggregate.distinct {
+                Accumulator::Distinct(HashSet::new(), Box::new(accumulator))
+            } else {
+                accumulator
+            }
+        })
+        .collect()
+}
+
+impl Accumulator {
+    fn new(function: &AggregateFunction) -> Accumulator {
+        match function {
+            AggregateFunction::Count => Accumulator::Count(0),
+            AggregateFunction::Sum => Accumulator::Sum(None),
+            AggregateFunction::Min => Accumulator::Min(None),
+            AggregateFunction::Max => Accumulator::Max(None),
+            AggregateFunction::Avg => Accumulator::Avg(None, 0),
+            AggregateFunction::Udf(function) => Accumulator::Udf(function.accumulator()),
+        }
+    }
+
+    /// Add the arguments of a row of the group, ignoring rows with any argument `NULL`,
+    /// returning the bytes of memory newly held to remember them.
+    fn add(&mut self, args: Vec<Value>) -> ExecResult<usize> {
+        if args.iter().any(Value::is_null) {
+            return Ok(0);
+        }
+        let value = match self {
+            Accumulator::Distinct(seen, accumulator) => {
+                if seen.contains(&args) {
+                    return Ok(0);
+                }
+                let bytes = row_size(&args);
+                seen.insert(args.clone());
+                return Ok(bytes + accumulator.add(args)?);
+            }
+            Accumulator::Udf(accumulator) => {
+                accumulator.add(args)?;
+                return Ok(0);
+            }
+            _ => args.into_iter().next().expect("Aggregate Argument"),
+        };
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Sum(sum) => *sum = Some(add(sum.take(), value, ValueType::BigInt)?),
+            Accumulator::Avg(sum, count) => {
+                *sum = Some(add(sum.take(), value, ValueType::Decimal)?);
+                *count += 1;
+            }
+            Accumulator::Min(min) => {
+                if min.as_ref().map_or(Ok(true), |min| {
+                    value
+                        .compare(min)
+                        .map(|order| order == Some(Ordering::Less))
+                })? {
+                    *min = Some(value);
+                }
+            }
+            Accumulator::Max(max) => {
+                if max.as_ref().map_or(Ok(true), |max| {
+                    value
+                        .compare(max)
+                        .map(|order| order == Some(Ordering::Greater))
+                })? {
+                    *max = Some(value);
+                }
+            }
+            Accumulator::Udf(_) | Accumulator::Distinct(..) => unreachable!("added above"),
+        }
+        Ok(0)
+    }
+
+    /// Value of the aggregate over the rows added, `NULL` for aggregates of no values but
+    /// `COUNT`.
+    fn finish(self) -> ExecResult<Value> {
+        Ok(match self {
+            Accumulator::Count(count) => Value::BigInt(count),
+            Accumulator::Sum(value) | Accumulator::Min(value) | Accumulator::Max(value) => {
+                value.unwrap_or(Value::Null)
+            }
+            Accumulator::Avg(None, _) => Value::Null,
+            Accumulator::Avg(Some(sum), count) => {
+                sum.binary(BinaryOperator::Divide, &Value::BigInt(count))?
+            }
+            Accumulator::Udf(accumulator) => accumulator.finish()?,
+            Accumulator::Distinct(_, accumulator) => accumulator.finish()?,
+        })
+    }
+}
+
+/// Sum of a running sum and a value, summing integers as `integers`.
+fn add(sum: Option<Value>, value: Value, integers: ValueType) -> ExecResult<Value> {
+    let value = match value.value_type() {
+        Some(value_type) if value_type.is_integer() => value.coerce(integers)?,
+        _ => value,
+    };
+    Ok(match sum {
+        Some(sum) => sum.binary(BinaryOperator::Plus, &value)?,
+        None => value.binary(BinaryOperator::Plus, &Value::BigInt(0))?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{accumulators, Accumulator};
+    use crate::{ExecError, Executor, MemoryStorage};
+    use minql_plan::{AggregateCall, AggregateFunction, Estimate, PhysicalPlan};
+    use minql_value::{ScalarExpr, Value, ValueError};
+    use minql_vfs::{FileSystem, MemoryFileSystem, VirtualFileSystem};
+    use std::sync::Arc;
+
+    fn aggregate(function: &AggregateFunction, values: Vec<Value>) -> Result<Value, ExecError> {
+        let mut accumulator = Accumulator::new(function);
+        for value in values {
+            accumulator.add(vec![value])?;
+        }
+        accumulator.finish()
+    }
+
+    fn distinct(function: AggregateFunction, values: Vec<Value>) -> Value {
+        let call = AggregateCall {
+            function,
+            args: Vec::new(),
+            distinct: true,
+        };
+        let mut accumulator = accumulators(&[call]).pop().unwrap();
+        for value in values {
+            accumulator.add(vec![value]).unwrap();
+        }
+        accumulator.finish().unwrap()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_distinct_accumulators() {
+        let values = || {
+            [1, 2, 2, 3, 3, 3]
+                .into_iter()
+                .map(Value::from)
+                .chain([Value::Null, Value::from(3.0)])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            distinct(AggregateFunction::Count, values()),
+            Value::BigInt(3)
+        );
+        assert_eq!(distinct(AggregateFunction::Sum, values()), Value::BigInt(6));
+        assert_eq!(distinct(AggregateFunction::Max, values()), Value::from(3));
+        assert_eq!(
+            distinct(AggregateFunction::Count, Vec::new()),
+            Value::BigInt(0)
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_hash_aggregate_spill() {
+        let fs = VirtualFileSystem::new(MemoryFileSystem::new());
+        let executor = Executor::new(Arc::new(MemoryStorage::new()))
+            .with_spill_directory(fs.clone(), "/spill")
+            .with_memory_budget(16 * 1024);
+        let rows = (0..3000i64)
+            .map(|id| {
+                vec![
+                    ScalarExpr::Literal(Value::from(id % 1000)),
+                    ScalarExpr::Literal(Value::from(id % 7)),
+                ]
+            })
+            .collect();
+        let call = |function, distinct| AggregateCall {
+            function,
+            args: vec![ScalarExpr::Column(1)],
+            distinct,
+        };
+        let plan = PhysicalPlan::HashAggregate {
+            input: Box::new(PhysicalPlan::Values {
+                rows,
+                estimate: Estimate::default(),
+            }),
+            group_by: vec![ScalarExpr::Column(0)],
+            aggregates: vec![
+                call(AggregateFunction::Count, false),
+                call(AggregateFunction::Count, true),
+                call(AggregateFunction::Sum, true),
+            ],
+            estimate: Estimate::default(),
+        };
+        let mut cursor = executor.execute(&plan, Vec::new()).unwrap();
+        let mut groups: Vec<Vec<Value>> = cursor.by_ref().map(Result::unwrap).collect();
+        assert!(cursor.metrics().spilled > 0);
+        drop(cursor);
+        assert!(fs.list_directory("/spill").unwrap().is_empty());
+
+        groups.sort();
+        assert_eq!(groups.len(), 1000);
+        for (group, row) in groups.iter().enumerate() {
+            let group = i64::try_from(group).unwrap();
+            let values: Vec<i64> = (0..3).map(|n| (group + n * 1000) % 7).collect();
+            let mut unique = values.clone();
+            unique.sort_unstable();
+            unique.dedup();
+            let expected = [
+                Value::from(group),
+                Value::BigInt(3),
+                Value::BigInt(i64::try_from(unique.len()).unwrap()),
+                Value::BigInt(unique.iter().sum()),
+            ];
+            assert_eq!(row[..], expected, "group {group}");
+        }
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_accumulators() {
+        let max = Value::from(i32::MAX);
+        assert_eq!(
+            aggregate(&AggregateFunction::Sum, vec![max.clone(), Value::Null, max]).unwrap(),
+            Value::BigInt(2 * i64::from(i32::MAX))
+        );
+        assert_eq!(
+            aggregate(
+                &AggregateFunction::Sum,
+                vec![Value::from(1.5), Value::from(2)]
+            )
+            .unwrap(),
+            Value::from(3.5)
+        );
+        assert_eq!(
+            aggregate(
+                &AggregateFunction::Min,
+                vec![Value::from("b"), Value::from("a")]
+            )
+            .unwrap(),
+            Value::from("a")
+        );
+        assert_eq!(
+            aggregate(
+                &AggregateFunction::Max,
+                vec![Value::Null, Value::from(2), Value::from(7.5)]
+            )
+            .unwrap(),
+            Value::from(7.5)
+        );
+        assert_eq!(
+            aggregate(&AggregateFunction::Count, vec![Value::Null, Value::from(0)]).unwrap(),
+            Value::BigInt(1)
+        );
+        assert_eq!(
+            aggregate(&AggregateFunction::Avg, vec![Value::Null]).unwrap(),
+            Value::Null
+        );
+        assert!(matches!(
+            aggregate(&AggregateFunction::Sum, vec![Value::from("a")]),
+            Err(ExecError::Value(ValueError::IncompatibleTypes { .. }))
+        ));
+        assert!(matches!(
+            aggregate(
+                &AggregateFunction::Min,
+                vec![Value::from("a"), Value::from(1)]
+            ),
+            Err(ExecError::Value(ValueError::IncompatibleTypes { .. }))
+        ));
+    }
+}
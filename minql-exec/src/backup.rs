@@ -0,0 +1,185 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{Engine, ExecError, ExecResult};
+use minql_catalog::{Catalog, CatalogSnapshot, CatalogTransaction, TableSchema};
+use minql_value::{decode_row, encode_row};
+use minql_vfs::{FileHandle, FileSystem, Manifest, MismatchKind};
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+
+/// Name of the manifest of a backup within its directory.
+const MANIFEST_NAME: &str = "MANIFEST";
+/// Name of the file of a backup holding the point in storage its rows were copied at.
+const POINT_NAME: &str = "POINT";
+
+impl<F: FileSystem> Engine<F> {
+    /// Write a backup of every database to `directory` of `target`, which must not hold one
+    /// already, returning the number of rows copied.
+    ///
+    /// The backup holds a catalog of the databases, tables, indexes and statistics as they are
+    /// when it starts under `catalog/`, the rows of each table under `tables/`, named by the
+    /// id of the table in that catalog, and a manifest of the size and digest of every file.
+    /// Every table is copied as they all were at one point after the catalog, however many
+    /// rows are inserted meanwhile, and that point is kept under `POINT`, which the manifest
+    /// covers like every other file.
+    #[tracing::instrument(level = "debug", skip(self, target))]
+    pub fn backup<G: FileSystem + Clone>(&self, target: &G, directory: &str) -> ExecResult<u64> {
+        let directory = directory.trim_end_matches('/');
+        if target.exists(&format!("{directory}/{MANIFEST_NAME}"))? {
+            return Err(ExecError::InvalidBackup(format!(
+                "{directory} already holds a backup"
+            )));
+        }
+        let snapshot = self.catalog().snapshot();
+        let catalog = Catalog::open(target.clone(), &format!("{directory}/catalog"))?;
+        let mut transaction = catalog.begin();
+        let tables = copy_schema(&snapshot, &mut transaction)?;
+        transaction.commit()?;
+
+        let schemas = tables.iter().map(|(from, _)| &**from).collect::<Vec<_>>();
+        let (point, streams) = self.executor().storage().scan_all(&schemas)?;
+        target.create_directory_all(&format!("{directory}/tables"))?;
+        let mut rows = 0;
+        for ((_, to), stream) in tables.iter().zip(streams) {
+            let file = target.create_file(&format!("{directory}/tables/{}", to.id))?;
+            let mut file = BufWriter::new(file);
+            for row in stream {
+                let bytes = encode_row(&row?);
+                let length = u64::try_from(bytes.len()).expect("Row Length");
+                file.write_all(&length.to_le_bytes())?;
+                file.write_all(&bytes)?;
+                rows += 1;
+            }
+            file.into_inner()
+                .map_err(std::io::IntoInnerError::into_error)?
+                .sync_all()?;
+        }
+        target.write(
+            &format!("{directory}/{POINT_NAME}"),
+            point.to_string().as_bytes(),
+        )?;
+        minql_vfs::manifest(target, directory)?
+            .save(target, &format!("{directory}/{MANIFEST_NAME}"))?;
+        tracing::debug!(tables = tables.len(), rows, point, "Wrote backup");
+        Ok(rows)
+    }
+
+    /// Restore a backup written by [`backup`](Engine::backup) from `directory` of `source` into
+    /// an engine holding no tables, returning the number of rows restored.
+    ///
+    /// The backup is checked against its manifest first, failing without restoring anything
+    /// if any file is missing or differs. Databases the engine lacks are created, and tables,
+    /// indexes and statistics created anew in them, so they get new ids.
+    #[tracing::instrument(level = "debug", skip(self, source))]
+    pub fn restore<G: FileSystem + Clone>(&self, source: &G, directory: &str) -> ExecResult<u64> {
+        let directory = directory.trim_end_matches('/');
+        let manifest = Manifest::load(source, &format!("{directory}/{MANIFEST_NAME}"))?;
+        let mismatches = minql_vfs::verify(source, directory, &manifest)?
+            .into_iter()
+            .filter(|mismatch| {
+                mismatch.path != MANIFEST_NAME || mismatch.kind != MismatchKind::Unexpected
+            })
+            .collect::<Vec<_>>();
+        if !mismatches.is_empty() {
+            return Err(ExecError::BackupMismatch(mismatches));
+        }
+        let snapshot = Catalog::open(source.clone(), &format!("{directory}/catalog"))?.snapshot();
+
+        let mut transaction = self.catalog().begin();
+        let state = transaction.state();
+        if state
+            .databases()
+            .any(|database| state.tables(&database.name).next().is_some())
+        {
+            return Err(ExecError::InvalidBackup(
+                "restore into an engine holding tables".to_string(),
+            ));
+        }
+        let tables = copy_schema(&snapshot, &mut transaction)?;
+        let storage = self.executor().storage();
+        for (_, to) in &tables {
+            for index in transaction.state().table_indexes(to.id) {
+                storage.create_index(to, index)?;
+            }
+        }
+        transaction.commit()?;
+
+        let mut rows = 0;
+        for (from, to) in &tables {
+            let bytes = source.read(&format!("{directory}/tables/{}", from.id))?;
+            let mut rest = &bytes[..];
+            while !rest.is_empty() {
+                let row = rest
+                    .split_first_chunk::<8>()
+                    .and_then(|(length, rest)| {
+                        let length = usize::try_from(u64::from_le_bytes(*length)).ok()?;
+                        (length <= rest.len()).then(|| rest.split_at(length))
+                    })
+                    .ok_or_else(|| ExecError::InvalidBackup(format!("rows of {}", from.name)));
+                let (row, remaining) = row?;
+                storage.insert(to, decode_row(row)?)?;
+                rest = remaining;
+                rows += 1;
+            }
+        }
+        tracing::debug!(tables = tables.len(), rows, "Restored backup");
+        Ok(rows)
+    }
+}
+
+/// Create the databases, tables, indexes and statistics of `snapshot` in a transaction,
+/// returning each table of `snapshot` with the table created for it.
+///
+/// Databases already in the catalog are kept. Tables are created in the order of their ids,
+/// so each is created after those its foreign keys refer to.
+fn copy_schema<G: FileSystem>(
+    snapshot: &CatalogSnapshot,
+    transaction: &mut CatalogTransaction<'_, G>,
+) -> ExecResult<Vec<(Arc<TableSchema>, Arc<TableSchema>)>> {
+    let mut tables = Vec::new();
+    for database in snapshot.databases() {
+        if transaction.state().database(&database.name).is_none() {
+            transaction.create_database(&database.name, &database.uri)?;
+        }
+        tables.extend(snapshot.tables(&database.name).cloned());
+    }
+    tables.sort_unstable_by_key(|table| table.id);
+    let mut copied = Vec::with_capacity(tables.len());
+    for from in tables {
+        let to = transaction.create_table(&from.database, TableSchema::clone(&from))?;
+        for index in snapshot.table_indexes(from.id) {
+            let columns: Vec<&str> = index
+                .columns
+                .iter()
+                .map(|&column| from.columns[column].name.as_str())
+                .collect();
+            transaction.create_index(
+                &from.database,
+                &index.name,
+                &from.name,
+                &columns,
+                index.unique,
+            )?;
+        }
+        if let Some(statistics) = snapshot.statistics(from.id) {
+            let statistics = (**statistics).clone();
+            transaction.set_statistics(&from.database, &from.name, statistics)?;
+        }
+        copied.push((from, to));
+    }
+    Ok(copied)
+}
@@ -0,0 +1,259 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Most entries a chunk holds before it splits in two.
+const CHUNK_ENTRIES: usize = 256;
+
+/// Ordered map holding its entries in chunks shared between clones.
+///
+/// Cloning the map copies a reference to each chunk instead of its entries, and a change
+/// copies only the chunk it touches while another clone still shares it. Storage keeps the
+/// rows and index entries of a table in these, so a table can change while scans hold on to
+/// the table as it was without copying every row on each insert.
+#[derive(Clone, Debug)]
+pub(crate) struct ChunkedMap<K, V> {
+    /// Chunks by a key no greater than any they hold and greater than any of earlier chunks
+    chunks: BTreeMap<K, Arc<BTreeMap<K, V>>>,
+    len: usize,
+}
+
+impl<K: Clone + Ord, V: Clone> ChunkedMap<K, V> {
+    /// Create a map without entries.
+    pub(crate) fn new() -> ChunkedMap<K, V> {
+        ChunkedMap {
+            chunks: BTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    /// Number of entries.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the map holds no entries.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Value of the entry of a key.
+    pub(crate) fn get<Q: Ord + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.chunks.get::<K>(self.chunk_of(key)?)?.get(key)
+    }
+
+    /// Insert an entry, returning the value it replaced.
+    pub(crate) fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let Some(mut start) = self.chunk_of(&key).cloned() else {
+            self.chunks
+                .insert(key.clone(), Arc::new(BTreeMap::from([(key, value)])));
+            self.len += 1;
+            return None;
+        };
+        if key < start {
+            // The first chunk takes keys below every other, and is keyed by the least of them.
+            let chunk = self.chunks.remove(&start).expect("Chunk");
+            start = key.clone();
+            self.chunks.insert(start.clone(), chunk);
+        }
+        let chunk = Arc::make_mut(self.chunks.get_mut(&start).expect("Chunk"));
+        let appended = chunk.last_key_value().is_none_or(|(last, _)| *last < key);
+        let replaced = chunk.insert(key, value);
+        if replaced.is_none() {
+            self.len += 1;
+        }
+        if chunk.len() > CHUNK_ENTRIES {
+            // Keys inserted in order start a new chunk rather than leaving two half empty.
+            let split = if appended {
+                chunk.len() - 1
+            } else {
+                chunk.len() / 2
+            };
+            let middle = chunk.keys().nth(split).expect("Middle").clone();
+            let upper = chunk.split_off(&middle);
+            self.chunks.insert(middle, Arc::new(upper));
+        }
+        replaced
+    }
+
+    /// Remove the entry of a key, returning its value.
+    pub(crate) fn remove<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        let start = self.chunk_of(key)?.clone();
+        let chunk = self.chunks.get_mut::<K>(&start).expect("Chunk");
+        if !chunk.contains_key(key) {
+            return None;
+        }
+        let chunk = Arc::make_mut(chunk);
+        let removed = chunk.remove(key);
+        if chunk.is_empty() {
+            self.chunks.remove::<K>(&start);
+        }
+        self.len -= 1;
+        removed
+    }
+
+    /// Entries whose keys lie between bounds, in order.
+    pub(crate) fn range(
+        &self,
+        lower: Bound<K>,
+        upper: Bound<K>,
+    ) -> impl Iterator<Item = (&K, &V)> + '_ {
+        let chunks = if is_empty(&lower, &upper) {
+            None
+        } else {
+            let first = match &lower {
+                Bound::Included(key) | Bound::Excluded(key) => self
+                    .chunk_of::<K>(key)
+                    .map_or(Bound::Unbounded, Bound::Included),
+                Bound::Unbounded => Bound::Unbounded,
+            };
+            Some(self.chunks.range((first, Bound::Unbounded)))
+        };
+        let end = upper.clone();
+        chunks
+            .into_iter()
+            .flatten()
+            .take_while(move |(start, _)| match &end {
+                Bound::Included(end) => *start <= end,
+                Bound::Excluded(end) => *start < end,
+                Bound::Unbounded => true,
+            })
+            .flat_map(move |(_, chunk)| chunk.range((lower.clone(), upper.clone())))
+    }
+
+    /// Key of the chunk that holds or would hold a key.
+    fn chunk_of<Q: Ord + ?Sized>(&self, key: &Q) -> Option<&K>
+    where
+        K: Borrow<Q>,
+    {
+        self.chunks
+            .range((Bound::Unbounded, Bound::Included(key)))
+            .next_back()
+            .or_else(|| self.chunks.first_key_value())
+            .map(|(start, _)| start)
+    }
+}
+
+impl<K: Clone + Ord, V: Clone> Default for ChunkedMap<K, V> {
+    fn default() -> ChunkedMap<K, V> {
+        ChunkedMap::new()
+    }
+}
+
+/// Whether a range holds no keys, as ranges a map would refuse.
+fn is_empty<K: Ord>(lower: &Bound<K>, upper: &Bound<K>) -> bool {
+    match (lower, upper) {
+        (Bound::Included(lower), Bound::Included(upper)) => lower > upper,
+        (Bound::Included(lower) | Bound::Excluded(lower), Bound::Excluded(upper))
+        | (Bound::Excluded(lower), Bound::Included(upper)) => lower >= upper,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ChunkedMap, CHUNK_ENTRIES};
+    use std::ops::Bound;
+    use std::sync::Arc;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_chunked_map() {
+        let mut map = ChunkedMap::new();
+        // Odd keys in order, then even keys in reverse, so chunks split at their end and middle
+        for key in (1..2000u32).step_by(2) {
+            assert_eq!(map.insert(key, key * 10), None);
+        }
+        for key in (0..2000u32).step_by(2).rev() {
+            assert_eq!(map.insert(key, key * 10), None);
+        }
+        assert_eq!(map.insert(7, 0), Some(70));
+        assert_eq!(map.len(), 2000);
+        assert!(map.chunks.len() > 2000 / CHUNK_ENTRIES);
+        assert!(map
+            .chunks
+            .values()
+            .all(|chunk| chunk.len() <= CHUNK_ENTRIES));
+        assert_eq!(map.get(&8), Some(&80));
+        assert_eq!(map.get(&2000), None);
+
+        let keys = |lower, upper| {
+            map.range(lower, upper)
+                .map(|(&key, _)| key)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            keys(Bound::Unbounded, Bound::Unbounded),
+            (0..2000).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            keys(Bound::Excluded(997), Bound::Included(1001)),
+            [998, 999, 1000, 1001]
+        );
+        assert_eq!(
+            keys(Bound::Included(1998), Bound::Excluded(5000)),
+            [1998, 1999]
+        );
+        assert!(keys(Bound::Included(5), Bound::Excluded(5)).is_empty());
+        assert!(keys(Bound::Included(6), Bound::Included(5)).is_empty());
+
+        assert_eq!(map.remove(&0), Some(0));
+        assert_eq!(map.remove(&0), None);
+        for key in 1..300 {
+            map.remove(&key);
+        }
+        assert_eq!(map.len(), 1700);
+        assert_eq!(
+            map.range(Bound::Unbounded, Bound::Unbounded).next(),
+            Some((&300, &3000))
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_chunked_map_sharing() {
+        let mut map = ChunkedMap::new();
+        for key in 0..10_000u64 {
+            map.insert(key, key);
+        }
+        let snapshot = map.clone();
+        map.insert(10_000, 10_000);
+        map.insert(5_000, 0);
+        map.remove(&0);
+
+        // Only the chunks holding the changed keys were copied
+        let shared = map
+            .chunks
+            .values()
+            .filter(|chunk| snapshot.chunks.values().any(|old| Arc::ptr_eq(chunk, old)))
+            .count();
+        assert!(shared >= map.chunks.len() - 3);
+        assert_eq!(snapshot.len(), 10_000);
+        assert_eq!(snapshot.get(&5_000), Some(&5_000));
+        assert_eq!(map.get(&5_000), Some(&0));
+        assert_eq!(map.len(), 10_000);
+    }
+}
@@ -0,0 +1,516 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::aggregate::HashAggregateOperator;
+use crate::filter::{FilterOperator, LimitOperator, ProjectOperator};
+use crate::join::{HashJoinOperator, MergeJoinOperator, NestedLoopJoinOperator};
+use crate::operator::Node;
+use crate::scan::{IndexScanOperator, SeqScanOperator, ValuesOperator};
+use crate::sort::SortOperator;
+use crate::spill::SpillSpace;
+use crate::{ExecResult, OperatorMetrics, Storage};
+use minql_plan::PhysicalPlan;
+use minql_value::Value;
+use minql_vfs::{FileSystem, MetricsData};
+use std::sync::Arc;
+
+/// Runs physical plans over the tables of a [`Storage`], returning their rows as they are
+/// pulled from a [`Cursor`].
+///
+/// Each operator of the plan becomes an operator pulling rows from its inputs one at a time,
+/// so rows stream through filters, projections and limits without being held, and only
+/// sorts, aggregates and the inner side of joins keep the rows they need. Given a directory
+/// to spill to, sorts, hash joins and aggregates write the rows beyond their memory budget to
+/// temporary files there.
+#[derive(Clone, Debug)]
+pub struct Executor {
+    storage: Arc<dyn Storage>,
+    spill: SpillSpace,
+}
+
+/// Rows of a running plan, read one at a time, with the metrics of the work done reading
+/// them.
+///
+/// The plan is closed once its last row is read, an error is returned, or the cursor is
+/// dropped.
+pub struct Cursor {
+    root: Node,
+    open: bool,
+}
+
+impl Executor {
+    /// Default bytes of rows an operator may hold in memory before spilling them.
+    pub const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+    /// Create an executor reading tables from `storage`.
+    #[must_use]
+    pub fn new(storage: Arc<dyn Storage>) -> Executor {
+        Executor {
+            storage,
+            spill: SpillSpace::new(Self::DEFAULT_MEMORY_BUDGET),
+        }
+    }
+
+    /// Write rows operators can't hold in memory to temporary files in `directory` of `fs`.
+    ///
+    /// Without a directory to spill to, operators hold every row they need in memory.
+    #[must_use]
+    pub fn with_spill_directory<F: FileSystem>(mut self, fs: F, directory: &str) -> Self {
+        self.spill = self.spill.with_files(fs, directory);
+        self
+    }
+
+    /// Let each operator hold `bytes` bytes of rows in memory before spilling them.
+    #[must_use]
+    pub fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.spill = self.spill.with_budget(bytes);
+        self
+    }
+
+    /// Bytes of rows each operator may hold in memory before spilling them.
+    #[must_use]
+    pub fn memory_budget(&self) -> usize {
+        self.spill.budget()
+    }
+
+    /// Bytes written and read and operations done on temporary files by every plan run so
+    /// far, shared by clones of the executor, or `None` without a directory to spill to.
+    #[must_use]
+    pub fn spill_metrics(&self) -> Option<MetricsData> {
+        self.spill.metrics()
+    }
+
+    /// Storage tables are read from.
+    #[must_use]
+    pub fn storage(&self) -> &Arc<dyn Storage> {
+        &self.storage
+    }
+
+    /// Start running a plan, given the values of the parameters of the query.
+    #[tracing::instrument(level = "debug", skip_all, fields(params = params.len()))]
+    pub fn execute(&self, plan: &PhysicalPlan, params: Vec<Value>) -> ExecResult<Cursor> {
+        let mut root = self.build(plan);
+        let params: Arc<[Value]> = params.into();
+        if let Err(err) = root.open(&params) {
+            root.close();
+            return Err(err);
+        }
+        Ok(Cursor { root, open: true })
+    }
+
+    /// Operator running a plan.
+    fn build(&self, plan: &PhysicalPlan) -> Node {
+        let describe = plan.describe();
+        match plan {
+            PhysicalPlan::Values { rows, .. } => {
+                Node::new(describe, ValuesOperator::new(rows.clone()))
+            }
+            PhysicalPlan::SeqScan { table, filter, .. } => Node::new(
+                describe,
+                SeqScanOperator::new(self.storage.clone(), table.clone(), filter.clone()),
+            ),
+            PhysicalPlan::IndexScan {
+                table,
+                index,
+                bounds,
+                filter,
+                ..
+            } => Node::new(
+                describe,
+                IndexScanOperator::new(
+                    self.storage.clone(),
+                    table.clone(),
+                    index.clone(),
+                    bounds.clone(),
+                    filter.clone(),
+                ),
+            ),
+            PhysicalPlan::Filter {
+                input, predicate, ..
+            } => Node::new(
+                describe,
+                FilterOperator::new(self.build(input), predicate.clone()),
+            ),
+            PhysicalPlan::NestedLoopJoin { .. }
+            | PhysicalPlan::HashJoin { .. }
+            | PhysicalPlan::MergeJoin { .. } => self.join(describe, plan),
+            PhysicalPlan::Project { input, exprs, .. } => Node::new(
+                describe,
+                ProjectOperator::new(self.build(input), exprs.clone()),
+            ),
+            PhysicalPlan::HashAggregate {
+                input,
+                group_by,
+                aggregates,
+                ..
+            } => Node::new(
+                describe,
+                HashAggregateOperator::new(
+                    self.build(input),
+                    group_by.clone(),
+                    aggregates.clone(),
+                    self.spill.clone(),
+                ),
+            ),
+            PhysicalPlan::Sort { input, keys, .. } => Node::new(
+                describe,
+                SortOperator::new(self.build(input), keys.clone(), self.spill.clone()),
+            ),
+            PhysicalPlan::Limit {
+                input,
+                limit,
+                offset,
+                ..
+            } => Node::new(
+                describe,
+                LimitOperator::new(self.build(input), limit.clone(), offset.clone()),
+            ),
+        }
+    }
+
+    /// Operator running a plan joining two inputs.
+    fn join(&self, describe: String, plan: &PhysicalPlan) -> Node {
+        match plan {
+            PhysicalPlan::NestedLoopJoin {
+                left,
+                right,
+                condition,
+                ..
+            } => Node::new(
+                describe,
+                NestedLoopJoinOperator::new(self.build(left), self.build(right), condition.clone()),
+            ),
+            PhysicalPlan::HashJoin {
+                left,
+                right,
+                left_keys,
+                right_keys,
+                condition,
+                ..
+            } => Node::new(
+                describe,
+                HashJoinOperator::new(
+                    self.build(left),
+                    self.build(right),
+                    left_keys.clone(),
+                    right_keys.clone(),
+                    condition.clone(),
+                    self.spill.clone(),
+                ),
+            ),
+            PhysicalPlan::MergeJoin {
+                left,
+                right,
+                left_keys,
+                right_keys,
+                condition,
+                ..
+            } => Node::new(
+                describe,
+                MergeJoinOperator::new(
+                    self.build(left),
+                    self.build(right),
+                    left_keys.clone(),
+                    right_keys.clone(),
+                    condition.clone(),
+                ),
+            ),
+            _ => unreachable!("not a join"),
+        }
+    }
+}
+
+impl Cursor {
+    /// Metrics of each operator of the plan so far, complete once every row is read.
+    #[must_use]
+    pub fn metrics(&self) -> OperatorMetrics {
+        self.root.metrics()
+    }
+
+    fn close(&mut self) {
+        if self.open {
+            self.open = false;
+            self.root.close();
+        }
+    }
+}
+
+impl Iterator for Cursor {
+    type Item = ExecResult<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.open {
+            return None;
+        }
+        let row = self.root.next().transpose();
+        if !matches!(row, Some(Ok(_))) {
+            self.close();
+        }
+        row
+    }
+}
+
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl std::fmt::Debug for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cursor")
+            .field("metrics", &self.metrics())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ExecError, Executor, MemoryStorage, Storage};
+    use minql_catalog::{Catalog, CatalogSnapshot, ColumnSchema, TableSchema};
+    use minql_lang::ast::{DataType, Statement};
+    use minql_lang::Parser;
+    use minql_plan::Planner;
+    use minql_value::{Decimal, Value, ValueError};
+    use minql_vfs::MemoryFileSystem;
+    use std::sync::Arc;
+
+    fn shop() -> (Arc<CatalogSnapshot>, Executor) {
+        let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+        let mut transaction = catalog.begin();
+        transaction.create_database("shop", "mem:///shop").unwrap();
+        let customers = TableSchema::new("customers")
+            .with_column(ColumnSchema::new("id", DataType::BigInt))
+            .with_column(ColumnSchema::new("name", DataType::Text));
+        let orders = TableSchema::new("orders")
+            .with_column(ColumnSchema::new("id", DataType::BigInt))
+            .with_column(ColumnSchema::new("customer", DataType::BigInt))
+            .with_column(ColumnSchema::new("amount", DataType::Integer));
+        transaction.create_table("shop", customers).unwrap();
+        transaction.create_table("shop", orders).unwrap();
+        transaction
+            .create_index("shop", "customers_id", "customers", &["id"], true)
+            .unwrap();
+        transaction
+            .create_index("shop", "orders_id", "orders", &["id"], true)
+            .unwrap();
+        let snapshot = transaction.commit().unwrap();
+
+        let storage = MemoryStorage::new();
+        for table in ["customers", "orders"] {
+            let table = snapshot.table("shop", table).unwrap();
+            for index in snapshot.table_indexes(table.id) {
+                storage.create_index(table, index).unwrap();
+            }
+        }
+        let insert = |table: &str, row: Vec<Value>| {
+            let table = snapshot.table("shop", table).unwrap();
+            storage.insert(table, row).unwrap();
+        };
+        for (id, name) in [(1, "ann"), (2, "bob"), (3, "cy")] {
+            insert("customers", vec![Value::from(id), Value::from(name)]);
+        }
+        for id in 0..40i64 {
+            let amount = if id == 39 {
+                Value::Null
+            } else {
+                Value::from(id * 10)
+            };
+            insert(
+                "orders",
+                vec![Value::from(id), Value::from(id % 2 + 1), amount],
+            );
+        }
+        (snapshot, Executor::new(Arc::new(storage)))
+    }
+
+    fn query(sql: &str, params: Vec<Value>) -> Result<Vec<Vec<Value>>, ExecError> {
+        let (snapshot, executor) = shop();
+        let Statement::Query(query) = Parser::parse_statement(sql).unwrap() else {
+            panic!("not a query: {sql}");
+        };
+        let plan = Planner::new()
+            .plan_query(&snapshot, "shop", &query)
+            .unwrap();
+        executor.execute(&plan.plan, params)?.collect()
+    }
+
+    fn rows(sql: &str) -> Vec<String> {
+        query(sql, Vec::new())
+            .unwrap()
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_scans() {
+        assert_eq!(
+            rows("SELECT id, amount / 10 FROM orders WHERE amount >= 300 ORDER BY id DESC LIMIT 3 OFFSET 1"),
+            ["37,37", "36,36", "35,35"]
+        );
+        assert_eq!(rows("SELECT name FROM customers WHERE id = 2"), ["bob"]);
+        assert_eq!(
+            rows("SELECT name FROM customers WHERE id = 2.5"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            rows("SELECT name FROM customers WHERE id > 1.5 AND id <= 3"),
+            ["bob", "cy"]
+        );
+        assert_eq!(
+            rows("SELECT id FROM orders WHERE id < 2.5 ORDER BY id"),
+            ["0", "1", "2"]
+        );
+        assert_eq!(
+            rows("SELECT id FROM orders WHERE id > 37 AND id < 39.5"),
+            ["38", "39"]
+        );
+        assert_eq!(
+            query(
+                "SELECT name FROM customers WHERE id = ?",
+                vec![Value::from(3)]
+            )
+            .unwrap(),
+            [[Value::from("cy")]]
+        );
+        assert_eq!(rows("SELECT 1 + 1, 'a'"), ["2,a"]);
+        assert_eq!(
+            rows("VALUES (1), (3), (2) ORDER BY 1 DESC"),
+            ["3", "2", "1"]
+        );
+        assert!(matches!(
+            query("SELECT name FROM customers WHERE id = ?", Vec::new()),
+            Err(ExecError::Value(ValueError::UnboundParameter(_)))
+        ));
+        assert!(matches!(
+            query("SELECT id FROM orders LIMIT -1", Vec::new()),
+            Err(ExecError::Value(ValueError::InvalidArgument { .. }))
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_joins_and_aggregates() {
+        assert_eq!(
+            rows(
+                "SELECT c.name, count(*), count(o.amount), sum(o.amount), min(o.amount), max(o.id) \
+                 FROM customers c JOIN orders o ON o.customer = c.id \
+                 GROUP BY c.name ORDER BY 1"
+            ),
+            ["ann,20,20,3800,0,38", "bob,20,19,3610,10,39"]
+        );
+        assert_eq!(
+            rows("SELECT name FROM customers, orders WHERE orders.id = 7 AND customers.id <> orders.customer"),
+            ["ann", "cy"]
+        );
+        let averages = query(
+            "SELECT customer, avg(amount) FROM orders GROUP BY customer HAVING count(*) > 1 ORDER BY customer",
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(averages[0][1], Value::from(Decimal::parse("190").unwrap()));
+        assert_eq!(averages[1][1], averages[0][1]);
+        assert_eq!(
+            rows("SELECT avg(id) FROM orders WHERE id < 2"),
+            ["0.5000000000000000"]
+        );
+        assert_eq!(
+            rows("SELECT count(*), sum(amount), avg(amount) FROM orders WHERE id > 100"),
+            ["0,NULL,NULL"]
+        );
+        assert_eq!(
+            rows("SELECT customer FROM orders WHERE id > 100 GROUP BY customer"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            rows("SELECT count(DISTINCT customer), sum(DISTINCT customer), count(customer) FROM orders"),
+            ["2,3,40"]
+        );
+        assert_eq!(
+            rows("SELECT DISTINCT customer FROM orders ORDER BY customer DESC"),
+            ["2", "1"]
+        );
+        assert_eq!(
+            rows("SELECT amount FROM orders WHERE id > 37 ORDER BY amount NULLS FIRST"),
+            ["NULL", "380"]
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_join_methods() {
+        let (snapshot, executor) = shop();
+        let sql = "SELECT c.name, o.amount FROM customers c JOIN orders o ON o.id = c.id \
+                   WHERE c.id = 2 AND o.id = 2";
+        let Statement::Query(query) = Parser::parse_statement(sql).unwrap() else {
+            unreachable!()
+        };
+        let plan = Planner::new()
+            .plan_query(&snapshot, "shop", &query)
+            .unwrap();
+        assert!(
+            plan.plan.to_string().contains("MergeJoin on 1"),
+            "{}",
+            plan.plan
+        );
+        let rows: Vec<Vec<Value>> = executor
+            .execute(&plan.plan, Vec::new())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows, [[Value::from("bob"), Value::from(20)]]);
+        assert_eq!(
+            self::rows(
+                "SELECT c.name, o.id FROM customers c JOIN orders o ON o.customer = c.id \
+                 WHERE o.id > 35 ORDER BY 2"
+            ),
+            ["ann,36", "bob,37", "ann,38", "bob,39"]
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_metrics() {
+        let (snapshot, executor) = shop();
+        let Statement::Query(query) =
+            Parser::parse_statement("SELECT id FROM orders WHERE amount > 100 LIMIT 5").unwrap()
+        else {
+            unreachable!()
+        };
+        let plan = Planner::new()
+            .plan_query(&snapshot, "shop", &query)
+            .unwrap();
+        let mut cursor = executor.execute(&plan.plan, Vec::new()).unwrap();
+        assert_eq!(cursor.by_ref().count(), 5);
+        let metrics = cursor.metrics();
+        assert_eq!(metrics.operator, "Limit");
+        assert_eq!((metrics.rows, metrics.loops), (5, 1));
+        let scan = &metrics.children[0].children[0];
+        assert_eq!(scan.operator, "SeqScan orders filtered");
+        assert_eq!(scan.rows, 5);
+        let explained = metrics.to_string();
+        assert!(explained.starts_with("Limit (rows=5 loops=1 time="));
+        assert!(explained.contains("\n    SeqScan orders filtered (rows=5 loops=1 time="));
+        assert!(cursor.next().is_none());
+    }
+}
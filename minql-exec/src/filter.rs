@@ -0,0 +1,191 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::operator::{Node, Operator};
+use crate::ExecResult;
+use minql_value::{ScalarExpr, Value, ValueError, ValueType};
+use std::sync::Arc;
+
+/// Rows of the input meeting a condition.
+pub(crate) struct FilterOperator {
+    input: Node,
+    predicate: ScalarExpr,
+    params: Arc<[Value]>,
+}
+
+impl FilterOperator {
+    pub(crate) fn new(input: Node, predicate: ScalarExpr) -> FilterOperator {
+        FilterOperator {
+            input,
+            predicate,
+            params: Arc::new([]),
+        }
+    }
+}
+
+impl Operator for FilterOperator {
+    fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()> {
+        self.params = params.clone();
+        self.input.open(params)
+    }
+
+    fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        while let Some(row) = self.input.next()? {
+            if self.predicate.eval(&row, &self.params)?.is_true() {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+
+    fn close(&mut self) {
+        self.input.close();
+    }
+
+    fn children(&self) -> Vec<&Node> {
+        vec![&self.input]
+    }
+}
+
+/// Expressions computed over each row of the input.
+pub(crate) struct ProjectOperator {
+    input: Node,
+    exprs: Vec<ScalarExpr>,
+    params: Arc<[Value]>,
+}
+
+impl ProjectOperator {
+    pub(crate) fn new(input: Node, exprs: Vec<ScalarExpr>) -> ProjectOperator {
+        ProjectOperator {
+            input,
+            exprs,
+            params: Arc::new([]),
+        }
+    }
+}
+
+impl Operator for ProjectOperator {
+    fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()> {
+        self.params = params.clone();
+        self.input.open(params)
+    }
+
+    fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        let Some(row) = self.input.next()? else {
+            return Ok(None);
+        };
+        let row = self
+            .exprs
+            .iter()
+            .map(|expr| expr.eval(&row, &self.params))
+            .collect::<Result<_, _>>()?;
+        Ok(Some(row))
+    }
+
+    fn close(&mut self) {
+        self.input.close();
+    }
+
+    fn children(&self) -> Vec<&Node> {
+        vec![&self.input]
+    }
+}
+
+/// Rows of the input after skipping some, up to a number of rows, reading no further rows
+/// of the input once the last is returned.
+pub(crate) struct LimitOperator {
+    input: Node,
+    limit: Option<ScalarExpr>,
+    offset: Option<ScalarExpr>,
+    /// Rows left to skip and to return
+    skip: u64,
+    left: Option<u64>,
+}
+
+impl LimitOperator {
+    pub(crate) fn new(
+        input: Node,
+        limit: Option<ScalarExpr>,
+        offset: Option<ScalarExpr>,
+    ) -> LimitOperator {
+        LimitOperator {
+            input,
+            limit,
+            offset,
+            skip: 0,
+            left: None,
+        }
+    }
+}
+
+impl Operator for LimitOperator {
+    fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()> {
+        self.left = count(self.limit.as_ref(), params, "LIMIT")?;
+        self.skip = count(self.offset.as_ref(), params, "OFFSET")?.unwrap_or(0);
+        self.input.open(params)
+    }
+
+    fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        if self.left == Some(0) {
+            return Ok(None);
+        }
+        while self.skip > 0 {
+            if self.input.next()?.is_none() {
+                return Ok(None);
+            }
+            self.skip -= 1;
+        }
+        let row = self.input.next()?;
+        if let (Some(left), Some(_)) = (&mut self.left, &row) {
+            *left -= 1;
+        }
+        Ok(row)
+    }
+
+    fn close(&mut self) {
+        self.input.close();
+    }
+
+    fn children(&self) -> Vec<&Node> {
+        vec![&self.input]
+    }
+}
+
+/// Number of rows of `LIMIT` or `OFFSET`, or `None` if not given or `NULL`.
+fn count(
+    expr: Option<&ScalarExpr>,
+    params: &[Value],
+    operation: &'static str,
+) -> ExecResult<Option<u64>> {
+    let Some(expr) = expr else {
+        return Ok(None);
+    };
+    let value = expr.eval(&[], params)?;
+    if value.is_null() {
+        return Ok(None);
+    }
+    let invalid = || ValueError::InvalidArgument {
+        operation,
+        value: value.clone(),
+    };
+    if !value.value_type().is_some_and(ValueType::is_integer) {
+        return Err(invalid().into());
+    }
+    match value.coerce(ValueType::BigInt)? {
+        Value::BigInt(count) => Ok(Some(u64::try_from(count).map_err(|_| invalid())?)),
+        _ => Err(invalid().into()),
+    }
+}
@@ -0,0 +1,145 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::chunked::ChunkedMap;
+use crate::{ExecError, ExecResult};
+use minql_catalog::IndexSchema;
+use minql_value::{encode_key, Value};
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Entries of a secondary index, one for each row of its table.
+///
+/// Storage keeps the entries of every index of a table with its rows and changes them in the
+/// same step as the rows, so no caller has to remember which indexes a row belongs to. An
+/// entry is keyed by the values of the indexed columns, encoded by [`encode_key`], followed by
+/// the row id in big endian, so rows of equal values keep distinct entries. Entries are held
+/// in chunks shared between clones, so cloning the entries of a large index is cheap.
+///
+/// The entries live in memory only, built again from the rows whenever an index is created;
+/// they aren't written to a `minql-btree` tree, persisted or logged. Storage keeping its rows
+/// on disk would keep its indexes there as well.
+///
+/// ```rust
+/// use minql_catalog::IndexSchema;
+/// use minql_exec::IndexEntries;
+/// use minql_value::Value;
+/// use std::ops::Bound;
+/// use std::sync::Arc;
+///
+/// let index = Arc::new(IndexSchema {
+///     id: 1,
+///     database: "db".to_string(),
+///     name: "t_a".to_string(),
+///     table: 1,
+///     columns: vec![0],
+///     unique: true,
+///     uri: "mem:///t_a".to_string(),
+/// });
+/// let mut entries = IndexEntries::new(index);
+/// entries.add(&[Value::from(2)], 0).unwrap();
+/// entries.add(&[Value::from(1)], 1).unwrap();
+/// assert!(entries.add(&[Value::from(1)], 2).is_err());
+/// let rows: Vec<u64> = entries.range(Bound::Unbounded, Bound::Unbounded).map(|(_, row)| row).collect();
+/// assert_eq!(rows, [1, 0]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct IndexEntries {
+    index: Arc<IndexSchema>,
+    entries: ChunkedMap<Vec<u8>, u64>,
+}
+
+impl IndexEntries {
+    /// Create an index without entries.
+    #[must_use]
+    pub fn new(index: Arc<IndexSchema>) -> IndexEntries {
+        IndexEntries {
+            index,
+            entries: ChunkedMap::new(),
+        }
+    }
+
+    /// Schema of the index.
+    #[must_use]
+    pub fn schema(&self) -> &Arc<IndexSchema> {
+        &self.index
+    }
+
+    /// Number of entries.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the index holds no entries.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Key of the entry of a row, failing if the index is unique and already holds an entry
+    /// of equal values. Rows with a null indexed value never conflict.
+    pub fn entry(&self, row: &[Value], id: u64) -> ExecResult<Vec<u8>> {
+        let values: Vec<Value> = self
+            .index
+            .columns
+            .iter()
+            .map(|&column| row[column].clone())
+            .collect();
+        let mut key = Vec::new();
+        encode_key(&values, &mut key);
+        if self.index.unique && !values.iter().any(Value::is_null) {
+            let taken = self
+                .entries
+                .range(Bound::Included(key.clone()), Bound::Unbounded)
+                .next()
+                .is_some_and(|(entry, _)| entry.starts_with(&key));
+            if taken {
+                return Err(ExecError::UniqueViolation(self.index.name.clone()));
+            }
+        }
+        key.extend_from_slice(&id.to_be_bytes());
+        Ok(key)
+    }
+
+    /// Insert an entry keyed by [`entry`](Self::entry).
+    pub fn insert(&mut self, key: Vec<u8>, id: u64) {
+        self.entries.insert(key, id);
+    }
+
+    /// Remove an entry keyed by [`entry`](Self::entry), returning the id of its row.
+    pub fn remove(&mut self, key: &[u8]) -> Option<u64> {
+        self.entries.remove(key)
+    }
+
+    /// Add the entry of a row.
+    pub fn add(&mut self, row: &[Value], id: u64) -> ExecResult<()> {
+        let key = self.entry(row, id)?;
+        self.insert(key, id);
+        Ok(())
+    }
+
+    /// Entries whose keys lie between bounds, in order, as keys and row ids.
+    pub fn range(
+        &self,
+        lower: Bound<Vec<u8>>,
+        upper: Bound<Vec<u8>>,
+    ) -> impl Iterator<Item = (&[u8], u64)> + '_ {
+        self.entries
+            .range(lower, upper)
+            .map(|(key, &row)| (key.as_slice(), row))
+    }
+}
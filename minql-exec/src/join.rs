@@ -0,0 +1,649 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::operator::{Node, Operator};
+use crate::spill::{partition, row_size, SpillReader, SpillSpace, SpillWriter, MAX_DEPTH};
+use crate::ExecResult;
+use minql_value::{ScalarExpr, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Every pair of rows of the inputs meeting a condition, reading the right input once and
+/// keeping its rows to compare with each row of the left.
+pub(crate) struct NestedLoopJoinOperator {
+    left: Node,
+    right: Node,
+    condition: Option<ScalarExpr>,
+    params: Arc<[Value]>,
+    /// Rows of the right input
+    inner: Vec<Vec<Value>>,
+    /// Row of the left input being joined, and the position of the next row of the right
+    outer: Option<(Vec<Value>, usize)>,
+}
+
+impl NestedLoopJoinOperator {
+    pub(crate) fn new(
+        left: Node,
+        right: Node,
+        condition: Option<ScalarExpr>,
+    ) -> NestedLoopJoinOperator {
+        NestedLoopJoinOperator {
+            left,
+            right,
+            condition,
+            params: Arc::new([]),
+            inner: Vec::new(),
+            outer: None,
+        }
+    }
+}
+
+impl Operator for NestedLoopJoinOperator {
+    fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()> {
+        self.params = params.clone();
+        self.inner.clear();
+        self.outer = None;
+        self.right.open(params)?;
+        while let Some(row) = self.right.next()? {
+            self.inner.push(row);
+        }
+        self.right.close();
+        self.left.open(params)
+    }
+
+    fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        if self.inner.is_empty() {
+            return Ok(None);
+        }
+        loop {
+            let (outer, position) = match &mut self.outer {
+                Some((outer, position)) if *position < self.inner.len() => (outer, position),
+                _ => match self.left.next()? {
+                    Some(row) => {
+                        let (outer, position) = self.outer.insert((row, 0));
+                        (outer, position)
+                    }
+                    None => return Ok(None),
+                },
+            };
+            let inner = &self.inner[*position];
+            *position += 1;
+            let row = joined(outer, inner);
+            match &self.condition {
+                Some(condition) if !condition.eval(&row, &self.params)?.is_true() => {}
+                _ => return Ok(Some(row)),
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        self.left.close();
+        self.inner = Vec::new();
+        self.outer = None;
+    }
+
+    fn children(&self) -> Vec<&Node> {
+        vec![&self.left, &self.right]
+    }
+}
+
+/// Pairs of rows of the inputs whose keys are equal, holding the rows of the right by their
+/// keys to look up those of each row of the left.
+///
+/// Once the rows of the right exceed the memory budget, both inputs are split by the hash of
+/// their keys into partitions written to temporary files, and each pair of partitions is
+/// joined in turn, split again if still too large.
+pub(crate) struct HashJoinOperator {
+    left: Node,
+    right: Node,
+    left_keys: Vec<ScalarExpr>,
+    right_keys: Vec<ScalarExpr>,
+    condition: Option<ScalarExpr>,
+    spill: SpillSpace,
+    params: Arc<[Value]>,
+    /// Rows of the right, or of the partition being joined, by their keys
+    table: HashMap<Vec<Value>, Vec<Vec<Value>>>,
+    /// Where rows of the left are read from
+    probe: Probe,
+    /// Partitions of both inputs not yet joined
+    partitions: Vec<Partition>,
+    /// Row of the left being joined, its keys, and the position of its next match
+    outer: Option<(Vec<Value>, Vec<Value>, usize)>,
+    spilled: u64,
+}
+
+/// Source of the rows of the left of a hash join.
+enum Probe {
+    /// Rows read from the input, all of the right fitting in memory
+    Input,
+    /// Rows of the partition being joined
+    Partition(SpillReader),
+    /// No rows left
+    Done,
+}
+
+/// Rows of both inputs of a hash join whose keys fall in the same partition.
+struct Partition {
+    build: SpillReader,
+    probe: SpillReader,
+    /// Times the rows have been split
+    depth: u32,
+}
+
+impl HashJoinOperator {
+    pub(crate) fn new(
+        left: Node,
+        right: Node,
+        left_keys: Vec<ScalarExpr>,
+        right_keys: Vec<ScalarExpr>,
+        condition: Option<ScalarExpr>,
+        spill: SpillSpace,
+    ) -> HashJoinOperator {
+        HashJoinOperator {
+            left,
+            right,
+            left_keys,
+            right_keys,
+            condition,
+            spill,
+            params: Arc::new([]),
+            table: HashMap::new(),
+            probe: Probe::Done,
+            partitions: Vec::new(),
+            outer: None,
+            spilled: 0,
+        }
+    }
+
+    /// Keep the pairs of partitions of both inputs with rows to join.
+    fn add_partitions(
+        &mut self,
+        build: Vec<SpillWriter>,
+        probe: Vec<SpillWriter>,
+        depth: u32,
+    ) -> ExecResult<()> {
+        for (build, probe) in build.into_iter().zip(probe) {
+            self.spilled += build.rows() + probe.rows();
+            if build.rows() > 0 && probe.rows() > 0 {
+                self.partitions.push(Partition {
+                    build: build.finish()?,
+                    probe: probe.finish()?,
+                    depth: depth + 1,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Hold the rows of the right of the next partition to join, splitting any too large,
+    /// or return `false` if none are left.
+    fn next_partition(&mut self) -> ExecResult<bool> {
+        self.table.clear();
+        while let Some(Partition {
+            mut build,
+            mut probe,
+            depth,
+        }) = self.partitions.pop()
+        {
+            tracing::trace!(rows = build.remaining(), depth, "Joining partition");
+            let params = &self.params;
+            let split = load(
+                &mut self.table,
+                &self.right_keys,
+                params,
+                &self.spill,
+                depth,
+                || build.next(),
+            )?;
+            if let Some(build) = split {
+                let mut partitions = self.spill.partitions("hash-join-probe")?;
+                split_into(&mut partitions, &self.left_keys, params, depth, || {
+                    probe.next()
+                })?;
+                self.add_partitions(build, partitions, depth)?;
+            } else {
+                self.probe = Probe::Partition(probe);
+                return Ok(true);
+            }
+        }
+        self.probe = Probe::Done;
+        Ok(false)
+    }
+}
+
+impl Operator for HashJoinOperator {
+    fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()> {
+        self.params = params.clone();
+        self.table.clear();
+        self.partitions.clear();
+        self.outer = None;
+        self.probe = Probe::Done;
+        self.right.open(params)?;
+        let right = &mut self.right;
+        let split = load(
+            &mut self.table,
+            &self.right_keys,
+            params,
+            &self.spill,
+            0,
+            || right.next(),
+        )?;
+        self.right.close();
+        self.left.open(params)?;
+        if let Some(build) = split {
+            tracing::debug!(budget = self.spill.budget(), "Hash join spilling");
+            let mut partitions = self.spill.partitions("hash-join-probe")?;
+            let left = &mut self.left;
+            split_into(&mut partitions, &self.left_keys, params, 0, || left.next())?;
+            self.left.close();
+            self.add_partitions(build, partitions, 0)?;
+        } else if !self.table.is_empty() {
+            self.probe = Probe::Input;
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        loop {
+            if let Some((outer, key, position)) = &mut self.outer {
+                if let Some(inner) = self.table.get(key).and_then(|rows| rows.get(*position)) {
+                    *position += 1;
+                    let row = joined(outer, inner);
+                    match &self.condition {
+                        Some(condition) if !condition.eval(&row, &self.params)?.is_true() => {}
+                        _ => return Ok(Some(row)),
+                    }
+                    continue;
+                }
+                self.outer = None;
+            }
+            let row = match &mut self.probe {
+                Probe::Input => self.left.next()?,
+                Probe::Partition(probe) => probe.next()?,
+                Probe::Done => None,
+            };
+            let Some(row) = row else {
+                if self.next_partition()? {
+                    continue;
+                }
+                return Ok(None);
+            };
+            if let Some(key) = keys(&self.left_keys, &row, &self.params)? {
+                if self.table.contains_key(&key) {
+                    self.outer = Some((row, key, 0));
+                }
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        self.left.close();
+        self.table = HashMap::new();
+        self.partitions.clear();
+        self.probe = Probe::Done;
+        self.outer = None;
+    }
+
+    fn children(&self) -> Vec<&Node> {
+        vec![&self.left, &self.right]
+    }
+
+    fn spilled(&self) -> u64 {
+        self.spilled
+    }
+}
+
+/// Pairs of rows of the inputs whose keys are equal, reading both in ascending order of
+/// their keys together and holding the rows of the right of equal keys.
+pub(crate) struct MergeJoinOperator {
+    left: Node,
+    right: Node,
+    left_keys: Vec<ScalarExpr>,
+    right_keys: Vec<ScalarExpr>,
+    condition: Option<ScalarExpr>,
+    params: Arc<[Value]>,
+    /// Next row of the right not yet in a group, after its keys
+    next_right: Option<(Vec<Value>, Vec<Value>)>,
+    /// Keys of the rows of the right in `group`
+    group_key: Option<Vec<Value>>,
+    /// Rows of the right whose keys equal `group_key`
+    group: Vec<Vec<Value>>,
+    /// Row of the left being joined with the group, and the position of its next match
+    outer: Option<(Vec<Value>, usize)>,
+}
+
+impl MergeJoinOperator {
+    pub(crate) fn new(
+        left: Node,
+        right: Node,
+        left_keys: Vec<ScalarExpr>,
+        right_keys: Vec<ScalarExpr>,
+        condition: Option<ScalarExpr>,
+    ) -> MergeJoinOperator {
+        MergeJoinOperator {
+            left,
+            right,
+            left_keys,
+            right_keys,
+            condition,
+            params: Arc::new([]),
+            next_right: None,
+            group_key: None,
+            group: Vec::new(),
+            outer: None,
+        }
+    }
+}
+
+impl Operator for MergeJoinOperator {
+    fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()> {
+        self.params = params.clone();
+        self.group_key = None;
+        self.group.clear();
+        self.outer = None;
+        self.left.open(params)?;
+        self.right.open(params)?;
+        self.next_right = keyed(&mut self.right, &self.right_keys, params)?;
+        Ok(())
+    }
+
+    fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        loop {
+            if let Some((outer, position)) = &mut self.outer {
+                if let Some(inner) = self.group.get(*position) {
+                    *position += 1;
+                    let row = joined(outer, inner);
+                    match &self.condition {
+                        Some(condition) if !condition.eval(&row, &self.params)?.is_true() => {}
+                        _ => return Ok(Some(row)),
+                    }
+                    continue;
+                }
+                self.outer = None;
+            }
+            let Some((key, row)) = keyed(&mut self.left, &self.left_keys, &self.params)? else {
+                return Ok(None);
+            };
+            if self.group_key.as_ref() != Some(&key) {
+                self.group.clear();
+                self.group_key = None;
+                while self
+                    .next_right
+                    .as_ref()
+                    .is_some_and(|(right, _)| *right < key)
+                {
+                    self.next_right = keyed(&mut self.right, &self.right_keys, &self.params)?;
+                }
+                while let Some((_, row)) = self.next_right.take_if(|(right, _)| *right == key) {
+                    self.group.push(row);
+                    self.next_right = keyed(&mut self.right, &self.right_keys, &self.params)?;
+                }
+                if self.group.is_empty() {
+                    if self.next_right.is_none() {
+                        return Ok(None);
+                    }
+                    continue;
+                }
+                self.group_key = Some(key);
+            }
+            self.outer = Some((row, 0));
+        }
+    }
+
+    fn close(&mut self) {
+        self.left.close();
+        self.right.close();
+        self.next_right = None;
+        self.group_key = None;
+        self.group = Vec::new();
+        self.outer = None;
+    }
+
+    fn children(&self) -> Vec<&Node> {
+        vec![&self.left, &self.right]
+    }
+}
+
+/// Row of the columns of a row of the left followed by those of a row of the right.
+fn joined(outer: &[Value], inner: &[Value]) -> Vec<Value> {
+    let mut row = Vec::with_capacity(outer.len() + inner.len());
+    row.extend_from_slice(outer);
+    row.extend_from_slice(inner);
+    row
+}
+
+/// Values of the keys of a row, or `None` if any is `NULL`, matching no row.
+fn keys(exprs: &[ScalarExpr], row: &[Value], params: &[Value]) -> ExecResult<Option<Vec<Value>>> {
+    let mut key = Vec::with_capacity(exprs.len());
+    for expr in exprs {
+        let value = expr.eval(row, params)?;
+        if value.is_null() {
+            return Ok(None);
+        }
+        key.push(value);
+    }
+    Ok(Some(key))
+}
+
+/// Next row of an input whose keys aren't `NULL`, after its keys.
+fn keyed(
+    input: &mut Node,
+    exprs: &[ScalarExpr],
+    params: &[Value],
+) -> ExecResult<Option<(Vec<Value>, Vec<Value>)>> {
+    while let Some(row) = input.next()? {
+        if let Some(key) = keys(exprs, &row, params)? {
+            return Ok(Some((key, row)));
+        }
+    }
+    Ok(None)
+}
+
+/// Hold rows by their keys, or once they exceed the memory budget, write them all to
+/// partitions returned instead.
+fn load(
+    table: &mut HashMap<Vec<Value>, Vec<Vec<Value>>>,
+    exprs: &[ScalarExpr],
+    params: &[Value],
+    spill: &SpillSpace,
+    depth: u32,
+    mut next: impl FnMut() -> ExecResult<Option<Vec<Value>>>,
+) -> ExecResult<Option<Vec<SpillWriter>>> {
+    let mut bytes = 0;
+    while let Some(row) = next()? {
+        let Some(key) = keys(exprs, &row, params)? else {
+            continue;
+        };
+        bytes += row_size(&row) + row_size(&key);
+        table.entry(key).or_default().push(row);
+        if depth < MAX_DEPTH && spill.exceeded(bytes) {
+            let mut partitions = spill.partitions("hash-join-build")?;
+            for (key, rows) in table.drain() {
+                let file = &mut partitions[partition(&key, depth)];
+                rows.iter().try_for_each(|row| file.write(row))?;
+            }
+            split_into(&mut partitions, exprs, params, depth, next)?;
+            return Ok(Some(partitions));
+        }
+    }
+    Ok(None)
+}
+
+/// Write each row whose keys aren't `NULL` to the partition of its keys.
+fn split_into(
+    partitions: &mut [SpillWriter],
+    exprs: &[ScalarExpr],
+    params: &[Value],
+    depth: u32,
+    mut next: impl FnMut() -> ExecResult<Option<Vec<Value>>>,
+) -> ExecResult<()> {
+    while let Some(row) = next()? {
+        if let Some(key) = keys(exprs, &row, params)? {
+            partitions[partition(&key, depth)].write(&row)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Executor, MemoryStorage};
+    use minql_lang::ast::BinaryOperator;
+    use minql_plan::{Estimate, PhysicalPlan};
+    use minql_value::{ScalarExpr, Value};
+    use minql_vfs::{FileSystem, MemoryFileSystem, VirtualFileSystem};
+    use std::sync::Arc;
+
+    fn values(rows: impl IntoIterator<Item = (Option<i64>, String)>) -> Box<PhysicalPlan> {
+        let rows = rows
+            .into_iter()
+            .map(|(key, name)| {
+                vec![
+                    ScalarExpr::Literal(key.map_or(Value::Null, Value::from)),
+                    ScalarExpr::Literal(Value::from(name)),
+                ]
+            })
+            .collect();
+        Box::new(PhysicalPlan::Values {
+            rows,
+            estimate: Estimate::default(),
+        })
+    }
+
+    fn named(rows: &[(Option<i64>, &str)]) -> Box<PhysicalPlan> {
+        values(rows.iter().map(|(key, name)| (*key, (*name).to_string())))
+    }
+
+    fn hash_join(left: Box<PhysicalPlan>, right: Box<PhysicalPlan>) -> PhysicalPlan {
+        PhysicalPlan::HashJoin {
+            left,
+            right,
+            left_keys: vec![ScalarExpr::Column(0)],
+            right_keys: vec![ScalarExpr::Column(0)],
+            condition: None,
+            estimate: Estimate::default(),
+        }
+    }
+
+    fn pairs(executor: &Executor, plan: &PhysicalPlan) -> Vec<String> {
+        let mut pairs: Vec<String> = executor
+            .execute(plan, Vec::new())
+            .unwrap()
+            .map(|row| {
+                let row = row.unwrap();
+                format!("{}{}", row[1], row[3])
+            })
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_equi_joins() {
+        let executor = Executor::new(Arc::new(MemoryStorage::new()));
+        let left = named(&[
+            (None, "n"),
+            (Some(1), "a"),
+            (Some(2), "b"),
+            (Some(2), "c"),
+            (Some(4), "d"),
+            (Some(5), "e"),
+        ]);
+        let right = named(&[
+            (Some(1), "x"),
+            (Some(2), "y"),
+            (Some(2), "z"),
+            (Some(3), "w"),
+            (Some(5), "v"),
+            (None, "m"),
+        ]);
+        let expected = ["ax", "by", "bz", "cy", "cz", "ev"];
+        let hashed = hash_join(left.clone(), right.clone());
+        assert_eq!(pairs(&executor, &hashed), expected);
+        let PhysicalPlan::HashJoin {
+            left,
+            right,
+            left_keys,
+            right_keys,
+            ..
+        } = hashed
+        else {
+            unreachable!()
+        };
+        let merged = PhysicalPlan::MergeJoin {
+            left,
+            right,
+            left_keys,
+            right_keys,
+            condition: Some(ScalarExpr::Binary {
+                left: Box::new(ScalarExpr::Column(3)),
+                op: BinaryOperator::NotEq,
+                right: Box::new(ScalarExpr::Literal(Value::from("z"))),
+            }),
+            estimate: Estimate::default(),
+        };
+        assert_eq!(pairs(&executor, &merged), ["ax", "by", "cy", "ev"]);
+
+        let empty = hash_join(named(&[(Some(1), "a")]), named(&[]));
+        assert!(pairs(&executor, &empty).is_empty());
+        let PhysicalPlan::HashJoin { left, right, .. } = empty else {
+            unreachable!()
+        };
+        let merged = PhysicalPlan::MergeJoin {
+            left: right,
+            right: left,
+            left_keys: vec![ScalarExpr::Column(0)],
+            right_keys: vec![ScalarExpr::Column(0)],
+            condition: None,
+            estimate: Estimate::default(),
+        };
+        assert!(pairs(&executor, &merged).is_empty());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_hash_join_spill() {
+        let fs = VirtualFileSystem::new(MemoryFileSystem::new());
+        let spilling = Executor::new(Arc::new(MemoryStorage::new()))
+            .with_spill_directory(fs.clone(), "/spill")
+            .with_memory_budget(16 * 1024);
+        let in_memory = Executor::new(Arc::new(MemoryStorage::new()));
+
+        let left = values((0..2000).map(|id| (Some(id % 500), format!("l{id}"))));
+        let right = values((0..600).map(|id| (Some(id), format!("r{id}"))));
+        let plan = hash_join(left, right);
+        let expected = pairs(&in_memory, &plan);
+        assert_eq!(expected.len(), 2000);
+        let mut cursor = spilling.execute(&plan, Vec::new()).unwrap();
+        assert_eq!(cursor.by_ref().count(), 2000);
+        assert_eq!(cursor.metrics().spilled, 2600);
+        assert!(cursor.metrics().to_string().contains(" spilled=2600)"));
+        assert_eq!(pairs(&spilling, &plan), expected);
+        drop(cursor);
+        assert!(fs.list_directory("/spill").unwrap().is_empty());
+
+        // Rows of one key can't be split, so are held once split as often as allowed
+        let left = values((0..3).map(|id| (Some(7), format!("l{id}"))));
+        let right = values((0..300).map(|id| (Some(7), format!("r{id}"))));
+        let plan = hash_join(left, right);
+        let mut cursor = spilling.execute(&plan, Vec::new()).unwrap();
+        assert_eq!(cursor.by_ref().count(), 900);
+        assert!(cursor.metrics().spilled > 303);
+        drop(cursor);
+        assert!(fs.list_directory("/spill").unwrap().is_empty());
+    }
+}
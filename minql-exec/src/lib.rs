@@ -0,0 +1,110 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Pull Based Query Executor
+//!
+//! The [`Executor`] runs a [`PhysicalPlan`](minql_plan::PhysicalPlan) of `minql-plan` as a
+//! tree of operators, each pulling rows from its inputs one at a time as rows are read from
+//! the [`Cursor`] at its root. Tables are read from a [`Storage`] holding their rows in the
+//! row format of `minql-value`, such as the [`MemoryStorage`], which keeps the
+//! [`IndexEntries`] of every index of a table in memory with its rows, and every operator keeps
+//! [`OperatorMetrics`] of the rows it returned and the time it took. Sorts, hash joins
+//! and aggregates whose rows exceed the memory budget of the executor write them to
+//! temporary files of a `minql-vfs` filesystem, given one with
+//! [`Executor::with_spill_directory`].
+//!
+//! A [`Session`] runs whole statements against the catalog of an [`Engine`], changing the
+//! catalog for DDL, inserting rows into the storage of the executor and planning and running
+//! queries. `BACKUP TO` and `RESTORE FROM` copy every database of an engine to and from the
+//! filesystem of a URI, checked against a manifest of the digests of its files.
+//!
+//! ```rust
+//! use minql_catalog::{Catalog, ColumnSchema, TableSchema};
+//! use minql_exec::{Executor, MemoryStorage, Storage};
+//! use minql_lang::ast::{DataType, Statement};
+//! use minql_lang::Parser;
+//! use minql_plan::Planner;
+//! use minql_value::Value;
+//! use minql_vfs::MemoryFileSystem;
+//! use std::sync::Arc;
+//!
+//! let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+//! let mut transaction = catalog.begin();
+//! transaction.create_database("shop", "mem:///data/shop").unwrap();
+//! let table = TableSchema::new("items")
+//!     .with_column(ColumnSchema::new("kind", DataType::Text))
+//!     .with_column(ColumnSchema::new("price", DataType::BigInt));
+//! transaction.create_table("shop", table).unwrap();
+//! let snapshot = transaction.commit().unwrap();
+//!
+//! let items = snapshot.table("shop", "items").unwrap();
+//! let storage = Arc::new(MemoryStorage::new());
+//! for (kind, price) in [("tea", 3), ("cake", 5), ("tea", 4)] {
+//!     let row = vec![Value::from(kind), Value::from(price)];
+//!     storage.insert(items, row).unwrap();
+//! }
+//!
+//! let sql = "SELECT kind, sum(price) FROM items GROUP BY kind ORDER BY kind";
+//! let Statement::Query(query) = Parser::parse_statement(sql).unwrap() else { unreachable!() };
+//! let plan = Planner::new().plan_query(&snapshot, "shop", &query).unwrap();
+//! let rows = Executor::new(storage)
+//!     .execute(&plan.plan, Vec::new())
+//!     .unwrap()
+//!     .collect::<Result<Vec<_>, _>>()
+//!     .unwrap();
+//! assert_eq!(rows, [
+//!     [Value::from("cake"), Value::from(5)],
+//!     [Value::from("tea"), Value::from(7)],
+//! ]);
+//! ```
+
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod aggregate;
+mod backup;
+mod chunked;
+mod executor;
+mod filter;
+mod index;
+mod join;
+mod metrics;
+mod operator;
+mod result;
+mod scan;
+mod session;
+mod sort;
+mod spill;
+mod storage;
+
+pub use self::executor::{Cursor, Executor};
+pub use self::index::IndexEntries;
+pub use self::metrics::OperatorMetrics;
+pub use self::result::{ExecError, ExecResult};
+pub use self::session::{Engine, Prepared, Response, Session};
+pub use self::storage::{MemoryStorage, RowStream, Storage};
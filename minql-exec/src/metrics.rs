@@ -0,0 +1,65 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::time::Duration;
+
+/// Work an operator of a running plan did, and that of its inputs, as `EXPLAIN ANALYZE`
+/// shows it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OperatorMetrics {
+    /// Operator, as `EXPLAIN` describes it
+    pub operator: String,
+    /// Rows returned
+    pub rows: u64,
+    /// Times the operator was opened
+    pub loops: u64,
+    /// Time spent opening the operator and reading rows from it, including its inputs
+    pub elapsed: Duration,
+    /// Rows written to temporary files for not fitting in memory
+    pub spilled: u64,
+    /// Metrics of the inputs, in order
+    pub children: Vec<OperatorMetrics>,
+}
+
+impl OperatorMetrics {
+    /// Write the metrics as an indented line per operator.
+    fn explain(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        write!(
+            f,
+            "{:width$}{} (rows={} loops={} time={:.3}ms",
+            "",
+            self.operator,
+            self.rows,
+            self.loops,
+            self.elapsed.as_secs_f64() * 1000.0,
+            width = depth * 2
+        )?;
+        if self.spilled > 0 {
+            write!(f, " spilled={}", self.spilled)?;
+        }
+        writeln!(f, ")")?;
+        for child in &self.children {
+            child.explain(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for OperatorMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.explain(f, 0)
+    }
+}
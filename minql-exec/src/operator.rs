@@ -0,0 +1,109 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{ExecResult, OperatorMetrics};
+use minql_value::Value;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Physical operator of a running plan, returning its rows one at a time as they are
+/// pulled.
+///
+/// An operator is opened before its first row is read, reading whatever it needs up front,
+/// and closed when no more rows are wanted, releasing what it holds. It may be opened again
+/// after closing to return its rows again.
+pub(crate) trait Operator: Send {
+    /// Prepare to return rows, given the values of the parameters of the query.
+    fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()>;
+
+    /// Next row, or `None` after the last.
+    fn next(&mut self) -> ExecResult<Option<Vec<Value>>>;
+
+    /// Release what the operator holds.
+    fn close(&mut self);
+
+    /// Inputs of the operator, in order.
+    fn children(&self) -> Vec<&Node> {
+        Vec::new()
+    }
+
+    /// Rows written to temporary files so far.
+    fn spilled(&self) -> u64 {
+        0
+    }
+}
+
+/// Operator of a running plan with the metrics of its work.
+pub(crate) struct Node {
+    operator: Box<dyn Operator>,
+    /// Description of the operator
+    describe: String,
+    rows: u64,
+    loops: u64,
+    elapsed: Duration,
+}
+
+impl Node {
+    /// Track the work of an operator.
+    pub(crate) fn new(describe: String, operator: impl Operator + 'static) -> Node {
+        Node {
+            operator: Box::new(operator),
+            describe,
+            rows: 0,
+            loops: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()> {
+        let start = Instant::now();
+        self.loops += 1;
+        let result = self.operator.open(params);
+        self.elapsed += start.elapsed();
+        result
+    }
+
+    pub(crate) fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        let start = Instant::now();
+        let row = self.operator.next();
+        self.elapsed += start.elapsed();
+        if let Ok(Some(_)) = row {
+            self.rows += 1;
+        }
+        row
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.operator.close();
+    }
+
+    /// Metrics of the operator and its inputs so far.
+    pub(crate) fn metrics(&self) -> OperatorMetrics {
+        OperatorMetrics {
+            operator: self.describe.clone(),
+            rows: self.rows,
+            loops: self.loops,
+            elapsed: self.elapsed,
+            spilled: self.operator.spilled(),
+            children: self
+                .operator
+                .children()
+                .into_iter()
+                .map(Node::metrics)
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use minql_catalog::CatalogError;
+use minql_lang::LangError;
+use minql_plan::PlanError;
+use minql_value::ValueError;
+use minql_vfs::{FileSystemError, ManifestMismatch};
+
+/// Result Type for the Executor
+pub type ExecResult<T> = Result<T, ExecError>;
+
+/// Error Type for the Executor
+#[derive(Debug)]
+pub enum ExecError {
+    /// Table has no storage
+    TableMissing(String),
+    /// Row has more or fewer values than its table has columns
+    RowWidth {
+        /// Name of the table
+        table: String,
+        /// Number of columns of the table
+        expected: usize,
+        /// Number of values of the row
+        found: usize,
+    },
+    /// `NULL` given for a column that doesn't accept it
+    NotNull(String),
+    /// Values of the columns of a unique index already indexed for another row
+    UniqueViolation(String),
+    /// Error evaluating an expression or decoding a row
+    Value(ValueError),
+    /// Error writing or reading rows spilled to temporary files
+    FileSystem(FileSystemError),
+    /// Number of parameter values given differs from the number a statement needs
+    Parameters {
+        /// Number of parameters of the statement
+        expected: usize,
+        /// Number of values given
+        found: usize,
+    },
+    /// Statement uses a feature the session doesn't support yet
+    Unsupported(String),
+    /// Error parsing a statement
+    Lang(LangError),
+    /// Error planning a statement
+    Plan(PlanError),
+    /// Error reading or changing the catalog
+    Catalog(CatalogError),
+    /// Backup is missing or incomplete, or a backup or restore would overwrite another
+    InvalidBackup(String),
+    /// Files of a backup differ from its manifest
+    BackupMismatch(Vec<ManifestMismatch>),
+}
+
+impl std::fmt::Display for ExecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ExecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExecError::Value(err) => Some(err),
+            ExecError::FileSystem(err) => Some(err),
+            ExecError::Lang(err) => Some(err),
+            ExecError::Plan(err) => Some(err),
+            ExecError::Catalog(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ValueError> for ExecError {
+    fn from(err: ValueError) -> Self {
+        ExecError::Value(err)
+    }
+}
+
+impl From<FileSystemError> for ExecError {
+    fn from(err: FileSystemError) -> Self {
+        ExecError::FileSystem(err)
+    }
+}
+
+impl From<std::io::Error> for ExecError {
+    fn from(err: std::io::Error) -> Self {
+        ExecError::FileSystem(FileSystemError::io_error(err))
+    }
+}
+
+impl From<LangError> for ExecError {
+    fn from(err: LangError) -> Self {
+        ExecError::Lang(err)
+    }
+}
+
+impl From<PlanError> for ExecError {
+    fn from(err: PlanError) -> Self {
+        ExecError::Plan(err)
+    }
+}
+
+impl From<CatalogError> for ExecError {
+    fn from(err: CatalogError) -> Self {
+        ExecError::Catalog(err)
+    }
+}
@@ -0,0 +1,390 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::operator::Operator;
+use crate::{ExecResult, RowStream, Storage};
+use minql_catalog::{IndexSchema, TableSchema};
+use minql_lang::ast::DataType;
+use minql_plan::IndexBounds;
+use minql_value::{encode_key, ScalarExpr, Value};
+use std::cmp::Ordering;
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Range of keys of an index, as the storage reads it.
+type KeyRange = (Bound<Vec<u8>>, Bound<Vec<u8>>);
+
+/// Rows of constant expressions.
+pub(crate) struct ValuesOperator {
+    exprs: Vec<Vec<ScalarExpr>>,
+    rows: std::vec::IntoIter<Vec<Value>>,
+}
+
+impl ValuesOperator {
+    pub(crate) fn new(exprs: Vec<Vec<ScalarExpr>>) -> ValuesOperator {
+        ValuesOperator {
+            exprs,
+            rows: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl Operator for ValuesOperator {
+    fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()> {
+        self.rows = self
+            .exprs
+            .iter()
+            .map(|row| row.iter().map(|expr| expr.eval(&[], params)).collect())
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter();
+        Ok(())
+    }
+
+    fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        Ok(self.rows.next())
+    }
+
+    fn close(&mut self) {
+        self.rows = Vec::new().into_iter();
+    }
+}
+
+/// Every row of a table meeting a condition.
+pub(crate) struct SeqScanOperator {
+    storage: Arc<dyn Storage>,
+    table: Arc<TableSchema>,
+    filter: Option<ScalarExpr>,
+    params: Arc<[Value]>,
+    rows: Option<RowStream>,
+}
+
+impl SeqScanOperator {
+    pub(crate) fn new(
+        storage: Arc<dyn Storage>,
+        table: Arc<TableSchema>,
+        filter: Option<ScalarExpr>,
+    ) -> SeqScanOperator {
+        SeqScanOperator {
+            storage,
+            table,
+            filter,
+            params: Arc::new([]),
+            rows: None,
+        }
+    }
+}
+
+impl Operator for SeqScanOperator {
+    fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()> {
+        self.params = params.clone();
+        self.rows = Some(self.storage.scan(&self.table)?);
+        Ok(())
+    }
+
+    fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        filtered(self.rows.as_mut(), self.filter.as_ref(), &self.params)
+    }
+
+    fn close(&mut self) {
+        self.rows = None;
+    }
+}
+
+/// Rows of a table within a range of an index meeting a condition.
+pub(crate) struct IndexScanOperator {
+    storage: Arc<dyn Storage>,
+    table: Arc<TableSchema>,
+    index: Arc<IndexSchema>,
+    bounds: IndexBounds,
+    filter: Option<ScalarExpr>,
+    params: Arc<[Value]>,
+    rows: Option<RowStream>,
+}
+
+impl IndexScanOperator {
+    pub(crate) fn new(
+        storage: Arc<dyn Storage>,
+        table: Arc<TableSchema>,
+        index: Arc<IndexSchema>,
+        bounds: IndexBounds,
+        filter: Option<ScalarExpr>,
+    ) -> IndexScanOperator {
+        IndexScanOperator {
+            storage,
+            table,
+            index,
+            bounds,
+            filter,
+            params: Arc::new([]),
+            rows: None,
+        }
+    }
+
+    /// Range of keys of the index holding the rows within the bounds, or `None` if no row
+    /// can be.
+    ///
+    /// Values of the bounds are converted to the types of their columns first. Where that
+    /// rounds them, equalities match nothing, and ranges are adjusted to hold the same
+    /// values of the column as the bounds would.
+    fn key_range(&self) -> ExecResult<Option<KeyRange>> {
+        let data_type =
+            |position: usize| self.table.columns[self.index.columns[position]].data_type;
+        let mut prefix = Vec::new();
+        for (position, expr) in self.bounds.prefix.iter().enumerate() {
+            match self.convert(expr, data_type(position))? {
+                Some((value, Ordering::Equal)) => encode_key(&[value], &mut prefix),
+                _ => return Ok(None),
+            }
+        }
+        if self.bounds.lower == Bound::Unbounded && self.bounds.upper == Bound::Unbounded {
+            let lower = if prefix.is_empty() {
+                Bound::Unbounded
+            } else {
+                Bound::Included(prefix.clone())
+            };
+            return Ok(Some((lower, upper_after(&prefix))));
+        }
+        let data_type = data_type(self.bounds.prefix.len());
+        let lower = match &self.bounds.lower {
+            Bound::Unbounded => {
+                // Skip `NULL`, whose keys come before those of every other value
+                let mut key = prefix.clone();
+                key.push(1);
+                Bound::Included(key)
+            }
+            Bound::Included(expr) | Bound::Excluded(expr) => {
+                let Some((value, rounded)) = self.convert(expr, data_type)? else {
+                    return Ok(None);
+                };
+                let inclusive = matches!(self.bounds.lower, Bound::Included(_));
+                let mut key = prefix.clone();
+                encode_key(&[value], &mut key);
+                if rounded == Ordering::Greater || (inclusive && rounded == Ordering::Equal) {
+                    Bound::Included(key)
+                } else {
+                    match successor(&key) {
+                        Some(key) => Bound::Included(key),
+                        None => return Ok(None),
+                    }
+                }
+            }
+        };
+        let upper = match &self.bounds.upper {
+            Bound::Unbounded => upper_after(&prefix),
+            Bound::Included(expr) | Bound::Excluded(expr) => {
+                let Some((value, rounded)) = self.convert(expr, data_type)? else {
+                    return Ok(None);
+                };
+                let inclusive = matches!(self.bounds.upper, Bound::Included(_));
+                let mut key = prefix.clone();
+                encode_key(&[value], &mut key);
+                if rounded == Ordering::Less || (inclusive && rounded == Ordering::Equal) {
+                    upper_after(&key)
+                } else {
+                    Bound::Excluded(key)
+                }
+            }
+        };
+        Ok(Some((lower, upper)))
+    }
+
+    /// Value of a bound converted to the type of its column, and how the conversion rounded
+    /// it, or `None` for `NULL`, which bounds nothing.
+    fn convert(
+        &self,
+        expr: &ScalarExpr,
+        data_type: DataType,
+    ) -> ExecResult<Option<(Value, Ordering)>> {
+        let value = expr.eval(&[], &self.params)?;
+        let converted = value.cast(data_type)?;
+        Ok(converted
+            .compare(&value)?
+            .map(|rounded| (converted, rounded)))
+    }
+}
+
+impl Operator for IndexScanOperator {
+    fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()> {
+        self.params = params.clone();
+        self.rows = match self.key_range()? {
+            Some((lower, upper)) => {
+                Some(
+                    self.storage
+                        .index_scan(&self.table, &self.index, lower, upper)?,
+                )
+            }
+            None => Some(Box::new(std::iter::empty())),
+        };
+        Ok(())
+    }
+
+    fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        filtered(self.rows.as_mut(), self.filter.as_ref(), &self.params)
+    }
+
+    fn close(&mut self) {
+        self.rows = None;
+    }
+}
+
+/// Next row read meeting a condition, if any.
+fn filtered(
+    rows: Option<&mut RowStream>,
+    filter: Option<&ScalarExpr>,
+    params: &[Value],
+) -> ExecResult<Option<Vec<Value>>> {
+    let Some(rows) = rows else {
+        return Ok(None);
+    };
+    for row in rows {
+        let row = row?;
+        match filter {
+            Some(filter) if !filter.eval(&row, params)?.is_true() => {}
+            _ => return Ok(Some(row)),
+        }
+    }
+    Ok(None)
+}
+
+/// Smallest key after every key starting with `key`, or `None` if there is none.
+fn successor(key: &[u8]) -> Option<Vec<u8>> {
+    let mut key = key.to_vec();
+    while let Some(byte) = key.pop() {
+        if byte < u8::MAX {
+            key.push(byte + 1);
+            return Some(key);
+        }
+    }
+    None
+}
+
+/// Upper bound of the keys starting with `key`.
+fn upper_after(key: &[u8]) -> Bound<Vec<u8>> {
+    successor(key).map_or(Bound::Unbounded, Bound::Excluded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::IndexScanOperator;
+    use crate::operator::Operator;
+    use crate::{MemoryStorage, Storage};
+    use minql_catalog::{ColumnSchema, IndexSchema, TableSchema};
+    use minql_lang::ast::DataType;
+    use minql_plan::IndexBounds;
+    use minql_value::{ScalarExpr, Value};
+    use std::ops::Bound;
+    use std::sync::Arc;
+
+    fn literal(value: impl Into<Value>) -> ScalarExpr {
+        ScalarExpr::Literal(value.into())
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_index_ranges() {
+        let table = Arc::new(
+            TableSchema::new("t")
+                .with_column(ColumnSchema::new("a", DataType::Text))
+                .with_column(ColumnSchema::new("b", DataType::Integer)),
+        );
+        let index = Arc::new(IndexSchema {
+            id: 1,
+            database: "db".to_string(),
+            name: "t_a_b".to_string(),
+            table: table.id,
+            columns: vec![0, 1],
+            unique: false,
+            uri: "mem:///t_a_b".to_string(),
+        });
+        let storage = Arc::new(MemoryStorage::new());
+        storage.create_index(&table, &index).unwrap();
+        for (a, b) in [
+            ("y", Some(2)),
+            ("x", Some(3)),
+            ("x", None),
+            ("x", Some(1)),
+            ("w", Some(2)),
+            ("x", Some(2)),
+        ] {
+            let row = vec![Value::from(a), Value::from(b)];
+            storage.insert(&table, row).unwrap();
+        }
+        let scan = |prefix: &[ScalarExpr], lower, upper| {
+            let bounds = IndexBounds {
+                prefix: prefix.to_vec(),
+                lower,
+                upper,
+            };
+            let mut operator =
+                IndexScanOperator::new(storage.clone(), table.clone(), index.clone(), bounds, None);
+            operator.open(&Arc::from(Vec::new())).unwrap();
+            let mut rows = Vec::new();
+            while let Some(row) = operator.next().unwrap() {
+                rows.push(format!("{}{}", row[0], row[1]));
+            }
+            operator.close();
+            rows
+        };
+        let x = [literal("x")];
+        assert_eq!(
+            scan(&x, Bound::Unbounded, Bound::Unbounded),
+            ["xNULL", "x1", "x2", "x3"]
+        );
+        assert_eq!(
+            scan(&x, Bound::Unbounded, Bound::Excluded(literal(3))),
+            ["x1", "x2"]
+        );
+        assert_eq!(
+            scan(&x, Bound::Excluded(literal(1)), Bound::Included(literal(3))),
+            ["x2", "x3"]
+        );
+        assert_eq!(
+            scan(
+                &x,
+                Bound::Excluded(literal(1.5)),
+                Bound::Excluded(literal(2.5))
+            ),
+            ["x2"]
+        );
+        assert_eq!(
+            scan(
+                &x,
+                Bound::Included(literal(1.5)),
+                Bound::Included(literal(2.5))
+            ),
+            ["x2"]
+        );
+        assert!(scan(&x, Bound::Included(literal(Value::Null)), Bound::Unbounded).is_empty());
+        assert!(scan(
+            &[literal("x"), literal(2.5)],
+            Bound::Unbounded,
+            Bound::Unbounded
+        )
+        .is_empty());
+        assert_eq!(
+            scan(
+                &[literal("x"), literal(2.0)],
+                Bound::Unbounded,
+                Bound::Unbounded
+            ),
+            ["x2"]
+        );
+        assert_eq!(
+            scan(&[], Bound::Excluded(literal("w")), Bound::Unbounded),
+            ["xNULL", "x1", "x2", "x3", "y2"]
+        );
+    }
+}
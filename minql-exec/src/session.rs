@@ -0,0 +1,1066 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::{Cursor, ExecError, ExecResult, Executor};
+use minql_catalog::{Catalog, CatalogError, CatalogSnapshot, StatisticsCollector, TableSchema};
+use minql_lang::ast::{Ident, Query, Statement};
+use minql_lang::Parser;
+use minql_plan::{Estimate, PhysicalPlan, PlanError, Planner, QueryPlan};
+use minql_value::{AggregateUdf, FunctionRegistry, ScalarExpr, ScalarUdf, Scope, Value};
+use minql_vfs::{FileSystem, VirtualFileSystemManager};
+use std::sync::Arc;
+
+/// Catalog, planner and executor shared by the [`Session`]s running statements against them.
+#[derive(Debug)]
+pub struct Engine<F: FileSystem> {
+    catalog: Catalog<F>,
+    planner: Planner,
+    executor: Executor,
+    filesystems: Arc<VirtualFileSystemManager>,
+}
+
+/// Connection to a database of an [`Engine`], running one statement at a time.
+///
+/// Queries may call the user-defined functions registered with the session, starting from
+/// those of the planner of the engine.
+///
+/// Each statement is committed as it runs: `BEGIN` and `COMMIT` are accepted and do nothing,
+/// and `ROLLBACK` is refused. Rows are checked against `NOT NULL` columns and unique indexes
+/// as they are inserted, and an `INSERT` inserts either every one of its rows or, if any
+/// fails, none.
+///
+/// ```rust
+/// use minql_catalog::Catalog;
+/// use minql_exec::{Engine, Executor, MemoryStorage, Response, Session};
+/// use minql_value::Value;
+/// use minql_vfs::MemoryFileSystem;
+/// use std::sync::Arc;
+///
+/// let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+/// let mut transaction = catalog.begin();
+/// transaction.create_database("shop", "mem:///data/shop").unwrap();
+/// transaction.commit().unwrap();
+/// let executor = Executor::new(Arc::new(MemoryStorage::new()));
+/// let engine = Arc::new(Engine::new(catalog, executor));
+///
+/// let session = Session::new(engine, "shop").unwrap();
+/// session.run("CREATE TABLE items (kind TEXT, price BIGINT)").unwrap();
+/// session.run("INSERT INTO items VALUES ('tea', 3), ('cake', 5)").unwrap();
+/// let Response::Rows { columns, cursor } = session.run("SELECT kind FROM items WHERE price > 4")
+///     .unwrap() else { unreachable!() };
+/// assert_eq!(columns, ["kind"]);
+/// assert_eq!(cursor.collect::<Result<Vec<_>, _>>().unwrap(), [[Value::from("cake")]]);
+/// ```
+#[derive(Debug)]
+pub struct Session<F: FileSystem> {
+    engine: Arc<Engine<F>>,
+    database: String,
+    functions: Arc<FunctionRegistry>,
+}
+
+/// Statement parsed and planned by a [`Session`], run any number of times with the values of
+/// its parameters.
+///
+/// Statements are planned against the catalog and functions as they were when prepared, and
+/// planned again when run after either changed.
+#[derive(Clone, Debug)]
+pub struct Prepared {
+    statement: Statement,
+    /// Plan of the query of the statement, if it has one
+    plan: Option<QueryPlan>,
+    /// Version of the catalog the statement was planned against
+    version: u64,
+    /// Functions the statement was planned against
+    functions: Arc<FunctionRegistry>,
+}
+
+/// What running a statement returned.
+#[derive(Debug)]
+pub enum Response {
+    /// Rows of a query, computed as they are read from the cursor
+    Rows {
+        /// Names of the columns of the rows, in order
+        columns: Vec<String>,
+        /// Rows of the query
+        cursor: Cursor,
+    },
+    /// Statement ran to completion
+    Done {
+        /// Number of rows the statement changed
+        rows: u64,
+    },
+}
+
+impl<F: FileSystem> Engine<F> {
+    /// Create an engine running statements against the tables of `catalog`, whose rows
+    /// `executor` reads from its storage.
+    #[must_use]
+    pub fn new(catalog: Catalog<F>, executor: Executor) -> Engine<F> {
+        Engine {
+            catalog,
+            planner: Planner::new(),
+            executor,
+            filesystems: Arc::new(VirtualFileSystemManager::with_defaults()),
+        }
+    }
+
+    /// Plan statements with `planner`.
+    #[must_use]
+    pub fn with_planner(mut self, planner: Planner) -> Self {
+        self.planner = planner;
+        self
+    }
+
+    /// Resolve the URIs of `BACKUP TO` and `RESTORE FROM` with `filesystems`, instead of a
+    /// manager of `file://` and `mem://` URIs.
+    #[must_use]
+    pub fn with_filesystems(mut self, filesystems: Arc<VirtualFileSystemManager>) -> Self {
+        self.filesystems = filesystems;
+        self
+    }
+
+    /// Catalog of the databases, tables and indexes.
+    #[must_use]
+    pub fn catalog(&self) -> &Catalog<F> {
+        &self.catalog
+    }
+
+    /// Planner of queries.
+    #[must_use]
+    pub fn planner(&self) -> &Planner {
+        &self.planner
+    }
+
+    /// Executor of plans, and the storage of the rows of tables.
+    #[must_use]
+    pub fn executor(&self) -> &Executor {
+        &self.executor
+    }
+
+    /// Filesystems of the URIs of backups.
+    #[must_use]
+    pub fn filesystems(&self) -> &Arc<VirtualFileSystemManager> {
+        &self.filesystems
+    }
+}
+
+impl<F: FileSystem> Session<F> {
+    /// Open a session on `database` of an engine.
+    pub fn new(engine: Arc<Engine<F>>, database: &str) -> ExecResult<Session<F>> {
+        if engine.catalog.snapshot().database(database).is_none() {
+            return Err(CatalogError::DatabaseMissing(database.to_string()).into());
+        }
+        Ok(Session {
+            functions: engine.planner.functions().clone(),
+            engine,
+            database: database.to_string(),
+        })
+    }
+
+    /// Engine statements run against.
+    #[must_use]
+    pub fn engine(&self) -> &Arc<Engine<F>> {
+        &self.engine
+    }
+
+    /// Name of the database tables are looked up in.
+    #[must_use]
+    pub fn database(&self) -> &str {
+        &self.database
+    }
+
+    /// User-defined functions statements may call.
+    #[must_use]
+    pub fn functions(&self) -> &FunctionRegistry {
+        &self.functions
+    }
+
+    /// Register a scalar function for the statements of the session, replacing any function
+    /// of its name.
+    ///
+    /// ```rust
+    /// use minql_catalog::Catalog;
+    /// use minql_exec::{Engine, Executor, MemoryStorage, Response, Session};
+    /// use minql_lang::ast::DataType;
+    /// use minql_value::{ScalarUdf, Signature, Value};
+    /// use minql_vfs::MemoryFileSystem;
+    /// use std::sync::Arc;
+    ///
+    /// let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+    /// let mut transaction = catalog.begin();
+    /// transaction.create_database("shop", "mem:///data/shop").unwrap();
+    /// transaction.commit().unwrap();
+    /// let engine = Engine::new(catalog, Executor::new(Arc::new(MemoryStorage::new())));
+    ///
+    /// let mut session = Session::new(Arc::new(engine), "shop").unwrap();
+    /// let signature = Signature::new(&[DataType::Text], DataType::BigInt);
+    /// let words = ScalarUdf::new("words", signature, |args| {
+    ///     let count = |text: &str| i64::try_from(text.split_whitespace().count()).unwrap();
+    ///     Ok(args.column(0).iter().map(|value| match value {
+    ///         Value::Text(text) => Value::from(count(text)),
+    ///         _ => Value::Null,
+    ///     }).collect())
+    /// });
+    /// session.register_scalar(words).unwrap();
+    /// let Response::Rows { cursor, .. } = session.run("SELECT words('green tea')").unwrap()
+    /// else { unreachable!() };
+    /// assert_eq!(cursor.collect::<Result<Vec<_>, _>>().unwrap(), [[Value::from(2i64)]]);
+    /// ```
+    pub fn register_scalar(&mut self, function: ScalarUdf) -> ExecResult<()> {
+        Arc::make_mut(&mut self.functions).register_scalar(function)?;
+        Ok(())
+    }
+
+    /// Register an aggregate function for the statements of the session, replacing any
+    /// function of its name.
+    pub fn register_aggregate(&mut self, function: AggregateUdf) -> ExecResult<()> {
+        Arc::make_mut(&mut self.functions).register_aggregate(function)?;
+        Ok(())
+    }
+
+    /// Remove a user-defined function of the session, returning whether there was one.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        if self.functions.scalar(name).is_none() && self.functions.aggregate(name).is_none() {
+            return false;
+        }
+        Arc::make_mut(&mut self.functions).unregister(name)
+    }
+
+    /// Parse and run a statement without parameters.
+    pub fn run(&self, sql: &str) -> ExecResult<Response> {
+        let prepared = self.prepare(Parser::parse_statement(sql)?)?;
+        self.execute(&prepared, Vec::new())
+    }
+
+    /// Plan a statement to be run by [`execute`](Session::execute).
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn prepare(&self, statement: Statement) -> ExecResult<Prepared> {
+        let snapshot = self.engine.catalog.snapshot();
+        let plan = self.plan(&snapshot, &statement)?;
+        Ok(Prepared {
+            statement,
+            plan,
+            version: snapshot.version(),
+            functions: self.functions.clone(),
+        })
+    }
+
+    /// Run a prepared statement, given the values of its parameters.
+    #[tracing::instrument(level = "debug", skip_all, fields(command = prepared.command()))]
+    pub fn execute(&self, prepared: &Prepared, params: Vec<Value>) -> ExecResult<Response> {
+        let snapshot = self.engine.catalog.snapshot();
+        let replanned;
+        let plan = if snapshot.version() == prepared.version
+            && Arc::ptr_eq(&prepared.functions, &self.functions)
+        {
+            prepared.plan.as_ref()
+        } else {
+            replanned = self.plan(&snapshot, &prepared.statement)?;
+            replanned.as_ref()
+        };
+        let expected = plan.map_or(0, |plan| plan.parameters);
+        if params.len() != expected {
+            return Err(ExecError::Parameters {
+                expected,
+                found: params.len(),
+            });
+        }
+        let executor = &self.engine.executor;
+        match (&prepared.statement, plan) {
+            (Statement::Query(_), Some(plan)) => Ok(Response::Rows {
+                columns: plan.columns.clone(),
+                cursor: executor.execute(&plan.plan, params)?,
+            }),
+            (Statement::Insert { table, columns, .. }, Some(plan)) => {
+                let rows = self.insert(&snapshot, table, columns, plan, params)?;
+                Ok(Response::Done { rows })
+            }
+            (Statement::Explain { analyze, .. }, Some(plan)) => {
+                self.explain(plan, *analyze, params)
+            }
+            (Statement::Backup { uri }, _) => {
+                let target = self.engine.filesystems.get(uri)?;
+                let rows = self.engine.backup(&target, "/")?;
+                Ok(Response::Done { rows })
+            }
+            (Statement::Restore { uri }, _) => {
+                let source = self.engine.filesystems.get(uri)?;
+                let rows = self.engine.restore(&source, "/")?;
+                Ok(Response::Done { rows })
+            }
+            (statement, _) => {
+                self.define(statement)?;
+                Ok(Response::Done { rows: 0 })
+            }
+        }
+    }
+
+    /// Rows of the text of a plan, or of the metrics of running it if `analyze` is given.
+    fn explain(&self, plan: &QueryPlan, analyze: bool, params: Vec<Value>) -> ExecResult<Response> {
+        let executor = &self.engine.executor;
+        let text = if analyze {
+            let mut cursor = executor.execute(&plan.plan, params)?;
+            for row in cursor.by_ref() {
+                row?;
+            }
+            cursor.metrics().to_string()
+        } else {
+            plan.plan.to_string()
+        };
+        let rows: Vec<Vec<ScalarExpr>> = text
+            .lines()
+            .map(|line| vec![ScalarExpr::Literal(Value::from(line))])
+            .collect();
+        #[allow(clippy::cast_precision_loss)]
+        let estimate = Estimate {
+            rows: rows.len() as f64,
+            cost: 0.0,
+        };
+        let plan = PhysicalPlan::Values { rows, estimate };
+        Ok(Response::Rows {
+            columns: vec!["QUERY PLAN".to_string()],
+            cursor: executor.execute(&plan, Vec::new())?,
+        })
+    }
+
+    /// Plan the query of a statement, if it has one.
+    fn plan(
+        &self,
+        snapshot: &CatalogSnapshot,
+        statement: &Statement,
+    ) -> ExecResult<Option<QueryPlan>> {
+        let query: &Query = match statement {
+            Statement::Query(query) | Statement::Insert { source: query, .. } => query,
+            Statement::Explain { statement, .. } => match &**statement {
+                Statement::Query(query) => query,
+                _ => {
+                    return Err(ExecError::Unsupported(
+                        "EXPLAIN of other than a query".to_string(),
+                    ))
+                }
+            },
+            _ => return Ok(None),
+        };
+        let plan = self
+            .engine
+            .planner
+            .clone()
+            .with_functions(self.functions.clone())
+            .plan_query(snapshot, &self.database, query)?;
+        Ok(Some(plan))
+    }
+
+    /// Insert the rows of a planned query into the named columns of a table, returning the
+    /// number of rows inserted.
+    fn insert(
+        &self,
+        snapshot: &CatalogSnapshot,
+        table: &[Ident],
+        columns: &[Ident],
+        plan: &QueryPlan,
+        params: Vec<Value>,
+    ) -> ExecResult<u64> {
+        let (database, name) = self.object(table)?;
+        let table = snapshot
+            .table(&database, &name)
+            .ok_or(CatalogError::TableMissing(name))?;
+        let positions = positions(table, columns, plan.columns.len())?;
+        let defaults = table
+            .columns
+            .iter()
+            .map(|column| match &column.default {
+                Some(default) => Ok(Some(ScalarExpr::bind(default, &Scope::new())?)),
+                None => Ok(None),
+            })
+            .collect::<ExecResult<Vec<_>>>()?;
+        // Every row is built before any is inserted, so the statement inserts all or none.
+        let mut rows = Vec::new();
+        for values in self.engine.executor.execute(&plan.plan, params)? {
+            let mut row = defaults
+                .iter()
+                .map(|default| match default {
+                    Some(default) => default.eval(&[], &[]),
+                    None => Ok(Value::Null),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            for (value, &position) in values?.into_iter().zip(&positions) {
+                row[position] = value;
+            }
+            rows.push(row);
+        }
+        let inserted = rows.len() as u64;
+        self.engine.executor.storage().insert_rows(table, rows)?;
+        Ok(inserted)
+    }
+
+    /// Run a statement changing the catalog, or one that does nothing.
+    fn define(&self, statement: &Statement) -> ExecResult<()> {
+        match statement {
+            Statement::CreateTable {
+                name,
+                if_not_exists,
+                columns,
+                constraints,
+            } => {
+                let (database, name) = self.object(name)?;
+                let table = TableSchema::from_ast(&name, columns, constraints)?;
+                let mut transaction = self.engine.catalog.begin();
+                if *if_not_exists && transaction.state().table(&database, &name).is_some() {
+                    return Ok(());
+                }
+                transaction.create_table(&database, table)?;
+                transaction.commit()?;
+            }
+            Statement::CreateIndex {
+                name,
+                table,
+                columns,
+                unique,
+                if_not_exists,
+            } => {
+                let (database, table) = self.object(table)?;
+                let name = name.normalized();
+                let columns: Vec<String> = columns.iter().map(Ident::normalized).collect();
+                let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+                let mut transaction = self.engine.catalog.begin();
+                if *if_not_exists && transaction.state().index(&database, &name).is_some() {
+                    return Ok(());
+                }
+                let index =
+                    transaction.create_index(&database, &name, &table, &columns, *unique)?;
+                let table = transaction
+                    .state()
+                    .table_by_id(index.table)
+                    .expect("Indexed Table");
+                let storage = self.engine.executor.storage();
+                storage.create_index(table, &index)?;
+                let table = table.clone();
+                if let Err(error) = transaction.commit() {
+                    storage.drop_index(&table, &index)?;
+                    return Err(error.into());
+                }
+            }
+            Statement::DropTable { names, if_exists } => {
+                let mut transaction = self.engine.catalog.begin();
+                for name in names {
+                    let (database, name) = self.object(name)?;
+                    if !*if_exists || transaction.state().table(&database, &name).is_some() {
+                        transaction.drop_table(&database, &name)?;
+                    }
+                }
+                transaction.commit()?;
+            }
+            Statement::DropIndex { names, if_exists } => {
+                let mut transaction = self.engine.catalog.begin();
+                let mut dropped = Vec::new();
+                for name in names {
+                    let (database, name) = self.object(name)?;
+                    if !*if_exists || transaction.state().index(&database, &name).is_some() {
+                        let index = transaction.drop_index(&database, &name)?;
+                        let table = transaction.state().table_by_id(index.table).cloned();
+                        dropped.extend(table.map(|table| (table, index)));
+                    }
+                }
+                transaction.commit()?;
+                for (table, index) in dropped {
+                    self.engine.executor.storage().drop_index(&table, &index)?;
+                }
+            }
+            Statement::Analyze { table } => self.analyze(table.as_deref())?,
+            // Every statement commits on its own, so there's no transaction to roll back.
+            Statement::Begin | Statement::Commit => {}
+            Statement::Rollback => {
+                return Err(ExecError::Unsupported(
+                    "ROLLBACK of statements already committed".to_string(),
+                ))
+            }
+            Statement::Update { .. } => return Err(ExecError::Unsupported("UPDATE".to_string())),
+            Statement::Delete { .. } => return Err(ExecError::Unsupported("DELETE".to_string())),
+            Statement::Query(_) | Statement::Insert { .. } | Statement::Explain { .. } => {
+                unreachable!("statement has a plan")
+            }
+            Statement::Backup { .. } | Statement::Restore { .. } => {
+                unreachable!("statement copies rows")
+            }
+        }
+        Ok(())
+    }
+
+    /// Gather the statistics of a table, or of every table of the database.
+    fn analyze(&self, table: Option<&[Ident]>) -> ExecResult<()> {
+        let mut transaction = self.engine.catalog.begin();
+        let tables: Vec<Arc<TableSchema>> = match table {
+            Some(table) => {
+                let (database, name) = self.object(table)?;
+                let table = transaction
+                    .state()
+                    .table(&database, &name)
+                    .ok_or(CatalogError::TableMissing(name))?;
+                vec![table.clone()]
+            }
+            None => transaction
+                .state()
+                .tables(&self.database)
+                .cloned()
+                .collect(),
+        };
+        for table in tables {
+            let mut collector = StatisticsCollector::new(table.columns.len());
+            for row in self.engine.executor.storage().scan(&table)? {
+                collector.add_row(&row?);
+            }
+            transaction.set_statistics(&table.database, &table.name, collector.finish())?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Database and name of a table or index, named alone or qualified by its database.
+    fn object(&self, name: &[Ident]) -> ExecResult<(String, String)> {
+        match name {
+            [name] => Ok((self.database.clone(), name.normalized())),
+            [database, name] => Ok((database.normalized(), name.normalized())),
+            _ => Err(PlanError::InvalidQuery(format!(
+                "invalid name {}",
+                name.iter()
+                    .map(|ident| ident.value.as_str())
+                    .collect::<Vec<_>>()
+                    .join(".")
+            ))
+            .into()),
+        }
+    }
+}
+
+impl Prepared {
+    /// Statement prepared.
+    #[must_use]
+    pub fn statement(&self) -> &Statement {
+        &self.statement
+    }
+
+    /// Names of the columns of the rows the statement returns, empty if it returns none.
+    #[must_use]
+    pub fn columns(&self) -> Vec<String> {
+        match (&self.statement, &self.plan) {
+            (Statement::Query(_), Some(plan)) => plan.columns.clone(),
+            (Statement::Explain { .. }, _) => vec!["QUERY PLAN".to_string()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Number of parameter values the statement needs.
+    #[must_use]
+    pub fn parameters(&self) -> usize {
+        self.plan.as_ref().map_or(0, |plan| plan.parameters)
+    }
+
+    /// Name of the command of the statement, such as `SELECT` or `CREATE TABLE`.
+    #[must_use]
+    pub fn command(&self) -> &'static str {
+        match self.statement {
+            Statement::Query(_) => "SELECT",
+            Statement::Insert { .. } => "INSERT",
+            Statement::Update { .. } => "UPDATE",
+            Statement::Delete { .. } => "DELETE",
+            Statement::CreateTable { .. } => "CREATE TABLE",
+            Statement::CreateIndex { .. } => "CREATE INDEX",
+            Statement::DropTable { .. } => "DROP TABLE",
+            Statement::DropIndex { .. } => "DROP INDEX",
+            Statement::Begin => "BEGIN",
+            Statement::Commit => "COMMIT",
+            Statement::Rollback => "ROLLBACK",
+            Statement::Explain { .. } => "EXPLAIN",
+            Statement::Analyze { .. } => "ANALYZE",
+            Statement::Backup { .. } => "BACKUP",
+            Statement::Restore { .. } => "RESTORE",
+        }
+    }
+}
+
+/// Positions in a table of the columns an `INSERT` gives `width` values for, every column in
+/// order if none are named.
+fn positions(table: &TableSchema, columns: &[Ident], width: usize) -> ExecResult<Vec<usize>> {
+    let positions = if columns.is_empty() {
+        (0..width.min(table.columns.len())).collect()
+    } else {
+        let mut positions = Vec::with_capacity(columns.len());
+        for column in columns {
+            let name = column.normalized();
+            let position = table
+                .column_index(&name)
+                .ok_or(CatalogError::ColumnMissing(name.clone()))?;
+            if positions.contains(&position) {
+                return Err(PlanError::InvalidQuery(format!("column {name} given twice")).into());
+            }
+            positions.push(position);
+        }
+        positions
+    };
+    if positions.len() != width {
+        return Err(PlanError::InvalidQuery(format!(
+            "INSERT of {width} values into {} columns",
+            positions.len()
+        ))
+        .into());
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Engine, Response, Session};
+    use crate::{ExecError, Executor, MemoryStorage};
+    use minql_catalog::{Catalog, CatalogError};
+    use minql_lang::ast::{BinaryOperator, DataType};
+    use minql_lang::Parser;
+    use minql_value::{
+        AggregateState, AggregateUdf, Batch, ScalarUdf, Signature, Value, ValueError, ValueResult,
+    };
+    use minql_vfs::{FileSystem, MemoryFileSystem, VirtualFileSystemManager};
+    use std::sync::Arc;
+
+    fn session() -> Session<MemoryFileSystem> {
+        session_with(Arc::new(VirtualFileSystemManager::with_defaults()))
+    }
+
+    fn session_with(filesystems: Arc<VirtualFileSystemManager>) -> Session<MemoryFileSystem> {
+        let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+        let mut transaction = catalog.begin();
+        transaction
+            .create_database("shop", "mem:///data/shop")
+            .unwrap();
+        transaction.commit().unwrap();
+        let executor = Executor::new(Arc::new(MemoryStorage::new()));
+        let engine = Engine::new(catalog, executor).with_filesystems(filesystems);
+        Session::new(Arc::new(engine), "shop").unwrap()
+    }
+
+    fn rows(response: Response) -> Vec<String> {
+        let Response::Rows { cursor, .. } = response else {
+            panic!("no rows");
+        };
+        cursor
+            .map(|row| {
+                let row = row.unwrap();
+                row.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_definitions() {
+        let session = session();
+        session
+            .run("CREATE TABLE items (id BIGINT PRIMARY KEY, kind TEXT NOT NULL, price BIGINT DEFAULT 1)")
+            .unwrap();
+        session
+            .run("CREATE UNIQUE INDEX items_kind ON items (kind)")
+            .unwrap();
+        session
+            .run("CREATE TABLE IF NOT EXISTS items (a BIGINT)")
+            .unwrap();
+        assert!(matches!(
+            session.run("CREATE TABLE items (a BIGINT)"),
+            Err(ExecError::Catalog(CatalogError::TableExists(_)))
+        ));
+
+        let response = session
+            .run("INSERT INTO items VALUES (1, 'tea', 3), (2, 'cake', 5)")
+            .unwrap();
+        assert!(matches!(response, Response::Done { rows: 2 }));
+        session
+            .run("INSERT INTO items (kind, id) VALUES ('bun', 3)")
+            .unwrap();
+        assert!(matches!(
+            session.run("INSERT INTO items (id) VALUES (4)"),
+            Err(ExecError::NotNull(_))
+        ));
+        assert!(matches!(
+            session.run("INSERT INTO items VALUES (5, 'tea', 1)"),
+            Err(ExecError::UniqueViolation(_))
+        ));
+        // A statement inserting several rows inserts none of them if any fails
+        assert!(matches!(
+            session.run("INSERT INTO items VALUES (5, 'pie', 1), (6, 'tea', 1)"),
+            Err(ExecError::UniqueViolation(_))
+        ));
+        assert!(matches!(
+            session.run("INSERT INTO items VALUES (7, 'jam', 1), (7, 'ham', 1)"),
+            Err(ExecError::UniqueViolation(name)) if name == "items_pkey"
+        ));
+        assert!(matches!(
+            session.run("INSERT INTO items (id, id) VALUES (6, 6)"),
+            Err(ExecError::Plan(_))
+        ));
+        session
+            .run("CREATE INDEX items_price ON items (price)")
+            .unwrap();
+        session.run("DROP INDEX items_kind").unwrap();
+        session
+            .run("INSERT INTO items VALUES (4, 'tea', 7)")
+            .unwrap();
+        assert!(matches!(
+            session.run("CREATE UNIQUE INDEX items_kind ON items (kind)"),
+            Err(ExecError::UniqueViolation(_))
+        ));
+        assert!(session
+            .engine()
+            .catalog()
+            .snapshot()
+            .index("shop", "items_kind")
+            .is_none());
+
+        assert_eq!(
+            rows(
+                session
+                    .run("SELECT id, kind, price FROM items ORDER BY id")
+                    .unwrap()
+            ),
+            ["1,tea,3", "2,cake,5", "3,bun,1", "4,tea,7"]
+        );
+        assert_eq!(
+            rows(
+                session
+                    .run("SELECT id FROM items WHERE price > 2 ORDER BY price")
+                    .unwrap()
+            ),
+            ["1", "2", "4"]
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_prepared_statements() {
+        let session = session();
+        session
+            .run("CREATE TABLE items (id BIGINT PRIMARY KEY, kind TEXT)")
+            .unwrap();
+        session
+            .run("CREATE UNIQUE INDEX items_kind ON items (kind)")
+            .unwrap();
+        session
+            .run("INSERT INTO items VALUES (1, 'tea'), (2, 'cake'), (3, 'bun')")
+            .unwrap();
+        let prepared = session
+            .prepare(Parser::parse_statement("SELECT kind FROM items WHERE id = $1").unwrap())
+            .unwrap();
+        assert_eq!(prepared.columns(), ["kind"]);
+        assert_eq!(prepared.parameters(), 1);
+        assert_eq!(prepared.command(), "SELECT");
+        assert_eq!(
+            rows(session.execute(&prepared, vec![Value::from(2)]).unwrap()),
+            ["cake"]
+        );
+        assert!(matches!(
+            session.execute(&prepared, Vec::new()),
+            Err(ExecError::Parameters {
+                expected: 1,
+                found: 0
+            })
+        ));
+
+        session.run("ANALYZE items").unwrap();
+        let snapshot = session.engine().catalog().snapshot();
+        let table = snapshot.table("shop", "items").unwrap();
+        assert_eq!(snapshot.statistics(table.id).unwrap().rows, 3);
+        assert_eq!(
+            rows(session.execute(&prepared, vec![Value::from(3)]).unwrap()),
+            ["bun"]
+        );
+        let plan = rows(
+            session
+                .run("EXPLAIN SELECT kind FROM items WHERE kind = 'tea'")
+                .unwrap(),
+        );
+        assert!(
+            plan.iter().any(|line| line.contains("Scan items")),
+            "{plan:?}"
+        );
+        let plan = rows(
+            session
+                .run("EXPLAIN ANALYZE SELECT kind FROM items")
+                .unwrap(),
+        );
+        assert!(plan[0].contains("rows=3"));
+
+        for statement in ["BEGIN", "COMMIT"] {
+            let prepared = session
+                .prepare(Parser::parse_statement(statement).unwrap())
+                .unwrap();
+            assert_eq!(prepared.command(), statement);
+            assert!(matches!(
+                session.execute(&prepared, Vec::new()).unwrap(),
+                Response::Done { rows: 0 }
+            ));
+        }
+        assert!(matches!(
+            session.run("ROLLBACK"),
+            Err(ExecError::Unsupported(_))
+        ));
+        session.run("DROP INDEX items_kind").unwrap();
+        session.run("DROP TABLE items").unwrap();
+        session.run("DROP TABLE IF EXISTS items").unwrap();
+        assert!(matches!(
+            session.run("SELECT * FROM items"),
+            Err(ExecError::Plan(_))
+        ));
+        assert!(matches!(
+            Session::new(session.engine().clone(), "missing"),
+            Err(ExecError::Catalog(CatalogError::DatabaseMissing(_)))
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_user_defined_functions() {
+        /// Values of a group joined by a separator, in the order added.
+        #[derive(Debug, Default)]
+        struct Join(Vec<String>);
+
+        impl AggregateState for Join {
+            fn update(&mut self, args: &Batch) -> ValueResult<()> {
+                for (value, separator) in args.column(0).iter().zip(args.column(1)) {
+                    if !self.0.is_empty() {
+                        self.0.push(separator.to_string());
+                    }
+                    self.0.push(value.to_string());
+                }
+                Ok(())
+            }
+
+            fn finish(&mut self) -> ValueResult<Value> {
+                Ok(Value::Text(self.0.concat()))
+            }
+        }
+
+        let scale = |factor: i64| {
+            let signature = Signature::new(&[DataType::BigInt], DataType::BigInt);
+            ScalarUdf::new("scale", signature, move |args| {
+                let factor = Value::from(factor);
+                args.column(0)
+                    .iter()
+                    .map(|value| value.binary(BinaryOperator::Multiply, &factor))
+                    .collect()
+            })
+        };
+        let mut session = session();
+        session
+            .run("CREATE TABLE items (id BIGINT, kind TEXT, price BIGINT)")
+            .unwrap();
+        session
+            .run("INSERT INTO items VALUES (1, 'tea', 3), (2, 'cake', 5), (3, 'tea', NULL)")
+            .unwrap();
+        session.register_scalar(scale(10)).unwrap();
+        let signature = Signature::new(&[DataType::Text, DataType::Text], DataType::Text);
+        session
+            .register_aggregate(AggregateUdf::new("joined", signature, || {
+                Box::new(Join::default())
+            }))
+            .unwrap();
+
+        assert_eq!(
+            rows(
+                session
+                    .run("SELECT id, scale(price) FROM items WHERE scale(id) > 10 ORDER BY id")
+                    .unwrap()
+            ),
+            ["2,50", "3,NULL"]
+        );
+        assert_eq!(
+            rows(
+                session
+                    .run(
+                        "SELECT kind, joined(scale(price), '+') FROM items \
+                         GROUP BY kind HAVING joined(id, '-') = '1-3' ORDER BY 1"
+                    )
+                    .unwrap()
+            ),
+            ["tea,30"]
+        );
+        assert_eq!(
+            rows(session.run("SELECT joined(kind, ',') FROM items").unwrap()),
+            ["tea,cake,tea"]
+        );
+
+        let prepared = session
+            .prepare(
+                Parser::parse_statement("SELECT scale(price) FROM items WHERE id = 1").unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            rows(session.execute(&prepared, Vec::new()).unwrap()),
+            ["30"]
+        );
+        session.register_scalar(scale(100)).unwrap();
+        assert_eq!(
+            rows(session.execute(&prepared, Vec::new()).unwrap()),
+            ["300"]
+        );
+        assert!(session.unregister("SCALE"));
+        assert!(!session.unregister("scale"));
+        assert!(matches!(
+            session.execute(&prepared, Vec::new()),
+            Err(ExecError::Plan(_))
+        ));
+        assert!(matches!(
+            session.register_scalar(ScalarUdf::new(
+                "abs",
+                Signature::new(&[], DataType::Text),
+                |_| { Ok(Vec::new()) }
+            )),
+            Err(ExecError::Value(ValueError::Function { .. }))
+        ));
+        assert!(session.engine().planner().functions().is_empty());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_backup_and_restore() {
+        let filesystems = Arc::new(VirtualFileSystemManager::with_defaults());
+        let session = session_with(filesystems.clone());
+        session
+            .run("CREATE TABLE kinds (name TEXT PRIMARY KEY)")
+            .unwrap();
+        session
+            .run("CREATE TABLE items (id BIGINT PRIMARY KEY, kind TEXT REFERENCES kinds (name), price BIGINT)")
+            .unwrap();
+        session
+            .run("CREATE UNIQUE INDEX items_id ON items (id)")
+            .unwrap();
+        session
+            .run("INSERT INTO kinds VALUES ('tea'), ('cake')")
+            .unwrap();
+        session
+            .run("INSERT INTO items VALUES (1, 'tea', 3), (2, 'cake', NULL)")
+            .unwrap();
+        session.run("ANALYZE").unwrap();
+        let response = session.run("BACKUP TO 'mem://nightly'").unwrap();
+        assert!(matches!(response, Response::Done { rows: 4 }));
+        assert!(matches!(
+            session.run("BACKUP TO 'mem://nightly'"),
+            Err(ExecError::InvalidBackup(_))
+        ));
+        session
+            .run("INSERT INTO items VALUES (3, 'tea', 4)")
+            .unwrap();
+
+        let restored = session_with(filesystems.clone());
+        let response = restored.run("RESTORE FROM 'mem://nightly'").unwrap();
+        assert!(matches!(response, Response::Done { rows: 4 }));
+        assert_eq!(
+            rows(
+                restored
+                    .run("SELECT id, kind, price FROM items ORDER BY id")
+                    .unwrap()
+            ),
+            ["1,tea,3", "2,cake,NULL"]
+        );
+        assert!(matches!(
+            restored.run("INSERT INTO items VALUES (2, 'tea', 1)"),
+            Err(ExecError::UniqueViolation(_))
+        ));
+        let snapshot = restored.engine().catalog().snapshot();
+        let items = snapshot.table("shop", "items").unwrap();
+        assert_eq!(snapshot.statistics(items.id).unwrap().rows, 2);
+        assert!(matches!(
+            restored.run("RESTORE FROM 'mem://nightly'"),
+            Err(ExecError::InvalidBackup(_))
+        ));
+
+        let backup = filesystems.get("mem://nightly").unwrap();
+        backup.write("/tables/extra", b"rows").unwrap();
+        assert!(matches!(
+            session_with(filesystems).run("RESTORE FROM 'mem://nightly'"),
+            Err(ExecError::BackupMismatch(_))
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_backup_during_inserts() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let filesystems = Arc::new(VirtualFileSystemManager::with_defaults());
+        let session = session_with(filesystems.clone());
+        session
+            .run("CREATE TABLE orders (id BIGINT PRIMARY KEY)")
+            .unwrap();
+        session
+            .run("CREATE TABLE lines (id BIGINT PRIMARY KEY)")
+            .unwrap();
+        // Every order is inserted before its line, so any one point holds as many lines as
+        // orders, or one fewer.
+        let writer = Session::new(session.engine().clone(), "shop").unwrap();
+        let done = Arc::new(AtomicBool::new(false));
+        let inserts = std::thread::spawn({
+            let done = done.clone();
+            move || {
+                for id in 0.. {
+                    if done.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    writer
+                        .run(&format!("INSERT INTO orders VALUES ({id})"))
+                        .unwrap();
+                    writer
+                        .run(&format!("INSERT INTO lines VALUES ({id})"))
+                        .unwrap();
+                }
+            }
+        });
+        while rows(session.run("SELECT count(*) FROM orders").unwrap()) == ["0"] {
+            std::thread::yield_now();
+        }
+        for backup in 0..10 {
+            session
+                .run(&format!("BACKUP TO 'mem://backup{backup}'"))
+                .unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        inserts.join().unwrap();
+
+        for backup in 0..10 {
+            let restored = session_with(filesystems.clone());
+            restored
+                .run(&format!("RESTORE FROM 'mem://backup{backup}'"))
+                .unwrap();
+            let count = |table: &str| -> u64 {
+                rows(
+                    restored
+                        .run(&format!("SELECT count(*) FROM {table}"))
+                        .unwrap(),
+                )[0]
+                .parse()
+                .unwrap()
+            };
+            let (orders, lines) = (count("orders"), count("lines"));
+            assert!(lines <= orders && orders <= lines + 1);
+            let point = filesystems
+                .get(&format!("mem://backup{backup}"))
+                .unwrap()
+                .read("/POINT")
+                .unwrap();
+            assert_eq!(
+                String::from_utf8(point).unwrap().parse::<u64>().unwrap(),
+                orders + lines
+            );
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::operator::{Node, Operator};
+use crate::spill::{row_size, SpillReader, SpillSpace};
+use crate::ExecResult;
+use minql_plan::SortKey;
+use minql_value::Value;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+/// Most runs merged at once, beyond which runs are merged into fewer runs first.
+const MERGE_FAN_IN: usize = 16;
+
+/// Values of the keys of a row, and the row.
+type Keyed = (Vec<Value>, Vec<Value>);
+
+/// Rows of the input in order, all read and sorted when opened.
+///
+/// Rows are sorted in memory until they exceed the memory budget, when they are written as a
+/// sorted run to a temporary file and the next run started. Runs are then merged, at most
+/// [`MERGE_FAN_IN`] at a time, as rows are read. Rows of equal keys keep the order they were
+/// read in.
+pub(crate) struct SortOperator {
+    input: Node,
+    keys: Vec<SortKey>,
+    spill: SpillSpace,
+    merge: Merge,
+    spilled: u64,
+}
+
+/// Sorted rows, in memory or written to a temporary file.
+enum Run {
+    Memory(std::vec::IntoIter<Keyed>),
+    /// Rows written after their keys
+    File(SpillReader),
+}
+
+/// Rows of sorted runs merged in order, those of earlier runs first among equal keys.
+#[derive(Default)]
+struct Merge {
+    runs: Vec<Run>,
+    /// Next row of each run, or `None` after its last
+    heads: Vec<Option<Keyed>>,
+}
+
+impl SortOperator {
+    pub(crate) fn new(input: Node, keys: Vec<SortKey>, spill: SpillSpace) -> SortOperator {
+        SortOperator {
+            input,
+            keys,
+            spill,
+            merge: Merge::default(),
+            spilled: 0,
+        }
+    }
+
+    /// Sort rows held in memory, writing them to a temporary file if there are runs of
+    /// earlier rows.
+    fn run(&mut self, mut rows: Vec<Keyed>, runs: &mut Vec<Run>, last: bool) -> ExecResult<()> {
+        rows.sort_by(|(left, _), (right, _)| compare(&self.keys, left, right));
+        if last && runs.is_empty() {
+            runs.push(Run::Memory(rows.into_iter()));
+            return Ok(());
+        }
+        let mut writer = self.spill.create("sort-run")?;
+        for (mut key, row) in rows {
+            key.extend(row);
+            writer.write(&key)?;
+        }
+        self.spilled += writer.rows();
+        runs.push(Run::File(writer.finish()?));
+        Ok(())
+    }
+
+    /// Merge runs into fewer runs written to temporary files until few enough are left to
+    /// merge at once.
+    fn reduce(&mut self, mut runs: Vec<Run>) -> ExecResult<Vec<Run>> {
+        while runs.len() > MERGE_FAN_IN {
+            tracing::debug!(runs = runs.len(), "Merging sort runs");
+            let mut merged = Vec::new();
+            let mut remaining = runs.into_iter();
+            loop {
+                let chunk: Vec<Run> = remaining.by_ref().take(MERGE_FAN_IN).collect();
+                if chunk.len() < 2 {
+                    merged.extend(chunk);
+                    break;
+                }
+                let mut merge = Merge::new(chunk, self.keys.len())?;
+                let mut writer = self.spill.create("sort-run")?;
+                while let Some((mut key, row)) = merge.next(&self.keys)? {
+                    key.extend(row);
+                    writer.write(&key)?;
+                }
+                self.spilled += writer.rows();
+                merged.push(Run::File(writer.finish()?));
+            }
+            runs = merged;
+        }
+        Ok(runs)
+    }
+}
+
+impl Operator for SortOperator {
+    fn open(&mut self, params: &Arc<[Value]>) -> ExecResult<()> {
+        let mut runs = Vec::new();
+        let mut rows = Vec::new();
+        let mut bytes = 0;
+        self.merge = Merge::default();
+        self.input.open(params)?;
+        while let Some(row) = self.input.next()? {
+            let key = self
+                .keys
+                .iter()
+                .map(|key| key.expr.eval(&row, params))
+                .collect::<Result<Vec<_>, _>>()?;
+            bytes += row_size(&key) + row_size(&row);
+            rows.push((key, row));
+            if self.spill.exceeded(bytes) {
+                self.run(std::mem::take(&mut rows), &mut runs, false)?;
+                bytes = 0;
+            }
+        }
+        self.input.close();
+        if !rows.is_empty() || runs.is_empty() {
+            self.run(rows, &mut runs, true)?;
+        }
+        let runs = self.reduce(runs)?;
+        self.merge = Merge::new(runs, self.keys.len())?;
+        Ok(())
+    }
+
+    fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        Ok(self.merge.next(&self.keys)?.map(|(_, row)| row))
+    }
+
+    fn close(&mut self) {
+        self.merge = Merge::default();
+    }
+
+    fn children(&self) -> Vec<&Node> {
+        vec![&self.input]
+    }
+
+    fn spilled(&self) -> u64 {
+        self.spilled
+    }
+}
+
+impl Run {
+    /// Next row of the run after its keys, given their number.
+    fn next(&mut self, keys: usize) -> ExecResult<Option<Keyed>> {
+        Ok(match self {
+            Run::Memory(rows) => rows.next(),
+            Run::File(file) => file.next()?.map(|mut key| {
+                let row = key.split_off(keys);
+                (key, row)
+            }),
+        })
+    }
+}
+
+impl Merge {
+    /// Merge runs of rows of `keys` keys.
+    fn new(mut runs: Vec<Run>, keys: usize) -> ExecResult<Merge> {
+        let heads = runs
+            .iter_mut()
+            .map(|run| run.next(keys))
+            .collect::<ExecResult<_>>()?;
+        Ok(Merge { runs, heads })
+    }
+
+    /// Next row of the runs in order, after its keys.
+    fn next(&mut self, keys: &[SortKey]) -> ExecResult<Option<Keyed>> {
+        let mut first: Option<(usize, &Vec<Value>)> = None;
+        for (position, head) in self.heads.iter().enumerate() {
+            let Some((key, _)) = head else {
+                continue;
+            };
+            if first.is_none_or(|(_, first)| compare(keys, key, first).is_lt()) {
+                first = Some((position, key));
+            }
+        }
+        let Some((position, _)) = first else {
+            return Ok(None);
+        };
+        let next = self.runs[position].next(keys.len())?;
+        Ok(std::mem::replace(&mut self.heads[position], next))
+    }
+}
+
+/// Order of the values of the keys of two rows.
+fn compare(keys: &[SortKey], left: &[Value], right: &[Value]) -> Ordering {
+    keys.iter()
+        .zip(left.iter().zip(right))
+        .map(
+            |(key, (left, right))| match (left.is_null(), right.is_null()) {
+                (true, true) => Ordering::Equal,
+                (true, false) if key.nulls_first => Ordering::Less,
+                (true, false) => Ordering::Greater,
+                (false, true) if key.nulls_first => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) if key.ascending => left.cmp(right),
+                (false, false) => right.cmp(left),
+            },
+        )
+        .find(|order| order.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Executor, MemoryStorage};
+    use minql_plan::{Estimate, PhysicalPlan, SortKey};
+    use minql_value::{ScalarExpr, Value};
+    use minql_vfs::{FileSystem, MemoryFileSystem, VirtualFileSystem};
+    use std::sync::Arc;
+
+    fn sort(ascending: bool, nulls_first: bool) -> PhysicalPlan {
+        let rows = (0..2000i64)
+            .map(|id| {
+                let key = if id % 100 == 0 {
+                    Value::Null
+                } else {
+                    Value::from(id * 7919 % 250)
+                };
+                vec![
+                    ScalarExpr::Literal(Value::from(id)),
+                    ScalarExpr::Literal(key),
+                ]
+            })
+            .collect();
+        PhysicalPlan::Sort {
+            input: Box::new(PhysicalPlan::Values {
+                rows,
+                estimate: Estimate::default(),
+            }),
+            keys: vec![SortKey {
+                expr: ScalarExpr::Column(1),
+                ascending,
+                nulls_first,
+            }],
+            estimate: Estimate::default(),
+        }
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_external_sort() {
+        let fs = VirtualFileSystem::new(MemoryFileSystem::new());
+        let executor = Executor::new(Arc::new(MemoryStorage::new()))
+            .with_spill_directory(fs.clone(), "/spill")
+            .with_memory_budget(4 * 1024);
+        let in_memory = Executor::new(Arc::new(MemoryStorage::new()));
+        for (ascending, nulls_first) in [(true, false), (false, true), (true, true)] {
+            let plan = sort(ascending, nulls_first);
+            let mut cursor = executor.execute(&plan, Vec::new()).unwrap();
+            let rows: Vec<Vec<Value>> = cursor.by_ref().map(Result::unwrap).collect();
+            assert!(cursor.metrics().spilled > 2000);
+            drop(cursor);
+            let expected: Vec<Vec<Value>> = in_memory
+                .execute(&plan, Vec::new())
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(rows, expected);
+            assert_eq!(rows.len(), 2000);
+            assert_eq!(rows[0][1].is_null(), nulls_first);
+            for pair in rows.windows(2) {
+                let (first, second) = (&pair[0], &pair[1]);
+                if first[1] == second[1] {
+                    assert!(first[0] < second[0], "{first:?} before {second:?}");
+                } else if !first[1].is_null() && !second[1].is_null() {
+                    assert_eq!(first[1] < second[1], ascending);
+                }
+            }
+        }
+        assert!(fs.list_directory("/spill").unwrap().is_empty());
+        let metrics = executor.spill_metrics().unwrap();
+        assert!(metrics.bytes_written() > 0);
+        assert_eq!(metrics.bytes_read(), metrics.bytes_written());
+        assert!(in_memory.spill_metrics().is_none());
+    }
+}
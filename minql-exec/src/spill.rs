@@ -0,0 +1,238 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::ExecResult;
+use minql_value::{decode_row, encode_row, Value};
+use minql_vfs::{temp_name, FileSystem, MetricFileSystem, MetricsData, MetricsFileHandle};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+/// Number of partitions rows are split into when they don't fit in memory.
+const PARTITIONS: u64 = 16;
+
+/// Times rows of a partition still too large for memory are split again, before they are
+/// held whatever their size.
+pub(crate) const MAX_DEPTH: u32 = 3;
+
+/// Memory an operator may hold rows in, and where it writes the rows that don't fit.
+#[derive(Clone, Debug)]
+pub(crate) struct SpillSpace {
+    /// Filesystem and directory of temporary files, or `None` to hold every row in memory
+    files: Option<(MetricFileSystem, String)>,
+    /// Bytes of rows an operator may hold
+    budget: usize,
+}
+
+impl SpillSpace {
+    pub(crate) fn new(budget: usize) -> SpillSpace {
+        SpillSpace {
+            files: None,
+            budget,
+        }
+    }
+
+    /// Write rows that don't fit in memory to temporary files in `directory`, recording the
+    /// work done on them.
+    pub(crate) fn with_files<F: FileSystem>(mut self, fs: F, directory: &str) -> Self {
+        let directory = directory.trim_end_matches('/').to_string();
+        self.files = Some((MetricFileSystem::new(fs), directory));
+        self
+    }
+
+    /// Keep at most `budget` bytes of rows in memory.
+    pub(crate) fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub(crate) fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Work done on temporary files so far, or `None` if rows are never spilled.
+    pub(crate) fn metrics(&self) -> Option<MetricsData> {
+        self.files.as_ref().map(|(fs, _)| fs.filesystem_metrics())
+    }
+
+    /// Whether rows of `bytes` bytes exceed the budget and can be written to temporary
+    /// files instead.
+    pub(crate) fn exceeded(&self, bytes: usize) -> bool {
+        self.files.is_some() && bytes > self.budget
+    }
+
+    /// Create a temporary file of rows, named after what it holds.
+    pub(crate) fn create(&self, name: &str) -> ExecResult<SpillWriter> {
+        let (fs, directory) = self.files.as_ref().expect("Spill Directory");
+        fs.create_directory_all(directory)?;
+        let path = format!("{directory}/{}", temp_name(name));
+        let file = fs.create_file(&path)?;
+        tracing::trace!(path, "Created spill file");
+        Ok(SpillWriter {
+            file: BufWriter::new(file),
+            rows: 0,
+            temp: TempFile {
+                fs: fs.clone(),
+                path,
+            },
+        })
+    }
+
+    /// Empty temporary files of each partition rows are split into.
+    pub(crate) fn partitions(&self, name: &str) -> ExecResult<Vec<SpillWriter>> {
+        (0..PARTITIONS).map(|_| self.create(name)).collect()
+    }
+}
+
+/// Temporary file of rows being written.
+pub(crate) struct SpillWriter {
+    file: BufWriter<MetricsFileHandle>,
+    rows: u64,
+    temp: TempFile,
+}
+
+impl SpillWriter {
+    /// Append a row, in the row format of `minql-value` after its length.
+    pub(crate) fn write(&mut self, row: &[Value]) -> ExecResult<()> {
+        let bytes = encode_row(row);
+        let length = u64::try_from(bytes.len()).expect("Row Length");
+        self.file.write_all(&length.to_le_bytes())?;
+        self.file.write_all(&bytes)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Number of rows written.
+    pub(crate) fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Finish writing, to read the rows back from the first.
+    pub(crate) fn finish(self) -> ExecResult<SpillReader> {
+        let SpillWriter { file, rows, temp } = self;
+        let mut file = file
+            .into_inner()
+            .map_err(std::io::IntoInnerError::into_error)?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(SpillReader {
+            file: BufReader::new(file),
+            remaining: rows,
+            _temp: temp,
+        })
+    }
+}
+
+/// Temporary file of rows being read back, removed when dropped.
+pub(crate) struct SpillReader {
+    file: BufReader<MetricsFileHandle>,
+    remaining: u64,
+    /// File removed once the reader is dropped
+    _temp: TempFile,
+}
+
+impl SpillReader {
+    /// Number of rows not yet read.
+    pub(crate) fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Next row, or `None` after the last.
+    pub(crate) fn next(&mut self) -> ExecResult<Option<Vec<Value>>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        let mut length = [0; 8];
+        self.file.read_exact(&mut length)?;
+        let length = usize::try_from(u64::from_le_bytes(length)).expect("Row Length");
+        let mut bytes = vec![0; length];
+        self.file.read_exact(&mut bytes)?;
+        self.remaining -= 1;
+        Ok(Some(decode_row(&bytes)?))
+    }
+}
+
+/// Path of a temporary file, removed when dropped.
+struct TempFile {
+    fs: MetricFileSystem,
+    path: String,
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(err) = self.fs.remove_file(&self.path) {
+            tracing::warn!(path = self.path, ?err, "Failed to remove spill file");
+        }
+    }
+}
+
+/// Partition of the values of a key among those of [`SpillSpace::partitions`], hashed
+/// differently each time rows are split.
+pub(crate) fn partition(key: &[Value], depth: u32) -> usize {
+    let mut hasher = DefaultHasher::new();
+    depth.hash(&mut hasher);
+    key.hash(&mut hasher);
+    usize::try_from(hasher.finish() % PARTITIONS).expect("Partition")
+}
+
+/// Bytes of memory a row holds, roughly.
+pub(crate) fn row_size(row: &[Value]) -> usize {
+    let heap: usize = row
+        .iter()
+        .map(|value| match value {
+            Value::Text(text) => text.len(),
+            Value::Blob(blob) => blob.len(),
+            _ => 0,
+        })
+        .sum();
+    std::mem::size_of::<Vec<Value>>() + std::mem::size_of_val(row) + heap
+}
+
+#[cfg(test)]
+mod test {
+    use super::{row_size, SpillSpace};
+    use minql_value::Value;
+    use minql_vfs::{FileSystem, MemoryFileSystem, VirtualFileSystem};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_spill_files() {
+        let fs = VirtualFileSystem::new(MemoryFileSystem::new());
+        let space = SpillSpace::new(256).with_files(fs.clone(), "/tmp/");
+        let small = vec![Value::from(1)];
+        assert!(!space.exceeded(row_size(&small)));
+        assert!(space.exceeded(row_size(&[Value::from("x".repeat(256))])));
+        assert!(!SpillSpace::new(0).exceeded(1));
+
+        let mut writer = space.create("rows").unwrap();
+        let rows = [
+            vec![Value::from(1), Value::Null, Value::from("one")],
+            Vec::new(),
+            vec![Value::from(vec![0u8, 1, 2])],
+        ];
+        for row in &rows {
+            writer.write(row).unwrap();
+        }
+        assert_eq!(writer.rows(), 3);
+        let mut reader = writer.finish().unwrap();
+        assert_eq!(fs.list_directory("/tmp").unwrap().len(), 1);
+        for row in &rows {
+            assert_eq!(reader.next().unwrap().as_ref(), Some(row));
+        }
+        assert_eq!(reader.remaining(), 0);
+        assert!(reader.next().unwrap().is_none());
+        drop(reader);
+        assert!(fs.list_directory("/tmp").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,530 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::chunked::ChunkedMap;
+use crate::{ExecError, ExecResult, IndexEntries};
+use minql_catalog::{IndexSchema, TableSchema};
+use minql_value::{decode_row, encode_row, Value};
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Rows read from storage, each decoded into the values of its columns.
+pub type RowStream = Box<dyn Iterator<Item = ExecResult<Vec<Value>>> + Send>;
+
+/// Rows of tables and entries of their indexes, as the executor reads them.
+///
+/// Index keys are the values of the indexed columns, converted to the types of the columns
+/// and encoded by [`encode_key`](minql_value::encode_key), so a range of values is a range of
+/// keys compared bytewise. Storage keeps the indexes of each table itself, from
+/// [`create_index`](Storage::create_index) to [`drop_index`](Storage::drop_index), and changes
+/// their entries with every row it inserts. Where it keeps them is up to the storage, as
+/// rows are.
+pub trait Storage: std::fmt::Debug + Send + Sync {
+    /// Every row of a table.
+    fn scan(&self, table: &TableSchema) -> ExecResult<RowStream>;
+
+    /// Every row of several tables as they all were at one point, with the number of inserts
+    /// storage had committed by then, which names that point.
+    fn scan_all(&self, tables: &[&TableSchema]) -> ExecResult<(u64, Vec<RowStream>)>;
+
+    /// Rows of a table whose keys in an index lie between bounds, in the order of the index.
+    fn index_scan(
+        &self,
+        table: &TableSchema,
+        index: &IndexSchema,
+        lower: Bound<Vec<u8>>,
+        upper: Bound<Vec<u8>>,
+    ) -> ExecResult<RowStream>;
+
+    /// Insert a row into a table and every index of it, converting its values to the types of
+    /// the columns. The row is inserted into all of them or none, and only if its primary key
+    /// holds no nulls and differs from that of every other row.
+    fn insert(&self, table: &TableSchema, row: Vec<Value>) -> ExecResult<()> {
+        self.insert_rows(table, vec![row])
+    }
+
+    /// Insert rows into a table as [`insert`](Storage::insert) does, inserting either every
+    /// one of them or, if any fails, none.
+    fn insert_rows(&self, table: &TableSchema, rows: Vec<Vec<Value>>) -> ExecResult<()>;
+
+    /// Build the entries of a new index from the rows of its table, and keep them with every
+    /// row inserted from then on.
+    ///
+    /// The build reads the rows the table held when it started without holding back inserts,
+    /// then catches up with the rows inserted meanwhile before the index takes over, failing
+    /// without an index if the index is unique and two rows hold equal values.
+    fn create_index(&self, table: &TableSchema, index: &Arc<IndexSchema>) -> ExecResult<()>;
+
+    /// Drop the entries of an index, returning whether there were any to drop.
+    fn drop_index(&self, table: &TableSchema, index: &IndexSchema) -> ExecResult<bool>;
+}
+
+/// Storage holding tables in memory in the row format, for tests and scratch databases.
+///
+/// Scans read the rows a table held when they started, whatever is inserted meanwhile.
+/// Indexes are held as [`IndexEntries`] beside the rows, and like them are neither persisted
+/// nor logged, so they last only as long as the storage.
+///
+/// ```rust
+/// use minql_catalog::{ColumnSchema, TableSchema};
+/// use minql_exec::{MemoryStorage, Storage};
+/// use minql_lang::ast::DataType;
+/// use minql_value::Value;
+///
+/// let table = TableSchema::new("t").with_column(ColumnSchema::new("a", DataType::BigInt));
+/// let storage = MemoryStorage::new();
+/// storage.insert(&table, vec![Value::from(1)]).unwrap();
+/// let rows = storage.scan(&table).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(rows, [[Value::BigInt(1)]]);
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    tables: RwLock<HashMap<u64, Arc<MemoryTable>>>,
+    /// Inserts committed so far, only counted while holding the lock of `tables`
+    inserts: AtomicU64,
+}
+
+/// Rows of a table by row id, and the entries of its indexes.
+///
+/// Rows and entries are held in chunks, so the copy an insert takes of a table a scan still
+/// reads shares every chunk the insert leaves unchanged.
+#[derive(Clone, Debug, Default)]
+struct MemoryTable {
+    rows: ChunkedMap<u64, Vec<u8>>,
+    next: u64,
+    /// Entries of each index by id
+    indexes: HashMap<u64, IndexEntries>,
+    /// Entries of the primary key, once a row of a table with one is inserted
+    primary_key: Option<IndexEntries>,
+}
+
+impl MemoryTable {
+    /// Add the entries of a row to the primary key and every index, recording each one added
+    /// as the id of its index, or `None` for the primary key, and its key.
+    fn add_entries(
+        &mut self,
+        row: &[Value],
+        id: u64,
+        added: &mut Vec<(Option<u64>, Vec<u8>)>,
+    ) -> ExecResult<()> {
+        if let Some(entries) = &mut self.primary_key {
+            let key = entries.entry(row, id)?;
+            entries.insert(key.clone(), id);
+            added.push((None, key));
+        }
+        for (&index, entries) in &mut self.indexes {
+            let key = entries.entry(row, id)?;
+            entries.insert(key.clone(), id);
+            added.push((Some(index), key));
+        }
+        Ok(())
+    }
+
+    /// Entries of an index by id, or of the primary key for `None`.
+    fn entries_mut(&mut self, index: Option<u64>) -> &mut IndexEntries {
+        match index {
+            Some(index) => self.indexes.get_mut(&index).expect("Index"),
+            None => self.primary_key.as_mut().expect("Primary Key"),
+        }
+    }
+}
+
+impl MemoryStorage {
+    /// Create storage without tables.
+    #[must_use]
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+
+    /// Rows of a table as it is now, empty if nothing was ever inserted.
+    fn table(&self, table: &TableSchema) -> Arc<MemoryTable> {
+        let tables = self.tables.read().expect("Poisoned Lock");
+        tables.get(&table.id).cloned().unwrap_or_default()
+    }
+
+    /// Entries of an index for the rows a table holds now, and the row id of the first row
+    /// inserted after them.
+    fn build(
+        &self,
+        table: &TableSchema,
+        index: &Arc<IndexSchema>,
+    ) -> ExecResult<(IndexEntries, u64)> {
+        let snapshot = self.table(table);
+        let mut entries = IndexEntries::new(index.clone());
+        for (&id, row) in snapshot.rows.range(Bound::Unbounded, Bound::Unbounded) {
+            entries.add(&decode_row(row)?, id)?;
+        }
+        Ok((entries, snapshot.next))
+    }
+
+    /// Add the entries of the rows inserted since a build to its index, and install it.
+    fn install(&self, table: &TableSchema, mut entries: IndexEntries, next: u64) -> ExecResult<()> {
+        let mut tables = self.tables.write().expect("Poisoned Lock");
+        let stored = Arc::make_mut(tables.entry(table.id).or_default());
+        for (&id, row) in stored.rows.range(Bound::Included(next), Bound::Unbounded) {
+            entries.add(&decode_row(row)?, id)?;
+        }
+        tracing::debug!(
+            index = %entries.schema().name,
+            caught_up = stored.next - next,
+            entries = entries.len(),
+            "installed index"
+        );
+        stored.indexes.insert(entries.schema().id, entries);
+        Ok(())
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn scan(&self, table: &TableSchema) -> ExecResult<RowStream> {
+        Ok(rows(self.table(table)))
+    }
+
+    fn scan_all(&self, tables: &[&TableSchema]) -> ExecResult<(u64, Vec<RowStream>)> {
+        let stored = self.tables.read().expect("Poisoned Lock");
+        let inserts = self.inserts.load(Ordering::Acquire);
+        let tables = tables
+            .iter()
+            .map(|table| stored.get(&table.id).cloned().unwrap_or_default())
+            .collect::<Vec<_>>();
+        drop(stored);
+        Ok((inserts, tables.into_iter().map(rows).collect()))
+    }
+
+    fn index_scan(
+        &self,
+        table: &TableSchema,
+        index: &IndexSchema,
+        lower: Bound<Vec<u8>>,
+        upper: Bound<Vec<u8>>,
+    ) -> ExecResult<RowStream> {
+        let table = self.table(table);
+        let id = index.id;
+        let mut lower = lower;
+        Ok(Box::new(std::iter::from_fn(move || {
+            let entries = table.indexes.get(&id)?;
+            let (key, row) = entries.range(lower.clone(), upper.clone()).next()?;
+            lower = Bound::Excluded(key.to_vec());
+            let row = table.rows.get(&row).expect("Indexed Row");
+            Some(decode_row(row).map_err(ExecError::from))
+        })))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, rows), fields(table = %table.name))]
+    fn insert_rows(&self, table: &TableSchema, rows: Vec<Vec<Value>>) -> ExecResult<()> {
+        let rows = rows
+            .into_iter()
+            .map(|row| convert(table, row))
+            .collect::<ExecResult<Vec<_>>>()?;
+        let mut tables = self.tables.write().expect("Poisoned Lock");
+        let stored = Arc::make_mut(tables.entry(table.id).or_default());
+        if !table.primary_key.is_empty() && stored.primary_key.is_none() {
+            stored.primary_key = Some(IndexEntries::new(primary_key_index(table)));
+        }
+        // Entries are added row by row, so later rows conflict with earlier ones, and taken
+        // back out if any row fails.
+        let mut added = Vec::new();
+        for (id, row) in (stored.next..).zip(&rows) {
+            if let Err(err) = stored.add_entries(row, id, &mut added) {
+                for (index, key) in added {
+                    stored.entries_mut(index).remove(&key);
+                }
+                return Err(err);
+            }
+        }
+        for row in rows {
+            stored.rows.insert(stored.next, encode_row(&row));
+            stored.next += 1;
+        }
+        self.inserts.fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self, table), fields(table = %table.name, index = %index.name))]
+    fn create_index(&self, table: &TableSchema, index: &Arc<IndexSchema>) -> ExecResult<()> {
+        let (entries, next) = self.build(table, index)?;
+        self.install(table, entries, next)
+    }
+
+    fn drop_index(&self, table: &TableSchema, index: &IndexSchema) -> ExecResult<bool> {
+        let mut tables = self.tables.write().expect("Poisoned Lock");
+        let Some(stored) = tables.get_mut(&table.id) else {
+            return Ok(false);
+        };
+        if !stored.indexes.contains_key(&index.id) {
+            return Ok(false);
+        }
+        Arc::make_mut(stored).indexes.remove(&index.id);
+        Ok(true)
+    }
+}
+
+/// Rows of a snapshot of a table, in the order of their row ids.
+fn rows(table: Arc<MemoryTable>) -> RowStream {
+    let mut after = None;
+    Box::new(std::iter::from_fn(move || {
+        let lower = after.map_or(Bound::Unbounded, Bound::Excluded);
+        let (&id, row) = table.rows.range(lower, Bound::Unbounded).next()?;
+        after = Some(id);
+        Some(decode_row(row).map_err(ExecError::from))
+    }))
+}
+
+/// Check a row fits a table, converting its values to the types of the columns.
+fn convert(table: &TableSchema, row: Vec<Value>) -> ExecResult<Vec<Value>> {
+    if row.len() != table.columns.len() {
+        return Err(ExecError::RowWidth {
+            table: table.name.clone(),
+            expected: table.columns.len(),
+            found: row.len(),
+        });
+    }
+    row.into_iter()
+        .zip(&table.columns)
+        .enumerate()
+        .map(|(position, (value, column))| {
+            if value.is_null() {
+                if !column.nullable || table.primary_key.contains(&position) {
+                    return Err(ExecError::NotNull(column.name.clone()));
+                }
+                return Ok(Value::Null);
+            }
+            Ok(value.cast(column.data_type)?)
+        })
+        .collect()
+}
+
+/// Unique index over the primary key of a table, named as `{table}_pkey`.
+fn primary_key_index(table: &TableSchema) -> Arc<IndexSchema> {
+    Arc::new(IndexSchema {
+        id: 0,
+        database: table.database.clone(),
+        name: format!("{}_pkey", table.name),
+        table: table.id,
+        columns: table.primary_key.clone(),
+        unique: true,
+        uri: table.uri.clone(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MemoryStorage, Storage};
+    use crate::ExecError;
+    use minql_catalog::{ColumnSchema, IndexSchema, TableSchema};
+    use minql_lang::ast::DataType;
+    use minql_value::{encode_key, Value};
+    use std::ops::Bound;
+    use std::sync::Arc;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_storage() {
+        let table = TableSchema::new("t")
+            .with_column(ColumnSchema::new("id", DataType::BigInt).with_nullable(false))
+            .with_column(ColumnSchema::new("name", DataType::Text));
+        let index = Arc::new(IndexSchema {
+            id: 7,
+            database: "db".to_string(),
+            name: "t_id".to_string(),
+            table: table.id,
+            columns: vec![0],
+            unique: true,
+            uri: "mem:///t_id".to_string(),
+        });
+        let storage = MemoryStorage::new();
+        storage.create_index(&table, &index).unwrap();
+        let row = |id: i32, name: &str| vec![Value::from(id), Value::from(name)];
+        storage.insert(&table, row(3, "c")).unwrap();
+        storage.insert(&table, row(1, "a")).unwrap();
+        let mut scan = storage.scan(&table).unwrap();
+        storage.insert(&table, row(2, "b")).unwrap();
+        assert!(matches!(
+            storage.insert(&table, row(1, "again")),
+            Err(ExecError::UniqueViolation(_))
+        ));
+        assert!(matches!(
+            storage.insert(&table, vec![Value::Null, Value::Null]),
+            Err(ExecError::NotNull(_))
+        ));
+        assert!(matches!(
+            storage.insert(&table, vec![Value::from(4)]),
+            Err(ExecError::RowWidth { .. })
+        ));
+        assert!(matches!(
+            storage.insert(&table, vec![Value::from("x"), Value::Null]),
+            Err(ExecError::Value(_))
+        ));
+
+        let first = scan.next().unwrap().unwrap();
+        assert_eq!(first, [Value::BigInt(3), Value::from("c")]);
+        assert_eq!(first[0].value_type(), Some(minql_value::ValueType::BigInt));
+        assert_eq!(scan.count(), 1);
+        assert_eq!(storage.scan(&table).unwrap().count(), 3);
+
+        let key = |id: i64| {
+            let mut key = Vec::new();
+            encode_key(&[Value::from(id)], &mut key);
+            key
+        };
+        let names = |lower, upper| {
+            storage
+                .index_scan(&table, &index, lower, upper)
+                .unwrap()
+                .map(|row| row.unwrap()[1].to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(Bound::Unbounded, Bound::Unbounded), ["a", "b", "c"]);
+        assert_eq!(names(Bound::Included(key(2)), Bound::Unbounded), ["b", "c"]);
+        assert_eq!(names(Bound::Unbounded, Bound::Excluded(key(2))), ["a"]);
+        assert!(names(Bound::Included(key(3)), Bound::Excluded(key(2))).is_empty());
+        assert!(names(Bound::Excluded(key(2)), Bound::Excluded(key(2))).is_empty());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_index_build() {
+        let table = TableSchema::new("t")
+            .with_column(ColumnSchema::new("id", DataType::BigInt))
+            .with_column(ColumnSchema::new("name", DataType::Text));
+        let index = |id: u64, unique: bool| {
+            Arc::new(IndexSchema {
+                id,
+                database: "db".to_string(),
+                name: format!("t_{id}"),
+                table: table.id,
+                columns: vec![1],
+                unique,
+                uri: format!("mem:///t_{id}"),
+            })
+        };
+        let storage = MemoryStorage::new();
+        let row = |id: i32, name: &str| vec![Value::from(id), Value::from(name)];
+        storage.insert(&table, row(1, "b")).unwrap();
+        storage.insert(&table, row(2, "a")).unwrap();
+
+        let names = |index: &IndexSchema| {
+            storage
+                .index_scan(&table, index, Bound::Unbounded, Bound::Unbounded)
+                .unwrap()
+                .map(|row| row.unwrap()[0].to_string())
+                .collect::<Vec<_>>()
+        };
+        let (entries, next) = storage.build(&table, &index(1, true)).unwrap();
+        assert_eq!(entries.len(), 2);
+        storage.insert(&table, row(3, "c")).unwrap();
+        storage.install(&table, entries, next).unwrap();
+        assert_eq!(names(&index(1, true)), ["2", "1", "3"]);
+        storage.insert(&table, row(4, "d")).unwrap();
+        assert!(matches!(
+            storage.insert(&table, row(5, "a")),
+            Err(ExecError::UniqueViolation(_))
+        ));
+        assert_eq!(names(&index(1, true)), ["2", "1", "3", "4"]);
+        assert_eq!(storage.scan(&table).unwrap().count(), 4);
+
+        let (entries, next) = storage.build(&table, &index(2, true)).unwrap();
+        storage.insert(&table, row(6, "b")).unwrap_err();
+        assert!(storage.drop_index(&table, &index(1, true)).unwrap());
+        assert!(!storage.drop_index(&table, &index(1, true)).unwrap());
+        storage.insert(&table, row(6, "b")).unwrap();
+        assert!(matches!(
+            storage.install(&table, entries, next),
+            Err(ExecError::UniqueViolation(_))
+        ));
+        assert!(names(&index(2, true)).is_empty());
+        storage.create_index(&table, &index(3, false)).unwrap();
+        assert_eq!(names(&index(3, false)), ["2", "1", "6", "3", "4"]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_primary_key() {
+        let table = TableSchema::new("u")
+            .with_column(ColumnSchema::new("id", DataType::BigInt))
+            .with_column(ColumnSchema::new("name", DataType::Text))
+            .with_primary_key(vec![0]);
+        let storage = MemoryStorage::new();
+        let row = |id: i32, name: &str| vec![Value::from(id), Value::from(name)];
+        storage.insert(&table, row(1, "a")).unwrap();
+        let Err(ExecError::UniqueViolation(name)) = storage.insert(&table, row(1, "b")) else {
+            panic!("inserted a duplicate primary key");
+        };
+        assert_eq!(name, "u_pkey");
+        assert!(matches!(
+            storage.insert(&table, vec![Value::Null, Value::from("c")]),
+            Err(ExecError::NotNull(column)) if column == "id"
+        ));
+        storage.insert(&table, row(2, "b")).unwrap();
+        assert_eq!(storage.scan(&table).unwrap().count(), 2);
+
+        // Entries of the rows before a failing one are taken back out
+        assert!(matches!(
+            storage.insert_rows(&table, vec![row(3, "c"), row(1, "d")]),
+            Err(ExecError::UniqueViolation(_))
+        ));
+        assert_eq!(storage.scan(&table).unwrap().count(), 2);
+        storage
+            .insert_rows(&table, vec![row(3, "c"), row(4, "d")])
+            .unwrap();
+        assert_eq!(storage.scan(&table).unwrap().count(), 4);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_insert_during_scan() {
+        let table = TableSchema::new("t")
+            .with_column(ColumnSchema::new("id", DataType::BigInt))
+            .with_column(ColumnSchema::new("name", DataType::Text))
+            .with_primary_key(vec![0]);
+        let index = Arc::new(IndexSchema {
+            id: 1,
+            database: "db".to_string(),
+            name: "t_name".to_string(),
+            table: table.id,
+            columns: vec![1],
+            unique: false,
+            uri: "mem:///t_name".to_string(),
+        });
+        let storage = MemoryStorage::new();
+        storage.create_index(&table, &index).unwrap();
+
+        // Every insert copies a table some scan still reads, which only stays linear in the
+        // rows inserted while the copy shares the rows and entries the insert left unchanged.
+        let mut scans = Vec::new();
+        for id in 0..5_000 {
+            let mut scan = storage.scan(&table).unwrap();
+            let row = vec![Value::from(id), Value::from(format!("row {}", id % 100))];
+            storage.insert(&table, row).unwrap();
+            if id % 1_000 == 0 {
+                scans.push((id, scan));
+            } else if id > 0 {
+                assert_eq!(scan.next().unwrap().unwrap()[0], Value::BigInt(0));
+            }
+        }
+        for (id, scan) in scans {
+            assert_eq!(scan.count(), usize::try_from(id).unwrap());
+        }
+        assert_eq!(storage.scan(&table).unwrap().count(), 5_000);
+        let mut key = Vec::new();
+        encode_key(&[Value::from("row 7")], &mut key);
+        let upper = Bound::Excluded([key.as_slice(), &[0xFF]].concat());
+        let rows = storage.index_scan(&table, &index, Bound::Included(key), upper);
+        assert_eq!(rows.unwrap().count(), 50);
+    }
+}
@@ -0,0 +1,454 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::page::MIN_RECORD_SPACE;
+use crate::{HeapError, HeapResult, SlottedPage};
+use minql_vfs::{FileHandle, FileSystemError, PageId, PagedFile};
+
+/// Magic bytes at the start of the header page of every heap file.
+const HEAP_MAGIC: &[u8; 8] = b"MQLHEAP\0";
+
+/// Version of the heap file format.
+const HEAP_VERSION: u32 = 1;
+
+/// Tag of a record stored on its home page.
+const TAG_RECORD: u8 = 1;
+
+/// Tag of a stub on a record's home page pointing to where the record moved.
+const TAG_FORWARD: u8 = 2;
+
+/// Tag of a record stored away from its home page, reached through a forwarding stub.
+const TAG_MOVED: u8 = 3;
+
+/// Bytes of a slot entry, which a new record may need on top of its own space.
+const SLOT_OVERHEAD: usize = 4;
+
+/// Stable address of a record in a [`HeapFile`].
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct RecordId {
+    page: PageId,
+    slot: u16,
+}
+
+impl RecordId {
+    /// Create a record id from its page and slot.
+    #[must_use]
+    pub fn new(page: PageId, slot: u16) -> RecordId {
+        RecordId { page, slot }
+    }
+
+    /// Page holding the record, or its forwarding stub.
+    #[must_use]
+    pub fn page(&self) -> PageId {
+        self.page
+    }
+
+    /// Slot of the record within its page.
+    #[must_use]
+    pub fn slot(&self) -> u16 {
+        self.slot
+    }
+
+    /// Encode a forwarding stub, including its tag.
+    fn encode(self) -> [u8; 11] {
+        let mut stub = [0; 11];
+        stub[0] = TAG_FORWARD;
+        stub[1..9].copy_from_slice(&self.page.to_le_bytes());
+        stub[9..].copy_from_slice(&self.slot.to_le_bytes());
+        stub
+    }
+
+    /// Decode a forwarding stub without its tag.
+    fn decode(stub: &[u8]) -> Option<RecordId> {
+        let page = u64::from_le_bytes(stub.get(..8)?.try_into().ok()?);
+        let slot = u16::from_le_bytes(stub.get(8..10)?.try_into().ok()?);
+        Some(RecordId { page, slot })
+    }
+}
+
+impl std::fmt::Display for RecordId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.page, self.slot)
+    }
+}
+
+/// Heap File
+///
+/// Stores unordered variable length records in the [`SlottedPage`]s of a [`PagedFile`]. The
+/// first page holds a header identifying the file, and every later page holds records.
+///
+/// Each record is addressed by the [`RecordId`] of the slot it was inserted into, its home. An
+/// update that no longer fits on the home page moves the record to another page and leaves a
+/// forwarding stub in its home slot, so the id stays valid. Reads follow at most one forward,
+/// since moving a record again updates the stub on its home page. Records larger than a page
+/// can't be stored.
+///
+/// The free space of every page is tracked in memory, built by reading each page when the
+/// file is opened.
+#[derive(Debug)]
+pub struct HeapFile<H: FileHandle> {
+    file: PagedFile<H>,
+    /// Free space of each page, where the header page has none
+    free: Vec<usize>,
+}
+
+impl<H: FileHandle> HeapFile<H> {
+    /// Create a heap file in an empty paged file.
+    pub fn create(mut file: PagedFile<H>) -> HeapResult<HeapFile<H>> {
+        if file.page_count()? != 0 {
+            return Err(HeapError::InvalidHeader);
+        }
+        if file.payload_size() > usize::from(u16::MAX) {
+            return Err(HeapError::InvalidHeader);
+        }
+        let mut header = file.new_page();
+        header.data_mut()[..8].copy_from_slice(HEAP_MAGIC);
+        header.data_mut()[8..12].copy_from_slice(&HEAP_VERSION.to_le_bytes());
+        let page_size = u32::try_from(file.page_size()).map_err(|_| HeapError::InvalidHeader)?;
+        header.data_mut()[12..16].copy_from_slice(&page_size.to_le_bytes());
+        file.write_page(0, &header)?;
+        Ok(HeapFile {
+            file,
+            free: vec![0],
+        })
+    }
+
+    /// Open a heap file, reading every page to learn its free space.
+    pub fn open(mut file: PagedFile<H>) -> HeapResult<HeapFile<H>> {
+        if file.page_count()? == 0 {
+            return Err(HeapError::InvalidHeader);
+        }
+        // A header read with the wrong page size fails its checksum.
+        let header = file.read_page(0).map_err(|err| match err {
+            FileSystemError::CorruptData { .. } => HeapError::InvalidHeader,
+            err => HeapError::FileSystem(err),
+        })?;
+        let data = header.data();
+        let page_size = u32::from_le_bytes(data[12..16].try_into().expect("Header Page Size"));
+        if &data[..8] != HEAP_MAGIC
+            || u32::from_le_bytes(data[8..12].try_into().expect("Header Version")) != HEAP_VERSION
+            || usize::try_from(page_size).ok() != Some(file.page_size())
+        {
+            return Err(HeapError::InvalidHeader);
+        }
+        let mut free = vec![0];
+        for id in 1..file.page_count()? {
+            free.push(SlottedPage::new(file.read_page(id)?).free_space());
+        }
+        Ok(HeapFile { file, free })
+    }
+
+    /// Largest record a page holds.
+    #[must_use]
+    pub fn max_record_size(&self) -> usize {
+        SlottedPage::max_record_size(self.file.payload_size()) - 1
+    }
+
+    /// Number of pages, including the header page.
+    #[must_use]
+    pub fn page_count(&self) -> u64 {
+        self.free.len() as u64
+    }
+
+    /// Insert a record, returning its id.
+    #[tracing::instrument(level = "trace", skip(record))]
+    pub fn insert(&mut self, record: &[u8]) -> HeapResult<RecordId> {
+        self.store(TAG_RECORD, record, None)
+    }
+
+    /// Read a record.
+    #[tracing::instrument(level = "trace")]
+    pub fn get(&mut self, id: RecordId) -> HeapResult<Vec<u8>> {
+        let page = self.read(id.page)?;
+        match page.get(id.slot).and_then(<[u8]>::split_first) {
+            Some((&TAG_RECORD, record)) => Ok(record.to_vec()),
+            Some((&TAG_FORWARD, stub)) => self.moved(id, stub).map(|(_, record)| record),
+            _ => Err(HeapError::RecordMissing(id)),
+        }
+    }
+
+    /// Replace a record, moving it to another page if it no longer fits on its own.
+    #[tracing::instrument(level = "trace", skip(record))]
+    pub fn update(&mut self, id: RecordId, record: &[u8]) -> HeapResult<()> {
+        self.check_size(record.len())?;
+        let mut page = self.read(id.page)?;
+        let target = match page.get(id.slot).and_then(<[u8]>::split_first) {
+            Some((&TAG_RECORD, _)) => {
+                if page.update(id.slot, &tagged(TAG_RECORD, record)) {
+                    return self.write(id.page, page);
+                }
+                let target = self.store(TAG_MOVED, record, Some(id.page))?;
+                tracing::debug!(%id, %target, "Moved record");
+                target
+            }
+            Some((&TAG_FORWARD, stub)) => {
+                let (previous, _) = self.moved(id, stub)?;
+                let mut moved = self.read(previous.page)?;
+                if moved.update(previous.slot, &tagged(TAG_MOVED, record)) {
+                    return self.write(previous.page, moved);
+                }
+                let target = self.store(TAG_MOVED, record, Some(id.page))?;
+                let mut moved = self.read(previous.page)?;
+                moved.delete(previous.slot);
+                self.write(previous.page, moved)?;
+                target
+            }
+            _ => return Err(HeapError::RecordMissing(id)),
+        };
+        let mut page = self.read(id.page)?;
+        if !page.update(id.slot, &target.encode()) {
+            return Err(HeapError::CorruptPage(id.page));
+        }
+        self.write(id.page, page)
+    }
+
+    /// Delete a record, along with where it moved to.
+    #[tracing::instrument(level = "trace")]
+    pub fn delete(&mut self, id: RecordId) -> HeapResult<()> {
+        let mut page = self.read(id.page)?;
+        match page.get(id.slot).and_then(<[u8]>::split_first) {
+            Some((&TAG_RECORD, _)) => {}
+            Some((&TAG_FORWARD, stub)) => {
+                let (target, _) = self.moved(id, stub)?;
+                let mut moved = self.read(target.page)?;
+                moved.delete(target.slot);
+                self.write(target.page, moved)?;
+            }
+            _ => return Err(HeapError::RecordMissing(id)),
+        }
+        page.delete(id.slot);
+        self.write(id.page, page)
+    }
+
+    /// Iterate over every record and its id, in the order of their home slots.
+    pub fn scan(&mut self) -> HeapScan<'_, H> {
+        HeapScan {
+            heap: self,
+            page: 0,
+            records: Vec::new(),
+        }
+    }
+
+    /// Flush written pages to storage.
+    pub fn sync(&mut self) -> HeapResult<()> {
+        Ok(self.file.sync()?)
+    }
+
+    /// Unwrap the underlying paged file.
+    #[must_use]
+    pub fn into_inner(self) -> PagedFile<H> {
+        self.file
+    }
+
+    /// Store a tagged record on the first page with room for it other than `exclude`,
+    /// appending a page if none has.
+    fn store(&mut self, tag: u8, record: &[u8], exclude: Option<PageId>) -> HeapResult<RecordId> {
+        self.check_size(record.len())?;
+        let needed = (record.len() + 1).max(MIN_RECORD_SPACE) + SLOT_OVERHEAD;
+        let candidate = (1..self.page_count())
+            .find(|page| Some(*page) != exclude && self.free[page_index(*page)] >= needed);
+        let page_id = if let Some(page) = candidate {
+            page
+        } else {
+            let page = self.file.extend(1)?;
+            self.free.push(self.file.payload_size());
+            page
+        };
+        let mut page = self.read(page_id)?;
+        let slot = page
+            .insert(&tagged(tag, record))
+            .ok_or(HeapError::CorruptPage(page_id))?;
+        self.write(page_id, page)?;
+        Ok(RecordId::new(page_id, slot))
+    }
+
+    /// Follow the forwarding stub of the record `id` to where it moved.
+    fn moved(&mut self, id: RecordId, stub: &[u8]) -> HeapResult<(RecordId, Vec<u8>)> {
+        let target = RecordId::decode(stub).ok_or(HeapError::CorruptPage(id.page))?;
+        if target.page == 0 || target.page >= self.page_count() {
+            return Err(HeapError::CorruptPage(id.page));
+        }
+        let page = self.read(target.page)?;
+        match page.get(target.slot).and_then(<[u8]>::split_first) {
+            Some((&TAG_MOVED, record)) => Ok((target, record.to_vec())),
+            _ => Err(HeapError::CorruptPage(target.page)),
+        }
+    }
+
+    fn check_size(&self, size: usize) -> HeapResult<()> {
+        let max = self.max_record_size();
+        if size > max {
+            return Err(HeapError::RecordTooLarge { size, max });
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, id: PageId) -> HeapResult<SlottedPage> {
+        if id == 0 || id >= self.page_count() {
+            return Err(HeapError::RecordMissing(RecordId::new(id, 0)));
+        }
+        Ok(SlottedPage::new(self.file.read_page(id)?))
+    }
+
+    fn write(&mut self, id: PageId, page: SlottedPage) -> HeapResult<()> {
+        self.free[page_index(id)] = page.free_space();
+        self.file.write_page(id, &page.into_page())?;
+        Ok(())
+    }
+}
+
+/// Iterator over the records of a [`HeapFile`], returned by [`HeapFile::scan`].
+///
+/// Moved records are returned with the id of their home slot, once.
+#[derive(Debug)]
+pub struct HeapScan<'a, H: FileHandle> {
+    heap: &'a mut HeapFile<H>,
+    page: PageId,
+    /// Slots and contents of the records of the current page not yet returned, in reverse
+    records: Vec<(u16, Vec<u8>)>,
+}
+
+impl<H: FileHandle> Iterator for HeapScan<'_, H> {
+    type Item = HeapResult<(RecordId, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((slot, record)) = self.records.pop() {
+                let id = RecordId::new(self.page, slot);
+                match record.split_first() {
+                    Some((&TAG_RECORD, record)) => return Some(Ok((id, record.to_vec()))),
+                    Some((&TAG_FORWARD, stub)) => {
+                        return Some(self.heap.moved(id, stub).map(|(_, record)| (id, record)));
+                    }
+                    Some((&TAG_MOVED, _)) => continue,
+                    _ => return Some(Err(HeapError::CorruptPage(self.page))),
+                }
+            }
+            self.page += 1;
+            if self.page >= self.heap.page_count() {
+                return None;
+            }
+            match self.heap.read(self.page) {
+                Ok(page) => {
+                    self.records = page
+                        .records()
+                        .rev()
+                        .map(|(slot, record)| (slot, record.to_vec()))
+                        .collect();
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Prefix a record with its tag.
+fn tagged(tag: u8, record: &[u8]) -> Vec<u8> {
+    let mut tagged = Vec::with_capacity(record.len() + 1);
+    tagged.push(tag);
+    tagged.extend_from_slice(record);
+    tagged
+}
+
+fn page_index(id: PageId) -> usize {
+    usize::try_from(id).expect("Page Index")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{HeapError, HeapFile, RecordId};
+    use minql_vfs::{FileSystem, MemoryFileSystem, PagedFile};
+    use std::collections::BTreeMap;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_heap_file() {
+        let fs = MemoryFileSystem::new();
+        let file = PagedFile::new(fs.create_file("/table.heap").unwrap(), 128).unwrap();
+        let mut heap = HeapFile::create(file).unwrap();
+        assert_eq!(heap.max_record_size(), 115);
+        assert!(matches!(
+            heap.insert(&[0; 116]),
+            Err(HeapError::RecordTooLarge {
+                size: 116,
+                max: 115
+            })
+        ));
+
+        // Records fill pages in turn
+        let mut expected = BTreeMap::new();
+        for index in 0..12_u8 {
+            let record = vec![index; 20];
+            expected.insert(heap.insert(&record).unwrap(), record);
+        }
+        assert_eq!(heap.page_count(), 4);
+        let first = *expected.keys().next().unwrap();
+        assert_eq!(first, RecordId::new(1, 0));
+        assert_eq!(heap.get(first).unwrap(), vec![0; 20]);
+
+        // Growing a record on a full page moves it, keeping its id
+        heap.update(first, &[0xAA; 60]).unwrap();
+        assert_eq!(heap.get(first).unwrap(), vec![0xAA; 60]);
+        heap.update(first, &[0xBB; 100]).unwrap();
+        assert_eq!(heap.get(first).unwrap(), vec![0xBB; 100]);
+        heap.update(first, b"small").unwrap();
+        assert_eq!(heap.get(first).unwrap(), b"small");
+        expected.insert(first, b"small".to_vec());
+        let scanned = heap.scan().collect::<Result<BTreeMap<_, _>, _>>().unwrap();
+        assert_eq!(scanned, expected);
+
+        // Deleting removes moved records too, freeing their space
+        let pages = heap.page_count();
+        heap.delete(first).unwrap();
+        expected.remove(&first);
+        assert!(matches!(
+            heap.get(first),
+            Err(HeapError::RecordMissing(id)) if id == first
+        ));
+        assert!(matches!(
+            heap.delete(first),
+            Err(HeapError::RecordMissing(_))
+        ));
+        assert!(matches!(
+            heap.get(RecordId::new(99, 0)),
+            Err(HeapError::RecordMissing(_))
+        ));
+        let reused = heap.insert(&[9; 20]).unwrap();
+        assert_eq!(reused, first);
+        expected.insert(reused, vec![9; 20]);
+        assert_eq!(heap.page_count(), pages);
+
+        // Reopening finds the records and free space again
+        heap.sync().unwrap();
+        let mut heap = HeapFile::open(heap.into_inner()).unwrap();
+        let scanned = heap.scan().collect::<Result<BTreeMap<_, _>, _>>().unwrap();
+        assert_eq!(scanned, expected);
+        let id = heap.insert(&[7; 20]).unwrap();
+        assert!(id.page() < pages);
+
+        // Only heap files with the same page size open
+        let file = PagedFile::new(fs.open_file("/table.heap").unwrap(), 256).unwrap();
+        assert!(matches!(
+            HeapFile::open(file),
+            Err(HeapError::InvalidHeader)
+        ));
+        let file = PagedFile::new(fs.create_file("/empty.heap").unwrap(), 128).unwrap();
+        assert!(matches!(
+            HeapFile::open(file),
+            Err(HeapError::InvalidHeader)
+        ));
+    }
+}
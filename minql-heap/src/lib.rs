@@ -0,0 +1,63 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Heap File Storage
+//!
+//! Stores unordered variable length records in the [`SlottedPage`]s of a [`PagedFile`],
+//! addressing each by a [`RecordId`] that stays stable for its lifetime, even when an update
+//! moves it to another page.
+//!
+//! ```rust
+//! use minql_heap::HeapFile;
+//! use minql_vfs::{FileSystem, MemoryFileSystem, PagedFile};
+//!
+//! let fs = MemoryFileSystem::new();
+//! let file = PagedFile::new(fs.create_file("/users.heap").unwrap(), 4096).unwrap();
+//! let mut heap = HeapFile::create(file).unwrap();
+//!
+//! let alice = heap.insert(b"alice").unwrap();
+//! let bob = heap.insert(b"bob").unwrap();
+//! heap.update(bob, b"robert").unwrap();
+//! heap.delete(alice).unwrap();
+//!
+//! let records = heap.scan().collect::<Result<Vec<_>, _>>().unwrap();
+//! assert_eq!(records, vec![(bob, b"robert".to_vec())]);
+//! ```
+//!
+//! [`PagedFile`]: minql_vfs::PagedFile
+
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod heap;
+mod page;
+mod result;
+
+pub use self::heap::{HeapFile, HeapScan, RecordId};
+pub use self::page::SlottedPage;
+pub use self::result::{HeapError, HeapResult};
@@ -0,0 +1,311 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use minql_vfs::Page;
+
+/// Bytes at the start of a page holding its slot count and the start of its record data.
+const HEADER_SIZE: usize = 4;
+
+/// Bytes of each slot, holding the offset and length of its record.
+const SLOT_SIZE: usize = 4;
+
+/// Least space reserved for each record, so any record can be replaced in place by a
+/// forwarding stub of a [`HeapFile`](crate::HeapFile).
+pub(crate) const MIN_RECORD_SPACE: usize = 11;
+
+/// Slotted Page
+///
+/// Lays out variable length records in the payload of a [`Page`]. A header at the start of the
+/// page holds the number of slots and where record data starts, followed by a directory of
+/// slots growing towards the end of the page, each holding the offset and length of a record.
+/// Record data grows from the end of the page towards the slot directory. Records are
+/// addressed by their slot, which stays the same when records are compacted to reclaim the
+/// space of deleted ones, and deleted slots are reused by later inserts.
+///
+/// A zeroed page is a valid empty slotted page. Pages may hold at most 65535 bytes of payload,
+/// and every record reserves at least 11 bytes.
+///
+/// ```rust
+/// use minql_heap::SlottedPage;
+/// use minql_vfs::Page;
+///
+/// let mut page = SlottedPage::new(Page::new(128));
+/// let first = page.insert(b"first").unwrap();
+/// let second = page.insert(b"second").unwrap();
+/// page.delete(first);
+/// assert_eq!(page.get(second), Some(&b"second"[..]));
+/// assert_eq!(page.insert(b"third"), Some(first));
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SlottedPage {
+    page: Page,
+}
+
+impl SlottedPage {
+    /// Interpret a page as a slotted page.
+    #[must_use]
+    pub fn new(page: Page) -> SlottedPage {
+        assert!(
+            u16::try_from(page.len()).is_ok(),
+            "Slotted pages hold at most 65535 bytes"
+        );
+        SlottedPage { page }
+    }
+
+    /// Largest record an empty page of `payload_size` bytes holds.
+    #[must_use]
+    pub fn max_record_size(payload_size: usize) -> usize {
+        payload_size.saturating_sub(HEADER_SIZE + SLOT_SIZE)
+    }
+
+    /// Borrow the underlying page.
+    #[must_use]
+    pub fn page(&self) -> &Page {
+        &self.page
+    }
+
+    /// Unwrap the underlying page.
+    #[must_use]
+    pub fn into_page(self) -> Page {
+        self.page
+    }
+
+    /// Number of slots, including deleted slots not yet reused.
+    #[must_use]
+    pub fn slot_count(&self) -> u16 {
+        self.read_u16(0)
+    }
+
+    /// Number of live records.
+    #[must_use]
+    pub fn record_count(&self) -> usize {
+        (0..self.slot_count())
+            .filter(|slot| self.slot(*slot).0 != 0)
+            .count()
+    }
+
+    /// Bytes available to records, counting space of deleted records that compaction reclaims
+    /// but not the slot a new record may need.
+    #[must_use]
+    pub fn free_space(&self) -> usize {
+        let used: usize = (0..self.slot_count())
+            .map(|slot| self.slot(slot))
+            .filter(|(offset, _)| *offset != 0)
+            .map(|(_, len)| reserved(usize::from(len)))
+            .sum();
+        self.page.len() - self.directory_end() - used
+    }
+
+    /// Borrow the record in `slot`, or `None` if it's empty.
+    #[must_use]
+    pub fn get(&self, slot: u16) -> Option<&[u8]> {
+        if slot >= self.slot_count() {
+            return None;
+        }
+        match self.slot(slot) {
+            (0, _) => None,
+            (offset, len) => {
+                let offset = usize::from(offset);
+                self.page.data().get(offset..offset + usize::from(len))
+            }
+        }
+    }
+
+    /// Iterate over the slots and records of every live record.
+    #[must_use]
+    pub fn records(&self) -> impl DoubleEndedIterator<Item = (u16, &[u8])> {
+        (0..self.slot_count()).filter_map(|slot| Some((slot, self.get(slot)?)))
+    }
+
+    /// Insert a record into the first empty slot, or a new one, compacting the page if needed.
+    ///
+    /// Returns the slot, or `None` if the page doesn't have room for the record.
+    pub fn insert(&mut self, record: &[u8]) -> Option<u16> {
+        let slot = (0..self.slot_count())
+            .find(|slot| self.slot(*slot).0 == 0)
+            .unwrap_or(self.slot_count());
+        self.place(slot, record).then_some(slot)
+    }
+
+    /// Replace the record in `slot`, moving it within the page if it grew.
+    ///
+    /// Returns false, leaving the record as it was, if the slot is empty or the page doesn't
+    /// have room for the new record.
+    pub fn update(&mut self, slot: u16, record: &[u8]) -> bool {
+        let Some(previous) = self.get(slot).map(<[u8]>::len) else {
+            return false;
+        };
+        let (offset, _) = self.slot(slot);
+        if reserved(record.len()) <= reserved(previous) {
+            let start = usize::from(offset);
+            self.page.data_mut()[start..start + record.len()].copy_from_slice(record);
+            self.set_slot(slot, offset, record.len());
+            return true;
+        }
+        self.set_slot(slot, 0, 0);
+        if self.place(slot, record) {
+            true
+        } else {
+            self.set_slot(slot, offset, previous);
+            false
+        }
+    }
+
+    /// Delete the record in `slot`, returning whether there was one.
+    pub fn delete(&mut self, slot: u16) -> bool {
+        if self.get(slot).is_none() {
+            return false;
+        }
+        self.set_slot(slot, 0, 0);
+        let mut count = self.slot_count();
+        while count > 0 && self.slot(count - 1).0 == 0 {
+            count -= 1;
+        }
+        self.write_u16(0, count);
+        if count == 0 {
+            self.write_u16(2, 0);
+        }
+        true
+    }
+
+    /// Move every record to the end of the page, so all free space is contiguous.
+    pub fn compact(&mut self) {
+        let records = self
+            .records()
+            .map(|(slot, record)| (slot, record.to_vec()))
+            .collect::<Vec<_>>();
+        let mut start = self.page.len();
+        for (slot, record) in records {
+            start -= reserved(record.len());
+            self.page.data_mut()[start..start + record.len()].copy_from_slice(&record);
+            self.set_slot(slot, to_u16(start), record.len());
+        }
+        self.write_u16(2, to_u16(start));
+    }
+
+    /// Store a record in an empty `slot`, which may be one past the last.
+    fn place(&mut self, slot: u16, record: &[u8]) -> bool {
+        let space = reserved(record.len());
+        let count = self.slot_count().max(slot + 1);
+        let directory_end = HEADER_SIZE + usize::from(count) * SLOT_SIZE;
+        let needed = directory_end + space;
+        if needed > self.free_space() + self.directory_end() {
+            return false;
+        }
+        if needed > self.data_start() {
+            self.compact();
+        }
+        let start = self.data_start() - space;
+        self.page.data_mut()[start..start + record.len()].copy_from_slice(record);
+        self.write_u16(0, count);
+        self.write_u16(2, to_u16(start));
+        self.set_slot(slot, to_u16(start), record.len());
+        true
+    }
+
+    /// Offset just past the slot directory.
+    fn directory_end(&self) -> usize {
+        HEADER_SIZE + usize::from(self.slot_count()) * SLOT_SIZE
+    }
+
+    /// Offset of the lowest record, which is the end of the page while there are none.
+    fn data_start(&self) -> usize {
+        match self.read_u16(2) {
+            0 => self.page.len(),
+            start => usize::from(start),
+        }
+    }
+
+    fn slot(&self, slot: u16) -> (u16, u16) {
+        let at = HEADER_SIZE + usize::from(slot) * SLOT_SIZE;
+        (self.read_u16(at), self.read_u16(at + 2))
+    }
+
+    fn set_slot(&mut self, slot: u16, offset: u16, len: usize) {
+        let at = HEADER_SIZE + usize::from(slot) * SLOT_SIZE;
+        self.write_u16(at, offset);
+        self.write_u16(at + 2, to_u16(len));
+    }
+
+    fn read_u16(&self, at: usize) -> u16 {
+        u16::from_le_bytes([self.page.data()[at], self.page.data()[at + 1]])
+    }
+
+    fn write_u16(&mut self, at: usize, value: u16) {
+        self.page.data_mut()[at..at + 2].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Space reserved for a record of `len` bytes.
+fn reserved(len: usize) -> usize {
+    len.max(MIN_RECORD_SPACE)
+}
+
+fn to_u16(value: usize) -> u16 {
+    u16::try_from(value).expect("Offset within Page")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SlottedPage;
+    use minql_vfs::Page;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_slotted_page() {
+        let mut page = SlottedPage::new(Page::new(64));
+        assert_eq!(page.slot_count(), 0);
+        assert_eq!(page.free_space(), 60);
+        assert_eq!(SlottedPage::max_record_size(64), 56);
+
+        // Records fill the page from the end, reserving a minimum of space each
+        let a = page.insert(b"a").unwrap();
+        let b = page.insert(b"bbbbbbbbbbbbbbbb").unwrap();
+        let c = page.insert(b"c").unwrap();
+        assert_eq!((a, b, c), (0, 1, 2));
+        assert_eq!(page.free_space(), 60 - 12 - 11 - 16 - 11);
+        assert!(page.insert(&[0; 20]).is_none());
+
+        // Deleted slots are reused, and trailing ones dropped
+        assert!(page.delete(a));
+        assert!(!page.delete(a));
+        assert_eq!(page.get(a), None);
+        assert_eq!(page.insert(b"d"), Some(a));
+        assert!(page.delete(c));
+        assert_eq!(page.slot_count(), 2);
+
+        // Updates happen in place when they fit, and compact the page when they grow
+        assert!(page.update(b, b"short"));
+        assert_eq!(page.get(b), Some(&b"short"[..]));
+        assert!(page.update(b, &[7; 30]));
+        assert_eq!(page.get(b), Some(&[7; 30][..]));
+        assert!(!page.update(b, &[7; 50]));
+        assert_eq!(page.get(b), Some(&[7; 30][..]));
+        assert!(!page.update(9, b"missing"));
+        assert_eq!(page.get(a), Some(&b"d"[..]));
+        assert_eq!(page.records().count(), 2);
+        assert_eq!(page.record_count(), 2);
+
+        // A page emptied of records can hold the largest record again
+        page.delete(a);
+        page.delete(b);
+        assert_eq!(page.slot_count(), 0);
+        assert_eq!(page.insert(&[1; 56]), Some(0));
+        assert_eq!(page.free_space(), 0);
+        let page = SlottedPage::new(page.into_page());
+        assert_eq!(page.get(0), Some(&[1; 56][..]));
+    }
+}
@@ -0,0 +1,62 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::RecordId;
+use minql_vfs::{FileSystemError, PageId};
+
+/// Result Type for Heap Files
+pub type HeapResult<T> = Result<T, HeapError>;
+
+/// Error Type for Heap Files
+#[derive(Debug)]
+pub enum HeapError {
+    /// Record is too large to fit in a single page
+    RecordTooLarge {
+        /// Size of the record in bytes
+        size: usize,
+        /// Largest record a page holds
+        max: usize,
+    },
+    /// No record exists with the id
+    RecordMissing(RecordId),
+    /// File isn't a heap file, or has a different page size
+    InvalidHeader,
+    /// Page contents are inconsistent
+    CorruptPage(PageId),
+    /// Error of the underlying `FileSystem`
+    FileSystem(FileSystemError),
+}
+
+impl std::fmt::Display for HeapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for HeapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HeapError::FileSystem(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<FileSystemError> for HeapError {
+    fn from(err: FileSystemError) -> Self {
+        HeapError::FileSystem(err)
+    }
+}
@@ -0,0 +1,394 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::KvResult;
+use minql_lsm::{LsmOptions, LsmScan, LsmSnapshot, LsmTree, WriteBatch};
+use minql_vfs::{
+    FileSystemProvider, LocalFileSystemProvider, MemoryFileSystemProvider, VirtualFileHandle,
+    VirtualFileSystem, VirtualFileSystemManager,
+};
+use std::collections::HashMap;
+use std::ops::{Bound, RangeBounds};
+use std::sync::OnceLock;
+
+/// Configuration of a [`Kv`] store.
+#[derive(Clone, Debug)]
+pub struct KvOptions {
+    engine: LsmOptions,
+    sync_writes: bool,
+}
+
+impl KvOptions {
+    /// Create options with the defaults.
+    #[must_use]
+    pub fn new() -> KvOptions {
+        KvOptions {
+            engine: LsmOptions::new(),
+            sync_writes: false,
+        }
+    }
+
+    /// Configuration of the underlying [`LsmTree`].
+    #[must_use]
+    pub fn with_engine_options(mut self, engine: LsmOptions) -> KvOptions {
+        self.engine = engine;
+        self
+    }
+
+    /// Sync the log after every write, so a write is durable once it returns rather than
+    /// after the next [`Kv::sync`]. Defaults to `false`.
+    #[must_use]
+    pub fn with_sync_writes(mut self, sync_writes: bool) -> KvOptions {
+        self.sync_writes = sync_writes;
+        self
+    }
+}
+
+impl Default for KvOptions {
+    fn default() -> Self {
+        KvOptions::new()
+    }
+}
+
+/// Key Value Store
+///
+/// An ordered map of byte string keys to values stored in the [`LsmTree`] found at a URI. The
+/// URI is resolved to a filesystem by a [`VirtualFileSystemManager`], and the store occupies
+/// the root of that filesystem, so `file:///var/lib/minql/db` keeps its files in the local
+/// directory `/var/lib/minql/db` while `mem://scratch` keeps them in memory.
+///
+/// Reads see every write made before them. A [`WriteBatch`] applies several writes that are
+/// recovered all or none, and a [`Snapshot`] keeps reading the store as it was when taken.
+///
+/// ```rust
+/// use minql_kv::{Kv, WriteBatch};
+///
+/// let mut kv = Kv::open("mem://example").unwrap();
+/// kv.put(b"apple", b"red").unwrap();
+/// let snapshot = kv.snapshot();
+///
+/// let mut batch = WriteBatch::new();
+/// batch.put(b"banana", b"yellow").delete(b"apple");
+/// kv.write(&batch).unwrap();
+///
+/// assert_eq!(kv.get(b"apple").unwrap(), None);
+/// assert_eq!(snapshot.get(b"apple").unwrap(), Some(b"red".to_vec()));
+/// let keys = kv
+///     .iter()
+///     .map(|entry| entry.map(|(key, _)| key))
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+/// assert_eq!(keys, vec![b"banana".to_vec()]);
+/// ```
+#[derive(Debug)]
+pub struct Kv {
+    uri: String,
+    tree: LsmTree<VirtualFileSystem>,
+    sync_writes: bool,
+}
+
+impl Kv {
+    /// Open or create the store at a URI, resolved by a shared manager with providers for
+    /// `file://` URIs, creating missing directories, and `mem://` URIs.
+    pub fn open(uri: &str) -> KvResult<Kv> {
+        Kv::open_with(default_manager(), uri, KvOptions::new())
+    }
+
+    /// Open or create the store at a URI resolved by the given manager.
+    #[tracing::instrument(level = "trace", skip(manager))]
+    pub fn open_with(
+        manager: &VirtualFileSystemManager,
+        uri: &str,
+        options: KvOptions,
+    ) -> KvResult<Kv> {
+        let fs = manager.get(uri)?;
+        let tree = LsmTree::open(fs, "/", options.engine)?;
+        Ok(Kv {
+            uri: uri.to_string(),
+            tree,
+            sync_writes: options.sync_writes,
+        })
+    }
+
+    /// URI the store was opened at.
+    #[must_use]
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// Get the value of a key.
+    pub fn get(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        Ok(self.tree.get(key)?)
+    }
+
+    /// Set the value of a key.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> KvResult<()> {
+        self.tree.put(key, value)?;
+        self.synced()
+    }
+
+    /// Delete a key.
+    pub fn delete(&mut self, key: &[u8]) -> KvResult<()> {
+        self.tree.delete(key)?;
+        self.synced()
+    }
+
+    /// Apply every write of a batch, recovered all or none after a crash.
+    pub fn write(&mut self, batch: &WriteBatch) -> KvResult<()> {
+        self.tree.write(batch)?;
+        self.synced()
+    }
+
+    /// Iterate over the entries with keys in a range, in key order.
+    ///
+    /// ```rust
+    /// use minql_kv::Kv;
+    ///
+    /// let mut kv = Kv::open("mem://range").unwrap();
+    /// for key in [b"a", b"b", b"c", b"d"] {
+    ///     kv.put(key, b"").unwrap();
+    /// }
+    /// let keys = kv
+    ///     .range(&b"b"[..]..&b"d"[..])
+    ///     .map(|entry| entry.unwrap().0)
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(keys, vec![b"b".to_vec(), b"c".to_vec()]);
+    /// ```
+    pub fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> KvRange<'_> {
+        let (start, end) = bounds(&range);
+        KvRange(self.tree.scan(start, end))
+    }
+
+    /// Iterate over every entry, in key order.
+    #[must_use]
+    pub fn iter(&self) -> KvRange<'_> {
+        KvRange(self.tree.scan(Bound::Unbounded, Bound::Unbounded))
+    }
+
+    /// Take a snapshot of the store, reading it as it is now regardless of later writes.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot(self.tree.snapshot())
+    }
+
+    /// Flush every write to storage.
+    pub fn sync(&mut self) -> KvResult<()> {
+        Ok(self.tree.sync()?)
+    }
+
+    /// Write buffered writes out to a table, then compact the engine as needed.
+    pub fn flush(&mut self) -> KvResult<()> {
+        Ok(self.tree.flush()?)
+    }
+
+    /// Consume the store, returning the underlying engine.
+    #[must_use]
+    pub fn into_inner(self) -> LsmTree<VirtualFileSystem> {
+        self.tree
+    }
+
+    fn synced(&mut self) -> KvResult<()> {
+        if self.sync_writes {
+            self.tree.sync()?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for &'a Kv {
+    type Item = KvResult<(Vec<u8>, Vec<u8>)>;
+    type IntoIter = KvRange<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Snapshot of a [`Kv`] store, returned by [`Kv::snapshot`].
+///
+/// A snapshot reads independently of the store, which may keep writing, and keeps the files it
+/// reads until dropped.
+#[derive(Clone, Debug)]
+pub struct Snapshot(LsmSnapshot<VirtualFileHandle>);
+
+impl Snapshot {
+    /// Get the value of a key when the snapshot was taken.
+    pub fn get(&self, key: &[u8]) -> KvResult<Option<Vec<u8>>> {
+        Ok(self.0.get(key)?)
+    }
+
+    /// Iterate over the entries with keys in a range when the snapshot was taken, in key order.
+    pub fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> KvRange<'_> {
+        let (start, end) = bounds(&range);
+        KvRange(self.0.scan(start, end))
+    }
+
+    /// Iterate over every entry when the snapshot was taken, in key order.
+    #[must_use]
+    pub fn iter(&self) -> KvRange<'_> {
+        KvRange(self.0.scan(Bound::Unbounded, Bound::Unbounded))
+    }
+}
+
+impl<'a> IntoIterator for &'a Snapshot {
+    type Item = KvResult<(Vec<u8>, Vec<u8>)>;
+    type IntoIter = KvRange<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over a range of entries of a [`Kv`] store or [`Snapshot`].
+#[derive(Debug)]
+pub struct KvRange<'a>(LsmScan<'a>);
+
+impl Iterator for KvRange<'_> {
+    type Item = KvResult<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.0.next()?.map_err(Into::into))
+    }
+}
+
+/// Borrow the bounds of a range as byte strings.
+fn bounds<'a, K: AsRef<[u8]> + 'a, R: RangeBounds<K>>(
+    range: &'a R,
+) -> (Bound<&'a [u8]>, Bound<&'a [u8]>) {
+    (
+        range.start_bound().map(AsRef::as_ref),
+        range.end_bound().map(AsRef::as_ref),
+    )
+}
+
+/// Manager shared by every store opened by [`Kv::open`].
+fn default_manager() -> &'static VirtualFileSystemManager {
+    static MANAGER: OnceLock<VirtualFileSystemManager> = OnceLock::new();
+    MANAGER.get_or_init(|| {
+        let manager = VirtualFileSystemManager::default();
+        let local = LocalFileSystemProvider::default();
+        let configuration =
+            HashMap::from([("create_root_if_missing".to_string(), "true".to_string())]);
+        local
+            .configure(&configuration)
+            .expect("Local Provider Configuration");
+        manager.register(local).expect("Local Provider");
+        manager
+            .register(MemoryFileSystemProvider::default())
+            .expect("Memory Provider");
+        manager
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Kv, KvOptions, WriteBatch};
+    use minql_lsm::LsmOptions;
+    use minql_vfs::{FileSystem, VirtualFileSystemManager};
+    use std::collections::BTreeMap;
+
+    fn options() -> KvOptions {
+        KvOptions::new().with_engine_options(
+            LsmOptions::new()
+                .with_memtable_size(1024)
+                .with_table_size(2048)
+                .with_level0_tables(2)
+                .with_level_size(4096),
+        )
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key{index:04}").into_bytes()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_kv() {
+        let manager = VirtualFileSystemManager::with_defaults();
+        let mut kv = Kv::open_with(&manager, "mem://test-kv", options()).unwrap();
+        assert_eq!(kv.uri(), "mem://test-kv");
+        let mut expected = BTreeMap::new();
+        for index in 0..400 {
+            let value = format!("value{index}").into_bytes();
+            kv.put(&key(index), &value).unwrap();
+            expected.insert(key(index), value);
+        }
+        let snapshot = kv.snapshot();
+        let before = expected.clone();
+
+        let mut batch = WriteBatch::new();
+        for index in (0..400).step_by(3) {
+            batch.delete(&key(index));
+            expected.remove(&key(index));
+        }
+        batch.put(b"batched", b"value");
+        expected.insert(b"batched".to_vec(), b"value".to_vec());
+        kv.write(&batch).unwrap();
+        for index in 400..800 {
+            kv.put(&key(index), b"later").unwrap();
+            expected.insert(key(index), b"later".to_vec());
+        }
+        kv.delete(&key(1)).unwrap();
+        expected.remove(&key(1));
+
+        assert_eq!(kv.get(&key(0)).unwrap(), None);
+        assert_eq!(kv.get(&key(2)).unwrap(), expected.get(&key(2)).cloned());
+        let entries = kv.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries, expected.clone().into_iter().collect::<Vec<_>>());
+        let entries = kv
+            .range(key(10)..=key(20))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let range = expected
+            .range(key(10)..=key(20))
+            .map(|(k, v)| (k.clone(), v.clone()));
+        assert_eq!(entries, range.collect::<Vec<_>>());
+
+        assert_eq!(snapshot.get(&key(0)).unwrap(), before.get(&key(0)).cloned());
+        assert_eq!(snapshot.get(b"batched").unwrap(), None);
+        let entries = snapshot.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries, before.into_iter().collect::<Vec<_>>());
+        drop(snapshot);
+
+        // Reopening the URI finds the same store
+        drop(kv);
+        let kv = Kv::open_with(&manager, "mem://test-kv", options()).unwrap();
+        let entries = (&kv).into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries, expected.into_iter().collect::<Vec<_>>());
+        assert!(manager
+            .get("mem://test-kv")
+            .unwrap()
+            .exists("/wal")
+            .unwrap());
+        assert!(Kv::open_with(&manager, "unknown://test-kv", options()).is_err());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_kv_local() {
+        let root = std::env::temp_dir().join(format!("minql-kv-test-{}", std::process::id()));
+        let uri = format!("file://{}", root.join("db").display());
+        let mut kv = Kv::open(&uri).unwrap();
+        kv.put(b"durable", b"value").unwrap();
+        kv.sync().unwrap();
+        drop(kv);
+
+        let kv = Kv::open(&uri).unwrap();
+        assert_eq!(kv.get(b"durable").unwrap(), Some(b"value".to_vec()));
+        drop(kv);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,51 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Key Value Store
+//!
+//! A [`Kv`] store of ordered byte string keys and values, opened by URI through the
+//! `VirtualFileSystemManager` of `minql-vfs` and stored in an LSM tree of `minql-lsm`, with
+//! range iterators, atomic [`WriteBatch`]es and consistent [`Snapshot`]s.
+//!
+//! ```rust
+//! use minql_kv::Kv;
+//!
+//! let mut kv = Kv::open("mem://quickstart").unwrap();
+//! kv.put(b"greeting", b"hello").unwrap();
+//! assert_eq!(kv.get(b"greeting").unwrap(), Some(b"hello".to_vec()));
+//! ```
+
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod kv;
+mod result;
+
+pub use self::kv::{Kv, KvOptions, KvRange, Snapshot};
+pub use self::result::{KvError, KvResult};
+pub use minql_lsm::WriteBatch;
@@ -0,0 +1,60 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use minql_lsm::LsmError;
+use minql_vfs::FileSystemError;
+
+/// Result Type for Key Value Stores
+pub type KvResult<T> = Result<T, KvError>;
+
+/// Error Type for Key Value Stores
+#[derive(Debug)]
+pub enum KvError {
+    /// Error of the underlying storage engine
+    Storage(LsmError),
+    /// Error of the underlying `FileSystem`, including URIs no provider can open
+    FileSystem(FileSystemError),
+}
+
+impl std::fmt::Display for KvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for KvError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KvError::Storage(err) => Some(err),
+            KvError::FileSystem(err) => Some(err),
+        }
+    }
+}
+
+impl From<LsmError> for KvError {
+    fn from(err: LsmError) -> Self {
+        match err {
+            LsmError::FileSystem(err) => KvError::FileSystem(err),
+            err => KvError::Storage(err),
+        }
+    }
+}
+
+impl From<FileSystemError> for KvError {
+    fn from(err: FileSystemError) -> Self {
+        KvError::FileSystem(err)
+    }
+}
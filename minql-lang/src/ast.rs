@@ -0,0 +1,50 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Abstract Syntax Tree of SQL statements, produced by the [`Parser`](crate::Parser) and
+//! rendered back to text by the [`Formatter`](crate::Formatter).
+
+mod expr;
+mod query;
+mod statement;
+
+pub use self::expr::{BinaryOperator, DataType, Expr, Function, Ident, Literal, UnaryOperator};
+pub use self::query::{
+    Join, JoinConstraint, JoinKind, OrderByExpr, Query, Select, SelectItem, SetExpr, SetOperator,
+    TableFactor, TableWithJoins,
+};
+pub use self::statement::{Assignment, ColumnDef, ColumnOption, Statement, TableConstraint};
+
+use crate::Formatter;
+
+/// Render a node compactly, on one line with upper case keywords.
+macro_rules! display {
+    ($($node:ty => $method:ident,)*) => {
+        $(
+            impl std::fmt::Display for $node {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    f.write_str(&Formatter::compact().$method(self))
+                }
+            }
+        )*
+    };
+}
+
+display! {
+    Expr => format_expr,
+    Query => format_query,
+    Statement => format,
+}
@@ -0,0 +1,382 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::Keyword;
+
+/// Identifier of a table, column, or other object.
+///
+/// Unquoted identifiers are kept as written and compare regardless of case once folded by
+/// [`Ident::normalized`], while quoted identifiers keep their case.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Ident {
+    /// Name as written, without quotes
+    pub value: String,
+    /// Whether the name was double quoted
+    pub quoted: bool,
+}
+
+impl Ident {
+    /// Create an unquoted identifier.
+    #[must_use]
+    pub fn new(value: &str) -> Ident {
+        Ident {
+            value: value.to_string(),
+            quoted: false,
+        }
+    }
+
+    /// Create a quoted identifier.
+    #[must_use]
+    pub fn quoted(value: &str) -> Ident {
+        Ident {
+            value: value.to_string(),
+            quoted: true,
+        }
+    }
+
+    /// Name the identifier refers to, folded to lower case unless quoted.
+    #[must_use]
+    pub fn normalized(&self) -> String {
+        if self.quoted {
+            self.value.clone()
+        } else {
+            self.value.to_lowercase()
+        }
+    }
+
+    /// Check if the identifier can be written without quotes and still read back the same.
+    #[must_use]
+    pub fn is_plain(&self) -> bool {
+        let mut chars = self.value.chars();
+        chars
+            .next()
+            .is_some_and(|first| first.is_alphabetic() || first == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+            && Keyword::lookup(&self.value).is_none_or(|keyword| !keyword.is_reserved())
+    }
+}
+
+/// Literal value written in SQL source.
+///
+/// Numbers keep their text so no precision is lost before they are given a type.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Literal {
+    /// `NULL`
+    Null,
+    /// `TRUE` or `FALSE`
+    Boolean(bool),
+    /// Number without a fraction or exponent
+    Integer(String),
+    /// Number with a fraction or exponent
+    Float(String),
+    /// Quoted string, without quotes or escapes
+    String(String),
+    /// Hexadecimal digits of a blob
+    Blob(String),
+}
+
+/// Prefix operator.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UnaryOperator {
+    /// `+`
+    Plus,
+    /// `-`
+    Minus,
+    /// `NOT`
+    Not,
+}
+
+/// Infix operator.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum BinaryOperator {
+    /// `OR`
+    Or,
+    /// `AND`
+    And,
+    /// `=`
+    Eq,
+    /// `<>`
+    NotEq,
+    /// `<`
+    Lt,
+    /// `<=`
+    LtEq,
+    /// `>`
+    Gt,
+    /// `>=`
+    GtEq,
+    /// `||`
+    Concat,
+    /// `+`
+    Plus,
+    /// `-`
+    Minus,
+    /// `*`
+    Multiply,
+    /// `/`
+    Divide,
+    /// `%`
+    Modulo,
+}
+
+impl BinaryOperator {
+    /// Binding strength, where operators of higher precedence are applied first.
+    #[must_use]
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Eq
+            | BinaryOperator::NotEq
+            | BinaryOperator::Lt
+            | BinaryOperator::LtEq
+            | BinaryOperator::Gt
+            | BinaryOperator::GtEq => 4,
+            BinaryOperator::Concat => 5,
+            BinaryOperator::Plus | BinaryOperator::Minus => 6,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 7,
+        }
+    }
+
+    /// Operator as written in SQL.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BinaryOperator::Or => "OR",
+            BinaryOperator::And => "AND",
+            BinaryOperator::Eq => "=",
+            BinaryOperator::NotEq => "<>",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::LtEq => "<=",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::GtEq => ">=",
+            BinaryOperator::Concat => "||",
+            BinaryOperator::Plus => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+        }
+    }
+}
+
+/// Type of a column or cast.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DataType {
+    /// `BOOLEAN`
+    Boolean,
+    /// `SMALLINT`, a 16 bit integer
+    SmallInt,
+    /// `INTEGER`, a 32 bit integer
+    Integer,
+    /// `BIGINT`, a 64 bit integer
+    BigInt,
+    /// `REAL`, a 32 bit float
+    Real,
+    /// `DOUBLE`, a 64 bit float
+    Double,
+    /// `DECIMAL`, with an optional precision and scale
+    Decimal(Option<(u32, Option<u32>)>),
+    /// `TEXT`
+    Text,
+    /// `VARCHAR`, with an optional maximum length in characters
+    Varchar(Option<u32>),
+    /// `BLOB`
+    Blob,
+    /// `TIMESTAMP`
+    Timestamp,
+}
+
+impl DataType {
+    /// Find the type named by a word, ignoring case, without any length or precision.
+    #[must_use]
+    pub fn lookup(name: &str) -> Option<DataType> {
+        match name.to_ascii_uppercase().as_str() {
+            "BOOLEAN" | "BOOL" => Some(DataType::Boolean),
+            "SMALLINT" | "INT2" => Some(DataType::SmallInt),
+            "INTEGER" | "INT" | "INT4" => Some(DataType::Integer),
+            "BIGINT" | "INT8" => Some(DataType::BigInt),
+            "REAL" | "FLOAT4" => Some(DataType::Real),
+            "DOUBLE" | "FLOAT" | "FLOAT8" => Some(DataType::Double),
+            "DECIMAL" | "NUMERIC" => Some(DataType::Decimal(None)),
+            "TEXT" => Some(DataType::Text),
+            "VARCHAR" => Some(DataType::Varchar(None)),
+            "BLOB" | "BYTEA" => Some(DataType::Blob),
+            "TIMESTAMP" => Some(DataType::Timestamp),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataType::Boolean => write!(f, "BOOLEAN"),
+            DataType::SmallInt => write!(f, "SMALLINT"),
+            DataType::Integer => write!(f, "INTEGER"),
+            DataType::BigInt => write!(f, "BIGINT"),
+            DataType::Real => write!(f, "REAL"),
+            DataType::Double => write!(f, "DOUBLE"),
+            DataType::Decimal(None) => write!(f, "DECIMAL"),
+            DataType::Decimal(Some((precision, None))) => write!(f, "DECIMAL({precision})"),
+            DataType::Decimal(Some((precision, Some(scale)))) => {
+                write!(f, "DECIMAL({precision}, {scale})")
+            }
+            DataType::Text => write!(f, "TEXT"),
+            DataType::Varchar(None) => write!(f, "VARCHAR"),
+            DataType::Varchar(Some(length)) => write!(f, "VARCHAR({length})"),
+            DataType::Blob => write!(f, "BLOB"),
+            DataType::Timestamp => write!(f, "TIMESTAMP"),
+        }
+    }
+}
+
+/// Call of a function by name.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Function {
+    /// Name of the function, optionally qualified
+    pub name: Vec<Ident>,
+    /// Arguments, empty for `*`
+    pub args: Vec<Expr>,
+    /// Whether called as `f(*)`
+    pub wildcard: bool,
+    /// Whether only distinct arguments are used, as in `COUNT(DISTINCT x)`
+    pub distinct: bool,
+}
+
+/// Scalar expression.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Expr {
+    /// Literal value
+    Literal(Literal),
+    /// Column or other name, optionally qualified, as in `t.c`
+    Identifier(Vec<Ident>),
+    /// Positional parameter, `?` or numbered as in `$1`
+    Parameter(Option<usize>),
+    /// Prefix operator applied to an operand
+    Unary {
+        /// Operator
+        op: UnaryOperator,
+        /// Operand
+        expr: Box<Expr>,
+    },
+    /// Infix operator applied to two operands
+    Binary {
+        /// Left operand
+        left: Box<Expr>,
+        /// Operator
+        op: BinaryOperator,
+        /// Right operand
+        right: Box<Expr>,
+    },
+    /// `expr IS [NOT] NULL`
+    IsNull {
+        /// Operand
+        expr: Box<Expr>,
+        /// Whether `NOT` was given
+        negated: bool,
+    },
+    /// `expr [NOT] BETWEEN low AND high`
+    Between {
+        /// Operand
+        expr: Box<Expr>,
+        /// Whether `NOT` was given
+        negated: bool,
+        /// Lower bound, inclusive
+        low: Box<Expr>,
+        /// Upper bound, inclusive
+        high: Box<Expr>,
+    },
+    /// `expr [NOT] IN (list)`
+    InList {
+        /// Operand
+        expr: Box<Expr>,
+        /// Whether `NOT` was given
+        negated: bool,
+        /// Values compared against
+        list: Vec<Expr>,
+    },
+    /// `expr [NOT] IN (query)`
+    InSubquery {
+        /// Operand
+        expr: Box<Expr>,
+        /// Whether `NOT` was given
+        negated: bool,
+        /// Query of the values compared against
+        query: Box<crate::ast::Query>,
+    },
+    /// `expr [NOT] LIKE pattern [ESCAPE escape]`
+    Like {
+        /// Operand
+        expr: Box<Expr>,
+        /// Whether `NOT` was given
+        negated: bool,
+        /// Pattern, where `%` matches any text and `_` any character
+        pattern: Box<Expr>,
+        /// Character escaping `%` and `_` in the pattern
+        escape: Option<Box<Expr>>,
+    },
+    /// `CASE [operand] WHEN .. THEN .. [ELSE ..] END`
+    Case {
+        /// Value compared with each condition, which are otherwise boolean
+        operand: Option<Box<Expr>>,
+        /// Conditions and their results, in order
+        branches: Vec<(Expr, Expr)>,
+        /// Result when no condition matches
+        else_result: Option<Box<Expr>>,
+    },
+    /// `CAST(expr AS type)` or `expr::type`
+    Cast {
+        /// Operand
+        expr: Box<Expr>,
+        /// Type converted to
+        data_type: DataType,
+    },
+    /// Function call
+    Function(Function),
+    /// `[NOT] EXISTS (query)`
+    Exists {
+        /// Query checked for rows
+        query: Box<crate::ast::Query>,
+        /// Whether `NOT` was given
+        negated: bool,
+    },
+    /// Query returning a single value
+    Subquery(Box<crate::ast::Query>),
+    /// Parenthesized expression
+    Nested(Box<Expr>),
+}
+
+impl Expr {
+    /// Binding strength of the outermost operator, where atoms bind tightest.
+    #[must_use]
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary { op, .. } => op.precedence(),
+            Expr::Unary {
+                op: UnaryOperator::Not,
+                ..
+            } => 3,
+            Expr::IsNull { .. }
+            | Expr::Between { .. }
+            | Expr::InList { .. }
+            | Expr::InSubquery { .. }
+            | Expr::Like { .. } => 4,
+            Expr::Unary { .. } => 8,
+            _ => 10,
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::ast::{Expr, Ident};
+
+/// Query with optional ordering and limits.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Query {
+    /// Rows of the query
+    pub body: SetExpr,
+    /// `ORDER BY` expressions
+    pub order_by: Vec<OrderByExpr>,
+    /// `LIMIT` row count
+    pub limit: Option<Expr>,
+    /// `OFFSET` row count
+    pub offset: Option<Expr>,
+}
+
+impl Query {
+    /// Create a query of a body without ordering or limits.
+    #[must_use]
+    pub fn new(body: SetExpr) -> Query {
+        Query {
+            body,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+        }
+    }
+}
+
+/// Source of the rows of a [`Query`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum SetExpr {
+    /// `SELECT ..`
+    Select(Box<Select>),
+    /// `VALUES (..), ..`
+    Values(Vec<Vec<Expr>>),
+    /// Parenthesized query
+    Query(Box<Query>),
+    /// `left UNION|EXCEPT|INTERSECT [ALL] right`
+    SetOperation {
+        /// Operator
+        op: SetOperator,
+        /// Whether duplicate rows are kept
+        all: bool,
+        /// Left operand
+        left: Box<SetExpr>,
+        /// Right operand
+        right: Box<SetExpr>,
+    },
+}
+
+/// Operator combining the rows of two queries.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SetOperator {
+    /// `UNION`
+    Union,
+    /// `EXCEPT`
+    Except,
+    /// `INTERSECT`, which binds tighter than the others
+    Intersect,
+}
+
+impl SetOperator {
+    /// Binding strength, where operators of higher precedence are applied first.
+    #[must_use]
+    pub fn precedence(self) -> u8 {
+        match self {
+            SetOperator::Union | SetOperator::Except => 1,
+            SetOperator::Intersect => 2,
+        }
+    }
+}
+
+/// `SELECT` of a query.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Select {
+    /// Whether only distinct rows are returned
+    pub distinct: bool,
+    /// Columns returned
+    pub projection: Vec<SelectItem>,
+    /// `FROM` tables, joined as a cross product
+    pub from: Vec<TableWithJoins>,
+    /// `WHERE` condition
+    pub selection: Option<Expr>,
+    /// `GROUP BY` expressions
+    pub group_by: Vec<Expr>,
+    /// `HAVING` condition
+    pub having: Option<Expr>,
+}
+
+/// Column returned by a [`Select`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum SelectItem {
+    /// Expression, optionally named by `AS`
+    Expr {
+        /// Value of the column
+        expr: Expr,
+        /// Name of the column
+        alias: Option<Ident>,
+    },
+    /// `*`, or `t.*` for every column of a table
+    Wildcard(Vec<Ident>),
+}
+
+/// Table and the tables joined to it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TableWithJoins {
+    /// First table
+    pub relation: TableFactor,
+    /// Tables joined, in order
+    pub joins: Vec<Join>,
+}
+
+/// Table read by a query.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum TableFactor {
+    /// Named table, optionally qualified and aliased
+    Table {
+        /// Name of the table
+        name: Vec<Ident>,
+        /// Name the table is referred to by
+        alias: Option<Ident>,
+    },
+    /// Parenthesized query, optionally aliased
+    Derived {
+        /// Query of the rows
+        subquery: Box<Query>,
+        /// Name the rows are referred to by
+        alias: Option<Ident>,
+    },
+}
+
+/// Table joined to those before it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Join {
+    /// Table joined
+    pub relation: TableFactor,
+    /// How unmatched rows are treated
+    pub kind: JoinKind,
+    /// Condition matching rows
+    pub constraint: JoinConstraint,
+}
+
+/// Kind of [`Join`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum JoinKind {
+    /// `[INNER] JOIN`, keeping matched rows
+    Inner,
+    /// `LEFT [OUTER] JOIN`, also keeping unmatched rows on the left
+    Left,
+    /// `RIGHT [OUTER] JOIN`, also keeping unmatched rows on the right
+    Right,
+    /// `FULL [OUTER] JOIN`, also keeping unmatched rows on either side
+    Full,
+    /// `CROSS JOIN`, matching every pair of rows
+    Cross,
+}
+
+/// Condition of a [`Join`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum JoinConstraint {
+    /// `ON condition`
+    On(Expr),
+    /// `USING (columns)`, matching columns of the same name
+    Using(Vec<Ident>),
+    /// No condition
+    None,
+}
+
+/// `ORDER BY` expression.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct OrderByExpr {
+    /// Value ordered by
+    pub expr: Expr,
+    /// `ASC` as `Some(true)` or `DESC` as `Some(false)`
+    pub asc: Option<bool>,
+    /// `NULLS FIRST` as `Some(true)` or `NULLS LAST` as `Some(false)`
+    pub nulls_first: Option<bool>,
+}
@@ -0,0 +1,196 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::ast::{DataType, Expr, Ident, Query};
+
+/// SQL statement.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Statement {
+    /// Query of rows
+    Query(Box<Query>),
+    /// `INSERT INTO table [(columns)] query`
+    Insert {
+        /// Table inserted into
+        table: Vec<Ident>,
+        /// Columns given values, or all columns if empty
+        columns: Vec<Ident>,
+        /// Rows inserted, commonly `VALUES`
+        source: Box<Query>,
+    },
+    /// `UPDATE table SET assignments [WHERE selection]`
+    Update {
+        /// Table updated
+        table: Vec<Ident>,
+        /// Columns set
+        assignments: Vec<Assignment>,
+        /// Condition of the rows updated
+        selection: Option<Expr>,
+    },
+    /// `DELETE FROM table [WHERE selection]`
+    Delete {
+        /// Table deleted from
+        table: Vec<Ident>,
+        /// Condition of the rows deleted
+        selection: Option<Expr>,
+    },
+    /// `CREATE TABLE [IF NOT EXISTS] name (columns, constraints)`
+    CreateTable {
+        /// Name of the table
+        name: Vec<Ident>,
+        /// Whether an existing table is left alone
+        if_not_exists: bool,
+        /// Columns
+        columns: Vec<ColumnDef>,
+        /// Constraints over several columns
+        constraints: Vec<TableConstraint>,
+    },
+    /// `CREATE [UNIQUE] INDEX [IF NOT EXISTS] name ON table (columns)`
+    CreateIndex {
+        /// Name of the index
+        name: Ident,
+        /// Table indexed
+        table: Vec<Ident>,
+        /// Columns indexed, in order
+        columns: Vec<Ident>,
+        /// Whether indexed values must be distinct
+        unique: bool,
+        /// Whether an existing index is left alone
+        if_not_exists: bool,
+    },
+    /// `DROP TABLE [IF EXISTS] names`
+    DropTable {
+        /// Tables dropped
+        names: Vec<Vec<Ident>>,
+        /// Whether missing tables are ignored
+        if_exists: bool,
+    },
+    /// `DROP INDEX [IF EXISTS] names`
+    DropIndex {
+        /// Indexes dropped
+        names: Vec<Vec<Ident>>,
+        /// Whether missing indexes are ignored
+        if_exists: bool,
+    },
+    /// `BEGIN [TRANSACTION]`
+    Begin,
+    /// `COMMIT [TRANSACTION]`
+    Commit,
+    /// `ROLLBACK [TRANSACTION]`
+    Rollback,
+    /// `EXPLAIN [ANALYZE] statement`
+    Explain {
+        /// Whether the statement is run to gather actual figures
+        analyze: bool,
+        /// Statement explained
+        statement: Box<Statement>,
+    },
+    /// `ANALYZE [table]`
+    Analyze {
+        /// Table analyzed, or every table
+        table: Option<Vec<Ident>>,
+    },
+    /// `BACKUP TO 'uri'`
+    Backup {
+        /// URI of the filesystem the backup is written to
+        uri: String,
+    },
+    /// `RESTORE FROM 'uri'`
+    Restore {
+        /// URI of the filesystem the backup is read from
+        uri: String,
+    },
+}
+
+/// `column = value` of an `UPDATE`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Assignment {
+    /// Column set
+    pub column: Ident,
+    /// Value set
+    pub value: Expr,
+}
+
+/// Column of a `CREATE TABLE`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ColumnDef {
+    /// Name of the column
+    pub name: Ident,
+    /// Type of the column
+    pub data_type: DataType,
+    /// Constraints and default, in the order written
+    pub options: Vec<ColumnOption>,
+}
+
+/// Constraint or default of a [`ColumnDef`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ColumnOption {
+    /// `NULL`
+    Null,
+    /// `NOT NULL`
+    NotNull,
+    /// `PRIMARY KEY`
+    PrimaryKey,
+    /// `UNIQUE`
+    Unique,
+    /// `DEFAULT value`
+    Default(Expr),
+    /// `CHECK (condition)`
+    Check(Expr),
+    /// `REFERENCES table [(columns)]`
+    References {
+        /// Table referred to
+        table: Vec<Ident>,
+        /// Columns referred to, or the primary key if empty
+        columns: Vec<Ident>,
+    },
+}
+
+/// Constraint of a `CREATE TABLE` over one or more columns, optionally named by `CONSTRAINT`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum TableConstraint {
+    /// `PRIMARY KEY (columns)`
+    PrimaryKey {
+        /// Name of the constraint
+        name: Option<Ident>,
+        /// Columns of the key
+        columns: Vec<Ident>,
+    },
+    /// `UNIQUE (columns)`
+    Unique {
+        /// Name of the constraint
+        name: Option<Ident>,
+        /// Columns whose values are distinct together
+        columns: Vec<Ident>,
+    },
+    /// `FOREIGN KEY (columns) REFERENCES table [(columns)]`
+    ForeignKey {
+        /// Name of the constraint
+        name: Option<Ident>,
+        /// Columns referring
+        columns: Vec<Ident>,
+        /// Table referred to
+        foreign_table: Vec<Ident>,
+        /// Columns referred to, or the primary key if empty
+        referred_columns: Vec<Ident>,
+    },
+    /// `CHECK (condition)`
+    Check {
+        /// Name of the constraint
+        name: Option<Ident>,
+        /// Condition every row meets
+        expr: Expr,
+    },
+}
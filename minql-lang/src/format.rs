@@ -0,0 +1,966 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::ast::{
+    ColumnDef, ColumnOption, Expr, Function, Ident, Join, JoinConstraint, JoinKind, Literal,
+    OrderByExpr, Query, Select, SelectItem, SetExpr, SetOperator, Statement, TableConstraint,
+    TableFactor, TableWithJoins, UnaryOperator,
+};
+
+/// Letter case of keywords or identifiers written by a [`Formatter`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Case {
+    /// As written, or upper case for keywords
+    Preserve,
+    /// Upper case
+    Upper,
+    /// Lower case
+    Lower,
+}
+
+impl Case {
+    fn apply(self, text: &str) -> String {
+        match self {
+            Case::Preserve => text.to_string(),
+            Case::Upper => text.to_uppercase(),
+            Case::Lower => text.to_lowercase(),
+        }
+    }
+}
+
+/// Configuration of a [`Formatter`].
+#[derive(Clone, Debug)]
+pub struct FormatOptions {
+    keyword_case: Case,
+    identifier_case: Case,
+    indent: usize,
+    line_width: usize,
+}
+
+impl FormatOptions {
+    /// Create options with upper case keywords, identifiers as written, an indent of 2 spaces
+    /// and a line width of 80 characters.
+    #[must_use]
+    pub fn new() -> FormatOptions {
+        FormatOptions {
+            keyword_case: Case::Upper,
+            identifier_case: Case::Preserve,
+            indent: 2,
+            line_width: 80,
+        }
+    }
+
+    /// Letter case of keywords and type names.
+    #[must_use]
+    pub fn with_keyword_case(mut self, keyword_case: Case) -> FormatOptions {
+        self.keyword_case = keyword_case;
+        self
+    }
+
+    /// Letter case of unquoted identifiers, which doesn't change what they refer to.
+    #[must_use]
+    pub fn with_identifier_case(mut self, identifier_case: Case) -> FormatOptions {
+        self.identifier_case = identifier_case;
+        self
+    }
+
+    /// Spaces each clause item is indented by when a statement spans several lines.
+    #[must_use]
+    pub fn with_indent(mut self, indent: usize) -> FormatOptions {
+        self.indent = indent;
+        self
+    }
+
+    /// Characters a line may hold before a statement is broken over several lines.
+    #[must_use]
+    pub fn with_line_width(mut self, line_width: usize) -> FormatOptions {
+        self.line_width = line_width;
+        self
+    }
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions::new()
+    }
+}
+
+/// Clause of a statement, a head such as `SELECT` followed by its items.
+struct Clause {
+    head: String,
+    items: Vec<String>,
+    /// Whether items are separated by commas rather than spaces
+    comma: bool,
+    /// Whether items are enclosed in parentheses
+    parenthesized: bool,
+}
+
+impl Clause {
+    fn new(head: String, items: Vec<String>) -> Clause {
+        Clause {
+            head,
+            items,
+            comma: true,
+            parenthesized: false,
+        }
+    }
+
+    fn head(head: String) -> Clause {
+        Clause::new(head, Vec::new())
+    }
+
+    fn inline(&self) -> String {
+        if self.items.is_empty() {
+            self.head.clone()
+        } else if self.parenthesized {
+            format!("{} ({})", self.head, self.items.join(", "))
+        } else {
+            let separator = if self.comma { ", " } else { " " };
+            format!("{} {}", self.head, self.items.join(separator))
+        }
+    }
+}
+
+/// SQL Formatter
+///
+/// Renders [`Statement`]s and [`Expr`]essions as canonical SQL text, which parses back to the
+/// same tree. Statements are written on one line if it fits the line width, and are otherwise
+/// broken into a line per clause, with the items of any clause still too wide on lines of their
+/// own. Expressions are parenthesized only where written so or where operator precedence
+/// demands it, and `::` casts are written as `CAST`.
+///
+/// The `Display` implementations of the tree write the compact form, on a single line with
+/// upper case keywords.
+///
+/// ```rust
+/// use minql_lang::{Case, FormatOptions, Formatter, Parser};
+///
+/// let statement = Parser::parse_statement("select id, name from users where id=1").unwrap();
+/// let formatter = Formatter::new(
+///     FormatOptions::new()
+///         .with_keyword_case(Case::Lower)
+///         .with_line_width(20),
+/// );
+/// assert_eq!(formatter.format(&statement), "select id, name\nfrom users\nwhere id = 1");
+/// assert_eq!(statement.to_string(), "SELECT id, name FROM users WHERE id = 1");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Formatter {
+    options: FormatOptions,
+}
+
+impl Formatter {
+    /// Create a formatter.
+    #[must_use]
+    pub fn new(options: FormatOptions) -> Formatter {
+        Formatter { options }
+    }
+
+    /// Create a formatter writing everything on one line with upper case keywords.
+    #[must_use]
+    pub fn compact() -> Formatter {
+        Formatter::new(FormatOptions::new().with_line_width(usize::MAX))
+    }
+
+    /// Render a statement, without a terminating semicolon.
+    #[must_use]
+    pub fn format(&self, statement: &Statement) -> String {
+        self.layout(&self.statement(statement))
+    }
+
+    /// Render statements, each terminated by a semicolon and a line break.
+    #[must_use]
+    pub fn format_script(&self, statements: &[Statement]) -> String {
+        let mut script = String::new();
+        for statement in statements {
+            script.push_str(&self.format(statement));
+            script.push_str(";\n");
+        }
+        script
+    }
+
+    /// Render a query.
+    #[must_use]
+    pub fn format_query(&self, query: &Query) -> String {
+        self.layout(&self.query(query))
+    }
+
+    /// Render an expression on one line.
+    #[must_use]
+    pub fn format_expr(&self, expr: &Expr) -> String {
+        self.expr(expr)
+    }
+
+    /// Join clauses on one line, or break them over several if too wide.
+    fn layout(&self, clauses: &[Clause]) -> String {
+        let inline = clauses
+            .iter()
+            .map(Clause::inline)
+            .collect::<Vec<_>>()
+            .join(" ");
+        if self.fits(&inline) {
+            return inline;
+        }
+        let indent = " ".repeat(self.options.indent);
+        let mut lines = Vec::new();
+        for clause in clauses {
+            let line = clause.inline();
+            if self.fits(&line) || clause.items.is_empty() {
+                lines.push(line);
+                continue;
+            }
+            if clause.parenthesized {
+                lines.push(format!("{} (", clause.head));
+            } else {
+                lines.push(clause.head.clone());
+            }
+            for (index, item) in clause.items.iter().enumerate() {
+                let last = index + 1 == clause.items.len();
+                let comma = if clause.comma && !last { "," } else { "" };
+                lines.push(format!("{indent}{item}{comma}"));
+            }
+            if clause.parenthesized {
+                lines.push(")".to_string());
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn fits(&self, line: &str) -> bool {
+        line.chars().count() <= self.options.line_width
+    }
+
+    fn statement(&self, statement: &Statement) -> Vec<Clause> {
+        match statement {
+            Statement::Query(query) => self.query(query),
+            Statement::Insert {
+                table,
+                columns,
+                source,
+            } => {
+                let mut head = format!("{} {}", self.keyword("INSERT INTO"), self.name(table));
+                if !columns.is_empty() {
+                    head = format!("{head} ({})", self.idents(columns));
+                }
+                let mut clauses = vec![Clause::head(head)];
+                clauses.extend(self.query(source));
+                clauses
+            }
+            Statement::Update {
+                table,
+                assignments,
+                selection,
+            } => {
+                let assignments = assignments
+                    .iter()
+                    .map(|assignment| {
+                        format!(
+                            "{} = {}",
+                            self.ident(&assignment.column),
+                            self.expr(&assignment.value)
+                        )
+                    })
+                    .collect();
+                let mut clauses = vec![
+                    Clause::head(format!("{} {}", self.keyword("UPDATE"), self.name(table))),
+                    Clause::new(self.keyword("SET"), assignments),
+                ];
+                clauses.extend(self.selection("WHERE", selection.as_ref()));
+                clauses
+            }
+            Statement::Delete { table, selection } => {
+                let mut clauses = vec![Clause::head(format!(
+                    "{} {}",
+                    self.keyword("DELETE FROM"),
+                    self.name(table)
+                ))];
+                clauses.extend(self.selection("WHERE", selection.as_ref()));
+                clauses
+            }
+            Statement::CreateTable { .. } | Statement::CreateIndex { .. } => {
+                vec![self.create(statement)]
+            }
+            Statement::DropTable { names, if_exists } => self.drop("DROP TABLE", names, *if_exists),
+            Statement::DropIndex { names, if_exists } => self.drop("DROP INDEX", names, *if_exists),
+            Statement::Begin => vec![Clause::head(self.keyword("BEGIN"))],
+            Statement::Commit => vec![Clause::head(self.keyword("COMMIT"))],
+            Statement::Rollback => vec![Clause::head(self.keyword("ROLLBACK"))],
+            Statement::Explain { analyze, statement } => {
+                let head = self.keyword(if *analyze {
+                    "EXPLAIN ANALYZE"
+                } else {
+                    "EXPLAIN"
+                });
+                let mut clauses = vec![Clause::head(head)];
+                clauses.extend(self.statement(statement));
+                clauses
+            }
+            Statement::Analyze { table } => {
+                let mut head = self.keyword("ANALYZE");
+                if let Some(table) = table {
+                    head = format!("{head} {}", self.name(table));
+                }
+                vec![Clause::head(head)]
+            }
+            Statement::Backup { uri } => {
+                let uri = self.literal(&Literal::String(uri.clone()));
+                vec![Clause::head(format!("{} {uri}", self.keyword("BACKUP TO")))]
+            }
+            Statement::Restore { uri } => {
+                let uri = self.literal(&Literal::String(uri.clone()));
+                vec![Clause::head(format!(
+                    "{} {uri}",
+                    self.keyword("RESTORE FROM")
+                ))]
+            }
+        }
+    }
+
+    fn create(&self, statement: &Statement) -> Clause {
+        match statement {
+            Statement::CreateTable {
+                name,
+                if_not_exists,
+                columns,
+                constraints,
+            } => {
+                let mut head = self.keyword("CREATE TABLE");
+                if *if_not_exists {
+                    head = format!("{head} {}", self.keyword("IF NOT EXISTS"));
+                }
+                let items = columns
+                    .iter()
+                    .map(|column| self.column_def(column))
+                    .chain(
+                        constraints
+                            .iter()
+                            .map(|constraint| self.table_constraint(constraint)),
+                    )
+                    .collect();
+                Clause {
+                    head: format!("{head} {}", self.name(name)),
+                    items,
+                    comma: true,
+                    parenthesized: true,
+                }
+            }
+            Statement::CreateIndex {
+                name,
+                table,
+                columns,
+                unique,
+                if_not_exists,
+            } => {
+                let mut head = self.keyword(if *unique {
+                    "CREATE UNIQUE INDEX"
+                } else {
+                    "CREATE INDEX"
+                });
+                if *if_not_exists {
+                    head = format!("{head} {}", self.keyword("IF NOT EXISTS"));
+                }
+                Clause::head(format!(
+                    "{head} {} {} {} ({})",
+                    self.ident(name),
+                    self.keyword("ON"),
+                    self.name(table),
+                    self.idents(columns)
+                ))
+            }
+            _ => unreachable!("not a CREATE statement"),
+        }
+    }
+
+    fn drop(&self, head: &str, names: &[Vec<crate::ast::Ident>], if_exists: bool) -> Vec<Clause> {
+        let mut head = self.keyword(head);
+        if if_exists {
+            head = format!("{head} {}", self.keyword("IF EXISTS"));
+        }
+        let names = names.iter().map(|name| self.name(name)).collect();
+        vec![Clause::new(head, names)]
+    }
+
+    fn column_def(&self, column: &ColumnDef) -> String {
+        let mut text = format!(
+            "{} {}",
+            self.ident(&column.name),
+            self.keyword(&column.data_type.to_string())
+        );
+        for option in &column.options {
+            let option = match option {
+                ColumnOption::Null => self.keyword("NULL"),
+                ColumnOption::NotNull => self.keyword("NOT NULL"),
+                ColumnOption::PrimaryKey => self.keyword("PRIMARY KEY"),
+                ColumnOption::Unique => self.keyword("UNIQUE"),
+                ColumnOption::Default(expr) => {
+                    format!("{} {}", self.keyword("DEFAULT"), self.expr(expr))
+                }
+                ColumnOption::Check(expr) => {
+                    format!("{} ({})", self.keyword("CHECK"), self.expr(expr))
+                }
+                ColumnOption::References { table, columns } => self.references(table, columns),
+            };
+            text = format!("{text} {option}");
+        }
+        text
+    }
+
+    fn table_constraint(&self, constraint: &TableConstraint) -> String {
+        let (name, body) = match constraint {
+            TableConstraint::PrimaryKey { name, columns } => (
+                name,
+                format!("{} ({})", self.keyword("PRIMARY KEY"), self.idents(columns)),
+            ),
+            TableConstraint::Unique { name, columns } => (
+                name,
+                format!("{} ({})", self.keyword("UNIQUE"), self.idents(columns)),
+            ),
+            TableConstraint::ForeignKey {
+                name,
+                columns,
+                foreign_table,
+                referred_columns,
+            } => (
+                name,
+                format!(
+                    "{} ({}) {}",
+                    self.keyword("FOREIGN KEY"),
+                    self.idents(columns),
+                    self.references(foreign_table, referred_columns)
+                ),
+            ),
+            TableConstraint::Check { name, expr } => (
+                name,
+                format!("{} ({})", self.keyword("CHECK"), self.expr(expr)),
+            ),
+        };
+        match name {
+            Some(name) => format!("{} {} {body}", self.keyword("CONSTRAINT"), self.ident(name)),
+            None => body,
+        }
+    }
+
+    fn references(&self, table: &[Ident], columns: &[Ident]) -> String {
+        let text = format!("{} {}", self.keyword("REFERENCES"), self.name(table));
+        if columns.is_empty() {
+            text
+        } else {
+            format!("{text} ({})", self.idents(columns))
+        }
+    }
+
+    fn query(&self, query: &Query) -> Vec<Clause> {
+        let mut clauses = self.set_expr(&query.body, 0, false);
+        if !query.order_by.is_empty() {
+            let items = query
+                .order_by
+                .iter()
+                .map(|order| self.order_by_expr(order))
+                .collect();
+            clauses.push(Clause::new(self.keyword("ORDER BY"), items));
+        }
+        clauses.extend(self.selection("LIMIT", query.limit.as_ref()));
+        clauses.extend(self.selection("OFFSET", query.offset.as_ref()));
+        clauses
+    }
+
+    /// Clauses of a set expression operand of an operator of `precedence`, on its right if
+    /// `right`.
+    fn set_expr(&self, body: &SetExpr, precedence: u8, right: bool) -> Vec<Clause> {
+        match body {
+            SetExpr::Select(select) => self.select(select),
+            SetExpr::Values(rows) => {
+                let rows = rows
+                    .iter()
+                    .map(|row| format!("({})", self.exprs(row)))
+                    .collect();
+                vec![Clause::new(self.keyword("VALUES"), rows)]
+            }
+            SetExpr::Query(query) => vec![Clause::head(format!("({})", self.inline_query(query)))],
+            SetExpr::SetOperation {
+                op,
+                all,
+                left,
+                right: operand,
+            } => {
+                let inner = op.precedence();
+                if inner < precedence || (right && inner == precedence) {
+                    let clauses = self.set_expr(body, 0, false);
+                    return vec![Clause::head(format!("({})", Formatter::inline(&clauses)))];
+                }
+                let mut head = self.keyword(match op {
+                    SetOperator::Union => "UNION",
+                    SetOperator::Except => "EXCEPT",
+                    SetOperator::Intersect => "INTERSECT",
+                });
+                if *all {
+                    head = format!("{head} {}", self.keyword("ALL"));
+                }
+                let mut clauses = self.set_expr(left, inner, false);
+                clauses.push(Clause::head(head));
+                clauses.extend(self.set_expr(operand, inner, true));
+                clauses
+            }
+        }
+    }
+
+    fn select(&self, select: &Select) -> Vec<Clause> {
+        let head = self.keyword(if select.distinct {
+            "SELECT DISTINCT"
+        } else {
+            "SELECT"
+        });
+        let projection = select
+            .projection
+            .iter()
+            .map(|item| match item {
+                SelectItem::Wildcard(name) if name.is_empty() => "*".to_string(),
+                SelectItem::Wildcard(name) => format!("{}.*", self.name(name)),
+                SelectItem::Expr { expr, alias } => self.aliased(self.expr(expr), alias.as_ref()),
+            })
+            .collect();
+        let mut clauses = vec![Clause::new(head, projection)];
+        if !select.from.is_empty() {
+            let from = select
+                .from
+                .iter()
+                .map(|table| self.table_with_joins(table))
+                .collect();
+            clauses.push(Clause::new(self.keyword("FROM"), from));
+        }
+        clauses.extend(self.selection("WHERE", select.selection.as_ref()));
+        if !select.group_by.is_empty() {
+            let items = select.group_by.iter().map(|expr| self.expr(expr)).collect();
+            clauses.push(Clause::new(self.keyword("GROUP BY"), items));
+        }
+        clauses.extend(self.selection("HAVING", select.having.as_ref()));
+        clauses
+    }
+
+    /// Clause of a keyword and a single expression, if there is one.
+    fn selection(&self, head: &str, expr: Option<&Expr>) -> Option<Clause> {
+        expr.map(|expr| Clause::new(self.keyword(head), vec![self.expr(expr)]))
+    }
+
+    fn table_with_joins(&self, table: &TableWithJoins) -> String {
+        let mut text = self.table_factor(&table.relation);
+        for join in &table.joins {
+            text = format!("{text} {}", self.join(join));
+        }
+        text
+    }
+
+    fn join(&self, join: &Join) -> String {
+        let kind = self.keyword(match join.kind {
+            JoinKind::Inner => "JOIN",
+            JoinKind::Left => "LEFT JOIN",
+            JoinKind::Right => "RIGHT JOIN",
+            JoinKind::Full => "FULL JOIN",
+            JoinKind::Cross => "CROSS JOIN",
+        });
+        let relation = self.table_factor(&join.relation);
+        match &join.constraint {
+            JoinConstraint::On(expr) => {
+                format!(
+                    "{kind} {relation} {} {}",
+                    self.keyword("ON"),
+                    self.expr(expr)
+                )
+            }
+            JoinConstraint::Using(columns) => format!(
+                "{kind} {relation} {} ({})",
+                self.keyword("USING"),
+                self.idents(columns)
+            ),
+            JoinConstraint::None => format!("{kind} {relation}"),
+        }
+    }
+
+    fn table_factor(&self, factor: &TableFactor) -> String {
+        match factor {
+            TableFactor::Table { name, alias } => self.aliased(self.name(name), alias.as_ref()),
+            TableFactor::Derived { subquery, alias } => {
+                self.aliased(format!("({})", self.inline_query(subquery)), alias.as_ref())
+            }
+        }
+    }
+
+    fn order_by_expr(&self, order: &OrderByExpr) -> String {
+        let mut text = self.expr(&order.expr);
+        match order.asc {
+            Some(true) => text = format!("{text} {}", self.keyword("ASC")),
+            Some(false) => text = format!("{text} {}", self.keyword("DESC")),
+            None => {}
+        }
+        match order.nulls_first {
+            Some(true) => text = format!("{text} {}", self.keyword("NULLS FIRST")),
+            Some(false) => text = format!("{text} {}", self.keyword("NULLS LAST")),
+            None => {}
+        }
+        text
+    }
+
+    fn aliased(&self, text: String, alias: Option<&Ident>) -> String {
+        match alias {
+            Some(alias) => format!("{text} {} {}", self.keyword("AS"), self.ident(alias)),
+            None => text,
+        }
+    }
+
+    /// Render a query nested in another on one line.
+    fn inline_query(&self, query: &Query) -> String {
+        Formatter::inline(&self.query(query))
+    }
+
+    fn inline(clauses: &[Clause]) -> String {
+        clauses
+            .iter()
+            .map(Clause::inline)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        match expr {
+            Expr::Literal(literal) => self.literal(literal),
+            Expr::Identifier(name) => self.name(name),
+            Expr::Parameter(Some(number)) => format!("${number}"),
+            Expr::Parameter(None) => "?".to_string(),
+            Expr::Unary {
+                op: UnaryOperator::Not,
+                expr,
+            } => format!("{} {}", self.keyword("NOT"), self.operand(expr, 3, false)),
+            Expr::Unary { op, expr } => {
+                let sign = if *op == UnaryOperator::Minus {
+                    "-"
+                } else {
+                    "+"
+                };
+                let operand = self.operand(expr, 8, false);
+                // Keep signs apart, since `--` starts a comment
+                if operand.starts_with(['-', '+']) {
+                    format!("{sign} {operand}")
+                } else {
+                    format!("{sign}{operand}")
+                }
+            }
+            Expr::Binary { left, op, right } => {
+                let precedence = op.precedence();
+                format!(
+                    "{} {} {}",
+                    self.operand(left, precedence, false),
+                    self.keyword(op.as_str()),
+                    self.operand(right, precedence, true)
+                )
+            }
+            Expr::IsNull { .. }
+            | Expr::Between { .. }
+            | Expr::InList { .. }
+            | Expr::InSubquery { .. }
+            | Expr::Like { .. } => self.predicate(expr),
+            Expr::Case {
+                operand,
+                branches,
+                else_result,
+            } => {
+                let mut text = self.keyword("CASE");
+                if let Some(operand) = operand {
+                    text = format!("{text} {}", self.expr(operand));
+                }
+                for (condition, result) in branches {
+                    text = format!(
+                        "{text} {} {} {} {}",
+                        self.keyword("WHEN"),
+                        self.expr(condition),
+                        self.keyword("THEN"),
+                        self.expr(result)
+                    );
+                }
+                if let Some(else_result) = else_result {
+                    text = format!("{text} {} {}", self.keyword("ELSE"), self.expr(else_result));
+                }
+                format!("{text} {}", self.keyword("END"))
+            }
+            Expr::Cast { expr, data_type } => format!(
+                "{}({} {} {})",
+                self.keyword("CAST"),
+                self.expr(expr),
+                self.keyword("AS"),
+                self.keyword(&data_type.to_string())
+            ),
+            Expr::Function(function) => self.function(function),
+            Expr::Exists { query, negated } => format!(
+                "{} ({})",
+                self.keyword(if *negated { "NOT EXISTS" } else { "EXISTS" }),
+                self.inline_query(query)
+            ),
+            Expr::Subquery(query) => format!("({})", self.inline_query(query)),
+            Expr::Nested(expr) => format!("({})", self.expr(expr)),
+        }
+    }
+
+    fn predicate(&self, expr: &Expr) -> String {
+        match expr {
+            Expr::IsNull { expr, negated } => format!(
+                "{} {}",
+                self.operand(expr, 4, false),
+                self.keyword(if *negated { "IS NOT NULL" } else { "IS NULL" })
+            ),
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => format!(
+                "{} {} {} {} {}",
+                self.operand(expr, 4, false),
+                self.keyword(if *negated { "NOT BETWEEN" } else { "BETWEEN" }),
+                self.operand(low, 4, true),
+                self.keyword("AND"),
+                self.operand(high, 4, true)
+            ),
+            Expr::InList {
+                expr,
+                negated,
+                list,
+            } => format!(
+                "{} {} ({})",
+                self.operand(expr, 4, false),
+                self.keyword(if *negated { "NOT IN" } else { "IN" }),
+                self.exprs(list)
+            ),
+            Expr::InSubquery {
+                expr,
+                negated,
+                query,
+            } => format!(
+                "{} {} ({})",
+                self.operand(expr, 4, false),
+                self.keyword(if *negated { "NOT IN" } else { "IN" }),
+                self.inline_query(query)
+            ),
+            Expr::Like {
+                expr,
+                negated,
+                pattern,
+                escape,
+            } => {
+                let text = format!(
+                    "{} {} {}",
+                    self.operand(expr, 4, false),
+                    self.keyword(if *negated { "NOT LIKE" } else { "LIKE" }),
+                    self.operand(pattern, 4, true)
+                );
+                match escape {
+                    Some(escape) => format!(
+                        "{text} {} {}",
+                        self.keyword("ESCAPE"),
+                        self.operand(escape, 4, true)
+                    ),
+                    None => text,
+                }
+            }
+            _ => unreachable!("not a predicate"),
+        }
+    }
+
+    /// Render an operand of an operator of `precedence`, on its right if `right`,
+    /// parenthesized if it would otherwise bind to its neighbors.
+    fn operand(&self, expr: &Expr, precedence: u8, right: bool) -> String {
+        let inner = expr.precedence();
+        let text = self.expr(expr);
+        if inner < precedence || (right && inner == precedence) {
+            format!("({text})")
+        } else {
+            text
+        }
+    }
+
+    fn function(&self, function: &Function) -> String {
+        let args = if function.wildcard {
+            "*".to_string()
+        } else if function.distinct {
+            format!(
+                "{} {}",
+                self.keyword("DISTINCT"),
+                self.exprs(&function.args)
+            )
+        } else {
+            self.exprs(&function.args)
+        };
+        format!("{}({args})", self.name(&function.name))
+    }
+
+    fn literal(&self, literal: &Literal) -> String {
+        match literal {
+            Literal::Null => self.keyword("NULL"),
+            Literal::Boolean(true) => self.keyword("TRUE"),
+            Literal::Boolean(false) => self.keyword("FALSE"),
+            Literal::Integer(number) | Literal::Float(number) => number.clone(),
+            Literal::String(text) => format!("'{}'", text.replace('\'', "''")),
+            Literal::Blob(digits) => format!("{}'{digits}'", self.keyword("X")),
+        }
+    }
+
+    fn exprs(&self, exprs: &[Expr]) -> String {
+        exprs
+            .iter()
+            .map(|expr| self.expr(expr))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn keyword(&self, keyword: &str) -> String {
+        match self.options.keyword_case {
+            Case::Preserve | Case::Upper => keyword.to_string(),
+            Case::Lower => keyword.to_lowercase(),
+        }
+    }
+
+    fn ident(&self, ident: &Ident) -> String {
+        if ident.quoted || !ident.is_plain() {
+            format!("\"{}\"", ident.value.replace('"', "\"\""))
+        } else {
+            self.options.identifier_case.apply(&ident.value)
+        }
+    }
+
+    fn idents(&self, idents: &[Ident]) -> String {
+        idents
+            .iter()
+            .map(|ident| self.ident(ident))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn name(&self, name: &[Ident]) -> String {
+        name.iter()
+            .map(|ident| self.ident(ident))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Case, FormatOptions, Formatter, Parser};
+
+    const STATEMENTS: &[&str] = &[
+        "SELECT 1",
+        "SELECT DISTINCT a, b AS c, t.*, * FROM t",
+        "SELECT a FROM t1 AS x JOIN t2 ON x.id = t2.id LEFT JOIN t3 USING (id) CROSS JOIN t4, t5",
+        "SELECT count(*), count(DISTINCT a), sum(b) FROM t GROUP BY c HAVING sum(b) > 10",
+        "SELECT a FROM t WHERE a IN (1, 2) AND b NOT IN (SELECT b FROM u) OR NOT EXISTS (SELECT 1)",
+        "SELECT a FROM t ORDER BY a DESC NULLS LAST, b LIMIT 10 OFFSET 5",
+        "SELECT (a + b) * c, a - (b - c), a - b - c, - -a, -(a + 1), 2 * -3",
+        "SELECT CASE WHEN a IS NULL THEN 'none' WHEN a < 0 THEN 'it''s' ELSE x'00ff' END FROM t",
+        "SELECT CASE a WHEN 1 THEN TRUE ELSE FALSE END, CAST(a AS DECIMAL(10, 2)), b::varchar(3)",
+        "SELECT a FROM t WHERE b LIKE 'x!%' ESCAPE '!' AND c NOT BETWEEN 1 AND 2 AND d IS NOT NULL",
+        "SELECT a FROM t UNION ALL SELECT b FROM u INTERSECT SELECT c FROM v",
+        "SELECT a FROM t EXCEPT (SELECT b FROM u UNION SELECT c FROM v) ORDER BY 1",
+        "SELECT \"Mixed Case\", \"select\", \"a\"\"b\" FROM \"my table\" WHERE x = $1 OR y = ?",
+        "SELECT x FROM (SELECT a AS x FROM t) AS s WHERE x = (SELECT max(a) FROM t)",
+        "INSERT INTO t (a, b) VALUES (1, 'one'), (2, NULL)",
+        "INSERT INTO t SELECT * FROM u",
+        "UPDATE t SET a = a + 1, b = DEFAULT_B WHERE c <> 0",
+        "DELETE FROM s.t WHERE a || b = 'ab'",
+        "CREATE TABLE IF NOT EXISTS t (id BIGINT PRIMARY KEY, name TEXT NOT NULL DEFAULT 'x', \
+         score DOUBLE CHECK (score >= 0), parent INTEGER REFERENCES t (id), \
+         CONSTRAINT uq UNIQUE (name), FOREIGN KEY (parent) REFERENCES t)",
+        "CREATE UNIQUE INDEX IF NOT EXISTS ix ON t (a, b)",
+        "DROP TABLE IF EXISTS a, b",
+        "DROP INDEX ix",
+        "BEGIN",
+        "COMMIT",
+        "ROLLBACK",
+        "EXPLAIN ANALYZE SELECT a FROM t",
+        "ANALYZE t",
+        "ANALYZE",
+        "BACKUP TO 'mem://backups'",
+        "RESTORE FROM 'file:///var/backups/it''s'",
+    ];
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_format() {
+        let statement = Parser::parse_statement(
+            "select a, b from t join u on t.id = u.id where a > 1 and b < 2 order by a",
+        )
+        .unwrap();
+        assert_eq!(
+            statement.to_string(),
+            "SELECT a, b FROM t JOIN u ON t.id = u.id WHERE a > 1 AND b < 2 ORDER BY a"
+        );
+        let formatter = Formatter::new(FormatOptions::new().with_line_width(24));
+        assert_eq!(
+            formatter.format(&statement),
+            "SELECT a, b\nFROM\n  t JOIN u ON t.id = u.id\nWHERE a > 1 AND b < 2\nORDER BY a"
+        );
+        let statement =
+            Parser::parse_statement("create table t (id integer primary key, name text)").unwrap();
+        let formatter = Formatter::new(
+            FormatOptions::new()
+                .with_keyword_case(Case::Lower)
+                .with_indent(4)
+                .with_line_width(30),
+        );
+        assert_eq!(
+            formatter.format(&statement),
+            "create table t (\n    id integer primary key,\n    name text\n)"
+        );
+        let formatter = Formatter::new(FormatOptions::new().with_identifier_case(Case::Upper));
+        let statements = Parser::parse("select a from \"t\"; commit").unwrap();
+        assert_eq!(
+            formatter.format_script(&statements),
+            "SELECT A FROM \"t\";\nCOMMIT;\n"
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_format_round_trip() {
+        let formatters = [
+            Formatter::compact(),
+            Formatter::new(FormatOptions::new().with_line_width(1)),
+            Formatter::new(
+                FormatOptions::new()
+                    .with_keyword_case(Case::Lower)
+                    .with_line_width(40),
+            ),
+        ];
+        for source in STATEMENTS {
+            let statement = Parser::parse_statement(source).unwrap();
+            for formatter in &formatters {
+                let formatted = formatter.format(&statement);
+                let reparsed = Parser::parse_statement(&formatted)
+                    .unwrap_or_else(|error| panic!("{formatted}: {error}"));
+                assert_eq!(reparsed, statement, "{formatted}");
+                assert_eq!(formatter.format(&reparsed), formatted);
+            }
+        }
+        // Folding the case of unquoted identifiers changes their spelling but not their meaning
+        let folding = Formatter::new(FormatOptions::new().with_identifier_case(Case::Lower));
+        let compact = Formatter::compact();
+        for source in STATEMENTS {
+            let statement = Parser::parse_statement(source).unwrap();
+            let text = compact.format(&statement);
+            assert!(!text.contains('\n'), "{text}");
+            let folded = folding.format(&statement);
+            let reparsed = Parser::parse_statement(&folded).unwrap();
+            assert_eq!(folding.format(&reparsed), folded);
+        }
+    }
+}
@@ -0,0 +1,509 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{Keyword, LangError, LangResult, Position, Span, Token, TokenKind};
+use nom::{
+    branch::alt,
+    bytes::complete::{is_not, tag, tag_no_case, take_until, take_while},
+    character::complete::{
+        char as nchar, digit0, digit1, hex_digit0, not_line_ending, one_of, satisfy,
+    },
+    combinator::{cut, map, opt, recognize, value},
+    error::{ErrorKind, ParseError},
+    multi::many0,
+    sequence::{pair, preceded, terminated, tuple},
+    IResult,
+};
+use std::borrow::Cow;
+
+/// SQL Lexer
+///
+/// Splits SQL source text into [`Token`]s, skipping whitespace but keeping comments, each with
+/// the [`Span`] of source it was read from. Keywords are matched regardless of case, while
+/// unquoted identifiers are kept as written for the parser to fold.
+///
+/// The lexer stops at the first text that isn't a token, yielding an error with its
+/// [`Position`].
+///
+/// ```rust
+/// use minql_lang::{Keyword, Lexer, TokenKind};
+///
+/// let kinds = Lexer::new("select \"Name\" from t -- all of them")
+///     .map(|token| token.map(|token| token.kind))
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+/// assert_eq!(kinds, vec![
+///     TokenKind::Keyword(Keyword::Select),
+///     TokenKind::QuotedIdentifier("Name".into()),
+///     TokenKind::Keyword(Keyword::From),
+///     TokenKind::Identifier("t"),
+///     TokenKind::Comment(" all of them"),
+/// ]);
+///
+/// let error = Lexer::tokenize("SELECT 'open\nFROM t").unwrap_err();
+/// assert_eq!(error.position().to_string(), "1:8");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Lexer<'str> {
+    source: &'str str,
+    offset: usize,
+    failed: bool,
+}
+
+impl<'str> Lexer<'str> {
+    /// Create a lexer over source text.
+    #[must_use]
+    pub fn new(source: &'str str) -> Lexer<'str> {
+        Lexer {
+            source,
+            offset: 0,
+            failed: false,
+        }
+    }
+
+    /// Split source text into tokens, including comments.
+    #[tracing::instrument(level = "trace")]
+    pub fn tokenize(source: &'str str) -> LangResult<Vec<Token<'str>>> {
+        Lexer::new(source).collect()
+    }
+
+    /// Source text being split.
+    #[must_use]
+    pub fn source(&self) -> &'str str {
+        self.source
+    }
+
+    /// Read the token at the current offset, which follows any whitespace.
+    fn read(&self) -> LangResult<(TokenKind<'str>, usize)> {
+        let input = &self.source[self.offset..];
+        match token::<(&str, ErrorKind)>(input) {
+            Ok((rest, kind)) => {
+                let kind = match kind {
+                    TokenKind::QuotedIdentifier(name) if name.is_empty() => {
+                        return Err(LangError::EmptyIdentifier(self.position()))
+                    }
+                    TokenKind::Blob(digits) if digits.len() % 2 != 0 => {
+                        return Err(LangError::InvalidBlob(self.position()))
+                    }
+                    TokenKind::Integer(_) | TokenKind::Float(_)
+                        if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') =>
+                    {
+                        return Err(LangError::InvalidNumber(self.position()))
+                    }
+                    kind => kind,
+                };
+                Ok((kind, input.len() - rest.len()))
+            }
+            Err(_) => Err(self.unrecognized(input)),
+        }
+    }
+
+    /// Explain why no token could be read from the input.
+    fn unrecognized(&self, input: &str) -> LangError {
+        let position = self.position();
+        let mut chars = input.chars();
+        match (chars.next(), chars.next()) {
+            (Some('\''), _) => LangError::UnterminatedString(position),
+            (Some('x' | 'X'), Some('\'')) => LangError::InvalidBlob(position),
+            (Some('"'), _) => LangError::UnterminatedIdentifier(position),
+            (Some('/'), Some('*')) => LangError::UnterminatedComment(position),
+            (Some(character), _) => LangError::UnexpectedCharacter {
+                character,
+                position,
+            },
+            (None, _) => LangError::UnexpectedCharacter {
+                character: '\0',
+                position,
+            },
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position::locate(self.source, self.offset)
+    }
+}
+
+impl<'str> Iterator for Lexer<'str> {
+    type Item = LangResult<Token<'str>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let rest = &self.source[self.offset..];
+        self.offset += rest.len() - rest.trim_start().len();
+        if self.offset == self.source.len() {
+            return None;
+        }
+        match self.read() {
+            Ok((kind, len)) => {
+                let span = Span::new(self.offset, self.offset + len);
+                self.offset += len;
+                Some(Ok(Token::new(kind, span)))
+            }
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// ```abnf
+/// token = comment / string / blob / quoted-identifier / number / parameter / word / operator
+/// ```
+#[tracing::instrument(level = "trace")]
+fn token<'str, E>(input: &'str str) -> IResult<&'str str, TokenKind<'str>, E>
+where
+    E: ParseError<&'str str>,
+{
+    alt((
+        map(alt((line_comment, block_comment)), TokenKind::Comment),
+        map(string, TokenKind::String),
+        map(blob, TokenKind::Blob),
+        map(quoted_identifier, TokenKind::QuotedIdentifier),
+        number,
+        map(parameter, TokenKind::Parameter),
+        map(word, |word| {
+            Keyword::lookup(word).map_or(TokenKind::Identifier(word), TokenKind::Keyword)
+        }),
+        operator,
+    ))(input)
+}
+
+/// ```abnf
+/// line-comment = "--" *( %x00-09 / %x0B-0C / %x0E-10FFFF )
+/// ```
+#[tracing::instrument(level = "trace")]
+fn line_comment<'str, E>(input: &'str str) -> IResult<&'str str, &'str str, E>
+where
+    E: ParseError<&'str str>,
+{
+    preceded(tag("--"), not_line_ending)(input)
+}
+
+/// ```abnf
+/// block-comment = "/*" *CHAR "*/"
+/// ```
+/// * Block comments don't nest, ending at the first `*/`
+#[tracing::instrument(level = "trace")]
+fn block_comment<'str, E>(input: &'str str) -> IResult<&'str str, &'str str, E>
+where
+    E: ParseError<&'str str>,
+{
+    preceded(tag("/*"), cut(terminated(take_until("*/"), tag("*/"))))(input)
+}
+
+/// ```abnf
+/// string = "'" *( %x00-26 / %x28-10FFFF / "''" ) "'"
+/// ```
+#[tracing::instrument(level = "trace")]
+fn string<'str, E>(input: &'str str) -> IResult<&'str str, Cow<'str, str>, E>
+where
+    E: ParseError<&'str str>,
+{
+    map(quoted('\''), |text| unescape(text, "''", "'"))(input)
+}
+
+/// ```abnf
+/// blob = ( "X" / "x" ) "'" *HEXDIG "'"
+/// ```
+#[tracing::instrument(level = "trace")]
+fn blob<'str, E>(input: &'str str) -> IResult<&'str str, &'str str, E>
+where
+    E: ParseError<&'str str>,
+{
+    preceded(
+        pair(tag_no_case("x"), nchar('\'')),
+        cut(terminated(hex_digit0, nchar('\''))),
+    )(input)
+}
+
+/// ```abnf
+/// quoted-identifier = DQUOTE *( %x00-21 / %x23-10FFFF / 2DQUOTE ) DQUOTE
+/// ```
+#[tracing::instrument(level = "trace")]
+fn quoted_identifier<'str, E>(input: &'str str) -> IResult<&'str str, Cow<'str, str>, E>
+where
+    E: ParseError<&'str str>,
+{
+    map(quoted('"'), |text| unescape(text, "\"\"", "\""))(input)
+}
+
+/// Text between two `quote` characters, where a doubled quote stands for one.
+///
+/// Once the opening quote is read no other token is tried, as with the other delimited tokens.
+fn quoted<'str, E>(quote: char) -> impl FnMut(&'str str) -> IResult<&'str str, &'str str, E>
+where
+    E: ParseError<&'str str>,
+{
+    let escaped = if quote == '\'' { "''" } else { "\"\"" };
+    let others = if quote == '\'' { "'" } else { "\"" };
+    preceded(
+        nchar(quote),
+        cut(terminated(
+            recognize(many0(alt((is_not(others), tag(escaped))))),
+            nchar(quote),
+        )),
+    )
+}
+
+/// ```abnf
+/// number   = ( 1*DIGIT [ "." *DIGIT ] / "." 1*DIGIT ) [ exponent ]
+/// exponent = ( "E" / "e" ) [ "+" / "-" ] 1*DIGIT
+/// ```
+#[tracing::instrument(level = "trace")]
+fn number<'str, E>(input: &'str str) -> IResult<&'str str, TokenKind<'str>, E>
+where
+    E: ParseError<&'str str>,
+{
+    let (rest, (mantissa, exponent)) = pair(
+        alt((
+            recognize(pair(digit1, opt(pair(nchar('.'), digit0)))),
+            recognize(pair(nchar('.'), digit1)),
+        )),
+        opt(recognize(tuple((one_of("eE"), opt(one_of("+-")), digit1)))),
+    )(input)?;
+    let text = &input[..input.len() - rest.len()];
+    let kind = if exponent.is_none() && !mantissa.contains('.') {
+        TokenKind::Integer(text)
+    } else {
+        TokenKind::Float(text)
+    };
+    Ok((rest, kind))
+}
+
+/// ```abnf
+/// parameter = "?" / "$" 1*DIGIT
+/// ```
+#[tracing::instrument(level = "trace")]
+fn parameter<'str, E>(input: &'str str) -> IResult<&'str str, Option<&'str str>, E>
+where
+    E: ParseError<&'str str>,
+{
+    alt((
+        value(None, nchar('?')),
+        map(preceded(nchar('$'), digit1), Some),
+    ))(input)
+}
+
+/// ```abnf
+/// word = ( ALPHA / "_" ) *( ALPHA / DIGIT / "_" / "$" )
+/// ```
+/// * Letters and digits of any script are accepted
+#[tracing::instrument(level = "trace")]
+fn word<'str, E>(input: &'str str) -> IResult<&'str str, &'str str, E>
+where
+    E: ParseError<&'str str>,
+{
+    recognize(pair(
+        satisfy(|c| c.is_alphabetic() || c == '_'),
+        take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '$'),
+    ))(input)
+}
+
+/// ```abnf
+/// operator = "<>" / "!=" / "<=" / ">=" / "||" / "::" / "," / ";" / "." / "(" / ")"
+///          / "+" / "-" / "*" / "/" / "%" / "=" / "<" / ">"
+/// ```
+#[tracing::instrument(level = "trace")]
+fn operator<'str, E>(input: &'str str) -> IResult<&'str str, TokenKind<'str>, E>
+where
+    E: ParseError<&'str str>,
+{
+    alt((
+        alt((
+            value(TokenKind::NotEq, alt((tag("<>"), tag("!=")))),
+            value(TokenKind::LtEq, tag("<=")),
+            value(TokenKind::GtEq, tag(">=")),
+            value(TokenKind::Concat, tag("||")),
+            value(TokenKind::DoubleColon, tag("::")),
+        )),
+        alt((
+            value(TokenKind::Comma, nchar(',')),
+            value(TokenKind::Semicolon, nchar(';')),
+            value(TokenKind::Period, nchar('.')),
+            value(TokenKind::LeftParen, nchar('(')),
+            value(TokenKind::RightParen, nchar(')')),
+            value(TokenKind::Plus, nchar('+')),
+            value(TokenKind::Minus, nchar('-')),
+            value(TokenKind::Star, nchar('*')),
+            value(TokenKind::Slash, nchar('/')),
+            value(TokenKind::Percent, nchar('%')),
+            value(TokenKind::Eq, nchar('=')),
+            value(TokenKind::Lt, nchar('<')),
+            value(TokenKind::Gt, nchar('>')),
+        )),
+    ))(input)
+}
+
+/// Replace doubled quotes, borrowing the text if it has none.
+fn unescape<'str>(text: &'str str, escaped: &str, quote: &str) -> Cow<'str, str> {
+    if text.contains(escaped) {
+        Cow::Owned(text.replace(escaped, quote))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Keyword, LangError, Lexer, Position, Span, TokenKind};
+
+    fn kinds(source: &str) -> Vec<TokenKind<'_>> {
+        Lexer::tokenize(source)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect()
+    }
+
+    fn error_at(source: &str) -> (LangError, usize, usize) {
+        let error = Lexer::tokenize(source).unwrap_err();
+        let Position { line, column, .. } = error.position();
+        (error, line, column)
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_lexer() {
+        assert_eq!(
+            kinds("SELECT a.b, \"Mixed \"\"Case\"\"\" FROM t1 WHERE x >= 1.5e-3;"),
+            vec![
+                TokenKind::Keyword(Keyword::Select),
+                TokenKind::Identifier("a"),
+                TokenKind::Period,
+                TokenKind::Identifier("b"),
+                TokenKind::Comma,
+                TokenKind::QuotedIdentifier("Mixed \"Case\"".into()),
+                TokenKind::Keyword(Keyword::From),
+                TokenKind::Identifier("t1"),
+                TokenKind::Keyword(Keyword::Where),
+                TokenKind::Identifier("x"),
+                TokenKind::GtEq,
+                TokenKind::Float("1.5e-3"),
+                TokenKind::Semicolon,
+            ]
+        );
+        assert_eq!(
+            kinds("'it''s' '' x'00FF' X'' 42 4. .5 ? $12 _tmp$1 größe"),
+            vec![
+                TokenKind::String("it's".into()),
+                TokenKind::String("".into()),
+                TokenKind::Blob("00FF"),
+                TokenKind::Blob(""),
+                TokenKind::Integer("42"),
+                TokenKind::Float("4."),
+                TokenKind::Float(".5"),
+                TokenKind::Parameter(None),
+                TokenKind::Parameter(Some("12")),
+                TokenKind::Identifier("_tmp$1"),
+                TokenKind::Identifier("größe"),
+            ]
+        );
+        assert_eq!(
+            kinds("a<>b!=c<=d<e||f::g%h/i*j-k+l=m>n(o)"),
+            vec![
+                TokenKind::Identifier("a"),
+                TokenKind::NotEq,
+                TokenKind::Identifier("b"),
+                TokenKind::NotEq,
+                TokenKind::Identifier("c"),
+                TokenKind::LtEq,
+                TokenKind::Identifier("d"),
+                TokenKind::Lt,
+                TokenKind::Identifier("e"),
+                TokenKind::Concat,
+                TokenKind::Identifier("f"),
+                TokenKind::DoubleColon,
+                TokenKind::Identifier("g"),
+                TokenKind::Percent,
+                TokenKind::Identifier("h"),
+                TokenKind::Slash,
+                TokenKind::Identifier("i"),
+                TokenKind::Star,
+                TokenKind::Identifier("j"),
+                TokenKind::Minus,
+                TokenKind::Identifier("k"),
+                TokenKind::Plus,
+                TokenKind::Identifier("l"),
+                TokenKind::Eq,
+                TokenKind::Identifier("m"),
+                TokenKind::Gt,
+                TokenKind::Identifier("n"),
+                TokenKind::LeftParen,
+                TokenKind::Identifier("o"),
+                TokenKind::RightParen,
+            ]
+        );
+
+        // Comments are kept, and spans cover the source of each token
+        let source = "-- first\n  /* second\n */ 1-2";
+        let tokens = Lexer::tokenize(source).unwrap();
+        assert_eq!(
+            tokens.iter().map(|token| &token.kind).collect::<Vec<_>>(),
+            vec![
+                &TokenKind::Comment(" first"),
+                &TokenKind::Comment(" second\n "),
+                &TokenKind::Integer("1"),
+                &TokenKind::Minus,
+                &TokenKind::Integer("2"),
+            ]
+        );
+        assert_eq!(tokens[0].span, Span::new(0, 8));
+        assert_eq!(tokens[1].span.text(source), "/* second\n */");
+        assert_eq!(tokens[2].span.position(source).to_string(), "3:5");
+        assert!(tokens[1].is_comment());
+        assert_eq!(Lexer::tokenize("  \n\t").unwrap(), vec![]);
+
+        assert!(matches!(
+            error_at("SELECT\n  'never closed"),
+            (LangError::UnterminatedString(_), 2, 3)
+        ));
+        assert!(matches!(
+            error_at("SELECT \"open"),
+            (LangError::UnterminatedIdentifier(_), 1, 8)
+        ));
+        assert!(matches!(
+            error_at("SELECT \"\""),
+            (LangError::EmptyIdentifier(_), 1, 8)
+        ));
+        assert!(matches!(
+            error_at("1 /* open"),
+            (LangError::UnterminatedComment(_), 1, 3)
+        ));
+        assert!(matches!(
+            error_at("SELECT 12abc"),
+            (LangError::InvalidNumber(_), 1, 8)
+        ));
+        assert!(matches!(
+            error_at("x'ABC'"),
+            (LangError::InvalidBlob(_), 1, 1)
+        ));
+        assert!(matches!(
+            error_at("x'XY'"),
+            (LangError::InvalidBlob(_), 1, 1)
+        ));
+        assert!(matches!(
+            error_at("é = #"),
+            (LangError::UnexpectedCharacter { character: '#', .. }, 1, 5)
+        ));
+        let mut lexer = Lexer::new("a # b");
+        assert!(lexer.next().unwrap().is_ok());
+        assert!(lexer.next().unwrap().is_err());
+        assert!(lexer.next().is_none());
+    }
+}
@@ -0,0 +1,60 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! SQL Language Library
+//!
+//! Reads SQL source text into [`Token`]s with the [`Lexer`], each carrying the [`Span`] of
+//! source it came from, so later errors can point at a [`Position`] in the original text. The
+//! [`Parser`] builds the [`ast`] of statements from the tokens, and the [`Formatter`] renders it
+//! back to canonical SQL text.
+//!
+//! ```rust
+//! use minql_lang::{Keyword, Lexer, TokenKind};
+//!
+//! let tokens = Lexer::tokenize("SELECT 1").unwrap();
+//! assert_eq!(tokens[0].kind, TokenKind::Keyword(Keyword::Select));
+//! assert_eq!(tokens[1].kind, TokenKind::Integer("1"));
+//! ```
+
+#![forbid(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+pub use self::format::{Case, FormatOptions, Formatter};
+pub use self::lexer::Lexer;
+pub use self::parser::Parser;
+pub use self::result::{LangError, LangResult};
+pub use self::span::{Position, Span};
+pub use self::token::{Keyword, Token, TokenKind};
+
+pub mod ast;
+mod format;
+mod lexer;
+mod parser;
+mod result;
+mod span;
+mod token;
@@ -0,0 +1,1168 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::ast::{
+    Assignment, BinaryOperator, ColumnDef, ColumnOption, DataType, Expr, Function, Ident, Join,
+    JoinConstraint, JoinKind, Literal, OrderByExpr, Query, Select, SelectItem, SetExpr,
+    SetOperator, Statement, TableConstraint, TableFactor, TableWithJoins, UnaryOperator,
+};
+use crate::{Keyword, LangError, LangResult, Lexer, Position, Token, TokenKind};
+
+/// SQL Parser
+///
+/// Parses SQL source text into [`Statement`]s or [`Expr`]essions by recursive descent over the
+/// tokens of the [`Lexer`], ignoring comments. Errors name what the grammar expected and the
+/// [`Position`] of the token found instead.
+///
+/// Operators bind, from loosest to tightest, as `OR`, `AND`, `NOT`, comparisons along with
+/// `IS`, `IN`, `LIKE` and `BETWEEN`, `||`, `+` and `-`, `*`, `/` and `%`, prefix `-` and `+`,
+/// and finally `::` casts. Operators of equal precedence associate to the left.
+///
+/// ```rust
+/// use minql_lang::ast::{Expr, Statement};
+/// use minql_lang::Parser;
+///
+/// let statements = Parser::parse("BEGIN; SELECT a + 1 FROM t WHERE b IS NULL; COMMIT").unwrap();
+/// assert_eq!(statements.len(), 3);
+/// assert_eq!(statements[1].to_string(), "SELECT a + 1 FROM t WHERE b IS NULL");
+///
+/// let expr = Parser::parse_expr("1 + 2 * 3").unwrap();
+/// assert!(matches!(expr, Expr::Binary { .. }));
+///
+/// let error = Parser::parse_statement("SELECT FROM t").unwrap_err();
+/// assert_eq!(error.position().to_string(), "1:8");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Parser<'str> {
+    source: &'str str,
+    tokens: Vec<Token<'str>>,
+    index: usize,
+}
+
+impl<'str> Parser<'str> {
+    /// Create a parser over the tokens of source text.
+    pub fn new(source: &'str str) -> LangResult<Parser<'str>> {
+        let mut tokens = Lexer::tokenize(source)?;
+        tokens.retain(|token| !token.is_comment());
+        Ok(Parser {
+            source,
+            tokens,
+            index: 0,
+        })
+    }
+
+    /// Parse statements separated by semicolons.
+    #[tracing::instrument(level = "trace")]
+    pub fn parse(source: &'str str) -> LangResult<Vec<Statement>> {
+        let mut parser = Parser::new(source)?;
+        let mut statements = Vec::new();
+        loop {
+            while parser.consume(&TokenKind::Semicolon) {}
+            if parser.peek().is_none() {
+                return Ok(statements);
+            }
+            statements.push(parser.statement()?);
+            if parser.peek().is_some() {
+                parser.expect(&TokenKind::Semicolon, "`;`")?;
+            }
+        }
+    }
+
+    /// Parse a single statement, optionally followed by a semicolon.
+    #[tracing::instrument(level = "trace")]
+    pub fn parse_statement(source: &'str str) -> LangResult<Statement> {
+        let mut parser = Parser::new(source)?;
+        let statement = parser.statement()?;
+        parser.consume(&TokenKind::Semicolon);
+        parser.finish()?;
+        Ok(statement)
+    }
+
+    /// Parse a single expression.
+    #[tracing::instrument(level = "trace")]
+    pub fn parse_expr(source: &'str str) -> LangResult<Expr> {
+        let mut parser = Parser::new(source)?;
+        let expr = parser.expr()?;
+        parser.finish()?;
+        Ok(expr)
+    }
+
+    /// Parse a single data type, as written by its `Display`.
+    #[tracing::instrument(level = "trace")]
+    pub fn parse_data_type(source: &'str str) -> LangResult<DataType> {
+        let mut parser = Parser::new(source)?;
+        let data_type = parser.data_type()?;
+        parser.finish()?;
+        Ok(data_type)
+    }
+
+    /// Parse a statement at the current token.
+    fn statement(&mut self) -> LangResult<Statement> {
+        match self.peek() {
+            Some(TokenKind::Keyword(Keyword::Select | Keyword::Values) | TokenKind::LeftParen) => {
+                Ok(Statement::Query(Box::new(self.query()?)))
+            }
+            Some(TokenKind::Keyword(Keyword::Insert)) => self.insert(),
+            Some(TokenKind::Keyword(Keyword::Update)) => self.update(),
+            Some(TokenKind::Keyword(Keyword::Delete)) => self.delete(),
+            Some(TokenKind::Keyword(Keyword::Create)) => self.create(),
+            Some(TokenKind::Keyword(Keyword::Drop)) => self.drop(),
+            Some(TokenKind::Keyword(
+                keyword @ (Keyword::Begin | Keyword::Commit | Keyword::Rollback),
+            )) => {
+                let statement = match keyword {
+                    Keyword::Begin => Statement::Begin,
+                    Keyword::Commit => Statement::Commit,
+                    _ => Statement::Rollback,
+                };
+                self.index += 1;
+                self.keyword(Keyword::Transaction);
+                Ok(statement)
+            }
+            Some(TokenKind::Keyword(Keyword::Explain)) => {
+                self.index += 1;
+                let analyze = self.keyword(Keyword::Analyze);
+                let statement = Box::new(self.statement()?);
+                Ok(Statement::Explain { analyze, statement })
+            }
+            Some(TokenKind::Keyword(Keyword::Analyze)) => {
+                self.index += 1;
+                let table = match self.peek() {
+                    None | Some(TokenKind::Semicolon) => None,
+                    Some(_) => Some(self.object_name()?),
+                };
+                Ok(Statement::Analyze { table })
+            }
+            Some(TokenKind::Keyword(Keyword::Backup)) => {
+                self.index += 1;
+                self.expect_keyword(Keyword::To)?;
+                let uri = self.string()?;
+                Ok(Statement::Backup { uri })
+            }
+            Some(TokenKind::Keyword(Keyword::Restore)) => {
+                self.index += 1;
+                self.expect_keyword(Keyword::From)?;
+                let uri = self.string()?;
+                Ok(Statement::Restore { uri })
+            }
+            _ => Err(self.error("statement")),
+        }
+    }
+
+    fn insert(&mut self) -> LangResult<Statement> {
+        self.expect_keyword(Keyword::Insert)?;
+        self.expect_keyword(Keyword::Into)?;
+        let table = self.object_name()?;
+        let columns = if self.peek() == Some(&TokenKind::LeftParen)
+            && !matches!(
+                self.peek_nth(1),
+                Some(TokenKind::Keyword(Keyword::Select | Keyword::Values))
+            ) {
+            self.ident_list()?
+        } else {
+            Vec::new()
+        };
+        let source = Box::new(self.query()?);
+        Ok(Statement::Insert {
+            table,
+            columns,
+            source,
+        })
+    }
+
+    fn update(&mut self) -> LangResult<Statement> {
+        self.expect_keyword(Keyword::Update)?;
+        let table = self.object_name()?;
+        self.expect_keyword(Keyword::Set)?;
+        let assignments = self.list(|parser| {
+            let column = parser.ident()?;
+            parser.expect(&TokenKind::Eq, "`=`")?;
+            let value = parser.expr()?;
+            Ok(Assignment { column, value })
+        })?;
+        let selection = self.selection()?;
+        Ok(Statement::Update {
+            table,
+            assignments,
+            selection,
+        })
+    }
+
+    fn delete(&mut self) -> LangResult<Statement> {
+        self.expect_keyword(Keyword::Delete)?;
+        self.expect_keyword(Keyword::From)?;
+        let table = self.object_name()?;
+        let selection = self.selection()?;
+        Ok(Statement::Delete { table, selection })
+    }
+
+    fn create(&mut self) -> LangResult<Statement> {
+        self.expect_keyword(Keyword::Create)?;
+        let unique = self.keyword(Keyword::Unique);
+        if unique || self.keyword(Keyword::Index) {
+            if unique {
+                self.expect_keyword(Keyword::Index)?;
+            }
+            let if_not_exists = self.keywords(&[Keyword::If, Keyword::Not, Keyword::Exists]);
+            let name = self.ident()?;
+            self.expect_keyword(Keyword::On)?;
+            let table = self.object_name()?;
+            let columns = self.ident_list()?;
+            return Ok(Statement::CreateIndex {
+                name,
+                table,
+                columns,
+                unique,
+                if_not_exists,
+            });
+        }
+        self.expect_keyword(Keyword::Table)?;
+        let if_not_exists = self.keywords(&[Keyword::If, Keyword::Not, Keyword::Exists]);
+        let name = self.object_name()?;
+        self.expect(&TokenKind::LeftParen, "`(`")?;
+        let mut columns = Vec::new();
+        let mut constraints = Vec::new();
+        loop {
+            if let Some(constraint) = self.table_constraint()? {
+                constraints.push(constraint);
+            } else {
+                columns.push(self.column_def()?);
+            }
+            if !self.consume(&TokenKind::Comma) {
+                break;
+            }
+        }
+        self.expect(&TokenKind::RightParen, "`,` or `)`")?;
+        Ok(Statement::CreateTable {
+            name,
+            if_not_exists,
+            columns,
+            constraints,
+        })
+    }
+
+    fn column_def(&mut self) -> LangResult<ColumnDef> {
+        let name = self.ident()?;
+        let data_type = self.data_type()?;
+        let mut options = Vec::new();
+        loop {
+            let option = if self.keywords(&[Keyword::Not, Keyword::Null]) {
+                ColumnOption::NotNull
+            } else if self.keyword(Keyword::Null) {
+                ColumnOption::Null
+            } else if self.keywords(&[Keyword::Primary, Keyword::Key]) {
+                ColumnOption::PrimaryKey
+            } else if self.keyword(Keyword::Unique) {
+                ColumnOption::Unique
+            } else if self.keyword(Keyword::Default) {
+                ColumnOption::Default(self.expr()?)
+            } else if self.keyword(Keyword::Check) {
+                ColumnOption::Check(self.parenthesized_expr()?)
+            } else if self.keyword(Keyword::References) {
+                let (table, columns) = self.references()?;
+                ColumnOption::References { table, columns }
+            } else {
+                return Ok(ColumnDef {
+                    name,
+                    data_type,
+                    options,
+                });
+            };
+            options.push(option);
+        }
+    }
+
+    /// Parse a table constraint, if one starts at the current token.
+    fn table_constraint(&mut self) -> LangResult<Option<TableConstraint>> {
+        let name = if self.keyword(Keyword::Constraint) {
+            Some(self.ident()?)
+        } else {
+            None
+        };
+        let constraint = if self.keywords(&[Keyword::Primary, Keyword::Key]) {
+            TableConstraint::PrimaryKey {
+                name,
+                columns: self.ident_list()?,
+            }
+        } else if self.keyword(Keyword::Unique) {
+            TableConstraint::Unique {
+                name,
+                columns: self.ident_list()?,
+            }
+        } else if self.keywords(&[Keyword::Foreign, Keyword::Key]) {
+            let columns = self.ident_list()?;
+            self.expect_keyword(Keyword::References)?;
+            let (foreign_table, referred_columns) = self.references()?;
+            TableConstraint::ForeignKey {
+                name,
+                columns,
+                foreign_table,
+                referred_columns,
+            }
+        } else if self.keyword(Keyword::Check) {
+            TableConstraint::Check {
+                name,
+                expr: self.parenthesized_expr()?,
+            }
+        } else if name.is_some() {
+            return Err(self.error("PRIMARY KEY, UNIQUE, FOREIGN KEY or CHECK"));
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(constraint))
+    }
+
+    /// Parse the table and optional columns following `REFERENCES`.
+    fn references(&mut self) -> LangResult<(Vec<Ident>, Vec<Ident>)> {
+        let table = self.object_name()?;
+        let columns = if self.peek() == Some(&TokenKind::LeftParen) {
+            self.ident_list()?
+        } else {
+            Vec::new()
+        };
+        Ok((table, columns))
+    }
+
+    fn drop(&mut self) -> LangResult<Statement> {
+        self.expect_keyword(Keyword::Drop)?;
+        let index = if self.keyword(Keyword::Index) {
+            true
+        } else {
+            self.expect_keyword(Keyword::Table)?;
+            false
+        };
+        let if_exists = self.keywords(&[Keyword::If, Keyword::Exists]);
+        let names = self.list(Parser::object_name)?;
+        Ok(if index {
+            Statement::DropIndex { names, if_exists }
+        } else {
+            Statement::DropTable { names, if_exists }
+        })
+    }
+
+    /// Parse a query with optional ordering and limits.
+    fn query(&mut self) -> LangResult<Query> {
+        let body = self.set_expr(0)?;
+        let order_by = if self.keywords(&[Keyword::Order, Keyword::By]) {
+            self.list(Parser::order_by_expr)?
+        } else {
+            Vec::new()
+        };
+        let limit = if self.keyword(Keyword::Limit) {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let offset = if self.keyword(Keyword::Offset) {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        Ok(Query {
+            body,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    /// Parse set operations binding tighter than `precedence`.
+    fn set_expr(&mut self, precedence: u8) -> LangResult<SetExpr> {
+        let mut left = match self.peek() {
+            Some(TokenKind::Keyword(Keyword::Select)) => SetExpr::Select(Box::new(self.select()?)),
+            Some(TokenKind::Keyword(Keyword::Values)) => {
+                self.index += 1;
+                SetExpr::Values(self.list(|parser| {
+                    parser.expect(&TokenKind::LeftParen, "`(`")?;
+                    let row = parser.list(Parser::expr)?;
+                    parser.expect(&TokenKind::RightParen, "`,` or `)`")?;
+                    Ok(row)
+                })?)
+            }
+            Some(TokenKind::LeftParen) => {
+                self.index += 1;
+                let query = self.query()?;
+                self.expect(&TokenKind::RightParen, "`)`")?;
+                SetExpr::Query(Box::new(query))
+            }
+            _ => return Err(self.error("SELECT, VALUES or `(`")),
+        };
+        loop {
+            let op = match self.peek() {
+                Some(TokenKind::Keyword(Keyword::Union)) => SetOperator::Union,
+                Some(TokenKind::Keyword(Keyword::Except)) => SetOperator::Except,
+                Some(TokenKind::Keyword(Keyword::Intersect)) => SetOperator::Intersect,
+                _ => return Ok(left),
+            };
+            if op.precedence() <= precedence {
+                return Ok(left);
+            }
+            self.index += 1;
+            let all = self.keyword(Keyword::All);
+            if !all {
+                self.keyword(Keyword::Distinct);
+            }
+            let right = self.set_expr(op.precedence())?;
+            left = SetExpr::SetOperation {
+                op,
+                all,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+    }
+
+    fn select(&mut self) -> LangResult<Select> {
+        self.expect_keyword(Keyword::Select)?;
+        let distinct = self.keyword(Keyword::Distinct);
+        if !distinct {
+            self.keyword(Keyword::All);
+        }
+        let projection = self.list(Parser::select_item)?;
+        let from = if self.keyword(Keyword::From) {
+            self.list(Parser::table_with_joins)?
+        } else {
+            Vec::new()
+        };
+        let selection = self.selection()?;
+        let group_by = if self.keywords(&[Keyword::Group, Keyword::By]) {
+            self.list(Parser::expr)?
+        } else {
+            Vec::new()
+        };
+        let having = if self.keyword(Keyword::Having) {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        Ok(Select {
+            distinct,
+            projection,
+            from,
+            selection,
+            group_by,
+            having,
+        })
+    }
+
+    fn select_item(&mut self) -> LangResult<SelectItem> {
+        if self.consume(&TokenKind::Star) {
+            return Ok(SelectItem::Wildcard(Vec::new()));
+        }
+        // A qualified wildcard is a name followed by `.*`
+        let mut ahead = 0;
+        while self.is_ident_at(ahead) && self.peek_nth(ahead + 1) == Some(&TokenKind::Period) {
+            ahead += 2;
+            if self.peek_nth(ahead) == Some(&TokenKind::Star) {
+                let mut name = Vec::new();
+                while self.peek() != Some(&TokenKind::Star) {
+                    name.push(self.ident()?);
+                    self.index += 1;
+                }
+                self.index += 1;
+                return Ok(SelectItem::Wildcard(name));
+            }
+        }
+        let expr = self.expr()?;
+        let alias = self.alias()?;
+        Ok(SelectItem::Expr { expr, alias })
+    }
+
+    /// Parse an alias given by `AS` or by an identifier alone.
+    fn alias(&mut self) -> LangResult<Option<Ident>> {
+        if self.keyword(Keyword::As) {
+            return Ok(Some(self.ident()?));
+        }
+        match self.peek() {
+            Some(TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_)) => {
+                Ok(Some(self.ident()?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn table_with_joins(&mut self) -> LangResult<TableWithJoins> {
+        let relation = self.table_factor()?;
+        let mut joins = Vec::new();
+        loop {
+            let kind =
+                if self.keyword(Keyword::Join) || self.keywords(&[Keyword::Inner, Keyword::Join]) {
+                    JoinKind::Inner
+                } else if self.keywords(&[Keyword::Cross, Keyword::Join]) {
+                    JoinKind::Cross
+                } else if let Some(kind) = self.outer_join()? {
+                    kind
+                } else {
+                    return Ok(TableWithJoins { relation, joins });
+                };
+            let relation = self.table_factor()?;
+            let constraint = if self.keyword(Keyword::On) {
+                JoinConstraint::On(self.expr()?)
+            } else if self.keyword(Keyword::Using) {
+                JoinConstraint::Using(self.ident_list()?)
+            } else {
+                JoinConstraint::None
+            };
+            joins.push(Join {
+                relation,
+                kind,
+                constraint,
+            });
+        }
+    }
+
+    /// Parse `LEFT`, `RIGHT` or `FULL`, an optional `OUTER`, and `JOIN`.
+    fn outer_join(&mut self) -> LangResult<Option<JoinKind>> {
+        let kind = match self.peek() {
+            Some(TokenKind::Keyword(Keyword::Left)) => JoinKind::Left,
+            Some(TokenKind::Keyword(Keyword::Right)) => JoinKind::Right,
+            Some(TokenKind::Keyword(Keyword::Full)) => JoinKind::Full,
+            _ => return Ok(None),
+        };
+        self.index += 1;
+        self.keyword(Keyword::Outer);
+        self.expect_keyword(Keyword::Join)?;
+        Ok(Some(kind))
+    }
+
+    fn table_factor(&mut self) -> LangResult<TableFactor> {
+        if self.consume(&TokenKind::LeftParen) {
+            let subquery = Box::new(self.query()?);
+            self.expect(&TokenKind::RightParen, "`)`")?;
+            let alias = self.alias()?;
+            return Ok(TableFactor::Derived { subquery, alias });
+        }
+        let name = self.object_name()?;
+        let alias = self.alias()?;
+        Ok(TableFactor::Table { name, alias })
+    }
+
+    fn order_by_expr(&mut self) -> LangResult<OrderByExpr> {
+        let expr = self.expr()?;
+        let asc = if self.keyword(Keyword::Asc) {
+            Some(true)
+        } else if self.keyword(Keyword::Desc) {
+            Some(false)
+        } else {
+            None
+        };
+        let nulls_first = if self.keywords(&[Keyword::Nulls, Keyword::First]) {
+            Some(true)
+        } else if self.keywords(&[Keyword::Nulls, Keyword::Last]) {
+            Some(false)
+        } else {
+            None
+        };
+        Ok(OrderByExpr {
+            expr,
+            asc,
+            nulls_first,
+        })
+    }
+
+    /// Parse an optional `WHERE` condition.
+    fn selection(&mut self) -> LangResult<Option<Expr>> {
+        if self.keyword(Keyword::Where) {
+            Ok(Some(self.expr()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parse an expression.
+    fn expr(&mut self) -> LangResult<Expr> {
+        self.subexpr(0)
+    }
+
+    /// Parse an expression of operators binding tighter than `precedence`.
+    fn subexpr(&mut self, precedence: u8) -> LangResult<Expr> {
+        let mut expr = self.prefix()?;
+        loop {
+            let next = self.infix_precedence();
+            if next <= precedence {
+                return Ok(expr);
+            }
+            expr = self.infix(expr, next)?;
+        }
+    }
+
+    /// Precedence of the infix operator at the current token, or 0 if there is none.
+    fn infix_precedence(&self) -> u8 {
+        match self.peek() {
+            Some(TokenKind::Keyword(Keyword::Or)) => 1,
+            Some(TokenKind::Keyword(Keyword::And)) => 2,
+            Some(
+                TokenKind::Eq
+                | TokenKind::NotEq
+                | TokenKind::Lt
+                | TokenKind::LtEq
+                | TokenKind::Gt
+                | TokenKind::GtEq
+                | TokenKind::Keyword(Keyword::Is | Keyword::In | Keyword::Like | Keyword::Between),
+            ) => 4,
+            Some(TokenKind::Keyword(Keyword::Not)) => match self.peek_nth(1) {
+                Some(TokenKind::Keyword(Keyword::In | Keyword::Like | Keyword::Between)) => 4,
+                _ => 0,
+            },
+            Some(TokenKind::Concat) => 5,
+            Some(TokenKind::Plus | TokenKind::Minus) => 6,
+            Some(TokenKind::Star | TokenKind::Slash | TokenKind::Percent) => 7,
+            Some(TokenKind::DoubleColon) => 9,
+            _ => 0,
+        }
+    }
+
+    /// Parse the infix operator at the current token and its right operand.
+    fn infix(&mut self, left: Expr, precedence: u8) -> LangResult<Expr> {
+        let token = self.next_token().expect("Infix Operator");
+        let op = match token.kind {
+            TokenKind::Keyword(Keyword::Or) => BinaryOperator::Or,
+            TokenKind::Keyword(Keyword::And) => BinaryOperator::And,
+            TokenKind::Eq => BinaryOperator::Eq,
+            TokenKind::NotEq => BinaryOperator::NotEq,
+            TokenKind::Lt => BinaryOperator::Lt,
+            TokenKind::LtEq => BinaryOperator::LtEq,
+            TokenKind::Gt => BinaryOperator::Gt,
+            TokenKind::GtEq => BinaryOperator::GtEq,
+            TokenKind::Concat => BinaryOperator::Concat,
+            TokenKind::Plus => BinaryOperator::Plus,
+            TokenKind::Minus => BinaryOperator::Minus,
+            TokenKind::Star => BinaryOperator::Multiply,
+            TokenKind::Slash => BinaryOperator::Divide,
+            TokenKind::Percent => BinaryOperator::Modulo,
+            TokenKind::DoubleColon => {
+                return Ok(Expr::Cast {
+                    expr: Box::new(left),
+                    data_type: self.data_type()?,
+                });
+            }
+            TokenKind::Keyword(Keyword::Is) => {
+                let negated = self.keyword(Keyword::Not);
+                self.expect_keyword(Keyword::Null)?;
+                return Ok(Expr::IsNull {
+                    expr: Box::new(left),
+                    negated,
+                });
+            }
+            _ => {
+                self.index -= 1;
+                return self.predicate(left);
+            }
+        };
+        Ok(Expr::Binary {
+            left: Box::new(left),
+            op,
+            right: Box::new(self.subexpr(precedence)?),
+        })
+    }
+
+    /// Parse `[NOT] IN`, `[NOT] LIKE` or `[NOT] BETWEEN` and their operands.
+    fn predicate(&mut self, left: Expr) -> LangResult<Expr> {
+        let expr = Box::new(left);
+        let negated = self.keyword(Keyword::Not);
+        if self.keyword(Keyword::Between) {
+            let low = Box::new(self.subexpr(4)?);
+            self.expect_keyword(Keyword::And)?;
+            let high = Box::new(self.subexpr(4)?);
+            Ok(Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            })
+        } else if self.keyword(Keyword::Like) {
+            let pattern = Box::new(self.subexpr(4)?);
+            let escape = if self.keyword(Keyword::Escape) {
+                Some(Box::new(self.subexpr(4)?))
+            } else {
+                None
+            };
+            Ok(Expr::Like {
+                expr,
+                negated,
+                pattern,
+                escape,
+            })
+        } else {
+            self.expect_keyword(Keyword::In)?;
+            self.expect(&TokenKind::LeftParen, "`(`")?;
+            let in_expr = if self.is_query_start() {
+                Expr::InSubquery {
+                    expr,
+                    negated,
+                    query: Box::new(self.query()?),
+                }
+            } else {
+                Expr::InList {
+                    expr,
+                    negated,
+                    list: self.list(Parser::expr)?,
+                }
+            };
+            self.expect(&TokenKind::RightParen, "`)`")?;
+            Ok(in_expr)
+        }
+    }
+
+    /// Parse an operand and any prefix operators applied to it.
+    fn prefix(&mut self) -> LangResult<Expr> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.error("expression"));
+        };
+        let literal = match token {
+            TokenKind::String(text) => Literal::String(text.into_owned()),
+            TokenKind::Integer(number) => Literal::Integer(number.to_string()),
+            TokenKind::Float(number) => Literal::Float(number.to_string()),
+            TokenKind::Blob(digits) => Literal::Blob(digits.to_string()),
+            TokenKind::Keyword(Keyword::Null) => Literal::Null,
+            TokenKind::Keyword(Keyword::True) => Literal::Boolean(true),
+            TokenKind::Keyword(Keyword::False) => Literal::Boolean(false),
+            TokenKind::Parameter(number) => {
+                let position = self.position();
+                self.index += 1;
+                let number = number
+                    .map(str::parse)
+                    .transpose()
+                    .map_err(|_| LangError::InvalidNumber(position))?;
+                return Ok(Expr::Parameter(number));
+            }
+            TokenKind::Plus | TokenKind::Minus => {
+                self.index += 1;
+                let op = if token == TokenKind::Plus {
+                    UnaryOperator::Plus
+                } else {
+                    UnaryOperator::Minus
+                };
+                let expr = Box::new(self.subexpr(8)?);
+                return Ok(Expr::Unary { op, expr });
+            }
+            TokenKind::Keyword(Keyword::Not) => {
+                self.index += 1;
+                if self.keyword(Keyword::Exists) {
+                    let query = Box::new(self.subquery()?);
+                    return Ok(Expr::Exists {
+                        query,
+                        negated: true,
+                    });
+                }
+                let expr = Box::new(self.subexpr(3)?);
+                return Ok(Expr::Unary {
+                    op: UnaryOperator::Not,
+                    expr,
+                });
+            }
+            TokenKind::Keyword(Keyword::Exists) => {
+                self.index += 1;
+                let query = Box::new(self.subquery()?);
+                return Ok(Expr::Exists {
+                    query,
+                    negated: false,
+                });
+            }
+            TokenKind::Keyword(Keyword::Case) => return self.case(),
+            TokenKind::Keyword(Keyword::Cast) => {
+                self.index += 1;
+                self.expect(&TokenKind::LeftParen, "`(`")?;
+                let expr = Box::new(self.expr()?);
+                self.expect_keyword(Keyword::As)?;
+                let data_type = self.data_type()?;
+                self.expect(&TokenKind::RightParen, "`)`")?;
+                return Ok(Expr::Cast { expr, data_type });
+            }
+            TokenKind::LeftParen => {
+                self.index += 1;
+                let expr = if self.is_query_start() {
+                    Expr::Subquery(Box::new(self.query()?))
+                } else {
+                    Expr::Nested(Box::new(self.expr()?))
+                };
+                self.expect(&TokenKind::RightParen, "`)`")?;
+                return Ok(expr);
+            }
+            _ if self.is_ident_at(0) => return self.identifier(),
+            _ => return Err(self.error("expression")),
+        };
+        self.index += 1;
+        Ok(Expr::Literal(literal))
+    }
+
+    /// Parse a name, or a function call if followed by arguments.
+    fn identifier(&mut self) -> LangResult<Expr> {
+        let name = self.object_name()?;
+        if !self.consume(&TokenKind::LeftParen) {
+            return Ok(Expr::Identifier(name));
+        }
+        let mut function = Function {
+            name,
+            args: Vec::new(),
+            wildcard: false,
+            distinct: false,
+        };
+        if self.consume(&TokenKind::Star) {
+            function.wildcard = true;
+        } else if self.peek() != Some(&TokenKind::RightParen) {
+            function.distinct = self.keyword(Keyword::Distinct);
+            function.args = self.list(Parser::expr)?;
+        }
+        self.expect(&TokenKind::RightParen, "`)`")?;
+        Ok(Expr::Function(function))
+    }
+
+    fn case(&mut self) -> LangResult<Expr> {
+        self.expect_keyword(Keyword::Case)?;
+        let operand = if self.peek() == Some(&TokenKind::Keyword(Keyword::When)) {
+            None
+        } else {
+            Some(Box::new(self.expr()?))
+        };
+        let mut branches = Vec::new();
+        while self.keyword(Keyword::When) {
+            let condition = self.expr()?;
+            self.expect_keyword(Keyword::Then)?;
+            branches.push((condition, self.expr()?));
+        }
+        if branches.is_empty() {
+            return Err(self.error("WHEN"));
+        }
+        let else_result = if self.keyword(Keyword::Else) {
+            Some(Box::new(self.expr()?))
+        } else {
+            None
+        };
+        self.expect_keyword(Keyword::End)?;
+        Ok(Expr::Case {
+            operand,
+            branches,
+            else_result,
+        })
+    }
+
+    /// Parse a parenthesized query.
+    fn subquery(&mut self) -> LangResult<Query> {
+        self.expect(&TokenKind::LeftParen, "`(`")?;
+        let query = self.query()?;
+        self.expect(&TokenKind::RightParen, "`)`")?;
+        Ok(query)
+    }
+
+    /// Parse a parenthesized expression.
+    fn parenthesized_expr(&mut self) -> LangResult<Expr> {
+        self.expect(&TokenKind::LeftParen, "`(`")?;
+        let expr = self.expr()?;
+        self.expect(&TokenKind::RightParen, "`)`")?;
+        Ok(expr)
+    }
+
+    fn data_type(&mut self) -> LangResult<DataType> {
+        let data_type = match self.peek() {
+            Some(TokenKind::Identifier(name)) => DataType::lookup(name),
+            _ => None,
+        };
+        let Some(data_type) = data_type else {
+            return Err(self.error("data type"));
+        };
+        self.index += 1;
+        Ok(match data_type {
+            DataType::Double => {
+                if matches!(self.peek(), Some(TokenKind::Identifier(word)) if word.eq_ignore_ascii_case("precision"))
+                {
+                    self.index += 1;
+                }
+                DataType::Double
+            }
+            DataType::Decimal(_) if self.consume(&TokenKind::LeftParen) => {
+                let precision = self.length()?;
+                let scale = if self.consume(&TokenKind::Comma) {
+                    Some(self.length()?)
+                } else {
+                    None
+                };
+                self.expect(&TokenKind::RightParen, "`)`")?;
+                DataType::Decimal(Some((precision, scale)))
+            }
+            DataType::Varchar(_) if self.consume(&TokenKind::LeftParen) => {
+                let length = self.length()?;
+                self.expect(&TokenKind::RightParen, "`)`")?;
+                DataType::Varchar(Some(length))
+            }
+            data_type => data_type,
+        })
+    }
+
+    /// Parse the length or precision of a type.
+    fn length(&mut self) -> LangResult<u32> {
+        let position = self.position();
+        match self.peek() {
+            Some(TokenKind::Integer(number)) => {
+                let length = number
+                    .parse()
+                    .map_err(|_| LangError::InvalidNumber(position))?;
+                self.index += 1;
+                Ok(length)
+            }
+            _ => Err(self.error("length")),
+        }
+    }
+
+    /// Parse an identifier, which may be a keyword that isn't reserved.
+    fn ident(&mut self) -> LangResult<Ident> {
+        let ident = match self.tokens.get(self.index) {
+            Some(Token {
+                kind: TokenKind::Identifier(name),
+                ..
+            }) => Ident::new(name),
+            Some(Token {
+                kind: TokenKind::QuotedIdentifier(name),
+                ..
+            }) => Ident::quoted(name),
+            Some(Token {
+                kind: TokenKind::Keyword(keyword),
+                span,
+            }) if !keyword.is_reserved() => Ident::new(span.text(self.source)),
+            _ => return Err(self.error("identifier")),
+        };
+        self.index += 1;
+        Ok(ident)
+    }
+
+    /// Parse a name of identifiers separated by periods.
+    fn object_name(&mut self) -> LangResult<Vec<Ident>> {
+        let mut name = vec![self.ident()?];
+        while self.consume(&TokenKind::Period) {
+            name.push(self.ident()?);
+        }
+        Ok(name)
+    }
+
+    /// Parse a parenthesized list of identifiers.
+    fn ident_list(&mut self) -> LangResult<Vec<Ident>> {
+        self.expect(&TokenKind::LeftParen, "`(`")?;
+        let idents = self.list(Parser::ident)?;
+        self.expect(&TokenKind::RightParen, "`,` or `)`")?;
+        Ok(idents)
+    }
+
+    /// Parse items separated by commas.
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> LangResult<T>) -> LangResult<Vec<T>> {
+        let mut items = vec![item(self)?];
+        while self.consume(&TokenKind::Comma) {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn is_ident_at(&self, ahead: usize) -> bool {
+        match self.peek_nth(ahead) {
+            Some(TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_)) => true,
+            Some(TokenKind::Keyword(keyword)) => !keyword.is_reserved(),
+            _ => false,
+        }
+    }
+
+    fn is_query_start(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(TokenKind::Keyword(Keyword::Select | Keyword::Values))
+        )
+    }
+
+    fn peek(&self) -> Option<&TokenKind<'str>> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, ahead: usize) -> Option<&TokenKind<'str>> {
+        self.tokens.get(self.index + ahead).map(|token| &token.kind)
+    }
+
+    fn next_token(&mut self) -> Option<Token<'str>> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += usize::from(token.is_some());
+        token
+    }
+
+    /// Consume the current token if it is `kind`.
+    fn consume(&mut self, kind: &TokenKind<'_>) -> bool {
+        let found = self.peek() == Some(kind);
+        self.index += usize::from(found);
+        found
+    }
+
+    /// Consume the current token if it is `keyword`.
+    fn keyword(&mut self, keyword: Keyword) -> bool {
+        self.consume(&TokenKind::Keyword(keyword))
+    }
+
+    /// Consume the keywords at the current token if they are all `keywords`.
+    fn keywords(&mut self, keywords: &[Keyword]) -> bool {
+        let found = keywords
+            .iter()
+            .enumerate()
+            .all(|(ahead, keyword)| self.peek_nth(ahead) == Some(&TokenKind::Keyword(*keyword)));
+        if found {
+            self.index += keywords.len();
+        }
+        found
+    }
+
+    /// Consume a string literal, returning its text.
+    fn string(&mut self) -> LangResult<String> {
+        match self.peek() {
+            Some(TokenKind::String(text)) => {
+                let text = text.to_string();
+                self.index += 1;
+                Ok(text)
+            }
+            _ => Err(self.error("string")),
+        }
+    }
+
+    fn expect(&mut self, kind: &TokenKind<'_>, expected: &str) -> LangResult<()> {
+        if self.consume(kind) {
+            Ok(())
+        } else {
+            Err(self.error(expected))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: Keyword) -> LangResult<()> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error(keyword.as_str()))
+        }
+    }
+
+    /// Check every token was parsed.
+    fn finish(&self) -> LangResult<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error("end of input")),
+        }
+    }
+
+    /// Position of the current token, or the end of the source.
+    fn position(&self) -> Position {
+        let offset = self
+            .tokens
+            .get(self.index)
+            .map_or(self.source.len(), |token| token.span.start);
+        Position::locate(self.source, offset)
+    }
+
+    /// Error for finding the current token where `expected` should be.
+    fn error(&self, expected: &str) -> LangError {
+        let position = self.position();
+        match self.tokens.get(self.index) {
+            Some(token) => LangError::UnexpectedToken {
+                expected: expected.to_string(),
+                found: token.span.text(self.source).to_string(),
+                position,
+            },
+            None => LangError::UnexpectedEnd {
+                expected: expected.to_string(),
+                position,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{BinaryOperator, Expr, Ident, Literal, Statement};
+    use crate::{LangError, Parser, Position};
+
+    fn error_at(source: &str) -> (LangError, usize, usize) {
+        let error = Parser::parse(source).unwrap_err();
+        let Position { line, column, .. } = error.position();
+        (error, line, column)
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_parser() {
+        let expr = Parser::parse_expr("1 + 2 * 3 = 7 AND NOT a OR b").unwrap();
+        assert_eq!(expr.to_string(), "1 + 2 * 3 = 7 AND NOT a OR b");
+        let Expr::Binary { op, left, .. } = &expr else {
+            panic!("{expr:?}")
+        };
+        assert_eq!(*op, BinaryOperator::Or);
+        assert!(matches!(
+            **left,
+            Expr::Binary {
+                op: BinaryOperator::And,
+                ..
+            }
+        ));
+        assert_eq!(
+            Parser::parse_expr("-x::integer").unwrap().to_string(),
+            "-CAST(x AS INTEGER)"
+        );
+        assert_eq!(
+            Parser::parse_expr("a NOT BETWEEN 1 AND 2 AND b NOT LIKE 'x%'")
+                .unwrap()
+                .to_string(),
+            "a NOT BETWEEN 1 AND 2 AND b NOT LIKE 'x%'"
+        );
+        assert_eq!(
+            Parser::parse_expr("Price").unwrap(),
+            Expr::Identifier(vec![Ident::new("Price")])
+        );
+        assert_eq!(
+            Parser::parse_expr("NULL").unwrap(),
+            Expr::Literal(Literal::Null)
+        );
+
+        for data_type in ["DECIMAL(10, 2)", "VARCHAR(8)", "DOUBLE", "TIMESTAMP"] {
+            let parsed = Parser::parse_data_type(data_type).unwrap();
+            assert_eq!(parsed.to_string(), data_type);
+        }
+        assert!(Parser::parse_data_type("INTEGER NOT NULL").is_err());
+
+        let statements = Parser::parse("BEGIN; SELECT * FROM t;; COMMIT;").unwrap();
+        assert_eq!(statements.len(), 3);
+        assert_eq!(statements[0], Statement::Begin);
+        assert_eq!(statements[2], Statement::Commit);
+        assert!(Parser::parse("  -- nothing\n").unwrap().is_empty());
+        assert_eq!(
+            Parser::parse_statement("backup to 's3://bucket/nightly'").unwrap(),
+            Statement::Backup {
+                uri: "s3://bucket/nightly".to_string()
+            }
+        );
+        assert!(Parser::parse_statement("RESTORE FROM backups").is_err());
+
+        assert!(matches!(
+            error_at("SELECT FROM t"),
+            (LangError::UnexpectedToken { .. }, 1, 8)
+        ));
+        assert!(matches!(
+            error_at("SELECT a\nFROM"),
+            (LangError::UnexpectedEnd { .. }, 2, 5)
+        ));
+        assert!(matches!(
+            error_at("SELECT 1 SELECT 2"),
+            (LangError::UnexpectedToken { .. }, 1, 10)
+        ));
+        assert!(matches!(
+            error_at("SELECT 'open"),
+            (LangError::UnterminatedString(_), 1, 8)
+        ));
+        assert!(matches!(
+            error_at("CREATE TABLE t (a VARCHAR(x))"),
+            (LangError::UnexpectedToken { .. }, 1, 27)
+        ));
+    }
+}
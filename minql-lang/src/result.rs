@@ -0,0 +1,86 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::Position;
+
+/// Result Type for SQL Language Processing
+pub type LangResult<T> = Result<T, LangError>;
+
+/// Error Type for SQL Language Processing
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LangError {
+    /// Character can't start any token
+    UnexpectedCharacter {
+        /// Character found
+        character: char,
+        /// Where it was found
+        position: Position,
+    },
+    /// String literal is missing its closing quote
+    UnterminatedString(Position),
+    /// Quoted identifier is missing its closing quote
+    UnterminatedIdentifier(Position),
+    /// Quoted identifier has no characters
+    EmptyIdentifier(Position),
+    /// Block comment is missing its closing `*/`
+    UnterminatedComment(Position),
+    /// Number is malformed or runs into an identifier
+    InvalidNumber(Position),
+    /// Blob literal holds other than an even number of hexadecimal digits
+    InvalidBlob(Position),
+    /// Token doesn't fit the grammar where it was found
+    UnexpectedToken {
+        /// What the grammar allows there
+        expected: String,
+        /// Token found, as written
+        found: String,
+        /// Where it was found
+        position: Position,
+    },
+    /// Source ended where the grammar expects more
+    UnexpectedEnd {
+        /// What the grammar expects
+        expected: String,
+        /// End of the source
+        position: Position,
+    },
+}
+
+impl LangError {
+    /// Where in the source the error was found.
+    #[must_use]
+    pub fn position(&self) -> Position {
+        match self {
+            LangError::UnexpectedCharacter { position, .. }
+            | LangError::UnexpectedToken { position, .. }
+            | LangError::UnexpectedEnd { position, .. }
+            | LangError::UnterminatedString(position)
+            | LangError::UnterminatedIdentifier(position)
+            | LangError::EmptyIdentifier(position)
+            | LangError::UnterminatedComment(position)
+            | LangError::InvalidNumber(position)
+            | LangError::InvalidBlob(position) => *position,
+        }
+    }
+}
+
+impl std::fmt::Display for LangError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for LangError {}
@@ -0,0 +1,119 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+/// Byte range of source text covered by a token.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Span {
+    /// Offset of the first byte
+    pub start: usize,
+    /// Offset just past the last byte
+    pub end: usize,
+}
+
+impl Span {
+    /// Create a span from `start` up to `end`.
+    #[must_use]
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// Number of bytes covered.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Check if the span covers no bytes.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Smallest span covering both spans.
+    #[must_use]
+    pub fn merge(&self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    /// Text of the source covered by the span.
+    #[must_use]
+    pub fn text<'str>(&self, source: &'str str) -> &'str str {
+        &source[self.start..self.end]
+    }
+
+    /// Position of the start of the span in the source.
+    #[must_use]
+    pub fn position(&self, source: &str) -> Position {
+        Position::locate(source, self.start)
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// Location in source text, by byte offset and by line and column, both counted from 1.
+///
+/// Columns count characters rather than bytes, so they match what an editor shows.
+///
+/// ```rust
+/// use minql_lang::Position;
+///
+/// let position = Position::locate("SELECT 1\nFROM t", 14);
+/// assert_eq!((position.line, position.column), (2, 6));
+/// assert_eq!(position.to_string(), "2:6");
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Position {
+    /// Byte offset into the source
+    pub offset: usize,
+    /// Line number, counted from 1
+    pub line: usize,
+    /// Column in characters, counted from 1
+    pub column: usize,
+}
+
+impl Position {
+    /// Find the line and column of a byte offset in the source.
+    #[must_use]
+    pub fn locate(source: &str, offset: usize) -> Position {
+        let before = &source[..offset.min(source.len())];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        Position {
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
@@ -0,0 +1,329 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::Span;
+use std::borrow::Cow;
+
+/// Token of SQL source text and the span of source it was read from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token<'str> {
+    /// What was read
+    pub kind: TokenKind<'str>,
+    /// Where it was read from
+    pub span: Span,
+}
+
+impl<'str> Token<'str> {
+    /// Create a token.
+    #[must_use]
+    pub fn new(kind: TokenKind<'str>, span: Span) -> Token<'str> {
+        Token { kind, span }
+    }
+
+    /// Check if the token is a comment, which carries no meaning.
+    #[must_use]
+    pub fn is_comment(&self) -> bool {
+        matches!(self.kind, TokenKind::Comment(_))
+    }
+}
+
+/// Kind of [`Token`], holding its value as written, with quotes and escapes removed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenKind<'str> {
+    /// Reserved word, matched regardless of case
+    Keyword(Keyword),
+    /// Unquoted identifier, as written
+    Identifier(&'str str),
+    /// Double quoted identifier, whose case is kept and where `""` stands for `"`
+    QuotedIdentifier(Cow<'str, str>),
+    /// Single quoted string, where `''` stands for `'`
+    String(Cow<'str, str>),
+    /// Hexadecimal digits of an `X'..'` blob literal
+    Blob(&'str str),
+    /// Number without a fraction or exponent
+    Integer(&'str str),
+    /// Number with a fraction or exponent
+    Float(&'str str),
+    /// Positional parameter, `?` or `$1`, holding its number if given
+    Parameter(Option<&'str str>),
+    /// `--` comment to the end of the line or `/* */` block comment, without delimiters
+    Comment(&'str str),
+    /// `,`
+    Comma,
+    /// `;`
+    Semicolon,
+    /// `.`
+    Period,
+    /// `(`
+    LeftParen,
+    /// `)`
+    RightParen,
+    /// `+`
+    Plus,
+    /// `-`
+    Minus,
+    /// `*`
+    Star,
+    /// `/`
+    Slash,
+    /// `%`
+    Percent,
+    /// `||`
+    Concat,
+    /// `::`
+    DoubleColon,
+    /// `=`
+    Eq,
+    /// `<>` or `!=`
+    NotEq,
+    /// `<`
+    Lt,
+    /// `<=`
+    LtEq,
+    /// `>`
+    Gt,
+    /// `>=`
+    GtEq,
+}
+
+impl std::fmt::Display for TokenKind<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Keyword(keyword) => write!(f, "{keyword}"),
+            TokenKind::Identifier(name) => write!(f, "{name}"),
+            TokenKind::QuotedIdentifier(name) => write!(f, "\"{}\"", name.replace('"', "\"\"")),
+            TokenKind::String(text) => write!(f, "'{}'", text.replace('\'', "''")),
+            TokenKind::Blob(digits) => write!(f, "X'{digits}'"),
+            TokenKind::Integer(number) | TokenKind::Float(number) => write!(f, "{number}"),
+            TokenKind::Parameter(Some(number)) => write!(f, "${number}"),
+            TokenKind::Parameter(None) => write!(f, "?"),
+            TokenKind::Comment(text) => write!(f, "/*{text}*/"),
+            TokenKind::Comma => write!(f, ","),
+            TokenKind::Semicolon => write!(f, ";"),
+            TokenKind::Period => write!(f, "."),
+            TokenKind::LeftParen => write!(f, "("),
+            TokenKind::RightParen => write!(f, ")"),
+            TokenKind::Plus => write!(f, "+"),
+            TokenKind::Minus => write!(f, "-"),
+            TokenKind::Star => write!(f, "*"),
+            TokenKind::Slash => write!(f, "/"),
+            TokenKind::Percent => write!(f, "%"),
+            TokenKind::Concat => write!(f, "||"),
+            TokenKind::DoubleColon => write!(f, "::"),
+            TokenKind::Eq => write!(f, "="),
+            TokenKind::NotEq => write!(f, "<>"),
+            TokenKind::Lt => write!(f, "<"),
+            TokenKind::LtEq => write!(f, "<="),
+            TokenKind::Gt => write!(f, ">"),
+            TokenKind::GtEq => write!(f, ">="),
+        }
+    }
+}
+
+/// Define the [`Keyword`] enum from pairs of variant and spelling, in alphabetical order.
+macro_rules! keywords {
+    ($($variant:ident => $text:literal,)*) => {
+        /// Reserved word of SQL.
+        #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+        pub enum Keyword {
+            $(
+                #[doc = concat!("`", $text, "`")]
+                $variant,
+            )*
+        }
+
+        impl Keyword {
+            /// Every keyword, in alphabetical order.
+            pub const ALL: &'static [Keyword] = &[$(Keyword::$variant,)*];
+
+            /// Spelling of the keyword in upper case.
+            #[must_use]
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(Keyword::$variant => $text,)*
+                }
+            }
+        }
+    };
+}
+
+keywords! {
+    Add => "ADD",
+    All => "ALL",
+    Alter => "ALTER",
+    Analyze => "ANALYZE",
+    And => "AND",
+    As => "AS",
+    Asc => "ASC",
+    Backup => "BACKUP",
+    Begin => "BEGIN",
+    Between => "BETWEEN",
+    By => "BY",
+    Cascade => "CASCADE",
+    Case => "CASE",
+    Cast => "CAST",
+    Check => "CHECK",
+    Column => "COLUMN",
+    Commit => "COMMIT",
+    Constraint => "CONSTRAINT",
+    Create => "CREATE",
+    Cross => "CROSS",
+    Default => "DEFAULT",
+    Delete => "DELETE",
+    Desc => "DESC",
+    Distinct => "DISTINCT",
+    Drop => "DROP",
+    Else => "ELSE",
+    End => "END",
+    Escape => "ESCAPE",
+    Except => "EXCEPT",
+    Exists => "EXISTS",
+    Explain => "EXPLAIN",
+    False => "FALSE",
+    First => "FIRST",
+    Foreign => "FOREIGN",
+    From => "FROM",
+    Full => "FULL",
+    Function => "FUNCTION",
+    Group => "GROUP",
+    Having => "HAVING",
+    If => "IF",
+    In => "IN",
+    Index => "INDEX",
+    Inner => "INNER",
+    Insert => "INSERT",
+    Intersect => "INTERSECT",
+    Into => "INTO",
+    Is => "IS",
+    Join => "JOIN",
+    Key => "KEY",
+    Last => "LAST",
+    Left => "LEFT",
+    Like => "LIKE",
+    Limit => "LIMIT",
+    Not => "NOT",
+    Null => "NULL",
+    Nulls => "NULLS",
+    Offset => "OFFSET",
+    On => "ON",
+    Or => "OR",
+    Order => "ORDER",
+    Outer => "OUTER",
+    Primary => "PRIMARY",
+    References => "REFERENCES",
+    Rename => "RENAME",
+    Restore => "RESTORE",
+    Restrict => "RESTRICT",
+    Returns => "RETURNS",
+    Right => "RIGHT",
+    Rollback => "ROLLBACK",
+    Select => "SELECT",
+    Set => "SET",
+    Table => "TABLE",
+    Then => "THEN",
+    To => "TO",
+    Transaction => "TRANSACTION",
+    True => "TRUE",
+    Union => "UNION",
+    Unique => "UNIQUE",
+    Update => "UPDATE",
+    Using => "USING",
+    Values => "VALUES",
+    View => "VIEW",
+    When => "WHEN",
+    Where => "WHERE",
+    With => "WITH",
+}
+
+impl Keyword {
+    /// Find the keyword spelled by a word, ignoring case.
+    ///
+    /// ```rust
+    /// use minql_lang::Keyword;
+    ///
+    /// assert_eq!(Keyword::lookup("Select"), Some(Keyword::Select));
+    /// assert_eq!(Keyword::lookup("selected"), None);
+    /// ```
+    #[must_use]
+    pub fn lookup(word: &str) -> Option<Keyword> {
+        if word.len() > 16 || !word.is_ascii() {
+            return None;
+        }
+        let upper = word.to_ascii_uppercase();
+        Keyword::ALL
+            .binary_search_by(|keyword| keyword.as_str().cmp(&upper))
+            .ok()
+            .map(|index| Keyword::ALL[index])
+    }
+
+    /// Check if the keyword can't be used as an unquoted identifier.
+    #[must_use]
+    pub fn is_reserved(self) -> bool {
+        !matches!(
+            self,
+            Keyword::Add
+                | Keyword::Analyze
+                | Keyword::Backup
+                | Keyword::Begin
+                | Keyword::Cascade
+                | Keyword::Column
+                | Keyword::Commit
+                | Keyword::Escape
+                | Keyword::Explain
+                | Keyword::First
+                | Keyword::Function
+                | Keyword::Index
+                | Keyword::Key
+                | Keyword::Last
+                | Keyword::Nulls
+                | Keyword::Rename
+                | Keyword::Restore
+                | Keyword::Restrict
+                | Keyword::Returns
+                | Keyword::Rollback
+                | Keyword::To
+                | Keyword::Transaction
+                | Keyword::View
+        )
+    }
+}
+
+impl std::fmt::Display for Keyword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Keyword;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_keywords() {
+        assert!(Keyword::ALL
+            .windows(2)
+            .all(|pair| pair[0].as_str() < pair[1].as_str()));
+        for keyword in Keyword::ALL {
+            assert_eq!(Keyword::lookup(keyword.as_str()), Some(*keyword));
+            let lower = keyword.as_str().to_ascii_lowercase();
+            assert_eq!(Keyword::lookup(&lower), Some(*keyword));
+        }
+        assert_eq!(Keyword::lookup("tables"), None);
+        assert_eq!(Keyword::lookup("sélect"), None);
+        assert_eq!(Keyword::Transaction.to_string(), "TRANSACTION");
+    }
+}
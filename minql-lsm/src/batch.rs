@@ -0,0 +1,161 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::merge::Entry;
+use crate::LsmResult;
+use minql_vfs::FileSystemError;
+
+/// Write Batch
+///
+/// Puts and deletes gathered to be applied to an [`LsmTree`](crate::LsmTree) together by
+/// [`LsmTree::write`](crate::LsmTree::write), which logs them as a single record so that after
+/// a crash either all or none of them are replayed. Writes are applied in the order given, so a
+/// later write to a key replaces an earlier one.
+///
+/// ```rust
+/// use minql_lsm::WriteBatch;
+///
+/// let mut batch = WriteBatch::new();
+/// batch.put(b"apple", b"red").delete(b"banana");
+/// assert_eq!(batch.len(), 2);
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct WriteBatch {
+    writes: Vec<Entry>,
+    size: usize,
+}
+
+impl WriteBatch {
+    /// Create an empty batch.
+    #[must_use]
+    pub fn new() -> WriteBatch {
+        WriteBatch::default()
+    }
+
+    /// Set the value of a key.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> &mut WriteBatch {
+        self.size += key.len() + value.len();
+        self.writes.push((key.to_vec(), Some(value.to_vec())));
+        self
+    }
+
+    /// Delete a key.
+    pub fn delete(&mut self, key: &[u8]) -> &mut WriteBatch {
+        self.size += key.len();
+        self.writes.push((key.to_vec(), None));
+        self
+    }
+
+    /// Number of writes in the batch.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// Check if the batch holds no writes.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Approximate bytes held by the keys and values.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Remove every write.
+    pub fn clear(&mut self) {
+        self.writes.clear();
+        self.size = 0;
+    }
+
+    /// Iterate over the writes in order, with `None` for deletes.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
+        self.writes
+            .iter()
+            .map(|(key, value)| (key.as_slice(), value.as_deref()))
+    }
+
+    /// Encode the writes as a sequence of whether each is a put, key length, value length, key
+    /// and value.
+    pub(crate) fn encode(&self) -> LsmResult<Vec<u8>> {
+        let mut encoded = Vec::with_capacity(self.size + 9 * self.writes.len());
+        for (key, value) in &self.writes {
+            let value = value.as_deref();
+            let key_len = u32::try_from(key.len()).map_err(FileSystemError::wrap_error)?;
+            let value_len =
+                u32::try_from(value.map_or(0, <[u8]>::len)).map_err(FileSystemError::wrap_error)?;
+            encoded.push(u8::from(value.is_some()));
+            encoded.extend_from_slice(&key_len.to_le_bytes());
+            encoded.extend_from_slice(&value_len.to_le_bytes());
+            encoded.extend_from_slice(key);
+            encoded.extend_from_slice(value.unwrap_or_default());
+        }
+        Ok(encoded)
+    }
+
+    /// Decode writes encoded by [`WriteBatch::encode`].
+    pub(crate) fn decode(mut encoded: &[u8]) -> Option<WriteBatch> {
+        let mut batch = WriteBatch::new();
+        while let Some((&put, rest)) = encoded.split_first() {
+            let key_len = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?);
+            let value_len = u32::from_le_bytes(rest.get(4..8)?.try_into().ok()?);
+            let key_end = 8 + usize::try_from(key_len).ok()?;
+            let value_end = key_end + usize::try_from(value_len).ok()?;
+            let key = rest.get(8..key_end)?;
+            match put {
+                0 => batch.delete(key),
+                1 => batch.put(key, rest.get(key_end..value_end)?),
+                _ => return None,
+            };
+            encoded = rest.get(value_end..)?;
+        }
+        Some(batch)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::WriteBatch;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_write_batch() {
+        let mut batch = WriteBatch::new();
+        assert!(batch.is_empty());
+        batch.put(b"a", b"one").delete(b"b").put(b"c", b"");
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch.size(), 6);
+        assert_eq!(
+            batch.iter().collect::<Vec<_>>(),
+            vec![
+                (&b"a"[..], Some(&b"one"[..])),
+                (&b"b"[..], None),
+                (&b"c"[..], Some(&b""[..])),
+            ]
+        );
+
+        let encoded = batch.encode().unwrap();
+        assert_eq!(WriteBatch::decode(&encoded), Some(batch.clone()));
+        assert_eq!(WriteBatch::decode(&encoded[..encoded.len() - 1]), None);
+        assert_eq!(WriteBatch::decode(&[]), Some(WriteBatch::new()));
+
+        batch.clear();
+        assert!(batch.is_empty());
+        assert_eq!(batch.size(), 0);
+    }
+}
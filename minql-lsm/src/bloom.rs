@@ -0,0 +1,134 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+/// Bloom Filter
+///
+/// A compact set of keys answering whether a key may be present, with no false negatives and
+/// a false positive rate set by the bits spent on each key: about 1% at 10 bits per key. Each
+/// key sets a number of bits chosen by double hashing a 64-bit hash of the key.
+///
+/// ```rust
+/// use minql_lsm::BloomFilter;
+///
+/// let mut filter = BloomFilter::new(100, 10);
+/// filter.insert(b"apple");
+/// assert!(filter.may_contain(b"apple"));
+///
+/// let filter = BloomFilter::decode(&filter.encode()).unwrap();
+/// assert!(filter.may_contain(b"apple"));
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    probes: u8,
+}
+
+impl BloomFilter {
+    /// Create an empty filter sized for `keys` keys at `bits_per_key` bits each.
+    #[must_use]
+    pub fn new(keys: usize, bits_per_key: usize) -> BloomFilter {
+        let bits = keys.saturating_mul(bits_per_key).max(64);
+        // Probing ln 2 bits per key minimizes false positives.
+        let probes = (bits_per_key * 69 / 100).clamp(1, 30);
+        BloomFilter {
+            bits: vec![0; bits.div_ceil(8)],
+            probes: u8::try_from(probes).expect("Probe Count"),
+        }
+    }
+
+    /// Add a key.
+    pub fn insert(&mut self, key: &[u8]) {
+        self.insert_hash(hash_key(key));
+    }
+
+    /// Check if a key may have been added.
+    #[must_use]
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.positions(hash_key(key))
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Encode the filter, as read back by [`BloomFilter::decode`].
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.bits.len() + 1);
+        data.push(self.probes);
+        data.extend_from_slice(&self.bits);
+        data
+    }
+
+    /// Decode a filter, or `None` if `data` doesn't hold one.
+    #[must_use]
+    pub fn decode(data: &[u8]) -> Option<BloomFilter> {
+        let (&probes, bits) = data.split_first()?;
+        if probes == 0 || bits.is_empty() {
+            return None;
+        }
+        Some(BloomFilter {
+            bits: bits.to_vec(),
+            probes,
+        })
+    }
+
+    /// Add a key by its [`hash_key`] hash.
+    pub(crate) fn insert_hash(&mut self, hash: u64) {
+        for bit in self.positions(hash).collect::<Vec<_>>() {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    fn positions(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let bits = self.bits.len() as u64 * 8;
+        let delta = hash.rotate_left(32) | 1;
+        (0..u64::from(self.probes)).map(move |probe| {
+            let bit = hash.wrapping_add(probe.wrapping_mul(delta)) % bits;
+            usize::try_from(bit).expect("Filter Bit")
+        })
+    }
+}
+
+/// Hash a key with FNV-1a, finished with the `SplitMix64` mixer so every bit depends on every
+/// byte. The hash is stored in tables, so it must never change.
+pub(crate) fn hash_key(key: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for byte in key {
+        hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::BloomFilter;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_bloom_filter() {
+        let mut filter = BloomFilter::new(1000, 10);
+        for index in 0..1000 {
+            filter.insert(format!("key{index}").as_bytes());
+        }
+        assert!((0..1000).all(|index| filter.may_contain(format!("key{index}").as_bytes())));
+        let false_positives = (0..1000)
+            .filter(|index| filter.may_contain(format!("other{index}").as_bytes()))
+            .count();
+        assert!(false_positives < 30, "{false_positives} false positives");
+        assert_eq!(BloomFilter::decode(&filter.encode()), Some(filter));
+        assert_eq!(BloomFilter::decode(&[]), None);
+    }
+}
@@ -0,0 +1,971 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::manifest::{
+    manifest_path, table_path, TableEntry, Version, MANIFEST_PREFIX, TABLE_EXTENSION,
+};
+use crate::memtable::is_empty_range;
+use crate::merge::{Entry, EntrySource, MergeIter};
+use crate::{LsmError, LsmResult, MemTable, SSTableReader, SSTableWriter, TableInfo, WriteBatch};
+use minql_vfs::{FileHandle, FileSystem, FileSystemError, Lsn, WalOptions, WriteAheadLog};
+use std::collections::HashSet;
+use std::io::Write;
+use std::ops::Bound;
+use std::sync::Arc;
+
+const RECORD_PUT: u8 = 1;
+const RECORD_DELETE: u8 = 2;
+const RECORD_BATCH: u8 = 3;
+
+/// Configuration of an [`LsmTree`].
+#[derive(Clone, Debug)]
+pub struct LsmOptions {
+    memtable_size: usize,
+    block_size: usize,
+    bloom_bits_per_key: usize,
+    table_size: u64,
+    level0_tables: usize,
+    level_size: u64,
+    level_multiplier: u64,
+    levels: usize,
+    wal_options: WalOptions,
+}
+
+impl LsmOptions {
+    /// Create the default options: 4 MiB memtables, 4 KiB blocks, 10 bit per key filters,
+    /// 2 MiB tables, compacting level 0 at 4 tables and level 1 at 10 MiB, with each of the 7
+    /// levels 10 times larger than the last.
+    #[must_use]
+    pub fn new() -> LsmOptions {
+        LsmOptions {
+            memtable_size: 4 * 1024 * 1024,
+            block_size: 4096,
+            bloom_bits_per_key: 10,
+            table_size: 2 * 1024 * 1024,
+            level0_tables: 4,
+            level_size: 10 * 1024 * 1024,
+            level_multiplier: 10,
+            levels: 7,
+            wal_options: WalOptions::new(),
+        }
+    }
+
+    /// Set the bytes the memtable holds before it is flushed to a table.
+    #[must_use]
+    pub fn with_memtable_size(mut self, memtable_size: usize) -> LsmOptions {
+        self.memtable_size = memtable_size;
+        self
+    }
+
+    /// Set the bytes of each data block of a table.
+    #[must_use]
+    pub fn with_block_size(mut self, block_size: usize) -> LsmOptions {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Set the bits of the bloom filter of a table spent on each key.
+    #[must_use]
+    pub fn with_bloom_bits_per_key(mut self, bloom_bits_per_key: usize) -> LsmOptions {
+        self.bloom_bits_per_key = bloom_bits_per_key;
+        self
+    }
+
+    /// Set the bytes at which compaction starts a new table.
+    #[must_use]
+    pub fn with_table_size(mut self, table_size: u64) -> LsmOptions {
+        self.table_size = table_size;
+        self
+    }
+
+    /// Set the number of level 0 tables that triggers their compaction into level 1.
+    #[must_use]
+    pub fn with_level0_tables(mut self, level0_tables: usize) -> LsmOptions {
+        self.level0_tables = level0_tables.max(1);
+        self
+    }
+
+    /// Set the bytes level 1 holds before it is compacted into level 2.
+    #[must_use]
+    pub fn with_level_size(mut self, level_size: u64) -> LsmOptions {
+        self.level_size = level_size;
+        self
+    }
+
+    /// Set how many times larger each level is allowed to grow than the one above it.
+    #[must_use]
+    pub fn with_level_multiplier(mut self, level_multiplier: u64) -> LsmOptions {
+        self.level_multiplier = level_multiplier.max(1);
+        self
+    }
+
+    /// Set the number of levels, of which the last grows without limit.
+    #[must_use]
+    pub fn with_levels(mut self, levels: usize) -> LsmOptions {
+        self.levels = levels.max(2);
+        self
+    }
+
+    /// Set the options of the write-ahead log protecting the memtable.
+    #[must_use]
+    pub fn with_wal_options(mut self, wal_options: WalOptions) -> LsmOptions {
+        self.wal_options = wal_options;
+        self
+    }
+}
+
+impl Default for LsmOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Number and size of the tables in a level of an [`LsmTree`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct LevelStats {
+    /// Number of tables
+    pub tables: usize,
+    /// Bytes of every table
+    pub bytes: u64,
+}
+
+/// A table of an [`LsmTree`] open for reading.
+#[derive(Debug)]
+struct Table<H: FileHandle> {
+    id: u64,
+    info: TableInfo,
+    reader: SSTableReader<H>,
+}
+
+impl<H: FileHandle> Table<H> {
+    /// Check if the keys of the table may lie between `start` and `end`.
+    fn overlaps(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+        let after_start = match start {
+            Bound::Included(start) => self.info.largest.as_slice() >= start,
+            Bound::Excluded(start) => self.info.largest.as_slice() > start,
+            Bound::Unbounded => true,
+        };
+        let before_end = match end {
+            Bound::Included(end) => self.info.smallest.as_slice() <= end,
+            Bound::Excluded(end) => self.info.smallest.as_slice() < end,
+            Bound::Unbounded => true,
+        };
+        after_start && before_end
+    }
+}
+
+/// LSM Tree
+///
+/// A log structured merge tree storing an ordered map of byte string keys to values using only
+/// the [`FileSystem`] abstraction, so it runs the same on memory, local disk, or object storage.
+///
+/// Writes go to a [`WriteAheadLog`] and then a [`MemTable`], which is flushed to an immutable
+/// table in level 0 once it reaches the memtable size. Tables are never modified, only written
+/// whole and later deleted, which suits object stores. When level 0 gathers enough tables they
+/// are merged into level 1, and when any later level outgrows its limit one of its tables is
+/// merged into the next, so each level below 0 holds tables of disjoint key ranges. Deletes
+/// are tombstones until compaction reaches the last populated level.
+///
+/// The tables of each level are recorded in numbered manifest files, each written whole before
+/// the previous one and any replaced tables are deleted, so a crash leaves either the old or the
+/// new set of tables. Opening a tree loads the newest intact manifest, deletes tables it doesn't
+/// reference, and replays the log into the memtable.
+///
+/// Flushes and compactions run on the writing thread, as part of the write that triggers them.
+///
+/// A [`WriteBatch`] is logged as one record, so its writes are recovered all or none. A
+/// [`LsmSnapshot`] keeps reading the tree as it was when taken, sharing the memtable until the
+/// next write copies it and holding on to its tables, whose files are only deleted by a later
+/// compaction once every snapshot reading them is dropped.
+///
+/// ```rust
+/// use minql_lsm::{LsmOptions, LsmTree};
+/// use minql_vfs::MemoryFileSystem;
+/// use std::ops::Bound;
+///
+/// let fs = MemoryFileSystem::new();
+/// let mut tree = LsmTree::open(fs.clone(), "/db", LsmOptions::new()).unwrap();
+/// tree.put(b"apple", b"red").unwrap();
+/// tree.put(b"banana", b"yellow").unwrap();
+/// tree.flush().unwrap();
+/// tree.delete(b"apple").unwrap();
+/// drop(tree);
+///
+/// let tree = LsmTree::open(fs, "/db", LsmOptions::new()).unwrap();
+/// assert_eq!(tree.get(b"apple").unwrap(), None);
+/// let entries = tree
+///     .scan(Bound::Unbounded, Bound::Unbounded)
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+/// assert_eq!(entries, vec![(b"banana".to_vec(), b"yellow".to_vec())]);
+/// ```
+#[derive(Debug)]
+pub struct LsmTree<F: FileSystem + Clone> {
+    fs: F,
+    directory: String,
+    options: LsmOptions,
+    wal: WriteAheadLog<F>,
+    memtable: Arc<MemTable>,
+    /// Tables of each level, newest first in level 0 and by key elsewhere
+    levels: Vec<Vec<Arc<Table<F::FileHandle>>>>,
+    /// Tables compacted away but possibly still read by snapshots
+    obsolete: Vec<Arc<Table<F::FileHandle>>>,
+    next_table: u64,
+    log_start: Lsn,
+    manifest: u64,
+    /// Largest key compacted from each level, so compaction cycles through its key space
+    compacted: Vec<Option<Vec<u8>>>,
+}
+
+impl<F: FileSystem + Clone> LsmTree<F> {
+    /// Open or create the tree stored in `directory`, replaying writes not yet in a table.
+    pub fn open(fs: F, directory: &str, options: LsmOptions) -> LsmResult<LsmTree<F>> {
+        let directory = directory.trim_end_matches('/').to_string();
+        fs.create_directory_all(&directory)?;
+        let (manifest, version) = load_version(&fs, &directory)?;
+        let mut levels = Vec::new();
+        for tables in &version.levels {
+            let mut level = Vec::with_capacity(tables.len());
+            for table in tables {
+                let handle = fs.open_file(&table_path(&directory, table.id))?;
+                level.push(Arc::new(Table {
+                    id: table.id,
+                    info: table.info.clone(),
+                    reader: SSTableReader::open(handle)?,
+                }));
+            }
+            levels.push(level);
+        }
+        levels.resize_with(levels.len().max(options.levels), Vec::new);
+        let wal =
+            WriteAheadLog::open(fs.clone(), &format!("{directory}/wal"), options.wal_options)?;
+
+        let mut tree = LsmTree {
+            compacted: vec![None; levels.len()],
+            fs,
+            directory,
+            options,
+            wal,
+            memtable: Arc::default(),
+            levels,
+            obsolete: Vec::new(),
+            next_table: version.next_table,
+            log_start: version.log_start,
+            manifest,
+        };
+        tree.remove_orphans()?;
+        tree.replay()?;
+        Ok(tree)
+    }
+
+    /// Set the value of a key.
+    #[tracing::instrument(level = "trace", skip(self, value))]
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> LsmResult<()> {
+        self.log(RECORD_PUT, key, value)?;
+        Arc::make_mut(&mut self.memtable).put(key, value);
+        self.maybe_flush()
+    }
+
+    /// Delete a key.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn delete(&mut self, key: &[u8]) -> LsmResult<()> {
+        self.log(RECORD_DELETE, key, &[])?;
+        Arc::make_mut(&mut self.memtable).delete(key);
+        self.maybe_flush()
+    }
+
+    /// Apply every write of a batch, logged together so they are recovered all or none.
+    #[tracing::instrument(level = "trace", skip(self, batch), fields(writes = batch.len()))]
+    pub fn write(&mut self, batch: &WriteBatch) -> LsmResult<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.log(RECORD_BATCH, &[], &batch.encode()?)?;
+        apply_batch(Arc::make_mut(&mut self.memtable), batch);
+        self.maybe_flush()
+    }
+
+    /// Get the value of a key.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn get(&self, key: &[u8]) -> LsmResult<Option<Vec<u8>>> {
+        lookup(&self.memtable, &self.levels, key)
+    }
+
+    /// Iterate over the entries with keys between `start` and `end`, in key order.
+    pub fn scan(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> LsmScan<'_> {
+        scan(&self.memtable, &self.levels, start, end)
+    }
+
+    /// Take a snapshot of the tree, reading it as it is now regardless of later writes.
+    #[must_use]
+    pub fn snapshot(&self) -> LsmSnapshot<F::FileHandle> {
+        LsmSnapshot {
+            memtable: self.memtable.clone(),
+            levels: self.levels.clone(),
+        }
+    }
+
+    /// Number and size of the tables in each level.
+    #[must_use]
+    pub fn levels(&self) -> Vec<LevelStats> {
+        self.levels
+            .iter()
+            .map(|tables| LevelStats {
+                tables: tables.len(),
+                bytes: tables.iter().map(|table| table.info.size).sum(),
+            })
+            .collect()
+    }
+
+    /// Flush every logged write to storage.
+    pub fn sync(&mut self) -> LsmResult<()> {
+        Ok(self.wal.sync()?)
+    }
+
+    /// Write the memtable to a new level 0 table, then compact any level over its limit.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn flush(&mut self) -> LsmResult<()> {
+        if self.memtable.is_empty() {
+            return Ok(());
+        }
+        let id = self.allocate_table();
+        let handle = self.fs.create_file(&table_path(&self.directory, id))?;
+        let mut writer = SSTableWriter::new(
+            handle,
+            self.options.block_size,
+            self.options.bloom_bits_per_key,
+        );
+        for (key, value) in self.memtable.iter() {
+            writer.add(key, value)?;
+        }
+        let table = self.open_table(id, writer.finish()?)?;
+        tracing::debug!(table = id, entries = table.info.entries, "Flushed memtable");
+        self.levels[0].insert(0, table);
+        self.log_start = self.wal.next_lsn();
+        self.save_version()?;
+        self.memtable = Arc::default();
+        self.wal.remove_segments_before(self.log_start)?;
+        self.compact()
+    }
+
+    /// Compact levels until every level is within its limit.
+    pub fn compact(&mut self) -> LsmResult<()> {
+        while let Some(level) = self.pick_level() {
+            self.compact_level(level)?;
+        }
+        self.remove_obsolete()
+    }
+
+    fn log(&mut self, kind: u8, key: &[u8], value: &[u8]) -> LsmResult<()> {
+        let key_len = u32::try_from(key.len()).map_err(FileSystemError::wrap_error)?;
+        let mut record = Vec::with_capacity(5 + key.len() + value.len());
+        record.push(kind);
+        record.extend_from_slice(&key_len.to_le_bytes());
+        record.extend_from_slice(key);
+        record.extend_from_slice(value);
+        self.wal.append(&record)?;
+        Ok(())
+    }
+
+    fn maybe_flush(&mut self) -> LsmResult<()> {
+        if self.memtable.size() >= self.options.memtable_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Apply the logged writes not yet in a table to the memtable.
+    fn replay(&mut self) -> LsmResult<()> {
+        for record in self.wal.iter(self.log_start) {
+            let (lsn, record) = record?;
+            let Some((kind, key, value)) = parse_record(&record) else {
+                return Err(LsmError::CorruptLog(lsn));
+            };
+            let memtable = Arc::make_mut(&mut self.memtable);
+            match kind {
+                RECORD_PUT => memtable.put(key, value),
+                RECORD_DELETE => memtable.delete(key),
+                RECORD_BATCH => match WriteBatch::decode(value) {
+                    Some(batch) => apply_batch(memtable, &batch),
+                    None => return Err(LsmError::CorruptLog(lsn)),
+                },
+                _ => return Err(LsmError::CorruptLog(lsn)),
+            }
+        }
+        if !self.memtable.is_empty() {
+            tracing::debug!(entries = self.memtable.len(), "Replayed write-ahead log");
+        }
+        Ok(())
+    }
+
+    /// The level most in need of compaction, if any is over its limit.
+    fn pick_level(&self) -> Option<usize> {
+        if self.levels[0].len() >= self.options.level0_tables {
+            return Some(0);
+        }
+        let mut limit = self.options.level_size;
+        for level in 1..self.levels.len() - 1 {
+            if self.levels[level]
+                .iter()
+                .map(|table| table.info.size)
+                .sum::<u64>()
+                > limit
+            {
+                return Some(level);
+            }
+            limit = limit.saturating_mul(self.options.level_multiplier);
+        }
+        None
+    }
+
+    /// Merge tables of `level` with the overlapping tables of the next level.
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn compact_level(&mut self, level: usize) -> LsmResult<()> {
+        // All of level 0 is compacted at once, since its tables overlap, while later levels give
+        // up a table at a time, following the last key compacted.
+        let inputs = if level == 0 {
+            (0..self.levels[0].len()).collect::<Vec<_>>()
+        } else {
+            let tables = &self.levels[level];
+            let next = self.compacted[level].as_ref().map_or(0, |last| {
+                tables
+                    .iter()
+                    .position(|table| table.info.smallest > *last)
+                    .unwrap_or(0)
+            });
+            vec![next]
+        };
+        let smallest = inputs
+            .iter()
+            .map(|index| self.levels[level][*index].info.smallest.clone())
+            .min()
+            .unwrap_or_default();
+        let largest = inputs
+            .iter()
+            .map(|index| self.levels[level][*index].info.largest.clone())
+            .max()
+            .unwrap_or_default();
+        let overlapping = (0..self.levels[level + 1].len())
+            .filter(|index| {
+                self.levels[level + 1][*index]
+                    .overlaps(Bound::Included(&smallest), Bound::Included(&largest))
+            })
+            .collect::<Vec<_>>();
+        // Tombstones only need to outlive the older values they hide in deeper levels.
+        let drop_tombstones = self.levels[level + 2..].iter().all(Vec::is_empty);
+
+        let outputs = {
+            let sources = inputs
+                .iter()
+                .map(|index| &self.levels[level][*index])
+                .chain(
+                    overlapping
+                        .iter()
+                        .map(|index| &self.levels[level + 1][*index]),
+                )
+                .map(|table| Box::new(table.reader.iter()) as EntrySource<'_>)
+                .collect();
+            let merged = MergeIter::new(sources);
+            write_tables(
+                &self.fs,
+                &self.directory,
+                &self.options,
+                &mut self.next_table,
+                merged,
+                drop_tombstones,
+            )?
+        };
+        let outputs = outputs
+            .into_iter()
+            .map(|(id, info)| self.open_table(id, info))
+            .collect::<LsmResult<Vec<_>>>()?;
+
+        let mut removed = HashSet::new();
+        removed.extend(inputs.iter().map(|index| self.levels[level][*index].id));
+        removed.extend(
+            overlapping
+                .iter()
+                .map(|index| self.levels[level + 1][*index].id),
+        );
+        tracing::debug!(
+            level,
+            inputs = removed.len(),
+            outputs = outputs.len(),
+            drop_tombstones,
+            "Compacted level"
+        );
+        for level in [level, level + 1] {
+            let (kept, replaced) = std::mem::take(&mut self.levels[level])
+                .into_iter()
+                .partition(|table| !removed.contains(&table.id));
+            self.levels[level] = kept;
+            self.obsolete.extend::<Vec<_>>(replaced);
+        }
+        self.levels[level + 1].extend(outputs);
+        self.levels[level + 1].sort_by(|left, right| left.info.smallest.cmp(&right.info.smallest));
+        self.compacted[level] = Some(largest);
+        self.save_version()
+    }
+
+    /// Delete the files of compacted tables no longer read by any snapshot.
+    fn remove_obsolete(&mut self) -> LsmResult<()> {
+        let mut index = 0;
+        while index < self.obsolete.len() {
+            if Arc::strong_count(&self.obsolete[index]) == 1 {
+                let table = self.obsolete.swap_remove(index);
+                self.fs
+                    .remove_file(&table_path(&self.directory, table.id))?;
+            } else {
+                index += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn allocate_table(&mut self) -> u64 {
+        let id = self.next_table;
+        self.next_table += 1;
+        id
+    }
+
+    fn open_table(&self, id: u64, info: TableInfo) -> LsmResult<Arc<Table<F::FileHandle>>> {
+        let handle = self.fs.open_file(&table_path(&self.directory, id))?;
+        Ok(Arc::new(Table {
+            id,
+            info,
+            reader: SSTableReader::open(handle)?,
+        }))
+    }
+
+    /// Write a new manifest of the current tables, then remove the one before it.
+    fn save_version(&mut self) -> LsmResult<()> {
+        let version = Version {
+            next_table: self.next_table,
+            log_start: self.log_start,
+            levels: self
+                .levels
+                .iter()
+                .map(|tables| {
+                    tables
+                        .iter()
+                        .map(|table| TableEntry {
+                            id: table.id,
+                            info: table.info.clone(),
+                        })
+                        .collect()
+                })
+                .collect(),
+        };
+        let path = manifest_path(&self.directory, self.manifest + 1);
+        let mut handle = self.fs.create_file(&path)?;
+        handle
+            .write_all(version.encode().as_bytes())
+            .map_err(FileSystemError::io_error)?;
+        handle.sync_all()?;
+        self.manifest += 1;
+        match self
+            .fs
+            .remove_file(&manifest_path(&self.directory, self.manifest - 1))
+        {
+            Ok(()) | Err(FileSystemError::PathMissing) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Remove tables and manifests left behind by a crash during a flush or compaction.
+    fn remove_orphans(&self) -> LsmResult<()> {
+        let live = self
+            .levels
+            .iter()
+            .flatten()
+            .map(|table| table.id)
+            .collect::<HashSet<_>>();
+        for name in self.fs.list_directory(&self.directory)? {
+            let orphan = if let Some(id) = name.strip_suffix(TABLE_EXTENSION) {
+                id.parse().is_ok_and(|id| !live.contains(&id))
+            } else if let Some(sequence) = name.strip_prefix(MANIFEST_PREFIX) {
+                sequence.parse() != Ok(self.manifest)
+            } else {
+                false
+            };
+            if orphan {
+                tracing::debug!(name, "Removing orphaned file");
+                self.fs.remove_file(&format!("{}/{name}", self.directory))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Snapshot of an [`LsmTree`], returned by [`LsmTree::snapshot`].
+///
+/// ```rust
+/// use minql_lsm::{LsmOptions, LsmTree};
+/// use minql_vfs::MemoryFileSystem;
+///
+/// let mut tree = LsmTree::open(MemoryFileSystem::new(), "/db", LsmOptions::new()).unwrap();
+/// tree.put(b"apple", b"red").unwrap();
+/// let snapshot = tree.snapshot();
+/// tree.put(b"apple", b"green").unwrap();
+/// assert_eq!(snapshot.get(b"apple").unwrap(), Some(b"red".to_vec()));
+/// assert_eq!(tree.get(b"apple").unwrap(), Some(b"green".to_vec()));
+/// ```
+#[derive(Debug)]
+pub struct LsmSnapshot<H: FileHandle> {
+    memtable: Arc<MemTable>,
+    levels: Vec<Vec<Arc<Table<H>>>>,
+}
+
+impl<H: FileHandle> LsmSnapshot<H> {
+    /// Get the value of a key when the snapshot was taken.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn get(&self, key: &[u8]) -> LsmResult<Option<Vec<u8>>> {
+        lookup(&self.memtable, &self.levels, key)
+    }
+
+    /// Iterate over the entries with keys between `start` and `end` when the snapshot was
+    /// taken, in key order.
+    #[must_use]
+    pub fn scan(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> LsmScan<'_> {
+        scan(&self.memtable, &self.levels, start, end)
+    }
+}
+
+impl<H: FileHandle> Clone for LsmSnapshot<H> {
+    fn clone(&self) -> Self {
+        LsmSnapshot {
+            memtable: self.memtable.clone(),
+            levels: self.levels.clone(),
+        }
+    }
+}
+
+/// Iterator over a range of entries of an [`LsmTree`], returned by [`LsmTree::scan`].
+#[derive(Debug)]
+pub struct LsmScan<'a> {
+    merged: MergeIter<'a>,
+    end: Bound<Vec<u8>>,
+    done: bool,
+}
+
+impl Iterator for LsmScan<'_> {
+    type Item = LsmResult<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let (key, value) = match self.merged.next()? {
+                Ok(entry) => entry,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            };
+            let past = match &self.end {
+                Bound::Included(end) => key > *end,
+                Bound::Excluded(end) => key >= *end,
+                Bound::Unbounded => false,
+            };
+            if past {
+                self.done = true;
+            } else if let Some(value) = value {
+                return Some(Ok((key, value)));
+            }
+        }
+        None
+    }
+}
+
+/// Get the value of a key from a memtable and the tables below it.
+fn lookup<H: FileHandle>(
+    memtable: &MemTable,
+    levels: &[Vec<Arc<Table<H>>>],
+    key: &[u8],
+) -> LsmResult<Option<Vec<u8>>> {
+    if let Some(value) = memtable.get(key) {
+        return Ok(value.map(<[u8]>::to_vec));
+    }
+    for table in levels.iter().flatten() {
+        if table.overlaps(Bound::Included(key), Bound::Included(key)) {
+            if let Some(value) = table.reader.get(key)? {
+                return Ok(value);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Merge the entries between `start` and `end` of a memtable and the tables below it.
+fn scan<'a, H: FileHandle>(
+    memtable: &MemTable,
+    levels: &'a [Vec<Arc<Table<H>>>],
+    start: Bound<&[u8]>,
+    end: Bound<&[u8]>,
+) -> LsmScan<'a> {
+    let memtable = memtable.range(start, end);
+    let mut sources: Vec<EntrySource<'a>> = vec![Box::new(memtable.into_iter().map(Ok))];
+    if !is_empty_range(start, end) {
+        for table in levels.iter().flatten() {
+            if table.overlaps(start, end) {
+                sources.push(Box::new(table.reader.iter_from(start)));
+            }
+        }
+    }
+    LsmScan {
+        merged: MergeIter::new(sources),
+        end: end.map(<[u8]>::to_vec),
+        done: false,
+    }
+}
+
+/// Apply the writes of a batch to a memtable.
+fn apply_batch(memtable: &mut MemTable, batch: &WriteBatch) {
+    for (key, value) in batch.iter() {
+        match value {
+            Some(value) => memtable.put(key, value),
+            None => memtable.delete(key),
+        }
+    }
+}
+
+/// Load the newest intact manifest and its sequence number, or an empty version if there is
+/// none.
+fn load_version<F: FileSystem>(fs: &F, directory: &str) -> LsmResult<(u64, Version)> {
+    let mut sequences = fs
+        .list_directory(directory)?
+        .iter()
+        .filter_map(|name| name.strip_prefix(MANIFEST_PREFIX)?.parse::<u64>().ok())
+        .collect::<Vec<_>>();
+    sequences.sort_unstable();
+    for sequence in sequences.into_iter().rev() {
+        let text = fs.read(&manifest_path(directory, sequence))?;
+        if let Some(version) = std::str::from_utf8(&text).ok().and_then(Version::decode) {
+            return Ok((sequence, version));
+        }
+        tracing::warn!(sequence, "Skipping damaged manifest");
+    }
+    Ok((0, Version::default()))
+}
+
+/// Write merged entries to new tables of about the table size.
+fn write_tables<F: FileSystem>(
+    fs: &F,
+    directory: &str,
+    options: &LsmOptions,
+    next_table: &mut u64,
+    entries: MergeIter<'_>,
+    drop_tombstones: bool,
+) -> LsmResult<Vec<(u64, TableInfo)>> {
+    let mut outputs = Vec::new();
+    let mut writer = None;
+    for entry in entries {
+        let (key, value): Entry = entry?;
+        if value.is_none() && drop_tombstones {
+            continue;
+        }
+        if writer.is_none() {
+            let id = *next_table;
+            *next_table += 1;
+            let handle = fs.create_file(&table_path(directory, id))?;
+            let table = SSTableWriter::new(handle, options.block_size, options.bloom_bits_per_key);
+            writer = Some((id, table));
+        }
+        let (_, table) = writer.as_mut().expect("Table Writer");
+        table.add(&key, value.as_deref())?;
+        if table.estimated_size() >= options.table_size {
+            let (id, table) = writer.take().expect("Table Writer");
+            outputs.push((id, table.finish()?));
+        }
+    }
+    if let Some((id, table)) = writer {
+        outputs.push((id, table.finish()?));
+    }
+    Ok(outputs)
+}
+
+/// Split a log record into its kind, key and value.
+fn parse_record(record: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&kind, rest) = record.split_first()?;
+    let key_len = usize::try_from(u32::from_le_bytes(rest.get(..4)?.try_into().ok()?)).ok()?;
+    let key = rest.get(4..4 + key_len)?;
+    Some((kind, key, &rest[4 + key_len..]))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::manifest::TABLE_EXTENSION;
+    use crate::{LsmOptions, LsmTree, WriteBatch};
+    use minql_vfs::{FileSystem, MemoryFileSystem};
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+
+    /// Options small enough that a few thousand writes flush and compact through every level.
+    fn options() -> LsmOptions {
+        LsmOptions::new()
+            .with_memtable_size(1024)
+            .with_block_size(256)
+            .with_table_size(2048)
+            .with_level0_tables(2)
+            .with_level_size(4096)
+            .with_level_multiplier(2)
+            .with_levels(4)
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key{index:04}").into_bytes()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_lsm_tree() {
+        let fs = MemoryFileSystem::new();
+        let mut tree = LsmTree::open(fs.clone(), "/db", options()).unwrap();
+        let mut expected = BTreeMap::new();
+        for round in 0..3 {
+            for index in 0..500 {
+                let index = index * 7 % 500;
+                if (index + round) % 4 == 0 {
+                    tree.delete(&key(index)).unwrap();
+                    expected.remove(&key(index));
+                } else {
+                    let value = format!("value{round}-{index}").into_bytes();
+                    tree.put(&key(index), &value).unwrap();
+                    expected.insert(key(index), value);
+                }
+            }
+        }
+        let levels = tree.levels();
+        assert!(levels[0].tables < 2);
+        assert!(levels[2..].iter().any(|level| level.tables > 0));
+        assert_eq!(tree.get(&key(1)).unwrap().as_ref(), expected.get(&key(1)));
+        assert_eq!(tree.get(&key(4)).unwrap().as_ref(), expected.get(&key(4)));
+        assert_eq!(tree.get(b"missing").unwrap(), None);
+        let entries = tree
+            .scan(Bound::Unbounded, Bound::Unbounded)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(entries, expected.clone().into_iter().collect::<Vec<_>>());
+        let (start, end) = (key(100), key(120));
+        let entries = tree
+            .scan(Bound::Excluded(&start), Bound::Included(&end))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let range = expected.range(key(101)..=key(120));
+        assert_eq!(
+            entries,
+            range
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            tree.scan(Bound::Included(&end), Bound::Excluded(&start))
+                .count(),
+            0
+        );
+
+        // Writes still in the memtable are replayed from the log, while a damaged manifest and
+        // tables it doesn't reference are left over from a crash
+        tree.put(b"unflushed", b"logged").unwrap();
+        expected.insert(b"unflushed".to_vec(), b"logged".to_vec());
+        drop(tree);
+        fs.write("/db/MANIFEST-999999", b"minql-lsm 1\nnext_table")
+            .unwrap();
+        fs.write("/db/999998.sst", b"partial").unwrap();
+        let tree = LsmTree::open(fs.clone(), "/db", options()).unwrap();
+        assert!(!fs.exists("/db/MANIFEST-999999").unwrap());
+        assert!(!fs.exists("/db/999998.sst").unwrap());
+        let entries = tree
+            .scan(Bound::Unbounded, Bound::Unbounded)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(entries, expected.into_iter().collect::<Vec<_>>());
+        let manifests = fs
+            .list_directory("/db")
+            .unwrap()
+            .into_iter()
+            .filter(|name| name.starts_with("MANIFEST-"))
+            .count();
+        assert_eq!(manifests, 1);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_lsm_snapshot() {
+        let fs = MemoryFileSystem::new();
+        let mut tree = LsmTree::open(fs.clone(), "/db", options()).unwrap();
+        for index in 0..200 {
+            tree.put(&key(index), b"before").unwrap();
+        }
+        let snapshot = tree.snapshot();
+        let tables = |fs: &MemoryFileSystem| {
+            fs.list_directory("/db")
+                .unwrap()
+                .into_iter()
+                .filter(|name| name.ends_with(TABLE_EXTENSION))
+                .collect::<Vec<_>>()
+        };
+        let live = |tree: &LsmTree<MemoryFileSystem>| {
+            tree.levels()
+                .iter()
+                .map(|level| level.tables)
+                .sum::<usize>()
+        };
+        let held = tables(&fs);
+
+        let mut batch = WriteBatch::new();
+        for index in 0..200 {
+            if index % 2 == 0 {
+                batch.put(&key(index), b"after");
+            } else {
+                batch.delete(&key(index));
+            }
+        }
+        tree.write(&batch).unwrap();
+        tree.flush().unwrap();
+        for round in 0..10 {
+            for index in 200..400 {
+                tree.put(&key(index), format!("{round}").as_bytes())
+                    .unwrap();
+            }
+        }
+        assert_eq!(tree.get(&key(0)).unwrap(), Some(b"after".to_vec()));
+        assert_eq!(tree.get(&key(1)).unwrap(), None);
+
+        // Compacted tables outlive the compaction while the snapshot reads them
+        let current = tables(&fs);
+        assert!(held.iter().all(|name| current.contains(name)));
+        assert!(current.len() > live(&tree));
+        assert_eq!(snapshot.get(&key(1)).unwrap(), Some(b"before".to_vec()));
+        assert_eq!(snapshot.get(&key(250)).unwrap(), None);
+        let entries = snapshot
+            .scan(Bound::Unbounded, Bound::Unbounded)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(entries.len(), 200);
+        assert!(entries.iter().all(|(_, value)| value == b"before"));
+        drop(snapshot);
+        tree.compact().unwrap();
+        assert_eq!(tables(&fs).len(), live(&tree));
+
+        // A logged batch is replayed whole
+        let mut batch = WriteBatch::new();
+        batch.put(b"batched", b"one").delete(&key(0));
+        tree.write(&batch).unwrap();
+        drop(tree);
+        let tree = LsmTree::open(fs, "/db", options()).unwrap();
+        assert_eq!(tree.get(b"batched").unwrap(), Some(b"one".to_vec()));
+        assert_eq!(tree.get(&key(0)).unwrap(), None);
+    }
+}
@@ -0,0 +1,53 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Log Structured Merge Tree Storage Engine
+//!
+//! An [`LsmTree`] suited to write heavy workloads, buffering writes in a [`MemTable`] and
+//! writing them out as immutable sorted tables through an [`SSTableWriter`], which are merged
+//! by leveled compaction. Every file is read and written through the `FileSystem` abstraction
+//! of `minql-vfs`, so a tree runs on memory, local disk, or object storage alike.
+
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod batch;
+mod bloom;
+mod engine;
+mod manifest;
+mod memtable;
+mod merge;
+mod result;
+mod sstable;
+
+pub use self::batch::WriteBatch;
+pub use self::bloom::BloomFilter;
+pub use self::engine::{LevelStats, LsmOptions, LsmScan, LsmSnapshot, LsmTree};
+pub use self::memtable::MemTable;
+pub use self::result::{LsmError, LsmResult};
+pub use self::sstable::{SSTableIter, SSTableReader, SSTableWriter, TableInfo};
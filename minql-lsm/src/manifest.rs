@@ -0,0 +1,199 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::TableInfo;
+use minql_vfs::Lsn;
+use std::fmt::Write;
+
+/// First line of every manifest.
+const MANIFEST_HEADER: &str = "minql-lsm 1";
+
+/// Prefix of manifest file names, followed by their sequence number.
+pub(crate) const MANIFEST_PREFIX: &str = "MANIFEST-";
+
+/// Extension of table file names.
+pub(crate) const TABLE_EXTENSION: &str = ".sst";
+
+/// A table recorded in a [`Version`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct TableEntry {
+    pub id: u64,
+    pub info: TableInfo,
+}
+
+/// The tables of each level and where the log resumes, saved as a manifest.
+///
+/// Manifests are text, a line per table, ended by a CRC32 of the lines before it so a torn
+/// manifest is never mistaken for a complete one.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Version {
+    /// Id of the next table written
+    pub next_table: u64,
+    /// Position in the log of the first write not yet in a table
+    pub log_start: Lsn,
+    /// Tables of each level, newest first in level 0 and by key elsewhere
+    pub levels: Vec<Vec<TableEntry>>,
+}
+
+impl Version {
+    pub fn encode(&self) -> String {
+        let mut text = format!("{MANIFEST_HEADER}\n");
+        writeln!(text, "next_table {}", self.next_table).expect("Write Manifest");
+        writeln!(
+            text,
+            "log_start {} {}",
+            self.log_start.segment, self.log_start.offset
+        )
+        .expect("Write Manifest");
+        for (level, tables) in self.levels.iter().enumerate() {
+            for table in tables {
+                writeln!(
+                    text,
+                    "table {level} {} {} {} {} {}",
+                    table.id,
+                    table.info.size,
+                    table.info.entries,
+                    to_hex(&table.info.smallest),
+                    to_hex(&table.info.largest)
+                )
+                .expect("Write Manifest");
+            }
+        }
+        writeln!(text, "crc {:08x}", crc32fast::hash(text.as_bytes())).expect("Write Manifest");
+        text
+    }
+
+    /// Decode a manifest, or `None` if it's damaged or incomplete.
+    pub fn decode(text: &str) -> Option<Version> {
+        let body_end = text.trim_end_matches('\n').rfind('\n')? + 1;
+        let (body, checksum) = text.split_at(body_end);
+        let checksum = u32::from_str_radix(checksum.trim().strip_prefix("crc ")?, 16).ok()?;
+        if crc32fast::hash(body.as_bytes()) != checksum {
+            return None;
+        }
+        let mut lines = body.lines();
+        if lines.next()? != MANIFEST_HEADER {
+            return None;
+        }
+        let mut version = Version::default();
+        for line in lines {
+            let fields = line.split(' ').collect::<Vec<_>>();
+            match fields.as_slice() {
+                ["next_table", next] => version.next_table = next.parse().ok()?,
+                ["log_start", segment, offset] => {
+                    version.log_start = Lsn {
+                        segment: segment.parse().ok()?,
+                        offset: offset.parse().ok()?,
+                    };
+                }
+                ["table", level, id, size, entries, smallest, largest] => {
+                    let level = level.parse::<usize>().ok()?;
+                    if version.levels.len() <= level {
+                        version.levels.resize(level + 1, Vec::new());
+                    }
+                    version.levels[level].push(TableEntry {
+                        id: id.parse().ok()?,
+                        info: TableInfo {
+                            smallest: from_hex(smallest)?,
+                            largest: from_hex(largest)?,
+                            size: size.parse().ok()?,
+                            entries: entries.parse().ok()?,
+                        },
+                    });
+                }
+                _ => return None,
+            }
+        }
+        Some(version)
+    }
+}
+
+/// Path of the manifest with sequence number `sequence`.
+pub(crate) fn manifest_path(directory: &str, sequence: u64) -> String {
+    format!("{directory}/{MANIFEST_PREFIX}{sequence:06}")
+}
+
+/// Path of the table `id`.
+pub(crate) fn table_path(directory: &str, id: u64) -> String {
+    format!("{directory}/{id:06}{TABLE_EXTENSION}")
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return "-".to_string();
+    }
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        write!(hex, "{byte:02x}").expect("Write Hex");
+        hex
+    })
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex == "-" {
+        return Some(Vec::new());
+    }
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TableEntry, Version};
+    use crate::TableInfo;
+    use minql_vfs::Lsn;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_version_encoding() {
+        let version = Version {
+            next_table: 9,
+            log_start: Lsn {
+                segment: 2,
+                offset: 128,
+            },
+            levels: vec![
+                vec![TableEntry {
+                    id: 8,
+                    info: TableInfo {
+                        smallest: b"a".to_vec(),
+                        largest: b"m\xff".to_vec(),
+                        size: 4096,
+                        entries: 12,
+                    },
+                }],
+                Vec::new(),
+                vec![TableEntry {
+                    id: 3,
+                    info: TableInfo {
+                        smallest: Vec::new(),
+                        largest: b"z".to_vec(),
+                        size: 100,
+                        entries: 1,
+                    },
+                }],
+            ],
+        };
+        let text = version.encode();
+        assert_eq!(Version::decode(&text), Some(version));
+        assert_eq!(Version::decode(&text[..text.len() - 4]), None);
+        assert_eq!(Version::decode(&text.replace("4096", "4097")), None);
+    }
+}
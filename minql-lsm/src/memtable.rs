@@ -0,0 +1,152 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::merge::Entry;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+/// Memtable
+///
+/// Sorted in-memory buffer of the latest writes to an [`LsmTree`](crate::LsmTree). A deleted
+/// key is kept as a tombstone, hiding older values of the key in tables until compaction
+/// drops both. The approximate bytes held are tracked so the memtable can be flushed to a table
+/// once it grows large enough.
+#[derive(Clone, Debug, Default)]
+pub struct MemTable {
+    entries: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    size: usize,
+}
+
+impl MemTable {
+    /// Create an empty memtable.
+    #[must_use]
+    pub fn new() -> MemTable {
+        MemTable::default()
+    }
+
+    /// Set the value of a key.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.set(key, Some(value.to_vec()));
+    }
+
+    /// Replace the value of a key with a tombstone.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.set(key, None);
+    }
+
+    /// Get the entry of a key, which is `Some(None)` for a tombstone, or `None` if the key
+    /// hasn't been written.
+    #[must_use]
+    pub fn get(&self, key: &[u8]) -> Option<Option<&[u8]>> {
+        self.entries.get(key).map(Option::as_deref)
+    }
+
+    /// Number of entries, including tombstones.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if the memtable holds no entries.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Approximate bytes held by the keys and values.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Iterate over every entry in key order, with `None` for tombstones.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_slice(), value.as_deref()))
+    }
+
+    /// Remove every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+
+    /// Copy the entries between `start` and `end`.
+    pub(crate) fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<Entry> {
+        if is_empty_range(start, end) {
+            return Vec::new();
+        }
+        self.entries
+            .range::<[u8], _>((start, end))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    fn set(&mut self, key: &[u8], value: Option<Vec<u8>>) {
+        let added = value.as_ref().map_or(0, Vec::len);
+        match self.entries.insert(key.to_vec(), value) {
+            Some(previous) => self.size -= previous.map_or(0, |value| value.len()),
+            None => self.size += key.len(),
+        }
+        self.size += added;
+    }
+}
+
+/// Check if no key lies between `start` and `end`, which a `BTreeMap` range rejects.
+pub(crate) fn is_empty_range(start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+    match (start, end) {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start) | Bound::Excluded(start), Bound::Excluded(end))
+        | (Bound::Excluded(start), Bound::Included(end)) => start >= end,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MemTable;
+    use std::ops::Bound;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memtable() {
+        let mut memtable = MemTable::new();
+        memtable.put(b"b", b"two");
+        memtable.put(b"a", b"one");
+        memtable.put(b"b", b"second");
+        memtable.delete(b"c");
+        assert_eq!(memtable.len(), 3);
+        assert_eq!(memtable.size(), 3 + 3 + 6);
+        assert_eq!(memtable.get(b"b"), Some(Some(&b"second"[..])));
+        assert_eq!(memtable.get(b"c"), Some(None));
+        assert_eq!(memtable.get(b"d"), None);
+        let range = memtable.range(Bound::Excluded(b"a"), Bound::Unbounded);
+        assert_eq!(
+            range,
+            vec![
+                (b"b".to_vec(), Some(b"second".to_vec())),
+                (b"c".to_vec(), None)
+            ]
+        );
+        assert!(memtable
+            .range(Bound::Excluded(b"b"), Bound::Excluded(b"b"))
+            .is_empty());
+        memtable.clear();
+        assert!(memtable.is_empty());
+        assert_eq!(memtable.size(), 0);
+    }
+}
@@ -0,0 +1,134 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::LsmResult;
+
+/// A key and its value, or `None` for a tombstone.
+pub(crate) type Entry = (Vec<u8>, Option<Vec<u8>>);
+
+/// Boxed source of sorted entries.
+pub(crate) type EntrySource<'a> = Box<dyn Iterator<Item = LsmResult<Entry>> + 'a>;
+
+/// Merges sorted sources into one sorted stream, where a key found in several sources is taken
+/// from the earliest, so sources are given newest first.
+pub(crate) struct MergeIter<'a> {
+    sources: Vec<EntrySource<'a>>,
+    /// Next entry of each source, filled once iteration starts
+    heads: Vec<Option<Entry>>,
+    started: bool,
+}
+
+impl<'a> MergeIter<'a> {
+    pub fn new(sources: Vec<EntrySource<'a>>) -> MergeIter<'a> {
+        MergeIter {
+            heads: vec![None; sources.len()],
+            sources,
+            started: false,
+        }
+    }
+
+    fn fill(&mut self, index: usize) -> LsmResult<()> {
+        self.heads[index] = self.sources[index].next().transpose()?;
+        Ok(())
+    }
+
+    fn advance(&mut self) -> LsmResult<Option<Entry>> {
+        if !self.started {
+            self.started = true;
+            for index in 0..self.sources.len() {
+                self.fill(index)?;
+            }
+        }
+        let Some((_, newest)) = self
+            .heads
+            .iter()
+            .enumerate()
+            .filter_map(|(index, head)| head.as_ref().map(|(key, _)| (key, index)))
+            .min()
+        else {
+            return Ok(None);
+        };
+        let entry = self.heads[newest].take().expect("Merge Head");
+        // Older entries of the same key are shadowed by the newest.
+        for index in 0..self.heads.len() {
+            if index == newest
+                || self.heads[index]
+                    .as_ref()
+                    .is_some_and(|(key, _)| *key == entry.0)
+            {
+                self.fill(index)?;
+            }
+        }
+        Ok(Some(entry))
+    }
+}
+
+impl std::fmt::Debug for MergeIter<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MergeIter")
+            .field("sources", &self.sources.len())
+            .field("heads", &self.heads)
+            .finish()
+    }
+}
+
+impl Iterator for MergeIter<'_> {
+    type Item = LsmResult<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.advance().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Entry, EntrySource, MergeIter};
+
+    fn source(entries: &[(&str, Option<&str>)]) -> EntrySource<'static> {
+        let entries = entries
+            .iter()
+            .map(|(key, value)| {
+                let entry: Entry = (
+                    key.as_bytes().to_vec(),
+                    value.map(|v| v.as_bytes().to_vec()),
+                );
+                Ok(entry)
+            })
+            .collect::<Vec<_>>();
+        Box::new(entries.into_iter())
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_merge_iter() {
+        let merged = MergeIter::new(vec![
+            source(&[("b", Some("new")), ("d", None)]),
+            source(&[("a", Some("old")), ("b", Some("old")), ("d", Some("old"))]),
+            source(&[("c", Some("oldest")), ("d", Some("oldest"))]),
+        ])
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        let expected = source(&[
+            ("a", Some("old")),
+            ("b", Some("new")),
+            ("c", Some("oldest")),
+            ("d", None),
+        ])
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        assert_eq!(merged, expected);
+    }
+}
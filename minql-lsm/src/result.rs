@@ -0,0 +1,54 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use minql_vfs::{FileSystemError, Lsn};
+
+/// Result Type for LSM Trees
+pub type LsmResult<T> = Result<T, LsmError>;
+
+/// Error Type for LSM Trees
+#[derive(Debug)]
+pub enum LsmError {
+    /// Key was added to a table out of order
+    UnsortedKey(Vec<u8>),
+    /// Table file is damaged or isn't a table
+    CorruptTable(String),
+    /// Log record can't be replayed
+    CorruptLog(Lsn),
+    /// Error of the underlying `FileSystem`
+    FileSystem(FileSystemError),
+}
+
+impl std::fmt::Display for LsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for LsmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LsmError::FileSystem(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<FileSystemError> for LsmError {
+    fn from(err: FileSystemError) -> Self {
+        LsmError::FileSystem(err)
+    }
+}
@@ -0,0 +1,491 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::bloom::hash_key;
+use crate::merge::Entry;
+use crate::{BloomFilter, LsmError, LsmResult};
+use minql_vfs::{FileHandle, FileSystemError};
+use std::collections::VecDeque;
+use std::ops::Bound;
+use std::sync::Mutex;
+
+/// Magic bytes ending every table.
+const TABLE_MAGIC: &[u8; 8] = b"MQLSST01";
+
+/// Bytes of the footer locating the index and filter.
+const FOOTER_SIZE: usize = 40;
+
+/// Bytes of the CRC32 trailing every block.
+const CHECKSUM_SIZE: usize = 4;
+
+const KIND_TOMBSTONE: u8 = 0;
+const KIND_VALUE: u8 = 1;
+
+/// Summary of a table written by an [`SSTableWriter`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TableInfo {
+    /// First key in the table
+    pub smallest: Vec<u8>,
+    /// Last key in the table
+    pub largest: Vec<u8>,
+    /// Size of the table file in bytes
+    pub size: u64,
+    /// Number of entries, including tombstones
+    pub entries: u64,
+}
+
+/// Location of a data block, found by the last key it holds.
+#[derive(Clone, Debug)]
+struct BlockHandle {
+    last: Vec<u8>,
+    offset: u64,
+    len: u32,
+}
+
+/// Sorted Table Writer
+///
+/// Writes entries, added in key order, to an immutable sorted table. Entries are packed into
+/// data blocks of about the block size, each followed by a CRC32. After the last block come an
+/// index holding the last key and location of every block, a [`BloomFilter`] of every key, and
+/// a footer locating both, so a lookup reads at most one data block and skips the table
+/// entirely when the filter rules the key out.
+///
+/// ```rust
+/// use minql_lsm::{SSTableReader, SSTableWriter};
+/// use minql_vfs::{FileSystem, MemoryFileSystem};
+///
+/// let fs = MemoryFileSystem::new();
+/// let mut writer = SSTableWriter::new(fs.create_file("/000001.sst").unwrap(), 4096, 10);
+/// writer.add(b"apple", Some(b"red")).unwrap();
+/// writer.add(b"banana", None).unwrap();
+/// let info = writer.finish().unwrap();
+/// assert_eq!(info.entries, 2);
+///
+/// let reader = SSTableReader::open(fs.open_file("/000001.sst").unwrap()).unwrap();
+/// assert_eq!(reader.get(b"apple").unwrap(), Some(Some(b"red".to_vec())));
+/// assert_eq!(reader.get(b"banana").unwrap(), Some(None));
+/// assert_eq!(reader.get(b"cherry").unwrap(), None);
+/// ```
+#[derive(Debug)]
+pub struct SSTableWriter<H: FileHandle> {
+    handle: H,
+    block_size: usize,
+    bits_per_key: usize,
+    offset: u64,
+    block: Vec<u8>,
+    index: Vec<BlockHandle>,
+    hashes: Vec<u64>,
+    info: TableInfo,
+}
+
+impl<H: FileHandle> SSTableWriter<H> {
+    /// Write a table to an empty file, aiming for `block_size` byte blocks and spending
+    /// `bits_per_key` bits of the filter on each key.
+    pub fn new(handle: H, block_size: usize, bits_per_key: usize) -> SSTableWriter<H> {
+        SSTableWriter {
+            handle,
+            block_size,
+            bits_per_key,
+            offset: 0,
+            block: Vec::new(),
+            index: Vec::new(),
+            hashes: Vec::new(),
+            info: TableInfo::default(),
+        }
+    }
+
+    /// Add an entry, where a `None` value is a tombstone. Keys must be added in increasing
+    /// order.
+    pub fn add(&mut self, key: &[u8], value: Option<&[u8]>) -> LsmResult<()> {
+        if self.info.entries > 0 && key <= self.info.largest.as_slice() {
+            return Err(LsmError::UnsortedKey(key.to_vec()));
+        }
+        let (kind, value) = match value {
+            Some(value) => (KIND_VALUE, value),
+            None => (KIND_TOMBSTONE, &[][..]),
+        };
+        self.block
+            .extend_from_slice(&to_u32(key.len())?.to_le_bytes());
+        self.block.push(kind);
+        self.block
+            .extend_from_slice(&to_u32(value.len())?.to_le_bytes());
+        self.block.extend_from_slice(key);
+        self.block.extend_from_slice(value);
+        self.hashes.push(hash_key(key));
+        if self.info.entries == 0 {
+            self.info.smallest = key.to_vec();
+        }
+        self.info.largest = key.to_vec();
+        self.info.entries += 1;
+        if self.block.len() >= self.block_size {
+            self.finish_block()?;
+        }
+        Ok(())
+    }
+
+    /// Number of entries added.
+    #[must_use]
+    pub fn entries(&self) -> u64 {
+        self.info.entries
+    }
+
+    /// Bytes written so far, including the block being filled.
+    #[must_use]
+    pub fn estimated_size(&self) -> u64 {
+        self.offset + self.block.len() as u64
+    }
+
+    /// Write the index, filter and footer, and sync the file.
+    pub fn finish(mut self) -> LsmResult<TableInfo> {
+        self.finish_block()?;
+        let mut index = Vec::new();
+        for block in &self.index {
+            index.extend_from_slice(&to_u32(block.last.len())?.to_le_bytes());
+            index.extend_from_slice(&block.last);
+            index.extend_from_slice(&block.offset.to_le_bytes());
+            index.extend_from_slice(&block.len.to_le_bytes());
+        }
+        let mut filter = BloomFilter::new(self.hashes.len(), self.bits_per_key);
+        for hash in &self.hashes {
+            filter.insert_hash(*hash);
+        }
+        let (index_offset, index_len) = self.write_block(&index)?;
+        let (filter_offset, filter_len) = self.write_block(&filter.encode())?;
+
+        let mut footer = Vec::with_capacity(FOOTER_SIZE);
+        footer.extend_from_slice(&index_offset.to_le_bytes());
+        footer.extend_from_slice(&index_len.to_le_bytes());
+        footer.extend_from_slice(&filter_offset.to_le_bytes());
+        footer.extend_from_slice(&filter_len.to_le_bytes());
+        footer.extend_from_slice(&self.info.entries.to_le_bytes());
+        footer.extend_from_slice(TABLE_MAGIC);
+        self.write(&footer)?;
+        self.handle.sync_all()?;
+        self.info.size = self.offset;
+        Ok(self.info)
+    }
+
+    fn finish_block(&mut self) -> LsmResult<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let block = std::mem::take(&mut self.block);
+        let (offset, len) = self.write_block(&block)?;
+        self.index.push(BlockHandle {
+            last: self.info.largest.clone(),
+            offset,
+            len,
+        });
+        Ok(())
+    }
+
+    /// Write a block followed by its checksum, returning its offset and length.
+    fn write_block(&mut self, block: &[u8]) -> LsmResult<(u64, u32)> {
+        let offset = self.offset;
+        self.write(block)?;
+        self.write(&crc32fast::hash(block).to_le_bytes())?;
+        Ok((offset, to_u32(block.len())?))
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> LsmResult<()> {
+        self.handle
+            .write_all(bytes)
+            .map_err(FileSystemError::io_error)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// Sorted Table Reader
+///
+/// Reads a table written by an [`SSTableWriter`], holding its index and filter in memory and
+/// reading data blocks on demand. Every block is checked against its CRC32 when read.
+#[derive(Debug)]
+pub struct SSTableReader<H: FileHandle> {
+    handle: Mutex<H>,
+    path: String,
+    index: Vec<BlockHandle>,
+    filter: BloomFilter,
+    entries: u64,
+}
+
+impl<H: FileHandle> SSTableReader<H> {
+    /// Open a table, reading its index and filter.
+    pub fn open(handle: H) -> LsmResult<SSTableReader<H>> {
+        let mut reader = SSTableReader {
+            path: handle.path().to_string(),
+            handle: Mutex::new(handle),
+            index: Vec::new(),
+            filter: BloomFilter::new(0, 1),
+            entries: 0,
+        };
+        let size = reader.handle.get_mut().expect("Poisoned Lock").get_size()?;
+        let Some(footer_offset) = size.checked_sub(FOOTER_SIZE as u64) else {
+            return Err(reader.corrupt());
+        };
+        let footer = reader.read(footer_offset, FOOTER_SIZE)?;
+        if &footer[32..] != TABLE_MAGIC {
+            return Err(reader.corrupt());
+        }
+        let index = reader.read_block(read_u64(&footer, 0), read_u32(&footer, 8))?;
+        let filter = reader.read_block(read_u64(&footer, 12), read_u32(&footer, 20))?;
+        reader.filter = BloomFilter::decode(&filter).ok_or_else(|| reader.corrupt())?;
+        reader.index = parse_index(&index).ok_or_else(|| reader.corrupt())?;
+        reader.entries = read_u64(&footer, 24);
+        Ok(reader)
+    }
+
+    /// Path of the table file.
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Number of entries, including tombstones.
+    #[must_use]
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    /// Get the entry of a key, which is `Some(None)` for a tombstone, or `None` if the table
+    /// doesn't hold the key.
+    pub fn get(&self, key: &[u8]) -> LsmResult<Option<Option<Vec<u8>>>> {
+        if !self.filter.may_contain(key) {
+            return Ok(None);
+        }
+        let block = self
+            .index
+            .partition_point(|block| block.last.as_slice() < key);
+        if block == self.index.len() {
+            return Ok(None);
+        }
+        Ok(self
+            .read_entries(block)?
+            .into_iter()
+            .find(|(entry, _)| entry == key)
+            .map(|(_, value)| value))
+    }
+
+    /// Iterate over every entry in key order.
+    pub fn iter(&self) -> SSTableIter<'_, H> {
+        self.iter_from(Bound::Unbounded)
+    }
+
+    /// Iterate over the entries from `start` onwards, in key order.
+    pub fn iter_from(&self, start: Bound<&[u8]>) -> SSTableIter<'_, H> {
+        let block = match start {
+            Bound::Included(start) | Bound::Excluded(start) => self
+                .index
+                .partition_point(|block| block.last.as_slice() < start),
+            Bound::Unbounded => 0,
+        };
+        SSTableIter {
+            reader: self,
+            block,
+            entries: VecDeque::new(),
+            start: Some(start.map(<[u8]>::to_vec)),
+        }
+    }
+
+    /// Read and parse the data block at `block` in the index.
+    fn read_entries(&self, block: usize) -> LsmResult<Vec<Entry>> {
+        let handle = &self.index[block];
+        let data = self.read_block(handle.offset, handle.len)?;
+        parse_block(&data).ok_or_else(|| self.corrupt())
+    }
+
+    /// Read a block and check its checksum.
+    fn read_block(&self, offset: u64, len: u32) -> LsmResult<Vec<u8>> {
+        let len = usize::try_from(len).expect("Block Length");
+        let mut data = self.read(offset, len + CHECKSUM_SIZE)?;
+        let checksum = data.split_off(len);
+        if crc32fast::hash(&data).to_le_bytes()[..] != checksum[..] {
+            return Err(self.corrupt());
+        }
+        Ok(data)
+    }
+
+    fn read(&self, offset: u64, len: usize) -> LsmResult<Vec<u8>> {
+        let mut handle = self.handle.lock().expect("Poisoned Lock");
+        let mut buffer = vec![0; len];
+        let mut filled = 0;
+        while filled < len {
+            match handle.read_at_offset(offset + filled as u64, &mut buffer[filled..])? {
+                0 => return Err(self.corrupt()),
+                read => filled += read,
+            }
+        }
+        Ok(buffer)
+    }
+
+    fn corrupt(&self) -> LsmError {
+        LsmError::CorruptTable(self.path.clone())
+    }
+}
+
+impl<'a, H: FileHandle> IntoIterator for &'a SSTableReader<H> {
+    type Item = LsmResult<(Vec<u8>, Option<Vec<u8>>)>;
+    type IntoIter = SSTableIter<'a, H>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the entries of an [`SSTableReader`], reading a block at a time.
+#[derive(Debug)]
+pub struct SSTableIter<'a, H: FileHandle> {
+    reader: &'a SSTableReader<H>,
+    /// Next block to read
+    block: usize,
+    /// Entries of the current block not yet returned
+    entries: VecDeque<Entry>,
+    /// Start of the range, until the first block is read
+    start: Option<Bound<Vec<u8>>>,
+}
+
+impl<H: FileHandle> Iterator for SSTableIter<'_, H> {
+    type Item = LsmResult<(Vec<u8>, Option<Vec<u8>>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.entries.is_empty() {
+            if self.block >= self.reader.index.len() {
+                return None;
+            }
+            let entries = match self.reader.read_entries(self.block) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    self.block = self.reader.index.len();
+                    return Some(Err(err));
+                }
+            };
+            self.block += 1;
+            self.entries = entries.into();
+            match self.start.take() {
+                Some(Bound::Included(start)) => self.entries.retain(|(key, _)| *key >= start),
+                Some(Bound::Excluded(start)) => self.entries.retain(|(key, _)| *key > start),
+                Some(Bound::Unbounded) | None => {}
+            }
+        }
+        self.entries.pop_front().map(Ok)
+    }
+}
+
+fn parse_block(mut data: &[u8]) -> Option<Vec<Entry>> {
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        let key_len = usize::try_from(read_u32(data.get(..4)?, 0)).ok()?;
+        let kind = *data.get(4)?;
+        let value_len = usize::try_from(read_u32(data.get(5..9)?, 0)).ok()?;
+        let key = data.get(9..9 + key_len)?.to_vec();
+        let value = data.get(9 + key_len..9 + key_len + value_len)?.to_vec();
+        entries.push(match kind {
+            KIND_VALUE => (key, Some(value)),
+            KIND_TOMBSTONE => (key, None),
+            _ => return None,
+        });
+        data = &data[9 + key_len + value_len..];
+    }
+    Some(entries)
+}
+
+fn parse_index(mut data: &[u8]) -> Option<Vec<BlockHandle>> {
+    let mut index = Vec::new();
+    while !data.is_empty() {
+        let key_len = usize::try_from(read_u32(data.get(..4)?, 0)).ok()?;
+        let last = data.get(4..4 + key_len)?.to_vec();
+        let rest = data.get(4 + key_len..16 + key_len)?;
+        index.push(BlockHandle {
+            last,
+            offset: read_u64(rest, 0),
+            len: read_u32(rest, 8),
+        });
+        data = &data[16 + key_len..];
+    }
+    Some(index)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().expect("Field Bytes"))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().expect("Field Bytes"))
+}
+
+fn to_u32(len: usize) -> LsmResult<u32> {
+    u32::try_from(len).map_err(|err| LsmError::FileSystem(FileSystemError::wrap_error(err)))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{LsmError, SSTableReader, SSTableWriter};
+    use minql_vfs::{FileSystem, MemoryFileSystem};
+    use std::ops::Bound;
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key{index:04}").into_bytes()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_sstable() {
+        let fs = MemoryFileSystem::new();
+        let mut writer = SSTableWriter::new(fs.create_file("/table.sst").unwrap(), 128, 10);
+        for index in (0..200).step_by(2) {
+            let value = (index % 10 != 0).then(|| vec![b'v'; index % 7]);
+            writer.add(&key(index), value.as_deref()).unwrap();
+        }
+        assert!(matches!(
+            writer.add(&key(10), None),
+            Err(LsmError::UnsortedKey(_))
+        ));
+        let info = writer.finish().unwrap();
+        assert_eq!(info.entries, 100);
+        assert_eq!(info.smallest, key(0));
+        assert_eq!(info.largest, key(198));
+        assert_eq!(info.size, fs.filesize("/table.sst").unwrap());
+
+        let reader = SSTableReader::open(fs.open_file("/table.sst").unwrap()).unwrap();
+        assert_eq!(reader.entries(), 100);
+        assert_eq!(reader.get(&key(4)).unwrap(), Some(Some(vec![b'v'; 4])));
+        assert_eq!(reader.get(&key(20)).unwrap(), Some(None));
+        assert_eq!(reader.get(&key(5)).unwrap(), None);
+        assert_eq!(reader.get(&key(300)).unwrap(), None);
+        assert_eq!(reader.iter().count(), 100);
+        let keys = reader
+            .iter_from(Bound::Excluded(&key(100)))
+            .take(3)
+            .map(|entry| entry.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![key(102), key(104), key(106)]);
+
+        // Damaged blocks are detected when read
+        let mut data = fs.read("/table.sst").unwrap();
+        data[20] ^= 0xFF;
+        fs.write("/damaged.sst", &data).unwrap();
+        let reader = SSTableReader::open(fs.open_file("/damaged.sst").unwrap()).unwrap();
+        assert!(matches!(
+            reader.get(&key(0)),
+            Err(LsmError::CorruptTable(path)) if path == "/damaged.sst"
+        ));
+        fs.write("/short.sst", &data[..30]).unwrap();
+        assert!(matches!(
+            SSTableReader::open(fs.open_file("/short.sst").unwrap()),
+            Err(LsmError::CorruptTable(_))
+        ));
+    }
+}
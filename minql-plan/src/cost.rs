@@ -0,0 +1,431 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::QueryGraph;
+use minql_catalog::TableStatistics;
+use minql_lang::ast::{BinaryOperator, UnaryOperator};
+use minql_value::ScalarExpr;
+use std::ops::Bound;
+
+/// Selectivity of an equality without statistics to go by.
+const DEFAULT_EQUALITY_SELECTIVITY: f64 = 0.005;
+
+/// Selectivity of a range without statistics to go by.
+const DEFAULT_RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+
+/// Selectivity of a condition the planner can't reason about.
+pub(crate) const DEFAULT_SELECTIVITY: f64 = 0.25;
+
+/// Fraction of rows assumed to start a group of their own when grouped.
+const DEFAULT_GROUP_FRACTION: f64 = 0.1;
+
+/// Estimated number of rows a plan returns and cost of returning them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Estimate {
+    /// Number of rows
+    pub rows: f64,
+    /// Cost, in units of reading a row of a table in order
+    pub cost: f64,
+}
+
+/// Costs of the work physical operators do, relative to reading a row of a table in order.
+///
+/// ```rust
+/// use minql_plan::CostModel;
+///
+/// let model = CostModel::new().with_random_row_cost(2.0).with_default_rows(10_000);
+/// assert_eq!(model.random_row_cost(), 2.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CostModel {
+    random_row_cost: f64,
+    index_seek_cost: f64,
+    cpu_row_cost: f64,
+    default_rows: u64,
+}
+
+impl CostModel {
+    /// Default cost of fetching a row through an index, out of order.
+    pub const DEFAULT_RANDOM_ROW_COST: f64 = 4.0;
+    /// Default cost of descending an index to the first row of a range.
+    pub const DEFAULT_INDEX_SEEK_COST: f64 = 4.0;
+    /// Default cost of evaluating a condition or expression over a row.
+    pub const DEFAULT_CPU_ROW_COST: f64 = 0.01;
+    /// Default number of rows assumed of tables never analyzed.
+    pub const DEFAULT_ROWS: u64 = 1000;
+
+    /// Create a cost model of the default costs.
+    #[must_use]
+    pub fn new() -> CostModel {
+        CostModel {
+            random_row_cost: Self::DEFAULT_RANDOM_ROW_COST,
+            index_seek_cost: Self::DEFAULT_INDEX_SEEK_COST,
+            cpu_row_cost: Self::DEFAULT_CPU_ROW_COST,
+            default_rows: Self::DEFAULT_ROWS,
+        }
+    }
+
+    /// Cost fetching a row through an index, lower for storage where seeks are cheap.
+    #[must_use]
+    pub fn with_random_row_cost(mut self, cost: f64) -> Self {
+        self.random_row_cost = cost;
+        self
+    }
+
+    /// Cost descending an index to the first row of a range.
+    #[must_use]
+    pub fn with_index_seek_cost(mut self, cost: f64) -> Self {
+        self.index_seek_cost = cost;
+        self
+    }
+
+    /// Cost evaluating a condition or expression over a row.
+    #[must_use]
+    pub fn with_cpu_row_cost(mut self, cost: f64) -> Self {
+        self.cpu_row_cost = cost;
+        self
+    }
+
+    /// Assume tables never analyzed hold `rows` rows.
+    #[must_use]
+    pub fn with_default_rows(mut self, rows: u64) -> Self {
+        self.default_rows = rows;
+        self
+    }
+
+    /// Cost of fetching a row through an index.
+    #[must_use]
+    pub fn random_row_cost(&self) -> f64 {
+        self.random_row_cost
+    }
+
+    /// Cost of descending an index.
+    #[must_use]
+    pub fn index_seek_cost(&self) -> f64 {
+        self.index_seek_cost
+    }
+
+    /// Cost of evaluating a condition or expression over a row.
+    #[must_use]
+    pub fn cpu_row_cost(&self) -> f64 {
+        self.cpu_row_cost
+    }
+
+    /// Rows assumed of tables never analyzed.
+    #[must_use]
+    pub fn default_rows(&self) -> u64 {
+        self.default_rows
+    }
+
+    /// Estimate of reading every row of a table of `rows` rows, returning `selectivity` of
+    /// them.
+    #[must_use]
+    pub fn seq_scan(&self, rows: f64, selectivity: f64, filtered: bool) -> Estimate {
+        let cpu = if filtered { self.cpu_row_cost } else { 0.0 };
+        Estimate {
+            rows: (rows * selectivity).max(1.0),
+            cost: rows * (1.0 + cpu),
+        }
+    }
+
+    /// Estimate of reading `matched` of a table's rows through an index, returning
+    /// `selectivity` of the table.
+    #[must_use]
+    pub fn index_scan(&self, rows: f64, matched: f64, selectivity: f64) -> Estimate {
+        let matched = (rows * matched).max(1.0);
+        Estimate {
+            rows: (rows * selectivity).max(1.0),
+            cost: self.index_seek_cost + matched * (self.random_row_cost + self.cpu_row_cost),
+        }
+    }
+
+    /// Estimate of filtering rows, returning `selectivity` of them.
+    #[must_use]
+    pub fn filter(&self, input: Estimate, selectivity: f64) -> Estimate {
+        Estimate {
+            rows: (input.rows * selectivity).max(1.0),
+            cost: input.cost + input.rows * self.cpu_row_cost,
+        }
+    }
+
+    /// Estimate of a nested loop join over a materialized right side, returning
+    /// `selectivity` of the pairs of rows.
+    #[must_use]
+    pub fn nested_loop_join(&self, left: Estimate, right: Estimate, selectivity: f64) -> Estimate {
+        let pairs = left.rows * right.rows;
+        Estimate {
+            rows: (pairs * selectivity).max(1.0),
+            cost: left.cost + right.cost + right.rows + pairs * self.cpu_row_cost,
+        }
+    }
+
+    /// Estimate of a hash join building a table of the rows of the right side, returning
+    /// `selectivity` of the pairs of rows.
+    #[must_use]
+    pub fn hash_join(&self, left: Estimate, right: Estimate, selectivity: f64) -> Estimate {
+        let rows = (left.rows * right.rows * selectivity).max(1.0);
+        Estimate {
+            rows,
+            cost: left.cost
+                + right.cost
+                + (right.rows * 2.0 + left.rows + rows) * self.cpu_row_cost,
+        }
+    }
+
+    /// Estimate of a merge join of inputs already in order, returning `selectivity` of the
+    /// pairs of rows.
+    #[must_use]
+    pub fn merge_join(&self, left: Estimate, right: Estimate, selectivity: f64) -> Estimate {
+        let rows = (left.rows * right.rows * selectivity).max(1.0);
+        Estimate {
+            rows,
+            cost: left.cost + right.cost + (right.rows + left.rows + rows) * self.cpu_row_cost,
+        }
+    }
+
+    /// Estimate of computing expressions over each row.
+    #[must_use]
+    pub fn project(&self, input: Estimate) -> Estimate {
+        Estimate {
+            rows: input.rows,
+            cost: input.cost + input.rows * self.cpu_row_cost,
+        }
+    }
+
+    /// Estimate of grouping rows by `groups` expressions, computing `aggregates` aggregates
+    /// over each row.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn hash_aggregate(&self, input: Estimate, groups: usize, aggregates: usize) -> Estimate {
+        let rows = if groups == 0 {
+            1.0
+        } else {
+            (input.rows * DEFAULT_GROUP_FRACTION).max(1.0)
+        };
+        Estimate {
+            rows,
+            cost: input.cost + input.rows * self.cpu_row_cost * (groups + aggregates).max(1) as f64,
+        }
+    }
+
+    /// Estimate of sorting rows, comparing each about `log2(rows)` times.
+    #[must_use]
+    pub fn sort(&self, input: Estimate) -> Estimate {
+        Estimate {
+            rows: input.rows,
+            cost: input.cost + input.rows * input.rows.max(2.0).log2() * self.cpu_row_cost,
+        }
+    }
+
+    /// Estimate of returning at most `limit` rows, if known, after skipping `offset`.
+    #[must_use]
+    pub fn limit(&self, input: Estimate, limit: Option<f64>, offset: f64) -> Estimate {
+        let rows = (input.rows - offset).max(0.0);
+        let rows = limit.map_or(rows, |limit| rows.min(limit));
+        let fraction = if input.rows > 0.0 {
+            ((rows + offset) / input.rows).min(1.0)
+        } else {
+            1.0
+        };
+        Estimate {
+            rows,
+            cost: input.cost * fraction,
+        }
+    }
+
+    /// Estimated number of rows of a relation of the query.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn rows(&self, graph: &QueryGraph, relation: usize) -> f64 {
+        let rows = match &graph.relations()[relation].statistics {
+            Some(statistics) => statistics.rows,
+            None => self.default_rows,
+        };
+        rows as f64
+    }
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel::new()
+    }
+}
+
+/// Estimated fraction of rows of the query meeting a condition.
+pub(crate) fn selectivity(graph: &QueryGraph, expr: &ScalarExpr) -> f64 {
+    let selectivity = match expr {
+        ScalarExpr::Literal(value) => {
+            if value.is_true() {
+                1.0
+            } else {
+                0.0
+            }
+        }
+        ScalarExpr::Unary {
+            op: UnaryOperator::Not,
+            expr,
+        } => 1.0 - selectivity(graph, expr),
+        ScalarExpr::Binary {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => selectivity(graph, left) * selectivity(graph, right),
+        ScalarExpr::Binary {
+            left,
+            op: BinaryOperator::Or,
+            right,
+        } => {
+            let left = selectivity(graph, left);
+            let right = selectivity(graph, right);
+            left + right - left * right
+        }
+        ScalarExpr::Binary { left, op, right } => comparison(graph, left, *op, right),
+        ScalarExpr::IsNull { expr, negated } => match column(graph, expr) {
+            Some((statistics, index)) => {
+                let nulls = statistics.map_or(DEFAULT_EQUALITY_SELECTIVITY, |statistics| {
+                    statistics.null_selectivity(index)
+                });
+                if *negated {
+                    1.0 - nulls
+                } else {
+                    nulls
+                }
+            }
+            None => DEFAULT_SELECTIVITY,
+        },
+        ScalarExpr::InList {
+            expr,
+            list,
+            negated,
+        } => {
+            let equal: f64 = list
+                .iter()
+                .map(|item| comparison(graph, expr, BinaryOperator::Eq, item))
+                .sum();
+            if *negated {
+                1.0 - equal.min(1.0)
+            } else {
+                equal
+            }
+        }
+        _ => DEFAULT_SELECTIVITY,
+    };
+    selectivity.clamp(0.0, 1.0)
+}
+
+/// Estimated fraction of rows where `left op right`.
+fn comparison(
+    graph: &QueryGraph,
+    left: &ScalarExpr,
+    op: BinaryOperator,
+    right: &ScalarExpr,
+) -> f64 {
+    let (expr, op, operand) = match (column(graph, left), column(graph, right)) {
+        (Some(left), Some(right)) => return join_selectivity(left, op, right),
+        (Some(_), None) => (left, op, right),
+        (None, Some(_)) => (right, flip(op), left),
+        (None, None) => return DEFAULT_SELECTIVITY,
+    };
+    if !operand.columns().is_empty() {
+        return comparison_default(op);
+    }
+    let Some((Some(statistics), index)) = column(graph, expr) else {
+        return comparison_default(op);
+    };
+    let ScalarExpr::Literal(value) = operand else {
+        // A parameter or computed constant, unknown until execution
+        return match op {
+            BinaryOperator::Eq => distinct_selectivity(Some(statistics), index),
+            op => comparison_default(op),
+        };
+    };
+    match op {
+        BinaryOperator::Eq => statistics.equality_selectivity(index, value),
+        BinaryOperator::NotEq => {
+            let equal = statistics.equality_selectivity(index, value);
+            1.0 - equal - statistics.null_selectivity(index)
+        }
+        BinaryOperator::Lt => {
+            statistics.range_selectivity(index, Bound::Unbounded, Bound::Excluded(value))
+        }
+        BinaryOperator::LtEq => {
+            statistics.range_selectivity(index, Bound::Unbounded, Bound::Included(value))
+        }
+        BinaryOperator::Gt => {
+            statistics.range_selectivity(index, Bound::Excluded(value), Bound::Unbounded)
+        }
+        BinaryOperator::GtEq => {
+            statistics.range_selectivity(index, Bound::Included(value), Bound::Unbounded)
+        }
+        _ => DEFAULT_SELECTIVITY,
+    }
+}
+
+/// Statistics of a column read directly by an expression, and its position in its table.
+type ColumnRef<'a> = (Option<&'a TableStatistics>, usize);
+
+/// Column an expression reads unchanged, if it's a column.
+fn column<'a>(graph: &'a QueryGraph, expr: &ScalarExpr) -> Option<ColumnRef<'a>> {
+    let ScalarExpr::Column(column) = expr else {
+        return None;
+    };
+    let relation = &graph.relations()[graph.relation_of(*column)];
+    Some((relation.statistics.as_deref(), column - relation.offset))
+}
+
+/// Estimated fraction of pairs of rows where columns compare, as joins compare them.
+fn join_selectivity(left: ColumnRef<'_>, op: BinaryOperator, right: ColumnRef<'_>) -> f64 {
+    match op {
+        // Each value on the smaller side matches one of the distinct values of the larger
+        BinaryOperator::Eq => {
+            distinct_selectivity(left.0, left.1).min(distinct_selectivity(right.0, right.1))
+        }
+        op => comparison_default(op),
+    }
+}
+
+/// Fraction of rows holding any one value of a column, assuming values are spread evenly.
+#[allow(clippy::cast_precision_loss)]
+fn distinct_selectivity(statistics: Option<&TableStatistics>, index: usize) -> f64 {
+    match statistics.and_then(|statistics| Some((statistics, statistics.columns.get(index)?))) {
+        Some((statistics, column)) if statistics.rows > 0 => {
+            (1.0 - statistics.null_selectivity(index)) / column.distinct.max(1) as f64
+        }
+        _ => DEFAULT_EQUALITY_SELECTIVITY,
+    }
+}
+
+/// Selectivity of a comparison without statistics to go by.
+fn comparison_default(op: BinaryOperator) -> f64 {
+    match op {
+        BinaryOperator::Eq => DEFAULT_EQUALITY_SELECTIVITY,
+        BinaryOperator::NotEq => 1.0 - DEFAULT_EQUALITY_SELECTIVITY,
+        BinaryOperator::Lt | BinaryOperator::LtEq | BinaryOperator::Gt | BinaryOperator::GtEq => {
+            DEFAULT_RANGE_SELECTIVITY
+        }
+        _ => DEFAULT_SELECTIVITY,
+    }
+}
+
+/// Operator comparing the same way with its operands swapped.
+pub(crate) fn flip(op: BinaryOperator) -> BinaryOperator {
+    match op {
+        BinaryOperator::Lt => BinaryOperator::Gt,
+        BinaryOperator::LtEq => BinaryOperator::GtEq,
+        BinaryOperator::Gt => BinaryOperator::Lt,
+        BinaryOperator::GtEq => BinaryOperator::LtEq,
+        op => op,
+    }
+}
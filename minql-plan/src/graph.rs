@@ -0,0 +1,416 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{PlanError, PlanResult};
+use minql_catalog::{CatalogSnapshot, IndexSchema, TableSchema, TableStatistics};
+use minql_lang::ast::{
+    BinaryOperator, Ident, JoinConstraint, JoinKind, Select, TableFactor, TableWithJoins,
+};
+use minql_value::{Binder, FunctionRegistry, ScalarExpr, Scope, ValueError};
+use std::sync::Arc;
+
+/// Most relations a query may join, one per bit of a relation set.
+pub(crate) const MAX_RELATIONS: usize = 64;
+
+/// Table read by a query, with what the planner knows of it.
+#[derive(Clone, Debug)]
+pub struct Relation {
+    /// Name the query refers to the table by, its alias or else its name
+    pub name: String,
+    /// Schema of the table
+    pub table: Arc<TableSchema>,
+    /// Indexes of the table
+    pub indexes: Vec<Arc<IndexSchema>>,
+    /// Statistics of the table, if it has been analyzed
+    pub statistics: Option<Arc<TableStatistics>>,
+    /// Position of the table's first column in rows of the query
+    pub offset: usize,
+}
+
+/// Condition rows of a query must meet, one of the conjuncts of its `WHERE` and join
+/// conditions.
+#[derive(Clone, Debug)]
+pub struct Predicate {
+    /// Condition over rows of the query
+    pub expr: ScalarExpr,
+    /// Relations whose columns the condition reads, by position
+    pub relations: Vec<usize>,
+}
+
+/// Tables a `SELECT` reads and the conditions relating them, the input of the
+/// [`Planner`](crate::Planner).
+///
+/// Rows of the query hold the columns of every table in the order of `FROM`, named by the
+/// [`scope`](QueryGraph::scope) the rest of the query binds against. Since inner joins and
+/// `WHERE` filter the same rows, their conditions are split into [`Predicate`]s the planner
+/// is free to apply in any order.
+#[derive(Clone, Debug)]
+pub struct QueryGraph {
+    relations: Vec<Relation>,
+    predicates: Vec<Predicate>,
+    scope: Scope,
+    parameters: usize,
+}
+
+impl QueryGraph {
+    /// Resolve the tables of a `SELECT` in `database` and bind its join and `WHERE`
+    /// conditions.
+    ///
+    /// Anonymous `?` parameters are numbered from the join conditions through to `WHERE`.
+    pub fn from_select(
+        snapshot: &CatalogSnapshot,
+        database: &str,
+        select: &Select,
+    ) -> PlanResult<QueryGraph> {
+        QueryGraph::from_select_with(snapshot, database, select, &FunctionRegistry::new())
+    }
+
+    /// Resolve the tables of a `SELECT` in `database` and bind its join and `WHERE`
+    /// conditions, which may call the user-defined functions of `functions`.
+    #[tracing::instrument(level = "debug", skip(snapshot, select, functions))]
+    pub fn from_select_with(
+        snapshot: &CatalogSnapshot,
+        database: &str,
+        select: &Select,
+        functions: &FunctionRegistry,
+    ) -> PlanResult<QueryGraph> {
+        if snapshot.database(database).is_none() {
+            return Err(PlanError::DatabaseMissing(database.to_string()));
+        }
+        let mut graph = QueryGraph {
+            relations: Vec::new(),
+            predicates: Vec::new(),
+            scope: Scope::new(),
+            parameters: 0,
+        };
+        let mut conditions = Vec::new();
+        for from in &select.from {
+            graph.add_from(snapshot, database, from, &mut conditions)?;
+        }
+        let scope = graph.scope.clone();
+        let mut binder = Binder::new(&scope).with_functions(functions);
+        for condition in conditions {
+            let expr = match condition {
+                Condition::On(expr) => binder.bind(expr)?,
+                Condition::Using(expr) => expr,
+            };
+            graph.add_predicate(expr);
+        }
+        if let Some(selection) = &select.selection {
+            let expr = binder.bind(selection)?;
+            graph.add_predicate(expr);
+        }
+        graph.parameters = binder.parameters();
+        Ok(graph)
+    }
+
+    /// Tables of the query, in the order of `FROM`.
+    #[must_use]
+    pub fn relations(&self) -> &[Relation] {
+        &self.relations
+    }
+
+    /// Conditions of the query.
+    #[must_use]
+    pub fn predicates(&self) -> &[Predicate] {
+        &self.predicates
+    }
+
+    /// Names of the columns of rows of the query.
+    #[must_use]
+    pub fn scope(&self) -> &Scope {
+        &self.scope
+    }
+
+    /// Number of parameter values the conditions need.
+    #[must_use]
+    pub fn parameters(&self) -> usize {
+        self.parameters
+    }
+
+    /// Position of the relation holding the column at `column` of rows of the query.
+    #[must_use]
+    pub fn relation_of(&self, column: usize) -> usize {
+        self.relations
+            .partition_point(|relation| relation.offset <= column)
+            .saturating_sub(1)
+    }
+
+    /// Add the tables of an item of `FROM`, collecting their join conditions.
+    fn add_from<'a>(
+        &mut self,
+        snapshot: &CatalogSnapshot,
+        database: &str,
+        from: &'a TableWithJoins,
+        conditions: &mut Vec<Condition<'a>>,
+    ) -> PlanResult<()> {
+        self.add_relation(snapshot, database, &from.relation)?;
+        for join in &from.joins {
+            match join.kind {
+                JoinKind::Inner | JoinKind::Cross => {}
+                JoinKind::Left | JoinKind::Right | JoinKind::Full => {
+                    return Err(PlanError::Unsupported(format!(
+                        "{:?} outer join",
+                        join.kind
+                    )));
+                }
+            }
+            self.add_relation(snapshot, database, &join.relation)?;
+            match &join.constraint {
+                JoinConstraint::On(expr) => conditions.push(Condition::On(expr)),
+                JoinConstraint::Using(columns) => {
+                    for column in columns {
+                        conditions.push(Condition::Using(self.using(column)?));
+                    }
+                }
+                JoinConstraint::None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a table read by the query.
+    fn add_relation(
+        &mut self,
+        snapshot: &CatalogSnapshot,
+        database: &str,
+        factor: &TableFactor,
+    ) -> PlanResult<()> {
+        let (name, alias) = match factor {
+            TableFactor::Table { name, alias } => (name, alias),
+            TableFactor::Derived { .. } => {
+                return Err(PlanError::Unsupported("derived table".to_string()));
+            }
+        };
+        let (table_database, table_name) = match &name[..] {
+            [table] => (database.to_string(), table.normalized()),
+            [database, table] => (database.normalized(), table.normalized()),
+            _ => return Err(PlanError::TableMissing(qualified(name))),
+        };
+        let table = snapshot
+            .table(&table_database, &table_name)
+            .ok_or_else(|| PlanError::TableMissing(qualified(name)))?;
+        let name = alias.as_ref().map_or(table_name, Ident::normalized);
+        if self.relations.iter().any(|relation| relation.name == name) {
+            return Err(PlanError::DuplicateTable(name));
+        }
+        if self.relations.len() == MAX_RELATIONS {
+            return Err(PlanError::Unsupported(format!(
+                "join of more than {MAX_RELATIONS} tables"
+            )));
+        }
+        for column in &table.columns {
+            self.scope.push(Some(&name), &column.name);
+        }
+        self.relations.push(Relation {
+            name,
+            table: table.clone(),
+            indexes: snapshot.table_indexes(table.id).to_vec(),
+            statistics: snapshot.statistics(table.id).cloned(),
+            offset: self.scope.len() - table.columns.len(),
+        });
+        Ok(())
+    }
+
+    /// Condition of `USING (column)` joining the last table added to the one before it with
+    /// that column.
+    fn using(&self, column: &Ident) -> PlanResult<ScalarExpr> {
+        let name = column.normalized();
+        let (joined, before) = self.relations.split_last().expect("Joined Table");
+        let position = |relation: &Relation| {
+            relation
+                .table
+                .column_index(&name)
+                .map(|index| relation.offset + index)
+        };
+        let right = position(joined).ok_or_else(|| ValueError::UnknownColumn(name.clone()))?;
+        let mut left = before.iter().filter_map(position);
+        match (left.next(), left.next()) {
+            (Some(left), None) => Ok(ScalarExpr::Binary {
+                left: Box::new(ScalarExpr::Column(left)),
+                op: BinaryOperator::Eq,
+                right: Box::new(ScalarExpr::Column(right)),
+            }),
+            (None, _) => Err(ValueError::UnknownColumn(name).into()),
+            (Some(_), Some(_)) => Err(ValueError::AmbiguousColumn(name).into()),
+        }
+    }
+
+    /// Add a condition, split into its conjuncts.
+    fn add_predicate(&mut self, expr: ScalarExpr) {
+        match expr {
+            ScalarExpr::Binary {
+                left,
+                op: BinaryOperator::And,
+                right,
+            } => {
+                self.add_predicate(*left);
+                self.add_predicate(*right);
+            }
+            expr => {
+                let mut relations: Vec<usize> = expr
+                    .columns()
+                    .into_iter()
+                    .map(|column| self.relation_of(column))
+                    .collect();
+                relations.dedup();
+                self.predicates.push(Predicate { expr, relations });
+            }
+        }
+    }
+}
+
+/// Join condition awaiting binding.
+enum Condition<'a> {
+    /// `ON` condition
+    On(&'a minql_lang::ast::Expr),
+    /// Equality of `USING`, bound already
+    Using(ScalarExpr),
+}
+
+/// Name as written, with its qualifiers.
+fn qualified(name: &[Ident]) -> String {
+    name.iter()
+        .map(|ident| ident.value.as_str())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod test {
+    use super::QueryGraph;
+    use crate::PlanError;
+    use minql_catalog::{Catalog, CatalogSnapshot, ColumnSchema, TableSchema};
+    use minql_lang::ast::{DataType, SetExpr, Statement};
+    use minql_lang::Parser;
+    use minql_value::{ScalarExpr, ValueError};
+    use minql_vfs::MemoryFileSystem;
+    use std::sync::Arc;
+
+    fn snapshot() -> Arc<CatalogSnapshot> {
+        let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+        let mut transaction = catalog.begin();
+        for database in ["shop", "crm"] {
+            transaction
+                .create_database(database, &format!("mem:///{database}"))
+                .unwrap();
+        }
+        let table = |name: &str, columns: &[&str]| {
+            columns
+                .iter()
+                .fold(TableSchema::new(name), |table, column| {
+                    table.with_column(ColumnSchema::new(column, DataType::BigInt))
+                })
+        };
+        transaction
+            .create_table("shop", table("orders", &["id", "customer", "amount"]))
+            .unwrap();
+        transaction
+            .create_table("crm", table("customers", &["customer", "region"]))
+            .unwrap();
+        transaction.commit().unwrap()
+    }
+
+    fn graph(snapshot: &CatalogSnapshot, sql: &str) -> Result<QueryGraph, PlanError> {
+        let Statement::Query(query) = Parser::parse_statement(sql).unwrap() else {
+            panic!("not a query: {sql}");
+        };
+        let SetExpr::Select(select) = &query.body else {
+            panic!("not a select: {sql}");
+        };
+        QueryGraph::from_select(snapshot, "shop", select)
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_query_graph() {
+        let snapshot = snapshot();
+        let graph = graph(
+            &snapshot,
+            "SELECT * FROM Orders o JOIN crm.customers USING (customer) \
+             WHERE o.amount > ? AND (region = 1 OR id = $3) AND 1 = 1",
+        )
+        .unwrap();
+        let names: Vec<_> = graph.relations().iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["o", "customers"]);
+        assert_eq!(graph.relations()[1].offset, 3);
+        assert_eq!(graph.scope().len(), 5);
+        assert_eq!(graph.relation_of(2), 0);
+        assert_eq!(graph.relation_of(3), 1);
+        assert_eq!(graph.relation_of(4), 1);
+        assert_eq!(graph.parameters(), 3);
+
+        let predicates: Vec<_> = graph
+            .predicates()
+            .iter()
+            .map(|predicate| (predicate.expr.columns(), predicate.relations.clone()))
+            .collect();
+        assert_eq!(
+            predicates,
+            [
+                (vec![1, 3], vec![0, 1]),
+                (vec![2], vec![0]),
+                (vec![0, 4], vec![0, 1]),
+                (vec![], vec![]),
+            ]
+        );
+        assert!(matches!(
+            graph.predicates()[3].expr,
+            ScalarExpr::Binary { .. }
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_query_graph_errors() {
+        let snapshot = snapshot();
+        let error = |sql| graph(&snapshot, sql).unwrap_err();
+        assert!(matches!(
+            error("SELECT * FROM customers"),
+            PlanError::TableMissing(name) if name == "customers"
+        ));
+        assert!(matches!(
+            error("SELECT * FROM orders, orders"),
+            PlanError::DuplicateTable(name) if name == "orders"
+        ));
+        assert!(matches!(
+            error("SELECT * FROM orders LEFT JOIN crm.customers c ON c.customer = id"),
+            PlanError::Unsupported(_)
+        ));
+        assert!(matches!(
+            error("SELECT * FROM (SELECT 1) t"),
+            PlanError::Unsupported(_)
+        ));
+        assert!(matches!(
+            error("SELECT * FROM orders a JOIN orders b USING (id) JOIN orders c USING (id)"),
+            PlanError::Value(ValueError::AmbiguousColumn(_))
+        ));
+        assert!(matches!(
+            error("SELECT * FROM orders WHERE region = 1"),
+            PlanError::Value(ValueError::UnknownColumn(_))
+        ));
+        let Statement::Query(query) = Parser::parse_statement("SELECT 1").unwrap() else {
+            unreachable!()
+        };
+        let SetExpr::Select(select) = &query.body else {
+            unreachable!()
+        };
+        assert!(matches!(
+            QueryGraph::from_select(&snapshot, "nope", select),
+            Err(PlanError::DatabaseMissing(_))
+        ));
+    }
+}
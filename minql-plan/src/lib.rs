@@ -0,0 +1,55 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Cost Based Query Planner
+//!
+//! A [`QueryGraph`] resolves the tables a `SELECT` reads against a `minql-catalog`
+//! snapshot, with their indexes and statistics, and binds its join and `WHERE` conditions
+//! into [`Predicate`]s. The [`Planner`] turns it into a [`PhysicalPlan`], choosing between a
+//! full scan and an index scan of each table and the order of joining them by the estimates
+//! of a [`CostModel`].
+//!
+//! [`Planner::plan_query`] plans a whole query into a [`QueryPlan`], adding the grouping,
+//! projection, ordering and limits of the query over the rows of its tables.
+
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod cost;
+mod graph;
+mod optimizer;
+mod plan;
+mod query;
+mod result;
+
+pub use self::cost::{CostModel, Estimate};
+pub use self::graph::{Predicate, QueryGraph, Relation};
+pub use self::optimizer::Planner;
+pub use self::plan::{AggregateCall, AggregateFunction, IndexBounds, PhysicalPlan, SortKey};
+pub use self::query::QueryPlan;
+pub use self::result::{PlanError, PlanResult};
@@ -0,0 +1,986 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::cost::{flip, selectivity};
+use crate::{CostModel, Estimate, IndexBounds, PhysicalPlan, Predicate, QueryGraph, SortKey};
+use minql_catalog::IndexSchema;
+use minql_lang::ast::BinaryOperator;
+use minql_value::{FunctionRegistry, ScalarExpr};
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Most tables a join may have for every order to be searched, beyond which tables are joined
+/// greedily.
+const MAX_EXHAUSTIVE_RELATIONS: usize = 10;
+
+/// Cost based planner, choosing how to read each table and in what order to join them.
+///
+/// Each table is read by a full scan or through whichever of its indexes its conditions
+/// narrow, whichever the [`CostModel`] finds cheaper given the statistics of the table. Joins
+/// of up to ten tables are ordered by searching every order of joining connected subsets,
+/// avoiding cross products unless the query asks for them, and larger joins greedily join the
+/// pair of inputs returning the fewest rows first. Inputs related by equalities are joined by
+/// hash join, or by merge join where they already come in order of the values compared.
+///
+/// ```rust
+/// use minql_catalog::{Catalog, ColumnSchema, TableSchema};
+/// use minql_lang::ast::{DataType, SetExpr, Statement};
+/// use minql_lang::Parser;
+/// use minql_plan::{Planner, QueryGraph};
+/// use minql_vfs::MemoryFileSystem;
+///
+/// let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+/// let mut transaction = catalog.begin();
+/// transaction.create_database("shop", "mem:///data/shop").unwrap();
+/// let table = TableSchema::new("items").with_column(ColumnSchema::new("id", DataType::BigInt));
+/// transaction.create_table("shop", table).unwrap();
+/// transaction.create_index("shop", "items_id", "items", &["id"], true).unwrap();
+/// let snapshot = transaction.commit().unwrap();
+///
+/// let Statement::Query(query) = Parser::parse_statement("SELECT * FROM items WHERE id = 7").unwrap()
+/// else { unreachable!() };
+/// let SetExpr::Select(select) = &query.body else { unreachable!() };
+/// let graph = QueryGraph::from_select(&snapshot, "shop", select).unwrap();
+/// let plan = Planner::new().plan(&graph);
+/// assert!(plan.to_string().starts_with("IndexScan items using items_id"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Planner {
+    cost_model: CostModel,
+    functions: Arc<FunctionRegistry>,
+}
+
+/// Best plan found for a set of tables.
+#[derive(Clone, Debug)]
+struct Candidate {
+    plan: PhysicalPlan,
+    /// Tables whose columns the plan returns, in order
+    layout: Vec<usize>,
+    /// Whether the plan joins tables without a condition relating them
+    cross: bool,
+}
+
+/// Condition relating tables of a join.
+#[derive(Debug)]
+struct JoinPredicate<'a> {
+    predicate: &'a Predicate,
+    /// Tables the condition reads, a bit each
+    relations: u64,
+    selectivity: f64,
+}
+
+impl Planner {
+    /// Create a planner of the default cost model.
+    #[must_use]
+    pub fn new() -> Planner {
+        Planner::default()
+    }
+
+    /// Weigh plans by `cost_model`.
+    #[must_use]
+    pub fn with_cost_model(mut self, cost_model: CostModel) -> Self {
+        self.cost_model = cost_model;
+        self
+    }
+
+    /// Cost model plans are weighed by.
+    #[must_use]
+    pub fn cost_model(&self) -> &CostModel {
+        &self.cost_model
+    }
+
+    /// Resolve functions not built in against `functions`.
+    #[must_use]
+    pub fn with_functions(mut self, functions: Arc<FunctionRegistry>) -> Self {
+        self.functions = functions;
+        self
+    }
+
+    /// User-defined functions queries may call.
+    #[must_use]
+    pub fn functions(&self) -> &Arc<FunctionRegistry> {
+        &self.functions
+    }
+
+    /// Cheapest plan found for the rows of a query, returned in the order of its
+    /// [`scope`](QueryGraph::scope).
+    #[tracing::instrument(level = "debug", skip_all, fields(relations = graph.relations().len()))]
+    pub fn plan(&self, graph: &QueryGraph) -> PhysicalPlan {
+        let relations = graph.relations().len();
+        let mut local = vec![Vec::new(); relations];
+        let mut joins = Vec::new();
+        let mut constant = Vec::new();
+        for predicate in graph.predicates() {
+            match predicate.relations[..] {
+                [] => constant.push(predicate),
+                [relation] => local[relation].push(predicate),
+                _ => joins.push(JoinPredicate {
+                    predicate,
+                    relations: predicate
+                        .relations
+                        .iter()
+                        .fold(0, |bits, relation| bits | 1 << relation),
+                    selectivity: selectivity(graph, &predicate.expr),
+                }),
+            }
+        }
+        let scans = local
+            .into_iter()
+            .enumerate()
+            .map(|(relation, predicates)| self.access_path(graph, relation, &predicates))
+            .collect::<Vec<_>>();
+        let candidate = if scans.is_empty() {
+            Candidate {
+                plan: PhysicalPlan::Values {
+                    rows: vec![Vec::new()],
+                    estimate: Estimate {
+                        rows: 1.0,
+                        cost: 0.0,
+                    },
+                },
+                layout: Vec::new(),
+                cross: false,
+            }
+        } else if relations <= MAX_EXHAUSTIVE_RELATIONS {
+            self.exhaustive(graph, scans, &joins)
+        } else {
+            self.greedy(graph, scans, &joins)
+        };
+        let mut plan = candidate.plan;
+        if let Some(predicate) = conjunction(constant.iter().map(|predicate| &predicate.expr)) {
+            let selectivity = selectivity(graph, &predicate);
+            plan = PhysicalPlan::Filter {
+                estimate: self.cost_model.filter(plan.estimate(), selectivity),
+                input: Box::new(plan),
+                predicate,
+            };
+        }
+        if !candidate.layout.iter().copied().eq(0..relations) {
+            let positions = positions(graph, &candidate.layout);
+            plan = PhysicalPlan::Project {
+                estimate: self.cost_model.project(plan.estimate()),
+                input: Box::new(plan),
+                exprs: positions.into_iter().map(ScalarExpr::Column).collect(),
+            };
+        }
+        tracing::debug!(cost = plan.estimate().cost, "Planned query");
+        plan
+    }
+
+    /// Cheapest way to read a table meeting its own conditions.
+    fn access_path(
+        &self,
+        graph: &QueryGraph,
+        relation: usize,
+        predicates: &[&Predicate],
+    ) -> Candidate {
+        let model = &self.cost_model;
+        let table = &graph.relations()[relation];
+        let rows = model.rows(graph, relation);
+        let selectivities: Vec<f64> = predicates
+            .iter()
+            .map(|predicate| selectivity(graph, &predicate.expr))
+            .collect();
+        let selectivity: f64 = selectivities.iter().product();
+        let local = |exprs: &mut dyn Iterator<Item = &ScalarExpr>| {
+            conjunction(exprs).map(|expr| expr.map_columns(&|column| column - table.offset))
+        };
+        let mut plan = PhysicalPlan::SeqScan {
+            table: table.table.clone(),
+            filter: local(&mut predicates.iter().map(|predicate| &predicate.expr)),
+            estimate: model.seq_scan(rows, selectivity, !predicates.is_empty()),
+        };
+        for index in &table.indexes {
+            let Some((bounds, used)) = match_index(index, table.offset, predicates) else {
+                continue;
+            };
+            let mut matched: f64 = used.iter().map(|&used| selectivities[used]).product();
+            if index.unique && bounds.prefix.len() == index.columns.len() {
+                matched = matched.min(1.0 / rows.max(1.0));
+            }
+            let estimate = model.index_scan(rows, matched, selectivity.min(matched));
+            if estimate.cost < plan.estimate().cost {
+                let bounds = IndexBounds {
+                    prefix: bounds.prefix.iter().map(|expr| (*expr).clone()).collect(),
+                    lower: bounds.lower.cloned(),
+                    upper: bounds.upper.cloned(),
+                };
+                plan = PhysicalPlan::IndexScan {
+                    table: table.table.clone(),
+                    index: index.clone(),
+                    bounds,
+                    filter: local(
+                        &mut predicates
+                            .iter()
+                            .enumerate()
+                            .filter(|(position, _)| !used.contains(position))
+                            .map(|(_, predicate)| &predicate.expr),
+                    ),
+                    estimate,
+                };
+            }
+        }
+        Candidate {
+            plan,
+            layout: vec![relation],
+            cross: false,
+        }
+    }
+
+    /// Best join of every table, searching every split of every subset of them.
+    ///
+    /// Subsets are visited in increasing order of their bits, so every subset of a set is
+    /// planned before it. A set is first split only into related parts planned without cross
+    /// products, and into any parts only if it can't be split so.
+    fn exhaustive(
+        &self,
+        graph: &QueryGraph,
+        scans: Vec<Candidate>,
+        joins: &[JoinPredicate<'_>],
+    ) -> Candidate {
+        let all = (1u64 << scans.len()) - 1;
+        let mut best: Vec<Option<Candidate>> = (0..=all).map(|_| None).collect();
+        for (relation, scan) in scans.into_iter().enumerate() {
+            best[1 << relation] = Some(scan);
+        }
+        for set in 1..=all {
+            if set.count_ones() < 2 {
+                continue;
+            }
+            for allow_cross in [false, true] {
+                let mut left = (set - 1) & set;
+                while left != 0 {
+                    let right = set ^ left;
+                    if let (Some(outer), Some(inner)) =
+                        (&best[to_index(left)], &best[to_index(right)])
+                    {
+                        let related = related(joins, left, right);
+                        if allow_cross || (related && !outer.cross && !inner.cross) {
+                            let current = best[to_index(set)].as_ref();
+                            if let Some(candidate) =
+                                self.join(graph, joins, outer, inner, !related, current)
+                            {
+                                best[to_index(set)] = Some(candidate);
+                            }
+                        }
+                    }
+                    left = (left - 1) & set;
+                }
+                if best[to_index(set)].is_some() {
+                    break;
+                }
+            }
+        }
+        best[to_index(all)].take().expect("Join of Every Table")
+    }
+
+    /// Join of every table, repeatedly joining the pair of inputs returning the fewest rows,
+    /// related pairs first.
+    fn greedy(
+        &self,
+        graph: &QueryGraph,
+        scans: Vec<Candidate>,
+        joins: &[JoinPredicate<'_>],
+    ) -> Candidate {
+        let mut parts: Vec<(u64, Candidate)> = scans
+            .into_iter()
+            .enumerate()
+            .map(|(relation, scan)| (1 << relation, scan))
+            .collect();
+        while parts.len() > 1 {
+            let mut chosen: Option<(bool, f64, usize, usize, Candidate)> = None;
+            for (i, (left, outer)) in parts.iter().enumerate() {
+                for (j, (right, inner)) in parts.iter().enumerate() {
+                    if i == j {
+                        continue;
+                    }
+                    let related = related(joins, *left, *right);
+                    let Some(candidate) = self.join(graph, joins, outer, inner, !related, None)
+                    else {
+                        continue;
+                    };
+                    let rows = candidate.plan.estimate().rows;
+                    let better = chosen.as_ref().is_none_or(|(best_related, best_rows, ..)| {
+                        (related, -rows) > (*best_related, -*best_rows)
+                    });
+                    if better {
+                        chosen = Some((related, rows, i, j, candidate));
+                    }
+                }
+            }
+            let (_, _, i, j, candidate) = chosen.expect("Pair of Inputs");
+            let set = parts[i].0 | parts[j].0;
+            parts.remove(i.max(j));
+            parts.remove(i.min(j));
+            parts.push((set, candidate));
+        }
+        parts.pop().expect("Join of Every Table").1
+    }
+
+    /// Cheapest join of two inputs, or `None` if it costs more than `current`.
+    ///
+    /// Inputs related by equalities of a value of each are joined by hash or merge join on
+    /// them too, the latter sorting inputs not already in order of their keys.
+    fn join(
+        &self,
+        graph: &QueryGraph,
+        joins: &[JoinPredicate<'_>],
+        outer: &Candidate,
+        inner: &Candidate,
+        cross: bool,
+        current: Option<&Candidate>,
+    ) -> Option<Candidate> {
+        let left = bits(&outer.layout);
+        let right = bits(&inner.layout);
+        let applied: Vec<&JoinPredicate<'_>> = joins
+            .iter()
+            .filter(|join| {
+                join.relations & !(left | right) == 0
+                    && join.relations & !left != 0
+                    && join.relations & !right != 0
+            })
+            .collect();
+        let selectivity = applied.iter().map(|join| join.selectivity).product();
+        let model = &self.cost_model;
+        let (left_estimate, right_estimate) = (outer.plan.estimate(), inner.plan.estimate());
+        let left_positions = positions(graph, &outer.layout);
+        let right_positions = positions(graph, &inner.layout);
+        let mut left_keys = Vec::new();
+        let mut right_keys = Vec::new();
+        let mut residual = Vec::new();
+        for join in &applied {
+            match equi_key(graph, &join.predicate.expr, left, right) {
+                Some((left_key, right_key)) => {
+                    left_keys.push(left_key.map_columns(&|column| left_positions[column]));
+                    right_keys.push(right_key.map_columns(&|column| right_positions[column]));
+                }
+                None => residual.push(&join.predicate.expr),
+            }
+        }
+        let mut method = JoinMethod::NestedLoop;
+        let mut estimate = model.nested_loop_join(left_estimate, right_estimate, selectivity);
+        if !left_keys.is_empty() {
+            let hashed = model.hash_join(left_estimate, right_estimate, selectivity);
+            if hashed.cost < estimate.cost {
+                (method, estimate) = (JoinMethod::Hash, hashed);
+            }
+            let sorted = |plan: &PhysicalPlan, keys: &[ScalarExpr]| {
+                if ordered(plan, keys) {
+                    plan.estimate()
+                } else {
+                    model.sort(plan.estimate())
+                }
+            };
+            let merged = model.merge_join(
+                sorted(&outer.plan, &left_keys),
+                sorted(&inner.plan, &right_keys),
+                selectivity,
+            );
+            if merged.cost < estimate.cost {
+                (method, estimate) = (JoinMethod::Merge, merged);
+            }
+        }
+        if current.is_some_and(|current| current.plan.estimate().cost <= estimate.cost) {
+            return None;
+        }
+        let layout: Vec<usize> = outer.layout.iter().chain(&inner.layout).copied().collect();
+        let positions = positions(graph, &layout);
+        let left = Box::new(outer.plan.clone());
+        let right = Box::new(inner.plan.clone());
+        let plan = match method {
+            JoinMethod::NestedLoop => PhysicalPlan::NestedLoopJoin {
+                left,
+                right,
+                condition: conjunction(applied.iter().map(|join| &join.predicate.expr))
+                    .map(|expr| expr.map_columns(&|column| positions[column])),
+                estimate,
+            },
+            JoinMethod::Hash => PhysicalPlan::HashJoin {
+                left,
+                right,
+                left_keys,
+                right_keys,
+                condition: conjunction(residual)
+                    .map(|expr| expr.map_columns(&|column| positions[column])),
+                estimate,
+            },
+            JoinMethod::Merge => PhysicalPlan::MergeJoin {
+                left: Box::new(self.sorted(*left, &left_keys)),
+                right: Box::new(self.sorted(*right, &right_keys)),
+                left_keys,
+                right_keys,
+                condition: conjunction(residual)
+                    .map(|expr| expr.map_columns(&|column| positions[column])),
+                estimate,
+            },
+        };
+        Some(Candidate {
+            plan,
+            layout,
+            cross: cross || outer.cross || inner.cross,
+        })
+    }
+
+    /// Rows of a plan in ascending order of `keys`, sorting them unless already so.
+    fn sorted(&self, plan: PhysicalPlan, keys: &[ScalarExpr]) -> PhysicalPlan {
+        if ordered(&plan, keys) {
+            return plan;
+        }
+        PhysicalPlan::Sort {
+            estimate: self.cost_model.sort(plan.estimate()),
+            input: Box::new(plan),
+            keys: keys
+                .iter()
+                .map(|key| SortKey {
+                    expr: key.clone(),
+                    ascending: true,
+                    nulls_first: false,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Way of joining two inputs.
+#[derive(Clone, Copy, Debug)]
+enum JoinMethod {
+    NestedLoop,
+    Hash,
+    Merge,
+}
+
+/// Value of each input an equality compares, of the inputs of tables `left` and `right` in
+/// that order, or `None` if the condition isn't such an equality.
+fn equi_key<'a>(
+    graph: &QueryGraph,
+    expr: &'a ScalarExpr,
+    left: u64,
+    right: u64,
+) -> Option<(&'a ScalarExpr, &'a ScalarExpr)> {
+    let ScalarExpr::Binary {
+        left: first,
+        op: BinaryOperator::Eq,
+        right: second,
+    } = expr
+    else {
+        return None;
+    };
+    let within = |expr: &ScalarExpr, set: u64| {
+        let relations = relations(graph, expr);
+        relations != 0 && relations & !set == 0
+    };
+    if within(first, left) && within(second, right) {
+        Some((first, second))
+    } else if within(second, left) && within(first, right) {
+        Some((second, first))
+    } else {
+        None
+    }
+}
+
+/// Bits of the tables whose columns an expression reads.
+fn relations(graph: &QueryGraph, expr: &ScalarExpr) -> u64 {
+    let tables = graph.relations();
+    expr.columns().into_iter().fold(0, |bits, column| {
+        let relation = tables
+            .iter()
+            .rposition(|relation| relation.offset <= column)
+            .expect("Table of Column");
+        bits | 1 << relation
+    })
+}
+
+/// Whether a plan returns its rows in ascending order of `keys`, leaving aside rows where
+/// they are `NULL`.
+fn ordered(plan: &PhysicalPlan, keys: &[ScalarExpr]) -> bool {
+    match plan {
+        PhysicalPlan::IndexScan { index, bounds, .. } => {
+            // Columns of the prefix are the same in every row, so in order whatever the keys
+            let (prefix, columns) = index
+                .columns
+                .split_at(bounds.prefix.len().min(index.columns.len()));
+            let keys: Vec<&ScalarExpr> = keys
+                .iter()
+                .filter(|key| !matches!(key, ScalarExpr::Column(column) if prefix.contains(column)))
+                .collect();
+            keys.len() <= columns.len()
+                && keys
+                    .iter()
+                    .zip(columns)
+                    .all(|(key, column)| **key == ScalarExpr::Column(*column))
+        }
+        PhysicalPlan::Sort { keys: sorted, .. } => {
+            keys.len() <= sorted.len()
+                && keys
+                    .iter()
+                    .zip(sorted)
+                    .all(|(key, sorted)| sorted.ascending && sorted.expr == *key)
+        }
+        PhysicalPlan::Filter { input, .. }
+        | PhysicalPlan::NestedLoopJoin { left: input, .. }
+        | PhysicalPlan::MergeJoin { left: input, .. } => ordered(input, keys),
+        _ => false,
+    }
+}
+
+/// Range of an index a table's conditions narrow its scan to, with the positions of the
+/// conditions it stands for, or `None` if they don't narrow it.
+fn match_index<'a>(
+    index: &IndexSchema,
+    offset: usize,
+    predicates: &[&'a Predicate],
+) -> Option<(BorrowedBounds<'a>, Vec<usize>)> {
+    let comparisons: Vec<Option<(usize, BinaryOperator, &ScalarExpr)>> = predicates
+        .iter()
+        .map(|predicate| comparison(&predicate.expr))
+        .collect();
+    let mut bounds = BorrowedBounds {
+        prefix: Vec::new(),
+        lower: Bound::Unbounded,
+        upper: Bound::Unbounded,
+    };
+    let mut used = Vec::new();
+    for &key in &index.columns {
+        let equal = comparisons.iter().position(|comparison| {
+            matches!(comparison, Some((column, BinaryOperator::Eq, _)) if column - offset == key)
+        });
+        if let Some(position) = equal {
+            bounds
+                .prefix
+                .push(comparisons[position].expect("Comparison").2);
+            used.push(position);
+            continue;
+        }
+        for (position, comparison) in comparisons.iter().enumerate() {
+            let Some((column, op, value)) = comparison else {
+                continue;
+            };
+            if column - offset != key {
+                continue;
+            }
+            let bound = match op {
+                BinaryOperator::Gt | BinaryOperator::Lt => Bound::Excluded(*value),
+                BinaryOperator::GtEq | BinaryOperator::LtEq => Bound::Included(*value),
+                _ => continue,
+            };
+            let side = match op {
+                BinaryOperator::Gt | BinaryOperator::GtEq => &mut bounds.lower,
+                _ => &mut bounds.upper,
+            };
+            if *side == Bound::Unbounded {
+                *side = bound;
+                used.push(position);
+            }
+        }
+        break;
+    }
+    (!used.is_empty()).then_some((bounds, used))
+}
+
+/// [`IndexBounds`] borrowing the expressions of the conditions they come from.
+struct BorrowedBounds<'a> {
+    prefix: Vec<&'a ScalarExpr>,
+    lower: Bound<&'a ScalarExpr>,
+    upper: Bound<&'a ScalarExpr>,
+}
+
+/// Column, operator and constant of a comparison of a column with a constant, written with
+/// the column first.
+fn comparison(expr: &ScalarExpr) -> Option<(usize, BinaryOperator, &ScalarExpr)> {
+    let ScalarExpr::Binary { left, op, right } = expr else {
+        return None;
+    };
+    if !matches!(
+        op,
+        BinaryOperator::Eq
+            | BinaryOperator::Lt
+            | BinaryOperator::LtEq
+            | BinaryOperator::Gt
+            | BinaryOperator::GtEq
+    ) {
+        return None;
+    }
+    match (&**left, &**right) {
+        (ScalarExpr::Column(column), value) if value.columns().is_empty() => {
+            Some((*column, *op, value))
+        }
+        (value, ScalarExpr::Column(column)) if value.columns().is_empty() => {
+            Some((*column, flip(*op), value))
+        }
+        _ => None,
+    }
+}
+
+/// Whether a condition relates tables of `left` with tables of `right`.
+fn related(joins: &[JoinPredicate<'_>], left: u64, right: u64) -> bool {
+    joins.iter().any(|join| {
+        join.relations & !(left | right) == 0
+            && join.relations & left != 0
+            && join.relations & right != 0
+    })
+}
+
+/// Conditions combined by `AND`, or `None` if there are none.
+fn conjunction<'a>(exprs: impl IntoIterator<Item = &'a ScalarExpr>) -> Option<ScalarExpr> {
+    exprs
+        .into_iter()
+        .cloned()
+        .reduce(|left, right| ScalarExpr::Binary {
+            left: Box::new(left),
+            op: BinaryOperator::And,
+            right: Box::new(right),
+        })
+}
+
+/// Position of each column of the query in rows holding the tables of `layout` in order.
+fn positions(graph: &QueryGraph, layout: &[usize]) -> Vec<usize> {
+    let relations = graph.relations();
+    let mut positions = vec![usize::MAX; graph.scope().len()];
+    let mut position = 0;
+    for &relation in layout {
+        let relation = &relations[relation];
+        for column in 0..relation.table.columns.len() {
+            positions[relation.offset + column] = position;
+            position += 1;
+        }
+    }
+    positions
+}
+
+/// Bits of the tables of a layout.
+fn bits(layout: &[usize]) -> u64 {
+    layout.iter().fold(0, |bits, relation| bits | 1 << relation)
+}
+
+/// Position of a set of tables in the table of best plans.
+fn to_index(set: u64) -> usize {
+    usize::try_from(set).expect("Table Set")
+}
+
+#[cfg(test)]
+mod test {
+    use super::Planner;
+    use crate::{PhysicalPlan, QueryGraph};
+    use minql_catalog::{
+        Catalog, CatalogSnapshot, ColumnSchema, ColumnStatistics, StatisticsCollector, TableSchema,
+        TableStatistics,
+    };
+    use minql_lang::ast::{DataType, SetExpr, Statement};
+    use minql_lang::Parser;
+    use minql_value::{ScalarExpr, Value};
+    use minql_vfs::MemoryFileSystem;
+    use std::fmt::Write;
+    use std::sync::Arc;
+
+    fn table(name: &str, columns: &[(&str, DataType)]) -> TableSchema {
+        columns
+            .iter()
+            .fold(TableSchema::new(name), |table, (column, data_type)| {
+                table.with_column(ColumnSchema::new(column, *data_type))
+            })
+    }
+
+    fn collect(columns: usize, rows: impl Iterator<Item = Vec<Value>>) -> TableStatistics {
+        let mut collector = StatisticsCollector::new(columns);
+        rows.for_each(|row| collector.add_row(&row));
+        collector.finish()
+    }
+
+    fn shop() -> Arc<CatalogSnapshot> {
+        let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+        let mut transaction = catalog.begin();
+        transaction.create_database("shop", "mem:///shop").unwrap();
+        let text = DataType::Text;
+        let int = DataType::BigInt;
+        for schema in [
+            table(
+                "customers",
+                &[("id", int), ("name", text), ("country", text)],
+            ),
+            table("orders", &[("id", int), ("customer", int), ("amount", int)]),
+            table("countries", &[("code", text), ("name", text)]),
+        ] {
+            transaction.create_table("shop", schema).unwrap();
+        }
+        transaction
+            .create_index("shop", "customers_id", "customers", &["id"], true)
+            .unwrap();
+        transaction
+            .create_index("shop", "orders_customer", "orders", &["customer"], false)
+            .unwrap();
+        transaction
+            .create_index("shop", "orders_amount", "orders", &["amount"], false)
+            .unwrap();
+        let customers = collect(
+            3,
+            (0..10_000i64).map(|id| {
+                vec![
+                    Value::from(id),
+                    Value::from(format!("customer {id}")),
+                    Value::from(format!("k{}", id % 50)),
+                ]
+            }),
+        );
+        let countries = collect(
+            2,
+            (0..50).map(|id| vec![Value::from(format!("k{id}")), Value::from(format!("n{id}"))]),
+        );
+        let column = |distinct: u64| ColumnStatistics {
+            distinct,
+            nulls: 0,
+            min: Some(Value::from(0i64)),
+            max: Some(Value::from(i64::try_from(distinct).unwrap() - 1)),
+            histogram: Vec::new(),
+        };
+        let orders = TableStatistics {
+            rows: 1_000_000,
+            columns: vec![column(1_000_000), column(10_000), column(1000)],
+        };
+        for (name, statistics) in [
+            ("customers", customers),
+            ("countries", countries),
+            ("orders", orders),
+        ] {
+            transaction
+                .set_statistics("shop", name, statistics)
+                .unwrap();
+        }
+        transaction.commit().unwrap()
+    }
+
+    fn graph(snapshot: &CatalogSnapshot, sql: &str) -> QueryGraph {
+        let Statement::Query(query) = Parser::parse_statement(sql).unwrap() else {
+            panic!("not a query: {sql}");
+        };
+        let SetExpr::Select(select) = &query.body else {
+            panic!("not a select: {sql}");
+        };
+        QueryGraph::from_select(snapshot, "shop", select).unwrap()
+    }
+
+    fn plan(snapshot: &CatalogSnapshot, sql: &str) -> PhysicalPlan {
+        Planner::new().plan(&graph(snapshot, sql))
+    }
+
+    /// Number of operators of the plan meeting a condition.
+    fn count(plan: &PhysicalPlan, matches: &impl Fn(&PhysicalPlan) -> bool) -> usize {
+        usize::from(matches(plan))
+            + plan
+                .children()
+                .into_iter()
+                .map(|child| count(child, matches))
+                .sum::<usize>()
+    }
+
+    /// Whether the plan joins two inputs, by whichever method.
+    fn is_join(plan: &PhysicalPlan) -> bool {
+        matches!(
+            plan,
+            PhysicalPlan::NestedLoopJoin { .. }
+                | PhysicalPlan::HashJoin { .. }
+                | PhysicalPlan::MergeJoin { .. }
+        )
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_access_paths() {
+        let shop = shop();
+        let explain = |sql| plan(&shop, sql).to_string();
+        assert!(explain("SELECT * FROM customers WHERE id = 42")
+            .starts_with("IndexScan customers using customers_id prefix 1 (rows=1 "));
+        assert!(
+            explain("SELECT * FROM orders WHERE 7 = customer AND amount < 10")
+                .starts_with("IndexScan orders using orders_customer prefix 1 filtered (rows=1 ")
+        );
+        assert!(
+            explain("SELECT * FROM orders WHERE amount > 5 AND amount <= 7")
+                .starts_with("IndexScan orders using orders_amount range after 0 (rows=")
+        );
+        assert!(explain("SELECT * FROM orders WHERE amount > 5")
+            .starts_with("SeqScan orders filtered (rows="));
+        assert!(explain("SELECT * FROM orders WHERE customer = ?")
+            .starts_with("IndexScan orders using orders_customer prefix 1 (rows=100 "));
+        assert!(explain("SELECT * FROM customers WHERE name LIKE 'a%'")
+            .starts_with("SeqScan customers filtered (rows=2500 "));
+
+        let PhysicalPlan::IndexScan { bounds, filter, .. } = plan(
+            &shop,
+            "SELECT * FROM orders WHERE amount >= 5 AND 7 > amount AND id > 1",
+        ) else {
+            panic!("not an index scan");
+        };
+        assert!(bounds.prefix.is_empty());
+        assert_eq!(
+            bounds.lower,
+            std::ops::Bound::Included(ScalarExpr::Literal(Value::from(5)))
+        );
+        assert_eq!(
+            bounds.upper,
+            std::ops::Bound::Excluded(ScalarExpr::Literal(Value::from(7)))
+        );
+        assert_eq!(filter.unwrap().columns(), [0]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_join_order() {
+        let shop = shop();
+        let graph = graph(
+            &shop,
+            "SELECT * FROM countries n, orders o JOIN customers c ON o.customer = c.id \
+             WHERE c.country = n.code AND n.name = 'n7'",
+        );
+        let planned = Planner::new().plan(&graph);
+        let explain = planned.to_string();
+        tracing::info!("\n{explain}");
+        let PhysicalPlan::Project { input, exprs, .. } = &planned else {
+            panic!("no projection restoring columns:\n{explain}");
+        };
+        let mut columns: Vec<_> = exprs.iter().flat_map(ScalarExpr::columns).collect();
+        columns.sort_unstable();
+        assert_eq!(columns, (0..graph.scope().len()).collect::<Vec<_>>());
+
+        // Countries narrow customers before the orders of the few left are found
+        let (PhysicalPlan::NestedLoopJoin { left, right, .. }
+        | PhysicalPlan::HashJoin { left, right, .. }
+        | PhysicalPlan::MergeJoin { left, right, .. }) = &**input
+        else {
+            panic!("not a join:\n{explain}");
+        };
+        let ((scan @ PhysicalPlan::SeqScan { .. }, join)
+        | (join, scan @ PhysicalPlan::SeqScan { .. })) = (&**left, &**right)
+        else {
+            panic!("orders not joined last:\n{explain}");
+        };
+        assert!(scan.to_string().starts_with("SeqScan orders"), "{explain}");
+        assert!(is_join(join), "{explain}");
+        assert!(!explain.contains("cross"), "{explain}");
+        assert!(
+            (input.estimate().rows - 20_000.0).abs() < 2000.0,
+            "{explain}"
+        );
+
+        let cross = plan(
+            &shop,
+            "SELECT * FROM countries a, countries b WHERE a.code < 'k3'",
+        );
+        assert!(
+            cross.to_string().contains("NestedLoopJoin cross"),
+            "{cross}"
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_join_methods() {
+        let shop = shop();
+        let explain = plan(
+            &shop,
+            "SELECT * FROM orders o JOIN customers c ON o.customer = c.id AND o.amount < c.id",
+        )
+        .to_string();
+        assert!(explain.contains("HashJoin on 1 filtered"), "{explain}");
+
+        // Both indexes return their rows in order of the keys joined on
+        let planned = plan(
+            &shop,
+            "SELECT * FROM orders o JOIN customers c ON o.customer = c.id \
+             WHERE c.id < 100 AND o.customer < 100",
+        );
+        let join = match &planned {
+            PhysicalPlan::Project { input, .. } => &**input,
+            plan => plan,
+        };
+        let PhysicalPlan::MergeJoin {
+            left,
+            right,
+            left_keys,
+            right_keys,
+            condition: None,
+            ..
+        } = join
+        else {
+            panic!("not a merge join:\n{planned}");
+        };
+        for (input, keys) in [(left, left_keys), (right, right_keys)] {
+            let PhysicalPlan::IndexScan { table, .. } = &**input else {
+                panic!("input sorted:\n{planned}");
+            };
+            let column = usize::from(table.name == "orders");
+            assert_eq!(keys, &[ScalarExpr::Column(column)], "{planned}");
+        }
+
+        let explain = plan(
+            &shop,
+            "SELECT * FROM countries a JOIN countries b ON a.name < b.name",
+        )
+        .to_string();
+        assert!(explain.contains("NestedLoopJoin (rows="), "{explain}");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_greedy_join_order() {
+        let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+        let mut transaction = catalog.begin();
+        transaction.create_database("shop", "mem:///shop").unwrap();
+        let mut sql = "SELECT * FROM t0".to_string();
+        for index in 0..12 {
+            let schema = table(
+                &format!("t{index}"),
+                &[("id", DataType::BigInt), ("next", DataType::BigInt)],
+            );
+            transaction.create_table("shop", schema).unwrap();
+            if index > 0 {
+                write!(sql, " JOIN t{index} ON t{}.next = t{index}.id", index - 1).unwrap();
+            }
+        }
+        let snapshot = transaction.commit().unwrap();
+        let plan = plan(&snapshot, &sql);
+        let joins = |plan: &PhysicalPlan| {
+            is_join(plan)
+                && !matches!(
+                    plan,
+                    PhysicalPlan::NestedLoopJoin {
+                        condition: None,
+                        ..
+                    }
+                )
+        };
+        assert_eq!(count(&plan, &joins), 11, "{plan}");
+        let scans = |plan: &PhysicalPlan| matches!(plan, PhysicalPlan::SeqScan { .. });
+        assert_eq!(count(&plan, &scans), 12, "{plan}");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_constant_conditions() {
+        let shop = shop();
+        let explain = plan(&shop, "SELECT 1 WHERE 1 = 2").to_string();
+        assert!(explain.starts_with("Filter (rows=1 "), "{explain}");
+        assert!(
+            explain.contains("\n  Values 1 (rows=1 cost=0.00)"),
+            "{explain}"
+        );
+        let explain = plan(&shop, "SELECT * FROM countries WHERE ? = 1").to_string();
+        assert!(explain.starts_with("Filter"), "{explain}");
+        assert!(
+            explain.contains("\n  SeqScan countries (rows=50 "),
+            "{explain}"
+        );
+    }
+}
@@ -0,0 +1,399 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::Estimate;
+use minql_catalog::{IndexSchema, TableSchema};
+use minql_value::{AggregateUdf, ScalarExpr};
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Plan of physical operators computing the rows of a query, each returning an estimate of its
+/// rows and cost.
+///
+/// Scans return the columns of their table in order, and joins the columns of their left
+/// input followed by those of their right. Expressions of each operator are bound to the
+/// columns of its input.
+#[derive(Clone, Debug)]
+pub enum PhysicalPlan {
+    /// Rows of constant expressions, such as the single empty row of a query without tables
+    Values {
+        /// Rows returned
+        rows: Vec<Vec<ScalarExpr>>,
+        /// Estimated rows and cost
+        estimate: Estimate,
+    },
+    /// Every row of a table, optionally filtered
+    SeqScan {
+        /// Table read
+        table: Arc<TableSchema>,
+        /// Condition rows must meet
+        filter: Option<ScalarExpr>,
+        /// Estimated rows and cost
+        estimate: Estimate,
+    },
+    /// Rows of a table within a range of an index, optionally filtered
+    IndexScan {
+        /// Table read
+        table: Arc<TableSchema>,
+        /// Index searched
+        index: Arc<IndexSchema>,
+        /// Range of keys read
+        bounds: IndexBounds,
+        /// Condition rows must meet besides the range
+        filter: Option<ScalarExpr>,
+        /// Estimated rows and cost
+        estimate: Estimate,
+    },
+    /// Rows of the input meeting a condition
+    Filter {
+        /// Rows filtered
+        input: Box<PhysicalPlan>,
+        /// Condition rows must meet
+        predicate: ScalarExpr,
+        /// Estimated rows and cost
+        estimate: Estimate,
+    },
+    /// Every pair of rows of the inputs meeting a condition, comparing each row of the left
+    /// with every row of the right
+    NestedLoopJoin {
+        /// Outer rows
+        left: Box<PhysicalPlan>,
+        /// Inner rows, read once and kept
+        right: Box<PhysicalPlan>,
+        /// Condition pairs must meet, or `None` for every pair
+        condition: Option<ScalarExpr>,
+        /// Estimated rows and cost
+        estimate: Estimate,
+    },
+    /// Pairs of rows of the inputs whose keys are equal and meeting a condition, keeping the
+    /// rows of the right by their keys to look up the keys of each row of the left
+    ///
+    /// Rows whose keys are `NULL` match no row. The rows of the right are partitioned to
+    /// temporary files if they don't fit in memory, and those of the left with them.
+    HashJoin {
+        /// Probing rows
+        left: Box<PhysicalPlan>,
+        /// Building rows, read once and kept by their keys
+        right: Box<PhysicalPlan>,
+        /// Keys of the rows of the left
+        left_keys: Vec<ScalarExpr>,
+        /// Keys of the rows of the right, compared with those of the left in order
+        right_keys: Vec<ScalarExpr>,
+        /// Condition pairs of equal keys must meet besides, or `None` for every pair
+        condition: Option<ScalarExpr>,
+        /// Estimated rows and cost
+        estimate: Estimate,
+    },
+    /// Pairs of rows of the inputs whose keys are equal and meeting a condition, reading both
+    /// inputs in ascending order of their keys together
+    ///
+    /// Rows whose keys are `NULL` match no row, wherever they are ordered.
+    MergeJoin {
+        /// Outer rows, in ascending order of their keys
+        left: Box<PhysicalPlan>,
+        /// Inner rows, in ascending order of their keys
+        right: Box<PhysicalPlan>,
+        /// Keys of the rows of the left
+        left_keys: Vec<ScalarExpr>,
+        /// Keys of the rows of the right, compared with those of the left in order
+        right_keys: Vec<ScalarExpr>,
+        /// Condition pairs of equal keys must meet besides, or `None` for every pair
+        condition: Option<ScalarExpr>,
+        /// Estimated rows and cost
+        estimate: Estimate,
+    },
+    /// Expressions computed over each row of the input
+    Project {
+        /// Rows projected
+        input: Box<PhysicalPlan>,
+        /// Columns of the rows returned
+        exprs: Vec<ScalarExpr>,
+        /// Estimated rows and cost
+        estimate: Estimate,
+    },
+    /// Rows of the input grouped by the values of expressions, returning the values grouped
+    /// by followed by the aggregates of each group
+    ///
+    /// Without expressions to group by every row is in one group, so a single row is
+    /// returned even of no rows.
+    HashAggregate {
+        /// Rows grouped
+        input: Box<PhysicalPlan>,
+        /// Values rows are grouped by
+        group_by: Vec<ScalarExpr>,
+        /// Aggregates computed over each group
+        aggregates: Vec<AggregateCall>,
+        /// Estimated rows and cost
+        estimate: Estimate,
+    },
+    /// Rows of the input in order
+    Sort {
+        /// Rows sorted
+        input: Box<PhysicalPlan>,
+        /// Values sorted by, the first deciding first
+        keys: Vec<SortKey>,
+        /// Estimated rows and cost
+        estimate: Estimate,
+    },
+    /// Rows of the input after skipping some, up to a number of rows
+    Limit {
+        /// Rows limited
+        input: Box<PhysicalPlan>,
+        /// Constant number of rows returned, or `None` for every row
+        limit: Option<ScalarExpr>,
+        /// Constant number of rows skipped first, or `None` for none
+        offset: Option<ScalarExpr>,
+        /// Estimated rows and cost
+        estimate: Estimate,
+    },
+}
+
+/// Aggregate function, computed over the rows of a group.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum AggregateFunction {
+    /// `COUNT(*)` of rows, or `COUNT(x)` of values not `NULL`
+    Count,
+    /// `SUM(x)`, of integers as `BIGINT`
+    Sum,
+    /// `MIN(x)`
+    Min,
+    /// `MAX(x)`
+    Max,
+    /// `AVG(x)`, of integers as `DECIMAL`
+    Avg,
+    /// User-defined aggregate, of any number of values
+    Udf(AggregateUdf),
+}
+
+impl AggregateFunction {
+    /// Built-in aggregate function of a normalized name, if any.
+    #[must_use]
+    pub fn lookup(name: &str) -> Option<AggregateFunction> {
+        match name {
+            "count" => Some(AggregateFunction::Count),
+            "sum" => Some(AggregateFunction::Sum),
+            "min" => Some(AggregateFunction::Min),
+            "max" => Some(AggregateFunction::Max),
+            "avg" => Some(AggregateFunction::Avg),
+            _ => None,
+        }
+    }
+
+    /// Name of the function.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        match self {
+            AggregateFunction::Udf(function) => function.name(),
+            builtin => builtin.builtin_name().expect("Built-in Aggregate"),
+        }
+    }
+
+    /// Name of a built-in function, or `None` for a user-defined one.
+    pub(crate) fn builtin_name(&self) -> Option<&'static str> {
+        Some(match self {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::Udf(_) => return None,
+        })
+    }
+}
+
+/// Call of an aggregate function over the rows of a group, ignoring rows with any argument
+/// `NULL`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AggregateCall {
+    /// Function called
+    pub function: AggregateFunction,
+    /// Values aggregated, or none for the rows themselves as in `COUNT(*)`
+    pub args: Vec<ScalarExpr>,
+    /// Whether only distinct values are aggregated
+    pub distinct: bool,
+}
+
+/// Value rows are sorted by.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortKey {
+    /// Value sorted by
+    pub expr: ScalarExpr,
+    /// Whether smaller values come first
+    pub ascending: bool,
+    /// Whether `NULL` comes before other values
+    pub nulls_first: bool,
+}
+
+/// Range of keys of an index to read, as constant expressions evaluated when the scan starts.
+///
+/// Keys match the `prefix` on the leading columns of the index exactly, and lie within the
+/// bounds on the column after.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexBounds {
+    /// Values of the leading columns of the index
+    pub prefix: Vec<ScalarExpr>,
+    /// Lower bound of the column after the prefix
+    pub lower: Bound<ScalarExpr>,
+    /// Upper bound of the column after the prefix
+    pub upper: Bound<ScalarExpr>,
+}
+
+impl PhysicalPlan {
+    /// Estimated rows and cost of the plan.
+    #[must_use]
+    pub fn estimate(&self) -> Estimate {
+        match self {
+            PhysicalPlan::Values { estimate, .. }
+            | PhysicalPlan::SeqScan { estimate, .. }
+            | PhysicalPlan::IndexScan { estimate, .. }
+            | PhysicalPlan::Filter { estimate, .. }
+            | PhysicalPlan::NestedLoopJoin { estimate, .. }
+            | PhysicalPlan::HashJoin { estimate, .. }
+            | PhysicalPlan::MergeJoin { estimate, .. }
+            | PhysicalPlan::Project { estimate, .. }
+            | PhysicalPlan::HashAggregate { estimate, .. }
+            | PhysicalPlan::Sort { estimate, .. }
+            | PhysicalPlan::Limit { estimate, .. } => *estimate,
+        }
+    }
+
+    /// Inputs of the plan, in order.
+    #[must_use]
+    pub fn children(&self) -> Vec<&PhysicalPlan> {
+        match self {
+            PhysicalPlan::Values { .. }
+            | PhysicalPlan::SeqScan { .. }
+            | PhysicalPlan::IndexScan { .. } => Vec::new(),
+            PhysicalPlan::Filter { input, .. }
+            | PhysicalPlan::Project { input, .. }
+            | PhysicalPlan::HashAggregate { input, .. }
+            | PhysicalPlan::Sort { input, .. }
+            | PhysicalPlan::Limit { input, .. } => vec![input],
+            PhysicalPlan::NestedLoopJoin { left, right, .. }
+            | PhysicalPlan::HashJoin { left, right, .. }
+            | PhysicalPlan::MergeJoin { left, right, .. } => vec![left, right],
+        }
+    }
+
+    /// Operator of the plan, as the line of `EXPLAIN` showing it, without its estimate.
+    #[must_use]
+    pub fn describe(&self) -> String {
+        let mut line = String::new();
+        self.write_operator(&mut line).expect("Write to String");
+        line
+    }
+
+    /// Write the operator of the plan, without its inputs.
+    fn write_operator(&self, f: &mut dyn std::fmt::Write) -> std::fmt::Result {
+        match self {
+            PhysicalPlan::Values { rows, .. } => write!(f, "Values {}", rows.len())?,
+            PhysicalPlan::SeqScan { table, filter, .. } => {
+                write!(f, "SeqScan {}", table.name)?;
+                if filter.is_some() {
+                    write!(f, " filtered")?;
+                }
+            }
+            PhysicalPlan::IndexScan {
+                table,
+                index,
+                bounds,
+                filter,
+                ..
+            } => {
+                write!(f, "IndexScan {} using {}", table.name, index.name)?;
+                if bounds.lower == Bound::Unbounded && bounds.upper == Bound::Unbounded {
+                    write!(f, " prefix {}", bounds.prefix.len())?;
+                } else {
+                    write!(f, " range after {}", bounds.prefix.len())?;
+                }
+                if filter.is_some() {
+                    write!(f, " filtered")?;
+                }
+            }
+            PhysicalPlan::Filter { .. } => write!(f, "Filter")?,
+            PhysicalPlan::NestedLoopJoin { condition, .. } => {
+                write!(f, "NestedLoopJoin")?;
+                if condition.is_none() {
+                    write!(f, " cross")?;
+                }
+            }
+            PhysicalPlan::HashJoin {
+                left_keys,
+                condition,
+                ..
+            } => {
+                write!(f, "HashJoin on {}", left_keys.len())?;
+                if condition.is_some() {
+                    write!(f, " filtered")?;
+                }
+            }
+            PhysicalPlan::MergeJoin {
+                left_keys,
+                condition,
+                ..
+            } => {
+                write!(f, "MergeJoin on {}", left_keys.len())?;
+                if condition.is_some() {
+                    write!(f, " filtered")?;
+                }
+            }
+            PhysicalPlan::Project { exprs, .. } => write!(f, "Project {}", exprs.len())?,
+            PhysicalPlan::HashAggregate {
+                group_by,
+                aggregates,
+                ..
+            } => {
+                write!(f, "HashAggregate")?;
+                if !group_by.is_empty() {
+                    write!(f, " group {}", group_by.len())?;
+                }
+                for aggregate in aggregates {
+                    write!(f, " {}", aggregate.function.name())?;
+                    if aggregate.distinct {
+                        write!(f, " distinct")?;
+                    }
+                }
+            }
+            PhysicalPlan::Sort { keys, .. } => write!(f, "Sort {}", keys.len())?,
+            PhysicalPlan::Limit { limit, offset, .. } => {
+                write!(f, "Limit")?;
+                if offset.is_some() {
+                    write!(f, " offset")?;
+                }
+                if limit.is_none() {
+                    write!(f, " all")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the plan as an indented line per operator, as `EXPLAIN` shows it.
+    fn explain(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        write!(f, "{:width$}{}", "", self.describe(), width = depth * 2)?;
+        let estimate = self.estimate();
+        writeln!(f, " (rows={:.0} cost={:.2})", estimate.rows, estimate.cost)?;
+        self.children()
+            .into_iter()
+            .try_for_each(|child| child.explain(f, depth + 1))
+    }
+}
+
+impl std::fmt::Display for PhysicalPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.explain(f, 0)
+    }
+}
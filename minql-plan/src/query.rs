@@ -0,0 +1,1104 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::cost::DEFAULT_SELECTIVITY;
+use crate::{
+    AggregateCall, AggregateFunction, CostModel, Estimate, PhysicalPlan, PlanError, PlanResult,
+    Planner, QueryGraph, SortKey,
+};
+use minql_catalog::CatalogSnapshot;
+use minql_lang::ast::{
+    Expr, Function, Ident, JoinConstraint, Literal, OrderByExpr, Query, Select, SelectItem, SetExpr,
+};
+use minql_value::{Binder, FunctionRegistry, ScalarExpr, Scope, Value, ValueError};
+use std::sync::Arc;
+
+/// Plan of a query, with the names of the columns it returns.
+#[derive(Clone, Debug)]
+pub struct QueryPlan {
+    /// Plan computing the rows of the query
+    pub plan: PhysicalPlan,
+    /// Names of the columns of the rows, in order
+    pub columns: Vec<String>,
+    /// Number of parameter values the query needs
+    pub parameters: usize,
+}
+
+/// Rows a query projects its columns from, and the columns projected.
+struct Projection {
+    plan: PhysicalPlan,
+    /// Names of the columns of the rows of the query before grouping
+    input: Scope,
+    /// Names of the columns of the rows projected from
+    scope: Scope,
+    /// Grouping of the rows, whose aggregates expressions bind against
+    grouping: Option<Grouping>,
+    /// Columns of the query over the rows projected from
+    exprs: Vec<ScalarExpr>,
+    /// Names of the columns of the query
+    names: Vec<String>,
+    /// Whether only distinct rows are returned
+    distinct: bool,
+    /// User-defined functions expressions may call
+    functions: Arc<FunctionRegistry>,
+}
+
+/// Groups of a grouped `SELECT`, and the aggregates its expressions compute of each.
+///
+/// Rows of the groups hold the values grouped by followed by the aggregates, named by a scope
+/// of their own. Expressions over them are rewritten to refer to those names, replacing the
+/// expressions grouped by and the calls of aggregates before binding.
+#[derive(Default)]
+struct Grouping {
+    /// Expressions grouped by, as written
+    exprs: Vec<Expr>,
+    /// Expressions grouped by, bound to the rows grouped
+    group_by: Vec<ScalarExpr>,
+    /// Calls of aggregates, as written
+    calls: Vec<Function>,
+    /// Calls of aggregates, bound to the rows grouped
+    aggregates: Vec<AggregateCall>,
+    /// User-defined functions expressions may call
+    functions: Arc<FunctionRegistry>,
+}
+
+impl Planner {
+    /// Plan a query against the tables of `database`, from its tables through to the rows it
+    /// returns.
+    ///
+    /// Anonymous `?` parameters are numbered in the order they are written.
+    ///
+    /// ```rust
+    /// use minql_catalog::{Catalog, ColumnSchema, TableSchema};
+    /// use minql_lang::ast::{DataType, Statement};
+    /// use minql_lang::Parser;
+    /// use minql_plan::Planner;
+    /// use minql_vfs::MemoryFileSystem;
+    ///
+    /// let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+    /// let mut transaction = catalog.begin();
+    /// transaction.create_database("shop", "mem:///data/shop").unwrap();
+    /// let table = TableSchema::new("items")
+    ///     .with_column(ColumnSchema::new("kind", DataType::Text))
+    ///     .with_column(ColumnSchema::new("price", DataType::BigInt));
+    /// transaction.create_table("shop", table).unwrap();
+    /// let snapshot = transaction.commit().unwrap();
+    ///
+    /// let sql = "SELECT kind, sum(price) AS total FROM items GROUP BY kind ORDER BY 2 DESC LIMIT ?";
+    /// let Statement::Query(query) = Parser::parse_statement(sql).unwrap() else { unreachable!() };
+    /// let plan = Planner::new().plan_query(&snapshot, "shop", &query).unwrap();
+    /// assert_eq!(plan.columns, ["kind", "total"]);
+    /// assert_eq!(plan.parameters, 1);
+    /// assert!(plan.plan.to_string().starts_with("Limit"));
+    /// ```
+    #[tracing::instrument(level = "debug", skip(self, snapshot, query))]
+    pub fn plan_query(
+        &self,
+        snapshot: &CatalogSnapshot,
+        database: &str,
+        query: &Query,
+    ) -> PlanResult<QueryPlan> {
+        let mut parameters = Parameters::default();
+        let query = parameters.number(query);
+        let mut projection = match &query.body {
+            SetExpr::Select(select) => self.select(snapshot, database, select, &query.order_by)?,
+            SetExpr::Values(rows) => self.values(rows)?,
+            SetExpr::Query(_) => return Err(PlanError::Unsupported("nested query".to_string())),
+            SetExpr::SetOperation { op, .. } => {
+                return Err(PlanError::Unsupported(format!("{op:?} of queries")));
+            }
+        };
+        let width = projection.exprs.len();
+        let keys = query
+            .order_by
+            .iter()
+            .map(|item| projection.sort_key(item))
+            .collect::<PlanResult<Vec<_>>>()?;
+        let model = self.cost_model();
+        let mut plan = projection.project(model);
+        if !keys.is_empty() {
+            plan = PhysicalPlan::Sort {
+                estimate: model.sort(plan.estimate()),
+                input: Box::new(plan),
+                keys,
+            };
+        }
+        if projection.exprs.len() > width {
+            plan = PhysicalPlan::Project {
+                estimate: model.project(plan.estimate()),
+                input: Box::new(plan),
+                exprs: (0..width).map(ScalarExpr::Column).collect(),
+            };
+        }
+        if query.limit.is_some() || query.offset.is_some() {
+            let scope = Scope::new();
+            let mut binder = Binder::new(&scope).with_functions(self.functions());
+            let limit = query
+                .limit
+                .as_ref()
+                .map(|expr| binder.bind(expr))
+                .transpose()?;
+            let offset = query
+                .offset
+                .as_ref()
+                .map(|expr| binder.bind(expr))
+                .transpose()?;
+            plan = PhysicalPlan::Limit {
+                estimate: model.limit(
+                    plan.estimate(),
+                    limit.as_ref().and_then(constant),
+                    offset.as_ref().and_then(constant).unwrap_or(0.0),
+                ),
+                input: Box::new(plan),
+                limit,
+                offset,
+            };
+        }
+        Ok(QueryPlan {
+            plan,
+            columns: projection.names,
+            parameters: parameters.count(),
+        })
+    }
+
+    /// Plan the rows of a `SELECT` up to its projection.
+    fn select(
+        &self,
+        snapshot: &CatalogSnapshot,
+        database: &str,
+        select: &Select,
+        order_by: &[OrderByExpr],
+    ) -> PlanResult<Projection> {
+        let graph = QueryGraph::from_select_with(snapshot, database, select, self.functions())?;
+        let items = items(&graph, &select.projection)?;
+        let mut plan = self.plan(&graph);
+        let model = self.cost_model();
+        let aggregated = items
+            .iter()
+            .map(|(expr, _)| expr)
+            .chain(&select.having)
+            .chain(order_by.iter().map(|item| &item.expr))
+            .any(|expr| has_aggregate(expr, self.functions()));
+        let mut grouping = None;
+        let mut scope = graph.scope().clone();
+        if aggregated || !select.group_by.is_empty() || select.having.is_some() {
+            let mut groups = Grouping {
+                functions: self.functions().clone(),
+                ..Grouping::default()
+            };
+            for expr in &select.group_by {
+                groups.group(graph.scope(), &items, expr)?;
+            }
+            let mut exprs = items.iter().map(|(expr, _)| expr).collect::<Vec<_>>();
+            exprs.extend(&select.having);
+            exprs.extend(order_by.iter().map(|item| &item.expr));
+            for expr in exprs {
+                groups.collect(graph.scope(), expr)?;
+            }
+            scope = groups.scope();
+            plan = PhysicalPlan::HashAggregate {
+                estimate: model.hash_aggregate(
+                    plan.estimate(),
+                    groups.group_by.len(),
+                    groups.aggregates.len(),
+                ),
+                input: Box::new(plan),
+                group_by: groups.group_by.clone(),
+                aggregates: groups.aggregates.clone(),
+            };
+            grouping = Some(groups);
+        }
+        let mut projection = Projection {
+            plan,
+            input: graph.scope().clone(),
+            scope,
+            grouping,
+            exprs: Vec::new(),
+            names: Vec::new(),
+            distinct: select.distinct,
+            functions: self.functions().clone(),
+        };
+        if let Some(having) = &select.having {
+            let predicate = projection.bind(having)?;
+            projection.plan = PhysicalPlan::Filter {
+                estimate: model.filter(projection.plan.estimate(), DEFAULT_SELECTIVITY),
+                input: Box::new(projection.plan),
+                predicate,
+            };
+        }
+        for (expr, name) in items {
+            let expr = projection.bind(&expr)?;
+            projection.exprs.push(expr);
+            projection.names.push(name);
+        }
+        Ok(projection)
+    }
+
+    /// Plan the rows of `VALUES`, whose columns are named `column1` onward.
+    fn values(&self, rows: &[Vec<Expr>]) -> PlanResult<Projection> {
+        let width = rows.first().map_or(0, Vec::len);
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(PlanError::InvalidQuery(
+                "VALUES rows of different lengths".to_string(),
+            ));
+        }
+        let empty = Scope::new();
+        let mut binder = Binder::new(&empty).with_functions(self.functions());
+        let rows = rows
+            .iter()
+            .map(|row| row.iter().map(|expr| binder.bind(expr)).collect())
+            .collect::<Result<Vec<Vec<_>>, _>>()?;
+        let names: Vec<String> = (1..=width)
+            .map(|column| format!("column{column}"))
+            .collect();
+        #[allow(clippy::cast_precision_loss)]
+        let estimate = Estimate {
+            rows: rows.len() as f64,
+            cost: 0.0,
+        };
+        let scope = names
+            .iter()
+            .fold(Scope::new(), |scope, name| scope.with_column(None, name));
+        Ok(Projection {
+            plan: PhysicalPlan::Values { rows, estimate },
+            input: scope.clone(),
+            scope,
+            grouping: None,
+            exprs: (0..width).map(ScalarExpr::Column).collect(),
+            names,
+            distinct: false,
+            functions: self.functions().clone(),
+        })
+    }
+}
+
+impl Projection {
+    /// Bind an expression over the rows projected from.
+    fn bind(&mut self, expr: &Expr) -> PlanResult<ScalarExpr> {
+        let expr = match &mut self.grouping {
+            Some(grouping) => grouping.rewrite(&self.input, expr)?,
+            None => expr.clone(),
+        };
+        let mut binder = Binder::new(&self.scope).with_functions(&self.functions);
+        Ok(binder.bind(&expr)?)
+    }
+
+    /// Key sorting by an item of `ORDER BY`, as a column of the query, or else a column of
+    /// its own added after the columns of the query.
+    ///
+    /// Items name columns by position, by name, or else compute an expression over the rows
+    /// projected from.
+    fn sort_key(&mut self, item: &OrderByExpr) -> PlanResult<SortKey> {
+        let column = match &item.expr {
+            Expr::Literal(Literal::Integer(position)) => {
+                let column = position
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|column| (1..=self.names.len()).contains(column))
+                    .ok_or_else(|| {
+                        PlanError::InvalidQuery(format!("ORDER BY position {position}"))
+                    })?;
+                column - 1
+            }
+            Expr::Identifier(name) if name.len() == 1 && self.named(&name[0]).is_some() => {
+                self.named(&name[0]).expect("Named Column")?
+            }
+            expr => {
+                let bound = self.bind(expr)?;
+                if let Some(column) = self.exprs.iter().position(|expr| *expr == bound) {
+                    column
+                } else if self.distinct {
+                    return Err(PlanError::InvalidQuery(
+                        "ORDER BY expression of SELECT DISTINCT not in its columns".to_string(),
+                    ));
+                } else {
+                    self.exprs.push(bound);
+                    self.exprs.len() - 1
+                }
+            }
+        };
+        let ascending = item.asc.unwrap_or(true);
+        Ok(SortKey {
+            expr: ScalarExpr::Column(column),
+            ascending,
+            nulls_first: item.nulls_first.unwrap_or(!ascending),
+        })
+    }
+
+    /// Column of the query of a name, if any, unless more than one has it.
+    fn named(&self, name: &Ident) -> Option<PlanResult<usize>> {
+        let name = name.normalized();
+        let mut found = self
+            .names
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| **column == name);
+        match (found.next(), found.next()) {
+            (None, _) => None,
+            (Some((column, _)), None) => Some(Ok(column)),
+            (Some(_), Some(_)) => Some(Err(ValueError::AmbiguousColumn(name).into())),
+        }
+    }
+
+    /// Plan of the columns of the query, and of any columns sorted by after them, removing
+    /// duplicates for `DISTINCT`.
+    fn project(&mut self, model: &CostModel) -> PhysicalPlan {
+        let mut plan = std::mem::replace(
+            &mut self.plan,
+            PhysicalPlan::Values {
+                rows: Vec::new(),
+                estimate: Estimate::default(),
+            },
+        );
+        let identity = self.exprs.len() == self.scope.len()
+            && self
+                .exprs
+                .iter()
+                .enumerate()
+                .all(|(position, expr)| *expr == ScalarExpr::Column(position));
+        if !identity {
+            plan = PhysicalPlan::Project {
+                estimate: model.project(plan.estimate()),
+                input: Box::new(plan),
+                exprs: self.exprs.clone(),
+            };
+        }
+        if self.distinct {
+            plan = PhysicalPlan::HashAggregate {
+                estimate: model.hash_aggregate(plan.estimate(), self.exprs.len(), 0),
+                input: Box::new(plan),
+                group_by: (0..self.exprs.len()).map(ScalarExpr::Column).collect(),
+                aggregates: Vec::new(),
+            };
+        }
+        plan
+    }
+}
+
+impl Grouping {
+    /// Add an expression of `GROUP BY`, which may also name a column of the query by
+    /// position or by its alias.
+    fn group(&mut self, input: &Scope, items: &[(Expr, String)], expr: &Expr) -> PlanResult<()> {
+        let expr = match expr {
+            Expr::Literal(Literal::Integer(position)) => {
+                let position = position
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|position| (1..=items.len()).contains(position))
+                    .ok_or_else(|| {
+                        PlanError::InvalidQuery(format!("GROUP BY position {position}"))
+                    })?;
+                &items[position - 1].0
+            }
+            Expr::Identifier(name) if name.len() == 1 && input.resolve(name).is_err() => items
+                .iter()
+                .find(|(_, alias)| *alias == name[0].normalized())
+                .map_or(expr, |(expr, _)| expr),
+            expr => expr,
+        };
+        if has_aggregate(expr, &self.functions) {
+            return Err(PlanError::InvalidQuery(format!(
+                "aggregate in GROUP BY {expr}"
+            )));
+        }
+        let bound = Binder::new(input)
+            .with_functions(&self.functions)
+            .bind(expr)?;
+        if !self.group_by.contains(&bound) {
+            self.exprs.push(expr.clone());
+            self.group_by.push(bound);
+        }
+        Ok(())
+    }
+
+    /// Add the calls of aggregates of an expression.
+    fn collect(&mut self, input: &Scope, expr: &Expr) -> PlanResult<()> {
+        let mut result = Ok(());
+        rewrite(expr, &mut |expr| match expr {
+            Expr::Function(function) if is_aggregate(function, &self.functions) => {
+                if result.is_ok() {
+                    result = self.aggregate(input, function).map(|_| ());
+                }
+                Some(expr.clone())
+            }
+            _ => None,
+        });
+        result
+    }
+
+    /// Copy of an expression over the rows grouped, referring to the values grouped by and
+    /// the aggregates of the rows of the group instead.
+    fn rewrite(&mut self, input: &Scope, expr: &Expr) -> PlanResult<Expr> {
+        let mut result = Ok(());
+        let mut fail = |err: PlanError| {
+            if result.is_ok() {
+                result = Err(err);
+            }
+        };
+        let expr = rewrite(expr, &mut |expr| {
+            if let Some(group) = self.exprs.iter().position(|grouped| grouped == expr) {
+                return Some(name(&format!("#group{group}")));
+            }
+            match expr {
+                Expr::Function(function) if is_aggregate(function, &self.functions) => {
+                    match self.aggregate(input, function) {
+                        Ok(aggregate) => Some(name(&format!("#aggregate{aggregate}"))),
+                        Err(err) => {
+                            fail(err);
+                            Some(expr.clone())
+                        }
+                    }
+                }
+                Expr::Identifier(column) => {
+                    match input.resolve(column) {
+                        Ok(position) => {
+                            let column = ScalarExpr::Column(position);
+                            match self.group_by.iter().position(|grouped| *grouped == column) {
+                                Some(group) => return Some(name(&format!("#group{group}"))),
+                                None => fail(PlanError::NotGrouped(expr.to_string())),
+                            }
+                        }
+                        Err(err) => fail(err.into()),
+                    }
+                    Some(expr.clone())
+                }
+                _ => None,
+            }
+        });
+        result.map(|()| expr)
+    }
+
+    /// Position of a call of an aggregate among the aggregates, adding it if new.
+    fn aggregate(&mut self, input: &Scope, function: &Function) -> PlanResult<usize> {
+        if let Some(position) = self.calls.iter().position(|call| call == function) {
+            return Ok(position);
+        }
+        let call = Expr::Function(function.clone()).to_string();
+        let aggregate = match &function.name[..] {
+            [name] => {
+                let name = name.normalized();
+                AggregateFunction::lookup(&name).or_else(|| {
+                    let function = self.functions.aggregate(&name)?;
+                    Some(AggregateFunction::Udf(function.clone()))
+                })
+            }
+            _ => None,
+        };
+        let Some(aggregate) = aggregate else {
+            return Err(ValueError::UnknownFunction(call).into());
+        };
+        let mut binder = Binder::new(input).with_functions(&self.functions);
+        let args = match (&aggregate, &function.args[..], function.wildcard) {
+            (AggregateFunction::Count, [], true) => Vec::new(),
+            (AggregateFunction::Udf(_), _, true) => {
+                return Err(ValueError::Unsupported(call).into());
+            }
+            (AggregateFunction::Udf(udf), args, false) => {
+                udf.check_arity(args.len())?;
+                args.iter()
+                    .map(|arg| binder.bind(arg))
+                    .collect::<Result<_, _>>()?
+            }
+            (_, [arg], false) => vec![binder.bind(arg)?],
+            (_, args, _) => {
+                return Err(ValueError::ArgumentCount {
+                    function: aggregate.builtin_name().expect("Built-in Aggregate"),
+                    found: args.len(),
+                }
+                .into());
+            }
+        };
+        self.calls.push(function.clone());
+        self.aggregates.push(AggregateCall {
+            function: aggregate,
+            args,
+            distinct: function.distinct,
+        });
+        Ok(self.aggregates.len() - 1)
+    }
+
+    /// Names of the columns of the groups, the values grouped by then the aggregates.
+    fn scope(&self) -> Scope {
+        let groups = (0..self.group_by.len()).map(|group| format!("#group{group}"));
+        let aggregates =
+            (0..self.aggregates.len()).map(|aggregate| format!("#aggregate{aggregate}"));
+        groups
+            .chain(aggregates)
+            .fold(Scope::new(), |scope, name| scope.with_column(None, &name))
+    }
+}
+
+/// Numbering of the parameters of a query.
+#[derive(Default)]
+struct Parameters {
+    anonymous: usize,
+    numbered: usize,
+}
+
+impl Parameters {
+    /// Copy of a query with its anonymous parameters numbered in the order they are written,
+    /// so parts of it bound separately number them as a whole.
+    fn number(&mut self, query: &Query) -> Query {
+        let mut query = query.clone();
+        match &mut query.body {
+            SetExpr::Select(select) => {
+                for item in &mut select.projection {
+                    if let SelectItem::Expr { expr, .. } = item {
+                        *expr = self.rewrite(expr);
+                    }
+                }
+                for join in select.from.iter_mut().flat_map(|from| &mut from.joins) {
+                    if let JoinConstraint::On(expr) = &mut join.constraint {
+                        *expr = self.rewrite(expr);
+                    }
+                }
+                let select = &mut **select;
+                for expr in select
+                    .selection
+                    .iter_mut()
+                    .chain(&mut select.group_by)
+                    .chain(&mut select.having)
+                {
+                    *expr = self.rewrite(expr);
+                }
+            }
+            SetExpr::Values(rows) => {
+                for expr in rows.iter_mut().flatten() {
+                    *expr = self.rewrite(expr);
+                }
+            }
+            SetExpr::Query(_) | SetExpr::SetOperation { .. } => {}
+        }
+        for item in &mut query.order_by {
+            item.expr = self.rewrite(&item.expr);
+        }
+        for expr in query.limit.iter_mut().chain(&mut query.offset) {
+            *expr = self.rewrite(expr);
+        }
+        query
+    }
+
+    fn rewrite(&mut self, expr: &Expr) -> Expr {
+        rewrite(expr, &mut |expr| match expr {
+            Expr::Parameter(None) => {
+                self.anonymous += 1;
+                Some(Expr::Parameter(Some(self.anonymous)))
+            }
+            Expr::Parameter(Some(number)) => {
+                self.numbered = self.numbered.max(*number);
+                None
+            }
+            _ => None,
+        })
+    }
+
+    /// Number of parameter values the query needs.
+    fn count(&self) -> usize {
+        self.anonymous.max(self.numbered)
+    }
+}
+
+/// Columns of a `SELECT` as expressions and their names, with wildcards expanded to the
+/// columns of the tables they stand for.
+fn items(graph: &QueryGraph, projection: &[SelectItem]) -> PlanResult<Vec<(Expr, String)>> {
+    let mut items = Vec::new();
+    for item in projection {
+        match item {
+            SelectItem::Expr { expr, alias } => {
+                let name = match (alias, expr) {
+                    (Some(alias), _) => alias.normalized(),
+                    (None, Expr::Identifier(name)) => {
+                        name.last().map_or_else(String::new, Ident::normalized)
+                    }
+                    (None, Expr::Function(function)) => function
+                        .name
+                        .last()
+                        .map_or_else(String::new, Ident::normalized),
+                    (None, _) => "?column?".to_string(),
+                };
+                items.push((expr.clone(), name));
+            }
+            SelectItem::Wildcard(qualifier) => {
+                if graph.relations().is_empty() {
+                    return Err(PlanError::InvalidQuery("* without tables".to_string()));
+                }
+                let table = qualifier.last().map(Ident::normalized);
+                let mut found = false;
+                for relation in graph.relations() {
+                    if table.as_ref().is_some_and(|table| *table != relation.name) {
+                        continue;
+                    }
+                    found = true;
+                    for column in &relation.table.columns {
+                        let expr = Expr::Identifier(vec![
+                            Ident::quoted(&relation.name),
+                            Ident::quoted(&column.name),
+                        ]);
+                        items.push((expr, column.name.clone()));
+                    }
+                }
+                if !found {
+                    let table = table.unwrap_or_default();
+                    return Err(PlanError::TableMissing(table));
+                }
+            }
+        }
+    }
+    Ok(items)
+}
+
+/// Whether an expression calls an aggregate, outside any subquery.
+fn has_aggregate(expr: &Expr, functions: &FunctionRegistry) -> bool {
+    let mut found = false;
+    rewrite(expr, &mut |expr| match expr {
+        Expr::Function(function) if is_aggregate(function, functions) => {
+            found = true;
+            Some(expr.clone())
+        }
+        _ => None,
+    });
+    found
+}
+
+/// Whether a function called is an aggregate, built in or of `functions`.
+fn is_aggregate(function: &Function, functions: &FunctionRegistry) -> bool {
+    match &function.name[..] {
+        [name] => {
+            let name = name.normalized();
+            AggregateFunction::lookup(&name).is_some() || functions.aggregate(&name).is_some()
+        }
+        _ => false,
+    }
+}
+
+/// Name of a column of the groups of a grouped query, quoted so it can't be mistaken for
+/// another.
+fn name(name: &str) -> Expr {
+    Expr::Identifier(vec![Ident::quoted(name)])
+}
+
+/// Number a constant expression evaluates to, if known when planning.
+#[allow(clippy::cast_precision_loss)]
+fn constant(expr: &ScalarExpr) -> Option<f64> {
+    match expr.eval(&[], &[]) {
+        Ok(Value::BigInt(value)) => Some(value as f64),
+        Ok(Value::Integer(value)) => Some(f64::from(value)),
+        Ok(Value::SmallInt(value)) => Some(f64::from(value)),
+        _ => None,
+    }
+}
+
+/// Copy of an expression, replacing each subexpression `replace` returns a replacement for
+/// rather than copying it.
+///
+/// Expressions are offered to `replace` before their operands, and operands from left to
+/// right as written. Subqueries are copied as they are.
+fn rewrite(expr: &Expr, replace: &mut dyn FnMut(&Expr) -> Option<Expr>) -> Expr {
+    if let Some(expr) = replace(expr) {
+        return expr;
+    }
+    let mut boxed = |expr: &Expr| Box::new(rewrite(expr, replace));
+    match expr {
+        Expr::Literal(_)
+        | Expr::Identifier(_)
+        | Expr::Parameter(_)
+        | Expr::Exists { .. }
+        | Expr::Subquery(_) => expr.clone(),
+        Expr::Unary { op, expr } => Expr::Unary {
+            op: *op,
+            expr: boxed(expr),
+        },
+        Expr::Binary { left, op, right } => Expr::Binary {
+            left: boxed(left),
+            op: *op,
+            right: boxed(right),
+        },
+        Expr::IsNull { expr, negated } => Expr::IsNull {
+            expr: boxed(expr),
+            negated: *negated,
+        },
+        Expr::Between {
+            expr,
+            negated,
+            low,
+            high,
+        } => Expr::Between {
+            expr: boxed(expr),
+            negated: *negated,
+            low: boxed(low),
+            high: boxed(high),
+        },
+        Expr::InList {
+            expr,
+            negated,
+            list,
+        } => Expr::InList {
+            expr: boxed(expr),
+            negated: *negated,
+            list: list.iter().map(|expr| *boxed(expr)).collect(),
+        },
+        Expr::InSubquery {
+            expr,
+            negated,
+            query,
+        } => Expr::InSubquery {
+            expr: boxed(expr),
+            negated: *negated,
+            query: query.clone(),
+        },
+        Expr::Like {
+            expr,
+            negated,
+            pattern,
+            escape,
+        } => Expr::Like {
+            expr: boxed(expr),
+            negated: *negated,
+            pattern: boxed(pattern),
+            escape: escape.as_deref().map(&mut boxed),
+        },
+        Expr::Case {
+            operand,
+            branches,
+            else_result,
+        } => Expr::Case {
+            operand: operand.as_deref().map(&mut boxed),
+            branches: branches
+                .iter()
+                .map(|(condition, result)| (*boxed(condition), *boxed(result)))
+                .collect(),
+            else_result: else_result.as_deref().map(&mut boxed),
+        },
+        Expr::Cast { expr, data_type } => Expr::Cast {
+            expr: boxed(expr),
+            data_type: *data_type,
+        },
+        Expr::Function(function) => Expr::Function(Function {
+            name: function.name.clone(),
+            args: function.args.iter().map(|expr| *boxed(expr)).collect(),
+            wildcard: function.wildcard,
+            distinct: function.distinct,
+        }),
+        Expr::Nested(expr) => Expr::Nested(boxed(expr)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{AggregateFunction, PhysicalPlan, PlanError, Planner, QueryPlan, SortKey};
+    use minql_catalog::{Catalog, CatalogSnapshot, ColumnSchema, TableSchema};
+    use minql_lang::ast::{DataType, Statement};
+    use minql_lang::Parser;
+    use minql_value::{
+        AggregateState, AggregateUdf, Batch, FunctionRegistry, ScalarExpr, ScalarUdf, Signature,
+        Value, ValueError, ValueResult,
+    };
+    use minql_vfs::MemoryFileSystem;
+    use std::sync::Arc;
+
+    fn shop() -> Arc<CatalogSnapshot> {
+        let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+        let mut transaction = catalog.begin();
+        transaction.create_database("shop", "mem:///shop").unwrap();
+        let table = TableSchema::new("orders")
+            .with_column(ColumnSchema::new("id", DataType::BigInt))
+            .with_column(ColumnSchema::new("customer", DataType::Text))
+            .with_column(ColumnSchema::new("amount", DataType::BigInt));
+        transaction.create_table("shop", table).unwrap();
+        transaction.commit().unwrap()
+    }
+
+    fn plan(sql: &str) -> Result<QueryPlan, PlanError> {
+        let Statement::Query(query) = Parser::parse_statement(sql).unwrap() else {
+            panic!("not a query: {sql}");
+        };
+        Planner::new().plan_query(&shop(), "shop", &query)
+    }
+
+    fn operators(plan: &PhysicalPlan) -> Vec<String> {
+        let mut operators = vec![plan.describe()];
+        let mut plan = plan;
+        while let Some(child) = plan.children().first() {
+            operators.push(child.describe());
+            plan = child;
+        }
+        operators
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_projection() {
+        let planned = plan("SELECT * FROM orders").unwrap();
+        assert_eq!(planned.columns, ["id", "customer", "amount"]);
+        assert_eq!(operators(&planned.plan), ["SeqScan orders"]);
+
+        let planned = plan(
+            "SELECT amount * 2 AS double, o.id, upper(customer), 1 + ? FROM orders o WHERE id > ?",
+        )
+        .unwrap();
+        assert_eq!(planned.columns, ["double", "id", "upper", "?column?"]);
+        assert_eq!(planned.parameters, 2);
+        let PhysicalPlan::Project { exprs, input, .. } = &planned.plan else {
+            panic!("{}", planned.plan);
+        };
+        assert_eq!(exprs[1], ScalarExpr::Column(0));
+        assert_eq!(exprs[3].columns(), Vec::<usize>::new());
+        let PhysicalPlan::SeqScan {
+            filter: Some(filter),
+            ..
+        } = &**input
+        else {
+            panic!("{input}");
+        };
+        assert!(format!("{filter:?}").contains("Parameter(1)"));
+
+        let planned = plan("VALUES (1, 'a'), (2, 'b') ORDER BY column2 DESC").unwrap();
+        assert_eq!(planned.columns, ["column1", "column2"]);
+        assert_eq!(operators(&planned.plan), ["Sort 1", "Values 2"]);
+
+        assert!(matches!(plan("SELECT *"), Err(PlanError::InvalidQuery(_))));
+        assert!(matches!(
+            plan("SELECT x.* FROM orders"),
+            Err(PlanError::TableMissing(_))
+        ));
+        assert!(matches!(
+            plan("SELECT 1 UNION SELECT 2"),
+            Err(PlanError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_aggregation() {
+        let planned = plan(
+            "SELECT customer, count(*), sum(amount) + 1 AS total FROM orders \
+             GROUP BY customer HAVING sum(amount) > 10 ORDER BY max(amount)",
+        )
+        .unwrap();
+        assert_eq!(planned.columns, ["customer", "count", "total"]);
+        assert_eq!(
+            operators(&planned.plan),
+            [
+                "Project 3",
+                "Sort 1",
+                "Project 4",
+                "Filter",
+                "HashAggregate group 1 count sum max",
+                "SeqScan orders"
+            ]
+        );
+        let PhysicalPlan::Project { input, .. } = &planned.plan else {
+            unreachable!()
+        };
+        let PhysicalPlan::Sort { keys, input, .. } = &**input else {
+            unreachable!()
+        };
+        assert_eq!(
+            keys[0],
+            SortKey {
+                expr: ScalarExpr::Column(3),
+                ascending: true,
+                nulls_first: false,
+            }
+        );
+        let PhysicalPlan::Project { exprs, .. } = &**input else {
+            unreachable!()
+        };
+        assert_eq!(exprs[0], ScalarExpr::Column(0));
+        assert_eq!(exprs[1], ScalarExpr::Column(1));
+        assert_eq!(exprs[3], ScalarExpr::Column(3));
+
+        let planned = plan("SELECT count(amount) FROM orders").unwrap();
+        let PhysicalPlan::HashAggregate {
+            group_by,
+            aggregates,
+            ..
+        } = &planned.plan
+        else {
+            panic!("{}", planned.plan);
+        };
+        assert!(group_by.is_empty());
+        assert_eq!(aggregates[0].function, AggregateFunction::Count);
+
+        let planned = plan("SELECT count(DISTINCT customer), count(customer) FROM orders").unwrap();
+        assert_eq!(
+            operators(&planned.plan),
+            ["HashAggregate count distinct count", "SeqScan orders"]
+        );
+        assert_eq!(aggregates[0].args, [ScalarExpr::Column(2)]);
+
+        let planned = plan("SELECT customer AS c FROM orders GROUP BY c ORDER BY 1").unwrap();
+        assert_eq!(
+            operators(&planned.plan),
+            ["Sort 1", "HashAggregate group 1", "SeqScan orders"]
+        );
+        let planned = plan("SELECT DISTINCT customer FROM orders ORDER BY customer").unwrap();
+        assert_eq!(
+            operators(&planned.plan),
+            [
+                "Sort 1",
+                "HashAggregate group 1",
+                "Project 1",
+                "SeqScan orders"
+            ]
+        );
+
+        assert!(matches!(
+            plan("SELECT id, count(*) FROM orders GROUP BY customer"),
+            Err(PlanError::NotGrouped(_))
+        ));
+        assert!(matches!(
+            plan("SELECT sum(count(*)) FROM orders"),
+            Err(PlanError::Value(ValueError::Unsupported(_)))
+        ));
+        assert!(matches!(
+            plan("SELECT DISTINCT customer FROM orders ORDER BY amount"),
+            Err(PlanError::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            plan("SELECT customer FROM orders GROUP BY 2"),
+            Err(PlanError::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_user_defined_functions() {
+        #[derive(Debug)]
+        struct Rows(i64);
+
+        impl AggregateState for Rows {
+            fn update(&mut self, args: &Batch) -> ValueResult<()> {
+                self.0 += i64::try_from(args.len()).unwrap();
+                Ok(())
+            }
+
+            fn finish(&mut self) -> ValueResult<Value> {
+                Ok(Value::BigInt(self.0))
+            }
+        }
+
+        let mut functions = FunctionRegistry::new();
+        let signature = Signature::new(&[DataType::BigInt], DataType::BigInt);
+        let half = ScalarUdf::new("half", signature, |args| Ok(args.column(0).to_vec()));
+        functions.register_scalar(half.clone()).unwrap();
+        let signature = Signature::new(&[DataType::BigInt, DataType::Text], DataType::BigInt);
+        let pairs = AggregateUdf::new("pairs", signature, || Box::new(Rows(0)));
+        functions.register_aggregate(pairs.clone()).unwrap();
+        let functions = Arc::new(functions);
+        let plan = |sql: &str| {
+            let Statement::Query(query) = Parser::parse_statement(sql).unwrap() else {
+                panic!("not a query: {sql}");
+            };
+            Planner::new()
+                .with_functions(functions.clone())
+                .plan_query(&shop(), "shop", &query)
+        };
+
+        let planned = plan(
+            "SELECT customer, pairs(half(amount), customer) AS n FROM orders \
+             WHERE half(id) > 1 GROUP BY customer HAVING pairs(half(amount), customer) > 2",
+        )
+        .unwrap();
+        assert_eq!(planned.columns, ["customer", "n"]);
+        assert_eq!(
+            operators(&planned.plan),
+            [
+                "Filter",
+                "HashAggregate group 1 pairs",
+                "SeqScan orders filtered"
+            ]
+        );
+        let PhysicalPlan::Filter { input, .. } = &planned.plan else {
+            panic!("{}", planned.plan);
+        };
+        let PhysicalPlan::HashAggregate { aggregates, .. } = &**input else {
+            panic!("{}", planned.plan);
+        };
+        assert_eq!(aggregates.len(), 1);
+        assert_eq!(aggregates[0].function, AggregateFunction::Udf(pairs));
+        assert_eq!(
+            aggregates[0].args,
+            [
+                ScalarExpr::Udf {
+                    function: half,
+                    args: vec![ScalarExpr::Column(2)],
+                },
+                ScalarExpr::Column(1),
+            ]
+        );
+
+        assert!(matches!(
+            plan("SELECT pairs(amount) FROM orders"),
+            Err(PlanError::Value(ValueError::Function { .. }))
+        ));
+        assert!(matches!(
+            plan("SELECT pairs(*) FROM orders"),
+            Err(PlanError::Value(ValueError::Unsupported(_)))
+        ));
+        assert!(matches!(
+            plan("SELECT id FROM orders GROUP BY pairs(id, customer)"),
+            Err(PlanError::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            self::plan("SELECT half(id) FROM orders"),
+            Err(PlanError::Value(ValueError::UnknownFunction(_)))
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_order_and_limit() {
+        let planned =
+            plan("SELECT id FROM orders ORDER BY amount DESC, 1 LIMIT 10 OFFSET ?").unwrap();
+        assert_eq!(planned.parameters, 1);
+        assert_eq!(
+            operators(&planned.plan),
+            [
+                "Limit offset",
+                "Project 1",
+                "Sort 2",
+                "Project 2",
+                "SeqScan orders"
+            ]
+        );
+        let PhysicalPlan::Limit {
+            input, estimate, ..
+        } = &planned.plan
+        else {
+            unreachable!()
+        };
+        assert!((estimate.rows - 10.0).abs() < f64::EPSILON);
+        let PhysicalPlan::Project { input, .. } = &**input else {
+            unreachable!()
+        };
+        let PhysicalPlan::Sort { keys, .. } = &**input else {
+            unreachable!()
+        };
+        assert_eq!(keys[0].expr, ScalarExpr::Column(1));
+        assert!(!keys[0].ascending && keys[0].nulls_first);
+        assert_eq!(keys[1].expr, ScalarExpr::Column(0));
+
+        assert!(matches!(
+            plan("SELECT id FROM orders ORDER BY 3"),
+            Err(PlanError::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            plan("SELECT id, amount AS id FROM orders ORDER BY id"),
+            Err(PlanError::Value(ValueError::AmbiguousColumn(_)))
+        ));
+    }
+}
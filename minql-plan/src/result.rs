@@ -0,0 +1,60 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use minql_value::ValueError;
+
+/// Result Type for the Planner
+pub type PlanResult<T> = Result<T, PlanError>;
+
+/// Error Type for the Planner
+#[derive(Debug)]
+pub enum PlanError {
+    /// Database doesn't exist in the catalog
+    DatabaseMissing(String),
+    /// Table doesn't exist in the database
+    TableMissing(String),
+    /// Table is named more than once in `FROM`, without aliases telling them apart
+    DuplicateTable(String),
+    /// Column is read outside an aggregate by a grouped query without being grouped by
+    NotGrouped(String),
+    /// Query is invalid in a way only its tables or columns reveal
+    InvalidQuery(String),
+    /// Query uses a feature the planner doesn't support yet
+    Unsupported(String),
+    /// Error binding an expression
+    Value(ValueError),
+}
+
+impl std::fmt::Display for PlanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for PlanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlanError::Value(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ValueError> for PlanError {
+    fn from(err: ValueError) -> Self {
+        PlanError::Value(err)
+    }
+}
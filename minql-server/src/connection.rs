@@ -0,0 +1,553 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::message::{format, read_message, read_startup, Backend, Frontend, Startup};
+use crate::message::{PROTOCOL_VERSION, TEXT_OID};
+use crate::{ServerError, ServerResult};
+use minql_catalog::CatalogError;
+use minql_exec::{Cursor, Engine, ExecError, Prepared, Response, Session};
+use minql_lang::ast::{DataType, Statement};
+use minql_lang::Parser;
+use minql_plan::PlanError;
+use minql_value::{Decimal, Timestamp, Value, ValueError};
+use minql_vfs::FileSystem;
+use std::collections::HashMap;
+use std::io::{BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+
+/// Microseconds from `1970-01-01`, where timestamps count from, to `2000-01-01`, where the
+/// binary timestamps of the protocol count from.
+const POSTGRES_EPOCH: i64 = 946_684_800_000_000;
+
+/// Parameters of the server reported to every client once its session started.
+const PARAMETERS: [(&str, &str); 7] = [
+    ("server_version", "14.0"),
+    ("server_encoding", "UTF8"),
+    ("client_encoding", "UTF8"),
+    ("DateStyle", "ISO, MDY"),
+    ("TimeZone", "UTC"),
+    ("integer_datetimes", "on"),
+    ("standard_conforming_strings", "on"),
+];
+
+/// Session of a client connected to the server, and the statements and portals it made by
+/// the extended query protocol.
+pub(crate) struct Connection<F: FileSystem> {
+    session: Session<F>,
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+    statements: HashMap<String, Arc<ParsedStatement>>,
+    portals: HashMap<String, Portal>,
+    /// Whether an extended query message failed, so messages are skipped until `Sync`
+    failed: bool,
+}
+
+/// Statement prepared by `Parse`.
+struct ParsedStatement {
+    /// Statement, or `None` if the query was empty
+    prepared: Option<Prepared>,
+    /// Types of the parameters given by the client, zero where left unspecified
+    types: Vec<u32>,
+}
+
+/// Prepared statement bound to the values of its parameters by `Bind`.
+struct Portal {
+    statement: Arc<ParsedStatement>,
+    params: Vec<Value>,
+    /// Formats of the columns of the rows returned
+    formats: Vec<i16>,
+    state: PortalState,
+}
+
+enum PortalState {
+    /// Not run yet
+    Bound,
+    /// Suspended before returning every row
+    Running(Cursor),
+    /// Run to completion
+    Done,
+}
+
+impl<F: FileSystem> Connection<F> {
+    /// Start the session of a client, refusing requests to encrypt the connection, or return
+    /// `None` if the client went away or asked for a database that doesn't exist.
+    #[tracing::instrument(level = "debug", skip(engine, stream))]
+    pub(crate) fn start(
+        engine: Arc<Engine<F>>,
+        stream: TcpStream,
+        process: u32,
+        secret: u32,
+    ) -> ServerResult<Option<Connection<F>>> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        let (version, parameters) = loop {
+            match read_startup(&mut reader)? {
+                Startup::Encryption => {
+                    writer.write_all(b"N")?;
+                    writer.flush()?;
+                }
+                Startup::Cancel => return Ok(None),
+                Startup::Start {
+                    version,
+                    parameters,
+                } => break (version, parameters),
+            }
+        };
+        let fatal = |writer: &mut BufWriter<TcpStream>, code, message: &str| {
+            Backend::ErrorResponse {
+                severity: "FATAL",
+                code,
+                message,
+            }
+            .write(writer)?;
+            writer.flush()?;
+            Ok(None)
+        };
+        if version != PROTOCOL_VERSION {
+            let message = format!("unsupported protocol version {version:#x}");
+            return fatal(&mut writer, "0A000", &message);
+        }
+        let parameter = |name| {
+            parameters
+                .iter()
+                .find(|(parameter, _)| parameter == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let Some(database) = parameter("database").or_else(|| parameter("user")) else {
+            return fatal(&mut writer, "28000", "no user or database given");
+        };
+        let session = match Session::new(engine, database) {
+            Ok(session) => session,
+            Err(err) => return fatal(&mut writer, sqlstate(&err), &err.to_string()),
+        };
+        Backend::AuthenticationOk.write(&mut writer)?;
+        for (name, value) in PARAMETERS {
+            Backend::ParameterStatus { name, value }.write(&mut writer)?;
+        }
+        Backend::KeyData { process, secret }.write(&mut writer)?;
+        Backend::ReadyForQuery.write(&mut writer)?;
+        writer.flush()?;
+        Ok(Some(Connection {
+            session,
+            reader,
+            writer,
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            failed: false,
+        }))
+    }
+
+    /// Answer the messages of the client until it ends the connection.
+    pub(crate) fn run(mut self) -> ServerResult<()> {
+        while let Some(message) = read_message(&mut self.reader)? {
+            match message {
+                Frontend::Query(sql) => {
+                    self.query(&sql)?;
+                    self.ready()?;
+                }
+                Frontend::Sync => {
+                    self.failed = false;
+                    self.ready()?;
+                }
+                Frontend::Flush => self.writer.flush()?,
+                Frontend::Terminate => break,
+                _ if self.failed => {}
+                message => match self.extended(message) {
+                    Ok(()) => {}
+                    Err(ServerError::Exec(err)) => {
+                        self.failed = true;
+                        self.error(sqlstate(&err), &err.to_string())?;
+                    }
+                    Err(ServerError::Request { code, message }) => {
+                        self.failed = true;
+                        self.error(code, &message)?;
+                    }
+                    Err(err) => return Err(err),
+                },
+            }
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Run the statements of a simple query, stopping at the first that fails.
+    #[tracing::instrument(level = "debug", skip(self))]
+    fn query(&mut self, sql: &str) -> ServerResult<()> {
+        self.portals.remove("");
+        let statements = match Parser::parse(sql) {
+            Ok(statements) => statements,
+            Err(err) => return self.error("42601", &err.to_string()),
+        };
+        if statements.is_empty() {
+            return self.send(&Backend::EmptyQueryResponse);
+        }
+        for statement in statements {
+            match self.simple(statement) {
+                Ok(()) => {}
+                Err(ServerError::Exec(err)) => return self.error(sqlstate(&err), &err.to_string()),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a statement of a simple query, returning every row it returns.
+    fn simple(&mut self, statement: Statement) -> ServerResult<()> {
+        let prepared = self.session.prepare(statement)?;
+        match self.session.execute(&prepared, Vec::new())? {
+            Response::Rows { columns, cursor } => {
+                self.send(&Backend::RowDescription {
+                    columns: &columns,
+                    formats: &[],
+                })?;
+                let rows = self.rows(cursor, 0)?.1;
+                self.send(&Backend::CommandComplete(&tag(&prepared, rows)))
+            }
+            Response::Done { rows } => self.send(&Backend::CommandComplete(&tag(&prepared, rows))),
+        }
+    }
+
+    /// Answer a message of the extended query protocol.
+    fn extended(&mut self, message: Frontend) -> ServerResult<()> {
+        match message {
+            Frontend::Parse { name, query, types } => {
+                let mut statements = Parser::parse(&query).map_err(ExecError::from)?;
+                if statements.len() > 1 {
+                    return Err(ServerError::Request {
+                        code: "42601",
+                        message: "cannot prepare several statements together".to_string(),
+                    });
+                }
+                let prepared = match statements.pop() {
+                    Some(statement) => Some(self.session.prepare(statement)?),
+                    None => None,
+                };
+                let statement = ParsedStatement { prepared, types };
+                self.statements.insert(name, Arc::new(statement));
+                self.send(&Backend::ParseComplete)
+            }
+            Frontend::Bind {
+                portal,
+                statement,
+                formats,
+                values,
+                results,
+            } => {
+                let statement = self.statement(&statement)?;
+                let portal_of = statement.bind(&formats, &values, results)?;
+                self.portals.insert(portal, portal_of);
+                self.send(&Backend::BindComplete)
+            }
+            Frontend::Describe { kind: b'S', name } => {
+                let statement = self.statement(&name)?;
+                let parameters = statement.prepared.as_ref().map_or(0, Prepared::parameters);
+                let types: Vec<u32> = (0..parameters)
+                    .map(|position| match statement.types.get(position) {
+                        Some(&oid) if oid != 0 => oid,
+                        _ => TEXT_OID,
+                    })
+                    .collect();
+                self.send(&Backend::ParameterDescription(&types))?;
+                self.describe(&statement, &[])
+            }
+            Frontend::Describe { kind: b'P', name } => {
+                let portal = self.portal(&name)?;
+                let (statement, formats) = (portal.statement.clone(), portal.formats.clone());
+                self.describe(&statement, &formats)
+            }
+            Frontend::Execute { portal, max_rows } => self.execute(portal, max_rows),
+            Frontend::Close { kind, name } => {
+                match kind {
+                    b'S' => self.statements.remove(&name).map(drop),
+                    _ => self.portals.remove(&name).map(drop),
+                };
+                self.send(&Backend::CloseComplete)
+            }
+            Frontend::Describe { kind, .. } => Err(ServerError::Protocol(format!(
+                "unexpected describe of {:?}",
+                char::from(kind)
+            ))),
+            Frontend::Query(_) | Frontend::Sync | Frontend::Flush | Frontend::Terminate => {
+                unreachable!("not extended query message")
+            }
+        }
+    }
+
+    /// Describe the rows a statement returns, formatted by `formats`.
+    fn describe(&mut self, statement: &ParsedStatement, formats: &[i16]) -> ServerResult<()> {
+        let columns = statement
+            .prepared
+            .as_ref()
+            .map(Prepared::columns)
+            .unwrap_or_default();
+        if columns.is_empty() {
+            self.send(&Backend::NoData)
+        } else {
+            self.send(&Backend::RowDescription {
+                columns: &columns,
+                formats,
+            })
+        }
+    }
+
+    /// Run a portal, suspending it once it returned `max_rows` rows unless that is zero.
+    fn execute(&mut self, name: String, max_rows: u32) -> ServerResult<()> {
+        let mut portal = self
+            .portals
+            .remove(&name)
+            .ok_or_else(|| missing("portal", &name))?;
+        let result = self.resume(&mut portal, max_rows);
+        self.portals.insert(name, portal);
+        result
+    }
+
+    fn resume(&mut self, portal: &mut Portal, max_rows: u32) -> ServerResult<()> {
+        let Some(prepared) = &portal.statement.prepared else {
+            return self.send(&Backend::EmptyQueryResponse);
+        };
+        if let PortalState::Bound = portal.state {
+            let params = std::mem::take(&mut portal.params);
+            portal.state = PortalState::Done;
+            match self.session.execute(prepared, params)? {
+                Response::Rows { cursor, .. } => portal.state = PortalState::Running(cursor),
+                Response::Done { rows } => {
+                    return self.send(&Backend::CommandComplete(&tag(prepared, rows)));
+                }
+            }
+        }
+        let rows = match std::mem::replace(&mut portal.state, PortalState::Done) {
+            PortalState::Running(cursor) => {
+                let (cursor, rows) = self.rows(cursor, max_rows)?;
+                if let Some(cursor) = cursor {
+                    portal.state = PortalState::Running(cursor);
+                    return self.send(&Backend::PortalSuspended);
+                }
+                rows
+            }
+            PortalState::Bound | PortalState::Done => 0,
+        };
+        self.send(&Backend::CommandComplete(&tag(prepared, rows)))
+    }
+
+    /// Send the rows of a cursor, returning the number sent and the cursor if it stopped at
+    /// `max_rows` rows, unless that is zero.
+    fn rows(&mut self, mut cursor: Cursor, max_rows: u32) -> ServerResult<(Option<Cursor>, u64)> {
+        let mut rows = 0;
+        loop {
+            if max_rows != 0 && rows == u64::from(max_rows) {
+                return Ok((Some(cursor), rows));
+            }
+            let Some(row) = cursor.next() else {
+                return Ok((None, rows));
+            };
+            self.send(&Backend::DataRow(&row?))?;
+            rows += 1;
+        }
+    }
+
+    fn statement(&self, name: &str) -> ServerResult<Arc<ParsedStatement>> {
+        self.statements
+            .get(name)
+            .cloned()
+            .ok_or_else(|| missing("prepared statement", name))
+    }
+
+    fn portal(&self, name: &str) -> ServerResult<&Portal> {
+        self.portals
+            .get(name)
+            .ok_or_else(|| missing("portal", name))
+    }
+
+    fn ready(&mut self) -> ServerResult<()> {
+        self.send(&Backend::ReadyForQuery)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn error(&mut self, code: &str, message: &str) -> ServerResult<()> {
+        tracing::debug!(code, message, "Statement failed");
+        self.send(&Backend::ErrorResponse {
+            severity: "ERROR",
+            code,
+            message,
+        })
+    }
+
+    fn send(&mut self, message: &Backend<'_>) -> ServerResult<()> {
+        message.write(&mut self.writer)
+    }
+}
+
+impl ParsedStatement {
+    /// Bind the values of the parameters of the statement into a portal.
+    fn bind(
+        self: &Arc<Self>,
+        formats: &[i16],
+        values: &[Option<Vec<u8>>],
+        results: Vec<i16>,
+    ) -> ServerResult<Portal> {
+        let expected = self.prepared.as_ref().map_or(0, Prepared::parameters);
+        if values.len() != expected {
+            return Err(ExecError::Parameters {
+                expected,
+                found: values.len(),
+            }
+            .into());
+        }
+        let params = values
+            .iter()
+            .enumerate()
+            .map(|(position, value)| {
+                let oid = self.types.get(position).copied().unwrap_or(0);
+                parameter(value.as_deref(), format(formats, position), oid)
+            })
+            .collect::<ServerResult<_>>()?;
+        Ok(Portal {
+            statement: self.clone(),
+            params,
+            formats: results,
+            state: PortalState::Bound,
+        })
+    }
+}
+
+/// Value of a parameter sent in a format, of the type of an OID, or of the type its text
+/// reads as if the client left it unspecified: a whole number as a `BIGINT`, a decimal number
+/// as a `DECIMAL` and anything else as `TEXT`.
+fn parameter(value: Option<&[u8]>, format: i16, oid: u32) -> ServerResult<Value> {
+    let Some(value) = value else {
+        return Ok(Value::Null);
+    };
+    let data_type = data_type(oid);
+    if format == 1 {
+        let value = match (data_type, value.len()) {
+            (Some(DataType::Boolean), 1) => Value::Boolean(value[0] != 0),
+            (Some(DataType::SmallInt), 2) => {
+                Value::SmallInt(i16::from_be_bytes([value[0], value[1]]))
+            }
+            (Some(DataType::Integer), 4) => Value::Integer(i32::from_be_bytes(fixed(value))),
+            (Some(DataType::BigInt), 8) => Value::BigInt(i64::from_be_bytes(fixed(value))),
+            (Some(DataType::Real), 4) => Value::Real(f32::from_be_bytes(fixed(value))),
+            (Some(DataType::Double), 8) => Value::Double(f64::from_be_bytes(fixed(value))),
+            (Some(DataType::Timestamp), 8) => {
+                let micros = i64::from_be_bytes(fixed(value)).saturating_add(POSTGRES_EPOCH);
+                Value::Timestamp(Timestamp::from_micros(micros))
+            }
+            (Some(DataType::Blob), _) => Value::Blob(value.to_vec()),
+            (None | Some(DataType::Text | DataType::Varchar(_)), _) => return text(value, None),
+            _ => {
+                return Err(ServerError::Request {
+                    code: "22P03",
+                    message: format!("invalid binary parameter of type {oid}"),
+                })
+            }
+        };
+        return Ok(value);
+    }
+    text(value, data_type)
+}
+
+/// Value of a parameter sent as text, converted to a type if it has one.
+fn text(value: &[u8], data_type: Option<DataType>) -> ServerResult<Value> {
+    let text = std::str::from_utf8(value).map_err(|_| ServerError::Request {
+        code: "22021",
+        message: "parameter is not UTF-8".to_string(),
+    })?;
+    match data_type {
+        Some(data_type) => Ok(Value::from(text).cast(data_type).map_err(ExecError::from)?),
+        None => Ok(if let Ok(number) = text.parse::<i64>() {
+            Value::BigInt(number)
+        } else if let Some(number) = Decimal::parse(text) {
+            Value::Decimal(number)
+        } else {
+            Value::from(text)
+        }),
+    }
+}
+
+fn fixed<const N: usize>(value: &[u8]) -> [u8; N] {
+    value.try_into().expect("Checked Length")
+}
+
+/// Type of the values of an OID, or `None` if unspecified or unknown.
+fn data_type(oid: u32) -> Option<DataType> {
+    match oid {
+        16 => Some(DataType::Boolean),
+        17 => Some(DataType::Blob),
+        20 => Some(DataType::BigInt),
+        21 => Some(DataType::SmallInt),
+        23 => Some(DataType::Integer),
+        25 => Some(DataType::Text),
+        700 => Some(DataType::Real),
+        701 => Some(DataType::Double),
+        1043 => Some(DataType::Varchar(None)),
+        1114 | 1184 => Some(DataType::Timestamp),
+        1700 => Some(DataType::Decimal(None)),
+        _ => None,
+    }
+}
+
+/// Command tag of a statement that changed or returned `rows` rows.
+fn tag(prepared: &Prepared, rows: u64) -> String {
+    match prepared.command() {
+        "SELECT" => format!("SELECT {rows}"),
+        "INSERT" => format!("INSERT 0 {rows}"),
+        command => command.to_string(),
+    }
+}
+
+fn missing(kind: &str, name: &str) -> ServerError {
+    ServerError::Request {
+        code: if kind == "portal" { "34000" } else { "26000" },
+        message: format!("{kind} {name:?} does not exist"),
+    }
+}
+
+/// SQLSTATE code of an error.
+fn sqlstate(err: &ExecError) -> &'static str {
+    match err {
+        ExecError::Plan(PlanError::DatabaseMissing(_))
+        | ExecError::Catalog(CatalogError::DatabaseMissing(_)) => "3D000",
+        ExecError::Plan(PlanError::TableMissing(_))
+        | ExecError::Catalog(CatalogError::TableMissing(_))
+        | ExecError::TableMissing(_) => "42P01",
+        ExecError::Plan(PlanError::DuplicateTable(_)) => "42712",
+        ExecError::Plan(PlanError::NotGrouped(_)) => "42803",
+        ExecError::Lang(_)
+        | ExecError::Plan(PlanError::InvalidQuery(_))
+        | ExecError::RowWidth { .. } => "42601",
+        ExecError::Plan(PlanError::Unsupported(_)) | ExecError::Unsupported(_) => "0A000",
+        ExecError::Plan(PlanError::Value(err)) | ExecError::Value(err) => match err {
+            ValueError::UnknownColumn(_) => "42703",
+            ValueError::AmbiguousColumn(_) => "42702",
+            ValueError::UnknownFunction(_) => "42883",
+            ValueError::DivisionByZero => "22012",
+            ValueError::Overflow(_) => "22003",
+            ValueError::TypeMismatch { .. } | ValueError::IncompatibleTypes { .. } => "42804",
+            _ => "22000",
+        },
+        ExecError::Catalog(CatalogError::TableExists(_) | CatalogError::IndexExists(_)) => "42P07",
+        ExecError::Catalog(CatalogError::IndexMissing(_)) => "42704",
+        ExecError::Catalog(CatalogError::ColumnMissing(_)) => "42703",
+        ExecError::Catalog(CatalogError::DuplicateColumn(_)) => "42701",
+        ExecError::Catalog(CatalogError::TableReferenced { .. }) => "2BP01",
+        ExecError::Catalog(CatalogError::InvalidSchema(_)) => "42P16",
+        ExecError::NotNull(_) => "23502",
+        ExecError::UniqueViolation(_) => "23505",
+        ExecError::Parameters { .. } => "08P01",
+        _ => "XX000",
+    }
+}
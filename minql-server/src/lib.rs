@@ -0,0 +1,64 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Postgres Wire Protocol Server
+//!
+//! The [`Server`] speaks enough of version 3.0 of the Postgres frontend/backend protocol
+//! for `psql` and standard drivers to run statements against a `minql-exec`
+//! [`Engine`](minql_exec::Engine): the startup of a session, simple queries of one or more
+//! statements, and the extended query protocol of prepared statements and portals, with the
+//! descriptions of their parameters and rows and errors reported by their SQLSTATE codes.
+//!
+//! ```rust
+//! use minql_catalog::Catalog;
+//! use minql_exec::{Engine, Executor, MemoryStorage};
+//! use minql_server::Server;
+//! use minql_vfs::MemoryFileSystem;
+//! use std::sync::Arc;
+//!
+//! let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+//! let mut transaction = catalog.begin();
+//! transaction.create_database("postgres", "mem:///data/postgres").unwrap();
+//! transaction.commit().unwrap();
+//! let engine = Engine::new(catalog, Executor::new(Arc::new(MemoryStorage::new())));
+//!
+//! let server = Server::bind(Arc::new(engine), "127.0.0.1:0").unwrap();
+//! let address = server.local_addr().unwrap();
+//! std::thread::spawn(move || server.serve());
+//! assert_ne!(address.port(), 0);
+//! ```
+
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod connection;
+mod message;
+mod result;
+mod server;
+
+pub use self::result::{ServerError, ServerResult};
+pub use self::server::Server;
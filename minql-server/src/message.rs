@@ -0,0 +1,438 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::{ServerError, ServerResult};
+use minql_value::Value;
+use std::io::{Read, Write};
+
+/// Version 3.0 of the protocol, the only one spoken.
+pub(crate) const PROTOCOL_VERSION: u32 = 0x0003_0000;
+
+/// Code of a request to encrypt the connection with TLS.
+const SSL_REQUEST: u32 = 80_877_103;
+
+/// Code of a request to encrypt the connection with GSSAPI.
+const GSSENC_REQUEST: u32 = 80_877_104;
+
+/// Code of a request to cancel the query of another connection.
+const CANCEL_REQUEST: u32 = 80_877_102;
+
+/// Longest message accepted, counting its length but not its type.
+const MAX_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Type of every column and parameter described, as values are sent and received as text.
+pub(crate) const TEXT_OID: u32 = 25;
+
+/// First message of a connection, sent before the type bytes of later messages.
+#[derive(Debug)]
+pub(crate) enum Startup {
+    /// Request to encrypt the connection, which is refused before the client starts again
+    Encryption,
+    /// Request to cancel the query of another connection
+    Cancel,
+    /// Start of a session, with the parameters of the client such as `user` and `database`
+    Start {
+        /// Version of the protocol requested
+        version: u32,
+        /// Names and values of the parameters
+        parameters: Vec<(String, String)>,
+    },
+}
+
+/// Message sent by a client once its session started.
+#[derive(Debug)]
+pub(crate) enum Frontend {
+    /// `Q`, statements run by the simple query protocol
+    Query(String),
+    /// `P`, statement prepared under a name, empty for the unnamed statement
+    Parse {
+        /// Name of the statement
+        name: String,
+        /// Text of the statement
+        query: String,
+        /// Types of the parameters given, zero where left unspecified
+        types: Vec<u32>,
+    },
+    /// `B`, values of the parameters of a prepared statement bound into a portal
+    Bind {
+        /// Name of the portal, empty for the unnamed portal
+        portal: String,
+        /// Name of the prepared statement
+        statement: String,
+        /// Formats of the parameters, `0` for text and `1` for binary
+        formats: Vec<i16>,
+        /// Values of the parameters, `None` for `NULL`
+        values: Vec<Option<Vec<u8>>>,
+        /// Formats of the columns of the rows returned
+        results: Vec<i16>,
+    },
+    /// `D`, description of a prepared statement (`S`) or portal (`P`)
+    Describe {
+        /// `S` or `P`
+        kind: u8,
+        /// Name of the statement or portal
+        name: String,
+    },
+    /// `E`, run a portal, returning at most a number of rows, or every row if zero
+    Execute {
+        /// Name of the portal
+        portal: String,
+        /// Rows returned before suspending the portal
+        max_rows: u32,
+    },
+    /// `C`, close a prepared statement (`S`) or portal (`P`)
+    Close {
+        /// `S` or `P`
+        kind: u8,
+        /// Name of the statement or portal
+        name: String,
+    },
+    /// `S`, end of a series of extended query messages
+    Sync,
+    /// `H`, send any buffered responses
+    Flush,
+    /// `X`, end of the connection
+    Terminate,
+}
+
+/// Message sent to a client.
+#[derive(Debug)]
+pub(crate) enum Backend<'a> {
+    /// `R`, the client is authenticated
+    AuthenticationOk,
+    /// `S`, value of a parameter of the server
+    ParameterStatus {
+        /// Name of the parameter
+        name: &'a str,
+        /// Value of the parameter
+        value: &'a str,
+    },
+    /// `K`, key a client cancels queries of the connection with
+    KeyData {
+        /// Id of the connection
+        process: u32,
+        /// Secret of the connection
+        secret: u32,
+    },
+    /// `Z`, ready for the next query, outside any transaction
+    ReadyForQuery,
+    /// `T`, names of the columns of rows, described as text
+    RowDescription {
+        /// Names of the columns
+        columns: &'a [String],
+        /// Formats of the columns, as given by `Bind`
+        formats: &'a [i16],
+    },
+    /// `t`, types of the parameters of a prepared statement
+    ParameterDescription(&'a [u32]),
+    /// `n`, the statement or portal described returns no rows
+    NoData,
+    /// `D`, values of a row, as text
+    DataRow(&'a [Value]),
+    /// `C`, statement ran to completion, with its command tag
+    CommandComplete(&'a str),
+    /// `I`, the query was empty
+    EmptyQueryResponse,
+    /// `1`
+    ParseComplete,
+    /// `2`
+    BindComplete,
+    /// `3`
+    CloseComplete,
+    /// `s`, the portal ran out of rows to return before its last row
+    PortalSuspended,
+    /// `E`, error with its SQLSTATE code
+    ErrorResponse {
+        /// `ERROR` or `FATAL`
+        severity: &'a str,
+        /// SQLSTATE code
+        code: &'a str,
+        /// Message of the error
+        message: &'a str,
+    },
+}
+
+/// Read the first message of a connection.
+pub(crate) fn read_startup(reader: &mut impl Read) -> ServerResult<Startup> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let mut body = Body::read(reader, u32::from_be_bytes(length))?;
+    let code = body.u32()?;
+    match code {
+        SSL_REQUEST | GSSENC_REQUEST => Ok(Startup::Encryption),
+        CANCEL_REQUEST => Ok(Startup::Cancel),
+        version => {
+            let mut parameters = Vec::new();
+            loop {
+                let name = body.string()?;
+                if name.is_empty() {
+                    break;
+                }
+                parameters.push((name, body.string()?));
+            }
+            Ok(Startup::Start {
+                version,
+                parameters,
+            })
+        }
+    }
+}
+
+/// Read the next message of a client, or `None` if it closed the connection.
+pub(crate) fn read_message(reader: &mut impl Read) -> ServerResult<Option<Frontend>> {
+    let mut header = [0; 5];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+    let mut body = Body::read(reader, length)?;
+    let message = match header[0] {
+        b'Q' => Frontend::Query(body.string()?),
+        b'P' => {
+            let name = body.string()?;
+            let query = body.string()?;
+            let types = (0..body.u16()?)
+                .map(|_| body.u32())
+                .collect::<ServerResult<_>>()?;
+            Frontend::Parse { name, query, types }
+        }
+        b'B' => {
+            let portal = body.string()?;
+            let statement = body.string()?;
+            let formats = body.formats()?;
+            let values = (0..body.u16()?)
+                .map(|_| match body.u32()? {
+                    u32::MAX => Ok(None),
+                    length => Ok(Some(body.bytes(length as usize)?.to_vec())),
+                })
+                .collect::<ServerResult<_>>()?;
+            let results = body.formats()?;
+            Frontend::Bind {
+                portal,
+                statement,
+                formats,
+                values,
+                results,
+            }
+        }
+        b'D' => Frontend::Describe {
+            kind: body.u8()?,
+            name: body.string()?,
+        },
+        b'E' => Frontend::Execute {
+            portal: body.string()?,
+            max_rows: body.u32()?,
+        },
+        b'C' => Frontend::Close {
+            kind: body.u8()?,
+            name: body.string()?,
+        },
+        b'S' => Frontend::Sync,
+        b'H' => Frontend::Flush,
+        b'X' => Frontend::Terminate,
+        other => {
+            return Err(ServerError::Protocol(format!(
+                "unexpected message type {:?}",
+                char::from(other)
+            )))
+        }
+    };
+    Ok(Some(message))
+}
+
+impl Backend<'_> {
+    /// Write the message, its type byte followed by its length and body.
+    pub(crate) fn write(&self, writer: &mut impl Write) -> ServerResult<()> {
+        let mut body = Vec::new();
+        let kind = match self {
+            Backend::AuthenticationOk => {
+                body.extend_from_slice(&0u32.to_be_bytes());
+                b'R'
+            }
+            Backend::ParameterStatus { name, value } => {
+                put_string(&mut body, name);
+                put_string(&mut body, value);
+                b'S'
+            }
+            Backend::KeyData { process, secret } => {
+                body.extend_from_slice(&process.to_be_bytes());
+                body.extend_from_slice(&secret.to_be_bytes());
+                b'K'
+            }
+            Backend::ReadyForQuery => {
+                body.push(b'I');
+                b'Z'
+            }
+            Backend::RowDescription { columns, formats } => {
+                put_count(&mut body, columns.len())?;
+                for (position, column) in columns.iter().enumerate() {
+                    put_string(&mut body, column);
+                    // Table and column of the table, none as columns are computed
+                    body.extend_from_slice(&0u32.to_be_bytes());
+                    body.extend_from_slice(&0u16.to_be_bytes());
+                    body.extend_from_slice(&TEXT_OID.to_be_bytes());
+                    // Variable length, without a type modifier
+                    body.extend_from_slice(&(-1i16).to_be_bytes());
+                    body.extend_from_slice(&(-1i32).to_be_bytes());
+                    body.extend_from_slice(&format(formats, position).to_be_bytes());
+                }
+                b'T'
+            }
+            Backend::ParameterDescription(types) => {
+                put_count(&mut body, types.len())?;
+                for oid in *types {
+                    body.extend_from_slice(&oid.to_be_bytes());
+                }
+                b't'
+            }
+            Backend::NoData => b'n',
+            Backend::DataRow(values) => {
+                put_count(&mut body, values.len())?;
+                for value in *values {
+                    if value.is_null() {
+                        body.extend_from_slice(&(-1i32).to_be_bytes());
+                    } else {
+                        let text = value.to_string();
+                        body.extend_from_slice(&length(text.len())?.to_be_bytes());
+                        body.extend_from_slice(text.as_bytes());
+                    }
+                }
+                b'D'
+            }
+            Backend::CommandComplete(tag) => {
+                put_string(&mut body, tag);
+                b'C'
+            }
+            Backend::EmptyQueryResponse => b'I',
+            Backend::ParseComplete => b'1',
+            Backend::BindComplete => b'2',
+            Backend::CloseComplete => b'3',
+            Backend::PortalSuspended => b's',
+            Backend::ErrorResponse {
+                severity,
+                code,
+                message,
+            } => {
+                for (field, value) in [(b'S', severity), (b'V', severity), (b'C', code)] {
+                    body.push(field);
+                    put_string(&mut body, value);
+                }
+                body.push(b'M');
+                put_string(&mut body, message);
+                body.push(0);
+                b'E'
+            }
+        };
+        writer.write_all(&[kind])?;
+        writer.write_all(&length(body.len() + 4)?.to_be_bytes())?;
+        writer.write_all(&body)?;
+        Ok(())
+    }
+}
+
+/// Format of a column or parameter at a position, given the formats of a `Bind`: text
+/// without any, the same for every position with one, and one for each position otherwise.
+pub(crate) fn format(formats: &[i16], position: usize) -> i16 {
+    match formats {
+        [] => 0,
+        [format] => *format,
+        formats => formats.get(position).copied().unwrap_or(0),
+    }
+}
+
+/// Body of a message being read.
+struct Body {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl Body {
+    /// Read the body of a message of a length, which counts the length itself.
+    fn read(reader: &mut impl Read, length: u32) -> ServerResult<Body> {
+        let length = (length as usize)
+            .checked_sub(4)
+            .filter(|&length| length <= MAX_LENGTH)
+            .ok_or_else(|| ServerError::Protocol(format!("invalid message length {length}")))?;
+        let mut data = vec![0; length];
+        reader.read_exact(&mut data)?;
+        Ok(Body { data, position: 0 })
+    }
+
+    fn bytes(&mut self, count: usize) -> ServerResult<&[u8]> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| ServerError::Protocol("message ended early".to_string()))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> ServerResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> ServerResult<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> ServerResult<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Null terminated string.
+    fn string(&mut self) -> ServerResult<String> {
+        let rest = &self.data[self.position..];
+        let end = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| ServerError::Protocol("unterminated string".to_string()))?;
+        let string = String::from_utf8(rest[..end].to_vec())
+            .map_err(|_| ServerError::Protocol("string is not UTF-8".to_string()))?;
+        self.position += end + 1;
+        Ok(string)
+    }
+
+    /// Format codes of the parameters or columns of a `Bind`.
+    fn formats(&mut self) -> ServerResult<Vec<i16>> {
+        (0..self.u16()?)
+            .map(|_| {
+                let bytes = self.bytes(2)?;
+                Ok(i16::from_be_bytes([bytes[0], bytes[1]]))
+            })
+            .collect()
+    }
+}
+
+fn put_string(body: &mut Vec<u8>, string: &str) {
+    body.extend_from_slice(string.as_bytes());
+    body.push(0);
+}
+
+fn put_count(body: &mut Vec<u8>, count: usize) -> ServerResult<()> {
+    let count = u16::try_from(count)
+        .map_err(|_| ServerError::Protocol(format!("{count} fields in one message")))?;
+    body.extend_from_slice(&count.to_be_bytes());
+    Ok(())
+}
+
+fn length(length: usize) -> ServerResult<i32> {
+    i32::try_from(length).map_err(|_| ServerError::Protocol(format!("message of {length} bytes")))
+}
@@ -0,0 +1,65 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use minql_exec::ExecError;
+
+/// Result Type for the Server
+pub type ServerResult<T> = Result<T, ServerError>;
+
+/// Error Type for the Server
+#[derive(Debug)]
+pub enum ServerError {
+    /// Client sent a message the protocol doesn't allow, ending the connection
+    Protocol(String),
+    /// Request of the client can't be carried out, reported to it with a SQLSTATE code
+    Request {
+        /// SQLSTATE code of the error
+        code: &'static str,
+        /// Description of the error
+        message: String,
+    },
+    /// Error running a statement
+    Exec(ExecError),
+    /// Error reading from or writing to a connection
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ServerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ServerError::Exec(err) => Some(err),
+            ServerError::Io(err) => Some(err),
+            ServerError::Protocol(_) | ServerError::Request { .. } => None,
+        }
+    }
+}
+
+impl From<ExecError> for ServerError {
+    fn from(err: ExecError) -> Self {
+        ServerError::Exec(err)
+    }
+}
+
+impl From<std::io::Error> for ServerError {
+    fn from(err: std::io::Error) -> Self {
+        ServerError::Io(err)
+    }
+}
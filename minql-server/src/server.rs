@@ -0,0 +1,369 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::connection::Connection;
+use crate::ServerResult;
+use minql_exec::Engine;
+use minql_vfs::FileSystem;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Server speaking the Postgres frontend/backend protocol, running the statements of each
+/// client in a [`Session`](minql_exec::Session) of an [`Engine`] on a thread of its own.
+///
+/// Clients are accepted without authentication, into the database named by the `database`
+/// parameter of their startup message, or by their `user` without one. Requests for TLS are
+/// refused, so clients must connect without encryption. Columns and parameters are described
+/// as `text`, and values are sent as text whatever format is asked for, which reads the same
+/// for `text`.
+///
+/// ```rust,no_run
+/// use minql_catalog::Catalog;
+/// use minql_exec::{Engine, Executor, MemoryStorage};
+/// use minql_server::Server;
+/// use minql_vfs::MemoryFileSystem;
+/// use std::sync::Arc;
+///
+/// let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+/// let mut transaction = catalog.begin();
+/// transaction.create_database("postgres", "mem:///data/postgres").unwrap();
+/// transaction.commit().unwrap();
+/// let engine = Engine::new(catalog, Executor::new(Arc::new(MemoryStorage::new())));
+///
+/// let server = Server::bind(Arc::new(engine), "127.0.0.1:5432").unwrap();
+/// server.serve().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Server<F: FileSystem> {
+    engine: Arc<Engine<F>>,
+    listener: TcpListener,
+    /// Id of the last connection accepted
+    connections: AtomicU32,
+    /// Seed of the secrets of connections
+    secrets: RandomState,
+}
+
+impl<F: FileSystem> Server<F> {
+    /// Listen for clients on `address`.
+    pub fn bind(engine: Arc<Engine<F>>, address: impl ToSocketAddrs) -> ServerResult<Server<F>> {
+        Ok(Server {
+            engine,
+            listener: TcpListener::bind(address)?,
+            connections: AtomicU32::new(0),
+            secrets: RandomState::new(),
+        })
+    }
+
+    /// Address the server listens on.
+    pub fn local_addr(&self) -> ServerResult<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Engine statements run against.
+    #[must_use]
+    pub fn engine(&self) -> &Arc<Engine<F>> {
+        &self.engine
+    }
+
+    /// Accept clients until accepting fails, serving each on a thread of its own.
+    pub fn serve(&self) -> ServerResult<()> {
+        for stream in self.listener.incoming() {
+            self.accept(stream?)?;
+        }
+        Ok(())
+    }
+
+    /// Serve a client on a thread of its own.
+    fn accept(&self, stream: TcpStream) -> ServerResult<()> {
+        let process = self.connections.fetch_add(1, Ordering::Relaxed) + 1;
+        #[allow(clippy::cast_possible_truncation)]
+        let secret = self.secrets.hash_one(process) as u32;
+        let peer = stream.peer_addr()?;
+        tracing::debug!(process, %peer, "Accepted connection");
+        let engine = self.engine.clone();
+        std::thread::Builder::new()
+            .name(format!("minql-connection-{process}"))
+            .spawn(move || {
+                let served = Connection::start(engine, stream, process, secret)
+                    .and_then(|connection| connection.map_or(Ok(()), Connection::run));
+                match served {
+                    Ok(()) => tracing::debug!(process, %peer, "Closed connection"),
+                    Err(err) => tracing::warn!(process, %peer, %err, "Connection failed"),
+                }
+            })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Server;
+    use minql_catalog::Catalog;
+    use minql_exec::{Engine, Executor, MemoryStorage};
+    use minql_vfs::MemoryFileSystem;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+
+    /// Client speaking just enough of the protocol to test the server.
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn connect(database: &str) -> Client {
+            let catalog = Catalog::open(MemoryFileSystem::new(), "/system").unwrap();
+            let mut transaction = catalog.begin();
+            transaction
+                .create_database("shop", "mem:///data/shop")
+                .unwrap();
+            transaction.commit().unwrap();
+            let engine = Engine::new(catalog, Executor::new(Arc::new(MemoryStorage::new())));
+            let server = Server::bind(Arc::new(engine), "127.0.0.1:0").unwrap();
+            let address = server.local_addr().unwrap();
+            std::thread::spawn(move || server.serve());
+            let mut client = Client {
+                stream: TcpStream::connect(address).unwrap(),
+            };
+            client.startup(&80_877_103u32.to_be_bytes());
+            let mut refused = [0];
+            client.stream.read_exact(&mut refused).unwrap();
+            assert_eq!(&refused, b"N");
+            let mut body = 0x0003_0000u32.to_be_bytes().to_vec();
+            for field in ["user", "tester", "database", database, ""] {
+                body.extend_from_slice(field.as_bytes());
+                body.push(0);
+            }
+            client.startup(&body);
+            client
+        }
+
+        fn startup(&mut self, body: &[u8]) {
+            let length = u32::try_from(body.len() + 4).unwrap();
+            self.stream.write_all(&length.to_be_bytes()).unwrap();
+            self.stream.write_all(body).unwrap();
+        }
+
+        fn send(&mut self, kind: u8, fields: &[&[u8]]) {
+            let body = fields.concat();
+            let length = u32::try_from(body.len() + 4).unwrap();
+            self.stream.write_all(&[kind]).unwrap();
+            self.stream.write_all(&length.to_be_bytes()).unwrap();
+            self.stream.write_all(&body).unwrap();
+        }
+
+        fn receive(&mut self) -> (u8, Vec<u8>) {
+            let mut header = [0; 5];
+            self.stream.read_exact(&mut header).unwrap();
+            let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+            let mut body = vec![0; length as usize - 4];
+            self.stream.read_exact(&mut body).unwrap();
+            (header[0], body)
+        }
+
+        /// Bind a statement into the unnamed portal, with parameters all of one format.
+        fn bind(&mut self, statement: &str, format: i16, values: &[Option<&[u8]>]) {
+            let mut body = format!("\0{statement}\0").into_bytes();
+            body.extend_from_slice(&1u16.to_be_bytes());
+            body.extend_from_slice(&format.to_be_bytes());
+            body.extend_from_slice(&u16::try_from(values.len()).unwrap().to_be_bytes());
+            for value in values {
+                match value {
+                    Some(value) => {
+                        body.extend_from_slice(&i32::try_from(value.len()).unwrap().to_be_bytes());
+                        body.extend_from_slice(value);
+                    }
+                    None => body.extend_from_slice(&(-1i32).to_be_bytes()),
+                }
+            }
+            body.extend_from_slice(&0u16.to_be_bytes());
+            self.send(b'B', &[&body]);
+        }
+
+        /// Messages up to and including `ReadyForQuery`, summarized as text: the type of each
+        /// followed by its command tag, the values of its row or the code of its error.
+        fn until_ready(&mut self) -> Vec<String> {
+            let mut messages = Vec::new();
+            loop {
+                let (kind, body) = self.receive();
+                let kind = char::from(kind);
+                messages.push(match kind {
+                    'C' => format!("C {}", cstring(&body)),
+                    'D' => format!("D {}", data_row(&body).join(",")),
+                    'E' => {
+                        let code = body
+                            .split(|&byte| byte == 0)
+                            .find_map(|field| field.strip_prefix(b"C"))
+                            .unwrap();
+                        format!("E {}", String::from_utf8_lossy(code))
+                    }
+                    kind => kind.to_string(),
+                });
+                if kind == 'Z' {
+                    return messages;
+                }
+            }
+        }
+
+        fn query(&mut self, sql: &str) -> Vec<String> {
+            self.send(b'Q', &[sql.as_bytes(), b"\0"]);
+            self.until_ready()
+        }
+    }
+
+    fn cstring(body: &[u8]) -> String {
+        String::from_utf8(body[..body.len() - 1].to_vec()).unwrap()
+    }
+
+    fn data_row(body: &[u8]) -> Vec<String> {
+        let mut values = Vec::new();
+        let mut position = 2;
+        while position < body.len() {
+            let length = i32::from_be_bytes(body[position..position + 4].try_into().unwrap());
+            position += 4;
+            if length < 0 {
+                values.push("NULL".to_string());
+            } else {
+                let end = position + usize::try_from(length).unwrap();
+                values.push(String::from_utf8(body[position..end].to_vec()).unwrap());
+                position = end;
+            }
+        }
+        values
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_simple_query() {
+        let mut client = Client::connect("shop");
+        let startup = client.until_ready();
+        assert_eq!(startup.first().unwrap(), "R");
+        assert!(startup.iter().filter(|kind| *kind == "S").count() >= 1);
+        assert_eq!(&startup[startup.len() - 2..], ["K", "Z"]);
+
+        assert_eq!(
+            client.query(
+                "CREATE TABLE items (id BIGINT PRIMARY KEY, kind TEXT); \
+                 INSERT INTO items VALUES (1, 'tea'), (2, NULL);"
+            ),
+            ["C CREATE TABLE", "C INSERT 0 2", "Z"]
+        );
+        assert_eq!(
+            client.query("SELECT id, kind FROM items ORDER BY id"),
+            ["T", "D 1,tea", "D 2,NULL", "C SELECT 2", "Z"]
+        );
+        assert_eq!(
+            client.query("SELECT * FROM missing; SELECT 1"),
+            ["E 42P01", "Z"]
+        );
+        assert_eq!(client.query("SELEC 1"), ["E 42601", "Z"]);
+        assert_eq!(client.query(" ; "), ["I", "Z"]);
+        client.send(b'X', &[]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_extended_query() {
+        let mut client = Client::connect("shop");
+        client.until_ready();
+        client.query("CREATE TABLE items (id BIGINT, kind TEXT)");
+        client.query("INSERT INTO items VALUES (1, 'tea'), (2, 'cake'), (3, 'bun')");
+
+        let sql = b"SELECT kind FROM items WHERE id >= $1 ORDER BY id\0";
+        client.send(b'P', &[b"items\0", sql, &0u16.to_be_bytes()]);
+        client.send(b'D', &[b"S", b"items\0"]);
+        client.bind("items", 0, &[Some(b"2")]);
+        client.send(b'D', &[b"P", b"\0"]);
+        client.send(b'E', &[b"\0", &1u32.to_be_bytes()]);
+        client.send(b'E', &[b"\0", &0u32.to_be_bytes()]);
+        client.send(b'S', &[]);
+        assert_eq!(
+            client.until_ready(),
+            [
+                "1",
+                "t",
+                "T",
+                "2",
+                "T",
+                "D cake",
+                "s",
+                "D bun",
+                "C SELECT 1",
+                "Z"
+            ]
+        );
+
+        // Declared binary BIGINT parameter
+        let parameter = 2i64.to_be_bytes();
+        client.send(
+            b'P',
+            &[b"\0", sql, &1u16.to_be_bytes(), &20u32.to_be_bytes()],
+        );
+        client.bind("", 1, &[Some(&parameter)]);
+        client.send(b'E', &[b"\0", &0u32.to_be_bytes()]);
+        client.send(b'S', &[]);
+        assert_eq!(
+            client.until_ready(),
+            ["1", "2", "D cake", "D bun", "C SELECT 2", "Z"]
+        );
+
+        // Messages after an error are skipped until Sync
+        client.send(
+            b'P',
+            &[b"\0", b"SELECT * FROM missing\0", &0u16.to_be_bytes()],
+        );
+        client.bind("", 0, &[]);
+        client.send(b'E', &[b"\0", &0u32.to_be_bytes()]);
+        client.send(b'S', &[]);
+        assert_eq!(client.until_ready(), ["E 42P01", "Z"]);
+
+        client.send(
+            b'P',
+            &[
+                b"\0",
+                b"INSERT INTO items VALUES ($1, $2)\0",
+                &0u16.to_be_bytes(),
+            ],
+        );
+        client.bind("", 0, &[Some(b"4"), None]);
+        client.send(b'D', &[b"P", b"\0"]);
+        client.send(b'E', &[b"\0", &0u32.to_be_bytes()]);
+        client.send(b'C', &[b"S", b"items\0"]);
+        client.bind("items", 0, &[]);
+        client.send(b'S', &[]);
+        assert_eq!(
+            client.until_ready(),
+            ["1", "2", "n", "C INSERT 0 1", "3", "E 26000", "Z"]
+        );
+        assert_eq!(
+            client.query("SELECT id, kind FROM items WHERE id = 4"),
+            ["T", "D 4,NULL", "C SELECT 1", "Z"]
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_missing_database() {
+        let mut client = Client::connect("missing");
+        let (kind, _) = client.receive();
+        assert_eq!(kind, b'E');
+        let mut rest = Vec::new();
+        client.stream.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use minql_vfs::Lsn;
+use std::fmt::Write;
+
+/// First line of every backup label.
+const LABEL_HEADER: &str = "minql-backup 1";
+
+/// Name of the label of a backup within its directory.
+pub(crate) const LABEL_NAME: &str = "BACKUP";
+
+/// Name of the manifest of a backup within its directory.
+pub(crate) const MANIFEST_NAME: &str = "MANIFEST";
+
+/// What a backup taken by [`TransactionManager::backup`](crate::TransactionManager::backup)
+/// holds, saved with it as its label.
+///
+/// A backup directory holds the label, the checkpoint recovery starts from, the segments of
+/// the log from that checkpoint up to `end` under `wal/`, a copy of every registered file under
+/// `files/` named by its id, and a manifest of the sizes and digests of all of them.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BackupLabel {
+    /// Start of the checkpoint recovery of the backup starts from
+    pub checkpoint: Lsn,
+    /// End of the log copied, the last point in time the backup restores
+    pub end: Lsn,
+    /// Id and path of every file copied, ordered by id
+    pub files: Vec<(u64, String)>,
+}
+
+impl BackupLabel {
+    /// Encode the label as text, a line per field.
+    pub(crate) fn encode(&self) -> String {
+        let mut text = format!(
+            "{LABEL_HEADER}\ncheckpoint {} {}\nend {} {}\n",
+            self.checkpoint.segment, self.checkpoint.offset, self.end.segment, self.end.offset
+        );
+        for (id, path) in &self.files {
+            let _ = writeln!(text, "file {id} {path}");
+        }
+        text
+    }
+
+    /// Decode a label encoded by [`encode`](Self::encode), or `None` if it's malformed.
+    pub(crate) fn decode(text: &str) -> Option<BackupLabel> {
+        let mut lines = text.lines();
+        if lines.next()? != LABEL_HEADER {
+            return None;
+        }
+        let mut label = BackupLabel::default();
+        let lsn = |segment: &str, offset: &str| {
+            Some(Lsn {
+                segment: segment.parse().ok()?,
+                offset: offset.parse().ok()?,
+            })
+        };
+        label.checkpoint = match lines.next()?.split(' ').collect::<Vec<_>>().as_slice() {
+            ["checkpoint", segment, offset] => lsn(segment, offset)?,
+            _ => return None,
+        };
+        label.end = match lines.next()?.split(' ').collect::<Vec<_>>().as_slice() {
+            ["end", segment, offset] => lsn(segment, offset)?,
+            _ => return None,
+        };
+        for line in lines {
+            let mut fields = line.splitn(3, ' ');
+            if fields.next()? != "file" {
+                return None;
+            }
+            let id = fields.next()?.parse().ok()?;
+            label.files.push((id, fields.next()?.to_string()));
+        }
+        Some(label)
+    }
+}
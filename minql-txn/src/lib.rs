@@ -0,0 +1,58 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Transaction Locking and Recovery
+//!
+//! A [`LockManager`] granting table and row locks, with intention modes for multiple
+//! granularity locking, queues of waiting requests, deadlock detection over the wait-for graph,
+//! and timeouts. Transactions take their locks through it before reading or writing, and
+//! release them all when they end.
+//!
+//! A [`TransactionManager`] logs every change transactions make to pages of the `minql-vfs`
+//! buffer pools registered with it, takes fuzzy checkpoints, and recovers from a crash with
+//! the analysis, redo and undo passes of ARIES. It copies backups of the log and its files to
+//! any `FileSystem` while transactions carry on, and restores them with their
+//! [`BackupLabel`], checked against a manifest of the digests of their files.
+//!
+//! A restored backup follows the manager it was taken from as a read replica, applying the log
+//! the leader ships to it through any `FileSystem`, reporting any records missing from it, until
+//! promoted to take over from the leader.
+
+#![deny(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod backup;
+mod lock;
+mod manager;
+mod record;
+mod result;
+
+pub use self::backup::BackupLabel;
+pub use self::lock::{LockManager, LockMode, LockTarget, TransactionId};
+pub use self::manager::{RecoveryStats, TransactionManager, TransactionOptions};
+pub use self::result::{TransactionError, TransactionResult};
@@ -0,0 +1,612 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{TransactionError, TransactionResult};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Identifier of a transaction, increasing in the order transactions begin.
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct TransactionId(pub u64);
+
+impl std::fmt::Display for TransactionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "T{}", self.0)
+    }
+}
+
+/// Mode of a lock in the multiple granularity locking hierarchy.
+///
+/// Intention modes are taken on a table to announce shared or exclusive locks on its rows, so
+/// a lock on the whole table conflicts with row locks without looking at them.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum LockMode {
+    /// `IS`, rows below will be read
+    IntentionShared,
+    /// `IX`, rows below will be written
+    IntentionExclusive,
+    /// `S`, read everything below
+    Shared,
+    /// `SIX`, read everything below and write some rows
+    SharedIntentionExclusive,
+    /// `X`, read and write everything below
+    Exclusive,
+}
+
+impl LockMode {
+    /// Check if a lock in this mode can be held alongside one in `other` by another transaction.
+    #[must_use]
+    pub fn is_compatible(self, other: LockMode) -> bool {
+        use LockMode::{Exclusive, IntentionExclusive, IntentionShared, Shared};
+        match (self, other) {
+            (Exclusive, _) | (_, Exclusive) => false,
+            (IntentionShared, _)
+            | (_, IntentionShared)
+            | (IntentionExclusive, IntentionExclusive)
+            | (Shared, Shared) => true,
+            _ => false,
+        }
+    }
+
+    /// Check if this mode grants everything `other` does.
+    #[must_use]
+    pub fn covers(self, other: LockMode) -> bool {
+        use LockMode::{
+            Exclusive, IntentionExclusive, IntentionShared, Shared, SharedIntentionExclusive,
+        };
+        match (self, other) {
+            (Exclusive, _)
+            | (SharedIntentionExclusive, IntentionShared | IntentionExclusive | Shared)
+            | (IntentionExclusive | Shared, IntentionShared) => true,
+            _ => self == other,
+        }
+    }
+
+    /// Weakest mode granting everything this mode and `other` do, taken when upgrading a lock.
+    #[must_use]
+    pub fn join(self, other: LockMode) -> LockMode {
+        if self.covers(other) {
+            self
+        } else if other.covers(self) {
+            other
+        } else {
+            LockMode::SharedIntentionExclusive
+        }
+    }
+
+    /// Mode needed on the parent of a target locked in this mode.
+    #[must_use]
+    pub fn intention(self) -> LockMode {
+        match self {
+            LockMode::IntentionShared | LockMode::Shared => LockMode::IntentionShared,
+            _ => LockMode::IntentionExclusive,
+        }
+    }
+}
+
+impl std::fmt::Display for LockMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LockMode::IntentionShared => "IS",
+            LockMode::IntentionExclusive => "IX",
+            LockMode::Shared => "S",
+            LockMode::SharedIntentionExclusive => "SIX",
+            LockMode::Exclusive => "X",
+        })
+    }
+}
+
+/// Lockable item, a table or a row of one.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum LockTarget {
+    /// Table with a catalog id
+    Table(u64),
+    /// Row of a table, by the encoding of its key
+    Row(u64, Vec<u8>),
+}
+
+impl LockTarget {
+    /// Target whose lock covers this one, or `None` at the top of the hierarchy.
+    #[must_use]
+    pub fn parent(&self) -> Option<LockTarget> {
+        match self {
+            LockTarget::Table(_) => None,
+            LockTarget::Row(table, _) => Some(LockTarget::Table(*table)),
+        }
+    }
+}
+
+/// Hierarchical Lock Manager
+///
+/// Grants table and row locks to transactions for concurrency control. Locking a row first
+/// takes the matching intention lock on its table. Requests that conflict wait in a queue per
+/// target, granted in order as locks are released, until a timeout. A transaction whose wait
+/// would complete a cycle in the wait-for graph fails with [`TransactionError::Deadlock`]
+/// instead, and should abort to release its locks.
+///
+/// Locks are held until [`release_all`](LockManager::release_all), as two phase locking
+/// requires, though a shared lock may be released early with [`unlock`](LockManager::unlock).
+///
+/// ```rust
+/// use minql_txn::{LockManager, LockMode, LockTarget, TransactionError, TransactionId};
+/// use std::time::Duration;
+///
+/// let locks = LockManager::new().with_timeout(Duration::from_millis(10));
+/// let row = LockTarget::Row(7, b"alice".to_vec());
+/// locks.lock(TransactionId(1), &row, LockMode::Exclusive).unwrap();
+/// assert_eq!(locks.mode(TransactionId(1), &LockTarget::Table(7)), Some(LockMode::IntentionExclusive));
+/// assert!(matches!(
+///     locks.lock(TransactionId(2), &LockTarget::Table(7), LockMode::Shared),
+///     Err(TransactionError::LockTimeout(TransactionId(2)))
+/// ));
+/// locks.release_all(TransactionId(1));
+/// locks.lock(TransactionId(2), &LockTarget::Table(7), LockMode::Shared).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct LockManager {
+    table: Mutex<LockTable>,
+    released: Condvar,
+    timeout: Duration,
+}
+
+/// Locks granted and requested, by target and by transaction.
+#[derive(Debug, Default)]
+struct LockTable {
+    queues: HashMap<LockTarget, LockQueue>,
+    held: HashMap<TransactionId, HashSet<LockTarget>>,
+}
+
+/// Locks of one target.
+#[derive(Debug, Default)]
+struct LockQueue {
+    granted: HashMap<TransactionId, LockMode>,
+    /// Requests not yet granted, upgrades of granted locks first
+    waiting: VecDeque<(TransactionId, LockMode)>,
+}
+
+impl LockQueue {
+    /// Check if the request of `transaction` can be granted now.
+    fn is_grantable(&self, transaction: TransactionId) -> bool {
+        let Some(position) = self.waiting.iter().position(|(id, _)| *id == transaction) else {
+            return false;
+        };
+        let mode = self.waiting[position].1;
+        self.granted
+            .iter()
+            .all(|(id, granted)| *id == transaction || granted.is_compatible(mode))
+            && self
+                .waiting
+                .iter()
+                .take(position)
+                .all(|(_, waiting)| waiting.is_compatible(mode))
+    }
+
+    /// Transactions the request of `transaction` waits for.
+    fn blockers(&self, transaction: TransactionId) -> impl Iterator<Item = TransactionId> + '_ {
+        let position = self
+            .waiting
+            .iter()
+            .position(|(id, _)| *id == transaction)
+            .unwrap_or(0);
+        let mode = self.waiting.get(position).map(|(_, mode)| *mode);
+        self.granted
+            .iter()
+            .map(|(id, granted)| (*id, *granted))
+            .chain(self.waiting.iter().take(position).copied())
+            .filter(move |(id, other)| {
+                *id != transaction && mode.is_some_and(|mode| !other.is_compatible(mode))
+            })
+            .map(|(id, _)| id)
+    }
+}
+
+impl LockManager {
+    /// Default time a request waits for a conflicting lock before failing.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Create a Lock Manager holding no locks.
+    #[must_use]
+    pub fn new() -> LockManager {
+        LockManager {
+            table: Mutex::default(),
+            released: Condvar::new(),
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Set how long a request waits for a conflicting lock before failing.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> LockManager {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Lock `target` in `mode` for `transaction`, and its parents in the intention mode,
+    /// waiting up to the timeout for conflicting locks to be released.
+    ///
+    /// A lock already held in a weaker mode is upgraded.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn lock(
+        &self,
+        transaction: TransactionId,
+        target: &LockTarget,
+        mode: LockMode,
+    ) -> TransactionResult<()> {
+        self.lock_until(transaction, target, mode, Instant::now() + self.timeout)
+    }
+
+    /// Lock `target` in `mode` for `transaction`, and its parents in the intention mode,
+    /// failing with [`TransactionError::LockTimeout`] rather than waiting.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn try_lock(
+        &self,
+        transaction: TransactionId,
+        target: &LockTarget,
+        mode: LockMode,
+    ) -> TransactionResult<()> {
+        self.lock_until(transaction, target, mode, Instant::now())
+    }
+
+    /// Mode `transaction` holds `target` in, if any.
+    #[must_use]
+    pub fn mode(&self, transaction: TransactionId, target: &LockTarget) -> Option<LockMode> {
+        let table = self.table.lock().expect("Poisoned Lock");
+        table.queues.get(target)?.granted.get(&transaction).copied()
+    }
+
+    /// Locks held by `transaction`.
+    #[must_use]
+    pub fn locks(&self, transaction: TransactionId) -> Vec<(LockTarget, LockMode)> {
+        let table = self.table.lock().expect("Poisoned Lock");
+        table
+            .held
+            .get(&transaction)
+            .map_or_else(Vec::new, |targets| {
+                targets
+                    .iter()
+                    .map(|target| (target.clone(), table.queues[target].granted[&transaction]))
+                    .collect()
+            })
+    }
+
+    /// Release the lock `transaction` holds on `target`, leaving those on its parents.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn unlock(&self, transaction: TransactionId, target: &LockTarget) {
+        let mut table = self.table.lock().expect("Poisoned Lock");
+        if let Some(targets) = table.held.get_mut(&transaction) {
+            targets.remove(target);
+            if targets.is_empty() {
+                table.held.remove(&transaction);
+            }
+        }
+        table.release(transaction, target);
+        self.released.notify_all();
+    }
+
+    /// Release every lock held by `transaction`, when it commits or aborts.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn release_all(&self, transaction: TransactionId) {
+        let mut table = self.table.lock().expect("Poisoned Lock");
+        for target in table.held.remove(&transaction).unwrap_or_default() {
+            table.release(transaction, &target);
+        }
+        self.released.notify_all();
+    }
+
+    fn lock_until(
+        &self,
+        transaction: TransactionId,
+        target: &LockTarget,
+        mode: LockMode,
+        deadline: Instant,
+    ) -> TransactionResult<()> {
+        if let Some(parent) = target.parent() {
+            self.lock_until(transaction, &parent, mode.intention(), deadline)?;
+        }
+        let mut table = self.table.lock().expect("Poisoned Lock");
+        let queue = table.queues.entry(target.clone()).or_default();
+        let held = queue.granted.get(&transaction).copied();
+        if held.is_some_and(|held| held.covers(mode)) {
+            return Ok(());
+        }
+        match held {
+            // Upgrades go ahead of new requests, which would otherwise wait on them forever.
+            Some(held) => queue.waiting.push_front((transaction, held.join(mode))),
+            None => queue.waiting.push_back((transaction, mode)),
+        }
+        loop {
+            let queue = table.queues.get_mut(target).expect("Lock Queue");
+            if queue.is_grantable(transaction) {
+                let position = queue
+                    .waiting
+                    .iter()
+                    .position(|(id, _)| *id == transaction)
+                    .expect("Lock Request");
+                let (_, mode) = queue.waiting.remove(position).expect("Lock Request");
+                queue.granted.insert(transaction, mode);
+                table
+                    .held
+                    .entry(transaction)
+                    .or_default()
+                    .insert(target.clone());
+                return Ok(());
+            }
+            let error = if table.is_deadlocked(transaction) {
+                tracing::debug!(%transaction, "Deadlock detected");
+                TransactionError::Deadlock(transaction)
+            } else {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if !remaining.is_zero() {
+                    table = self
+                        .released
+                        .wait_timeout(table, remaining)
+                        .expect("Poisoned Lock")
+                        .0;
+                    continue;
+                }
+                TransactionError::LockTimeout(transaction)
+            };
+            // Requests behind this one may be grantable once it leaves the queue.
+            let queue = table.queues.get_mut(target).expect("Lock Queue");
+            queue.waiting.retain(|(id, _)| *id != transaction);
+            table.remove_if_empty(target);
+            self.released.notify_all();
+            return Err(error);
+        }
+    }
+}
+
+impl Default for LockManager {
+    fn default() -> Self {
+        LockManager::new()
+    }
+}
+
+impl LockTable {
+    /// Remove the lock of `transaction` on `target` from its queue.
+    fn release(&mut self, transaction: TransactionId, target: &LockTarget) {
+        if let Some(queue) = self.queues.get_mut(target) {
+            queue.granted.remove(&transaction);
+            self.remove_if_empty(target);
+        }
+    }
+
+    fn remove_if_empty(&mut self, target: &LockTarget) {
+        if self
+            .queues
+            .get(target)
+            .is_some_and(|queue| queue.granted.is_empty() && queue.waiting.is_empty())
+        {
+            self.queues.remove(target);
+        }
+    }
+
+    /// Check if `transaction` waits, through the wait-for graph, on itself.
+    fn is_deadlocked(&self, transaction: TransactionId) -> bool {
+        let mut edges = HashMap::<TransactionId, Vec<TransactionId>>::new();
+        for queue in self.queues.values() {
+            for (waiter, _) in &queue.waiting {
+                edges
+                    .entry(*waiter)
+                    .or_default()
+                    .extend(queue.blockers(*waiter));
+            }
+        }
+        let mut visited = HashSet::new();
+        let mut stack = edges.get(&transaction).cloned().unwrap_or_default();
+        while let Some(next) = stack.pop() {
+            if next == transaction {
+                return true;
+            }
+            if visited.insert(next) {
+                stack.extend(edges.get(&next).into_iter().flatten());
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LockManager, LockMode, LockTarget, TransactionId};
+    use crate::TransactionError;
+    use std::sync::{Arc, Barrier};
+    use std::time::Duration;
+
+    const MODES: [LockMode; 5] = [
+        LockMode::IntentionShared,
+        LockMode::IntentionExclusive,
+        LockMode::Shared,
+        LockMode::SharedIntentionExclusive,
+        LockMode::Exclusive,
+    ];
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_lock_modes() {
+        let compatible = [
+            [true, true, true, true, false],
+            [true, true, false, false, false],
+            [true, false, true, false, false],
+            [true, false, false, false, false],
+            [false, false, false, false, false],
+        ];
+        for (row, left) in MODES.iter().enumerate() {
+            for (column, right) in MODES.iter().enumerate() {
+                assert_eq!(
+                    left.is_compatible(*right),
+                    compatible[row][column],
+                    "{left} {right}"
+                );
+                let join = left.join(*right);
+                assert!(join.covers(*left) && join.covers(*right), "{left} {right}");
+            }
+        }
+        assert_eq!(
+            LockMode::IntentionExclusive.join(LockMode::Shared),
+            LockMode::SharedIntentionExclusive
+        );
+        assert!(!LockMode::Shared.covers(LockMode::IntentionExclusive));
+        assert_eq!(
+            LockMode::SharedIntentionExclusive.intention(),
+            LockMode::IntentionExclusive
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_lock_manager() {
+        let locks = LockManager::new().with_timeout(Duration::from_millis(20));
+        let (t1, t2) = (TransactionId(1), TransactionId(2));
+        let table = LockTarget::Table(1);
+        let row = |key: &[u8]| LockTarget::Row(1, key.to_vec());
+
+        locks.lock(t1, &row(b"a"), LockMode::Shared).unwrap();
+        locks.lock(t2, &row(b"a"), LockMode::Shared).unwrap();
+        locks.lock(t2, &row(b"b"), LockMode::Exclusive).unwrap();
+        assert_eq!(locks.mode(t2, &table), Some(LockMode::IntentionExclusive));
+        assert!(matches!(
+            locks.try_lock(t1, &row(b"b"), LockMode::Shared),
+            Err(TransactionError::LockTimeout(id)) if id == t1
+        ));
+        assert!(matches!(
+            locks.lock(t1, &table, LockMode::Shared),
+            Err(TransactionError::LockTimeout(_))
+        ));
+        assert!(matches!(
+            locks.try_lock(t1, &row(b"a"), LockMode::Exclusive),
+            Err(TransactionError::LockTimeout(_))
+        ));
+        assert_eq!(locks.mode(t1, &row(b"a")), Some(LockMode::Shared));
+
+        locks.unlock(t2, &row(b"a"));
+        locks.lock(t1, &row(b"a"), LockMode::Exclusive).unwrap();
+        locks.lock(t1, &row(b"a"), LockMode::Shared).unwrap();
+        assert_eq!(locks.mode(t1, &row(b"a")), Some(LockMode::Exclusive));
+        assert_eq!(locks.locks(t1).len(), 2);
+
+        locks.release_all(t2);
+        locks.lock(t1, &table, LockMode::Shared).unwrap();
+        assert_eq!(
+            locks.mode(t1, &table),
+            Some(LockMode::SharedIntentionExclusive)
+        );
+        locks.release_all(t1);
+        assert!(locks.locks(t1).is_empty());
+        assert!(locks.table.lock().unwrap().queues.is_empty());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_lock_wait() {
+        let locks = Arc::new(LockManager::new());
+        let table = LockTarget::Table(1);
+        locks
+            .lock(TransactionId(1), &table, LockMode::Exclusive)
+            .unwrap();
+        let waiter = {
+            let locks = locks.clone();
+            let table = table.clone();
+            std::thread::spawn(move || locks.lock(TransactionId(2), &table, LockMode::Shared))
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(locks.mode(TransactionId(2), &table), None);
+        locks.release_all(TransactionId(1));
+        waiter.join().unwrap().unwrap();
+        assert_eq!(locks.mode(TransactionId(2), &table), Some(LockMode::Shared));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_deadlock() {
+        let locks = Arc::new(LockManager::new());
+        let barrier = Arc::new(Barrier::new(2));
+        let threads = (1..=2)
+            .map(|id| {
+                let locks = locks.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    let transaction = TransactionId(id);
+                    let (first, second) = if id == 1 { (b"a", b"b") } else { (b"b", b"a") };
+                    locks
+                        .lock(
+                            transaction,
+                            &LockTarget::Row(1, first.to_vec()),
+                            LockMode::Exclusive,
+                        )
+                        .unwrap();
+                    barrier.wait();
+                    let result = locks.lock(
+                        transaction,
+                        &LockTarget::Row(1, second.to_vec()),
+                        LockMode::Exclusive,
+                    );
+                    if result.is_err() {
+                        locks.release_all(transaction);
+                    }
+                    result
+                })
+            })
+            .collect::<Vec<_>>();
+        let results = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results
+                .iter()
+                .filter(|result| matches!(result, Err(TransactionError::Deadlock(_))))
+                .count(),
+            1
+        );
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_upgrade_deadlock() {
+        let locks = Arc::new(LockManager::new());
+        let table = LockTarget::Table(1);
+        locks
+            .lock(TransactionId(1), &table, LockMode::Shared)
+            .unwrap();
+        locks
+            .lock(TransactionId(2), &table, LockMode::Shared)
+            .unwrap();
+        let upgrade = {
+            let locks = locks.clone();
+            let table = table.clone();
+            std::thread::spawn(move || locks.lock(TransactionId(1), &table, LockMode::Exclusive))
+        };
+        while locks.table.lock().unwrap().queues[&table]
+            .waiting
+            .is_empty()
+        {
+            std::thread::yield_now();
+        }
+        assert!(matches!(
+            locks.lock(TransactionId(2), &table, LockMode::Exclusive),
+            Err(TransactionError::Deadlock(TransactionId(2)))
+        ));
+        locks.release_all(TransactionId(2));
+        upgrade.join().unwrap().unwrap();
+        assert_eq!(
+            locks.mode(TransactionId(1), &table),
+            Some(LockMode::Exclusive)
+        );
+    }
+}
@@ -0,0 +1,1564 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::backup::{LABEL_NAME, MANIFEST_NAME};
+use crate::record::{LogRecord, PageRef, TransactionEntry, TransactionStatus};
+use crate::{
+    BackupLabel, LockManager, LockMode, LockTarget, TransactionError, TransactionId,
+    TransactionResult,
+};
+use minql_vfs::{
+    BufferPool, FileHandle, FileSystem, FileSystemError, FileSystemResult, Lsn, Manifest,
+    MismatchKind, PageId, WalOptions, WalSyncPolicy, WriteAheadLog,
+};
+use std::collections::{BinaryHeap, HashMap};
+use std::io::Write;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// First line of every checkpoint file.
+const CHECKPOINT_HEADER: &str = "minql-txn 1";
+
+/// Prefix of checkpoint file names, followed by their sequence number.
+const CHECKPOINT_PREFIX: &str = "CHECKPOINT-";
+
+/// Records read from the log at a time during recovery.
+const SCAN_BATCH: usize = 1024;
+
+/// Configuration of a [`TransactionManager`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TransactionOptions {
+    wal_options: WalOptions,
+    checkpoint_interval: u64,
+    lock_timeout: Duration,
+}
+
+impl TransactionOptions {
+    /// Default number of bytes logged between checkpoints.
+    pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 16 * 1024 * 1024;
+
+    /// Create the default options, syncing the log on commit and checkpointing every 16 MiB.
+    #[must_use]
+    pub fn new() -> TransactionOptions {
+        TransactionOptions {
+            wal_options: WalOptions::new().with_sync_policy(WalSyncPolicy::Manual),
+            checkpoint_interval: Self::DEFAULT_CHECKPOINT_INTERVAL,
+            lock_timeout: LockManager::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Set the options of the log.
+    ///
+    /// The log is always synced when a transaction commits and before a page is written back.
+    #[must_use]
+    pub fn with_wal_options(mut self, wal_options: WalOptions) -> TransactionOptions {
+        self.wal_options = wal_options;
+        self
+    }
+
+    /// Take a checkpoint once a commit finds at least this many bytes logged since the last.
+    #[must_use]
+    pub fn with_checkpoint_interval(mut self, checkpoint_interval: u64) -> TransactionOptions {
+        self.checkpoint_interval = checkpoint_interval;
+        self
+    }
+
+    /// Set how long a transaction waits for a lock before failing.
+    #[must_use]
+    pub fn with_lock_timeout(mut self, lock_timeout: Duration) -> TransactionOptions {
+        self.lock_timeout = lock_timeout;
+        self
+    }
+}
+
+impl Default for TransactionOptions {
+    fn default() -> Self {
+        TransactionOptions::new()
+    }
+}
+
+/// Work done by [`TransactionManager::recover`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RecoveryStats {
+    /// Updates reapplied to pages that were missing them
+    pub redone: usize,
+    /// Updates of unfinished transactions rolled back
+    pub undone: usize,
+    /// Transactions left unfinished by the crash and rolled back
+    pub losers: usize,
+}
+
+/// Transaction Manager with ARIES-style recovery
+///
+/// Changes to pages of registered [`BufferPool`]s are made through transactions, logging the
+/// bytes before and after each change to a [`WriteAheadLog`] and stamping the page with the
+/// [`Lsn`] of the record. Pools only write a page back once the log holds its records, and a
+/// commit syncs the log, so after a crash [`recover`](TransactionManager::recover) rebuilds
+/// the state of every committed transaction and none of any other:
+///
+/// * Analysis reads the log from the last checkpoint, finding the unfinished transactions
+///   and the pages that may be missing updates.
+/// * Redo repeats history, reapplying every update newer than the page it belongs to.
+/// * Undo rolls back the unfinished transactions, newest update first, logging compensation
+///   records so a crash during recovery never undoes an update twice.
+///
+/// Checkpoints are fuzzy, recording the open transactions and dirty pages without flushing
+/// anything, and let log segments nobody needs any more be removed. Locks taken through
+/// [`lock`](TransactionManager::lock) are released when the transaction ends.
+///
+/// ```rust
+/// use minql_txn::{TransactionManager, TransactionOptions};
+/// use minql_vfs::{BufferPool, FileSystem, LruPolicy, MemoryFileSystem, PagedFile};
+///
+/// let fs = MemoryFileSystem::new();
+/// let manager = TransactionManager::open(fs.clone(), "/txn", TransactionOptions::new()).unwrap();
+/// let file = PagedFile::new(fs.create_file("/table.dat").unwrap(), 4096).unwrap();
+/// let file = file.with_page_lsn().unwrap();
+/// let pool = manager.register_file(1, BufferPool::new(file, 1 << 20, LruPolicy::new()));
+/// manager.recover().unwrap();
+/// let page = pool.new_page().unwrap().id();
+///
+/// let transaction = manager.begin().unwrap();
+/// manager.write(transaction, 1, page, 0, b"Hello").unwrap();
+/// manager.commit(transaction).unwrap();
+///
+/// let transaction = manager.begin().unwrap();
+/// manager.write(transaction, 1, page, 0, b"Jello").unwrap();
+/// manager.abort(transaction).unwrap();
+/// assert_eq!(&pool.pin(page).unwrap().read().data()[..5], b"Hello");
+/// ```
+#[derive(Debug)]
+pub struct TransactionManager<F: FileSystem + Clone> {
+    fs: F,
+    directory: String,
+    options: TransactionOptions,
+    log: Arc<Log<F>>,
+    state: Mutex<ManagerState>,
+    files: RwLock<HashMap<u64, Arc<BufferPool<F::FileHandle>>>>,
+    locks: LockManager,
+}
+
+/// Transactions and pages the log must still cover.
+#[derive(Debug, Default)]
+struct ManagerState {
+    next_transaction: u64,
+    transactions: HashMap<TransactionId, TransactionEntry>,
+    /// Pages possibly changed since last written back, with the first record changing them
+    dirty_pages: HashMap<PageRef, Lsn>,
+    /// Sequence number of the newest checkpoint file
+    checkpoint: u64,
+    /// Start of the log each backup in progress copies from, which the log must keep
+    backups: Vec<Lsn>,
+    /// Position each follower was last shipped the log from, which the log must keep
+    replicas: HashMap<String, Lsn>,
+    /// Progress applying the shipped log, while following a leader
+    following: Option<Following>,
+}
+
+/// Progress of a follower through the log shipped to it.
+#[derive(Debug)]
+struct Following {
+    /// Position of the next record to apply, or of the last one applied if `applied`
+    next: Lsn,
+    applied: bool,
+    /// Start of the leader's checkpoint being applied, if its end hasn't been yet
+    checkpoint: Option<Lsn>,
+}
+
+/// The log, shared with the write-ahead hooks of the registered pools.
+#[derive(Debug)]
+struct Log<F: FileSystem> {
+    wal: Mutex<LogState<F>>,
+}
+
+#[derive(Debug)]
+struct LogState<F: FileSystem> {
+    wal: WriteAheadLog<F>,
+    /// Position up to which the log is known to be on storage
+    durable: Lsn,
+    /// Bytes logged since the last checkpoint
+    logged: u64,
+}
+
+impl<F: FileSystem> Log<F> {
+    fn append(&self, record: &LogRecord) -> FileSystemResult<Lsn> {
+        let payload = record.encode();
+        let mut log = self.wal.lock().expect("Poisoned Lock");
+        let lsn = log.wal.append(&payload)?;
+        log.logged += payload.len() as u64;
+        Ok(lsn)
+    }
+
+    /// Sync the log if the record at `lsn` might not be on storage yet.
+    fn force(&self, lsn: Lsn) -> FileSystemResult<()> {
+        let mut log = self.wal.lock().expect("Poisoned Lock");
+        if lsn >= log.durable {
+            log.wal.sync()?;
+            log.durable = log.wal.next_lsn();
+        }
+        Ok(())
+    }
+
+    /// Read up to `limit` records from `from` onwards.
+    fn scan(&self, from: Lsn, limit: usize) -> TransactionResult<Vec<(Lsn, LogRecord)>> {
+        let log = self.wal.lock().expect("Poisoned Lock");
+        log.wal
+            .iter(from)
+            .take(limit)
+            .map(|record| {
+                let (lsn, payload) = record?;
+                let record =
+                    LogRecord::decode(&payload).ok_or(TransactionError::CorruptLog(lsn))?;
+                Ok((lsn, record))
+            })
+            .collect()
+    }
+
+    /// Read the record at `lsn`.
+    fn read(&self, lsn: Lsn) -> TransactionResult<LogRecord> {
+        match self.scan(lsn, 1)?.pop() {
+            Some((found, record)) if found == lsn => Ok(record),
+            _ => Err(TransactionError::CorruptLog(lsn)),
+        }
+    }
+
+    /// Visit every record from `from` onwards, without holding the log while visiting.
+    fn for_each(
+        &self,
+        from: Lsn,
+        mut visit: impl FnMut(Lsn, LogRecord) -> TransactionResult<()>,
+    ) -> TransactionResult<()> {
+        let mut batch = self.scan(from, SCAN_BATCH)?;
+        loop {
+            let last = batch.last().map(|(lsn, _)| *lsn);
+            let full = batch.len() == SCAN_BATCH;
+            for (lsn, record) in batch {
+                visit(lsn, record)?;
+            }
+            match last {
+                Some(last) if full => {
+                    batch = self.scan(last, SCAN_BATCH + 1)?;
+                    batch.remove(0);
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+impl<F: FileSystem + Clone> TransactionManager<F> {
+    /// Open the log and checkpoints kept in `directory`.
+    ///
+    /// Every file changed by transactions must be registered, and the manager recovered,
+    /// before beginning any transaction.
+    #[tracing::instrument(level = "debug", skip(fs))]
+    pub fn open(
+        fs: F,
+        directory: &str,
+        options: TransactionOptions,
+    ) -> TransactionResult<TransactionManager<F>> {
+        let directory = directory.trim_end_matches('/').to_string();
+        fs.create_directory_all(&directory)?;
+        let wal =
+            WriteAheadLog::open(fs.clone(), &format!("{directory}/wal"), options.wal_options)?;
+        Ok(TransactionManager {
+            log: Arc::new(Log {
+                wal: Mutex::new(LogState {
+                    wal,
+                    durable: Lsn::default(),
+                    logged: 0,
+                }),
+            }),
+            fs,
+            directory,
+            options,
+            state: Mutex::default(),
+            files: RwLock::default(),
+            locks: LockManager::new().with_timeout(options.lock_timeout),
+        })
+    }
+
+    /// Register the pool of a file whose pages are changed by transactions as `file`.
+    ///
+    /// The pool's file should store page LSNs, see
+    /// [`PagedFile::with_page_lsn`](minql_vfs::PagedFile::with_page_lsn), or recovery will
+    /// redo every update it finds. The pool is given a hook forcing the log before it writes
+    /// back a page.
+    pub fn register_file(
+        &self,
+        file: u64,
+        pool: BufferPool<F::FileHandle>,
+    ) -> Arc<BufferPool<F::FileHandle>> {
+        let log = self.log.clone();
+        let pool = Arc::new(pool.with_write_ahead(move |lsn| log.force(lsn)));
+        self.files
+            .write()
+            .expect("Poisoned Lock")
+            .insert(file, pool.clone());
+        pool
+    }
+
+    /// Begin a transaction.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn begin(&self) -> TransactionResult<TransactionId> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        if state.following.is_some() {
+            return Err(TransactionError::Following);
+        }
+        let transaction = TransactionId(state.next_transaction);
+        state.next_transaction += 1;
+        let lsn = self.log.append(&LogRecord::Begin { transaction })?;
+        state.transactions.insert(
+            transaction,
+            TransactionEntry {
+                status: TransactionStatus::Active,
+                first_lsn: lsn,
+                last_lsn: lsn,
+                undo_next: None,
+            },
+        );
+        Ok(transaction)
+    }
+
+    /// Lock `target` in `mode` for `transaction` until it ends.
+    pub fn lock(
+        &self,
+        transaction: TransactionId,
+        target: &LockTarget,
+        mode: LockMode,
+    ) -> TransactionResult<()> {
+        self.locks.lock(transaction, target, mode)
+    }
+
+    /// Replace the bytes at `offset` in a page of `file` with `data`, logging the change.
+    #[tracing::instrument(level = "trace", skip(self, data))]
+    pub fn write(
+        &self,
+        transaction: TransactionId,
+        file: u64,
+        page: PageId,
+        offset: usize,
+        data: &[u8],
+    ) -> TransactionResult<Lsn> {
+        let page = PageRef { file, page };
+        let pool = self.pool(file)?;
+        let pinned = pool.pin(page.page)?;
+        if offset + data.len() > pinned.read().len() {
+            return Err(TransactionError::OutOfBounds {
+                file,
+                page: page.page,
+                offset,
+            });
+        }
+        let mut contents = pinned.write();
+        let range = offset..offset + data.len();
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        let entry = state
+            .transactions
+            .get_mut(&transaction)
+            .filter(|entry| entry.status == TransactionStatus::Active)
+            .ok_or(TransactionError::UnknownTransaction(transaction))?;
+        let lsn = self.log.append(&LogRecord::Update {
+            transaction,
+            prev: entry.last_lsn,
+            page,
+            offset,
+            before: contents.data()[range.clone()].to_vec(),
+            after: data.to_vec(),
+        })?;
+        entry.last_lsn = lsn;
+        entry.undo_next = Some(lsn);
+        state.dirty_pages.entry(page).or_insert(lsn);
+        contents.data_mut()[range].copy_from_slice(data);
+        contents.set_lsn(lsn);
+        Ok(lsn)
+    }
+
+    /// Commit a transaction, returning once its changes are durable.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn commit(&self, transaction: TransactionId) -> TransactionResult<()> {
+        let lsn = {
+            let mut state = self.state.lock().expect("Poisoned Lock");
+            let entry = state
+                .transactions
+                .get_mut(&transaction)
+                .filter(|entry| entry.status == TransactionStatus::Active)
+                .ok_or(TransactionError::UnknownTransaction(transaction))?;
+            let lsn = self.log.append(&LogRecord::Commit {
+                transaction,
+                prev: entry.last_lsn,
+            })?;
+            entry.status = TransactionStatus::Committed;
+            entry.last_lsn = lsn;
+            lsn
+        };
+        self.log.force(lsn)?;
+        self.end(transaction)?;
+        self.maybe_checkpoint()
+    }
+
+    /// Abort a transaction, undoing its changes.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn abort(&self, transaction: TransactionId) -> TransactionResult<()> {
+        let (mut last, mut next) = {
+            let mut state = self.state.lock().expect("Poisoned Lock");
+            let entry = state
+                .transactions
+                .get_mut(&transaction)
+                .filter(|entry| entry.status == TransactionStatus::Active)
+                .ok_or(TransactionError::UnknownTransaction(transaction))?;
+            let lsn = self.log.append(&LogRecord::Abort {
+                transaction,
+                prev: entry.last_lsn,
+            })?;
+            entry.status = TransactionStatus::Aborting;
+            entry.last_lsn = lsn;
+            (lsn, entry.undo_next)
+        };
+        while let Some(lsn) = next {
+            next = self.undo(transaction, lsn, &mut last)?;
+        }
+        self.end(transaction)?;
+        self.maybe_checkpoint()
+    }
+
+    /// Take a fuzzy checkpoint, then remove log segments older than any record still needed.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn checkpoint(&self) -> TransactionResult<Lsn> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        if state.following.is_some() {
+            return Err(TransactionError::Following);
+        }
+        let begin = self.log.append(&LogRecord::CheckpointBegin)?;
+        {
+            // Pages written back since they were dirtied no longer need redoing.
+            let files = self.files.read().expect("Poisoned Lock");
+            state.dirty_pages.retain(|page, _| {
+                files
+                    .get(&page.file)
+                    .is_none_or(|pool| pool.is_dirty(page.page))
+            });
+        }
+        let end = self.log.append(&LogRecord::CheckpointEnd {
+            next_transaction: state.next_transaction,
+            transactions: state
+                .transactions
+                .iter()
+                .map(|(transaction, entry)| (*transaction, *entry))
+                .collect(),
+            dirty_pages: state
+                .dirty_pages
+                .iter()
+                .map(|(page, lsn)| (*page, *lsn))
+                .collect(),
+        })?;
+        self.log.force(end)?;
+        state.checkpoint += 1;
+        self.save_checkpoint(state.checkpoint, begin)?;
+
+        let keep = state
+            .dirty_pages
+            .values()
+            .copied()
+            .chain(state.transactions.values().map(|entry| entry.first_lsn))
+            .chain(state.backups.iter().copied())
+            .chain(state.replicas.values().copied())
+            .fold(begin, Lsn::min);
+        let mut log = self.log.wal.lock().expect("Poisoned Lock");
+        log.wal.remove_segments_before(keep)?;
+        log.logged = 0;
+        Ok(begin)
+    }
+
+    /// Copy a backup of every registered file, and of the log needed to bring them up to date,
+    /// to `directory` of `target` while transactions carry on.
+    ///
+    /// The backup takes a checkpoint, copies the pages of every file, then copies the log from
+    /// the checkpoint to its end, and saves a manifest of the size and digest of everything
+    /// copied. Pages changing during the copy are brought up to date by recovery, so restoring
+    /// the backup with [`restore`](TransactionManager::restore) and recovering yields every
+    /// transaction committed before the copy of the log ended, and none other.
+    ///
+    /// ```rust
+    /// use minql_txn::{TransactionManager, TransactionOptions};
+    /// use minql_vfs::{BufferPool, FileSystem, LruPolicy, MemoryFileSystem, PagedFile};
+    ///
+    /// let fs = MemoryFileSystem::new();
+    /// let manager = TransactionManager::open(fs.clone(), "/txn", TransactionOptions::new()).unwrap();
+    /// let file = PagedFile::new(fs.create_file("/table.dat").unwrap(), 4096).unwrap();
+    /// let pool = manager.register_file(1, BufferPool::new(file.with_page_lsn().unwrap(), 1 << 20, LruPolicy::new()));
+    /// manager.recover().unwrap();
+    /// let page = pool.new_page().unwrap().id();
+    /// let transaction = manager.begin().unwrap();
+    /// manager.write(transaction, 1, page, 0, b"Hello").unwrap();
+    /// manager.commit(transaction).unwrap();
+    ///
+    /// let backups = MemoryFileSystem::new();
+    /// manager.backup(&backups, "/monday").unwrap();
+    ///
+    /// let restored = MemoryFileSystem::new();
+    /// let label = TransactionManager::restore(&backups, "/monday", &restored, "/txn").unwrap();
+    /// assert_eq!(label.files, [(1, "/table.dat".to_string())]);
+    /// let manager = TransactionManager::open(restored.clone(), "/txn", TransactionOptions::new()).unwrap();
+    /// let file = PagedFile::new(restored.open_file("/table.dat").unwrap(), 4096).unwrap();
+    /// let pool = manager.register_file(1, BufferPool::new(file.with_page_lsn().unwrap(), 1 << 20, LruPolicy::new()));
+    /// manager.recover().unwrap();
+    /// assert_eq!(&pool.pin(page).unwrap().read().data()[..5], b"Hello");
+    /// ```
+    #[tracing::instrument(level = "debug", skip(self, target))]
+    pub fn backup<G: FileSystem>(
+        &self,
+        target: &G,
+        directory: &str,
+    ) -> TransactionResult<BackupLabel> {
+        let directory = directory.trim_end_matches('/');
+        if target.exists(&format!("{directory}/{MANIFEST_NAME}"))? {
+            return Err(TransactionError::InvalidBackup(format!(
+                "{directory} already holds a backup"
+            )));
+        }
+        let start = self.log.wal.lock().expect("Poisoned Lock").wal.next_lsn();
+        self.state
+            .lock()
+            .expect("Poisoned Lock")
+            .backups
+            .push(start);
+        let label = self.copy_backup(target, directory);
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        let position = state.backups.iter().position(|lsn| *lsn == start);
+        state.backups.swap_remove(position.expect("Backup"));
+        label
+    }
+
+    /// Restore a backup taken by [`backup`](TransactionManager::backup) from `directory` of
+    /// `source`, writing its log and checkpoint to the empty log directory `log` of `fs`, and
+    /// every file back to the path it was copied from.
+    ///
+    /// The backup is checked against its manifest first, failing without writing anything if
+    /// any file is missing or differs. Open a manager on `log`, register the files and recover
+    /// to bring them up to date.
+    #[tracing::instrument(level = "debug", skip(source, fs))]
+    pub fn restore<G: FileSystem>(
+        source: &G,
+        directory: &str,
+        fs: &F,
+        log: &str,
+    ) -> TransactionResult<BackupLabel> {
+        let directory = directory.trim_end_matches('/');
+        let log = log.trim_end_matches('/');
+        let manifest = Manifest::load(source, &format!("{directory}/{MANIFEST_NAME}"))?;
+        let mismatches = minql_vfs::verify(source, directory, &manifest)?
+            .into_iter()
+            .filter(|mismatch| {
+                mismatch.path != MANIFEST_NAME || mismatch.kind != MismatchKind::Unexpected
+            })
+            .collect::<Vec<_>>();
+        if !mismatches.is_empty() {
+            return Err(TransactionError::BackupMismatch(mismatches));
+        }
+        let text = source.read(&format!("{directory}/{LABEL_NAME}"))?;
+        let label = std::str::from_utf8(&text)
+            .ok()
+            .and_then(BackupLabel::decode)
+            .ok_or_else(|| TransactionError::InvalidBackup(format!("label of {directory}")))?;
+        if fs.exists(log)? && !fs.list_directory(log)?.is_empty() {
+            return Err(TransactionError::InvalidBackup(format!(
+                "{log} isn't empty"
+            )));
+        }
+
+        fs.create_directory_all(&format!("{log}/wal"))?;
+        for name in source.list_directory(&format!("{directory}/wal"))? {
+            let segment = source.read(&format!("{directory}/wal/{name}"))?;
+            fs.write(&format!("{log}/wal/{name}"), &segment)?;
+        }
+        let checkpoint = source.read(&checkpoint_path(directory, 1))?;
+        fs.write(&checkpoint_path(log, 1), &checkpoint)?;
+        for (id, path) in &label.files {
+            if let Some((parent, _)) = path
+                .rsplit_once('/')
+                .filter(|(parent, _)| !parent.is_empty())
+            {
+                fs.create_directory_all(parent)?;
+            }
+            fs.write(path, &source.read(&format!("{directory}/files/{id}"))?)?;
+        }
+        tracing::debug!(?label, "Restored backup");
+        Ok(label)
+    }
+
+    /// Ship the log from `from` to its end to the follower `replica`, copying it into the log
+    /// directory `directory` of `target`, and return the end of the log shipped, which the next
+    /// shipment to the follower starts from.
+    ///
+    /// A follower starts from a backup restored to `directory` with
+    /// [`restore`](TransactionManager::restore), shipped from the end of its label, and
+    /// follows the leader with [`open_follower`](TransactionManager::open_follower) and
+    /// [`apply`](TransactionManager::apply). Records keep their positions in the shipped log,
+    /// and the segment holding `from` is copied whole, so shipping again from the same
+    /// position repairs a shipment that was cut short. The leader keeps the log from the last
+    /// position each follower was shipped from until
+    /// [`remove_replica`](TransactionManager::remove_replica), failing with a
+    /// [`ReplicationGap`](TransactionError::ReplicationGap) if a follower asks for records
+    /// it no longer holds, which must then start over from a new backup.
+    #[tracing::instrument(level = "debug", skip(self, target))]
+    pub fn ship<G: FileSystem>(
+        &self,
+        replica: &str,
+        target: &G,
+        directory: &str,
+        from: Lsn,
+    ) -> TransactionResult<Lsn> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        if state.following.is_some() {
+            return Err(TransactionError::Following);
+        }
+        let mut log = self.log.wal.lock().expect("Poisoned Lock");
+        log.wal.sync()?;
+        log.durable = log.wal.next_lsn();
+        let end = log.durable;
+        let oldest = log.wal.segments()?.first().copied().unwrap_or(end.segment);
+        if from > end {
+            return Err(TransactionError::ReplicationGap {
+                needed: from,
+                available: end,
+            });
+        }
+        if from.segment < oldest {
+            return Err(TransactionError::ReplicationGap {
+                needed: from,
+                available: Lsn {
+                    segment: oldest,
+                    offset: 0,
+                },
+            });
+        }
+        state.replicas.insert(replica.to_string(), from);
+        drop(state);
+        if from < end {
+            let directory = directory.trim_end_matches('/');
+            let copied = log
+                .wal
+                .copy_to(target, &format!("{directory}/wal"), from, end)?;
+            tracing::debug!(copied, ?end, "Shipped log");
+        }
+        Ok(end)
+    }
+
+    /// Stop keeping the log for the follower `replica`, returning whether it was shipped to.
+    pub fn remove_replica(&self, replica: &str) -> bool {
+        self.state
+            .lock()
+            .expect("Poisoned Lock")
+            .replicas
+            .remove(replica)
+            .is_some()
+    }
+
+    /// Open the log and checkpoints kept in `directory` as a follower of the leader shipping
+    /// its log there with [`ship`](TransactionManager::ship).
+    ///
+    /// Every file changed by transactions must be registered before applying the shipped log
+    /// with [`apply`](TransactionManager::apply). The files can be read in between, seeing
+    /// every change applied so far, including those of transactions still open on the leader.
+    /// Transactions can't begin until the follower is
+    /// [`promote`](TransactionManager::promote)d.
+    #[tracing::instrument(level = "debug", skip(fs))]
+    pub fn open_follower(
+        fs: F,
+        directory: &str,
+        options: TransactionOptions,
+    ) -> TransactionResult<TransactionManager<F>> {
+        let manager = TransactionManager::open(fs, directory, options)?;
+        let (sequence, next) = manager.load_checkpoint()?;
+        {
+            let mut state = manager.state.lock().expect("Poisoned Lock");
+            state.checkpoint = sequence;
+            state.following = Some(Following {
+                next,
+                applied: false,
+                checkpoint: None,
+            });
+        }
+        Ok(manager)
+    }
+
+    /// Apply every record shipped to this follower since the last call, returning the number
+    /// applied.
+    ///
+    /// Fails with a [`ReplicationGap`](TransactionError::ReplicationGap), applying nothing past
+    /// it, if records are missing from the shipped log, which shipping again from the last
+    /// position shipped repairs. Whenever a checkpoint of the leader is applied, the files are
+    /// written back and the follower's own checkpoint moves to it, so reopening the follower
+    /// after a crash carries on from there.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn apply(&self) -> TransactionResult<usize> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        let mut following = state
+            .following
+            .take()
+            .ok_or(TransactionError::NotFollowing)?;
+        let applied = self.apply_shipped(&mut following, &mut state.checkpoint);
+        state.following = Some(following);
+        applied
+    }
+
+    /// Apply the rest of the shipped log, then stop following and recover from it, undoing the
+    /// changes of the transactions the leader hadn't finished, so transactions can begin.
+    ///
+    /// The leader must no longer ship to the follower once it's promoted.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn promote(&self) -> TransactionResult<RecoveryStats> {
+        self.apply()?;
+        {
+            // The log was opened before the shipped records were added to it.
+            let mut log = self.log.wal.lock().expect("Poisoned Lock");
+            log.wal = WriteAheadLog::open(
+                self.fs.clone(),
+                &format!("{}/wal", self.directory),
+                self.options.wal_options,
+            )?;
+            log.logged = 0;
+        }
+        self.state.lock().expect("Poisoned Lock").following = None;
+        let recovered = self.recover()?;
+        tracing::info!(?recovered, "Promoted follower");
+        Ok(recovered)
+    }
+
+    /// Recover from a crash, redoing committed changes and undoing those of transactions left
+    /// unfinished, then take a checkpoint.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn recover(&self) -> TransactionResult<RecoveryStats> {
+        if self
+            .state
+            .lock()
+            .expect("Poisoned Lock")
+            .following
+            .is_some()
+        {
+            return Err(TransactionError::Following);
+        }
+        let (sequence, start) = self.load_checkpoint()?;
+        let mut recovered = RecoveryStats::default();
+        let (mut transactions, dirty_pages, next_transaction) = self.analyze(start)?;
+        {
+            let mut state = self.state.lock().expect("Poisoned Lock");
+            state.next_transaction = next_transaction;
+            state.checkpoint = sequence;
+            state.dirty_pages.clone_from(&dirty_pages);
+        }
+        if let Some(from) = dirty_pages.values().min() {
+            self.log.for_each(*from, |lsn, record| {
+                if self.redo(&dirty_pages, lsn, &record)? {
+                    recovered.redone += 1;
+                }
+                Ok(())
+            })?;
+        }
+
+        // Undo the updates of every loser, newest first across all of them.
+        let mut undo = BinaryHeap::new();
+        for (transaction, entry) in &mut transactions {
+            if entry.status == TransactionStatus::Committed {
+                self.log.append(&LogRecord::End {
+                    transaction: *transaction,
+                    prev: entry.last_lsn,
+                })?;
+                continue;
+            }
+            recovered.losers += 1;
+            if entry.status == TransactionStatus::Active {
+                entry.last_lsn = self.log.append(&LogRecord::Abort {
+                    transaction: *transaction,
+                    prev: entry.last_lsn,
+                })?;
+            }
+            match entry.undo_next {
+                Some(lsn) => undo.push((lsn, *transaction)),
+                None => self.end_loser(*transaction, entry.last_lsn)?,
+            }
+        }
+        while let Some((lsn, transaction)) = undo.pop() {
+            let entry = transactions.get_mut(&transaction).expect("Loser");
+            let last = entry.last_lsn;
+            let next = self.undo(transaction, lsn, &mut entry.last_lsn)?;
+            if entry.last_lsn != last {
+                recovered.undone += 1;
+            }
+            match next {
+                Some(next) => undo.push((next, transaction)),
+                None => self.end_loser(transaction, entry.last_lsn)?,
+            }
+        }
+        self.checkpoint()?;
+        tracing::debug!(?recovered, "Recovered");
+        Ok(recovered)
+    }
+
+    /// Analysis: find the transactions unfinished at the crash and the pages possibly missing
+    /// updates, starting from the checkpoint at `start`.
+    #[allow(clippy::type_complexity)]
+    fn analyze(
+        &self,
+        start: Lsn,
+    ) -> TransactionResult<(
+        HashMap<TransactionId, TransactionEntry>,
+        HashMap<PageRef, Lsn>,
+        u64,
+    )> {
+        let mut transactions = HashMap::<TransactionId, TransactionEntry>::new();
+        let mut dirty_pages = HashMap::new();
+        let mut next_transaction = 0;
+        self.log.for_each(start, |lsn, record| {
+            if let Some(transaction) = record.transaction() {
+                next_transaction = next_transaction.max(transaction.0 + 1);
+                let entry = transactions.entry(transaction).or_insert(TransactionEntry {
+                    status: TransactionStatus::Active,
+                    first_lsn: lsn,
+                    last_lsn: lsn,
+                    undo_next: None,
+                });
+                entry.last_lsn = lsn;
+                match &record {
+                    LogRecord::Update { page, .. } => {
+                        entry.undo_next = Some(lsn);
+                        dirty_pages.entry(*page).or_insert(lsn);
+                    }
+                    LogRecord::Compensation {
+                        page, undo_next, ..
+                    } => {
+                        entry.undo_next = *undo_next;
+                        dirty_pages.entry(*page).or_insert(lsn);
+                    }
+                    LogRecord::Commit { .. } => entry.status = TransactionStatus::Committed,
+                    LogRecord::Abort { .. } => entry.status = TransactionStatus::Aborting,
+                    LogRecord::End { .. } => {
+                        transactions.remove(&transaction);
+                    }
+                    _ => {}
+                }
+            } else if let LogRecord::CheckpointEnd {
+                next_transaction: next,
+                transactions: open,
+                dirty_pages: dirty,
+            } = record
+            {
+                next_transaction = next_transaction.max(next);
+                for (transaction, entry) in open {
+                    transactions.entry(transaction).or_insert(entry);
+                }
+                for (page, lsn) in dirty {
+                    let rec_lsn = dirty_pages.entry(page).or_insert(lsn);
+                    *rec_lsn = lsn.min(*rec_lsn);
+                }
+            }
+            Ok(())
+        })?;
+        Ok((transactions, dirty_pages, next_transaction))
+    }
+
+    /// Redo: reapply an update or compensation to its page if the page doesn't have it.
+    fn redo(
+        &self,
+        dirty_pages: &HashMap<PageRef, Lsn>,
+        lsn: Lsn,
+        record: &LogRecord,
+    ) -> TransactionResult<bool> {
+        let Some((page, offset, after)) = record.change() else {
+            return Ok(false);
+        };
+        if dirty_pages.get(&page).is_none_or(|rec_lsn| lsn < *rec_lsn) {
+            return Ok(false);
+        }
+        self.replay(lsn, page, offset, after)
+    }
+
+    /// Apply the change of the record at `lsn` to its page if the page doesn't have it.
+    fn replay(
+        &self,
+        lsn: Lsn,
+        page: PageRef,
+        offset: usize,
+        after: &[u8],
+    ) -> TransactionResult<bool> {
+        let pool = self.pool(page.file)?;
+        // Pages added to the file but lost with the crash, or not yet shipped to a follower,
+        // are added again.
+        while pool.page_count()? <= page.page {
+            pool.new_page()?;
+        }
+        let pinned = pool.pin(page.page)?;
+        if pinned.read().lsn() >= lsn {
+            return Ok(false);
+        }
+        let mut contents = pinned.write();
+        let bytes = contents
+            .data_mut()
+            .get_mut(offset..offset + after.len())
+            .ok_or(TransactionError::CorruptLog(lsn))?;
+        bytes.copy_from_slice(after);
+        contents.set_lsn(lsn);
+        Ok(true)
+    }
+
+    /// Apply the shipped log from where `following` left off, saving the follower's
+    /// checkpoints with sequence numbers after `sequence`.
+    fn apply_shipped(
+        &self,
+        following: &mut Following,
+        sequence: &mut u64,
+    ) -> TransactionResult<usize> {
+        let start = following.next;
+        let skip = following.applied;
+        let mut previous = None::<Lsn>;
+        let mut applied = 0;
+        self.log.for_each(start, |lsn, record| {
+            // Records run on within a segment, so only a missing segment leaves a gap.
+            let needed = match previous {
+                None => start,
+                Some(previous) if lsn.segment == previous.segment => lsn,
+                Some(previous) => Lsn {
+                    segment: previous.segment + 1,
+                    offset: 0,
+                },
+            };
+            if lsn != needed {
+                return Err(TransactionError::ReplicationGap {
+                    needed,
+                    available: lsn,
+                });
+            }
+            previous = Some(lsn);
+            if skip && lsn == start {
+                return Ok(());
+            }
+            if let Some((page, offset, after)) = record.change() {
+                self.replay(lsn, page, offset, after)?;
+            }
+            match record {
+                LogRecord::CheckpointBegin => following.checkpoint = Some(lsn),
+                LogRecord::CheckpointEnd { .. } => {
+                    if let Some(begin) = following.checkpoint.take() {
+                        // Recovery can start at the checkpoint once every page changed by
+                        // the records before it is written back.
+                        let files = self
+                            .files
+                            .read()
+                            .expect("Poisoned Lock")
+                            .values()
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        for pool in files {
+                            pool.flush_all()?;
+                        }
+                        *sequence += 1;
+                        self.save_checkpoint(*sequence, begin)?;
+                    }
+                }
+                _ => {}
+            }
+            following.next = lsn;
+            following.applied = true;
+            applied += 1;
+            Ok(())
+        })?;
+        tracing::debug!(applied, next = ?following.next, "Applied shipped log");
+        Ok(applied)
+    }
+
+    /// Undo the record at `lsn` of `transaction` if it's an update, logging a compensation
+    /// after `last`, and return the next record to undo.
+    fn undo(
+        &self,
+        transaction: TransactionId,
+        lsn: Lsn,
+        last: &mut Lsn,
+    ) -> TransactionResult<Option<Lsn>> {
+        let (page, offset, before, prev) = match self.log.read(lsn)? {
+            LogRecord::Update {
+                page,
+                offset,
+                before,
+                prev,
+                ..
+            } => (page, offset, before, prev),
+            LogRecord::Compensation { undo_next, .. } => return Ok(undo_next),
+            LogRecord::Begin { .. } => return Ok(None),
+            LogRecord::Commit { prev, .. }
+            | LogRecord::Abort { prev, .. }
+            | LogRecord::End { prev, .. } => return Ok(Some(prev)),
+            LogRecord::CheckpointBegin | LogRecord::CheckpointEnd { .. } => {
+                return Err(TransactionError::CorruptLog(lsn))
+            }
+        };
+        let undo_next = Some(prev);
+        let pool = self.pool(page.file)?;
+        let pinned = pool.pin(page.page)?;
+        let mut contents = pinned.write();
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        let compensation = self.log.append(&LogRecord::Compensation {
+            transaction,
+            prev: *last,
+            undo_next,
+            page,
+            offset,
+            after: before.clone(),
+        })?;
+        *last = compensation;
+        if let Some(entry) = state.transactions.get_mut(&transaction) {
+            entry.last_lsn = compensation;
+            entry.undo_next = undo_next;
+        }
+        state.dirty_pages.entry(page).or_insert(compensation);
+        contents
+            .data_mut()
+            .get_mut(offset..offset + before.len())
+            .ok_or(TransactionError::CorruptLog(lsn))?
+            .copy_from_slice(&before);
+        contents.set_lsn(compensation);
+        Ok(undo_next)
+    }
+
+    /// Log the end of a transaction and release its locks.
+    fn end(&self, transaction: TransactionId) -> TransactionResult<()> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        if let Some(entry) = state.transactions.remove(&transaction) {
+            self.log.append(&LogRecord::End {
+                transaction,
+                prev: entry.last_lsn,
+            })?;
+        }
+        drop(state);
+        self.locks.release_all(transaction);
+        Ok(())
+    }
+
+    /// Log the end of a loser whose updates have all been undone.
+    fn end_loser(&self, transaction: TransactionId, last: Lsn) -> TransactionResult<()> {
+        self.log.append(&LogRecord::End {
+            transaction,
+            prev: last,
+        })?;
+        Ok(())
+    }
+
+    fn maybe_checkpoint(&self) -> TransactionResult<()> {
+        let logged = self.log.wal.lock().expect("Poisoned Lock").logged;
+        if logged >= self.options.checkpoint_interval {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    fn pool(&self, file: u64) -> TransactionResult<Arc<BufferPool<F::FileHandle>>> {
+        self.files
+            .read()
+            .expect("Poisoned Lock")
+            .get(&file)
+            .cloned()
+            .ok_or(TransactionError::UnknownFile(file))
+    }
+
+    /// Copy the files and log of a backup once the log is kept from its start.
+    fn copy_backup<G: FileSystem>(
+        &self,
+        target: &G,
+        directory: &str,
+    ) -> TransactionResult<BackupLabel> {
+        let checkpoint = self.checkpoint()?;
+        let mut files = self
+            .files
+            .read()
+            .expect("Poisoned Lock")
+            .iter()
+            .map(|(id, pool)| (*id, pool.clone()))
+            .collect::<Vec<_>>();
+        files.sort_unstable_by_key(|(id, _)| *id);
+        target.create_directory_all(&format!("{directory}/files"))?;
+        let mut copies = Vec::with_capacity(files.len());
+        for (id, pool) in &files {
+            let handle = target.create_file(&format!("{directory}/files/{id}"))?;
+            let mut copy = pool.create_copy(handle)?;
+            pool.copy_to(&mut copy)?;
+            copies.push(copy);
+        }
+        let end = {
+            let mut log = self.log.wal.lock().expect("Poisoned Lock");
+            log.wal.sync()?;
+            log.durable = log.wal.next_lsn();
+            let end = log.durable;
+            log.wal
+                .copy_to(target, &format!("{directory}/wal"), checkpoint, end)?;
+            end
+        };
+        // Pages appended before the log ended may have records in the copy, and are added
+        // empty for recovery to redo them, as copying them now could include later records.
+        for ((_, pool), copy) in files.iter().zip(&mut copies) {
+            let missing = pool.page_count()?.saturating_sub(copy.page_count()?);
+            if missing > 0 {
+                copy.extend(missing)?;
+                copy.sync()?;
+            }
+        }
+        target.write(
+            &checkpoint_path(directory, 1),
+            encode_checkpoint(checkpoint).as_bytes(),
+        )?;
+        let label = BackupLabel {
+            checkpoint,
+            end,
+            files: files.iter().map(|(id, pool)| (*id, pool.path())).collect(),
+        };
+        target.write(
+            &format!("{directory}/{LABEL_NAME}"),
+            label.encode().as_bytes(),
+        )?;
+        minql_vfs::manifest(target, directory)?
+            .save(target, &format!("{directory}/{MANIFEST_NAME}"))?;
+        tracing::debug!(?label, "Copied backup");
+        Ok(label)
+    }
+
+    /// Point recovery at the checkpoint beginning at `lsn`, then remove the previous file.
+    fn save_checkpoint(&self, sequence: u64, lsn: Lsn) -> TransactionResult<()> {
+        let text = encode_checkpoint(lsn);
+        let mut handle = self
+            .fs
+            .create_file(&checkpoint_path(&self.directory, sequence))?;
+        handle
+            .write_all(text.as_bytes())
+            .map_err(FileSystemError::io_error)?;
+        handle.sync_all()?;
+        match self
+            .fs
+            .remove_file(&checkpoint_path(&self.directory, sequence - 1))
+        {
+            Ok(()) | Err(FileSystemError::PathMissing) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Find the newest intact checkpoint, or the start of the log if there is none.
+    fn load_checkpoint(&self) -> TransactionResult<(u64, Lsn)> {
+        let mut sequences = self
+            .fs
+            .list_directory(&self.directory)?
+            .iter()
+            .filter_map(|name| name.strip_prefix(CHECKPOINT_PREFIX)?.parse::<u64>().ok())
+            .collect::<Vec<_>>();
+        sequences.sort_unstable();
+        for sequence in sequences.into_iter().rev() {
+            let text = self.fs.read(&checkpoint_path(&self.directory, sequence))?;
+            if let Some(lsn) = std::str::from_utf8(&text).ok().and_then(decode_checkpoint) {
+                return Ok((sequence, lsn));
+            }
+            tracing::warn!(sequence, "Skipping damaged checkpoint");
+        }
+        Ok((0, Lsn::default()))
+    }
+}
+
+/// Path of the checkpoint file with sequence number `sequence`.
+fn checkpoint_path(directory: &str, sequence: u64) -> String {
+    format!("{directory}/{CHECKPOINT_PREFIX}{sequence:06}")
+}
+
+/// Encode a checkpoint file pointing recovery at the checkpoint beginning at `lsn`.
+fn encode_checkpoint(lsn: Lsn) -> String {
+    let body = format!(
+        "{CHECKPOINT_HEADER}\ncheckpoint {} {}\n",
+        lsn.segment, lsn.offset
+    );
+    format!("{body}crc {:08x}\n", crc32fast::hash(body.as_bytes()))
+}
+
+/// Decode a checkpoint file, or `None` if it's damaged or incomplete.
+fn decode_checkpoint(text: &str) -> Option<Lsn> {
+    let body_end = text.trim_end_matches('\n').rfind('\n')? + 1;
+    let (body, checksum) = text.split_at(body_end);
+    let checksum = u32::from_str_radix(checksum.trim().strip_prefix("crc ")?, 16).ok()?;
+    if crc32fast::hash(body.as_bytes()) != checksum {
+        return None;
+    }
+    let mut lines = body.lines();
+    if lines.next()? != CHECKPOINT_HEADER {
+        return None;
+    }
+    let fields = lines.next()?.split(' ').collect::<Vec<_>>();
+    match fields.as_slice() {
+        ["checkpoint", segment, offset] => Some(Lsn {
+            segment: segment.parse().ok()?,
+            offset: offset.parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TransactionManager, TransactionOptions};
+    use crate::{LockMode, LockTarget, RecoveryStats, TransactionError};
+    use minql_vfs::{BufferPool, FileSystem, LruPolicy, MemoryFileSystem, PagedFile, WalOptions};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    type Pool = Arc<BufferPool<<MemoryFileSystem as FileSystem>::FileHandle>>;
+
+    fn open(
+        fs: &MemoryFileSystem,
+        options: TransactionOptions,
+    ) -> (TransactionManager<MemoryFileSystem>, Pool) {
+        let manager = TransactionManager::open(fs.clone(), "/txn", options).unwrap();
+        let handle = if fs.exists("/data.db").unwrap() {
+            fs.open_file("/data.db").unwrap()
+        } else {
+            fs.create_file("/data.db").unwrap()
+        };
+        let file = PagedFile::new(handle, 128)
+            .unwrap()
+            .with_page_lsn()
+            .unwrap();
+        let pool = manager.register_file(1, BufferPool::new(file, 2 * 128, LruPolicy::new()));
+        (manager, pool)
+    }
+
+    fn read(pool: &Pool, page: u64, len: usize) -> Vec<u8> {
+        pool.pin(page).unwrap().read().data()[..len].to_vec()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_commit_and_abort() {
+        let fs = MemoryFileSystem::new();
+        let options = TransactionOptions::new().with_lock_timeout(Duration::from_millis(10));
+        let (manager, pool) = open(&fs, options);
+        assert_eq!(manager.recover().unwrap(), RecoveryStats::default());
+        for _ in 0..3 {
+            pool.new_page().unwrap();
+        }
+
+        let t1 = manager.begin().unwrap();
+        manager
+            .lock(t1, &LockTarget::Table(1), LockMode::Exclusive)
+            .unwrap();
+        manager.write(t1, 1, 0, 0, b"one").unwrap();
+        manager.write(t1, 1, 2, 4, b"two").unwrap();
+        let t2 = manager.begin().unwrap();
+        assert!(matches!(
+            manager.lock(t2, &LockTarget::Table(1), LockMode::Shared),
+            Err(TransactionError::LockTimeout(_))
+        ));
+        manager.commit(t1).unwrap();
+        manager
+            .lock(t2, &LockTarget::Table(1), LockMode::Shared)
+            .unwrap();
+        assert!(matches!(
+            manager.commit(t1),
+            Err(TransactionError::UnknownTransaction(id)) if id == t1
+        ));
+
+        manager.write(t2, 1, 0, 1, b"XY").unwrap();
+        manager.write(t2, 1, 1, 0, b"Z").unwrap();
+        manager.write(t2, 1, 0, 0, b"W").unwrap();
+        assert_eq!(read(&pool, 0, 3), b"WXY");
+        assert!(matches!(
+            manager.write(t2, 1, 0, 104, b"too long"),
+            Err(TransactionError::OutOfBounds { offset: 104, .. })
+        ));
+        assert!(matches!(
+            manager.write(t2, 9, 0, 0, b"?"),
+            Err(TransactionError::UnknownFile(9))
+        ));
+        manager.abort(t2).unwrap();
+        assert_eq!(read(&pool, 0, 3), b"one");
+        assert_eq!(read(&pool, 1, 1), [0]);
+        assert_eq!(read(&pool, 2, 7), b"\0\0\0\0two");
+        assert!(matches!(
+            manager.write(t2, 1, 0, 0, b"!"),
+            Err(TransactionError::UnknownTransaction(_))
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_recovery() {
+        let fs = MemoryFileSystem::new();
+        let (manager, pool) = open(&fs, TransactionOptions::new());
+        manager.recover().unwrap();
+        for _ in 0..3 {
+            pool.new_page().unwrap();
+        }
+        pool.flush_all().unwrap();
+
+        let committed = manager.begin().unwrap();
+        manager.write(committed, 1, 0, 0, b"committed").unwrap();
+        manager.commit(committed).unwrap();
+        let loser = manager.begin().unwrap();
+        manager.write(loser, 1, 1, 0, b"loser").unwrap();
+        manager.write(loser, 1, 0, 20, b"loser").unwrap();
+        let aborted = manager.begin().unwrap();
+        manager.write(aborted, 1, 2, 0, b"aborted").unwrap();
+        manager.abort(aborted).unwrap();
+        // The loser's changes reach storage before it ends
+        pool.flush_all().unwrap();
+        let late = manager.begin().unwrap();
+        manager.write(late, 1, 2, 10, b"late").unwrap();
+        manager.commit(late).unwrap();
+        // Crash, losing the pages still in the pool
+        drop((manager, pool));
+
+        let (manager, pool) = open(&fs, TransactionOptions::new());
+        assert_eq!(read(&pool, 2, 14), [0; 14]);
+        let stats = manager.recover().unwrap();
+        assert_eq!(stats.losers, 1);
+        assert_eq!(stats.undone, 2);
+        assert!(stats.redone >= 1);
+        assert_eq!(read(&pool, 0, 9), b"committed");
+        assert_eq!(read(&pool, 0, 25)[20..], [0; 5]);
+        assert_eq!(read(&pool, 1, 5), [0; 5]);
+        assert_eq!(&read(&pool, 2, 14)[10..], b"late");
+        let next = manager.begin().unwrap();
+        assert!(next > late);
+        manager.commit(next).unwrap();
+        drop((manager, pool));
+
+        // Recovering again finds nothing left to undo
+        let (manager, pool) = open(&fs, TransactionOptions::new());
+        assert_eq!(manager.recover().unwrap().losers, 0);
+        assert_eq!(read(&pool, 0, 9), b"committed");
+        assert_eq!(read(&pool, 1, 5), [0; 5]);
+        assert_eq!(&read(&pool, 2, 14)[10..], b"late");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_checkpoint() {
+        let fs = MemoryFileSystem::new();
+        let options = TransactionOptions::new()
+            .with_wal_options(WalOptions::new().with_segment_size(512))
+            .with_checkpoint_interval(1024);
+        let (manager, pool) = open(&fs, options);
+        manager.recover().unwrap();
+        pool.new_page().unwrap();
+        pool.new_page().unwrap();
+
+        let open_transaction = manager.begin().unwrap();
+        manager.write(open_transaction, 1, 1, 0, b"open").unwrap();
+        for i in 0..100u8 {
+            let transaction = manager.begin().unwrap();
+            manager.write(transaction, 1, 0, 0, &[i; 8]).unwrap();
+            manager.commit(transaction).unwrap();
+            if i == 50 {
+                pool.flush_all().unwrap();
+            }
+        }
+        let checkpoints = fs
+            .list_directory("/txn")
+            .unwrap()
+            .into_iter()
+            .filter(|name| name.starts_with("CHECKPOINT-"))
+            .count();
+        assert_eq!(checkpoints, 1);
+        // The open transaction holds back truncation until it ends
+        let segments = fs.list_directory("/txn/wal").unwrap().len();
+        assert!(segments > 10, "{segments}");
+        drop((manager, pool));
+
+        let (manager, pool) = open(&fs, options);
+        let stats = manager.recover().unwrap();
+        assert_eq!(stats.losers, 1);
+        assert_eq!(read(&pool, 0, 8), [99; 8]);
+        assert_eq!(read(&pool, 1, 4), [0; 4]);
+        pool.flush_all().unwrap();
+        manager.checkpoint().unwrap();
+        // Only the segments holding the new checkpoint remain
+        assert!(fs.list_directory("/txn/wal").unwrap().len() <= 2);
+        drop((manager, pool));
+
+        let (manager, pool) = open(&fs, options);
+        assert_eq!(manager.recover().unwrap(), RecoveryStats::default());
+        assert_eq!(read(&pool, 0, 8), [99; 8]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_backup_and_restore() {
+        let fs = MemoryFileSystem::new();
+        let options = TransactionOptions::new()
+            .with_wal_options(WalOptions::new().with_segment_size(512))
+            .with_checkpoint_interval(1024);
+        let (manager, pool) = open(&fs, options);
+        manager.recover().unwrap();
+        for _ in 0..3 {
+            pool.new_page().unwrap();
+        }
+        let transaction = manager.begin().unwrap();
+        manager.write(transaction, 1, 0, 0, b"committed").unwrap();
+        manager.commit(transaction).unwrap();
+        // The change of the open transaction reaches the file, so the backup must undo it
+        let open_transaction = manager.begin().unwrap();
+        manager.write(open_transaction, 1, 1, 0, b"open").unwrap();
+        pool.flush_all().unwrap();
+
+        let backups = MemoryFileSystem::new();
+        let label = manager.backup(&backups, "/backup").unwrap();
+        assert_eq!(label.files, [(1, "/data.db".to_string())]);
+        assert!(matches!(
+            manager.backup(&backups, "/backup"),
+            Err(TransactionError::InvalidBackup(_))
+        ));
+        let transaction = manager.begin().unwrap();
+        manager.write(transaction, 1, 2, 0, b"late").unwrap();
+        manager.commit(transaction).unwrap();
+        manager.commit(open_transaction).unwrap();
+        assert_eq!(read(&pool, 1, 4), b"open");
+
+        let restored = MemoryFileSystem::new();
+        let restore =
+            |fs: &MemoryFileSystem| TransactionManager::restore(&backups, "/backup", fs, "/txn");
+        assert_eq!(restore(&restored).unwrap(), label);
+        assert!(matches!(
+            restore(&restored),
+            Err(TransactionError::InvalidBackup(_))
+        ));
+        let (manager, pool) = open(&restored, options);
+        assert_eq!(manager.recover().unwrap().losers, 1);
+        assert_eq!(read(&pool, 0, 9), b"committed");
+        assert_eq!(read(&pool, 1, 4), [0; 4]);
+        assert_eq!(read(&pool, 2, 4), [0; 4]);
+
+        backups.write("/backup/files/1", b"tampered").unwrap();
+        let Err(TransactionError::BackupMismatch(mismatches)) = restore(&MemoryFileSystem::new())
+        else {
+            panic!("restored a damaged backup");
+        };
+        assert_eq!(mismatches[0].path, "files/1");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_replication() {
+        let fs = MemoryFileSystem::new();
+        let options = TransactionOptions::new()
+            .with_wal_options(WalOptions::new().with_segment_size(512))
+            .with_checkpoint_interval(1024);
+        let (leader, pool) = open(&fs, options);
+        leader.recover().unwrap();
+        for _ in 0..3 {
+            pool.new_page().unwrap();
+        }
+        let transaction = leader.begin().unwrap();
+        leader.write(transaction, 1, 0, 0, b"committed").unwrap();
+        leader.commit(transaction).unwrap();
+        let backups = MemoryFileSystem::new();
+        let label = leader.backup(&backups, "/backup").unwrap();
+        let replica = MemoryFileSystem::new();
+        TransactionManager::restore(&backups, "/backup", &replica, "/txn").unwrap();
+
+        let follower = TransactionManager::open_follower(replica.clone(), "/txn", options).unwrap();
+        let handle = replica.open_file("/data.db").unwrap();
+        let file = PagedFile::new(handle, 128)
+            .unwrap()
+            .with_page_lsn()
+            .unwrap();
+        let replica_pool =
+            follower.register_file(1, BufferPool::new(file, 2 * 128, LruPolicy::new()));
+        assert!(follower.apply().unwrap() > 0);
+        assert_eq!(read(&replica_pool, 0, 9), b"committed");
+        assert!(matches!(follower.begin(), Err(TransactionError::Following)));
+        assert!(matches!(
+            follower.recover(),
+            Err(TransactionError::Following)
+        ));
+        assert!(matches!(
+            follower.ship("cascade", &MemoryFileSystem::new(), "/txn", label.end),
+            Err(TransactionError::Following)
+        ));
+        assert!(matches!(
+            leader.apply(),
+            Err(TransactionError::NotFollowing)
+        ));
+
+        // Enough changes to fill several segments and take checkpoints on both sides
+        for i in 0..40u8 {
+            let transaction = leader.begin().unwrap();
+            leader.write(transaction, 1, 1, 0, &[i; 8]).unwrap();
+            leader.commit(transaction).unwrap();
+        }
+        let shipped = leader.ship("replica", &replica, "/txn", label.end).unwrap();
+        let segments = replica.list_directory("/txn/wal").unwrap();
+        assert!(segments.len() > 3, "{segments:?}");
+        replica
+            .remove_file(&format!("/txn/wal/{}", segments[segments.len() - 2]))
+            .unwrap();
+        assert!(matches!(
+            follower.apply(),
+            Err(TransactionError::ReplicationGap { .. })
+        ));
+        assert_eq!(
+            leader.ship("replica", &replica, "/txn", label.end).unwrap(),
+            shipped
+        );
+        assert!(follower.apply().unwrap() > 0);
+        assert_eq!(read(&replica_pool, 1, 8), [39; 8]);
+        assert_eq!(follower.apply().unwrap(), 0);
+        // The follower's checkpoint moved on with the leader's
+        assert!(!replica.exists("/txn/CHECKPOINT-000001").unwrap());
+
+        // The follower sees the change of a transaction still open, until promoted
+        let open_transaction = leader.begin().unwrap();
+        leader.write(open_transaction, 1, 2, 0, b"open").unwrap();
+        let end = leader.ship("replica", &replica, "/txn", shipped).unwrap();
+        follower.apply().unwrap();
+        assert_eq!(read(&replica_pool, 2, 4), b"open");
+        let stats = follower.promote().unwrap();
+        assert_eq!(stats.losers, 1);
+        assert_eq!(read(&replica_pool, 2, 4), [0; 4]);
+        assert_eq!(read(&replica_pool, 1, 8), [39; 8]);
+        let transaction = follower.begin().unwrap();
+        follower.write(transaction, 1, 2, 0, b"promoted").unwrap();
+        follower.commit(transaction).unwrap();
+        assert!(matches!(
+            follower.promote(),
+            Err(TransactionError::NotFollowing)
+        ));
+
+        // Without the follower, the leader no longer keeps the log it needs
+        assert!(leader.remove_replica("replica"));
+        assert!(!leader.remove_replica("replica"));
+        leader.commit(open_transaction).unwrap();
+        for i in 0..20u8 {
+            let transaction = leader.begin().unwrap();
+            leader.write(transaction, 1, 1, 0, &[i; 8]).unwrap();
+            leader.commit(transaction).unwrap();
+        }
+        pool.flush_all().unwrap();
+        leader.checkpoint().unwrap();
+        assert!(matches!(
+            leader.ship("replica", &replica, "/txn", shipped),
+            Err(TransactionError::ReplicationGap { needed, .. }) if needed == shipped
+        ));
+        let beyond = minql_vfs::Lsn {
+            segment: end.segment + 1,
+            offset: 0,
+        };
+        assert!(matches!(
+            leader.ship("replica", &replica, "/txn", beyond),
+            Err(TransactionError::ReplicationGap { available, .. }) if available > end
+        ));
+    }
+}
@@ -0,0 +1,442 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::TransactionId;
+use minql_vfs::{Lsn, PageId};
+
+/// Page of a file registered with a [`TransactionManager`](crate::TransactionManager).
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub(crate) struct PageRef {
+    pub file: u64,
+    pub page: PageId,
+}
+
+/// Outcome of a transaction as far as the log records.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum TransactionStatus {
+    Active,
+    Committed,
+    Aborting,
+}
+
+/// A transaction open at a checkpoint.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct TransactionEntry {
+    pub status: TransactionStatus,
+    /// Position of its first record, which the log must keep while it's open
+    pub first_lsn: Lsn,
+    /// Position of its latest record
+    pub last_lsn: Lsn,
+    /// Position of its latest update not yet undone
+    pub undo_next: Option<Lsn>,
+}
+
+/// Record of a [`WriteAheadLog`](minql_vfs::WriteAheadLog) kept by a transaction manager.
+///
+/// Updates are physical, replacing a range of bytes in a page, and carry both images so they
+/// can be redone and undone. Every record of a transaction links back to the one before it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum LogRecord {
+    Begin {
+        transaction: TransactionId,
+    },
+    Update {
+        transaction: TransactionId,
+        prev: Lsn,
+        page: PageRef,
+        offset: usize,
+        before: Vec<u8>,
+        after: Vec<u8>,
+    },
+    /// Compensation log record, undoing an update and never undone itself
+    Compensation {
+        transaction: TransactionId,
+        prev: Lsn,
+        /// Next update of the transaction to undo
+        undo_next: Option<Lsn>,
+        page: PageRef,
+        offset: usize,
+        after: Vec<u8>,
+    },
+    Commit {
+        transaction: TransactionId,
+        prev: Lsn,
+    },
+    Abort {
+        transaction: TransactionId,
+        prev: Lsn,
+    },
+    End {
+        transaction: TransactionId,
+        prev: Lsn,
+    },
+    CheckpointBegin,
+    CheckpointEnd {
+        next_transaction: u64,
+        transactions: Vec<(TransactionId, TransactionEntry)>,
+        dirty_pages: Vec<(PageRef, Lsn)>,
+    },
+}
+
+const BEGIN: u8 = 1;
+const UPDATE: u8 = 2;
+const COMPENSATION: u8 = 3;
+const COMMIT: u8 = 4;
+const ABORT: u8 = 5;
+const END: u8 = 6;
+const CHECKPOINT_BEGIN: u8 = 7;
+const CHECKPOINT_END: u8 = 8;
+
+impl LogRecord {
+    /// Transaction the record belongs to, if any.
+    pub fn transaction(&self) -> Option<TransactionId> {
+        match self {
+            LogRecord::Begin { transaction }
+            | LogRecord::Update { transaction, .. }
+            | LogRecord::Compensation { transaction, .. }
+            | LogRecord::Commit { transaction, .. }
+            | LogRecord::Abort { transaction, .. }
+            | LogRecord::End { transaction, .. } => Some(*transaction),
+            LogRecord::CheckpointBegin | LogRecord::CheckpointEnd { .. } => None,
+        }
+    }
+
+    /// Page, offset and new contents of the bytes an update or compensation changes.
+    pub fn change(&self) -> Option<(PageRef, usize, &[u8])> {
+        match self {
+            LogRecord::Update {
+                page,
+                offset,
+                after,
+                ..
+            }
+            | LogRecord::Compensation {
+                page,
+                offset,
+                after,
+                ..
+            } => Some((*page, *offset, after)),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::default();
+        match self {
+            LogRecord::Begin { transaction } => {
+                encoder.u8(BEGIN);
+                encoder.u64(transaction.0);
+            }
+            LogRecord::Update {
+                transaction,
+                prev,
+                page,
+                offset,
+                before,
+                after,
+            } => {
+                encoder.u8(UPDATE);
+                encoder.u64(transaction.0);
+                encoder.lsn(*prev);
+                encoder.page(*page);
+                encoder.u64(*offset as u64);
+                encoder.bytes(before);
+                encoder.bytes(after);
+            }
+            LogRecord::Compensation {
+                transaction,
+                prev,
+                undo_next,
+                page,
+                offset,
+                after,
+            } => {
+                encoder.u8(COMPENSATION);
+                encoder.u64(transaction.0);
+                encoder.lsn(*prev);
+                encoder.optional_lsn(*undo_next);
+                encoder.page(*page);
+                encoder.u64(*offset as u64);
+                encoder.bytes(after);
+            }
+            LogRecord::Commit { transaction, prev }
+            | LogRecord::Abort { transaction, prev }
+            | LogRecord::End { transaction, prev } => {
+                encoder.u8(match self {
+                    LogRecord::Commit { .. } => COMMIT,
+                    LogRecord::Abort { .. } => ABORT,
+                    _ => END,
+                });
+                encoder.u64(transaction.0);
+                encoder.lsn(*prev);
+            }
+            LogRecord::CheckpointBegin => encoder.u8(CHECKPOINT_BEGIN),
+            LogRecord::CheckpointEnd {
+                next_transaction,
+                transactions,
+                dirty_pages,
+            } => {
+                encoder.u8(CHECKPOINT_END);
+                encoder.u64(*next_transaction);
+                encoder.u64(transactions.len() as u64);
+                for (transaction, entry) in transactions {
+                    encoder.u64(transaction.0);
+                    encoder.u8(match entry.status {
+                        TransactionStatus::Active => 0,
+                        TransactionStatus::Committed => 1,
+                        TransactionStatus::Aborting => 2,
+                    });
+                    encoder.lsn(entry.first_lsn);
+                    encoder.lsn(entry.last_lsn);
+                    encoder.optional_lsn(entry.undo_next);
+                }
+                encoder.u64(dirty_pages.len() as u64);
+                for (page, lsn) in dirty_pages {
+                    encoder.page(*page);
+                    encoder.lsn(*lsn);
+                }
+            }
+        }
+        encoder.0
+    }
+
+    /// Decode a record, or `None` if it's malformed.
+    pub fn decode(bytes: &[u8]) -> Option<LogRecord> {
+        let mut decoder = Decoder { bytes };
+        let record = match decoder.u8()? {
+            BEGIN => LogRecord::Begin {
+                transaction: TransactionId(decoder.u64()?),
+            },
+            UPDATE => LogRecord::Update {
+                transaction: TransactionId(decoder.u64()?),
+                prev: decoder.lsn()?,
+                page: decoder.page()?,
+                offset: usize::try_from(decoder.u64()?).ok()?,
+                before: decoder.bytes()?,
+                after: decoder.bytes()?,
+            },
+            COMPENSATION => LogRecord::Compensation {
+                transaction: TransactionId(decoder.u64()?),
+                prev: decoder.lsn()?,
+                undo_next: decoder.optional_lsn().ok()?,
+                page: decoder.page()?,
+                offset: usize::try_from(decoder.u64()?).ok()?,
+                after: decoder.bytes()?,
+            },
+            kind @ (COMMIT | ABORT | END) => {
+                let transaction = TransactionId(decoder.u64()?);
+                let prev = decoder.lsn()?;
+                match kind {
+                    COMMIT => LogRecord::Commit { transaction, prev },
+                    ABORT => LogRecord::Abort { transaction, prev },
+                    _ => LogRecord::End { transaction, prev },
+                }
+            }
+            CHECKPOINT_BEGIN => LogRecord::CheckpointBegin,
+            CHECKPOINT_END => decode_checkpoint(&mut decoder)?,
+            _ => return None,
+        };
+        decoder.bytes.is_empty().then_some(record)
+    }
+}
+
+fn decode_checkpoint(decoder: &mut Decoder<'_>) -> Option<LogRecord> {
+    let next_transaction = decoder.u64()?;
+    let mut transactions = Vec::new();
+    for _ in 0..decoder.u64()? {
+        let transaction = TransactionId(decoder.u64()?);
+        let status = match decoder.u8()? {
+            0 => TransactionStatus::Active,
+            1 => TransactionStatus::Committed,
+            2 => TransactionStatus::Aborting,
+            _ => return None,
+        };
+        transactions.push((
+            transaction,
+            TransactionEntry {
+                status,
+                first_lsn: decoder.lsn()?,
+                last_lsn: decoder.lsn()?,
+                undo_next: decoder.optional_lsn().ok()?,
+            },
+        ));
+    }
+    let mut dirty_pages = Vec::new();
+    for _ in 0..decoder.u64()? {
+        dirty_pages.push((decoder.page()?, decoder.lsn()?));
+    }
+    Some(LogRecord::CheckpointEnd {
+        next_transaction,
+        transactions,
+        dirty_pages,
+    })
+}
+
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn lsn(&mut self, lsn: Lsn) {
+        self.u64(lsn.segment);
+        self.u64(lsn.offset);
+    }
+
+    fn optional_lsn(&mut self, lsn: Option<Lsn>) {
+        match lsn {
+            Some(lsn) => {
+                self.u8(1);
+                self.lsn(lsn);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn page(&mut self, page: PageRef) {
+        self.u64(page.file);
+        self.u64(page.page);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, len: usize) -> Option<&[u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn lsn(&mut self) -> Option<Lsn> {
+        Some(Lsn {
+            segment: self.u64()?,
+            offset: self.u64()?,
+        })
+    }
+
+    fn optional_lsn(&mut self) -> Result<Option<Lsn>, ()> {
+        match self.u8() {
+            Some(0) => Ok(None),
+            Some(1) => self.lsn().map(Some).ok_or(()),
+            _ => Err(()),
+        }
+    }
+
+    fn page(&mut self) -> Option<PageRef> {
+        Some(PageRef {
+            file: self.u64()?,
+            page: self.u64()?,
+        })
+    }
+
+    fn bytes(&mut self) -> Option<Vec<u8>> {
+        let len = usize::try_from(self.u64()?).ok()?;
+        Some(self.take(len)?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LogRecord, PageRef, TransactionEntry, TransactionStatus};
+    use crate::TransactionId;
+    use minql_vfs::Lsn;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_log_record_encoding() {
+        let lsn = |offset| Lsn { segment: 1, offset };
+        let page = PageRef { file: 3, page: 9 };
+        let records = [
+            LogRecord::Begin {
+                transaction: TransactionId(1),
+            },
+            LogRecord::Update {
+                transaction: TransactionId(1),
+                prev: lsn(0),
+                page,
+                offset: 12,
+                before: b"old".to_vec(),
+                after: b"new".to_vec(),
+            },
+            LogRecord::Compensation {
+                transaction: TransactionId(1),
+                prev: lsn(40),
+                undo_next: None,
+                page,
+                offset: 12,
+                after: b"old".to_vec(),
+            },
+            LogRecord::Commit {
+                transaction: TransactionId(2),
+                prev: lsn(8),
+            },
+            LogRecord::Abort {
+                transaction: TransactionId(1),
+                prev: lsn(80),
+            },
+            LogRecord::End {
+                transaction: TransactionId(1),
+                prev: lsn(90),
+            },
+            LogRecord::CheckpointBegin,
+            LogRecord::CheckpointEnd {
+                next_transaction: 3,
+                transactions: vec![(
+                    TransactionId(2),
+                    TransactionEntry {
+                        status: TransactionStatus::Aborting,
+                        first_lsn: lsn(8),
+                        last_lsn: lsn(70),
+                        undo_next: Some(lsn(60)),
+                    },
+                )],
+                dirty_pages: vec![(page, lsn(40))],
+            },
+        ];
+        for record in records {
+            let bytes = record.encode();
+            assert_eq!(LogRecord::decode(&bytes), Some(record));
+            assert_eq!(LogRecord::decode(&bytes[..bytes.len() - 1]), None);
+        }
+        assert_eq!(LogRecord::decode(&[]), None);
+        assert_eq!(LogRecord::decode(&[99]), None);
+    }
+}
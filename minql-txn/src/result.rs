@@ -0,0 +1,85 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::TransactionId;
+use minql_vfs::{FileSystemError, Lsn, ManifestMismatch, PageId};
+
+/// Result Type for Transactions
+pub type TransactionResult<T> = Result<T, TransactionError>;
+
+/// Error Type for Transactions
+#[derive(Debug)]
+pub enum TransactionError {
+    /// Transaction was chosen to abort to break a cycle of transactions waiting on each other
+    Deadlock(TransactionId),
+    /// Transaction waited longer than its timeout for a lock
+    LockTimeout(TransactionId),
+    /// Transaction isn't open, or is already committing or aborting
+    UnknownTransaction(TransactionId),
+    /// File isn't registered with the transaction manager
+    UnknownFile(u64),
+    /// Write extends past the end of the page
+    OutOfBounds {
+        /// File written
+        file: u64,
+        /// Page written
+        page: PageId,
+        /// Offset of the write in the page
+        offset: usize,
+    },
+    /// Log record can't be read or doesn't fit the page it applies to
+    CorruptLog(Lsn),
+    /// Backup is missing or incomplete, or a backup or restore would overwrite another
+    InvalidBackup(String),
+    /// Files of a backup differ from its manifest
+    BackupMismatch(Vec<ManifestMismatch>),
+    /// Follower needs the log from `needed`, but the log it's shipped or applied from holds
+    /// nothing there, continuing at `available` instead
+    ReplicationGap {
+        /// Position the follower needs the log from
+        needed: Lsn,
+        /// Position of the log nearest to it that's available
+        available: Lsn,
+    },
+    /// Manager is a follower applying a shipped log, which can't begin transactions, take
+    /// checkpoints or ship its log until promoted
+    Following,
+    /// Manager isn't a follower, so has no shipped log to apply or promotion to make
+    NotFollowing,
+    /// Error of the underlying `FileSystem`
+    FileSystem(FileSystemError),
+}
+
+impl std::fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for TransactionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TransactionError::FileSystem(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<FileSystemError> for TransactionError {
+    fn from(err: FileSystemError) -> Self {
+        TransactionError::FileSystem(err)
+    }
+}
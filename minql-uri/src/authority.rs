@@ -23,13 +23,13 @@ use crate::{hostinfo::HostInfo, userinfo::UserInfo};
 /// Per [Wikipedia](https://en.wikipedia.org/wiki/Uniform_Resource_Identifier):
 /// > An optional authority component preceded by two slashes (//), comprising:
 /// > * An optional userinfo subcomponent followed by an at symbol (@), that may consist of a
-/// user-name and an optional password preceded by a colon (:). Use of the format username:password
-/// in the userinfo subcomponent is deprecated for security reasons. Applications should not render
-/// as clear text any data after the first colon (:) found within a userinfo subcomponent unless the
-/// data after the colon is the empty string (indicating no password).
+/// >   user-name and an optional password preceded by a colon (:). Use of the format username:password
+/// >   in the userinfo subcomponent is deprecated for security reasons. Applications should not render
+/// >   as clear text any data after the first colon (:) found within a userinfo subcomponent unless the
+/// >   data after the colon is the empty string (indicating no password).
 /// > * A host subcomponent, consisting of either a registered name (including but not limited to a
-/// hostname) or an IP address. IPv4 addresses must be in dot-decimal notation, and IPv6 addresses
-/// must be enclosed in brackets ([]).
+/// >   hostname) or an IP address. IPv4 addresses must be in dot-decimal notation, and IPv6 addresses
+/// >   must be enclosed in brackets ([]).
 /// > * An optional port subcomponent preceded by a colon (:), consisting of decimal digits.  
 ///
 /// ## ABNF Grammar
@@ -48,9 +48,9 @@ pub struct Authority<'str> {
     pub port: Option<u16>,
 }
 
-impl<'str> Authority<'str> {
+impl Authority<'_> {
     /// Convert Parsed Authority into a Builder
-    #[must_use]  
+    #[must_use]
     pub fn builder(&self) -> AuthorityBuilder {
         AuthorityBuilder {
             userinfo: self.userinfo.as_ref().map(UserInfo::builder),
@@ -60,7 +60,7 @@ impl<'str> Authority<'str> {
     }
 }
 
-impl<'str> std::fmt::Display for Authority<'str> {
+impl std::fmt::Display for Authority<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.raw)
     }
@@ -84,7 +84,7 @@ impl std::fmt::Display for AuthorityBuilder {
         }
         write!(f, "{}", self.hostinfo)?;
         if let Some(port) = &self.port {
-            write!(f, ":{}", port)?;
+            write!(f, ":{port}")?;
         }
         Ok(())
     }
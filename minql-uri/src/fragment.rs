@@ -35,13 +35,13 @@ pub struct Fragment<'str> {
     pub fragment: &'str str,
 }
 
-impl<'str> std::fmt::Display for Fragment<'str> {
+impl std::fmt::Display for Fragment<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.fragment)
     }
 }
 
-impl<'str> Fragment<'str> {
+impl Fragment<'_> {
     /// Get Pct Decoded Fragment
     ///
     /// # Panics
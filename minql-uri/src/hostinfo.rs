@@ -48,7 +48,7 @@ pub enum HostInfo<'str> {
     },
 }
 
-impl<'str> std::fmt::Display for HostInfo<'str> {
+impl std::fmt::Display for HostInfo<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             HostInfo::RegistryName { raw }
@@ -59,7 +59,7 @@ impl<'str> std::fmt::Display for HostInfo<'str> {
     }
 }
 
-impl<'str> HostInfo<'str> {
+impl HostInfo<'_> {
     /// Get Pct Decoded Raw `Query`.
     ///
     /// # Panics
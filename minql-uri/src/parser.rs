@@ -139,8 +139,8 @@ where
     E: ParseError<&'str str>,
 {
     alt((
-        map(uri, |uri| URIReference::Absolute(uri)),
-        map(relative_ref, |uri_ref| URIReference::Relative(uri_ref)),
+        map(uri, URIReference::Absolute),
+        map(relative_ref, URIReference::Relative),
     ))(input)
 }
 
@@ -205,7 +205,7 @@ where
         map(tag_no_case("HTTP"), |_| Scheme::HTTP),
         map(
             recognize(pair(alpha, many0(alt((alpha, digit, one_of("+-.")))))),
-            |str| Scheme::Other(str),
+            Scheme::Other,
         ),
     ))(input)
 }
@@ -314,7 +314,8 @@ where
     E: ParseError<&'str str>,
 {
     let (input, str) = digit1(input)?;
-    let val = u16::from_str_radix(str, 10)
+    let val = str
+        .parse::<u16>()
         .map_err(|_| nom::Err::Error(E::from_error_kind(input, ErrorKind::HexDigit)))?;
     Ok((input, val))
 }
@@ -432,7 +433,8 @@ where
     E: ParseError<&'str str>,
 {
     let (input, str) = digit1(input)?;
-    let val = u8::from_str_radix(str, 10)
+    let val = str
+        .parse::<u8>()
         .map_err(|_| nom::Err::Error(E::from_error_kind(input, ErrorKind::Digit)))?;
     Ok((input, val))
 }
@@ -609,7 +611,7 @@ fn query<'str, E>(input: &'str str) -> IResult<&'str str, Query<'str>, E>
 where
     E: ParseError<&'str str>,
 {
-    let (input, query_string) = recognize(alt((pchar, one_of("/?"))))(input)?;
+    let (input, query_string) = recognize(many1(alt((pchar, one_of("/?")))))(input)?;
     let (_, query_pairs) = separated_list0(
         one_of("&;"),
         pair(
@@ -808,6 +810,18 @@ mod tests {
         assert_eq!(failures, 0, "Failures Detected");
     }
 
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_query_parsing() {
+        let uri = URI::parse("https://example.com/thing?hi=bye&ho=no;flag#test").unwrap();
+        let query = uri.query.unwrap();
+        assert_eq!(query.raw, "hi=bye&ho=no;flag");
+        assert_eq!(
+            query.parameters,
+            [("hi", Some("bye")), ("ho", Some("no")), ("flag", None)]
+        );
+    }
+
     #[test]
     #[tracing_test::traced_test]
     fn test_path_parsing() {
@@ -88,34 +88,28 @@ pub enum Path<'str> {
     },
 }
 
-impl<'str> std::fmt::Display for Path<'str> {
+impl std::fmt::Display for Path<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Path::Empty => write!(f, ""),
-            Path::AbEmpty { raw, .. } => write!(f, "{raw}"),
-            Path::Absolute { raw, .. } => write!(f, "{raw}"),
-            Path::NoScheme { raw, .. } => write!(f, "{raw}"),
-            Path::Rootless { raw, .. } => write!(f, "{raw}"),
+            Path::AbEmpty { raw, .. }
+            | Path::Absolute { raw, .. }
+            | Path::NoScheme { raw, .. }
+            | Path::Rootless { raw, .. } => write!(f, "{raw}"),
         }
     }
 }
 
-impl<'str> Path<'str> {
+impl Path<'_> {
     /// Convert the parsed `Path` into a `PathBuilder`
     #[must_use]
     pub fn builder(&self) -> PathBuilder {
         match self {
             Path::Empty => PathBuilder::Empty,
-            Path::AbEmpty { segments, .. } => PathBuilder::Absolute {
-                segments: segments.iter().map(ToString::to_string).collect(),
-            },
-            Path::Absolute { segments, .. } => PathBuilder::Absolute {
-                segments: segments.iter().map(ToString::to_string).collect(),
-            },
-            Path::NoScheme { segments, .. } => PathBuilder::Absolute {
-                segments: segments.iter().map(ToString::to_string).collect(),
-            },
-            Path::Rootless { segments, .. } => PathBuilder::Absolute {
+            Path::AbEmpty { segments, .. }
+            | Path::Absolute { segments, .. }
+            | Path::NoScheme { segments, .. }
+            | Path::Rootless { segments, .. } => PathBuilder::Absolute {
                 segments: segments.iter().map(ToString::to_string).collect(),
             },
         }
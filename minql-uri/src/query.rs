@@ -37,7 +37,7 @@ pub struct Query<'str> {
     pub parameters: Vec<(&'str str, Option<&'str str>)>,
 }
 
-impl<'str> Query<'str> {
+impl Query<'_> {
     /// Get Pct Decoded Raw `Query`.
     ///
     /// # Panics
@@ -70,7 +70,7 @@ impl<'str> Query<'str> {
     }
 }
 
-impl<'str> std::fmt::Display for Query<'str> {
+impl std::fmt::Display for Query<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.raw)
     }
@@ -25,7 +25,7 @@ pub enum Scheme<'str> {
     Other(&'str str),
 }
 
-impl<'str> Scheme<'str> {
+impl Scheme<'_> {
     /// Convert a parsed `Scheme` into a `SchemeBuilder`
     #[must_use]
     pub fn builder(&self) -> SchemeBuilder {
@@ -37,7 +37,7 @@ impl<'str> Scheme<'str> {
     }
 }
 
-impl<'str> std::fmt::Display for Scheme<'str> {
+impl std::fmt::Display for Scheme<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Scheme::HTTP => write!(f, "http"),
@@ -47,7 +47,7 @@ impl<'str> std::fmt::Display for Scheme<'str> {
     }
 }
 
-impl<'str> AsRef<str> for Scheme<'str> {
+impl AsRef<str> for Scheme<'_> {
     fn as_ref(&self) -> &str {
         match self {
             Scheme::HTTP => "http",
@@ -36,8 +36,9 @@ pub enum URIReference<'str> {
     Relative(URIRelativeReference<'str>),
 }
 
-impl<'str> URIReference<'str> {
+impl URIReference<'_> {
     /// Convert Reference to a Builder
+    #[must_use]
     pub fn builder(&self) -> URIReferenceBuilder {
         match self {
             URIReference::Absolute(uri) => URIReferenceBuilder::Absolute(uri.builder()),
@@ -46,7 +47,7 @@ impl<'str> URIReference<'str> {
     }
 }
 
-impl<'str> std::fmt::Display for URIReference<'str> {
+impl std::fmt::Display for URIReference<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             URIReference::Absolute(uri) => std::fmt::Display::fmt(uri, f),
@@ -98,7 +99,7 @@ pub struct URI<'str> {
     pub fragment: Option<Fragment<'str>>,
 }
 
-impl<'str> URI<'str> {
+impl URI<'_> {
     /// Convert a parsed `URI` into a `URIBuilder`
     #[must_use]
     pub fn builder(&self) -> URIBuilder {
@@ -112,18 +113,18 @@ impl<'str> URI<'str> {
     }
 }
 
-impl<'str> std::fmt::Display for URI<'str> {
+impl std::fmt::Display for URI<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}:", self.scheme)?;
         if let Some(authority) = self.authority.as_ref() {
-            write!(f, "{}", authority)?;
+            write!(f, "{authority}")?;
         }
         write!(f, "{}", self.path)?;
         if let Some(query) = self.query.as_ref() {
-            write!(f, "?{}", query)?;
+            write!(f, "?{query}")?;
         }
         if let Some(fragment) = self.fragment.as_ref() {
-            write!(f, "#{}", fragment)?;
+            write!(f, "#{fragment}")?;
         }
         Ok(())
     }
@@ -148,14 +149,14 @@ impl std::fmt::Display for URIBuilder {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}:", self.scheme)?;
         if let Some(authority) = self.authority.as_ref() {
-            write!(f, "{}", authority)?;
+            write!(f, "{authority}")?;
         }
         write!(f, "{}", self.path)?;
         if let Some(query) = self.query.as_ref() {
-            write!(f, "?{}", query)?;
+            write!(f, "?{query}")?;
         }
         if let Some(fragment) = self.fragment.as_ref() {
-            write!(f, "#{}", fragment)?;
+            write!(f, "#{fragment}")?;
         }
         Ok(())
     }
@@ -184,7 +185,7 @@ pub struct URIRelativeReference<'str> {
     pub fragment: Option<Fragment<'str>>,
 }
 
-impl<'str> URIRelativeReference<'str> {
+impl URIRelativeReference<'_> {
     /// Convert a parsed `URIRelativeReference` into a `URIRelativeReferenceBuilder`
     #[must_use]
     pub fn builder(&self) -> URIRelativeReferenceBuilder {
@@ -197,17 +198,17 @@ impl<'str> URIRelativeReference<'str> {
     }
 }
 
-impl<'str> std::fmt::Display for URIRelativeReference<'str> {
+impl std::fmt::Display for URIRelativeReference<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(authority) = self.authority.as_ref() {
-            write!(f, "{}", authority)?;
+            write!(f, "{authority}")?;
         }
         write!(f, "{}", self.path)?;
         if let Some(query) = self.query.as_ref() {
-            write!(f, "?{}", query)?;
+            write!(f, "?{query}")?;
         }
         if let Some(fragment) = self.fragment.as_ref() {
-            write!(f, "#{}", fragment)?;
+            write!(f, "#{fragment}")?;
         }
         Ok(())
     }
@@ -229,14 +230,14 @@ pub struct URIRelativeReferenceBuilder {
 impl std::fmt::Display for URIRelativeReferenceBuilder {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(authority) = self.authority.as_ref() {
-            write!(f, "{}", authority)?;
+            write!(f, "{authority}")?;
         }
         write!(f, "{}", self.path)?;
         if let Some(query) = self.query.as_ref() {
-            write!(f, "?{}", query)?;
+            write!(f, "?{query}")?;
         }
         if let Some(fragment) = self.fragment.as_ref() {
-            write!(f, "#{}", fragment)?;
+            write!(f, "#{fragment}")?;
         }
         Ok(())
     }
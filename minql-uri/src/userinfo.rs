@@ -36,7 +36,7 @@ pub enum UserInfo<'str> {
     },
 }
 
-impl<'str> UserInfo<'str> {
+impl UserInfo<'_> {
     /// Get Pct Decoded Raw `UserInfo`.
     ///
     /// # Panics
@@ -89,7 +89,7 @@ impl<'str> UserInfo<'str> {
     }
 }
 
-impl<'str> std::fmt::Display for UserInfo<'str> {
+impl std::fmt::Display for UserInfo<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             UserInfo::Unparsed { raw } | UserInfo::Parsed { raw, .. } => write!(f, "{raw}"),
@@ -37,7 +37,7 @@ pub(crate) fn pct_encode(f: &mut std::fmt::Formatter<'_>, value: &str) -> std::f
 ///
 /// Returns an `Err` if an invalid percent encoding sequence is found.
 pub(crate) fn pct_decode(s: &str) -> Result<String, std::num::ParseIntError> {
-    let mut result = String::with_capacity(s.len());
+    let mut result = Vec::with_capacity(s.len());
     let mut chars = s.chars();
 
     while let Some(ch) = chars.next() {
@@ -47,16 +47,17 @@ pub(crate) fn pct_decode(s: &str) -> Result<String, std::num::ParseIntError> {
                 .and_then(|c1| chars.next().map(|c2| format!("{c1}{c2}")))
                 .unwrap_or_default();
             if hex.len() == 2 {
-                let byte = u8::from_str_radix(&hex, 16)?;
-                result.push(byte as char);
+                result.push(u8::from_str_radix(&hex, 16)?);
             } else {
-                result.push('%');
-                result.push_str(&hex);
+                result.push(b'%');
+                result.extend_from_slice(hex.as_bytes());
             }
         } else {
-            result.push(ch);
+            result.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
         }
     }
 
-    Ok(result)
+    // Encoded bytes are UTF-8, so multi-byte characters span several escapes.
+    Ok(String::from_utf8(result)
+        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned()))
 }
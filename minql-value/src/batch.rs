@@ -0,0 +1,107 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::Value;
+
+/// Rows stored by column, evaluated a column at a time by
+/// [`ScalarExpr::eval_batch`](crate::ScalarExpr::eval_batch).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Batch {
+    columns: Vec<Vec<Value>>,
+    len: usize,
+}
+
+impl Batch {
+    /// Create a batch of `len` rows without columns.
+    #[must_use]
+    pub fn new(len: usize) -> Batch {
+        Batch {
+            columns: Vec::new(),
+            len,
+        }
+    }
+
+    /// Add a column holding a value for every row.
+    #[must_use]
+    pub fn with_column(mut self, column: Vec<Value>) -> Batch {
+        assert_eq!(column.len(), self.len, "Column Length");
+        self.columns.push(column);
+        self
+    }
+
+    /// Create a batch of rows of equal width.
+    #[must_use]
+    pub fn from_rows(rows: &[Vec<Value>]) -> Batch {
+        let width = rows.first().map_or(0, Vec::len);
+        let mut columns = vec![Vec::with_capacity(rows.len()); width];
+        for row in rows {
+            assert_eq!(row.len(), width, "Row Width");
+            for (column, value) in columns.iter_mut().zip(row) {
+                column.push(value.clone());
+            }
+        }
+        Batch {
+            columns,
+            len: rows.len(),
+        }
+    }
+
+    /// Number of rows.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the batch has no rows.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of columns.
+    #[must_use]
+    pub fn width(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Values of a column.
+    #[must_use]
+    pub fn column(&self, index: usize) -> &[Value] {
+        &self.columns[index]
+    }
+
+    /// Values of a row.
+    #[must_use]
+    pub fn row(&self, index: usize) -> Vec<Value> {
+        self.columns
+            .iter()
+            .map(|column| column[index].clone())
+            .collect()
+    }
+
+    /// Batch of the rows at `indexes`, in that order.
+    #[must_use]
+    pub fn take(&self, indexes: &[usize]) -> Batch {
+        Batch {
+            columns: self
+                .columns
+                .iter()
+                .map(|column| indexes.iter().map(|index| column[*index].clone()).collect())
+                .collect(),
+            len: indexes.len(),
+        }
+    }
+}
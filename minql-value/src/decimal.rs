@@ -0,0 +1,450 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::cmp::Ordering;
+
+/// Digits after the point a quotient keeps, unless its operands have more.
+const DIVISION_SCALE: u32 = 16;
+
+/// Exact decimal number, a mantissa of at most [`MAX_PRECISION`](Decimal::MAX_PRECISION) digits scaled by a power of
+/// ten.
+///
+/// Decimals of equal value compare and hash equal regardless of scale, so `1.50` equals `1.5`.
+#[derive(Clone, Copy, Debug)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    /// Most digits a decimal holds, and its largest scale.
+    pub const MAX_PRECISION: u32 = 38;
+
+    /// Zero
+    pub const ZERO: Decimal = Decimal {
+        mantissa: 0,
+        scale: 0,
+    };
+
+    /// Create a decimal of `mantissa / 10^scale`, if it has no more than [`MAX_PRECISION`](Decimal::MAX_PRECISION)
+    /// digits.
+    #[must_use]
+    pub fn new(mantissa: i128, scale: u32) -> Option<Decimal> {
+        if scale > Decimal::MAX_PRECISION
+            || mantissa.unsigned_abs() >= 10u128.pow(Decimal::MAX_PRECISION)
+        {
+            None
+        } else {
+            Some(Decimal { mantissa, scale })
+        }
+    }
+
+    /// Unscaled digits.
+    #[must_use]
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    /// Digits after the point.
+    #[must_use]
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Digits of the mantissa, at least one.
+    #[must_use]
+    pub fn precision(&self) -> u32 {
+        self.mantissa.unsigned_abs().checked_ilog10().unwrap_or(0) + 1
+    }
+
+    /// Whether the decimal is zero.
+    #[must_use]
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    /// Parse decimal notation with an optional sign, fraction and exponent, as in `-1.5e3`.
+    ///
+    /// Fractions beyond [`MAX_PRECISION`](Decimal::MAX_PRECISION) digits after the point are rounded.
+    #[must_use]
+    pub fn parse(text: &str) -> Option<Decimal> {
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (number, exponent) = match text.find(['e', 'E']) {
+            Some(index) => (&text[..index], text[index + 1..].parse::<i32>().ok()?),
+            None => (text, 0),
+        };
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        let mut mantissa = 0i128;
+        for digit in whole.chars().chain(fraction.chars()) {
+            let digit = i128::from(digit.to_digit(10)?);
+            mantissa = mantissa.checked_mul(10)?.checked_add(digit)?;
+        }
+        if negative {
+            mantissa = -mantissa;
+        }
+        let scale = i64::try_from(fraction.len()).ok()? - i64::from(exponent);
+        if scale < 0 {
+            let factor = 10i128.checked_pow(u32::try_from(-scale).ok()?)?;
+            Decimal::new(mantissa.checked_mul(factor)?, 0)
+        } else {
+            let scale = u32::try_from(scale).ok()?;
+            if scale > Decimal::MAX_PRECISION {
+                let excess = 10i128.checked_pow(scale - Decimal::MAX_PRECISION)?;
+                Decimal::new(divide_rounded(mantissa, excess), Decimal::MAX_PRECISION)
+            } else {
+                Decimal::fit(mantissa, scale)
+            }
+        }
+    }
+
+    /// Convert a finite float to the shortest decimal that converts back to it.
+    #[must_use]
+    pub fn from_f64(value: f64) -> Option<Decimal> {
+        if value.is_finite() {
+            Decimal::parse(&value.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Nearest float.
+    #[must_use]
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Round to the nearest integer, halves away from zero, if it fits.
+    #[must_use]
+    pub fn to_i64(&self) -> Option<i64> {
+        let rounded = self.round(0)?;
+        i64::try_from(rounded.mantissa).ok()
+    }
+
+    /// Round to `scale` digits after the point, halves away from zero, or pad to it with zeros.
+    #[must_use]
+    pub fn round(&self, scale: u32) -> Option<Decimal> {
+        match scale.cmp(&self.scale) {
+            Ordering::Equal => Some(*self),
+            Ordering::Greater => {
+                let factor = 10i128.checked_pow(scale - self.scale)?;
+                Decimal::new(self.mantissa.checked_mul(factor)?, scale)
+            }
+            Ordering::Less => {
+                let factor = 10i128.checked_pow(self.scale - scale)?;
+                Decimal::new(divide_rounded(self.mantissa, factor), scale)
+            }
+        }
+    }
+
+    /// Integer part, rounded toward zero.
+    #[must_use]
+    pub fn trunc(&self) -> Decimal {
+        Decimal {
+            mantissa: self.mantissa / 10i128.pow(self.scale),
+            scale: 0,
+        }
+    }
+
+    /// Largest integer no greater than the decimal.
+    #[must_use]
+    pub fn floor(&self) -> Decimal {
+        let trunc = self.trunc();
+        if self.mantissa < 0 && trunc != *self {
+            Decimal {
+                mantissa: trunc.mantissa - 1,
+                scale: 0,
+            }
+        } else {
+            trunc
+        }
+    }
+
+    /// Smallest integer no less than the decimal.
+    #[must_use]
+    pub fn ceil(&self) -> Decimal {
+        let trunc = self.trunc();
+        if self.mantissa > 0 && trunc != *self {
+            Decimal {
+                mantissa: trunc.mantissa + 1,
+                scale: 0,
+            }
+        } else {
+            trunc
+        }
+    }
+
+    /// Same value with trailing zeros after the point removed.
+    #[must_use]
+    pub fn normalize(&self) -> Decimal {
+        let mut decimal = *self;
+        while decimal.scale > 0 && decimal.mantissa % 10 == 0 {
+            decimal.mantissa /= 10;
+            decimal.scale -= 1;
+        }
+        decimal
+    }
+
+    /// Negation.
+    #[must_use]
+    pub fn neg(&self) -> Decimal {
+        Decimal {
+            mantissa: -self.mantissa,
+            scale: self.scale,
+        }
+    }
+
+    /// Absolute value.
+    #[must_use]
+    pub fn abs(&self) -> Decimal {
+        Decimal {
+            mantissa: self.mantissa.abs(),
+            scale: self.scale,
+        }
+    }
+
+    /// Sum, if it fits.
+    #[must_use]
+    pub fn checked_add(&self, other: &Decimal) -> Option<Decimal> {
+        let (left, right, scale) = Decimal::align(self, other)?;
+        Decimal::new(left.checked_add(right)?, scale)
+    }
+
+    /// Difference, if it fits.
+    #[must_use]
+    pub fn checked_sub(&self, other: &Decimal) -> Option<Decimal> {
+        let (left, right, scale) = Decimal::align(self, other)?;
+        Decimal::new(left.checked_sub(right)?, scale)
+    }
+
+    /// Product, rounded to [`MAX_PRECISION`](Decimal::MAX_PRECISION) digits, if it fits.
+    #[must_use]
+    pub fn checked_mul(&self, other: &Decimal) -> Option<Decimal> {
+        let mantissa = self.mantissa.checked_mul(other.mantissa)?;
+        let scale = self.scale + other.scale;
+        if scale > Decimal::MAX_PRECISION {
+            let excess = 10i128.checked_pow(scale - Decimal::MAX_PRECISION)?;
+            Decimal::fit(divide_rounded(mantissa, excess), Decimal::MAX_PRECISION)
+        } else {
+            Decimal::fit(mantissa, scale)
+        }
+    }
+
+    /// Quotient, rounded to at least 16 digits after the point where it fits, or `None` if it
+    /// doesn't fit or `other` is zero.
+    #[must_use]
+    pub fn checked_div(&self, other: &Decimal) -> Option<Decimal> {
+        if other.is_zero() {
+            return None;
+        }
+        let minimum = self.scale.max(other.scale);
+        for scale in (minimum..=minimum.max(DIVISION_SCALE)).rev() {
+            let shift = scale + other.scale - self.scale;
+            let numerator = 10i128
+                .checked_pow(shift)
+                .and_then(|factor| self.mantissa.checked_mul(factor));
+            if let Some(numerator) = numerator {
+                return Decimal::fit(divide_rounded(numerator, other.mantissa), scale);
+            }
+        }
+        None
+    }
+
+    /// Remainder of truncated division, with the sign of `self`, or `None` if `other` is zero.
+    #[must_use]
+    pub fn checked_rem(&self, other: &Decimal) -> Option<Decimal> {
+        let (left, right, scale) = Decimal::align(self, other)?;
+        if right == 0 {
+            return None;
+        }
+        Decimal::new(left % right, scale)
+    }
+
+    /// Mantissas of both decimals at their common scale.
+    fn align(left: &Decimal, right: &Decimal) -> Option<(i128, i128, u32)> {
+        let scale = left.scale.max(right.scale);
+        let left_factor = 10i128.checked_pow(scale - left.scale)?;
+        let right_factor = 10i128.checked_pow(scale - right.scale)?;
+        Some((
+            left.mantissa.checked_mul(left_factor)?,
+            right.mantissa.checked_mul(right_factor)?,
+            scale,
+        ))
+    }
+
+    /// Create a decimal, giving up digits after the point until the mantissa fits.
+    fn fit(mut mantissa: i128, mut scale: u32) -> Option<Decimal> {
+        while scale > 0 && mantissa.unsigned_abs() >= 10u128.pow(Decimal::MAX_PRECISION) {
+            mantissa = divide_rounded(mantissa, 10);
+            scale -= 1;
+        }
+        Decimal::new(mantissa, scale)
+    }
+}
+
+/// Divide, rounding halves away from zero.
+fn divide_rounded(numerator: i128, denominator: i128) -> i128 {
+    let quotient = numerator / denominator;
+    let remainder = numerator % denominator;
+    if remainder.unsigned_abs() * 2 >= denominator.unsigned_abs() {
+        if (numerator < 0) == (denominator < 0) {
+            quotient + 1
+        } else {
+            quotient - 1
+        }
+    } else {
+        quotient
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Self {
+        Decimal {
+            mantissa: i128::from(value),
+            scale: 0,
+        }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Integer parts can't overflow, and fractions are below 10^38 once aligned
+        let left_factor = 10i128.pow(self.scale);
+        let right_factor = 10i128.pow(other.scale);
+        let integers = (self.mantissa / left_factor).cmp(&(other.mantissa / right_factor));
+        integers.then_with(|| {
+            let scale = self.scale.max(other.scale);
+            let left = (self.mantissa % left_factor) * 10i128.pow(scale - self.scale);
+            let right = (other.mantissa % right_factor) * 10i128.pow(scale - other.scale);
+            left.cmp(&right)
+        })
+    }
+}
+
+impl std::hash::Hash for Decimal {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        let normalized = self.normalize();
+        normalized.mantissa.hash(state);
+        normalized.scale.hash(state);
+    }
+}
+
+impl std::fmt::Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            write!(f, "{sign}{digits}")
+        } else if digits.len() > scale {
+            let (whole, fraction) = digits.split_at(digits.len() - scale);
+            write!(f, "{sign}{whole}.{fraction}")
+        } else {
+            write!(f, "{sign}0.{digits:0>scale$}")
+        }
+    }
+}
+
+impl std::str::FromStr for Decimal {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Decimal::parse(text).ok_or(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Decimal;
+
+    fn decimal(text: &str) -> Decimal {
+        Decimal::parse(text).unwrap()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_decimal() {
+        assert_eq!(decimal("1.50").to_string(), "1.50");
+        assert_eq!(decimal("-0.05").to_string(), "-0.05");
+        assert_eq!(decimal("1.5e3").to_string(), "1500");
+        assert_eq!(decimal("25e-3").to_string(), "0.025");
+        assert_eq!(decimal(".5").to_string(), "0.5");
+        assert!(Decimal::parse("").is_none());
+        assert!(Decimal::parse("1.2.3").is_none());
+        assert!(Decimal::parse("1e").is_none());
+        assert!(Decimal::parse(&"9".repeat(39)).is_none());
+
+        assert_eq!(decimal("1.50"), decimal("1.5"));
+        assert!(decimal("-0.5") < decimal("0.25"));
+        assert!(decimal("-1.5") < decimal("-1.25"));
+        assert!(decimal("2") > decimal("1.99"));
+
+        assert_eq!(
+            decimal("1.25").checked_add(&decimal("2.5")),
+            Some(decimal("3.75"))
+        );
+        assert_eq!(
+            decimal("1.25").checked_sub(&decimal("2.5")),
+            Some(decimal("-1.25"))
+        );
+        assert_eq!(
+            decimal("1.5").checked_mul(&decimal("-0.2")),
+            Some(decimal("-0.3"))
+        );
+        assert_eq!(
+            decimal("1").checked_div(&decimal("3")).unwrap().to_string(),
+            "0.3333333333333333"
+        );
+        assert_eq!(
+            decimal("2").checked_div(&decimal("3")).unwrap().to_string(),
+            "0.6666666666666667"
+        );
+        assert!(decimal("1").checked_div(&Decimal::ZERO).is_none());
+        assert_eq!(
+            decimal("-7.5").checked_rem(&decimal("2")),
+            Some(decimal("-1.5"))
+        );
+
+        assert_eq!(decimal("2.345").round(2), Some(decimal("2.35")));
+        assert_eq!(decimal("-2.345").round(2), Some(decimal("-2.35")));
+        assert_eq!(decimal("2.5").round(4).unwrap().to_string(), "2.5000");
+        assert_eq!(decimal("-2.5").floor(), decimal("-3"));
+        assert_eq!(decimal("-2.5").ceil(), decimal("-2"));
+        assert_eq!(decimal("2.5").to_i64(), Some(3));
+        assert_eq!(decimal("123.4500").precision(), 7);
+        assert_eq!(decimal("0.1").to_f64().to_bits(), 0.1f64.to_bits());
+        assert_eq!(Decimal::from_f64(0.1), Some(decimal("0.1")));
+    }
+}
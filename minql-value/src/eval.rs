@@ -0,0 +1,1190 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{Batch, FunctionRegistry, ScalarFunction, ScalarUdf, Value, ValueError, ValueResult};
+use minql_lang::ast::{BinaryOperator, DataType, Expr, Function, Ident, UnaryOperator};
+
+/// Aggregate functions, which bind only where rows are grouped.
+pub(crate) const AGGREGATES: &[&str] = &["avg", "count", "max", "min", "sum"];
+
+/// Names of the columns of the rows an expression is evaluated over, in order.
+///
+/// Names are matched as given, so should already be normalized, see [`Ident::normalized`].
+#[derive(Clone, Debug, Default)]
+pub struct Scope {
+    columns: Vec<(Option<String>, String)>,
+}
+
+impl Scope {
+    /// Create a scope without columns.
+    #[must_use]
+    pub fn new() -> Scope {
+        Scope::default()
+    }
+
+    /// Add a column, optionally qualified by the name of its table.
+    #[must_use]
+    pub fn with_column(mut self, table: Option<&str>, name: &str) -> Scope {
+        self.push(table, name);
+        self
+    }
+
+    /// Add a column, optionally qualified by the name of its table.
+    pub fn push(&mut self, table: Option<&str>, name: &str) {
+        self.columns
+            .push((table.map(str::to_string), name.to_string()));
+    }
+
+    /// Number of columns.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Whether the scope has no columns.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Position of the column a name refers to, qualified by table or not.
+    pub fn resolve(&self, name: &[Ident]) -> ValueResult<usize> {
+        let text = name
+            .iter()
+            .map(|ident| ident.value.as_str())
+            .collect::<Vec<_>>()
+            .join(".");
+        let (table, column) = match name {
+            [] => return Err(ValueError::UnknownColumn(text)),
+            [column] => (None, column.normalized()),
+            [.., table, column] => (Some(table.normalized()), column.normalized()),
+        };
+        let mut found = self
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, (qualifier, name))| {
+                *name == column
+                    && table
+                        .as_ref()
+                        .is_none_or(|table| qualifier.as_ref() == Some(table))
+            });
+        match (found.next(), found.next()) {
+            (Some((index, _)), None) => Ok(index),
+            (None, _) => Err(ValueError::UnknownColumn(text)),
+            (Some(_), Some(_)) => Err(ValueError::AmbiguousColumn(text)),
+        }
+    }
+}
+
+/// Scalar expression with its names resolved, ready for evaluation.
+///
+/// Evaluates over a single row with [`ScalarExpr::eval`], or over a [`Batch`] of rows a
+/// column at a time with [`ScalarExpr::eval_batch`]. Both skip operands whose value can't
+/// matter, so `x <> 0 AND 1 / x > 1` and the branches of `CASE` only evaluate where needed.
+///
+/// ```rust
+/// use minql_lang::Parser;
+/// use minql_value::{Batch, ScalarExpr, Scope, Value};
+///
+/// let scope = Scope::new().with_column(Some("t"), "a");
+/// let expr = Parser::parse_expr("CASE WHEN t.a > 1 THEN a * 10 ELSE -a END").unwrap();
+/// let expr = ScalarExpr::bind(&expr, &scope).unwrap();
+/// assert_eq!(expr.eval(&[Value::from(2)], &[]).unwrap(), Value::from(20));
+///
+/// let batch = Batch::new(3).with_column(vec![Value::from(1), Value::Null, Value::from(3)]);
+/// assert_eq!(
+///     expr.eval_batch(&batch, &[]).unwrap(),
+///     vec![Value::from(-1), Value::Null, Value::from(30)]
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScalarExpr {
+    /// Constant value
+    Literal(Value),
+    /// Value of the column at a position of the row
+    Column(usize),
+    /// Value of the parameter at a position, counting from 0
+    Parameter(usize),
+    /// Prefix operator applied to an operand
+    Unary {
+        /// Operator
+        op: UnaryOperator,
+        /// Operand
+        expr: Box<ScalarExpr>,
+    },
+    /// Infix operator applied to two operands
+    Binary {
+        /// Left operand
+        left: Box<ScalarExpr>,
+        /// Operator
+        op: BinaryOperator,
+        /// Right operand
+        right: Box<ScalarExpr>,
+    },
+    /// `expr IS [NOT] NULL`
+    IsNull {
+        /// Operand
+        expr: Box<ScalarExpr>,
+        /// Whether `NOT` was given
+        negated: bool,
+    },
+    /// `expr [NOT] IN (list)`
+    InList {
+        /// Operand
+        expr: Box<ScalarExpr>,
+        /// Values compared against
+        list: Vec<ScalarExpr>,
+        /// Whether `NOT` was given
+        negated: bool,
+    },
+    /// `expr [NOT] LIKE pattern [ESCAPE escape]`
+    Like {
+        /// Operand
+        expr: Box<ScalarExpr>,
+        /// Pattern, where `%` matches any text and `_` any character
+        pattern: Box<ScalarExpr>,
+        /// Character escaping `%` and `_` in the pattern
+        escape: Option<Box<ScalarExpr>>,
+        /// Whether `NOT` was given
+        negated: bool,
+    },
+    /// `CASE [operand] WHEN .. THEN .. [ELSE ..] END`
+    Case {
+        /// Value compared with each condition, which are otherwise boolean
+        operand: Option<Box<ScalarExpr>>,
+        /// Conditions and their results, in order
+        branches: Vec<(ScalarExpr, ScalarExpr)>,
+        /// Result when no condition matches
+        else_result: Option<Box<ScalarExpr>>,
+    },
+    /// Conversion to a type
+    Cast {
+        /// Operand
+        expr: Box<ScalarExpr>,
+        /// Type converted to
+        data_type: DataType,
+    },
+    /// Call of a built-in function
+    Function {
+        /// Function called
+        function: ScalarFunction,
+        /// Arguments
+        args: Vec<ScalarExpr>,
+    },
+    /// Call of a user-defined function
+    Udf {
+        /// Function called
+        function: ScalarUdf,
+        /// Arguments
+        args: Vec<ScalarExpr>,
+    },
+}
+
+impl ScalarExpr {
+    /// Resolve the names of an expression against a scope.
+    pub fn bind(expr: &Expr, scope: &Scope) -> ValueResult<ScalarExpr> {
+        Binder::new(scope).bind(expr)
+    }
+
+    /// Evaluate over a row of values in the order of the scope.
+    pub fn eval(&self, row: &[Value], params: &[Value]) -> ValueResult<Value> {
+        let eval = |expr: &ScalarExpr| expr.eval(row, params);
+        match self {
+            ScalarExpr::Literal(value) => Ok(value.clone()),
+            ScalarExpr::Column(index) => Ok(row[*index].clone()),
+            ScalarExpr::Parameter(index) => parameter(params, *index),
+            ScalarExpr::Unary { op, expr } => eval(expr)?.unary(*op),
+            ScalarExpr::Binary { left, op, right } => {
+                let left = eval(left)?;
+                match short_circuit(*op, &left)? {
+                    Some(result) => Ok(result),
+                    None => left.binary(*op, &eval(right)?),
+                }
+            }
+            ScalarExpr::IsNull { expr, negated } => {
+                Ok(Value::Boolean(eval(expr)?.is_null() != *negated))
+            }
+            ScalarExpr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let list = list.iter().map(eval).collect::<ValueResult<Vec<_>>>()?;
+                in_list(&eval(expr)?, list.iter(), *negated)
+            }
+            ScalarExpr::Like {
+                expr,
+                pattern,
+                escape,
+                negated,
+            } => {
+                let escape = escape.as_deref().map(eval).transpose()?;
+                like(&eval(expr)?, &eval(pattern)?, escape.as_ref(), *negated)
+            }
+            ScalarExpr::Case {
+                operand,
+                branches,
+                else_result,
+            } => {
+                let operand = operand.as_deref().map(eval).transpose()?;
+                for (condition, result) in branches {
+                    if case_matches(operand.as_ref(), &eval(condition)?)? {
+                        return eval(result);
+                    }
+                }
+                else_result.as_deref().map_or(Ok(Value::Null), eval)
+            }
+            ScalarExpr::Cast { expr, data_type } => eval(expr)?.cast(*data_type),
+            ScalarExpr::Function { function, args } => {
+                let args = args.iter().map(eval).collect::<ValueResult<Vec<_>>>()?;
+                function.call(&args)
+            }
+            ScalarExpr::Udf { function, args } => {
+                let args = args.iter().map(eval).collect::<ValueResult<Vec<_>>>()?;
+                function.call(&args)
+            }
+        }
+    }
+
+    /// Evaluate over every row of a batch, returning a value per row.
+    pub fn eval_batch(&self, batch: &Batch, params: &[Value]) -> ValueResult<Vec<Value>> {
+        let len = batch.len();
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let eval = |expr: &ScalarExpr| expr.eval_batch(batch, params);
+        match self {
+            ScalarExpr::Literal(value) => Ok(vec![value.clone(); len]),
+            ScalarExpr::Column(index) => Ok(batch.column(*index).to_vec()),
+            ScalarExpr::Parameter(index) => Ok(vec![parameter(params, *index)?; len]),
+            ScalarExpr::Unary { op, expr } => {
+                eval(expr)?.iter().map(|value| value.unary(*op)).collect()
+            }
+            ScalarExpr::Binary { left, op, right } => binary_batch(left, *op, right, batch, params),
+            ScalarExpr::IsNull { expr, negated } => Ok(eval(expr)?
+                .iter()
+                .map(|value| Value::Boolean(value.is_null() != *negated))
+                .collect()),
+            ScalarExpr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let list = list.iter().map(eval).collect::<ValueResult<Vec<_>>>()?;
+                eval(expr)?
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| {
+                        in_list(value, list.iter().map(|column| &column[index]), *negated)
+                    })
+                    .collect()
+            }
+            ScalarExpr::Like {
+                expr,
+                pattern,
+                escape,
+                negated,
+            } => {
+                let patterns = eval(pattern)?;
+                let escapes = escape.as_deref().map(eval).transpose()?;
+                eval(expr)?
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| {
+                        let escape = escapes.as_ref().map(|escapes| &escapes[index]);
+                        like(value, &patterns[index], escape, *negated)
+                    })
+                    .collect()
+            }
+            ScalarExpr::Case {
+                operand,
+                branches,
+                else_result,
+            } => case_batch(
+                operand.as_deref(),
+                branches,
+                else_result.as_deref(),
+                batch,
+                params,
+            ),
+            ScalarExpr::Cast { expr, data_type } => eval(expr)?
+                .iter()
+                .map(|value| value.cast(*data_type))
+                .collect(),
+            ScalarExpr::Function { function, args } => {
+                let args = args.iter().map(eval).collect::<ValueResult<Vec<_>>>()?;
+                (0..len)
+                    .map(|index| {
+                        let row: Vec<Value> =
+                            args.iter().map(|column| column[index].clone()).collect();
+                        function.call(&row)
+                    })
+                    .collect()
+            }
+            ScalarExpr::Udf { function, args } => {
+                let args = args.iter().map(eval).collect::<ValueResult<Vec<_>>>()?;
+                function.call_batch(args, len)
+            }
+        }
+    }
+
+    /// Positions of the columns the expression reads, in order and without repeats.
+    #[must_use]
+    pub fn columns(&self) -> Vec<usize> {
+        let mut columns = Vec::new();
+        self.visit(&mut |expr| {
+            if let ScalarExpr::Column(index) = expr {
+                columns.push(*index);
+            }
+        });
+        columns.sort_unstable();
+        columns.dedup();
+        columns
+    }
+
+    /// Copy of the expression reading each column from the position `map` gives for it, to
+    /// evaluate over rows of another layout.
+    #[must_use]
+    pub fn map_columns(&self, map: &impl Fn(usize) -> usize) -> ScalarExpr {
+        let boxed = |expr: &ScalarExpr| Box::new(expr.map_columns(map));
+        match self {
+            ScalarExpr::Literal(_) | ScalarExpr::Parameter(_) => self.clone(),
+            ScalarExpr::Column(index) => ScalarExpr::Column(map(*index)),
+            ScalarExpr::Unary { op, expr } => ScalarExpr::Unary {
+                op: *op,
+                expr: boxed(expr),
+            },
+            ScalarExpr::Binary { left, op, right } => ScalarExpr::Binary {
+                left: boxed(left),
+                op: *op,
+                right: boxed(right),
+            },
+            ScalarExpr::IsNull { expr, negated } => ScalarExpr::IsNull {
+                expr: boxed(expr),
+                negated: *negated,
+            },
+            ScalarExpr::InList {
+                expr,
+                list,
+                negated,
+            } => ScalarExpr::InList {
+                expr: boxed(expr),
+                list: list.iter().map(|expr| expr.map_columns(map)).collect(),
+                negated: *negated,
+            },
+            ScalarExpr::Like {
+                expr,
+                pattern,
+                escape,
+                negated,
+            } => ScalarExpr::Like {
+                expr: boxed(expr),
+                pattern: boxed(pattern),
+                escape: escape.as_deref().map(boxed),
+                negated: *negated,
+            },
+            ScalarExpr::Case {
+                operand,
+                branches,
+                else_result,
+            } => ScalarExpr::Case {
+                operand: operand.as_deref().map(boxed),
+                branches: branches
+                    .iter()
+                    .map(|(condition, result)| {
+                        (condition.map_columns(map), result.map_columns(map))
+                    })
+                    .collect(),
+                else_result: else_result.as_deref().map(boxed),
+            },
+            ScalarExpr::Cast { expr, data_type } => ScalarExpr::Cast {
+                expr: boxed(expr),
+                data_type: *data_type,
+            },
+            ScalarExpr::Function { function, args } => ScalarExpr::Function {
+                function: *function,
+                args: args.iter().map(|expr| expr.map_columns(map)).collect(),
+            },
+            ScalarExpr::Udf { function, args } => ScalarExpr::Udf {
+                function: function.clone(),
+                args: args.iter().map(|expr| expr.map_columns(map)).collect(),
+            },
+        }
+    }
+
+    /// Call `visitor` on the expression and every expression within it, parents first.
+    fn visit(&self, visitor: &mut impl FnMut(&ScalarExpr)) {
+        visitor(self);
+        match self {
+            ScalarExpr::Literal(_) | ScalarExpr::Column(_) | ScalarExpr::Parameter(_) => {}
+            ScalarExpr::Unary { expr, .. }
+            | ScalarExpr::IsNull { expr, .. }
+            | ScalarExpr::Cast { expr, .. } => expr.visit(visitor),
+            ScalarExpr::Binary { left, right, .. } => {
+                left.visit(visitor);
+                right.visit(visitor);
+            }
+            ScalarExpr::InList { expr, list, .. } => {
+                expr.visit(visitor);
+                for expr in list {
+                    expr.visit(visitor);
+                }
+            }
+            ScalarExpr::Like {
+                expr,
+                pattern,
+                escape,
+                ..
+            } => {
+                expr.visit(visitor);
+                pattern.visit(visitor);
+                if let Some(escape) = escape {
+                    escape.visit(visitor);
+                }
+            }
+            ScalarExpr::Case {
+                operand,
+                branches,
+                else_result,
+            } => {
+                if let Some(operand) = operand {
+                    operand.visit(visitor);
+                }
+                for (condition, result) in branches {
+                    condition.visit(visitor);
+                    result.visit(visitor);
+                }
+                if let Some(else_result) = else_result {
+                    else_result.visit(visitor);
+                }
+            }
+            ScalarExpr::Function { args, .. } | ScalarExpr::Udf { args, .. } => {
+                for expr in args {
+                    expr.visit(visitor);
+                }
+            }
+        }
+    }
+}
+
+/// Binds expressions against a [`Scope`], resolving names to columns and functions.
+///
+/// Anonymous `?` parameters are numbered in the order they are bound, across all the
+/// expressions bound by one binder, so a statement binds its expressions with a single one.
+#[derive(Debug)]
+pub struct Binder<'a> {
+    scope: &'a Scope,
+    functions: Option<&'a FunctionRegistry>,
+    anonymous: usize,
+    parameters: usize,
+}
+
+impl<'a> Binder<'a> {
+    /// Create a binder resolving names in a scope.
+    #[must_use]
+    pub fn new(scope: &'a Scope) -> Binder<'a> {
+        Binder {
+            scope,
+            functions: None,
+            anonymous: 0,
+            parameters: 0,
+        }
+    }
+
+    /// Resolve functions not built in against `functions`.
+    #[must_use]
+    pub fn with_functions(mut self, functions: &'a FunctionRegistry) -> Self {
+        self.functions = Some(functions);
+        self
+    }
+
+    /// Number of parameter values the expressions bound so far need.
+    #[must_use]
+    pub fn parameters(&self) -> usize {
+        self.parameters
+    }
+
+    /// Resolve the names of an expression.
+    pub fn bind(&mut self, expr: &Expr) -> ValueResult<ScalarExpr> {
+        Ok(match expr {
+            Expr::Literal(literal) => ScalarExpr::Literal(Value::from_literal(literal)?),
+            Expr::Identifier(name) => ScalarExpr::Column(self.scope.resolve(name)?),
+            Expr::Parameter(number) => {
+                let index = match number {
+                    Some(0) => return Err(ValueError::UnboundParameter(0)),
+                    Some(number) => number - 1,
+                    None => {
+                        self.anonymous += 1;
+                        self.anonymous - 1
+                    }
+                };
+                self.parameters = self.parameters.max(index + 1);
+                ScalarExpr::Parameter(index)
+            }
+            Expr::Unary { op, expr } => ScalarExpr::Unary {
+                op: *op,
+                expr: self.boxed(expr)?,
+            },
+            Expr::Binary { left, op, right } => ScalarExpr::Binary {
+                left: self.boxed(left)?,
+                op: *op,
+                right: self.boxed(right)?,
+            },
+            Expr::IsNull { expr, negated } => ScalarExpr::IsNull {
+                expr: self.boxed(expr)?,
+                negated: *negated,
+            },
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => self.between(expr, *negated, low, high)?,
+            Expr::InList {
+                expr,
+                negated,
+                list,
+            } => ScalarExpr::InList {
+                expr: self.boxed(expr)?,
+                list: list
+                    .iter()
+                    .map(|expr| self.bind(expr))
+                    .collect::<ValueResult<_>>()?,
+                negated: *negated,
+            },
+            Expr::Like {
+                expr,
+                negated,
+                pattern,
+                escape,
+            } => ScalarExpr::Like {
+                expr: self.boxed(expr)?,
+                pattern: self.boxed(pattern)?,
+                escape: escape.as_deref().map(|expr| self.boxed(expr)).transpose()?,
+                negated: *negated,
+            },
+            Expr::Case {
+                operand,
+                branches,
+                else_result,
+            } => ScalarExpr::Case {
+                operand: operand
+                    .as_deref()
+                    .map(|expr| self.boxed(expr))
+                    .transpose()?,
+                branches: branches
+                    .iter()
+                    .map(|(condition, result)| Ok((self.bind(condition)?, self.bind(result)?)))
+                    .collect::<ValueResult<_>>()?,
+                else_result: else_result
+                    .as_deref()
+                    .map(|expr| self.boxed(expr))
+                    .transpose()?,
+            },
+            Expr::Cast { expr, data_type } => ScalarExpr::Cast {
+                expr: self.boxed(expr)?,
+                data_type: *data_type,
+            },
+            Expr::Function(function) => self.function(function)?,
+            Expr::InSubquery { .. } | Expr::Exists { .. } | Expr::Subquery(_) => {
+                return Err(ValueError::Unsupported(format!("subquery {expr}")));
+            }
+            Expr::Nested(expr) => self.bind(expr)?,
+        })
+    }
+
+    /// Bind `BETWEEN` as a pair of comparisons.
+    fn between(
+        &mut self,
+        expr: &Expr,
+        negated: bool,
+        low: &Expr,
+        high: &Expr,
+    ) -> ValueResult<ScalarExpr> {
+        let expr = self.bind(expr)?;
+        let compare = |op, bound| ScalarExpr::Binary {
+            left: Box::new(expr.clone()),
+            op,
+            right: bound,
+        };
+        let range = ScalarExpr::Binary {
+            left: Box::new(compare(BinaryOperator::GtEq, self.boxed(low)?)),
+            op: BinaryOperator::And,
+            right: Box::new(compare(BinaryOperator::LtEq, self.boxed(high)?)),
+        };
+        Ok(if negated {
+            ScalarExpr::Unary {
+                op: UnaryOperator::Not,
+                expr: Box::new(range),
+            }
+        } else {
+            range
+        })
+    }
+
+    fn boxed(&mut self, expr: &Expr) -> ValueResult<Box<ScalarExpr>> {
+        self.bind(expr).map(Box::new)
+    }
+
+    fn function(&mut self, function: &Function) -> ValueResult<ScalarExpr> {
+        let call = Expr::Function(function.clone()).to_string();
+        let name = match &function.name[..] {
+            [name] => name.normalized(),
+            _ => return Err(ValueError::UnknownFunction(call)),
+        };
+        let scalar = ScalarFunction::lookup(&name);
+        let udf = self.functions.and_then(|functions| functions.scalar(&name));
+        if scalar.is_none() && udf.is_none() {
+            let aggregate = AGGREGATES.contains(&name.as_str())
+                || self
+                    .functions
+                    .is_some_and(|functions| functions.aggregate(&name).is_some());
+            if aggregate {
+                return Err(ValueError::Unsupported(format!("aggregate {call}")));
+            }
+            return Err(ValueError::UnknownFunction(call));
+        }
+        if function.wildcard || function.distinct {
+            return Err(ValueError::Unsupported(call));
+        }
+        match scalar {
+            Some(scalar) => scalar.check_arity(function.args.len())?,
+            None => udf.expect("Function").check_arity(function.args.len())?,
+        }
+        let args = function
+            .args
+            .iter()
+            .map(|expr| self.bind(expr))
+            .collect::<ValueResult<_>>()?;
+        Ok(match scalar {
+            Some(function) => ScalarExpr::Function { function, args },
+            None => ScalarExpr::Udf {
+                function: udf.expect("Function").clone(),
+                args,
+            },
+        })
+    }
+}
+
+/// Evaluate an infix operator over a batch, evaluating the right operand only for rows the
+/// left doesn't decide.
+fn binary_batch(
+    left: &ScalarExpr,
+    op: BinaryOperator,
+    right: &ScalarExpr,
+    batch: &Batch,
+    params: &[Value],
+) -> ValueResult<Vec<Value>> {
+    let len = batch.len();
+    let left = left.eval_batch(batch, params)?;
+    let mut results = Vec::with_capacity(len);
+    let mut pending = Vec::new();
+    for (index, value) in left.iter().enumerate() {
+        let result = short_circuit(op, value)?;
+        if result.is_none() {
+            pending.push(index);
+        }
+        results.push(result);
+    }
+    // Only rows the left operand doesn't decide evaluate the right
+    let right = if pending.len() == len {
+        right.eval_batch(batch, params)?
+    } else {
+        right.eval_batch(&batch.take(&pending), params)?
+    };
+    for (index, value) in pending.into_iter().zip(right) {
+        results[index] = Some(left[index].binary(op, &value)?);
+    }
+    Ok(results
+        .into_iter()
+        .map(|result| result.expect("Evaluated Row"))
+        .collect())
+}
+
+/// Evaluate `CASE` over a batch, evaluating each condition only for rows no earlier branch
+/// took, and each result only for rows taking its branch.
+fn case_batch(
+    operand: Option<&ScalarExpr>,
+    branches: &[(ScalarExpr, ScalarExpr)],
+    else_result: Option<&ScalarExpr>,
+    batch: &Batch,
+    params: &[Value],
+) -> ValueResult<Vec<Value>> {
+    let len = batch.len();
+    let operand = operand
+        .map(|operand| operand.eval_batch(batch, params))
+        .transpose()?;
+    let mut results = vec![Value::Null; len];
+    let mut remaining: Vec<usize> = (0..len).collect();
+    for (condition, result) in branches {
+        let conditions = condition.eval_batch(&batch.take(&remaining), params)?;
+        let mut matched = Vec::new();
+        let mut unmatched = Vec::new();
+        for (index, condition) in remaining.into_iter().zip(&conditions) {
+            let operand = operand.as_ref().map(|operand| &operand[index]);
+            if case_matches(operand, condition)? {
+                matched.push(index);
+            } else {
+                unmatched.push(index);
+            }
+        }
+        let values = result.eval_batch(&batch.take(&matched), params)?;
+        for (index, value) in matched.into_iter().zip(values) {
+            results[index] = value;
+        }
+        remaining = unmatched;
+    }
+    if let Some(else_result) = else_result {
+        let values = else_result.eval_batch(&batch.take(&remaining), params)?;
+        for (index, value) in remaining.into_iter().zip(values) {
+            results[index] = value;
+        }
+    }
+    Ok(results)
+}
+
+fn parameter(params: &[Value], index: usize) -> ValueResult<Value> {
+    params
+        .get(index)
+        .cloned()
+        .ok_or(ValueError::UnboundParameter(index + 1))
+}
+
+/// Result of `AND` or `OR` decided by its left operand alone, if any.
+fn short_circuit(op: BinaryOperator, left: &Value) -> ValueResult<Option<Value>> {
+    Ok(match op {
+        BinaryOperator::And if left.as_bool()? == Some(false) => Some(Value::Boolean(false)),
+        BinaryOperator::Or if left.as_bool()? == Some(true) => Some(Value::Boolean(true)),
+        _ => None,
+    })
+}
+
+/// Whether a `CASE` branch is taken, comparing its condition with the operand if there is one.
+fn case_matches(operand: Option<&Value>, condition: &Value) -> ValueResult<bool> {
+    match operand {
+        Some(operand) => Ok(operand.binary(BinaryOperator::Eq, condition)?.is_true()),
+        None => Ok(condition.as_bool()? == Some(true)),
+    }
+}
+
+fn in_list<'a>(
+    value: &Value,
+    list: impl Iterator<Item = &'a Value>,
+    negated: bool,
+) -> ValueResult<Value> {
+    if value.is_null() {
+        return Ok(Value::Null);
+    }
+    let mut unknown = false;
+    for item in list {
+        match value.binary(BinaryOperator::Eq, item)? {
+            Value::Boolean(true) => return Ok(Value::Boolean(!negated)),
+            Value::Null => unknown = true,
+            _ => {}
+        }
+    }
+    Ok(if unknown {
+        Value::Null
+    } else {
+        Value::Boolean(negated)
+    })
+}
+
+/// Element of a `LIKE` pattern.
+#[derive(Clone, Copy, PartialEq)]
+enum Wildcard {
+    /// `%`, any text
+    Any,
+    /// `_`, any character
+    One,
+    /// Character matching itself
+    Literal(char),
+}
+
+fn like(
+    value: &Value,
+    pattern: &Value,
+    escape: Option<&Value>,
+    negated: bool,
+) -> ValueResult<Value> {
+    if value.is_null() || pattern.is_null() || escape.is_some_and(Value::is_null) {
+        return Ok(Value::Null);
+    }
+    let invalid = |value: &Value| ValueError::InvalidArgument {
+        operation: "LIKE",
+        value: value.clone(),
+    };
+    let (Value::Text(text), Value::Text(pattern_text)) = (value, pattern) else {
+        let other = if matches!(value, Value::Text(_)) {
+            pattern
+        } else {
+            value
+        };
+        return Err(other.mismatch("LIKE"));
+    };
+    let escape = match escape {
+        None => None,
+        Some(Value::Text(escape)) => {
+            let mut chars = escape.chars();
+            match (chars.next(), chars.next()) {
+                (Some(escape), None) => Some(escape),
+                _ => return Err(invalid(&Value::Text(escape.clone()))),
+            }
+        }
+        Some(escape) => return Err(escape.mismatch("LIKE")),
+    };
+    let mut wildcards = Vec::new();
+    let mut chars = pattern_text.chars();
+    while let Some(c) = chars.next() {
+        wildcards.push(match c {
+            c if Some(c) == escape => {
+                Wildcard::Literal(chars.next().ok_or_else(|| invalid(pattern))?)
+            }
+            '%' => Wildcard::Any,
+            '_' => Wildcard::One,
+            c => Wildcard::Literal(c),
+        });
+    }
+    let text: Vec<char> = text.chars().collect();
+    Ok(Value::Boolean(
+        matches_wildcards(&text, &wildcards) != negated,
+    ))
+}
+
+/// Match text against a pattern, backtracking to the last `%` on a mismatch.
+fn matches_wildcards(text: &[char], pattern: &[Wildcard]) -> bool {
+    let (mut t, mut p) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(Wildcard::One) => (t, p) = (t + 1, p + 1),
+            Some(Wildcard::Literal(c)) if *c == text[t] => (t, p) = (t + 1, p + 1),
+            Some(Wildcard::Any) => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            _ => match backtrack {
+                // Let the last `%` match one more character
+                Some((any, start)) => {
+                    backtrack = Some((any, start + 1));
+                    (t, p) = (start + 1, any + 1);
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..]
+        .iter()
+        .all(|wildcard| *wildcard == Wildcard::Any)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        AggregateState, AggregateUdf, Batch, Binder, FunctionRegistry, ScalarExpr, ScalarUdf,
+        Scope, Signature, Value, ValueError, ValueResult,
+    };
+    use minql_lang::ast::DataType;
+    use minql_lang::Parser;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn scope() -> Scope {
+        Scope::new()
+            .with_column(Some("t"), "a")
+            .with_column(Some("t"), "b")
+            .with_column(Some("u"), "a")
+            .with_column(Some("u"), "s")
+    }
+
+    fn bind(source: &str) -> Result<ScalarExpr, ValueError> {
+        ScalarExpr::bind(&Parser::parse_expr(source).unwrap(), &scope())
+    }
+
+    fn rows() -> Vec<Vec<Value>> {
+        vec![
+            vec![
+                Value::from(1),
+                Value::from(0),
+                Value::from(10),
+                Value::from("apple"),
+            ],
+            vec![
+                Value::from(2),
+                Value::from(4),
+                Value::Null,
+                Value::from("Banana"),
+            ],
+            vec![Value::Null, Value::from(2), Value::from(30), Value::Null],
+            vec![
+                Value::from(4),
+                Value::from(-1),
+                Value::from(40),
+                Value::from("50%"),
+            ],
+        ]
+    }
+
+    /// Evaluate over each row and as a batch, checking both agree.
+    fn eval(source: &str, params: &[Value]) -> Result<Vec<Value>, ValueError> {
+        let expr = bind(source)?;
+        let rows = rows();
+        let batch = expr.eval_batch(&Batch::from_rows(&rows), params);
+        let single = rows
+            .iter()
+            .map(|row| expr.eval(row, params))
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(batch, single, "{source}");
+        batch
+    }
+
+    fn eval_texts(source: &str) -> Vec<String> {
+        eval(source, &[])
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_bind() {
+        assert_eq!(bind("b"), Ok(ScalarExpr::Column(1)));
+        assert_eq!(bind("U.A"), Ok(ScalarExpr::Column(2)));
+        assert_eq!(bind("db.u.s"), Ok(ScalarExpr::Column(3)));
+        assert_eq!(bind("a"), Err(ValueError::AmbiguousColumn("a".into())));
+        assert_eq!(bind("\"B\""), Err(ValueError::UnknownColumn("B".into())));
+        assert_eq!(bind("v.a"), Err(ValueError::UnknownColumn("v.a".into())));
+        assert!(matches!(
+            bind("nope(1)"),
+            Err(ValueError::UnknownFunction(_))
+        ));
+        assert!(matches!(bind("count(*)"), Err(ValueError::Unsupported(_))));
+        assert!(matches!(
+            bind("abs(1, 2)"),
+            Err(ValueError::ArgumentCount { .. })
+        ));
+        assert!(matches!(
+            bind("b IN (SELECT 1)"),
+            Err(ValueError::Unsupported(_))
+        ));
+
+        let scope = scope();
+        let mut binder = Binder::new(&scope);
+        let first = binder.bind(&Parser::parse_expr("? + ?").unwrap()).unwrap();
+        let second = binder
+            .bind(&Parser::parse_expr("b = ? OR $5").unwrap())
+            .unwrap();
+        assert_eq!(binder.parameters(), 5);
+        let params = [Value::from(1), Value::from(2), Value::from(3)];
+        assert_eq!(first.eval(&[], &params), Ok(Value::from(3)));
+        assert_eq!(
+            second.eval(&[Value::Null, Value::from(3)], &params),
+            Ok(Value::from(true))
+        );
+        assert_eq!(
+            second.eval(&[Value::Null, Value::from(2)], &params),
+            Err(ValueError::UnboundParameter(5))
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_columns() {
+        let expr = bind("CASE WHEN s > 1 THEN u.a ELSE abs(b) END || t.a LIKE 'x%'").unwrap();
+        assert_eq!(expr.columns(), [0, 1, 2, 3]);
+        let mapped = expr.map_columns(&|index| 3 - index);
+        assert_eq!(mapped.columns(), [0, 1, 2, 3]);
+        let row = [
+            Value::from("x"),
+            Value::from(-4),
+            Value::from("xy"),
+            Value::from(2),
+        ];
+        let mapped_row: Vec<_> = row.iter().rev().cloned().collect();
+        assert_eq!(expr.eval(&row, &[]), Ok(Value::from(true)));
+        assert_eq!(mapped.eval(&mapped_row, &[]), Ok(Value::from(true)));
+        assert_eq!(bind("1 + ?").unwrap().columns(), []);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_bind_functions() {
+        #[derive(Debug)]
+        struct Nothing;
+
+        impl AggregateState for Nothing {
+            fn update(&mut self, _: &Batch) -> ValueResult<()> {
+                Ok(())
+            }
+
+            fn finish(&mut self) -> ValueResult<Value> {
+                Ok(Value::Null)
+            }
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let signature = Signature::new(&[DataType::BigInt, DataType::BigInt], DataType::BigInt);
+        let mut functions = FunctionRegistry::new();
+        functions
+            .register_scalar(ScalarUdf::new("at_least", signature, move |args| {
+                counted.fetch_add(1, Ordering::Relaxed);
+                Ok(args
+                    .column(0)
+                    .iter()
+                    .zip(args.column(1))
+                    .map(|(value, least)| match (value, least) {
+                        (Value::BigInt(value), Value::BigInt(least)) => {
+                            Value::BigInt(*value.max(least))
+                        }
+                        _ => Value::Null,
+                    })
+                    .collect())
+            }))
+            .unwrap();
+        let signature = Signature::new(&[DataType::Text], DataType::Text);
+        functions
+            .register_aggregate(AggregateUdf::new("nothing", signature, || {
+                Box::new(Nothing)
+            }))
+            .unwrap();
+
+        let scope = scope();
+        let bind = |source: &str| {
+            Binder::new(&scope)
+                .with_functions(&functions)
+                .bind(&Parser::parse_expr(source).unwrap())
+        };
+        let expr = bind("AT_LEAST(t.a, b) + 1").unwrap();
+        let rows = rows();
+        let expected = vec![
+            Value::BigInt(2),
+            Value::BigInt(5),
+            Value::Null,
+            Value::BigInt(5),
+        ];
+        assert_eq!(
+            expr.eval_batch(&Batch::from_rows(&rows), &[]),
+            Ok(expected.clone())
+        );
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        let single = rows.iter().map(|row| expr.eval(row, &[]));
+        assert_eq!(single.collect::<Result<Vec<_>, _>>(), Ok(expected));
+        assert_eq!(expr.map_columns(&|index| index + 1).columns(), [1, 2]);
+
+        assert!(matches!(
+            bind("at_least(1)"),
+            Err(ValueError::Function { .. })
+        ));
+        assert!(matches!(
+            bind("nothing(s)"),
+            Err(ValueError::Unsupported(_))
+        ));
+        assert!(matches!(
+            ScalarExpr::bind(&Parser::parse_expr("at_least(1, 2)").unwrap(), &scope),
+            Err(ValueError::UnknownFunction(_))
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_eval() {
+        assert_eq!(eval_texts("t.a + b * 2"), vec!["1", "10", "NULL", "2"]);
+        assert_eq!(
+            eval_texts("t.a BETWEEN 2 AND 4"),
+            vec!["false", "true", "NULL", "true"]
+        );
+        assert_eq!(
+            eval_texts("u.a NOT IN (10, 20, NULL)"),
+            vec!["false", "NULL", "NULL", "NULL"]
+        );
+        assert_eq!(
+            eval_texts("t.a IN (1, 4)"),
+            vec!["true", "false", "NULL", "true"]
+        );
+        assert_eq!(
+            eval_texts("s IS NULL"),
+            vec!["false", "false", "true", "false"]
+        );
+        assert_eq!(
+            eval_texts("s LIKE '%a_a%' OR s LIKE 'a%'"),
+            vec!["true", "true", "NULL", "false"]
+        );
+        assert_eq!(
+            eval_texts("s LIKE '50!%' ESCAPE '!'"),
+            vec!["false", "false", "NULL", "true"]
+        );
+        assert_eq!(
+            eval_texts("CASE t.a WHEN 1 THEN 'one' WHEN 2 THEN 'two' END"),
+            vec!["one", "two", "NULL", "NULL"]
+        );
+        assert_eq!(
+            eval_texts("coalesce(upper(s), CAST(b AS TEXT)) || '!'"),
+            vec!["APPLE!", "BANANA!", "2!", "50%!"]
+        );
+        assert_eq!(
+            eval_texts("round(u.a / 3.0, 2)"),
+            vec!["3.33", "NULL", "10.00", "13.33"]
+        );
+        assert_eq!(
+            eval_texts("'2024-01-01'::timestamp > '2023-12-31 23:59'"),
+            vec!["true"; 4]
+        );
+
+        // Right operands and branches are only evaluated where they decide the result
+        assert_eq!(
+            eval_texts("b <> 0 AND t.a / b > 0"),
+            vec!["false", "false", "NULL", "false"]
+        );
+        assert_eq!(
+            eval_texts("b = 0 OR t.a / b > 0"),
+            vec!["true", "false", "NULL", "false"]
+        );
+        assert_eq!(
+            eval_texts("CASE WHEN b = 0 THEN NULL ELSE t.a % b END"),
+            vec!["NULL", "2", "NULL", "0"]
+        );
+        assert_eq!(eval("t.a / b", &[]), Err(ValueError::DivisionByZero));
+        assert!(matches!(
+            eval("s + 1", &[]),
+            Err(ValueError::IncompatibleTypes { .. })
+        ));
+        assert!(matches!(
+            eval("b AND TRUE", &[]),
+            Err(ValueError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            eval("s LIKE 'a' ESCAPE 'ab'", &[]),
+            Err(ValueError::InvalidArgument { .. })
+        ));
+        assert_eq!(
+            eval("b * $1", &[Value::from(1.5)]).unwrap(),
+            vec![
+                Value::from(0.0),
+                Value::from(6.0),
+                Value::from(3.0),
+                Value::from(-1.5)
+            ]
+        );
+        assert!(Batch::new(0).is_empty());
+        assert_eq!(
+            bind("t.a").unwrap().eval_batch(&Batch::new(0), &[]),
+            Ok(Vec::new())
+        );
+    }
+}
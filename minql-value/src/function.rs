@@ -0,0 +1,507 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{Decimal, Value, ValueError, ValueResult, ValueType};
+use std::cmp::Ordering;
+
+/// Built-in scalar function.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ScalarFunction {
+    /// `ABS(x)`
+    Abs,
+    /// `CEIL(x)` or `CEILING(x)`
+    Ceil,
+    /// `FLOOR(x)`
+    Floor,
+    /// `ROUND(x [, digits])`, halves away from zero
+    Round,
+    /// `SQRT(x)`
+    Sqrt,
+    /// `POWER(x, y)` or `POW(x, y)`
+    Power,
+    /// `LENGTH(s)`, in characters of text or bytes of a blob
+    Length,
+    /// `LOWER(s)`
+    Lower,
+    /// `UPPER(s)`
+    Upper,
+    /// `TRIM(s [, characters])`, removing spaces by default
+    Trim,
+    /// `LTRIM(s [, characters])`
+    LTrim,
+    /// `RTRIM(s [, characters])`
+    RTrim,
+    /// `SUBSTR(s, start [, count])` or `SUBSTRING`, counting characters from 1
+    Substr,
+    /// `REPLACE(s, from, to)`
+    Replace,
+    /// `CONCAT(s, ...)`, skipping `NULL`s
+    Concat,
+    /// `COALESCE(x, ...)`, the first value not `NULL`
+    Coalesce,
+    /// `NULLIF(x, y)`, `NULL` if `x = y` and otherwise `x`
+    NullIf,
+    /// `GREATEST(x, ...)`, skipping `NULL`s
+    Greatest,
+    /// `LEAST(x, ...)`, skipping `NULL`s
+    Least,
+}
+
+impl ScalarFunction {
+    /// Find the function of a name, ignoring case.
+    #[must_use]
+    pub fn lookup(name: &str) -> Option<ScalarFunction> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "abs" => ScalarFunction::Abs,
+            "ceil" | "ceiling" => ScalarFunction::Ceil,
+            "floor" => ScalarFunction::Floor,
+            "round" => ScalarFunction::Round,
+            "sqrt" => ScalarFunction::Sqrt,
+            "power" | "pow" => ScalarFunction::Power,
+            "length" | "char_length" | "character_length" => ScalarFunction::Length,
+            "lower" => ScalarFunction::Lower,
+            "upper" => ScalarFunction::Upper,
+            "trim" | "btrim" => ScalarFunction::Trim,
+            "ltrim" => ScalarFunction::LTrim,
+            "rtrim" => ScalarFunction::RTrim,
+            "substr" | "substring" => ScalarFunction::Substr,
+            "replace" => ScalarFunction::Replace,
+            "concat" => ScalarFunction::Concat,
+            "coalesce" => ScalarFunction::Coalesce,
+            "nullif" => ScalarFunction::NullIf,
+            "greatest" => ScalarFunction::Greatest,
+            "least" => ScalarFunction::Least,
+            _ => return None,
+        })
+    }
+
+    /// Canonical name.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            ScalarFunction::Abs => "abs",
+            ScalarFunction::Ceil => "ceil",
+            ScalarFunction::Floor => "floor",
+            ScalarFunction::Round => "round",
+            ScalarFunction::Sqrt => "sqrt",
+            ScalarFunction::Power => "power",
+            ScalarFunction::Length => "length",
+            ScalarFunction::Lower => "lower",
+            ScalarFunction::Upper => "upper",
+            ScalarFunction::Trim => "trim",
+            ScalarFunction::LTrim => "ltrim",
+            ScalarFunction::RTrim => "rtrim",
+            ScalarFunction::Substr => "substr",
+            ScalarFunction::Replace => "replace",
+            ScalarFunction::Concat => "concat",
+            ScalarFunction::Coalesce => "coalesce",
+            ScalarFunction::NullIf => "nullif",
+            ScalarFunction::Greatest => "greatest",
+            ScalarFunction::Least => "least",
+        }
+    }
+
+    /// Least and most arguments accepted, where `None` is any number.
+    #[must_use]
+    pub fn arity(self) -> (usize, Option<usize>) {
+        match self {
+            ScalarFunction::Abs
+            | ScalarFunction::Ceil
+            | ScalarFunction::Floor
+            | ScalarFunction::Sqrt
+            | ScalarFunction::Length
+            | ScalarFunction::Lower
+            | ScalarFunction::Upper => (1, Some(1)),
+            ScalarFunction::Round
+            | ScalarFunction::Trim
+            | ScalarFunction::LTrim
+            | ScalarFunction::RTrim => (1, Some(2)),
+            ScalarFunction::Power | ScalarFunction::NullIf => (2, Some(2)),
+            ScalarFunction::Substr => (2, Some(3)),
+            ScalarFunction::Replace => (3, Some(3)),
+            ScalarFunction::Concat
+            | ScalarFunction::Coalesce
+            | ScalarFunction::Greatest
+            | ScalarFunction::Least => (1, None),
+        }
+    }
+
+    /// Check a number of arguments against the arity.
+    pub fn check_arity(self, count: usize) -> ValueResult<()> {
+        let (least, most) = self.arity();
+        if count < least || most.is_some_and(|most| count > most) {
+            Err(ValueError::ArgumentCount {
+                function: self.name(),
+                found: count,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Call the function, returning `NULL` if any argument is `NULL` unless the function
+    /// handles them itself.
+    pub fn call(self, args: &[Value]) -> ValueResult<Value> {
+        self.check_arity(args.len())?;
+        let handles_nulls = matches!(
+            self,
+            ScalarFunction::Concat
+                | ScalarFunction::Coalesce
+                | ScalarFunction::NullIf
+                | ScalarFunction::Greatest
+                | ScalarFunction::Least
+        );
+        if !handles_nulls && args.iter().any(Value::is_null) {
+            return Ok(Value::Null);
+        }
+        match self {
+            ScalarFunction::Abs
+            | ScalarFunction::Ceil
+            | ScalarFunction::Floor
+            | ScalarFunction::Round
+            | ScalarFunction::Sqrt
+            | ScalarFunction::Power => self.call_numeric(args),
+            ScalarFunction::Length
+            | ScalarFunction::Lower
+            | ScalarFunction::Upper
+            | ScalarFunction::Trim
+            | ScalarFunction::LTrim
+            | ScalarFunction::RTrim
+            | ScalarFunction::Substr
+            | ScalarFunction::Replace => self.call_text(args),
+            _ => self.call_variadic(args),
+        }
+    }
+
+    /// Call a function of numbers.
+    fn call_numeric(self, args: &[Value]) -> ValueResult<Value> {
+        let name = self.name();
+        match self {
+            ScalarFunction::Abs => match &args[0] {
+                Value::Decimal(value) => Ok(Value::Decimal(value.abs())),
+                Value::Real(value) => Ok(Value::Real(value.abs())),
+                Value::Double(value) => Ok(Value::Double(value.abs())),
+                value => {
+                    let (value, value_type) = integer(value, name)?;
+                    let overflow = ValueError::Overflow(value_type);
+                    Value::integer(value.checked_abs().ok_or(overflow)?, value_type)
+                }
+            },
+            ScalarFunction::Ceil | ScalarFunction::Floor => {
+                let ceil = self == ScalarFunction::Ceil;
+                match &args[0] {
+                    Value::Decimal(value) => Ok(Value::Decimal(if ceil {
+                        value.ceil()
+                    } else {
+                        value.floor()
+                    })),
+                    Value::Real(value) => {
+                        Ok(Value::Real(if ceil { value.ceil() } else { value.floor() }))
+                    }
+                    Value::Double(value) => Ok(Value::Double(if ceil {
+                        value.ceil()
+                    } else {
+                        value.floor()
+                    })),
+                    value => integer(value, name).map(|_| value.clone()),
+                }
+            }
+            ScalarFunction::Round => {
+                let digits = match args.get(1) {
+                    Some(digits) => integer(digits, name)?.0,
+                    None => 0,
+                };
+                let Ok(digits) = u32::try_from(digits) else {
+                    return Err(ValueError::InvalidArgument {
+                        operation: name,
+                        value: args[1].clone(),
+                    });
+                };
+                match &args[0] {
+                    Value::Decimal(value) => value
+                        .round(digits.min(Decimal::MAX_PRECISION))
+                        .map(Value::Decimal)
+                        .ok_or(ValueError::Overflow(ValueType::Decimal)),
+                    #[allow(clippy::cast_possible_truncation)]
+                    Value::Real(value) => Ok(Value::Real(round(f64::from(*value), digits) as f32)),
+                    Value::Double(value) => Ok(Value::Double(round(*value, digits))),
+                    value => integer(value, name).map(|_| value.clone()),
+                }
+            }
+            ScalarFunction::Sqrt => {
+                let value = float(&args[0], name)?;
+                if value < 0.0 {
+                    return Err(ValueError::InvalidArgument {
+                        operation: name,
+                        value: args[0].clone(),
+                    });
+                }
+                Ok(Value::Double(value.sqrt()))
+            }
+            ScalarFunction::Power => Ok(Value::Double(
+                float(&args[0], name)?.powf(float(&args[1], name)?),
+            )),
+            _ => unreachable!("not a function of numbers"),
+        }
+    }
+
+    /// Call a function of text.
+    fn call_text(self, args: &[Value]) -> ValueResult<Value> {
+        let name = self.name();
+        match self {
+            ScalarFunction::Length => {
+                let length = match &args[0] {
+                    Value::Blob(bytes) => bytes.len(),
+                    value => text(value, name)?.chars().count(),
+                };
+                Ok(Value::BigInt(
+                    i64::try_from(length).expect("Length fits i64"),
+                ))
+            }
+            ScalarFunction::Lower => Ok(Value::Text(text(&args[0], name)?.to_lowercase())),
+            ScalarFunction::Upper => Ok(Value::Text(text(&args[0], name)?.to_uppercase())),
+            ScalarFunction::Trim | ScalarFunction::LTrim | ScalarFunction::RTrim => {
+                let value = text(&args[0], name)?;
+                let characters: Vec<char> = match args.get(1) {
+                    Some(characters) => text(characters, name)?.chars().collect(),
+                    None => vec![' '],
+                };
+                let trimmed = match self {
+                    ScalarFunction::LTrim => value.trim_start_matches(&characters[..]),
+                    ScalarFunction::RTrim => value.trim_end_matches(&characters[..]),
+                    _ => value.trim_matches(&characters[..]),
+                };
+                Ok(Value::Text(trimmed.to_string()))
+            }
+            ScalarFunction::Substr => {
+                let value = text(&args[0], name)?;
+                let start = integer(&args[1], name)?.0;
+                // Positions before the first character still count toward the length
+                let end = match args.get(2) {
+                    Some(count) => {
+                        let count = integer(count, name)?.0;
+                        if count < 0 {
+                            return Err(ValueError::InvalidArgument {
+                                operation: name,
+                                value: args[2].clone(),
+                            });
+                        }
+                        start.saturating_add(count)
+                    }
+                    None => i64::MAX,
+                };
+                let skip = usize::try_from(start.max(1) - 1).unwrap_or(usize::MAX);
+                let take = usize::try_from(end.max(1) - start.max(1)).unwrap_or(usize::MAX);
+                Ok(Value::Text(value.chars().skip(skip).take(take).collect()))
+            }
+            ScalarFunction::Replace => {
+                let value = text(&args[0], name)?;
+                let from = text(&args[1], name)?;
+                let to = text(&args[2], name)?;
+                if from.is_empty() {
+                    Ok(Value::Text(value.to_string()))
+                } else {
+                    Ok(Value::Text(value.replace(from, to)))
+                }
+            }
+            _ => unreachable!("not a function of text"),
+        }
+    }
+
+    /// Call a function of any number of values, handling `NULL`s itself.
+    fn call_variadic(self, args: &[Value]) -> ValueResult<Value> {
+        match self {
+            ScalarFunction::Concat => Ok(Value::Text(
+                args.iter()
+                    .filter(|value| !value.is_null())
+                    .map(ToString::to_string)
+                    .collect(),
+            )),
+            ScalarFunction::Coalesce => Ok(args
+                .iter()
+                .find(|value| !value.is_null())
+                .cloned()
+                .unwrap_or(Value::Null)),
+            ScalarFunction::NullIf => match args[0].compare(&args[1])? {
+                Some(Ordering::Equal) => Ok(Value::Null),
+                _ => Ok(args[0].clone()),
+            },
+            ScalarFunction::Greatest | ScalarFunction::Least => {
+                let wanted = if self == ScalarFunction::Greatest {
+                    Ordering::Greater
+                } else {
+                    Ordering::Less
+                };
+                let mut best: Option<&Value> = None;
+                for value in args.iter().filter(|value| !value.is_null()) {
+                    match best {
+                        Some(current) if current.compare(value)? != Some(wanted.reverse()) => {}
+                        _ => best = Some(value),
+                    }
+                }
+                Ok(best.cloned().unwrap_or(Value::Null))
+            }
+            _ => unreachable!("not a variadic function"),
+        }
+    }
+}
+
+/// Round a float to `digits` after the point, halves away from zero.
+fn round(value: f64, digits: u32) -> f64 {
+    let factor = 10f64.powi(i32::try_from(digits).unwrap_or(i32::MAX));
+    if factor.is_finite() {
+        (value * factor).round() / factor
+    } else {
+        value
+    }
+}
+
+/// Integer argument and its type.
+fn integer(value: &Value, operation: &'static str) -> ValueResult<(i64, ValueType)> {
+    match value {
+        Value::SmallInt(value) => Ok((i64::from(*value), ValueType::SmallInt)),
+        Value::Integer(value) => Ok((i64::from(*value), ValueType::Integer)),
+        Value::BigInt(value) => Ok((*value, ValueType::BigInt)),
+        value => Err(value.mismatch(operation)),
+    }
+}
+
+/// Numeric argument as a float.
+fn float(value: &Value, operation: &'static str) -> ValueResult<f64> {
+    match value.value_type() {
+        Some(value_type) if value_type.is_numeric() => Ok(value.to_f64().expect("Numeric Value")),
+        _ => Err(value.mismatch(operation)),
+    }
+}
+
+/// Text argument.
+fn text<'a>(value: &'a Value, operation: &'static str) -> ValueResult<&'a str> {
+    match value {
+        Value::Text(text) => Ok(text),
+        value => Err(value.mismatch(operation)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Decimal, ScalarFunction, Value, ValueError};
+
+    fn call(name: &str, args: &[Value]) -> Result<Value, ValueError> {
+        ScalarFunction::lookup(name).unwrap().call(args)
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_functions() {
+        let decimal = |text| Value::Decimal(Decimal::parse(text).unwrap());
+        assert_eq!(call("ABS", &[Value::from(-3)]), Ok(Value::Integer(3)));
+        assert_eq!(call("abs", &[decimal("-1.5")]), Ok(decimal("1.5")));
+        assert_eq!(call("ceiling", &[decimal("1.2")]), Ok(decimal("2")));
+        assert_eq!(call("floor", &[Value::from(-1.5)]), Ok(Value::Double(-2.0)));
+        assert_eq!(
+            call("round", &[decimal("2.345"), Value::from(2)]),
+            Ok(decimal("2.35"))
+        );
+        assert_eq!(call("round", &[Value::from(2.5)]), Ok(Value::Double(3.0)));
+        assert_eq!(call("sqrt", &[Value::from(16)]), Ok(Value::Double(4.0)));
+        assert!(matches!(
+            call("sqrt", &[Value::from(-1)]),
+            Err(ValueError::InvalidArgument { .. })
+        ));
+        assert_eq!(
+            call("power", &[Value::from(2), Value::from(10)]),
+            Ok(Value::Double(1024.0))
+        );
+
+        assert_eq!(
+            call("length", &[Value::from("größe")]),
+            Ok(Value::BigInt(5))
+        );
+        assert_eq!(
+            call("length", &[Value::Blob(vec![1, 2])]),
+            Ok(Value::BigInt(2))
+        );
+        assert_eq!(call("upper", &[Value::from("abc")]), Ok(Value::from("ABC")));
+        assert_eq!(call("trim", &[Value::from("  a  ")]), Ok(Value::from("a")));
+        assert_eq!(
+            call("ltrim", &[Value::from("xxaxx"), Value::from("x")]),
+            Ok(Value::from("axx"))
+        );
+        assert_eq!(
+            call(
+                "substr",
+                &[Value::from("hello"), Value::from(2), Value::from(3)]
+            ),
+            Ok(Value::from("ell"))
+        );
+        assert_eq!(
+            call(
+                "substring",
+                &[Value::from("hello"), Value::from(0), Value::from(3)]
+            ),
+            Ok(Value::from("he"))
+        );
+        assert_eq!(
+            call("substr", &[Value::from("hello"), Value::from(4)]),
+            Ok(Value::from("lo"))
+        );
+        assert_eq!(
+            call(
+                "replace",
+                &[Value::from("a-b-c"), Value::from("-"), Value::from("+")]
+            ),
+            Ok(Value::from("a+b+c"))
+        );
+        assert_eq!(call("lower", &[Value::Null]), Ok(Value::Null));
+        assert!(matches!(
+            call("lower", &[Value::from(1)]),
+            Err(ValueError::TypeMismatch { .. })
+        ));
+
+        assert_eq!(
+            call("concat", &[Value::from("a"), Value::Null, Value::from(1)]),
+            Ok(Value::from("a1"))
+        );
+        assert_eq!(
+            call("coalesce", &[Value::Null, Value::from(2), Value::from(3)]),
+            Ok(Value::from(2))
+        );
+        assert_eq!(
+            call("nullif", &[Value::from(1), Value::from(1)]),
+            Ok(Value::Null)
+        );
+        assert_eq!(
+            call("nullif", &[Value::from(1), Value::Null]),
+            Ok(Value::from(1))
+        );
+        assert_eq!(
+            call("greatest", &[Value::from(1), Value::Null, decimal("2.5")]),
+            Ok(decimal("2.5"))
+        );
+        assert_eq!(
+            call("least", &[Value::from(1), Value::from(0.5)]),
+            Ok(Value::from(0.5))
+        );
+        assert_eq!(
+            call("abs", &[]),
+            Err(ValueError::ArgumentCount {
+                function: "abs",
+                found: 0
+            })
+        );
+        assert!(ScalarFunction::lookup("count").is_none());
+    }
+}
@@ -0,0 +1,72 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! SQL Values and Expression Evaluation
+//!
+//! The [`Value`] of every SQL type, with implicit coercion between related types, SQL
+//! comparison and a total order for sorting and keys. Expressions of the `minql-lang` AST bind
+//! against a [`Scope`] of column names into [`ScalarExpr`]s, which evaluate over single rows or
+//! a [`Batch`] of rows at a time, calling [`ScalarFunction`]s for built-in functions and the
+//! [`ScalarUdf`]s of a [`FunctionRegistry`] for functions defined by the application.
+//! Rows are stored in the row format of [`encode_row`], and index keys in the order
+//! preserving encoding of [`encode_key`].
+//!
+//! ```rust
+//! use minql_lang::Parser;
+//! use minql_value::{ScalarExpr, Scope, Value};
+//!
+//! let scope = Scope::new().with_column(None, "price");
+//! let expr = Parser::parse_expr("round(price * 1.2, 2)").unwrap();
+//! let expr = ScalarExpr::bind(&expr, &scope).unwrap();
+//! assert_eq!(expr.eval(&[Value::from(10)], &[]).unwrap().to_string(), "12.00");
+//! ```
+
+#![forbid(unsafe_code)]
+#![warn(
+    clippy::cargo,
+    missing_docs,
+    clippy::pedantic,
+    future_incompatible,
+    rust_2018_idioms
+)]
+#![allow(
+    clippy::option_if_let_else,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod batch;
+mod decimal;
+mod eval;
+mod function;
+mod result;
+mod row;
+mod timestamp;
+mod udf;
+mod value;
+
+pub use self::batch::Batch;
+pub use self::decimal::Decimal;
+pub use self::eval::{Binder, ScalarExpr, Scope};
+pub use self::function::ScalarFunction;
+pub use self::result::{ValueError, ValueResult};
+pub use self::row::{decode_row, encode_key, encode_row};
+pub use self::timestamp::Timestamp;
+pub use self::udf::{
+    AggregateState, AggregateUdf, FunctionRegistry, ScalarUdf, Signature, UdfAccumulator,
+};
+pub use self::value::{Value, ValueType};
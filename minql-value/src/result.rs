@@ -0,0 +1,94 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{Value, ValueType};
+use minql_lang::ast::DataType;
+
+/// Result Type for Values and Expressions
+pub type ValueResult<T> = Result<T, ValueError>;
+
+/// Error Type for Values and Expressions
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValueError {
+    /// Operator or function applied to a value of a type it doesn't accept
+    TypeMismatch {
+        /// Operator or function applied
+        operation: &'static str,
+        /// Type of the value
+        found: ValueType,
+    },
+    /// Operator applied to values of types with no common type
+    IncompatibleTypes {
+        /// Operator applied
+        operation: &'static str,
+        /// Type of the left operand
+        left: ValueType,
+        /// Type of the right operand
+        right: ValueType,
+    },
+    /// Value can't be converted to a type
+    InvalidCast {
+        /// Value converted
+        value: Value,
+        /// Type converted to
+        data_type: DataType,
+    },
+    /// Value outside what an operator or function accepts
+    InvalidArgument {
+        /// Operator or function applied
+        operation: &'static str,
+        /// Value given
+        value: Value,
+    },
+    /// Result doesn't fit its type
+    Overflow(ValueType),
+    /// Division or remainder by zero
+    DivisionByZero,
+    /// Name matches no column
+    UnknownColumn(String),
+    /// Name matches more than one column
+    AmbiguousColumn(String),
+    /// Name matches no function
+    UnknownFunction(String),
+    /// Function called with too few or too many arguments
+    ArgumentCount {
+        /// Function called
+        function: &'static str,
+        /// Arguments given
+        found: usize,
+    },
+    /// User-defined function refused its arguments or failed
+    Function {
+        /// Function called
+        function: String,
+        /// What went wrong
+        message: String,
+    },
+    /// Parameter, numbered from 1, has no value
+    UnboundParameter(usize),
+    /// Expression can't be evaluated by itself, such as a subquery or aggregate
+    Unsupported(String),
+    /// Bytes aren't a row in the row format
+    InvalidRow(String),
+}
+
+impl std::fmt::Display for ValueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ValueError {}
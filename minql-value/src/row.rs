@@ -0,0 +1,385 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{Decimal, Timestamp, Value, ValueError, ValueResult};
+
+/// Tags of the types of values in the row format.
+const NULL: u8 = 0;
+const BOOLEAN: u8 = 1;
+const SMALLINT: u8 = 2;
+const INTEGER: u8 = 3;
+const BIGINT: u8 = 4;
+const REAL: u8 = 5;
+const DOUBLE: u8 = 6;
+const DECIMAL: u8 = 7;
+const TEXT: u8 = 8;
+const BLOB: u8 = 9;
+const TIMESTAMP: u8 = 10;
+
+/// Encode a row in the row format tables store it in.
+///
+/// A row is the number of its values followed by each value tagged with its type, so rows
+/// decode without their schema and keep their values exactly. Numbers are little endian, and
+/// text and blobs are prefixed with their length.
+///
+/// ```rust
+/// use minql_value::{decode_row, encode_row, Value};
+///
+/// let row = vec![Value::from(7), Value::Null, Value::from("seven")];
+/// assert_eq!(decode_row(&encode_row(&row)).unwrap(), row);
+/// ```
+#[must_use]
+pub fn encode_row(row: &[Value]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&length(row.len()).to_le_bytes());
+    for value in row {
+        match value {
+            Value::Null => bytes.push(NULL),
+            Value::Boolean(value) => bytes.extend_from_slice(&[BOOLEAN, u8::from(*value)]),
+            Value::SmallInt(value) => {
+                bytes.push(SMALLINT);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Value::Integer(value) => {
+                bytes.push(INTEGER);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Value::BigInt(value) => {
+                bytes.push(BIGINT);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Value::Real(value) => {
+                bytes.push(REAL);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Value::Double(value) => {
+                bytes.push(DOUBLE);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Value::Decimal(value) => {
+                bytes.push(DECIMAL);
+                bytes.extend_from_slice(&value.mantissa().to_le_bytes());
+                bytes.extend_from_slice(&value.scale().to_le_bytes());
+            }
+            Value::Text(value) => {
+                bytes.push(TEXT);
+                bytes.extend_from_slice(&length(value.len()).to_le_bytes());
+                bytes.extend_from_slice(value.as_bytes());
+            }
+            Value::Blob(value) => {
+                bytes.push(BLOB);
+                bytes.extend_from_slice(&length(value.len()).to_le_bytes());
+                bytes.extend_from_slice(value);
+            }
+            Value::Timestamp(value) => {
+                bytes.push(TIMESTAMP);
+                bytes.extend_from_slice(&value.micros().to_le_bytes());
+            }
+        }
+    }
+    bytes
+}
+
+/// Decode a row encoded by [`encode_row`].
+pub fn decode_row(bytes: &[u8]) -> ValueResult<Vec<Value>> {
+    let mut reader = Reader { bytes };
+    let count = u32::from_le_bytes(reader.array()?);
+    let mut row = Vec::with_capacity(count.min(1024) as usize);
+    for _ in 0..count {
+        let [tag] = reader.array()?;
+        row.push(match tag {
+            NULL => Value::Null,
+            BOOLEAN => match reader.array()? {
+                [0] => Value::Boolean(false),
+                [1] => Value::Boolean(true),
+                [byte] => return Err(invalid(format!("boolean {byte}"))),
+            },
+            SMALLINT => Value::SmallInt(i16::from_le_bytes(reader.array()?)),
+            INTEGER => Value::Integer(i32::from_le_bytes(reader.array()?)),
+            BIGINT => Value::BigInt(i64::from_le_bytes(reader.array()?)),
+            REAL => Value::Real(f32::from_le_bytes(reader.array()?)),
+            DOUBLE => Value::Double(f64::from_le_bytes(reader.array()?)),
+            DECIMAL => {
+                let mantissa = i128::from_le_bytes(reader.array()?);
+                let scale = u32::from_le_bytes(reader.array()?);
+                Value::Decimal(
+                    Decimal::new(mantissa, scale)
+                        .ok_or_else(|| invalid(format!("decimal {mantissa}e-{scale}")))?,
+                )
+            }
+            TEXT => {
+                let text = reader.sized()?.to_vec();
+                Value::Text(String::from_utf8(text).map_err(|_| invalid("text".to_string()))?)
+            }
+            BLOB => Value::Blob(reader.sized()?.to_vec()),
+            TIMESTAMP => {
+                Value::Timestamp(Timestamp::from_micros(i64::from_le_bytes(reader.array()?)))
+            }
+            tag => return Err(invalid(format!("type tag {tag}"))),
+        });
+    }
+    if !reader.bytes.is_empty() {
+        return Err(invalid(format!("{} trailing bytes", reader.bytes.len())));
+    }
+    Ok(row)
+}
+
+/// Append the key encoding of values to `key`, as the keys of indexes store them.
+///
+/// Keys compare bytewise in the order their values sort, `NULL` first, so indexes can keep
+/// them with a bytewise comparator. The encoding of each value is self delimiting, so the key
+/// of leading values is a prefix of the keys of every longer run of values starting with them.
+///
+/// Values of different types compare by their encoding only where both are integers, both
+/// are floating point or both are decimals, so values of a column should be converted to its
+/// type first.
+///
+/// ```rust
+/// use minql_value::{encode_key, Value};
+///
+/// let key = |value: Value| {
+///     let mut key = Vec::new();
+///     encode_key(&[value], &mut key);
+///     key
+/// };
+/// assert!(key(Value::from(-3)) < key(Value::from(2)));
+/// assert!(key(Value::from("ab")) < key(Value::from("abc")));
+/// assert!(key(Value::Null) < key(Value::from(i64::MIN)));
+/// ```
+pub fn encode_key(values: &[Value], key: &mut Vec<u8>) {
+    for value in values {
+        match value {
+            Value::Null => key.push(0),
+            Value::Boolean(value) => key.extend_from_slice(&[1, u8::from(*value)]),
+            Value::SmallInt(_) | Value::Integer(_) | Value::BigInt(_) => {
+                let value = match value {
+                    Value::SmallInt(value) => i64::from(*value),
+                    Value::Integer(value) => i64::from(*value),
+                    Value::BigInt(value) => *value,
+                    _ => unreachable!("integer value"),
+                };
+                key.push(1);
+                key.extend_from_slice(&signed(value));
+            }
+            Value::Real(value) => float_key(f64::from(*value), key),
+            Value::Double(value) => float_key(*value, key),
+            Value::Decimal(value) => decimal_key(*value, key),
+            Value::Text(value) => bytes_key(value.as_bytes(), key),
+            Value::Blob(value) => bytes_key(value, key),
+            Value::Timestamp(value) => {
+                key.push(1);
+                key.extend_from_slice(&signed(value.micros()));
+            }
+        }
+    }
+}
+
+/// Big endian bytes of an integer with its sign flipped, ordering negatives first.
+fn signed(value: i64) -> [u8; 8] {
+    (value.cast_unsigned() ^ (1 << 63)).to_be_bytes()
+}
+
+/// Key of a float, ordering `NaN` after every other value as [`Value`] does.
+fn float_key(value: f64, key: &mut Vec<u8>) {
+    let value = if value.is_nan() {
+        f64::NAN
+    } else {
+        value + 0.0
+    };
+    let bits = value.to_bits();
+    let bits = if bits >> 63 == 0 {
+        bits | 1 << 63
+    } else {
+        !bits
+    };
+    key.push(1);
+    key.extend_from_slice(&bits.to_be_bytes());
+}
+
+/// Key of a decimal, as its sign, then the exponent and significant digits of its magnitude,
+/// inverted for negatives.
+fn decimal_key(value: Decimal, key: &mut Vec<u8>) {
+    key.push(1);
+    if value.is_zero() {
+        key.push(2);
+        return;
+    }
+    let digits = value.mantissa().unsigned_abs().to_string();
+    let exponent = i64::try_from(digits.len()).expect("Digits") - i64::from(value.scale());
+    let mut magnitude = Vec::with_capacity(digits.len() + 9);
+    magnitude.extend_from_slice(&signed(exponent));
+    magnitude.extend_from_slice(digits.trim_end_matches('0').as_bytes());
+    magnitude.push(0);
+    if value.mantissa() < 0 {
+        key.push(1);
+        key.extend(magnitude.iter().map(|byte| !byte));
+    } else {
+        key.push(3);
+        key.extend_from_slice(&magnitude);
+    }
+}
+
+/// Key of text or a blob, with zero bytes escaped and ended by a zero byte.
+fn bytes_key(value: &[u8], key: &mut Vec<u8>) {
+    key.push(1);
+    for &byte in value {
+        if byte == 0 {
+            key.extend_from_slice(&[0, 0xFF]);
+        } else {
+            key.push(byte);
+        }
+    }
+    key.extend_from_slice(&[0, 0]);
+}
+
+/// Length of a run of the row format.
+fn length(len: usize) -> u32 {
+    u32::try_from(len).expect("Row Length")
+}
+
+fn invalid(message: String) -> ValueError {
+    ValueError::InvalidRow(message)
+}
+
+/// Bytes of an encoded row not yet decoded.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn array<const N: usize>(&mut self) -> ValueResult<[u8; N]> {
+        let (array, rest) = self
+            .bytes
+            .split_first_chunk::<N>()
+            .ok_or_else(|| invalid("truncated row".to_string()))?;
+        self.bytes = rest;
+        Ok(*array)
+    }
+
+    fn sized(&mut self) -> ValueResult<&[u8]> {
+        let len = u32::from_le_bytes(self.array()?) as usize;
+        if self.bytes.len() < len {
+            return Err(invalid("truncated row".to_string()));
+        }
+        let (value, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_row, encode_key, encode_row};
+    use crate::{Decimal, Timestamp, Value, ValueError};
+
+    fn key(value: &Value) -> Vec<u8> {
+        let mut key = Vec::new();
+        encode_key(std::slice::from_ref(value), &mut key);
+        key
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_row() {
+        let row = vec![
+            Value::Null,
+            Value::from(true),
+            Value::from(-3i16),
+            Value::from(40),
+            Value::from(i64::MIN),
+            Value::from(1.5f32),
+            Value::from(-2.25),
+            Value::from(Decimal::parse("-12.340").unwrap()),
+            Value::from("héllo"),
+            Value::from(vec![0u8, 1, 2]),
+            Value::from(Timestamp::from_micros(-5)),
+        ];
+        let bytes = encode_row(&row);
+        let decoded = decode_row(&bytes).unwrap();
+        assert_eq!(decoded, row);
+        assert_eq!(decoded[7].to_string(), "-12.340");
+        assert_eq!(decoded[2].value_type(), row[2].value_type());
+        assert_eq!(decode_row(&encode_row(&[])).unwrap(), Vec::<Value>::new());
+        assert!(matches!(
+            decode_row(&bytes[..bytes.len() - 1]),
+            Err(ValueError::InvalidRow(_))
+        ));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            decode_row(&trailing),
+            Err(ValueError::InvalidRow(_))
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_key_order() {
+        let decimal = |text: &str| Value::from(Decimal::parse(text).unwrap());
+        let runs = [
+            vec![
+                Value::Null,
+                Value::from(i64::MIN),
+                Value::from(-1),
+                Value::from(0i16),
+                Value::from(7),
+                Value::from(i64::MAX),
+            ],
+            vec![
+                Value::from(f64::NEG_INFINITY),
+                Value::from(-1.5),
+                Value::from(-0.0),
+                Value::from(0.25f32),
+                Value::from(f64::INFINITY),
+                Value::from(f64::NAN),
+            ],
+            vec![
+                decimal("-120"),
+                decimal("-12.5"),
+                decimal("-12.25"),
+                decimal("-0.001"),
+                decimal("0.00"),
+                decimal("0.12"),
+                decimal("0.123"),
+                decimal("1.5"),
+                decimal("12"),
+                decimal("100.0"),
+            ],
+            vec![
+                Value::from(""),
+                Value::from("\0"),
+                Value::from("\0a"),
+                Value::from("a"),
+                Value::from("a\0"),
+                Value::from("ab"),
+                Value::from("b"),
+            ],
+        ];
+        for run in runs {
+            for pair in run.windows(2) {
+                assert_eq!(pair[0].cmp(&pair[1]), key(&pair[0]).cmp(&key(&pair[1])));
+            }
+        }
+        assert_eq!(key(&decimal("1.50")), key(&decimal("1.5")));
+        assert_eq!(key(&Value::from(0.0)), key(&Value::from(-0.0)));
+
+        let mut prefix = Vec::new();
+        encode_key(&[Value::from("a")], &mut prefix);
+        let mut longer = Vec::new();
+        encode_key(&[Value::from("a"), Value::from(1)], &mut longer);
+        assert!(longer.starts_with(&prefix));
+    }
+}
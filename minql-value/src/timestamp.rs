@@ -0,0 +1,223 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+/// Microseconds in a second.
+const MICROS_PER_SECOND: i64 = 1_000_000;
+
+/// Microseconds in a day.
+const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+/// Point in time without a time zone, counted in microseconds from `1970-01-01 00:00:00`.
+///
+/// Written as `YYYY-MM-DD HH:MM:SS`, followed by as many fractional digits as needed.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Timestamp(i64);
+
+impl Timestamp {
+    /// Create a timestamp from microseconds since `1970-01-01 00:00:00`.
+    #[must_use]
+    pub fn from_micros(micros: i64) -> Timestamp {
+        Timestamp(micros)
+    }
+
+    /// Microseconds since `1970-01-01 00:00:00`.
+    #[must_use]
+    pub fn micros(self) -> i64 {
+        self.0
+    }
+
+    /// Create a timestamp from a calendar date and time of day, if valid.
+    #[must_use]
+    pub fn from_parts(
+        year: i64,
+        month: u32,
+        day: u32,
+        hour: u32,
+        minute: u32,
+        second: u32,
+        micros: u32,
+    ) -> Option<Timestamp> {
+        if !(1..=12).contains(&month)
+            || day < 1
+            || day > days_in_month(year, month)
+            || hour > 23
+            || minute > 59
+            || second > 59
+            || i64::from(micros) >= MICROS_PER_SECOND
+        {
+            return None;
+        }
+        let seconds = i64::from(hour * 3600 + minute * 60 + second);
+        days_from_civil(year, month, day)
+            .checked_mul(MICROS_PER_DAY)?
+            .checked_add(seconds * MICROS_PER_SECOND + i64::from(micros))
+            .map(Timestamp)
+    }
+
+    /// Parse `YYYY-MM-DD`, optionally followed by a space or `T` and `HH:MM[:SS[.FFFFFF]]`,
+    /// and optionally `Z`.
+    #[must_use]
+    pub fn parse(text: &str) -> Option<Timestamp> {
+        let text = text.strip_suffix(['Z', 'z']).unwrap_or(text);
+        let (date, time) = match text.find([' ', 'T', 't']) {
+            Some(index) => (&text[..index], Some(&text[index + 1..])),
+            None => (text, None),
+        };
+        let mut date = date.splitn(3, '-');
+        let year = number(date.next()?)?;
+        let month = number(date.next()?)?;
+        let day = number(date.next()?)?;
+        let (mut hour, mut minute, mut second, mut micros) = (0, 0, 0, 0);
+        if let Some(time) = time {
+            let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+            let mut time = time.splitn(3, ':');
+            hour = number(time.next()?)?;
+            minute = number(time.next()?)?;
+            if let Some(seconds) = time.next() {
+                second = number(seconds)?;
+            }
+            if !fraction.is_empty() {
+                // Digits beyond microseconds are dropped
+                let digits = &fraction[..fraction.len().min(6)];
+                micros = number(digits)? * 10u64.pow(u32::try_from(6 - digits.len()).ok()?);
+                if !fraction.chars().all(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+            }
+        }
+        Timestamp::from_parts(
+            i64::try_from(year).ok()?,
+            u32::try_from(month).ok()?,
+            u32::try_from(day).ok()?,
+            u32::try_from(hour).ok()?,
+            u32::try_from(minute).ok()?,
+            u32::try_from(second).ok()?,
+            u32::try_from(micros).ok()?,
+        )
+    }
+}
+
+/// Parse a non-empty run of ASCII digits.
+fn number(text: &str) -> Option<u64> {
+    if text.is_empty() || !text.chars().all(|c| c.is_ascii_digit()) {
+        None
+    } else {
+        text.parse().ok()
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from `1970-01-01` to a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from((month + 9) % 12);
+    let day_of_year = (153 * month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Date of the proleptic Gregorian calendar `days` from `1970-01-01`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+impl std::fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let days = self.0.div_euclid(MICROS_PER_DAY);
+        let micros = self.0.rem_euclid(MICROS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        let seconds = micros / MICROS_PER_SECOND;
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )?;
+        let fraction = micros % MICROS_PER_SECOND;
+        if fraction != 0 {
+            let digits = format!("{fraction:06}");
+            write!(f, ".{}", digits.trim_end_matches('0'))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Timestamp;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_timestamp() {
+        assert_eq!(
+            Timestamp::parse("1970-01-01"),
+            Some(Timestamp::from_micros(0))
+        );
+        assert_eq!(
+            Timestamp::parse("1970-01-02T00:00:01.5Z"),
+            Some(Timestamp::from_micros(86_401_500_000))
+        );
+        let timestamp = Timestamp::parse("2024-02-29 23:59:58.000125").unwrap();
+        assert_eq!(timestamp.to_string(), "2024-02-29 23:59:58.000125");
+        assert_eq!(
+            Timestamp::parse("1969-12-31 23:59").unwrap().to_string(),
+            "1969-12-31 23:59:00"
+        );
+        assert_eq!(
+            Timestamp::parse("1969-12-31 23:59").unwrap().micros(),
+            -60_000_000
+        );
+        assert_eq!(
+            Timestamp::parse("0001-01-01").unwrap().to_string(),
+            "0001-01-01 00:00:00"
+        );
+        assert!(Timestamp::parse("2023-02-29").is_none());
+        assert!(Timestamp::parse("2024-13-01").is_none());
+        assert!(Timestamp::parse("2024-01-01 24:00").is_none());
+        assert!(Timestamp::parse("2024-01-01 12").is_none());
+        assert!(Timestamp::parse("2024-01-01 12:00:00.1x").is_none());
+        assert!(Timestamp::parse("yesterday").is_none());
+        assert!(
+            Timestamp::parse("2024-01-01").unwrap()
+                < Timestamp::parse("2024-01-01 00:00:00.000001").unwrap()
+        );
+    }
+}
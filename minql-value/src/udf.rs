@@ -0,0 +1,598 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use crate::eval::AGGREGATES;
+use crate::{Batch, ScalarFunction, Value, ValueError, ValueResult};
+use minql_lang::ast::DataType;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Rows of arguments an aggregate gathers before handing them to its state.
+const AGGREGATE_BATCH_ROWS: usize = 1024;
+
+/// Callback computing a scalar function over a batch of rows of arguments.
+type ScalarEval = dyn Fn(&Batch) -> ValueResult<Vec<Value>> + Send + Sync;
+
+/// Callback creating the state of an aggregate over a new group.
+type AggregateInit = dyn Fn() -> Box<dyn AggregateState> + Send + Sync;
+
+/// Types of the arguments a user-defined function accepts and of the value it returns.
+///
+/// Arguments are cast to their types before the function is called, and results to the type
+/// returned, as `CAST` does. `NULL` stays `NULL`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Signature {
+    arguments: Vec<DataType>,
+    variadic: bool,
+    returns: DataType,
+}
+
+impl Signature {
+    /// Create a signature of arguments of the types given, in order.
+    #[must_use]
+    pub fn new(arguments: &[DataType], returns: DataType) -> Signature {
+        Signature {
+            arguments: arguments.to_vec(),
+            variadic: false,
+            returns,
+        }
+    }
+
+    /// Create a signature whose last argument may be repeated any number of times.
+    #[must_use]
+    pub fn variadic(arguments: &[DataType], returns: DataType) -> Signature {
+        assert!(!arguments.is_empty(), "Variadic Argument");
+        Signature {
+            variadic: true,
+            ..Signature::new(arguments, returns)
+        }
+    }
+
+    /// Types of the arguments, in order.
+    #[must_use]
+    pub fn arguments(&self) -> &[DataType] {
+        &self.arguments
+    }
+
+    /// Whether the last argument may be repeated.
+    #[must_use]
+    pub fn is_variadic(&self) -> bool {
+        self.variadic
+    }
+
+    /// Type of the value returned.
+    #[must_use]
+    pub fn returns(&self) -> DataType {
+        self.returns
+    }
+
+    /// Check a number of arguments of a call of the function named `function`.
+    fn check_arity(&self, function: &str, count: usize) -> ValueResult<()> {
+        let least = self.arguments.len();
+        if count == least || (self.variadic && count > least) {
+            return Ok(());
+        }
+        let expected = if self.variadic {
+            format!("at least {least}")
+        } else {
+            least.to_string()
+        };
+        Err(ValueError::Function {
+            function: function.to_string(),
+            message: format!("expected {expected} arguments, found {count}"),
+        })
+    }
+
+    /// Batch of columns of arguments cast to their types.
+    fn cast(&self, args: Vec<Vec<Value>>, len: usize) -> ValueResult<Batch> {
+        args.into_iter()
+            .enumerate()
+            .try_fold(Batch::new(len), |batch, (position, column)| {
+                let data_type = self.arguments[position.min(self.arguments.len() - 1)];
+                let column = column
+                    .iter()
+                    .map(|value| value.cast(data_type))
+                    .collect::<ValueResult<_>>()?;
+                Ok(batch.with_column(column))
+            })
+    }
+}
+
+/// Scalar function defined outside the engine, computing its values a batch of rows at a
+/// time.
+///
+/// ```rust
+/// use minql_lang::ast::{BinaryOperator, DataType};
+/// use minql_value::{ScalarUdf, Signature, Value};
+///
+/// let signature = Signature::new(&[DataType::BigInt], DataType::BigInt);
+/// let double = ScalarUdf::new("double", signature, |args| {
+///     let two = Value::from(2);
+///     args.column(0)
+///         .iter()
+///         .map(|value| value.binary(BinaryOperator::Multiply, &two))
+///         .collect()
+/// });
+/// assert_eq!(double.call(&[Value::from("21")]).unwrap(), Value::BigInt(42));
+/// ```
+#[derive(Clone)]
+pub struct ScalarUdf {
+    name: String,
+    signature: Signature,
+    eval: Arc<ScalarEval>,
+}
+
+impl ScalarUdf {
+    /// Create a function of a name, ignoring case, whose `eval` returns a value for each row
+    /// of a batch of arguments cast to the types of `signature`, `NULL`s included.
+    pub fn new(
+        name: &str,
+        signature: Signature,
+        eval: impl Fn(&Batch) -> ValueResult<Vec<Value>> + Send + Sync + 'static,
+    ) -> ScalarUdf {
+        ScalarUdf {
+            name: name.to_ascii_lowercase(),
+            signature,
+            eval: Arc::new(eval),
+        }
+    }
+
+    /// Name of the function, in lower case.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Types of the arguments and result.
+    #[must_use]
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// Check a number of arguments against the signature.
+    pub fn check_arity(&self, count: usize) -> ValueResult<()> {
+        self.signature.check_arity(&self.name, count)
+    }
+
+    /// Call the function over a single row of arguments.
+    pub fn call(&self, args: &[Value]) -> ValueResult<Value> {
+        let args = args.iter().map(|value| vec![value.clone()]).collect();
+        let mut values = self.call_batch(args, 1)?;
+        Ok(values.pop().expect("Row Value"))
+    }
+
+    /// Call the function over `len` rows of arguments, given a column at a time.
+    pub fn call_batch(&self, args: Vec<Vec<Value>>, len: usize) -> ValueResult<Vec<Value>> {
+        self.check_arity(args.len())?;
+        let batch = self.signature.cast(args, len)?;
+        let values = (self.eval)(&batch)?;
+        if values.len() != len {
+            return Err(ValueError::Function {
+                function: self.name.clone(),
+                message: format!("returned {} values for {len} rows", values.len()),
+            });
+        }
+        values
+            .iter()
+            .map(|value| value.cast(self.signature.returns))
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for ScalarUdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScalarUdf")
+            .field("name", &self.name)
+            .field("signature", &self.signature)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for ScalarUdf {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.signature == other.signature
+            && std::ptr::addr_eq(Arc::as_ptr(&self.eval), Arc::as_ptr(&other.eval))
+    }
+}
+
+/// Running value of a user-defined aggregate over the rows of a group.
+pub trait AggregateState: Send + std::fmt::Debug {
+    /// Add a batch of rows of arguments of the group, cast to the types of the signature,
+    /// leaving out rows with any argument `NULL`.
+    fn update(&mut self, args: &Batch) -> ValueResult<()>;
+
+    /// Value of the aggregate over the rows added.
+    fn finish(&mut self) -> ValueResult<Value>;
+}
+
+/// Aggregate function defined outside the engine, whose [`AggregateState`] is handed the
+/// rows of a group a batch at a time.
+///
+/// ```rust
+/// use minql_lang::ast::DataType;
+/// use minql_value::{AggregateState, AggregateUdf, Batch, Signature, Value, ValueResult};
+///
+/// /// Product of the values of a group.
+/// #[derive(Debug)]
+/// struct Product(f64);
+///
+/// impl AggregateState for Product {
+///     fn update(&mut self, args: &Batch) -> ValueResult<()> {
+///         for value in args.column(0) {
+///             if let Value::Double(value) = value {
+///                 self.0 *= value;
+///             }
+///         }
+///         Ok(())
+///     }
+///
+///     fn finish(&mut self) -> ValueResult<Value> {
+///         Ok(Value::Double(self.0))
+///     }
+/// }
+///
+/// let product = AggregateUdf::new(
+///     "product",
+///     Signature::new(&[DataType::Double], DataType::Double),
+///     || Box::new(Product(1.0)),
+/// );
+/// let mut accumulator = product.accumulator();
+/// for value in [Value::from(2), Value::Null, Value::from(2.5)] {
+///     accumulator.add(vec![value]).unwrap();
+/// }
+/// assert_eq!(accumulator.finish().unwrap(), Value::Double(5.0));
+/// ```
+#[derive(Clone)]
+pub struct AggregateUdf {
+    name: String,
+    signature: Signature,
+    init: Arc<AggregateInit>,
+}
+
+impl AggregateUdf {
+    /// Create an aggregate of a name, ignoring case, whose `init` creates the state of each
+    /// group.
+    pub fn new(
+        name: &str,
+        signature: Signature,
+        init: impl Fn() -> Box<dyn AggregateState> + Send + Sync + 'static,
+    ) -> AggregateUdf {
+        AggregateUdf {
+            name: name.to_ascii_lowercase(),
+            signature,
+            init: Arc::new(init),
+        }
+    }
+
+    /// Name of the aggregate, in lower case.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Types of the arguments and result.
+    #[must_use]
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// Check a number of arguments against the signature.
+    pub fn check_arity(&self, count: usize) -> ValueResult<()> {
+        self.signature.check_arity(&self.name, count)
+    }
+
+    /// Accumulator of the aggregate over a new group.
+    #[must_use]
+    pub fn accumulator(&self) -> UdfAccumulator {
+        UdfAccumulator {
+            aggregate: self.clone(),
+            state: (self.init)(),
+            pending: Vec::new(),
+            rows: 0,
+        }
+    }
+}
+
+impl std::fmt::Debug for AggregateUdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AggregateUdf")
+            .field("name", &self.name)
+            .field("signature", &self.signature)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for AggregateUdf {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.signature == other.signature
+            && std::ptr::addr_eq(Arc::as_ptr(&self.init), Arc::as_ptr(&other.init))
+    }
+}
+
+impl Eq for AggregateUdf {}
+
+impl std::hash::Hash for AggregateUdf {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+/// Rows of a group added to the state of a user-defined aggregate, gathered into batches.
+#[derive(Debug)]
+pub struct UdfAccumulator {
+    aggregate: AggregateUdf,
+    state: Box<dyn AggregateState>,
+    /// Arguments of the rows not yet added to the state, a column each
+    pending: Vec<Vec<Value>>,
+    rows: usize,
+}
+
+impl UdfAccumulator {
+    /// Add the arguments of a row, skipping it if any is `NULL`.
+    pub fn add(&mut self, args: Vec<Value>) -> ValueResult<()> {
+        if args.iter().any(Value::is_null) {
+            return Ok(());
+        }
+        self.aggregate.check_arity(args.len())?;
+        self.pending.resize_with(args.len(), Vec::new);
+        for (column, value) in self.pending.iter_mut().zip(args) {
+            column.push(value);
+        }
+        self.rows += 1;
+        if self.rows == AGGREGATE_BATCH_ROWS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Value of the aggregate over the rows added.
+    pub fn finish(mut self) -> ValueResult<Value> {
+        self.flush()?;
+        self.state.finish()?.cast(self.aggregate.signature.returns)
+    }
+
+    fn flush(&mut self) -> ValueResult<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let args = std::mem::take(&mut self.pending);
+        let batch = self.aggregate.signature.cast(args, self.rows)?;
+        self.rows = 0;
+        self.state.update(&batch)
+    }
+}
+
+/// User-defined functions callable from SQL, alongside the built-in ones.
+///
+/// Names of built-in functions can't be taken, and registering a function of a name already
+/// registered replaces it.
+///
+/// ```rust
+/// use minql_lang::ast::DataType;
+/// use minql_lang::Parser;
+/// use minql_value::{Binder, FunctionRegistry, ScalarUdf, Scope, Signature, Value};
+///
+/// let mut functions = FunctionRegistry::new();
+/// let signature = Signature::new(&[DataType::Text], DataType::Text);
+/// functions
+///     .register_scalar(ScalarUdf::new("reverse", signature, |args| {
+///         Ok(args.column(0).iter().map(|value| match value {
+///             Value::Text(text) => Value::Text(text.chars().rev().collect()),
+///             value => value.clone(),
+///         }).collect())
+///     }))
+///     .unwrap();
+///
+/// let scope = Scope::new();
+/// let expr = Parser::parse_expr("upper(reverse('tea'))").unwrap();
+/// let expr = Binder::new(&scope).with_functions(&functions).bind(&expr).unwrap();
+/// assert_eq!(expr.eval(&[], &[]).unwrap(), Value::from("AET"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FunctionRegistry {
+    scalars: HashMap<String, ScalarUdf>,
+    aggregates: HashMap<String, AggregateUdf>,
+}
+
+impl FunctionRegistry {
+    /// Create a registry without functions.
+    #[must_use]
+    pub fn new() -> FunctionRegistry {
+        FunctionRegistry::default()
+    }
+
+    /// Register a scalar function, replacing any function of its name.
+    pub fn register_scalar(&mut self, function: ScalarUdf) -> ValueResult<()> {
+        check_name(function.name())?;
+        self.aggregates.remove(function.name());
+        self.scalars.insert(function.name().to_string(), function);
+        Ok(())
+    }
+
+    /// Register an aggregate function, replacing any function of its name.
+    pub fn register_aggregate(&mut self, function: AggregateUdf) -> ValueResult<()> {
+        check_name(function.name())?;
+        self.scalars.remove(function.name());
+        self.aggregates
+            .insert(function.name().to_string(), function);
+        Ok(())
+    }
+
+    /// Remove the function of a name, ignoring case, returning whether there was one.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        self.scalars.remove(&name).is_some() | self.aggregates.remove(&name).is_some()
+    }
+
+    /// Scalar function of a name, ignoring case.
+    #[must_use]
+    pub fn scalar(&self, name: &str) -> Option<&ScalarUdf> {
+        self.scalars.get(&name.to_ascii_lowercase())
+    }
+
+    /// Aggregate function of a name, ignoring case.
+    #[must_use]
+    pub fn aggregate(&self, name: &str) -> Option<&AggregateUdf> {
+        self.aggregates.get(&name.to_ascii_lowercase())
+    }
+
+    /// Whether no functions are registered.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.scalars.is_empty() && self.aggregates.is_empty()
+    }
+}
+
+/// Check a name isn't taken by a built-in function.
+fn check_name(name: &str) -> ValueResult<()> {
+    if name.is_empty() || ScalarFunction::lookup(name).is_some() || AGGREGATES.contains(&name) {
+        return Err(ValueError::Function {
+            function: name.to_string(),
+            message: "name of a built-in function".to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        AggregateState, AggregateUdf, Batch, FunctionRegistry, ScalarUdf, Signature, Value,
+        ValueError, ValueResult,
+    };
+    use minql_lang::ast::DataType;
+
+    /// Count of the batches and rows added.
+    #[derive(Debug, Default)]
+    struct Batches(i64, i64);
+
+    impl AggregateState for Batches {
+        fn update(&mut self, args: &Batch) -> ValueResult<()> {
+            assert!(args.column(0).iter().all(|value| !value.is_null()));
+            self.0 += 1;
+            self.1 += i64::try_from(args.len()).unwrap();
+            Ok(())
+        }
+
+        fn finish(&mut self) -> ValueResult<Value> {
+            Ok(Value::Text(format!("{}/{}", self.0, self.1)))
+        }
+    }
+
+    fn join() -> ScalarUdf {
+        let signature = Signature::variadic(&[DataType::Text], DataType::Text);
+        ScalarUdf::new("Join", signature, |args| {
+            Ok((0..args.len())
+                .map(|row| {
+                    let words: Vec<String> = (0..args.width())
+                        .map(|column| args.column(column)[row].to_string())
+                        .collect();
+                    Value::Text(words.join("-"))
+                })
+                .collect())
+        })
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_scalar_udf() {
+        let join = join();
+        assert_eq!(join.name(), "join");
+        assert_eq!(
+            join.call(&[Value::from(1), Value::from("a")]),
+            Ok(Value::from("1-a"))
+        );
+        assert_eq!(
+            join.call_batch(vec![vec![Value::from(1), Value::from(2)]], 2),
+            Ok(vec![Value::from("1"), Value::from("2")])
+        );
+        assert!(matches!(
+            join.call(&[]),
+            Err(ValueError::Function { function, .. }) if function == "join"
+        ));
+
+        let broken = ScalarUdf::new("broken", Signature::new(&[], DataType::BigInt), |_| {
+            Ok(vec![Value::from("many"), Value::from("values")])
+        });
+        assert!(matches!(broken.call(&[]), Err(ValueError::Function { .. })));
+        let text = ScalarUdf::new("text", Signature::new(&[], DataType::BigInt), |args| {
+            Ok(vec![Value::from("x"); args.len()])
+        });
+        assert!(matches!(
+            text.call(&[]),
+            Err(ValueError::InvalidCast { .. })
+        ));
+        assert_eq!(join.clone(), join);
+        assert_ne!(join, self::join());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_aggregate_udf() {
+        let batches = AggregateUdf::new(
+            "batches",
+            Signature::new(&[DataType::BigInt], DataType::Text),
+            || Box::new(Batches::default()),
+        );
+        let mut accumulator = batches.accumulator();
+        for value in 0..2500 {
+            let value = if value % 5 == 0 {
+                Value::Null
+            } else {
+                Value::from(value)
+            };
+            accumulator.add(vec![value]).unwrap();
+        }
+        assert_eq!(accumulator.finish(), Ok(Value::from("2/2000")));
+        assert_eq!(batches.accumulator().finish(), Ok(Value::from("0/0")));
+        let mut accumulator = batches.accumulator();
+        accumulator.add(vec![Value::from("x")]).unwrap();
+        assert!(matches!(
+            accumulator.finish(),
+            Err(ValueError::InvalidCast { .. })
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_function_registry() {
+        let mut functions = FunctionRegistry::new();
+        assert!(functions.is_empty());
+        functions.register_scalar(join()).unwrap();
+        assert!(functions.scalar("JOIN").is_some());
+        for name in ["upper", "sum"] {
+            let function = ScalarUdf::new(name, Signature::new(&[], DataType::Text), |args| {
+                Ok(vec![Value::Null; args.len()])
+            });
+            assert!(matches!(
+                functions.register_scalar(function),
+                Err(ValueError::Function { .. })
+            ));
+        }
+        let aggregate = AggregateUdf::new(
+            "join",
+            Signature::new(&[DataType::Text], DataType::Text),
+            || Box::new(Batches::default()),
+        );
+        functions.register_aggregate(aggregate).unwrap();
+        assert!(functions.scalar("join").is_none());
+        assert!(functions.aggregate("join").is_some());
+        assert!(functions.unregister("Join"));
+        assert!(!functions.unregister("join"));
+        assert!(functions.is_empty());
+    }
+}
@@ -0,0 +1,1151 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{Decimal, Timestamp, ValueError, ValueResult};
+use minql_lang::ast::{BinaryOperator, DataType, Literal, UnaryOperator};
+use std::cmp::Ordering;
+
+/// Type of a non-null [`Value`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ValueType {
+    /// `BOOLEAN`
+    Boolean,
+    /// `SMALLINT`
+    SmallInt,
+    /// `INTEGER`
+    Integer,
+    /// `BIGINT`
+    BigInt,
+    /// `REAL`
+    Real,
+    /// `DOUBLE`
+    Double,
+    /// `DECIMAL`
+    Decimal,
+    /// `TEXT`
+    Text,
+    /// `BLOB`
+    Blob,
+    /// `TIMESTAMP`
+    Timestamp,
+}
+
+impl ValueType {
+    /// Whether the type is a number.
+    #[must_use]
+    pub fn is_numeric(self) -> bool {
+        self.numeric_rank().is_some()
+    }
+
+    /// Whether the type is an integer.
+    #[must_use]
+    pub fn is_integer(self) -> bool {
+        matches!(
+            self,
+            ValueType::SmallInt | ValueType::Integer | ValueType::BigInt
+        )
+    }
+
+    /// Type values of both types are implicitly converted to when used together, if any.
+    ///
+    /// Numbers widen to the type that holds both, with floats winning over exact numbers, and
+    /// text converts to timestamps.
+    #[must_use]
+    pub fn common(self, other: ValueType) -> Option<ValueType> {
+        if self == other {
+            return Some(self);
+        }
+        match (self.numeric_rank(), other.numeric_rank()) {
+            (Some(left), Some(right)) => {
+                let (narrow, wide) = if left < right {
+                    (self, other)
+                } else {
+                    (other, self)
+                };
+                // A real can't hold every integer or decimal
+                if wide == ValueType::Real && narrow != ValueType::SmallInt {
+                    Some(ValueType::Double)
+                } else {
+                    Some(wide)
+                }
+            }
+            _ => match (self, other) {
+                (ValueType::Text, ValueType::Timestamp)
+                | (ValueType::Timestamp, ValueType::Text) => Some(ValueType::Timestamp),
+                _ => None,
+            },
+        }
+    }
+
+    /// Column type holding values of this type.
+    #[must_use]
+    pub fn data_type(self) -> DataType {
+        match self {
+            ValueType::Boolean => DataType::Boolean,
+            ValueType::SmallInt => DataType::SmallInt,
+            ValueType::Integer => DataType::Integer,
+            ValueType::BigInt => DataType::BigInt,
+            ValueType::Real => DataType::Real,
+            ValueType::Double => DataType::Double,
+            ValueType::Decimal => DataType::Decimal(None),
+            ValueType::Text => DataType::Text,
+            ValueType::Blob => DataType::Blob,
+            ValueType::Timestamp => DataType::Timestamp,
+        }
+    }
+
+    fn numeric_rank(self) -> Option<u8> {
+        match self {
+            ValueType::SmallInt => Some(0),
+            ValueType::Integer => Some(1),
+            ValueType::BigInt => Some(2),
+            ValueType::Decimal => Some(3),
+            ValueType::Real => Some(4),
+            ValueType::Double => Some(5),
+            _ => None,
+        }
+    }
+}
+
+impl From<DataType> for ValueType {
+    fn from(data_type: DataType) -> Self {
+        match data_type {
+            DataType::Boolean => ValueType::Boolean,
+            DataType::SmallInt => ValueType::SmallInt,
+            DataType::Integer => ValueType::Integer,
+            DataType::BigInt => ValueType::BigInt,
+            DataType::Real => ValueType::Real,
+            DataType::Double => ValueType::Double,
+            DataType::Decimal(_) => ValueType::Decimal,
+            DataType::Text | DataType::Varchar(_) => ValueType::Text,
+            DataType::Blob => ValueType::Blob,
+            DataType::Timestamp => ValueType::Timestamp,
+        }
+    }
+}
+
+impl std::fmt::Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.data_type(), f)
+    }
+}
+
+/// SQL Value
+///
+/// Values of different numeric types are equal when their numbers are, so `1`, `1.0` and
+/// `CAST(1 AS BIGINT)` compare and hash alike. Ordering is total, for sorting and keys: `NULL`
+/// sorts first, `NaN` above all other numbers, and values of unrelated types by type. SQL
+/// comparison, where `NULL` compares unknown and unrelated types are an error, is
+/// [`Value::compare`].
+#[derive(Clone, Debug)]
+pub enum Value {
+    /// Unknown or missing value
+    Null,
+    /// `BOOLEAN`
+    Boolean(bool),
+    /// `SMALLINT`
+    SmallInt(i16),
+    /// `INTEGER`
+    Integer(i32),
+    /// `BIGINT`
+    BigInt(i64),
+    /// `REAL`
+    Real(f32),
+    /// `DOUBLE`
+    Double(f64),
+    /// `DECIMAL`
+    Decimal(Decimal),
+    /// `TEXT` or `VARCHAR`
+    Text(String),
+    /// `BLOB`
+    Blob(Vec<u8>),
+    /// `TIMESTAMP`
+    Timestamp(Timestamp),
+}
+
+/// Number of any numeric value, widened for comparison.
+enum Number {
+    Integer(i64),
+    Decimal(Decimal),
+    Float(f64),
+}
+
+impl Value {
+    /// Type of the value, or `None` for `NULL`.
+    #[must_use]
+    pub fn value_type(&self) -> Option<ValueType> {
+        match self {
+            Value::Null => None,
+            Value::Boolean(_) => Some(ValueType::Boolean),
+            Value::SmallInt(_) => Some(ValueType::SmallInt),
+            Value::Integer(_) => Some(ValueType::Integer),
+            Value::BigInt(_) => Some(ValueType::BigInt),
+            Value::Real(_) => Some(ValueType::Real),
+            Value::Double(_) => Some(ValueType::Double),
+            Value::Decimal(_) => Some(ValueType::Decimal),
+            Value::Text(_) => Some(ValueType::Text),
+            Value::Blob(_) => Some(ValueType::Blob),
+            Value::Timestamp(_) => Some(ValueType::Timestamp),
+        }
+    }
+
+    /// Whether the value is `NULL`.
+    #[must_use]
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Whether the value is `TRUE`, so a row passes a `WHERE` condition.
+    #[must_use]
+    pub fn is_true(&self) -> bool {
+        matches!(self, Value::Boolean(true))
+    }
+
+    /// Truth of a boolean, or `None` for `NULL`.
+    pub fn as_bool(&self) -> ValueResult<Option<bool>> {
+        match self {
+            Value::Null => Ok(None),
+            Value::Boolean(value) => Ok(Some(*value)),
+            value => Err(value.mismatch("boolean")),
+        }
+    }
+
+    /// Value of a literal, typed as `INTEGER` or `BIGINT` if an integer fits, `DECIMAL` if a
+    /// number without an exponent fits, and `DOUBLE` otherwise.
+    pub fn from_literal(literal: &Literal) -> ValueResult<Value> {
+        Ok(match literal {
+            Literal::Null => Value::Null,
+            Literal::Boolean(value) => Value::Boolean(*value),
+            Literal::Integer(text) => match text.parse::<i64>() {
+                Ok(value) => match i32::try_from(value) {
+                    Ok(value) => Value::Integer(value),
+                    Err(_) => Value::BigInt(value),
+                },
+                Err(_) => Value::Decimal(
+                    Decimal::parse(text).ok_or(ValueError::Overflow(ValueType::Decimal))?,
+                ),
+            },
+            Literal::Float(text) => match Decimal::parse(text) {
+                Some(decimal) if !text.contains(['e', 'E']) => Value::Decimal(decimal),
+                _ => Value::Double(text.parse().map_err(|_| ValueError::InvalidCast {
+                    value: Value::Text(text.clone()),
+                    data_type: DataType::Double,
+                })?),
+            },
+            Literal::String(text) => Value::Text(text.clone()),
+            Literal::Blob(digits) => Value::Blob(
+                (0..digits.len())
+                    .step_by(2)
+                    .map(|index| u8::from_str_radix(&digits[index..index + 2], 16))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| ValueError::InvalidCast {
+                        value: Value::Text(digits.clone()),
+                        data_type: DataType::Blob,
+                    })?,
+            ),
+        })
+    }
+
+    /// Convert to a type, as `CAST` does.
+    ///
+    /// Numbers round to fit integer and decimal types, failing if too large, and text is
+    /// parsed. `VARCHAR(n)` truncates to `n` characters. `NULL` stays `NULL`.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn cast(&self, data_type: DataType) -> ValueResult<Value> {
+        let invalid = || ValueError::InvalidCast {
+            value: self.clone(),
+            data_type,
+        };
+        if self.is_null() {
+            return Ok(Value::Null);
+        }
+        Ok(match data_type {
+            DataType::Boolean => Value::Boolean(match self {
+                Value::Boolean(value) => *value,
+                Value::Text(text) => match text.trim().to_ascii_lowercase().as_str() {
+                    "true" | "t" | "yes" | "y" | "on" | "1" => true,
+                    "false" | "f" | "no" | "n" | "off" | "0" => false,
+                    _ => return Err(invalid()),
+                },
+                value => match value.number() {
+                    Some(Number::Integer(value)) => value != 0,
+                    _ => return Err(invalid()),
+                },
+            }),
+            DataType::SmallInt | DataType::Integer | DataType::BigInt => {
+                let value_type = ValueType::from(data_type);
+                let overflow = ValueError::Overflow(value_type);
+                let value = match self {
+                    Value::Boolean(value) => i64::from(*value),
+                    Value::Text(text) => match text.trim().parse::<i64>() {
+                        Ok(value) => value,
+                        Err(_) => Decimal::parse(text.trim())
+                            .ok_or_else(invalid)?
+                            .to_i64()
+                            .ok_or(overflow)?,
+                    },
+                    value => match value.number().ok_or_else(invalid)? {
+                        Number::Integer(value) => value,
+                        Number::Decimal(value) => value.to_i64().ok_or(overflow)?,
+                        Number::Float(value) => float_to_i64(value).ok_or(overflow)?,
+                    },
+                };
+                Value::integer(value, value_type)?
+            }
+            DataType::Real => {
+                let value = self.to_f64().ok_or_else(invalid)?;
+                let real = value as f32;
+                if real.is_infinite() && value.is_finite() {
+                    return Err(ValueError::Overflow(ValueType::Real));
+                }
+                Value::Real(real)
+            }
+            DataType::Double => Value::Double(self.to_f64().ok_or_else(invalid)?),
+            DataType::Decimal(spec) => {
+                let value = match self {
+                    Value::Text(text) => Decimal::parse(text.trim()),
+                    value => match value.number() {
+                        Some(Number::Integer(value)) => Some(Decimal::from(value)),
+                        Some(Number::Decimal(value)) => Some(value),
+                        Some(Number::Float(value)) => Decimal::from_f64(value),
+                        None => None,
+                    },
+                }
+                .ok_or_else(invalid)?;
+                match spec {
+                    None => Value::Decimal(value),
+                    Some((precision, scale)) => {
+                        let overflow = ValueError::Overflow(ValueType::Decimal);
+                        let value = value.round(scale.unwrap_or(0)).ok_or(overflow.clone())?;
+                        if value.precision() > precision && !value.is_zero() {
+                            return Err(overflow);
+                        }
+                        Value::Decimal(value)
+                    }
+                }
+            }
+            DataType::Text => match self {
+                Value::Text(text) => Value::Text(text.clone()),
+                value => Value::Text(value.to_string()),
+            },
+            DataType::Varchar(length) => {
+                let Value::Text(text) = self.cast(DataType::Text)? else {
+                    unreachable!("text cast returns text")
+                };
+                match length {
+                    Some(length) => Value::Text(text.chars().take(length as usize).collect()),
+                    None => Value::Text(text),
+                }
+            }
+            DataType::Blob => match self {
+                Value::Blob(bytes) => Value::Blob(bytes.clone()),
+                Value::Text(text) => Value::Blob(text.as_bytes().to_vec()),
+                _ => return Err(invalid()),
+            },
+            DataType::Timestamp => match self {
+                Value::Timestamp(timestamp) => Value::Timestamp(*timestamp),
+                Value::Text(text) => {
+                    Value::Timestamp(Timestamp::parse(text.trim()).ok_or_else(invalid)?)
+                }
+                _ => return Err(invalid()),
+            },
+        })
+    }
+
+    /// Convert implicitly to a type common to this value's, see [`ValueType::common`].
+    pub fn coerce(&self, value_type: ValueType) -> ValueResult<Value> {
+        match self.value_type() {
+            None => Ok(Value::Null),
+            Some(found) if found == value_type => Ok(self.clone()),
+            Some(found) if found.common(value_type) == Some(value_type) => {
+                self.cast(value_type.data_type())
+            }
+            Some(found) => Err(ValueError::TypeMismatch {
+                operation: "coercion",
+                found,
+            }),
+        }
+    }
+
+    /// Compare as SQL does, converting both values to their common type, or `None` if either
+    /// is `NULL`.
+    pub fn compare(&self, other: &Value) -> ValueResult<Option<Ordering>> {
+        self.compare_with(other, "comparison")
+    }
+
+    /// Apply a prefix operator.
+    pub fn unary(&self, op: UnaryOperator) -> ValueResult<Value> {
+        match (op, self) {
+            (_, Value::Null) => Ok(Value::Null),
+            (UnaryOperator::Not, value) => Ok(value
+                .as_bool()?
+                .map_or(Value::Null, |value| Value::Boolean(!value))),
+            (UnaryOperator::Plus, value) if value.number().is_some() => Ok(value.clone()),
+            (UnaryOperator::Minus, Value::Decimal(value)) => Ok(Value::Decimal(value.neg())),
+            (UnaryOperator::Minus, Value::Real(value)) => Ok(Value::Real(-value)),
+            (UnaryOperator::Minus, Value::Double(value)) => Ok(Value::Double(-value)),
+            (UnaryOperator::Minus, value) => match value.number() {
+                Some(Number::Integer(number)) => {
+                    let value_type = value.value_type().expect("Non-null Value");
+                    Value::integer(
+                        number
+                            .checked_neg()
+                            .ok_or(ValueError::Overflow(value_type))?,
+                        value_type,
+                    )
+                }
+                _ => Err(value.mismatch("-")),
+            },
+            (UnaryOperator::Plus, value) => Err(value.mismatch("+")),
+        }
+    }
+
+    /// Apply an infix operator, with three valued logic for `AND` and `OR`.
+    pub fn binary(&self, op: BinaryOperator, other: &Value) -> ValueResult<Value> {
+        match op {
+            BinaryOperator::And | BinaryOperator::Or => {
+                let (left, right) = (self.as_bool()?, other.as_bool()?);
+                // The operand deciding the result wins over an unknown one
+                let decisive = op == BinaryOperator::Or;
+                Ok(match (left, right) {
+                    (Some(value), _) | (_, Some(value)) if value == decisive => {
+                        Value::Boolean(decisive)
+                    }
+                    (Some(_), Some(_)) => Value::Boolean(!decisive),
+                    _ => Value::Null,
+                })
+            }
+            BinaryOperator::Eq
+            | BinaryOperator::NotEq
+            | BinaryOperator::Lt
+            | BinaryOperator::LtEq
+            | BinaryOperator::Gt
+            | BinaryOperator::GtEq => Ok(match self.compare_with(other, op.as_str())? {
+                None => Value::Null,
+                Some(ordering) => Value::Boolean(match op {
+                    BinaryOperator::Eq => ordering.is_eq(),
+                    BinaryOperator::NotEq => ordering.is_ne(),
+                    BinaryOperator::Lt => ordering.is_lt(),
+                    BinaryOperator::LtEq => ordering.is_le(),
+                    BinaryOperator::Gt => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                }),
+            }),
+            BinaryOperator::Concat => match (self, other) {
+                (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+                (Value::Blob(left), Value::Blob(right)) => {
+                    Ok(Value::Blob([&left[..], right].concat()))
+                }
+                (Value::Blob(_), _) | (_, Value::Blob(_)) => Err(self.incompatible(other, "||")),
+                (left, right) => Ok(Value::Text(format!("{left}{right}"))),
+            },
+            BinaryOperator::Plus
+            | BinaryOperator::Minus
+            | BinaryOperator::Multiply
+            | BinaryOperator::Divide
+            | BinaryOperator::Modulo => self.arithmetic(op, other),
+        }
+    }
+
+    fn compare_with(
+        &self,
+        other: &Value,
+        operation: &'static str,
+    ) -> ValueResult<Option<Ordering>> {
+        let (Some(left), Some(right)) = (self.value_type(), other.value_type()) else {
+            return Ok(None);
+        };
+        match left.common(right) {
+            Some(common) if common.is_numeric() || common == left && common == right => {
+                Ok(Some(self.cmp(other)))
+            }
+            Some(common) => Ok(Some(self.coerce(common)?.cmp(&other.coerce(common)?))),
+            None => Err(self.incompatible(other, operation)),
+        }
+    }
+
+    fn arithmetic(&self, op: BinaryOperator, other: &Value) -> ValueResult<Value> {
+        let (Some(left), Some(right)) = (self.value_type(), other.value_type()) else {
+            return Ok(Value::Null);
+        };
+        let operation = op.as_str();
+        let value_type = left
+            .common(right)
+            .filter(|common| common.is_numeric())
+            .ok_or_else(|| self.incompatible(other, operation))?;
+        let overflow = ValueError::Overflow(value_type);
+        match (self.coerce(value_type)?, other.coerce(value_type)?) {
+            (Value::Decimal(left), Value::Decimal(right)) => {
+                if right.is_zero() && matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo)
+                {
+                    return Err(ValueError::DivisionByZero);
+                }
+                let result = match op {
+                    BinaryOperator::Plus => left.checked_add(&right),
+                    BinaryOperator::Minus => left.checked_sub(&right),
+                    BinaryOperator::Multiply => left.checked_mul(&right),
+                    BinaryOperator::Divide => left.checked_div(&right),
+                    _ => left.checked_rem(&right),
+                };
+                result.map(Value::Decimal).ok_or(overflow)
+            }
+            (Value::Real(left), Value::Real(right)) => {
+                let result = float_arithmetic(op, f64::from(left), f64::from(right))?;
+                #[allow(clippy::cast_possible_truncation)]
+                let real = result as f32;
+                if real.is_infinite() && !(left.is_infinite() || right.is_infinite()) {
+                    return Err(overflow);
+                }
+                Ok(Value::Real(real))
+            }
+            (Value::Double(left), Value::Double(right)) => {
+                let result = float_arithmetic(op, left, right)?;
+                if result.is_infinite() && !(left.is_infinite() || right.is_infinite()) {
+                    return Err(overflow);
+                }
+                Ok(Value::Double(result))
+            }
+            (left, right) => {
+                let (Some(Number::Integer(left)), Some(Number::Integer(right))) =
+                    (left.number(), right.number())
+                else {
+                    unreachable!("numeric values of a common type")
+                };
+                if right == 0 && matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo) {
+                    return Err(ValueError::DivisionByZero);
+                }
+                let result = match op {
+                    BinaryOperator::Plus => left.checked_add(right),
+                    BinaryOperator::Minus => left.checked_sub(right),
+                    BinaryOperator::Multiply => left.checked_mul(right),
+                    BinaryOperator::Divide => left.checked_div(right),
+                    _ => left.checked_rem(right),
+                };
+                Value::integer(result.ok_or(overflow)?, value_type)
+            }
+        }
+    }
+
+    /// Integer of an integer type, if it fits.
+    pub(crate) fn integer(value: i64, value_type: ValueType) -> ValueResult<Value> {
+        let overflow = || ValueError::Overflow(value_type);
+        Ok(match value_type {
+            ValueType::SmallInt => Value::SmallInt(i16::try_from(value).map_err(|_| overflow())?),
+            ValueType::Integer => Value::Integer(i32::try_from(value).map_err(|_| overflow())?),
+            _ => Value::BigInt(value),
+        })
+    }
+
+    /// Number as a float, if numeric.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn to_f64(&self) -> Option<f64> {
+        match self {
+            Value::Text(text) => text.trim().parse().ok(),
+            value => match value.number()? {
+                Number::Integer(value) => Some(value as f64),
+                Number::Decimal(value) => Some(value.to_f64()),
+                Number::Float(value) => Some(value),
+            },
+        }
+    }
+
+    /// Error for an operation not accepting this value.
+    pub(crate) fn mismatch(&self, operation: &'static str) -> ValueError {
+        match self.value_type() {
+            Some(found) => ValueError::TypeMismatch { operation, found },
+            None => unreachable!("NULL is accepted everywhere"),
+        }
+    }
+
+    fn incompatible(&self, other: &Value, operation: &'static str) -> ValueError {
+        ValueError::IncompatibleTypes {
+            operation,
+            left: self.value_type().expect("Non-null Value"),
+            right: other.value_type().expect("Non-null Value"),
+        }
+    }
+
+    fn number(&self) -> Option<Number> {
+        match self {
+            Value::SmallInt(value) => Some(Number::Integer(i64::from(*value))),
+            Value::Integer(value) => Some(Number::Integer(i64::from(*value))),
+            Value::BigInt(value) => Some(Number::Integer(*value)),
+            Value::Real(value) => Some(Number::Float(f64::from(*value))),
+            Value::Double(value) => Some(Number::Float(*value)),
+            Value::Decimal(value) => Some(Number::Decimal(*value)),
+            _ => None,
+        }
+    }
+
+    /// Rank of the type class in the total order.
+    fn rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Boolean(_) => 1,
+            Value::SmallInt(_)
+            | Value::Integer(_)
+            | Value::BigInt(_)
+            | Value::Real(_)
+            | Value::Double(_)
+            | Value::Decimal(_) => 2,
+            Value::Text(_) => 3,
+            Value::Blob(_) => 4,
+            Value::Timestamp(_) => 5,
+        }
+    }
+}
+
+fn float_arithmetic(op: BinaryOperator, left: f64, right: f64) -> ValueResult<f64> {
+    if right == 0.0 && matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo) {
+        return Err(ValueError::DivisionByZero);
+    }
+    Ok(match op {
+        BinaryOperator::Plus => left + right,
+        BinaryOperator::Minus => left - right,
+        BinaryOperator::Multiply => left * right,
+        BinaryOperator::Divide => left / right,
+        _ => left % right,
+    })
+}
+
+/// Round a float to the nearest integer, if it fits.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn float_to_i64(value: f64) -> Option<i64> {
+    let value = value.round();
+    if value >= i64::MIN as f64 && value < i64::MAX as f64 {
+        Some(value as i64)
+    } else {
+        None
+    }
+}
+
+/// Total order of floats, with `NaN` above all others and `-0.0` equal to `0.0`.
+fn float_cmp(left: f64, right: f64) -> Ordering {
+    match (left.is_nan(), right.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => left.partial_cmp(&right).expect("Non-NaN Float"),
+    }
+}
+
+/// Exact order of an integer and a float, with `NaN` above all integers.
+///
+/// Converting the integer to a float would round integers beyond 2^53 onto floats they
+/// differ from, so the float is split into its integral part, compared as an integer when in
+/// range, and its fraction.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn integer_float_cmp(left: i64, right: f64) -> Ordering {
+    // The range of `i64` is -2^63 up to but excluding 2^63, both of which convert exactly.
+    let bound = i64::MIN as f64;
+    if right.is_nan() || right >= -bound {
+        return Ordering::Less;
+    }
+    if right < bound {
+        return Ordering::Greater;
+    }
+    let integral = i128::from(right.trunc() as i64);
+    i128::from(left)
+        .cmp(&integral)
+        .then_with(|| float_cmp(0.0, right.fract()))
+}
+
+impl Number {
+    fn cmp(&self, other: &Number) -> Ordering {
+        match (self, other) {
+            (Number::Integer(left), Number::Integer(right)) => left.cmp(right),
+            (Number::Float(left), Number::Float(right)) => float_cmp(*left, *right),
+            (Number::Float(left), Number::Integer(right)) => {
+                integer_float_cmp(*right, *left).reverse()
+            }
+            (Number::Integer(left), Number::Float(right)) => integer_float_cmp(*left, *right),
+            (Number::Float(left), Number::Decimal(right)) => float_cmp(*left, right.to_f64()),
+            (Number::Decimal(left), Number::Float(right)) => float_cmp(left.to_f64(), *right),
+            (Number::Decimal(left), Number::Integer(right)) => left.cmp(&Decimal::from(*right)),
+            (Number::Integer(left), Number::Decimal(right)) => Decimal::from(*left).cmp(right),
+            (Number::Decimal(left), Number::Decimal(right)) => left.cmp(right),
+        }
+    }
+
+    /// Hash integral numbers within the range of `i64` as integers and others by their float,
+    /// so equal numbers of different types hash alike.
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        use std::hash::Hash;
+        let float = match self {
+            Number::Integer(value) => return value.hash(state),
+            Number::Decimal(value) => match value.to_i64() {
+                Some(integer) if value.trunc() == *value => return integer.hash(state),
+                _ => value.to_f64(),
+            },
+            Number::Float(value) => *value,
+        };
+        match float_to_i64(float) {
+            Some(integer) if float.fract() == 0.0 => integer.hash(state),
+            _ if float.is_nan() => f64::NAN.to_bits().hash(state),
+            _ => float.to_bits().hash(state),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Boolean(left), Value::Boolean(right)) => left.cmp(right),
+            (Value::Text(left), Value::Text(right)) => left.cmp(right),
+            (Value::Blob(left), Value::Blob(right)) => left.cmp(right),
+            (Value::Timestamp(left), Value::Timestamp(right)) => left.cmp(right),
+            (left, right) => match (left.number(), right.number()) {
+                (Some(left), Some(right)) => left.cmp(&right),
+                _ => left.rank().cmp(&right.rank()),
+            },
+        }
+    }
+}
+
+impl std::hash::Hash for Value {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.rank().hash(state);
+        match self {
+            Value::Null => {}
+            Value::Boolean(value) => value.hash(state),
+            Value::Text(value) => value.hash(state),
+            Value::Blob(value) => value.hash(state),
+            Value::Timestamp(value) => value.hash(state),
+            value => value.number().expect("Numeric Value").hash(state),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Boolean(value) => write!(f, "{value}"),
+            Value::SmallInt(value) => write!(f, "{value}"),
+            Value::Integer(value) => write!(f, "{value}"),
+            Value::BigInt(value) => write!(f, "{value}"),
+            Value::Real(value) => write!(f, "{value}"),
+            Value::Double(value) => write!(f, "{value}"),
+            Value::Decimal(value) => write!(f, "{value}"),
+            Value::Text(value) => write!(f, "{value}"),
+            Value::Blob(value) => {
+                write!(f, "\\x")?;
+                value.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+            }
+            Value::Timestamp(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Boolean(value)
+    }
+}
+
+impl From<i16> for Value {
+    fn from(value: i16) -> Self {
+        Value::SmallInt(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::BigInt(value)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::Real(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Double(value)
+    }
+}
+
+impl From<Decimal> for Value {
+    fn from(value: Decimal) -> Self {
+        Value::Decimal(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Text(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Text(value)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(value: Vec<u8>) -> Self {
+        Value::Blob(value)
+    }
+}
+
+impl From<Timestamp> for Value {
+    fn from(value: Timestamp) -> Self {
+        Value::Timestamp(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Decimal, Timestamp, Value, ValueError, ValueType};
+    use minql_lang::ast::{BinaryOperator, DataType, Literal, UnaryOperator};
+    use std::cmp::Ordering;
+    use std::collections::HashSet;
+
+    fn decimal(text: &str) -> Value {
+        Value::Decimal(Decimal::parse(text).unwrap())
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_value_types() {
+        assert_eq!(
+            ValueType::SmallInt.common(ValueType::BigInt),
+            Some(ValueType::BigInt)
+        );
+        assert_eq!(
+            ValueType::Integer.common(ValueType::Decimal),
+            Some(ValueType::Decimal)
+        );
+        assert_eq!(
+            ValueType::Real.common(ValueType::SmallInt),
+            Some(ValueType::Real)
+        );
+        assert_eq!(
+            ValueType::Real.common(ValueType::BigInt),
+            Some(ValueType::Double)
+        );
+        assert_eq!(
+            ValueType::Text.common(ValueType::Timestamp),
+            Some(ValueType::Timestamp)
+        );
+        assert_eq!(ValueType::Text.common(ValueType::Integer), None);
+
+        assert_eq!(
+            Value::from_literal(&Literal::Integer("7".into())).unwrap(),
+            Value::Integer(7)
+        );
+        assert!(matches!(
+            Value::from_literal(&Literal::Integer("4294967296".into())).unwrap(),
+            Value::BigInt(4_294_967_296)
+        ));
+        assert!(matches!(
+            Value::from_literal(&Literal::Integer("99999999999999999999".into())).unwrap(),
+            Value::Decimal(_)
+        ));
+        assert!(matches!(
+            Value::from_literal(&Literal::Float("1.5".into())).unwrap(),
+            Value::Decimal(_)
+        ));
+        assert!(matches!(
+            Value::from_literal(&Literal::Float("1.5e3".into())).unwrap(),
+            Value::Double(_)
+        ));
+        assert_eq!(
+            Value::from_literal(&Literal::Blob("00fF".into())).unwrap(),
+            Value::Blob(vec![0, 255])
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_value_cast() {
+        let cast = |value: Value, data_type| value.cast(data_type);
+        assert_eq!(
+            cast(Value::from("42"), DataType::SmallInt),
+            Ok(Value::SmallInt(42))
+        );
+        assert_eq!(
+            cast(Value::from(2.5), DataType::Integer),
+            Ok(Value::Integer(3))
+        );
+        assert_eq!(
+            cast(decimal("-2.5"), DataType::BigInt),
+            Ok(Value::BigInt(-3))
+        );
+        assert_eq!(
+            cast(Value::from(70_000), DataType::SmallInt),
+            Err(ValueError::Overflow(ValueType::SmallInt))
+        );
+        assert_eq!(
+            cast(Value::from(1.234_56), DataType::Decimal(Some((5, Some(2))))),
+            Ok(decimal("1.23"))
+        );
+        assert_eq!(
+            cast(Value::from(1234), DataType::Decimal(Some((5, Some(2))))),
+            Err(ValueError::Overflow(ValueType::Decimal))
+        );
+        assert_eq!(
+            cast(Value::from(" yes "), DataType::Boolean),
+            Ok(Value::Boolean(true))
+        );
+        assert!(matches!(
+            cast(Value::from("maybe"), DataType::Boolean),
+            Err(ValueError::InvalidCast { .. })
+        ));
+        assert_eq!(
+            cast(Value::from("hello"), DataType::Varchar(Some(3))),
+            Ok(Value::from("hel"))
+        );
+        assert_eq!(
+            cast(decimal("1.50"), DataType::Text),
+            Ok(Value::from("1.50"))
+        );
+        assert_eq!(
+            cast(Value::Blob(vec![1, 171]), DataType::Text),
+            Ok(Value::from("\\x01ab"))
+        );
+        assert_eq!(
+            cast(Value::from("2024-01-02 03:04:05"), DataType::Timestamp),
+            Ok(Value::Timestamp(
+                Timestamp::parse("2024-01-02 03:04:05").unwrap()
+            ))
+        );
+        assert_eq!(cast(Value::Null, DataType::Integer), Ok(Value::Null));
+        assert!(matches!(
+            cast(Value::from(true), DataType::Timestamp),
+            Err(ValueError::InvalidCast { .. })
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_value_compare() {
+        assert_eq!(
+            Value::from(1).compare(&decimal("1.0")),
+            Ok(Some(Ordering::Equal))
+        );
+        assert_eq!(
+            Value::from(2_i64).compare(&Value::from(1.5)),
+            Ok(Some(Ordering::Greater))
+        );
+        assert_eq!(Value::from(1).compare(&Value::Null), Ok(None));
+        assert_eq!(
+            Value::from("2024-01-01").compare(&Value::from(Timestamp::from_micros(0))),
+            Ok(Some(Ordering::Greater))
+        );
+        assert!(matches!(
+            Value::from(1).compare(&Value::from("1")),
+            Err(ValueError::IncompatibleTypes { .. })
+        ));
+
+        // Total order for sorting, with numbers of any type interleaved
+        let mut values = [
+            Value::from("b"),
+            Value::from(f64::NAN),
+            decimal("2.5"),
+            Value::Null,
+            Value::from(1_i16),
+            Value::from(false),
+            Value::from(-3.0),
+        ];
+        values.sort();
+        assert_eq!(
+            values.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec!["NULL", "false", "-3", "1", "2.5", "NaN", "b"]
+        );
+
+        let set: HashSet<Value> = [
+            Value::from(1),
+            Value::from(1_i64),
+            Value::from(1.0),
+            decimal("1.00"),
+            Value::from(0.5),
+            decimal("0.50"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_value_compare_exact() {
+        use std::hash::{BuildHasher, RandomState};
+
+        // i64::MAX rounds up to 2^63 as a float, but is below it
+        let max = Value::from(i64::MAX);
+        let above = Value::from(9.223_372_036_854_776e18);
+        assert_eq!(max.cmp(&above), Ordering::Less);
+        assert_eq!(above.cmp(&max), Ordering::Greater);
+        assert_ne!(max, above);
+
+        // Integers beyond 2^53 no longer equal the float they round to
+        let exact = Value::from(9_007_199_254_740_992_i64);
+        let beyond = Value::from(9_007_199_254_740_993_i64);
+        let float = Value::from(9_007_199_254_740_992.0);
+        assert_eq!(exact, float);
+        assert_eq!(beyond.cmp(&float), Ordering::Greater);
+        assert_eq!(
+            Value::from(i64::MIN),
+            Value::from(-9.223_372_036_854_776e18)
+        );
+        assert_eq!(Value::from(2_i64).cmp(&Value::from(2.5)), Ordering::Less);
+        assert_eq!(
+            Value::from(-2_i64).cmp(&Value::from(-2.5)),
+            Ordering::Greater
+        );
+        assert_eq!(
+            Value::from(i64::MIN).cmp(&Value::from(f64::NAN)),
+            Ordering::Less
+        );
+        assert_eq!(Value::from(0_i64), Value::from(-0.0));
+
+        // Equal numbers hash alike
+        let hasher = RandomState::new();
+        for (integer, float) in [
+            (exact, float),
+            (
+                Value::from(i64::MIN),
+                Value::from(-9.223_372_036_854_776e18),
+            ),
+        ] {
+            assert_eq!(hasher.hash_one(&integer), hasher.hash_one(&float));
+        }
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_value_operators() {
+        let apply = |left: Value, op, right: Value| left.binary(op, &right);
+        assert_eq!(
+            apply(Value::from(2), BinaryOperator::Plus, Value::from(3_i64)),
+            Ok(Value::BigInt(5))
+        );
+        assert_eq!(
+            apply(Value::from(7), BinaryOperator::Divide, Value::from(2)),
+            Ok(Value::Integer(3))
+        );
+        assert_eq!(
+            apply(Value::from(-7), BinaryOperator::Modulo, Value::from(2)),
+            Ok(Value::Integer(-1))
+        );
+        assert_eq!(
+            apply(Value::from(1), BinaryOperator::Divide, decimal("4")),
+            Ok(decimal("0.25"))
+        );
+        assert_eq!(
+            apply(Value::from(1), BinaryOperator::Plus, Value::from(0.5)),
+            Ok(Value::Double(1.5))
+        );
+        assert_eq!(
+            apply(Value::from(1), BinaryOperator::Divide, Value::from(0)),
+            Err(ValueError::DivisionByZero)
+        );
+        assert_eq!(
+            apply(Value::from(i32::MAX), BinaryOperator::Plus, Value::from(1)),
+            Err(ValueError::Overflow(ValueType::Integer))
+        );
+        assert_eq!(
+            Value::SmallInt(i16::MIN).unary(UnaryOperator::Minus),
+            Err(ValueError::Overflow(ValueType::SmallInt))
+        );
+        assert_eq!(
+            apply(Value::Null, BinaryOperator::Multiply, Value::from(2)),
+            Ok(Value::Null)
+        );
+        assert!(matches!(
+            apply(Value::from("a"), BinaryOperator::Plus, Value::from(1)),
+            Err(ValueError::IncompatibleTypes { .. })
+        ));
+
+        assert_eq!(
+            apply(Value::from("a"), BinaryOperator::Concat, Value::from(1)),
+            Ok(Value::from("a1"))
+        );
+        assert_eq!(
+            apply(
+                Value::Blob(vec![1]),
+                BinaryOperator::Concat,
+                Value::Blob(vec![2])
+            ),
+            Ok(Value::Blob(vec![1, 2]))
+        );
+        assert_eq!(
+            apply(Value::from(1), BinaryOperator::Lt, Value::from(2)),
+            Ok(Value::Boolean(true))
+        );
+        assert_eq!(
+            apply(Value::from(1), BinaryOperator::Eq, Value::Null),
+            Ok(Value::Null)
+        );
+
+        let (t, f, n) = (Value::from(true), Value::from(false), Value::Null);
+        assert_eq!(
+            apply(f.clone(), BinaryOperator::And, n.clone()),
+            Ok(f.clone())
+        );
+        assert_eq!(
+            apply(t.clone(), BinaryOperator::And, n.clone()),
+            Ok(n.clone())
+        );
+        assert_eq!(
+            apply(n.clone(), BinaryOperator::Or, t.clone()),
+            Ok(t.clone())
+        );
+        assert_eq!(
+            apply(f.clone(), BinaryOperator::Or, n.clone()),
+            Ok(n.clone())
+        );
+        assert_eq!(
+            apply(t.clone(), BinaryOperator::And, t.clone()),
+            Ok(t.clone())
+        );
+        assert_eq!(n.unary(UnaryOperator::Not), Ok(Value::Null));
+        assert!(matches!(
+            Value::from(1).unary(UnaryOperator::Not),
+            Err(ValueError::TypeMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,96 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Concurrency benchmark for `MemoryFileSystem`.
+//!
+//! Runs a mix of metadata operations and shared reads from a growing number of threads, once
+//! with every thread working in the same directory and once with each thread in its own. With
+//! the namespace sharded by directory the second workload keeps scaling with threads, while the
+//! first shows the cost of contending on a single shard. Run with `cargo bench`.
+
+use minql_vfs::{FileHandle, FileSystem, MemoryFileSystem};
+use std::io::Write;
+use std::sync::Barrier;
+use std::time::{Duration, Instant};
+
+const OPERATIONS: usize = 20_000;
+
+fn workload(fs: &MemoryFileSystem, directory: &str, thread: usize) {
+    let mut shared = fs.open_file("/shared.bin").unwrap();
+    let mut buffer = [0; 256];
+    for operation in 0..OPERATIONS {
+        let path = format!("{directory}/{thread}-{}", operation % 64);
+        match operation % 4 {
+            0 => {
+                let _ = fs.remove_file(&path);
+                fs.create_file(&path).unwrap();
+            }
+            1 => {
+                fs.exists(&path).unwrap();
+            }
+            2 => {
+                fs.list_directory(directory).unwrap();
+            }
+            _ => {
+                shared.read_at_offset(0, &mut buffer).unwrap();
+            }
+        }
+    }
+}
+
+fn run(threads: usize, same_directory: bool) -> Duration {
+    let fs = MemoryFileSystem::new();
+    fs.create_file("/shared.bin")
+        .unwrap()
+        .write_all(&[7; 4096])
+        .unwrap();
+    for thread in 0..threads {
+        fs.create_directory_all(&format!("/thread-{thread}"))
+            .unwrap();
+    }
+    fs.create_directory("/common").unwrap();
+    let barrier = Barrier::new(threads + 1);
+    std::thread::scope(|scope| {
+        for thread in 0..threads {
+            let (fs, barrier) = (&fs, &barrier);
+            scope.spawn(move || {
+                let directory = if same_directory {
+                    "/common".to_string()
+                } else {
+                    format!("/thread-{thread}")
+                };
+                barrier.wait();
+                workload(fs, &directory, thread);
+            });
+        }
+        barrier.wait();
+        // The scope only returns once every thread has finished.
+        Instant::now()
+    })
+    .elapsed()
+}
+
+fn main() {
+    println!("threads  layout           ops/sec");
+    for threads in [1, 2, 4, 8] {
+        for (layout, same_directory) in [("same directory", true), ("per-thread dirs", false)] {
+            let elapsed = run(threads, same_directory);
+            #[allow(clippy::cast_precision_loss)]
+            let rate = (threads * OPERATIONS) as f64 / elapsed.as_secs_f64();
+            println!("{threads:>7}  {layout:<15}  {rate:>12.0}");
+        }
+    }
+}
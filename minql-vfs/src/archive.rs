@@ -0,0 +1,435 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::progress::{NoProgress, ProgressTracker};
+use crate::utility::{child_path, normalize_path};
+use crate::{
+    FileHandle, FileSystem, FileSystemError, FileSystemResult, FileType, OpenOptions, Progress,
+    ProgressObserver, SymlinkPolicy,
+};
+use std::io::{Read, Write};
+
+/// Format of an archive.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ArchiveFormat {
+    /// POSIX tar archive, uncompressed
+    Tar,
+    /// Zip archive, deflating file contents
+    Zip,
+}
+
+/// Entry of a `FileSystem` tree being packed.
+enum PackEntry {
+    Directory,
+    File,
+    Symlink(String),
+}
+
+/// Pack the tree below `directory` of a `FileSystem` into an archive written to `handle`.
+///
+/// Entries are named relative to `directory`, with parents before their children. Symbolic
+/// links are stored as links rather than followed.
+///
+/// ```rust
+/// use minql_vfs::{pack, unpack, ArchiveFormat, FileSystem, MemoryFileSystem};
+///
+/// let source = MemoryFileSystem::new();
+/// source.create_directory_all("/export/tables").unwrap();
+/// source.write("/export/tables/users.db", b"rows").unwrap();
+///
+/// let mut archive = source.create_file("/export.zip").unwrap();
+/// let packed = pack(&source, "/export", ArchiveFormat::Zip, &mut archive).unwrap();
+/// assert_eq!(packed.entries, 2);
+///
+/// let target = MemoryFileSystem::new();
+/// let mut archive = source.open_file("/export.zip").unwrap();
+/// assert_eq!(unpack(&mut archive, ArchiveFormat::Zip, &target, "/import").unwrap(), packed);
+/// assert_eq!(target.read("/import/tables/users.db").unwrap(), b"rows");
+/// ```
+pub fn pack<F: FileSystem, H: FileHandle + ?Sized>(
+    fs: &F,
+    directory: &str,
+    format: ArchiveFormat,
+    handle: &mut H,
+) -> FileSystemResult<Progress> {
+    pack_with_progress(fs, directory, format, handle, &mut NoProgress)
+}
+
+/// Pack the tree below `directory` of a `FileSystem` into an archive written to `handle` like
+/// [`pack`], reporting progress to `observer`.
+pub fn pack_with_progress<F: FileSystem, H: FileHandle + ?Sized>(
+    fs: &F,
+    directory: &str,
+    format: ArchiveFormat,
+    handle: &mut H,
+    observer: &mut dyn ProgressObserver,
+) -> FileSystemResult<Progress> {
+    let directory = normalize_path(directory)?;
+    if !fs.is_directory(&directory)? {
+        return Err(FileSystemError::InvalidOperation);
+    }
+    let mut tracker = ProgressTracker::new(observer);
+    match format {
+        ArchiveFormat::Tar => {
+            let mut builder = tar::Builder::new(handle);
+            walk(fs, &directory, "", &mut |name, entry| {
+                let mut header = tar::Header::new_gnu();
+                match entry {
+                    PackEntry::Directory => {
+                        header.set_entry_type(tar::EntryType::Directory);
+                        header.set_mode(0o755);
+                        header.set_size(0);
+                        builder.append_data(&mut header, format!("{name}/"), std::io::empty())
+                    }
+                    PackEntry::File => {
+                        let path = child_path(&directory, name);
+                        let file = fs.open_file(&path)?;
+                        let size = fs.filesize(&path)?;
+                        header.set_entry_type(tar::EntryType::Regular);
+                        header.set_mode(0o644);
+                        header.set_size(size);
+                        builder.append_data(&mut header, name, tracker.reader(name, file))
+                    }
+                    PackEntry::Symlink(target) => {
+                        header.set_entry_type(tar::EntryType::Symlink);
+                        header.set_mode(0o777);
+                        header.set_size(0);
+                        builder.append_link(&mut header, name, target)
+                    }
+                }
+                .map_err(FileSystemError::io_error)?;
+                tracker.entry(name);
+                Ok(())
+            })?;
+            builder.finish().map_err(FileSystemError::io_error)?;
+        }
+        ArchiveFormat::Zip => {
+            let mut writer = zip::ZipWriter::new(handle);
+            let options = zip::write::SimpleFileOptions::default();
+            walk(fs, &directory, "", &mut |name, entry| {
+                match entry {
+                    PackEntry::Directory => writer
+                        .add_directory(name, options.unix_permissions(0o755))
+                        .map_err(FileSystemError::wrap_error)?,
+                    PackEntry::File => {
+                        let path = child_path(&directory, name);
+                        writer
+                            .start_file(name, options.unix_permissions(0o644))
+                            .map_err(FileSystemError::wrap_error)?;
+                        let file = fs.open_file(&path)?;
+                        std::io::copy(&mut tracker.reader(name, file), &mut writer)
+                            .map_err(FileSystemError::io_error)?;
+                    }
+                    PackEntry::Symlink(target) => writer
+                        .add_symlink(name, target, options)
+                        .map_err(FileSystemError::wrap_error)?,
+                }
+                tracker.entry(name);
+                Ok(())
+            })?;
+            writer.finish().map_err(FileSystemError::wrap_error)?;
+        }
+    }
+    Ok(tracker.progress())
+}
+
+/// Visit every entry below `directory`, named by its path relative to the packed root.
+fn walk<F: FileSystem>(
+    fs: &F,
+    directory: &str,
+    prefix: &str,
+    visit: &mut dyn FnMut(&str, PackEntry) -> FileSystemResult<()>,
+) -> FileSystemResult<()> {
+    let mut children = fs.list_directory(directory)?;
+    children.sort();
+    for name in children {
+        let path = child_path(directory, &name);
+        let name = if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix}/{name}")
+        };
+        match fs.file_type(&path, SymlinkPolicy::NoFollow)? {
+            FileType::Directory => {
+                visit(&name, PackEntry::Directory)?;
+                walk(fs, &path, &name, visit)?;
+            }
+            FileType::File => visit(&name, PackEntry::File)?,
+            FileType::Symlink => visit(&name, PackEntry::Symlink(fs.read_link(&path)?))?,
+        }
+    }
+    Ok(())
+}
+
+/// Unpack an archive read from `handle` into `directory` of a `FileSystem`, creating it if
+/// missing and replacing existing files.
+///
+/// Entries are checked before anything is written for them, and an entry whose name is
+/// absolute or climbs out of `directory` with `..`, or a symbolic link whose target does,
+/// fails the unpack with [`FileSystemError::InvalidPath`]. Entries of other types, like hard
+/// links and devices, are skipped.
+pub fn unpack<F: FileSystem, H: FileHandle + ?Sized>(
+    handle: &mut H,
+    format: ArchiveFormat,
+    fs: &F,
+    directory: &str,
+) -> FileSystemResult<Progress> {
+    unpack_with_progress(handle, format, fs, directory, &mut NoProgress)
+}
+
+/// Unpack an archive read from `handle` into `directory` of a `FileSystem` like [`unpack`],
+/// reporting progress to `observer`.
+pub fn unpack_with_progress<F: FileSystem, H: FileHandle + ?Sized>(
+    handle: &mut H,
+    format: ArchiveFormat,
+    fs: &F,
+    directory: &str,
+    observer: &mut dyn ProgressObserver,
+) -> FileSystemResult<Progress> {
+    let directory = normalize_path(directory)?;
+    fs.create_directory_all(&directory)?;
+    let mut tracker = ProgressTracker::new(observer);
+    match format {
+        ArchiveFormat::Tar => {
+            let mut archive = tar::Archive::new(handle);
+            for entry in archive.entries().map_err(FileSystemError::io_error)? {
+                let mut entry = entry.map_err(FileSystemError::io_error)?;
+                let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+                let kind = entry.header().entry_type();
+                if kind.is_dir() {
+                    extract_directory(fs, &directory, &name, &mut tracker)?;
+                } else if kind.is_file() {
+                    extract_file(fs, &directory, &name, &mut entry, &mut tracker)?;
+                } else if kind.is_symlink() {
+                    let target = entry
+                        .link_name_bytes()
+                        .map(|target| String::from_utf8_lossy(&target).into_owned())
+                        .unwrap_or_default();
+                    extract_symlink(fs, &directory, &name, &target, &mut tracker)?;
+                } else {
+                    tracing::debug!(name, ?kind, "Skipping unsupported archive entry");
+                }
+            }
+        }
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(handle).map_err(FileSystemError::wrap_error)?;
+            for index in 0..archive.len() {
+                let mut entry = archive
+                    .by_index(index)
+                    .map_err(FileSystemError::wrap_error)?;
+                let name = entry.name().to_string();
+                if entry.is_dir() {
+                    extract_directory(fs, &directory, &name, &mut tracker)?;
+                } else if entry.is_symlink() {
+                    let mut target = String::new();
+                    entry
+                        .read_to_string(&mut target)
+                        .map_err(FileSystemError::io_error)?;
+                    extract_symlink(fs, &directory, &name, &target, &mut tracker)?;
+                } else {
+                    extract_file(fs, &directory, &name, &mut entry, &mut tracker)?;
+                }
+            }
+        }
+    }
+    Ok(tracker.progress())
+}
+
+/// Resolve the name of an archive entry below `directory`, or `None` for the root itself.
+fn entry_path(directory: &str, name: &str) -> FileSystemResult<Option<String>> {
+    let segments = safe_segments(name, &[])?;
+    if segments.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(child_path(directory, &segments.join("/"))))
+}
+
+/// Lexically resolve a relative path from the `base` segments, failing if it's absolute or
+/// climbs above the root.
+fn safe_segments<'a>(path: &'a str, base: &[&'a str]) -> FileSystemResult<Vec<&'a str>> {
+    let invalid = || FileSystemError::invalid_path(path);
+    if path.starts_with(['/', '\\'])
+        || path
+            .split(['/', '\\'])
+            .next()
+            .is_some_and(|first| first.contains(':'))
+    {
+        return Err(invalid());
+    }
+    let mut segments = base.to_vec();
+    for segment in path.split(['/', '\\']) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop().ok_or_else(invalid)?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Ok(segments)
+}
+
+/// Create a directory entry.
+fn extract_directory<F: FileSystem>(
+    fs: &F,
+    directory: &str,
+    name: &str,
+    tracker: &mut ProgressTracker<'_>,
+) -> FileSystemResult<()> {
+    if let Some(path) = entry_path(directory, name)? {
+        fs.create_directory_all(&path)?;
+        tracker.entry(&path);
+    }
+    Ok(())
+}
+
+/// Create a file entry, along with any missing parents.
+fn extract_file<F: FileSystem, R: Read>(
+    fs: &F,
+    directory: &str,
+    name: &str,
+    contents: &mut R,
+    tracker: &mut ProgressTracker<'_>,
+) -> FileSystemResult<()> {
+    let path = entry_path(directory, name)?.ok_or_else(|| FileSystemError::invalid_path(name))?;
+    if let Some((parent, _)) = path.rsplit_once('/') {
+        fs.create_directory_all(parent)?;
+    }
+    let mut file = fs.open_with(
+        &path,
+        OpenOptions::new().write(true).create(true).truncate(true),
+    )?;
+    std::io::copy(&mut tracker.reader(&path, contents), &mut file)
+        .map_err(FileSystemError::io_error)?;
+    file.flush().map_err(FileSystemError::io_error)?;
+    tracker.entry(&path);
+    Ok(())
+}
+
+/// Create a symbolic link entry, after checking its target stays below the unpacked directory.
+fn extract_symlink<F: FileSystem>(
+    fs: &F,
+    directory: &str,
+    name: &str,
+    target: &str,
+    tracker: &mut ProgressTracker<'_>,
+) -> FileSystemResult<()> {
+    let segments = safe_segments(name, &[])?;
+    let Some((_, parent)) = segments.split_last() else {
+        return Err(FileSystemError::invalid_path(name));
+    };
+    safe_segments(target, parent)?;
+    let path = child_path(directory, &segments.join("/"));
+    if let Some((parent, _)) = path.rsplit_once('/') {
+        fs.create_directory_all(parent)?;
+    }
+    fs.create_symlink(target, &path)?;
+    tracker.entry(&path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pack, unpack, unpack_with_progress, ArchiveFormat};
+    use crate::{FileSystem, FileSystemError, MemoryFileSystem, Progress};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_archive_round_trip() {
+        let source = MemoryFileSystem::new();
+        source.create_directory_all("/root/a/empty").unwrap();
+        source.write("/root/a/file.txt", b"hello").unwrap();
+        source.write("/root/top.bin", &[7; 10_000]).unwrap();
+        source.create_symlink("a/file.txt", "/root/link").unwrap();
+        for format in [ArchiveFormat::Tar, ArchiveFormat::Zip] {
+            let mut archive = source.create_file("/archive").unwrap();
+            let packed = pack(&source, "/root", format, &mut archive).unwrap();
+            assert_eq!(
+                packed,
+                Progress {
+                    entries: 5,
+                    bytes: 10_005
+                }
+            );
+
+            let target = MemoryFileSystem::new();
+            let mut archive = source.open_file("/archive").unwrap();
+            let mut entries = Vec::new();
+            let unpacked = unpack_with_progress(
+                &mut archive,
+                format,
+                &target,
+                "/restored",
+                &mut |path: &str, progress: Progress| entries.push(progress.entries),
+            )
+            .unwrap();
+            assert_eq!(unpacked, packed);
+            assert_eq!(entries.last(), Some(&5));
+            assert!(target.is_directory("/restored/a/empty").unwrap());
+            assert_eq!(target.read("/restored/a/file.txt").unwrap(), b"hello");
+            assert_eq!(target.read("/restored/top.bin").unwrap(), [7; 10_000]);
+            assert_eq!(target.read_link("/restored/link").unwrap(), "a/file.txt");
+            assert_eq!(target.read("/restored/link").unwrap(), b"hello");
+            source.remove_file("/archive").unwrap();
+        }
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_unpack_rejects_escaping_paths() {
+        let fs = MemoryFileSystem::new();
+        let entries: [(&str, Option<&str>); 4] = [
+            ("../escape.txt", None),
+            ("a/../../escape.txt", None),
+            ("/absolute.txt", None),
+            ("a/link", Some("../../outside")),
+        ];
+        for (name, target) in entries {
+            let mut builder = tar::Builder::new(Vec::new());
+            let mut header = tar::Header::new_gnu();
+            match target {
+                None => {
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_size(1);
+                    // Write the name directly, as the builder itself refuses `..`.
+                    header.as_gnu_mut().unwrap().name[..name.len()]
+                        .copy_from_slice(name.as_bytes());
+                    header.set_cksum();
+                    builder.append(&header, &b"x"[..]).unwrap();
+                }
+                Some(target) => {
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    header.set_size(0);
+                    builder.append_link(&mut header, name, target).unwrap();
+                }
+            }
+            fs.write("/archive.tar", &builder.into_inner().unwrap())
+                .unwrap();
+            let mut archive = fs.open_file("/archive.tar").unwrap();
+            assert!(
+                matches!(
+                    unpack(&mut archive, ArchiveFormat::Tar, &fs, "/target/inner"),
+                    Err(FileSystemError::InvalidPath(_))
+                ),
+                "{name} wasn't rejected"
+            );
+        }
+        assert!(!fs.exists("/target/escape.txt").unwrap());
+        assert!(!fs.exists("/escape.txt").unwrap());
+        assert!(!fs.exists("/absolute.txt").unwrap());
+        assert!(!fs.exists("/target/inner/a/link").unwrap());
+    }
+}
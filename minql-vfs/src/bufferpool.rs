@@ -0,0 +1,622 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{FileHandle, FileSystemError, FileSystemResult, Lsn, Page, PageId, PagedFile};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Strategy choosing which unpinned page a [`BufferPool`] evicts when it is full.
+pub trait EvictionPolicy: std::fmt::Debug + Send + 'static {
+    /// Record that a page was loaded into or accessed through the pool.
+    fn access(&mut self, id: PageId);
+    /// Forget a page that has left the pool.
+    fn remove(&mut self, id: PageId);
+    /// Choose a page to evict from those accepted by `evictable`.
+    fn victim(&mut self, evictable: &mut dyn FnMut(PageId) -> bool) -> Option<PageId>;
+}
+
+/// Evicts the least recently used page.
+#[derive(Debug, Default)]
+pub struct LruPolicy {
+    tick: u64,
+    order: BTreeMap<u64, PageId>,
+    ticks: HashMap<PageId, u64>,
+}
+
+impl LruPolicy {
+    /// Create a new Least Recently Used policy.
+    #[must_use]
+    pub fn new() -> LruPolicy {
+        LruPolicy::default()
+    }
+}
+
+impl EvictionPolicy for LruPolicy {
+    fn access(&mut self, id: PageId) {
+        self.tick += 1;
+        if let Some(tick) = self.ticks.insert(id, self.tick) {
+            self.order.remove(&tick);
+        }
+        self.order.insert(self.tick, id);
+    }
+
+    fn remove(&mut self, id: PageId) {
+        if let Some(tick) = self.ticks.remove(&id) {
+            self.order.remove(&tick);
+        }
+    }
+
+    fn victim(&mut self, evictable: &mut dyn FnMut(PageId) -> bool) -> Option<PageId> {
+        self.order.values().copied().find(|id| evictable(*id))
+    }
+}
+
+/// Approximates LRU by sweeping a clock hand over pages, giving each recently referenced page a
+/// second chance before evicting it.
+#[derive(Debug, Default)]
+pub struct ClockPolicy {
+    hand: usize,
+    ring: Vec<(PageId, bool)>,
+    slots: HashMap<PageId, usize>,
+}
+
+impl ClockPolicy {
+    /// Create a new Clock policy.
+    #[must_use]
+    pub fn new() -> ClockPolicy {
+        ClockPolicy::default()
+    }
+}
+
+impl EvictionPolicy for ClockPolicy {
+    fn access(&mut self, id: PageId) {
+        if let Some(slot) = self.slots.get(&id) {
+            self.ring[*slot].1 = true;
+        } else {
+            self.slots.insert(id, self.ring.len());
+            self.ring.push((id, true));
+        }
+    }
+
+    fn remove(&mut self, id: PageId) {
+        if let Some(slot) = self.slots.remove(&id) {
+            self.ring.swap_remove(slot);
+            if let Some((moved, _)) = self.ring.get(slot) {
+                self.slots.insert(*moved, slot);
+            }
+        }
+    }
+
+    fn victim(&mut self, evictable: &mut dyn FnMut(PageId) -> bool) -> Option<PageId> {
+        // Two full sweeps clear every reference bit, so a third can only find pinned pages.
+        for _ in 0..self.ring.len() * 2 {
+            if self.hand >= self.ring.len() {
+                self.hand = 0;
+            }
+            let (id, referenced) = &mut self.ring[self.hand];
+            if *referenced {
+                *referenced = false;
+            } else if evictable(*id) {
+                return Some(*id);
+            }
+            self.hand += 1;
+        }
+        None
+    }
+}
+
+/// A page held in memory by a [`BufferPool`].
+#[derive(Debug)]
+struct Frame {
+    page: Arc<RwLock<Page>>,
+    dirty: Arc<AtomicBool>,
+    pins: usize,
+}
+
+/// Hook forcing the log to storage up to an [`Lsn`], run before a page is written back.
+type WriteAhead = Box<dyn Fn(Lsn) -> FileSystemResult<()> + Send + Sync>;
+
+#[derive(Debug)]
+struct PoolState {
+    frames: HashMap<PageId, Frame>,
+    policy: Box<dyn EvictionPolicy>,
+}
+
+/// Buffer Pool
+///
+/// Caches pages of a [`PagedFile`] in memory within a fixed byte budget. Pages are pinned while
+/// in use and only unpinned pages are ever evicted, with the [`EvictionPolicy`] choosing between
+/// them. Modified pages are tracked as dirty and written back when evicted or flushed.
+///
+/// Pools over pages changed under a write-ahead log are given a hook with
+/// [`BufferPool::with_write_ahead`], which is passed the [`Lsn`] of each page before it is
+/// written back, so no page reaches storage ahead of the log records describing it.
+///
+/// ```rust
+/// use minql_vfs::{BufferPool, FileSystem, LruPolicy, MemoryFileSystem, PagedFile};
+///
+/// let fs = MemoryFileSystem::new();
+/// let file = PagedFile::new(fs.create_file("/pages.dat").unwrap(), 4096).unwrap();
+/// let pool = BufferPool::new(file, 16 * 4096, LruPolicy::new());
+///
+/// let page = pool.new_page().unwrap();
+/// page.write().data_mut()[..5].copy_from_slice(b"Hello");
+/// let id = page.id();
+/// drop(page);
+///
+/// pool.flush_all().unwrap();
+/// assert_eq!(&pool.pin(id).unwrap().read().data()[..5], b"Hello");
+/// ```
+pub struct BufferPool<H: FileHandle> {
+    state: Mutex<PoolState>,
+    file: Mutex<PagedFile<H>>,
+    capacity: usize,
+    write_ahead: Option<WriteAhead>,
+}
+
+impl<H: FileHandle> BufferPool<H> {
+    /// Create a pool caching as many pages of `file` as fit in `budget` bytes, and at least one.
+    pub fn new<P: EvictionPolicy>(file: PagedFile<H>, budget: usize, policy: P) -> BufferPool<H> {
+        BufferPool {
+            capacity: (budget / file.page_size()).max(1),
+            state: Mutex::new(PoolState {
+                frames: HashMap::new(),
+                policy: Box::new(policy),
+            }),
+            file: Mutex::new(file),
+            write_ahead: None,
+        }
+    }
+
+    /// Run `write_ahead` with the [`Lsn`] of every page before writing it back, to force the
+    /// log to storage up to it.
+    ///
+    /// The hook runs while the pool is locked, so it must not use the pool.
+    #[must_use]
+    pub fn with_write_ahead(
+        mut self,
+        write_ahead: impl Fn(Lsn) -> FileSystemResult<()> + Send + Sync + 'static,
+    ) -> BufferPool<H> {
+        self.write_ahead = Some(Box::new(write_ahead));
+        self
+    }
+
+    /// Maximum number of pages held in memory.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of pages currently held in memory.
+    #[must_use]
+    pub fn resident(&self) -> usize {
+        self.state.lock().expect("Poisoned Lock").frames.len()
+    }
+
+    /// Number of pages in the file, including any not yet written back.
+    pub fn page_count(&self) -> FileSystemResult<u64> {
+        self.file.lock().expect("Poisoned Lock").page_count()
+    }
+
+    /// Check if a page is resident with changes not yet written back.
+    #[must_use]
+    pub fn is_dirty(&self, id: PageId) -> bool {
+        let state = self.state.lock().expect("Poisoned Lock");
+        state
+            .frames
+            .get(&id)
+            .is_some_and(|frame| frame.dirty.load(Ordering::Acquire))
+    }
+
+    /// Pin a page in memory, reading it from the file if it isn't already resident.
+    #[tracing::instrument(level = "trace")]
+    pub fn pin(&self, id: PageId) -> FileSystemResult<PinnedPage<'_, H>> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        if !state.frames.contains_key(&id) {
+            self.make_room(&mut state)?;
+            let page = self.file.lock().expect("Poisoned Lock").read_page(id)?;
+            state.frames.insert(id, Frame::new(page));
+        }
+        Ok(self.pin_frame(&mut state, id))
+    }
+
+    /// Append a zeroed page to the file and pin it.
+    #[tracing::instrument(level = "trace")]
+    pub fn new_page(&self) -> FileSystemResult<PinnedPage<'_, H>> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        self.make_room(&mut state)?;
+        let mut file = self.file.lock().expect("Poisoned Lock");
+        let id = file.extend(1)?;
+        let page = file.new_page();
+        drop(file);
+        state.frames.insert(id, Frame::new(page));
+        Ok(self.pin_frame(&mut state, id))
+    }
+
+    /// Write a page back to the file if it is resident and dirty.
+    #[tracing::instrument(level = "trace")]
+    pub fn flush_page(&self, id: PageId) -> FileSystemResult<()> {
+        self.flush(|page| page == id)
+    }
+
+    /// Write every dirty page back to the file in page order, then sync it once.
+    #[tracing::instrument(level = "trace")]
+    pub fn flush_all(&self) -> FileSystemResult<()> {
+        self.flush(|_| true)?;
+        self.file.lock().expect("Poisoned Lock").sync()
+    }
+
+    /// Path of the underlying file.
+    #[must_use]
+    pub fn path(&self) -> String {
+        self.file
+            .lock()
+            .expect("Poisoned Lock")
+            .handle()
+            .path()
+            .to_string()
+    }
+
+    /// Wrap `handle` as an empty [`PagedFile`] laid out like the file of the pool, to receive a
+    /// copy of its pages with [`BufferPool::copy_to`].
+    pub fn create_copy<T: FileHandle>(&self, handle: T) -> FileSystemResult<PagedFile<T>> {
+        let file = self.file.lock().expect("Poisoned Lock");
+        let copy = PagedFile::new(handle, file.page_size())?;
+        if file.has_page_lsn() {
+            copy.with_page_lsn()
+        } else {
+            Ok(copy)
+        }
+    }
+
+    /// Copy every page `target` doesn't have yet, returning how many were copied.
+    ///
+    /// Resident pages are copied as they are in memory, changes not yet written back included,
+    /// and the rest as they are on storage. Each page is copied whole, but pages changing
+    /// meanwhile may be copied before or after any change, so a copy of a pool in use must be
+    /// brought up to date by replaying the log written during the copy. Pages appended during
+    /// the copy are copied too.
+    #[tracing::instrument(level = "trace", skip(target))]
+    pub fn copy_to<T: FileHandle>(&self, target: &mut PagedFile<T>) -> FileSystemResult<u64> {
+        let first = target.page_count()?;
+        let mut id = first;
+        while id < self.page_count()? {
+            let resident = self
+                .state
+                .lock()
+                .expect("Poisoned Lock")
+                .frames
+                .get(&id)
+                .map(|frame| frame.page.clone());
+            let page = match resident {
+                Some(page) => page.read().expect("Poisoned Lock").clone(),
+                None => self.file.lock().expect("Poisoned Lock").read_page(id)?,
+            };
+            target.write_page(id, &page)?;
+            id += 1;
+        }
+        target.sync()?;
+        Ok(id - first)
+    }
+
+    /// Flush every dirty page and return the underlying file.
+    pub fn into_inner(self) -> FileSystemResult<PagedFile<H>> {
+        self.flush_all()?;
+        Ok(self.file.into_inner().expect("Poisoned Lock"))
+    }
+
+    fn flush(&self, mut filter: impl FnMut(PageId) -> bool) -> FileSystemResult<()> {
+        // Pin the dirty pages so they can't be evicted and reloaded stale while being written.
+        let mut dirty = {
+            let mut state = self.state.lock().expect("Poisoned Lock");
+            let ids = state
+                .frames
+                .iter()
+                .filter(|(id, frame)| filter(**id) && frame.dirty.load(Ordering::Acquire))
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+            ids.into_iter()
+                .map(|id| self.pin_frame(&mut state, id))
+                .collect::<Vec<_>>()
+        };
+        dirty.sort_by_key(PinnedPage::id);
+        for page in &dirty {
+            page.write_back()?;
+        }
+        Ok(())
+    }
+
+    /// Evict unpinned pages until there is room for another.
+    fn make_room(&self, state: &mut PoolState) -> FileSystemResult<()> {
+        while state.frames.len() >= self.capacity {
+            let frames = &state.frames;
+            let victim = state
+                .policy
+                .victim(&mut |id| frames.get(&id).is_some_and(|frame| frame.pins == 0))
+                .ok_or_else(|| {
+                    FileSystemError::internal_error("buffer pool exhausted with every page pinned")
+                })?;
+            let frame = &state.frames[&victim];
+            if frame.dirty.load(Ordering::Acquire) {
+                let page = frame.page.read().expect("Poisoned Lock");
+                self.write_page(victim, &page)?;
+                frame.dirty.store(false, Ordering::Release);
+            }
+            tracing::trace!(page = victim, "Evicting page");
+            state.frames.remove(&victim);
+            state.policy.remove(victim);
+        }
+        Ok(())
+    }
+
+    /// Write a page to the file once the log holds every record applied to it.
+    fn write_page(&self, id: PageId, page: &Page) -> FileSystemResult<()> {
+        if let Some(write_ahead) = &self.write_ahead {
+            write_ahead(page.lsn())?;
+        }
+        self.file
+            .lock()
+            .expect("Poisoned Lock")
+            .write_page(id, page)
+    }
+
+    fn pin_frame(&self, state: &mut PoolState, id: PageId) -> PinnedPage<'_, H> {
+        let frame = state.frames.get_mut(&id).expect("Resident Frame");
+        frame.pins += 1;
+        let pinned = PinnedPage {
+            pool: self,
+            id,
+            page: frame.page.clone(),
+            dirty: frame.dirty.clone(),
+        };
+        state.policy.access(id);
+        pinned
+    }
+
+    fn unpin(&self, id: PageId) {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        if let Some(frame) = state.frames.get_mut(&id) {
+            frame.pins -= 1;
+        }
+    }
+}
+
+impl<H: FileHandle> std::fmt::Debug for BufferPool<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("state", &self.state)
+            .field("file", &self.file)
+            .field("capacity", &self.capacity)
+            .field("write_ahead", &self.write_ahead.is_some())
+            .finish()
+    }
+}
+
+impl Frame {
+    fn new(page: Page) -> Frame {
+        Frame {
+            page: Arc::new(RwLock::new(page)),
+            dirty: Arc::new(AtomicBool::new(false)),
+            pins: 0,
+        }
+    }
+}
+
+/// A page pinned in a [`BufferPool`], unpinned when dropped.
+#[derive(Debug)]
+pub struct PinnedPage<'a, H: FileHandle> {
+    pool: &'a BufferPool<H>,
+    id: PageId,
+    page: Arc<RwLock<Page>>,
+    dirty: Arc<AtomicBool>,
+}
+
+impl<H: FileHandle> PinnedPage<'_, H> {
+    /// Id of the pinned page.
+    #[must_use]
+    pub fn id(&self) -> PageId {
+        self.id
+    }
+
+    /// Check if the page has changes not yet written back to the file.
+    #[must_use]
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+
+    /// Lock the page for reading.
+    pub fn read(&self) -> RwLockReadGuard<'_, Page> {
+        self.page.read().expect("Poisoned Lock")
+    }
+
+    /// Lock the page for writing, marking it dirty.
+    pub fn write(&self) -> RwLockWriteGuard<'_, Page> {
+        let guard = self.page.write().expect("Poisoned Lock");
+        self.dirty.store(true, Ordering::Release);
+        guard
+    }
+
+    fn write_back(&self) -> FileSystemResult<()> {
+        let page = self.read();
+        if self.dirty.swap(false, Ordering::AcqRel) {
+            let result = self.pool.write_page(self.id, &page);
+            if result.is_err() {
+                self.dirty.store(true, Ordering::Release);
+            }
+            result?;
+        }
+        Ok(())
+    }
+}
+
+impl<H: FileHandle> Drop for PinnedPage<'_, H> {
+    fn drop(&mut self) {
+        self.pool.unpin(self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        BufferPool, ClockPolicy, EvictionPolicy, FileSystem, LruPolicy, Lsn, MemoryFileSystem,
+        PagedFile,
+    };
+    use std::sync::{Arc, Mutex};
+
+    fn pool<P: EvictionPolicy>(
+        policy: P,
+    ) -> BufferPool<<MemoryFileSystem as FileSystem>::FileHandle> {
+        let fs = MemoryFileSystem::new();
+        let file = PagedFile::new(fs.create_file("/pages.dat").unwrap(), 64).unwrap();
+        BufferPool::new(file, 3 * 64, policy)
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_buffer_pool_eviction() {
+        for pool in [pool(LruPolicy::new()), pool(ClockPolicy::new())] {
+            assert_eq!(pool.capacity(), 3);
+            for i in 0..8u8 {
+                let page = pool.new_page().unwrap();
+                page.write().data_mut()[0] = i;
+                assert!(page.is_dirty());
+            }
+            assert_eq!(pool.resident(), 3);
+
+            // Evicted pages were written back and reload intact
+            for i in 0..8u8 {
+                assert_eq!(pool.pin(u64::from(i)).unwrap().read().data()[0], i);
+            }
+
+            // Pinned pages are never evicted
+            let pinned = (0..3).map(|id| pool.pin(id).unwrap()).collect::<Vec<_>>();
+            assert!(pool.pin(5).is_err());
+            drop(pinned);
+            assert_eq!(pool.pin(5).unwrap().read().data()[0], 5);
+        }
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_buffer_pool_lru_order() {
+        let pool = pool(LruPolicy::new());
+        for _ in 0..3 {
+            pool.new_page().unwrap();
+        }
+        // Touch page 0 so page 1 becomes the least recently used
+        pool.pin(0).unwrap();
+        let page = pool.new_page().unwrap();
+        assert_eq!(page.id(), 3);
+        drop(page);
+        let state = pool.state.lock().unwrap();
+        assert!(state.frames.contains_key(&0));
+        assert!(!state.frames.contains_key(&1));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_buffer_pool_flush() {
+        let pool = pool(ClockPolicy::new());
+        let page = pool.new_page().unwrap();
+        page.write().data_mut()[..4].copy_from_slice(b"data");
+        pool.flush_page(page.id()).unwrap();
+        assert!(!page.is_dirty());
+        page.write().data_mut()[..4].copy_from_slice(b"more");
+        drop(page);
+
+        let mut file = pool.into_inner().unwrap();
+        assert_eq!(&file.read_page(0).unwrap().data()[..4], b"more");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_buffer_pool_write_ahead() {
+        let fs = MemoryFileSystem::new();
+        let file = PagedFile::new(fs.create_file("/pages.dat").unwrap(), 64)
+            .unwrap()
+            .with_page_lsn()
+            .unwrap();
+        let forced = Arc::new(Mutex::new(Vec::new()));
+        let pool = BufferPool::new(file, 64, LruPolicy::new()).with_write_ahead({
+            let forced = forced.clone();
+            move |lsn| {
+                forced.lock().unwrap().push(lsn.offset);
+                Ok(())
+            }
+        });
+
+        let page = pool.new_page().unwrap();
+        page.write().set_lsn(Lsn {
+            segment: 0,
+            offset: 7,
+        });
+        assert!(pool.is_dirty(0));
+        drop(page);
+        // Evicting the page forces the log first
+        pool.new_page().unwrap().write().set_lsn(Lsn {
+            segment: 0,
+            offset: 9,
+        });
+        assert!(!pool.is_dirty(0));
+        assert_eq!(*forced.lock().unwrap(), [7]);
+        pool.flush_all().unwrap();
+        assert_eq!(*forced.lock().unwrap(), [7, 9]);
+        assert_eq!(pool.page_count().unwrap(), 2);
+        assert_eq!(pool.pin(0).unwrap().read().lsn().offset, 7);
+
+        // A failed hook leaves the page dirty
+        let pool = pool.with_write_ahead(|_| Err(crate::FileSystemError::InvalidOperation));
+        pool.pin(1).unwrap().write().data_mut()[0] = 1;
+        assert!(pool.flush_page(1).is_err());
+        assert!(pool.is_dirty(1));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_buffer_pool_copy() {
+        let fs = MemoryFileSystem::new();
+        let file = PagedFile::new(fs.create_file("/pages.dat").unwrap(), 64)
+            .unwrap()
+            .with_page_lsn()
+            .unwrap();
+        let pool = BufferPool::new(file, 2 * 64, LruPolicy::new());
+        for byte in 1..=3 {
+            let page = pool.new_page().unwrap();
+            page.write().data_mut()[0] = byte;
+            page.write().set_lsn(Lsn {
+                segment: 0,
+                offset: u64::from(byte),
+            });
+        }
+        assert_eq!(pool.path(), "/pages.dat");
+
+        let mut copy = pool
+            .create_copy(fs.create_file("/copy.dat").unwrap())
+            .unwrap();
+        assert!(copy.has_page_lsn());
+        assert_eq!(pool.copy_to(&mut copy).unwrap(), 3);
+        pool.new_page().unwrap().write().data_mut()[0] = 4;
+        assert_eq!(pool.copy_to(&mut copy).unwrap(), 1);
+        assert_eq!(pool.copy_to(&mut copy).unwrap(), 0);
+        for (id, byte) in (0..4).zip(1..) {
+            assert_eq!(copy.read_page(id).unwrap().data()[0], byte);
+        }
+        assert_eq!(copy.read_page(2).unwrap().lsn().offset, 3);
+        // Pages written back are left as they were
+        assert_eq!(pool.resident(), 2);
+    }
+}
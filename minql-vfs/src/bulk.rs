@@ -0,0 +1,680 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::progress::{NoProgress, ProgressTracker};
+use crate::utility::{child_path, normalize_path};
+use crate::{
+    FileHandle, FileSystem, FileSystemError, FileSystemResult, FileType, OpenOptions, Progress,
+    ProgressObserver, SymlinkPolicy,
+};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Worker pool of the parallel bulk operations, like [`copy_parallel`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ParallelOptions {
+    workers: usize,
+}
+
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        ParallelOptions {
+            workers: std::thread::available_parallelism().map_or(4, NonZeroUsize::get),
+        }
+    }
+}
+
+impl ParallelOptions {
+    /// Create options with a worker per available CPU.
+    #[must_use]
+    pub fn new() -> ParallelOptions {
+        ParallelOptions::default()
+    }
+
+    /// Set the number of worker threads, at least one.
+    ///
+    /// Remote backends spend most of each operation waiting on the network, so they benefit
+    /// from many more workers than there are CPUs.
+    #[must_use]
+    pub fn with_workers(mut self, workers: usize) -> ParallelOptions {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Number of worker threads.
+    #[must_use]
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+}
+
+/// Outcome of a parallel bulk operation, which keeps going past entries that fail.
+#[derive(Debug, Default)]
+pub struct BulkReport {
+    progress: Progress,
+    errors: Vec<(String, FileSystemError)>,
+}
+
+impl BulkReport {
+    /// Number of entries and bytes processed successfully.
+    #[must_use]
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    /// Entries which failed along with their errors, in the order the entries were walked
+    /// rather than the order the workers failed on them.
+    #[must_use]
+    pub fn errors(&self) -> &[(String, FileSystemError)] {
+        &self.errors
+    }
+
+    /// Check if every entry succeeded.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Get the progress if every entry succeeded, or else the first error.
+    pub fn into_result(self) -> FileSystemResult<Progress> {
+        match self.errors.into_iter().next() {
+            Some((_, err)) => Err(err),
+            None => Ok(self.progress),
+        }
+    }
+
+    /// Count the outcome of an entry, where success carries the number of bytes processed.
+    pub(crate) fn record(&mut self, path: &str, result: FileSystemResult<u64>) {
+        match result {
+            Ok(bytes) => {
+                self.progress.entries += 1;
+                self.progress.bytes += bytes;
+            }
+            Err(err) => self.errors.push((path.to_string(), err)),
+        }
+    }
+}
+
+/// Run `task` over every item on up to `workers` threads, returning the results in the order
+/// of the items.
+pub(crate) fn run_parallel<T: Sync, R: Send>(
+    workers: usize,
+    items: &[T],
+    task: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    if workers <= 1 || items.len() <= 1 {
+        return items.iter().map(task).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(items.iter().map(|_| None).collect::<Vec<Option<R>>>());
+    std::thread::scope(|scope| {
+        for _ in 0..workers.min(items.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = task(item);
+                results.lock().expect("Poisoned Lock")[index] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .expect("Poisoned Lock")
+        .into_iter()
+        .map(|result| result.expect("Task Completed"))
+        .collect()
+}
+
+/// Copy the contents of one file over another, returning the number of bytes copied.
+pub(crate) fn copy_contents<S: FileSystem, D: FileSystem>(
+    source: &S,
+    source_path: &str,
+    destination: &D,
+    destination_path: &str,
+) -> FileSystemResult<u64> {
+    let mut reader = source.open_file(source_path)?;
+    let mut writer = destination.open_with(
+        destination_path,
+        OpenOptions::new().write(true).create(true).truncate(true),
+    )?;
+    let copied = std::io::copy(&mut reader, &mut writer).map_err(FileSystemError::io_error)?;
+    writer.sync_all()?;
+    Ok(copied)
+}
+
+/// Copy a file or directory tree from one `FileSystem` to another, replacing existing files.
+///
+/// Directories are created as needed and symbolic links are copied as links. Returns the
+/// number of entries and bytes copied.
+///
+/// ```rust
+/// use minql_vfs::{copy, FileSystem, MemoryFileSystem};
+///
+/// let source = MemoryFileSystem::new();
+/// source.create_directory_all("/db/tables").unwrap();
+/// source.write("/db/tables/users.db", b"rows").unwrap();
+/// let destination = MemoryFileSystem::new();
+///
+/// let copied = copy(&source, "/db", &destination, "/backup").unwrap();
+/// assert_eq!((copied.entries, copied.bytes), (3, 4));
+/// assert_eq!(destination.read("/backup/tables/users.db").unwrap(), b"rows");
+/// ```
+pub fn copy<S: FileSystem, D: FileSystem>(
+    source: &S,
+    source_path: &str,
+    destination: &D,
+    destination_path: &str,
+) -> FileSystemResult<Progress> {
+    copy_with_progress(
+        source,
+        source_path,
+        destination,
+        destination_path,
+        &mut NoProgress,
+    )
+}
+
+/// Copy a file or directory tree from one `FileSystem` to another like [`copy`], reporting
+/// progress to `observer`.
+pub fn copy_with_progress<S: FileSystem, D: FileSystem>(
+    source: &S,
+    source_path: &str,
+    destination: &D,
+    destination_path: &str,
+    observer: &mut dyn ProgressObserver,
+) -> FileSystemResult<Progress> {
+    let source_path = normalize_path(source_path)?;
+    let destination_path = normalize_path(destination_path)?;
+    let mut tracker = ProgressTracker::new(observer);
+    copy_entry(
+        source,
+        &source_path,
+        destination,
+        &destination_path,
+        &mut tracker,
+    )?;
+    Ok(tracker.progress())
+}
+
+/// Copy an entry and everything below it.
+fn copy_entry<S: FileSystem, D: FileSystem>(
+    source: &S,
+    source_path: &str,
+    destination: &D,
+    destination_path: &str,
+    tracker: &mut ProgressTracker<'_>,
+) -> FileSystemResult<()> {
+    match source.file_type(source_path, SymlinkPolicy::NoFollow)? {
+        FileType::Directory => {
+            destination.create_directory_all(destination_path)?;
+            tracker.entry(destination_path);
+            let mut children = source.list_directory(source_path)?;
+            children.sort();
+            for name in children {
+                copy_entry(
+                    source,
+                    &child_path(source_path, &name),
+                    destination,
+                    &child_path(destination_path, &name),
+                    tracker,
+                )?;
+            }
+        }
+        FileType::File => {
+            let reader = source.open_file(source_path)?;
+            let mut writer = destination.open_with(
+                destination_path,
+                OpenOptions::new().write(true).create(true).truncate(true),
+            )?;
+            std::io::copy(&mut tracker.reader(destination_path, reader), &mut writer)
+                .map_err(FileSystemError::io_error)?;
+            writer.sync_all()?;
+            tracker.entry(destination_path);
+        }
+        FileType::Symlink => {
+            destination.create_symlink(&source.read_link(source_path)?, destination_path)?;
+            tracker.entry(destination_path);
+        }
+    }
+    Ok(())
+}
+
+/// Copy a file or directory tree from one `FileSystem` to another like [`copy`], copying files
+/// and symbolic links on a pool of worker threads.
+///
+/// Directories are created while walking the source, before any file below them is copied.
+/// Entries which fail are reported in the [`BulkReport`] without stopping the others, and
+/// nothing is copied below a directory which couldn't be created or listed. Fails outright only
+/// if the source path can't be inspected.
+///
+/// ```rust
+/// use minql_vfs::{copy_parallel, FileSystem, MemoryFileSystem, ParallelOptions};
+///
+/// let source = MemoryFileSystem::new();
+/// source.create_directory("/segments").unwrap();
+/// for index in 0..100 {
+///     source.write(&format!("/segments/{index:03}.seg"), b"rows").unwrap();
+/// }
+/// let destination = MemoryFileSystem::new();
+///
+/// let options = ParallelOptions::new().with_workers(8);
+/// let report = copy_parallel(&source, "/segments", &destination, "/backup", &options).unwrap();
+/// assert!(report.is_ok());
+/// assert_eq!(report.progress().entries, 101);
+/// assert_eq!(destination.read("/backup/042.seg").unwrap(), b"rows");
+/// ```
+pub fn copy_parallel<S: FileSystem, D: FileSystem>(
+    source: &S,
+    source_path: &str,
+    destination: &D,
+    destination_path: &str,
+    options: &ParallelOptions,
+) -> FileSystemResult<BulkReport> {
+    let source_path = normalize_path(source_path)?;
+    let destination_path = normalize_path(destination_path)?;
+    let file_type = source.file_type(&source_path, SymlinkPolicy::NoFollow)?;
+    let mut walk = Walk::default();
+    walk.copy(
+        source,
+        &source_path,
+        destination,
+        &destination_path,
+        file_type,
+    );
+    let results = run_parallel(options.workers, &walk.tasks, |task| match task.file_type {
+        FileType::File => copy_contents(source, &task.source, destination, &task.path),
+        FileType::Directory | FileType::Symlink => source
+            .read_link(&task.source)
+            .and_then(|target| destination.create_symlink(&target, &task.path))
+            .map(|()| 0),
+    });
+    Ok(walk.report(results))
+}
+
+/// Remove a directory and everything below it like [`FileSystem::remove_directory_all`],
+/// removing entries on a pool of worker threads.
+///
+/// Files and symbolic links are removed first, then directories from the deepest up, each
+/// level at once. Entries which fail are reported in the [`BulkReport`] without stopping the
+/// others, which leaves the directories above them in place. Bytes count the sizes of removed
+/// files.
+pub fn remove_directory_all_parallel<F: FileSystem>(
+    fs: &F,
+    path: &str,
+    options: &ParallelOptions,
+) -> FileSystemResult<BulkReport> {
+    let path = normalize_path(path)?;
+    if !fs.is_directory(&path)? {
+        return Err(FileSystemError::InvalidOperation);
+    }
+    let mut walk = Walk::default();
+    walk.list(fs, &path, 0);
+    let mut results = run_parallel(options.workers, &walk.tasks, |task| match task.file_type {
+        FileType::File => {
+            let size = fs.filesize(&task.path)?;
+            fs.remove_file(&task.path)?;
+            Ok(size)
+        }
+        FileType::Directory | FileType::Symlink => fs.remove_file(&task.path).map(|()| 0),
+    });
+    walk.directories
+        .sort_by_key(|(depth, _)| std::cmp::Reverse(*depth));
+    for level in walk
+        .directories
+        .chunk_by(|(first, _), (second, _)| first == second)
+    {
+        let level = level.iter().map(|(_, task)| task).collect::<Vec<_>>();
+        let removed = run_parallel(options.workers, &level, |task| {
+            fs.remove_directory(&task.path).map(|()| 0)
+        });
+        walk.tasks.extend(level.into_iter().cloned());
+        results.extend(removed);
+    }
+    Ok(walk.report(results))
+}
+
+/// Entry of a tree walked by a parallel bulk operation, numbered in walk order.
+#[derive(Clone)]
+struct WalkTask {
+    index: usize,
+    source: String,
+    path: String,
+    file_type: FileType,
+}
+
+/// Tree walked by a parallel bulk operation, split into the entries left for the workers and
+/// the directories or failures already dealt with while walking.
+#[derive(Default)]
+struct Walk {
+    entries: usize,
+    tasks: Vec<WalkTask>,
+    directories: Vec<(usize, WalkTask)>,
+    walked: Vec<(usize, String, FileSystemResult<u64>)>,
+}
+
+impl Walk {
+    /// Number the next entry.
+    fn next(&mut self) -> usize {
+        self.entries += 1;
+        self.entries
+    }
+
+    /// Create the destination directories of a copy, queueing its files and links.
+    fn copy<S: FileSystem, D: FileSystem>(
+        &mut self,
+        source: &S,
+        source_path: &str,
+        destination: &D,
+        destination_path: &str,
+        file_type: FileType,
+    ) {
+        let index = self.next();
+        if file_type != FileType::Directory {
+            self.tasks.push(WalkTask {
+                index,
+                source: source_path.to_string(),
+                path: destination_path.to_string(),
+                file_type,
+            });
+            return;
+        }
+        let children = destination
+            .create_directory_all(destination_path)
+            .and_then(|()| source.list_directory(source_path));
+        let mut children = match children {
+            Ok(children) => children,
+            Err(err) => {
+                self.walked
+                    .push((index, destination_path.to_string(), Err(err)));
+                return;
+            }
+        };
+        self.walked
+            .push((index, destination_path.to_string(), Ok(0)));
+        children.sort();
+        for name in children {
+            let (source_child, destination_child) = (
+                child_path(source_path, &name),
+                child_path(destination_path, &name),
+            );
+            match source.file_type(&source_child, SymlinkPolicy::NoFollow) {
+                Ok(file_type) => self.copy(
+                    source,
+                    &source_child,
+                    destination,
+                    &destination_child,
+                    file_type,
+                ),
+                Err(err) => {
+                    let index = self.next();
+                    self.walked.push((index, destination_child, Err(err)));
+                }
+            }
+        }
+    }
+
+    /// List a directory to remove, queueing its files and links and recording its
+    /// directories by depth.
+    fn list<F: FileSystem>(&mut self, fs: &F, path: &str, depth: usize) {
+        let index = self.next();
+        let mut children = match fs.list_directory(path) {
+            Ok(children) => children,
+            Err(err) => {
+                self.walked.push((index, path.to_string(), Err(err)));
+                return;
+            }
+        };
+        self.directories.push((
+            depth,
+            WalkTask {
+                index,
+                source: path.to_string(),
+                path: path.to_string(),
+                file_type: FileType::Directory,
+            },
+        ));
+        children.sort();
+        for name in children {
+            let child = child_path(path, &name);
+            match fs.file_type(&child, SymlinkPolicy::NoFollow) {
+                Ok(FileType::Directory) => self.list(fs, &child, depth + 1),
+                Ok(file_type) => {
+                    let index = self.next();
+                    self.tasks.push(WalkTask {
+                        index,
+                        source: child.clone(),
+                        path: child,
+                        file_type,
+                    });
+                }
+                Err(err) => {
+                    let index = self.next();
+                    self.walked.push((index, child, Err(err)));
+                }
+            }
+        }
+    }
+
+    /// Combine the outcomes of the walk and of the tasks, matched to the tasks by position,
+    /// into a report ordered by entry.
+    fn report(self, results: Vec<FileSystemResult<u64>>) -> BulkReport {
+        let mut outcomes = self.walked;
+        outcomes.extend(
+            self.tasks
+                .into_iter()
+                .zip(results)
+                .map(|(task, result)| (task.index, task.path, result)),
+        );
+        outcomes.sort_by_key(|(index, _, _)| *index);
+        let mut report = BulkReport::default();
+        for (_, path, result) in outcomes {
+            report.record(&path, result);
+        }
+        report
+    }
+}
+
+/// Remove a directory and everything below it like [`FileSystem::remove_directory_all`], one
+/// entry at a time, reporting progress to `observer`.
+///
+/// Bytes count the sizes of removed files. Returns the number of entries and bytes removed.
+pub fn remove_directory_all_with_progress<F: FileSystem>(
+    fs: &F,
+    path: &str,
+    observer: &mut dyn ProgressObserver,
+) -> FileSystemResult<Progress> {
+    let path = normalize_path(path)?;
+    if !fs.is_directory(&path)? {
+        return Err(FileSystemError::InvalidOperation);
+    }
+    let mut tracker = ProgressTracker::new(observer);
+    remove_entry(fs, &path, FileType::Directory, &mut tracker)?;
+    Ok(tracker.progress())
+}
+
+/// Remove an entry of the given type and everything below it.
+fn remove_entry<F: FileSystem>(
+    fs: &F,
+    path: &str,
+    file_type: FileType,
+    tracker: &mut ProgressTracker<'_>,
+) -> FileSystemResult<()> {
+    match file_type {
+        FileType::Directory => {
+            let mut children = fs.list_directory(path)?;
+            children.sort();
+            for name in children {
+                let child = child_path(path, &name);
+                let file_type = fs.file_type(&child, SymlinkPolicy::NoFollow)?;
+                remove_entry(fs, &child, file_type, tracker)?;
+            }
+            fs.remove_directory(path)?;
+        }
+        FileType::File => {
+            let size = fs.filesize(path)?;
+            fs.remove_file(path)?;
+            tracker.bytes(path, size);
+        }
+        FileType::Symlink => fs.remove_file(path)?,
+    }
+    tracker.entry(path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        copy, copy_parallel, copy_with_progress, remove_directory_all_parallel,
+        remove_directory_all_with_progress, ParallelOptions,
+    };
+    use crate::{FileSystem, FileSystemError, MemoryFileSystem, Permissions, Progress};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_copy_and_remove_with_progress() {
+        let source = MemoryFileSystem::new();
+        source.create_directory_all("/tree/a").unwrap();
+        source.write("/tree/a/big", &vec![1; 100_000]).unwrap();
+        source.write("/tree/small", b"small").unwrap();
+        source.create_symlink("small", "/tree/link").unwrap();
+
+        let destination = MemoryFileSystem::new();
+        let mut updates = Vec::new();
+        let copied = copy_with_progress(
+            &source,
+            "/tree",
+            &destination,
+            "/copy",
+            &mut |path: &str, progress: Progress| updates.push((path.to_string(), progress)),
+        )
+        .unwrap();
+        assert_eq!(
+            copied,
+            Progress {
+                entries: 5,
+                bytes: 100_005
+            }
+        );
+        assert_eq!(
+            updates.last().unwrap(),
+            &("/copy/small".to_string(), copied)
+        );
+        assert!(updates
+            .windows(2)
+            .all(|pair| pair[0].1.bytes <= pair[1].1.bytes));
+        assert!(updates.len() > 5, "large files report while in flight");
+        assert_eq!(destination.read("/copy/a/big").unwrap(), vec![1; 100_000]);
+        assert_eq!(destination.read_link("/copy/link").unwrap(), "small");
+
+        assert_eq!(
+            copy(&source, "/tree/small", &destination, "/copy/a/big").unwrap(),
+            Progress {
+                entries: 1,
+                bytes: 5
+            }
+        );
+        assert_eq!(destination.read("/copy/a/big").unwrap(), b"small");
+
+        let mut removed = Vec::new();
+        let progress = remove_directory_all_with_progress(
+            &destination,
+            "/copy",
+            &mut |path: &str, progress: Progress| removed.push(path.to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            progress,
+            Progress {
+                entries: 5,
+                bytes: 10
+            }
+        );
+        assert_eq!(removed.last().unwrap(), "/copy");
+        assert!(!destination.exists("/copy").unwrap());
+    }
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_parallel_copy_and_remove() {
+        let source = MemoryFileSystem::new();
+        for directory in 0..4 {
+            source
+                .create_directory_all(&format!("/tree/{directory}"))
+                .unwrap();
+            for file in 0..25 {
+                source
+                    .write(&format!("/tree/{directory}/{file:02}"), &[7; 10])
+                    .unwrap();
+            }
+        }
+        source.create_symlink("0/00", "/tree/link").unwrap();
+
+        let destination = MemoryFileSystem::new();
+        let options = ParallelOptions::new().with_workers(8);
+        assert_eq!(options.workers(), 8);
+        let report = copy_parallel(&source, "/tree", &destination, "/copy", &options).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(
+            report.progress(),
+            Progress {
+                entries: 106,
+                bytes: 1000
+            }
+        );
+        assert_eq!(destination.read("/copy/3/24").unwrap(), vec![7; 10]);
+        assert_eq!(destination.read_link("/copy/link").unwrap(), "0/00");
+
+        // Failures are collected in walk order, and leave their parents in place
+        destination
+            .set_permissions("/copy/1", Permissions::new().readonly(true))
+            .unwrap();
+        destination
+            .set_permissions("/copy/2", Permissions::new().readonly(true))
+            .unwrap();
+        let report = remove_directory_all_parallel(&destination, "/copy", &options).unwrap();
+        let failed = report
+            .errors()
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(failed.len(), 53);
+        assert_eq!(failed[..3], ["/copy", "/copy/1", "/copy/1/00"]);
+        assert_eq!(failed[26..28], ["/copy/1/24", "/copy/2"]);
+        assert_eq!(failed[52], "/copy/2/24");
+        assert!(report
+            .errors()
+            .iter()
+            .all(|(path, err)| match path.matches('/').count() {
+                3 => matches!(err, FileSystemError::PermissionDenied),
+                _ => matches!(err, FileSystemError::InvalidOperation),
+            }));
+        assert_eq!(report.progress().entries, 106 - 53);
+        assert!(report.into_result().is_err());
+        assert_eq!(destination.list_directory("/copy").unwrap(), ["1", "2"]);
+
+        destination
+            .set_permissions("/copy/1", Permissions::new())
+            .unwrap();
+        destination
+            .set_permissions("/copy/2", Permissions::new())
+            .unwrap();
+        let report = remove_directory_all_parallel(&destination, "/copy", &options).unwrap();
+        assert_eq!(report.into_result().unwrap().entries, 53);
+        assert!(!destination.exists("/copy").unwrap());
+    }
+}
@@ -0,0 +1,483 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{temp_name, FileHandle, FileSystem, FileSystemError, FileSystemResult};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::sync::Mutex;
+
+/// Suffix of the file holding the reference count of each object.
+const REFS_SUFFIX: &str = ".refs";
+
+/// Size of the chunks objects are copied in.
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// SHA-256 hash identifying a blob in a [`CasStore`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ContentHash([u8; 32]);
+
+impl ContentHash {
+    /// Hash a blob.
+    #[must_use]
+    pub fn of(data: &[u8]) -> ContentHash {
+        ContentHash(Sha256::digest(data).into())
+    }
+
+    /// Raw bytes of the hash.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ContentHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ContentHash({self})")
+    }
+}
+
+impl std::str::FromStr for ContentHash {
+    type Err = FileSystemError;
+
+    fn from_str(hex: &str) -> Result<Self, Self::Err> {
+        let invalid = || FileSystemError::InternalError(format!("invalid content hash {hex:?}"));
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 32];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte =
+                u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(ContentHash(bytes))
+    }
+}
+
+/// Outcome of a [`CasStore::gc`] sweep.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct GcStats {
+    /// Unreferenced objects removed
+    pub objects: u64,
+    /// Bytes freed by removing objects
+    pub bytes: u64,
+    /// Abandoned temporary files removed
+    pub temporaries: u64,
+}
+
+/// Content-Addressed Store
+///
+/// Stores blobs in any [`FileSystem`] keyed by their SHA-256, so identical content is only ever
+/// stored once. Each object carries a reference count: inserting a blob or calling
+/// [`CasStore::add_ref`] increments it, [`CasStore::release`] decrements it, and
+/// [`CasStore::gc`] removes every object no longer referenced.
+///
+/// Objects live under `objects/` in the store's root, fanned out by the first byte of their
+/// hash. Streaming inserts are staged under `tmp/` and copied into place once their hash is
+/// known.
+///
+/// ```rust
+/// use minql_vfs::{CasStore, MemoryFileSystem};
+/// use std::io::{Read, Write};
+///
+/// let store = CasStore::open(MemoryFileSystem::new(), "/cas").unwrap();
+/// let hash = store.put(b"artifact").unwrap();
+///
+/// let mut writer = store.writer().unwrap();
+/// writer.write_all(b"arti").unwrap();
+/// writer.write_all(b"fact").unwrap();
+/// assert_eq!(writer.finish().unwrap(), hash);
+/// assert_eq!(store.refcount(&hash).unwrap(), 2);
+///
+/// let mut contents = String::new();
+/// store.reader(&hash).unwrap().read_to_string(&mut contents).unwrap();
+/// assert_eq!(contents, "artifact");
+/// ```
+#[derive(Debug)]
+pub struct CasStore<F: FileSystem> {
+    fs: F,
+    root: String,
+    /// Temporary files of writers still in progress, which also serializes reference updates.
+    active: Mutex<HashSet<String>>,
+}
+
+impl<F: FileSystem> CasStore<F> {
+    /// Open or create a store rooted at `root` within `fs`.
+    pub fn open(fs: F, root: &str) -> FileSystemResult<CasStore<F>> {
+        let root = root.trim_end_matches('/').to_string();
+        fs.create_directory_all(&format!("{root}/objects"))?;
+        fs.create_directory_all(&format!("{root}/tmp"))?;
+        Ok(CasStore {
+            fs,
+            root,
+            active: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Borrow the underlying filesystem.
+    pub fn filesystem(&self) -> &F {
+        &self.fs
+    }
+
+    /// Store a blob, adding a reference to it.
+    #[tracing::instrument(level = "trace", skip(data))]
+    pub fn put(&self, data: &[u8]) -> FileSystemResult<ContentHash> {
+        let mut writer = self.writer()?;
+        writer.write_all(data).map_err(FileSystemError::io_error)?;
+        writer.finish()
+    }
+
+    /// Start streaming a blob into the store.
+    #[tracing::instrument(level = "trace")]
+    pub fn writer(&self) -> FileSystemResult<CasWriter<'_, F>> {
+        let name = temp_name("blob");
+        let path = format!("{}/tmp/{name}", self.root);
+        let handle = self.fs.create_file(&path)?;
+        self.active
+            .lock()
+            .expect("Poisoned Lock")
+            .insert(name.clone());
+        Ok(CasWriter {
+            store: self,
+            name,
+            path,
+            handle: Some(handle),
+            hasher: Sha256::new(),
+        })
+    }
+
+    /// Read a whole blob, verifying its hash.
+    #[tracing::instrument(level = "trace")]
+    pub fn get(&self, hash: &ContentHash) -> FileSystemResult<Vec<u8>> {
+        let path = self.object_path(hash);
+        let mut contents = Vec::new();
+        self.fs
+            .open_file(&path)?
+            .read_to_end(&mut contents)
+            .map_err(FileSystemError::io_error)?;
+        if ContentHash::of(&contents) != *hash {
+            return Err(FileSystemError::CorruptData { path, offset: 0 });
+        }
+        Ok(contents)
+    }
+
+    /// Stream a blob, which fails at the end if its contents don't match its hash.
+    #[tracing::instrument(level = "trace")]
+    pub fn reader(&self, hash: &ContentHash) -> FileSystemResult<CasReader<F::FileHandle>> {
+        Ok(CasReader {
+            path: self.object_path(hash),
+            expected: *hash,
+            handle: self.fs.open_file(&self.object_path(hash))?,
+            hasher: Sha256::new(),
+        })
+    }
+
+    /// Check if a blob is stored.
+    pub fn contains(&self, hash: &ContentHash) -> FileSystemResult<bool> {
+        self.fs.is_file(&self.object_path(hash))
+    }
+
+    /// Size of a stored blob.
+    pub fn size(&self, hash: &ContentHash) -> FileSystemResult<u64> {
+        self.fs.filesize(&self.object_path(hash))
+    }
+
+    /// Number of references held on a blob.
+    pub fn refcount(&self, hash: &ContentHash) -> FileSystemResult<u64> {
+        let _guard = self.active.lock().expect("Poisoned Lock");
+        self.read_refs(hash)
+    }
+
+    /// Add a reference to a stored blob, returning the new count.
+    #[tracing::instrument(level = "trace")]
+    pub fn add_ref(&self, hash: &ContentHash) -> FileSystemResult<u64> {
+        let _guard = self.active.lock().expect("Poisoned Lock");
+        if !self.contains(hash)? {
+            return Err(FileSystemError::PathMissing);
+        }
+        let count = self.read_refs(hash)? + 1;
+        self.write_refs(hash, count)?;
+        Ok(count)
+    }
+
+    /// Drop a reference to a blob, returning the remaining count. Unreferenced blobs stay
+    /// readable until the next [`CasStore::gc`].
+    #[tracing::instrument(level = "trace")]
+    pub fn release(&self, hash: &ContentHash) -> FileSystemResult<u64> {
+        let _guard = self.active.lock().expect("Poisoned Lock");
+        let count = self.read_refs(hash)?;
+        if count == 0 {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        self.write_refs(hash, count - 1)?;
+        Ok(count - 1)
+    }
+
+    /// Every stored blob, referenced or not.
+    pub fn list(&self) -> FileSystemResult<Vec<ContentHash>> {
+        let mut hashes = Vec::new();
+        let objects = format!("{}/objects", self.root);
+        for fanout in self.fs.list_directory(&objects)? {
+            for name in self.fs.list_directory(&format!("{objects}/{fanout}"))? {
+                if name.ends_with(REFS_SUFFIX) {
+                    continue;
+                }
+                if let Ok(hash) = format!("{fanout}{name}").parse() {
+                    hashes.push(hash);
+                }
+            }
+        }
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    /// Remove every unreferenced blob and any temporary file abandoned by an interrupted insert.
+    #[tracing::instrument(level = "trace")]
+    pub fn gc(&self) -> FileSystemResult<GcStats> {
+        let active = self.active.lock().expect("Poisoned Lock");
+        let mut stats = GcStats::default();
+        for hash in self.list()? {
+            if self.read_refs(&hash)? == 0 {
+                let path = self.object_path(&hash);
+                stats.bytes += self.fs.filesize(&path)?;
+                stats.objects += 1;
+                self.fs.remove_file(&path)?;
+                let refs = format!("{path}{REFS_SUFFIX}");
+                if self.fs.exists(&refs)? {
+                    self.fs.remove_file(&refs)?;
+                }
+            }
+        }
+        let tmp = format!("{}/tmp", self.root);
+        for name in self.fs.list_directory(&tmp)? {
+            if !active.contains(&name) {
+                self.fs.remove_file(&format!("{tmp}/{name}"))?;
+                stats.temporaries += 1;
+            }
+        }
+        tracing::debug!(?stats, "Collected garbage");
+        Ok(stats)
+    }
+
+    fn object_path(&self, hash: &ContentHash) -> String {
+        let hex = hash.to_string();
+        format!("{}/objects/{}/{}", self.root, &hex[..2], &hex[2..])
+    }
+
+    fn read_refs(&self, hash: &ContentHash) -> FileSystemResult<u64> {
+        let path = format!("{}{REFS_SUFFIX}", self.object_path(hash));
+        if !self.fs.exists(&path)? {
+            return Ok(0);
+        }
+        let mut count = [0; 8];
+        let mut handle = self.fs.open_file(&path)?;
+        handle
+            .read_exact(&mut count)
+            .map_err(FileSystemError::io_error)?;
+        Ok(u64::from_le_bytes(count))
+    }
+
+    fn write_refs(&self, hash: &ContentHash, count: u64) -> FileSystemResult<()> {
+        let path = format!("{}{REFS_SUFFIX}", self.object_path(hash));
+        let mut handle = if self.fs.exists(&path)? {
+            self.fs
+                .open_with(&path, crate::OpenOptions::new().write(true))?
+        } else {
+            self.fs.create_file(&path)?
+        };
+        handle.write_to_offset(0, &count.to_le_bytes())?;
+        handle.sync_data()
+    }
+
+    /// Move a finished temporary file into place as `hash`, adding a reference to it.
+    fn commit(&self, name: &str, temp: &str, hash: &ContentHash) -> FileSystemResult<()> {
+        let mut active = self.active.lock().expect("Poisoned Lock");
+        active.remove(name);
+        let path = self.object_path(hash);
+        if !self.fs.is_file(&path)? {
+            self.fs
+                .create_directory_all(&path[..path.rfind('/').expect("Object Directory")])?;
+            let mut source = self.fs.open_file(temp)?;
+            let mut target = self.fs.create_file(&path)?;
+            let mut buffer = vec![0; COPY_BUFFER_SIZE];
+            loop {
+                let read = source
+                    .read(&mut buffer)
+                    .map_err(FileSystemError::io_error)?;
+                if read == 0 {
+                    break;
+                }
+                target
+                    .write_all(&buffer[..read])
+                    .map_err(FileSystemError::io_error)?;
+            }
+            target.sync_all()?;
+        }
+        self.fs.remove_file(temp)?;
+        let count = self.read_refs(hash)? + 1;
+        self.write_refs(hash, count)
+    }
+}
+
+/// Streaming insert into a [`CasStore`], completed by [`CasWriter::finish`].
+///
+/// Dropping a writer without finishing it discards what was written.
+#[derive(Debug)]
+pub struct CasWriter<'a, F: FileSystem> {
+    store: &'a CasStore<F>,
+    name: String,
+    path: String,
+    handle: Option<F::FileHandle>,
+    hasher: Sha256,
+}
+
+impl<F: FileSystem> CasWriter<'_, F> {
+    /// Store everything written, returning its hash.
+    #[tracing::instrument(level = "trace")]
+    pub fn finish(mut self) -> FileSystemResult<ContentHash> {
+        let hash = ContentHash(std::mem::take(&mut self.hasher).finalize().into());
+        if let Some(mut handle) = self.handle.take() {
+            handle.sync_data()?;
+        }
+        self.store.commit(&self.name, &self.path, &hash)?;
+        self.name.clear();
+        Ok(hash)
+    }
+}
+
+impl<F: FileSystem> Write for CasWriter<'_, F> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let handle = self.handle.as_mut().expect("Open Writer");
+        let written = handle.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.handle.as_mut().expect("Open Writer").flush()
+    }
+}
+
+impl<F: FileSystem> Drop for CasWriter<'_, F> {
+    fn drop(&mut self) {
+        if !self.name.is_empty() {
+            self.handle = None;
+            let _ = self.store.fs.remove_file(&self.path);
+            self.store
+                .active
+                .lock()
+                .expect("Poisoned Lock")
+                .remove(&self.name);
+        }
+    }
+}
+
+/// Streaming read of a blob from a [`CasStore`].
+///
+/// Reaching the end of a blob whose contents don't match its hash fails with
+/// [`FileSystemError::CorruptData`].
+#[derive(Debug)]
+pub struct CasReader<H: FileHandle> {
+    path: String,
+    expected: ContentHash,
+    handle: H,
+    hasher: Sha256,
+}
+
+impl<H: FileHandle> Read for CasReader<H> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.handle.read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            let actual = ContentHash(std::mem::take(&mut self.hasher).finalize().into());
+            if actual != self.expected {
+                return Err(FileSystemError::CorruptData {
+                    path: self.path.clone(),
+                    offset: 0,
+                }
+                .into());
+            }
+            // Verified once, the reader stays at its end.
+            self.expected = ContentHash(Sha256::new().finalize().into());
+        } else {
+            self.hasher.update(&buf[..read]);
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CasStore, ContentHash, FileHandle, FileSystem, FileSystemError, MemoryFileSystem};
+    use std::io::Write;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_cas_store() {
+        let fs = MemoryFileSystem::new();
+        let store = CasStore::open(fs.clone(), "/cas").unwrap();
+        let first = store.put(b"first").unwrap();
+        let second = store.put(b"second").unwrap();
+        assert_eq!(store.put(b"first").unwrap(), first);
+        assert_eq!(first, ContentHash::of(b"first"));
+        assert_eq!(first.to_string().parse::<ContentHash>().unwrap(), first);
+        assert_eq!(store.refcount(&first).unwrap(), 2);
+        assert_eq!(store.get(&second).unwrap(), b"second");
+        assert_eq!(store.size(&second).unwrap(), 6);
+        assert_eq!(store.list().unwrap().len(), 2);
+
+        // Abandoned inserts leave nothing behind
+        let mut writer = store.writer().unwrap();
+        writer.write_all(b"abandoned").unwrap();
+        drop(writer);
+        assert!(fs.list_directory("/cas/tmp").unwrap().is_empty());
+
+        // Only unreferenced objects are collected
+        assert_eq!(store.release(&first).unwrap(), 1);
+        assert_eq!(store.release(&second).unwrap(), 0);
+        assert!(store.release(&second).is_err());
+        let stats = store.gc().unwrap();
+        assert_eq!((stats.objects, stats.bytes), (1, 6));
+        assert!(store.contains(&first).unwrap());
+        assert!(!store.contains(&second).unwrap());
+        assert!(store.add_ref(&second).is_err());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_cas_store_corruption() {
+        let fs = MemoryFileSystem::new();
+        let store = CasStore::open(fs.clone(), "/cas").unwrap();
+        let hash = store.put(b"precious").unwrap();
+        let hex = hash.to_string();
+        fs.open_file(&format!("/cas/objects/{}/{}", &hex[..2], &hex[2..]))
+            .unwrap()
+            .write_to_offset(0, b"P")
+            .unwrap();
+        assert!(matches!(
+            store.get(&hash),
+            Err(FileSystemError::CorruptData { .. })
+        ));
+    }
+}
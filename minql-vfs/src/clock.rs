@@ -0,0 +1,83 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::fmt::Debug;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Source of the current time for timestamps, injectable so tests and simulations control it.
+///
+/// Implemented by [`SystemClock`], the wall clock, [`ManualClock`], which only moves when told
+/// to, and [`Simulation`](crate::Simulation), whose virtual time counts from the Unix epoch.
+pub trait Clock: Debug + Send + Sync + RefUnwindSafe + UnwindSafe + 'static {
+    /// Current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// Clock reading the wall clock of the system.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Clock frozen at a point in time until set or advanced, shared between its clones.
+///
+/// ```rust
+/// use minql_vfs::{Clock, ManualClock};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let clock = ManualClock::new(UNIX_EPOCH);
+/// let shared = clock.clone();
+/// shared.advance(Duration::from_secs(90));
+/// assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(90));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ManualClock(Arc<Mutex<SystemTime>>);
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+impl ManualClock {
+    /// Create a clock frozen at `now`.
+    #[must_use]
+    pub fn new(now: SystemTime) -> ManualClock {
+        ManualClock(Arc::new(Mutex::new(now)))
+    }
+
+    /// Move the clock to `now`, which may be earlier than its current time.
+    pub fn set(&self, now: SystemTime) {
+        *self.0.lock().expect("Poisoned Lock") = now;
+    }
+
+    /// Move the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock().expect("Poisoned Lock") += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().expect("Poisoned Lock")
+    }
+}
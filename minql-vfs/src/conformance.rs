@@ -0,0 +1,434 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Behavioral Conformance Checks for `FileSystem` Implementations
+//!
+//! Each check exercises one area of the [`FileSystem`] and [`FileHandle`] contract against a
+//! fresh, empty and writable filesystem and panics describing the first deviation, so
+//! backends can run them from their own tests:
+//!
+//! ```rust,ignore
+//! #[test]
+//! fn test_conformance() {
+//!     minql_vfs::conformance::run(MemoryFileSystem::new);
+//! }
+//! ```
+//!
+//! Locks are optional, so the locking checks pass on filesystems whose handles return
+//! [`FileSystemError::UnsupportedOperation`] for them. Everything else is required.
+//!
+//! This module is available to other crates with the `conformance` feature.
+
+use crate::{BatchOperation, FileHandle, FileLockMode, FileSystem, FileSystemError, OpenOptions};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+
+/// Run every check, each against a new filesystem from `factory`.
+pub fn run<F: FileSystem>(factory: impl Fn() -> F) {
+    check_files(&factory());
+    check_directories(&factory());
+    check_rename_and_batch(&factory());
+    check_seek_and_size(&factory());
+    check_positional_io(&factory());
+    check_open_options(&factory());
+    check_locking(&factory());
+    check_range_locking(&factory());
+}
+
+/// Read a handle from its start to its end.
+fn contents<H: FileHandle>(handle: &mut H) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    handle
+        .seek(SeekFrom::Start(0))
+        .expect("Error seeking to start");
+    handle.read_to_end(&mut buffer).expect("Error reading file");
+    buffer
+}
+
+/// Check creating, writing, reopening and removing files.
+pub fn check_files<F: FileSystem>(fs: &F) {
+    assert!(!fs.exists("/file.dat").unwrap(), "Filesystem isn't empty");
+    {
+        let mut file = fs.create_file("/file.dat").expect("Error creating file");
+        assert_eq!(file.get_size().unwrap(), 0, "New file isn't empty");
+        file.write_all(b"Hello, World!").unwrap();
+        assert_eq!(file.get_size().unwrap(), 13, "Size doesn't include write");
+        assert_eq!(contents(&mut file), b"Hello, World!");
+        file.flush().unwrap();
+    }
+    assert!(fs.exists("/file.dat").unwrap());
+    assert!(fs.is_file("/file.dat").unwrap());
+    assert!(!fs.is_directory("/file.dat").unwrap());
+    assert_eq!(fs.filesize("/file.dat").unwrap(), 13);
+    {
+        let mut file = fs.open_file("/file.dat").expect("Error opening file");
+        assert_eq!(file.stream_position().unwrap(), 0, "Opened away from start");
+        file.write_all(b"Jello").unwrap();
+        assert_eq!(
+            contents(&mut file),
+            b"Jello, World!",
+            "Write didn't overwrite in place"
+        );
+    }
+    assert_eq!(
+        fs.read("/file.dat").unwrap(),
+        b"Jello, World!",
+        "Write didn't persist"
+    );
+    assert!(
+        matches!(
+            fs.create_file("/file.dat"),
+            Err(FileSystemError::PathExists)
+        ),
+        "Creating an existing file didn't fail with PathExists"
+    );
+    fs.write("/file.dat", b"replaced").unwrap();
+    fs.append("/file.dat", b" and appended").unwrap();
+    assert_eq!(
+        fs.read_to_string("/file.dat").unwrap(),
+        "replaced and appended"
+    );
+
+    fs.remove_file("/file.dat").expect("Error removing file");
+    assert!(!fs.exists("/file.dat").unwrap());
+    assert!(!fs.is_file("/file.dat").unwrap());
+    assert!(
+        matches!(fs.open_file("/file.dat"), Err(FileSystemError::PathMissing)),
+        "Opening a missing file didn't fail with PathMissing"
+    );
+    assert!(
+        matches!(
+            fs.remove_file("/file.dat"),
+            Err(FileSystemError::PathMissing)
+        ),
+        "Removing a missing file didn't fail with PathMissing"
+    );
+}
+
+/// Check creating, listing and removing directories.
+pub fn check_directories<F: FileSystem>(fs: &F) {
+    assert!(fs.is_directory("/").unwrap(), "Root isn't a directory");
+    assert!(
+        fs.list_directory("/").unwrap().is_empty(),
+        "Filesystem isn't empty"
+    );
+
+    fs.create_directory("/a").unwrap();
+    assert!(fs.is_directory("/a").unwrap());
+    assert!(!fs.is_file("/a").unwrap());
+    assert!(
+        matches!(fs.create_directory("/a"), Err(FileSystemError::PathExists)),
+        "Creating an existing directory didn't fail with PathExists"
+    );
+    fs.create_directory_all("/a/b/c").unwrap();
+    fs.create_directory_all("/a/b/c")
+        .expect("Creating existing directories failed");
+    fs.write("/a/b/file.txt", b"file").unwrap();
+    fs.write("/a/top.txt", b"top").unwrap();
+
+    let mut listing = fs.list_directory("/a").unwrap();
+    listing.sort();
+    assert_eq!(listing, ["b", "top.txt"], "Listing isn't direct children");
+    let mut listing = fs.list_directory("/a/b").unwrap();
+    listing.sort();
+    assert_eq!(listing, ["c", "file.txt"], "Listing isn't direct children");
+    assert!(
+        fs.list_directory("/a/c").is_err(),
+        "Listed a missing directory"
+    );
+
+    assert!(
+        fs.remove_directory("/a/b").is_err(),
+        "Removed a non-empty directory"
+    );
+    fs.remove_directory("/a/b/c").unwrap();
+    assert!(!fs.exists("/a/b/c").unwrap());
+    fs.remove_directory_all("/a/b").unwrap();
+    assert!(!fs.exists("/a/b").unwrap());
+    assert!(!fs.exists("/a/b/file.txt").unwrap());
+    assert!(fs.exists("/a/top.txt").unwrap(), "Removed a sibling");
+    fs.remove_file("/a/top.txt").unwrap();
+    fs.remove_directory("/a").unwrap();
+    assert!(fs.list_directory("/").unwrap().is_empty());
+}
+
+/// Check renaming files and applying batches of operations.
+pub fn check_rename_and_batch<F: FileSystem>(fs: &F) {
+    fs.create_directory("/dir").unwrap();
+    fs.write("/old.txt", b"old").unwrap();
+    fs.write("/other.txt", b"other").unwrap();
+    fs.rename("/old.txt", "/dir/new.txt")
+        .expect("Error renaming file");
+    assert!(!fs.exists("/old.txt").unwrap(), "Rename kept the source");
+    assert_eq!(fs.read("/dir/new.txt").unwrap(), b"old");
+    fs.rename("/other.txt", "/dir/new.txt")
+        .expect("Error renaming onto a file");
+    assert_eq!(
+        fs.read("/dir/new.txt").unwrap(),
+        b"other",
+        "Rename didn't replace the target"
+    );
+    assert!(
+        matches!(
+            fs.rename("/old.txt", "/new.txt"),
+            Err(FileSystemError::PathMissing)
+        ),
+        "Renaming a missing file didn't fail with PathMissing"
+    );
+    assert!(
+        fs.rename("/dir/new.txt", "/dir").is_err(),
+        "Renamed a file onto a directory"
+    );
+    assert!(fs.rename("/dir", "/moved").is_err(), "Renamed a directory");
+
+    let results = fs.apply(&[
+        BatchOperation::create_directory_all("/batch/nested"),
+        BatchOperation::write("/batch/nested/a.txt", b"a"),
+        BatchOperation::write("/batch/b.txt", b"b"),
+        BatchOperation::create_directory("/batch"),
+        BatchOperation::rename("/batch/nested/a.txt", "/batch/c.txt"),
+        BatchOperation::remove_file("/batch/b.txt"),
+        BatchOperation::remove_directory("/batch/nested"),
+        BatchOperation::remove_file("/batch/b.txt"),
+    ]);
+    assert_eq!(results.len(), 8, "Batch didn't report every operation");
+    for (index, result) in results.iter().enumerate() {
+        match index {
+            3 => assert!(
+                matches!(result, Err(FileSystemError::PathExists)),
+                "Creating an existing directory in a batch didn't fail with PathExists"
+            ),
+            7 => assert!(
+                matches!(result, Err(FileSystemError::PathMissing)),
+                "Removing a missing file in a batch didn't fail with PathMissing"
+            ),
+            _ => assert!(result.is_ok(), "Batch operation {index} failed: {result:?}"),
+        }
+    }
+    assert_eq!(fs.list_directory("/batch").unwrap(), ["c.txt"]);
+    assert_eq!(fs.read("/batch/c.txt").unwrap(), b"a");
+}
+
+/// Check cursor movement and resizing at and beyond the end of files.
+pub fn check_seek_and_size<F: FileSystem>(fs: &F) {
+    let mut file = fs.create_file("/sized.dat").unwrap();
+    file.write_all(b"0123456789").unwrap();
+
+    assert_eq!(file.seek(SeekFrom::End(-4)).unwrap(), 6);
+    assert_eq!(file.seek(SeekFrom::Current(-2)).unwrap(), 4);
+    let mut buffer = [0; 3];
+    file.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer, b"456");
+    assert!(
+        file.seek(SeekFrom::Current(-100)).is_err(),
+        "Seeked before the start"
+    );
+
+    // Reading at or beyond the end returns nothing
+    file.seek(SeekFrom::End(0)).unwrap();
+    assert_eq!(file.read(&mut buffer).unwrap(), 0, "Read beyond the end");
+    assert_eq!(file.seek(SeekFrom::Start(20)).unwrap(), 20);
+    assert_eq!(file.read(&mut buffer).unwrap(), 0, "Read beyond the end");
+    assert_eq!(file.get_size().unwrap(), 10, "Seeking changed the size");
+
+    // Writing beyond the end fills the gap with zeroes
+    file.write_all(b"end").unwrap();
+    assert_eq!(file.get_size().unwrap(), 23);
+    let data = contents(&mut file);
+    assert_eq!(&data[..10], b"0123456789");
+    assert!(
+        data[10..20].iter().all(|byte| *byte == 0),
+        "Gap isn't zeroed"
+    );
+    assert_eq!(&data[20..], b"end");
+
+    // Shrinking discards data and growing exposes zeroes
+    file.set_size(4).unwrap();
+    assert_eq!(file.get_size().unwrap(), 4);
+    file.set_size(8).unwrap();
+    assert_eq!(
+        contents(&mut file),
+        b"0123\0\0\0\0",
+        "Regrown data isn't zeroed"
+    );
+    file.truncate().unwrap();
+    assert_eq!(file.get_size().unwrap(), 0);
+    drop(file);
+    assert_eq!(fs.filesize("/sized.dat").unwrap(), 0);
+}
+
+/// Check positional and vectored reads and writes leave the cursor in place.
+pub fn check_positional_io<F: FileSystem>(fs: &F) {
+    let mut file = fs.create_file("/positional.dat").unwrap();
+    file.write_all(b"0123456789").unwrap();
+    file.seek(SeekFrom::Start(2)).unwrap();
+
+    let mut buffer = [0; 4];
+    assert_eq!(file.read_at_offset(6, &mut buffer).unwrap(), 4);
+    assert_eq!(&buffer, b"6789");
+    assert_eq!(
+        file.read_at_offset(8, &mut buffer).unwrap(),
+        2,
+        "Read beyond the end"
+    );
+    assert_eq!(
+        file.read_at_offset(20, &mut buffer).unwrap(),
+        0,
+        "Read beyond the end"
+    );
+    assert_eq!(file.write_to_offset(8, b"abcd").unwrap(), 4);
+    assert_eq!(file.get_size().unwrap(), 12);
+    assert_eq!(
+        file.stream_position().unwrap(),
+        2,
+        "Positional I/O moved the cursor"
+    );
+
+    let written = file
+        .write_at_vectored(0, &[IoSlice::new(b"AB"), IoSlice::new(b"CD")])
+        .unwrap();
+    assert_eq!(written, 4);
+    let (mut first, mut second) = ([0; 3], [0; 20]);
+    let read = file
+        .read_at_vectored(
+            1,
+            &mut [IoSliceMut::new(&mut first), IoSliceMut::new(&mut second)],
+        )
+        .unwrap();
+    assert_eq!(read, 11, "Vectored read didn't stop at the end");
+    assert_eq!(&first, b"BCD");
+    assert_eq!(&second[..8], b"4567abcd");
+    assert_eq!(
+        file.stream_position().unwrap(),
+        2,
+        "Positional I/O moved the cursor"
+    );
+    assert_eq!(contents(&mut file), b"ABCD4567abcd");
+}
+
+/// Check [`FileSystem::open_with`] honours its options.
+pub fn check_open_options<F: FileSystem>(fs: &F) {
+    let create_new = OpenOptions::new().write(true).create_new(true);
+    fs.open_with("/options.dat", create_new)
+        .expect("Error creating new file")
+        .write_all(b"first")
+        .unwrap();
+    assert!(
+        matches!(
+            fs.open_with("/options.dat", create_new),
+            Err(FileSystemError::PathExists)
+        ),
+        "Exclusively creating an existing file didn't fail with PathExists"
+    );
+    assert!(
+        matches!(
+            fs.open_with("/missing.dat", OpenOptions::new().read(true)),
+            Err(FileSystemError::PathMissing)
+        ),
+        "Opening a missing file without create didn't fail with PathMissing"
+    );
+
+    let mut file = fs
+        .open_with("/options.dat", OpenOptions::new().append(true))
+        .unwrap();
+    file.write_all(b" second").unwrap();
+    drop(file);
+    assert_eq!(
+        fs.read("/options.dat").unwrap(),
+        b"first second",
+        "Append overwrote"
+    );
+
+    let truncate = OpenOptions::new().write(true).truncate(true);
+    drop(fs.open_with("/options.dat", truncate).unwrap());
+    assert_eq!(fs.filesize("/options.dat").unwrap(), 0, "Didn't truncate");
+
+    let create = OpenOptions::new().write(true).create(true);
+    drop(fs.open_with("/created.dat", create).unwrap());
+    assert!(fs.is_file("/created.dat").unwrap(), "Didn't create");
+}
+
+/// Check whole-file locks between handles, if supported.
+///
+/// Conflicts are checked with [`FileHandle::try_set_lock_status`], so a backend that blocks
+/// instead fails rather than hanging.
+pub fn check_locking<F: FileSystem>(fs: &F) {
+    let mut first = fs.create_file("/locked.dat").unwrap();
+    let mut second = fs.open_file("/locked.dat").unwrap();
+    match first.set_lock_status(FileLockMode::Shared) {
+        Err(FileSystemError::UnsupportedOperation) => return,
+        result => result.expect("Error taking shared lock"),
+    }
+    assert_eq!(first.get_lock_status().unwrap(), FileLockMode::Shared);
+    second
+        .try_set_lock_status(FileLockMode::Shared)
+        .expect("Shared locks conflicted");
+    assert!(
+        matches!(
+            first.try_set_lock_status(FileLockMode::Exclusive),
+            Err(FileSystemError::FileAlreadyLocked)
+        ),
+        "Exclusive lock didn't conflict with shared lock"
+    );
+
+    second.set_lock_status(FileLockMode::Unlocked).unwrap();
+    assert_eq!(second.get_lock_status().unwrap(), FileLockMode::Unlocked);
+    first
+        .try_set_lock_status(FileLockMode::Exclusive)
+        .expect("Unlocking didn't release the lock");
+    assert!(
+        matches!(
+            second.try_set_lock_status(FileLockMode::Shared),
+            Err(FileSystemError::FileAlreadyLocked)
+        ),
+        "Shared lock didn't conflict with exclusive lock"
+    );
+
+    drop(first);
+    second
+        .try_set_lock_status(FileLockMode::Exclusive)
+        .expect("Dropping a handle didn't release its lock");
+}
+
+/// Check byte-range locks between handles, if supported.
+pub fn check_range_locking<F: FileSystem>(fs: &F) {
+    let mut first = fs.create_file("/ranges.dat").unwrap();
+    let mut second = fs.open_file("/ranges.dat").unwrap();
+    match first.lock_range(0, 100, FileLockMode::Exclusive) {
+        Err(FileSystemError::UnsupportedOperation) => return,
+        result => result.expect("Error locking range"),
+    }
+    second
+        .lock_range(100, 100, FileLockMode::Shared)
+        .expect("Disjoint ranges conflicted");
+    assert!(
+        matches!(
+            second.lock_range(99, 2, FileLockMode::Shared),
+            Err(FileSystemError::FileAlreadyLocked)
+        ),
+        "Overlapping ranges didn't conflict"
+    );
+    assert!(
+        matches!(
+            first.lock_range(150, 1, FileLockMode::Exclusive),
+            Err(FileSystemError::FileAlreadyLocked)
+        ),
+        "Overlapping ranges didn't conflict"
+    );
+    first.unlock_range(0, 100).unwrap();
+    second
+        .lock_range(0, 1, FileLockMode::Shared)
+        .expect("Unlocking didn't release the range");
+}
@@ -0,0 +1,266 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{FileHandle, FileSystemResult};
+
+/// Number of bytes at the start of a file sniffed for a signature.
+const SNIFF_LENGTH: usize = 512;
+
+/// MIME type reported for files that are neither recognized nor text.
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// MIME types of well known file extensions, compared case-insensitively.
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("bmp", "image/bmp"),
+    ("css", "text/css"),
+    ("csv", "text/csv"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("epub", "application/epub+zip"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("jar", "application/java-archive"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "text/javascript"),
+    ("json", "application/json"),
+    ("md", "text/markdown"),
+    ("mjs", "text/javascript"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("ogg", "audio/ogg"),
+    ("otf", "font/otf"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    (
+        "pptx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("toml", "application/toml"),
+    ("ttf", "font/ttf"),
+    ("txt", "text/plain"),
+    ("wasm", "application/wasm"),
+    ("wav", "audio/wav"),
+    ("webm", "video/webm"),
+    ("webp", "image/webp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    ("xml", "application/xml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("zip", "application/zip"),
+];
+
+/// Signatures at fixed offsets from the start of a file and the MIME types they identify.
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (8, b"WEBP", "image/webp"),
+    (8, b"WAVE", "audio/wav"),
+    (0, b"BM", "image/bmp"),
+    (0, b"\x00\x00\x01\x00", "image/vnd.microsoft.icon"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"PK\x05\x06", "application/zip"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (257, b"ustar", "application/x-tar"),
+    (0, b"\x00asm", "application/wasm"),
+    (0, b"wOFF", "font/woff"),
+    (0, b"wOF2", "font/woff2"),
+    (0, b"OTTO", "font/otf"),
+    (0, b"\x00\x01\x00\x00", "font/ttf"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"OggS", "audio/ogg"),
+    (4, b"ftyp", "video/mp4"),
+    (0, b"\x1a\x45\xdf\xa3", "video/webm"),
+];
+
+/// Detect the MIME type of the file behind a handle.
+///
+/// The first block of the file is sniffed for a signature, which takes precedence over the
+/// extension of the handle's path except for zip archives, whose extension tells which zip based
+/// format they hold. Files with neither are reported as `text/plain` if the block is UTF-8
+/// without control characters, or `application/octet-stream` otherwise. The cursor of the handle
+/// is left untouched.
+///
+/// ```rust
+/// use minql_vfs::{detect_content_type, FileSystem, MemoryFileSystem};
+///
+/// let fs = MemoryFileSystem::new();
+/// fs.write("/logo", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+/// fs.write("/index.html", b"<p>Hello</p>").unwrap();
+/// fs.write("/notes", b"Hello, World!").unwrap();
+///
+/// assert_eq!(detect_content_type(&mut fs.open_file("/logo").unwrap()).unwrap(), "image/png");
+/// assert_eq!(
+///     detect_content_type(&mut fs.open_file("/index.html").unwrap()).unwrap(),
+///     "text/html"
+/// );
+/// assert_eq!(detect_content_type(&mut fs.open_file("/notes").unwrap()).unwrap(), "text/plain");
+/// ```
+pub fn detect_content_type<H: FileHandle + ?Sized>(
+    handle: &mut H,
+) -> FileSystemResult<&'static str> {
+    let mut block = [0; SNIFF_LENGTH];
+    let mut filled = 0;
+    while filled < block.len() {
+        let read = handle.read_at_offset(filled as u64, &mut block[filled..])?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    let block = &block[..filled];
+    let extension = extension_content_type(handle.path());
+    Ok(match (sniff_content_type(block), extension) {
+        (Some("application/zip") | None, Some(extension)) => extension,
+        (Some(sniffed), _) => sniffed,
+        (None, None) if is_text(block) => "text/plain",
+        (None, None) => OCTET_STREAM,
+    })
+}
+
+/// MIME type of the extension of the last segment of a path.
+fn extension_content_type(path: &str) -> Option<&'static str> {
+    let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    let (stem, extension) = name.rsplit_once('.')?;
+    if stem.is_empty() {
+        return None;
+    }
+    EXTENSIONS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(extension))
+        .map(|(_, content_type)| *content_type)
+}
+
+/// MIME type identified by a signature or markup at the start of a block.
+fn sniff_content_type(block: &[u8]) -> Option<&'static str> {
+    let signature = SIGNATURES.iter().find(|(offset, magic, _)| {
+        block
+            .get(*offset..offset + magic.len())
+            .is_some_and(|bytes| bytes == *magic)
+    });
+    if let Some((_, _, content_type)) = signature {
+        return Some(content_type);
+    }
+    let start = block
+        .strip_prefix(b"\xef\xbb\xbf")
+        .unwrap_or(block)
+        .trim_ascii_start();
+    let starts_with = |prefix: &[u8]| {
+        start
+            .get(..prefix.len())
+            .is_some_and(|bytes| bytes.eq_ignore_ascii_case(prefix))
+    };
+    if starts_with(b"<!doctype html") || starts_with(b"<html") {
+        Some("text/html")
+    } else if starts_with(b"<svg") {
+        Some("image/svg+xml")
+    } else if starts_with(b"<?xml") {
+        Some("application/xml")
+    } else {
+        None
+    }
+}
+
+/// Whether a block looks like text: UTF-8, allowing a character cut off at the end of the
+/// block, without control characters other than whitespace.
+fn is_text(block: &[u8]) -> bool {
+    let valid = match std::str::from_utf8(block) {
+        Ok(text) => text,
+        Err(error) if error.error_len().is_none() => {
+            std::str::from_utf8(&block[..error.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return false,
+    };
+    !valid
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c'))
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_detect_content_type() {
+        use crate::{detect_content_type, FileSystem, MemoryFileSystem};
+        use std::io::{Read, Seek, SeekFrom};
+
+        let fs = MemoryFileSystem::new();
+        let detect = |path: &str, contents: &[u8]| {
+            fs.write(path, contents).unwrap();
+            detect_content_type(&mut fs.open_file(path).unwrap()).unwrap()
+        };
+
+        // Signatures win over a misleading extension.
+        assert_eq!(
+            detect("/photo.png", b"\xff\xd8\xff\xe0\0\x10JFIF"),
+            "image/jpeg"
+        );
+        assert_eq!(detect("/doc.PDF", b"%PDF-1.7\n"), "application/pdf");
+        assert_eq!(detect("/image", b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        let mut tar = vec![0; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(detect("/backup", &tar), "application/x-tar");
+
+        // Zip archives defer to the extension naming the zip based format.
+        assert_eq!(detect("/bundle", b"PK\x03\x04\x14\0"), "application/zip");
+        assert_eq!(
+            detect("/app.jar", b"PK\x03\x04\x14\0"),
+            "application/java-archive"
+        );
+
+        // Text formats are identified by extension or markup.
+        assert_eq!(detect("/style.css", b"body { margin: 0 }"), "text/css");
+        assert_eq!(detect("/data.json", b"{\"a\": 1}"), "application/json");
+        assert_eq!(detect("/page", b"\n  <!DOCTYPE HTML><html>"), "text/html");
+        assert_eq!(
+            detect("/icon", b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"),
+            "image/svg+xml"
+        );
+        assert_eq!(detect("/.profile", b"export PATH"), "text/plain");
+        assert_eq!(detect("/empty", b""), "text/plain");
+        assert_eq!(
+            detect("/blob", b"\0\x01\x02\x03\xfe"),
+            "application/octet-stream"
+        );
+
+        // A multi-byte character cut off by the end of the sniffed block is still text.
+        let mut text = vec![b'a'; 511];
+        text.extend_from_slice("é".as_bytes());
+        assert_eq!(detect("/long", &text), "text/plain");
+
+        let mut handle = fs.open_file("/long").unwrap();
+        handle.seek(SeekFrom::Start(100)).unwrap();
+        detect_content_type(&mut handle).unwrap();
+        let mut rest = Vec::new();
+        handle.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest.len(), text.len() - 100);
+    }
+}
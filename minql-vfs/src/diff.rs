@@ -0,0 +1,322 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::utility::{child_path, normalize_path};
+use crate::{
+    FileSystem, FileSystemError, FileSystemResult, FileType, HashAlgorithm, SymlinkPolicy,
+};
+
+/// Options of a [`diff_with`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct DiffOptions {
+    hash: bool,
+}
+
+impl DiffOptions {
+    /// Create options comparing files by size only.
+    #[must_use]
+    pub fn new() -> DiffOptions {
+        DiffOptions::default()
+    }
+
+    /// Set whether files of the same size are also compared by a hash of their contents.
+    #[must_use]
+    pub fn with_hash(mut self, hash: bool) -> DiffOptions {
+        self.hash = hash;
+        self
+    }
+
+    /// Whether files of the same size are compared by content.
+    #[must_use]
+    pub fn is_hash(&self) -> bool {
+        self.hash
+    }
+}
+
+/// Change of an entry between two trees.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DiffChange {
+    /// Entry only present in the second tree
+    Added(FileType),
+    /// Entry only present in the first tree
+    Removed(FileType),
+    /// File whose size or contents differ, or symbolic link whose target differs
+    Modified(FileType),
+    /// Entry whose type differs, from the first tree to the second
+    TypeChanged(FileType, FileType),
+}
+
+/// Entry differing between two trees.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DiffEntry {
+    /// Path of the entry
+    pub path: String,
+    /// How the entry changed
+    pub change: DiffChange,
+}
+
+/// Differences between two trees, returned by [`diff`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DiffReport {
+    entries: Vec<DiffEntry>,
+}
+
+impl DiffReport {
+    /// Differing entries, ordered by path with parents before their children.
+    #[must_use]
+    pub fn entries(&self) -> &[DiffEntry] {
+        &self.entries
+    }
+
+    /// Check if both trees are the same.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries only present in the second tree.
+    pub fn added(&self) -> impl Iterator<Item = &DiffEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.change, DiffChange::Added(_)))
+    }
+
+    /// Entries only present in the first tree.
+    pub fn removed(&self) -> impl Iterator<Item = &DiffEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.change, DiffChange::Removed(_)))
+    }
+
+    /// Entries present in both trees which differ, including in type.
+    pub fn modified(&self) -> impl Iterator<Item = &DiffEntry> {
+        self.entries.iter().filter(|entry| {
+            matches!(
+                entry.change,
+                DiffChange::Modified(_) | DiffChange::TypeChanged(_, _)
+            )
+        })
+    }
+}
+
+/// Compare the trees at `path` of two `FileSystem`s, comparing files by size.
+///
+/// ```rust
+/// use minql_vfs::{diff, DiffChange, DiffEntry, FileSystem, FileType, MemoryFileSystem};
+///
+/// let before = MemoryFileSystem::new();
+/// before.create_directory("/data").unwrap();
+/// before.write("/data/table.db", b"rows").unwrap();
+/// before.write("/data/old.db", b"old").unwrap();
+/// let after = MemoryFileSystem::new();
+/// after.create_directory("/data").unwrap();
+/// after.write("/data/table.db", b"more rows").unwrap();
+/// after.write("/data/new.db", b"new").unwrap();
+///
+/// let report = diff(&before, &after, "/data").unwrap();
+/// assert_eq!(report.entries(), [
+///     DiffEntry { path: "/data/new.db".to_string(), change: DiffChange::Added(FileType::File) },
+///     DiffEntry { path: "/data/old.db".to_string(), change: DiffChange::Removed(FileType::File) },
+///     DiffEntry {
+///         path: "/data/table.db".to_string(),
+///         change: DiffChange::Modified(FileType::File),
+///     },
+/// ]);
+/// ```
+pub fn diff<A: FileSystem, B: FileSystem>(
+    first: &A,
+    second: &B,
+    path: &str,
+) -> FileSystemResult<DiffReport> {
+    diff_with(first, second, path, DiffOptions::new())
+}
+
+/// Compare the trees at `path` of two `FileSystem`s.
+///
+/// Entries below an added or removed directory are reported as well, while the contents of a
+/// directory which changed type are not. Symbolic links aren't followed, and compare by target.
+/// Fails with [`FileSystemError::PathMissing`] if `path` exists in neither tree.
+pub fn diff_with<A: FileSystem, B: FileSystem>(
+    first: &A,
+    second: &B,
+    path: &str,
+    options: DiffOptions,
+) -> FileSystemResult<DiffReport> {
+    let path = normalize_path(path)?;
+    let mut diff = Diff {
+        first,
+        second,
+        options,
+        report: DiffReport::default(),
+    };
+    match (file_type(first, &path)?, file_type(second, &path)?) {
+        (None, None) => return Err(FileSystemError::PathMissing),
+        (first, second) => diff.entry(&path, first, second)?,
+    }
+    Ok(diff.report)
+}
+
+/// State of a running [`diff`].
+struct Diff<'a, A: FileSystem, B: FileSystem> {
+    first: &'a A,
+    second: &'a B,
+    options: DiffOptions,
+    report: DiffReport,
+}
+
+impl<A: FileSystem, B: FileSystem> Diff<'_, A, B> {
+    /// Compare an entry of the given types, `None` where missing, and everything below it.
+    fn entry(
+        &mut self,
+        path: &str,
+        first: Option<FileType>,
+        second: Option<FileType>,
+    ) -> FileSystemResult<()> {
+        let change = match (first, second) {
+            (Some(first), None) => Some(DiffChange::Removed(first)),
+            (None, Some(second)) => Some(DiffChange::Added(second)),
+            (Some(first), Some(second)) if first != second => {
+                Some(DiffChange::TypeChanged(first, second))
+            }
+            (Some(FileType::File), Some(_)) => self
+                .file_differs(path)?
+                .then_some(DiffChange::Modified(FileType::File)),
+            (Some(FileType::Symlink), Some(_)) => (self.first.read_link(path)?
+                != self.second.read_link(path)?)
+            .then_some(DiffChange::Modified(FileType::Symlink)),
+            (None, None) | (Some(FileType::Directory), Some(_)) => None,
+        };
+        if let Some(change) = change {
+            self.report.entries.push(DiffEntry {
+                path: path.to_string(),
+                change,
+            });
+        }
+        let first = first == Some(FileType::Directory);
+        let second = second == Some(FileType::Directory);
+        if matches!(change, Some(DiffChange::TypeChanged(_, _))) || !(first || second) {
+            return Ok(());
+        }
+        let mut children = Vec::new();
+        if first {
+            children.extend(self.first.list_directory(path)?);
+        }
+        if second {
+            children.extend(self.second.list_directory(path)?);
+        }
+        children.sort();
+        children.dedup();
+        for name in children {
+            let child = child_path(path, &name);
+            let first = if first {
+                file_type(self.first, &child)?
+            } else {
+                None
+            };
+            let second = if second {
+                file_type(self.second, &child)?
+            } else {
+                None
+            };
+            self.entry(&child, first, second)?;
+        }
+        Ok(())
+    }
+
+    /// Check if a file present in both trees differs.
+    fn file_differs(&self, path: &str) -> FileSystemResult<bool> {
+        if self.first.filesize(path)? != self.second.filesize(path)? {
+            return Ok(true);
+        }
+        Ok(self.options.hash
+            && self.first.hash_file(path, HashAlgorithm::Sha256)?
+                != self.second.hash_file(path, HashAlgorithm::Sha256)?)
+    }
+}
+
+/// Get the type of an entry without following symbolic links, or `None` if it's missing.
+fn file_type<F: FileSystem>(fs: &F, path: &str) -> FileSystemResult<Option<FileType>> {
+    match fs.file_type(path, SymlinkPolicy::NoFollow) {
+        Ok(file_type) => Ok(Some(file_type)),
+        Err(FileSystemError::PathMissing) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diff, diff_with, DiffChange, DiffEntry, DiffOptions};
+    use crate::{FileSystem, FileSystemError, FileType, MemoryFileSystem};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_diff() {
+        let first = MemoryFileSystem::new();
+        first.create_directory_all("/a/gone").unwrap();
+        first.write("/a/gone/file", b"x").unwrap();
+        first.write("/a/same", b"same").unwrap();
+        first.write("/a/edited", b"abcd").unwrap();
+        first.write("/a/swapped", b"file").unwrap();
+        first.create_symlink("/a/same", "/a/link").unwrap();
+
+        let second = MemoryFileSystem::new();
+        second.create_directory_all("/a/swapped/inner").unwrap();
+        second.create_directory_all("/a/fresh").unwrap();
+        second.write("/a/fresh/file", b"y").unwrap();
+        second.write("/a/same", b"same").unwrap();
+        second.write("/a/edited", b"ABCD").unwrap();
+        second.create_symlink("/a/edited", "/a/link").unwrap();
+
+        let entry = |path: &str, change| DiffEntry {
+            path: path.to_string(),
+            change,
+        };
+        let report = diff(&first, &second, "/").unwrap();
+        assert_eq!(
+            report.entries(),
+            [
+                entry("/a/fresh", DiffChange::Added(FileType::Directory)),
+                entry("/a/fresh/file", DiffChange::Added(FileType::File)),
+                entry("/a/gone", DiffChange::Removed(FileType::Directory)),
+                entry("/a/gone/file", DiffChange::Removed(FileType::File)),
+                entry("/a/link", DiffChange::Modified(FileType::Symlink)),
+                entry(
+                    "/a/swapped",
+                    DiffChange::TypeChanged(FileType::File, FileType::Directory)
+                ),
+            ]
+        );
+        assert_eq!(report.added().count(), 2);
+        assert_eq!(report.removed().count(), 2);
+        assert_eq!(report.modified().count(), 2);
+
+        let hashed = diff_with(&first, &second, "/a", DiffOptions::new().with_hash(true)).unwrap();
+        assert_eq!(hashed.modified().count(), 3);
+        assert!(hashed
+            .entries()
+            .contains(&entry("/a/edited", DiffChange::Modified(FileType::File))));
+
+        assert!(diff(&first, &first, "/").unwrap().is_empty());
+        assert_eq!(
+            diff(&first, &second, "/a/fresh/file").unwrap().entries(),
+            [entry("/a/fresh/file", DiffChange::Added(FileType::File))]
+        );
+        assert!(matches!(
+            diff(&first, &second, "/missing"),
+            Err(FileSystemError::PathMissing)
+        ));
+    }
+}
@@ -14,32 +14,163 @@
 // limitations under the License.
 //
 
+mod aclfs;
+mod asyncfile;
+#[cfg(feature = "azure")]
+mod azurefs;
+mod bufferedfile;
+mod cachingfs;
+mod checksumfs;
+#[cfg(feature = "config")]
+mod config;
+mod crashfs;
+mod embeddedfs;
+mod failoverfs;
+#[cfg(feature = "gcs")]
+mod gcsfs;
+#[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+mod httpstore;
 mod localfs;
 mod memoryfs;
 mod metricfs;
+mod mirrorfs;
+mod mountfs;
+mod objectfs;
+mod priorityfs;
+mod recordfs;
+mod replicatedfs;
+#[cfg(feature = "s3")]
+mod s3fs;
+mod scopedfs;
+mod simulatedfs;
+mod stripedfs;
+mod syncfs;
+mod tenantfs;
+mod testfs;
+mod throttledfs;
+mod timeoutfs;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uringfs;
+mod versionedfs;
 mod virtualfs;
+mod writebehindfs;
 
-use crate::{FileSystemError, FileSystemResult};
+use crate::hash::hash_handle;
+use crate::utility::{apply_operation, copy_file, move_file};
+use crate::{ContentDigest, FileSystemError, FileSystemResult, HashAlgorithm};
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fs::File;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-pub use self::localfs::{LocalFileHandle, LocalFileSystem};
-pub use self::memoryfs::{MemoryFileHandle, MemoryFileSystem};
-pub use self::metricfs::{MetricsFileHandle, MetricFileSystem};
+/// Longest wait between attempts on an advisory lock held through another handle.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub use self::aclfs::{AclEffect, AclFileHandle, AclFileSystem, AclOperation, AclRule};
+pub use self::asyncfile::{block_on, AsyncFileHandle};
+#[cfg(feature = "azure")]
+pub use self::azurefs::{AzureFileSystemProvider, AzureObjectStore};
+pub use self::bufferedfile::BufferedFileHandle;
+pub use self::cachingfs::{CacheStats, CachingFileHandle, CachingFileSystem};
+pub use self::checksumfs::{ChecksumFileHandle, ChecksumFileSystem};
+pub use self::crashfs::{CrashFileHandle, CrashFileSystem};
+pub use self::embeddedfs::{EmbeddedFileHandle, EmbeddedFileSystem};
+pub use self::failoverfs::{
+    is_connectivity_error, FailoverBackend, FailoverEvent, FailoverFileHandle, FailoverFileSystem,
+    FailoverObserver,
+};
+#[cfg(feature = "gcs")]
+pub use self::gcsfs::{GcsFileSystemProvider, GcsObjectStore};
+pub use self::localfs::{
+    LocalFileHandle, LocalFileSystem, LocalFileSystemBuilder, LocalFileSystemProvider,
+};
+pub use self::memoryfs::{MemoryFileHandle, MemoryFileSystem, MemoryFileSystemProvider};
+pub use self::metricfs::{
+    LatencyHistogram, ManagerMetrics, MetricFileSystem, MetricOperation, MetricsData,
+    MetricsFileHandle, MetricsSnapshot, OperationMetrics, SchemeMetrics,
+};
+pub use self::mirrorfs::{Divergence, MirrorCheckFileHandle, MirrorCheckFileSystem, MirrorPolicy};
+#[cfg(test)]
+pub(crate) use self::objectfs::test::TestObjectStore;
+pub use self::objectfs::{
+    ObjectListing, ObjectMeta, ObjectStore, ObjectStoreFileHandle, ObjectStoreFileSystem,
+    PutCondition,
+};
+pub use self::priorityfs::{
+    IoPriority, PriorityFileHandle, PriorityFileSystem, PriorityStats, PriorityView,
+};
+pub use self::recordfs::{
+    RecordFileHandle, RecordFileSystem, ReplayMismatch, ReplayReport, TraceOperation, TraceRecord,
+    TraceReplayer, TraceValue,
+};
+pub use self::replicatedfs::{ReplicatedFileHandle, ReplicatedFileSystem, ReplicationPolicy};
+#[cfg(feature = "s3")]
+pub use self::s3fs::{S3FileSystemProvider, S3ObjectStore};
+pub use self::scopedfs::{ScopedFileHandle, ScopedFileSystem};
+pub use self::simulatedfs::{SimulatedFileHandle, SimulatedFileSystem};
+pub use self::stripedfs::{StripePolicy, StripedFileSystem};
+pub use self::syncfs::{GroupCommit, SyncFileHandle, SyncFileSystem, SyncPolicy};
+pub use self::tenantfs::{Tenant, TenantFileHandle, TenantFileSystem};
+pub use self::testfs::TestFileSystem;
+pub use self::throttledfs::{ThrottleLimits, ThrottledFileHandle, ThrottledFileSystem};
+pub use self::timeoutfs::{TimeoutFileHandle, TimeoutFileSystem};
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub use self::uringfs::{UringFileHandle, UringFileSystem};
+pub use self::versionedfs::{VersionedFileHandle, VersionedFileSystem, VersionedSnapshot};
 pub use self::virtualfs::{VirtualFileHandle, VirtualFileSystem, VirtualFileSystemManager};
+pub use self::writebehindfs::{WriteBehindFileHandle, WriteBehindFileSystem, WriteBehindOptions};
+
+/// Read-only view of a range of a file returned by [`FileHandle::map_readonly`].
+#[cfg(feature = "mmap")]
+pub enum FileMapping {
+    /// Range mapped directly from the file.
+    Mapped(memmap2::Mmap),
+    /// Range copied from a handle that doesn't support mapping.
+    Copied(Vec<u8>),
+}
+
+#[cfg(feature = "mmap")]
+impl std::ops::Deref for FileMapping {
+    type Target = [u8];
 
-/// API FileSystem Provider
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileMapping::Mapped(mapping) => mapping,
+            FileMapping::Copied(buffer) => buffer,
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl AsRef<[u8]> for FileMapping {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl Debug for FileMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileMapping::Mapped(mapping) => {
+                write!(f, "FileMapping::Mapped({} bytes)", mapping.len())
+            }
+            FileMapping::Copied(buffer) => write!(f, "FileMapping::Copied({} bytes)", buffer.len()),
+        }
+    }
+}
+
+/// API `FileSystem` Provider
 pub trait FileSystemProvider: Debug + Send + Sync + 'static {
-    /// FileSystem this Provider manages.
+    /// `FileSystem` this Provider manages.
     type FileSystem: FileSystem;
     /// Get the protocol handled by this provider.
     fn schemes(&self) -> &[&str];
     /// Configure the provider
     fn configure(&self, configuration: &HashMap<String, String>) -> FileSystemResult<()>;
-    /// Provision a FileSystem
+    /// Provision a `FileSystem`
     fn provision(&self, url: &str) -> FileSystemResult<Self::FileSystem>;
 }
 
@@ -48,7 +179,7 @@ pub(crate) trait DynamicFileSystemProvider: Debug + Send + Sync + 'static {
     fn schemes(&self) -> &[&str];
     /// Configure the provider
     fn configure(&self, configuration: &HashMap<String, String>) -> FileSystemResult<()>;
-    /// Provision a FileSystem
+    /// Provision a `FileSystem`
     fn provision(&self, url: &str) -> FileSystemResult<Arc<dyn DynamicFileSystem>>;
 }
 
@@ -61,7 +192,7 @@ impl<T: FileSystemProvider> DynamicFileSystemProvider for T {
     fn configure(&self, configuration: &HashMap<String, String>) -> FileSystemResult<()> {
         FileSystemProvider::configure(self, configuration)
     }
-    /// Provision a FileSystem
+    /// Provision a `FileSystem`
     fn provision(&self, url: &str) -> FileSystemResult<Arc<dyn DynamicFileSystem>> {
         Ok(Arc::new(self.provision(url)?))
     }
@@ -69,7 +200,7 @@ impl<T: FileSystemProvider> DynamicFileSystemProvider for T {
 
 /// API definition all [`FileSystem`] implementations must adhere to.
 pub trait FileSystem: Debug + Sync + Send + 'static {
-    /// Configured FileHandle
+    /// Configured `FileHandle`
     type FileHandle: FileHandle;
     /// Check if an entry exists at the provided path.
     fn exists(&self, path: &str) -> FileSystemResult<bool>;
@@ -84,20 +215,214 @@ pub trait FileSystem: Debug + Sync + Send + 'static {
     /// Creates a new, empty folder entry at the provided path.
     fn create_directory_all(&self, path: &str) -> FileSystemResult<()>;
     /// Returns an iterator over the names of entries within a Folder.
-    fn list_directory<'a>(&self, path: &str) -> FileSystemResult<Vec<String>>;
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>>;
     /// Removes the folder at this path.
     fn remove_directory(&self, path: &str) -> FileSystemResult<()>;
     /// Removes the folder at this path and all children.
     fn remove_directory_all(&self, path: &str) -> FileSystemResult<()>;
     /// Create or Open a new append only file for writing.
     fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle>;
-    /// Create or Open a new append only file for writing.
+    /// Open an existing file for reading and writing.
     fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle>;
     /// Removes the file at this path
     fn remove_file(&self, path: &str) -> FileSystemResult<()>;
+    /// Get the kind of entry at a path, following a symbolic link at the end of the path unless
+    /// `policy` is [`SymlinkPolicy::NoFollow`].
+    ///
+    /// The other metadata calls always follow symbolic links. The default implementation, for
+    /// backends without symbolic links, is derived from [`FileSystem::is_file`] and
+    /// [`FileSystem::is_directory`].
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        if self.is_file(path)? {
+            Ok(FileType::File)
+        } else if self.is_directory(path)? {
+            Ok(FileType::Directory)
+        } else {
+            Err(FileSystemError::PathMissing)
+        }
+    }
+    /// Create a symbolic link at `path` pointing to `target`.
+    ///
+    /// Absolute targets are resolved from the root of this filesystem and relative targets from
+    /// the directory holding the link. The target doesn't need to exist. The default
+    /// implementation fails with [`FileSystemError::UnsupportedOperation`].
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        Err(FileSystemError::UnsupportedOperation)
+    }
+    /// Read the target of the symbolic link at `path`, failing with
+    /// [`FileSystemError::InvalidOperation`] if the entry isn't a symbolic link.
+    ///
+    /// The default implementation fails with [`FileSystemError::UnsupportedOperation`].
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        Err(FileSystemError::UnsupportedOperation)
+    }
+    /// Get the permissions of the entry at a path, following symbolic links.
+    ///
+    /// The default implementation, for backends without permissions, reports every existing
+    /// entry as writable with no mode.
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        if self.exists(path)? {
+            Ok(Permissions::new())
+        } else {
+            Err(FileSystemError::PathMissing)
+        }
+    }
+    /// Get the time the file at a path was last modified, following symbolic links.
+    ///
+    /// The default implementation, for backends that don't track modification times, fails with
+    /// [`FileSystemError::UnsupportedOperation`].
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        Err(FileSystemError::UnsupportedOperation)
+    }
+    /// Replace the permissions of the entry at a path, following symbolic links.
+    ///
+    /// Operations the permissions forbid fail with [`FileSystemError::PermissionDenied`]. The
+    /// default implementation fails with [`FileSystemError::UnsupportedOperation`].
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        Err(FileSystemError::UnsupportedOperation)
+    }
+    /// Open a file using the provided options.
+    ///
+    /// The default implementation composes [`FileSystem::create_file`] and
+    /// [`FileSystem::open_file`], honouring `create`, `create_new`, `truncate`, `append` and
+    /// `no_follow`. Backends without an OS page cache have nothing to bypass and ignore `direct`.
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        if options.is_no_follow()
+            && matches!(
+                self.file_type(path, SymlinkPolicy::NoFollow),
+                Ok(FileType::Symlink)
+            )
+        {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        if options.is_create_new() {
+            return self.create_file(path);
+        }
+        let mut handle = match self.open_file(path) {
+            Err(FileSystemError::PathMissing) if options.is_create() => self.create_file(path)?,
+            handle => handle?,
+        };
+        if options.is_truncate() {
+            handle.set_size(0)?;
+        }
+        if options.is_append() {
+            handle
+                .seek(SeekFrom::End(0))
+                .map_err(FileSystemError::io_error)?;
+        }
+        Ok(handle)
+    }
+    /// Get the total, used and available bytes of the storage holding this filesystem.
+    ///
+    /// Wrappers report the space of the filesystem they store their data in. The default
+    /// implementation fails with [`FileSystemError::UnsupportedOperation`].
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        Err(FileSystemError::UnsupportedOperation)
+    }
+    /// Create a new file at `dst` with the contents of the file at `src`, failing with
+    /// [`FileSystemError::PathExists`] if `dst` already exists.
+    ///
+    /// Backends that support copy-on-write share the blocks of `src` instead of copying them and
+    /// report [`CloneMethod::Reflink`]. The default implementation streams the contents through
+    /// the handles and reports [`CloneMethod::Copy`].
+    ///
+    /// ```rust
+    /// use minql_vfs::{CloneMethod, FileSystem, MemoryFileSystem};
+    ///
+    /// let fs = MemoryFileSystem::new();
+    /// fs.write("/segment.dat", b"records").unwrap();
+    /// assert_eq!(fs.clone_file("/segment.dat", "/snapshot.dat").unwrap(), CloneMethod::Copy);
+    /// assert_eq!(fs.read("/snapshot.dat").unwrap(), b"records");
+    /// ```
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        copy_file(self, src, dst)?;
+        Ok(CloneMethod::Copy)
+    }
+    /// Move the file at `from` to `to`, replacing any file already at `to`.
+    ///
+    /// Only files can be renamed; renaming a directory, or onto one, fails with
+    /// [`FileSystemError::InvalidOperation`]. The default implementation copies the file and
+    /// removes the original, so it isn't atomic; backends able to move entries in place do so.
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        move_file(self, from, to)
+    }
+    /// Apply a batch of operations in order, returning the result of each.
+    ///
+    /// A failed operation doesn't stop the ones after it, and the batch as a whole isn't
+    /// atomic. Backends may apply a batch faster than issuing its operations one by one, such as
+    /// by taking their locks once or pipelining requests to a remote store; the default
+    /// implementation applies them one by one.
+    ///
+    /// ```rust
+    /// use minql_vfs::{BatchOperation, FileSystem, FileSystemError, MemoryFileSystem};
+    ///
+    /// let fs = MemoryFileSystem::new();
+    /// let results = fs.apply(&[
+    ///     BatchOperation::create_directory("/staging"),
+    ///     BatchOperation::write("/staging/part-0", b"rows"),
+    ///     BatchOperation::rename("/staging/part-0", "/part-0"),
+    ///     BatchOperation::remove_file("/missing"),
+    ///     BatchOperation::remove_directory("/staging"),
+    /// ]);
+    /// assert!(matches!(results[3], Err(FileSystemError::PathMissing)));
+    /// assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 4);
+    /// assert_eq!(fs.list_directory("/").unwrap(), vec!["part-0"]);
+    /// ```
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        batch
+            .iter()
+            .map(|operation| apply_operation(self, operation))
+            .collect()
+    }
+    /// Hash the contents of a file, which is streamed through the hasher in chunks.
+    fn hash_file(&self, path: &str, algorithm: HashAlgorithm) -> FileSystemResult<ContentDigest> {
+        self.open_with(path, OpenOptions::new().read(true))?
+            .hash(algorithm)
+    }
+    /// Read the entire contents of a file.
+    ///
+    /// ```rust
+    /// use minql_vfs::{FileSystem, MemoryFileSystem};
+    ///
+    /// let fs = MemoryFileSystem::new();
+    /// fs.write("/greeting.txt", b"Hello").unwrap();
+    /// fs.append("/greeting.txt", b", World!").unwrap();
+    /// assert_eq!(fs.read("/greeting.txt").unwrap(), b"Hello, World!");
+    /// assert_eq!(fs.read_to_string("/greeting.txt").unwrap(), "Hello, World!");
+    /// ```
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        let mut handle = self.open_with(path, OpenOptions::new().read(true))?;
+        let mut contents = Vec::new();
+        handle
+            .read_to_end(&mut contents)
+            .map_err(FileSystemError::io_error)?;
+        Ok(contents)
+    }
+    /// Read the entire contents of a file as UTF-8 text.
+    fn read_to_string(&self, path: &str) -> FileSystemResult<String> {
+        String::from_utf8(self.read(path)?).map_err(FileSystemError::wrap_error)
+    }
+    /// Replace the contents of a file, creating it if it doesn't exist.
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        let options = OpenOptions::new().write(true).create(true).truncate(true);
+        let mut handle = self.open_with(path, options)?;
+        handle
+            .write_all(contents)
+            .and_then(|()| handle.flush())
+            .map_err(FileSystemError::io_error)
+    }
+    /// Add to the end of a file, creating it if it doesn't exist.
+    fn append(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        let options = OpenOptions::new().write(true).create(true).append(true);
+        let mut handle = self.open_with(path, options)?;
+        handle
+            .write_all(contents)
+            .and_then(|()| handle.flush())
+            .map_err(FileSystemError::io_error)
+    }
 }
 
-/// Dynamic Wrapper for FileSystems
+/// Dynamic Wrapper for `FileSystems`
 pub(crate) trait DynamicFileSystem: Debug + Send + Sync + 'static {
     /// Check if an entry exists at the provided path.
     fn exists(&self, path: &str) -> FileSystemResult<bool>;
@@ -112,17 +437,49 @@ pub(crate) trait DynamicFileSystem: Debug + Send + Sync + 'static {
     /// Creates a new, empty folder entry at the provided path.
     fn create_directory_all(&self, path: &str) -> FileSystemResult<()>;
     /// Returns an iterator over the names of entries within a Folder.
-    fn list_directory<'a>(&self, path: &str) -> FileSystemResult<Vec<String>>;
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>>;
     /// Removes the folder at this path.
     fn remove_directory(&self, path: &str) -> FileSystemResult<()>;
     /// Removes the folder at this path and all children.
     fn remove_directory_all(&self, path: &str) -> FileSystemResult<()>;
     /// Create or Open a new append only file for writing.
     fn create_file(&self, path: &str) -> FileSystemResult<Box<dyn FileHandle>>;
-    /// Create or Open a new append only file for writing.
+    /// Open an existing file for reading and writing.
     fn open_file(&self, path: &str) -> FileSystemResult<Box<dyn FileHandle>>;
     /// Removes the file at this path
     fn remove_file(&self, path: &str) -> FileSystemResult<()>;
+    /// Get the kind of entry at a path.
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType>;
+    /// Create a symbolic link at `path` pointing to `target`.
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()>;
+    /// Read the target of the symbolic link at `path`.
+    fn read_link(&self, path: &str) -> FileSystemResult<String>;
+    /// Get the permissions of the entry at a path.
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions>;
+    /// Get the time the file at a path was last modified.
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime>;
+    /// Replace the permissions of the entry at a path.
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()>;
+    /// Open a file using the provided options.
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Box<dyn FileHandle>>;
+    /// Get the total, used and available bytes of the storage holding this filesystem.
+    fn space(&self) -> FileSystemResult<FileSystemSpace>;
+    /// Create a new file at `dst` with the contents of the file at `src`.
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod>;
+    /// Move the file at `from` to `to`, replacing any file already at `to`.
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()>;
+    /// Apply a batch of operations in order, returning the result of each.
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>>;
+    /// Hash the contents of a file.
+    fn hash_file(&self, path: &str, algorithm: HashAlgorithm) -> FileSystemResult<ContentDigest>;
+    /// Read the entire contents of a file.
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>>;
+    /// Read the entire contents of a file as UTF-8 text.
+    fn read_to_string(&self, path: &str) -> FileSystemResult<String>;
+    /// Replace the contents of a file, creating it if it doesn't exist.
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()>;
+    /// Add to the end of a file, creating it if it doesn't exist.
+    fn append(&self, path: &str, contents: &[u8]) -> FileSystemResult<()>;
 }
 
 impl<T: FileSystem> DynamicFileSystem for T {
@@ -150,7 +507,7 @@ impl<T: FileSystem> DynamicFileSystem for T {
         FileSystem::create_directory_all(self, path)
     }
 
-    fn list_directory<'a>(&self, path: &str) -> FileSystemResult<Vec<String>> {
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
         FileSystem::list_directory(self, path)
     }
 
@@ -166,7 +523,7 @@ impl<T: FileSystem> DynamicFileSystem for T {
     fn create_file(&self, path: &str) -> FileSystemResult<Box<dyn FileHandle>> {
         Ok(Box::new(FileSystem::create_file(self, path)?))
     }
-    /// Create or Open a new append only file for writing.
+    /// Open an existing file for reading and writing.
     fn open_file(&self, path: &str) -> FileSystemResult<Box<dyn FileHandle>> {
         Ok(Box::new(FileSystem::open_file(self, path)?))
     }
@@ -174,6 +531,70 @@ impl<T: FileSystem> DynamicFileSystem for T {
     fn remove_file(&self, path: &str) -> FileSystemResult<()> {
         FileSystem::remove_file(self, path)
     }
+
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        FileSystem::file_type(self, path, policy)
+    }
+
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        FileSystem::create_symlink(self, target, path)
+    }
+
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        FileSystem::read_link(self, path)
+    }
+
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        FileSystem::permissions(self, path)
+    }
+
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        FileSystem::modified(self, path)
+    }
+
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        FileSystem::set_permissions(self, path, permissions)
+    }
+
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Box<dyn FileHandle>> {
+        Ok(Box::new(FileSystem::open_with(self, path, options)?))
+    }
+
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        FileSystem::space(self)
+    }
+
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        FileSystem::clone_file(self, src, dst)
+    }
+
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        FileSystem::rename(self, from, to)
+    }
+
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        FileSystem::apply(self, batch)
+    }
+
+    fn hash_file(&self, path: &str, algorithm: HashAlgorithm) -> FileSystemResult<ContentDigest> {
+        FileSystem::hash_file(self, path, algorithm)
+    }
+
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        FileSystem::read(self, path)
+    }
+
+    fn read_to_string(&self, path: &str) -> FileSystemResult<String> {
+        FileSystem::read_to_string(self, path)
+    }
+
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        FileSystem::write(self, path, contents)
+    }
+
+    fn append(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        FileSystem::append(self, path, contents)
+    }
 }
 
 /// Handle for File Access
@@ -184,14 +605,111 @@ pub trait FileHandle: Debug + Read + Write + Seek + Sync + Send + 'static {
     fn get_size(&self) -> FileSystemResult<u64>;
     /// Set File Length
     fn set_size(&mut self, new_size: u64) -> FileSystemResult<()>;
+    /// Reserve storage for the first `len` bytes of the file, growing it to at least `len`.
+    ///
+    /// Unlike [`FileHandle::set_size`], which may leave a sparse hole, backends that support it
+    /// allocate the blocks up front so later writes into the range can't fail for lack of space.
+    /// The default implementation falls back to growing the file with `set_size`.
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        if self.get_size()? < len {
+            self.set_size(len)?;
+        }
+        Ok(())
+    }
     /// Flushes all data and metadata to storage.
     fn sync_all(&mut self) -> FileSystemResult<()>;
     /// Flush all data to storage.
     fn sync_data(&mut self) -> FileSystemResult<()>;
     /// Get Advisory Lock Status of this file
     fn get_lock_status(&self) -> FileSystemResult<FileLockMode>;
-    /// Apply or Clear Advisory Lock of this File
+    /// Apply or Clear Advisory Lock of this File, waiting for conflicting holders to release.
     fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()>;
+    /// Apply or Clear Advisory Lock of this File without waiting, failing with
+    /// [`FileSystemError::FileAlreadyLocked`] if another handle holds a conflicting lock.
+    ///
+    /// The default implementation fails with [`FileSystemError::UnsupportedOperation`], as
+    /// does every lock operation built on it, for backends that can't take a lock without
+    /// waiting.
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        Err(FileSystemError::UnsupportedOperation)
+    }
+    /// Apply or Clear Advisory Lock of this File, waiting up to `timeout` for conflicting
+    /// holders to release before failing with [`FileSystemError::FileAlreadyLocked`].
+    ///
+    /// Two handles each holding a shared lock and waiting to upgrade it deadlock until one of
+    /// them times out, see [`FileHandle::upgrade_lock`].
+    fn set_lock_status_timeout(
+        &mut self,
+        mode: FileLockMode,
+        timeout: Duration,
+    ) -> FileSystemResult<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.try_set_lock_status(mode) {
+                Err(FileSystemError::FileAlreadyLocked) => {}
+                result => return result,
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(FileSystemError::FileAlreadyLocked);
+            }
+            std::thread::sleep(remaining.min(LOCK_POLL_INTERVAL));
+        }
+    }
+    /// Upgrade a shared lock held by this handle to an exclusive lock without waiting.
+    ///
+    /// Upgrading an exclusive lock does nothing, and upgrading without a lock fails with
+    /// [`FileSystemError::InvalidOperation`]. If another handle holds a shared lock this fails
+    /// with [`FileSystemError::FileAlreadyLocked`]; since that handle may be trying to upgrade
+    /// as well, waiting for it would deadlock, so callers should release their shared lock and
+    /// acquire an exclusive one afresh rather than retry the upgrade. A failed upgrade keeps the
+    /// shared lock where the backend can convert locks atomically; otherwise another handle may
+    /// take the lock in between, which [`FileHandle::get_lock_status`] reports.
+    fn upgrade_lock(&mut self) -> FileSystemResult<()> {
+        match self.get_lock_status()? {
+            FileLockMode::Unlocked => Err(FileSystemError::InvalidOperation),
+            FileLockMode::Shared => self.try_set_lock_status(FileLockMode::Exclusive),
+            FileLockMode::Exclusive => Ok(()),
+        }
+    }
+    /// Downgrade an exclusive lock held by this handle to a shared lock without waiting.
+    ///
+    /// Downgrading a shared lock does nothing, and downgrading without a lock fails with
+    /// [`FileSystemError::InvalidOperation`]. Downgrades can't conflict on backends which
+    /// convert locks atomically; otherwise another handle may take the lock in between, in which
+    /// case this fails with [`FileSystemError::FileAlreadyLocked`] and the lock is lost.
+    fn downgrade_lock(&mut self) -> FileSystemResult<()> {
+        match self.get_lock_status()? {
+            FileLockMode::Unlocked => Err(FileSystemError::InvalidOperation),
+            FileLockMode::Shared => Ok(()),
+            FileLockMode::Exclusive => self.try_set_lock_status(FileLockMode::Shared),
+        }
+    }
+    /// Alignment in bytes required of buffer addresses, offsets and lengths for I/O through this
+    /// handle, which is only greater than one for handles opened with [`OpenOptions::direct`].
+    fn alignment(&self) -> FileSystemResult<usize> {
+        Ok(1)
+    }
+    /// Apply an Advisory Lock to `len` bytes starting at `offset`, where a `len` of zero extends
+    /// the range to the end of the file however large it grows.
+    ///
+    /// Locking a range this handle already holds replaces the lock on the overlapping bytes, and
+    /// locking with [`FileLockMode::Unlocked`] releases them. A conflicting lock held through
+    /// another handle fails with [`FileSystemError::FileAlreadyLocked`].
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        Err(FileSystemError::UnsupportedOperation)
+    }
+    /// Release any Advisory Lock this handle holds on `len` bytes starting at `offset`.
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.lock_range(offset, len, FileLockMode::Unlocked)
+    }
+    /// Hint how `len` bytes starting at `offset` will be accessed, where a `len` of zero extends
+    /// the range to the end of the file.
+    ///
+    /// Hints only affect performance, so handles which can't make use of them ignore them.
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        Ok(())
+    }
     /// Write directly to a location without modifying cursor.
     fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
         let pos = self.stream_position().map_err(FileSystemError::io_error)?;
@@ -212,13 +730,525 @@ pub trait FileHandle: Debug + Read + Write + Seek + Sync + Send + 'static {
             .map_err(FileSystemError::io_error)?;
         Ok(rv)
     }
+    /// Read directly from a location into a sequence of buffers without modifying cursor.
+    ///
+    /// Buffers are filled in order, stopping early at the end of the file.
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        let mut total = 0;
+        for buffer in buffers.iter_mut() {
+            let read = self.read_at_offset(offset + total as u64, buffer)?;
+            total += read;
+            if read < buffer.len() {
+                break;
+            }
+        }
+        Ok(total)
+    }
+    /// Write a sequence of buffers directly to a location without modifying cursor.
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        let mut total = 0;
+        for buffer in buffers {
+            let written = self.write_to_offset(offset + total as u64, buffer)?;
+            total += written;
+            if written < buffer.len() {
+                break;
+            }
+        }
+        Ok(total)
+    }
+    /// Map `len` bytes starting at `offset` for reading without copying where supported.
+    ///
+    /// The range is clamped to the end of the file. Handles that can't be memory mapped return
+    /// a copy of the range read through [`FileHandle::read_at_offset`]. A mapping reflects
+    /// the file as it is on storage, so callers must only map files that aren't modified or
+    /// truncated while the mapping is alive.
+    #[cfg(feature = "mmap")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<FileMapping> {
+        let available = self.get_size()?.saturating_sub(offset);
+        let len = len.min(usize::try_from(available).unwrap_or(usize::MAX));
+        let mut buffer = vec![0; len];
+        let mut filled = 0;
+        while filled < len {
+            match self.read_at_offset(offset + filled as u64, &mut buffer[filled..])? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+        buffer.truncate(filled);
+        Ok(FileMapping::Copied(buffer))
+    }
 
+    /// Hash the whole contents of the file, reading it in chunks without modifying cursor.
+    ///
+    /// ```rust
+    /// use minql_vfs::{FileHandle, FileSystem, HashAlgorithm, MemoryFileSystem};
+    /// use std::io::Write;
+    ///
+    /// let fs = MemoryFileSystem::new();
+    /// let mut handle = fs.create_file("/greeting.txt").unwrap();
+    /// handle.write_all(b"Hello, World!").unwrap();
+    /// assert_eq!(handle.hash(HashAlgorithm::Crc32).unwrap().to_string(), "ec4ac3d0");
+    /// ```
+    fn hash(&mut self, algorithm: HashAlgorithm) -> FileSystemResult<ContentDigest> {
+        hash_handle(self, algorithm)
+    }
     /// Truncate a file
     fn truncate(&mut self) -> FileSystemResult<()> {
         self.set_size(0)
     }
 }
 
+impl<H: FileHandle + ?Sized> FileHandle for Box<H> {
+    fn path(&self) -> &str {
+        H::path(self)
+    }
+
+    fn get_size(&self) -> FileSystemResult<u64> {
+        H::get_size(self)
+    }
+
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        H::set_size(self, new_size)
+    }
+
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        H::allocate(self, len)
+    }
+
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        H::sync_all(self)
+    }
+
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        H::sync_data(self)
+    }
+
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        H::get_lock_status(self)
+    }
+
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        H::set_lock_status(self, mode)
+    }
+
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        H::try_set_lock_status(self, mode)
+    }
+
+    fn set_lock_status_timeout(
+        &mut self,
+        mode: FileLockMode,
+        timeout: Duration,
+    ) -> FileSystemResult<()> {
+        H::set_lock_status_timeout(self, mode, timeout)
+    }
+
+    fn upgrade_lock(&mut self) -> FileSystemResult<()> {
+        H::upgrade_lock(self)
+    }
+
+    fn downgrade_lock(&mut self) -> FileSystemResult<()> {
+        H::downgrade_lock(self)
+    }
+
+    fn alignment(&self) -> FileSystemResult<usize> {
+        H::alignment(self)
+    }
+
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        H::lock_range(self, offset, len, mode)
+    }
+
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        H::unlock_range(self, offset, len)
+    }
+
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        H::advise(self, offset, len, advice)
+    }
+
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        H::read_at_offset(self, offset, buffer)
+    }
+
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        H::write_to_offset(self, offset, buffer)
+    }
+
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        H::read_at_vectored(self, offset, buffers)
+    }
+
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        H::write_at_vectored(self, offset, buffers)
+    }
+
+    #[cfg(feature = "mmap")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<FileMapping> {
+        H::map_readonly(self, offset, len)
+    }
+
+    fn hash(&mut self, algorithm: HashAlgorithm) -> FileSystemResult<ContentDigest> {
+        H::hash(self, algorithm)
+    }
+
+    fn truncate(&mut self) -> FileSystemResult<()> {
+        H::truncate(self)
+    }
+}
+
+/// Positional reads through a shared reference.
+///
+/// [`FileHandle::read_at_offset`] needs exclusive access, so readers sharing a handle have to
+/// take turns. Handles implementing `ReadAt` can instead be shared, for example behind an
+/// [`Arc`], and read from many threads at once.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, ReadAt};
+/// use std::sync::Arc;
+///
+/// let fs = MemoryFileSystem::new();
+/// fs.write("/pages.bin", b"page0page1").unwrap();
+/// let handle = Arc::new(fs.open_file("/pages.bin").unwrap());
+///
+/// let reader = Arc::clone(&handle);
+/// let second = std::thread::spawn(move || {
+///     let mut page = [0; 5];
+///     reader.read_exact_at(5, &mut page).unwrap();
+///     page
+/// });
+/// let mut page = [0; 5];
+/// handle.read_exact_at(0, &mut page).unwrap();
+/// assert_eq!(&page, b"page0");
+/// assert_eq!(&second.join().unwrap(), b"page1");
+/// ```
+pub trait ReadAt: Debug + Send + Sync {
+    /// Read into `buffer` starting at `offset` without modifying cursor, returning fewer bytes
+    /// than requested at the end of the file.
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize>;
+    /// Fill `buffer` starting at `offset` without modifying cursor, failing if the file ends
+    /// first.
+    fn read_exact_at(&self, mut offset: u64, mut buffer: &mut [u8]) -> FileSystemResult<()> {
+        while !buffer.is_empty() {
+            let read = self.read_at(offset, buffer)?;
+            if read == 0 {
+                return Err(FileSystemError::io_error(
+                    std::io::ErrorKind::UnexpectedEof.into(),
+                ));
+            }
+            buffer = &mut buffer[read..];
+            offset += read as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Positional writes through a shared reference.
+///
+/// The counterpart of [`ReadAt`] for [`FileHandle::write_to_offset`]. Writes to overlapping
+/// ranges from different threads are not ordered with respect to each other.
+pub trait WriteAt: Debug + Send + Sync {
+    /// Write `buffer` starting at `offset` without modifying cursor, returning how many bytes
+    /// were written.
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize>;
+    /// Write all of `buffer` starting at `offset` without modifying cursor.
+    fn write_all_at(&self, mut offset: u64, mut buffer: &[u8]) -> FileSystemResult<()> {
+        while !buffer.is_empty() {
+            let written = self.write_at(offset, buffer)?;
+            if written == 0 {
+                return Err(FileSystemError::io_error(
+                    std::io::ErrorKind::WriteZero.into(),
+                ));
+            }
+            buffer = &buffer[written..];
+            offset += written as u64;
+        }
+        Ok(())
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        T::read_at(self, offset, buffer)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Box<T> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        T::read_at(self, offset, buffer)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Arc<T> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        T::read_at(self, offset, buffer)
+    }
+}
+
+impl<T: WriteAt + ?Sized> WriteAt for &T {
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        T::write_at(self, offset, buffer)
+    }
+}
+
+impl<T: WriteAt + ?Sized> WriteAt for Box<T> {
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        T::write_at(self, offset, buffer)
+    }
+}
+
+impl<T: WriteAt + ?Sized> WriteAt for Arc<T> {
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        T::write_at(self, offset, buffer)
+    }
+}
+
+/// Space of the storage holding a filesystem, returned by [`FileSystem::space`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileSystemSpace {
+    /// Total bytes of storage
+    pub total: u64,
+    /// Bytes in use
+    pub used: u64,
+    /// Bytes that can still be written
+    pub available: u64,
+}
+
+/// Single operation of a batch passed to [`FileSystem::apply`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BatchOperation {
+    /// [`FileSystem::create_directory`]
+    CreateDirectory {
+        /// Path of the new directory
+        path: String,
+    },
+    /// [`FileSystem::create_directory_all`]
+    CreateDirectoryAll {
+        /// Path of the new directory
+        path: String,
+    },
+    /// [`FileSystem::write`]
+    Write {
+        /// Path of the file
+        path: String,
+        /// New contents of the file
+        contents: Vec<u8>,
+    },
+    /// [`FileSystem::rename`]
+    Rename {
+        /// Current path of the file
+        from: String,
+        /// New path of the file
+        to: String,
+    },
+    /// [`FileSystem::remove_file`]
+    RemoveFile {
+        /// Path of the file
+        path: String,
+    },
+    /// [`FileSystem::remove_directory`]
+    RemoveDirectory {
+        /// Path of the empty directory
+        path: String,
+    },
+}
+
+impl BatchOperation {
+    /// Create the directory at `path`.
+    #[must_use]
+    pub fn create_directory(path: &str) -> BatchOperation {
+        BatchOperation::CreateDirectory {
+            path: path.to_string(),
+        }
+    }
+
+    /// Create the directory at `path` along with any missing parents.
+    #[must_use]
+    pub fn create_directory_all(path: &str) -> BatchOperation {
+        BatchOperation::CreateDirectoryAll {
+            path: path.to_string(),
+        }
+    }
+
+    /// Replace the contents of the file at `path`, creating it if it doesn't exist.
+    #[must_use]
+    pub fn write(path: &str, contents: &[u8]) -> BatchOperation {
+        BatchOperation::Write {
+            path: path.to_string(),
+            contents: contents.to_vec(),
+        }
+    }
+
+    /// Move the file at `from` to `to`.
+    #[must_use]
+    pub fn rename(from: &str, to: &str) -> BatchOperation {
+        BatchOperation::Rename {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    /// Remove the file at `path`.
+    #[must_use]
+    pub fn remove_file(path: &str) -> BatchOperation {
+        BatchOperation::RemoveFile {
+            path: path.to_string(),
+        }
+    }
+
+    /// Remove the empty directory at `path`.
+    #[must_use]
+    pub fn remove_directory(path: &str) -> BatchOperation {
+        BatchOperation::RemoveDirectory {
+            path: path.to_string(),
+        }
+    }
+
+    /// Paths the operation reads or modifies.
+    pub(crate) fn paths(&self) -> impl Iterator<Item = &str> {
+        let (first, second) = match self {
+            BatchOperation::CreateDirectory { path }
+            | BatchOperation::CreateDirectoryAll { path }
+            | BatchOperation::Write { path, .. }
+            | BatchOperation::RemoveFile { path }
+            | BatchOperation::RemoveDirectory { path } => (path.as_str(), None),
+            BatchOperation::Rename { from, to } => (from.as_str(), Some(to.as_str())),
+        };
+        std::iter::once(first).chain(second)
+    }
+
+    /// Apply `map` to every path of the operation.
+    pub(crate) fn map_paths<M: FnMut(&str) -> FileSystemResult<String>>(
+        &self,
+        mut map: M,
+    ) -> FileSystemResult<BatchOperation> {
+        Ok(match self {
+            BatchOperation::CreateDirectory { path } => {
+                BatchOperation::CreateDirectory { path: map(path)? }
+            }
+            BatchOperation::CreateDirectoryAll { path } => {
+                BatchOperation::CreateDirectoryAll { path: map(path)? }
+            }
+            BatchOperation::Write { path, contents } => BatchOperation::Write {
+                path: map(path)?,
+                contents: contents.clone(),
+            },
+            BatchOperation::Rename { from, to } => BatchOperation::Rename {
+                from: map(from)?,
+                to: map(to)?,
+            },
+            BatchOperation::RemoveFile { path } => BatchOperation::RemoveFile { path: map(path)? },
+            BatchOperation::RemoveDirectory { path } => {
+                BatchOperation::RemoveDirectory { path: map(path)? }
+            }
+        })
+    }
+}
+
+/// How [`FileSystem::clone_file`] produced the new file.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CloneMethod {
+    /// The new file shares the blocks of the source until either is modified
+    Reflink,
+    /// The contents were copied
+    Copy,
+}
+
+/// Permissions of an entry, read by [`FileSystem::permissions`] and applied by
+/// [`FileSystem::set_permissions`].
+///
+/// A read-only file can't be modified and a read-only directory can't have entries created in or
+/// removed from it. The unix-like mode is only enforced by backends that map it onto the OS.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, FileSystemError, MemoryFileSystem, Permissions};
+/// use std::io::Write;
+///
+/// let fs = MemoryFileSystem::new();
+/// fs.create_file("/test.txt").unwrap();
+/// fs.set_permissions("/test.txt", Permissions::new().readonly(true).mode(0o444))
+///     .unwrap();
+///
+/// assert!(fs.permissions("/test.txt").unwrap().is_readonly());
+/// assert!(fs.open_file("/test.txt").unwrap().write_all(b"denied").is_err());
+/// ```
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Permissions {
+    readonly: bool,
+    mode: Option<u32>,
+}
+
+impl Permissions {
+    /// Create writable permissions with no mode.
+    #[must_use]
+    pub fn new() -> Permissions {
+        Permissions::default()
+    }
+
+    /// Forbid modifying the entry.
+    #[must_use]
+    pub fn readonly(mut self, readonly: bool) -> Permissions {
+        self.readonly = readonly;
+        self
+    }
+
+    /// Set the unix-like permission bits of the entry, such as `0o644`.
+    #[must_use]
+    pub fn mode(mut self, mode: u32) -> Permissions {
+        self.mode = Some(mode & 0o7777);
+        self
+    }
+
+    /// Whether modifying the entry is forbidden.
+    #[must_use]
+    pub fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// Unix-like permission bits of the entry, if any.
+    #[must_use]
+    pub fn get_mode(&self) -> Option<u32> {
+        self.mode
+    }
+}
+
+/// Kind of entry at a path, returned by [`FileSystem::file_type`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FileType {
+    /// Regular file
+    File,
+    /// Directory
+    Directory,
+    /// Symbolic link
+    Symlink,
+}
+
+/// Whether a call follows a symbolic link at the end of its path.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum SymlinkPolicy {
+    /// Act on the entry the link points to
+    #[default]
+    Follow,
+    /// Act on the link itself
+    NoFollow,
+}
+
 /// An enumeration of types which represents the state of an advisory lock.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum FileLockMode {
@@ -229,3 +1259,158 @@ pub enum FileLockMode {
     /// ## EXCLUSIVE
     Exclusive,
 }
+
+/// Expected access pattern of a range of a file, passed to [`FileHandle::advise`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Advice {
+    /// No particular pattern, undoing earlier advice
+    #[default]
+    Normal,
+    /// Read in order from lower to higher offsets, so reading ahead pays off
+    Sequential,
+    /// Read in no particular order, so reading ahead is wasted
+    Random,
+    /// Read soon, so it should be fetched ahead of time
+    WillNeed,
+    /// Not read again soon, so any cached copy can be dropped
+    DontNeed,
+}
+
+/// Options controlling how [`FileSystem::open_with`] opens a file.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, OpenOptions};
+///
+/// let fs = MemoryFileSystem::new();
+/// let options = OpenOptions::new().read(true).write(true).create(true);
+/// let file = fs.open_with("/test.txt", options).expect("Error Opening File");
+/// ```
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+    direct: bool,
+    no_follow: bool,
+}
+
+impl OpenOptions {
+    /// Create a new set of options with everything disabled.
+    #[must_use]
+    pub fn new() -> OpenOptions {
+        OpenOptions::default()
+    }
+
+    /// Open the file for reading.
+    #[must_use]
+    pub fn read(mut self, read: bool) -> OpenOptions {
+        self.read = read;
+        self
+    }
+
+    /// Open the file for writing.
+    #[must_use]
+    pub fn write(mut self, write: bool) -> OpenOptions {
+        self.write = write;
+        self
+    }
+
+    /// Position the cursor at the end of the file when opened.
+    #[must_use]
+    pub fn append(mut self, append: bool) -> OpenOptions {
+        self.append = append;
+        self
+    }
+
+    /// Truncate an existing file to zero length when opened.
+    #[must_use]
+    pub fn truncate(mut self, truncate: bool) -> OpenOptions {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Create the file if it doesn't exist.
+    #[must_use]
+    pub fn create(mut self, create: bool) -> OpenOptions {
+        self.create = create;
+        self
+    }
+
+    /// Create the file, failing with [`FileSystemError::PathExists`] if it already exists.
+    #[must_use]
+    pub fn create_new(mut self, create_new: bool) -> OpenOptions {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Bypass the OS page cache, using `O_DIRECT` or `FILE_FLAG_NO_BUFFERING` where available.
+    ///
+    /// Direct I/O generally requires buffers, offsets and lengths to be multiples of
+    /// [`FileHandle::alignment`]. Backends that can't honour it fail with
+    /// [`FileSystemError::UnsupportedOperation`].
+    #[must_use]
+    pub fn direct(mut self, direct: bool) -> OpenOptions {
+        self.direct = direct;
+        self
+    }
+
+    /// Fail with [`FileSystemError::InvalidOperation`] rather than open the target if the path
+    /// is a symbolic link. Links earlier in the path are still followed.
+    #[must_use]
+    pub fn no_follow(mut self, no_follow: bool) -> OpenOptions {
+        self.no_follow = no_follow;
+        self
+    }
+
+    /// Whether the file is opened for reading.
+    #[must_use]
+    pub fn is_read(&self) -> bool {
+        self.read
+    }
+
+    /// Whether the file is opened for writing.
+    #[must_use]
+    pub fn is_write(&self) -> bool {
+        self.write
+    }
+
+    /// Whether the cursor starts at the end of the file.
+    #[must_use]
+    pub fn is_append(&self) -> bool {
+        self.append
+    }
+
+    /// Whether an existing file is truncated.
+    #[must_use]
+    pub fn is_truncate(&self) -> bool {
+        self.truncate
+    }
+
+    /// Whether a missing file is created.
+    #[must_use]
+    pub fn is_create(&self) -> bool {
+        self.create
+    }
+
+    /// Whether the file must not already exist.
+    #[must_use]
+    pub fn is_create_new(&self) -> bool {
+        self.create_new
+    }
+
+    /// Whether the OS page cache is bypassed.
+    #[must_use]
+    pub fn is_direct(&self) -> bool {
+        self.direct
+    }
+
+    /// Whether opening a symbolic link fails rather than following it.
+    #[must_use]
+    pub fn is_no_follow(&self) -> bool {
+        self.no_follow
+    }
+}
@@ -0,0 +1,646 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::utility::{glob_segments, match_segments, normalize_segments};
+use crate::{
+    Advice, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError, FileSystemResult,
+    FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::time::SystemTime;
+
+/// Class of operation an [`AclRule`] applies to.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum AclOperation {
+    /// Reading file contents
+    Read,
+    /// Modifying file contents or permissions
+    Write,
+    /// Creating files, directories and symbolic links
+    Create,
+    /// Removing files and directories
+    Delete,
+    /// Listing the entries of a directory
+    List,
+    /// Inspecting whether an entry exists, its type, size, permissions or link target
+    Metadata,
+}
+
+impl AclOperation {
+    /// Every class of operation.
+    pub const ALL: [AclOperation; 6] = [
+        AclOperation::Read,
+        AclOperation::Write,
+        AclOperation::Create,
+        AclOperation::Delete,
+        AclOperation::List,
+        AclOperation::Metadata,
+    ];
+}
+
+/// Whether an [`AclRule`] allows or denies the operations it matches.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AclEffect {
+    /// Permit the operation
+    Allow,
+    /// Reject the operation with [`FileSystemError::PermissionDenied`]
+    Deny,
+}
+
+/// Rule of an [`AclFileSystem`], matching operations of the given classes on paths matching a
+/// glob pattern.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AclRule {
+    effect: AclEffect,
+    pattern: Vec<String>,
+    operations: Vec<AclOperation>,
+}
+
+impl AclRule {
+    /// Create a rule applying `effect` to `operations` on paths matching `pattern`.
+    ///
+    /// Patterns are matched a path segment at a time, where `*` matches any run of characters
+    /// and `?` any single character within a segment, and a `**` segment matches any number of
+    /// segments, including none.
+    #[must_use]
+    pub fn new(effect: AclEffect, pattern: &str, operations: &[AclOperation]) -> AclRule {
+        AclRule {
+            effect,
+            pattern: glob_segments(pattern),
+            operations: operations.to_vec(),
+        }
+    }
+
+    /// Effect of this rule.
+    #[must_use]
+    pub fn effect(&self) -> AclEffect {
+        self.effect
+    }
+
+    /// Classes of operation this rule applies to.
+    #[must_use]
+    pub fn operations(&self) -> &[AclOperation] {
+        &self.operations
+    }
+
+    /// Check if this rule applies to `operation` on the normalized `path` segments.
+    fn matches(&self, segments: &[&str], operation: AclOperation) -> bool {
+        self.operations.contains(&operation) && match_segments(&self.pattern, segments)
+    }
+}
+
+/// Access-Control `FileSystem` Wrapper
+///
+/// Checks every operation against an ordered list of [`AclRule`]s, where the first rule
+/// matching both the path and the class of operation decides, and rejects denied operations
+/// with [`FileSystemError::PermissionDenied`]. Operations no rule matches get the default
+/// effect, which is to deny.
+///
+/// Handles are checked when opened, and only permit writes if [`AclOperation::Write`] was
+/// allowed on their path at the time. Rules match paths as given, after normalization, so
+/// creating a symbolic link also requires read access to its target.
+///
+/// ```rust
+/// use minql_vfs::{AclFileSystem, AclOperation, FileSystem, FileSystemError, MemoryFileSystem};
+///
+/// let fs = AclFileSystem::new(MemoryFileSystem::new())
+///     .deny("/data/secrets/**", &AclOperation::ALL)
+///     .allow("/data/**", &AclOperation::ALL)
+///     .allow("/", &[AclOperation::List, AclOperation::Metadata]);
+///
+/// fs.create_directory("/data").unwrap();
+/// fs.write("/data/table.dat", b"rows").unwrap();
+/// assert!(matches!(fs.write("/data/secrets/key", b"key"), Err(FileSystemError::PermissionDenied)));
+/// assert!(matches!(fs.create_file("/other.dat"), Err(FileSystemError::PermissionDenied)));
+/// assert_eq!(fs.list_directory("/").unwrap(), ["data"]);
+/// ```
+#[derive(Debug)]
+pub struct AclFileSystem<F: FileSystem> {
+    inner: F,
+    rules: Vec<AclRule>,
+    default: AclEffect,
+}
+
+impl<F: FileSystem> AclFileSystem<F> {
+    /// Create a new Access-Control `FileSystem` over `filesystem`, denying everything until
+    /// rules are added.
+    pub fn new(filesystem: F) -> AclFileSystem<F> {
+        AclFileSystem {
+            inner: filesystem,
+            rules: Vec::new(),
+            default: AclEffect::Deny,
+        }
+    }
+
+    /// Append a rule, which only applies to operations no earlier rule matched.
+    #[must_use]
+    pub fn with_rule(mut self, rule: AclRule) -> AclFileSystem<F> {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Append a rule allowing `operations` on paths matching `pattern`.
+    #[must_use]
+    pub fn allow(self, pattern: &str, operations: &[AclOperation]) -> AclFileSystem<F> {
+        self.with_rule(AclRule::new(AclEffect::Allow, pattern, operations))
+    }
+
+    /// Append a rule denying `operations` on paths matching `pattern`.
+    #[must_use]
+    pub fn deny(self, pattern: &str, operations: &[AclOperation]) -> AclFileSystem<F> {
+        self.with_rule(AclRule::new(AclEffect::Deny, pattern, operations))
+    }
+
+    /// Set the effect for operations no rule matches.
+    #[must_use]
+    pub fn with_default(mut self, default: AclEffect) -> AclFileSystem<F> {
+        self.default = default;
+        self
+    }
+
+    /// Borrow the rules, in the order they are checked.
+    #[must_use]
+    pub fn rules(&self) -> &[AclRule] {
+        &self.rules
+    }
+
+    /// Borrow the wrapped filesystem.
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// Decide the effect of `operation` on `path`.
+    pub fn effect(&self, path: &str, operation: AclOperation) -> FileSystemResult<AclEffect> {
+        let segments = normalize_segments(path)?;
+        Ok(self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(&segments, operation))
+            .map_or(self.default, AclRule::effect))
+    }
+
+    /// Fail with [`FileSystemError::PermissionDenied`] unless `operation` is allowed on `path`.
+    fn check(&self, path: &str, operation: AclOperation) -> FileSystemResult<()> {
+        match self.effect(path, operation)? {
+            AclEffect::Allow => Ok(()),
+            AclEffect::Deny => {
+                tracing::debug!(path, ?operation, "Denied by access control");
+                Err(FileSystemError::PermissionDenied)
+            }
+        }
+    }
+}
+
+impl<F: FileSystem> FileSystem for AclFileSystem<F> {
+    type FileHandle = AclFileHandle<F::FileHandle>;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        self.check(path, AclOperation::Metadata)?;
+        self.inner.exists(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        self.check(path, AclOperation::Metadata)?;
+        self.inner.is_file(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        self.check(path, AclOperation::Metadata)?;
+        self.inner.is_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.check(path, AclOperation::Metadata)?;
+        self.inner.filesize(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.check(path, AclOperation::Create)?;
+        self.inner.create_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.check(path, AclOperation::Create)?;
+        self.inner.create_directory_all(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        self.check(path, AclOperation::List)?;
+        self.inner.list_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.check(path, AclOperation::Delete)?;
+        self.inner.remove_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.check(path, AclOperation::Delete)?;
+        self.inner.remove_directory_all(path)
+    }
+
+    /// Requires both [`AclOperation::Create`] and [`AclOperation::Write`].
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.check(path, AclOperation::Create)?;
+        self.check(path, AclOperation::Write)?;
+        Ok(AclFileHandle {
+            inner: self.inner.create_file(path)?,
+            writable: true,
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.check(path, AclOperation::Read)?;
+        let writable = self.effect(path, AclOperation::Write)? == AclEffect::Allow;
+        Ok(AclFileHandle {
+            inner: self.inner.open_file(path)?,
+            writable,
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        self.check(path, AclOperation::Delete)?;
+        self.inner.remove_file(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.check(path, AclOperation::Metadata)?;
+        self.inner.file_type(path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        self.check(path, AclOperation::Create)?;
+        let resolved = if target.starts_with(['/', '\\']) {
+            target.to_string()
+        } else {
+            let parent = path
+                .rsplit_once(['/', '\\'])
+                .map_or("", |(parent, _)| parent);
+            format!("{parent}/{target}")
+        };
+        self.check(&resolved, AclOperation::Read)?;
+        self.inner.create_symlink(target, path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.check(path, AclOperation::Metadata)?;
+        self.inner.read_link(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.check(path, AclOperation::Metadata)?;
+        self.inner.permissions(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.check(path, AclOperation::Metadata)?;
+        self.inner.modified(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.check(path, AclOperation::Write)?;
+        self.inner.set_permissions(path, permissions)
+    }
+
+    /// Opening for reading requires [`AclOperation::Read`], for writing, appending or
+    /// truncating [`AclOperation::Write`], and creating a file that doesn't exist yet
+    /// [`AclOperation::Create`].
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        let writable = options.is_write() || options.is_append() || options.is_truncate();
+        if options.is_read() {
+            self.check(path, AclOperation::Read)?;
+        }
+        if writable {
+            self.check(path, AclOperation::Write)?;
+        }
+        if options.is_create_new() || (options.is_create() && !self.inner.exists(path)?) {
+            self.check(path, AclOperation::Create)?;
+        }
+        Ok(AclFileHandle {
+            inner: self.inner.open_with(path, options)?,
+            writable,
+        })
+    }
+
+    /// Requires [`AclOperation::Read`] on `src` and both [`AclOperation::Create`] and
+    /// [`AclOperation::Write`] on `dst`.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        self.check(src, AclOperation::Read)?;
+        self.check(dst, AclOperation::Create)?;
+        self.check(dst, AclOperation::Write)?;
+        self.inner.clone_file(src, dst)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        self.check(from, AclOperation::Read)?;
+        self.check(from, AclOperation::Delete)?;
+        self.check(to, AclOperation::Create)?;
+        self.check(to, AclOperation::Write)?;
+        self.inner.rename(from, to)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        self.inner.space()
+    }
+}
+
+/// Access-Control File Handle
+///
+/// Rejects writes with [`FileSystemError::PermissionDenied`] unless it was opened with write
+/// access by its [`AclFileSystem`].
+pub struct AclFileHandle<H: FileHandle> {
+    inner: H,
+    writable: bool,
+}
+
+impl<H: FileHandle> AclFileHandle<H> {
+    /// Check if this handle permits writes.
+    #[must_use]
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    /// Fail unless this handle permits writes.
+    fn writable(&self) -> FileSystemResult<()> {
+        if self.writable {
+            Ok(())
+        } else {
+            Err(FileSystemError::PermissionDenied)
+        }
+    }
+}
+
+impl<H: FileHandle> std::fmt::Debug for AclFileHandle<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<H: FileHandle> Read for AclFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        self.inner.read_vectored(bufs)
+    }
+}
+
+impl<H: FileHandle> Write for AclFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writable()?;
+        self.inner.write(buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        self.writable()?;
+        self.inner.write_vectored(bufs)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<H: FileHandle> Seek for AclFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<H: FileHandle> FileHandle for AclFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        self.inner.path()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        self.inner.get_size()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.writable()?;
+        self.inner.set_size(new_size)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.inner.sync_all()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.inner.sync_data()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        self.inner.get_lock_status()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.try_set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        self.inner.read_at_offset(offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.writable()?;
+        self.inner.write_to_offset(offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.lock_range(offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.inner.unlock_range(offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        self.inner.advise(offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        self.inner.read_at_vectored(offset, buffers)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        self.writable()?;
+        self.inner.write_at_vectored(offset, buffers)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        self.inner.map_readonly(offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        self.inner.alignment()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.writable()?;
+        self.inner.allocate(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{AclEffect, AclFileSystem, AclOperation, FileHandle, FileSystem};
+    use crate::{FileSystemError, MemoryFileSystem, OpenOptions};
+    use std::io::Write;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_acl_filesystem() {
+        let inner = MemoryFileSystem::new();
+        inner.create_directory_all("/logs/2024").unwrap();
+        inner.write("/logs/2024/app.log", b"started").unwrap();
+        inner.write("/logs/2024/audit.log", b"audit").unwrap();
+        let fs = AclFileSystem::new(inner)
+            .deny("/logs/*/audit.*", &AclOperation::ALL)
+            .allow(
+                "/logs/**",
+                &[
+                    AclOperation::Read,
+                    AclOperation::List,
+                    AclOperation::Metadata,
+                ],
+            )
+            .allow("/tmp/**", &AclOperation::ALL);
+
+        // The first matching rule decides, and anything unmatched is denied
+        assert_eq!(fs.read("/logs/2024/app.log").unwrap(), b"started");
+        assert_eq!(
+            fs.list_directory("/logs/2024").unwrap().len(),
+            2,
+            "listing isn't filtered"
+        );
+        assert!(matches!(
+            fs.read("/logs/2024/audit.log"),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        assert!(matches!(
+            fs.read("/logs/./2024/../2024/audit.log"),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        assert!(matches!(
+            fs.exists("/elsewhere"),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        assert_eq!(
+            fs.effect("/tmp", AclOperation::Delete).unwrap(),
+            AclEffect::Allow
+        );
+
+        // Handles opened without write access reject writes
+        let mut handle = fs.open_file("/logs/2024/app.log").unwrap();
+        assert!(!handle.is_writable());
+        assert!(handle.write_all(b"tampered").is_err());
+        assert!(matches!(
+            handle.set_size(0),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        let options = OpenOptions::new().write(true).append(true);
+        assert!(matches!(
+            fs.open_with("/logs/2024/app.log", options),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        assert!(matches!(
+            fs.remove_file("/logs/2024/app.log"),
+            Err(FileSystemError::PermissionDenied)
+        ));
+
+        // Links need read access to their target
+        fs.create_directory("/tmp").unwrap();
+        let mut scratch = fs.create_file("/tmp/scratch").unwrap();
+        scratch.write_all(b"scratch").unwrap();
+        fs.create_symlink("/logs/2024/app.log", "/tmp/app").unwrap();
+        assert!(matches!(
+            fs.create_symlink("../logs/2024/audit.log", "/tmp/audit"),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        fs.remove_directory_all("/tmp").unwrap();
+
+        let open = AclFileSystem::new(MemoryFileSystem::new()).with_default(AclEffect::Allow);
+        open.write("/anything", b"allowed").unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_acl_conformance() {
+        crate::conformance::run(|| {
+            AclFileSystem::new(MemoryFileSystem::new()).allow("/**", &AclOperation::ALL)
+        });
+    }
+}
@@ -0,0 +1,81 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::FileSystemResult;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::Thread;
+
+/// Asynchronous File Handle
+///
+/// Positional I/O which completes in the background, so many operations on a handle can be in
+/// flight at once. Buffers are owned by the operation rather than borrowed, as completion based
+/// backends such as `io_uring` keep using them until the operation finishes, even if the future
+/// is dropped first.
+///
+/// Futures can be awaited on any executor, or driven to completion synchronously with
+/// [`block_on`].
+pub trait AsyncFileHandle: Debug + Send + Sync + 'static {
+    /// Read up to `len` bytes starting at `offset`, returning fewer at the end of the file.
+    fn read_at(
+        &self,
+        offset: u64,
+        len: usize,
+    ) -> impl Future<Output = FileSystemResult<Vec<u8>>> + Send;
+    /// Write `data` starting at `offset`, returning how many bytes were written.
+    fn write_at(
+        &self,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> impl Future<Output = FileSystemResult<usize>> + Send;
+    /// Flush file contents to storage.
+    fn sync_data(&self) -> impl Future<Output = FileSystemResult<()>> + Send;
+    /// Flush file contents and metadata to storage.
+    fn sync_all(&self) -> impl Future<Output = FileSystemResult<()>> + Send;
+}
+
+/// Run a future to completion on the current thread, parking it while the future is pending.
+///
+/// ```rust
+/// assert_eq!(minql_vfs::block_on(async { 42 }), 42);
+/// ```
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+/// Waker unparking the thread blocked in [`block_on`].
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
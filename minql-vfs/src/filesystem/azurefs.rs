@@ -0,0 +1,438 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::httpstore::{
+    hmac_sha256, query_string, send, uri_encode, xml_elements, xml_text, CivilTime, HttpResponse,
+};
+use crate::filesystem::objectfs::{ObjectListing, ObjectMeta, ObjectStore, PutCondition};
+use crate::{FileSystemError, FileSystemProvider, FileSystemResult, ObjectStoreFileSystem};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use minql_uri::URI;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Azure Blob Storage Object Store Client
+///
+/// Stores objects as block blobs within a single container, authorized with either the storage
+/// account's Shared Key or a shared access signature. Multipart uploads are staged as
+/// uncommitted blocks and committed with a block list; abandoned blocks are discarded by the
+/// service once they expire.
+pub struct AzureObjectStore {
+    endpoint: String,
+    base_path: String,
+    account: String,
+    container: String,
+    credential: AzureCredential,
+    uploads: AtomicU32,
+    agent: ureq::Agent,
+}
+
+/// Credential used to authorize Azure requests.
+enum AzureCredential {
+    Anonymous,
+    SharedKey(Vec<u8>),
+    SasToken(String),
+}
+
+impl AzureObjectStore {
+    /// Service version requested from Blob Storage.
+    const VERSION: &'static str = "2021-08-06";
+
+    /// Create a new Blob Storage client for a container.
+    ///
+    /// `endpoint` is the account's blob endpoint, such as `https://{account}.blob.core.windows.net`,
+    /// or a path-style emulator endpoint like `http://127.0.0.1:10000/{account}`.
+    #[must_use]
+    pub fn new(endpoint: &str, account: &str, container: &str) -> AzureObjectStore {
+        let endpoint = endpoint.trim_end_matches('/');
+        let base_path = endpoint
+            .split_once("://")
+            .map_or(endpoint, |(_, rest)| rest)
+            .split_once('/')
+            .map(|(_, path)| format!("/{path}"))
+            .unwrap_or_default();
+        AzureObjectStore {
+            endpoint: endpoint.to_string(),
+            base_path,
+            account: account.to_string(),
+            container: container.to_string(),
+            credential: AzureCredential::Anonymous,
+            uploads: AtomicU32::new(0),
+            agent: ureq::AgentBuilder::new().build(),
+        }
+    }
+
+    /// Authorize requests with the base64 encoded storage account key.
+    ///
+    /// # Errors
+    /// Returns an error if the key isn't valid base64.
+    pub fn with_access_key(mut self, key: &str) -> FileSystemResult<AzureObjectStore> {
+        let key = STANDARD
+            .decode(key.trim())
+            .map_err(FileSystemError::wrap_error)?;
+        self.credential = AzureCredential::SharedKey(key);
+        Ok(self)
+    }
+
+    /// Authorize requests with a shared access signature.
+    #[must_use]
+    pub fn with_sas_token(mut self, token: &str) -> AzureObjectStore {
+        self.credential = AzureCredential::SasToken(token.trim_start_matches('?').to_string());
+        self
+    }
+
+    /// Authorize and send a request for a blob, or for the container if `key` is empty.
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> FileSystemResult<HttpResponse> {
+        let mut path = format!("{}/{}", self.base_path, uri_encode(&self.container, false));
+        if !key.is_empty() {
+            path.push('/');
+            path.push_str(&uri_encode(key, false));
+        }
+        let mut ms_headers = BTreeMap::new();
+        ms_headers.insert(
+            "x-ms-date".to_string(),
+            CivilTime::new(SystemTime::now()).http_date(),
+        );
+        ms_headers.insert("x-ms-version".to_string(), Self::VERSION.to_string());
+        let mut standard = BTreeMap::new();
+        for (name, value) in headers {
+            let name = name.to_ascii_lowercase();
+            if name.starts_with("x-ms-") {
+                ms_headers.insert(name, value.trim().to_string());
+            } else {
+                standard.insert(name, value.trim().to_string());
+            }
+        }
+
+        let mut url = format!("{}{}", self.endpoint, &path[self.base_path.len()..]);
+        let mut query_text = query_string(query);
+        let mut authorization = None;
+        match &self.credential {
+            AzureCredential::Anonymous => {}
+            AzureCredential::SasToken(token) => {
+                if !query_text.is_empty() {
+                    query_text.push('&');
+                }
+                query_text.push_str(token);
+            }
+            AzureCredential::SharedKey(secret) => {
+                let length = if body.is_empty() {
+                    String::new()
+                } else {
+                    body.len().to_string()
+                };
+                let field = |name: &str| standard.get(name).map_or("", String::as_str);
+                // Verb, then Content-Encoding through Range; only the length and conditional
+                // headers are ever sent.
+                let mut string_to_sign = format!(
+                    "{method}\n\n\n{length}\n\n\n\n\n{}\n{}\n\n\n",
+                    field("if-match"),
+                    field("if-none-match")
+                );
+                for (name, value) in &ms_headers {
+                    let _ = writeln!(string_to_sign, "{name}:{value}");
+                }
+                let _ = write!(string_to_sign, "/{}{path}", self.account);
+                let mut parameters = query
+                    .iter()
+                    .map(|(name, value)| (name.to_ascii_lowercase(), *value))
+                    .collect::<Vec<_>>();
+                parameters.sort_unstable();
+                for (name, value) in parameters {
+                    let _ = write!(string_to_sign, "\n{name}:{value}");
+                }
+                let signature = STANDARD.encode(hmac_sha256(secret, string_to_sign.as_bytes()));
+                authorization = Some(format!("SharedKey {}:{signature}", self.account));
+            }
+        }
+        if !query_text.is_empty() {
+            url.push('?');
+            url.push_str(&query_text);
+        }
+        let headers = ms_headers
+            .iter()
+            .chain(&standard)
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .chain(
+                authorization
+                    .as_deref()
+                    .map(|authorization| ("Authorization", authorization)),
+            );
+        send(&self.agent, method, &url, headers, body)
+    }
+
+    /// Block ids must be base64 and of equal length within a blob.
+    fn block_id(upload_id: &str, part_number: u32) -> String {
+        STANDARD.encode(format!("{upload_id}-{part_number:08}"))
+    }
+}
+
+impl std::fmt::Debug for AzureObjectStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AzureObjectStore({}/{})", self.endpoint, self.container)
+    }
+}
+
+impl ObjectStore for AzureObjectStore {
+    fn head(&self, key: &str) -> FileSystemResult<Option<ObjectMeta>> {
+        match self.request("HEAD", key, &[], &[], &[])?.check("Azure") {
+            Ok(response) => Ok(Some(ObjectMeta {
+                key: key.to_string(),
+                size: response.length().unwrap_or_default(),
+            })),
+            Err(FileSystemError::PathMissing) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn get_range(&self, key: &str, offset: u64, length: u64) -> FileSystemResult<Vec<u8>> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+        let range = format!("bytes={offset}-{}", offset + length - 1);
+        let response = self.request("GET", key, &[], &[("x-ms-range", &range)], &[])?;
+        if response.status == 416 {
+            return Ok(Vec::new());
+        }
+        Ok(response.check("Azure")?.body)
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> FileSystemResult<()> {
+        self.request("PUT", key, &[], &[("x-ms-blob-type", "BlockBlob")], data)?
+            .check("Azure")?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> FileSystemResult<()> {
+        match self.request("DELETE", key, &[], &[], &[])?.check("Azure") {
+            Ok(_) | Err(FileSystemError::PathMissing) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn list(&self, prefix: &str, delimiter: Option<char>) -> FileSystemResult<ObjectListing> {
+        let delimiter = delimiter.map(String::from);
+        let mut listing = ObjectListing::default();
+        let mut marker: Option<String> = None;
+        loop {
+            let mut query = vec![
+                ("restype", "container"),
+                ("comp", "list"),
+                ("prefix", prefix),
+            ];
+            if let Some(delimiter) = &delimiter {
+                query.push(("delimiter", delimiter));
+            }
+            if let Some(marker) = &marker {
+                query.push(("marker", marker));
+            }
+            let response = self.request("GET", "", &query, &[], &[])?.check("Azure")?;
+            let body = response.text();
+            for blob in xml_elements(&body, "Blob") {
+                let key = xml_text(blob, "Name").unwrap_or_default();
+                let size = xml_text(blob, "Content-Length")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or_default();
+                listing.objects.push(ObjectMeta { key, size });
+            }
+            for common in xml_elements(&body, "BlobPrefix") {
+                if let Some(prefix) = xml_text(common, "Name") {
+                    listing.prefixes.push(prefix);
+                }
+            }
+            marker = xml_text(&body, "NextMarker").filter(|marker| !marker.is_empty());
+            if marker.is_none() {
+                return Ok(listing);
+            }
+        }
+    }
+
+    fn create_multipart(&self, _key: &str) -> FileSystemResult<String> {
+        // Blocks are staged against the blob itself, so the id only has to keep concurrent
+        // uploads of the same blob apart.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let sequence = self.uploads.fetch_add(1, Ordering::Relaxed);
+        Ok(format!(
+            "{:016x}{sequence:08x}",
+            nanos & u128::from(u64::MAX)
+        ))
+    }
+
+    fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: &[u8],
+    ) -> FileSystemResult<String> {
+        let block_id = Self::block_id(upload_id, part_number);
+        let query = [("comp", "block"), ("blockid", block_id.as_str())];
+        self.request("PUT", key, &query, &[], data)?
+            .check("Azure")?;
+        Ok(block_id)
+    }
+
+    fn complete_multipart(
+        &self,
+        key: &str,
+        _upload_id: &str,
+        parts: &[(u32, String)],
+    ) -> FileSystemResult<()> {
+        let mut document = String::from(r#"<?xml version="1.0" encoding="utf-8"?><BlockList>"#);
+        for (_, block_id) in parts {
+            let _ = write!(document, "<Latest>{block_id}</Latest>");
+        }
+        document.push_str("</BlockList>");
+        self.request(
+            "PUT",
+            key,
+            &[("comp", "blocklist")],
+            &[],
+            document.as_bytes(),
+        )?
+        .check("Azure")?;
+        Ok(())
+    }
+
+    fn abort_multipart(&self, _key: &str, _upload_id: &str) -> FileSystemResult<()> {
+        // Uncommitted blocks are garbage collected by the service.
+        Ok(())
+    }
+
+    fn get_versioned(&self, key: &str) -> FileSystemResult<Option<(Vec<u8>, String)>> {
+        let response = match self.request("GET", key, &[], &[], &[])?.check("Azure") {
+            Ok(response) => response,
+            Err(FileSystemError::PathMissing) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let etag = response
+            .header("etag")
+            .map(ToString::to_string)
+            .ok_or_else(|| FileSystemError::internal_error("Azure response missing ETag"))?;
+        Ok(Some((response.body, etag)))
+    }
+
+    fn put_if(
+        &self,
+        key: &str,
+        data: &[u8],
+        condition: &PutCondition,
+    ) -> FileSystemResult<Option<String>> {
+        let (name, value) = match condition {
+            PutCondition::Absent => ("if-none-match", "*"),
+            PutCondition::Matches(etag) => ("if-match", etag.as_str()),
+        };
+        let headers = [("x-ms-blob-type", "BlockBlob"), (name, value)];
+        let response = self.request("PUT", key, &[], &headers, data)?;
+        if matches!(response.status, 409 | 412) {
+            return Ok(None);
+        }
+        let response = response.check("Azure")?;
+        Ok(response.header("etag").map(ToString::to_string))
+    }
+}
+
+/// Azure Blob Storage `FileSystem` Provider
+///
+/// Provisions an [`ObjectStoreFileSystem`] for `az://container/prefix` URIs. Recognized
+/// configuration keys are `account`, `access_key`, `sas_token`, and `endpoint`; they fall back
+/// to the `AZURE_STORAGE_ACCOUNT`, `AZURE_STORAGE_KEY`, `AZURE_STORAGE_SAS_TOKEN`, and
+/// `AZURE_STORAGE_ENDPOINT` environment variables.
+#[derive(Debug, Default)]
+pub struct AzureFileSystemProvider {
+    configuration: RwLock<HashMap<String, String>>,
+}
+
+impl AzureFileSystemProvider {
+    fn setting(&self, key: &str, env: &str) -> Option<String> {
+        let configuration = self.configuration.read().expect("Poisoned Lock");
+        configuration
+            .get(key)
+            .cloned()
+            .or_else(|| std::env::var(env).ok())
+    }
+}
+
+impl FileSystemProvider for AzureFileSystemProvider {
+    type FileSystem = ObjectStoreFileSystem;
+
+    fn schemes(&self) -> &[&str] {
+        &["az"]
+    }
+
+    fn configure(&self, configuration: &HashMap<String, String>) -> FileSystemResult<()> {
+        let mut current = self.configuration.write().expect("Poisoned Lock");
+        current.extend(configuration.clone());
+        Ok(())
+    }
+
+    fn provision(&self, url: &str) -> FileSystemResult<ObjectStoreFileSystem> {
+        let uri = URI::parse(url)?;
+        let container = uri
+            .authority
+            .as_ref()
+            .map(|authority| authority.hostinfo.raw())
+            .ok_or_else(|| FileSystemError::invalid_path(url))?;
+        let account = self
+            .setting("account", "AZURE_STORAGE_ACCOUNT")
+            .ok_or_else(|| FileSystemError::internal_error("Azure storage account not set"))?;
+        let endpoint = self
+            .setting("endpoint", "AZURE_STORAGE_ENDPOINT")
+            .unwrap_or_else(|| format!("https://{account}.blob.core.windows.net"));
+        let mut store = AzureObjectStore::new(&endpoint, &account, &container);
+        if let Some(key) = self.setting("access_key", "AZURE_STORAGE_KEY") {
+            store = store.with_access_key(&key)?;
+        } else if let Some(token) = self.setting("sas_token", "AZURE_STORAGE_SAS_TOKEN") {
+            store = store.with_sas_token(&token);
+        }
+        Ok(ObjectStoreFileSystem::new(store, &uri.path.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AzureObjectStore;
+
+    #[test]
+    fn test_azure_block_ids() {
+        let store = AzureObjectStore::new("http://127.0.0.1:10000/devstoreaccount1", "a", "c");
+        assert_eq!(store.base_path, "/devstoreaccount1");
+        let upload_id = super::ObjectStore::create_multipart(&store, "key").unwrap();
+        let first = AzureObjectStore::block_id(&upload_id, 1);
+        let last = AzureObjectStore::block_id(&upload_id, 10_000);
+        assert_eq!(first.len(), last.len());
+        assert_ne!(first, last);
+        assert!(
+            AzureObjectStore::new("https://a.blob.core.windows.net", "a", "c")
+                .base_path
+                .is_empty()
+        );
+    }
+}
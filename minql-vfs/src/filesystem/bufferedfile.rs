@@ -0,0 +1,439 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{Advice, FileHandle, FileLockMode, FileSystemError, FileSystemResult};
+use std::io::{BufRead, IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+
+/// Default capacity of each buffer of a [`BufferedFileHandle`].
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+/// Buffered File Handle
+///
+/// Wraps any [`FileHandle`], including a `Box<dyn FileHandle>`, with a read buffer and a write
+/// buffer so that small sequential reads and writes through the cursor become a few large
+/// calls into the backend. It implements [`FileHandle`] and [`BufRead`] itself, so it can be
+/// passed anywhere a handle is expected.
+///
+/// Sequential writes are collected until the buffer fills, the cursor moves away from the end
+/// of the pending run, or any other operation needs the file to be current. Positional reads and
+/// writes through [`FileHandle`] flush pending writes and bypass the buffers. Buffered writes are
+/// flushed when the handle is dropped, but errors are only reported by an explicit
+/// [`Write::flush`] or `sync_*`.
+///
+/// ```rust
+/// use minql_vfs::{BufferedFileHandle, FileSystem, MemoryFileSystem};
+/// use std::io::{BufRead, Seek, SeekFrom, Write};
+///
+/// let fs = MemoryFileSystem::new();
+/// let mut file = BufferedFileHandle::new(fs.create_file("/lines.txt").unwrap()).unwrap();
+/// writeln!(file, "first").unwrap();
+/// writeln!(file, "second").unwrap();
+/// file.seek(SeekFrom::Start(0)).unwrap();
+/// let lines = file.lines().collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(lines, vec!["first", "second"]);
+/// ```
+pub struct BufferedFileHandle<H: FileHandle> {
+    inner: H,
+    capacity: usize,
+    cursor: u64,
+    /// Bytes read ahead from the file starting at `read_start`.
+    read_buffer: Vec<u8>,
+    read_start: u64,
+    /// Bytes waiting to be written to the file starting at `write_start`.
+    write_buffer: Vec<u8>,
+    write_start: u64,
+}
+
+impl<H: FileHandle> BufferedFileHandle<H> {
+    /// Wrap `inner` with buffers of the default capacity, starting at its current position.
+    pub fn new(inner: H) -> FileSystemResult<BufferedFileHandle<H>> {
+        BufferedFileHandle::with_capacity(DEFAULT_CAPACITY, inner)
+    }
+
+    /// Wrap `inner` with buffers of `capacity` bytes each, starting at its current position.
+    pub fn with_capacity(capacity: usize, mut inner: H) -> FileSystemResult<BufferedFileHandle<H>> {
+        let cursor = inner.stream_position().map_err(FileSystemError::io_error)?;
+        Ok(BufferedFileHandle {
+            inner,
+            capacity: capacity.max(1),
+            cursor,
+            read_buffer: Vec::new(),
+            read_start: 0,
+            write_buffer: Vec::new(),
+            write_start: 0,
+        })
+    }
+
+    /// Capacity of each buffer in bytes.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Reference to the wrapped handle, which doesn't reflect pending writes.
+    #[must_use]
+    pub fn get_ref(&self) -> &H {
+        &self.inner
+    }
+
+    /// Bytes read ahead and not yet consumed.
+    #[must_use]
+    pub fn buffer(&self) -> &[u8] {
+        let start = self.cursor.saturating_sub(self.read_start);
+        match usize::try_from(start) {
+            Ok(start) if self.cursor >= self.read_start && start <= self.read_buffer.len() => {
+                &self.read_buffer[start..]
+            }
+            _ => &[],
+        }
+    }
+
+    /// Write pending bytes to the wrapped handle.
+    fn flush_writes(&mut self) -> FileSystemResult<()> {
+        let mut written = 0;
+        while written < self.write_buffer.len() {
+            let offset = self.write_start + written as u64;
+            match self
+                .inner
+                .write_to_offset(offset, &self.write_buffer[written..])
+            {
+                Ok(0) => {
+                    self.write_buffer.drain(..written);
+                    self.write_start += written as u64;
+                    return Err(FileSystemError::InvalidOperation);
+                }
+                Ok(count) => written += count,
+                Err(err) => {
+                    self.write_buffer.drain(..written);
+                    self.write_start += written as u64;
+                    return Err(err);
+                }
+            }
+        }
+        self.write_buffer.clear();
+        Ok(())
+    }
+
+    /// Discard read-ahead bytes overlapping `len` bytes at `offset`.
+    fn invalidate(&mut self, offset: u64, len: u64) {
+        let end = self.read_start + self.read_buffer.len() as u64;
+        if offset < end && self.read_start < offset.saturating_add(len) {
+            self.read_buffer.clear();
+        }
+    }
+}
+
+impl<H: FileHandle> std::fmt::Debug for BufferedFileHandle<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferedFileHandle")
+            .field("inner", &self.inner)
+            .field("capacity", &self.capacity)
+            .field("cursor", &self.cursor)
+            .field("read_buffered", &self.read_buffer.len())
+            .field("write_buffered", &self.write_buffer.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<H: FileHandle> Read for BufferedFileHandle<H> {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.buffer().is_empty() && buf.len() >= self.capacity {
+            // Large reads skip the buffer entirely.
+            let read = self.read_at_offset(self.cursor, buf)?;
+            self.cursor += read as u64;
+            return Ok(read);
+        }
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<H: FileHandle> BufRead for BufferedFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.buffer().is_empty() {
+            self.flush_writes()?;
+            self.read_buffer.resize(self.capacity, 0);
+            let read = self
+                .inner
+                .read_at_offset(self.cursor, &mut self.read_buffer);
+            let read = read.inspect_err(|_| self.read_buffer.clear())?;
+            self.read_buffer.truncate(read);
+            self.read_start = self.cursor;
+        }
+        Ok(self.buffer())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn consume(&mut self, amt: usize) {
+        self.cursor += amt.min(self.buffer().len()) as u64;
+    }
+}
+
+impl<H: FileHandle> Write for BufferedFileHandle<H> {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !self.write_buffer.is_empty()
+            && (self.write_start + self.write_buffer.len() as u64 != self.cursor
+                || self.write_buffer.len() + buf.len() > self.capacity)
+        {
+            self.flush_writes()?;
+        }
+        self.invalidate(self.cursor, buf.len() as u64);
+        if buf.len() >= self.capacity {
+            // Large writes skip the buffer entirely.
+            let written = self.inner.write_to_offset(self.cursor, buf)?;
+            self.cursor += written as u64;
+            return Ok(written);
+        }
+        if self.write_buffer.is_empty() {
+            self.write_start = self.cursor;
+        }
+        self.write_buffer.extend_from_slice(buf);
+        self.cursor += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.flush_writes()?;
+        self.inner.flush()
+    }
+}
+
+impl<H: FileHandle> Seek for BufferedFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let cursor = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.get_size()?.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.cursor.checked_add_signed(offset),
+        };
+        self.cursor = cursor.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Seek before start of file",
+            )
+        })?;
+        Ok(self.cursor)
+    }
+}
+
+impl<H: FileHandle> FileHandle for BufferedFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        self.inner.path()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        let size = self.inner.get_size()?;
+        if self.write_buffer.is_empty() {
+            return Ok(size);
+        }
+        Ok(size.max(self.write_start + self.write_buffer.len() as u64))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.flush_writes()?;
+        self.read_buffer.clear();
+        self.inner.set_size(new_size)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.flush_writes()?;
+        self.inner.sync_all()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.flush_writes()?;
+        self.inner.sync_data()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        self.inner.get_lock_status()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.flush_writes()?;
+        self.read_buffer.clear();
+        self.inner.set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.flush_writes()?;
+        self.read_buffer.clear();
+        self.inner.try_set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        self.flush_writes()?;
+        self.inner.read_at_offset(offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.flush_writes()?;
+        self.invalidate(offset, buffer.len() as u64);
+        self.inner.write_to_offset(offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.flush_writes()?;
+        self.read_buffer.clear();
+        self.inner.lock_range(offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.flush_writes()?;
+        self.inner.unlock_range(offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        self.inner.advise(offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        self.flush_writes()?;
+        self.inner.read_at_vectored(offset, buffers)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        self.flush_writes()?;
+        let len = buffers.iter().map(|buffer| buffer.len() as u64).sum();
+        self.invalidate(offset, len);
+        self.inner.write_at_vectored(offset, buffers)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        self.flush_writes()?;
+        self.inner.map_readonly(offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        self.inner.alignment()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.flush_writes()?;
+        self.inner.allocate(len)
+    }
+}
+
+impl<H: FileHandle> Drop for BufferedFileHandle<H> {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush_writes() {
+            tracing::warn!(?err, "Failed to flush buffered writes on drop");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BufferedFileHandle, FileHandle, FileSystem, MemoryFileSystem};
+    use std::io::{BufRead, Read, Seek, SeekFrom, Write};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_buffered_file_handle() {
+        let fs = MemoryFileSystem::new();
+        let handle: Box<dyn FileHandle> = Box::new(fs.create_file("/test.txt").unwrap());
+        let mut file = BufferedFileHandle::with_capacity(16, handle).unwrap();
+
+        for byte in b"abcdefghij" {
+            file.write_all(&[*byte]).unwrap();
+        }
+        assert_eq!(fs.filesize("/test.txt").unwrap(), 0);
+        assert_eq!(file.get_size().unwrap(), 10);
+        file.write_all(b"klmnopq").unwrap();
+        assert_eq!(fs.filesize("/test.txt").unwrap(), 10);
+
+        // Reads see pending writes and are served from the read-ahead buffer
+        file.seek(SeekFrom::Start(2)).unwrap();
+        let mut byte = [0; 1];
+        file.read_exact(&mut byte).unwrap();
+        assert_eq!(&byte, b"c");
+        assert_eq!(file.buffer(), b"defghijklmnopq");
+
+        // Overwriting invalidates the read-ahead
+        file.write_to_offset(3, b"D").unwrap();
+        let mut rest = String::new();
+        file.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "Defghijklmnopq");
+
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(b"AB").unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(b"\nend\n").unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let lines = file.lines().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(lines, vec!["ABcDefghijklmnopq", "end"]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_buffered_file_handle_flush_on_drop() {
+        let fs = MemoryFileSystem::new();
+        let mut file = BufferedFileHandle::new(fs.create_file("/test.txt").unwrap()).unwrap();
+        file.write_all(b"pending").unwrap();
+        file.sync_data().unwrap();
+        assert_eq!(fs.filesize("/test.txt").unwrap(), 7);
+        file.write_all(b" and more").unwrap();
+        drop(file);
+        assert_eq!(fs.filesize("/test.txt").unwrap(), 16);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_buffered_file_handle_shrink() {
+        let fs = MemoryFileSystem::new();
+        let mut file = BufferedFileHandle::new(fs.create_file("/test.txt").unwrap()).unwrap();
+        file.seek(SeekFrom::Start(1000)).unwrap();
+        file.write_all(b"x").unwrap();
+        file.flush().unwrap();
+        file.set_size(10).unwrap();
+        assert_eq!(file.get_size().unwrap(), 10);
+        assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), 10);
+    }
+}
@@ -0,0 +1,876 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::utility::normalize_path;
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::collections::HashMap;
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Size of the chunks files are copied into the cache in.
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Bytes read ahead at a time by uncached handles advised of sequential access.
+const READAHEAD_SIZE: usize = 1024 * 1024;
+
+/// Most bytes fetched ahead of time for a single [`Advice::WillNeed`].
+const MAX_PREFETCH_SIZE: usize = 16 * 1024 * 1024;
+
+/// Hit and miss counts of a [`CachingFileSystem`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    /// Opens served from the fast filesystem
+    pub hits: u64,
+    /// Opens which fetched the file from the slow filesystem
+    pub misses: u64,
+    /// Files evicted to stay within the capacity
+    pub evictions: u64,
+    /// Bytes currently cached
+    pub used: u64,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    size: u64,
+    fetched: Instant,
+    used: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+/// Read-Through Caching `FileSystem` Wrapper
+///
+/// Serves files opened for reading from a fast filesystem, such as a [`MemoryFileSystem`] or
+/// local disk, copying each from the slow filesystem the first time it is opened. Cached files
+/// are evicted least recently used first to keep the cache within its capacity, and are
+/// refetched once older than the optional time to live.
+///
+/// The slow filesystem remains authoritative. Metadata and directory operations go straight to
+/// it, and files opened for writing bypass the cache, invalidating any cached copy. Handles
+/// served from the cache are read-only.
+///
+/// [`MemoryFileSystem`]: crate::MemoryFileSystem
+///
+/// ```rust
+/// use minql_vfs::{CachingFileSystem, FileSystem, MemoryFileSystem};
+/// use std::io::{Read, Write};
+///
+/// let remote = MemoryFileSystem::new();
+/// remote.create_file("/table.dat").unwrap().write_all(b"rows").unwrap();
+///
+/// let fs = CachingFileSystem::new(remote, MemoryFileSystem::new(), 1024 * 1024);
+/// for _ in 0..2 {
+///     let mut contents = String::new();
+///     fs.open_file("/table.dat").unwrap().read_to_string(&mut contents).unwrap();
+///     assert_eq!(contents, "rows");
+/// }
+/// assert_eq!((fs.stats().misses, fs.stats().hits), (1, 1));
+/// ```
+#[derive(Debug)]
+pub struct CachingFileSystem<S: FileSystem, F: FileSystem> {
+    slow: S,
+    fast: F,
+    capacity: u64,
+    ttl: Option<Duration>,
+    state: Mutex<CacheState>,
+}
+
+impl<S: FileSystem, F: FileSystem> CachingFileSystem<S, F> {
+    /// Create a new Caching `FileSystem` keeping up to `capacity` bytes of `slow` in `fast`.
+    pub fn new(slow: S, fast: F, capacity: u64) -> CachingFileSystem<S, F> {
+        CachingFileSystem {
+            slow,
+            fast,
+            capacity,
+            ttl: None,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Refetch cached files once they are older than `ttl`.
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> CachingFileSystem<S, F> {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Hit and miss counts so far.
+    #[must_use]
+    pub fn stats(&self) -> CacheStats {
+        self.state.lock().expect("Poisoned Lock").stats
+    }
+
+    /// Borrow the slow, authoritative filesystem.
+    pub fn slow(&self) -> &S {
+        &self.slow
+    }
+
+    /// Borrow the fast, caching filesystem.
+    pub fn fast(&self) -> &F {
+        &self.fast
+    }
+
+    /// Drop the cached copy of a file, if any.
+    pub fn invalidate(&self, path: &str) -> FileSystemResult<()> {
+        let path = normalize_path(path)?;
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        self.evict(&mut state, &path)
+    }
+
+    /// Open a cached copy of a file, fetching it from the slow filesystem if needed.
+    fn open_cached(&self, path: &str) -> FileSystemResult<CachingFileHandle> {
+        let path = normalize_path(path)?;
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        state.tick += 1;
+        let tick = state.tick;
+        let fresh = state
+            .entries
+            .get(&path)
+            .is_some_and(|entry| self.ttl.is_none_or(|ttl| entry.fetched.elapsed() < ttl));
+        if fresh {
+            state.stats.hits += 1;
+            state.entries.get_mut(&path).expect("Cache Entry").used = tick;
+            return Ok(CachingFileHandle::cached(self.fast.open_file(&path)?));
+        }
+        state.stats.misses += 1;
+        self.evict(&mut state, &path)?;
+
+        let size = self.slow.filesize(&path)?;
+        if size > self.capacity {
+            return Ok(CachingFileHandle::uncached(self.slow.open_file(&path)?));
+        }
+        while state.stats.used + size > self.capacity {
+            let victim = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(path, _)| path.clone())
+                .expect("Cached Entries");
+            self.evict(&mut state, &victim)?;
+            state.stats.evictions += 1;
+        }
+
+        tracing::trace!(path, size, "Fetching file into cache");
+        if let Some(parent) = path.rfind('/').filter(|index| *index > 0) {
+            self.fast.create_directory_all(&path[..parent])?;
+        }
+        let mut source = self.slow.open_file(&path)?;
+        let mut target = self.fast.create_file(&path)?;
+        let mut buffer = vec![0; COPY_BUFFER_SIZE];
+        let mut size = 0;
+        loop {
+            let read = source
+                .read(&mut buffer)
+                .map_err(FileSystemError::io_error)?;
+            if read == 0 {
+                break;
+            }
+            target
+                .write_all(&buffer[..read])
+                .map_err(FileSystemError::io_error)?;
+            size += read as u64;
+        }
+        target
+            .seek(SeekFrom::Start(0))
+            .map_err(FileSystemError::io_error)?;
+        state.stats.used += size;
+        state.entries.insert(
+            path,
+            CacheEntry {
+                size,
+                fetched: Instant::now(),
+                used: tick,
+            },
+        );
+        Ok(CachingFileHandle::cached(target))
+    }
+
+    fn evict(&self, state: &mut CacheState, path: &str) -> FileSystemResult<()> {
+        if let Some(entry) = state.entries.remove(path) {
+            state.stats.used -= entry.size;
+            if self.fast.exists(path)? {
+                self.fast.remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the cached copy of `from` to `to`, dropping it if it can't be moved.
+    fn move_entry(&self, state: &mut CacheState, from: &str, to: &str) -> FileSystemResult<()> {
+        let Some(entry) = state.entries.remove(from) else {
+            return Ok(());
+        };
+        let moved = match to.rfind('/').filter(|index| *index > 0) {
+            Some(parent) => self.fast.create_directory_all(&to[..parent]),
+            None => Ok(()),
+        }
+        .and_then(|()| self.fast.rename(from, to));
+        if let Err(err) = moved {
+            tracing::debug!(?err, from, to, "Dropping cached file instead of moving it");
+            state.stats.used -= entry.size;
+            if self.fast.exists(from)? {
+                self.fast.remove_file(from)?;
+            }
+        } else {
+            state.entries.insert(to.to_string(), entry);
+        }
+        Ok(())
+    }
+
+    fn evict_prefix(&self, prefix: &str) -> FileSystemResult<()> {
+        let prefix = format!("{}/", normalize_path(prefix)?.trim_end_matches('/'));
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        let paths = state
+            .entries
+            .keys()
+            .filter(|path| path.starts_with(&prefix))
+            .cloned()
+            .collect::<Vec<_>>();
+        for path in paths {
+            self.evict(&mut state, &path)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: FileSystem, F: FileSystem> FileSystem for CachingFileSystem<S, F> {
+    type FileHandle = CachingFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        self.slow.exists(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        self.slow.is_file(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        self.slow.is_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.slow.filesize(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.slow.create_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.slow.create_directory_all(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        self.slow.list_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.slow.remove_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.evict_prefix(path)?;
+        self.slow.remove_directory_all(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.invalidate(path)?;
+        Ok(CachingFileHandle::direct(self.slow.create_file(path)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.open_cached(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        self.invalidate(path)?;
+        self.slow.remove_file(path)
+    }
+
+    /// A cached copy of `from` stays cached under `to`.
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        let (from_path, to_path) = (normalize_path(from)?, normalize_path(to)?);
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        self.slow.rename(from, to)?;
+        self.evict(&mut state, &to_path)?;
+        self.move_entry(&mut state, &from_path, &to_path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        self.invalidate(dst)?;
+        self.slow.clone_file(src, dst)
+    }
+
+    /// Drops the cached copy of every file the batch touches before forwarding it, failing
+    /// the operations on a file whose copy couldn't be dropped without applying them.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let mut invalidated = Vec::with_capacity(batch.len());
+        let mut results: Vec<Option<FileSystemResult<()>>> = Vec::with_capacity(batch.len());
+        for operation in batch {
+            match operation.paths().try_for_each(|path| self.invalidate(path)) {
+                Ok(()) => {
+                    invalidated.push(operation.clone());
+                    results.push(None);
+                }
+                Err(error) => results.push(Some(Err(error))),
+            }
+        }
+        let mut applied = self.slow.apply(&invalidated).into_iter();
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| applied.next().expect("Missing Batch Result")))
+            .collect()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.slow.file_type(path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        self.invalidate(path)?;
+        self.slow.create_symlink(target, path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.slow.read_link(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.slow.permissions(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.slow.modified(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.slow.set_permissions(path, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        let writes = options.is_write()
+            || options.is_append()
+            || options.is_truncate()
+            || options.is_create()
+            || options.is_create_new();
+        if writes {
+            self.invalidate(path)?;
+            Ok(CachingFileHandle::direct(
+                self.slow.open_with(path, options)?,
+            ))
+        } else {
+            self.open_cached(path)
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        self.slow.space()
+    }
+}
+
+/// Caching File Handle
+///
+/// Either a read-only handle on a cached copy, or a handle opened directly on the slow
+/// filesystem for writing.
+///
+/// Files too large to cache are read straight from the slow filesystem through a read-only
+/// handle, which uses [`FileHandle::advise`] to decide what to prefetch. Once advised of
+/// [`Advice::Sequential`] access it reads ahead in large chunks, and [`Advice::WillNeed`]
+/// fetches the advised range ahead of time.
+pub struct CachingFileHandle {
+    cached: bool,
+    inner: Box<dyn FileHandle>,
+    readahead: Option<Readahead>,
+}
+
+/// Data prefetched from the slow filesystem by an uncached handle.
+#[derive(Debug, Default)]
+struct Readahead {
+    advice: Advice,
+    offset: u64,
+    data: Vec<u8>,
+}
+
+impl Readahead {
+    /// Replace the prefetched data with up to `len` bytes starting at `offset`.
+    fn fill(
+        &mut self,
+        inner: &mut dyn FileHandle,
+        offset: u64,
+        len: usize,
+    ) -> FileSystemResult<()> {
+        self.offset = offset;
+        self.data.resize(len, 0);
+        let mut filled = 0;
+        while filled < len {
+            match inner.read_at_offset(offset + filled as u64, &mut self.data[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(err) => {
+                    self.data.clear();
+                    return Err(err);
+                }
+            }
+        }
+        self.data.truncate(filled);
+        Ok(())
+    }
+
+    /// Check if the prefetched data contains `offset`.
+    fn contains(&self, offset: u64) -> bool {
+        offset >= self.offset && offset - self.offset < self.data.len() as u64
+    }
+}
+
+impl CachingFileHandle {
+    fn cached<H: FileHandle>(inner: H) -> CachingFileHandle {
+        CachingFileHandle {
+            cached: true,
+            inner: Box::new(inner),
+            readahead: None,
+        }
+    }
+
+    fn uncached<H: FileHandle>(inner: H) -> CachingFileHandle {
+        CachingFileHandle {
+            cached: true,
+            inner: Box::new(inner),
+            readahead: Some(Readahead::default()),
+        }
+    }
+
+    fn direct<H: FileHandle>(inner: H) -> CachingFileHandle {
+        CachingFileHandle {
+            cached: false,
+            inner: Box::new(inner),
+            readahead: None,
+        }
+    }
+
+    /// Serve a read at `offset` from prefetched data, reading ahead first under sequential
+    /// access. Returns `None` when the read should go to the inner handle instead.
+    fn read_ahead(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<Option<usize>> {
+        let Some(readahead) = self.readahead.as_mut() else {
+            return Ok(None);
+        };
+        if !readahead.contains(offset) {
+            if readahead.advice != Advice::Sequential {
+                return Ok(None);
+            }
+            let len = READAHEAD_SIZE.max(buffer.len());
+            readahead.fill(self.inner.as_mut(), offset, len)?;
+        }
+        let start = usize::try_from(offset - readahead.offset).unwrap_or(usize::MAX);
+        let available = readahead.data.get(start..).unwrap_or_default();
+        let read = available.len().min(buffer.len());
+        buffer[..read].copy_from_slice(&available[..read]);
+        Ok(Some(read))
+    }
+
+    /// Check if this handle is read-only, reading from the cache or a file too large to cache.
+    #[must_use]
+    pub fn is_cached(&self) -> bool {
+        self.cached
+    }
+
+    /// Fail if this handle reads from the cache.
+    fn writable(&self) -> FileSystemResult<()> {
+        if self.cached {
+            Err(FileSystemError::PermissionDenied)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl std::fmt::Debug for CachingFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.inner.as_ref(), f)
+    }
+}
+
+impl Read for CachingFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.readahead.is_some() {
+            let position = Seek::stream_position(self.inner.as_mut())?;
+            if let Some(read) = self.read_ahead(position, buf)? {
+                Seek::seek(self.inner.as_mut(), SeekFrom::Start(position + read as u64))?;
+                return Ok(read);
+            }
+        }
+        Read::read(self.inner.as_mut(), buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        Read::read_vectored(self.inner.as_mut(), bufs)
+    }
+}
+
+impl Write for CachingFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writable()?;
+        Write::write(self.inner.as_mut(), buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        self.writable()?;
+        Write::write_vectored(self.inner.as_mut(), bufs)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Write::flush(self.inner.as_mut())
+    }
+}
+
+impl Seek for CachingFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        Seek::seek(self.inner.as_mut(), pos)
+    }
+}
+
+impl FileHandle for CachingFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        FileHandle::path(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        FileHandle::get_size(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.writable()?;
+        FileHandle::set_size(self.inner.as_mut(), new_size)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        FileHandle::sync_all(self.inner.as_mut())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        FileHandle::sync_data(self.inner.as_mut())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        FileHandle::get_lock_status(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::try_set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        match self.read_ahead(offset, buffer)? {
+            Some(read) => Ok(read),
+            None => FileHandle::read_at_offset(self.inner.as_mut(), offset, buffer),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.writable()?;
+        FileHandle::write_to_offset(self.inner.as_mut(), offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::lock_range(self.inner.as_mut(), offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        FileHandle::unlock_range(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        FileHandle::advise(self.inner.as_mut(), offset, len, advice)?;
+        let Some(readahead) = self.readahead.as_mut() else {
+            return Ok(());
+        };
+        match advice {
+            Advice::Normal | Advice::Sequential | Advice::Random => {
+                readahead.advice = advice;
+                if advice != Advice::Sequential {
+                    readahead.data = Vec::new();
+                }
+            }
+            Advice::WillNeed => {
+                let len = match usize::try_from(len) {
+                    Ok(0) | Err(_) => MAX_PREFETCH_SIZE,
+                    Ok(len) => len.min(MAX_PREFETCH_SIZE),
+                };
+                readahead.fill(self.inner.as_mut(), offset, len)?;
+            }
+            Advice::DontNeed => {
+                let end = offset.checked_add(len).filter(|_| len > 0);
+                let prefetched = readahead.offset + readahead.data.len() as u64;
+                if offset < prefetched && end.is_none_or(|end| end > readahead.offset) {
+                    readahead.data = Vec::new();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        FileHandle::read_at_vectored(self.inner.as_mut(), offset, buffers)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        self.writable()?;
+        FileHandle::write_at_vectored(self.inner.as_mut(), offset, buffers)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        FileHandle::map_readonly(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        FileHandle::alignment(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.writable()?;
+        FileHandle::allocate(self.inner.as_mut(), len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Advice, CachingFileSystem, FileHandle, FileSystem, MemoryFileSystem, OpenOptions};
+    use std::io::{Read, Write};
+    use std::time::Duration;
+
+    fn read(fs: &impl FileSystem, path: &str) -> Vec<u8> {
+        let mut contents = Vec::new();
+        fs.open_file(path)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_caching_filesystem() {
+        let slow = MemoryFileSystem::new();
+        slow.create_directory("/data").unwrap();
+        for name in ["a", "b", "c"] {
+            let mut file = slow.create_file(&format!("/data/{name}")).unwrap();
+            file.write_all(&[name.as_bytes()[0]; 40]).unwrap();
+        }
+        let fast = MemoryFileSystem::new();
+        let fs = CachingFileSystem::new(slow.clone(), fast.clone(), 100);
+
+        assert_eq!(read(&fs, "/data/a"), vec![b'a'; 40]);
+        assert_eq!(read(&fs, "/data/b"), vec![b'b'; 40]);
+        assert_eq!(read(&fs, "/data/a"), vec![b'a'; 40]);
+        assert!(fast.exists("/data/b").unwrap());
+
+        // Fetching a third file evicts the least recently used
+        assert_eq!(read(&fs, "/data/c"), vec![b'c'; 40]);
+        assert!(!fast.exists("/data/b").unwrap());
+        let stats = fs.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 3, 1));
+        assert_eq!(stats.used, 80);
+
+        // Cached handles are read-only, and writes invalidate the cache
+        assert!(fs.open_file("/data/a").unwrap().write_all(b"x").is_err());
+        let options = OpenOptions::new().write(true).truncate(true);
+        fs.open_with("/data/a", options)
+            .unwrap()
+            .write_all(b"rewritten")
+            .unwrap();
+        assert!(!fast.exists("/data/a").unwrap());
+        assert_eq!(read(&fs, "/data/a"), b"rewritten");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_caching_filesystem_rename() {
+        let slow = MemoryFileSystem::new();
+        slow.write("/segment.tmp", b"new").unwrap();
+        slow.write("/segment.dat", b"old").unwrap();
+        let fast = MemoryFileSystem::new();
+        let fs = CachingFileSystem::new(slow.clone(), fast.clone(), 100);
+        assert_eq!(read(&fs, "/segment.tmp"), b"new");
+        assert_eq!(read(&fs, "/segment.dat"), b"old");
+
+        // The cached copy moves with the file, replacing the copy of the file it replaced
+        fs.rename("/segment.tmp", "/segment.dat").unwrap();
+        assert!(!fast.exists("/segment.tmp").unwrap());
+        assert_eq!(read(&fs, "/segment.dat"), b"new");
+        let stats = fs.stats();
+        assert_eq!((stats.hits, stats.misses, stats.used), (1, 2, 3));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_caching_filesystem_batch() {
+        use crate::BatchOperation;
+
+        let slow = MemoryFileSystem::new();
+        slow.write("/a", b"a").unwrap();
+        slow.write("/b", b"b").unwrap();
+        let fs = CachingFileSystem::new(slow.clone(), MemoryFileSystem::new(), 100);
+        assert_eq!(read(&fs, "/a"), b"a");
+        assert_eq!(read(&fs, "/b"), b"b");
+
+        let results = fs.apply(&[
+            BatchOperation::write("/a", b"A"),
+            BatchOperation::rename("/a", "/b"),
+        ]);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(fs.stats().used, 0);
+        assert_eq!(read(&fs, "/b"), b"A");
+        fs.clone_file("/b", "/c").unwrap();
+        assert_eq!(read(&fs, "/c"), b"A");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_caching_filesystem_ttl() {
+        let slow = MemoryFileSystem::new();
+        slow.create_file("/file").unwrap().write_all(b"v1").unwrap();
+        let fs = CachingFileSystem::new(slow.clone(), MemoryFileSystem::new(), 100)
+            .with_ttl(Duration::from_millis(50));
+        assert_eq!(read(&fs, "/file"), b"v1");
+
+        slow.open_file("/file")
+            .unwrap()
+            .write_to_offset(0, b"v2")
+            .unwrap();
+        assert_eq!(read(&fs, "/file"), b"v1");
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(read(&fs, "/file"), b"v2");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_caching_filesystem_readahead() {
+        let slow = MemoryFileSystem::new();
+        let data = (0..200u8).collect::<Vec<u8>>();
+        slow.create_file("/large")
+            .unwrap()
+            .write_all(&data)
+            .unwrap();
+        let fs = CachingFileSystem::new(slow.clone(), MemoryFileSystem::new(), 100);
+
+        // Files too large to cache prefetch once advised of sequential access
+        let mut handle = fs.open_file("/large").unwrap();
+        assert!(handle.is_cached());
+        handle.advise(0, 0, Advice::Sequential).unwrap();
+        let mut buffer = [0; 50];
+        handle.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer[..], data[..50]);
+        slow.open_file("/large")
+            .unwrap()
+            .write_to_offset(50, &[0; 150])
+            .unwrap();
+        handle.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer[..], data[50..100]);
+
+        // Random access drops the prefetched data, while will need fetches a range early
+        handle.advise(0, 0, Advice::Random).unwrap();
+        assert_eq!(handle.read_at_offset(100, &mut buffer).unwrap(), 50);
+        assert_eq!(buffer, [0; 50]);
+        handle.advise(150, 50, Advice::WillNeed).unwrap();
+        slow.open_file("/large")
+            .unwrap()
+            .write_to_offset(150, &data[150..])
+            .unwrap();
+        assert_eq!(handle.read_at_offset(150, &mut buffer).unwrap(), 50);
+        assert_eq!(buffer, [0; 50]);
+        handle.advise(150, 50, Advice::DontNeed).unwrap();
+        assert_eq!(handle.read_at_offset(150, &mut buffer).unwrap(), 50);
+        assert_eq!(buffer[..], data[150..]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_caching_conformance() {
+        // Cached handles are read-only, so only the checks that don't write through
+        // `open_file` apply
+        let fs = CachingFileSystem::new(MemoryFileSystem::new(), MemoryFileSystem::new(), 1024);
+        crate::conformance::check_directories(&fs);
+        crate::conformance::check_rename_and_batch(&fs);
+    }
+}
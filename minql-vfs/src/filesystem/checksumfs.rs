@@ -0,0 +1,642 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::utility::apply_operation;
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Suffix of the sidecar file holding the checksums of each data file.
+const CHECKSUM_SUFFIX: &str = ".crc";
+
+/// Size of each stored checksum.
+const CHECKSUM_SIZE: u64 = 4;
+
+/// Checksumming `FileSystem` Wrapper
+///
+/// Maintains a CRC32 for every block of each file in a sidecar file next to it, and verifies the
+/// blocks touched by every read. A mismatch fails with [`FileSystemError::CorruptData`] naming
+/// the file and the offset of the corrupt block. Sidecar files are hidden from directory
+/// listings, and are rebuilt from the current contents when a file without one is opened.
+///
+/// ```rust
+/// use minql_vfs::{ChecksumFileSystem, FileHandle, FileSystem, FileSystemError, MemoryFileSystem};
+///
+/// let inner = MemoryFileSystem::new();
+/// let fs = ChecksumFileSystem::new(inner.clone()).with_block_size(16);
+/// let mut file = fs.create_file("/data.bin").unwrap();
+/// file.write_to_offset(0, &[7; 64]).unwrap();
+///
+/// // Flip a byte behind the wrapper's back
+/// inner.open_file("/data.bin").unwrap().write_to_offset(40, &[0]).unwrap();
+///
+/// let mut buffer = [0; 8];
+/// assert!(file.read_at_offset(0, &mut buffer).is_ok());
+/// assert!(matches!(
+///     file.read_at_offset(36, &mut buffer),
+///     Err(FileSystemError::CorruptData { offset: 32, .. })
+/// ));
+/// ```
+#[derive(Debug)]
+pub struct ChecksumFileSystem {
+    block_size: u64,
+    inner: Arc<dyn DynamicFileSystem>,
+}
+
+impl ChecksumFileSystem {
+    /// Default size of each checksummed block.
+    pub const DEFAULT_BLOCK_SIZE: u64 = 4096;
+
+    /// Create a new Checksumming `FileSystem` over `filesystem`.
+    pub fn new<F: FileSystem>(filesystem: F) -> ChecksumFileSystem {
+        ChecksumFileSystem {
+            block_size: Self::DEFAULT_BLOCK_SIZE,
+            inner: Arc::new(filesystem),
+        }
+    }
+
+    /// Set the number of bytes covered by each checksum.
+    #[must_use]
+    pub fn with_block_size(mut self, block_size: u64) -> ChecksumFileSystem {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// Size of each checksummed block.
+    #[must_use]
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// Verify every block of a file.
+    #[tracing::instrument(level = "trace")]
+    pub fn verify(&self, path: &str) -> FileSystemResult<()> {
+        let mut handle = FileSystem::open_file(self, path)?;
+        let size = handle.get_size()?;
+        let mut offset = 0;
+        while offset < size {
+            handle.read_block(offset / self.block_size)?;
+            offset += self.block_size;
+        }
+        Ok(())
+    }
+
+    /// Open the sidecar of a file, rebuilding it if it doesn't exist yet.
+    fn open(
+        &self,
+        path: &str,
+        inner: Box<dyn FileHandle>,
+        truncate: bool,
+    ) -> FileSystemResult<ChecksumFileHandle> {
+        let sidecar = format!("{path}{CHECKSUM_SUFFIX}");
+        let rebuild = !DynamicFileSystem::exists(self.inner.as_ref(), &sidecar)?;
+        let options = OpenOptions::new().read(true).write(true).create(true);
+        let sums = DynamicFileSystem::open_with(self.inner.as_ref(), &sidecar, options)?;
+        let mut handle = ChecksumFileHandle {
+            block_size: self.block_size,
+            cursor: 0,
+            inner,
+            sums,
+        };
+        if truncate {
+            handle.sums.set_size(0)?;
+        } else if rebuild {
+            tracing::debug!(path, "Rebuilding checksums");
+            let blocks = handle.inner.get_size()?.div_ceil(self.block_size);
+            handle.update_checksums(0, blocks)?;
+            handle.sums.set_size(blocks * CHECKSUM_SIZE)?;
+        }
+        Ok(handle)
+    }
+}
+
+impl FileSystem for ChecksumFileSystem {
+    type FileHandle = ChecksumFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::exists(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::is_file(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::is_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        DynamicFileSystem::filesize(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_directory_all(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        let mut entries = DynamicFileSystem::list_directory(self.inner.as_ref(), path)?;
+        entries.retain(|entry| !entry.ends_with(CHECKSUM_SUFFIX));
+        Ok(entries)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_directory_all(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let inner = DynamicFileSystem::create_file(self.inner.as_ref(), path)?;
+        self.open(path, inner, true)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let inner = DynamicFileSystem::open_file(self.inner.as_ref(), path)?;
+        self.open(path, inner, false)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_file(self.inner.as_ref(), path)?;
+        let sidecar = format!("{path}{CHECKSUM_SUFFIX}");
+        if DynamicFileSystem::exists(self.inner.as_ref(), &sidecar)? {
+            DynamicFileSystem::remove_file(self.inner.as_ref(), &sidecar)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the sidecar along with the file, or removes the sidecar of the file it replaces if
+    /// it has none.
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::rename(self.inner.as_ref(), from, to)?;
+        let from = format!("{from}{CHECKSUM_SUFFIX}");
+        let to = format!("{to}{CHECKSUM_SUFFIX}");
+        if DynamicFileSystem::exists(self.inner.as_ref(), &from)? {
+            DynamicFileSystem::rename(self.inner.as_ref(), &from, &to)
+        } else if DynamicFileSystem::exists(self.inner.as_ref(), &to)? {
+            DynamicFileSystem::remove_file(self.inner.as_ref(), &to)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Clones the sidecar along with the file, if it has one.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        let method = DynamicFileSystem::clone_file(self.inner.as_ref(), src, dst)?;
+        let src = format!("{src}{CHECKSUM_SUFFIX}");
+        let dst = format!("{dst}{CHECKSUM_SUFFIX}");
+        if DynamicFileSystem::exists(self.inner.as_ref(), &dst)? {
+            DynamicFileSystem::remove_file(self.inner.as_ref(), &dst)?;
+        }
+        if DynamicFileSystem::exists(self.inner.as_ref(), &src)? {
+            DynamicFileSystem::clone_file(self.inner.as_ref(), &src, &dst)?;
+        }
+        Ok(method)
+    }
+
+    /// Applied one operation at a time through this wrapper rather than forwarded, as each
+    /// sidecar may only be written, moved or removed once the operation on its file succeeded,
+    /// which a batch can't express.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        batch
+            .iter()
+            .map(|operation| apply_operation(self, operation))
+            .collect()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        DynamicFileSystem::file_type(self.inner.as_ref(), path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_symlink(self.inner.as_ref(), target, path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        DynamicFileSystem::read_link(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        DynamicFileSystem::permissions(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        DynamicFileSystem::modified(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        DynamicFileSystem::set_permissions(self.inner.as_ref(), path, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        let truncate = options.is_truncate() || options.is_create_new();
+        let inner = DynamicFileSystem::open_with(self.inner.as_ref(), path, options)?;
+        let mut handle = self.open(path, inner, truncate)?;
+        if options.is_append() {
+            handle.cursor = handle.get_size()?;
+        }
+        Ok(handle)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        DynamicFileSystem::space(self.inner.as_ref())
+    }
+}
+
+/// Checksumming File Handle
+///
+/// Reads whole blocks from the underlying file to verify them, and rewrites the checksum of
+/// every block it modifies.
+pub struct ChecksumFileHandle {
+    block_size: u64,
+    cursor: u64,
+    inner: Box<dyn FileHandle>,
+    sums: Box<dyn FileHandle>,
+}
+
+impl ChecksumFileHandle {
+    /// Read and verify a block, which is short if it is the last in the file.
+    fn read_block(&mut self, block: u64) -> FileSystemResult<Vec<u8>> {
+        let offset = block * self.block_size;
+        let size = self.inner.get_size()?;
+        #[allow(clippy::cast_possible_truncation)]
+        let mut data = vec![0; self.block_size.min(size.saturating_sub(offset)) as usize];
+        read_exact_at(self.inner.as_mut(), offset, &mut data)?;
+        let mut stored = [0; 4];
+        read_exact_at(self.sums.as_mut(), block * CHECKSUM_SIZE, &mut stored)?;
+        if u32::from_le_bytes(stored) != crc32fast::hash(&data) {
+            return Err(FileSystemError::CorruptData {
+                path: self.inner.path().to_string(),
+                offset,
+            });
+        }
+        Ok(data)
+    }
+
+    /// Recompute the checksums of blocks `first..last` from the underlying file.
+    fn update_checksums(&mut self, first: u64, last: u64) -> FileSystemResult<()> {
+        let size = self.inner.get_size()?;
+        for block in first..last {
+            let offset = block * self.block_size;
+            #[allow(clippy::cast_possible_truncation)]
+            let mut data = vec![0; self.block_size.min(size.saturating_sub(offset)) as usize];
+            read_exact_at(self.inner.as_mut(), offset, &mut data)?;
+            let sum = crc32fast::hash(&data).to_le_bytes();
+            write_all_at(self.sums.as_mut(), block * CHECKSUM_SIZE, &sum)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ChecksumFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.inner.as_ref(), f)
+    }
+}
+
+impl Read for ChecksumFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.read_at_offset(self.cursor, buf)?;
+        self.cursor += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for ChecksumFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.write_to_offset(self.cursor, buf)?;
+        self.cursor += written as u64;
+        Ok(written)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Write::flush(self.inner.as_mut())?;
+        Write::flush(self.sums.as_mut())
+    }
+}
+
+impl Seek for ChecksumFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let cursor = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.get_size()?.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.cursor.checked_add_signed(offset),
+        };
+        self.cursor = cursor.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Seek before start of file",
+            )
+        })?;
+        Ok(self.cursor)
+    }
+}
+
+impl FileHandle for ChecksumFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        FileHandle::path(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        FileHandle::get_size(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        let size = self.get_size()?;
+        let first = size.min(new_size) / self.block_size;
+        if new_size < size && !new_size.is_multiple_of(self.block_size) {
+            self.read_block(first)?;
+        }
+        FileHandle::set_size(self.inner.as_mut(), new_size)?;
+        let blocks = new_size.div_ceil(self.block_size);
+        self.update_checksums(first, blocks)?;
+        self.sums.set_size(blocks * CHECKSUM_SIZE)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        FileHandle::sync_all(self.inner.as_mut())?;
+        FileHandle::sync_all(self.sums.as_mut())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        FileHandle::sync_data(self.inner.as_mut())?;
+        FileHandle::sync_data(self.sums.as_mut())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        FileHandle::get_lock_status(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::try_set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        let end = self.get_size()?.min(offset + buffer.len() as u64);
+        let mut read = 0;
+        let mut position = offset;
+        while position < end {
+            let block = position / self.block_size;
+            let data = self.read_block(block)?;
+            #[allow(clippy::cast_possible_truncation)]
+            let start = (position - block * self.block_size) as usize;
+            let count = (data.len() - start).min(buffer.len() - read);
+            buffer[read..read + count].copy_from_slice(&data[start..start + count]);
+            read += count;
+            position += count as u64;
+        }
+        Ok(read)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let size = self.get_size()?;
+        let end = offset + buffer.len() as u64;
+        let first = offset.min(size) / self.block_size;
+        let last = end.div_ceil(self.block_size);
+        // Existing bytes left in a partially overwritten block must be intact, or the new
+        // checksum would hide their corruption.
+        for block in [first, last - 1] {
+            let start = block * self.block_size;
+            let existing = start..size.min(start + self.block_size);
+            if !existing.is_empty() && (existing.start < offset || existing.end > end) {
+                self.read_block(block)?;
+            }
+        }
+        write_all_at(self.inner.as_mut(), offset, buffer)?;
+        self.update_checksums(first, last)?;
+        Ok(buffer.len())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::lock_range(self.inner.as_mut(), offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        FileHandle::unlock_range(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        FileHandle::advise(self.inner.as_mut(), offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        FileHandle::alignment(self.inner.as_ref())
+    }
+}
+
+/// Fill `buffer` from `offset`, failing if the file ends first.
+fn read_exact_at(
+    handle: &mut dyn FileHandle,
+    offset: u64,
+    buffer: &mut [u8],
+) -> FileSystemResult<()> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match handle.read_at_offset(offset + filled as u64, &mut buffer[filled..])? {
+            0 => {
+                return Err(FileSystemError::CorruptData {
+                    path: handle.path().to_string(),
+                    offset: offset + filled as u64,
+                })
+            }
+            read => filled += read,
+        }
+    }
+    Ok(())
+}
+
+/// Write all of `data` at `offset`.
+fn write_all_at(handle: &mut dyn FileHandle, offset: u64, data: &[u8]) -> FileSystemResult<()> {
+    let mut written = 0;
+    while written < data.len() {
+        match handle.write_to_offset(offset + written as u64, &data[written..])? {
+            0 => return Err(FileSystemError::InvalidOperation),
+            count => written += count,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ChecksumFileSystem, FileHandle, FileSystem, FileSystemError, MemoryFileSystem};
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_checksum_filesystem() {
+        let inner = MemoryFileSystem::new();
+        let fs = ChecksumFileSystem::new(inner.clone()).with_block_size(8);
+        let mut file = fs.create_file("/data.bin").unwrap();
+        file.write_all(b"Hello, World! This spans several blocks.")
+            .unwrap();
+        file.write_to_offset(5, b"!").unwrap();
+        file.write_to_offset(44, b"gap").unwrap();
+        assert_eq!(file.get_size().unwrap(), 47);
+        file.set_size(45).unwrap();
+        assert_eq!(fs.list_directory("/").unwrap(), vec!["data.bin"]);
+
+        let mut contents = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(&contents[..14], b"Hello! World! ");
+        assert_eq!(&contents[41..], &[0, 0, 0, b'g']);
+        fs.verify("/data.bin").unwrap();
+
+        // Corruption behind the wrapper is detected on read
+        inner
+            .open_file("/data.bin")
+            .unwrap()
+            .write_to_offset(20, b"X")
+            .unwrap();
+        let mut buffer = [0; 4];
+        assert!(file.read_at_offset(8, &mut buffer).is_ok());
+        assert!(matches!(
+            file.read_at_offset(18, &mut buffer),
+            Err(FileSystemError::CorruptData { offset: 16, .. })
+        ));
+        assert!(matches!(
+            fs.verify("/data.bin"),
+            Err(FileSystemError::CorruptData { offset: 16, .. })
+        ));
+        // Partial writes refuse to launder a corrupt block
+        assert!(file.write_to_offset(17, b"Y").is_err());
+        file.write_to_offset(16, &[b'Z'; 8]).unwrap();
+        fs.verify("/data.bin").unwrap();
+
+        fs.remove_file("/data.bin").unwrap();
+        assert!(inner.list_directory("/").unwrap().is_empty());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_checksum_rename() {
+        let inner = MemoryFileSystem::new();
+        let fs = ChecksumFileSystem::new(inner.clone()).with_block_size(8);
+        fs.write("/index.tmp", &[1; 20]).unwrap();
+        fs.write("/index", &[2; 12]).unwrap();
+        fs.rename("/index.tmp", "/index").unwrap();
+        fs.verify("/index").unwrap();
+        assert_eq!(fs.read("/index").unwrap(), [1; 20]);
+        assert_eq!(inner.list_directory("/").unwrap().len(), 2);
+
+        // A file without a sidecar doesn't inherit the one of the file it replaces
+        inner.write("/adopted", &[3; 4]).unwrap();
+        fs.rename("/adopted", "/index").unwrap();
+        fs.verify("/index").unwrap();
+        assert_eq!(fs.read("/index").unwrap(), [3; 4]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_checksum_batch() {
+        use crate::BatchOperation;
+
+        let inner = MemoryFileSystem::new();
+        let fs = ChecksumFileSystem::new(inner.clone()).with_block_size(8);
+        let results = fs.apply(&[
+            BatchOperation::write("/a.bin", &[1; 20]),
+            BatchOperation::rename("/a.bin", "/b.bin"),
+        ]);
+        assert!(results.iter().all(Result::is_ok));
+        fs.clone_file("/b.bin", "/c.bin").unwrap();
+        fs.verify("/b.bin").unwrap();
+        fs.verify("/c.bin").unwrap();
+        let mut entries = inner.list_directory("/").unwrap();
+        entries.sort();
+        assert_eq!(entries, ["b.bin", "b.bin.crc", "c.bin", "c.bin.crc"]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_checksum_filesystem_adopts_files() {
+        let inner = MemoryFileSystem::new();
+        inner
+            .create_file("/existing.bin")
+            .unwrap()
+            .write_all(&[3; 20])
+            .unwrap();
+        let fs = ChecksumFileSystem::new(inner.clone()).with_block_size(8);
+        fs.verify("/existing.bin").unwrap();
+        assert_eq!(inner.filesize("/existing.bin.crc").unwrap(), 12);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_checksum_conformance() {
+        use crate::{ChecksumFileSystem, MemoryFileSystem};
+
+        crate::conformance::run(|| ChecksumFileSystem::new(MemoryFileSystem::new()));
+    }
+}
@@ -0,0 +1,201 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{
+    FileSystemError, FileSystemProvider, FileSystemResult, LocalFileSystemProvider,
+    MemoryFileSystemProvider, VirtualFileSystemManager,
+};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+
+/// Description of a [`VirtualFileSystemManager`] read by
+/// [`VirtualFileSystemManager::from_config`].
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManagerConfig {
+    cache_capacity: Option<usize>,
+    #[serde(default)]
+    providers: BTreeMap<String, HashMap<String, OptionValue>>,
+    #[serde(default)]
+    mounts: Vec<MountConfig>,
+}
+
+/// Filesystem provisioned from a URI and mounted at a prefix.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MountConfig {
+    prefix: String,
+    uri: String,
+}
+
+/// Provider option, which providers take as a string whatever type it's written as.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OptionValue {
+    String(String),
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+}
+
+impl std::fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptionValue::String(value) => f.write_str(value),
+            OptionValue::Boolean(value) => write!(f, "{value}"),
+            OptionValue::Integer(value) => write!(f, "{value}"),
+            OptionValue::Float(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl VirtualFileSystemManager {
+    /// Build a manager from a TOML or JSON description of its providers and mounts.
+    ///
+    /// Documents starting with `{` are read as JSON and anything else as TOML. The optional
+    /// `cache_capacity` sets [`VirtualFileSystemManager::cache_capacity`], each table under
+    /// `providers` configures the built-in provider for the scheme it's named after, and each
+    /// entry of `mounts` provisions the filesystem for its `uri` and mounts it at its `prefix`,
+    /// in order. Providers for `file://` and `mem://` are registered whether listed or not, as
+    /// by [`VirtualFileSystemManager::with_defaults`], while `s3`, `gs` and `az` need their
+    /// features enabled.
+    ///
+    /// Malformed documents and unknown keys fail with [`FileSystemError::WrappedError`], and
+    /// schemes without a built-in provider with [`FileSystemError::UnknownFileSystem`].
+    ///
+    /// ```rust
+    /// use minql_vfs::{FileSystem, VirtualFileSystemManager};
+    ///
+    /// let config = r#"
+    ///     cache_capacity = 8
+    ///
+    ///     [providers.file]
+    ///     create_root_if_missing = true
+    ///
+    ///     [[mounts]]
+    ///     prefix = "/scratch"
+    ///     uri = "mem://scratch"
+    /// "#;
+    /// let manager = VirtualFileSystemManager::from_config(config.as_bytes()).unwrap();
+    /// assert_eq!(manager.mounts(), vec!["/scratch"]);
+    /// manager.namespace().write("/scratch/run-0", b"rows").unwrap();
+    /// ```
+    pub fn from_config<R: Read>(mut reader: R) -> FileSystemResult<VirtualFileSystemManager> {
+        let mut document = String::new();
+        reader
+            .read_to_string(&mut document)
+            .map_err(FileSystemError::io_error)?;
+        let config: ManagerConfig = if document.trim_start().starts_with('{') {
+            serde_json::from_str(&document).map_err(FileSystemError::wrap_error)?
+        } else {
+            toml::from_str(&document).map_err(FileSystemError::wrap_error)?
+        };
+
+        let mut manager = VirtualFileSystemManager::with_defaults();
+        if let Some(capacity) = config.cache_capacity {
+            manager = manager.cache_capacity(capacity);
+        }
+        for (scheme, options) in config.providers {
+            let options = options
+                .into_iter()
+                .map(|(key, value)| (key, value.to_string()))
+                .collect();
+            install_provider(&manager, &scheme, &options)?;
+        }
+        for mount in config.mounts {
+            manager.mount_uri(&mount.prefix, &mount.uri)?;
+        }
+        Ok(manager)
+    }
+}
+
+/// Register the built-in provider for a scheme, configured with `options`.
+fn install_provider(
+    manager: &VirtualFileSystemManager,
+    scheme: &str,
+    options: &HashMap<String, String>,
+) -> FileSystemResult<()> {
+    match scheme {
+        "file" => configured(manager, LocalFileSystemProvider::default(), options),
+        "mem" => configured(manager, MemoryFileSystemProvider::default(), options),
+        #[cfg(feature = "s3")]
+        "s3" => configured(manager, crate::S3FileSystemProvider::default(), options),
+        #[cfg(feature = "gcs")]
+        "gs" => configured(manager, crate::GcsFileSystemProvider::default(), options),
+        #[cfg(feature = "azure")]
+        "az" => configured(manager, crate::AzureFileSystemProvider::default(), options),
+        _ => Err(FileSystemError::UnknownFileSystem),
+    }
+}
+
+/// Configure a provider and register it, replacing any provider for its schemes.
+fn configured<P: FileSystemProvider>(
+    manager: &VirtualFileSystemManager,
+    provider: P,
+    options: &HashMap<String, String>,
+) -> FileSystemResult<()> {
+    provider.configure(options)?;
+    manager.register(provider)
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_manager_from_config() {
+        use crate::{FileSystem, FileSystemError, VirtualFileSystemManager};
+
+        let root = std::env::temp_dir().join(format!("minql-config-{}", std::process::id()));
+        let json = format!(
+            r#"{{
+                "providers": {{ "file": {{ "create_root_if_missing": true }}, "mem": {{}} }},
+                "mounts": [
+                    {{ "prefix": "/data", "uri": "file://{}" }},
+                    {{ "prefix": "/tmp", "uri": "mem://tmp" }}
+                ]
+            }}"#,
+            root.display()
+        );
+        let manager = VirtualFileSystemManager::from_config(json.as_bytes()).unwrap();
+        assert_eq!(manager.providers(), vec!["file", "mem"]);
+        assert_eq!(manager.mounts(), vec!["/data", "/tmp"]);
+        let fs = manager.namespace();
+        fs.write("/data/table.dat", b"rows").unwrap();
+        assert_eq!(std::fs::read(root.join("table.dat")).unwrap(), b"rows");
+        fs.write("/tmp/scratch", b"scratch").unwrap();
+        assert_eq!(
+            manager.get("mem://tmp").unwrap().read("/scratch").unwrap(),
+            b"scratch"
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(matches!(
+            VirtualFileSystemManager::from_config("[providers.ftp]".as_bytes()),
+            Err(FileSystemError::UnknownFileSystem)
+        ));
+        assert!(matches!(
+            VirtualFileSystemManager::from_config("mount = []".as_bytes()),
+            Err(FileSystemError::WrappedError(_))
+        ));
+        assert!(matches!(
+            VirtualFileSystemManager::from_config(
+                "[[mounts]]\nprefix = \"/a\"\nuri = \"mem://a\"\n\n[[mounts]]\nprefix = \"/a\"\nuri = \"mem://b\"".as_bytes()
+            ),
+            Err(FileSystemError::PathExists)
+        ));
+    }
+}
@@ -0,0 +1,809 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::utility::normalize_path;
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::collections::HashMap;
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Durability of a single file tracked by a [`CrashFileSystem`].
+#[derive(Debug)]
+struct CrashFile {
+    /// Contents as of the last sync, or `None` if the file has never been durable.
+    durable: Option<Vec<u8>>,
+    /// Whether the file has changed since the last sync.
+    dirty: bool,
+}
+
+/// Most recent write not yet covered by a sync.
+#[derive(Debug)]
+struct PendingWrite {
+    path: String,
+    offset: u64,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct CrashState {
+    generation: u64,
+    files: HashMap<String, CrashFile>,
+    last_write: Option<PendingWrite>,
+}
+
+impl CrashState {
+    /// Record a file replaced without a handle, which is new unless it was already tracked.
+    fn replaced(&mut self, path: &str) {
+        self.files
+            .entry(path.to_string())
+            .or_insert(CrashFile {
+                durable: None,
+                dirty: true,
+            })
+            .dirty = true;
+    }
+
+    /// Record a file moved from `from` to `to`, keeping its durable contents.
+    fn renamed(&mut self, from: &str, to: &str) {
+        self.files.remove(to);
+        if let Some(file) = self.files.remove(from) {
+            self.files.insert(to.to_string(), file);
+        }
+        self.last_write = match self.last_write.take() {
+            Some(write) if write.path == to => None,
+            Some(mut write) if write.path == from => {
+                write.path = to.to_string();
+                Some(write)
+            }
+            last_write => last_write,
+        };
+    }
+}
+
+/// Crash Simulation `FileSystem` Wrapper
+///
+/// Tracks which writes to another [`FileSystem`] have been made durable by
+/// [`FileHandle::sync_data`] or [`FileHandle::sync_all`]. Calling [`CrashFileSystem::crash`]
+/// rolls every file back to its contents as of its last sync and removes files created but never
+/// synced, as if the machine had lost power. [`CrashFileSystem::crash_torn`] additionally
+/// persists part of the last unsynced write, simulating a partial sector write.
+///
+/// Contents are snapshotted the first time a file is opened through the wrapper, and directory
+/// operations are treated as immediately durable. Handles opened before a crash fail with
+/// [`FileSystemError::InvalidOperation`] afterwards.
+///
+/// ```rust
+/// use minql_vfs::{CrashFileSystem, FileHandle, FileSystem, MemoryFileSystem};
+/// use std::io::Write;
+///
+/// let fs = CrashFileSystem::new(MemoryFileSystem::new());
+/// let mut file = fs.create_file("/data.log").unwrap();
+/// file.write_all(b"durable").unwrap();
+/// file.sync_data().unwrap();
+/// file.write_all(b" and lost").unwrap();
+///
+/// fs.crash().unwrap();
+/// assert_eq!(fs.filesize("/data.log").unwrap(), 7);
+/// ```
+#[derive(Debug)]
+pub struct CrashFileSystem {
+    state: Arc<Mutex<CrashState>>,
+    inner: Arc<dyn DynamicFileSystem>,
+}
+
+impl CrashFileSystem {
+    /// Create a new Crash Simulation `FileSystem`, treating the current contents of `filesystem`
+    /// as durable.
+    pub fn new<F: FileSystem>(filesystem: F) -> CrashFileSystem {
+        CrashFileSystem {
+            state: Arc::new(Mutex::new(CrashState::default())),
+            inner: Arc::new(filesystem),
+        }
+    }
+
+    /// Simulate a crash, discarding every write not covered by a sync.
+    #[tracing::instrument(level = "trace")]
+    pub fn crash(&self) -> FileSystemResult<()> {
+        self.restore(None)
+    }
+
+    /// Simulate a crash which tears the last unsynced write, persisting only the part of it that
+    /// falls within its first `sector_size` byte sector.
+    #[tracing::instrument(level = "trace")]
+    pub fn crash_torn(&self, sector_size: u64) -> FileSystemResult<()> {
+        self.restore(Some(sector_size.max(1)))
+    }
+
+    /// Paths with changes that would be lost by a crash.
+    #[must_use]
+    pub fn unsynced(&self) -> Vec<String> {
+        let state = self.state.lock().expect("Poisoned Lock");
+        let mut paths = state
+            .files
+            .iter()
+            .filter(|(_, file)| file.dirty)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+
+    fn restore(&self, sector_size: Option<u64>) -> FileSystemResult<()> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        state.generation += 1;
+        let last_write = state.last_write.take();
+        for (path, file) in state.files.drain() {
+            if !file.dirty {
+                continue;
+            }
+            match file.durable {
+                None => {
+                    if DynamicFileSystem::exists(self.inner.as_ref(), &path)? {
+                        DynamicFileSystem::remove_file(self.inner.as_ref(), &path)?;
+                    }
+                }
+                Some(contents) => {
+                    let options = OpenOptions::new().write(true).create(true).truncate(true);
+                    let mut handle =
+                        DynamicFileSystem::open_with(self.inner.as_ref(), &path, options)?;
+                    write_all_at(handle.as_mut(), 0, &contents)?;
+                    handle.sync_all()?;
+                }
+            }
+        }
+        if let (Some(sector_size), Some(write)) = (sector_size, last_write) {
+            let torn = (sector_size - write.offset % sector_size)
+                .min(write.data.len().saturating_sub(1) as u64);
+            tracing::debug!(
+                path = write.path,
+                offset = write.offset,
+                torn,
+                "Tearing write"
+            );
+            if torn > 0 {
+                let options = OpenOptions::new().write(true).create(true);
+                let mut handle =
+                    DynamicFileSystem::open_with(self.inner.as_ref(), &write.path, options)?;
+                #[allow(clippy::cast_possible_truncation)]
+                write_all_at(handle.as_mut(), write.offset, &write.data[..torn as usize])?;
+                handle.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Start tracking a file before it is opened, returning the current crash generation.
+    fn track(&self, path: &str, truncate: bool) -> FileSystemResult<(String, u64)> {
+        let path = normalize_path(path)?;
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        if !state.files.contains_key(&path) {
+            let durable = if DynamicFileSystem::is_file(self.inner.as_ref(), &path)? {
+                Some(read_contents(self.inner.as_ref(), &path)?)
+            } else {
+                None
+            };
+            let dirty = durable.is_none() || truncate;
+            state
+                .files
+                .insert(path.clone(), CrashFile { durable, dirty });
+        } else if truncate {
+            state.files.get_mut(&path).expect("Tracked File").dirty = true;
+        }
+        Ok((path, state.generation))
+    }
+
+    /// Start tracking an existing file about to be changed without a handle, treating its
+    /// current contents as durable.
+    fn adopt(&self, state: &mut CrashState, path: &str) -> FileSystemResult<()> {
+        if !state.files.contains_key(path) && DynamicFileSystem::is_file(self.inner.as_ref(), path)?
+        {
+            let durable = Some(read_contents(self.inner.as_ref(), path)?);
+            state.files.insert(
+                path.to_string(),
+                CrashFile {
+                    durable,
+                    dirty: false,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn wrap(&self, path: String, generation: u64, inner: Box<dyn FileHandle>) -> CrashFileHandle {
+        CrashFileHandle {
+            path,
+            generation,
+            state: self.state.clone(),
+            filesystem: self.inner.clone(),
+            inner,
+        }
+    }
+}
+
+impl FileSystem for CrashFileSystem {
+    type FileHandle = CrashFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::exists(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::is_file(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::is_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        DynamicFileSystem::filesize(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_directory_all(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        DynamicFileSystem::list_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_directory_all(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let (key, generation) = self.track(path, true)?;
+        let handle = DynamicFileSystem::create_file(self.inner.as_ref(), path)?;
+        Ok(self.wrap(key, generation, handle))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let (key, generation) = self.track(path, false)?;
+        let handle = DynamicFileSystem::open_file(self.inner.as_ref(), path)?;
+        Ok(self.wrap(key, generation, handle))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_file(self.inner.as_ref(), path)?;
+        let path = normalize_path(path)?;
+        self.state
+            .lock()
+            .expect("Poisoned Lock")
+            .files
+            .remove(&path);
+        Ok(())
+    }
+
+    /// The rename itself is durable at once, and the file keeps its durable contents under its
+    /// new path.
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::rename(self.inner.as_ref(), from, to)?;
+        let (from, to) = (normalize_path(from)?, normalize_path(to)?);
+        self.state
+            .lock()
+            .expect("Poisoned Lock")
+            .renamed(&from, &to);
+        Ok(())
+    }
+
+    /// The new file isn't durable until synced.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        let dst = normalize_path(dst)?;
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        self.adopt(&mut state, &dst)?;
+        let method = DynamicFileSystem::clone_file(self.inner.as_ref(), src, &dst)?;
+        state.replaced(&dst);
+        Ok(method)
+    }
+
+    /// Files written by the batch aren't durable until synced, while the rest of its operations
+    /// are durable at once. Operations on files that can't be tracked fail on their own; the
+    /// rest are applied as one batch by the inner filesystem.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        let mut tracked = Vec::with_capacity(batch.len());
+        let mut results: Vec<Option<FileSystemResult<()>>> = Vec::with_capacity(batch.len());
+        for operation in batch {
+            let adopted = operation.map_paths(normalize_path).and_then(|operation| {
+                if let BatchOperation::Write { .. }
+                | BatchOperation::Rename { .. }
+                | BatchOperation::RemoveFile { .. } = operation
+                {
+                    for path in operation.paths() {
+                        self.adopt(&mut state, path)?;
+                    }
+                }
+                Ok(operation)
+            });
+            match adopted {
+                Ok(operation) => {
+                    tracked.push(operation);
+                    results.push(None);
+                }
+                Err(error) => results.push(Some(Err(error))),
+            }
+        }
+        let applied = DynamicFileSystem::apply(self.inner.as_ref(), &tracked);
+        for (operation, result) in tracked.into_iter().zip(&applied) {
+            match (operation, result) {
+                (BatchOperation::Write { path, contents }, Ok(())) => {
+                    state.replaced(&path);
+                    state.last_write = Some(PendingWrite {
+                        path,
+                        offset: 0,
+                        data: contents,
+                    });
+                }
+                (BatchOperation::Rename { from, to }, Ok(())) => state.renamed(&from, &to),
+                (BatchOperation::RemoveFile { path }, Ok(())) => {
+                    state.files.remove(&path);
+                }
+                _ => {}
+            }
+        }
+        let mut applied = applied.into_iter();
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| applied.next().expect("Missing Batch Result")))
+            .collect()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        DynamicFileSystem::file_type(self.inner.as_ref(), path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_symlink(self.inner.as_ref(), target, path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        DynamicFileSystem::read_link(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        DynamicFileSystem::permissions(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        DynamicFileSystem::modified(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        DynamicFileSystem::set_permissions(self.inner.as_ref(), path, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        let truncate = options.is_truncate() || options.is_create_new();
+        let (key, generation) = self.track(path, truncate)?;
+        let handle = DynamicFileSystem::open_with(self.inner.as_ref(), path, options)?;
+        Ok(self.wrap(key, generation, handle))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        DynamicFileSystem::space(self.inner.as_ref())
+    }
+}
+
+/// Crash Simulation File Handle
+///
+/// Records its writes with the [`CrashFileSystem`] that opened it, and snapshots the file as
+/// durable whenever it is synced.
+pub struct CrashFileHandle {
+    path: String,
+    generation: u64,
+    state: Arc<Mutex<CrashState>>,
+    filesystem: Arc<dyn DynamicFileSystem>,
+    inner: Box<dyn FileHandle>,
+}
+
+impl CrashFileHandle {
+    /// Fail if a crash has been simulated since this handle was opened.
+    fn check(&self) -> FileSystemResult<()> {
+        if self.state.lock().expect("Poisoned Lock").generation == self.generation {
+            Ok(())
+        } else {
+            Err(FileSystemError::InvalidOperation)
+        }
+    }
+
+    /// Record a write of `data` at `offset` that is about to be applied.
+    fn record(&self, offset: Option<u64>, data: Vec<u8>) -> FileSystemResult<()> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        if state.generation != self.generation {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        if let Some(file) = state.files.get_mut(&self.path) {
+            file.dirty = true;
+        }
+        state.last_write = offset.map(|offset| PendingWrite {
+            path: self.path.clone(),
+            offset,
+            data,
+        });
+        Ok(())
+    }
+
+    /// Snapshot the file as durable.
+    fn synced(&mut self) -> FileSystemResult<()> {
+        let contents = read_contents(self.filesystem.as_ref(), &self.path)?;
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        if state.generation != self.generation {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        state.files.insert(
+            self.path.clone(),
+            CrashFile {
+                durable: Some(contents),
+                dirty: false,
+            },
+        );
+        if state
+            .last_write
+            .as_ref()
+            .is_some_and(|write| write.path == self.path)
+        {
+            state.last_write = None;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for CrashFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.inner.as_ref(), f)
+    }
+}
+
+impl Read for CrashFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.check()?;
+        Read::read(self.inner.as_mut(), buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        self.check()?;
+        Read::read_vectored(self.inner.as_mut(), bufs)
+    }
+}
+
+impl Write for CrashFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let offset = self.inner.stream_position()?;
+        self.record(Some(offset), buf.to_vec())?;
+        Write::write(self.inner.as_mut(), buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        let offset = self.inner.stream_position()?;
+        self.record(
+            Some(offset),
+            bufs.iter().flat_map(|buf| buf.iter().copied()).collect(),
+        )?;
+        Write::write_vectored(self.inner.as_mut(), bufs)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.check()?;
+        Write::flush(self.inner.as_mut())
+    }
+}
+
+impl Seek for CrashFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.check()?;
+        Seek::seek(self.inner.as_mut(), pos)
+    }
+}
+
+impl FileHandle for CrashFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        FileHandle::path(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        self.check()?;
+        FileHandle::get_size(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.record(None, Vec::new())?;
+        FileHandle::set_size(self.inner.as_mut(), new_size)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.check()?;
+        FileHandle::sync_all(self.inner.as_mut())?;
+        self.synced()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.check()?;
+        FileHandle::sync_data(self.inner.as_mut())?;
+        self.synced()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        FileHandle::get_lock_status(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::try_set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        self.check()?;
+        FileHandle::read_at_offset(self.inner.as_mut(), offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.record(Some(offset), buffer.to_vec())?;
+        FileHandle::write_to_offset(self.inner.as_mut(), offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::lock_range(self.inner.as_mut(), offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        FileHandle::unlock_range(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        FileHandle::advise(self.inner.as_mut(), offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        self.check()?;
+        FileHandle::read_at_vectored(self.inner.as_mut(), offset, buffers)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        self.record(
+            Some(offset),
+            buffers.iter().flat_map(|buf| buf.iter().copied()).collect(),
+        )?;
+        FileHandle::write_at_vectored(self.inner.as_mut(), offset, buffers)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        self.check()?;
+        FileHandle::map_readonly(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        FileHandle::alignment(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.record(None, Vec::new())?;
+        FileHandle::allocate(self.inner.as_mut(), len)
+    }
+}
+
+/// Read the entire contents of a file.
+fn read_contents(filesystem: &dyn DynamicFileSystem, path: &str) -> FileSystemResult<Vec<u8>> {
+    let mut contents = Vec::new();
+    DynamicFileSystem::open_file(filesystem, path)?
+        .read_to_end(&mut contents)
+        .map_err(FileSystemError::io_error)?;
+    Ok(contents)
+}
+
+/// Write all of `data` at `offset`.
+fn write_all_at(handle: &mut dyn FileHandle, offset: u64, data: &[u8]) -> FileSystemResult<()> {
+    let mut written = 0;
+    while written < data.len() {
+        match handle.write_to_offset(offset + written as u64, &data[written..])? {
+            0 => return Err(FileSystemError::InvalidOperation),
+            count => written += count,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CrashFileSystem, FileHandle, FileSystem, FileSystemError, MemoryFileSystem};
+    use std::io::{Read, Write};
+
+    fn contents(fs: &impl FileSystem, path: &str) -> Vec<u8> {
+        let mut contents = Vec::new();
+        fs.open_file(path)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_crash_filesystem() {
+        let inner = MemoryFileSystem::new();
+        {
+            let mut file = inner.create_file("/existing.dat").unwrap();
+            file.write_all(b"before").unwrap();
+        }
+        let fs = CrashFileSystem::new(inner.clone());
+
+        let mut synced = fs.create_file("/synced.dat").unwrap();
+        synced.write_all(b"kept").unwrap();
+        synced.sync_data().unwrap();
+        synced.write_all(b" lost").unwrap();
+        let mut unsynced = fs.create_file("/unsynced.dat").unwrap();
+        unsynced.write_all(b"lost").unwrap();
+        let mut existing = fs.open_file("/existing.dat").unwrap();
+        existing.write_to_offset(0, b"AFTER").unwrap();
+        assert_eq!(
+            fs.unsynced(),
+            vec!["/existing.dat", "/synced.dat", "/unsynced.dat"]
+        );
+
+        fs.crash().unwrap();
+        assert_eq!(contents(&fs, "/synced.dat"), b"kept");
+        assert!(!fs.exists("/unsynced.dat").unwrap());
+        assert_eq!(contents(&inner, "/existing.dat"), b"before");
+        assert!(fs.unsynced().is_empty());
+
+        // Handles from before the crash are dead
+        assert!(matches!(
+            synced.write_to_offset(0, b"x"),
+            Err(FileSystemError::InvalidOperation)
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_crash_filesystem_torn_write() {
+        let fs = CrashFileSystem::new(MemoryFileSystem::new());
+        let mut file = fs.create_file("/pages.dat").unwrap();
+        file.write_all(&[1; 8]).unwrap();
+        file.sync_all().unwrap();
+        file.write_to_offset(4, &[2; 12]).unwrap();
+
+        // Only the rest of the first 8 byte sector of the last write survives
+        fs.crash_torn(8).unwrap();
+        assert_eq!(contents(&fs, "/pages.dat"), vec![1, 1, 1, 1, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_crash_rename() {
+        let fs = CrashFileSystem::new(MemoryFileSystem::new());
+        fs.write("/old.dat", b"replaced").unwrap();
+        let mut file = fs.create_file("/new.tmp").unwrap();
+        file.write_all(b"kept").unwrap();
+        file.sync_data().unwrap();
+        file.write_all(b" lost").unwrap();
+        drop(file);
+        fs.rename("/new.tmp", "/old.dat").unwrap();
+        assert_eq!(fs.unsynced(), vec!["/old.dat"]);
+
+        fs.crash().unwrap();
+        assert!(!fs.exists("/new.tmp").unwrap());
+        assert_eq!(contents(&fs, "/old.dat"), b"kept");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_crash_batch() {
+        use crate::BatchOperation;
+
+        let fs = CrashFileSystem::new(MemoryFileSystem::new());
+        let mut file = fs.create_file("/synced.dat").unwrap();
+        file.write_all(b"synced").unwrap();
+        file.sync_all().unwrap();
+        drop(file);
+        let results = fs.apply(&[
+            BatchOperation::create_directory("/data"),
+            BatchOperation::write("/data/new.dat", b"lost"),
+            BatchOperation::write("/synced.dat", b"overwritten"),
+            BatchOperation::rename("/synced.dat", "/data/moved.dat"),
+        ]);
+        assert!(results.iter().all(Result::is_ok));
+        fs.clone_file("/data/moved.dat", "/clone.dat").unwrap();
+        assert_eq!(
+            fs.unsynced(),
+            vec!["/clone.dat", "/data/moved.dat", "/data/new.dat"]
+        );
+
+        fs.crash().unwrap();
+        assert!(fs.exists("/data").unwrap());
+        assert!(!fs.exists("/data/new.dat").unwrap());
+        assert!(!fs.exists("/clone.dat").unwrap());
+        assert_eq!(contents(&fs, "/data/moved.dat"), b"synced");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_crash_conformance() {
+        use crate::{CrashFileSystem, MemoryFileSystem};
+
+        crate::conformance::run(|| CrashFileSystem::new(MemoryFileSystem::new()));
+    }
+}
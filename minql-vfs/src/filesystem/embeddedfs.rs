@@ -0,0 +1,410 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::utility::{join_segments, normalize_path, normalize_segments};
+use crate::{
+    FileHandle, FileLockMode, FileSystem, FileSystemError, FileSystemResult, FileSystemSpace,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+/// Embedded `FileSystem`
+///
+/// A read-only filesystem over byte slices compiled into the binary, such as those produced by
+/// `include_bytes!` or `include_dir`-style macros. Directories are implied by the file paths and
+/// file handles borrow the embedded slices directly, so nothing is copied when files are read.
+/// Every mutating operation fails with [`FileSystemError::PermissionDenied`].
+///
+/// ```rust
+/// use minql_vfs::{EmbeddedFileSystem, FileSystem};
+/// use std::io::Read;
+///
+/// static ASSETS: &[(&str, &[u8])] = &[
+///     ("/templates/index.html", b"<html></html>"),
+///     ("/migrations/0001_init.sql", b"CREATE TABLE test;"),
+/// ];
+///
+/// let fs = EmbeddedFileSystem::new(ASSETS);
+/// assert!(fs.is_directory("/templates").unwrap());
+///
+/// let mut contents = String::new();
+/// let mut file = fs.open_file("/migrations/0001_init.sql").unwrap();
+/// file.read_to_string(&mut contents).unwrap();
+/// assert_eq!(contents, "CREATE TABLE test;");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EmbeddedFileSystem(Arc<EmbeddedTree>);
+
+#[derive(Debug, Default)]
+struct EmbeddedTree {
+    files: BTreeMap<String, &'static [u8]>,
+    directories: BTreeSet<String>,
+}
+
+impl EmbeddedFileSystem {
+    /// Create a new Embedded `FileSystem` from a static table of paths and contents.
+    #[must_use]
+    pub fn new(entries: &'static [(&'static str, &'static [u8])]) -> EmbeddedFileSystem {
+        entries.iter().copied().collect()
+    }
+
+    /// Get the embedded contents of a file without opening a handle.
+    #[must_use]
+    pub fn get(&self, path: &str) -> Option<&'static [u8]> {
+        let path = normalize_path(path).ok()?;
+        self.0.files.get(&path).copied()
+    }
+}
+
+impl FromIterator<(&'static str, &'static [u8])> for EmbeddedFileSystem {
+    /// Paths are normalized; entries that aren't valid paths are skipped.
+    fn from_iter<I: IntoIterator<Item = (&'static str, &'static [u8])>>(
+        iter: I,
+    ) -> EmbeddedFileSystem {
+        let mut tree = EmbeddedTree::default();
+        tree.directories.insert(String::from("/"));
+        for (path, contents) in iter {
+            let Ok(segments) = normalize_segments(path) else {
+                continue;
+            };
+            if segments.is_empty() {
+                continue;
+            }
+            for depth in 1..segments.len() {
+                tree.directories.insert(join_segments(&segments[..depth]));
+            }
+            tree.files.insert(join_segments(&segments), contents);
+        }
+        EmbeddedFileSystem(Arc::new(tree))
+    }
+}
+
+impl FileSystem for EmbeddedFileSystem {
+    type FileHandle = EmbeddedFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        let path = normalize_path(path)?;
+        Ok(self.0.files.contains_key(&path) || self.0.directories.contains(&path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        Ok(self.0.files.contains_key(&normalize_path(path)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        Ok(self.0.directories.contains(&normalize_path(path)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        let path = normalize_path(path)?;
+        match self.0.files.get(&path) {
+            Some(contents) => Ok(contents.len() as u64),
+            None if self.0.directories.contains(&path) => Err(FileSystemError::InvalidOperation),
+            None => Err(FileSystemError::PathMissing),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        Err(FileSystemError::PermissionDenied)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        Err(FileSystemError::PermissionDenied)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        let segments = normalize_segments(path)?;
+        if !self.0.directories.contains(&join_segments(&segments)) {
+            return Err(if self.0.files.contains_key(&join_segments(&segments)) {
+                FileSystemError::InvalidOperation
+            } else {
+                FileSystemError::PathMissing
+            });
+        }
+        let children = self
+            .0
+            .files
+            .keys()
+            .chain(self.0.directories.iter())
+            .filter_map(|child| {
+                let child = normalize_segments(child).ok()?;
+                (child.len() == segments.len() + 1 && child.starts_with(&segments))
+                    .then(|| child[segments.len()].to_string())
+            })
+            .collect::<BTreeSet<_>>();
+        Ok(children.into_iter().collect())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        Err(FileSystemError::PermissionDenied)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        Err(FileSystemError::PermissionDenied)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        Err(FileSystemError::PermissionDenied)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let path = normalize_path(path)?;
+        match self.0.files.get(&path) {
+            Some(contents) => Ok(EmbeddedFileHandle {
+                path,
+                contents,
+                cursor: 0,
+                lock: FileLockMode::Unlocked,
+            }),
+            None if self.0.directories.contains(&path) => Err(FileSystemError::InvalidOperation),
+            None => Err(FileSystemError::PathMissing),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        Err(FileSystemError::PermissionDenied)
+    }
+
+    /// Reports the embedded contents as the total and used space, with nothing available.
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        let used = self
+            .0
+            .files
+            .values()
+            .map(|contents| contents.len() as u64)
+            .sum();
+        Ok(FileSystemSpace {
+            total: used,
+            used,
+            available: 0,
+        })
+    }
+}
+
+/// Embedded File Handle
+///
+/// Reads directly from the embedded slice. Writes fail with a permission denied error.
+pub struct EmbeddedFileHandle {
+    path: String,
+    contents: &'static [u8],
+    cursor: u64,
+    lock: FileLockMode,
+}
+
+impl EmbeddedFileHandle {
+    /// Borrow the entire contents of the file without copying.
+    #[must_use]
+    pub fn contents(&self) -> &'static [u8] {
+        self.contents
+    }
+
+    /// Remaining contents from the current cursor position.
+    fn remaining(&self) -> &'static [u8] {
+        let start = usize::try_from(self.cursor)
+            .unwrap_or(usize::MAX)
+            .min(self.contents.len());
+        &self.contents[start..]
+    }
+}
+
+impl std::fmt::Debug for EmbeddedFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "EmbeddedFileHandle {{ path: {}, size: {}, cursor: {} }}",
+            self.path,
+            self.contents.len(),
+            self.cursor
+        )
+    }
+}
+
+impl Read for EmbeddedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = Read::read(&mut self.remaining(), buf)?;
+        self.cursor += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for EmbeddedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Err(FileSystemError::PermissionDenied.into())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for EmbeddedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.contents.len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.cursor.checked_add_signed(offset),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        self.cursor = position;
+        Ok(position)
+    }
+}
+
+impl FileHandle for EmbeddedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        Ok(self.contents.len() as u64)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        Err(FileSystemError::PermissionDenied)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        Ok(self.lock)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        // Nothing can ever write to embedded contents, so any lock is trivially granted.
+        self.lock = mode;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(self.contents.len());
+        let end = start.saturating_add(buffer.len()).min(self.contents.len());
+        buffer[..end - start].copy_from_slice(&self.contents[start..end]);
+        Ok(end - start)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        Err(FileSystemError::PermissionDenied)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{EmbeddedFileSystem, FileHandle, FileSystem, FileSystemError};
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    static ASSETS: &[(&str, &[u8])] = &[
+        ("templates/index.html", b"<html></html>"),
+        ("/templates/partials/./header.html", b"<header/>"),
+        ("\\migrations\\0001_init.sql", b"CREATE TABLE test;"),
+    ];
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_embedded_filesystem() {
+        let fs = EmbeddedFileSystem::new(ASSETS);
+
+        assert!(fs.is_directory("/").unwrap());
+        assert!(fs.is_directory("/templates/partials").unwrap());
+        assert!(fs.is_file("/templates/partials/header.html").unwrap());
+        assert!(!fs.exists("/templates/missing.html").unwrap());
+        assert_eq!(
+            fs.list_directory("/").unwrap(),
+            vec!["migrations", "templates"]
+        );
+        assert_eq!(
+            fs.list_directory("/templates").unwrap(),
+            vec!["index.html", "partials"]
+        );
+        assert_eq!(fs.filesize("/migrations/0001_init.sql").unwrap(), 18);
+
+        // Reads borrow the embedded slice
+        let mut file = fs.open_file("/templates/index.html").unwrap();
+        assert!(std::ptr::eq(file.contents(), ASSETS[0].1));
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"<html></html>");
+        file.seek(SeekFrom::Start(6)).unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(file.read(&mut buf).unwrap(), 7);
+        assert_eq!(&buf[..7], b"</html>");
+        assert_eq!(file.read_at_offset(1, &mut buf[..4]).unwrap(), 4);
+        assert_eq!(&buf[..4], b"html");
+
+        // Everything mutating is rejected
+        assert!(file.write(b"nope").is_err());
+        assert!(matches!(
+            file.set_size(0),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        assert!(matches!(
+            fs.create_file("/new.txt"),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        assert!(matches!(
+            fs.remove_file("/templates/index.html"),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        assert!(matches!(
+            fs.create_directory("/new"),
+            Err(FileSystemError::PermissionDenied)
+        ));
+    }
+}
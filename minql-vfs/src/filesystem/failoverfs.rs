@@ -0,0 +1,808 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::{
+    Advice, CloneMethod, ContentDigest, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, HashAlgorithm, OpenOptions, Permissions,
+    SymlinkPolicy,
+};
+use std::io::{ErrorKind, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long to wait after the primary failed before probing it again by default.
+const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Backend of a [`FailoverFileSystem`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum FailoverBackend {
+    /// Backend serving operations while it's reachable
+    Primary,
+    /// Backend serving operations while the primary is unreachable
+    Secondary,
+}
+
+/// Change in the health of the primary of a [`FailoverFileSystem`], reported to its
+/// [`FailoverObserver`]s.
+#[derive(Debug)]
+pub enum FailoverEvent<'a> {
+    /// The primary failed with a connectivity error, so operations go to the secondary
+    FailedOver(&'a FileSystemError),
+    /// Probing the primary failed with a connectivity error, so the secondary stays active
+    ProbeFailed(&'a FileSystemError),
+    /// Probing the primary succeeded, so operations go to the primary again
+    FailedBack,
+}
+
+/// Observer of failovers and fail-backs of a [`FailoverFileSystem`], for logging, metrics or
+/// alerting.
+///
+/// Implemented for closures taking the event.
+pub trait FailoverObserver: Send + Sync + 'static {
+    /// Report a change in the health of the primary.
+    fn observe(&self, event: &FailoverEvent<'_>);
+}
+
+impl<F: Fn(&FailoverEvent<'_>) + Send + Sync + 'static> FailoverObserver for F {
+    fn observe(&self, event: &FailoverEvent<'_>) {
+        self(event);
+    }
+}
+
+/// Check if an error means a backend couldn't be reached, rather than the operation failing.
+///
+/// Covers timeouts, I/O errors of a lost or refused connection, and transport errors of object
+/// store requests. This is the default classifier of a [`FailoverFileSystem`].
+#[must_use]
+pub fn is_connectivity_error(err: &FileSystemError) -> bool {
+    match err {
+        FileSystemError::TimedOut | FileSystemError::WrappedError(_) => true,
+        FileSystemError::IOError(err) => matches!(
+            err.kind(),
+            ErrorKind::ConnectionRefused
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected
+                | ErrorKind::AddrNotAvailable
+                | ErrorKind::BrokenPipe
+                | ErrorKind::TimedOut
+                | ErrorKind::HostUnreachable
+                | ErrorKind::NetworkUnreachable
+                | ErrorKind::NetworkDown
+        ),
+        _ => false,
+    }
+}
+
+/// Backends and health of a [`FailoverFileSystem`], shared with its handles.
+struct FailoverCore {
+    primary: Arc<dyn DynamicFileSystem>,
+    secondary: Arc<dyn DynamicFileSystem>,
+    classifier: fn(&FileSystemError) -> bool,
+    probe_interval: Duration,
+    observers: Vec<Box<dyn FailoverObserver>>,
+    /// When the primary last failed or was probed, while the secondary is active
+    failed: Mutex<Option<Instant>>,
+    failovers: AtomicU64,
+}
+
+impl std::fmt::Debug for FailoverCore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FailoverCore")
+            .field("primary", &self.primary)
+            .field("secondary", &self.secondary)
+            .field("probe_interval", &self.probe_interval)
+            .field("failed", &self.failed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FailoverCore {
+    fn notify(&self, event: &FailoverEvent<'_>) {
+        for observer in &self.observers {
+            observer.observe(event);
+        }
+    }
+
+    fn active(&self) -> FailoverBackend {
+        match *self.failed.lock().expect("Poisoned Lock") {
+            None => FailoverBackend::Primary,
+            Some(_) => FailoverBackend::Secondary,
+        }
+    }
+
+    /// Switch to the secondary after the primary failed with a connectivity error.
+    fn fail_over(&self, err: &FileSystemError) {
+        let mut failed = self.failed.lock().expect("Poisoned Lock");
+        let first = failed.is_none();
+        *failed = Some(Instant::now());
+        drop(failed);
+        if first {
+            tracing::warn!(%err, "Primary unreachable, failing over to secondary");
+            self.failovers.fetch_add(1, Ordering::AcqRel);
+            self.notify(&FailoverEvent::FailedOver(err));
+        }
+    }
+
+    /// Check whether operations go to the primary, probing it if it failed long enough ago.
+    fn use_primary(&self, force: bool) -> bool {
+        let mut failed = self.failed.lock().expect("Poisoned Lock");
+        match *failed {
+            None => return true,
+            Some(at) if !force && at.elapsed() < self.probe_interval => return false,
+            Some(_) => *failed = Some(Instant::now()),
+        }
+        drop(failed);
+        match self.primary.list_directory("/") {
+            Err(err) if (self.classifier)(&err) => {
+                tracing::debug!(%err, "Primary still unreachable");
+                self.notify(&FailoverEvent::ProbeFailed(&err));
+                false
+            }
+            _ => {
+                tracing::info!("Primary reachable again, failing back");
+                *self.failed.lock().expect("Poisoned Lock") = None;
+                self.notify(&FailoverEvent::FailedBack);
+                true
+            }
+        }
+    }
+
+    /// Perform an operation on the primary, or the secondary if the primary is unreachable.
+    fn route<T>(
+        &self,
+        operation: impl Fn(&dyn DynamicFileSystem) -> FileSystemResult<T>,
+    ) -> FileSystemResult<(FailoverBackend, T)> {
+        if self.use_primary(false) {
+            match operation(self.primary.as_ref()) {
+                Err(err) if (self.classifier)(&err) => self.fail_over(&err),
+                result => return result.map(|value| (FailoverBackend::Primary, value)),
+            }
+        }
+        operation(self.secondary.as_ref()).map(|value| (FailoverBackend::Secondary, value))
+    }
+}
+
+/// Failover `FileSystem` Wrapper
+///
+/// Sends every operation to the primary backend until it fails with a connectivity error, such
+/// as a timeout or a refused connection, then transparently redirects the operation and every
+/// one after it to the secondary. Other errors, like missing paths, are returned as they are.
+/// Once the primary has been failed for the probe interval, the next operation probes it by
+/// listing its root, and fails back to it if it's reachable again. Observers are notified of
+/// every failover, failed probe and fail-back.
+///
+/// The secondary is meant to be a replica of the primary, such as an object store bucket in
+/// another region. Writes made while failed over land on the secondary only, and aren't copied
+/// back to the primary on fail-back.
+///
+/// Handles stay on the backend they were opened on. Handles opened for reading only on the
+/// primary reopen the file on the secondary and resume at the same position if the primary
+/// fails underneath them, so reads survive an outage; other handles return the error.
+///
+/// ```rust
+/// use minql_vfs::{FailoverBackend, FailoverEvent, FailoverFileSystem, FileSystem, MemoryFileSystem};
+/// use std::time::Duration;
+///
+/// let fs = FailoverFileSystem::new(MemoryFileSystem::new(), MemoryFileSystem::new())
+///     .with_probe_interval(Duration::from_secs(1))
+///     .with_observer(|event: &FailoverEvent<'_>| eprintln!("storage health changed: {event:?}"));
+///
+/// fs.write("/table.dat", b"rows").unwrap();
+/// assert_eq!(fs.read("/table.dat").unwrap(), b"rows");
+/// assert_eq!(fs.active(), FailoverBackend::Primary);
+/// assert_eq!(fs.failovers(), 0);
+/// ```
+#[derive(Debug)]
+pub struct FailoverFileSystem {
+    core: Arc<FailoverCore>,
+}
+
+impl FailoverFileSystem {
+    /// Create a new Failover `FileSystem` redirecting from `primary` to `secondary`.
+    pub fn new<P: FileSystem, S: FileSystem>(primary: P, secondary: S) -> FailoverFileSystem {
+        FailoverFileSystem {
+            core: Arc::new(FailoverCore {
+                primary: Arc::new(primary),
+                secondary: Arc::new(secondary),
+                classifier: is_connectivity_error,
+                probe_interval: DEFAULT_PROBE_INTERVAL,
+                observers: Vec::new(),
+                failed: Mutex::new(None),
+                failovers: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Set how long to wait after the primary failed before probing it again.
+    #[must_use]
+    pub fn with_probe_interval(mut self, interval: Duration) -> FailoverFileSystem {
+        self.core_mut().probe_interval = interval;
+        self
+    }
+
+    /// Set which errors of the primary cause a failover, instead of [`is_connectivity_error`].
+    #[must_use]
+    pub fn with_classifier(
+        mut self,
+        classifier: fn(&FileSystemError) -> bool,
+    ) -> FailoverFileSystem {
+        self.core_mut().classifier = classifier;
+        self
+    }
+
+    /// Add an observer of failovers and fail-backs.
+    #[must_use]
+    pub fn with_observer<O: FailoverObserver>(mut self, observer: O) -> FailoverFileSystem {
+        self.core_mut().observers.push(Box::new(observer));
+        self
+    }
+
+    /// Backend currently serving operations.
+    #[must_use]
+    pub fn active(&self) -> FailoverBackend {
+        self.core.active()
+    }
+
+    /// Number of times operations failed over to the secondary.
+    #[must_use]
+    pub fn failovers(&self) -> u64 {
+        self.core.failovers.load(Ordering::Acquire)
+    }
+
+    /// Probe the primary now if it's failed, failing back if it's reachable again, and return
+    /// the backend serving operations.
+    #[must_use]
+    pub fn check_health(&self) -> FailoverBackend {
+        if self.core.use_primary(true) {
+            FailoverBackend::Primary
+        } else {
+            FailoverBackend::Secondary
+        }
+    }
+
+    fn core_mut(&mut self) -> &mut FailoverCore {
+        Arc::get_mut(&mut self.core).expect("Configured Failover with Open Handles")
+    }
+
+    fn route<T>(
+        &self,
+        operation: impl Fn(&dyn DynamicFileSystem) -> FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        Ok(self.core.route(operation)?.1)
+    }
+
+    fn open(
+        &self,
+        path: &str,
+        options: Option<OpenOptions>,
+        open: impl Fn(&dyn DynamicFileSystem) -> FileSystemResult<Box<dyn FileHandle>>,
+    ) -> FileSystemResult<FailoverFileHandle> {
+        let (backend, inner) = self.core.route(open)?;
+        Ok(FailoverFileHandle {
+            path: path.to_string(),
+            inner,
+            backend,
+            reopen: options.filter(|options| !options.is_write() && !options.is_append()),
+            cursor: 0,
+            core: self.core.clone(),
+        })
+    }
+}
+
+impl FileSystem for FailoverFileSystem {
+    type FileHandle = FailoverFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        self.route(|fs| fs.exists(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        self.route(|fs| fs.is_file(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        self.route(|fs| fs.is_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.route(|fs| fs.filesize(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.route(|fs| fs.create_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.route(|fs| fs.create_directory_all(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        self.route(|fs| fs.list_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.route(|fs| fs.remove_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.route(|fs| fs.remove_directory_all(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.open(path, None, |fs| fs.create_file(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.open(path, None, |fs| fs.open_file(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        self.route(|fs| fs.remove_file(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.route(|fs| fs.file_type(path, policy))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        self.route(|fs| fs.create_symlink(target, path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.route(|fs| fs.read_link(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.route(|fs| fs.permissions(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.route(|fs| fs.modified(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.route(|fs| fs.set_permissions(path, permissions))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        self.open(path, Some(options), |fs| fs.open_with(path, options))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        self.route(DynamicFileSystem::space)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        self.route(|fs| fs.clone_file(src, dst))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        self.route(|fs| fs.rename(from, to))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn hash_file(&self, path: &str, algorithm: HashAlgorithm) -> FileSystemResult<ContentDigest> {
+        self.route(|fs| fs.hash_file(path, algorithm))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        self.route(|fs| fs.read(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_to_string(&self, path: &str) -> FileSystemResult<String> {
+        self.route(|fs| fs.read_to_string(path))
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        self.route(|fs| fs.write(path, contents))
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn append(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        self.route(|fs| fs.append(path, contents))
+    }
+}
+
+/// Failover File Handle
+///
+/// Handle on the backend of a [`FailoverFileSystem`] which served its open, reopening read-only
+/// handles on the secondary when the primary becomes unreachable.
+pub struct FailoverFileHandle {
+    path: String,
+    inner: Box<dyn FileHandle>,
+    backend: FailoverBackend,
+    /// Options to reopen the file with on the secondary, for read-only handles
+    reopen: Option<OpenOptions>,
+    cursor: u64,
+    core: Arc<FailoverCore>,
+}
+
+impl FailoverFileHandle {
+    /// Backend this handle is open on.
+    #[must_use]
+    pub fn backend(&self) -> FailoverBackend {
+        self.backend
+    }
+
+    /// Perform a read, reopening the file on the secondary and retrying it if the primary
+    /// became unreachable.
+    fn read_with<T>(
+        &mut self,
+        mut read: impl FnMut(&mut dyn FileHandle) -> FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        match read(self.inner.as_mut()) {
+            Err(err)
+                if self.backend == FailoverBackend::Primary && (self.core.classifier)(&err) =>
+            {
+                self.core.fail_over(&err);
+                let Some(options) = self.reopen else {
+                    return Err(err);
+                };
+                let mut inner = self.core.secondary.open_with(&self.path, options)?;
+                inner
+                    .seek(SeekFrom::Start(self.cursor))
+                    .map_err(FileSystemError::io_error)?;
+                tracing::debug!(path = self.path, "Reopened handle on secondary");
+                self.inner = inner;
+                self.backend = FailoverBackend::Secondary;
+                read(self.inner.as_mut())
+            }
+            result => result,
+        }
+    }
+
+    /// Perform a write, noting a failover if the primary became unreachable.
+    fn write_with<T>(
+        &mut self,
+        write: impl FnOnce(&mut dyn FileHandle) -> FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        let result = write(self.inner.as_mut());
+        if let Err(err) = &result {
+            if self.backend == FailoverBackend::Primary && (self.core.classifier)(err) {
+                self.core.fail_over(err);
+            }
+        }
+        result
+    }
+}
+
+impl std::fmt::Debug for FailoverFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FailoverFileHandle")
+            .field("path", &self.path)
+            .field("backend", &self.backend)
+            .field("cursor", &self.cursor)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Read for FailoverFileHandle {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.read_with(|handle| handle.read(buf).map_err(FileSystemError::io_error))?;
+        self.cursor += read as u64;
+        Ok(read)
+    }
+
+    #[tracing::instrument(level = "trace", skip(bufs))]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        let read = self.read_with(|handle| {
+            handle
+                .read_vectored(bufs)
+                .map_err(FileSystemError::io_error)
+        })?;
+        self.cursor += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for FailoverFileHandle {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written =
+            self.write_with(|handle| handle.write(buf).map_err(FileSystemError::io_error))?;
+        self.cursor += written as u64;
+        Ok(written)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(self.write_with(|handle| handle.flush().map_err(FileSystemError::io_error))?)
+    }
+}
+
+impl Seek for FailoverFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.cursor =
+            self.read_with(|handle| handle.seek(pos).map_err(FileSystemError::io_error))?;
+        Ok(self.cursor)
+    }
+}
+
+impl FileHandle for FailoverFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        self.inner.get_size()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.write_with(|handle| handle.set_size(new_size))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.write_with(|handle| handle.allocate(len))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.write_with(FileHandle::sync_all)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.write_with(FileHandle::sync_data)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        self.inner.get_lock_status()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.try_set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        self.inner.alignment()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.lock_range(offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.inner.unlock_range(offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        self.inner.advise(offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        self.read_with(|handle| handle.read_at_offset(offset, buffer))
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.write_with(|handle| handle.write_to_offset(offset, buffer))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::filesystem::TestObjectStore;
+    use crate::{
+        FailoverBackend, FailoverEvent, FailoverFileSystem, FileSystem, FileSystemError,
+        FileSystemResult, MemoryFileSystem, ObjectListing, ObjectMeta, ObjectStore,
+        ObjectStoreFileSystem, OpenOptions,
+    };
+    use std::io::Read;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Object store which times out every request while it's down.
+    #[derive(Clone, Debug, Default)]
+    struct OutageStore {
+        inner: TestObjectStore,
+        down: Arc<AtomicBool>,
+    }
+
+    impl OutageStore {
+        fn check(&self) -> FileSystemResult<()> {
+            if self.down.load(Ordering::Acquire) {
+                return Err(FileSystemError::TimedOut);
+            }
+            Ok(())
+        }
+    }
+
+    impl ObjectStore for OutageStore {
+        fn head(&self, key: &str) -> FileSystemResult<Option<ObjectMeta>> {
+            self.check()?;
+            self.inner.head(key)
+        }
+
+        fn get_range(&self, key: &str, offset: u64, length: u64) -> FileSystemResult<Vec<u8>> {
+            self.check()?;
+            self.inner.get_range(key, offset, length)
+        }
+
+        fn put(&self, key: &str, data: &[u8]) -> FileSystemResult<()> {
+            self.check()?;
+            self.inner.put(key, data)
+        }
+
+        fn delete(&self, key: &str) -> FileSystemResult<()> {
+            self.check()?;
+            self.inner.delete(key)
+        }
+
+        fn list(&self, prefix: &str, delimiter: Option<char>) -> FileSystemResult<ObjectListing> {
+            self.check()?;
+            self.inner.list(prefix, delimiter)
+        }
+
+        fn create_multipart(&self, key: &str) -> FileSystemResult<String> {
+            self.check()?;
+            self.inner.create_multipart(key)
+        }
+
+        fn upload_part(
+            &self,
+            key: &str,
+            upload_id: &str,
+            part_number: u32,
+            data: &[u8],
+        ) -> FileSystemResult<String> {
+            self.check()?;
+            self.inner.upload_part(key, upload_id, part_number, data)
+        }
+
+        fn complete_multipart(
+            &self,
+            key: &str,
+            upload_id: &str,
+            parts: &[(u32, String)],
+        ) -> FileSystemResult<()> {
+            self.check()?;
+            self.inner.complete_multipart(key, upload_id, parts)
+        }
+
+        fn abort_multipart(&self, key: &str, upload_id: &str) -> FileSystemResult<()> {
+            self.check()?;
+            self.inner.abort_multipart(key, upload_id)
+        }
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_failover() {
+        let store = OutageStore::default();
+        let primary = ObjectStoreFileSystem::new(store.clone(), "");
+        let secondary = MemoryFileSystem::new();
+        primary.write("/table.dat", b"0123456789").unwrap();
+        secondary.write("/table.dat", b"0123456789").unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = events.clone();
+        let fs = FailoverFileSystem::new(primary, secondary.clone())
+            .with_probe_interval(Duration::from_millis(50))
+            .with_observer(move |event: &FailoverEvent<'_>| {
+                log.lock().unwrap().push(match event {
+                    FailoverEvent::FailedOver(_) => "failed over",
+                    FailoverEvent::ProbeFailed(_) => "probe failed",
+                    FailoverEvent::FailedBack => "failed back",
+                });
+            });
+
+        // Served by the primary while it's reachable, passing other errors through
+        assert_eq!(fs.active(), FailoverBackend::Primary);
+        assert!(matches!(
+            fs.read("/missing"),
+            Err(FileSystemError::PathMissing)
+        ));
+        let mut reader = fs
+            .open_with("/table.dat", OpenOptions::new().read(true))
+            .unwrap();
+        assert_eq!(reader.backend(), FailoverBackend::Primary);
+        let mut head = [0; 4];
+        reader.read_exact(&mut head).unwrap();
+        let mut other = fs.open_file("/table.dat").unwrap();
+
+        // Read-only handles resume on the secondary, others fail
+        store.down.store(true, Ordering::Release);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"456789");
+        assert_eq!(reader.backend(), FailoverBackend::Secondary);
+        assert_eq!(fs.active(), FailoverBackend::Secondary);
+        assert!(other.read_to_end(&mut rest).is_err());
+
+        // Redirected to the secondary until the primary is probed
+        fs.write("/log.dat", b"entry").unwrap();
+        assert_eq!(secondary.read("/log.dat").unwrap(), b"entry");
+        assert_eq!(fs.check_health(), FailoverBackend::Secondary);
+        store.down.store(false, Ordering::Release);
+        assert_eq!(fs.check_health(), FailoverBackend::Primary);
+        assert!(!fs.exists("/log.dat").unwrap());
+
+        // ...which happens once the probe interval passes
+        store.down.store(true, Ordering::Release);
+        assert!(fs.exists("/log.dat").unwrap());
+        store.down.store(false, Ordering::Release);
+        assert!(fs.exists("/log.dat").unwrap());
+        assert_eq!(fs.active(), FailoverBackend::Secondary);
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!fs.exists("/log.dat").unwrap());
+        assert_eq!(fs.active(), FailoverBackend::Primary);
+        assert_eq!(fs.failovers(), 2);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "failed over",
+                "probe failed",
+                "failed back",
+                "failed over",
+                "failed back"
+            ]
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_failover_conformance() {
+        crate::conformance::run(|| {
+            FailoverFileSystem::new(MemoryFileSystem::new(), MemoryFileSystem::new())
+        });
+    }
+}
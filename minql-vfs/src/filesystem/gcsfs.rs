@@ -0,0 +1,225 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::httpstore::{
+    query_string, send, uri_encode, xml_abort_multipart, xml_complete_multipart,
+    xml_create_multipart, xml_delete, xml_get_range, xml_get_versioned, xml_head, xml_list,
+    xml_put, xml_put_if, xml_upload_part, HttpObjectClient, HttpResponse, ListPaging,
+};
+use crate::filesystem::objectfs::{ObjectListing, ObjectMeta, ObjectStore, PutCondition};
+use crate::{FileSystemError, FileSystemProvider, FileSystemResult, ObjectStoreFileSystem};
+use minql_uri::URI;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Google Cloud Storage Object Store Client
+///
+/// Issues path-style requests (`{endpoint}/{bucket}/{key}`) against the Cloud Storage XML API,
+/// authorized with an OAuth 2.0 bearer token. Requests are sent anonymously when no token is
+/// provided, which suffices for public buckets and local emulators.
+pub struct GcsObjectStore {
+    endpoint: String,
+    bucket: String,
+    access_token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl GcsObjectStore {
+    /// Default Cloud Storage XML API endpoint.
+    pub const DEFAULT_ENDPOINT: &'static str = "https://storage.googleapis.com";
+
+    /// Create a new Cloud Storage client for a bucket.
+    #[must_use]
+    pub fn new(endpoint: &str, bucket: &str) -> GcsObjectStore {
+        GcsObjectStore {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            bucket: bucket.to_string(),
+            access_token: None,
+            agent: ureq::AgentBuilder::new().build(),
+        }
+    }
+
+    /// Attach an OAuth 2.0 access token used to authorize requests.
+    #[must_use]
+    pub fn with_access_token(mut self, token: &str) -> GcsObjectStore {
+        self.access_token = Some(token.to_string());
+        self
+    }
+}
+
+impl HttpObjectClient for GcsObjectStore {
+    const SERVICE: &'static str = "GCS";
+    const PAGING: ListPaging = ListPaging::Marker;
+    const VERSION_HEADER: &'static str = "x-goog-generation";
+
+    fn condition_header(condition: &PutCondition) -> (&'static str, String) {
+        match condition {
+            PutCondition::Absent => ("x-goog-if-generation-match", String::from("0")),
+            PutCondition::Matches(version) => ("x-goog-if-generation-match", version.clone()),
+        }
+    }
+
+    /// Authorize and send a request.
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> FileSystemResult<HttpResponse> {
+        let mut url = format!(
+            "{}/{}/{}",
+            self.endpoint,
+            uri_encode(&self.bucket, false),
+            uri_encode(key, false)
+        );
+        let query = query_string(query);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query);
+        }
+        let authorization = self
+            .access_token
+            .as_ref()
+            .map(|token| format!("Bearer {token}"));
+        let headers = headers.iter().copied().chain(
+            authorization
+                .as_deref()
+                .map(|authorization| ("Authorization", authorization)),
+        );
+        send(&self.agent, method, &url, headers, body)
+    }
+}
+
+impl std::fmt::Debug for GcsObjectStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GcsObjectStore({}/{})", self.endpoint, self.bucket)
+    }
+}
+
+impl ObjectStore for GcsObjectStore {
+    fn head(&self, key: &str) -> FileSystemResult<Option<ObjectMeta>> {
+        xml_head(self, key)
+    }
+
+    fn get_range(&self, key: &str, offset: u64, length: u64) -> FileSystemResult<Vec<u8>> {
+        xml_get_range(self, key, offset, length)
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> FileSystemResult<()> {
+        xml_put(self, key, data)
+    }
+
+    fn delete(&self, key: &str) -> FileSystemResult<()> {
+        xml_delete(self, key)
+    }
+
+    fn list(&self, prefix: &str, delimiter: Option<char>) -> FileSystemResult<ObjectListing> {
+        xml_list(self, prefix, delimiter)
+    }
+
+    fn create_multipart(&self, key: &str) -> FileSystemResult<String> {
+        xml_create_multipart(self, key)
+    }
+
+    fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: &[u8],
+    ) -> FileSystemResult<String> {
+        xml_upload_part(self, key, upload_id, part_number, data)
+    }
+
+    fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[(u32, String)],
+    ) -> FileSystemResult<()> {
+        xml_complete_multipart(self, key, upload_id, parts)
+    }
+
+    fn abort_multipart(&self, key: &str, upload_id: &str) -> FileSystemResult<()> {
+        xml_abort_multipart(self, key, upload_id)
+    }
+
+    fn get_versioned(&self, key: &str) -> FileSystemResult<Option<(Vec<u8>, String)>> {
+        xml_get_versioned(self, key)
+    }
+
+    fn put_if(
+        &self,
+        key: &str,
+        data: &[u8],
+        condition: &PutCondition,
+    ) -> FileSystemResult<Option<String>> {
+        xml_put_if(self, key, data, condition)
+    }
+}
+
+/// Google Cloud Storage `FileSystem` Provider
+///
+/// Provisions an [`ObjectStoreFileSystem`] for `gs://bucket/prefix` URIs. Recognized
+/// configuration keys are `endpoint` and `access_token`; they fall back to the
+/// `STORAGE_EMULATOR_HOST` and `GOOGLE_OAUTH_ACCESS_TOKEN` environment variables.
+#[derive(Debug, Default)]
+pub struct GcsFileSystemProvider {
+    configuration: RwLock<HashMap<String, String>>,
+}
+
+impl GcsFileSystemProvider {
+    fn setting(&self, key: &str, env: &str) -> Option<String> {
+        let configuration = self.configuration.read().expect("Poisoned Lock");
+        configuration
+            .get(key)
+            .cloned()
+            .or_else(|| std::env::var(env).ok())
+    }
+}
+
+impl FileSystemProvider for GcsFileSystemProvider {
+    type FileSystem = ObjectStoreFileSystem;
+
+    fn schemes(&self) -> &[&str] {
+        &["gs"]
+    }
+
+    fn configure(&self, configuration: &HashMap<String, String>) -> FileSystemResult<()> {
+        let mut current = self.configuration.write().expect("Poisoned Lock");
+        current.extend(configuration.clone());
+        Ok(())
+    }
+
+    fn provision(&self, url: &str) -> FileSystemResult<ObjectStoreFileSystem> {
+        let uri = URI::parse(url)?;
+        let bucket = uri
+            .authority
+            .as_ref()
+            .map(|authority| authority.hostinfo.raw())
+            .ok_or_else(|| FileSystemError::invalid_path(url))?;
+        let endpoint = self
+            .setting("endpoint", "STORAGE_EMULATOR_HOST")
+            .unwrap_or_else(|| String::from(GcsObjectStore::DEFAULT_ENDPOINT));
+        let mut store = GcsObjectStore::new(&endpoint, &bucket);
+        if let Some(token) = self.setting("access_token", "GOOGLE_OAUTH_ACCESS_TOKEN") {
+            store = store.with_access_token(&token);
+        }
+        Ok(ObjectStoreFileSystem::new(store, &uri.path.to_string()))
+    }
+}
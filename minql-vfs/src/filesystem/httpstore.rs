@@ -0,0 +1,528 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Shared plumbing for the HTTP based [`ObjectStore`] clients.
+//!
+//! S3 and the Google Cloud Storage XML API speak the same dialect for objects, listings and
+//! multipart uploads and differ only in how requests are authorized and listings paginated, so
+//! that dialect lives here behind [`HttpObjectClient`]. The Azure client shares the transport,
+//! date and XML helpers but implements its own protocol.
+
+#[cfg(any(feature = "s3", feature = "gcs"))]
+use crate::filesystem::objectfs::{ObjectListing, ObjectMeta, PutCondition};
+use crate::{FileSystemError, FileSystemResult};
+#[cfg(any(feature = "s3", feature = "azure"))]
+use hmac::{Hmac, Mac};
+#[cfg(any(feature = "s3", feature = "azure"))]
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Response to an object store request.
+pub(crate) struct HttpResponse {
+    pub(crate) status: u16,
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Map a non-success status into a `FileSystemError`.
+    pub(crate) fn check(self, service: &str) -> FileSystemResult<HttpResponse> {
+        match self.status {
+            200..=299 => Ok(self),
+            404 => Err(FileSystemError::PathMissing),
+            401 | 403 => Err(FileSystemError::PermissionDenied),
+            status => Err(FileSystemError::InternalError(format!(
+                "{service} request failed with status {status}: {}",
+                String::from_utf8_lossy(&self.body)
+            ))),
+        }
+    }
+
+    /// Get a response header by its lowercase name.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    pub(crate) fn length(&self) -> Option<u64> {
+        self.header("content-length")
+            .and_then(|length| length.parse().ok())
+    }
+
+    pub(crate) fn text(&self) -> std::borrow::Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+}
+
+/// Send a request, treating error statuses as responses to be checked by the caller.
+pub(crate) fn send<'a>(
+    agent: &ureq::Agent,
+    method: &str,
+    url: &str,
+    headers: impl IntoIterator<Item = (&'a str, &'a str)>,
+    body: &[u8],
+) -> FileSystemResult<HttpResponse> {
+    let mut request = agent.request(method, url);
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    let response = match request.send_bytes(body) {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(err) => return Err(FileSystemError::wrap_error(err)),
+    };
+    let status = response.status();
+    let headers = response
+        .headers_names()
+        .into_iter()
+        .filter_map(|name| {
+            let value = response.header(&name)?.to_string();
+            Some((name.to_ascii_lowercase(), value))
+        })
+        .collect();
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut body)
+        .map_err(FileSystemError::io_error)?;
+    Ok(HttpResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+/// Client speaking the S3 XML dialect against a single bucket.
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) trait HttpObjectClient {
+    /// Name of the service used in error messages.
+    const SERVICE: &'static str;
+    /// How the service paginates bucket listings.
+    const PAGING: ListPaging;
+    /// Response header carrying the version of an object.
+    const VERSION_HEADER: &'static str;
+
+    /// Request header and value expressing a conditional write.
+    fn condition_header(condition: &PutCondition) -> (&'static str, String);
+
+    /// Authorize and send a request for `key`, or for the bucket itself if `key` is empty.
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> FileSystemResult<HttpResponse>;
+}
+
+/// Bucket listing pagination scheme.
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) enum ListPaging {
+    /// `list-type=2` listings resumed with `continuation-token`.
+    #[cfg_attr(not(feature = "s3"), allow(dead_code))]
+    ContinuationToken,
+    /// Listings resumed from `marker`.
+    #[cfg_attr(not(feature = "gcs"), allow(dead_code))]
+    Marker,
+}
+
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) fn xml_head<C: HttpObjectClient>(
+    client: &C,
+    key: &str,
+) -> FileSystemResult<Option<ObjectMeta>> {
+    match client
+        .request("HEAD", key, &[], &[], &[])?
+        .check(C::SERVICE)
+    {
+        Ok(response) => Ok(Some(ObjectMeta {
+            key: key.to_string(),
+            size: response.length().unwrap_or_default(),
+        })),
+        Err(FileSystemError::PathMissing) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) fn xml_get_range<C: HttpObjectClient>(
+    client: &C,
+    key: &str,
+    offset: u64,
+    length: u64,
+) -> FileSystemResult<Vec<u8>> {
+    if length == 0 {
+        return Ok(Vec::new());
+    }
+    let range = format!("bytes={offset}-{}", offset + length - 1);
+    let response = client.request("GET", key, &[], &[("range", &range)], &[])?;
+    if response.status == 416 {
+        return Ok(Vec::new());
+    }
+    Ok(response.check(C::SERVICE)?.body)
+}
+
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) fn xml_put<C: HttpObjectClient>(
+    client: &C,
+    key: &str,
+    data: &[u8],
+) -> FileSystemResult<()> {
+    client
+        .request("PUT", key, &[], &[], data)?
+        .check(C::SERVICE)?;
+    Ok(())
+}
+
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) fn xml_delete<C: HttpObjectClient>(client: &C, key: &str) -> FileSystemResult<()> {
+    match client
+        .request("DELETE", key, &[], &[], &[])?
+        .check(C::SERVICE)
+    {
+        Ok(_) | Err(FileSystemError::PathMissing) => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) fn xml_list<C: HttpObjectClient>(
+    client: &C,
+    prefix: &str,
+    delimiter: Option<char>,
+) -> FileSystemResult<ObjectListing> {
+    let delimiter = delimiter.map(String::from);
+    let mut listing = ObjectListing::default();
+    let mut token: Option<String> = None;
+    loop {
+        let mut query = vec![("prefix", prefix)];
+        if let Some(delimiter) = &delimiter {
+            query.push(("delimiter", delimiter));
+        }
+        match C::PAGING {
+            ListPaging::ContinuationToken => {
+                query.push(("list-type", "2"));
+                if let Some(token) = &token {
+                    query.push(("continuation-token", token));
+                }
+            }
+            ListPaging::Marker => {
+                if let Some(token) = &token {
+                    query.push(("marker", token));
+                }
+            }
+        }
+        let response = client
+            .request("GET", "", &query, &[], &[])?
+            .check(C::SERVICE)?;
+        let body = response.text();
+        let mut last = None;
+        for contents in xml_elements(&body, "Contents") {
+            let key = xml_text(contents, "Key").unwrap_or_default();
+            let size = xml_text(contents, "Size")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or_default();
+            last = Some(key.clone());
+            listing.objects.push(ObjectMeta { key, size });
+        }
+        for common in xml_elements(&body, "CommonPrefixes") {
+            if let Some(prefix) = xml_text(common, "Prefix") {
+                last = Some(prefix.clone());
+                listing.prefixes.push(prefix);
+            }
+        }
+        token = match (xml_text(&body, "IsTruncated").as_deref(), &C::PAGING) {
+            (Some("true"), ListPaging::ContinuationToken) => {
+                xml_text(&body, "NextContinuationToken")
+            }
+            // NextMarker is only returned for delimited listings, otherwise resume after the
+            // last entry returned.
+            (Some("true"), ListPaging::Marker) => xml_text(&body, "NextMarker").or(last),
+            _ => None,
+        };
+        if token.is_none() {
+            return Ok(listing);
+        }
+    }
+}
+
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) fn xml_create_multipart<C: HttpObjectClient>(
+    client: &C,
+    key: &str,
+) -> FileSystemResult<String> {
+    let response = client
+        .request("POST", key, &[("uploads", "")], &[], &[])?
+        .check(C::SERVICE)?;
+    xml_text(&response.text(), "UploadId").ok_or_else(|| {
+        FileSystemError::InternalError(format!(
+            "{} multipart response missing UploadId",
+            C::SERVICE
+        ))
+    })
+}
+
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) fn xml_upload_part<C: HttpObjectClient>(
+    client: &C,
+    key: &str,
+    upload_id: &str,
+    part_number: u32,
+    data: &[u8],
+) -> FileSystemResult<String> {
+    let part = part_number.to_string();
+    let query = [("partNumber", part.as_str()), ("uploadId", upload_id)];
+    let response = client
+        .request("PUT", key, &query, &[], data)?
+        .check(C::SERVICE)?;
+    let etag = response.header("etag").map(ToString::to_string);
+    etag.ok_or_else(|| {
+        FileSystemError::InternalError(format!("{} part response missing ETag", C::SERVICE))
+    })
+}
+
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) fn xml_complete_multipart<C: HttpObjectClient>(
+    client: &C,
+    key: &str,
+    upload_id: &str,
+    parts: &[(u32, String)],
+) -> FileSystemResult<()> {
+    let mut document = String::from("<CompleteMultipartUpload>");
+    for (number, etag) in parts {
+        let _ = write!(
+            document,
+            "<Part><PartNumber>{number}</PartNumber><ETag>{etag}</ETag></Part>"
+        );
+    }
+    document.push_str("</CompleteMultipartUpload>");
+    let response = client
+        .request(
+            "POST",
+            key,
+            &[("uploadId", upload_id)],
+            &[],
+            document.as_bytes(),
+        )?
+        .check(C::SERVICE)?;
+    // Completion can fail after the 200 status has been sent, reported in the body.
+    if xml_elements(&response.text(), "Error").next().is_some() {
+        return Err(FileSystemError::InternalError(response.text().to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) fn xml_abort_multipart<C: HttpObjectClient>(
+    client: &C,
+    key: &str,
+    upload_id: &str,
+) -> FileSystemResult<()> {
+    client
+        .request("DELETE", key, &[("uploadId", upload_id)], &[], &[])?
+        .check(C::SERVICE)?;
+    Ok(())
+}
+
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) fn xml_get_versioned<C: HttpObjectClient>(
+    client: &C,
+    key: &str,
+) -> FileSystemResult<Option<(Vec<u8>, String)>> {
+    let response = match client.request("GET", key, &[], &[], &[])?.check(C::SERVICE) {
+        Ok(response) => response,
+        Err(FileSystemError::PathMissing) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let version = response.header(C::VERSION_HEADER).ok_or_else(|| {
+        FileSystemError::InternalError(format!("{} response missing object version", C::SERVICE))
+    })?;
+    let version = version.to_string();
+    Ok(Some((response.body, version)))
+}
+
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub(crate) fn xml_put_if<C: HttpObjectClient>(
+    client: &C,
+    key: &str,
+    data: &[u8],
+    condition: &PutCondition,
+) -> FileSystemResult<Option<String>> {
+    let (name, value) = C::condition_header(condition);
+    let response = client.request("PUT", key, &[], &[(name, &value)], data)?;
+    // Conflicting concurrent conditional writes may be rejected rather than serialized.
+    if matches!(response.status, 409 | 412) {
+        return Ok(None);
+    }
+    let response = response.check(C::SERVICE)?;
+    Ok(response.header(C::VERSION_HEADER).map(ToString::to_string))
+}
+
+/// Percent encode per the `SigV4` rules, optionally encoding `/`.
+pub(crate) fn uri_encode(input: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(char::from(byte));
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+/// Encode a sorted query string from unencoded pairs.
+pub(crate) fn query_string(query: &[(&str, &str)]) -> String {
+    let mut query = query
+        .iter()
+        .map(|(k, v)| (uri_encode(k, true), uri_encode(v, true)))
+        .collect::<Vec<_>>();
+    query.sort();
+    query
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[cfg(feature = "s3")]
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(encoded, "{byte:02x}");
+    }
+    encoded
+}
+
+#[cfg(any(feature = "s3", feature = "azure"))]
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Broken down UTC time.
+#[cfg_attr(not(feature = "azure"), allow(dead_code))]
+pub(crate) struct CivilTime {
+    pub(crate) year: i64,
+    pub(crate) month: i64,
+    pub(crate) day: i64,
+    pub(crate) weekday: i64,
+    pub(crate) hour: u64,
+    pub(crate) minute: u64,
+    pub(crate) second: u64,
+}
+
+impl CivilTime {
+    pub(crate) fn new(time: SystemTime) -> CivilTime {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let days = i64::try_from(secs / 86_400).unwrap_or_default();
+        let rem = secs % 86_400;
+        // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        CivilTime {
+            year: yoe + era * 400 + i64::from(month <= 2),
+            month,
+            day,
+            // The epoch fell on a Thursday
+            weekday: (days + 4).rem_euclid(7),
+            hour: rem / 3600,
+            minute: (rem % 3600) / 60,
+            second: rem % 60,
+        }
+    }
+
+    /// Format as an RFC 1123 HTTP date.
+    #[cfg(feature = "azure")]
+    pub(crate) fn http_date(&self) -> String {
+        const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+        format!(
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            DAYS[usize::try_from(self.weekday).unwrap_or_default()],
+            self.day,
+            MONTHS[usize::try_from(self.month - 1).unwrap_or_default()],
+            self.year,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
+
+/// Iterate over the inner text of every `<tag>` element in a document.
+pub(crate) fn xml_elements<'a>(document: &'a str, tag: &str) -> impl Iterator<Item = &'a str> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let mut rest = document;
+    std::iter::from_fn(move || {
+        let start = rest.find(&open)? + open.len();
+        let end = rest[start..].find(&close)? + start;
+        let inner = &rest[start..end];
+        rest = &rest[end + close.len()..];
+        Some(inner)
+    })
+}
+
+/// Get the unescaped text of the first `<tag>` element in a document.
+pub(crate) fn xml_text(document: &str, tag: &str) -> Option<String> {
+    xml_elements(document, tag).next().map(|text| {
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{query_string, uri_encode, xml_text, CivilTime};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_http_helpers() {
+        assert_eq!(uri_encode("a b/c~", false), "a%20b/c~");
+        assert_eq!(uri_encode("a b/c~", true), "a%20b%2Fc~");
+        assert_eq!(query_string(&[("b", "x/y"), ("a", "")]), "a=&b=x%2Fy");
+        assert_eq!(
+            xml_text("<R><Key>a&amp;b</Key></R>", "Key").as_deref(),
+            Some("a&b")
+        );
+        let time = CivilTime::new(UNIX_EPOCH + Duration::from_mins(24_015_636));
+        assert_eq!((time.year, time.month, time.day), (2015, 8, 30));
+        assert_eq!((time.hour, time.minute, time.second), (12, 36, 0));
+        assert_eq!(time.weekday, 0);
+    }
+}
@@ -15,9 +15,21 @@
 //
 
 use crate::filesystem::FileLockMode;
-use crate::{FileHandle, FileSystem, FileSystemError, FileSystemResult};
+use crate::utility::normalize_segments;
+#[cfg(feature = "mmap")]
+use crate::FileMapping;
+use crate::{
+    Advice, CloneMethod, FileHandle, FileSystem, FileSystemError, FileSystemProvider,
+    FileSystemResult, FileSystemSpace, FileType, OpenOptions, Permissions, ReadAt, SymlinkPolicy,
+    SyncPolicy, WriteAt,
+};
 use fs2::FileExt;
-use std::io::{Read, Seek, SeekFrom, Write};
+use minql_uri::URI;
+use std::collections::HashMap;
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Instant, SystemTime};
 
 /// Local File System
 ///
@@ -37,18 +49,347 @@ use std::io::{Read, Seek, SeekFrom, Write};
 ///
 pub struct LocalFileSystem {
     root: std::path::PathBuf,
+    confine_symlinks: bool,
+    follow_symlinks: bool,
+    readonly: bool,
+    sync_policy: SyncPolicy,
+    file_mode: Option<u32>,
 }
 
 impl LocalFileSystem {
     /// Create a new `LocalFileSystem` with the provided root path.
+    ///
+    /// Paths are normalized before being joined onto the root, and any path using `..` to climb
+    /// above it is rejected with [`FileSystemError::InvalidPath`].
     pub fn new<T: AsRef<std::path::Path>>(root: T) -> Self {
         LocalFileSystem {
             root: root.as_ref().to_path_buf(),
+            confine_symlinks: false,
+            follow_symlinks: true,
+            readonly: false,
+            sync_policy: SyncPolicy::OnRequest,
+            file_mode: None,
         }
     }
+
+    /// Start configuring a `LocalFileSystem` with the provided root path.
+    ///
+    /// ```rust
+    /// use minql_vfs::{FileSystem, FileSystemError, LocalFileSystem, SyncPolicy};
+    ///
+    /// let root = std::env::temp_dir().join("builder-doc");
+    /// let fs = LocalFileSystem::builder(&root)
+    ///     .create_root_if_missing(true)
+    ///     .sync_policy(SyncPolicy::OnClose)
+    ///     .file_mode(0o600)
+    ///     .build()
+    ///     .unwrap();
+    /// fs.write("/config.toml", b"threads = 4").unwrap();
+    ///
+    /// let readonly = LocalFileSystem::builder(&root).readonly(true).build().unwrap();
+    /// assert_eq!(readonly.read("/config.toml").unwrap(), b"threads = 4");
+    /// assert!(matches!(
+    ///     readonly.remove_file("/config.toml"),
+    ///     Err(FileSystemError::PermissionDenied)
+    /// ));
+    /// # std::fs::remove_dir_all(root).unwrap();
+    /// ```
+    pub fn builder<T: AsRef<std::path::Path>>(root: T) -> LocalFileSystemBuilder {
+        LocalFileSystemBuilder {
+            filesystem: LocalFileSystem::new(root),
+            create_root: false,
+        }
+    }
+
+    /// Whether every modification is rejected.
+    #[must_use]
+    pub fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// Policy applied to the handles this filesystem opens.
+    #[must_use]
+    pub fn sync_policy(&self) -> SyncPolicy {
+        self.sync_policy
+    }
+
+    /// Reject paths that symbolic links resolve outside the root.
+    ///
+    /// Links are otherwise followed wherever they point. When confined, the existing part of
+    /// every path is canonicalized on each call, and dangling links are checked against where
+    /// they would create their target, so a path escaping the root fails with
+    /// [`FileSystemError::InvalidPath`].
+    ///
+    /// ```rust
+    /// use minql_vfs::{FileSystem, LocalFileSystem};
+    ///
+    /// let fs = LocalFileSystem::new(std::env::temp_dir()).confine_symlinks(true);
+    /// assert!(fs.open_file("/../../etc/passwd").is_err());
+    /// ```
+    #[must_use]
+    pub fn confine_symlinks(mut self, confine: bool) -> Self {
+        self.confine_symlinks = confine;
+        self
+    }
+
+    /// Fail with [`FileSystemError::PermissionDenied`] if this filesystem is read-only.
+    fn writable(&self) -> FileSystemResult<()> {
+        if self.readonly {
+            return Err(FileSystemError::PermissionDenied);
+        }
+        Ok(())
+    }
+
+    /// OS options for opening a file, creating it with the configured mode.
+    fn file_options(&self) -> std::fs::OpenOptions {
+        let mut options = std::fs::File::options();
+        #[cfg(unix)]
+        if let Some(mode) = self.file_mode {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(mode);
+        }
+        options
+    }
+
+    /// Reject a symbolic link at `absolute` unless links are followed.
+    fn check_follow(&self, absolute: &std::path::Path, follow: bool) -> FileSystemResult<()> {
+        if !(follow && self.follow_symlinks)
+            && std::fs::symlink_metadata(absolute)
+                .is_ok_and(|metadata| metadata.file_type().is_symlink())
+        {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        Ok(())
+    }
+
+    /// Wrap an opened file in a handle applying the sync policy.
+    fn handle(
+        &self,
+        path: std::path::PathBuf,
+        file: std::fs::File,
+        direct: bool,
+    ) -> LocalFileHandle {
+        LocalFileHandle {
+            path,
+            file,
+            lock: FileLockMode::Unlocked,
+            direct,
+            advice: Advice::Normal,
+            policy: self.sync_policy,
+            dirty: AtomicBool::new(false),
+            synced: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Apply the sync policy to a file written through without a handle, as it's closed.
+    fn closing_written(&self, file: &std::fs::File) -> FileSystemResult<()> {
+        match self.sync_policy {
+            SyncPolicy::Never | SyncPolicy::OnRequest => Ok(()),
+            SyncPolicy::OnClose | SyncPolicy::EveryWrite | SyncPolicy::Interval(_) => {
+                file.sync_data().map_err(io_error_to_file_system_error)
+            }
+        }
+    }
+
+    /// Map a path onto the OS path beneath the root, rejecting any path that escapes it.
+    ///
+    /// On Windows, segments naming devices, alternate data streams or drive-relative paths are
+    /// rejected, and paths too long for the Win32 APIs are given the `\\?\` extended-length
+    /// prefix.
     #[tracing::instrument(level = "trace")]
-    fn absolute_path(&self, path: &str) -> std::path::PathBuf {
-        self.root.join(path.trim_start_matches('/'))
+    fn absolute_path(&self, path: &str) -> FileSystemResult<std::path::PathBuf> {
+        let segments = normalize_segments(path)?;
+        if cfg!(windows) && !segments.iter().all(|segment| is_windows_segment(segment)) {
+            return Err(FileSystemError::invalid_path(path));
+        }
+        let mut absolute = self.root.clone();
+        absolute.extend(segments);
+        #[cfg(windows)]
+        let absolute = {
+            let absolute = std::path::absolute(absolute).map_err(io_error_to_file_system_error)?;
+            match absolute.to_str().and_then(extended_length) {
+                Some(extended) => std::path::PathBuf::from(extended),
+                None => absolute,
+            }
+        };
+        if self.confine_symlinks && !self.confined(&absolute) {
+            return Err(FileSystemError::invalid_path(path));
+        }
+        Ok(absolute)
+    }
+
+    /// Check the existing part of `absolute` resolves beneath the root, following dangling
+    /// links to where they would create their target.
+    fn confined(&self, absolute: &std::path::Path) -> bool {
+        let Ok(root) = self.root.canonicalize() else {
+            // Nothing exists beneath a missing root, so no link can lead out of it
+            return true;
+        };
+        let mut current = absolute.to_path_buf();
+        for _ in 0..MAX_SYMLINK_HOPS {
+            if let Ok(resolved) = current.canonicalize() {
+                return resolved.starts_with(&root);
+            }
+            current = match std::fs::read_link(&current) {
+                Ok(target) => match current.parent() {
+                    Some(parent) => parent.join(target),
+                    None => target,
+                },
+                Err(_) => match current.parent() {
+                    Some(parent) => parent.to_path_buf(),
+                    None => return true,
+                },
+            };
+        }
+        false
+    }
+}
+
+/// Most dangling symbolic links followed while confining a path.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Longest path the Win32 APIs accept without the extended-length prefix. Files may run to
+/// 260 characters, but directories stop at 248 to leave room for an 8.3 file name.
+const MAX_WIN32_PATH: usize = 248;
+
+/// Names Windows reserves for devices in every directory, whatever their extension.
+const RESERVED_NAMES: [&str; 30] = [
+    "CON",
+    "PRN",
+    "AUX",
+    "NUL",
+    "COM0",
+    "COM1",
+    "COM2",
+    "COM3",
+    "COM4",
+    "COM5",
+    "COM6",
+    "COM7",
+    "COM8",
+    "COM9",
+    "COM\u{b9}",
+    "COM\u{b2}",
+    "COM\u{b3}",
+    "LPT0",
+    "LPT1",
+    "LPT2",
+    "LPT3",
+    "LPT4",
+    "LPT5",
+    "LPT6",
+    "LPT7",
+    "LPT8",
+    "LPT9",
+    "LPT\u{b9}",
+    "LPT\u{b2}",
+    "LPT\u{b3}",
+];
+
+/// Check a path segment names an ordinary file on Windows, rather than a device, an alternate
+/// data stream or a drive-relative path, and won't have trailing dots or spaces stripped.
+fn is_windows_segment(segment: &str) -> bool {
+    if segment.contains(':') || segment.ends_with(['.', ' ']) {
+        return false;
+    }
+    let stem = segment.split('.').next().unwrap_or(segment).trim_end();
+    !RESERVED_NAMES
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(stem))
+}
+
+/// Give an absolute Windows path too long for the Win32 APIs the `\\?\` extended-length
+/// prefix, turning `\\server\share` UNC paths into `\\?\UNC\server\share`.
+///
+/// Extended-length paths bypass Win32 normalization, so the path must already be free of `.`
+/// and `..` segments. Forward slashes are converted, and paths which are short, already
+/// prefixed or not absolute are left alone.
+fn extended_length(path: &str) -> Option<String> {
+    if path.len() < MAX_WIN32_PATH || path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return None;
+    }
+    let path = path.replace('/', "\\");
+    if let Some(unc) = path.strip_prefix(r"\\") {
+        Some(format!(r"\\?\UNC\{unc}"))
+    } else if path.as_bytes()[0].is_ascii_alphabetic() && path[1..].starts_with(":\\") {
+        Some(format!(r"\\?\{path}"))
+    } else {
+        None
+    }
+}
+
+/// Strip the extended-length prefix [`extended_length`] adds, the inverse of that function.
+fn strip_extended_length(path: &str) -> std::borrow::Cow<'_, str> {
+    if let Some(unc) = path.strip_prefix(r"\\?\UNC\") {
+        std::borrow::Cow::Owned(format!(r"\\{unc}"))
+    } else {
+        std::borrow::Cow::Borrowed(path.strip_prefix(r"\\?\").unwrap_or(path))
+    }
+}
+
+/// Builder for a [`LocalFileSystem`], returned by [`LocalFileSystem::builder`].
+#[derive(Debug)]
+pub struct LocalFileSystemBuilder {
+    filesystem: LocalFileSystem,
+    create_root: bool,
+}
+
+impl LocalFileSystemBuilder {
+    /// Reject paths that symbolic links resolve outside the root. See
+    /// [`LocalFileSystem::confine_symlinks`].
+    #[must_use]
+    pub fn confine_symlinks(mut self, confine: bool) -> Self {
+        self.filesystem.confine_symlinks = confine;
+        self
+    }
+
+    /// Whether files are opened through a symbolic link at the end of their path. When `false`,
+    /// every open behaves as if [`OpenOptions::no_follow`] was set. Defaults to `true`.
+    #[must_use]
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.filesystem.follow_symlinks = follow;
+        self
+    }
+
+    /// Create the root directory and any missing parents when built. Defaults to `false`.
+    #[must_use]
+    pub fn create_root_if_missing(mut self, create: bool) -> Self {
+        self.create_root = create;
+        self
+    }
+
+    /// Reject every modification with [`FileSystemError::PermissionDenied`] and open files for
+    /// reading only. Defaults to `false`.
+    #[must_use]
+    pub fn readonly(mut self, readonly: bool) -> Self {
+        self.filesystem.readonly = readonly;
+        self
+    }
+
+    /// When handles make the data written through them durable, also applied to
+    /// [`FileSystem::write`] and [`FileSystem::append`]. Defaults to
+    /// [`SyncPolicy::OnRequest`].
+    #[must_use]
+    pub fn sync_policy(mut self, policy: SyncPolicy) -> Self {
+        self.filesystem.sync_policy = policy;
+        self
+    }
+
+    /// Permission bits given to files this filesystem creates, before the process umask is
+    /// applied. Only used on unix, and defaults to `0o666`.
+    #[must_use]
+    pub fn file_mode(mut self, mode: u32) -> Self {
+        self.filesystem.file_mode = Some(mode & 0o7777);
+        self
+    }
+
+    /// Build the filesystem, creating the root first if asked to.
+    pub fn build(self) -> FileSystemResult<LocalFileSystem> {
+        if self.create_root {
+            std::fs::create_dir_all(&self.filesystem.root)
+                .map_err(io_error_to_file_system_error)?;
+        }
+        Ok(self.filesystem)
     }
 }
 
@@ -63,40 +404,42 @@ impl FileSystem for LocalFileSystem {
 
     #[tracing::instrument(level = "trace")]
     fn exists(&self, path: &str) -> FileSystemResult<bool> {
-        Ok(self.absolute_path(path).exists())
+        Ok(self.absolute_path(path)?.exists())
     }
 
     #[tracing::instrument(level = "trace")]
     fn is_file(&self, path: &str) -> FileSystemResult<bool> {
-        Ok(self.absolute_path(path).is_file())
+        Ok(self.absolute_path(path)?.is_file())
     }
 
     #[tracing::instrument(level = "trace")]
     fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
-        Ok(self.absolute_path(path).is_dir())
+        Ok(self.absolute_path(path)?.is_dir())
     }
 
     #[tracing::instrument(level = "trace")]
     fn filesize(&self, path: &str) -> FileSystemResult<u64> {
-        std::fs::metadata(self.absolute_path(path))
+        std::fs::metadata(self.absolute_path(path)?)
             .map(|m| m.len())
             .map_err(io_error_to_file_system_error)
     }
 
     #[tracing::instrument(level = "trace")]
     fn create_directory(&self, path: &str) -> FileSystemResult<()> {
-        std::fs::create_dir(self.absolute_path(path)).map_err(io_error_to_file_system_error)
+        self.writable()?;
+        std::fs::create_dir(self.absolute_path(path)?).map_err(io_error_to_file_system_error)
     }
 
     #[tracing::instrument(level = "trace")]
     fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
-        std::fs::create_dir_all(self.absolute_path(path)).map_err(io_error_to_file_system_error)
+        self.writable()?;
+        std::fs::create_dir_all(self.absolute_path(path)?).map_err(io_error_to_file_system_error)
     }
 
     #[tracing::instrument(level = "trace")]
-    fn list_directory<'a>(&self, path: &str) -> FileSystemResult<Vec<String>> {
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
         let rd =
-            std::fs::read_dir(self.absolute_path(path)).map_err(io_error_to_file_system_error)?;
+            std::fs::read_dir(self.absolute_path(path)?).map_err(io_error_to_file_system_error)?;
         let x = rd
             .filter_map(Result::ok)
             .filter_map(|r| r.file_name().into_string().ok())
@@ -106,43 +449,363 @@ impl FileSystem for LocalFileSystem {
 
     #[tracing::instrument(level = "trace")]
     fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
-        std::fs::remove_dir(self.absolute_path(path)).map_err(io_error_to_file_system_error)
+        self.writable()?;
+        std::fs::remove_dir(self.absolute_path(path)?).map_err(io_error_to_file_system_error)
     }
 
     #[tracing::instrument(level = "trace")]
     fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
-        std::fs::remove_dir_all(self.absolute_path(path)).map_err(io_error_to_file_system_error)
+        self.writable()?;
+        std::fs::remove_dir_all(self.absolute_path(path)?).map_err(io_error_to_file_system_error)
     }
 
     #[tracing::instrument(level = "trace")]
     fn create_file(&self, path: &str) -> FileSystemResult<LocalFileHandle> {
-        std::fs::File::options()
+        self.writable()?;
+        let absolute = self.absolute_path(path)?;
+        let file = self
+            .file_options()
             .read(true)
             .write(true)
             .create_new(true)
-            .open(self.absolute_path(path))
-            .map(|file| LocalFileHandle {
-                path: self.root.join(path.trim_start_matches('/')),
-                file,
-                lock: FileLockMode::Unlocked,
-            })
-            .map_err(io_error_to_file_system_error)
+            .open(&absolute)
+            .map_err(io_error_to_file_system_error)?;
+        Ok(self.handle(absolute, file, false))
     }
 
+    /// Files are opened for reading and writing, or only for reading if the filesystem was
+    /// built [`readonly`](LocalFileSystemBuilder::readonly). A file or storage that doesn't
+    /// permit writes fails to open, so open it with [`FileSystem::open_with`] to read it alone.
     #[tracing::instrument(level = "trace")]
     fn open_file(&self, path: &str) -> FileSystemResult<LocalFileHandle> {
-        std::fs::File::open(self.absolute_path(path))
-            .map(|file| LocalFileHandle {
-                path: self.root.join(path),
-                file,
-                lock: FileLockMode::Unlocked,
-            })
-            .map_err(io_error_to_file_system_error)
+        if self.readonly {
+            tracing::debug!(path, "Opening file read-only on a read-only filesystem");
+            return self.open_with(path, OpenOptions::new().read(true));
+        }
+        self.open_with(path, OpenOptions::new().read(true).write(true))
     }
 
     #[tracing::instrument(level = "trace")]
     fn remove_file(&self, path: &str) -> FileSystemResult<()> {
-        std::fs::remove_file(self.absolute_path(path)).map_err(io_error_to_file_system_error)
+        self.writable()?;
+        std::fs::remove_file(self.absolute_path(path)?).map_err(io_error_to_file_system_error)
+    }
+
+    /// The mode is the permission bits of the entry on unix. Elsewhere only the read-only flag
+    /// maps onto the OS.
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        let metadata =
+            std::fs::metadata(self.absolute_path(path)?).map_err(io_error_to_file_system_error)?;
+        let permissions = Permissions::new().readonly(metadata.permissions().readonly());
+        #[cfg(unix)]
+        let permissions = {
+            use std::os::unix::fs::PermissionsExt;
+            permissions.mode(metadata.permissions().mode())
+        };
+        Ok(permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        std::fs::metadata(self.absolute_path(path)?)
+            .and_then(|metadata| metadata.modified())
+            .map_err(io_error_to_file_system_error)
+    }
+
+    /// On unix a mode replaces the permission bits of the entry, and read-only clears every
+    /// write bit while writable without a mode restores the owner's. Elsewhere the mode is
+    /// ignored.
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.writable()?;
+        let path = self.absolute_path(path)?;
+        let mut os_permissions = std::fs::metadata(&path)
+            .map_err(io_error_to_file_system_error)?
+            .permissions();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = permissions
+                .get_mode()
+                .unwrap_or((os_permissions.mode() & 0o7777) | 0o200);
+            os_permissions.set_mode(if permissions.is_readonly() {
+                mode & !0o222
+            } else {
+                mode
+            });
+        }
+        #[cfg(not(unix))]
+        #[allow(clippy::permissions_set_readonly_false)]
+        os_permissions.set_readonly(permissions.is_readonly());
+        std::fs::set_permissions(path, os_permissions).map_err(io_error_to_file_system_error)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        let path = self.absolute_path(path)?;
+        let metadata = match policy {
+            SymlinkPolicy::Follow => std::fs::metadata(path),
+            SymlinkPolicy::NoFollow => std::fs::symlink_metadata(path),
+        }
+        .map_err(io_error_to_file_system_error)?;
+        let file_type = metadata.file_type();
+        Ok(if file_type.is_symlink() {
+            FileType::Symlink
+        } else if file_type.is_dir() {
+            FileType::Directory
+        } else {
+            FileType::File
+        })
+    }
+
+    /// Absolute targets are written as OS paths beneath the root, so the link still points
+    /// inside this filesystem when followed by the OS. Windows picks a file or directory link
+    /// from the target, which must exist to be linked as a directory.
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        self.writable()?;
+        if target.is_empty() {
+            return Err(FileSystemError::InvalidPath(target.to_string()));
+        }
+        let link = self.absolute_path(path)?;
+        let target = if target.starts_with(['/', '\\']) {
+            std::path::absolute(self.absolute_path(target)?)
+                .map_err(io_error_to_file_system_error)?
+        } else {
+            if self.confine_symlinks {
+                let parent = path
+                    .rsplit_once(['/', '\\'])
+                    .map_or("", |(parent, _)| parent);
+                self.absolute_path(&format!("{parent}/{target}"))?;
+            }
+            std::path::PathBuf::from(target)
+        };
+        #[cfg(unix)]
+        let result = std::os::unix::fs::symlink(target, link);
+        #[cfg(windows)]
+        let result = match link.parent().map(|parent| parent.join(&target)) {
+            Some(resolved) if resolved.is_dir() => std::os::windows::fs::symlink_dir(target, link),
+            _ => std::os::windows::fs::symlink_file(target, link),
+        };
+        #[cfg(not(any(unix, windows)))]
+        let result = Err(std::io::Error::from(std::io::ErrorKind::Unsupported));
+        result.map_err(io_error_to_file_system_error)
+    }
+
+    /// Targets beneath the root are returned as absolute paths within this filesystem, and
+    /// relative targets as written.
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        let link = self.absolute_path(path)?;
+        let metadata = std::fs::symlink_metadata(&link).map_err(io_error_to_file_system_error)?;
+        if !metadata.file_type().is_symlink() {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        let target = std::fs::read_link(link).map_err(io_error_to_file_system_error)?;
+        #[cfg(windows)]
+        let target = match target.to_str() {
+            Some(extended) => std::path::PathBuf::from(strip_extended_length(extended).as_ref()),
+            None => target,
+        };
+        let root = std::path::absolute(&self.root).map_err(io_error_to_file_system_error)?;
+        let target = match target.strip_prefix(&root) {
+            Ok(relative) if target.is_absolute() => format!("/{}", relative.to_string_lossy()),
+            _ => target.to_string_lossy().into_owned(),
+        };
+        Ok(target.replace(std::path::MAIN_SEPARATOR, "/"))
+    }
+
+    /// Direct I/O is supported on Linux, Android and FreeBSD through `O_DIRECT` and on Windows
+    /// through `FILE_FLAG_NO_BUFFERING`. Filesystems that don't support it, such as `tmpfs`, fail
+    /// with [`FileSystemError::UnsupportedOperation`].
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<LocalFileHandle> {
+        if options.is_write()
+            || options.is_append()
+            || options.is_truncate()
+            || options.is_create()
+            || options.is_create_new()
+        {
+            self.writable()?;
+        }
+        let mut std_options = self.file_options();
+        std_options
+            .read(options.is_read())
+            .write(options.is_write())
+            .append(options.is_append())
+            .truncate(options.is_truncate())
+            .create(options.is_create())
+            .create_new(options.is_create_new());
+        if options.is_direct() {
+            enable_direct_io(&mut std_options)?;
+        }
+        let absolute = self.absolute_path(path)?;
+        self.check_follow(&absolute, !options.is_no_follow())?;
+        let file = std_options
+            .open(&absolute)
+            .map_err(|err| match err.raw_os_error() {
+                #[cfg(unix)]
+                Some(nix::libc::EINVAL) if options.is_direct() => {
+                    FileSystemError::UnsupportedOperation
+                }
+                _ => io_error_to_file_system_error(err),
+            })?;
+        Ok(self.handle(absolute, file, options.is_direct()))
+    }
+
+    /// Uses `FICLONE` on Linux, `clonefile` on macOS and block cloning on Windows where the
+    /// volume supports it, falling back to an OS copy.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        self.writable()?;
+        let cloned =
+            reflink_copy::reflink_or_copy(self.absolute_path(src)?, self.absolute_path(dst)?)
+                .map_err(io_error_to_file_system_error)?;
+        Ok(match cloned {
+            None => CloneMethod::Reflink,
+            Some(_) => CloneMethod::Copy,
+        })
+    }
+
+    /// Renames the entry in place with the OS, which is atomic when both paths are on the same
+    /// volume.
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        self.writable()?;
+        let from = self.absolute_path(from)?;
+        let to = self.absolute_path(to)?;
+        let source = std::fs::symlink_metadata(&from).map_err(io_error_to_file_system_error)?;
+        if source.is_dir() || to.is_dir() {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        std::fs::rename(from, to).map_err(io_error_to_file_system_error)
+    }
+
+    /// Reports the space of the volume holding the root directory, where `available` only
+    /// counts the blocks this process may use.
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        let stats = fs2::statvfs(&self.root).map_err(FileSystemError::io_error)?;
+        Ok(FileSystemSpace {
+            total: stats.total_space(),
+            used: stats.total_space().saturating_sub(stats.free_space()),
+            available: stats.available_space(),
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        std::fs::read(self.absolute_path(path)?).map_err(io_error_to_file_system_error)
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        self.writable()?;
+        let absolute = self.absolute_path(path)?;
+        self.check_follow(&absolute, true)?;
+        let mut file = self
+            .file_options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(absolute)
+            .map_err(io_error_to_file_system_error)?;
+        file.write_all(contents)
+            .map_err(io_error_to_file_system_error)?;
+        self.closing_written(&file)
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn append(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        self.writable()?;
+        let absolute = self.absolute_path(path)?;
+        self.check_follow(&absolute, true)?;
+        let mut file = self
+            .file_options()
+            .append(true)
+            .create(true)
+            .open(absolute)
+            .map_err(io_error_to_file_system_error)?;
+        file.write_all(contents)
+            .map_err(io_error_to_file_system_error)?;
+        self.closing_written(&file)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+#[allow(clippy::unnecessary_wraps)]
+fn enable_direct_io(options: &mut std::fs::OpenOptions) -> FileSystemResult<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.custom_flags(nix::libc::O_DIRECT);
+    Ok(())
+}
+
+#[cfg(windows)]
+#[allow(clippy::unnecessary_wraps)]
+fn enable_direct_io(options: &mut std::fs::OpenOptions) -> FileSystemResult<()> {
+    use std::os::windows::fs::OpenOptionsExt;
+    const FILE_FLAG_NO_BUFFERING: u32 = 0x2000_0000;
+    options.custom_flags(FILE_FLAG_NO_BUFFERING);
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    windows
+)))]
+fn enable_direct_io(options: &mut std::fs::OpenOptions) -> FileSystemResult<()> {
+    Err(FileSystemError::UnsupportedOperation)
+}
+
+/// Local `FileSystem` Provider
+///
+/// Provisions a [`LocalFileSystem`] rooted at the path of `file:///path/to/root` URIs, written
+/// `file:///C:/path/to/root` for Windows drives. Setting the `confine_symlinks`,
+/// `create_root_if_missing` or `readonly` configuration keys to `true` enables the matching
+/// [`LocalFileSystemBuilder`] option for every filesystem provisioned afterwards.
+#[derive(Debug, Default)]
+pub struct LocalFileSystemProvider {
+    configuration: RwLock<HashMap<String, String>>,
+}
+
+impl FileSystemProvider for LocalFileSystemProvider {
+    type FileSystem = LocalFileSystem;
+
+    fn schemes(&self) -> &[&str] {
+        &["file"]
+    }
+
+    fn configure(&self, configuration: &HashMap<String, String>) -> FileSystemResult<()> {
+        let mut current = self.configuration.write().expect("Poisoned Lock");
+        current.extend(configuration.clone());
+        Ok(())
+    }
+
+    fn provision(&self, url: &str) -> FileSystemResult<LocalFileSystem> {
+        let uri = URI::parse(url)?;
+        let host = uri
+            .authority
+            .as_ref()
+            .map(|authority| authority.hostinfo.raw())
+            .unwrap_or_default();
+        let path = uri.path.to_string();
+        if !(host.is_empty() || host == "localhost") || path.is_empty() {
+            return Err(FileSystemError::invalid_path(url));
+        }
+        let root = match path.strip_prefix('/') {
+            Some(drive) if cfg!(windows) && drive.as_bytes().get(1) == Some(&b':') => drive,
+            _ => path.as_str(),
+        };
+        let configuration = self.configuration.read().expect("Poisoned Lock");
+        let enabled = |key: &str| configuration.get(key).is_some_and(|value| value == "true");
+        LocalFileSystem::builder(root)
+            .confine_symlinks(enabled("confine_symlinks"))
+            .create_root_if_missing(enabled("create_root_if_missing"))
+            .readonly(enabled("readonly"))
+            .build()
     }
 }
 
@@ -151,6 +814,79 @@ pub struct LocalFileHandle {
     path: std::path::PathBuf,
     file: std::fs::File,
     lock: FileLockMode,
+    direct: bool,
+    advice: Advice,
+    policy: SyncPolicy,
+    /// Written to since the last sync
+    dirty: AtomicBool,
+    synced: Mutex<Instant>,
+}
+
+impl LocalFileHandle {
+    /// Borrow the underlying file.
+    pub(crate) fn file(&self) -> &std::fs::File {
+        &self.file
+    }
+
+    /// Flush written data to storage and restart the sync interval.
+    fn sync(&self) -> std::io::Result<()> {
+        self.file.sync_data()?;
+        self.dirty.store(false, Ordering::Relaxed);
+        *self.synced.lock().expect("Poisoned Lock") = Instant::now();
+        Ok(())
+    }
+
+    /// Apply the sync policy after a write.
+    fn written(&self) -> std::io::Result<()> {
+        self.dirty.store(true, Ordering::Relaxed);
+        match self.policy {
+            SyncPolicy::EveryWrite => self.sync(),
+            SyncPolicy::Interval(interval)
+                if self.synced.lock().expect("Poisoned Lock").elapsed() >= interval =>
+            {
+                self.sync()
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Uses `pread` on unix. On Windows the read moves the cursor of the handle.
+impl ReadAt for LocalFileHandle {
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        #[cfg(unix)]
+        let read = std::os::unix::fs::FileExt::read_at(&self.file, buffer, offset);
+        #[cfg(windows)]
+        let read = std::os::windows::fs::FileExt::seek_read(&self.file, buffer, offset);
+        read.map_err(io_error_to_file_system_error)
+    }
+}
+
+/// Uses `pwrite` on unix. On Windows the write moves the cursor of the handle.
+impl WriteAt for LocalFileHandle {
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        #[cfg(unix)]
+        let written = std::os::unix::fs::FileExt::write_at(&self.file, buffer, offset);
+        #[cfg(windows)]
+        let written = std::os::windows::fs::FileExt::seek_write(&self.file, buffer, offset);
+        let written = written.map_err(io_error_to_file_system_error)?;
+        self.written().map_err(io_error_to_file_system_error)?;
+        Ok(written)
+    }
+}
+
+impl Drop for LocalFileHandle {
+    fn drop(&mut self) {
+        if *self.dirty.get_mut()
+            && matches!(self.policy, SyncPolicy::OnClose | SyncPolicy::Interval(_))
+        {
+            if let Err(err) = self.sync() {
+                tracing::warn!(?err, "Failed to sync written data on drop");
+            }
+        }
+    }
 }
 
 impl std::fmt::Debug for LocalFileHandle {
@@ -164,12 +900,26 @@ impl Read for LocalFileHandle {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         self.file.read(buf)
     }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        self.file.read_vectored(bufs)
+    }
 }
 
 impl Write for LocalFileHandle {
     #[tracing::instrument(level = "trace")]
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.file.write(buf)
+        let written = self.file.write(buf)?;
+        self.written()?;
+        Ok(written)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        let written = self.file.write_vectored(bufs)?;
+        self.written()?;
+        Ok(written)
     }
 
     #[tracing::instrument(level = "trace")]
@@ -203,20 +953,39 @@ impl FileHandle for LocalFileHandle {
     fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
         self.file
             .set_len(new_size)
+            .and_then(|()| self.written())
             .map_err(|e| FileSystemError::WrappedError(Box::new(e)))
     }
 
+    /// Uses `fallocate` on Linux, `F_PREALLOCATE` on macOS and `SetFileInformationByHandle` on
+    /// Windows.
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        FileExt::allocate(&self.file, len).map_err(io_error_to_file_system_error)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    /// Does nothing under [`SyncPolicy::Never`].
     #[tracing::instrument(level = "trace")]
     fn sync_all(&mut self) -> FileSystemResult<()> {
+        if self.policy == SyncPolicy::Never {
+            return Ok(());
+        }
         self.file
             .sync_all()
-            .map_err(|e| FileSystemError::WrappedError(Box::new(e)))
+            .map_err(|e| FileSystemError::WrappedError(Box::new(e)))?;
+        self.dirty.store(false, Ordering::Relaxed);
+        *self.synced.lock().expect("Poisoned Lock") = Instant::now();
+        Ok(())
     }
 
+    /// Does nothing under [`SyncPolicy::Never`].
     #[tracing::instrument(level = "trace")]
     fn sync_data(&mut self) -> FileSystemResult<()> {
-        self.file
-            .sync_data()
+        if self.policy == SyncPolicy::Never {
+            return Ok(());
+        }
+        self.sync()
             .map_err(|e| FileSystemError::WrappedError(Box::new(e)))
     }
 
@@ -232,10 +1001,207 @@ impl FileHandle for LocalFileHandle {
             FileLockMode::Shared => FileExt::lock_shared(&self.file),
             FileLockMode::Exclusive => FileExt::lock_exclusive(&self.file),
         }
-        .map_err(io_error_to_file_system_error)
+        .map_err(io_error_to_file_system_error)?;
+        self.lock = mode;
+        Ok(())
+    }
+
+    /// Converting a lock isn't atomic, so a failed upgrade from shared to exclusive keeps the
+    /// shared lock unless another handle took an exclusive lock in between, and a downgrade
+    /// fails, losing the lock, if another handle took it in between.
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        let result = match mode {
+            FileLockMode::Unlocked => FileExt::unlock(&self.file),
+            FileLockMode::Shared => FileExt::try_lock_shared(&self.file),
+            FileLockMode::Exclusive => FileExt::try_lock_exclusive(&self.file),
+        };
+        match result {
+            Ok(()) => {
+                self.lock = mode;
+                Ok(())
+            }
+            Err(err) if err.kind() == fs2::lock_contended_error().kind() => {
+                // Converting a lock may release it before failing, so restore the shared lock
+                if self.lock != FileLockMode::Shared
+                    || FileExt::try_lock_shared(&self.file).is_err()
+                {
+                    self.lock = FileLockMode::Unlocked;
+                }
+                Err(FileSystemError::FileAlreadyLocked)
+            }
+            Err(err) => Err(io_error_to_file_system_error(err)),
+        }
+    }
+
+    #[cfg(unix)]
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        let offset = nix::libc::off_t::try_from(offset).map_err(FileSystemError::wrap_error)?;
+        nix::sys::uio::preadv(&self.file, buffers, offset)
+            .map_err(|errno| io_error_to_file_system_error(errno.into()))
+    }
+
+    #[cfg(unix)]
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        let offset = nix::libc::off_t::try_from(offset).map_err(FileSystemError::wrap_error)?;
+        let written = nix::sys::uio::pwritev(&self.file, buffers, offset)
+            .map_err(|errno| io_error_to_file_system_error(errno.into()))?;
+        self.written().map_err(io_error_to_file_system_error)?;
+        Ok(written)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<FileMapping> {
+        let available = self.get_size()?.saturating_sub(offset);
+        let len = len.min(usize::try_from(available).unwrap_or(usize::MAX));
+        if len == 0 {
+            // Zero length mappings are rejected by the OS.
+            return Ok(FileMapping::Copied(Vec::new()));
+        }
+        // SAFETY: the mapping is only read through shared references, and `map_readonly`
+        // requires callers not to modify or truncate the file while the mapping is alive.
+        #[allow(unsafe_code)]
+        let mapping = unsafe {
+            memmap2::MmapOptions::new()
+                .offset(offset)
+                .len(len)
+                .map(&self.file)
+        }
+        .map_err(io_error_to_file_system_error)?;
+        #[cfg(unix)]
+        {
+            let advice = match self.advice {
+                Advice::Normal | Advice::DontNeed => memmap2::Advice::Normal,
+                Advice::Sequential => memmap2::Advice::Sequential,
+                Advice::Random => memmap2::Advice::Random,
+                Advice::WillNeed => memmap2::Advice::WillNeed,
+            };
+            if let Err(err) = mapping.advise(advice) {
+                tracing::debug!(?err, "Failed to advise mapping");
+            }
+        }
+        Ok(FileMapping::Mapped(mapping))
+    }
+
+    /// Direct handles report the block size of the underlying filesystem, which is a safe
+    /// multiple of the device's logical sector size.
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        if !self.direct {
+            return Ok(1);
+        }
+        #[cfg(unix)]
+        {
+            let stats = nix::sys::statvfs::fstatvfs(&self.file)
+                .map_err(|errno| io_error_to_file_system_error(errno.into()))?;
+            usize::try_from(stats.block_size()).map_err(FileSystemError::wrap_error)
+        }
+        #[cfg(not(unix))]
+        {
+            Ok(4096)
+        }
+    }
+
+    /// Byte-range locks use open file description locks on Linux, so they conflict between
+    /// handles within the same process. Other Unix platforms fall back to POSIX record locks,
+    /// which are held per process.
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        fcntl_lock_range(&self.file, offset, len, mode)
+    }
+
+    /// Advice is passed to `posix_fadvise` where available, and the latest advice is applied
+    /// with `madvise` to mappings made afterwards.
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        self.advice = advice;
+        fadvise(&self.file, offset, len, advice)
     }
 }
 
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn fadvise(file: &std::fs::File, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+    use nix::fcntl::{posix_fadvise, PosixFadviseAdvice};
+    use nix::libc;
+    use std::os::fd::AsRawFd;
+
+    let advice = match advice {
+        Advice::Normal => PosixFadviseAdvice::POSIX_FADV_NORMAL,
+        Advice::Sequential => PosixFadviseAdvice::POSIX_FADV_SEQUENTIAL,
+        Advice::Random => PosixFadviseAdvice::POSIX_FADV_RANDOM,
+        Advice::WillNeed => PosixFadviseAdvice::POSIX_FADV_WILLNEED,
+        Advice::DontNeed => PosixFadviseAdvice::POSIX_FADV_DONTNEED,
+    };
+    posix_fadvise(
+        file.as_raw_fd(),
+        libc::off_t::try_from(offset).map_err(FileSystemError::wrap_error)?,
+        libc::off_t::try_from(len).map_err(FileSystemError::wrap_error)?,
+        advice,
+    )
+    .map_err(|errno| io_error_to_file_system_error(errno.into()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+fn fadvise(file: &std::fs::File, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_vendor = "apple"))]
+fn fcntl_lock_range(
+    file: &std::fs::File,
+    offset: u64,
+    len: u64,
+    mode: FileLockMode,
+) -> FileSystemResult<()> {
+    use nix::errno::Errno;
+    use nix::fcntl::{fcntl, FcntlArg};
+    use nix::libc;
+    use std::os::fd::AsRawFd;
+
+    let l_type = match mode {
+        FileLockMode::Unlocked => libc::F_UNLCK,
+        FileLockMode::Shared => libc::F_RDLCK,
+        FileLockMode::Exclusive => libc::F_WRLCK,
+    };
+    let lock = libc::flock {
+        l_type: libc::c_short::try_from(l_type).map_err(FileSystemError::wrap_error)?,
+        l_whence: libc::c_short::try_from(libc::SEEK_SET).unwrap_or_default(),
+        l_start: libc::off_t::try_from(offset).map_err(FileSystemError::wrap_error)?,
+        l_len: libc::off_t::try_from(len).map_err(FileSystemError::wrap_error)?,
+        l_pid: 0,
+    };
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let arg = FcntlArg::F_OFD_SETLK(&lock);
+    #[cfg(target_vendor = "apple")]
+    let arg = FcntlArg::F_SETLK(&lock);
+    match fcntl(file.as_raw_fd(), arg) {
+        Ok(_) => Ok(()),
+        Err(Errno::EAGAIN | Errno::EACCES) => Err(FileSystemError::FileAlreadyLocked),
+        Err(errno) => Err(io_error_to_file_system_error(errno.into())),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_vendor = "apple")))]
+fn fcntl_lock_range(
+    file: &std::fs::File,
+    offset: u64,
+    len: u64,
+    mode: FileLockMode,
+) -> FileSystemResult<()> {
+    Err(FileSystemError::UnsupportedOperation)
+}
+
 #[tracing::instrument(level = "trace")]
 fn io_error_to_file_system_error(error: std::io::Error) -> FileSystemError {
     match error.kind() {
@@ -243,6 +1209,9 @@ fn io_error_to_file_system_error(error: std::io::Error) -> FileSystemError {
         std::io::ErrorKind::AlreadyExists => FileSystemError::PathExists,
         std::io::ErrorKind::PermissionDenied => FileSystemError::PermissionDenied,
         std::io::ErrorKind::InvalidInput => FileSystemError::InvalidPath(error.to_string()),
+        std::io::ErrorKind::IsADirectory
+        | std::io::ErrorKind::NotADirectory
+        | std::io::ErrorKind::DirectoryNotEmpty => FileSystemError::InvalidOperation,
         _ => FileSystemError::WrappedError(Box::new(error)),
     }
 }
@@ -318,6 +1287,20 @@ mod test {
             file.seek(SeekFrom::Start(0)).expect("Error Seeking File");
             file.read_to_end(&mut buf).expect("Error Reading File");
             assert_eq!(buf, b"Goodbye!");
+
+            // Modify the existing file in place
+            file.seek(SeekFrom::Start(0)).expect("Error Seeking File");
+            file.write_all(b"Hello").expect("Error Writing File");
+            file.flush().expect("Error Flushing File");
+        }
+        {
+            // Reopen and check the modification persisted
+            let mut buf = Vec::new();
+            fs.open_file(filename.as_str())
+                .expect("Error Opening File")
+                .read_to_end(&mut buf)
+                .expect("Error Reading File");
+            assert_eq!(buf, b"Helloye!");
         }
 
         // Remove file and test
@@ -327,4 +1310,797 @@ mod test {
             .exists(filename.as_str())
             .expect("Error Checking File Existence"));
     }
+
+    #[test]
+    #[tracing_test::traced_test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn test_local_lock_conversion() {
+        use crate::{FileHandle, FileLockMode, FileSystem, FileSystemError, LocalFileSystem};
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        let fs = LocalFileSystem::new(std::env::temp_dir().to_str().unwrap());
+        let filename = format!(
+            "./test-{}.tst",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        );
+        {
+            let mut first = fs.create_file(&filename).unwrap();
+            let mut second = fs.open_file(&filename).unwrap();
+            first.try_set_lock_status(FileLockMode::Shared).unwrap();
+            second.try_set_lock_status(FileLockMode::Shared).unwrap();
+            assert!(matches!(
+                first.upgrade_lock(),
+                Err(FileSystemError::FileAlreadyLocked)
+            ));
+            assert_eq!(first.get_lock_status().unwrap(), FileLockMode::Shared);
+            assert!(matches!(
+                first.set_lock_status_timeout(FileLockMode::Exclusive, Duration::from_millis(20)),
+                Err(FileSystemError::FileAlreadyLocked)
+            ));
+            second.try_set_lock_status(FileLockMode::Unlocked).unwrap();
+            first.upgrade_lock().unwrap();
+            assert!(matches!(
+                second.try_set_lock_status(FileLockMode::Shared),
+                Err(FileSystemError::FileAlreadyLocked)
+            ));
+            first.downgrade_lock().unwrap();
+            assert_eq!(first.get_lock_status().unwrap(), FileLockMode::Shared);
+            second
+                .set_lock_status_timeout(FileLockMode::Shared, Duration::from_secs(1))
+                .unwrap();
+        }
+        fs.remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn test_local_lock_contention() {
+        use crate::{FileHandle, FileLockMode, FileSystem, FileSystemError, LocalFileSystem};
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        let fs = LocalFileSystem::new(std::env::temp_dir().to_str().unwrap());
+        let filename = format!(
+            "./test-{}.tst",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        );
+        {
+            let mut first = fs.create_file(&filename).unwrap();
+            let mut second = fs.open_file(&filename).unwrap();
+            first.set_lock_status(FileLockMode::Exclusive).unwrap();
+            assert!(matches!(
+                second.try_set_lock_status(FileLockMode::Shared),
+                Err(FileSystemError::FileAlreadyLocked)
+            ));
+            assert_eq!(second.get_lock_status().unwrap(), FileLockMode::Unlocked);
+
+            // A blocking lock waits for the holder to release rather than failing
+            let waiter = std::thread::spawn(move || {
+                second
+                    .set_lock_status(FileLockMode::Shared)
+                    .map(|()| second)
+            });
+            std::thread::sleep(Duration::from_millis(50));
+            assert!(!waiter.is_finished());
+            first.set_lock_status(FileLockMode::Unlocked).unwrap();
+            let second = waiter.join().unwrap().unwrap();
+            assert_eq!(second.get_lock_status().unwrap(), FileLockMode::Shared);
+            assert!(matches!(
+                first.try_set_lock_status(FileLockMode::Exclusive),
+                Err(FileSystemError::FileAlreadyLocked)
+            ));
+        }
+        fs.remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn test_local_range_locks() {
+        use crate::{FileHandle, FileLockMode, FileSystem, FileSystemError, LocalFileSystem};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let fs = LocalFileSystem::new(std::env::temp_dir().to_str().unwrap());
+        let filename = format!(
+            "./test-{}.tst",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        );
+        {
+            let mut first = fs.create_file(&filename).unwrap();
+            let mut second = fs.open_file(&filename).unwrap();
+            first.lock_range(0, 4096, FileLockMode::Exclusive).unwrap();
+            second.lock_range(4096, 4096, FileLockMode::Shared).unwrap();
+            assert!(matches!(
+                second.lock_range(0, 1, FileLockMode::Shared),
+                Err(FileSystemError::FileAlreadyLocked)
+            ));
+            assert!(matches!(
+                first.lock_range(4096, 1, FileLockMode::Exclusive),
+                Err(FileSystemError::FileAlreadyLocked)
+            ));
+            first.unlock_range(0, 4096).unwrap();
+            second.lock_range(0, 1, FileLockMode::Shared).unwrap();
+        }
+        fs.remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_vectored_io() {
+        use crate::{FileHandle, FileSystem, LocalFileSystem};
+        use std::io::{IoSlice, IoSliceMut, Seek, Write};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let fs = LocalFileSystem::new(std::env::temp_dir().to_str().unwrap());
+        let filename = format!(
+            "./test-{}.tst",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        );
+        {
+            let mut file = fs.create_file(&filename).unwrap();
+            let written = file
+                .write_vectored(&[IoSlice::new(b"Hello, "), IoSlice::new(b"World")])
+                .unwrap();
+            assert_eq!(written, 12);
+            let written = file
+                .write_at_vectored(7, &[IoSlice::new(b"Wo"), IoSlice::new(b"rms")])
+                .unwrap();
+            assert_eq!(written, 5);
+            assert_eq!(file.stream_position().unwrap(), 12);
+
+            let (mut first, mut second) = ([0u8; 5], [0u8; 16]);
+            let read = file
+                .read_at_vectored(
+                    0,
+                    &mut [IoSliceMut::new(&mut first), IoSliceMut::new(&mut second)],
+                )
+                .unwrap();
+            assert_eq!(read, 12);
+            assert_eq!(&first, b"Hello");
+            assert_eq!(&second[..7], b", Worms");
+        }
+        fs.remove_file(&filename).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_shared_positional_io() {
+        use crate::{FileSystem, LocalFileSystem, ReadAt, WriteAt};
+        use std::io::{Seek, Write};
+        use std::sync::Arc;
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let fs = LocalFileSystem::new(std::env::temp_dir().to_str().unwrap());
+        let filename = format!(
+            "./test-{}.tst",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        );
+        {
+            let mut file = fs.create_file(&filename).unwrap();
+            file.write_all(b"head").unwrap();
+            let file = Arc::new(file);
+            std::thread::scope(|scope| {
+                for page in 1..=4u8 {
+                    let file = Arc::clone(&file);
+                    scope.spawn(move || {
+                        file.write_all_at(u64::from(page) * 4, &[page; 4]).unwrap();
+                        let mut buffer = [0; 4];
+                        file.read_exact_at(u64::from(page) * 4, &mut buffer)
+                            .unwrap();
+                        assert_eq!(buffer, [page; 4]);
+                    });
+                }
+            });
+            let mut buffer = [0; 4];
+            file.read_exact_at(0, &mut buffer).unwrap();
+            assert_eq!(&buffer, b"head");
+            let mut file = Arc::into_inner(file).unwrap();
+            assert_eq!(file.stream_position().unwrap(), 4);
+        }
+        fs.remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    #[cfg(feature = "mmap")]
+    fn test_local_map_readonly() {
+        use crate::{FileHandle, FileMapping, FileSystem, LocalFileSystem};
+        use std::io::Write;
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let fs = LocalFileSystem::new(std::env::temp_dir().to_str().unwrap());
+        let filename = format!(
+            "./test-{}.tst",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        );
+        {
+            let mut file = fs.create_file(&filename).unwrap();
+            file.write_all(b"Hello, World!").unwrap();
+            file.sync_all().unwrap();
+
+            let mapping = file.map_readonly(0, 5).unwrap();
+            assert!(matches!(mapping, FileMapping::Mapped(_)));
+            assert_eq!(&*mapping, b"Hello");
+
+            // Ranges are clamped to the end of the file
+            let mapping = file.map_readonly(7, 1024).unwrap();
+            assert_eq!(&*mapping, b"World!");
+            assert!(file.map_readonly(64, 16).unwrap().is_empty());
+        }
+        fs.remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_open_with() {
+        use crate::{FileHandle, FileSystem, FileSystemError, LocalFileSystem, OpenOptions};
+        use std::io::{Read, Seek, SeekFrom, Write};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let fs = LocalFileSystem::new(std::env::temp_dir().to_str().unwrap());
+        let filename = format!(
+            "./test-{}.tst",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        );
+        let options = OpenOptions::new().read(true).write(true);
+        assert!(matches!(
+            fs.open_with(&filename, options),
+            Err(FileSystemError::PathMissing)
+        ));
+        {
+            let mut file = fs.open_with(&filename, options.create(true)).unwrap();
+            file.write_all(b"Hello").unwrap();
+            assert_eq!(file.alignment().unwrap(), 1);
+        }
+        assert!(matches!(
+            fs.open_with(&filename, options.create_new(true)),
+            Err(FileSystemError::PathExists)
+        ));
+        {
+            let mut file = fs.open_with(&filename, options.append(true)).unwrap();
+            file.write_all(b", World!").unwrap();
+            let mut buf = String::new();
+            file.seek(SeekFrom::Start(0)).unwrap();
+            file.read_to_string(&mut buf).unwrap();
+            assert_eq!(buf, "Hello, World!");
+        }
+
+        // Direct I/O needs aligned buffers, which the test can't guarantee, so only check that
+        // opening either succeeds with a sensible alignment or reports it's unsupported.
+        match fs.open_with(&filename, options.direct(true)) {
+            Ok(file) => assert!(file.alignment().unwrap() >= 512),
+            Err(FileSystemError::UnsupportedOperation) => {}
+            Err(err) => panic!("Unexpected error opening with direct I/O: {err}"),
+        }
+
+        fs.remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_open_file() {
+        use crate::{FileSystem, FileSystemError, LocalFileSystem, Permissions};
+        use std::io::{Read, Write};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let root = std::env::temp_dir().join(format!(
+            "minql-open-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let fs = LocalFileSystem::new(&root);
+        assert!(matches!(
+            fs.open_file("/missing.bin"),
+            Err(FileSystemError::PathMissing)
+        ));
+        fs.write("/data.bin", b"Hello").unwrap();
+        fs.open_file("/data.bin").unwrap().write_all(b"J").unwrap();
+        assert_eq!(fs.read("/data.bin").unwrap(), b"Jello");
+
+        // A file that can't be written fails to open rather than opening read-only, unless
+        // the process may write it anyway
+        fs.set_permissions("/data.bin", Permissions::new().readonly(true))
+            .unwrap();
+        let writable = std::fs::OpenOptions::new()
+            .write(true)
+            .open(root.join("data.bin"))
+            .is_ok();
+        if !writable {
+            assert!(matches!(
+                fs.open_file("/data.bin"),
+                Err(FileSystemError::PermissionDenied)
+            ));
+        }
+        fs.set_permissions("/data.bin", Permissions::new()).unwrap();
+
+        // A read-only filesystem opens files for reading alone
+        let readonly = LocalFileSystem::builder(&root)
+            .readonly(true)
+            .build()
+            .unwrap();
+        let mut file = readonly.open_file("/data.bin").unwrap();
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"Jello");
+        assert!(file.write_all(b"denied").is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_read_only_file() {
+        use crate::{
+            ContentHasher, FileSystem, HashAlgorithm, LocalFileSystem, MetricFileSystem,
+            Permissions,
+        };
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let root = std::env::temp_dir().join(format!(
+            "minql-read-only-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let fs = LocalFileSystem::new(&root);
+        fs.write("/sealed.dat", b"sealed").unwrap();
+        fs.set_permissions("/sealed.dat", Permissions::new().mode(0o444))
+            .unwrap();
+
+        // Reading, hashing and copying only need the file to be readable, directly or through
+        // a wrapper relying on the default implementations
+        let mut hasher = ContentHasher::new(HashAlgorithm::Blake3);
+        hasher.update(b"sealed");
+        let digest = hasher.finalize();
+        assert_eq!(
+            fs.hash_file("/sealed.dat", HashAlgorithm::Blake3).unwrap(),
+            digest
+        );
+        assert_eq!(fs.read("/sealed.dat").unwrap(), b"sealed");
+        let wrapped = MetricFileSystem::new(LocalFileSystem::new(&root));
+        assert_eq!(
+            wrapped
+                .hash_file("/sealed.dat", HashAlgorithm::Blake3)
+                .unwrap(),
+            digest
+        );
+        assert_eq!(wrapped.read("/sealed.dat").unwrap(), b"sealed");
+        wrapped.clone_file("/sealed.dat", "/copy.dat").unwrap();
+        assert_eq!(fs.read("/copy.dat").unwrap(), b"sealed");
+
+        fs.set_permissions("/sealed.dat", Permissions::new())
+            .unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_allocate() {
+        use crate::{FileHandle, FileSystem, LocalFileSystem};
+        use std::io::{Read, Seek, SeekFrom, Write};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let fs = LocalFileSystem::new(std::env::temp_dir().to_str().unwrap());
+        let filename = format!(
+            "./test-{}.tst",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        );
+        {
+            let mut file = fs.create_file(&filename).unwrap();
+            file.write_all(b"Hello").unwrap();
+            file.allocate(64 * 1024).unwrap();
+            assert_eq!(file.get_size().unwrap(), 64 * 1024);
+
+            // Allocating less than the current size never shrinks the file
+            file.allocate(16).unwrap();
+            assert_eq!(file.get_size().unwrap(), 64 * 1024);
+
+            let mut buf = [0xFFu8; 8];
+            file.seek(SeekFrom::Start(0)).unwrap();
+            file.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"Hello\0\0\0");
+        }
+        fs.remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_space() {
+        use crate::{FileSystem, LocalFileSystem};
+
+        let fs = LocalFileSystem::new(std::env::temp_dir());
+        let space = fs.space().unwrap();
+        assert!(space.total > 0);
+        assert!(space.used <= space.total);
+        assert!(space.available <= space.total);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_builder() {
+        use crate::{
+            FileHandle, FileSystem, FileSystemError, LocalFileSystem, OpenOptions, SyncPolicy,
+        };
+        use std::io::Write;
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let root = std::env::temp_dir()
+            .join(format!(
+                "test-builder-{}",
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Time went backwards")
+                    .as_nanos()
+            ))
+            .join("nested");
+        assert!(LocalFileSystem::builder(&root)
+            .build()
+            .unwrap()
+            .list_directory("/")
+            .is_err());
+
+        let fs = LocalFileSystem::builder(&root)
+            .create_root_if_missing(true)
+            .follow_symlinks(false)
+            .sync_policy(SyncPolicy::EveryWrite)
+            .file_mode(0o600)
+            .build()
+            .unwrap();
+        assert_eq!(fs.sync_policy(), SyncPolicy::EveryWrite);
+        let mut file = fs.create_file("/data.bin").unwrap();
+        file.write_all(b"data").unwrap();
+        file.sync_data().unwrap();
+        drop(file);
+        fs.write("/other.bin", b"other").unwrap();
+        let mode = std::fs::metadata(root.join("data.bin"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        fs.create_symlink("data.bin", "/link.bin").unwrap();
+        assert!(matches!(
+            fs.open_file("/link.bin"),
+            Err(FileSystemError::InvalidOperation)
+        ));
+        assert!(matches!(
+            fs.append("/link.bin", b"more"),
+            Err(FileSystemError::InvalidOperation)
+        ));
+
+        let readonly = LocalFileSystem::builder(&root)
+            .readonly(true)
+            .build()
+            .unwrap();
+        assert!(readonly.is_readonly());
+        assert_eq!(readonly.read("/data.bin").unwrap(), b"data");
+        assert_eq!(readonly.read("/link.bin").unwrap(), b"data");
+        let mut file = readonly.open_file("/data.bin").unwrap();
+        assert!(file.write_all(b"denied").is_err());
+        for result in [
+            readonly.write("/data.bin", b"denied"),
+            readonly.remove_file("/data.bin"),
+            readonly.create_directory("/dir"),
+            readonly.create_file("/new.bin").map(drop),
+            readonly
+                .open_with("/data.bin", OpenOptions::new().append(true))
+                .map(drop),
+        ] {
+            assert!(matches!(result, Err(FileSystemError::PermissionDenied)));
+        }
+        assert_eq!(fs.read("/data.bin").unwrap(), b"data");
+
+        std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_clone_file() {
+        use crate::{FileSystem, FileSystemError, LocalFileSystem};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let root = std::env::temp_dir().join(format!(
+            "test-clone-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        ));
+        let fs = LocalFileSystem::new(&root);
+        fs.create_directory_all("/").unwrap();
+        fs.write("/segment.dat", b"segment").unwrap();
+
+        // Whether the blocks are shared depends on the filesystem holding the temp directory.
+        fs.clone_file("/segment.dat", "/snapshot.dat").unwrap();
+        fs.append("/segment.dat", b" tail").unwrap();
+        assert_eq!(fs.read("/snapshot.dat").unwrap(), b"segment");
+        assert_eq!(fs.read("/segment.dat").unwrap(), b"segment tail");
+
+        assert!(matches!(
+            fs.clone_file("/segment.dat", "/snapshot.dat"),
+            Err(FileSystemError::PathExists)
+        ));
+        assert!(matches!(
+            fs.clone_file("/missing.dat", "/other.dat"),
+            Err(FileSystemError::PathMissing)
+        ));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_symlinks() {
+        use crate::{
+            FileSystem, FileSystemError, FileType, LocalFileSystem, OpenOptions, SymlinkPolicy,
+        };
+        use std::io::{Read, Write};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let root = std::env::temp_dir().join(format!(
+            "test-links-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        ));
+        let fs = LocalFileSystem::new(&root);
+        fs.create_directory_all("/v2").unwrap();
+        fs.create_file("/v2/table.bin")
+            .unwrap()
+            .write_all(b"v2")
+            .unwrap();
+        fs.create_symlink("v2", "/current").unwrap();
+        fs.create_symlink("/v2/table.bin", "/table.bin").unwrap();
+
+        assert_eq!(fs.read_link("/current").unwrap(), "v2");
+        assert_eq!(fs.read_link("/table.bin").unwrap(), "/v2/table.bin");
+        assert_eq!(
+            fs.file_type("/current", SymlinkPolicy::NoFollow).unwrap(),
+            FileType::Symlink
+        );
+        assert_eq!(
+            fs.file_type("/current", SymlinkPolicy::Follow).unwrap(),
+            FileType::Directory
+        );
+        let mut contents = String::new();
+        fs.open_file("/current/table.bin")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "v2");
+        assert!(matches!(
+            fs.open_with("/table.bin", OpenOptions::new().read(true).no_follow(true)),
+            Err(FileSystemError::InvalidOperation)
+        ));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_permissions() {
+        use crate::{FileSystem, LocalFileSystem, OpenOptions, Permissions};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let fs = LocalFileSystem::new(std::env::temp_dir());
+        let filename = format!(
+            "./test-{}.tst",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        );
+        fs.create_file(&filename).unwrap();
+
+        fs.set_permissions(&filename, Permissions::new().readonly(true))
+            .unwrap();
+        let permissions = fs.permissions(&filename).unwrap();
+        assert!(permissions.is_readonly());
+        #[cfg(unix)]
+        assert_eq!(permissions.get_mode().unwrap() & 0o222, 0);
+        assert!(fs
+            .open_with(&filename, OpenOptions::new().read(true))
+            .is_ok());
+
+        #[cfg(unix)]
+        {
+            fs.set_permissions(&filename, Permissions::new().mode(0o640))
+                .unwrap();
+            assert_eq!(fs.permissions(&filename).unwrap().get_mode(), Some(0o640));
+        }
+        fs.set_permissions(&filename, Permissions::new()).unwrap();
+        assert!(!fs.permissions(&filename).unwrap().is_readonly());
+        fs.remove_file(&filename).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_confinement() {
+        use crate::{FileSystem, FileSystemError, LocalFileSystem};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let base = std::env::temp_dir().join(format!(
+            "test-confine-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        ));
+        let root = base.join("root");
+        std::fs::create_dir_all(base.join("outside")).unwrap();
+        std::fs::write(base.join("outside/secret"), b"secret").unwrap();
+        let fs = LocalFileSystem::new(&root);
+        fs.create_directory_all("/data").unwrap();
+
+        assert!(matches!(
+            fs.open_file("../outside/secret"),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            fs.exists("/data/../../outside/secret"),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+        assert!(fs.exists("/data/../data").unwrap());
+
+        std::os::unix::fs::symlink(base.join("outside"), root.join("escape")).unwrap();
+        std::os::unix::fs::symlink(base.join("outside/created"), root.join("dangling")).unwrap();
+        assert!(fs.open_file("/escape/secret").is_ok());
+
+        let fs = fs.confine_symlinks(true);
+        assert!(matches!(
+            fs.open_file("/escape/secret"),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            fs.create_file("/dangling"),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+        assert!(!base.join("outside/created").exists());
+        assert!(matches!(
+            fs.create_symlink("../../outside", "/data/link"),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+        fs.create_symlink("../data", "/data/link").unwrap();
+        assert!(fs.exists("/data").unwrap());
+        fs.create_file("/data/table.bin").unwrap();
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_windows_paths() {
+        use super::{extended_length, is_windows_segment, strip_extended_length};
+
+        assert!(is_windows_segment("table.bin"));
+        assert!(is_windows_segment("console"));
+        assert!(is_windows_segment("COM10"));
+        assert!(!is_windows_segment("CON"));
+        assert!(!is_windows_segment("nul.txt"));
+        assert!(!is_windows_segment("Lpt1 .log"));
+        assert!(!is_windows_segment("COM\u{b9}"));
+        assert!(!is_windows_segment("C:table.bin"));
+        assert!(!is_windows_segment("table.bin:stream"));
+        assert!(!is_windows_segment("table."));
+        assert!(!is_windows_segment("table "));
+
+        let name = "a".repeat(300);
+        assert_eq!(extended_length(r"C:\data\table.bin"), None);
+        let drive = format!(r"C:\data/{name}");
+        assert_eq!(
+            extended_length(&drive).unwrap(),
+            format!(r"\\?\C:\data\{name}")
+        );
+        let unc = format!(r"\\server\share\{name}");
+        assert_eq!(
+            extended_length(&unc).unwrap(),
+            format!(r"\\?\UNC\server\share\{name}")
+        );
+        assert_eq!(extended_length(&format!(r"\\?\C:\{name}")), None);
+        assert_eq!(extended_length(&format!(r"data\{name}")), None);
+
+        assert_eq!(strip_extended_length(&extended_length(&unc).unwrap()), unc);
+        assert_eq!(
+            strip_extended_length(&format!(r"\\?\C:\data\{name}")),
+            format!(r"C:\data\{name}")
+        );
+        assert_eq!(strip_extended_length(r"C:\data"), r"C:\data");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_whole_file() {
+        use crate::{Advice, FileHandle, FileSystem, FileSystemError, LocalFileSystem};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let fs = LocalFileSystem::new(std::env::temp_dir());
+        let filename = format!(
+            "./test-{}.tst",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        );
+        fs.write(&filename, b"first").unwrap();
+        fs.append(&filename, b" second").unwrap();
+        assert_eq!(fs.read(&filename).unwrap(), b"first second");
+        fs.write(&filename, b"reset").unwrap();
+        assert_eq!(fs.read_to_string(&filename).unwrap(), "reset");
+        fs.remove_file(&filename).unwrap();
+
+        fs.append(&filename, b"created").unwrap();
+        assert_eq!(fs.filesize(&filename).unwrap(), 7);
+        let mut handle = fs.open_file(&filename).unwrap();
+        for advice in [Advice::Sequential, Advice::WillNeed, Advice::DontNeed] {
+            handle.advise(0, 0, advice).unwrap();
+        }
+        let mut buffer = [0; 7];
+        assert_eq!(handle.read_at_offset(0, &mut buffer).unwrap(), 7);
+        assert_eq!(&buffer, b"created");
+        drop(handle);
+        fs.remove_file(&filename).unwrap();
+        assert!(matches!(
+            fs.read(&filename),
+            Err(FileSystemError::PathMissing)
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_local_conformance() {
+        use crate::LocalFileSystem;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let root = std::env::temp_dir().join(format!(
+            "test-conformance-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        ));
+        let run = AtomicUsize::new(0);
+        crate::conformance::run(|| {
+            let root = root.join(run.fetch_add(1, Ordering::Relaxed).to_string());
+            std::fs::create_dir_all(&root).unwrap();
+            LocalFileSystem::new(root)
+        });
+        std::fs::remove_dir_all(root).unwrap();
+    }
 }
@@ -14,13 +14,22 @@
 // limitations under the License.
 //
 
-use super::{FileSystem, FileSystemError, FileSystemResult};
+use super::{
+    BatchOperation, FileSystem, FileSystemError, FileSystemProvider, FileSystemResult,
+    FileSystemSpace, FileType, Permissions, SymlinkPolicy,
+};
 use crate::filesystem::FileLockMode;
-use crate::FileHandle;
-use minql_uri::Path;
-use std::collections::BTreeMap;
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::sync::{Arc, RwLock};
+use crate::utility::{apply_operation, join_segments, normalize_segments};
+use crate::{Clock, FileHandle, ReadAt, SystemClock, WriteAt};
+use minql_uri::URI;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::time::{Duration, Instant, SystemTime};
 
 /// Memory File System
 ///
@@ -37,13 +46,697 @@ use std::sync::{Arc, RwLock};
 ///
 /// ```
 ///
-#[derive(Default)]
-pub struct MemoryFileSystem(Arc<RwLock<BTreeMap<String, MemoryEntry>>>);
+/// The namespace is split into shards by directory, so operations in different directories
+/// rarely contend, and each file's contents sit behind their own lock that readers share.
+#[derive(Clone, Default)]
+pub struct MemoryFileSystem(Arc<MemoryNamespace>);
 
 impl MemoryFileSystem {
-    /// Create a new Memory FileSystem
+    /// Create a new Memory `FileSystem`
+    #[must_use]
     pub fn new() -> MemoryFileSystem {
-        MemoryFileSystem(Arc::new(RwLock::new(BTreeMap::new())))
+        MemoryFileSystem(Arc::default())
+    }
+
+    /// Create a new Memory `FileSystem` reporting `capacity` bytes of total space.
+    ///
+    /// The capacity is only used to derive [`FileSystem::space`], writes beyond it still succeed.
+    #[must_use]
+    pub fn with_capacity(capacity: u64) -> MemoryFileSystem {
+        MemoryFileSystem(Arc::new(MemoryNamespace {
+            capacity,
+            ..MemoryNamespace::default()
+        }))
+    }
+
+    /// Create a new Memory `FileSystem` stamping modification times from `clock`.
+    ///
+    /// ```rust
+    /// use minql_vfs::{FileSystem, ManualClock, MemoryFileSystem};
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let clock = ManualClock::new(UNIX_EPOCH);
+    /// let fs = MemoryFileSystem::with_clock(clock.clone());
+    /// fs.write("/old.log", b"old").unwrap();
+    /// clock.advance(Duration::from_secs(60));
+    /// fs.write("/new.log", b"new").unwrap();
+    /// assert_eq!(fs.modified("/old.log").unwrap(), UNIX_EPOCH);
+    /// assert_eq!(fs.modified("/new.log").unwrap(), UNIX_EPOCH + Duration::from_secs(60));
+    /// ```
+    #[must_use]
+    pub fn with_clock<C: Clock>(clock: C) -> MemoryFileSystem {
+        MemoryFileSystem(Arc::new(MemoryNamespace {
+            clock: Arc::new(clock),
+            ..MemoryNamespace::default()
+        }))
+    }
+
+    /// Create a new Memory `FileSystem` acting as a cache of at most `budget` bytes.
+    ///
+    /// Whenever a handle is closed with the files above the budget, the least recently
+    /// accessed files are removed until they fit again. Files are accessed by opening or
+    /// reading them, and their size is accounted as of when they were last closed. Files with
+    /// open handles, and files [pinned](MemoryFileSystem::pin), are never evicted, so the budget
+    /// may be exceeded while they hold more than it. The budget is also reported as the total
+    /// capacity by [`FileSystem::space`].
+    ///
+    /// ```rust
+    /// use minql_vfs::{FileSystem, MemoryFileSystem};
+    ///
+    /// let fs = MemoryFileSystem::with_budget(8);
+    /// fs.write("/index", b"pin").unwrap();
+    /// fs.pin("/index").unwrap();
+    /// fs.write("/old", b"old").unwrap();
+    /// fs.write("/new", b"new").unwrap();
+    /// assert!(!fs.exists("/old").unwrap());
+    /// assert!(fs.exists("/index").unwrap() && fs.exists("/new").unwrap());
+    /// assert_eq!(fs.evictions(), 1);
+    /// ```
+    #[must_use]
+    pub fn with_budget(budget: u64) -> MemoryFileSystem {
+        MemoryFileSystem(Arc::new(MemoryNamespace {
+            capacity: budget,
+            cache: Some(Mutex::new(MemoryCache {
+                budget,
+                ..MemoryCache::default()
+            })),
+            ..MemoryNamespace::default()
+        }))
+    }
+
+    /// Protect the file at `path` from eviction, failing with
+    /// [`FileSystemError::UnsupportedOperation`] unless this filesystem was created
+    /// [with a budget](MemoryFileSystem::with_budget).
+    pub fn pin(&self, path: &str) -> FileSystemResult<()> {
+        let cache = self
+            .0
+            .cache
+            .as_ref()
+            .ok_or(FileSystemError::UnsupportedOperation)?;
+        let segments = self.resolve(path, true)?;
+        let Some(MemoryEntry::File(file)) = self.entry(&segments) else {
+            return Err(FileSystemError::PathMissing);
+        };
+        let path = join_segments(&segments);
+        self.0.touch(&path, &file.0, None);
+        cache.lock().expect("Poisoned Lock").pinned.insert(path);
+        Ok(())
+    }
+
+    /// Allow the file at `path` to be evicted again, evicting files if it kept the cache above
+    /// its budget.
+    pub fn unpin(&self, path: &str) -> FileSystemResult<()> {
+        let cache = self
+            .0
+            .cache
+            .as_ref()
+            .ok_or(FileSystemError::UnsupportedOperation)?;
+        let path = join_segments(&self.resolve(path, true)?);
+        cache.lock().expect("Poisoned Lock").pinned.remove(&path);
+        self.0.evict(None);
+        Ok(())
+    }
+
+    /// Number of files evicted so far to stay within the budget.
+    #[must_use]
+    pub fn evictions(&self) -> u64 {
+        self.0
+            .cache
+            .as_ref()
+            .map_or(0, |cache| cache.lock().expect("Poisoned Lock").evictions)
+    }
+
+    /// Get an immutable view of the contents of a file that readers can slice without copying.
+    ///
+    /// The file keeps its contents in chunks, and the view is built from them on request. Every
+    /// request shares the same view while any reader still holds it and the file hasn't changed
+    /// since, and the file itself never keeps a view alive, so contents are only held twice
+    /// while a reader holds a view. A view never changes, so it keeps showing the contents as
+    /// they were when it was taken.
+    ///
+    /// ```rust
+    /// use minql_vfs::{FileSystem, MemoryFileSystem};
+    /// use std::sync::Arc;
+    ///
+    /// let fs = MemoryFileSystem::new();
+    /// fs.write("/query.sql", b"SELECT 1").unwrap();
+    /// let view = fs.read_shared("/query.sql").unwrap();
+    /// assert_eq!(&view[..6], b"SELECT");
+    /// assert!(Arc::ptr_eq(&view, &fs.read_shared("/query.sql").unwrap()));
+    ///
+    /// fs.append("/query.sql", b";").unwrap();
+    /// assert_eq!(&*view, b"SELECT 1");
+    /// assert_eq!(&*fs.read_shared("/query.sql").unwrap(), b"SELECT 1;");
+    /// ```
+    pub fn read_shared(&self, path: &str) -> FileSystemResult<Arc<[u8]>> {
+        let segments = self.resolve(path, true)?;
+        match self.entry(&segments) {
+            Some(MemoryEntry::File(file)) => {
+                self.0.touch(&join_segments(&segments), &file.0, None);
+                Ok(MemoryFileData::snapshot(&file.0))
+            }
+            Some(MemoryEntry::Directory | MemoryEntry::Symlink(_)) => {
+                Err(FileSystemError::InvalidOperation)
+            }
+            None if segments.is_empty() => Err(FileSystemError::InvalidOperation),
+            None => Err(FileSystemError::PathMissing),
+        }
+    }
+
+    /// Create an independent copy of the current tree.
+    ///
+    /// File contents are shared copy-on-write, so forking is cheap and each side only copies a
+    /// chunk of a file the first time it modifies it. Changes made through either filesystem, or
+    /// through handles opened on it, are never visible to the other. Advisory locks aren't carried
+    /// over, and the fork of a cache has no budget.
+    ///
+    /// ```rust
+    /// use minql_vfs::{FileSystem, MemoryFileSystem};
+    /// use std::io::Write;
+    ///
+    /// let fixture = MemoryFileSystem::new();
+    /// fixture.create_file("/data.txt").unwrap().write_all(b"fixture").unwrap();
+    ///
+    /// let fork = fixture.fork();
+    /// fork.open_file("/data.txt").unwrap().write_all(b"FORK").unwrap();
+    /// fork.remove_file("/data.txt").unwrap();
+    /// assert_eq!(fixture.filesize("/data.txt").unwrap(), 7);
+    /// ```
+    #[must_use]
+    pub fn fork(&self) -> MemoryFileSystem {
+        let guards = self.0.read_all();
+        let shards = guards.0.values().map(|shard| {
+            let directories = shard.0.iter().map(|(directory, children)| {
+                let children = children.iter().map(|(name, entry)| {
+                    let entry = match entry {
+                        MemoryEntry::Directory => MemoryEntry::Directory,
+                        MemoryEntry::Symlink(target) => MemoryEntry::Symlink(target.clone()),
+                        MemoryEntry::File(file) => {
+                            let data = file.0.read().expect("Poisoned Lock");
+                            MemoryEntry::File(MemoryFileEntry(Arc::new(RwLock::new(
+                                MemoryFileData {
+                                    buffer: data.buffer.clone(),
+                                    permissions: data.permissions,
+                                    modified: data.modified,
+                                    clock: data.clock.clone(),
+                                    snapshot: data.snapshot.clone(),
+                                    locks: Arc::default(),
+                                },
+                            ))))
+                        }
+                    };
+                    (name.clone(), entry)
+                });
+                (directory.clone(), children.collect())
+            });
+            RwLock::new(MemoryShard(directories.collect()))
+        });
+        MemoryFileSystem(Arc::new(MemoryNamespace {
+            capacity: self.0.capacity,
+            symlinks: AtomicBool::new(self.0.symlinks.load(Ordering::Acquire)),
+            directories: RwLock::new(self.0.directories.read().expect("Poisoned Lock").clone()),
+            clock: self.0.clock.clone(),
+            cache: None,
+            shards: shards.collect(),
+        }))
+    }
+
+    /// Write an image of the whole tree to `handle` at its cursor.
+    ///
+    /// The image is a simple length-prefixed format, with integers in little endian:
+    ///
+    /// * the magic bytes `MQLMEMFS` and a `u32` format version, currently `3`
+    /// * a `u64` count of entries, followed by each entry with parents before their children
+    /// * per entry, a `u8` kind (`1` for a directory, `2` for a file, `3` for a symbolic link),
+    ///   then a `u32` length and the UTF-8 absolute path, then a `u8` of permission flags (`1`
+    ///   if read-only, `2` if a `u32` mode follows), then for files a `u64` length and the
+    ///   contents and for symbolic links a `u32` length and the UTF-8 target
+    ///
+    /// Permissions of the root directory aren't saved.
+    ///
+    /// ```rust
+    /// use minql_vfs::{FileSystem, MemoryFileSystem};
+    /// use std::io::{Seek, SeekFrom, Write};
+    ///
+    /// let staging = MemoryFileSystem::new();
+    /// staging.create_file("/data.txt").unwrap().write_all(b"staged").unwrap();
+    ///
+    /// let backing = MemoryFileSystem::new();
+    /// let mut image = backing.create_file("/staging.img").unwrap();
+    /// staging.save_to(&mut image).unwrap();
+    ///
+    /// image.seek(SeekFrom::Start(0)).unwrap();
+    /// let restored = MemoryFileSystem::load_from(&mut image).unwrap();
+    /// assert_eq!(restored.filesize("/data.txt").unwrap(), 6);
+    /// ```
+    pub fn save_to<H: FileHandle + ?Sized>(&self, handle: &mut H) -> FileSystemResult<()> {
+        let guards = self.0.read_all();
+        let mut entries = Vec::new();
+        guards.walk("/", &mut entries);
+        let mut writer = BufWriter::new(handle);
+        let mut write = |bytes: &[u8]| writer.write_all(bytes).map_err(FileSystemError::io_error);
+        write(IMAGE_MAGIC)?;
+        write(&IMAGE_VERSION.to_le_bytes())?;
+        write(&(entries.len() as u64).to_le_bytes())?;
+        for (path, entry) in entries {
+            match entry {
+                MemoryEntry::Directory => write(&[IMAGE_DIRECTORY])?,
+                MemoryEntry::File(_) => write(&[IMAGE_FILE])?,
+                MemoryEntry::Symlink(_) => write(&[IMAGE_SYMLINK])?,
+            }
+            let path_len = u32::try_from(path.len())
+                .map_err(|_| FileSystemError::InvalidPath(path.clone()))?;
+            write(&path_len.to_le_bytes())?;
+            write(path.as_bytes())?;
+            let permissions = match &entry {
+                MemoryEntry::Directory => self.0.directory_permissions(&path),
+                MemoryEntry::File(file) => file.0.read().expect("Poisoned Lock").permissions,
+                MemoryEntry::Symlink(_) => Permissions::new(),
+            };
+            let flags = u8::from(permissions.is_readonly())
+                | if permissions.get_mode().is_some() {
+                    2
+                } else {
+                    0
+                };
+            write(&[flags])?;
+            if let Some(mode) = permissions.get_mode() {
+                write(&mode.to_le_bytes())?;
+            }
+            if let MemoryEntry::Symlink(target) = &entry {
+                let target_len = u32::try_from(target.len())
+                    .map_err(|_| FileSystemError::InvalidPath(target.clone()))?;
+                write(&target_len.to_le_bytes())?;
+                write(target.as_bytes())?;
+            }
+            if let MemoryEntry::File(file) = entry {
+                let data = file.0.read().expect("Poisoned Lock");
+                write(&(data.buffer.len() as u64).to_le_bytes())?;
+                let mut chunk = vec![0; CHUNK_SIZE];
+                let mut offset = 0;
+                while offset < data.buffer.len() {
+                    let read = data.buffer.read(offset, &mut chunk);
+                    write(&chunk[..read])?;
+                    offset += read;
+                }
+            }
+        }
+        drop(guards);
+        writer.flush().map_err(FileSystemError::io_error)
+    }
+
+    /// Restore a tree from an image written by [`MemoryFileSystem::save_to`], reading from
+    /// `handle` at its cursor.
+    ///
+    /// A truncated or malformed image fails with [`FileSystemError::CorruptData`] at the offset
+    /// of the bad field.
+    pub fn load_from<H: FileHandle + ?Sized>(handle: &mut H) -> FileSystemResult<MemoryFileSystem> {
+        let path = handle.path().to_string();
+        let start = handle
+            .stream_position()
+            .map_err(FileSystemError::io_error)?;
+        let mut reader = ImageReader {
+            handle,
+            path,
+            start,
+            offset: 0,
+        };
+        if reader.bytes(IMAGE_MAGIC.len())? != IMAGE_MAGIC {
+            return Err(reader.corrupt(0));
+        }
+        let version = reader.u32()?;
+        if !(1..=IMAGE_VERSION).contains(&version) {
+            return Err(reader.corrupt(0));
+        }
+        let filesystem = MemoryFileSystem::new();
+        // Directory permissions are applied last, so read-only directories can still be filled
+        let mut directories = HashMap::new();
+        let count = reader.u64()?;
+        for _ in 0..count {
+            let entry_offset = reader.offset;
+            let kind = reader.bytes(1)?[0];
+            let path_len = reader.u32()? as usize;
+            let path = String::from_utf8(reader.bytes(path_len)?)
+                .map_err(|_| reader.corrupt(entry_offset))?;
+            let mut permissions = Permissions::new();
+            if version >= 3 {
+                let flags = reader.bytes(1)?[0];
+                permissions = permissions.readonly(flags & 1 != 0);
+                if flags & 2 != 0 {
+                    permissions = permissions.mode(reader.u32()?);
+                }
+            }
+            let entry = match kind {
+                IMAGE_DIRECTORY => MemoryEntry::Directory,
+                IMAGE_FILE => {
+                    let len =
+                        usize::try_from(reader.u64()?).map_err(|_| reader.corrupt(entry_offset))?;
+                    let mut buffer = ChunkedBuffer::default();
+                    while buffer.len() < len {
+                        let chunk = reader.bytes((len - buffer.len()).min(CHUNK_SIZE))?;
+                        buffer.write(buffer.len(), &chunk);
+                    }
+                    MemoryEntry::File(MemoryFileEntry(Arc::new(RwLock::new(MemoryFileData {
+                        buffer,
+                        permissions,
+                        modified: filesystem.0.clock.now(),
+                        clock: filesystem.0.clock.clone(),
+                        snapshot: None,
+                        locks: Arc::default(),
+                    }))))
+                }
+                IMAGE_SYMLINK => {
+                    let target_len = reader.u32()? as usize;
+                    let target = String::from_utf8(reader.bytes(target_len)?)
+                        .map_err(|_| reader.corrupt(entry_offset))?;
+                    MemoryEntry::Symlink(target)
+                }
+                _ => return Err(reader.corrupt(entry_offset)),
+            };
+            let segments = normalize_segments(&path).map_err(|_| reader.corrupt(entry_offset))?;
+            let Some((name, parent)) = segments.split_last() else {
+                return Err(reader.corrupt(entry_offset));
+            };
+            if let MemoryEntry::Directory = entry {
+                directories.insert(join_segments(&segments), permissions);
+            }
+            filesystem
+                .insert(parent, name, entry)
+                .map_err(|_| reader.corrupt(entry_offset))?;
+        }
+        directories.retain(|_, permissions| *permissions != Permissions::new());
+        *filesystem.0.directories.write().expect("Poisoned Lock") = directories;
+        Ok(filesystem)
+    }
+}
+
+impl MemoryFileSystem {
+    /// Namespace for handles to account their file in, if acting as a cache.
+    fn cache(&self) -> Option<Arc<MemoryNamespace>> {
+        self.0.cache.as_ref().map(|_| self.0.clone())
+    }
+
+    /// Normalize a path and replace the symbolic links along it with their targets, including
+    /// one at the end of the path if `follow` is set.
+    ///
+    /// Paths are normalized lexically before any link is followed. Resolution stops at the first
+    /// missing entry or file, leaving the caller to report it.
+    fn resolve(&self, path: &str, follow: bool) -> FileSystemResult<Vec<String>> {
+        let mut segments = normalize_segments(path)?
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if !self.0.symlinks.load(Ordering::Acquire) {
+            return Ok(segments);
+        }
+        let mut hops = 0;
+        let mut depth = 1;
+        while depth < segments.len() || (follow && depth == segments.len()) {
+            match self.entry(&segments[..depth]) {
+                Some(MemoryEntry::Directory) => depth += 1,
+                Some(MemoryEntry::Symlink(target)) => {
+                    hops += 1;
+                    if hops > MAX_SYMLINK_HOPS {
+                        return Err(FileSystemError::InvalidPath(format!(
+                            "{path}: Too many levels of symbolic links"
+                        )));
+                    }
+                    let target = if target.starts_with(['/', '\\']) {
+                        target
+                    } else {
+                        format!("{}/{target}", join_segments(&segments[..depth - 1]))
+                    };
+                    let mut resolved = normalize_segments(&target)?
+                        .into_iter()
+                        .map(str::to_string)
+                        .collect::<Vec<_>>();
+                    resolved.extend(segments.drain(depth..));
+                    segments = resolved;
+                    depth = 1;
+                }
+                Some(MemoryEntry::File(_)) | None => break,
+            }
+        }
+        Ok(segments)
+    }
+
+    /// Find the entry at a path, holding only the lock of its parent's shard.
+    fn entry<S: AsRef<str>>(&self, segments: &[S]) -> Option<MemoryEntry> {
+        let (name, parent) = segments.split_last()?;
+        let parent = join_segments(parent);
+        self.0
+            .read(&[&parent])
+            .children(&parent)?
+            .get(name.as_ref())
+            .cloned()
+    }
+
+    /// Check there is a directory at a path, failing like a walk down from the root would.
+    fn directory<S: AsRef<str>>(&self, segments: &[S]) -> FileSystemResult<()> {
+        for depth in 1..=segments.len() {
+            match self.entry(&segments[..depth]) {
+                Some(MemoryEntry::Directory) => {}
+                Some(MemoryEntry::File(_) | MemoryEntry::Symlink(_)) => {
+                    return Err(FileSystemError::InvalidOperation)
+                }
+                None => return Err(FileSystemError::PathMissing),
+            }
+        }
+        Ok(())
+    }
+
+    /// Add `entry` as `name` in the existing directory at `parent`.
+    fn insert<S: AsRef<str>>(
+        &self,
+        parent: &[S],
+        name: &str,
+        entry: MemoryEntry,
+    ) -> FileSystemResult<()> {
+        let directory = join_segments(parent);
+        self.0.writable(&directory)?;
+        if let MemoryEntry::Symlink(_) = entry {
+            self.0.symlinks.store(true, Ordering::Release);
+        }
+        let Some((parent_name, grandparent)) = parent.split_last() else {
+            let mut guards = self.0.write(&[&directory]);
+            return guards.insert(&directory, name, entry);
+        };
+        let grandparent = join_segments(grandparent);
+        let mut guards = self.0.write(&[&directory, &grandparent]);
+        match guards
+            .children(&grandparent)
+            .and_then(|children| children.get(parent_name.as_ref()))
+        {
+            Some(MemoryEntry::Directory) => guards.insert(&directory, name, entry),
+            Some(MemoryEntry::File(_) | MemoryEntry::Symlink(_)) => {
+                Err(FileSystemError::InvalidOperation)
+            }
+            None => {
+                drop(guards);
+                Err(match self.directory(parent) {
+                    Err(FileSystemError::PathMissing) | Ok(()) => FileSystemError::ParentMissing,
+                    Err(err) => err,
+                })
+            }
+        }
+    }
+
+    /// Apply one operation of a batch while holding every shard, failing like the matching
+    /// `FileSystem` method would. Only valid while the namespace has no symbolic links.
+    fn apply_locked(
+        &self,
+        guards: &mut ShardGuards<RwLockWriteGuard<'_, MemoryShard>>,
+        operation: &BatchOperation,
+    ) -> FileSystemResult<()> {
+        match operation {
+            BatchOperation::CreateDirectory { path } => {
+                let segments = normalize_segments(path)?;
+                let Some((name, parent)) = segments.split_last() else {
+                    return Err(FileSystemError::PathExists);
+                };
+                guards.parent(parent)?;
+                self.0.writable(&join_segments(parent))?;
+                guards.insert(&join_segments(parent), name, MemoryEntry::Directory)
+            }
+            BatchOperation::CreateDirectoryAll { path } => {
+                let segments = normalize_segments(path)?;
+                for depth in 1..=segments.len() {
+                    match guards.entry(&segments[..depth]) {
+                        Some(MemoryEntry::Directory) => {}
+                        Some(MemoryEntry::File(_) | MemoryEntry::Symlink(_)) => {
+                            return Err(FileSystemError::InvalidOperation)
+                        }
+                        None => {
+                            let parent = join_segments(&segments[..depth - 1]);
+                            self.0.writable(&parent)?;
+                            guards.insert(&parent, segments[depth - 1], MemoryEntry::Directory)?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            BatchOperation::Write { path, contents } => self.write_locked(guards, path, contents),
+            BatchOperation::Rename { from, to } => self.rename_locked(guards, from, to),
+            BatchOperation::RemoveFile { path } => {
+                let segments = normalize_segments(path)?;
+                let Some((name, parent)) = segments.split_last() else {
+                    return Err(FileSystemError::InvalidOperation);
+                };
+                match guards.entry(&segments) {
+                    Some(MemoryEntry::File(_) | MemoryEntry::Symlink(_)) => {
+                        let parent = join_segments(parent);
+                        self.0.writable(&parent)?;
+                        guards.remove(&parent, name);
+                        Ok(())
+                    }
+                    Some(MemoryEntry::Directory) => Err(FileSystemError::InvalidOperation),
+                    None => {
+                        guards.directory(parent)?;
+                        Err(FileSystemError::PathMissing)
+                    }
+                }
+            }
+            BatchOperation::RemoveDirectory { path } => {
+                let segments = normalize_segments(path)?;
+                let Some((name, parent)) = segments.split_last() else {
+                    return Err(FileSystemError::InvalidOperation);
+                };
+                let directory = join_segments(&segments);
+                match guards.entry(&segments) {
+                    Some(MemoryEntry::Directory) if guards.names(&directory).is_empty() => {
+                        let parent = join_segments(parent);
+                        self.0.writable(&parent)?;
+                        guards.remove(&parent, name);
+                        self.0.forget(&directory);
+                        Ok(())
+                    }
+                    Some(
+                        MemoryEntry::Directory | MemoryEntry::File(_) | MemoryEntry::Symlink(_),
+                    ) => Err(FileSystemError::InvalidOperation),
+                    None => {
+                        guards.directory(parent)?;
+                        Err(FileSystemError::PathMissing)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace the contents of a file while holding every shard, as [`FileSystem::write`].
+    fn write_locked(
+        &self,
+        guards: &mut ShardGuards<RwLockWriteGuard<'_, MemoryShard>>,
+        path: &str,
+        contents: &[u8],
+    ) -> FileSystemResult<()> {
+        let segments = normalize_segments(path)?;
+        let Some((name, parent)) = segments.split_last() else {
+            return Err(FileSystemError::InvalidOperation);
+        };
+        guards.parent(parent)?;
+        match guards.entry(&segments) {
+            Some(MemoryEntry::File(file)) => {
+                let mut data = file.0.write().expect("Poisoned Lock");
+                data.modify()?;
+                data.buffer.set_len(0);
+                data.buffer.write(0, contents);
+                Ok(())
+            }
+            Some(MemoryEntry::Directory | MemoryEntry::Symlink(_)) => {
+                Err(FileSystemError::InvalidOperation)
+            }
+            None => {
+                let parent = join_segments(parent);
+                self.0.writable(&parent)?;
+                let mut buffer = ChunkedBuffer::default();
+                buffer.write(0, contents);
+                let data = MemoryFileData {
+                    buffer,
+                    permissions: Permissions::new(),
+                    modified: self.0.clock.now(),
+                    clock: self.0.clock.clone(),
+                    snapshot: None,
+                    locks: Arc::default(),
+                };
+                let entry = MemoryFileEntry(Arc::new(RwLock::new(data)));
+                guards.insert(&parent, name, MemoryEntry::File(entry))
+            }
+        }
+    }
+
+    /// Move a file while holding every shard, as [`FileSystem::rename`].
+    fn rename_locked(
+        &self,
+        guards: &mut ShardGuards<RwLockWriteGuard<'_, MemoryShard>>,
+        from: &str,
+        to: &str,
+    ) -> FileSystemResult<()> {
+        let from = normalize_segments(from)?;
+        let to = normalize_segments(to)?;
+        let (Some((from_name, from_parent)), Some((to_name, to_parent))) =
+            (from.split_last(), to.split_last())
+        else {
+            return Err(FileSystemError::InvalidOperation);
+        };
+        let entry = match guards.entry(&from) {
+            Some(entry @ MemoryEntry::File(_)) => entry.clone(),
+            Some(MemoryEntry::Directory | MemoryEntry::Symlink(_)) => {
+                return Err(FileSystemError::InvalidOperation)
+            }
+            None => {
+                guards.directory(from_parent)?;
+                return Err(FileSystemError::PathMissing);
+            }
+        };
+        guards.parent(to_parent)?;
+        if let Some(MemoryEntry::Directory) = guards.entry(&to) {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        let (from_parent, to_parent) = (join_segments(from_parent), join_segments(to_parent));
+        self.0.writable(&from_parent)?;
+        self.0.writable(&to_parent)?;
+        if from == to {
+            return Ok(());
+        }
+        guards.remove(&from_parent, from_name);
+        guards.remove(&to_parent, to_name);
+        guards.insert(&to_parent, to_name, entry)
+    }
+}
+
+/// Memory `FileSystem` Provider
+///
+/// Provisions a [`MemoryFileSystem`] for `mem://name` URIs. Every URI with the same name shares
+/// one tree for as long as the provider lives, so data written through one provisioned
+/// filesystem is visible through the next.
+#[derive(Debug, Default)]
+pub struct MemoryFileSystemProvider {
+    namespaces: RwLock<HashMap<String, MemoryFileSystem>>,
+}
+
+impl FileSystemProvider for MemoryFileSystemProvider {
+    type FileSystem = MemoryFileSystem;
+
+    fn schemes(&self) -> &[&str] {
+        &["mem"]
+    }
+
+    fn configure(&self, configuration: &HashMap<String, String>) -> FileSystemResult<()> {
+        Ok(())
+    }
+
+    fn provision(&self, url: &str) -> FileSystemResult<MemoryFileSystem> {
+        let uri = URI::parse(url)?;
+        let name = uri
+            .authority
+            .as_ref()
+            .map(|authority| authority.hostinfo.raw())
+            .unwrap_or_default();
+        if !matches!(uri.path.to_string().as_str(), "" | "/") {
+            return Err(FileSystemError::invalid_path(url));
+        }
+        let mut namespaces = self.namespaces.write().expect("Poisoned Lock");
+        Ok(namespaces.entry(name).or_default().clone())
     }
 }
 
@@ -58,212 +751,1187 @@ impl FileSystem for MemoryFileSystem {
 
     #[tracing::instrument(level = "trace")]
     fn exists(&self, path: &str) -> FileSystemResult<bool> {
-        let tree = self.0.read().expect("Poisoned Lock");
-        Ok(tree.contains_key(path))
+        let segments = self.resolve(path, true)?;
+        Ok(segments.is_empty() || self.entry(&segments).is_some())
     }
 
     #[tracing::instrument(level = "trace")]
     fn is_file(&self, path: &str) -> FileSystemResult<bool> {
-        let tree = self.0.read().expect("Poisoned Lock");
-        if let Some(entry) = tree.get(path) {
-            match entry {
-                MemoryEntry::File(_) => Ok(true),
-                _ => Ok(false),
-            }
-        } else {
-            Ok(false)
-        }
+        let segments = self.resolve(path, true)?;
+        Ok(matches!(self.entry(&segments), Some(MemoryEntry::File(_))))
     }
 
     #[tracing::instrument(level = "trace")]
     fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
-        let tree = self.0.read().expect("Poisoned Lock");
-        if let Some(entry) = tree.get(path) {
-            match entry {
-                MemoryEntry::Directory(_) => Ok(true),
-                _ => Ok(false),
-            }
-        } else {
-            Ok(false)
-        }
+        let segments = self.resolve(path, true)?;
+        Ok(segments.is_empty() || matches!(self.entry(&segments), Some(MemoryEntry::Directory)))
     }
 
     #[tracing::instrument(level = "trace")]
     fn filesize(&self, path: &str) -> FileSystemResult<u64> {
-        let tree = self.0.read().expect("Poisoned Lock");
-        if let Some(entry) = tree.get(path) {
-            match entry {
-                MemoryEntry::File(file) => {
-                    let data = file.0.read().expect("Poisoned Lock");
-                    Ok(data.buffer.len() as u64)
-                }
-                _ => Err(FileSystemError::InvalidOperation),
+        let segments = self.resolve(path, true)?;
+        match self.entry(&segments) {
+            Some(MemoryEntry::File(file)) => {
+                let data = file.0.read().expect("Poisoned Lock");
+                Ok(data.buffer.len() as u64)
             }
-        } else {
-            Err(FileSystemError::PathMissing)
+            Some(MemoryEntry::Directory | MemoryEntry::Symlink(_)) => {
+                Err(FileSystemError::InvalidOperation)
+            }
+            None if segments.is_empty() => Err(FileSystemError::InvalidOperation),
+            None => Err(FileSystemError::PathMissing),
         }
     }
 
     #[tracing::instrument(level = "trace")]
     fn create_directory(&self, path: &str) -> FileSystemResult<()> {
-        let mut tree = self.0.write().expect("Poisoned Lock");
-        if tree.contains_key(path) {
-            Err(FileSystemError::PathExists)
-        } else {
-            tree.insert(
-                path.to_string(),
-                MemoryEntry::Directory(MemoryDirectoryEntry(Arc::new(RwLock::new(
-                    MemoryDirectoryData(BTreeMap::new()),
-                )))),
-            );
-            Ok(())
-        }
+        let segments = self.resolve(path, false)?;
+        let Some((name, parent)) = segments.split_last() else {
+            return Err(FileSystemError::PathExists);
+        };
+        self.insert(parent, name, MemoryEntry::Directory)
     }
 
     #[tracing::instrument(level = "trace")]
     fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
-        let mut tree = self.0.write().expect("Poisoned Lock");
-        if tree.contains_key(path) {
-            Err(FileSystemError::PathExists)
-        } else {
-            let mut parent_path = Path::parse(path)?.builder();
-            loop {
-                if parent_path.segments().is_empty() {
-                    break;
-                }
-                if !tree.contains_key(&parent_path.to_string()) {
-                    tree.insert(
-                        parent_path.to_string(),
-                        MemoryEntry::Directory(MemoryDirectoryEntry(Arc::new(RwLock::new(
-                            MemoryDirectoryData(BTreeMap::new()),
-                        )))),
-                    );
-                }
-                parent_path = parent_path.parent();
+        let segments = self.resolve(path, true)?;
+        for depth in 1..=segments.len() {
+            let (name, parent) = segments[..depth].split_last().expect("Non-Empty Path");
+            match self.insert(parent, name, MemoryEntry::Directory) {
+                Ok(()) => {}
+                Err(FileSystemError::PathExists | FileSystemError::PermissionDenied)
+                    if matches!(self.entry(&segments[..depth]), Some(MemoryEntry::Directory)) => {}
+                Err(err) => return Err(err),
             }
-            tree.insert(
-                path.to_string(),
-                MemoryEntry::Directory(MemoryDirectoryEntry(Arc::new(RwLock::new(
-                    MemoryDirectoryData(BTreeMap::new()),
-                )))),
-            );
-            Ok(())
         }
+        Ok(())
     }
 
     #[tracing::instrument(level = "trace")]
-    fn list_directory<'a>(&self, path: &str) -> FileSystemResult<Vec<String>> {
-        let tree = self.0.read().expect("Poisoned Lock");
-        if let Some(entry) = tree.get(path) {
-            match entry {
-                MemoryEntry::Directory(dir) => {
-                    let dir = dir.0.read().expect("Poisoned Lock");
-                    Ok(dir.0.keys().map(|s| s.clone()).collect())
-                }
-                _ => Err(FileSystemError::InvalidOperation),
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        let segments = self.resolve(path, true)?;
+        let directory = join_segments(&segments);
+        let Some((name, parent)) = segments.split_last() else {
+            let guards = self.0.read(&[&directory]);
+            return Ok(guards.names(&directory));
+        };
+        let parent = join_segments(parent);
+        let guards = self.0.read(&[&directory, &parent]);
+        match guards
+            .children(&parent)
+            .and_then(|children| children.get(name))
+        {
+            Some(MemoryEntry::Directory) => Ok(guards.names(&directory)),
+            Some(MemoryEntry::File(_) | MemoryEntry::Symlink(_)) => {
+                Err(FileSystemError::InvalidOperation)
+            }
+            None => {
+                drop(guards);
+                self.directory(&segments)?;
+                Err(FileSystemError::PathMissing)
             }
-        } else {
-            Err(FileSystemError::PathMissing)
         }
     }
 
     #[tracing::instrument(level = "trace")]
     fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
-        self.remove_directory_all(path)
+        let segments = self.resolve(path, false)?;
+        let Some((name, parent_segments)) = segments.split_last() else {
+            return Err(FileSystemError::InvalidOperation);
+        };
+        let directory = join_segments(&segments);
+        let parent = join_segments(parent_segments);
+        self.0.writable(&parent)?;
+        let mut guards = self.0.write(&[&directory, &parent]);
+        match guards
+            .children(&parent)
+            .and_then(|children| children.get(name))
+        {
+            Some(MemoryEntry::Directory) if guards.names(&directory).is_empty() => {
+                guards.remove(&parent, name);
+                self.0.forget(&directory);
+                Ok(())
+            }
+            Some(MemoryEntry::Directory | MemoryEntry::File(_) | MemoryEntry::Symlink(_)) => {
+                Err(FileSystemError::InvalidOperation)
+            }
+            None => {
+                drop(guards);
+                self.directory(parent_segments)?;
+                Err(FileSystemError::PathMissing)
+            }
+        }
     }
 
     #[tracing::instrument(level = "trace")]
     fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
-        let mut tree = self.0.write().expect("Poisoned Lock");
-        match tree.remove(path) {
-            Some(_) => Ok(()),
-            None => Err(FileSystemError::PathMissing),
+        let segments = self.resolve(path, false)?;
+        let Some((name, parent_segments)) = segments.split_last() else {
+            return Err(FileSystemError::InvalidOperation);
+        };
+        let directory = join_segments(&segments);
+        let parent = join_segments(parent_segments);
+        self.0.writable(&parent)?;
+        // Descendants may live in any shard, so this is the one operation that takes them all.
+        let mut guards = self.0.write_all();
+        match guards
+            .children(&parent)
+            .and_then(|children| children.get(name))
+        {
+            Some(MemoryEntry::Directory) => {
+                guards.remove(&parent, name);
+                let descendants = format!("{directory}/");
+                for shard in guards.0.values_mut() {
+                    shard
+                        .0
+                        .retain(|path, _| path != &directory && !path.starts_with(&descendants));
+                }
+                drop(guards);
+                self.0.forget(&directory);
+                self.0.uncache(&directory);
+                Ok(())
+            }
+            Some(MemoryEntry::File(_) | MemoryEntry::Symlink(_)) => {
+                Err(FileSystemError::InvalidOperation)
+            }
+            None => {
+                drop(guards);
+                self.directory(parent_segments)?;
+                Err(FileSystemError::PathMissing)
+            }
         }
     }
 
     #[tracing::instrument(level = "trace")]
     fn create_file(&self, path: &str) -> FileSystemResult<MemoryFileHandle> {
-        let mut tree = self.0.write().expect("Poisoned Lock");
-        if tree.contains_key(path) {
-            Err(FileSystemError::PathExists)
-        } else {
-            let parent = Path::parse(path)?.builder().parent();
-            let inner = Arc::new(RwLock::new(MemoryFileData {
-                buffer: Vec::default(),
-                lock: FileLockMode::Unlocked,
-            }));
-            tree.insert(
-                path.to_string(),
-                MemoryEntry::File(MemoryFileEntry(inner.clone())),
-            );
-            Ok(MemoryFileHandle {
-                cursor: 0,
-                name: path.to_string(),
-                data: inner.clone(),
-            })
-        }
+        let segments = self.resolve(path, true)?;
+        let Some((name, parent)) = segments.split_last() else {
+            return Err(FileSystemError::PathExists);
+        };
+        let inner = Arc::new(RwLock::new(MemoryFileData {
+            buffer: ChunkedBuffer::default(),
+            permissions: Permissions::new(),
+            modified: self.0.clock.now(),
+            clock: self.0.clock.clone(),
+            snapshot: None,
+            locks: Arc::default(),
+        }));
+        self.insert(
+            parent,
+            name,
+            MemoryEntry::File(MemoryFileEntry(inner.clone())),
+        )?;
+        let path = join_segments(&segments);
+        self.0.touch(&path, &inner, Some(0));
+        Ok(MemoryFileHandle::new(path, inner, self.cache()))
     }
 
     #[tracing::instrument(level = "trace")]
     fn open_file(&self, path: &str) -> FileSystemResult<MemoryFileHandle> {
-        if let Some(entry) = self.0.read().expect("Poisoned Lock").get(path) {
-            match entry {
-                MemoryEntry::File(file) => Ok(MemoryFileHandle {
-                    cursor: 0,
-                    name: path.to_string(),
-                    data: file.0.clone(),
-                }),
-                _ => Err(FileSystemError::InvalidOperation),
+        let segments = self.resolve(path, true)?;
+        match self.entry(&segments) {
+            Some(MemoryEntry::File(file)) => {
+                let path = join_segments(&segments);
+                self.0.touch(&path, &file.0, None);
+                Ok(MemoryFileHandle::new(path, file.0, self.cache()))
             }
-        } else {
-            Err(FileSystemError::PathMissing)
+            Some(MemoryEntry::Directory | MemoryEntry::Symlink(_)) => {
+                Err(FileSystemError::InvalidOperation)
+            }
+            None if segments.is_empty() => Err(FileSystemError::InvalidOperation),
+            None => Err(FileSystemError::PathMissing),
         }
     }
 
     #[tracing::instrument(level = "trace")]
     fn remove_file(&self, path: &str) -> FileSystemResult<()> {
-        if self.0.read().expect("Poisoned Lock").contains_key(path) {
-            self.0.write().expect("Poisoned Lock").remove(path);
-            Ok(())
+        let segments = self.resolve(path, false)?;
+        let Some((name, parent_segments)) = segments.split_last() else {
+            return Err(FileSystemError::InvalidOperation);
+        };
+        let parent = join_segments(parent_segments);
+        self.0.writable(&parent)?;
+        let mut guards = self.0.write(&[&parent]);
+        match guards
+            .children(&parent)
+            .and_then(|children| children.get(name))
+        {
+            Some(MemoryEntry::File(_) | MemoryEntry::Symlink(_)) => {
+                guards.remove(&parent, name);
+                drop(guards);
+                self.0.uncache(&join_segments(&segments));
+                Ok(())
+            }
+            Some(MemoryEntry::Directory) => Err(FileSystemError::InvalidOperation),
+            None => {
+                drop(guards);
+                self.directory(parent_segments)?;
+                Err(FileSystemError::PathMissing)
+            }
+        }
+    }
+
+    /// Moves the entry between directories under their locks, so open handles keep working on
+    /// the renamed file.
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        let from_segments = self.resolve(from, false)?;
+        let to_segments = self.resolve(to, false)?;
+        let (Some((from_name, from_parent)), Some((to_name, to_parent))) =
+            (from_segments.split_last(), to_segments.split_last())
+        else {
+            return Err(FileSystemError::InvalidOperation);
+        };
+        let (source, source_parent) = (join_segments(&from_segments), join_segments(from_parent));
+        let (target, target_parent) = (join_segments(&to_segments), join_segments(to_parent));
+        self.0.writable(&source_parent)?;
+        self.0.writable(&target_parent)?;
+        let target_grandparent = to_parent
+            .split_last()
+            .map(|(_, grandparent)| join_segments(grandparent));
+        let mut directories = vec![source_parent.as_str(), target_parent.as_str()];
+        directories.extend(target_grandparent.as_deref());
+        let mut guards = self.0.write(&directories);
+        let data = match guards.entry(&from_segments) {
+            Some(MemoryEntry::File(file)) => file.0.clone(),
+            Some(MemoryEntry::Directory | MemoryEntry::Symlink(_)) => {
+                return Err(FileSystemError::InvalidOperation)
+            }
+            None => {
+                drop(guards);
+                self.directory(from_parent)?;
+                return Err(FileSystemError::PathMissing);
+            }
+        };
+        if !to_parent.is_empty() && !matches!(guards.entry(to_parent), Some(MemoryEntry::Directory))
+        {
+            drop(guards);
+            return Err(match self.directory(to_parent) {
+                Err(FileSystemError::PathMissing) | Ok(()) => FileSystemError::ParentMissing,
+                Err(err) => err,
+            });
+        }
+        if let Some(MemoryEntry::Directory) = guards.entry(&to_segments) {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        if source == target {
+            return Ok(());
+        }
+        guards.remove(&source_parent, from_name);
+        guards.remove(&target_parent, to_name);
+        guards.insert(
+            &target_parent,
+            to_name,
+            MemoryEntry::File(MemoryFileEntry(data.clone())),
+        )?;
+        drop(guards);
+        self.0.uncache(&source);
+        let size = data.read().expect("Poisoned Lock").buffer.len() as u64;
+        self.0.touch(&target, &data, Some(size));
+        Ok(())
+    }
+
+    /// Takes the lock of every shard once for the whole batch, unless the namespace has symbolic
+    /// links or acts as a cache, in which case the operations are applied one by one.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        if self.0.cache.is_none() {
+            let mut guards = self.0.write_all();
+            // Checked under the locks as a link is only inserted while holding its shard.
+            if !self.0.symlinks.load(Ordering::Acquire) {
+                return batch
+                    .iter()
+                    .map(|operation| self.apply_locked(&mut guards, operation))
+                    .collect();
+            }
+        }
+        batch
+            .iter()
+            .map(|operation| apply_operation(self, operation))
+            .collect()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        let segments = self.resolve(path, policy == SymlinkPolicy::Follow)?;
+        match self.entry(&segments) {
+            Some(MemoryEntry::File(_)) => Ok(FileType::File),
+            Some(MemoryEntry::Directory) => Ok(FileType::Directory),
+            Some(MemoryEntry::Symlink(_)) => Ok(FileType::Symlink),
+            None if segments.is_empty() => Ok(FileType::Directory),
+            None => Err(FileSystemError::PathMissing),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        if target.is_empty() {
+            return Err(FileSystemError::invalid_path(target));
+        }
+        let segments = self.resolve(path, false)?;
+        let Some((name, parent)) = segments.split_last() else {
+            return Err(FileSystemError::PathExists);
+        };
+        self.insert(parent, name, MemoryEntry::Symlink(target.to_string()))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        let segments = self.resolve(path, false)?;
+        match self.entry(&segments) {
+            Some(MemoryEntry::Symlink(target)) => Ok(target),
+            Some(MemoryEntry::File(_) | MemoryEntry::Directory) => {
+                Err(FileSystemError::InvalidOperation)
+            }
+            None if segments.is_empty() => Err(FileSystemError::InvalidOperation),
+            None => Err(FileSystemError::PathMissing),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        let segments = self.resolve(path, true)?;
+        match self.entry(&segments) {
+            Some(MemoryEntry::File(file)) => Ok(file.0.read().expect("Poisoned Lock").permissions),
+            Some(MemoryEntry::Directory | MemoryEntry::Symlink(_)) => {
+                Ok(self.0.directory_permissions(&join_segments(&segments)))
+            }
+            None if segments.is_empty() => Ok(self.0.directory_permissions("/")),
+            None => Err(FileSystemError::PathMissing),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        let segments = self.resolve(path, true)?;
+        match self.entry(&segments) {
+            Some(MemoryEntry::File(file)) => Ok(file.0.read().expect("Poisoned Lock").modified),
+            Some(MemoryEntry::Directory | MemoryEntry::Symlink(_)) => {
+                Err(FileSystemError::UnsupportedOperation)
+            }
+            None if segments.is_empty() => Err(FileSystemError::UnsupportedOperation),
+            None => Err(FileSystemError::PathMissing),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        let segments = self.resolve(path, true)?;
+        match self.entry(&segments) {
+            Some(MemoryEntry::File(file)) => {
+                file.0.write().expect("Poisoned Lock").permissions = permissions;
+                return Ok(());
+            }
+            Some(MemoryEntry::Directory | MemoryEntry::Symlink(_)) => {}
+            None if segments.is_empty() => {}
+            None => return Err(FileSystemError::PathMissing),
+        }
+        let directory = join_segments(&segments);
+        let mut directories = self.0.directories.write().expect("Poisoned Lock");
+        if permissions == Permissions::new() {
+            directories.remove(&directory);
         } else {
-            Err(FileSystemError::PathMissing)
+            directories.insert(directory, permissions);
+        }
+        Ok(())
+    }
+
+    /// Reports the configured capacity as the total and the length of every file as used.
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        let guards = self.0.read_all();
+        let used = guards
+            .0
+            .values()
+            .flat_map(|shard| shard.0.values())
+            .flat_map(BTreeMap::values)
+            .map(|entry| match entry {
+                MemoryEntry::Directory | MemoryEntry::Symlink(_) => 0,
+                MemoryEntry::File(file) => {
+                    file.0.read().expect("Poisoned Lock").buffer.len() as u64
+                }
+            })
+            .sum::<u64>();
+        Ok(FileSystemSpace {
+            total: self.0.capacity,
+            used,
+            available: self.0.capacity.saturating_sub(used),
+        })
+    }
+
+    /// Copies the contents straight out of the file without opening a handle.
+    #[tracing::instrument(level = "trace")]
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        let segments = self.resolve(path, true)?;
+        match self.entry(&segments) {
+            Some(MemoryEntry::File(file)) => {
+                self.0.touch(&join_segments(&segments), &file.0, None);
+                let data = file.0.read().expect("Poisoned Lock");
+                let mut contents = vec![0; data.buffer.len()];
+                data.buffer.read(0, &mut contents);
+                Ok(contents)
+            }
+            Some(MemoryEntry::Directory | MemoryEntry::Symlink(_)) => {
+                Err(FileSystemError::InvalidOperation)
+            }
+            None if segments.is_empty() => Err(FileSystemError::InvalidOperation),
+            None => Err(FileSystemError::PathMissing),
         }
     }
 }
 
 #[derive(Clone, Debug)]
 enum MemoryEntry {
-    Directory(MemoryDirectoryEntry),
+    Directory,
     File(MemoryFileEntry),
+    Symlink(String),
 }
 
-#[derive(Clone, Debug)]
-struct MemoryDirectoryEntry(Arc<RwLock<MemoryDirectoryData>>);
+/// Files of a [`MemoryFileSystem`] acting as a cache, keyed by path.
+#[derive(Debug, Default)]
+struct MemoryCache {
+    budget: u64,
+    /// Total size of the cached files as of when they were last closed.
+    used: u64,
+    /// Logical clock ordering accesses.
+    clock: u64,
+    evictions: u64,
+    files: HashMap<String, CachedFile>,
+    pinned: HashSet<String>,
+}
 
-#[derive(Clone, Debug)]
-struct MemoryDirectoryData(BTreeMap<String, String>);
+#[derive(Debug)]
+struct CachedFile {
+    data: Weak<RwLock<MemoryFileData>>,
+    size: u64,
+    accessed: u64,
+}
+
+/// Most symbolic links followed while resolving a single path.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Number of shards the namespace of a [`MemoryFileSystem`] is split across.
+const NAMESPACE_SHARDS: usize = 16;
+
+/// Namespace of a [`MemoryFileSystem`].
+///
+/// The children of each directory are kept in the shard its path hashes to. Operations lock
+/// the shards they touch in index order, so concurrent operations can't deadlock.
+#[derive(Debug)]
+struct MemoryNamespace {
+    capacity: u64,
+    /// Set once any symbolic link is created, so paths without links skip resolving them.
+    symlinks: AtomicBool,
+    /// Permissions of directories that aren't writable with no mode, keyed by path.
+    directories: RwLock<HashMap<String, Permissions>>,
+    /// Source of modification times.
+    clock: Arc<dyn Clock>,
+    /// Budget and access order of files, when acting as a cache.
+    cache: Option<Mutex<MemoryCache>>,
+    shards: Vec<RwLock<MemoryShard>>,
+}
+
+impl Default for MemoryNamespace {
+    fn default() -> Self {
+        MemoryNamespace {
+            capacity: u64::MAX,
+            symlinks: AtomicBool::new(false),
+            directories: RwLock::default(),
+            clock: Arc::new(SystemClock),
+            cache: None,
+            shards: (0..NAMESPACE_SHARDS).map(|_| RwLock::default()).collect(),
+        }
+    }
+}
+
+impl MemoryNamespace {
+    /// Permissions of the directory at `directory`.
+    fn directory_permissions(&self, directory: &str) -> Permissions {
+        self.directories
+            .read()
+            .expect("Poisoned Lock")
+            .get(directory)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Fail unless entries can be created in or removed from `directory`.
+    fn writable(&self, directory: &str) -> FileSystemResult<()> {
+        if self.directory_permissions(directory).is_readonly() {
+            return Err(FileSystemError::PermissionDenied);
+        }
+        Ok(())
+    }
+
+    /// Drop the permissions of `directory` and everything below it once removed.
+    fn forget(&self, directory: &str) {
+        let descendants = format!("{directory}/");
+        self.directories
+            .write()
+            .expect("Poisoned Lock")
+            .retain(|path, _| path != directory && !path.starts_with(&descendants));
+    }
+
+    /// Record an access to the file at `path`, along with its size if known.
+    fn touch(&self, path: &str, data: &Arc<RwLock<MemoryFileData>>, size: Option<u64>) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        let mut cache = cache.lock().expect("Poisoned Lock");
+        cache.clock += 1;
+        let accessed = cache.clock;
+        let data = Arc::downgrade(data);
+        let previous = match cache.files.get_mut(path) {
+            Some(file) if file.data.ptr_eq(&data) => {
+                file.accessed = accessed;
+                match size {
+                    Some(size) => std::mem::replace(&mut file.size, size),
+                    None => file.size,
+                }
+            }
+            // A new file, or one which replaced a file that was removed.
+            _ => {
+                let file = CachedFile {
+                    data,
+                    size: size.unwrap_or(0),
+                    accessed,
+                };
+                cache
+                    .files
+                    .insert(path.to_string(), file)
+                    .map_or(0, |file| file.size)
+            }
+        };
+        let size = cache.files[path].size;
+        cache.used = cache.used - previous + size;
+    }
+
+    /// Account the size of a file as a handle on it closes, unless it was removed since, and
+    /// evict files if that puts the cache over its budget.
+    fn close(&self, path: &str, data: &Arc<RwLock<MemoryFileData>>) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        let size = data.read().expect("Poisoned Lock").buffer.len() as u64;
+        {
+            let mut cache = cache.lock().expect("Poisoned Lock");
+            cache.clock += 1;
+            let accessed = cache.clock;
+            let Some(file) = cache.files.get_mut(path) else {
+                return;
+            };
+            if !file.data.ptr_eq(&Arc::downgrade(data)) {
+                return;
+            }
+            file.accessed = accessed;
+            let previous = std::mem::replace(&mut file.size, size);
+            cache.used = cache.used - previous + size;
+        }
+        self.evict(Some(data));
+    }
+
+    /// Stop accounting for, and unpin, the file at `path` or every file below it.
+    fn uncache(&self, path: &str) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        let mut cache = cache.lock().expect("Poisoned Lock");
+        let descendants = format!("{path}/");
+        let mut freed = 0;
+        cache.files.retain(|file, cached| {
+            let keep = file != path && !file.starts_with(&descendants);
+            if !keep {
+                freed += cached.size;
+            }
+            keep
+        });
+        cache.used -= freed;
+        cache
+            .pinned
+            .retain(|file| file != path && !file.starts_with(&descendants));
+    }
+
+    /// Evict the least recently accessed files until the cache is within its budget.
+    ///
+    /// `closing` is the contents of a handle being closed, which is evictable as long as no
+    /// other handle is open on it.
+    fn evict(&self, closing: Option<&Arc<RwLock<MemoryFileData>>>) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        let mut cache = cache.lock().expect("Poisoned Lock");
+        if cache.used <= cache.budget {
+            return;
+        }
+        let mut candidates: Vec<(u64, String)> = cache
+            .files
+            .iter()
+            .filter(|(path, file)| {
+                let handles = match closing {
+                    Some(closing) if file.data.ptr_eq(&Arc::downgrade(closing)) => 2,
+                    _ => 1,
+                };
+                !cache.pinned.contains(*path) && file.data.strong_count() <= handles
+            })
+            .map(|(path, file)| (file.accessed, path.clone()))
+            .collect();
+        candidates.sort_unstable();
+        for (_, path) in candidates {
+            if cache.used <= cache.budget {
+                break;
+            }
+            let file = cache.files.remove(&path).expect("Cached File");
+            cache.used -= file.size;
+            let Some((parent, name)) = path.rsplit_once('/') else {
+                continue;
+            };
+            let parent = if parent.is_empty() { "/" } else { parent };
+            let mut guards = self.write(&[parent]);
+            if let Some(MemoryEntry::File(entry)) = guards
+                .children(parent)
+                .and_then(|children| children.get(name))
+            {
+                if file.data.ptr_eq(&Arc::downgrade(&entry.0)) {
+                    guards.remove(parent, name);
+                    cache.evictions += 1;
+                    tracing::debug!(path, size = file.size, "Evicted file");
+                }
+            }
+        }
+    }
+
+    /// Shard holding the children of `directory`.
+    fn shard(directory: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        directory.hash(&mut hasher);
+        usize::try_from(hasher.finish() % NAMESPACE_SHARDS as u64).expect("Shard In Range")
+    }
+
+    /// Indexes of the shards holding the children of `directories`, in lock order.
+    fn indexes(directories: &[&str]) -> Vec<usize> {
+        let mut indexes = directories
+            .iter()
+            .map(|directory| MemoryNamespace::shard(directory))
+            .collect::<Vec<_>>();
+        indexes.sort_unstable();
+        indexes.dedup();
+        indexes
+    }
+
+    /// Share the shards holding the children of `directories`.
+    fn read(&self, directories: &[&str]) -> ShardGuards<RwLockReadGuard<'_, MemoryShard>> {
+        ShardGuards(
+            MemoryNamespace::indexes(directories)
+                .into_iter()
+                .map(|index| (index, self.shards[index].read().expect("Poisoned Lock")))
+                .collect(),
+        )
+    }
+
+    /// Lock the shards holding the children of `directories`.
+    fn write(&self, directories: &[&str]) -> ShardGuards<RwLockWriteGuard<'_, MemoryShard>> {
+        ShardGuards(
+            MemoryNamespace::indexes(directories)
+                .into_iter()
+                .map(|index| (index, self.shards[index].write().expect("Poisoned Lock")))
+                .collect(),
+        )
+    }
+
+    /// Share every shard.
+    fn read_all(&self) -> ShardGuards<RwLockReadGuard<'_, MemoryShard>> {
+        ShardGuards(
+            self.shards
+                .iter()
+                .map(|shard| shard.read().expect("Poisoned Lock"))
+                .enumerate()
+                .collect(),
+        )
+    }
+
+    /// Lock every shard.
+    fn write_all(&self) -> ShardGuards<RwLockWriteGuard<'_, MemoryShard>> {
+        ShardGuards(
+            self.shards
+                .iter()
+                .map(|shard| shard.write().expect("Poisoned Lock"))
+                .enumerate()
+                .collect(),
+        )
+    }
+}
+
+/// Children of the directories hashed to one shard, keyed by directory path and then name.
+#[derive(Debug, Default)]
+struct MemoryShard(HashMap<String, BTreeMap<String, MemoryEntry>>);
+
+/// Guards on a set of shards, keyed by shard index.
+struct ShardGuards<G>(BTreeMap<usize, G>);
+
+impl<G: Deref<Target = MemoryShard>> ShardGuards<G> {
+    /// Children of `directory`, whose shard must be held.
+    fn children(&self, directory: &str) -> Option<&BTreeMap<String, MemoryEntry>> {
+        self.0[&MemoryNamespace::shard(directory)].0.get(directory)
+    }
+
+    /// Entry at a path, whose parent's shard must be held.
+    fn entry<S: AsRef<str>>(&self, segments: &[S]) -> Option<&MemoryEntry> {
+        let (name, parent) = segments.split_last()?;
+        self.children(&join_segments(parent))?.get(name.as_ref())
+    }
+
+    /// Check there is a directory at a path, whose ancestors' shards must all be held.
+    fn directory<S: AsRef<str>>(&self, segments: &[S]) -> FileSystemResult<()> {
+        for depth in 1..=segments.len() {
+            match self.entry(&segments[..depth]) {
+                Some(MemoryEntry::Directory) => {}
+                Some(MemoryEntry::File(_) | MemoryEntry::Symlink(_)) => {
+                    return Err(FileSystemError::InvalidOperation)
+                }
+                None => return Err(FileSystemError::PathMissing),
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the parent of a new entry exists, failing like [`MemoryFileSystem::insert`].
+    fn parent<S: AsRef<str>>(&self, segments: &[S]) -> FileSystemResult<()> {
+        self.directory(segments).map_err(|err| match err {
+            FileSystemError::PathMissing => FileSystemError::ParentMissing,
+            err => err,
+        })
+    }
+
+    /// Names of the children of `directory`, whose shard must be held.
+    fn names(&self, directory: &str) -> Vec<String> {
+        self.children(directory)
+            .map(|children| children.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Collect the path of every entry below `directory`, parents before children.
+    fn walk(&self, directory: &str, entries: &mut Vec<(String, MemoryEntry)>) {
+        for (name, entry) in self.children(directory).into_iter().flatten() {
+            let path = format!("{}/{name}", directory.trim_end_matches('/'));
+            entries.push((path.clone(), entry.clone()));
+            if let MemoryEntry::Directory = entry {
+                self.walk(&path, entries);
+            }
+        }
+    }
+}
+
+impl<G: DerefMut<Target = MemoryShard>> ShardGuards<G> {
+    /// Add `entry` as `name` in `directory`, whose shard must be held.
+    fn insert(&mut self, directory: &str, name: &str, entry: MemoryEntry) -> FileSystemResult<()> {
+        let shard = self
+            .0
+            .get_mut(&MemoryNamespace::shard(directory))
+            .expect("Shard Held");
+        let children = shard.0.entry(directory.to_string()).or_default();
+        if children.contains_key(name) {
+            return Err(FileSystemError::PathExists);
+        }
+        children.insert(name.to_string(), entry);
+        Ok(())
+    }
+
+    /// Remove `name` from `directory`, whose shard must be held.
+    fn remove(&mut self, directory: &str, name: &str) {
+        let shard = self
+            .0
+            .get_mut(&MemoryNamespace::shard(directory))
+            .expect("Shard Held");
+        if let Some(children) = shard.0.get_mut(directory) {
+            children.remove(name);
+            if children.is_empty() {
+                shard.0.remove(directory);
+            }
+        }
+    }
+}
+
+/// Magic bytes opening a [`MemoryFileSystem::save_to`] image.
+const IMAGE_MAGIC: &[u8] = b"MQLMEMFS";
+/// Version of the image format, which can also load every earlier version.
+const IMAGE_VERSION: u32 = 3;
+/// Image entry kind of a directory.
+const IMAGE_DIRECTORY: u8 = 1;
+/// Image entry kind of a file.
+const IMAGE_FILE: u8 = 2;
+/// Image entry kind of a symbolic link.
+const IMAGE_SYMLINK: u8 = 3;
+
+/// Reads the fields of an image, reporting short reads as corruption.
+struct ImageReader<'a, H: FileHandle + ?Sized> {
+    handle: &'a mut H,
+    path: String,
+    start: u64,
+    /// Offset of the next field from the start of the image.
+    offset: u64,
+}
+
+impl<H: FileHandle + ?Sized> ImageReader<'_, H> {
+    fn corrupt(&self, offset: u64) -> FileSystemError {
+        FileSystemError::CorruptData {
+            path: self.path.clone(),
+            offset: self.start + offset,
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> FileSystemResult<Vec<u8>> {
+        let mut buffer = Vec::new();
+        let read = (&mut *self.handle)
+            .take(len as u64)
+            .read_to_end(&mut buffer)
+            .map_err(FileSystemError::io_error)?;
+        if read < len {
+            return Err(self.corrupt(self.offset + read as u64));
+        }
+        self.offset += len as u64;
+        Ok(buffer)
+    }
+
+    fn u32(&mut self) -> FileSystemResult<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes(
+            bytes.try_into().expect("Length Checked"),
+        ))
+    }
+
+    fn u64(&mut self) -> FileSystemResult<u64> {
+        let bytes = self.bytes(8)?;
+        Ok(u64::from_le_bytes(
+            bytes.try_into().expect("Length Checked"),
+        ))
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct MemoryFileEntry(Arc<RwLock<MemoryFileData>>);
 
+/// Size of each chunk of a file's contents.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Contents of a file stored as fixed-size chunks.
+///
+/// Writes, truncation and growth only touch the chunks they cover, so large files never need to
+/// be reallocated or copied as a whole. Missing chunks are holes, chunks are only as long as
+/// the data written to them, and anything inside the file past the end of a chunk reads as
+/// zeros. Chunks are shared copy-on-write with forks.
+#[derive(Clone, Default)]
+struct ChunkedBuffer {
+    len: usize,
+    chunks: Vec<Option<Arc<Vec<u8>>>>,
+}
+
+impl ChunkedBuffer {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Copy bytes starting at `offset` into `buf`, returning how many were within the file.
+    fn read(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.len.saturating_sub(offset));
+        let mut done = 0;
+        while done < len {
+            let position = offset + done;
+            let (index, within) = (position / CHUNK_SIZE, position % CHUNK_SIZE);
+            let count = (CHUNK_SIZE - within).min(len - done);
+            let target = &mut buf[done..done + count];
+            match self.chunks.get(index).and_then(Option::as_ref) {
+                Some(chunk) => {
+                    let stored = chunk.get(within..).unwrap_or_default();
+                    let available = stored.len().min(count);
+                    target[..available].copy_from_slice(&stored[..available]);
+                    target[available..].fill(0);
+                }
+                None => target.fill(0),
+            }
+            done += count;
+        }
+        len
+    }
+
+    /// Copy `data` into the file at `offset`, growing it if needed.
+    fn write(&mut self, offset: usize, data: &[u8]) {
+        let mut done = 0;
+        while done < data.len() {
+            let position = offset + done;
+            let (index, within) = (position / CHUNK_SIZE, position % CHUNK_SIZE);
+            let count = (CHUNK_SIZE - within).min(data.len() - done);
+            if self.chunks.len() <= index {
+                self.chunks.resize(index + 1, None);
+            }
+            let chunk = Arc::make_mut(self.chunks[index].get_or_insert_with(Arc::default));
+            if chunk.len() < within + count {
+                chunk.resize(within + count, 0);
+            }
+            chunk[within..within + count].copy_from_slice(&data[done..done + count]);
+            done += count;
+        }
+        self.len = self.len.max(offset + data.len());
+    }
+
+    /// Truncate or extend the file to `len` bytes, leaving any extension as a hole.
+    fn set_len(&mut self, len: usize) {
+        if len < self.len {
+            let chunks = len.div_ceil(CHUNK_SIZE);
+            self.chunks.truncate(chunks);
+            let within = len % CHUNK_SIZE;
+            if within > 0 {
+                if let Some(Some(chunk)) = self.chunks.get_mut(chunks - 1) {
+                    if chunk.len() > within {
+                        Arc::make_mut(chunk).truncate(within);
+                    }
+                }
+            }
+        }
+        self.len = len;
+    }
+
+    /// Extend the file to at least `len` bytes with every chunk up to it fully allocated.
+    fn allocate(&mut self, len: usize) {
+        let chunks = len.div_ceil(CHUNK_SIZE);
+        if self.chunks.len() < chunks {
+            self.chunks.resize(chunks, None);
+        }
+        for (index, chunk) in self.chunks.iter_mut().enumerate().take(chunks) {
+            let size = CHUNK_SIZE.min(len - index * CHUNK_SIZE);
+            let chunk = chunk.get_or_insert_with(Arc::default);
+            if chunk.len() < size {
+                let chunk = Arc::make_mut(chunk);
+                // Reserve exactly, so the fill below doesn't over-allocate by doubling.
+                chunk.reserve_exact(size - chunk.len());
+                chunk.resize(size, 0);
+            }
+        }
+        self.len = self.len.max(len);
+    }
+}
+
 #[derive(Clone)]
 struct MemoryFileData {
-    buffer: Vec<u8>,
-    lock: FileLockMode,
+    buffer: ChunkedBuffer,
+    permissions: Permissions,
+    modified: SystemTime,
+    clock: Arc<dyn Clock>,
+    /// Contiguous view of the contents handed out by [`MemoryFileData::snapshot`], dropped by
+    /// the next modification. Held weakly, so the file never keeps a copy alive that no reader
+    /// holds.
+    snapshot: Option<Weak<[u8]>>,
+    locks: Arc<MemoryFileLock>,
+}
+
+/// Advisory lock shared by every handle open on a file.
+#[derive(Debug, Default)]
+struct MemoryFileLock {
+    state: Mutex<MemoryLockState>,
+    released: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct MemoryLockState {
+    shared: usize,
+    exclusive: bool,
+    ranges: Vec<MemoryRangeLock>,
+}
+
+/// Byte-range lock over `start..end` held by a single handle.
+#[derive(Clone, Copy, Debug)]
+struct MemoryRangeLock {
+    owner: u64,
+    start: u64,
+    end: u64,
+    exclusive: bool,
+}
+
+impl MemoryRangeLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+impl MemoryFileLock {
+    /// Move a holder from `current` to `requested`, failing if another holder conflicts.
+    fn try_transition(
+        &self,
+        current: FileLockMode,
+        requested: FileLockMode,
+    ) -> FileSystemResult<()> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        Self::transition(&mut state, current, requested)?;
+        drop(state);
+        self.released.notify_all();
+        Ok(())
+    }
+
+    /// Move a holder from `current` to `requested`, waiting up to `timeout`, or without limit
+    /// if `None`, for conflicting holders to release.
+    fn wait_transition(
+        &self,
+        current: FileLockMode,
+        requested: FileLockMode,
+        timeout: Option<Duration>,
+    ) -> FileSystemResult<()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        loop {
+            match Self::transition(&mut state, current, requested) {
+                Ok(()) => {
+                    drop(state);
+                    self.released.notify_all();
+                    return Ok(());
+                }
+                Err(FileSystemError::FileAlreadyLocked) => {
+                    let Some(deadline) = deadline else {
+                        state = self.released.wait(state).expect("Poisoned Lock");
+                        continue;
+                    };
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(FileSystemError::FileAlreadyLocked);
+                    }
+                    state = self
+                        .released
+                        .wait_timeout(state, remaining)
+                        .expect("Poisoned Lock")
+                        .0;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Replace the locks `owner` holds over `start..end` with `mode`.
+    fn lock_range(
+        &self,
+        owner: u64,
+        start: u64,
+        end: u64,
+        mode: FileLockMode,
+    ) -> FileSystemResult<()> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        let exclusive = mode == FileLockMode::Exclusive;
+        if mode != FileLockMode::Unlocked
+            && state.ranges.iter().any(|range| {
+                range.owner != owner && range.overlaps(start, end) && (range.exclusive || exclusive)
+            })
+        {
+            return Err(FileSystemError::FileAlreadyLocked);
+        }
+        let mut ranges = Vec::with_capacity(state.ranges.len() + 2);
+        for range in state.ranges.drain(..) {
+            if range.owner != owner || !range.overlaps(start, end) {
+                ranges.push(range);
+                continue;
+            }
+            // Keep whatever part of our existing lock falls outside the new range.
+            if range.start < start {
+                ranges.push(MemoryRangeLock {
+                    end: start,
+                    ..range
+                });
+            }
+            if range.end > end {
+                ranges.push(MemoryRangeLock {
+                    start: end,
+                    ..range
+                });
+            }
+        }
+        if mode != FileLockMode::Unlocked {
+            ranges.push(MemoryRangeLock {
+                owner,
+                start,
+                end,
+                exclusive,
+            });
+        }
+        state.ranges = ranges;
+        Ok(())
+    }
+
+    /// Release every byte-range lock held by `owner`.
+    fn release_ranges(&self, owner: u64) {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        state.ranges.retain(|range| range.owner != owner);
+    }
+
+    fn transition(
+        state: &mut MemoryLockState,
+        current: FileLockMode,
+        requested: FileLockMode,
+    ) -> FileSystemResult<()> {
+        // Discount whatever the caller already holds before checking for conflicts.
+        let others_shared = state.shared - usize::from(current == FileLockMode::Shared);
+        let others_exclusive = state.exclusive && current != FileLockMode::Exclusive;
+        let conflict = match requested {
+            FileLockMode::Unlocked => false,
+            FileLockMode::Shared => others_exclusive,
+            FileLockMode::Exclusive => others_exclusive || others_shared > 0,
+        };
+        if conflict {
+            return Err(FileSystemError::FileAlreadyLocked);
+        }
+        state.shared = others_shared + usize::from(requested == FileLockMode::Shared);
+        state.exclusive = others_exclusive || requested == FileLockMode::Exclusive;
+        Ok(())
+    }
+}
+
+impl MemoryFileData {
+    /// Fail unless the permissions of the file allow modifying it.
+    fn writable(&self) -> FileSystemResult<()> {
+        if self.permissions.is_readonly() {
+            return Err(FileSystemError::PermissionDenied);
+        }
+        Ok(())
+    }
+
+    /// Fail unless the file is writable, then stamp its modification time.
+    fn modify(&mut self) -> FileSystemResult<()> {
+        self.writable()?;
+        self.modified = self.clock.now();
+        self.snapshot = None;
+        Ok(())
+    }
+
+    /// Get the contents as one immutable buffer, copying them out of the chunks when no reader
+    /// holds a view taken since the last modification, and sharing that view otherwise.
+    fn snapshot(data: &RwLock<MemoryFileData>) -> Arc<[u8]> {
+        if let Some(snapshot) = data
+            .read()
+            .expect("Poisoned Lock")
+            .snapshot
+            .as_ref()
+            .and_then(Weak::upgrade)
+        {
+            return snapshot;
+        }
+        let mut data = data.write().expect("Poisoned Lock");
+        if let Some(snapshot) = data.snapshot.as_ref().and_then(Weak::upgrade) {
+            return snapshot;
+        }
+        let mut contents = vec![0; data.buffer.len()];
+        data.buffer.read(0, &mut contents);
+        let snapshot: Arc<[u8]> = contents.into();
+        data.snapshot = Some(Arc::downgrade(&snapshot));
+        snapshot
+    }
 }
 
 impl std::fmt::Debug for MemoryFileData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let locks = self.locks.state.lock().expect("Poisoned Lock");
         writeln!(
             f,
-            "MemoryFileData {{ size: {} bytes, status: {} }}",
+            "MemoryFileData {{ size: {} bytes, shared: {}, exclusive: {} }}",
             self.buffer.len(),
-            match self.lock {
-                FileLockMode::Unlocked => "Unlocked",
-                FileLockMode::Shared => "Shared",
-                FileLockMode::Exclusive => "Exclusive",
-            },
+            locks.shared,
+            locks.exclusive,
         )?;
+        drop(locks);
         writeln!(
             f,
             "---------------------------------Begin File--------------------------------"
@@ -272,12 +1940,15 @@ impl std::fmt::Debug for MemoryFileData {
             f,
             "            0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F 0123456789ABCDEF"
         )?;
-        for (i, chunk) in self.buffer.chunks(16).enumerate() {
+        let mut line = [0; 16];
+        for i in 0..self.buffer.len().div_ceil(16) {
+            let len = self.buffer.read(i * 16, &mut line);
+            let chunk = &line[..len];
             // Write Address
             write!(f, "{:08X}  ", i * 16)?;
             // Write Hex
             for byte in chunk {
-                write!(f, "{:02X} ", byte)?;
+                write!(f, "{byte:02X} ")?;
             }
             // Write Padding
             for _ in chunk.len()..16 {
@@ -293,7 +1964,7 @@ impl std::fmt::Debug for MemoryFileData {
                 }
             }
             // End Line
-            writeln!(f, "")?;
+            writeln!(f)?;
         }
         writeln!(
             f,
@@ -303,14 +1974,82 @@ impl std::fmt::Debug for MemoryFileData {
     }
 }
 
-/// Memory File Handle
-#[derive(Clone)]
-pub struct MemoryFileHandle {
-    cursor: usize,
-    name: String,
-    data: Arc<RwLock<MemoryFileData>>,
-}
-
+/// Source of unique ids identifying the owner of byte-range locks.
+static NEXT_HANDLE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Memory File Handle
+///
+/// Advisory locks are enforced between handles open on the same file: any number of handles may
+/// hold [`FileLockMode::Shared`], while [`FileLockMode::Exclusive`] conflicts with every other
+/// holder. A conflicting [`FileHandle::set_lock_status`] waits for the holders to release, a
+/// conflicting [`FileHandle::try_set_lock_status`] fails with
+/// [`FileSystemError::FileAlreadyLocked`], and locks are released when the handle is dropped.
+/// Byte-range locks follow the same rules, but only conflict where the ranges overlap.
+pub struct MemoryFileHandle {
+    id: u64,
+    cursor: usize,
+    name: String,
+    data: Arc<RwLock<MemoryFileData>>,
+    locks: Arc<MemoryFileLock>,
+    lock: FileLockMode,
+    /// Namespace to account the file in when closed, if acting as a cache.
+    cache: Option<Arc<MemoryNamespace>>,
+}
+
+impl MemoryFileHandle {
+    fn new(
+        name: String,
+        data: Arc<RwLock<MemoryFileData>>,
+        cache: Option<Arc<MemoryNamespace>>,
+    ) -> MemoryFileHandle {
+        let locks = data.read().expect("Poisoned Lock").locks.clone();
+        MemoryFileHandle {
+            id: NEXT_HANDLE_ID.fetch_add(1, Ordering::Relaxed),
+            cursor: 0,
+            name,
+            data,
+            locks,
+            lock: FileLockMode::Unlocked,
+            cache,
+        }
+    }
+
+    /// Get an immutable view of the file's current contents.
+    ///
+    /// See [`MemoryFileSystem::read_shared`].
+    #[must_use]
+    pub fn read_shared(&self) -> Arc<[u8]> {
+        MemoryFileData::snapshot(&self.data)
+    }
+}
+
+impl Clone for MemoryFileHandle {
+    /// Clones share the file contents but not the lock held by the original handle.
+    fn clone(&self) -> Self {
+        MemoryFileHandle {
+            id: NEXT_HANDLE_ID.fetch_add(1, Ordering::Relaxed),
+            cursor: self.cursor,
+            name: self.name.clone(),
+            data: self.data.clone(),
+            locks: self.locks.clone(),
+            lock: FileLockMode::Unlocked,
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl Drop for MemoryFileHandle {
+    fn drop(&mut self) {
+        self.locks.release_ranges(self.id);
+        if self.lock != FileLockMode::Unlocked {
+            let _ = self.locks.try_transition(self.lock, FileLockMode::Unlocked);
+        }
+        if let Some(cache) = &self.cache {
+            cache.close(&self.name, &self.data);
+        }
+    }
+}
+
 impl std::fmt::Debug for MemoryFileHandle {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(
@@ -318,7 +2057,7 @@ impl std::fmt::Debug for MemoryFileHandle {
             "MemoryFileHandle {{ name: {}, cursor: {}, data: {:?} }}",
             self.name,
             self.cursor,
-            self.data.read().unwrap()
+            self.data.read().expect("Poisoned Lock")
         )
     }
 }
@@ -326,9 +2065,16 @@ impl std::fmt::Debug for MemoryFileHandle {
 impl Read for MemoryFileHandle {
     #[tracing::instrument(level = "trace")]
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let mut data = self.data.write().unwrap();
-        let len = std::cmp::min(buf.len(), data.buffer.len() - self.cursor);
-        buf[..len].copy_from_slice(&data.buffer[self.cursor..self.cursor + len]);
+        let data = self.data.read().expect("Poisoned Lock");
+        let len = data.buffer.read(self.cursor, buf);
+        self.cursor += len;
+        Ok(len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        let data = self.data.read().expect("Poisoned Lock");
+        let len = scatter(&data.buffer, self.cursor, bufs);
         self.cursor += len;
         Ok(len)
     }
@@ -337,15 +2083,22 @@ impl Read for MemoryFileHandle {
 impl Write for MemoryFileHandle {
     #[tracing::instrument(level = "trace")]
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let mut data = self.data.write().unwrap();
-        if self.cursor + buf.len() > data.buffer.len() {
-            data.buffer.resize(self.cursor + buf.len(), 0);
-        }
-        data.buffer[self.cursor..self.cursor + buf.len()].copy_from_slice(buf);
+        let mut data = self.data.write().expect("Poisoned Lock");
+        data.modify()?;
+        data.buffer.write(self.cursor, buf);
         self.cursor += buf.len();
         Ok(buf.len())
     }
 
+    #[tracing::instrument(level = "trace")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        let mut data = self.data.write().expect("Poisoned Lock");
+        data.modify()?;
+        let len = gather(&mut data.buffer, self.cursor, bufs);
+        self.cursor += len;
+        Ok(len)
+    }
+
     #[tracing::instrument(level = "trace")]
     fn flush(&mut self) -> std::io::Result<()> {
         Ok(())
@@ -356,18 +2109,20 @@ impl Seek for MemoryFileHandle {
     #[tracing::instrument(level = "trace")]
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
         let data = self.data.read().expect("Poisoned Lock");
-        match pos {
-            SeekFrom::Start(offset) => {
-                self.cursor = offset as usize;
-            }
-            SeekFrom::End(offset) => {
-                self.cursor = (data.buffer.len() as i64 + offset) as usize;
-            }
-            SeekFrom::Current(offset) => {
-                self.cursor = (self.cursor as i64 + offset) as usize;
-            }
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (data.buffer.len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => (self.cursor as u64).checked_add_signed(offset),
         }
-        Ok(self.cursor as u64)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        self.cursor = usize::try_from(position)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        Ok(position)
     }
 }
 
@@ -386,7 +2141,18 @@ impl FileHandle for MemoryFileHandle {
     #[tracing::instrument(level = "trace")]
     fn set_size(&mut self, new_length: u64) -> FileSystemResult<()> {
         let mut file = self.data.write().expect("Poisoned Lock");
-        file.buffer.resize(new_length as usize, 0);
+        file.modify()?;
+        let new_length = usize::try_from(new_length).map_err(FileSystemError::wrap_error)?;
+        file.buffer.set_len(new_length);
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        let mut file = self.data.write().expect("Poisoned Lock");
+        file.modify()?;
+        let len = usize::try_from(len).map_err(FileSystemError::wrap_error)?;
+        file.buffer.allocate(len);
         Ok(())
     }
 
@@ -402,50 +2168,131 @@ impl FileHandle for MemoryFileHandle {
 
     #[tracing::instrument(level = "trace")]
     fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
-        let file = self.data.write().expect("Poisoned Lock");
-        Ok(file.lock)
+        Ok(self.lock)
     }
 
+    /// Waits on the holders' releases rather than polling.
     #[tracing::instrument(level = "trace")]
     fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
-        let mut file = self.data.write().expect("Poisoned Lock");
-        file.lock = mode;
+        self.locks.wait_transition(self.lock, mode, None)?;
+        self.lock = mode;
         Ok(())
     }
 
+    /// Locks convert atomically, so a failed upgrade keeps the shared lock.
     #[tracing::instrument(level = "trace")]
-    fn read_at_offset(&mut self, pos: u64, buf: &mut [u8]) -> FileSystemResult<usize> {
-        let mut data = self.data.read().expect("Poisoned Lock");
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.locks.try_transition(self.lock, mode)?;
+        self.lock = mode;
+        Ok(())
+    }
 
-        // Calculate Slice Bounds
-        let off = pos as usize; // Lower Slice Bound
-        let end = std::cmp::min(off + buf.len(), data.buffer.len()); // Upper Slice Bound
-        let len = end - off;
+    /// Waits on the holders' releases rather than polling. Locks convert atomically, so a
+    /// failed upgrade keeps the shared lock.
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status_timeout(
+        &mut self,
+        mode: FileLockMode,
+        timeout: Duration,
+    ) -> FileSystemResult<()> {
+        self.locks.wait_transition(self.lock, mode, Some(timeout))?;
+        self.lock = mode;
+        Ok(())
+    }
 
-        // Read
-        buf.copy_from_slice(&data.buffer[off..end]);
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        let data = self.data.read().expect("Poisoned Lock");
+        let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+        Ok(scatter(&data.buffer, offset, buffers))
+    }
 
-        Ok(len)
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        let mut data = self.data.write().expect("Poisoned Lock");
+        data.modify()?;
+        let offset = usize::try_from(offset).map_err(FileSystemError::wrap_error)?;
+        Ok(gather(&mut data.buffer, offset, buffers))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        let end = if len == 0 {
+            u64::MAX
+        } else {
+            offset.saturating_add(len)
+        };
+        self.locks.lock_range(self.id, offset, end, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, pos: u64, buf: &mut [u8]) -> FileSystemResult<usize> {
+        let data = self.data.read().expect("Poisoned Lock");
+        let off = usize::try_from(pos).unwrap_or(usize::MAX);
+        Ok(data.buffer.read(off, buf))
     }
 
     #[tracing::instrument(level = "trace")]
     fn write_to_offset(&mut self, pos: u64, buf: &[u8]) -> FileSystemResult<usize> {
-        let mut data = self.data.write().unwrap();
+        let mut data = self.data.write().expect("Poisoned Lock");
+        data.modify()?;
+        let off = usize::try_from(pos).expect("Position Too Large");
+        data.buffer.write(off, buf);
+        Ok(buf.len())
+    }
+}
+
+/// Positional reads take the shared lock on the file's contents, so they run in parallel with
+/// each other and serialize with writes.
+impl ReadAt for MemoryFileHandle {
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        let data = self.data.read().expect("Poisoned Lock");
+        let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+        Ok(data.buffer.read(offset, buffer))
+    }
+}
 
-        // Calculate Slice Bounds
-        let off = usize::try_from(pos).expect("Position Too Large"); // Lower Slice Bound
-        let end = off + buf.len(); // Upper Slice Bound
+impl WriteAt for MemoryFileHandle {
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        let mut data = self.data.write().expect("Poisoned Lock");
+        data.modify()?;
+        let offset = usize::try_from(offset).expect("Position Too Large");
+        data.buffer.write(offset, buffer);
+        Ok(buffer.len())
+    }
+}
 
-        // Resize if array capacity too small
-        if end > data.buffer.len() {
-            data.buffer.resize(end, 0);
+/// Copy from `buffer` starting at `offset` into each of `buffers` in turn.
+fn scatter(buffer: &ChunkedBuffer, offset: usize, buffers: &mut [IoSliceMut<'_>]) -> usize {
+    let mut total = 0;
+    for target in buffers.iter_mut() {
+        let len = buffer.read(offset.saturating_add(total), target);
+        total += len;
+        if len < target.len() {
+            break;
         }
+    }
+    total
+}
 
-        // Write data to buffer
-        data.buffer[off..end].copy_from_slice(buf);
-
-        Ok(buf.len())
+/// Copy each of `buffers` into `buffer` starting at `offset`.
+fn gather(buffer: &mut ChunkedBuffer, offset: usize, buffers: &[IoSlice<'_>]) -> usize {
+    let mut total = 0;
+    for source in buffers {
+        buffer.write(offset + total, source);
+        total += source.len();
     }
+    total
 }
 
 #[cfg(test)]
@@ -529,4 +2376,863 @@ mod test {
             .exists(filename.as_str())
             .expect("Error Checking File Existence"));
     }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_directories() {
+        use crate::{FileSystem, FileSystemError, MemoryFileSystem};
+
+        let fs = MemoryFileSystem::new();
+        assert!(fs.is_directory("/").unwrap());
+
+        // Parents must exist
+        assert!(matches!(
+            fs.create_file("/a/b/file.txt"),
+            Err(FileSystemError::ParentMissing)
+        ));
+        assert!(matches!(
+            fs.create_directory("/a/b"),
+            Err(FileSystemError::ParentMissing)
+        ));
+        fs.create_directory_all("/a/b").unwrap();
+        fs.create_directory_all("/a/b").unwrap();
+        fs.create_file("/a/b/file.txt").unwrap();
+        fs.create_file("/a/top.txt").unwrap();
+        assert!(matches!(
+            fs.create_directory("/a/top.txt"),
+            Err(FileSystemError::PathExists)
+        ));
+        assert!(matches!(
+            fs.create_file("/a/top.txt/nested.txt"),
+            Err(FileSystemError::InvalidOperation)
+        ));
+
+        // Listing returns direct children only
+        assert_eq!(fs.list_directory("/").unwrap(), vec!["a"]);
+        assert_eq!(fs.list_directory("/a").unwrap(), vec!["b", "top.txt"]);
+        assert_eq!(fs.list_directory("a/b/").unwrap(), vec!["file.txt"]);
+        assert!(matches!(
+            fs.list_directory("/a/top.txt"),
+            Err(FileSystemError::InvalidOperation)
+        ));
+
+        // Non-empty directories can only be removed recursively
+        assert!(matches!(
+            fs.remove_directory("/a/b"),
+            Err(FileSystemError::InvalidOperation)
+        ));
+        assert!(matches!(
+            fs.remove_file("/a/b"),
+            Err(FileSystemError::InvalidOperation)
+        ));
+        fs.remove_directory_all("/a/b").unwrap();
+        assert!(!fs.exists("/a/b/file.txt").unwrap());
+        assert!(fs.exists("/a/top.txt").unwrap());
+        fs.remove_file("/a/top.txt").unwrap();
+        fs.remove_directory("/a").unwrap();
+        assert!(fs.list_directory("/").unwrap().is_empty());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_locks() {
+        use crate::{FileHandle, FileLockMode, FileSystem, FileSystemError, MemoryFileSystem};
+        use std::time::Duration;
+
+        let fs = MemoryFileSystem::new();
+        let mut first = fs.create_file("/locked.txt").unwrap();
+        let mut second = fs.open_file("/locked.txt").unwrap();
+
+        // Shared locks coexist but block exclusive
+        first.set_lock_status(FileLockMode::Shared).unwrap();
+        second.set_lock_status(FileLockMode::Shared).unwrap();
+        assert!(matches!(
+            first.try_set_lock_status(FileLockMode::Exclusive),
+            Err(FileSystemError::FileAlreadyLocked)
+        ));
+        assert_eq!(first.get_lock_status().unwrap(), FileLockMode::Shared);
+
+        // Upgrade once the other reader releases
+        second.set_lock_status(FileLockMode::Unlocked).unwrap();
+        first.set_lock_status(FileLockMode::Exclusive).unwrap();
+        assert!(matches!(
+            second.try_set_lock_status(FileLockMode::Shared),
+            Err(FileSystemError::FileAlreadyLocked)
+        ));
+        assert!(matches!(
+            second.set_lock_status_timeout(FileLockMode::Shared, Duration::from_millis(10)),
+            Err(FileSystemError::FileAlreadyLocked)
+        ));
+
+        // Dropping a handle releases its lock, waking a waiter blocked on it
+        let waiter = std::thread::spawn(move || {
+            second
+                .set_lock_status(FileLockMode::Exclusive)
+                .map(|()| second)
+        });
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(!waiter.is_finished());
+        drop(first);
+        let mut second = waiter.join().unwrap().unwrap();
+        assert_eq!(second.get_lock_status().unwrap(), FileLockMode::Exclusive);
+
+        // Clones don't inherit the lock
+        let mut clone = second.clone();
+        assert_eq!(clone.get_lock_status().unwrap(), FileLockMode::Unlocked);
+        assert!(clone.try_set_lock_status(FileLockMode::Shared).is_err());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_lock_conversion() {
+        use crate::{FileHandle, FileLockMode, FileSystem, FileSystemError, MemoryFileSystem};
+
+        let fs = MemoryFileSystem::new();
+        let mut first = fs.create_file("/converted.txt").unwrap();
+        let mut second = fs.open_file("/converted.txt").unwrap();
+        assert!(matches!(
+            first.upgrade_lock(),
+            Err(FileSystemError::InvalidOperation)
+        ));
+
+        // A contended upgrade fails without waiting and keeps the shared lock
+        first.try_set_lock_status(FileLockMode::Shared).unwrap();
+        second.try_set_lock_status(FileLockMode::Shared).unwrap();
+        assert!(matches!(
+            first.upgrade_lock(),
+            Err(FileSystemError::FileAlreadyLocked)
+        ));
+        assert_eq!(first.get_lock_status().unwrap(), FileLockMode::Shared);
+        second.try_set_lock_status(FileLockMode::Unlocked).unwrap();
+        first.upgrade_lock().unwrap();
+        first.upgrade_lock().unwrap();
+        assert_eq!(first.get_lock_status().unwrap(), FileLockMode::Exclusive);
+
+        // Downgrading lets readers back in
+        assert!(matches!(
+            second.try_set_lock_status(FileLockMode::Shared),
+            Err(FileSystemError::FileAlreadyLocked)
+        ));
+        first.downgrade_lock().unwrap();
+        assert_eq!(first.get_lock_status().unwrap(), FileLockMode::Shared);
+        second.try_set_lock_status(FileLockMode::Shared).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_range_locks() {
+        use crate::{FileHandle, FileLockMode, FileSystem, FileSystemError, MemoryFileSystem};
+
+        let fs = MemoryFileSystem::new();
+        let mut first = fs.create_file("/pages.dat").unwrap();
+        let mut second = fs.open_file("/pages.dat").unwrap();
+
+        // Disjoint exclusive ranges don't conflict
+        first.lock_range(0, 4096, FileLockMode::Exclusive).unwrap();
+        second
+            .lock_range(4096, 4096, FileLockMode::Exclusive)
+            .unwrap();
+        assert!(matches!(
+            second.lock_range(4095, 2, FileLockMode::Shared),
+            Err(FileSystemError::FileAlreadyLocked)
+        ));
+
+        // Downgrading part of a range lets others share it
+        first.lock_range(0, 1024, FileLockMode::Shared).unwrap();
+        second.lock_range(0, 1024, FileLockMode::Shared).unwrap();
+        assert!(second.lock_range(1024, 1, FileLockMode::Shared).is_err());
+
+        // Unlocking the middle of a range splits it
+        first.unlock_range(1024, 1024).unwrap();
+        second
+            .lock_range(1024, 1024, FileLockMode::Exclusive)
+            .unwrap();
+        assert!(second.lock_range(2048, 1, FileLockMode::Shared).is_err());
+
+        // Zero length locks run to the end of the file
+        second.unlock_range(0, 0).unwrap();
+        second.lock_range(1 << 40, 0, FileLockMode::Shared).unwrap();
+        assert!(first
+            .lock_range(u64::MAX - 1, 1, FileLockMode::Exclusive)
+            .is_err());
+
+        // Dropping a handle releases its ranges
+        drop(first);
+        second.lock_range(0, 0, FileLockMode::Exclusive).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_vectored_io() {
+        use crate::{FileHandle, FileSystem, MemoryFileSystem};
+        use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+
+        let fs = MemoryFileSystem::new();
+        let mut file = fs.create_file("/log.dat").unwrap();
+
+        let written = file
+            .write_vectored(&[
+                IoSlice::new(b"Hello"),
+                IoSlice::new(b", "),
+                IoSlice::new(b"World"),
+            ])
+            .unwrap();
+        assert_eq!(written, 12);
+        let written = file
+            .write_at_vectored(16, &[IoSlice::new(b"ab"), IoSlice::new(b"cd")])
+            .unwrap();
+        assert_eq!(written, 4);
+        assert_eq!(file.get_size().unwrap(), 20);
+        assert_eq!(file.stream_position().unwrap(), 12);
+
+        let (mut first, mut second, mut third) = ([0u8; 7], [0u8; 4], [0u8; 16]);
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let read = file
+            .read_vectored(&mut [
+                IoSliceMut::new(&mut first),
+                IoSliceMut::new(&mut second),
+                IoSliceMut::new(&mut third),
+            ])
+            .unwrap();
+        assert_eq!(read, 20);
+        assert_eq!(&first, b"Hello, ");
+        assert_eq!(&second, b"Worl");
+        assert_eq!(&third[..9], b"d\0\0\0\0abcd");
+
+        let read = file
+            .read_at_vectored(
+                14,
+                &mut [IoSliceMut::new(&mut first), IoSliceMut::new(&mut second)],
+            )
+            .unwrap();
+        assert_eq!(read, 6);
+        assert_eq!(&first[..6], b"\0\0abcd");
+        assert_eq!(file.stream_position().unwrap(), 20);
+
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_shared_positional_io() {
+        use crate::{FileSystem, MemoryFileSystem, ReadAt, WriteAt};
+        use std::io::{Seek, SeekFrom};
+        use std::sync::Arc;
+
+        let fs = MemoryFileSystem::new();
+        let mut file = fs.create_file("/pages.dat").unwrap();
+        file.seek(SeekFrom::Start(3)).unwrap();
+        let file = Arc::new(file);
+        std::thread::scope(|scope| {
+            for page in 0..4u8 {
+                let file = Arc::clone(&file);
+                scope.spawn(move || file.write_all_at(u64::from(page) * 4, &[page; 4]).unwrap());
+            }
+        });
+        let readers: Vec<_> = (0..4u8)
+            .map(|page| {
+                let file = Arc::clone(&file);
+                std::thread::spawn(move || {
+                    let mut buffer = [0; 4];
+                    file.read_exact_at(u64::from(page) * 4, &mut buffer)
+                        .unwrap();
+                    buffer == [page; 4]
+                })
+            })
+            .collect();
+        assert!(readers.into_iter().all(|reader| reader.join().unwrap()));
+
+        let mut buffer = [0; 8];
+        assert_eq!(file.read_at(12, &mut buffer).unwrap(), 4);
+        assert!(file.read_exact_at(12, &mut buffer).is_err());
+        let mut file = Arc::into_inner(file).unwrap();
+        assert_eq!(file.stream_position().unwrap(), 3);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    #[cfg(feature = "mmap")]
+    fn test_memory_map_readonly() {
+        use crate::{FileHandle, FileMapping, FileSystem, MemoryFileSystem};
+        use std::io::Write;
+
+        let fs = MemoryFileSystem::new();
+        let mut file = fs.create_file("/pages.dat").unwrap();
+        file.write_all(b"Hello, World!").unwrap();
+
+        // Backends without mmap fall back to copying the range
+        let mapping = file.map_readonly(7, 1024).unwrap();
+        assert!(matches!(mapping, FileMapping::Copied(_)));
+        assert_eq!(mapping.as_ref(), b"World!");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_open_with() {
+        use crate::{FileHandle, FileSystem, FileSystemError, MemoryFileSystem, OpenOptions};
+        use std::io::{Seek, Write};
+
+        let fs = MemoryFileSystem::new();
+        let options = OpenOptions::new().read(true).write(true);
+        assert!(matches!(
+            fs.open_with("/test.txt", options),
+            Err(FileSystemError::PathMissing)
+        ));
+        fs.open_with("/test.txt", options.create(true))
+            .unwrap()
+            .write_all(b"Hello")
+            .unwrap();
+        assert!(matches!(
+            fs.open_with("/test.txt", options.create_new(true)),
+            Err(FileSystemError::PathExists)
+        ));
+        let mut file = fs.open_with("/test.txt", options.append(true)).unwrap();
+        assert_eq!(file.stream_position().unwrap(), 5);
+        let file = fs
+            .open_with("/test.txt", options.truncate(true).direct(true))
+            .unwrap();
+        assert_eq!(file.get_size().unwrap(), 0);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_allocate() {
+        use crate::{FileHandle, FileSystem, MemoryFileSystem};
+        use std::io::Write;
+
+        let fs = MemoryFileSystem::new();
+        let mut file = fs.create_file("/wal-0001.log").unwrap();
+        file.write_all(b"Hello").unwrap();
+        file.allocate(4096).unwrap();
+        assert_eq!(file.get_size().unwrap(), 4096);
+        file.allocate(16).unwrap();
+        assert_eq!(file.get_size().unwrap(), 4096);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_chunked_contents() {
+        use super::CHUNK_SIZE;
+        use crate::{FileHandle, FileSystem, MemoryFileSystem};
+
+        let fs = MemoryFileSystem::new();
+        let mut file = fs.create_file("/sparse.bin").unwrap();
+
+        // Writes spanning a chunk boundary
+        let boundary = (CHUNK_SIZE - 2) as u64;
+        file.write_to_offset(boundary, b"abcd").unwrap();
+        let mut buffer = [0xFF; 6];
+        assert_eq!(file.read_at_offset(boundary - 1, &mut buffer).unwrap(), 5);
+        assert_eq!(&buffer[..5], b"\0abcd");
+
+        // Holes read as zeros
+        let far = 3 * CHUNK_SIZE as u64 + 10;
+        file.write_to_offset(far, b"end").unwrap();
+        assert_eq!(file.get_size().unwrap(), far + 3);
+        let mut hole = [0xFF; 8];
+        file.read_at_offset(2 * CHUNK_SIZE as u64, &mut hole)
+            .unwrap();
+        assert_eq!(hole, [0; 8]);
+
+        // Truncated bytes don't come back when the file grows again
+        file.set_size(boundary + 1).unwrap();
+        file.set_size(far + 3).unwrap();
+        let mut buffer = [0xFF; 4];
+        file.read_at_offset(boundary, &mut buffer).unwrap();
+        assert_eq!(&buffer, b"a\0\0\0");
+        file.read_at_offset(far, &mut buffer).unwrap();
+        assert_eq!(buffer, [0; 4]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_concurrent_namespace() {
+        use crate::{FileSystem, FileSystemError, MemoryFileSystem};
+
+        let fs = MemoryFileSystem::new();
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let fs = &fs;
+                scope.spawn(move || {
+                    let directory = format!("/t{thread}/nested");
+                    for round in 0..20 {
+                        fs.create_directory_all(&directory).unwrap();
+                        for file in 0..5 {
+                            fs.create_file(&format!("{directory}/{file}")).unwrap();
+                        }
+                        assert_eq!(fs.list_directory(&directory).unwrap().len(), 5);
+                        if round % 2 == 0 {
+                            fs.remove_directory_all(&format!("/t{thread}")).unwrap();
+                        } else {
+                            for file in 0..5 {
+                                fs.remove_file(&format!("{directory}/{file}")).unwrap();
+                            }
+                            fs.remove_directory(&directory).unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(
+            fs.list_directory("/").unwrap(),
+            vec!["t0", "t1", "t2", "t3"]
+        );
+        assert!(matches!(
+            fs.list_directory("/t0/nested"),
+            Err(FileSystemError::PathMissing)
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_fork() {
+        use crate::{FileHandle, FileSystem, MemoryFileSystem};
+        use std::io::{Read, Write};
+
+        let fixture = MemoryFileSystem::new();
+        fixture.create_directory("/data").unwrap();
+        let mut original = fixture.create_file("/data/a.txt").unwrap();
+        original.write_all(b"fixture").unwrap();
+
+        let fork = fixture.fork();
+        let mut forked = fork.open_file("/data/a.txt").unwrap();
+        forked.write_to_offset(0, b"FORKED!").unwrap();
+        fork.create_file("/data/b.txt").unwrap();
+
+        // Writes through handles opened before the fork stay on their own side
+        original.write_all(b" more").unwrap();
+
+        let mut contents = String::new();
+        fixture
+            .open_file("/data/a.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "fixture more");
+        assert!(!fixture.exists("/data/b.txt").unwrap());
+
+        contents.clear();
+        fork.open_file("/data/a.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "FORKED!");
+
+        fork.remove_directory_all("/data").unwrap();
+        assert!(fixture.is_directory("/data").unwrap());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_save_and_load() {
+        use crate::{FileHandle, FileSystem, FileSystemError, MemoryFileSystem};
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        let staging = MemoryFileSystem::new();
+        staging.create_directory_all("/a/b").unwrap();
+        staging.create_directory("/empty").unwrap();
+        staging
+            .create_file("/a/b/c.txt")
+            .unwrap()
+            .write_all(b"nested")
+            .unwrap();
+        staging
+            .create_file("/root.bin")
+            .unwrap()
+            .write_all(&[0, 1, 2])
+            .unwrap();
+
+        let backing = MemoryFileSystem::new();
+        let mut image = backing.create_file("/staging.img").unwrap();
+        image.write_all(b"header").unwrap();
+        staging.save_to(&mut image).unwrap();
+        let len = image.get_size().unwrap();
+
+        image.seek(SeekFrom::Start(6)).unwrap();
+        let restored = MemoryFileSystem::load_from(&mut image).unwrap();
+        assert_eq!(image.stream_position().unwrap(), len);
+        assert!(restored.is_directory("/empty").unwrap());
+        assert_eq!(
+            restored.list_directory("/").unwrap(),
+            vec!["a", "empty", "root.bin"]
+        );
+        let mut contents = String::new();
+        restored
+            .open_file("/a/b/c.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "nested");
+        assert_eq!(restored.filesize("/root.bin").unwrap(), 3);
+
+        // A truncated image reports where it ends
+        image.set_size(len - 1).unwrap();
+        image.seek(SeekFrom::Start(6)).unwrap();
+        assert!(matches!(
+            MemoryFileSystem::load_from(&mut image),
+            Err(FileSystemError::CorruptData { offset, .. }) if offset == len - 1
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_space() {
+        use crate::{FileSystem, FileSystemSpace, MemoryFileSystem, MetricFileSystem};
+        use std::io::Write;
+
+        let fs = MemoryFileSystem::with_capacity(100);
+        fs.create_directory("/data").unwrap();
+        fs.create_file("/data/a.bin")
+            .unwrap()
+            .write_all(&[1; 30])
+            .unwrap();
+        fs.create_file("/b.bin")
+            .unwrap()
+            .write_all(&[2; 12])
+            .unwrap();
+        let expected = FileSystemSpace {
+            total: 100,
+            used: 42,
+            available: 58,
+        };
+        assert_eq!(fs.space().unwrap(), expected);
+        assert_eq!(fs.fork().space().unwrap(), expected);
+        assert_eq!(MetricFileSystem::new(fs.clone()).space().unwrap(), expected);
+
+        fs.create_file("/c.bin")
+            .unwrap()
+            .write_all(&[3; 80])
+            .unwrap();
+        assert_eq!(fs.space().unwrap().available, 0);
+        assert_eq!(MemoryFileSystem::new().space().unwrap().total, u64::MAX);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_symlinks() {
+        use crate::{
+            FileSystem, FileSystemError, FileType, MemoryFileSystem, OpenOptions, SymlinkPolicy,
+        };
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        let fs = MemoryFileSystem::new();
+        fs.create_directory_all("/data/v1").unwrap();
+        fs.create_directory_all("/data/v2").unwrap();
+        fs.create_file("/data/v2/table.bin")
+            .unwrap()
+            .write_all(b"v2")
+            .unwrap();
+        fs.create_symlink("v2", "/data/current").unwrap();
+        fs.create_symlink("/data/current/table.bin", "/table.bin")
+            .unwrap();
+
+        assert_eq!(fs.read_link("/data/current").unwrap(), "v2");
+        assert_eq!(
+            fs.file_type("/data/current", SymlinkPolicy::NoFollow)
+                .unwrap(),
+            FileType::Symlink
+        );
+        assert_eq!(
+            fs.file_type("/data/current", SymlinkPolicy::Follow)
+                .unwrap(),
+            FileType::Directory
+        );
+        assert_eq!(
+            fs.list_directory("/data/current").unwrap(),
+            vec!["table.bin"]
+        );
+        assert_eq!(fs.filesize("/table.bin").unwrap(), 2);
+        let mut contents = String::new();
+        let mut file = fs.open_file("/data/current/table.bin").unwrap();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "v2");
+        assert!(matches!(
+            fs.open_with("/table.bin", OpenOptions::new().read(true).no_follow(true)),
+            Err(FileSystemError::InvalidOperation)
+        ));
+        assert!(matches!(
+            fs.read_link("/data/v2"),
+            Err(FileSystemError::InvalidOperation)
+        ));
+
+        // Repointing the link switches every path through it
+        fs.remove_file("/data/current").unwrap();
+        fs.create_symlink("/data/v1", "/data/current").unwrap();
+        assert!(fs.exists("/data/v2/table.bin").unwrap());
+        assert!(!fs.exists("/table.bin").unwrap());
+        assert_eq!(
+            fs.file_type("/table.bin", SymlinkPolicy::NoFollow).unwrap(),
+            FileType::Symlink
+        );
+
+        // Links survive an image round trip
+        let backing = MemoryFileSystem::new();
+        let mut image = backing.create_file("/image").unwrap();
+        fs.save_to(&mut image).unwrap();
+        image.seek(SeekFrom::Start(0)).unwrap();
+        let restored = MemoryFileSystem::load_from(&mut image).unwrap();
+        assert_eq!(restored.read_link("/data/current").unwrap(), "/data/v1");
+
+        fs.create_symlink("/loop", "/loop").unwrap();
+        assert!(matches!(
+            fs.open_file("/loop"),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_permissions() {
+        use crate::{
+            FileHandle, FileSystem, FileSystemError, MemoryFileSystem, OpenOptions, Permissions,
+        };
+        use std::io::{Seek, SeekFrom, Write};
+
+        let fs = MemoryFileSystem::new();
+        fs.create_directory_all("/data/archive").unwrap();
+        let mut file = fs.create_file("/data/table.bin").unwrap();
+        file.write_all(b"rows").unwrap();
+
+        let readonly = Permissions::new().readonly(true).mode(0o444);
+        fs.set_permissions("/data/table.bin", readonly).unwrap();
+        assert_eq!(fs.permissions("/data/table.bin").unwrap(), readonly);
+        assert_eq!(fs.permissions("/data").unwrap(), Permissions::new());
+
+        // Handles opened before the change are denied too
+        let err = file.write_all(b"more").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(matches!(
+            file.write_to_offset(0, b"x"),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        assert!(matches!(
+            fs.open_with(
+                "/data/table.bin",
+                OpenOptions::new().write(true).truncate(true)
+            ),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        assert_eq!(fs.filesize("/data/table.bin").unwrap(), 4);
+
+        fs.set_permissions("/data", Permissions::new().readonly(true))
+            .unwrap();
+        assert!(matches!(
+            fs.create_file("/data/new.bin"),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        assert!(matches!(
+            fs.remove_directory("/data/archive"),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        fs.create_directory_all("/data/archive").unwrap();
+
+        // Permissions survive forks and images
+        let fork = fs.fork();
+        assert!(fork.permissions("/data").unwrap().is_readonly());
+        let backing = MemoryFileSystem::new();
+        let mut image = backing.create_file("/image").unwrap();
+        fs.save_to(&mut image).unwrap();
+        image.seek(SeekFrom::Start(0)).unwrap();
+        let restored = MemoryFileSystem::load_from(&mut image).unwrap();
+        assert_eq!(restored.permissions("/data/table.bin").unwrap(), readonly);
+        assert!(restored.permissions("/data").unwrap().is_readonly());
+
+        fs.set_permissions("/data", Permissions::new()).unwrap();
+        fs.set_permissions("/data/table.bin", Permissions::new())
+            .unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(b"more").unwrap();
+        fs.remove_directory_all("/data").unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_whole_file() {
+        use crate::{FileSystem, FileSystemError, MemoryFileSystem, ScopedFileSystem};
+
+        let fs = MemoryFileSystem::new();
+        fs.create_directory("/data").unwrap();
+        fs.write("/data/log.txt", b"first").unwrap();
+        fs.append("/data/log.txt", b" second").unwrap();
+        assert_eq!(fs.read("/data/log.txt").unwrap(), b"first second");
+        fs.write("/data/log.txt", b"reset").unwrap();
+        assert_eq!(fs.read_to_string("/data/log.txt").unwrap(), "reset");
+        fs.append("/data/new.txt", b"created").unwrap();
+        assert_eq!(fs.filesize("/data/new.txt").unwrap(), 7);
+
+        fs.write("/data/binary.bin", &[0xff, 0xfe]).unwrap();
+        assert!(fs.read_to_string("/data/binary.bin").is_err());
+        assert!(matches!(
+            fs.read("/data/missing.txt"),
+            Err(FileSystemError::PathMissing)
+        ));
+        assert!(matches!(
+            fs.read("/data"),
+            Err(FileSystemError::InvalidOperation)
+        ));
+
+        // Wrappers without overrides go through handles
+        let scoped = ScopedFileSystem::new(fs.clone(), "/data");
+        scoped.append("/log.txt", b"!").unwrap();
+        assert_eq!(scoped.read_to_string("/log.txt").unwrap(), "reset!");
+        assert_eq!(fs.read("/data/log.txt").unwrap(), b"reset!");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_read_shared() {
+        use crate::{FileHandle, FileSystem, FileSystemError, MemoryFileSystem};
+        use std::io::Write;
+        use std::sync::Arc;
+
+        let fs = MemoryFileSystem::new();
+        let mut file = fs.create_file("/table.dat").unwrap();
+        file.write_all(b"rows").unwrap();
+
+        let view = file.read_shared();
+        assert_eq!(&*view, b"rows");
+        assert!(Arc::ptr_eq(&view, &fs.read_shared("/table.dat").unwrap()));
+
+        file.write_to_offset(0, b"R").unwrap();
+        let updated = fs.read_shared("/table.dat").unwrap();
+        assert_eq!(&*updated, b"Rows");
+        assert_eq!(&*view, b"rows");
+
+        let fork = fs.fork();
+        file.set_size(2).unwrap();
+        assert_eq!(&*fork.read_shared("/table.dat").unwrap(), b"Rows");
+        assert_eq!(&*file.read_shared(), b"Ro");
+
+        // Dropping every view leaves nothing but the chunks holding the contents.
+        let weak = Arc::downgrade(&file.read_shared());
+        assert!(weak.upgrade().is_none());
+
+        // Writing after taking a view only copies the chunks the write touches.
+        let chunks = |fs: &MemoryFileSystem| {
+            let segments = fs.resolve("/large.dat", true).unwrap();
+            let Some(super::MemoryEntry::File(file)) = fs.entry(&segments) else {
+                panic!("file missing");
+            };
+            let data = file.0.read().expect("Poisoned Lock");
+            data.buffer.chunks.clone()
+        };
+        fs.write("/large.dat", &vec![7; 3 * super::CHUNK_SIZE])
+            .unwrap();
+        let fork = fs.fork();
+        let view = fs.read_shared("/large.dat").unwrap();
+        let mut large = fs.open_file("/large.dat").unwrap();
+        large.write_to_offset(0, b"changed").unwrap();
+        assert!(view.iter().all(|byte| *byte == 7));
+        let (written, forked) = (chunks(&fs), chunks(&fork));
+        let shared = |index: usize| {
+            Arc::ptr_eq(
+                written[index].as_ref().unwrap(),
+                forked[index].as_ref().unwrap(),
+            )
+        };
+        assert!(!shared(0));
+        assert!(shared(1) && shared(2));
+
+        assert!(matches!(
+            fs.read_shared("/missing.dat"),
+            Err(FileSystemError::PathMissing)
+        ));
+        assert!(matches!(
+            fs.read_shared("/"),
+            Err(FileSystemError::InvalidOperation)
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_budget() {
+        use crate::{FileSystem, FileSystemError, MemoryFileSystem};
+        use std::io::Write;
+
+        let fs = MemoryFileSystem::with_budget(10);
+        assert_eq!(fs.space().unwrap().total, 10);
+        fs.create_directory("/dir").unwrap();
+        fs.write("/dir/a", b"aaaa").unwrap();
+        fs.write("/b", b"bbbb").unwrap();
+        // Reading makes `a` more recently accessed than `b`.
+        assert_eq!(fs.read("/dir/a").unwrap(), b"aaaa");
+        fs.write("/c", b"cccc").unwrap();
+        assert!(!fs.exists("/b").unwrap());
+        assert!(fs.exists("/dir/a").unwrap() && fs.exists("/c").unwrap());
+        assert_eq!(fs.evictions(), 1);
+
+        // Files with open handles, and pinned files, are kept over the budget.
+        let mut open = fs.open_file("/dir/a").unwrap();
+        fs.pin("/c").unwrap();
+        fs.write("/d", b"dddd").unwrap();
+        assert!(!fs.exists("/d").unwrap());
+        assert!(fs.exists("/dir/a").unwrap() && fs.exists("/c").unwrap());
+        open.write_all(b"AAAAAAAA").unwrap();
+        drop(open);
+        assert!(!fs.exists("/dir/a").unwrap());
+        assert_eq!(fs.evictions(), 3);
+
+        // Removing a file frees its budget and unpins it.
+        fs.remove_file("/c").unwrap();
+        fs.write("/c", b"cccccccc").unwrap();
+        fs.write("/e", b"ee").unwrap();
+        assert!(fs.exists("/c").unwrap() && fs.exists("/e").unwrap());
+        fs.write("/f", b"f").unwrap();
+        assert!(!fs.exists("/c").unwrap());
+
+        assert!(matches!(
+            MemoryFileSystem::new().pin("/c"),
+            Err(FileSystemError::UnsupportedOperation)
+        ));
+        assert!(matches!(fs.pin("/c"), Err(FileSystemError::PathMissing)));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_clock() {
+        use crate::{FileSystem, ManualClock, MemoryFileSystem, Simulation};
+        use std::io::Write;
+        use std::time::Duration;
+
+        let clock = ManualClock::new(UNIX_EPOCH);
+        let fs = MemoryFileSystem::with_clock(clock.clone());
+        let mut file = fs.create_file("/wal.log").unwrap();
+        assert_eq!(fs.modified("/wal.log").unwrap(), UNIX_EPOCH);
+
+        // Time stands still until advanced
+        file.write_all(b"entry").unwrap();
+        assert_eq!(fs.modified("/wal.log").unwrap(), UNIX_EPOCH);
+        clock.advance(Duration::from_secs(10));
+        file.write_all(b"entry").unwrap();
+        let later = UNIX_EPOCH + Duration::from_secs(10);
+        assert_eq!(fs.modified("/wal.log").unwrap(), later);
+
+        // Forks keep the clock
+        let fork = fs.fork();
+        clock.set(later + Duration::from_secs(5));
+        fork.write("/wal.log", b"forked").unwrap();
+        assert_eq!(
+            fork.modified("/wal.log").unwrap(),
+            later + Duration::from_secs(5)
+        );
+        assert_eq!(fs.modified("/wal.log").unwrap(), later);
+
+        // Simulations drive time with their virtual clock
+        let sim = Simulation::new(7);
+        let fs = MemoryFileSystem::with_clock(sim.clone());
+        sim.sleep(Duration::from_millis(250));
+        fs.write("/table.dat", b"rows").unwrap();
+        assert_eq!(
+            fs.modified("/table.dat").unwrap(),
+            UNIX_EPOCH + Duration::from_millis(250)
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_conformance() {
+        crate::conformance::run(crate::MemoryFileSystem::new);
+    }
 }
@@ -15,34 +15,96 @@
 //
 
 use crate::filesystem::{DynamicFileSystem, DynamicFileSystemProvider, FileSystemProvider};
-use crate::{FileHandle, FileLockMode, FileSystem, FileSystemResult};
-use std::collections::HashMap;
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::ops::AddAssign;
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemResult,
+    FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 
 /// Metric Collection Filesystem Wrapper
-#[derive(Debug)]
+///
+/// Records bytes transferred, open handles, and the count, errors and latency of every
+/// operation, both per path and in aggregate. Counters are updated atomically, so snapshots
+/// can be taken at any time without pausing traffic, and are shared by clones.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, MetricFileSystem, MetricOperation};
+/// use std::io::Write;
+///
+/// let fs = MetricFileSystem::new(MemoryFileSystem::new());
+/// let mut file = fs.create_file("/test.txt").unwrap();
+/// file.write_all(b"Hello, World!").unwrap();
+///
+/// let snapshot = fs.snapshot();
+/// assert_eq!(snapshot.aggregate().bytes_written(), 13);
+/// assert_eq!(snapshot.aggregate().open_handles(), 1);
+/// assert_eq!(snapshot.file("/test.txt").unwrap().operation(MetricOperation::CreateFile).count(), 1);
+/// ```
+#[derive(Clone, Debug)]
 pub struct MetricFileSystem {
     metrics: FileSystemMetrics,
     inner: Arc<dyn DynamicFileSystem>,
 }
 
 impl MetricFileSystem {
-    /// Create a new Metrics FileSystem
+    /// Create a new Metrics `FileSystem`
     pub fn new<F: FileSystem>(filesystem: F) -> MetricFileSystem {
         MetricFileSystem {
             metrics: FileSystemMetrics::default(),
             inner: Arc::new(filesystem),
         }
     }
+    /// Create a new Metrics `FileSystem` recording into counters shared with other wrappers.
+    pub(crate) fn shared(
+        filesystem: Arc<dyn DynamicFileSystem>,
+        metrics: FileSystemMetrics,
+    ) -> MetricFileSystem {
+        MetricFileSystem {
+            metrics,
+            inner: filesystem,
+        }
+    }
     /// Get Aggregate Filesystem metrics
+    #[must_use]
     pub fn filesystem_metrics(&self) -> MetricsData {
-        self.metrics.filesystem_metrics()
+        self.metrics.snapshot().aggregate
     }
     /// Get Individual File Metrics
+    #[must_use]
     pub fn file_metrics(&self) -> HashMap<String, MetricsData> {
-        self.metrics.file_metrics()
+        self.metrics.snapshot().files
+    }
+    /// Get aggregate and per file metrics from a single pass over the counters
+    #[must_use]
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Run a `FileSystem` operation on `path`, recording it.
+    fn record<T>(
+        &self,
+        path: &str,
+        operation: MetricOperation,
+        action: impl FnOnce() -> FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        self.metrics.file(path).record(operation, action)
+    }
+
+    /// Open a handle on `path` with `open`, recording it.
+    fn open(
+        &self,
+        path: &str,
+        operation: MetricOperation,
+        open: impl FnOnce() -> FileSystemResult<Box<dyn FileHandle>>,
+    ) -> FileSystemResult<MetricsFileHandle> {
+        let metrics = self.metrics.file(path);
+        let inner = metrics.record(operation, open)?;
+        metrics.open_handles.fetch_add(1, Ordering::Relaxed);
+        Ok(MetricsFileHandle { metrics, inner })
     }
 }
 
@@ -51,74 +113,180 @@ impl FileSystem for MetricFileSystem {
 
     #[tracing::instrument(level = "debug")]
     fn exists(&self, path: &str) -> FileSystemResult<bool> {
-        DynamicFileSystem::exists(self.inner.as_ref(), path)
+        self.record(path, MetricOperation::Exists, || {
+            DynamicFileSystem::exists(self.inner.as_ref(), path)
+        })
     }
 
     #[tracing::instrument(level = "debug")]
     fn is_file(&self, path: &str) -> FileSystemResult<bool> {
-        DynamicFileSystem::is_file(self.inner.as_ref(), path)
+        self.record(path, MetricOperation::IsFile, || {
+            DynamicFileSystem::is_file(self.inner.as_ref(), path)
+        })
     }
 
     #[tracing::instrument(level = "debug")]
     fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
-        DynamicFileSystem::is_directory(self.inner.as_ref(), path)
+        self.record(path, MetricOperation::IsDirectory, || {
+            DynamicFileSystem::is_directory(self.inner.as_ref(), path)
+        })
     }
 
     #[tracing::instrument(level = "debug")]
     fn filesize(&self, path: &str) -> FileSystemResult<u64> {
-        DynamicFileSystem::filesize(self.inner.as_ref(), path)
+        self.record(path, MetricOperation::Filesize, || {
+            DynamicFileSystem::filesize(self.inner.as_ref(), path)
+        })
     }
 
     #[tracing::instrument(level = "debug")]
     fn create_directory(&self, path: &str) -> FileSystemResult<()> {
-        DynamicFileSystem::create_directory(self.inner.as_ref(), path)
+        self.record(path, MetricOperation::CreateDirectory, || {
+            DynamicFileSystem::create_directory(self.inner.as_ref(), path)
+        })
     }
 
     #[tracing::instrument(level = "debug")]
     fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
-        DynamicFileSystem::create_directory_all(self.inner.as_ref(), path)
+        self.record(path, MetricOperation::CreateDirectoryAll, || {
+            DynamicFileSystem::create_directory_all(self.inner.as_ref(), path)
+        })
     }
 
     #[tracing::instrument(level = "debug")]
-    fn list_directory<'a>(&self, path: &str) -> FileSystemResult<Vec<String>> {
-        DynamicFileSystem::list_directory(self.inner.as_ref(), path)
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        self.record(path, MetricOperation::ListDirectory, || {
+            DynamicFileSystem::list_directory(self.inner.as_ref(), path)
+        })
     }
 
     #[tracing::instrument(level = "debug")]
     fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
-        DynamicFileSystem::remove_directory(self.inner.as_ref(), path)
+        self.record(path, MetricOperation::RemoveDirectory, || {
+            DynamicFileSystem::remove_directory(self.inner.as_ref(), path)
+        })
     }
 
     #[tracing::instrument(level = "debug")]
     fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
-        DynamicFileSystem::remove_directory_all(self.inner.as_ref(), path)
+        self.record(path, MetricOperation::RemoveDirectoryAll, || {
+            DynamicFileSystem::remove_directory_all(self.inner.as_ref(), path)
+        })
     }
 
     #[tracing::instrument(level = "debug")]
     fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
-        Ok(MetricsFileHandle {
-            metrics: self.metrics.initialize_file(path),
-            inner: DynamicFileSystem::create_file(self.inner.as_ref(), path)?,
+        self.open(path, MetricOperation::CreateFile, || {
+            DynamicFileSystem::create_file(self.inner.as_ref(), path)
         })
     }
 
     #[tracing::instrument(level = "debug")]
     fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
-        Ok(MetricsFileHandle {
-            metrics: self.metrics.initialize_file(path),
-            inner: DynamicFileSystem::open_file(self.inner.as_ref(), path)?,
+        self.open(path, MetricOperation::OpenFile, || {
+            DynamicFileSystem::open_file(self.inner.as_ref(), path)
         })
     }
 
     #[tracing::instrument(level = "debug")]
     fn remove_file(&self, path: &str) -> FileSystemResult<()> {
-        DynamicFileSystem::remove_file(self.inner.as_ref(), path)
+        self.record(path, MetricOperation::RemoveFile, || {
+            DynamicFileSystem::remove_file(self.inner.as_ref(), path)
+        })
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.record(path, MetricOperation::FileType, || {
+            DynamicFileSystem::file_type(self.inner.as_ref(), path, policy)
+        })
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        self.record(path, MetricOperation::CreateSymlink, || {
+            DynamicFileSystem::create_symlink(self.inner.as_ref(), target, path)
+        })
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.record(path, MetricOperation::ReadLink, || {
+            DynamicFileSystem::read_link(self.inner.as_ref(), path)
+        })
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.record(path, MetricOperation::Permissions, || {
+            DynamicFileSystem::permissions(self.inner.as_ref(), path)
+        })
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.record(path, MetricOperation::Modified, || {
+            DynamicFileSystem::modified(self.inner.as_ref(), path)
+        })
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.record(path, MetricOperation::SetPermissions, || {
+            DynamicFileSystem::set_permissions(self.inner.as_ref(), path, permissions)
+        })
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        self.open(path, MetricOperation::OpenWith, || {
+            DynamicFileSystem::open_with(self.inner.as_ref(), path, options)
+        })
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        DynamicFileSystem::space(self.inner.as_ref())
+    }
+
+    /// Recorded against `src`.
+    #[tracing::instrument(level = "debug")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        self.record(src, MetricOperation::CloneFile, || {
+            DynamicFileSystem::clone_file(self.inner.as_ref(), src, dst)
+        })
+    }
+
+    /// Recorded against `from`.
+    #[tracing::instrument(level = "debug")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        self.record(from, MetricOperation::Rename, || {
+            DynamicFileSystem::rename(self.inner.as_ref(), from, to)
+        })
+    }
+
+    /// Each operation is recorded against its first path with an equal share of the latency
+    /// of the batch, and the contents of each write count as bytes written.
+    #[tracing::instrument(level = "debug", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let start = Instant::now();
+        let results = DynamicFileSystem::apply(self.inner.as_ref(), batch);
+        let share = start.elapsed() / u32::try_from(batch.len().max(1)).unwrap_or(u32::MAX);
+        for (operation, result) in batch.iter().zip(&results) {
+            let path = operation.paths().next().expect("Operation Path");
+            let metrics = self.metrics.file(path);
+            metrics.operations[MetricOperation::Apply as usize].record(share, result.is_err());
+            if let (BatchOperation::Write { contents, .. }, Ok(())) = (operation, result) {
+                metrics.write_bytes(contents.len() as u64);
+            }
+        }
+        results
     }
 }
 
 /// Virtual File Handle
 pub struct MetricsFileHandle {
-    metrics: FileHandleMetrics,
+    metrics: Arc<FileCounters>,
     inner: Box<dyn FileHandle>,
 }
 
@@ -128,10 +296,27 @@ impl std::fmt::Debug for MetricsFileHandle {
     }
 }
 
+impl Drop for MetricsFileHandle {
+    fn drop(&mut self) {
+        self.metrics.open_handles.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 impl Read for MetricsFileHandle {
     #[tracing::instrument(level = "debug")]
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let rv = Read::read(self.inner.as_mut(), buf)?;
+        let rv = self.metrics.record(MetricOperation::Read, || {
+            Read::read(self.inner.as_mut(), buf)
+        })?;
+        self.metrics.read_bytes(rv as u64);
+        Ok(rv)
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        let rv = self.metrics.record(MetricOperation::Read, || {
+            Read::read_vectored(self.inner.as_mut(), bufs)
+        })?;
         self.metrics.read_bytes(rv as u64);
         Ok(rv)
     }
@@ -140,21 +325,35 @@ impl Read for MetricsFileHandle {
 impl Write for MetricsFileHandle {
     #[tracing::instrument(level = "debug")]
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let rv = Write::write(self.inner.as_mut(), buf)?;
+        let rv = self.metrics.record(MetricOperation::Write, || {
+            Write::write(self.inner.as_mut(), buf)
+        })?;
+        self.metrics.write_bytes(rv as u64);
+        Ok(rv)
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        let rv = self.metrics.record(MetricOperation::Write, || {
+            Write::write_vectored(self.inner.as_mut(), bufs)
+        })?;
         self.metrics.write_bytes(rv as u64);
         Ok(rv)
     }
 
     #[tracing::instrument(level = "debug")]
     fn flush(&mut self) -> std::io::Result<()> {
-        Write::flush(self.inner.as_mut())
+        self.metrics
+            .record(MetricOperation::Flush, || Write::flush(self.inner.as_mut()))
     }
 }
 
 impl Seek for MetricsFileHandle {
     #[tracing::instrument(level = "debug")]
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
-        Seek::seek(self.inner.as_mut(), pos)
+        self.metrics.record(MetricOperation::Seek, || {
+            Seek::seek(self.inner.as_mut(), pos)
+        })
     }
 }
 
@@ -166,22 +365,30 @@ impl FileHandle for MetricsFileHandle {
 
     #[tracing::instrument(level = "debug")]
     fn get_size(&self) -> FileSystemResult<u64> {
-        FileHandle::get_size(self.inner.as_ref())
+        self.metrics.record(MetricOperation::GetSize, || {
+            FileHandle::get_size(self.inner.as_ref())
+        })
     }
 
     #[tracing::instrument(level = "debug")]
     fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
-        FileHandle::set_size(self.inner.as_mut(), new_size)
+        self.metrics.record(MetricOperation::SetSize, || {
+            FileHandle::set_size(self.inner.as_mut(), new_size)
+        })
     }
 
     #[tracing::instrument(level = "debug")]
     fn sync_all(&mut self) -> FileSystemResult<()> {
-        FileHandle::sync_all(self.inner.as_mut())
+        self.metrics.record(MetricOperation::SyncAll, || {
+            FileHandle::sync_all(self.inner.as_mut())
+        })
     }
 
     #[tracing::instrument(level = "debug")]
     fn sync_data(&mut self) -> FileSystemResult<()> {
-        FileHandle::sync_data(self.inner.as_mut())
+        self.metrics.record(MetricOperation::SyncData, || {
+            FileHandle::sync_data(self.inner.as_mut())
+        })
     }
 
     #[tracing::instrument(level = "debug")]
@@ -191,36 +398,248 @@ impl FileHandle for MetricsFileHandle {
 
     #[tracing::instrument(level = "debug")]
     fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
-        FileHandle::set_lock_status(self.inner.as_mut(), mode)
+        self.metrics.record(MetricOperation::Lock, || {
+            FileHandle::set_lock_status(self.inner.as_mut(), mode)
+        })
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.metrics.record(MetricOperation::Lock, || {
+            FileHandle::try_set_lock_status(self.inner.as_mut(), mode)
+        })
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.metrics.record(MetricOperation::Lock, || {
+            FileHandle::lock_range(self.inner.as_mut(), offset, len, mode)
+        })
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.metrics.record(MetricOperation::Lock, || {
+            FileHandle::unlock_range(self.inner.as_mut(), offset, len)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        FileHandle::advise(self.inner.as_mut(), offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        let rv = self.metrics.record(MetricOperation::ReadAt, || {
+            FileHandle::read_at_offset(self.inner.as_mut(), offset, buffer)
+        })?;
+        self.metrics.read_bytes(rv as u64);
+        Ok(rv)
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        let rv = self.metrics.record(MetricOperation::WriteAt, || {
+            FileHandle::write_to_offset(self.inner.as_mut(), offset, buffer)
+        })?;
+        self.metrics.write_bytes(rv as u64);
+        Ok(rv)
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        let rv = self.metrics.record(MetricOperation::ReadAt, || {
+            FileHandle::read_at_vectored(self.inner.as_mut(), offset, buffers)
+        })?;
+        self.metrics.read_bytes(rv as u64);
+        Ok(rv)
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        let rv = self.metrics.record(MetricOperation::WriteAt, || {
+            FileHandle::write_at_vectored(self.inner.as_mut(), offset, buffers)
+        })?;
+        self.metrics.write_bytes(rv as u64);
+        Ok(rv)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "debug")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        self.metrics.record(MetricOperation::Map, || {
+            FileHandle::map_readonly(self.inner.as_mut(), offset, len)
+        })
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        FileHandle::alignment(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "debug")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.metrics.record(MetricOperation::Allocate, || {
+            FileHandle::allocate(self.inner.as_mut(), len)
+        })
     }
 }
 
-/// Collection of Metrics for FileSystem
-#[derive(Debug, Default)]
-struct FileSystemMetrics {
-    inner: Arc<RwLock<HashMap<String, FileHandleMetrics>>>,
+/// Operation recorded by a [`MetricFileSystem`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum MetricOperation {
+    /// [`FileSystem::exists`]
+    Exists,
+    /// [`FileSystem::is_file`]
+    IsFile,
+    /// [`FileSystem::is_directory`]
+    IsDirectory,
+    /// [`FileSystem::filesize`]
+    Filesize,
+    /// [`FileSystem::create_directory`]
+    CreateDirectory,
+    /// [`FileSystem::create_directory_all`]
+    CreateDirectoryAll,
+    /// [`FileSystem::list_directory`]
+    ListDirectory,
+    /// [`FileSystem::remove_directory`]
+    RemoveDirectory,
+    /// [`FileSystem::remove_directory_all`]
+    RemoveDirectoryAll,
+    /// [`FileSystem::create_file`]
+    CreateFile,
+    /// [`FileSystem::open_file`]
+    OpenFile,
+    /// [`FileSystem::remove_file`]
+    RemoveFile,
+    /// [`FileSystem::open_with`]
+    OpenWith,
+    /// [`FileSystem::file_type`]
+    FileType,
+    /// [`FileSystem::create_symlink`]
+    CreateSymlink,
+    /// [`FileSystem::read_link`]
+    ReadLink,
+    /// [`FileSystem::permissions`]
+    Permissions,
+    /// [`FileSystem::set_permissions`]
+    SetPermissions,
+    /// [`FileSystem::modified`]
+    Modified,
+    /// Reads through the cursor
+    Read,
+    /// Writes through the cursor
+    Write,
+    /// [`Write::flush`]
+    Flush,
+    /// [`Seek::seek`]
+    Seek,
+    /// Positional reads
+    ReadAt,
+    /// Positional writes
+    WriteAt,
+    /// [`FileHandle::get_size`]
+    GetSize,
+    /// [`FileHandle::set_size`]
+    SetSize,
+    /// [`FileHandle::sync_all`]
+    SyncAll,
+    /// [`FileHandle::sync_data`]
+    SyncData,
+    /// Taking or releasing whole file or byte-range locks
+    Lock,
+    /// [`FileHandle::allocate`]
+    Allocate,
+    /// Read-only mappings of a file
+    Map,
+    /// [`FileSystem::rename`]
+    Rename,
+    /// [`FileSystem::clone_file`]
+    CloneFile,
+    /// Operations applied in a batch by [`FileSystem::apply`]
+    Apply,
+}
+
+impl MetricOperation {
+    /// Every operation, in order.
+    pub const ALL: [MetricOperation; 35] = [
+        MetricOperation::Exists,
+        MetricOperation::IsFile,
+        MetricOperation::IsDirectory,
+        MetricOperation::Filesize,
+        MetricOperation::CreateDirectory,
+        MetricOperation::CreateDirectoryAll,
+        MetricOperation::ListDirectory,
+        MetricOperation::RemoveDirectory,
+        MetricOperation::RemoveDirectoryAll,
+        MetricOperation::CreateFile,
+        MetricOperation::OpenFile,
+        MetricOperation::RemoveFile,
+        MetricOperation::OpenWith,
+        MetricOperation::FileType,
+        MetricOperation::CreateSymlink,
+        MetricOperation::ReadLink,
+        MetricOperation::Permissions,
+        MetricOperation::SetPermissions,
+        MetricOperation::Modified,
+        MetricOperation::Read,
+        MetricOperation::Write,
+        MetricOperation::Flush,
+        MetricOperation::Seek,
+        MetricOperation::ReadAt,
+        MetricOperation::WriteAt,
+        MetricOperation::GetSize,
+        MetricOperation::SetSize,
+        MetricOperation::SyncAll,
+        MetricOperation::SyncData,
+        MetricOperation::Lock,
+        MetricOperation::Allocate,
+        MetricOperation::Map,
+        MetricOperation::Rename,
+        MetricOperation::CloneFile,
+        MetricOperation::Apply,
+    ];
+}
+
+/// Number of buckets in a [`LatencyHistogram`].
+const LATENCY_BUCKETS: usize = 32;
+
+/// Collection of Metrics for `FileSystem`, cloned to share it between wrappers
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FileSystemMetrics {
+    inner: Arc<RwLock<HashMap<String, Arc<FileCounters>>>>,
 }
 
 impl FileSystemMetrics {
-    /// Get Aggregate `FileSystem` metrics
-    fn filesystem_metrics(&self) -> MetricsData {
-        let mut metrics = MetricsData::default();
-        for metric in self.inner.read().expect("Mutex Poisoned").values() {
-            metrics.bytes_read += metric.bytes_read();
-            metrics.bytes_written += metric.bytes_written();
+    /// Read every counter, without blocking operations in progress.
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        let files = self
+            .inner
+            .read()
+            .expect("Mutex Poisoned")
+            .iter()
+            .map(|(path, counters)| (path.clone(), counters.snapshot()))
+            .collect::<HashMap<_, _>>();
+        let mut aggregate = MetricsData::default();
+        for metrics in files.values() {
+            aggregate.merge(metrics);
         }
-        metrics
+        MetricsSnapshot { aggregate, files }
     }
-    /// Get file `MetricsData`
-    fn file_metrics(&self) -> HashMap<String, MetricsData> {
-        let mut metrics = HashMap::new();
-        for (path, metric) in self.inner.read().expect("Mutex Poisoned").iter() {
-            metrics.insert(path.clone(), metric.metrics());
+    /// Get the counters of a path, creating them if it hasn't been seen
+    fn file(&self, path: &str) -> Arc<FileCounters> {
+        if let Some(counters) = self.inner.read().expect("Mutex Poisoned").get(path) {
+            return counters.clone();
         }
-        metrics
-    }
-    /// Initialize a file, no-op if it already exists
-    fn initialize_file(&self, path: &str) -> FileHandleMetrics {
         self.inner
             .write()
             .expect("Mutex Poisoned")
@@ -230,34 +649,180 @@ impl FileSystemMetrics {
     }
 }
 
+/// Live counters of a single path.
+#[derive(Debug)]
+struct FileCounters {
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    open_handles: AtomicU64,
+    operations: [OperationCounters; MetricOperation::ALL.len()],
+}
+
+impl Default for FileCounters {
+    fn default() -> Self {
+        FileCounters {
+            bytes_read: AtomicU64::default(),
+            bytes_written: AtomicU64::default(),
+            open_handles: AtomicU64::default(),
+            operations: std::array::from_fn(|_| OperationCounters::default()),
+        }
+    }
+}
+
+impl FileCounters {
+    fn read_bytes(&self, bytes: u64) {
+        self.bytes_read.fetch_add(bytes, Ordering::Relaxed);
+    }
+    fn write_bytes(&self, bytes: u64) {
+        self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+    }
+    /// Run `action`, recording its latency and whether it failed.
+    fn record<T, E>(
+        &self,
+        operation: MetricOperation,
+        action: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let start = Instant::now();
+        let result = action();
+        self.operations[operation as usize].record(start.elapsed(), result.is_err());
+        result
+    }
+    fn snapshot(&self) -> MetricsData {
+        let operations = MetricOperation::ALL
+            .iter()
+            .zip(&self.operations)
+            .filter_map(|(operation, counters)| {
+                let metrics = counters.snapshot();
+                (metrics.count > 0).then_some((*operation, metrics))
+            })
+            .collect();
+        MetricsData {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            open_handles: self.open_handles.load(Ordering::Relaxed),
+            operations,
+        }
+    }
+}
+
+/// Live counters of a single operation on a single path.
+#[derive(Debug, Default)]
+struct OperationCounters {
+    count: AtomicU64,
+    errors: AtomicU64,
+    nanos: AtomicU64,
+    buckets: [AtomicU64; LATENCY_BUCKETS],
+}
+
+impl OperationCounters {
+    fn record(&self, latency: Duration, failed: bool) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.buckets[LatencyHistogram::bucket(latency)].fetch_add(1, Ordering::Relaxed);
+    }
+    fn snapshot(&self) -> OperationMetrics {
+        OperationMetrics {
+            count: self.count.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            total_latency: Duration::from_nanos(self.nanos.load(Ordering::Relaxed)),
+            histogram: LatencyHistogram {
+                buckets: std::array::from_fn(|bucket| self.buckets[bucket].load(Ordering::Relaxed)),
+            },
+        }
+    }
+}
+
+/// Aggregate and per path metrics read at one point in time.
 #[derive(Clone, Debug, Default)]
-struct FileHandleMetrics {
-    inner: Arc<RwLock<MetricsData>>,
+pub struct MetricsSnapshot {
+    aggregate: MetricsData,
+    files: HashMap<String, MetricsData>,
 }
 
-impl FileHandleMetrics {
-    fn metrics(&self) -> MetricsData {
-        self.inner.read().expect("Mutex Poisoned").clone()
+impl MetricsSnapshot {
+    /// Metrics summed over every path
+    #[must_use]
+    pub fn aggregate(&self) -> &MetricsData {
+        &self.aggregate
     }
-    fn bytes_read(&self) -> u64 {
-        self.inner.read().expect("Mutex Poisoned").bytes_read
+    /// Metrics of every path seen
+    #[must_use]
+    pub fn files(&self) -> &HashMap<String, MetricsData> {
+        &self.files
     }
-    fn bytes_written(&self) -> u64 {
-        self.inner.read().expect("Mutex Poisoned").bytes_written
+    /// Metrics of a single path, if it has been seen
+    #[must_use]
+    pub fn file(&self, path: &str) -> Option<&MetricsData> {
+        self.files.get(path)
     }
-    fn read_bytes(&self, bytes: u64) {
-        self.inner
-            .write()
-            .expect("Mutex Poisoned")
-            .bytes_read
-            .add_assign(bytes);
+}
+
+/// Metrics of the filesystems a [`VirtualFileSystemManager`] provisioned and mounted, read at
+/// one point in time.
+///
+/// [`VirtualFileSystemManager`]: crate::VirtualFileSystemManager
+#[derive(Clone, Debug, Default)]
+pub struct ManagerMetrics {
+    pub(crate) schemes: BTreeMap<String, SchemeMetrics>,
+    pub(crate) mounts: BTreeMap<String, MetricsData>,
+}
+
+impl ManagerMetrics {
+    /// Metrics of every scheme a filesystem was looked up for
+    #[must_use]
+    pub fn schemes(&self) -> &BTreeMap<String, SchemeMetrics> {
+        &self.schemes
     }
-    fn write_bytes(&self, bytes: u64) {
-        self.inner
-            .write()
-            .expect("Mutex Poisoned")
-            .bytes_written
-            .add_assign(bytes);
+    /// Metrics of a single scheme, if a filesystem was looked up for it
+    #[must_use]
+    pub fn scheme(&self, scheme: &str) -> Option<&SchemeMetrics> {
+        self.schemes.get(scheme)
+    }
+    /// Metrics of every mounted filesystem, by normalized prefix
+    #[must_use]
+    pub fn mounts(&self) -> &BTreeMap<String, MetricsData> {
+        &self.mounts
+    }
+    /// Metrics of the filesystem mounted at a prefix, if metrics are collected for it
+    #[must_use]
+    pub fn mount(&self, prefix: &str) -> Option<&MetricsData> {
+        self.mounts.get(prefix)
+    }
+}
+
+/// Provisioning outcomes and filesystem metrics of a single scheme
+#[derive(Clone, Debug, Default)]
+pub struct SchemeMetrics {
+    pub(crate) provisions: u64,
+    pub(crate) failures: u64,
+    pub(crate) cache_hits: u64,
+    pub(crate) metrics: MetricsData,
+}
+
+impl SchemeMetrics {
+    /// Filesystems its provider provisioned
+    #[must_use]
+    pub fn provisions(&self) -> u64 {
+        self.provisions
+    }
+    /// Lookups that failed, whether for want of a provider or in provisioning
+    #[must_use]
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+    /// Lookups served by a cached filesystem
+    #[must_use]
+    pub fn cache_hits(&self) -> u64 {
+        self.cache_hits
+    }
+    /// Operations on every filesystem provisioned for it, empty unless collected
+    #[must_use]
+    pub fn metrics(&self) -> &MetricsData {
+        &self.metrics
     }
 }
 
@@ -266,6 +831,145 @@ impl FileHandleMetrics {
 pub struct MetricsData {
     bytes_written: u64,
     bytes_read: u64,
+    open_handles: u64,
+    operations: BTreeMap<MetricOperation, OperationMetrics>,
+}
+
+impl MetricsData {
+    /// Bytes written through handles
+    #[must_use]
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+    /// Bytes read through handles
+    #[must_use]
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+    /// Handles currently open
+    #[must_use]
+    pub fn open_handles(&self) -> u64 {
+        self.open_handles
+    }
+    /// Metrics of a single operation, empty if it was never performed
+    #[must_use]
+    pub fn operation(&self, operation: MetricOperation) -> OperationMetrics {
+        self.operations.get(&operation).cloned().unwrap_or_default()
+    }
+    /// Metrics of every operation performed at least once
+    #[must_use]
+    pub fn operations(&self) -> &BTreeMap<MetricOperation, OperationMetrics> {
+        &self.operations
+    }
+    fn merge(&mut self, other: &MetricsData) {
+        self.bytes_written += other.bytes_written;
+        self.bytes_read += other.bytes_read;
+        self.open_handles += other.open_handles;
+        for (operation, metrics) in &other.operations {
+            self.operations
+                .entry(*operation)
+                .or_default()
+                .merge(metrics);
+        }
+    }
+}
+
+/// Count, errors and latency of a single operation
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OperationMetrics {
+    count: u64,
+    errors: u64,
+    total_latency: Duration,
+    histogram: LatencyHistogram,
+}
+
+impl OperationMetrics {
+    /// Times the operation was performed
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    /// Times the operation failed
+    #[must_use]
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+    /// Time spent in the operation
+    #[must_use]
+    pub fn total_latency(&self) -> Duration {
+        self.total_latency
+    }
+    /// Mean time spent in the operation
+    #[must_use]
+    pub fn mean_latency(&self) -> Duration {
+        match u32::try_from(self.count) {
+            Ok(0) => Duration::ZERO,
+            Ok(count) => self.total_latency / count,
+            Err(_) => Duration::from_nanos(
+                u64::try_from(self.total_latency.as_nanos() / u128::from(self.count))
+                    .unwrap_or(u64::MAX),
+            ),
+        }
+    }
+    /// Distribution of the time spent in the operation
+    #[must_use]
+    pub fn histogram(&self) -> &LatencyHistogram {
+        &self.histogram
+    }
+    fn merge(&mut self, other: &OperationMetrics) {
+        self.count += other.count;
+        self.errors += other.errors;
+        self.total_latency += other.total_latency;
+        for (bucket, count) in self
+            .histogram
+            .buckets
+            .iter_mut()
+            .zip(other.histogram.buckets)
+        {
+            *bucket += count;
+        }
+    }
+}
+
+/// Latency histogram with power of two buckets
+///
+/// Bucket `0` counts operations faster than a microsecond and bucket `n` those taking from
+/// `2^(n-1)` up to `2^n` microseconds, with the last bucket counting everything slower.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LatencyHistogram {
+    buckets: [u64; LATENCY_BUCKETS],
+}
+
+impl LatencyHistogram {
+    fn bucket(latency: Duration) -> usize {
+        let micros = latency.as_micros();
+        let bucket = (u128::BITS - micros.leading_zeros()) as usize;
+        bucket.min(LATENCY_BUCKETS - 1)
+    }
+    /// Count of operations in each bucket
+    #[must_use]
+    pub fn buckets(&self) -> &[u64] {
+        &self.buckets
+    }
+    /// Upper bound of the bucket holding the `quantile` of operations, between `0.0` and `1.0`
+    #[must_use]
+    pub fn quantile(&self, quantile: f64) -> Duration {
+        let total = self.buckets.iter().sum::<u64>();
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_precision_loss,
+            clippy::cast_sign_loss
+        )]
+        let target = ((total as f64 * quantile.clamp(0.0, 1.0)).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return Duration::from_micros(1 << bucket);
+            }
+        }
+        Duration::ZERO
+    }
 }
 
 #[cfg(test)]
@@ -350,4 +1054,74 @@ mod test {
             .exists(filename.as_str())
             .expect("Error Checking File Existence"));
     }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_metrics_operations() {
+        use crate::{FileSystem, MetricFileSystem, MetricOperation};
+        use std::io::Write;
+
+        let fs = MetricFileSystem::new(MemoryFileSystem::new());
+        {
+            let mut file = fs.create_file("/metrics.bin").unwrap();
+            file.write_all(b"abcdef").unwrap();
+            file.flush().unwrap();
+            assert_eq!(fs.snapshot().aggregate().open_handles(), 1);
+        }
+        assert!(fs.open_file("/missing.bin").is_err());
+        assert!(fs.exists("/metrics.bin").unwrap());
+
+        let snapshot = fs.snapshot();
+        let aggregate = snapshot.aggregate();
+        assert_eq!(aggregate.open_handles(), 0);
+        assert_eq!(aggregate.bytes_written(), 6);
+        let open = aggregate.operation(MetricOperation::OpenFile);
+        assert_eq!((open.count(), open.errors()), (1, 1));
+        let missing = snapshot.file("/missing.bin").unwrap();
+        assert_eq!(missing.operation(MetricOperation::OpenFile).errors(), 1);
+
+        let file = snapshot.file("/metrics.bin").unwrap();
+        assert_eq!(file.operation(MetricOperation::CreateFile).count(), 1);
+        assert_eq!(file.operation(MetricOperation::Exists).count(), 1);
+        assert_eq!(file.operation(MetricOperation::Flush).count(), 1);
+        assert_eq!(file.operation(MetricOperation::RemoveFile).count(), 0);
+        let write = file.operation(MetricOperation::Write);
+        assert!(write.count() >= 1);
+        assert_eq!(
+            write.histogram().buckets().iter().sum::<u64>(),
+            write.count()
+        );
+        assert!(write.histogram().quantile(1.0) >= write.mean_latency());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_metrics_positional() {
+        use crate::{FileHandle, FileSystem, MetricFileSystem, MetricOperation};
+        use std::io::{Seek, SeekFrom};
+
+        let fs = MetricFileSystem::new(MemoryFileSystem::new());
+        let mut file = fs.create_file("/positional.bin").unwrap();
+        assert_eq!(file.write_to_offset(4, b"wxyz").unwrap(), 4);
+        let mut buf = [0u8; 8];
+        assert_eq!(file.read_at_offset(0, &mut buf).unwrap(), 8);
+        assert_eq!(&buf, b"\0\0\0\0wxyz");
+        file.seek(SeekFrom::Start(2)).unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+
+        let metrics = &fs.file_metrics()["/positional.bin"];
+        assert_eq!(metrics.bytes_written(), 4);
+        assert_eq!(metrics.bytes_read(), 8);
+        assert_eq!(metrics.operation(MetricOperation::WriteAt).count(), 1);
+        assert_eq!(metrics.operation(MetricOperation::ReadAt).count(), 1);
+        assert_eq!(metrics.operation(MetricOperation::Seek).count(), 2);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_metrics_conformance() {
+        use crate::{MemoryFileSystem, MetricFileSystem};
+
+        crate::conformance::run(|| MetricFileSystem::new(MemoryFileSystem::new()));
+    }
 }
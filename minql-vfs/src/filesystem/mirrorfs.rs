@@ -0,0 +1,718 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::recordfs::{error_name, io_error_name};
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemResult,
+    FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::fmt::Debug;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// How a [`MirrorCheckFileSystem`] reacts to backends disagreeing.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum MirrorPolicy {
+    /// Panic with the divergence, failing the test at the operation that diverged
+    #[default]
+    Panic,
+    /// Log the divergence and keep going, collecting it for [`MirrorCheckFileSystem::divergences`]
+    Log,
+}
+
+/// Operation whose outcome differed between the backends of a [`MirrorCheckFileSystem`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Divergence {
+    /// Operation performed
+    pub operation: &'static str,
+    /// Path operated on
+    pub path: String,
+    /// Outcome on the primary backend, or the name of the error it failed with
+    pub primary: Result<String, String>,
+    /// Outcome on the secondary backend, or the name of the error it failed with
+    pub secondary: Result<String, String>,
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} on {} diverged: primary {:?}, secondary {:?}",
+            self.operation, self.path, self.primary, self.secondary
+        )
+    }
+}
+
+/// Divergence handling shared by a [`MirrorCheckFileSystem`] and its handles.
+#[derive(Debug)]
+struct MirrorState {
+    policy: MirrorPolicy,
+    divergences: Mutex<Vec<Divergence>>,
+}
+
+impl MirrorState {
+    fn check(
+        &self,
+        operation: &'static str,
+        path: &str,
+        primary: Result<String, String>,
+        secondary: Result<String, String>,
+    ) {
+        if primary == secondary {
+            return;
+        }
+        let divergence = Divergence {
+            operation,
+            path: path.to_string(),
+            primary,
+            secondary,
+        };
+        tracing::error!(%divergence, "Backends diverged");
+        assert!(self.policy != MirrorPolicy::Panic, "{divergence}");
+        self.divergences
+            .lock()
+            .expect("Poisoned Lock")
+            .push(divergence);
+    }
+
+    /// Compare the outcomes of an operation, returning the primary's.
+    fn compare<T: Debug>(
+        &self,
+        operation: &'static str,
+        path: &str,
+        primary: FileSystemResult<T>,
+        secondary: &FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        self.check(operation, path, render(&primary), render(secondary));
+        primary
+    }
+
+    /// Compare the outcomes of an I/O operation, returning the primary's.
+    fn compare_io<T: Debug>(
+        &self,
+        operation: &'static str,
+        path: &str,
+        primary: std::io::Result<T>,
+        secondary: &std::io::Result<T>,
+    ) -> std::io::Result<T> {
+        let render = |result: &std::io::Result<T>| {
+            result
+                .as_ref()
+                .map(|value| format!("{value:?}"))
+                .map_err(io_error_name)
+        };
+        self.check(operation, path, render(&primary), render(secondary));
+        primary
+    }
+}
+
+/// Render whether an operation succeeded, for results that don't compare.
+fn opened<T>(result: &FileSystemResult<T>) -> Result<String, String> {
+    result.as_ref().map(|_| String::new()).map_err(error_name)
+}
+
+fn render<T: Debug>(result: &FileSystemResult<T>) -> Result<String, String> {
+    result
+        .as_ref()
+        .map(|value| format!("{value:?}"))
+        .map_err(error_name)
+}
+
+/// Differential Testing `FileSystem` Wrapper
+///
+/// Executes every operation against both a primary and a secondary backend, returning the
+/// primary's result, and reports any operation whose results differ according to its
+/// [`MirrorPolicy`]. Errors compare by variant, so backends agree when they fail the same way
+/// with different messages.
+///
+/// Directory listings compare regardless of order, and permissions only by whether they are
+/// read-only, since modes are backend-specific. Free space, modification times and handle
+/// alignment aren't compared. Once a file opens on only one backend, operations on that handle go to the
+/// primary alone, if at all.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, MirrorCheckFileSystem, MirrorPolicy};
+///
+/// let secondary = MemoryFileSystem::new();
+/// let fs = MirrorCheckFileSystem::new(MemoryFileSystem::new(), secondary.clone())
+///     .with_policy(MirrorPolicy::Log);
+/// fs.write("/table.dat", b"rows").unwrap();
+/// assert!(fs.divergences().is_empty());
+///
+/// secondary.write("/table.dat", b"cols").unwrap();
+/// assert_eq!(fs.read("/table.dat").unwrap(), b"rows");
+/// assert_eq!(fs.divergences()[0].operation, "read");
+/// ```
+#[derive(Debug)]
+pub struct MirrorCheckFileSystem<A: FileSystem, B: FileSystem> {
+    primary: A,
+    secondary: B,
+    state: Arc<MirrorState>,
+}
+
+impl<A: FileSystem, B: FileSystem> MirrorCheckFileSystem<A, B> {
+    /// Create a new Differential Testing `FileSystem`, panicking on divergence.
+    pub fn new(primary: A, secondary: B) -> MirrorCheckFileSystem<A, B> {
+        MirrorCheckFileSystem {
+            primary,
+            secondary,
+            state: Arc::new(MirrorState {
+                policy: MirrorPolicy::default(),
+                divergences: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Set how divergences are reported.
+    #[must_use]
+    pub fn with_policy(self, policy: MirrorPolicy) -> MirrorCheckFileSystem<A, B> {
+        MirrorCheckFileSystem {
+            state: Arc::new(MirrorState {
+                policy,
+                divergences: Mutex::default(),
+            }),
+            ..self
+        }
+    }
+
+    /// Borrow the primary backend.
+    pub fn primary(&self) -> &A {
+        &self.primary
+    }
+
+    /// Borrow the secondary backend.
+    pub fn secondary(&self) -> &B {
+        &self.secondary
+    }
+
+    /// Divergences logged so far, in the order they happened.
+    #[must_use]
+    pub fn divergences(&self) -> Vec<Divergence> {
+        self.state
+            .divergences
+            .lock()
+            .expect("Poisoned Lock")
+            .clone()
+    }
+
+    fn open(
+        &self,
+        operation: &'static str,
+        path: &str,
+        primary: FileSystemResult<A::FileHandle>,
+        secondary: FileSystemResult<B::FileHandle>,
+    ) -> FileSystemResult<MirrorCheckFileHandle<A::FileHandle, B::FileHandle>> {
+        self.state
+            .check(operation, path, opened(&primary), opened(&secondary));
+        Ok(MirrorCheckFileHandle {
+            primary: primary?,
+            secondary: secondary.ok(),
+            state: self.state.clone(),
+        })
+    }
+}
+
+impl<A: FileSystem, B: FileSystem> FileSystem for MirrorCheckFileSystem<A, B> {
+    type FileHandle = MirrorCheckFileHandle<A::FileHandle, B::FileHandle>;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        let (primary, secondary) = (self.primary.exists(path), self.secondary.exists(path));
+        self.state.compare("exists", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        let (primary, secondary) = (self.primary.is_file(path), self.secondary.is_file(path));
+        self.state.compare("is_file", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        let primary = self.primary.is_directory(path);
+        let secondary = self.secondary.is_directory(path);
+        self.state
+            .compare("is_directory", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        let (primary, secondary) = (self.primary.filesize(path), self.secondary.filesize(path));
+        self.state.compare("filesize", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        let primary = self.primary.create_directory(path);
+        let secondary = self.secondary.create_directory(path);
+        self.state
+            .compare("create_directory", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        let primary = self.primary.create_directory_all(path);
+        let secondary = self.secondary.create_directory_all(path);
+        self.state
+            .compare("create_directory_all", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        let sorted = |mut names: Vec<String>| {
+            names.sort();
+            names
+        };
+        let primary = self.primary.list_directory(path).map(sorted);
+        let secondary = self.secondary.list_directory(path).map(sorted);
+        self.state
+            .compare("list_directory", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        let primary = self.primary.remove_directory(path);
+        let secondary = self.secondary.remove_directory(path);
+        self.state
+            .compare("remove_directory", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        let primary = self.primary.remove_directory_all(path);
+        let secondary = self.secondary.remove_directory_all(path);
+        self.state
+            .compare("remove_directory_all", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let primary = self.primary.create_file(path);
+        let secondary = self.secondary.create_file(path);
+        self.open("create_file", path, primary, secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let primary = self.primary.open_file(path);
+        let secondary = self.secondary.open_file(path);
+        self.open("open_file", path, primary, secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        let primary = self.primary.remove_file(path);
+        let secondary = self.secondary.remove_file(path);
+        self.state.compare("remove_file", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        let primary = self.primary.file_type(path, policy);
+        let secondary = self.secondary.file_type(path, policy);
+        self.state.compare("file_type", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        let primary = self.primary.create_symlink(target, path);
+        let secondary = self.secondary.create_symlink(target, path);
+        self.state
+            .compare("create_symlink", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        let (primary, secondary) = (self.primary.read_link(path), self.secondary.read_link(path));
+        self.state.compare("read_link", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        let primary = self.primary.permissions(path);
+        let secondary = self.secondary.permissions(path);
+        let readonly = |result: &FileSystemResult<Permissions>| {
+            result
+                .as_ref()
+                .map(|permissions| format!("readonly: {}", permissions.is_readonly()))
+                .map_err(error_name)
+        };
+        self.state.check(
+            "permissions",
+            path,
+            readonly(&primary),
+            readonly(&secondary),
+        );
+        primary
+    }
+
+    /// Only compares whether both backends could tell, since times are backend-specific.
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        let (primary, secondary) = (self.primary.modified(path), self.secondary.modified(path));
+        self.state
+            .check("modified", path, opened(&primary), opened(&secondary));
+        primary
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        let primary = self.primary.set_permissions(path, permissions);
+        let secondary = self.secondary.set_permissions(path, permissions);
+        self.state
+            .compare("set_permissions", path, primary, &secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        let primary = self.primary.open_with(path, options);
+        let secondary = self.secondary.open_with(path, options);
+        self.open("open_with", path, primary, secondary)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        self.primary.space()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        let primary = self.primary.rename(from, to);
+        let secondary = self.secondary.rename(from, to);
+        self.state.compare("rename", from, primary, &secondary)
+    }
+
+    /// Only compares whether the clones succeeded, as backends may clone files differently.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        let primary = self.primary.clone_file(src, dst);
+        let secondary = self.secondary.clone_file(src, dst);
+        self.state
+            .check("clone_file", src, opened(&primary), opened(&secondary));
+        primary
+    }
+
+    /// Compares the outcome of every operation in the batch.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let primary = self.primary.apply(batch);
+        let secondary = self.secondary.apply(batch);
+        batch
+            .iter()
+            .zip(primary.into_iter().zip(&secondary))
+            .map(|(operation, (primary, secondary))| {
+                let path = operation.paths().next().expect("Operation Path");
+                self.state.compare("apply", path, primary, secondary)
+            })
+            .collect()
+    }
+}
+
+/// Differential Testing File Handle
+///
+/// Performs every operation on the handles of both backends, unless the file only opened on
+/// the primary.
+pub struct MirrorCheckFileHandle<HA: FileHandle, HB: FileHandle> {
+    primary: HA,
+    secondary: Option<HB>,
+    state: Arc<MirrorState>,
+}
+
+impl<HA: FileHandle, HB: FileHandle> MirrorCheckFileHandle<HA, HB> {
+    /// Check if operations are still mirrored to the secondary backend.
+    #[must_use]
+    pub fn is_mirrored(&self) -> bool {
+        self.secondary.is_some()
+    }
+
+    /// Perform an operation on both handles, comparing outcomes and returning the primary's.
+    fn mirror<T: Debug>(
+        &mut self,
+        operation: &'static str,
+        action: impl Fn(&mut dyn FileHandle) -> FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        let primary = action(&mut self.primary);
+        match self.secondary.as_mut() {
+            Some(secondary) => {
+                let secondary = action(secondary);
+                self.state
+                    .compare(operation, self.primary.path(), primary, &secondary)
+            }
+            None => primary,
+        }
+    }
+
+    /// Perform an I/O operation on both handles, comparing outcomes and returning the
+    /// primary's.
+    fn mirror_io<T: Debug>(
+        &mut self,
+        operation: &'static str,
+        action: impl Fn(&mut dyn FileHandle) -> std::io::Result<T>,
+    ) -> std::io::Result<T> {
+        let primary = action(&mut self.primary);
+        match self.secondary.as_mut() {
+            Some(secondary) => {
+                let secondary = action(secondary);
+                self.state
+                    .compare_io(operation, self.primary.path(), primary, &secondary)
+            }
+            None => primary,
+        }
+    }
+}
+
+impl<HA: FileHandle, HB: FileHandle> Debug for MirrorCheckFileHandle<HA, HB> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MirrorCheckFileHandle")
+            .field("primary", &self.primary)
+            .field("secondary", &self.secondary)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<HA: FileHandle, HB: FileHandle> Read for MirrorCheckFileHandle<HA, HB> {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let primary = self.primary.read(buf);
+        let Some(secondary) = self.secondary.as_mut() else {
+            return primary;
+        };
+        let mut mirrored = vec![0; buf.len()];
+        let secondary = secondary
+            .read(&mut mirrored)
+            .map(|read| mirrored[..read].to_vec());
+        let primary = primary.map(|read| buf[..read].to_vec());
+        let read = self
+            .state
+            .compare_io("read", self.primary.path(), primary, &secondary)?;
+        Ok(read.len())
+    }
+}
+
+impl<HA: FileHandle, HB: FileHandle> Write for MirrorCheckFileHandle<HA, HB> {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.mirror_io("write", |handle| handle.write(buf))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.mirror_io("flush", Write::flush)
+    }
+}
+
+impl<HA: FileHandle, HB: FileHandle> Seek for MirrorCheckFileHandle<HA, HB> {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.mirror_io("seek", |handle| handle.seek(pos))
+    }
+}
+
+impl<HA: FileHandle, HB: FileHandle> FileHandle for MirrorCheckFileHandle<HA, HB> {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        self.primary.path()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        let primary = self.primary.get_size();
+        match self.secondary.as_ref() {
+            Some(secondary) => self.state.compare(
+                "get_size",
+                self.primary.path(),
+                primary,
+                &secondary.get_size(),
+            ),
+            None => primary,
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.mirror("set_size", |handle| handle.set_size(new_size))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.mirror("allocate", |handle| handle.allocate(len))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.mirror("sync_all", FileHandle::sync_all)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.mirror("sync_data", FileHandle::sync_data)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        let primary = self.primary.get_lock_status();
+        match self.secondary.as_ref() {
+            Some(secondary) => self.state.compare(
+                "get_lock_status",
+                self.primary.path(),
+                primary,
+                &secondary.get_lock_status(),
+            ),
+            None => primary,
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.mirror("set_lock_status", |handle| handle.set_lock_status(mode))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.mirror("try_set_lock_status", |handle| {
+            handle.try_set_lock_status(mode)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        self.primary.alignment()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.mirror("lock_range", |handle| handle.lock_range(offset, len, mode))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.mirror("unlock_range", |handle| handle.unlock_range(offset, len))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        self.mirror("advise", |handle| handle.advise(offset, len, advice))
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        let primary = self.primary.read_at_offset(offset, buffer);
+        let Some(secondary) = self.secondary.as_mut() else {
+            return primary;
+        };
+        let mut mirrored = vec![0; buffer.len()];
+        let secondary = secondary
+            .read_at_offset(offset, &mut mirrored)
+            .map(|read| mirrored[..read].to_vec());
+        let primary = primary.map(|read| buffer[..read].to_vec());
+        let read =
+            self.state
+                .compare("read_at_offset", self.primary.path(), primary, &secondary)?;
+        Ok(read.len())
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.mirror("write_to_offset", |handle| {
+            handle.write_to_offset(offset, buffer)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{FileSystem, FileSystemError, LocalFileSystem, MemoryFileSystem};
+    use crate::{MirrorCheckFileSystem, MirrorPolicy};
+    use std::io::Write;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_mirror_check_filesystem() {
+        let secondary = MemoryFileSystem::new();
+        let fs = MirrorCheckFileSystem::new(MemoryFileSystem::new(), secondary.clone())
+            .with_policy(MirrorPolicy::Log);
+        fs.create_directory("/data").unwrap();
+        let mut handle = fs.create_file("/data/table.dat").unwrap();
+        handle.write_all(b"rows").unwrap();
+        assert!(handle.is_mirrored());
+        drop(handle);
+        assert!(fs.divergences().is_empty());
+
+        // Only the secondary has the file, so the handle stops mirroring
+        secondary.write("/data/extra.dat", b"extra").unwrap();
+        assert_eq!(fs.list_directory("/data").unwrap(), ["table.dat"]);
+        assert!(matches!(
+            fs.open_file("/data/extra.dat"),
+            Err(FileSystemError::PathMissing)
+        ));
+        let divergences = fs.divergences();
+        assert_eq!(divergences.len(), 2);
+        assert_eq!(divergences[0].operation, "list_directory");
+        assert_eq!(divergences[1].primary, Err("PathMissing".to_string()));
+        assert_eq!(divergences[1].secondary, Ok(String::new()));
+
+        // Panicking is the default
+        let fs = MirrorCheckFileSystem::new(MemoryFileSystem::new(), secondary);
+        let diverged = std::panic::catch_unwind(|| fs.exists("/data"));
+        assert!(diverged.is_err());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_mirror_check_batch() {
+        use crate::BatchOperation;
+
+        let secondary = MemoryFileSystem::new();
+        secondary.write("/b.dat", b"extra").unwrap();
+        let fs = MirrorCheckFileSystem::new(MemoryFileSystem::new(), secondary)
+            .with_policy(MirrorPolicy::Log);
+        let results = fs.apply(&[
+            BatchOperation::write("/a.dat", b"rows"),
+            BatchOperation::create_directory("/b.dat"),
+        ]);
+        assert!(results.iter().all(Result::is_ok));
+        let divergences = fs.divergences();
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(divergences[0].operation, "apply");
+        assert_eq!(divergences[0].path, "/b.dat");
+
+        fs.clone_file("/a.dat", "/c.dat").unwrap();
+        assert_eq!(fs.divergences().len(), 1);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_memory_mirrors_local() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let root = std::env::temp_dir().join(format!(
+            "test-mirror-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        ));
+        let run = AtomicUsize::new(0);
+        crate::conformance::run(|| {
+            let root = root.join(run.fetch_add(1, Ordering::Relaxed).to_string());
+            std::fs::create_dir_all(&root).unwrap();
+            MirrorCheckFileSystem::new(MemoryFileSystem::new(), LocalFileSystem::new(root))
+        });
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::utility::{copy_file, join_segments, move_file, normalize_segments};
+use crate::{
+    CloneMethod, FileSystem, FileSystemError, FileSystemResult, FileSystemSpace, FileType,
+    OpenOptions, Permissions, SymlinkPolicy, VirtualFileHandle,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// Table of mounted filesystems keyed by their normalized prefix.
+pub(crate) type MountTable = Arc<RwLock<BTreeMap<String, Arc<dyn DynamicFileSystem>>>>;
+
+/// Mount Table `FileSystem`
+///
+/// Routes every operation to the filesystem mounted at the longest prefix of the requested path.
+/// Prefixes only match on whole segments, so `/data` never captures `/database`. Directories
+/// that only exist because something is mounted beneath them are synthesized.
+#[derive(Debug)]
+pub(crate) struct MountFileSystem {
+    mounts: MountTable,
+}
+
+impl MountFileSystem {
+    /// Create a new Mount `FileSystem` over a shared mount table.
+    pub(crate) fn new(mounts: MountTable) -> MountFileSystem {
+        MountFileSystem { mounts }
+    }
+
+    /// Resolve a path into its mounted filesystem and the path within it.
+    fn resolve(&self, path: &str) -> FileSystemResult<(Arc<dyn DynamicFileSystem>, String)> {
+        let segments = normalize_segments(path)?;
+        let mounts = self.mounts.read().expect("Poisoned Lock");
+        for depth in (0..=segments.len()).rev() {
+            if let Some(filesystem) = mounts.get(&join_segments(&segments[..depth])) {
+                return Ok((filesystem.clone(), join_segments(&segments[depth..])));
+            }
+        }
+        Err(FileSystemError::PathMissing)
+    }
+
+    /// Names of mount points directly beneath a path.
+    fn mount_children(&self, path: &str) -> FileSystemResult<BTreeSet<String>> {
+        let segments = normalize_segments(path)?;
+        let mounts = self.mounts.read().expect("Poisoned Lock");
+        let mut children = BTreeSet::new();
+        for prefix in mounts.keys() {
+            let prefix = normalize_segments(prefix)?;
+            if prefix.len() > segments.len() && prefix.starts_with(&segments) {
+                children.insert(prefix[segments.len()].to_string());
+            }
+        }
+        Ok(children)
+    }
+}
+
+impl FileSystem for MountFileSystem {
+    type FileHandle = VirtualFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        if !self.mount_children(path)?.is_empty() {
+            return Ok(true);
+        }
+        match self.resolve(path) {
+            Ok((filesystem, path)) => filesystem.exists(&path),
+            Err(FileSystemError::PathMissing) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        match self.resolve(path) {
+            Ok((filesystem, path)) => filesystem.is_file(&path),
+            Err(FileSystemError::PathMissing) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        if !self.mount_children(path)?.is_empty() {
+            return Ok(true);
+        }
+        match self.resolve(path) {
+            Ok((filesystem, path)) => filesystem.is_directory(&path),
+            Err(FileSystemError::PathMissing) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.filesize(&path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.create_directory(&path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.create_directory_all(&path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        let mut entries = self.mount_children(path)?;
+        match self.resolve(path) {
+            Ok((filesystem, path)) => match filesystem.list_directory(&path) {
+                Ok(listing) => entries.extend(listing),
+                Err(err) if entries.is_empty() => return Err(err),
+                Err(_) => {}
+            },
+            Err(err) if entries.is_empty() => return Err(err),
+            Err(_) => {}
+        }
+        Ok(entries.into_iter().collect())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        if !self.mount_children(path)?.is_empty() {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.remove_directory(&path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        if !self.mount_children(path)?.is_empty() {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.remove_directory_all(&path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let (filesystem, path) = self.resolve(path)?;
+        Ok(VirtualFileHandle(filesystem.create_file(&path)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let (filesystem, path) = self.resolve(path)?;
+        Ok(VirtualFileHandle(filesystem.open_file(&path)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.remove_file(&path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.file_type(&path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.create_symlink(target, &path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.read_link(&path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.permissions(&path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.modified(&path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.set_permissions(&path, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        let (filesystem, path) = self.resolve(path)?;
+        Ok(VirtualFileHandle(filesystem.open_with(&path, options)?))
+    }
+
+    /// Clones within a single mount are delegated to it, while clones across mounts are copied.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        let (source, src_path) = self.resolve(src)?;
+        let (destination, dst_path) = self.resolve(dst)?;
+        if Arc::ptr_eq(&source, &destination) {
+            return source.clone_file(&src_path, &dst_path);
+        }
+        copy_file(self, src, dst)?;
+        Ok(CloneMethod::Copy)
+    }
+
+    /// Renames within a mount are left to the mounted filesystem, while renames across mounts
+    /// copy the file and remove the original.
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        let (source, from_path) = self.resolve(from)?;
+        let (destination, to_path) = self.resolve(to)?;
+        if Arc::ptr_eq(&source, &destination) {
+            return source.rename(&from_path, &to_path);
+        }
+        move_file(self, from, to)
+    }
+
+    /// Reports the space of the filesystem mounted at the root.
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        let (filesystem, _) = self.resolve("/")?;
+        filesystem.space()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.read(&path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_to_string(&self, path: &str) -> FileSystemResult<String> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.read_to_string(&path)
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.write(&path, contents)
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn append(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        let (filesystem, path) = self.resolve(path)?;
+        filesystem.append(&path, contents)
+    }
+}
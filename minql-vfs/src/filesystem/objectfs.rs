@@ -0,0 +1,858 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::utility::{apply_operation, normalize_segments};
+use crate::{
+    BatchOperation, FileHandle, FileLockMode, FileSystem, FileSystemError, FileSystemResult,
+};
+use std::fmt::Debug;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+/// Metadata describing a single stored object.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ObjectMeta {
+    /// Full key of the object
+    pub key: String,
+    /// Size of the object in bytes
+    pub size: u64,
+}
+
+/// Result of listing an object store beneath a prefix.
+#[derive(Clone, Debug, Default)]
+pub struct ObjectListing {
+    /// Objects found directly beneath the prefix
+    pub objects: Vec<ObjectMeta>,
+    /// Common prefixes rolled up by the delimiter, each ending in the delimiter
+    pub prefixes: Vec<String>,
+}
+
+/// Precondition of a conditional [`ObjectStore::put_if`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PutCondition {
+    /// The object must not exist
+    Absent,
+    /// The object must exist with the given version, as returned by
+    /// [`ObjectStore::get_versioned`] or [`ObjectStore::put_if`]
+    Matches(String),
+}
+
+/// API an Object Store client must provide to back an [`ObjectStoreFileSystem`].
+///
+/// Keys are flat strings; the filesystem layer maps `/` separated paths onto them and treats
+/// common prefixes as directories.
+pub trait ObjectStore: Debug + Send + Sync + 'static {
+    /// Fetch the metadata of an object, or `None` if it doesn't exist.
+    fn head(&self, key: &str) -> FileSystemResult<Option<ObjectMeta>>;
+    /// Fetch up to `length` bytes of an object starting at `offset`.
+    fn get_range(&self, key: &str, offset: u64, length: u64) -> FileSystemResult<Vec<u8>>;
+    /// Store an object in a single request, replacing any existing object.
+    fn put(&self, key: &str, data: &[u8]) -> FileSystemResult<()>;
+    /// Delete an object. Deleting a missing object is not an error.
+    fn delete(&self, key: &str) -> FileSystemResult<()>;
+    /// List objects beneath `prefix`, rolling keys up to the next `delimiter` if provided.
+    fn list(&self, prefix: &str, delimiter: Option<char>) -> FileSystemResult<ObjectListing>;
+    /// Begin a multipart upload, returning its upload id.
+    fn create_multipart(&self, key: &str) -> FileSystemResult<String>;
+    /// Upload a single part of a multipart upload, returning its entity tag.
+    fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: &[u8],
+    ) -> FileSystemResult<String>;
+    /// Complete a multipart upload from its `(part_number, etag)` pairs.
+    fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[(u32, String)],
+    ) -> FileSystemResult<()>;
+    /// Abandon a multipart upload, discarding any uploaded parts.
+    fn abort_multipart(&self, key: &str, upload_id: &str) -> FileSystemResult<()>;
+    /// Fetch an object along with an opaque version usable in a [`PutCondition`], or `None` if
+    /// it doesn't exist.
+    fn get_versioned(&self, key: &str) -> FileSystemResult<Option<(Vec<u8>, String)>> {
+        Err(FileSystemError::UnsupportedOperation)
+    }
+    /// Store an object only if `condition` holds, returning its new version, or `None` if the
+    /// condition failed.
+    fn put_if(
+        &self,
+        key: &str,
+        data: &[u8],
+        condition: &PutCondition,
+    ) -> FileSystemResult<Option<String>> {
+        Err(FileSystemError::UnsupportedOperation)
+    }
+}
+
+/// Object Store `FileSystem`
+///
+/// Maps the [`FileSystem`] API onto a flat [`ObjectStore`]: listing is performed with `/` as a
+/// delimiter so common prefixes surface as directories, reads are served with ranged gets, and
+/// modified files are uploaded when flushed or synced, using a multipart upload once the
+/// contents exceed the configured part size. Empty directories are represented by a zero
+/// length marker object whose key ends in `/`.
+///
+/// Object stores don't support advisory locks, so only [`FileLockMode::Unlocked`] is accepted.
+#[derive(Debug)]
+pub struct ObjectStoreFileSystem {
+    store: Arc<dyn ObjectStore>,
+    prefix: String,
+    part_size: usize,
+}
+
+impl ObjectStoreFileSystem {
+    /// Default size of each multipart upload part.
+    pub const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+    /// Create a new Object Store `FileSystem` rooted at `prefix` within the store.
+    pub fn new<S: ObjectStore>(store: S, prefix: &str) -> ObjectStoreFileSystem {
+        Self::from_arc(Arc::new(store), prefix)
+    }
+
+    /// Create a new Object Store `FileSystem` over a shared store client.
+    pub fn from_arc(store: Arc<dyn ObjectStore>, prefix: &str) -> ObjectStoreFileSystem {
+        let prefix = prefix
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join("/");
+        ObjectStoreFileSystem {
+            store,
+            prefix,
+            part_size: Self::DEFAULT_PART_SIZE,
+        }
+    }
+
+    /// Set the size of multipart upload parts.
+    #[must_use]
+    pub fn with_part_size(mut self, part_size: usize) -> ObjectStoreFileSystem {
+        self.part_size = part_size.max(1);
+        self
+    }
+
+    /// Map a path to the key of the object holding it.
+    fn key(&self, path: &str) -> FileSystemResult<String> {
+        let segments = normalize_segments(path)?;
+        let mut key = self.prefix.clone();
+        for segment in segments {
+            if !key.is_empty() {
+                key.push('/');
+            }
+            key.push_str(segment);
+        }
+        Ok(key)
+    }
+
+    /// Map a path to the prefix under which its children are stored.
+    fn directory_prefix(&self, path: &str) -> FileSystemResult<String> {
+        let mut key = self.key(path)?;
+        if !key.is_empty() {
+            key.push('/');
+        }
+        Ok(key)
+    }
+
+    fn head_file(&self, path: &str) -> FileSystemResult<Option<ObjectMeta>> {
+        let key = self.key(path)?;
+        if key.len() == self.prefix.len() {
+            return Ok(None);
+        }
+        self.store.head(&key)
+    }
+
+    /// Split a batch into waves of operations which touch no common path, so that each wave can
+    /// be sent to the store at once while keeping the outcome of applying the batch in order.
+    fn waves(&self, batch: &[BatchOperation]) -> Vec<std::ops::Range<usize>> {
+        let mut waves = Vec::new();
+        let mut start = 0;
+        let mut keys: Vec<String> = Vec::new();
+        for (index, operation) in batch.iter().enumerate() {
+            // Paths failing to map fail on their own without touching the store.
+            let operation_keys = operation
+                .paths()
+                .filter_map(|path| self.key(path).ok())
+                .collect::<Vec<_>>();
+            let conflict = operation_keys
+                .iter()
+                .any(|key| keys.iter().any(|other| overlaps(key, other)));
+            if index > start && (conflict || index - start == PIPELINE_DEPTH) {
+                waves.push(start..index);
+                start = index;
+                keys.clear();
+            }
+            keys.extend(operation_keys);
+        }
+        if start < batch.len() {
+            waves.push(start..batch.len());
+        }
+        waves
+    }
+}
+
+/// Most operations of a batch sent to the store at once.
+const PIPELINE_DEPTH: usize = 16;
+
+/// Whether one of two keys names the other or an object below it.
+fn overlaps(first: &str, second: &str) -> bool {
+    let below = |key: &str, ancestor: &str| {
+        ancestor.is_empty()
+            || key
+                .strip_prefix(ancestor)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    };
+    below(first, second) || below(second, first)
+}
+
+impl FileSystem for ObjectStoreFileSystem {
+    type FileHandle = ObjectStoreFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        Ok(self.head_file(path)?.is_some() || self.is_directory(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        Ok(self.head_file(path)?.is_some())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        // The root exists even before anything is stored beneath the prefix
+        if normalize_segments(path)?.is_empty() {
+            return Ok(true);
+        }
+        let prefix = self.directory_prefix(path)?;
+        let listing = self.store.list(&prefix, Some('/'))?;
+        Ok(!listing.objects.is_empty() || !listing.prefixes.is_empty())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        match self.head_file(path)? {
+            Some(meta) => Ok(meta.size),
+            None if self.is_directory(path)? => Err(FileSystemError::InvalidOperation),
+            None => Err(FileSystemError::PathMissing),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        if self.exists(path)? {
+            return Err(FileSystemError::PathExists);
+        }
+        self.store.put(&self.directory_prefix(path)?, &[])
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        if self.is_file(path)? {
+            return Err(FileSystemError::PathExists);
+        }
+        if self.is_directory(path)? {
+            return Ok(());
+        }
+        self.store.put(&self.directory_prefix(path)?, &[])
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        if !self.is_directory(path)? {
+            return if self.is_file(path)? {
+                Err(FileSystemError::InvalidOperation)
+            } else {
+                Err(FileSystemError::PathMissing)
+            };
+        }
+        let prefix = self.directory_prefix(path)?;
+        let listing = self.store.list(&prefix, Some('/'))?;
+        let mut entries = Vec::new();
+        for object in &listing.objects {
+            let name = &object.key[prefix.len()..];
+            if !name.is_empty() {
+                entries.push(name.to_string());
+            }
+        }
+        for common in &listing.prefixes {
+            let name = common[prefix.len()..].trim_end_matches('/');
+            if !name.is_empty() {
+                entries.push(name.to_string());
+            }
+        }
+        entries.sort();
+        entries.dedup();
+        Ok(entries)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        if !self.list_directory(path)?.is_empty() {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        self.store.delete(&self.directory_prefix(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        if !self.is_directory(path)? {
+            return Err(FileSystemError::PathMissing);
+        }
+        let prefix = self.directory_prefix(path)?;
+        for object in self.store.list(&prefix, None)?.objects {
+            self.store.delete(&object.key)?;
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let key = self.key(path)?;
+        if key.is_empty() || self.exists(path)? {
+            return Err(FileSystemError::PathExists);
+        }
+        self.store.put(&key, &[])?;
+        Ok(ObjectStoreFileHandle {
+            path: path.to_string(),
+            key,
+            store: self.store.clone(),
+            part_size: self.part_size,
+            size: 0,
+            cursor: 0,
+            buffer: Some(Vec::new()),
+            dirty: false,
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let meta = match self.head_file(path)? {
+            Some(meta) => meta,
+            None if self.is_directory(path)? => return Err(FileSystemError::InvalidOperation),
+            None => return Err(FileSystemError::PathMissing),
+        };
+        Ok(ObjectStoreFileHandle {
+            path: path.to_string(),
+            key: meta.key,
+            store: self.store.clone(),
+            part_size: self.part_size,
+            size: meta.size,
+            cursor: 0,
+            buffer: None,
+            dirty: false,
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        match self.head_file(path)? {
+            Some(meta) => self.store.delete(&meta.key),
+            None => Err(FileSystemError::PathMissing),
+        }
+    }
+
+    /// Uploads the contents as a single object without opening a handle.
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        let key = self.key(path)?;
+        if key.len() == self.prefix.len() || self.is_directory(path)? {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        self.store.put(&key, contents)
+    }
+
+    /// Copies the object to its new key and deletes the original, so the rename isn't atomic.
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        let meta = match self.head_file(from)? {
+            Some(meta) => meta,
+            None if self.is_directory(from)? => return Err(FileSystemError::InvalidOperation),
+            None => return Err(FileSystemError::PathMissing),
+        };
+        let key = self.key(to)?;
+        if key.len() == self.prefix.len() || self.is_directory(to)? {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        if key == meta.key {
+            return Ok(());
+        }
+        let data = self.store.get_range(&meta.key, 0, meta.size)?;
+        self.store.put(&key, &data)?;
+        self.store.delete(&meta.key)
+    }
+
+    /// Sends operations touching unrelated paths to the store concurrently, up to 16 at a time,
+    /// while operations on a path wait for the earlier ones on it or its ancestors.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let mut results = Vec::with_capacity(batch.len());
+        for wave in self.waves(batch) {
+            if wave.len() == 1 {
+                results.push(apply_operation(self, &batch[wave.start]));
+                continue;
+            }
+            std::thread::scope(|scope| {
+                let pending = batch[wave]
+                    .iter()
+                    .map(|operation| scope.spawn(|| apply_operation(self, operation)))
+                    .collect::<Vec<_>>();
+                results.extend(
+                    pending
+                        .into_iter()
+                        .map(|handle| handle.join().expect("Batch Operation Panicked")),
+                );
+            });
+        }
+        results
+    }
+}
+
+/// Object Store File Handle
+///
+/// Reads are served with ranged gets until the handle is first modified, at which point the
+/// object is materialized in memory. Modifications are uploaded on `flush`, `sync_*`, or drop.
+pub struct ObjectStoreFileHandle {
+    path: String,
+    key: String,
+    store: Arc<dyn ObjectStore>,
+    part_size: usize,
+    size: u64,
+    cursor: u64,
+    buffer: Option<Vec<u8>>,
+    dirty: bool,
+}
+
+impl ObjectStoreFileHandle {
+    /// Materialize the full object contents for modification.
+    fn materialize(&mut self) -> FileSystemResult<&mut Vec<u8>> {
+        if self.buffer.is_none() {
+            let data = if self.size == 0 {
+                Vec::new()
+            } else {
+                self.store.get_range(&self.key, 0, self.size)?
+            };
+            self.buffer = Some(data);
+        }
+        Ok(self.buffer.get_or_insert_with(Vec::new))
+    }
+
+    /// Upload the materialized contents if they have been modified.
+    fn upload(&mut self) -> FileSystemResult<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let data = self.buffer.as_deref().unwrap_or_default();
+        if data.len() <= self.part_size {
+            self.store.put(&self.key, data)?;
+        } else {
+            let upload_id = self.store.create_multipart(&self.key)?;
+            let mut parts = Vec::new();
+            for (index, chunk) in data.chunks(self.part_size).enumerate() {
+                let part_number = u32::try_from(index + 1).map_err(FileSystemError::wrap_error)?;
+                match self
+                    .store
+                    .upload_part(&self.key, &upload_id, part_number, chunk)
+                {
+                    Ok(etag) => parts.push((part_number, etag)),
+                    Err(err) => {
+                        let _ = self.store.abort_multipart(&self.key, &upload_id);
+                        return Err(err);
+                    }
+                }
+            }
+            self.store
+                .complete_multipart(&self.key, &upload_id, &parts)?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ObjectStoreFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ObjectStoreFileHandle({})", self.key)
+    }
+}
+
+impl Drop for ObjectStoreFileHandle {
+    fn drop(&mut self) {
+        if let Err(err) = self.upload() {
+            tracing::warn!("Unable to upload {} on drop: {err}", self.key);
+        }
+    }
+}
+
+impl Read for ObjectStoreFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = std::cmp::min(buf.len() as u64, self.size.saturating_sub(self.cursor));
+        if len == 0 {
+            return Ok(0);
+        }
+        let len = usize::try_from(len).map_err(std::io::Error::other)?;
+        if let Some(buffer) = &self.buffer {
+            let start = usize::try_from(self.cursor).map_err(std::io::Error::other)?;
+            buf[..len].copy_from_slice(&buffer[start..start + len]);
+        } else {
+            let data = self
+                .store
+                .get_range(&self.key, self.cursor, len as u64)
+                .map_err(std::io::Error::from)?;
+            let len = std::cmp::min(len, data.len());
+            buf[..len].copy_from_slice(&data[..len]);
+        }
+        self.cursor += len as u64;
+        Ok(len)
+    }
+}
+
+impl Write for ObjectStoreFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let start = usize::try_from(self.cursor).map_err(std::io::Error::other)?;
+        let buffer = self.materialize().map_err(std::io::Error::from)?;
+        if start + buf.len() > buffer.len() {
+            buffer.resize(start + buf.len(), 0);
+        }
+        buffer[start..start + buf.len()].copy_from_slice(buf);
+        self.size = buffer.len() as u64;
+        self.cursor += buf.len() as u64;
+        self.dirty = true;
+        Ok(buf.len())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.upload().map_err(std::io::Error::from)
+    }
+}
+
+impl Seek for ObjectStoreFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.cursor.checked_add_signed(offset),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        self.cursor = position;
+        Ok(position)
+    }
+}
+
+impl FileHandle for ObjectStoreFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        Ok(self.size)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        let new_len = usize::try_from(new_size).map_err(FileSystemError::wrap_error)?;
+        self.materialize()?.resize(new_len, 0);
+        self.size = new_size;
+        self.dirty = true;
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.upload()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.upload()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        Ok(FileLockMode::Unlocked)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        match mode {
+            FileLockMode::Unlocked => Ok(()),
+            FileLockMode::Shared | FileLockMode::Exclusive => {
+                Err(FileSystemError::UnsupportedOperation)
+            }
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.set_lock_status(mode)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::{ObjectListing, ObjectMeta, ObjectStore, PutCondition};
+    use crate::{
+        ContentHasher, FileHandle, FileSystem, FileSystemError, FileSystemResult, HashAlgorithm,
+        ObjectStoreFileSystem,
+    };
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::sync::{Arc, Mutex};
+
+    type Parts = BTreeMap<u32, Vec<u8>>;
+
+    /// In-memory Object Store used to exercise the object store adapter.
+    #[derive(Clone, Debug, Default)]
+    pub(crate) struct TestObjectStore {
+        pub(crate) objects: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+        pub(crate) uploads: Arc<Mutex<BTreeMap<String, Parts>>>,
+    }
+
+    impl ObjectStore for TestObjectStore {
+        fn head(&self, key: &str) -> FileSystemResult<Option<ObjectMeta>> {
+            let objects = self.objects.lock().unwrap();
+            Ok(objects.get(key).map(|data| ObjectMeta {
+                key: key.to_string(),
+                size: data.len() as u64,
+            }))
+        }
+
+        fn get_range(&self, key: &str, offset: u64, length: u64) -> FileSystemResult<Vec<u8>> {
+            let objects = self.objects.lock().unwrap();
+            let data = objects.get(key).ok_or(FileSystemError::PathMissing)?;
+            let start = usize::try_from(offset).unwrap().min(data.len());
+            let end = (start + usize::try_from(length).unwrap()).min(data.len());
+            Ok(data[start..end].to_vec())
+        }
+
+        fn put(&self, key: &str, data: &[u8]) -> FileSystemResult<()> {
+            let mut objects = self.objects.lock().unwrap();
+            objects.insert(key.to_string(), data.to_vec());
+            Ok(())
+        }
+
+        fn delete(&self, key: &str) -> FileSystemResult<()> {
+            self.objects.lock().unwrap().remove(key);
+            Ok(())
+        }
+
+        fn list(&self, prefix: &str, delimiter: Option<char>) -> FileSystemResult<ObjectListing> {
+            let objects = self.objects.lock().unwrap();
+            let mut listing = ObjectListing::default();
+            let mut prefixes = BTreeSet::new();
+            for (key, data) in objects.range(prefix.to_string()..) {
+                let Some(rest) = key.strip_prefix(prefix) else {
+                    break;
+                };
+                match delimiter.and_then(|d| rest.find(d).map(|i| (d, i))) {
+                    Some((d, i)) => {
+                        prefixes.insert(format!("{prefix}{}{d}", &rest[..i]));
+                    }
+                    None => listing.objects.push(ObjectMeta {
+                        key: key.clone(),
+                        size: data.len() as u64,
+                    }),
+                }
+            }
+            listing.prefixes = prefixes.into_iter().collect();
+            Ok(listing)
+        }
+
+        fn create_multipart(&self, key: &str) -> FileSystemResult<String> {
+            let id = format!("upload-{key}");
+            self.uploads
+                .lock()
+                .unwrap()
+                .insert(id.clone(), BTreeMap::new());
+            Ok(id)
+        }
+
+        fn upload_part(
+            &self,
+            _key: &str,
+            upload_id: &str,
+            part_number: u32,
+            data: &[u8],
+        ) -> FileSystemResult<String> {
+            let mut uploads = self.uploads.lock().unwrap();
+            let parts = uploads
+                .get_mut(upload_id)
+                .ok_or(FileSystemError::PathMissing)?;
+            parts.insert(part_number, data.to_vec());
+            Ok(format!("etag-{part_number}"))
+        }
+
+        fn complete_multipart(
+            &self,
+            key: &str,
+            upload_id: &str,
+            parts: &[(u32, String)],
+        ) -> FileSystemResult<()> {
+            let mut uploads = self.uploads.lock().unwrap();
+            let uploaded = uploads
+                .remove(upload_id)
+                .ok_or(FileSystemError::PathMissing)?;
+            let mut data = Vec::new();
+            for (number, _) in parts {
+                data.extend_from_slice(&uploaded[number]);
+            }
+            self.objects.lock().unwrap().insert(key.to_string(), data);
+            Ok(())
+        }
+
+        fn abort_multipart(&self, _key: &str, upload_id: &str) -> FileSystemResult<()> {
+            self.uploads.lock().unwrap().remove(upload_id);
+            Ok(())
+        }
+
+        fn get_versioned(&self, key: &str) -> FileSystemResult<Option<(Vec<u8>, String)>> {
+            let objects = self.objects.lock().unwrap();
+            Ok(objects
+                .get(key)
+                .map(|data| (data.clone(), Self::version(data))))
+        }
+
+        fn put_if(
+            &self,
+            key: &str,
+            data: &[u8],
+            condition: &PutCondition,
+        ) -> FileSystemResult<Option<String>> {
+            let mut objects = self.objects.lock().unwrap();
+            let holds = match (condition, objects.get(key)) {
+                (PutCondition::Absent, current) => current.is_none(),
+                (PutCondition::Matches(version), Some(current)) => {
+                    *version == Self::version(current)
+                }
+                (PutCondition::Matches(_), None) => false,
+            };
+            if !holds {
+                return Ok(None);
+            }
+            objects.insert(key.to_string(), data.to_vec());
+            Ok(Some(Self::version(data)))
+        }
+    }
+
+    impl TestObjectStore {
+        fn version(data: &[u8]) -> String {
+            let mut hasher = ContentHasher::new(HashAlgorithm::Sha256);
+            hasher.update(data);
+            hasher.finalize().to_string()
+        }
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_object_store_filesystem() {
+        let store = TestObjectStore::default();
+        let fs = ObjectStoreFileSystem::new(store.clone(), "/bucket-prefix/").with_part_size(4);
+        {
+            // Create and write a file large enough for a multipart upload
+            let mut file = fs.create_file("/tables/users.dat").unwrap();
+            file.write_all(b"Hello, World!").unwrap();
+            assert_eq!(file.get_size().unwrap(), 13);
+            file.flush().unwrap();
+        }
+        assert_eq!(
+            store.objects.lock().unwrap()["bucket-prefix/tables/users.dat"],
+            b"Hello, World!"
+        );
+        assert!(store.uploads.lock().unwrap().is_empty());
+
+        // Prefixes surface as directories
+        assert!(fs.is_directory("/tables").unwrap());
+        assert!(fs.is_file("/tables/users.dat").unwrap());
+        assert_eq!(fs.list_directory("/").unwrap(), vec!["tables"]);
+        assert_eq!(fs.list_directory("/tables").unwrap(), vec!["users.dat"]);
+        fs.create_directory("/empty").unwrap();
+        assert!(fs.is_directory("/empty").unwrap());
+        assert!(fs.list_directory("/empty").unwrap().is_empty());
+        fs.remove_directory("/empty").unwrap();
+        assert!(!fs.exists("/empty").unwrap());
+
+        {
+            // Ranged reads, then modification and upload on drop
+            let mut file = fs.open_file("/tables/users.dat").unwrap();
+            let mut buf = [0; 5];
+            file.seek(SeekFrom::Start(7)).unwrap();
+            file.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"World");
+            file.seek(SeekFrom::Start(0)).unwrap();
+            file.write_all(b"Jello").unwrap();
+            file.set_size(5).unwrap();
+        }
+        let mut buf = Vec::new();
+        let mut file = fs.open_file("/tables/users.dat").unwrap();
+        file.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"Jello");
+
+        assert!(matches!(
+            file.set_lock_status(crate::FileLockMode::Exclusive),
+            Err(FileSystemError::UnsupportedOperation)
+        ));
+        fs.remove_directory_all("/tables").unwrap();
+        assert!(!fs.exists("/tables/users.dat").unwrap());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_object_store_batch() {
+        use crate::BatchOperation;
+        let store = TestObjectStore::default();
+        let fs = ObjectStoreFileSystem::new(store.clone(), "/bucket-prefix/");
+        let mut batch = (0..20)
+            .map(|index| BatchOperation::write(&format!("/part-{index}"), b"rows"))
+            .collect::<Vec<_>>();
+        batch.push(BatchOperation::rename("/part-0", "/tables/part-0"));
+        batch.push(BatchOperation::write("/tables", b"file"));
+        batch.push(BatchOperation::remove_file("/part-1"));
+        // Writes to distinct files share waves, while operations on a path or its descendants
+        // wait for the earlier ones.
+        assert_eq!(fs.waves(&batch), vec![0..16, 16..21, 21..23]);
+        let results = fs.apply(&batch);
+        assert!(results[..21].iter().all(Result::is_ok));
+        assert!(matches!(
+            results[21],
+            Err(FileSystemError::InvalidOperation)
+        ));
+        assert!(results[22].is_ok());
+        assert_eq!(fs.read("/tables/part-0").unwrap(), b"rows");
+        assert!(!fs.exists("/part-0").unwrap());
+        assert!(!fs.exists("/part-1").unwrap());
+        assert_eq!(store.objects.lock().unwrap().len(), 19);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_object_store_conformance() {
+        crate::conformance::run(|| {
+            ObjectStoreFileSystem::new(TestObjectStore::default(), "/bucket-prefix/")
+        });
+    }
+}
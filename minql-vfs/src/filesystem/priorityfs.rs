@@ -0,0 +1,718 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::{
+    Advice, BatchOperation, CloneMethod, ContentDigest, FileHandle, FileLockMode, FileSystem,
+    FileSystemResult, FileSystemSpace, FileType, HashAlgorithm, OpenOptions, Permissions,
+    SymlinkPolicy,
+};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long background operations wait after the last foreground operation by default.
+const DEFAULT_GRACE: Duration = Duration::from_millis(2);
+
+/// Longest a background operation waits for foreground traffic by default.
+const DEFAULT_MAX_WAIT: Duration = Duration::from_millis(100);
+
+/// Priority class of the operations issued through a [`PriorityView`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum IoPriority {
+    /// Latency sensitive work, such as queries, which never waits
+    Foreground,
+    /// Bulk work, such as compaction and sync, which yields to foreground work
+    Background,
+}
+
+impl IoPriority {
+    fn index(self) -> usize {
+        match self {
+            IoPriority::Foreground => 0,
+            IoPriority::Background => 1,
+        }
+    }
+}
+
+/// Operations and bytes of a single priority class, read at one point in time.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct PriorityStats {
+    operations: u64,
+    bytes: u64,
+    delayed: u64,
+    waited: Duration,
+    elapsed: Duration,
+}
+
+impl PriorityStats {
+    /// Operations performed
+    #[must_use]
+    pub fn operations(&self) -> u64 {
+        self.operations
+    }
+    /// Bytes read and written
+    #[must_use]
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+    /// Operations which waited for higher priority work
+    #[must_use]
+    pub fn delayed(&self) -> u64 {
+        self.delayed
+    }
+    /// Total time operations waited for higher priority work
+    #[must_use]
+    pub fn waited(&self) -> Duration {
+        self.waited
+    }
+    /// Bytes read and written per second since the filesystem was created
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+#[derive(Debug)]
+struct SchedulerState {
+    grace: Duration,
+    max_wait: Duration,
+    foreground: usize,
+    last_foreground: Option<Instant>,
+    stats: [PriorityStats; 2],
+}
+
+/// Admission of operations shared by every view and handle of a [`PriorityFileSystem`].
+#[derive(Debug)]
+struct Scheduler {
+    created: Instant,
+    state: Mutex<SchedulerState>,
+    idle: Condvar,
+}
+
+impl Scheduler {
+    /// Run an operation of a class, holding background work back while foreground work is in
+    /// flight or finished within the grace period.
+    fn run<T>(&self, priority: IoPriority, operation: impl FnOnce() -> T) -> T {
+        match priority {
+            IoPriority::Foreground => {
+                self.state.lock().expect("Poisoned Lock").foreground += 1;
+                let _guard = ForegroundGuard(self);
+                self.count(priority, 0, 1);
+                operation()
+            }
+            IoPriority::Background => {
+                self.admit_background();
+                self.count(priority, 0, 1);
+                operation()
+            }
+        }
+    }
+
+    /// Wait until background work may proceed, or it has waited as long as it may.
+    fn admit_background(&self) {
+        let start = Instant::now();
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        let deadline = start + state.max_wait;
+        let mut delayed = false;
+        loop {
+            let now = Instant::now();
+            let quiet = state.last_foreground.map_or(Duration::ZERO, |last| {
+                (last + state.grace).saturating_duration_since(now)
+            });
+            if (state.foreground == 0 && quiet.is_zero()) || now >= deadline {
+                break;
+            }
+            let wait = if state.foreground > 0 {
+                deadline - now
+            } else {
+                quiet.min(deadline - now)
+            };
+            delayed = true;
+            state = self
+                .idle
+                .wait_timeout(state, wait)
+                .expect("Poisoned Lock")
+                .0;
+        }
+        if delayed {
+            let waited = start.elapsed();
+            tracing::trace!(?waited, "Background I/O yielded to foreground I/O");
+            let class = &mut state.stats[IoPriority::Background.index()];
+            class.delayed += 1;
+            class.waited += waited;
+        }
+    }
+
+    /// Count operations of a class and the bytes they transferred.
+    fn count(&self, priority: IoPriority, bytes: usize, operations: u64) {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        let class = &mut state.stats[priority.index()];
+        class.operations += operations;
+        class.bytes += bytes as u64;
+    }
+}
+
+/// Marks foreground work finished when dropped, waking background work once it's idle.
+struct ForegroundGuard<'a>(&'a Scheduler);
+
+impl Drop for ForegroundGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().expect("Poisoned Lock");
+        state.foreground -= 1;
+        state.last_foreground = Some(Instant::now());
+        drop(state);
+        self.0.idle.notify_all();
+    }
+}
+
+/// I/O Prioritizing `FileSystem` Wrapper
+///
+/// Hands out views of a filesystem tagged with an [`IoPriority`], so background jobs such as
+/// compaction and sync yield to foreground reads and writes. A background operation waits
+/// while any foreground operation is in flight, and for a short grace period after the last
+/// one finishes so bursts of foreground work aren't interleaved with background work. It
+/// proceeds regardless once it has waited the maximum wait, so background work is slowed but
+/// never starved. Foreground operations never wait.
+///
+/// Every operation of a view and the handles it opens is scheduled, and counted along with the
+/// bytes it read or wrote in the [`PriorityStats`] of its class.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, IoPriority, MemoryFileSystem, PriorityFileSystem};
+///
+/// let fs = PriorityFileSystem::new(MemoryFileSystem::new());
+/// let queries = fs.foreground();
+/// let compaction = fs.background();
+///
+/// compaction.write("/segment-1.dat", b"merged").unwrap();
+/// assert_eq!(queries.read("/segment-1.dat").unwrap(), b"merged");
+/// assert_eq!(fs.stats(IoPriority::Foreground).bytes(), 6);
+/// assert_eq!(fs.stats(IoPriority::Background).operations(), 1);
+/// ```
+#[derive(Debug)]
+pub struct PriorityFileSystem {
+    inner: Arc<dyn DynamicFileSystem>,
+    scheduler: Arc<Scheduler>,
+}
+
+impl PriorityFileSystem {
+    /// Create a new Prioritizing `FileSystem` around `filesystem`.
+    pub fn new<F: FileSystem>(filesystem: F) -> PriorityFileSystem {
+        PriorityFileSystem {
+            inner: Arc::new(filesystem),
+            scheduler: Arc::new(Scheduler {
+                created: Instant::now(),
+                state: Mutex::new(SchedulerState {
+                    grace: DEFAULT_GRACE,
+                    max_wait: DEFAULT_MAX_WAIT,
+                    foreground: 0,
+                    last_foreground: None,
+                    stats: [PriorityStats::default(); 2],
+                }),
+                idle: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Set how long background operations wait after the last foreground operation.
+    #[must_use]
+    pub fn with_grace(self, grace: Duration) -> PriorityFileSystem {
+        self.scheduler.state.lock().expect("Poisoned Lock").grace = grace;
+        self
+    }
+
+    /// Set the longest a background operation waits for foreground work.
+    #[must_use]
+    pub fn with_max_wait(self, max_wait: Duration) -> PriorityFileSystem {
+        self.scheduler.state.lock().expect("Poisoned Lock").max_wait = max_wait;
+        self
+    }
+
+    /// Get a view issuing operations with a priority.
+    #[must_use]
+    pub fn view(&self, priority: IoPriority) -> PriorityView {
+        PriorityView {
+            inner: self.inner.clone(),
+            scheduler: self.scheduler.clone(),
+            priority,
+        }
+    }
+
+    /// Get a view issuing foreground operations.
+    #[must_use]
+    pub fn foreground(&self) -> PriorityView {
+        self.view(IoPriority::Foreground)
+    }
+
+    /// Get a view issuing background operations.
+    #[must_use]
+    pub fn background(&self) -> PriorityView {
+        self.view(IoPriority::Background)
+    }
+
+    /// Get the operations and bytes of a priority class so far.
+    #[must_use]
+    pub fn stats(&self, priority: IoPriority) -> PriorityStats {
+        let state = self.scheduler.state.lock().expect("Poisoned Lock");
+        PriorityStats {
+            elapsed: self.scheduler.created.elapsed(),
+            ..state.stats[priority.index()]
+        }
+    }
+}
+
+/// View of a [`PriorityFileSystem`] issuing every operation with one [`IoPriority`].
+#[derive(Clone, Debug)]
+pub struct PriorityView {
+    inner: Arc<dyn DynamicFileSystem>,
+    scheduler: Arc<Scheduler>,
+    priority: IoPriority,
+}
+
+impl PriorityView {
+    /// Priority of the operations issued through this view.
+    #[must_use]
+    pub fn priority(&self) -> IoPriority {
+        self.priority
+    }
+
+    /// Schedule an operation of this view's class.
+    fn run<T>(&self, operation: impl FnOnce(&dyn DynamicFileSystem) -> T) -> T {
+        self.scheduler
+            .run(self.priority, || operation(self.inner.as_ref()))
+    }
+
+    fn wrap(
+        &self,
+        handle: FileSystemResult<Box<dyn FileHandle>>,
+    ) -> FileSystemResult<PriorityFileHandle> {
+        Ok(PriorityFileHandle {
+            inner: handle?,
+            scheduler: self.scheduler.clone(),
+            priority: self.priority,
+        })
+    }
+}
+
+impl FileSystem for PriorityView {
+    type FileHandle = PriorityFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        self.run(|fs| fs.exists(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        self.run(|fs| fs.is_file(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        self.run(|fs| fs.is_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.run(|fs| fs.filesize(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.run(|fs| fs.create_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.run(|fs| fs.create_directory_all(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        self.run(|fs| fs.list_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.run(|fs| fs.remove_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.run(|fs| fs.remove_directory_all(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.wrap(self.run(|fs| fs.create_file(path)))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.wrap(self.run(|fs| fs.open_file(path)))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        self.run(|fs| fs.remove_file(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.run(|fs| fs.file_type(path, policy))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        self.run(|fs| fs.create_symlink(target, path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.run(|fs| fs.read_link(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.run(|fs| fs.permissions(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.run(|fs| fs.modified(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.run(|fs| fs.set_permissions(path, permissions))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        self.wrap(self.run(|fs| fs.open_with(path, options)))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        self.inner.space()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        self.run(|fs| fs.clone_file(src, dst))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        self.run(|fs| fs.rename(from, to))
+    }
+
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        self.run(|fs| fs.apply(batch))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn hash_file(&self, path: &str, algorithm: HashAlgorithm) -> FileSystemResult<ContentDigest> {
+        self.run(|fs| fs.hash_file(path, algorithm))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        let contents = self.run(|fs| fs.read(path))?;
+        self.scheduler.count(self.priority, contents.len(), 0);
+        Ok(contents)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_to_string(&self, path: &str) -> FileSystemResult<String> {
+        let contents = self.run(|fs| fs.read_to_string(path))?;
+        self.scheduler.count(self.priority, contents.len(), 0);
+        Ok(contents)
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        self.run(|fs| fs.write(path, contents))?;
+        self.scheduler.count(self.priority, contents.len(), 0);
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn append(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        self.run(|fs| fs.append(path, contents))?;
+        self.scheduler.count(self.priority, contents.len(), 0);
+        Ok(())
+    }
+}
+
+/// Prioritized File Handle
+///
+/// Schedules its reads, writes, syncs and resizes with the priority of the [`PriorityView`]
+/// which opened it.
+pub struct PriorityFileHandle {
+    inner: Box<dyn FileHandle>,
+    scheduler: Arc<Scheduler>,
+    priority: IoPriority,
+}
+
+impl PriorityFileHandle {
+    /// Priority of the operations issued through this handle.
+    #[must_use]
+    pub fn priority(&self) -> IoPriority {
+        self.priority
+    }
+
+    /// Schedule an operation transferring the bytes it returns.
+    fn transfer<E>(
+        &mut self,
+        operation: impl FnOnce(&mut dyn FileHandle) -> Result<usize, E>,
+    ) -> Result<usize, E> {
+        let inner = self.inner.as_mut();
+        let transferred = self.scheduler.run(self.priority, || operation(inner))?;
+        self.scheduler.count(self.priority, transferred, 0);
+        Ok(transferred)
+    }
+
+    /// Schedule an operation without a transfer.
+    fn run<T>(&mut self, operation: impl FnOnce(&mut dyn FileHandle) -> T) -> T {
+        let inner = self.inner.as_mut();
+        self.scheduler.run(self.priority, || operation(inner))
+    }
+}
+
+impl std::fmt::Debug for PriorityFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.inner.as_ref(), f)
+    }
+}
+
+impl Read for PriorityFileHandle {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.transfer(|handle| handle.read(buf))
+    }
+
+    #[tracing::instrument(level = "trace", skip(bufs))]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        self.transfer(|handle| handle.read_vectored(bufs))
+    }
+}
+
+impl Write for PriorityFileHandle {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.transfer(|handle| handle.write(buf))
+    }
+
+    #[tracing::instrument(level = "trace", skip(bufs))]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        self.transfer(|handle| handle.write_vectored(bufs))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.run(Write::flush)
+    }
+}
+
+impl Seek for PriorityFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl FileHandle for PriorityFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        self.inner.path()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        self.inner.get_size()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.run(|handle| handle.set_size(new_size))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.run(|handle| handle.allocate(len))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.run(FileHandle::sync_all)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.run(FileHandle::sync_data)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        self.inner.get_lock_status()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.try_set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        self.inner.alignment()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.lock_range(offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.inner.unlock_range(offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        self.inner.advise(offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        self.transfer(|handle| handle.read_at_offset(offset, buffer))
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.transfer(|handle| handle.write_to_offset(offset, buffer))
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffers))]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        self.transfer(|handle| handle.read_at_vectored(offset, buffers))
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffers))]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        self.transfer(|handle| handle.write_at_vectored(offset, buffers))
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        let mapping = self.run(|handle| handle.map_readonly(offset, len))?;
+        self.scheduler.count(self.priority, mapping.len(), 0);
+        Ok(mapping)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{FileHandle, FileSystem, IoPriority, MemoryFileSystem, PriorityFileSystem};
+    use std::io::Write;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_background_yields() {
+        let fs = PriorityFileSystem::new(MemoryFileSystem::new())
+            .with_grace(Duration::from_millis(200))
+            .with_max_wait(Duration::from_secs(10));
+        let foreground = fs.foreground();
+        let background = fs.background();
+        assert_eq!(background.priority(), IoPriority::Background);
+
+        // Background work waits out the grace period after foreground work
+        let mut file = foreground.create_file("/table.dat").unwrap();
+        file.write_all(b"rows").unwrap();
+        let start = Instant::now();
+        background.write("/segment.dat", b"merged").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        let stats = fs.stats(IoPriority::Background);
+        assert_eq!(stats.operations(), 1);
+        assert_eq!(stats.bytes(), 6);
+        assert_eq!(stats.delayed(), 1);
+        assert!(stats.waited() >= Duration::from_millis(150));
+
+        // ...and while foreground work is in flight, waking when it finishes
+        std::thread::scope(|scope| {
+            let background = fs.background();
+            let blocked = scope.spawn(move || {
+                let start = Instant::now();
+                background.read("/segment.dat").unwrap();
+                start.elapsed()
+            });
+            for _ in 0..10 {
+                file.write_to_offset(0, b"ROWS").unwrap();
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            assert!(blocked.join().unwrap() >= Duration::from_millis(450));
+        });
+
+        // Foreground work never waits
+        let start = Instant::now();
+        background.write("/other.dat", b"other").unwrap();
+        assert_eq!(foreground.read("/other.dat").unwrap(), b"other");
+        assert!(start.elapsed() < Duration::from_millis(150));
+        let stats = fs.stats(IoPriority::Foreground);
+        assert_eq!(stats.delayed(), 0);
+        assert_eq!(stats.bytes(), 4 + 40 + 5);
+        assert!(stats.bytes_per_second() > 0.0);
+
+        // Background work isn't starved past the maximum wait
+        let fs = PriorityFileSystem::new(MemoryFileSystem::new())
+            .with_grace(Duration::from_secs(10))
+            .with_max_wait(Duration::from_millis(100));
+        fs.foreground().write("/table.dat", b"rows").unwrap();
+        let start = Instant::now();
+        fs.background().write("/segment.dat", b"merged").unwrap();
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(100) && elapsed < Duration::from_secs(5));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_priority_conformance() {
+        crate::conformance::run(|| PriorityFileSystem::new(MemoryFileSystem::new()).foreground());
+        crate::conformance::run(|| {
+            PriorityFileSystem::new(MemoryFileSystem::new())
+                .with_grace(Duration::ZERO)
+                .background()
+        });
+    }
+}
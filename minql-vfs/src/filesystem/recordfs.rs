@@ -0,0 +1,1596 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Operation recorded in a trace by a [`RecordFileSystem`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum TraceOperation {
+    /// [`FileSystem::exists`]
+    Exists,
+    /// [`FileSystem::is_file`]
+    IsFile,
+    /// [`FileSystem::is_directory`]
+    IsDirectory,
+    /// [`FileSystem::filesize`]
+    Filesize,
+    /// [`FileSystem::create_directory`]
+    CreateDirectory,
+    /// [`FileSystem::create_directory_all`]
+    CreateDirectoryAll,
+    /// [`FileSystem::list_directory`]
+    ListDirectory,
+    /// [`FileSystem::remove_directory`]
+    RemoveDirectory,
+    /// [`FileSystem::remove_directory_all`]
+    RemoveDirectoryAll,
+    /// [`FileSystem::create_file`]
+    CreateFile,
+    /// [`FileSystem::open_file`]
+    OpenFile,
+    /// [`FileSystem::open_with`]
+    OpenWith,
+    /// [`FileSystem::remove_file`]
+    RemoveFile,
+    /// [`FileSystem::file_type`]
+    FileType,
+    /// [`FileSystem::create_symlink`]
+    CreateSymlink,
+    /// [`FileSystem::read_link`]
+    ReadLink,
+    /// [`FileSystem::permissions`]
+    Permissions,
+    /// [`FileSystem::set_permissions`]
+    SetPermissions,
+    /// [`FileSystem::space`]
+    Space,
+    /// [`FileSystem::modified`]
+    Modified,
+    /// [`FileSystem::rename`]
+    Rename,
+    /// [`FileSystem::clone_file`]
+    CloneFile,
+    /// Single operation of a [`FileSystem::apply`] batch
+    Apply,
+    /// [`Read::read`] on a handle
+    Read,
+    /// [`Write::write`] on a handle
+    Write,
+    /// [`Write::flush`] on a handle
+    Flush,
+    /// [`Seek::seek`] on a handle
+    Seek,
+    /// [`FileHandle::get_size`]
+    GetSize,
+    /// [`FileHandle::set_size`]
+    SetSize,
+    /// [`FileHandle::allocate`]
+    Allocate,
+    /// [`FileHandle::sync_all`]
+    SyncAll,
+    /// [`FileHandle::sync_data`]
+    SyncData,
+    /// [`FileHandle::get_lock_status`]
+    GetLockStatus,
+    /// [`FileHandle::set_lock_status`]
+    SetLockStatus,
+    /// [`FileHandle::try_set_lock_status`]
+    TrySetLockStatus,
+    /// [`FileHandle::lock_range`]
+    LockRange,
+    /// [`FileHandle::unlock_range`]
+    UnlockRange,
+    /// [`FileHandle::advise`]
+    Advise,
+    /// [`FileHandle::read_at_offset`]
+    ReadAt,
+    /// [`FileHandle::write_to_offset`]
+    WriteAt,
+    /// Dropping a handle
+    Close,
+}
+
+impl TraceOperation {
+    /// Every recorded operation.
+    pub const ALL: [TraceOperation; 41] = [
+        TraceOperation::Exists,
+        TraceOperation::IsFile,
+        TraceOperation::IsDirectory,
+        TraceOperation::Filesize,
+        TraceOperation::CreateDirectory,
+        TraceOperation::CreateDirectoryAll,
+        TraceOperation::ListDirectory,
+        TraceOperation::RemoveDirectory,
+        TraceOperation::RemoveDirectoryAll,
+        TraceOperation::CreateFile,
+        TraceOperation::OpenFile,
+        TraceOperation::OpenWith,
+        TraceOperation::RemoveFile,
+        TraceOperation::FileType,
+        TraceOperation::CreateSymlink,
+        TraceOperation::ReadLink,
+        TraceOperation::Permissions,
+        TraceOperation::SetPermissions,
+        TraceOperation::Space,
+        TraceOperation::Modified,
+        TraceOperation::Rename,
+        TraceOperation::CloneFile,
+        TraceOperation::Apply,
+        TraceOperation::Read,
+        TraceOperation::Write,
+        TraceOperation::Flush,
+        TraceOperation::Seek,
+        TraceOperation::GetSize,
+        TraceOperation::SetSize,
+        TraceOperation::Allocate,
+        TraceOperation::SyncAll,
+        TraceOperation::SyncData,
+        TraceOperation::GetLockStatus,
+        TraceOperation::SetLockStatus,
+        TraceOperation::TrySetLockStatus,
+        TraceOperation::LockRange,
+        TraceOperation::UnlockRange,
+        TraceOperation::Advise,
+        TraceOperation::ReadAt,
+        TraceOperation::WriteAt,
+        TraceOperation::Close,
+    ];
+
+    /// Check if replaying this operation must reproduce the recorded value, rather than only
+    /// whether it succeeded.
+    ///
+    /// Handle ids, free space, modification times and clone methods are specific to the recorded
+    /// backend.
+    #[must_use]
+    pub fn compares_value(self) -> bool {
+        !matches!(
+            self,
+            TraceOperation::CreateFile
+                | TraceOperation::OpenFile
+                | TraceOperation::OpenWith
+                | TraceOperation::Space
+                | TraceOperation::Modified
+                | TraceOperation::CloneFile
+        )
+    }
+
+    fn from_name(name: &str) -> Option<TraceOperation> {
+        TraceOperation::ALL
+            .into_iter()
+            .find(|operation| format!("{operation:?}") == name)
+    }
+}
+
+/// Argument or result of a recorded operation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TraceValue {
+    /// No value
+    Unit,
+    /// Boolean
+    Bool(bool),
+    /// Integer, such as an offset, length or handle id
+    Int(u64),
+    /// Text, such as a path or an option
+    Str(String),
+    /// Raw file contents
+    Bytes(Vec<u8>),
+    /// List of text, such as directory entries
+    List(Vec<String>),
+}
+
+impl TraceValue {
+    /// Parse a value from its trace encoding.
+    pub fn parse(token: &str) -> FileSystemResult<TraceValue> {
+        if token == "-" {
+            return Ok(TraceValue::Unit);
+        }
+        let malformed = || FileSystemError::InternalError(format!("Malformed trace value {token}"));
+        let (tag, payload) = token.split_once(':').ok_or_else(malformed)?;
+        match tag {
+            "b" => payload
+                .parse()
+                .map(TraceValue::Bool)
+                .map_err(|_| malformed()),
+            "i" => payload
+                .parse()
+                .map(TraceValue::Int)
+                .map_err(|_| malformed()),
+            "s" => Ok(TraceValue::Str(unescape(payload)?)),
+            "x" => {
+                if payload.len() % 2 != 0 {
+                    return Err(malformed());
+                }
+                (0..payload.len())
+                    .step_by(2)
+                    .map(|index| u8::from_str_radix(&payload[index..index + 2], 16))
+                    .collect::<Result<_, _>>()
+                    .map(TraceValue::Bytes)
+                    .map_err(|_| malformed())
+            }
+            "l" if payload.is_empty() => Ok(TraceValue::List(Vec::new())),
+            "l" => payload
+                .split(',')
+                .map(unescape)
+                .collect::<FileSystemResult<_>>()
+                .map(TraceValue::List),
+            _ => Err(malformed()),
+        }
+    }
+}
+
+impl std::fmt::Display for TraceValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceValue::Unit => f.write_str("-"),
+            TraceValue::Bool(value) => write!(f, "b:{value}"),
+            TraceValue::Int(value) => write!(f, "i:{value}"),
+            TraceValue::Str(value) => write!(f, "s:{}", escape(value)),
+            TraceValue::Bytes(value) => {
+                f.write_str("x:")?;
+                value.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+            }
+            TraceValue::List(values) => {
+                let values: Vec<String> = values.iter().map(|value| escape(value)).collect();
+                write!(f, "l:{}", values.join(","))
+            }
+        }
+    }
+}
+
+/// Escape text so it contains no separators of the trace encoding.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'/' | b'.' | b'_' | b'-' | b'~') {
+            escaped.push(char::from(byte));
+        } else {
+            let _ = write!(escaped, "%{byte:02X}");
+        }
+    }
+    escaped
+}
+
+/// Reverse [`escape`].
+fn unescape(text: &str) -> FileSystemResult<String> {
+    let malformed = || FileSystemError::InternalError(format!("Malformed trace text {text}"));
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut input = text.bytes();
+    while let Some(byte) = input.next() {
+        if byte == b'%' {
+            let digits = [
+                input.next().ok_or_else(malformed)?,
+                input.next().ok_or_else(malformed)?,
+            ];
+            let digits = std::str::from_utf8(&digits).map_err(|_| malformed())?;
+            bytes.push(u8::from_str_radix(digits, 16).map_err(|_| malformed())?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| malformed())
+}
+
+/// Single operation of a trace, with its arguments and outcome.
+///
+/// Records are encoded one per line, as their sequence number, operation, arguments and
+/// either `=> ok` and the result or `=> err` and the name of the error. Handle operations take
+/// the id of their handle, as returned when it was opened, as first argument.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TraceRecord {
+    /// Position of the operation in the trace
+    pub sequence: u64,
+    /// Operation performed
+    pub operation: TraceOperation,
+    /// Arguments of the operation
+    pub arguments: Vec<TraceValue>,
+    /// Result of the operation, or the name of the error it failed with
+    pub outcome: Result<TraceValue, String>,
+}
+
+impl TraceRecord {
+    /// Parse a record from a line of a trace.
+    pub fn parse(line: &str) -> FileSystemResult<TraceRecord> {
+        let malformed = || FileSystemError::InternalError(format!("Malformed trace record {line}"));
+        let mut tokens = line.split_whitespace();
+        let sequence = tokens
+            .next()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(malformed)?;
+        let operation = tokens
+            .next()
+            .and_then(TraceOperation::from_name)
+            .ok_or_else(malformed)?;
+        let mut arguments = Vec::new();
+        loop {
+            match tokens.next().ok_or_else(malformed)? {
+                "=>" => break,
+                token => arguments.push(TraceValue::parse(token)?),
+            }
+        }
+        let outcome = match (tokens.next(), tokens.next(), tokens.next()) {
+            (Some("ok"), Some(value), None) => Ok(TraceValue::parse(value)?),
+            (Some("err"), Some(error), None) => Err(unescape(error)?),
+            _ => return Err(malformed()),
+        };
+        Ok(TraceRecord {
+            sequence,
+            operation,
+            arguments,
+            outcome,
+        })
+    }
+
+    fn argument(&self, index: usize) -> FileSystemResult<&TraceValue> {
+        self.arguments.get(index).ok_or_else(|| {
+            FileSystemError::InternalError(format!(
+                "Trace record {} lacks arguments",
+                self.sequence
+            ))
+        })
+    }
+
+    fn mismatched(&self) -> FileSystemError {
+        FileSystemError::InternalError(format!("Trace record {} has bad arguments", self.sequence))
+    }
+
+    fn int(&self, index: usize) -> FileSystemResult<u64> {
+        match self.argument(index)? {
+            TraceValue::Int(value) => Ok(*value),
+            _ => Err(self.mismatched()),
+        }
+    }
+
+    fn len(&self, index: usize) -> FileSystemResult<usize> {
+        usize::try_from(self.int(index)?).map_err(|_| self.mismatched())
+    }
+
+    fn text(&self, index: usize) -> FileSystemResult<&str> {
+        match self.argument(index)? {
+            TraceValue::Str(value) => Ok(value),
+            _ => Err(self.mismatched()),
+        }
+    }
+
+    fn bytes(&self, index: usize) -> FileSystemResult<&[u8]> {
+        match self.argument(index)? {
+            TraceValue::Bytes(value) => Ok(value),
+            _ => Err(self.mismatched()),
+        }
+    }
+
+    fn parsed<T>(&self, index: usize, parse: fn(&str) -> Option<T>) -> FileSystemResult<T> {
+        parse(self.text(index)?).ok_or_else(|| self.mismatched())
+    }
+}
+
+impl std::fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {:?}", self.sequence, self.operation)?;
+        for argument in &self.arguments {
+            write!(f, " {argument}")?;
+        }
+        match &self.outcome {
+            Ok(value) => write!(f, " => ok {value}"),
+            Err(error) => write!(f, " => err {}", escape(error)),
+        }
+    }
+}
+
+/// Name an error by its variant, so outcomes compare across backends.
+pub(crate) fn error_name(err: &FileSystemError) -> String {
+    match err {
+        FileSystemError::IOError(err) => io_error_name(err),
+        err => {
+            let name = format!("{err:?}");
+            let end = name.find(['(', ' ', '{']).unwrap_or(name.len());
+            name[..end].to_string()
+        }
+    }
+}
+
+pub(crate) fn io_error_name(err: &std::io::Error) -> String {
+    format!("IOError:{:?}", err.kind())
+}
+
+fn outcome<T>(
+    result: &FileSystemResult<T>,
+    value: impl FnOnce(&T) -> TraceValue,
+) -> Result<TraceValue, String> {
+    result.as_ref().map(value).map_err(error_name)
+}
+
+fn io_outcome<T>(
+    result: &std::io::Result<T>,
+    value: impl FnOnce(&T) -> TraceValue,
+) -> Result<TraceValue, String> {
+    result.as_ref().map(value).map_err(io_error_name)
+}
+
+fn encode_options(options: OpenOptions) -> TraceValue {
+    let flags = [
+        ("read", options.is_read()),
+        ("write", options.is_write()),
+        ("append", options.is_append()),
+        ("truncate", options.is_truncate()),
+        ("create", options.is_create()),
+        ("create_new", options.is_create_new()),
+        ("direct", options.is_direct()),
+        ("no_follow", options.is_no_follow()),
+    ];
+    let flags: Vec<&str> = flags
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect();
+    TraceValue::Str(flags.join(","))
+}
+
+fn decode_options(text: &str) -> Option<OpenOptions> {
+    text.split(',')
+        .filter(|flag| !flag.is_empty())
+        .try_fold(OpenOptions::new(), |options, flag| match flag {
+            "read" => Some(options.read(true)),
+            "write" => Some(options.write(true)),
+            "append" => Some(options.append(true)),
+            "truncate" => Some(options.truncate(true)),
+            "create" => Some(options.create(true)),
+            "create_new" => Some(options.create_new(true)),
+            "direct" => Some(options.direct(true)),
+            "no_follow" => Some(options.no_follow(true)),
+            _ => None,
+        })
+}
+
+fn encode_permissions(permissions: Permissions) -> TraceValue {
+    let access = if permissions.is_readonly() {
+        "ro"
+    } else {
+        "rw"
+    };
+    TraceValue::Str(match permissions.get_mode() {
+        Some(mode) => format!("{access}:{mode:o}"),
+        None => access.to_string(),
+    })
+}
+
+fn encode_time(time: SystemTime) -> TraceValue {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    TraceValue::Int(u64::try_from(since_epoch.as_nanos()).unwrap_or(u64::MAX))
+}
+
+fn decode_permissions(text: &str) -> Option<Permissions> {
+    let (access, mode) = match text.split_once(':') {
+        Some((access, mode)) => (access, Some(u32::from_str_radix(mode, 8).ok()?)),
+        None => (text, None),
+    };
+    let permissions = Permissions::new().readonly(match access {
+        "ro" => true,
+        "rw" => false,
+        _ => return None,
+    });
+    Some(mode.map_or(permissions, |mode| permissions.mode(mode)))
+}
+
+fn encode_seek(pos: SeekFrom) -> TraceValue {
+    TraceValue::Str(match pos {
+        SeekFrom::Start(offset) => format!("Start:{offset}"),
+        SeekFrom::Current(offset) => format!("Current:{offset}"),
+        SeekFrom::End(offset) => format!("End:{offset}"),
+    })
+}
+
+fn decode_seek(text: &str) -> Option<SeekFrom> {
+    match text.split_once(':')? {
+        ("Start", offset) => offset.parse().ok().map(SeekFrom::Start),
+        ("Current", offset) => offset.parse().ok().map(SeekFrom::Current),
+        ("End", offset) => offset.parse().ok().map(SeekFrom::End),
+        _ => None,
+    }
+}
+
+fn decode_policy(text: &str) -> Option<SymlinkPolicy> {
+    match text {
+        "Follow" => Some(SymlinkPolicy::Follow),
+        "NoFollow" => Some(SymlinkPolicy::NoFollow),
+        _ => None,
+    }
+}
+
+fn decode_lock_mode(text: &str) -> Option<FileLockMode> {
+    match text {
+        "Unlocked" => Some(FileLockMode::Unlocked),
+        "Shared" => Some(FileLockMode::Shared),
+        "Exclusive" => Some(FileLockMode::Exclusive),
+        _ => None,
+    }
+}
+
+fn decode_advice(text: &str) -> Option<Advice> {
+    match text {
+        "Normal" => Some(Advice::Normal),
+        "Sequential" => Some(Advice::Sequential),
+        "Random" => Some(Advice::Random),
+        "WillNeed" => Some(Advice::WillNeed),
+        "DontNeed" => Some(Advice::DontNeed),
+        _ => None,
+    }
+}
+
+fn encode_batch_operation(operation: &BatchOperation) -> Vec<TraceValue> {
+    let (name, path, extra) = match operation {
+        BatchOperation::CreateDirectory { path } => ("CreateDirectory", path, None),
+        BatchOperation::CreateDirectoryAll { path } => ("CreateDirectoryAll", path, None),
+        BatchOperation::Write { path, contents } => {
+            ("Write", path, Some(TraceValue::Bytes(contents.clone())))
+        }
+        BatchOperation::Rename { from, to } => ("Rename", from, Some(TraceValue::Str(to.clone()))),
+        BatchOperation::RemoveFile { path } => ("RemoveFile", path, None),
+        BatchOperation::RemoveDirectory { path } => ("RemoveDirectory", path, None),
+    };
+    let mut arguments = vec![
+        TraceValue::Str(name.to_string()),
+        TraceValue::Str(path.clone()),
+    ];
+    arguments.extend(extra);
+    arguments
+}
+
+fn decode_batch_operation(record: &TraceRecord) -> FileSystemResult<BatchOperation> {
+    let path = record.text(1)?;
+    Ok(match record.text(0)? {
+        "CreateDirectory" => BatchOperation::create_directory(path),
+        "CreateDirectoryAll" => BatchOperation::create_directory_all(path),
+        "Write" => BatchOperation::write(path, record.bytes(2)?),
+        "Rename" => BatchOperation::rename(path, record.text(2)?),
+        "RemoveFile" => BatchOperation::remove_file(path),
+        "RemoveDirectory" => BatchOperation::remove_directory(path),
+        _ => return Err(record.mismatched()),
+    })
+}
+
+fn debug_name(value: &impl std::fmt::Debug) -> TraceValue {
+    TraceValue::Str(format!("{value:?}"))
+}
+
+/// Shared destination of the records of a [`RecordFileSystem`] and its handles.
+struct Recorder {
+    trace: Mutex<TraceWriter>,
+    next_handle: AtomicU64,
+}
+
+struct TraceWriter {
+    writer: Box<dyn Write + Send>,
+    sequence: u64,
+}
+
+impl Recorder {
+    /// Append a record to the trace, logging rather than failing the operation if it can't be
+    /// written.
+    fn log(
+        &self,
+        operation: TraceOperation,
+        arguments: Vec<TraceValue>,
+        outcome: Result<TraceValue, String>,
+    ) {
+        let mut trace = self.trace.lock().expect("Poisoned Lock");
+        let record = TraceRecord {
+            sequence: trace.sequence,
+            operation,
+            arguments,
+            outcome,
+        };
+        trace.sequence += 1;
+        if let Err(err) = writeln!(trace.writer, "{record}") {
+            tracing::warn!(?err, "Failed to write trace record");
+        }
+    }
+}
+
+impl std::fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recorder")
+            .field("next_handle", &self.next_handle)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Recording `FileSystem` Wrapper
+///
+/// Writes every operation on the wrapped filesystem and its handles, with arguments and
+/// results, as a [`TraceRecord`] line to a trace, so a workload can be re-executed against
+/// another backend with a [`TraceReplayer`]. Records are written in the order operations
+/// complete and include all data read and written, so traces of large workloads are large.
+///
+/// Vectored and memory-mapped reads and writes are recorded as the positional reads and
+/// writes they are composed of.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, RecordFileSystem, TraceReplayer};
+///
+/// let traces = MemoryFileSystem::new();
+/// let fs = RecordFileSystem::new(MemoryFileSystem::new(), traces.create_file("/trace").unwrap());
+/// fs.create_directory("/data").unwrap();
+/// fs.write("/data/table.dat", b"rows").unwrap();
+/// assert_eq!(fs.read("/data/table.dat").unwrap(), b"rows");
+/// drop(fs);
+///
+/// let trace = traces.read("/trace").unwrap();
+/// let report = TraceReplayer::new(MemoryFileSystem::new()).replay(trace.as_slice()).unwrap();
+/// assert!(report.is_consistent());
+/// ```
+#[derive(Debug)]
+pub struct RecordFileSystem {
+    inner: Arc<dyn DynamicFileSystem>,
+    recorder: Arc<Recorder>,
+}
+
+impl RecordFileSystem {
+    /// Create a new Recording `FileSystem` over `filesystem`, writing its trace to `trace`.
+    pub fn new<F: FileSystem, W: Write + Send + 'static>(
+        filesystem: F,
+        trace: W,
+    ) -> RecordFileSystem {
+        RecordFileSystem {
+            inner: Arc::new(filesystem),
+            recorder: Arc::new(Recorder {
+                trace: Mutex::new(TraceWriter {
+                    writer: Box::new(trace),
+                    sequence: 0,
+                }),
+                next_handle: AtomicU64::new(1),
+            }),
+        }
+    }
+
+    /// Flush the trace.
+    pub fn flush_trace(&self) -> FileSystemResult<()> {
+        let mut trace = self.recorder.trace.lock().expect("Poisoned Lock");
+        trace.writer.flush().map_err(FileSystemError::io_error)
+    }
+
+    fn record<T>(
+        &self,
+        operation: TraceOperation,
+        arguments: Vec<TraceValue>,
+        result: FileSystemResult<T>,
+        value: impl FnOnce(&T) -> TraceValue,
+    ) -> FileSystemResult<T> {
+        self.recorder
+            .log(operation, arguments, outcome(&result, value));
+        result
+    }
+
+    fn open(
+        &self,
+        operation: TraceOperation,
+        arguments: Vec<TraceValue>,
+        result: FileSystemResult<Box<dyn FileHandle>>,
+    ) -> FileSystemResult<RecordFileHandle> {
+        let result = result.map(|inner| RecordFileHandle {
+            id: self.recorder.next_handle.fetch_add(1, Ordering::Relaxed),
+            inner,
+            recorder: self.recorder.clone(),
+        });
+        self.record(operation, arguments, result, |handle| {
+            TraceValue::Int(handle.id)
+        })
+    }
+}
+
+fn path_argument(path: &str) -> Vec<TraceValue> {
+    vec![TraceValue::Str(path.to_string())]
+}
+
+impl FileSystem for RecordFileSystem {
+    type FileHandle = RecordFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        let result = DynamicFileSystem::exists(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::Exists,
+            path_argument(path),
+            result,
+            |exists| TraceValue::Bool(*exists),
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        let result = DynamicFileSystem::is_file(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::IsFile,
+            path_argument(path),
+            result,
+            |file| TraceValue::Bool(*file),
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        let result = DynamicFileSystem::is_directory(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::IsDirectory,
+            path_argument(path),
+            result,
+            |directory| TraceValue::Bool(*directory),
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        let result = DynamicFileSystem::filesize(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::Filesize,
+            path_argument(path),
+            result,
+            |size| TraceValue::Int(*size),
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        let result = DynamicFileSystem::create_directory(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::CreateDirectory,
+            path_argument(path),
+            result,
+            |()| TraceValue::Unit,
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        let result = DynamicFileSystem::create_directory_all(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::CreateDirectoryAll,
+            path_argument(path),
+            result,
+            |()| TraceValue::Unit,
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        let result = DynamicFileSystem::list_directory(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::ListDirectory,
+            path_argument(path),
+            result,
+            |names| {
+                let mut names = names.clone();
+                names.sort();
+                TraceValue::List(names)
+            },
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        let result = DynamicFileSystem::remove_directory(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::RemoveDirectory,
+            path_argument(path),
+            result,
+            |()| TraceValue::Unit,
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        let result = DynamicFileSystem::remove_directory_all(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::RemoveDirectoryAll,
+            path_argument(path),
+            result,
+            |()| TraceValue::Unit,
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let result = DynamicFileSystem::create_file(self.inner.as_ref(), path);
+        self.open(TraceOperation::CreateFile, path_argument(path), result)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let result = DynamicFileSystem::open_file(self.inner.as_ref(), path);
+        self.open(TraceOperation::OpenFile, path_argument(path), result)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        let result = DynamicFileSystem::remove_file(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::RemoveFile,
+            path_argument(path),
+            result,
+            |()| TraceValue::Unit,
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        let result = DynamicFileSystem::file_type(self.inner.as_ref(), path, policy);
+        let arguments = vec![TraceValue::Str(path.to_string()), debug_name(&policy)];
+        self.record(TraceOperation::FileType, arguments, result, debug_name)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        let result = DynamicFileSystem::create_symlink(self.inner.as_ref(), target, path);
+        let arguments = vec![
+            TraceValue::Str(target.to_string()),
+            TraceValue::Str(path.to_string()),
+        ];
+        self.record(TraceOperation::CreateSymlink, arguments, result, |()| {
+            TraceValue::Unit
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        let result = DynamicFileSystem::read_link(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::ReadLink,
+            path_argument(path),
+            result,
+            |target| TraceValue::Str(target.clone()),
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        let result = DynamicFileSystem::permissions(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::Permissions,
+            path_argument(path),
+            result,
+            |permissions| encode_permissions(*permissions),
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        let result = DynamicFileSystem::modified(self.inner.as_ref(), path);
+        self.record(
+            TraceOperation::Modified,
+            path_argument(path),
+            result,
+            |time| encode_time(*time),
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        let result = DynamicFileSystem::set_permissions(self.inner.as_ref(), path, permissions);
+        let arguments = vec![
+            TraceValue::Str(path.to_string()),
+            encode_permissions(permissions),
+        ];
+        self.record(TraceOperation::SetPermissions, arguments, result, |()| {
+            TraceValue::Unit
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        let result = DynamicFileSystem::open_with(self.inner.as_ref(), path, options);
+        let arguments = vec![TraceValue::Str(path.to_string()), encode_options(options)];
+        self.open(TraceOperation::OpenWith, arguments, result)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        let result = DynamicFileSystem::space(self.inner.as_ref());
+        self.record(TraceOperation::Space, Vec::new(), result, |space| {
+            TraceValue::List(vec![
+                space.total.to_string(),
+                space.used.to_string(),
+                space.available.to_string(),
+            ])
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        let result = DynamicFileSystem::rename(self.inner.as_ref(), from, to);
+        let arguments = vec![
+            TraceValue::Str(from.to_string()),
+            TraceValue::Str(to.to_string()),
+        ];
+        self.record(TraceOperation::Rename, arguments, result, |()| {
+            TraceValue::Unit
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        let result = DynamicFileSystem::clone_file(self.inner.as_ref(), src, dst);
+        let arguments = vec![
+            TraceValue::Str(src.to_string()),
+            TraceValue::Str(dst.to_string()),
+        ];
+        self.record(TraceOperation::CloneFile, arguments, result, debug_name)
+    }
+
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let results = DynamicFileSystem::apply(self.inner.as_ref(), batch);
+        for (operation, result) in batch.iter().zip(&results) {
+            self.recorder.log(
+                TraceOperation::Apply,
+                encode_batch_operation(operation),
+                outcome(result, |()| TraceValue::Unit),
+            );
+        }
+        results
+    }
+}
+
+/// Recording File Handle
+///
+/// Records operations under an id unique within its [`RecordFileSystem`], and records closing
+/// when dropped.
+pub struct RecordFileHandle {
+    id: u64,
+    inner: Box<dyn FileHandle>,
+    recorder: Arc<Recorder>,
+}
+
+impl RecordFileHandle {
+    /// Id of this handle in the trace.
+    #[must_use]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn arguments(&self, mut arguments: Vec<TraceValue>) -> Vec<TraceValue> {
+        arguments.insert(0, TraceValue::Int(self.id));
+        arguments
+    }
+
+    fn record<T>(
+        &self,
+        operation: TraceOperation,
+        arguments: Vec<TraceValue>,
+        result: FileSystemResult<T>,
+        value: impl FnOnce(&T) -> TraceValue,
+    ) -> FileSystemResult<T> {
+        self.recorder.log(
+            operation,
+            self.arguments(arguments),
+            outcome(&result, value),
+        );
+        result
+    }
+
+    fn record_io<T>(
+        &self,
+        operation: TraceOperation,
+        arguments: Vec<TraceValue>,
+        result: std::io::Result<T>,
+        value: impl FnOnce(&T) -> TraceValue,
+    ) -> std::io::Result<T> {
+        self.recorder.log(
+            operation,
+            self.arguments(arguments),
+            io_outcome(&result, value),
+        );
+        result
+    }
+}
+
+impl Drop for RecordFileHandle {
+    fn drop(&mut self) {
+        self.recorder.log(
+            TraceOperation::Close,
+            vec![TraceValue::Int(self.id)],
+            Ok(TraceValue::Unit),
+        );
+    }
+}
+
+impl std::fmt::Debug for RecordFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordFileHandle")
+            .field("id", &self.id)
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Read for RecordFileHandle {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let result = self.inner.read(buf);
+        let arguments = vec![TraceValue::Int(buf.len() as u64)];
+        self.record_io(TraceOperation::Read, arguments, result, |read| {
+            TraceValue::Bytes(buf[..*read].to_vec())
+        })
+    }
+}
+
+impl Write for RecordFileHandle {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let result = self.inner.write(buf);
+        let arguments = vec![TraceValue::Bytes(buf.to_vec())];
+        self.record_io(TraceOperation::Write, arguments, result, |written| {
+            TraceValue::Int(*written as u64)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        let result = self.inner.flush();
+        self.record_io(TraceOperation::Flush, Vec::new(), result, |()| {
+            TraceValue::Unit
+        })
+    }
+}
+
+impl Seek for RecordFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let result = self.inner.seek(pos);
+        self.record_io(
+            TraceOperation::Seek,
+            vec![encode_seek(pos)],
+            result,
+            |pos| TraceValue::Int(*pos),
+        )
+    }
+}
+
+impl FileHandle for RecordFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        self.inner.path()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        let result = self.inner.get_size();
+        self.record(TraceOperation::GetSize, Vec::new(), result, |size| {
+            TraceValue::Int(*size)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        let result = self.inner.set_size(new_size);
+        let arguments = vec![TraceValue::Int(new_size)];
+        self.record(TraceOperation::SetSize, arguments, result, |()| {
+            TraceValue::Unit
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        let result = self.inner.allocate(len);
+        self.record(
+            TraceOperation::Allocate,
+            vec![TraceValue::Int(len)],
+            result,
+            |()| TraceValue::Unit,
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        let result = self.inner.sync_all();
+        self.record(TraceOperation::SyncAll, Vec::new(), result, |()| {
+            TraceValue::Unit
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        let result = self.inner.sync_data();
+        self.record(TraceOperation::SyncData, Vec::new(), result, |()| {
+            TraceValue::Unit
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        let result = self.inner.get_lock_status();
+        self.record(
+            TraceOperation::GetLockStatus,
+            Vec::new(),
+            result,
+            debug_name,
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        let result = self.inner.set_lock_status(mode);
+        let arguments = vec![debug_name(&mode)];
+        self.record(TraceOperation::SetLockStatus, arguments, result, |()| {
+            TraceValue::Unit
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        let result = self.inner.try_set_lock_status(mode);
+        let arguments = vec![debug_name(&mode)];
+        self.record(TraceOperation::TrySetLockStatus, arguments, result, |()| {
+            TraceValue::Unit
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        self.inner.alignment()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        let result = self.inner.lock_range(offset, len, mode);
+        let arguments = vec![
+            TraceValue::Int(offset),
+            TraceValue::Int(len),
+            debug_name(&mode),
+        ];
+        self.record(TraceOperation::LockRange, arguments, result, |()| {
+            TraceValue::Unit
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        let result = self.inner.unlock_range(offset, len);
+        let arguments = vec![TraceValue::Int(offset), TraceValue::Int(len)];
+        self.record(TraceOperation::UnlockRange, arguments, result, |()| {
+            TraceValue::Unit
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        let result = self.inner.advise(offset, len, advice);
+        let arguments = vec![
+            TraceValue::Int(offset),
+            TraceValue::Int(len),
+            debug_name(&advice),
+        ];
+        self.record(TraceOperation::Advise, arguments, result, |()| {
+            TraceValue::Unit
+        })
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        let result = self.inner.read_at_offset(offset, buffer);
+        let arguments = vec![
+            TraceValue::Int(offset),
+            TraceValue::Int(buffer.len() as u64),
+        ];
+        self.record(TraceOperation::ReadAt, arguments, result, |read| {
+            TraceValue::Bytes(buffer[..*read].to_vec())
+        })
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        let result = self.inner.write_to_offset(offset, buffer);
+        let arguments = vec![TraceValue::Int(offset), TraceValue::Bytes(buffer.to_vec())];
+        self.record(TraceOperation::WriteAt, arguments, result, |written| {
+            TraceValue::Int(*written as u64)
+        })
+    }
+}
+
+/// Operation whose replayed outcome differed from the recorded one.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReplayMismatch {
+    /// Position of the operation in the trace
+    pub sequence: u64,
+    /// Operation replayed
+    pub operation: TraceOperation,
+    /// Outcome recorded in the trace
+    pub expected: Result<TraceValue, String>,
+    /// Outcome of replaying the operation
+    pub actual: Result<TraceValue, String>,
+}
+
+/// Summary of replaying a trace with a [`TraceReplayer`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReplayReport {
+    /// Number of operations replayed
+    pub operations: u64,
+    /// Operations whose outcome differed from the trace, in trace order
+    pub mismatches: Vec<ReplayMismatch>,
+    /// Time spent executing the operations, excluding parsing the trace
+    pub elapsed: Duration,
+}
+
+impl ReplayReport {
+    /// Check if every operation reproduced its recorded outcome.
+    #[must_use]
+    pub fn is_consistent(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Re-executes traces written by a [`RecordFileSystem`] against a `FileSystem`.
+///
+/// Handles are tracked by the ids they were recorded with, so a trace of several
+/// interleaved handles replays in its recorded order. Operations on handles that failed to
+/// open during replay fail with `UnknownHandle`.
+#[derive(Debug)]
+pub struct TraceReplayer<F: FileSystem> {
+    fs: F,
+    handles: HashMap<u64, F::FileHandle>,
+}
+
+impl<F: FileSystem> TraceReplayer<F> {
+    /// Create a replayer executing against `fs`.
+    pub fn new(fs: F) -> TraceReplayer<F> {
+        TraceReplayer {
+            fs,
+            handles: HashMap::new(),
+        }
+    }
+
+    /// Borrow the filesystem replayed against.
+    pub fn filesystem(&self) -> &F {
+        &self.fs
+    }
+
+    /// Replay every record of `trace`, skipping blank lines and lines starting with `#`.
+    ///
+    /// Fails only if the trace can't be read or parsed, mismatching outcomes are reported.
+    pub fn replay<R: BufRead>(&mut self, trace: R) -> FileSystemResult<ReplayReport> {
+        let mut report = ReplayReport::default();
+        for line in trace.lines() {
+            let line = line.map_err(FileSystemError::io_error)?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let record = TraceRecord::parse(line)?;
+            let started = Instant::now();
+            let actual = self.apply(&record)?;
+            report.elapsed += started.elapsed();
+            report.operations += 1;
+            let consistent = if record.operation.compares_value() {
+                actual == record.outcome
+            } else {
+                actual.is_ok() == record.outcome.is_ok()
+            };
+            if !consistent {
+                tracing::debug!(%record, ?actual, "Replayed outcome differs");
+                report.mismatches.push(ReplayMismatch {
+                    sequence: record.sequence,
+                    operation: record.operation,
+                    expected: record.outcome,
+                    actual,
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    /// Execute a single record, returning its outcome.
+    ///
+    /// Fails if the record's arguments don't fit its operation.
+    pub fn apply(&mut self, record: &TraceRecord) -> FileSystemResult<Result<TraceValue, String>> {
+        let unit = |(): &()| TraceValue::Unit;
+        Ok(match record.operation {
+            TraceOperation::Exists => {
+                outcome(&self.fs.exists(record.text(0)?), |v| TraceValue::Bool(*v))
+            }
+            TraceOperation::IsFile => {
+                outcome(&self.fs.is_file(record.text(0)?), |v| TraceValue::Bool(*v))
+            }
+            TraceOperation::IsDirectory => outcome(&self.fs.is_directory(record.text(0)?), |v| {
+                TraceValue::Bool(*v)
+            }),
+            TraceOperation::Filesize => {
+                outcome(&self.fs.filesize(record.text(0)?), |v| TraceValue::Int(*v))
+            }
+            TraceOperation::CreateDirectory => {
+                outcome(&self.fs.create_directory(record.text(0)?), unit)
+            }
+            TraceOperation::CreateDirectoryAll => {
+                outcome(&self.fs.create_directory_all(record.text(0)?), unit)
+            }
+            TraceOperation::ListDirectory => {
+                outcome(&self.fs.list_directory(record.text(0)?), |names| {
+                    let mut names = names.clone();
+                    names.sort();
+                    TraceValue::List(names)
+                })
+            }
+            TraceOperation::RemoveDirectory => {
+                outcome(&self.fs.remove_directory(record.text(0)?), unit)
+            }
+            TraceOperation::RemoveDirectoryAll => {
+                outcome(&self.fs.remove_directory_all(record.text(0)?), unit)
+            }
+            TraceOperation::CreateFile => {
+                let result = self.fs.create_file(record.text(0)?);
+                self.opened(record, result)
+            }
+            TraceOperation::OpenFile => {
+                let result = self.fs.open_file(record.text(0)?);
+                self.opened(record, result)
+            }
+            TraceOperation::OpenWith => {
+                let options = record.parsed(1, decode_options)?;
+                let result = self.fs.open_with(record.text(0)?, options);
+                self.opened(record, result)
+            }
+            TraceOperation::RemoveFile => outcome(&self.fs.remove_file(record.text(0)?), unit),
+            TraceOperation::FileType => {
+                let policy = record.parsed(1, decode_policy)?;
+                outcome(&self.fs.file_type(record.text(0)?, policy), debug_name)
+            }
+            TraceOperation::CreateSymlink => outcome(
+                &self.fs.create_symlink(record.text(0)?, record.text(1)?),
+                unit,
+            ),
+            TraceOperation::ReadLink => outcome(&self.fs.read_link(record.text(0)?), |target| {
+                TraceValue::Str(target.clone())
+            }),
+            TraceOperation::Permissions => {
+                outcome(&self.fs.permissions(record.text(0)?), |permissions| {
+                    encode_permissions(*permissions)
+                })
+            }
+            TraceOperation::SetPermissions => {
+                let permissions = record.parsed(1, decode_permissions)?;
+                outcome(&self.fs.set_permissions(record.text(0)?, permissions), unit)
+            }
+            TraceOperation::Space => outcome(&self.fs.space(), |space| {
+                TraceValue::List(vec![
+                    space.total.to_string(),
+                    space.used.to_string(),
+                    space.available.to_string(),
+                ])
+            }),
+            TraceOperation::Modified => outcome(&self.fs.modified(record.text(0)?), |time| {
+                encode_time(*time)
+            }),
+            TraceOperation::Rename => {
+                outcome(&self.fs.rename(record.text(0)?, record.text(1)?), unit)
+            }
+            TraceOperation::CloneFile => outcome(
+                &self.fs.clone_file(record.text(0)?, record.text(1)?),
+                debug_name,
+            ),
+            TraceOperation::Apply => {
+                let operation = decode_batch_operation(record)?;
+                let result = self.fs.apply(&[operation]).pop().ok_or_else(|| {
+                    FileSystemError::InternalError("Missing Batch Result".to_string())
+                })?;
+                outcome(&result, unit)
+            }
+            TraceOperation::Close => {
+                self.handles.remove(&record.int(0)?);
+                Ok(TraceValue::Unit)
+            }
+            _ => self.apply_handle(record)?,
+        })
+    }
+
+    fn opened(
+        &mut self,
+        record: &TraceRecord,
+        result: FileSystemResult<F::FileHandle>,
+    ) -> Result<TraceValue, String> {
+        let id = match record.outcome {
+            Ok(TraceValue::Int(id)) => Some(id),
+            _ => None,
+        };
+        match result {
+            Ok(handle) => {
+                if let Some(id) = id {
+                    self.handles.insert(id, handle);
+                }
+                Ok(TraceValue::Int(id.unwrap_or_default()))
+            }
+            Err(err) => Err(error_name(&err)),
+        }
+    }
+
+    fn apply_handle(
+        &mut self,
+        record: &TraceRecord,
+    ) -> FileSystemResult<Result<TraceValue, String>> {
+        let unit = |(): &()| TraceValue::Unit;
+        let Some(handle) = self.handles.get_mut(&record.int(0)?) else {
+            return Ok(Err("UnknownHandle".to_string()));
+        };
+        Ok(match record.operation {
+            TraceOperation::Read => {
+                let mut buffer = vec![0; record.len(1)?];
+                let result = handle.read(&mut buffer);
+                io_outcome(&result, |read| TraceValue::Bytes(buffer[..*read].to_vec()))
+            }
+            TraceOperation::Write => io_outcome(&handle.write(record.bytes(1)?), |written| {
+                TraceValue::Int(*written as u64)
+            }),
+            TraceOperation::Flush => io_outcome(&handle.flush(), unit),
+            TraceOperation::Seek => {
+                let pos = record.parsed(1, decode_seek)?;
+                io_outcome(&handle.seek(pos), |pos| TraceValue::Int(*pos))
+            }
+            TraceOperation::GetSize => outcome(&handle.get_size(), |size| TraceValue::Int(*size)),
+            TraceOperation::SetSize => outcome(&handle.set_size(record.int(1)?), unit),
+            TraceOperation::Allocate => outcome(&handle.allocate(record.int(1)?), unit),
+            TraceOperation::SyncAll => outcome(&handle.sync_all(), unit),
+            TraceOperation::SyncData => outcome(&handle.sync_data(), unit),
+            TraceOperation::GetLockStatus => outcome(&handle.get_lock_status(), debug_name),
+            TraceOperation::SetLockStatus => {
+                let mode = record.parsed(1, decode_lock_mode)?;
+                outcome(&handle.set_lock_status(mode), unit)
+            }
+            TraceOperation::TrySetLockStatus => {
+                let mode = record.parsed(1, decode_lock_mode)?;
+                outcome(&handle.try_set_lock_status(mode), unit)
+            }
+            TraceOperation::LockRange => {
+                let mode = record.parsed(3, decode_lock_mode)?;
+                outcome(
+                    &handle.lock_range(record.int(1)?, record.int(2)?, mode),
+                    unit,
+                )
+            }
+            TraceOperation::UnlockRange => {
+                outcome(&handle.unlock_range(record.int(1)?, record.int(2)?), unit)
+            }
+            TraceOperation::Advise => {
+                let advice = record.parsed(3, decode_advice)?;
+                outcome(&handle.advise(record.int(1)?, record.int(2)?, advice), unit)
+            }
+            TraceOperation::ReadAt => {
+                let mut buffer = vec![0; record.len(2)?];
+                let result = handle.read_at_offset(record.int(1)?, &mut buffer);
+                outcome(&result, |read| TraceValue::Bytes(buffer[..*read].to_vec()))
+            }
+            TraceOperation::WriteAt => outcome(
+                &handle.write_to_offset(record.int(1)?, record.bytes(2)?),
+                |written| TraceValue::Int(*written as u64),
+            ),
+            _ => return Err(record.mismatched()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BatchOperation, FileHandle, FileSystem, MemoryFileSystem, RecordFileSystem};
+    use crate::{TraceOperation, TraceRecord, TraceReplayer, TraceValue};
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_record_replay() {
+        let traces = MemoryFileSystem::new();
+        let fs = RecordFileSystem::new(
+            MemoryFileSystem::new(),
+            traces.create_file("/trace").unwrap(),
+        );
+        fs.create_directory_all("/data/my table").unwrap();
+        let mut first = fs.create_file("/data/my table/0.dat").unwrap();
+        let mut second = fs.create_file("/data/my table/1.dat").unwrap();
+        first.write_all(b"first\nrow").unwrap();
+        second.write_to_offset(4, &[0, 1, 2, 255]).unwrap();
+        first.seek(SeekFrom::Start(0)).unwrap();
+        let mut contents = String::new();
+        first.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "first\nrow");
+        drop(second);
+        assert!(fs.open_file("/data/missing").is_err());
+        first.sync_all().unwrap();
+        drop(first);
+        assert_eq!(fs.list_directory("/data/my table").unwrap().len(), 2);
+        fs.remove_file("/data/my table/1.dat").unwrap();
+        fs.rename("/data/my table/0.dat", "/data/my table/1.dat")
+            .unwrap();
+        fs.clone_file("/data/my table/1.dat", "/data/my table/2.dat")
+            .unwrap();
+        let results = fs.apply(&[
+            BatchOperation::write("/data/my table/3.dat", &[7, 0, 255]),
+            BatchOperation::rename("/data/my table/3.dat", "/data/my table/4.dat"),
+            BatchOperation::remove_directory("/data/my table"),
+        ]);
+        assert!(results[2].is_err());
+        drop(fs);
+
+        // Every record survives encoding
+        let trace = traces.read_to_string("/trace").unwrap();
+        let records: Vec<TraceRecord> = trace
+            .lines()
+            .map(|line| TraceRecord::parse(line).unwrap())
+            .collect();
+        for record in &records {
+            assert_eq!(TraceRecord::parse(&record.to_string()).unwrap(), *record);
+        }
+        assert!(records
+            .iter()
+            .any(|record| record.operation == TraceOperation::OpenFile
+                && record.outcome == Err("PathMissing".to_string())));
+        assert_eq!(
+            records
+                .iter()
+                .filter(|record| record.operation == TraceOperation::Close)
+                .count(),
+            2
+        );
+        assert!(records.iter().any(|record| record
+            .arguments
+            .contains(&TraceValue::Bytes(vec![0, 1, 2, 255]))));
+        assert_eq!(
+            records
+                .iter()
+                .filter(|record| record.operation == TraceOperation::Apply)
+                .count(),
+            3
+        );
+        assert!(records
+            .iter()
+            .any(|record| record.operation == TraceOperation::Rename));
+        assert!(records
+            .iter()
+            .any(|record| record.operation == TraceOperation::CloneFile));
+
+        // Replaying against an equivalent backend reproduces every outcome
+        let report = TraceReplayer::new(MemoryFileSystem::new())
+            .replay(trace.as_bytes())
+            .unwrap();
+        assert_eq!(report.operations, records.len() as u64);
+        assert!(report.is_consistent(), "{:?}", report.mismatches);
+
+        // Replaying against a diverging backend reports where it diverged
+        let diverged = MemoryFileSystem::new();
+        diverged.create_directory_all("/data/my table").unwrap();
+        diverged.write("/data/missing", b"present").unwrap();
+        let report = TraceReplayer::new(diverged)
+            .replay(trace.as_bytes())
+            .unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.mismatches[0].operation, TraceOperation::OpenFile);
+        assert_eq!(
+            report.mismatches[0].expected,
+            Err("PathMissing".to_string())
+        );
+
+        assert!(TraceRecord::parse("0 Exists s:/a =>").is_err());
+        assert!(TraceRecord::parse("0 Unknown => ok -").is_err());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_record_conformance() {
+        crate::conformance::run(|| RecordFileSystem::new(MemoryFileSystem::new(), std::io::sink()));
+    }
+}
@@ -0,0 +1,762 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::{
+    Advice, CloneMethod, ContentDigest, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, HashAlgorithm, OpenOptions, Permissions,
+    SymlinkPolicy,
+};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// How many replicas of a [`ReplicatedFileSystem`] must apply a write for it to succeed.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ReplicationPolicy {
+    /// Every replica must apply the write
+    #[default]
+    All,
+    /// At least this many replicas must apply the write, clamped to the number of replicas
+    Quorum(usize),
+}
+
+/// Policy and health of the replicas, shared by a [`ReplicatedFileSystem`] and its handles.
+#[derive(Debug)]
+struct ReplicaState {
+    policy: ReplicationPolicy,
+    healthy: Vec<AtomicBool>,
+}
+
+impl ReplicaState {
+    fn new(policy: ReplicationPolicy, replicas: usize) -> Arc<ReplicaState> {
+        Arc::new(ReplicaState {
+            policy,
+            healthy: (0..replicas).map(|_| AtomicBool::new(true)).collect(),
+        })
+    }
+
+    /// Number of replicas which must apply a write.
+    fn required(&self) -> usize {
+        match self.policy {
+            ReplicationPolicy::All => self.healthy.len(),
+            ReplicationPolicy::Quorum(quorum) => quorum.clamp(1, self.healthy.len()),
+        }
+    }
+
+    /// Stop reading from a replica until it's marked healthy again.
+    fn fail(&self, replica: usize) {
+        if self.healthy[replica].swap(false, Ordering::AcqRel) {
+            tracing::warn!(replica, "Replica marked unhealthy");
+        }
+    }
+
+    /// Combine the outcomes of a write on each replica, returning those of the replicas which
+    /// applied it if at least `required` did, or else the first error.
+    ///
+    /// Replicas failing a write that others applied have diverged, so they're marked unhealthy.
+    fn settle<T>(
+        &self,
+        path: &str,
+        required: usize,
+        results: Vec<(usize, FileSystemResult<T>)>,
+    ) -> FileSystemResult<Vec<(usize, T)>> {
+        let (applied, failed): (Vec<_>, Vec<_>) =
+            results.into_iter().partition(|(_, result)| result.is_ok());
+        if !applied.is_empty() {
+            for (replica, result) in &failed {
+                if let Err(err) = result {
+                    tracing::warn!(path, replica, %err, "Replica failed a write");
+                }
+                self.fail(*replica);
+            }
+        }
+        if applied.len() >= required {
+            return Ok(applied
+                .into_iter()
+                .map(|(replica, result)| (replica, result.expect("Applied Write")))
+                .collect());
+        }
+        Err(failed
+            .into_iter()
+            .find_map(|(_, result)| result.err())
+            .expect("Failed Write"))
+    }
+}
+
+/// Check if an error means the replica itself is failing rather than the operation being
+/// invalid, so another replica may succeed.
+fn is_fault(err: &FileSystemError) -> bool {
+    matches!(
+        err,
+        FileSystemError::IOError(_)
+            | FileSystemError::WrappedError(_)
+            | FileSystemError::InternalError(_)
+            | FileSystemError::TimedOut
+            | FileSystemError::CorruptData { .. }
+    )
+}
+
+/// Replicating `FileSystem` Wrapper
+///
+/// Applies every write to each of its replicas in order, and succeeds if enough of them
+/// applied it according to its [`ReplicationPolicy`]. Replicas that fail a write which others
+/// applied have diverged and are marked unhealthy, though they keep receiving writes. Writes
+/// aren't rolled back on the replicas which applied them when too few did.
+///
+/// Reads go to the preferred replica, or the first healthy one, falling over to the next
+/// healthy replica when one fails with an I/O error or times out. If no replica is healthy,
+/// reads try each in turn. After resynchronizing a replica, for instance with [`sync`](crate::sync),
+/// mark it healthy again with [`ReplicatedFileSystem::mark_healthy`].
+///
+/// Handles opened for writing are opened on every replica, while read-only handles are opened
+/// on the replica serving reads.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, ReplicatedFileSystem, ReplicationPolicy};
+///
+/// let local = MemoryFileSystem::new();
+/// let remote = MemoryFileSystem::new();
+/// let fs = ReplicatedFileSystem::new(local.clone())
+///     .with_replica(remote.clone())
+///     .with_policy(ReplicationPolicy::All);
+///
+/// fs.write("/table.dat", b"rows").unwrap();
+/// assert_eq!(local.read("/table.dat").unwrap(), b"rows");
+/// assert_eq!(remote.read("/table.dat").unwrap(), b"rows");
+/// ```
+#[derive(Debug)]
+pub struct ReplicatedFileSystem {
+    replicas: Vec<Arc<dyn DynamicFileSystem>>,
+    preferred: usize,
+    state: Arc<ReplicaState>,
+}
+
+impl ReplicatedFileSystem {
+    /// Create a new Replicating `FileSystem` with a single replica, which reads prefer.
+    pub fn new<F: FileSystem>(filesystem: F) -> ReplicatedFileSystem {
+        ReplicatedFileSystem {
+            replicas: vec![Arc::new(filesystem)],
+            preferred: 0,
+            state: ReplicaState::new(ReplicationPolicy::default(), 1),
+        }
+    }
+
+    /// Add another replica.
+    #[must_use]
+    pub fn with_replica<F: FileSystem>(mut self, filesystem: F) -> ReplicatedFileSystem {
+        self.replicas.push(Arc::new(filesystem));
+        self.state = ReplicaState::new(self.state.policy, self.replicas.len());
+        self
+    }
+
+    /// Set how many replicas must apply a write.
+    #[must_use]
+    pub fn with_policy(mut self, policy: ReplicationPolicy) -> ReplicatedFileSystem {
+        self.state = ReplicaState::new(policy, self.replicas.len());
+        self
+    }
+
+    /// Set the replica reads prefer while it's healthy, by the order replicas were added in.
+    #[must_use]
+    pub fn with_preferred(mut self, replica: usize) -> ReplicatedFileSystem {
+        self.preferred = replica;
+        self
+    }
+
+    /// How many replicas must apply a write.
+    #[must_use]
+    pub fn policy(&self) -> ReplicationPolicy {
+        self.state.policy
+    }
+
+    /// Number of replicas.
+    #[must_use]
+    pub fn replicas(&self) -> usize {
+        self.replicas.len()
+    }
+
+    /// Check if a replica is healthy, so it serves reads.
+    #[must_use]
+    pub fn is_healthy(&self, replica: usize) -> bool {
+        self.state
+            .healthy
+            .get(replica)
+            .is_some_and(|healthy| healthy.load(Ordering::Acquire))
+    }
+
+    /// Let a replica serve reads again, once it has caught up with the others.
+    pub fn mark_healthy(&self, replica: usize) {
+        if let Some(healthy) = self.state.healthy.get(replica) {
+            healthy.store(true, Ordering::Release);
+        }
+    }
+
+    /// Replicas in the order reads try them: the preferred replica, then the others in order,
+    /// leaving out unhealthy replicas unless every replica is.
+    fn order(&self) -> Vec<usize> {
+        let mut order = (0..self.replicas.len()).collect::<Vec<_>>();
+        order.sort_by_key(|replica| *replica != self.preferred);
+        let healthy = order
+            .iter()
+            .copied()
+            .filter(|replica| self.is_healthy(*replica))
+            .collect::<Vec<_>>();
+        if healthy.is_empty() {
+            order
+        } else {
+            healthy
+        }
+    }
+
+    /// Perform a read on the first replica in read order which doesn't fail with a fault.
+    fn read_from<T>(
+        &self,
+        read: impl Fn(&dyn DynamicFileSystem) -> FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        let mut fault = None;
+        for replica in self.order() {
+            match read(self.replicas[replica].as_ref()) {
+                Err(err) if is_fault(&err) => {
+                    tracing::warn!(replica, %err, "Replica failed a read");
+                    self.state.fail(replica);
+                    fault = Some(err);
+                }
+                result => return result,
+            }
+        }
+        Err(fault.expect("Failed Read"))
+    }
+
+    /// Perform a write on every replica, returning the outcome on the first which applied it.
+    fn write_to<T>(
+        &self,
+        path: &str,
+        write: impl Fn(&dyn DynamicFileSystem) -> FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        let results = self
+            .replicas
+            .iter()
+            .enumerate()
+            .map(|(replica, filesystem)| (replica, write(filesystem.as_ref())))
+            .collect();
+        let mut applied = self.state.settle(path, self.state.required(), results)?;
+        Ok(applied.swap_remove(0).1)
+    }
+
+    /// Open a handle on every replica, ordered for reads.
+    fn open_on_all(
+        &self,
+        path: &str,
+        open: impl Fn(&dyn DynamicFileSystem) -> FileSystemResult<Box<dyn FileHandle>>,
+        append: bool,
+    ) -> FileSystemResult<ReplicatedFileHandle> {
+        let results = self
+            .replicas
+            .iter()
+            .enumerate()
+            .map(|(replica, filesystem)| (replica, open(filesystem.as_ref())))
+            .collect();
+        let mut handles = self.state.settle(path, self.state.required(), results)?;
+        let order = self.order();
+        handles.sort_by_key(|(replica, _)| {
+            order
+                .iter()
+                .position(|ordered| ordered == replica)
+                .unwrap_or(usize::MAX)
+        });
+        Ok(ReplicatedFileHandle {
+            path: path.to_string(),
+            handles,
+            required: self.state.required(),
+            state: self.state.clone(),
+            cursor: 0,
+            append,
+        })
+    }
+}
+
+impl FileSystem for ReplicatedFileSystem {
+    type FileHandle = ReplicatedFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        self.read_from(|fs| fs.exists(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        self.read_from(|fs| fs.is_file(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        self.read_from(|fs| fs.is_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.read_from(|fs| fs.filesize(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.write_to(path, |fs| fs.create_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.write_to(path, |fs| fs.create_directory_all(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        self.read_from(|fs| fs.list_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.write_to(path, |fs| fs.remove_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.write_to(path, |fs| fs.remove_directory_all(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.open_on_all(path, |fs| fs.create_file(path), false)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.open_on_all(path, |fs| fs.open_file(path), false)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        self.write_to(path, |fs| fs.remove_file(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.read_from(|fs| fs.file_type(path, policy))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        self.write_to(path, |fs| fs.create_symlink(target, path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.read_from(|fs| fs.read_link(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.read_from(|fs| fs.permissions(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.read_from(|fs| fs.modified(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.write_to(path, |fs| fs.set_permissions(path, permissions))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        if options.is_write() || options.is_append() {
+            return self.open_on_all(path, |fs| fs.open_with(path, options), options.is_append());
+        }
+        let mut order = self.order().into_iter();
+        let mut fault = None;
+        for replica in order.by_ref() {
+            match self.replicas[replica].open_with(path, options) {
+                Err(err) if is_fault(&err) => {
+                    self.state.fail(replica);
+                    fault = Some(err);
+                }
+                handle => {
+                    return Ok(ReplicatedFileHandle {
+                        path: path.to_string(),
+                        handles: vec![(replica, handle?)],
+                        required: 1,
+                        state: self.state.clone(),
+                        cursor: 0,
+                        append: false,
+                    })
+                }
+            }
+        }
+        Err(fault.expect("Failed Open"))
+    }
+
+    /// Reports the space of the replica serving reads.
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        self.read_from(DynamicFileSystem::space)
+    }
+
+    /// Reports how the first replica to apply the clone produced it.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        self.write_to(dst, |fs| fs.clone_file(src, dst))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        self.write_to(to, |fs| fs.rename(from, to))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn hash_file(&self, path: &str, algorithm: HashAlgorithm) -> FileSystemResult<ContentDigest> {
+        self.read_from(|fs| fs.hash_file(path, algorithm))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        self.read_from(|fs| fs.read(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_to_string(&self, path: &str) -> FileSystemResult<String> {
+        self.read_from(|fs| fs.read_to_string(path))
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        self.write_to(path, |fs| fs.write(path, contents))
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn append(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        self.write_to(path, |fs| fs.append(path, contents))
+    }
+}
+
+/// Replicating File Handle
+///
+/// Holds a handle on every replica that opened the file, keeping its own cursor and using
+/// positional I/O on them. Reads are served by the first handle, falling over to the next when
+/// it faults, and writes go to every handle under the policy of the [`ReplicatedFileSystem`].
+/// Handles whose replica fails a write the others applied are dropped.
+#[derive(Debug)]
+pub struct ReplicatedFileHandle {
+    path: String,
+    handles: Vec<(usize, Box<dyn FileHandle>)>,
+    required: usize,
+    state: Arc<ReplicaState>,
+    cursor: u64,
+    append: bool,
+}
+
+impl ReplicatedFileHandle {
+    /// Number of replicas this handle still writes to.
+    #[must_use]
+    pub fn replicas(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Perform a read on the first handle which doesn't fail with a fault.
+    fn read_from<T>(
+        &mut self,
+        mut read: impl FnMut(&mut dyn FileHandle) -> FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        loop {
+            let spare = self.handles.len() > 1;
+            let (replica, handle) = self
+                .handles
+                .first_mut()
+                .ok_or(FileSystemError::InvalidOperation)?;
+            match read(handle.as_mut()) {
+                Err(err) if is_fault(&err) && spare => {
+                    tracing::warn!(replica, %err, "Replica failed a read");
+                    let replica = *replica;
+                    self.state.fail(replica);
+                    self.handles.remove(0);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Perform a write on every handle, keeping only those which applied it if enough did.
+    fn write_to<T>(
+        &mut self,
+        mut write: impl FnMut(&mut dyn FileHandle) -> FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        let results = self
+            .handles
+            .iter_mut()
+            .map(|(replica, handle)| (*replica, write(handle.as_mut())))
+            .collect();
+        let mut applied = self.state.settle(&self.path, self.required, results)?;
+        self.handles
+            .retain(|(replica, _)| applied.iter().any(|(applied, _)| applied == replica));
+        Ok(applied.swap_remove(0).1)
+    }
+
+    /// Write all of `buffer` at `offset` to every handle.
+    fn write_all_at(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.write_to(|handle| {
+            let mut written = 0;
+            while written < buffer.len() {
+                match handle.write_to_offset(offset + written as u64, &buffer[written..])? {
+                    0 => {
+                        return Err(FileSystemError::io_error(
+                            std::io::ErrorKind::WriteZero.into(),
+                        ))
+                    }
+                    count => written += count,
+                }
+            }
+            Ok(written)
+        })
+    }
+}
+
+impl Read for ReplicatedFileHandle {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let cursor = self.cursor;
+        let read = self.read_from(|handle| handle.read_at_offset(cursor, buf))?;
+        self.cursor += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for ReplicatedFileHandle {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.append {
+            self.cursor = self.get_size()?;
+        }
+        let written = self.write_all_at(self.cursor, buf)?;
+        self.cursor += written as u64;
+        Ok(written)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_to(|handle| handle.flush().map_err(FileSystemError::io_error))?;
+        Ok(())
+    }
+}
+
+impl Seek for ReplicatedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let cursor = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.cursor.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.get_size()?.checked_add_signed(delta),
+        };
+        self.cursor = cursor.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Seek before start of file",
+            )
+        })?;
+        Ok(self.cursor)
+    }
+}
+
+impl FileHandle for ReplicatedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        let (_, handle) = self
+            .handles
+            .first()
+            .ok_or(FileSystemError::InvalidOperation)?;
+        handle.get_size()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.write_to(|handle| handle.set_size(new_size))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.write_to(|handle| handle.allocate(len))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.write_to(FileHandle::sync_all)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.write_to(FileHandle::sync_data)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        let (_, handle) = self
+            .handles
+            .first()
+            .ok_or(FileSystemError::InvalidOperation)?;
+        handle.get_lock_status()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.write_to(|handle| handle.set_lock_status(mode))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.write_to(|handle| handle.try_set_lock_status(mode))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        let (_, handle) = self
+            .handles
+            .first()
+            .ok_or(FileSystemError::InvalidOperation)?;
+        handle.alignment()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.write_to(|handle| handle.lock_range(offset, len, mode))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.write_to(|handle| handle.unlock_range(offset, len))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        self.read_from(|handle| handle.advise(offset, len, advice))
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        self.read_from(|handle| handle.read_at_offset(offset, buffer))
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.write_all_at(offset, buffer)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        self.read_from(|handle| handle.map_readonly(offset, len))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_replicated_quorum() {
+        use crate::{
+            FileHandle, FileSystem, FileSystemError, MemoryFileSystem, Permissions,
+            ReplicatedFileSystem, ReplicationPolicy,
+        };
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        let first = MemoryFileSystem::new();
+        let second = MemoryFileSystem::new();
+        let fs = ReplicatedFileSystem::new(first.clone())
+            .with_replica(second.clone())
+            .with_policy(ReplicationPolicy::Quorum(1))
+            .with_preferred(1);
+        assert_eq!(fs.replicas(), 2);
+        {
+            let mut file = fs.create_file("/table.dat").unwrap();
+            assert_eq!(file.replicas(), 2);
+            file.write_all(b"Hello, World!").unwrap();
+            file.seek(SeekFrom::Start(7)).unwrap();
+            let mut buf = String::new();
+            file.read_to_string(&mut buf).unwrap();
+            assert_eq!(buf, "World!");
+        }
+        assert_eq!(first.read("/table.dat").unwrap(), b"Hello, World!");
+        assert_eq!(second.read("/table.dat").unwrap(), b"Hello, World!");
+
+        // A replica refusing a write the other applies diverges and stops serving reads
+        second
+            .set_permissions("/", Permissions::new().readonly(true))
+            .unwrap();
+        fs.write("/new.dat", b"new").unwrap();
+        assert!(fs.is_healthy(0));
+        assert!(!fs.is_healthy(1));
+        assert_eq!(fs.read("/new.dat").unwrap(), b"new");
+        fs.mark_healthy(1);
+        assert!(matches!(
+            fs.read("/new.dat"),
+            Err(FileSystemError::PathMissing)
+        ));
+
+        // Every replica must apply writes under the default policy
+        let fs = ReplicatedFileSystem::new(first.clone()).with_replica(second.clone());
+        assert_eq!(fs.policy(), ReplicationPolicy::All);
+        assert!(matches!(
+            fs.write("/other.dat", b"other"),
+            Err(FileSystemError::PermissionDenied)
+        ));
+        assert!(!fs.is_healthy(1));
+        assert!(matches!(
+            fs.remove_file("/missing"),
+            Err(FileSystemError::PathMissing)
+        ));
+        {
+            // Handles stop writing to replicas which fail
+            let fs = ReplicatedFileSystem::new(first.clone())
+                .with_replica(second.clone())
+                .with_policy(ReplicationPolicy::Quorum(1));
+            let mut file = fs.open_file("/table.dat").unwrap();
+            assert_eq!(file.replicas(), 2);
+            second
+                .set_permissions("/table.dat", Permissions::new().readonly(true))
+                .unwrap();
+            file.write_all(b"Jello").unwrap();
+            assert_eq!(file.replicas(), 1);
+            assert_eq!(file.get_size().unwrap(), 13);
+        }
+        assert_eq!(first.read("/table.dat").unwrap(), b"Jello, World!");
+        assert_eq!(second.read("/table.dat").unwrap(), b"Hello, World!");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_replicated_conformance() {
+        use crate::{MemoryFileSystem, ReplicatedFileSystem};
+
+        crate::conformance::run(|| {
+            ReplicatedFileSystem::new(MemoryFileSystem::new()).with_replica(MemoryFileSystem::new())
+        });
+    }
+}
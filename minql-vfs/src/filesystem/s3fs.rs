@@ -0,0 +1,314 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::httpstore::{
+    hex, hmac_sha256, query_string, send, uri_encode, xml_abort_multipart, xml_complete_multipart,
+    xml_create_multipart, xml_delete, xml_get_range, xml_get_versioned, xml_head, xml_list,
+    xml_put, xml_put_if, xml_upload_part, CivilTime, HttpObjectClient, HttpResponse, ListPaging,
+};
+use crate::filesystem::objectfs::{ObjectListing, ObjectMeta, ObjectStore, PutCondition};
+use crate::{FileSystemError, FileSystemProvider, FileSystemResult, ObjectStoreFileSystem};
+use minql_uri::URI;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+/// S3 Compatible Object Store Client
+///
+/// Issues path-style requests (`{endpoint}/{bucket}/{key}`) signed with AWS Signature Version 4,
+/// which works against AWS S3 as well as S3 compatible stores such as `MinIO` and Ceph.
+pub struct S3ObjectStore {
+    endpoint: String,
+    region: String,
+    bucket: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl S3ObjectStore {
+    /// Create a new S3 client for a bucket.
+    #[must_use]
+    pub fn new(
+        endpoint: &str,
+        region: &str,
+        bucket: &str,
+        access_key_id: &str,
+        secret_access_key: &str,
+    ) -> S3ObjectStore {
+        S3ObjectStore {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            region: region.to_string(),
+            bucket: bucket.to_string(),
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+            session_token: None,
+            agent: ureq::AgentBuilder::new().build(),
+        }
+    }
+
+    /// Attach a session token for temporary credentials.
+    #[must_use]
+    pub fn with_session_token(mut self, token: &str) -> S3ObjectStore {
+        self.session_token = Some(token.to_string());
+        self
+    }
+}
+
+impl HttpObjectClient for S3ObjectStore {
+    const SERVICE: &'static str = "S3";
+    const PAGING: ListPaging = ListPaging::ContinuationToken;
+    const VERSION_HEADER: &'static str = "etag";
+
+    fn condition_header(condition: &PutCondition) -> (&'static str, String) {
+        match condition {
+            PutCondition::Absent => ("if-none-match", String::from("*")),
+            PutCondition::Matches(version) => ("if-match", version.clone()),
+        }
+    }
+
+    /// Sign and send a request.
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> FileSystemResult<HttpResponse> {
+        let host = self
+            .endpoint
+            .split_once("://")
+            .map_or(self.endpoint.as_str(), |(_, host)| host);
+        let canonical_uri = format!(
+            "/{}/{}",
+            uri_encode(&self.bucket, false),
+            uri_encode(key, false)
+        );
+        let canonical_query = query_string(query);
+
+        let (amz_date, date) = amz_timestamp(SystemTime::now());
+        let payload_hash = hex(&Sha256::digest(body));
+        let mut signed = BTreeMap::new();
+        signed.insert("host".to_string(), host.to_string());
+        signed.insert("x-amz-content-sha256".to_string(), payload_hash.clone());
+        signed.insert("x-amz-date".to_string(), amz_date.clone());
+        if let Some(token) = &self.session_token {
+            signed.insert("x-amz-security-token".to_string(), token.clone());
+        }
+        for (name, value) in headers {
+            signed.insert(name.to_ascii_lowercase(), value.trim().to_string());
+        }
+        let mut canonical_headers = String::new();
+        for (name, value) in &signed {
+            let _ = writeln!(canonical_headers, "{name}:{value}");
+        }
+        let signed_headers = signed.keys().cloned().collect::<Vec<_>>().join(";");
+        let canonical_request = format!(
+            "{method}\n{canonical_uri}\n{canonical_query}\n{canonical_headers}\n{signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut key_bytes = hmac_sha256(
+            format!("AWS4{}", self.secret_access_key).as_bytes(),
+            date.as_bytes(),
+        );
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key_bytes = hmac_sha256(&key_bytes, part.as_bytes());
+        }
+        let signature = hex(&hmac_sha256(&key_bytes, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key_id
+        );
+
+        let mut url = format!("{}{canonical_uri}", self.endpoint);
+        if !canonical_query.is_empty() {
+            url.push('?');
+            url.push_str(&canonical_query);
+        }
+        let headers = signed
+            .iter()
+            .filter(|(name, _)| name.as_str() != "host")
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .chain([("Authorization", authorization.as_str())]);
+        send(&self.agent, method, &url, headers, body)
+    }
+}
+
+impl std::fmt::Debug for S3ObjectStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "S3ObjectStore({}/{})", self.endpoint, self.bucket)
+    }
+}
+
+impl ObjectStore for S3ObjectStore {
+    fn head(&self, key: &str) -> FileSystemResult<Option<ObjectMeta>> {
+        xml_head(self, key)
+    }
+
+    fn get_range(&self, key: &str, offset: u64, length: u64) -> FileSystemResult<Vec<u8>> {
+        xml_get_range(self, key, offset, length)
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> FileSystemResult<()> {
+        xml_put(self, key, data)
+    }
+
+    fn delete(&self, key: &str) -> FileSystemResult<()> {
+        xml_delete(self, key)
+    }
+
+    fn list(&self, prefix: &str, delimiter: Option<char>) -> FileSystemResult<ObjectListing> {
+        xml_list(self, prefix, delimiter)
+    }
+
+    fn create_multipart(&self, key: &str) -> FileSystemResult<String> {
+        xml_create_multipart(self, key)
+    }
+
+    fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: &[u8],
+    ) -> FileSystemResult<String> {
+        xml_upload_part(self, key, upload_id, part_number, data)
+    }
+
+    fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[(u32, String)],
+    ) -> FileSystemResult<()> {
+        xml_complete_multipart(self, key, upload_id, parts)
+    }
+
+    fn abort_multipart(&self, key: &str, upload_id: &str) -> FileSystemResult<()> {
+        xml_abort_multipart(self, key, upload_id)
+    }
+
+    fn get_versioned(&self, key: &str) -> FileSystemResult<Option<(Vec<u8>, String)>> {
+        xml_get_versioned(self, key)
+    }
+
+    fn put_if(
+        &self,
+        key: &str,
+        data: &[u8],
+        condition: &PutCondition,
+    ) -> FileSystemResult<Option<String>> {
+        xml_put_if(self, key, data, condition)
+    }
+}
+
+/// S3 `FileSystem` Provider
+///
+/// Provisions an [`ObjectStoreFileSystem`] for `s3://bucket/prefix` URIs. Recognized
+/// configuration keys are `endpoint`, `region`, `access_key_id`, `secret_access_key`, and
+/// `session_token`; credentials fall back to the standard `AWS_*` environment variables.
+#[derive(Debug, Default)]
+pub struct S3FileSystemProvider {
+    configuration: RwLock<HashMap<String, String>>,
+}
+
+impl S3FileSystemProvider {
+    fn setting(&self, key: &str, env: &str) -> Option<String> {
+        let configuration = self.configuration.read().expect("Poisoned Lock");
+        configuration
+            .get(key)
+            .cloned()
+            .or_else(|| std::env::var(env).ok())
+    }
+}
+
+impl FileSystemProvider for S3FileSystemProvider {
+    type FileSystem = ObjectStoreFileSystem;
+
+    fn schemes(&self) -> &[&str] {
+        &["s3"]
+    }
+
+    fn configure(&self, configuration: &HashMap<String, String>) -> FileSystemResult<()> {
+        let mut current = self.configuration.write().expect("Poisoned Lock");
+        current.extend(configuration.clone());
+        Ok(())
+    }
+
+    fn provision(&self, url: &str) -> FileSystemResult<ObjectStoreFileSystem> {
+        let uri = URI::parse(url)?;
+        let bucket = uri
+            .authority
+            .as_ref()
+            .map(|authority| authority.hostinfo.raw())
+            .ok_or_else(|| FileSystemError::invalid_path(url))?;
+        let region = self
+            .setting("region", "AWS_REGION")
+            .unwrap_or_else(|| String::from("us-east-1"));
+        let endpoint = self
+            .setting("endpoint", "AWS_ENDPOINT_URL")
+            .unwrap_or_else(|| format!("https://s3.{region}.amazonaws.com"));
+        let access_key_id = self
+            .setting("access_key_id", "AWS_ACCESS_KEY_ID")
+            .unwrap_or_default();
+        let secret_access_key = self
+            .setting("secret_access_key", "AWS_SECRET_ACCESS_KEY")
+            .unwrap_or_default();
+        let mut store = S3ObjectStore::new(
+            &endpoint,
+            &region,
+            &bucket,
+            &access_key_id,
+            &secret_access_key,
+        );
+        if let Some(token) = self.setting("session_token", "AWS_SESSION_TOKEN") {
+            store = store.with_session_token(&token);
+        }
+        Ok(ObjectStoreFileSystem::new(store, &uri.path.to_string()))
+    }
+}
+
+/// Format a timestamp as the `x-amz-date` and credential scope date.
+fn amz_timestamp(time: SystemTime) -> (String, String) {
+    let time = CivilTime::new(time);
+    let date = format!("{:04}{:02}{:02}", time.year, time.month, time.day);
+    let stamp = format!(
+        "{date}T{:02}{:02}{:02}Z",
+        time.hour, time.minute, time.second
+    );
+    (stamp, date)
+}
+
+#[cfg(test)]
+mod test {
+    use super::amz_timestamp;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_s3_helpers() {
+        let (stamp, date) = amz_timestamp(UNIX_EPOCH + Duration::from_mins(24_015_636));
+        assert_eq!(stamp, "20150830T123600Z");
+        assert_eq!(date, "20150830");
+    }
+}
@@ -0,0 +1,462 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::utility::{join_segments, normalize_segments};
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Scoped `FileSystem` Wrapper
+///
+/// Exposes a single subtree of another [`FileSystem`] as its root. Every path is normalized
+/// before being rewritten under the prefix, and any path using `..` to climb above the scoped
+/// root is rejected with [`FileSystemError::InvalidPath`](crate::FileSystemError::InvalidPath).
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, ScopedFileSystem};
+///
+/// let inner = MemoryFileSystem::new();
+/// inner.create_directory_all("/some/prefix").unwrap();
+/// let fs = ScopedFileSystem::new(inner, "/some/prefix");
+///
+/// fs.create_file("/test.txt").expect("Error Creating File");
+/// assert!(fs.exists("/test.txt").unwrap());
+/// assert!(fs.open_file("/../escape.txt").is_err());
+/// ```
+#[derive(Debug)]
+pub struct ScopedFileSystem {
+    prefix: Vec<String>,
+    inner: Arc<dyn DynamicFileSystem>,
+}
+
+impl ScopedFileSystem {
+    /// Create a new Scoped `FileSystem` rooted at `prefix` within `filesystem`.
+    ///
+    /// The prefix is normalized, with any `..` segments that climb above the root of the inner
+    /// filesystem being discarded.
+    pub fn new<F: FileSystem>(filesystem: F, prefix: &str) -> ScopedFileSystem {
+        let mut segments: Vec<String> = Vec::new();
+        for segment in prefix.split(['/', '\\']) {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    segments.pop();
+                }
+                segment => segments.push(segment.to_string()),
+            }
+        }
+        ScopedFileSystem {
+            prefix: segments,
+            inner: Arc::new(filesystem),
+        }
+    }
+
+    /// Get the normalized prefix this `FileSystem` is scoped to.
+    #[must_use]
+    pub fn prefix(&self) -> String {
+        join_segments(&self.prefix)
+    }
+
+    /// Rewrite a scoped path into a path on the inner filesystem.
+    fn resolve(&self, path: &str) -> FileSystemResult<String> {
+        let segments = normalize_segments(path)?;
+        let mut resolved = self.prefix.clone();
+        resolved.extend(segments.iter().map(ToString::to_string));
+        Ok(join_segments(&resolved))
+    }
+}
+
+impl FileSystem for ScopedFileSystem {
+    type FileHandle = ScopedFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::exists(self.inner.as_ref(), &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::is_file(self.inner.as_ref(), &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::is_directory(self.inner.as_ref(), &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        DynamicFileSystem::filesize(self.inner.as_ref(), &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_directory(self.inner.as_ref(), &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_directory_all(self.inner.as_ref(), &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        DynamicFileSystem::list_directory(self.inner.as_ref(), &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_directory(self.inner.as_ref(), &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_directory_all(self.inner.as_ref(), &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let resolved = self.resolve(path)?;
+        Ok(ScopedFileHandle {
+            path: join_segments(&normalize_segments(path)?),
+            inner: DynamicFileSystem::create_file(self.inner.as_ref(), &resolved)?,
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let resolved = self.resolve(path)?;
+        Ok(ScopedFileHandle {
+            path: join_segments(&normalize_segments(path)?),
+            inner: DynamicFileSystem::open_file(self.inner.as_ref(), &resolved)?,
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_file(self.inner.as_ref(), &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        DynamicFileSystem::file_type(self.inner.as_ref(), &self.resolve(path)?, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        let target = if target.starts_with(['/', '\\']) {
+            self.resolve(target)?
+        } else {
+            target.to_string()
+        };
+        DynamicFileSystem::create_symlink(self.inner.as_ref(), &target, &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        let target = DynamicFileSystem::read_link(self.inner.as_ref(), &self.resolve(path)?)?;
+        if !target.starts_with(['/', '\\']) {
+            return Ok(target);
+        }
+        let segments = normalize_segments(&target)?;
+        if segments.len() < self.prefix.len() || segments[..self.prefix.len()] != self.prefix[..] {
+            return Err(FileSystemError::InvalidPath(target));
+        }
+        Ok(join_segments(&segments[self.prefix.len()..]))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        DynamicFileSystem::permissions(self.inner.as_ref(), &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        DynamicFileSystem::modified(self.inner.as_ref(), &self.resolve(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        DynamicFileSystem::set_permissions(self.inner.as_ref(), &self.resolve(path)?, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        let resolved = self.resolve(path)?;
+        Ok(ScopedFileHandle {
+            path: join_segments(&normalize_segments(path)?),
+            inner: DynamicFileSystem::open_with(self.inner.as_ref(), &resolved, options)?,
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        DynamicFileSystem::clone_file(
+            self.inner.as_ref(),
+            &self.resolve(src)?,
+            &self.resolve(dst)?,
+        )
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::rename(
+            self.inner.as_ref(),
+            &self.resolve(from)?,
+            &self.resolve(to)?,
+        )
+    }
+
+    /// Operations whose paths escape the scope fail on their own; the rest are applied as one
+    /// batch by the inner filesystem.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let mut resolved = Vec::with_capacity(batch.len());
+        let mut results: Vec<Option<FileSystemResult<()>>> = Vec::with_capacity(batch.len());
+        for operation in batch {
+            match operation.map_paths(|path| self.resolve(path)) {
+                Ok(operation) => {
+                    resolved.push(operation);
+                    results.push(None);
+                }
+                Err(error) => results.push(Some(Err(error))),
+            }
+        }
+        let mut applied = DynamicFileSystem::apply(self.inner.as_ref(), &resolved).into_iter();
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| applied.next().expect("Missing Batch Result")))
+            .collect()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        DynamicFileSystem::space(self.inner.as_ref())
+    }
+}
+
+/// Scoped File Handle
+///
+/// Reports its path relative to the root of the [`ScopedFileSystem`] that opened it.
+pub struct ScopedFileHandle {
+    path: String,
+    inner: Box<dyn FileHandle>,
+}
+
+impl std::fmt::Debug for ScopedFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.inner.as_ref(), f)
+    }
+}
+
+impl Read for ScopedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Read::read(self.inner.as_mut(), buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        Read::read_vectored(self.inner.as_mut(), bufs)
+    }
+}
+
+impl Write for ScopedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Write::write(self.inner.as_mut(), buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        Write::write_vectored(self.inner.as_mut(), bufs)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Write::flush(self.inner.as_mut())
+    }
+}
+
+impl Seek for ScopedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        Seek::seek(self.inner.as_mut(), pos)
+    }
+}
+
+impl FileHandle for ScopedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        FileHandle::get_size(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        FileHandle::set_size(self.inner.as_mut(), new_size)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        FileHandle::sync_all(self.inner.as_mut())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        FileHandle::sync_data(self.inner.as_mut())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        FileHandle::get_lock_status(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::try_set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        FileHandle::read_at_offset(self.inner.as_mut(), offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        FileHandle::write_to_offset(self.inner.as_mut(), offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::lock_range(self.inner.as_mut(), offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        FileHandle::unlock_range(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        FileHandle::advise(self.inner.as_mut(), offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        FileHandle::read_at_vectored(self.inner.as_mut(), offset, buffers)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        FileHandle::write_at_vectored(self.inner.as_mut(), offset, buffers)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        FileHandle::map_readonly(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        FileHandle::alignment(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        FileHandle::allocate(self.inner.as_mut(), len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{FileHandle, FileSystem, FileSystemError, MemoryFileSystem, ScopedFileSystem};
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_scoped_filesystem() {
+        let inner = MemoryFileSystem::new();
+        inner.create_directory_all("/some/prefix").unwrap();
+        let fs = ScopedFileSystem::new(inner.clone(), "/some/./prefix/");
+        assert_eq!(fs.prefix(), "/some/prefix");
+
+        {
+            // Create a file through the scope and check where it landed
+            let mut file = fs
+                .create_file("./nested/../test.txt")
+                .expect("Error Creating File");
+            assert_eq!(file.path(), "/test.txt");
+            file.write_all(b"Hello, World!").unwrap();
+            assert!(fs.exists("/test.txt").unwrap());
+            assert!(inner.exists("/some/prefix/test.txt").unwrap());
+        }
+        {
+            // Reopen and read back through the scope
+            let mut file = fs.open_file("test.txt").expect("Error Opening File");
+            let mut buf = Vec::new();
+            file.seek(SeekFrom::Start(0)).expect("Error Seeking File");
+            file.read_to_end(&mut buf).expect("Error Reading File");
+            assert_eq!(buf, b"Hello, World!");
+        }
+
+        // Paths escaping the prefix are rejected
+        for path in ["../test.txt", "/a/../../test.txt", "..\\..\\etc\\passwd"] {
+            assert!(
+                matches!(fs.open_file(path), Err(FileSystemError::InvalidPath(_))),
+                "{path} escaped the scope"
+            );
+        }
+
+        fs.remove_file("/test.txt").expect("Error Removing File");
+        assert!(!inner.exists("/some/prefix/test.txt").unwrap());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_scoped_conformance() {
+        use crate::{FileSystem, MemoryFileSystem, ScopedFileSystem};
+
+        crate::conformance::run(|| {
+            let fs = MemoryFileSystem::new();
+            fs.create_directory("/scope").unwrap();
+            ScopedFileSystem::new(fs, "/scope")
+        });
+    }
+}
@@ -0,0 +1,412 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::simulation::SimShared;
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemResult,
+    FileSystemSpace, FileType, LatencyModel, OpenOptions, Permissions, Simulation, SymlinkPolicy,
+};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Simulated `FileSystem` Wrapper
+///
+/// Charges every operation against another [`FileSystem`] to the virtual clock of a
+/// [`Simulation`] according to a [`LatencyModel`], making each one a deterministic scheduling
+/// point between simulated tasks. Wrap each backend with its own model to simulate a mix of
+/// fast and slow storage.
+#[derive(Debug)]
+pub struct SimulatedFileSystem {
+    simulation: Arc<SimShared>,
+    latency: LatencyModel,
+    inner: Arc<dyn DynamicFileSystem>,
+}
+
+impl SimulatedFileSystem {
+    /// Create a new Simulated `FileSystem` running `filesystem` within `simulation`.
+    pub fn new<F: FileSystem>(
+        filesystem: F,
+        simulation: &Simulation,
+        latency: LatencyModel,
+    ) -> SimulatedFileSystem {
+        SimulatedFileSystem {
+            simulation: simulation.shared(),
+            latency,
+            inner: Arc::new(filesystem),
+        }
+    }
+
+    fn advance(&self, operation: &str, path: &str) {
+        self.transfer(operation, path, 0);
+    }
+
+    fn transfer(&self, operation: &str, path: &str, bytes: usize) {
+        let latency = self.latency;
+        self.simulation
+            .advance(&format!("{operation} {path}"), |rng| {
+                latency.sample(rng, bytes, false)
+            });
+    }
+
+    fn wrap(&self, inner: Box<dyn FileHandle>) -> SimulatedFileHandle {
+        SimulatedFileHandle {
+            simulation: self.simulation.clone(),
+            latency: self.latency,
+            inner,
+        }
+    }
+}
+
+impl FileSystem for SimulatedFileSystem {
+    type FileHandle = SimulatedFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        self.advance("exists", path);
+        DynamicFileSystem::exists(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        self.advance("is_file", path);
+        DynamicFileSystem::is_file(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        self.advance("is_directory", path);
+        DynamicFileSystem::is_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.advance("filesize", path);
+        DynamicFileSystem::filesize(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.advance("create_directory", path);
+        DynamicFileSystem::create_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.advance("create_directory_all", path);
+        DynamicFileSystem::create_directory_all(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        self.advance("list_directory", path);
+        DynamicFileSystem::list_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.advance("remove_directory", path);
+        DynamicFileSystem::remove_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.advance("remove_directory_all", path);
+        DynamicFileSystem::remove_directory_all(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.advance("create_file", path);
+        Ok(self.wrap(DynamicFileSystem::create_file(self.inner.as_ref(), path)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.advance("open_file", path);
+        Ok(self.wrap(DynamicFileSystem::open_file(self.inner.as_ref(), path)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        self.advance("remove_file", path);
+        DynamicFileSystem::remove_file(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.advance("file_type", path);
+        DynamicFileSystem::file_type(self.inner.as_ref(), path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        self.advance("create_symlink", path);
+        DynamicFileSystem::create_symlink(self.inner.as_ref(), target, path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.advance("read_link", path);
+        DynamicFileSystem::read_link(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.advance("permissions", path);
+        DynamicFileSystem::permissions(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.advance("modified", path);
+        DynamicFileSystem::modified(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.advance("set_permissions", path);
+        DynamicFileSystem::set_permissions(self.inner.as_ref(), path, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        self.advance("open_with", path);
+        Ok(self.wrap(DynamicFileSystem::open_with(
+            self.inner.as_ref(),
+            path,
+            options,
+        )?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        DynamicFileSystem::space(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        self.advance("rename", from);
+        DynamicFileSystem::rename(self.inner.as_ref(), from, to)
+    }
+
+    /// Charged as transferring the whole source, since the inner filesystem may copy it.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        let size = DynamicFileSystem::filesize(self.inner.as_ref(), src)?;
+        self.transfer(
+            "clone_file",
+            src,
+            usize::try_from(size).unwrap_or(usize::MAX),
+        );
+        DynamicFileSystem::clone_file(self.inner.as_ref(), src, dst)
+    }
+
+    /// Charged as a single operation transferring every byte the batch writes.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let bytes = batch
+            .iter()
+            .map(|operation| match operation {
+                BatchOperation::Write { contents, .. } => contents.len(),
+                _ => 0,
+            })
+            .sum();
+        let path = batch.first().and_then(|operation| operation.paths().next());
+        self.transfer("apply", path.unwrap_or_default(), bytes);
+        DynamicFileSystem::apply(self.inner.as_ref(), batch)
+    }
+}
+
+/// Simulated File Handle
+///
+/// Charges reads, writes and syncs to the virtual clock of the [`Simulation`] that owns the
+/// [`SimulatedFileSystem`] which opened it.
+pub struct SimulatedFileHandle {
+    simulation: Arc<SimShared>,
+    latency: LatencyModel,
+    inner: Box<dyn FileHandle>,
+}
+
+impl SimulatedFileHandle {
+    fn advance(&self, operation: &str, bytes: usize, sync: bool) {
+        let latency = self.latency;
+        let operation = format!("{operation} {}", self.inner.path());
+        self.simulation
+            .advance(&operation, |rng| latency.sample(rng, bytes, sync));
+    }
+}
+
+impl std::fmt::Debug for SimulatedFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.inner.as_ref(), f)
+    }
+}
+
+impl Read for SimulatedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.advance("read", buf.len(), false);
+        Read::read(self.inner.as_mut(), buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        self.advance("read", bufs.iter().map(|buf| buf.len()).sum(), false);
+        Read::read_vectored(self.inner.as_mut(), bufs)
+    }
+}
+
+impl Write for SimulatedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.advance("write", buf.len(), false);
+        Write::write(self.inner.as_mut(), buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        self.advance("write", bufs.iter().map(|buf| buf.len()).sum(), false);
+        Write::write_vectored(self.inner.as_mut(), bufs)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Write::flush(self.inner.as_mut())
+    }
+}
+
+impl Seek for SimulatedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        Seek::seek(self.inner.as_mut(), pos)
+    }
+}
+
+impl FileHandle for SimulatedFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        FileHandle::path(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        FileHandle::get_size(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.advance("set_size", 0, false);
+        FileHandle::set_size(self.inner.as_mut(), new_size)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.advance("sync_all", 0, true);
+        FileHandle::sync_all(self.inner.as_mut())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.advance("sync_data", 0, true);
+        FileHandle::sync_data(self.inner.as_mut())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        FileHandle::get_lock_status(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.advance("set_lock_status", 0, false);
+        FileHandle::set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.advance("try_set_lock_status", 0, false);
+        FileHandle::try_set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        self.advance("read", buffer.len(), false);
+        FileHandle::read_at_offset(self.inner.as_mut(), offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.advance("write", buffer.len(), false);
+        FileHandle::write_to_offset(self.inner.as_mut(), offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.advance("lock_range", 0, false);
+        FileHandle::lock_range(self.inner.as_mut(), offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.advance("unlock_range", 0, false);
+        FileHandle::unlock_range(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        FileHandle::advise(self.inner.as_mut(), offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        self.advance("read", buffers.iter().map(|buf| buf.len()).sum(), false);
+        FileHandle::read_at_vectored(self.inner.as_mut(), offset, buffers)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        self.advance("write", buffers.iter().map(|buf| buf.len()).sum(), false);
+        FileHandle::write_at_vectored(self.inner.as_mut(), offset, buffers)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        self.advance("map_readonly", len, false);
+        FileHandle::map_readonly(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        FileHandle::alignment(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.advance("allocate", 0, false);
+        FileHandle::allocate(self.inner.as_mut(), len)
+    }
+}
@@ -0,0 +1,573 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::utility::{child_path, join_segments, normalize_path, normalize_segments};
+use crate::{
+    CloneMethod, ContentDigest, FileSystem, FileSystemError, FileSystemResult, FileSystemSpace,
+    FileType, HashAlgorithm, OpenOptions, Permissions, SymlinkPolicy, VirtualFileHandle,
+};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// How a [`StripedFileSystem`] picks the backend for a new file.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum StripePolicy {
+    /// Place a file by the hash of its path, so placement doesn't depend on creation order
+    #[default]
+    Hash,
+    /// Place files on each backend in turn
+    RoundRobin,
+}
+
+/// Striping `FileSystem` Wrapper
+///
+/// Spreads files across several backends to aggregate their capacity and bandwidth. Each file
+/// lives whole on one backend, chosen by its [`StripePolicy`] when it's created and kept when
+/// it's rewritten, renamed or cloned. Directories are mirrored on every backend, with the first
+/// backend holding the authoritative tree, and listings merge the entries of every backend.
+///
+/// The backend holding each file is kept in a small index, filled in by probing the backends
+/// the first time a path is looked up, so a striped filesystem can be reopened over backends
+/// populated earlier. Symbolic links are placed on the backend holding their target, which
+/// they're resolved within. Space is reported as the sum over every backend.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, StripePolicy, StripedFileSystem};
+///
+/// let disks = [MemoryFileSystem::new(), MemoryFileSystem::new()];
+/// let fs = StripedFileSystem::new(disks[0].clone())
+///     .with_backend(disks[1].clone())
+///     .with_policy(StripePolicy::RoundRobin);
+///
+/// fs.write("/scratch-0.dat", b"rows").unwrap();
+/// fs.write("/scratch-1.dat", b"rows").unwrap();
+/// assert_eq!(fs.placement("/scratch-0.dat").unwrap(), Some(0));
+/// assert_eq!(fs.placement("/scratch-1.dat").unwrap(), Some(1));
+/// assert!(disks[1].exists("/scratch-1.dat").unwrap());
+/// assert_eq!(fs.list_directory("/").unwrap(), ["scratch-0.dat", "scratch-1.dat"]);
+/// ```
+#[derive(Debug)]
+pub struct StripedFileSystem {
+    backends: Vec<Arc<dyn DynamicFileSystem>>,
+    policy: StripePolicy,
+    index: RwLock<HashMap<String, usize>>,
+    next: AtomicUsize,
+}
+
+impl StripedFileSystem {
+    /// Create a new Striping `FileSystem` over a single backend, which holds the directory tree.
+    pub fn new<F: FileSystem>(filesystem: F) -> StripedFileSystem {
+        StripedFileSystem {
+            backends: vec![Arc::new(filesystem)],
+            policy: StripePolicy::default(),
+            index: RwLock::default(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Add another backend to stripe files across.
+    ///
+    /// Files already placed by [`StripePolicy::Hash`] are found by probing rather than moved,
+    /// so adding a backend doesn't rebalance them.
+    #[must_use]
+    pub fn with_backend<F: FileSystem>(mut self, filesystem: F) -> StripedFileSystem {
+        self.backends.push(Arc::new(filesystem));
+        self
+    }
+
+    /// Set how new files are placed.
+    #[must_use]
+    pub fn with_policy(mut self, policy: StripePolicy) -> StripedFileSystem {
+        self.policy = policy;
+        self
+    }
+
+    /// How new files are placed.
+    #[must_use]
+    pub fn policy(&self) -> StripePolicy {
+        self.policy
+    }
+
+    /// Number of backends.
+    #[must_use]
+    pub fn backends(&self) -> usize {
+        self.backends.len()
+    }
+
+    /// Get the backend holding the file or symbolic link at `path`, by the order backends were
+    /// added in, or `None` if there's no file there.
+    pub fn placement(&self, path: &str) -> FileSystemResult<Option<usize>> {
+        self.locate(path)
+    }
+
+    /// Backend holding the directory tree.
+    fn primary(&self) -> &dyn DynamicFileSystem {
+        self.backends[0].as_ref()
+    }
+
+    /// Find the backend holding a file or symbolic link, from the index or else by probing.
+    fn locate(&self, path: &str) -> FileSystemResult<Option<usize>> {
+        let key = normalize_path(path)?;
+        if let Some(backend) = self.index.read().expect("Poisoned Lock").get(&key) {
+            return Ok(Some(*backend));
+        }
+        for (backend, filesystem) in self.backends.iter().enumerate() {
+            match filesystem.file_type(&key, SymlinkPolicy::NoFollow) {
+                Ok(FileType::Directory) => return Ok(None),
+                Ok(FileType::File | FileType::Symlink) => {
+                    self.record(&key, backend);
+                    return Ok(Some(backend));
+                }
+                Err(
+                    FileSystemError::PathMissing
+                    | FileSystemError::ParentMissing
+                    | FileSystemError::InvalidOperation,
+                ) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Backend an operation on an existing path goes to: the one holding the file, or the
+    /// primary for directories and missing paths.
+    fn owner(&self, path: &str) -> FileSystemResult<&dyn DynamicFileSystem> {
+        let backend = self.locate(path)?.unwrap_or(0);
+        Ok(self.backends[backend].as_ref())
+    }
+
+    /// Pick the backend for a new file, creating its parent directory there if it's missing.
+    ///
+    /// Existing files stay where they are, and directories go to the primary so it reports
+    /// the error.
+    fn target(&self, path: &str) -> FileSystemResult<usize> {
+        if let Some(backend) = self.locate(path)? {
+            return Ok(backend);
+        }
+        if self.primary().is_directory(path)? {
+            return Ok(0);
+        }
+        let backend = match self.policy {
+            StripePolicy::Hash => {
+                let hash = blake3::hash(normalize_path(path)?.as_bytes());
+                let mut prefix = [0; 8];
+                prefix.copy_from_slice(&hash.as_bytes()[..8]);
+                usize::try_from(u64::from_le_bytes(prefix) % self.backends.len() as u64)
+                    .expect("Backend Index")
+            }
+            StripePolicy::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+        } % self.backends.len();
+        self.prepare(backend, path)?;
+        Ok(backend)
+    }
+
+    /// Mirror the parent directory of `path` from the primary onto a backend missing it.
+    fn prepare(&self, backend: usize, path: &str) -> FileSystemResult<()> {
+        let segments = normalize_segments(path)?;
+        if backend == 0 || segments.is_empty() {
+            return Ok(());
+        }
+        let parent = join_segments(&segments[..segments.len() - 1]);
+        let filesystem = self.backends[backend].as_ref();
+        if self.primary().is_directory(&parent)? && !filesystem.is_directory(&parent)? {
+            filesystem.create_directory_all(&parent)?;
+        }
+        Ok(())
+    }
+
+    /// Remember which backend holds a path.
+    fn record(&self, path: &str, backend: usize) {
+        if let Ok(key) = normalize_path(path) {
+            self.index
+                .write()
+                .expect("Poisoned Lock")
+                .insert(key, backend);
+        }
+    }
+
+    /// Forget every indexed path at or beneath `path`.
+    fn forget(&self, path: &str) {
+        let Ok(key) = normalize_path(path) else {
+            return;
+        };
+        let prefix = child_path(&key, "");
+        self.index
+            .write()
+            .expect("Poisoned Lock")
+            .retain(|path, _| *path != key && !path.starts_with(&prefix));
+    }
+
+    /// Find a file standing where a directory would go along `path`, returning its backend.
+    fn blocking_file(&self, path: &str) -> FileSystemResult<Option<usize>> {
+        let segments = normalize_segments(path)?;
+        for depth in 1..=segments.len() {
+            if let Some(backend) = self.locate(&join_segments(&segments[..depth]))? {
+                return Ok(Some(backend));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Apply a directory operation to the primary, then mirror it on the other backends,
+    /// ignoring those missing the directory.
+    fn mirror(
+        &self,
+        operation: impl Fn(&dyn DynamicFileSystem) -> FileSystemResult<()>,
+    ) -> FileSystemResult<()> {
+        operation(self.primary())?;
+        for filesystem in &self.backends[1..] {
+            match operation(filesystem.as_ref()) {
+                Ok(()) | Err(FileSystemError::PathMissing) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FileSystem for StripedFileSystem {
+    type FileHandle = VirtualFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        Ok(self.locate(path)?.is_some() || self.primary().exists(path)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        match self.locate(path)? {
+            Some(backend) => self.backends[backend].is_file(path),
+            None => Ok(false),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        self.owner(path)?.is_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.owner(path)?.filesize(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        if let Some(backend) = self.blocking_file(path)? {
+            return self.backends[backend].create_directory(path);
+        }
+        self.primary().create_directory(path)?;
+        for filesystem in &self.backends[1..] {
+            filesystem.create_directory_all(path)?;
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        if let Some(backend) = self.blocking_file(path)? {
+            return self.backends[backend].create_directory_all(path);
+        }
+        self.backends
+            .iter()
+            .try_for_each(|filesystem| filesystem.create_directory_all(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        if let Some(backend) = self.locate(path)? {
+            return self.backends[backend].list_directory(path);
+        }
+        let mut entries = self
+            .primary()
+            .list_directory(path)?
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        for filesystem in &self.backends[1..] {
+            match filesystem.list_directory(path) {
+                Ok(listing) => entries.extend(listing),
+                Err(FileSystemError::PathMissing) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(entries.into_iter().collect())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        if let Some(backend) = self.locate(path)? {
+            return self.backends[backend].remove_directory(path);
+        }
+        for filesystem in &self.backends[1..] {
+            if filesystem
+                .list_directory(path)
+                .is_ok_and(|listing| !listing.is_empty())
+            {
+                return filesystem.remove_directory(path);
+            }
+        }
+        self.mirror(|filesystem| filesystem.remove_directory(path))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        if let Some(backend) = self.locate(path)? {
+            return self.backends[backend].remove_directory_all(path);
+        }
+        let result = self.mirror(|filesystem| filesystem.remove_directory_all(path));
+        self.forget(path);
+        result
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let backend = self.target(path)?;
+        let handle = self.backends[backend].create_file(path)?;
+        self.record(path, backend);
+        Ok(VirtualFileHandle(handle))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        Ok(VirtualFileHandle(self.owner(path)?.open_file(path)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        self.owner(path)?.remove_file(path)?;
+        self.forget(path);
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.owner(path)?.file_type(path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        if let Some(backend) = self.locate(path)? {
+            return self.backends[backend].create_symlink(target, path);
+        }
+        let segments = normalize_segments(path)?;
+        let resolved = if target.starts_with('/') || segments.is_empty() {
+            target.to_string()
+        } else {
+            child_path(&join_segments(&segments[..segments.len() - 1]), target)
+        };
+        let backend = self.locate(&resolved).ok().flatten().unwrap_or(0);
+        self.prepare(backend, path)?;
+        self.backends[backend].create_symlink(target, path)?;
+        self.record(path, backend);
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.owner(path)?.read_link(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.owner(path)?.permissions(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.owner(path)?.modified(path)
+    }
+
+    /// Permissions of directories are set on every backend.
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        match self.locate(path)? {
+            Some(backend) => self.backends[backend].set_permissions(path, permissions),
+            None => self.mirror(|filesystem| filesystem.set_permissions(path, permissions)),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        if !options.is_create() && !options.is_create_new() {
+            return Ok(VirtualFileHandle(
+                self.owner(path)?.open_with(path, options)?,
+            ));
+        }
+        let backend = self.target(path)?;
+        let handle = self.backends[backend].open_with(path, options)?;
+        self.record(path, backend);
+        Ok(VirtualFileHandle(handle))
+    }
+
+    /// Reports the combined space of every backend.
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        let mut space = FileSystemSpace::default();
+        for filesystem in &self.backends {
+            let backend = filesystem.space()?;
+            space.total = space.total.saturating_add(backend.total);
+            space.used = space.used.saturating_add(backend.used);
+            space.available = space.available.saturating_add(backend.available);
+        }
+        Ok(space)
+    }
+
+    /// Clones are placed on the backend holding the source, so they can share its blocks.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        if self.locate(dst)?.is_some() || self.primary().exists(dst)? {
+            return Err(FileSystemError::PathExists);
+        }
+        let backend = self.locate(src)?.unwrap_or(0);
+        self.prepare(backend, dst)?;
+        let method = self.backends[backend].clone_file(src, dst)?;
+        self.record(dst, backend);
+        Ok(method)
+    }
+
+    /// Renamed files stay on the backend holding them, replacing any file at `to` on another.
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        let Some(backend) = self.locate(from)? else {
+            return self.primary().rename(from, to);
+        };
+        if self.primary().is_directory(to)? {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        match self.locate(to)? {
+            Some(existing) if existing != backend => self.backends[existing].remove_file(to)?,
+            _ => {}
+        }
+        self.prepare(backend, to)?;
+        self.backends[backend].rename(from, to)?;
+        self.forget(from);
+        self.forget(to);
+        self.record(to, backend);
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn hash_file(&self, path: &str, algorithm: HashAlgorithm) -> FileSystemResult<ContentDigest> {
+        self.owner(path)?.hash_file(path, algorithm)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        self.owner(path)?.read(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_to_string(&self, path: &str) -> FileSystemResult<String> {
+        self.owner(path)?.read_to_string(path)
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        let backend = self.target(path)?;
+        self.backends[backend].write(path, contents)?;
+        self.record(path, backend);
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn append(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        let backend = self.target(path)?;
+        self.backends[backend].append(path, contents)?;
+        self.record(path, backend);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_striped_placement() {
+        use crate::{FileSystem, MemoryFileSystem, StripePolicy, StripedFileSystem};
+
+        let disks = [
+            MemoryFileSystem::new(),
+            MemoryFileSystem::new(),
+            MemoryFileSystem::new(),
+        ];
+        let fs = StripedFileSystem::new(disks[0].clone())
+            .with_backend(disks[1].clone())
+            .with_backend(disks[2].clone())
+            .with_policy(StripePolicy::RoundRobin);
+        assert_eq!(fs.backends(), 3);
+        fs.create_directory_all("/scratch/sort").unwrap();
+        for index in 0..6 {
+            fs.write(&format!("/scratch/sort/run-{index}"), b"run")
+                .unwrap();
+        }
+        for (index, disk) in disks.iter().enumerate() {
+            assert!(disk.is_directory("/scratch/sort").unwrap());
+            assert_eq!(
+                disk.list_directory("/scratch/sort").unwrap(),
+                [format!("run-{index}"), format!("run-{}", index + 3)]
+            );
+        }
+        assert_eq!(fs.list_directory("/scratch/sort").unwrap().len(), 6);
+
+        // Files stay on their backend when rewritten, renamed or cloned
+        fs.write("/scratch/sort/run-1", b"rewritten").unwrap();
+        fs.rename("/scratch/sort/run-1", "/scratch/merged").unwrap();
+        fs.clone_file("/scratch/merged", "/scratch/copy").unwrap();
+        assert_eq!(fs.placement("/scratch/merged").unwrap(), Some(1));
+        assert_eq!(fs.placement("/scratch/copy").unwrap(), Some(1));
+        assert_eq!(disks[1].read("/scratch/copy").unwrap(), b"rewritten");
+        fs.rename("/scratch/sort/run-0", "/scratch/merged").unwrap();
+        assert_eq!(fs.placement("/scratch/merged").unwrap(), Some(0));
+        assert!(!disks[1].exists("/scratch/merged").unwrap());
+
+        // Files are found by probing when reopened, and hashing places by path alone
+        let reopened = StripedFileSystem::new(disks[0].clone())
+            .with_backend(disks[1].clone())
+            .with_backend(disks[2].clone());
+        assert_eq!(reopened.policy(), StripePolicy::Hash);
+        assert_eq!(reopened.placement("/scratch/sort/run-5").unwrap(), Some(2));
+        assert_eq!(reopened.read("/scratch/sort/run-5").unwrap(), b"run");
+        reopened.write("/hashed", b"hashed").unwrap();
+        let placed = reopened.placement("/hashed").unwrap().unwrap();
+        let other = StripedFileSystem::new(MemoryFileSystem::new())
+            .with_backend(MemoryFileSystem::new())
+            .with_backend(MemoryFileSystem::new());
+        other.write("/hashed", b"hashed").unwrap();
+        assert_eq!(other.placement("/hashed").unwrap(), Some(placed));
+
+        reopened.remove_directory_all("/scratch").unwrap();
+        assert!(disks.iter().all(|disk| !disk.exists("/scratch").unwrap()));
+        assert_eq!(reopened.placement("/scratch/merged").unwrap(), None);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_striped_conformance() {
+        use crate::{MemoryFileSystem, StripePolicy, StripedFileSystem};
+
+        for policy in [StripePolicy::Hash, StripePolicy::RoundRobin] {
+            crate::conformance::run(|| {
+                StripedFileSystem::new(MemoryFileSystem::new())
+                    .with_backend(MemoryFileSystem::new())
+                    .with_backend(MemoryFileSystem::new())
+                    .with_policy(policy)
+            });
+        }
+    }
+}
@@ -0,0 +1,730 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::collections::HashMap;
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime};
+
+/// When a [`SyncFileHandle`] makes the data written through it durable.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum SyncPolicy {
+    /// Never sync, even when asked through [`FileHandle::sync_data`] or
+    /// [`FileHandle::sync_all`]. Only suited to data that can be rebuilt after a crash.
+    Never,
+    /// Only sync when asked through [`FileHandle::sync_data`] or [`FileHandle::sync_all`].
+    OnRequest,
+    /// Sync when a handle that was written to is dropped.
+    #[default]
+    OnClose,
+    /// Sync after every write before returning from it.
+    EveryWrite,
+    /// Sync on the first write once the interval has passed since the last sync, and when a
+    /// handle that was written to is dropped.
+    Interval(Duration),
+}
+
+/// Coalesces concurrent sync requests into a single sync.
+///
+/// Each caller of [`GroupCommit::sync`] is covered by the first sync to start after it asked.
+/// While one caller leads a sync, later callers wait for it to finish and the first of them to
+/// wake leads one more sync on behalf of them all, so a burst of commits costs two syncs rather
+/// than one each. A failed sync covers nobody, and every caller it was meant to cover retries
+/// with its own.
+///
+/// ```rust
+/// use minql_vfs::{FileHandle, FileSystem, GroupCommit, MemoryFileSystem};
+/// use std::io::Write;
+///
+/// let fs = MemoryFileSystem::new();
+/// let group = GroupCommit::new();
+/// let mut file = fs.create_file("/commit.log").unwrap();
+/// file.write_all(b"commit").unwrap();
+/// group.sync(|| file.sync_data()).unwrap();
+/// assert_eq!(group.syncs(), 1);
+/// ```
+#[derive(Debug, Default)]
+pub struct GroupCommit {
+    state: Mutex<GroupCommitState>,
+    completed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct GroupCommitState {
+    /// Requests made so far, the ticket of the latest caller.
+    requested: u64,
+    /// Every request up to this ticket is covered by a successful sync.
+    completed: u64,
+    syncing: bool,
+    syncs: u64,
+}
+
+impl GroupCommit {
+    /// Create a new Group Commit with no requests.
+    #[must_use]
+    pub fn new() -> GroupCommit {
+        GroupCommit::default()
+    }
+
+    /// Wait until a sync started after this call succeeds, running `sync` if no other caller is
+    /// already running one that will cover it.
+    ///
+    /// Every caller must pass a `sync` making the same data durable, such as `sync_data` on
+    /// handles of the same file.
+    #[tracing::instrument(level = "trace", skip(sync))]
+    pub fn sync<F: FnOnce() -> FileSystemResult<()>>(&self, sync: F) -> FileSystemResult<()> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        state.requested += 1;
+        let ticket = state.requested;
+        loop {
+            if state.completed >= ticket {
+                return Ok(());
+            }
+            if !state.syncing {
+                break;
+            }
+            state = self.completed.wait(state).expect("Poisoned Lock");
+        }
+        state.syncing = true;
+        let covered = state.requested;
+        drop(state);
+
+        let result = sync();
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        state.syncing = false;
+        state.syncs += 1;
+        if result.is_ok() {
+            state.completed = state.completed.max(covered);
+        }
+        drop(state);
+        self.completed.notify_all();
+        result
+    }
+
+    /// Get the number of sync requests made.
+    #[must_use]
+    pub fn requests(&self) -> u64 {
+        self.state.lock().expect("Poisoned Lock").requested
+    }
+
+    /// Get the number of syncs run on behalf of those requests.
+    #[must_use]
+    pub fn syncs(&self) -> u64 {
+        self.state.lock().expect("Poisoned Lock").syncs
+    }
+}
+
+/// Durability Policy `FileSystem` Wrapper
+///
+/// Syncs the files written through it according to a [`SyncPolicy`], which each handle can
+/// override. Syncs of the same file from different handles are coalesced through a
+/// [`GroupCommit`], so the inner filesystem must make a sync through any handle durable for the
+/// writes of every handle on the file, as OS files do. Handles are flushed before they sync.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, SyncFileSystem, SyncPolicy};
+/// use std::io::Write;
+///
+/// let fs = SyncFileSystem::new(MemoryFileSystem::new(), SyncPolicy::EveryWrite);
+/// let mut file = fs.create_file("/commit.log").unwrap();
+/// file.write_all(b"commit").unwrap();
+/// assert_eq!(file.policy(), SyncPolicy::EveryWrite);
+/// ```
+#[derive(Debug)]
+pub struct SyncFileSystem {
+    inner: Arc<dyn DynamicFileSystem>,
+    policy: SyncPolicy,
+    groups: Mutex<HashMap<String, Weak<GroupCommit>>>,
+}
+
+impl SyncFileSystem {
+    /// Create a new Sync `FileSystem` syncing handles of `filesystem` according to `policy`.
+    pub fn new<F: FileSystem>(filesystem: F, policy: SyncPolicy) -> SyncFileSystem {
+        SyncFileSystem {
+            inner: Arc::new(filesystem),
+            policy,
+            groups: Mutex::default(),
+        }
+    }
+
+    /// Get the policy handles start with.
+    #[must_use]
+    pub fn policy(&self) -> SyncPolicy {
+        self.policy
+    }
+
+    /// Wrap an inner handle, sharing the group commit of every other handle on its file.
+    fn wrap(&self, inner: Box<dyn FileHandle>) -> SyncFileHandle {
+        let mut groups = self.groups.lock().expect("Poisoned Lock");
+        let group = if let Some(group) = groups.get(inner.path()).and_then(Weak::upgrade) {
+            group
+        } else {
+            groups.retain(|_, group| group.strong_count() > 0);
+            let group = Arc::new(GroupCommit::new());
+            groups.insert(inner.path().to_string(), Arc::downgrade(&group));
+            group
+        };
+        SyncFileHandle {
+            inner,
+            policy: self.policy,
+            group,
+            dirty: false,
+            synced: Instant::now(),
+        }
+    }
+}
+
+impl SyncFileSystem {
+    /// Sync a file written without a handle, as a handle writing it would have been by the time
+    /// it was closed.
+    fn sync_written(&self, path: &str) -> FileSystemResult<()> {
+        if matches!(self.policy, SyncPolicy::Never | SyncPolicy::OnRequest) {
+            return Ok(());
+        }
+        let options = OpenOptions::new().read(true);
+        let inner = DynamicFileSystem::open_with(self.inner.as_ref(), path, options)?;
+        self.wrap(inner).sync()
+    }
+}
+
+impl FileSystem for SyncFileSystem {
+    type FileHandle = SyncFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::exists(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::is_file(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::is_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        DynamicFileSystem::filesize(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_directory_all(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        DynamicFileSystem::list_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_directory_all(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        Ok(self.wrap(DynamicFileSystem::create_file(self.inner.as_ref(), path)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        Ok(self.wrap(DynamicFileSystem::open_file(self.inner.as_ref(), path)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_file(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        DynamicFileSystem::file_type(self.inner.as_ref(), path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_symlink(self.inner.as_ref(), target, path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        DynamicFileSystem::read_link(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        DynamicFileSystem::permissions(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        DynamicFileSystem::modified(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        DynamicFileSystem::set_permissions(self.inner.as_ref(), path, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        Ok(self.wrap(DynamicFileSystem::open_with(
+            self.inner.as_ref(),
+            path,
+            options,
+        )?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        DynamicFileSystem::space(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::rename(self.inner.as_ref(), from, to)
+    }
+
+    /// Syncs the new file unless the policy only syncs on request.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        let method = DynamicFileSystem::clone_file(self.inner.as_ref(), src, dst)?;
+        self.sync_written(dst)?;
+        Ok(method)
+    }
+
+    /// Syncs every file the batch wrote, under the path it ends up at, unless the policy only
+    /// syncs on request. A failed sync fails the write.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let mut results = DynamicFileSystem::apply(self.inner.as_ref(), batch);
+        let mut written: Vec<(usize, String)> = Vec::new();
+        for (index, (operation, result)) in batch.iter().zip(&results).enumerate() {
+            match (operation, result) {
+                (BatchOperation::Write { path, .. }, Ok(())) => {
+                    written.retain(|(_, written)| written != path);
+                    written.push((index, path.clone()));
+                }
+                (BatchOperation::Rename { from, to }, Ok(())) => {
+                    written.retain(|(_, written)| written != to);
+                    for (_, written) in &mut written {
+                        if written == from {
+                            written.clone_from(to);
+                        }
+                    }
+                }
+                (BatchOperation::RemoveFile { path }, Ok(())) => {
+                    written.retain(|(_, written)| written != path);
+                }
+                _ => {}
+            }
+        }
+        for (index, path) in written {
+            if let Err(err) = self.sync_written(&path) {
+                results[index] = Err(err);
+            }
+        }
+        results
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        DynamicFileSystem::read(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_to_string(&self, path: &str) -> FileSystemResult<String> {
+        DynamicFileSystem::read_to_string(self.inner.as_ref(), path)
+    }
+}
+
+/// Sync File Handle
+///
+/// Syncs the data written through it according to its [`SyncPolicy`].
+pub struct SyncFileHandle {
+    inner: Box<dyn FileHandle>,
+    policy: SyncPolicy,
+    group: Arc<GroupCommit>,
+    /// Written to since the last sync
+    dirty: bool,
+    synced: Instant,
+}
+
+impl SyncFileHandle {
+    /// Get the policy of this handle.
+    #[must_use]
+    pub fn policy(&self) -> SyncPolicy {
+        self.policy
+    }
+
+    /// Replace the policy of this handle, leaving other handles on the file unchanged.
+    pub fn set_policy(&mut self, policy: SyncPolicy) {
+        self.policy = policy;
+    }
+
+    /// Flush and sync the data of this handle through the group commit of its file.
+    fn sync(&mut self) -> FileSystemResult<()> {
+        self.inner.flush().map_err(FileSystemError::io_error)?;
+        let inner = &mut self.inner;
+        self.group.sync(|| inner.sync_data())?;
+        self.dirty = false;
+        self.synced = Instant::now();
+        Ok(())
+    }
+
+    /// Apply the policy after a write.
+    fn written(&mut self) -> FileSystemResult<()> {
+        self.dirty = true;
+        match self.policy {
+            SyncPolicy::EveryWrite => self.sync(),
+            SyncPolicy::Interval(interval) if self.synced.elapsed() >= interval => self.sync(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for SyncFileHandle {
+    fn drop(&mut self) {
+        if self.dirty && matches!(self.policy, SyncPolicy::OnClose | SyncPolicy::Interval(_)) {
+            if let Err(err) = self.sync() {
+                tracing::warn!(?err, "Failed to sync written data on drop");
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for SyncFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyncFileHandle")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .field("dirty", &self.dirty)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Read for SyncFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Read::read(self.inner.as_mut(), buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        Read::read_vectored(self.inner.as_mut(), bufs)
+    }
+}
+
+impl Write for SyncFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = Write::write(self.inner.as_mut(), buf)?;
+        self.written()?;
+        Ok(written)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        let written = Write::write_vectored(self.inner.as_mut(), bufs)?;
+        self.written()?;
+        Ok(written)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Write::flush(self.inner.as_mut())
+    }
+}
+
+impl Seek for SyncFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        Seek::seek(self.inner.as_mut(), pos)
+    }
+}
+
+impl FileHandle for SyncFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        FileHandle::path(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        FileHandle::get_size(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        FileHandle::set_size(self.inner.as_mut(), new_size)?;
+        self.written()
+    }
+
+    /// Syncs metadata as well, so bypasses the group commit, unless the policy is
+    /// [`SyncPolicy::Never`].
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        if self.policy == SyncPolicy::Never {
+            return Ok(());
+        }
+        self.inner.flush().map_err(FileSystemError::io_error)?;
+        FileHandle::sync_all(self.inner.as_mut())?;
+        self.dirty = false;
+        self.synced = Instant::now();
+        Ok(())
+    }
+
+    /// Syncs through the group commit of the file, unless the policy is [`SyncPolicy::Never`].
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        if self.policy == SyncPolicy::Never {
+            return Ok(());
+        }
+        self.sync()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        FileHandle::get_lock_status(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::try_set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        FileHandle::read_at_offset(self.inner.as_mut(), offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        let written = FileHandle::write_to_offset(self.inner.as_mut(), offset, buffer)?;
+        self.written()?;
+        Ok(written)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::lock_range(self.inner.as_mut(), offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        FileHandle::unlock_range(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        FileHandle::advise(self.inner.as_mut(), offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        FileHandle::read_at_vectored(self.inner.as_mut(), offset, buffers)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        let written = FileHandle::write_at_vectored(self.inner.as_mut(), offset, buffers)?;
+        self.written()?;
+        Ok(written)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        FileHandle::map_readonly(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        FileHandle::alignment(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        FileHandle::allocate(self.inner.as_mut(), len)?;
+        self.written()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_group_commit() {
+        use crate::GroupCommit;
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::{Arc, Barrier};
+        use std::time::Duration;
+
+        let group = Arc::new(GroupCommit::new());
+        let fsyncs = Arc::new(AtomicU64::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let (group, fsyncs, barrier) = (group.clone(), fsyncs.clone(), barrier.clone());
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    group
+                        .sync(|| {
+                            fsyncs.fetch_add(1, Ordering::Relaxed);
+                            std::thread::sleep(Duration::from_millis(50));
+                            Ok(())
+                        })
+                        .unwrap();
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(group.requests(), 8);
+        assert_eq!(group.syncs(), fsyncs.load(Ordering::Relaxed));
+        assert!(group.syncs() < 8);
+
+        // A failed sync covers nobody
+        assert!(group
+            .sync(|| Err(crate::FileSystemError::UnsupportedOperation))
+            .is_err());
+        group.sync(|| Ok(())).unwrap();
+        assert_eq!(group.syncs(), fsyncs.load(Ordering::Relaxed) + 2);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_sync_policies() {
+        use super::SyncFileSystem;
+        use crate::{CrashFileSystem, FileHandle, FileSystem, MemoryFileSystem, SyncPolicy};
+        use std::io::{Seek, SeekFrom, Write};
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        let crash = Arc::new(CrashFileSystem::new(MemoryFileSystem::new()));
+        let fs = SyncFileSystem {
+            inner: crash.clone(),
+            policy: SyncPolicy::EveryWrite,
+            groups: Mutex::default(),
+        };
+        {
+            let mut file = fs.create_file("/commit.log").unwrap();
+            file.write_all(b"one").unwrap();
+            file.write_to_offset(3, b"two").unwrap();
+            assert!(crash.unsynced().is_empty());
+            file.seek(SeekFrom::End(0)).unwrap();
+
+            file.set_policy(SyncPolicy::Never);
+            file.write_all(b"three").unwrap();
+            file.sync_data().unwrap();
+            assert_eq!(crash.unsynced(), vec!["/commit.log"]);
+
+            file.set_policy(SyncPolicy::OnClose);
+            file.write_all(b"four").unwrap();
+            assert_eq!(crash.unsynced(), vec!["/commit.log"]);
+        }
+        assert!(crash.unsynced().is_empty());
+
+        {
+            let mut file = fs.open_file("/commit.log").unwrap();
+            file.seek(SeekFrom::End(0)).unwrap();
+            file.set_policy(SyncPolicy::Interval(Duration::from_hours(1)));
+            file.write_all(b"five").unwrap();
+            assert_eq!(crash.unsynced(), vec!["/commit.log"]);
+            file.set_policy(SyncPolicy::Interval(Duration::ZERO));
+            file.write_all(b"six").unwrap();
+            assert!(crash.unsynced().is_empty());
+        }
+        crash.crash().unwrap();
+        assert_eq!(fs.read("/commit.log").unwrap(), b"onetwothreefourfivesix");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_sync_batch() {
+        use super::SyncFileSystem;
+        use crate::{BatchOperation, CrashFileSystem, FileSystem, MemoryFileSystem, SyncPolicy};
+        use std::sync::{Arc, Mutex};
+
+        let crash = Arc::new(CrashFileSystem::new(MemoryFileSystem::new()));
+        let fs = SyncFileSystem {
+            inner: crash.clone(),
+            policy: SyncPolicy::OnClose,
+            groups: Mutex::default(),
+        };
+        let results = fs.apply(&[
+            BatchOperation::write("/manifest.tmp", b"manifest"),
+            BatchOperation::rename("/manifest.tmp", "/manifest"),
+            BatchOperation::write("/log", b"entry"),
+        ]);
+        assert!(results.iter().all(Result::is_ok));
+        fs.clone_file("/log", "/log.bak").unwrap();
+        assert!(crash.unsynced().is_empty());
+
+        let fs = SyncFileSystem {
+            policy: SyncPolicy::OnRequest,
+            ..fs
+        };
+        fs.apply(&[BatchOperation::write("/lost", b"lost")]);
+        assert_eq!(crash.unsynced(), vec!["/lost"]);
+        crash.crash().unwrap();
+        assert_eq!(fs.read("/manifest").unwrap(), b"manifest");
+        assert!(!fs.exists("/lost").unwrap());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_sync_conformance() {
+        use crate::{MemoryFileSystem, SyncFileSystem, SyncPolicy};
+
+        crate::conformance::run(|| {
+            SyncFileSystem::new(MemoryFileSystem::new(), SyncPolicy::OnClose)
+        });
+    }
+}
@@ -0,0 +1,932 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::utility::{apply_operation, join_segments, normalize_segments};
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Most symbolic links followed while resolving a single path.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Multi-Tenant `FileSystem`
+///
+/// Divides a single backend [`FileSystem`] between tenants, each confined to the directory
+/// named by its id. A [`Tenant`] exposes that directory as its root, rejecting paths that climb
+/// above it and symbolic links that resolve into another tenant, and enforces the tenant's
+/// optional quota on the bytes its files hold.
+///
+/// Usage is measured by walking the tenant's directory the first time it is opened, and then
+/// kept up to date as files grow, shrink and are removed through its views.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, FileSystemError, MemoryFileSystem, TenantFileSystem};
+///
+/// let tenants = TenantFileSystem::new(MemoryFileSystem::new());
+/// tenants.set_quota("acme", Some(8)).unwrap();
+/// let acme = tenants.tenant("acme").unwrap();
+/// acme.write("/table.dat", b"rows").unwrap();
+/// assert!(matches!(acme.append("/table.dat", b" and more"), Err(FileSystemError::QuotaExceeded)));
+/// assert!(acme.open_file("/../globex/table.dat").is_err());
+/// assert_eq!(tenants.usage("acme").unwrap(), 4);
+/// ```
+#[derive(Debug)]
+pub struct TenantFileSystem {
+    inner: Arc<dyn DynamicFileSystem>,
+    tenants: Mutex<HashMap<String, Arc<TenantState>>>,
+}
+
+impl TenantFileSystem {
+    /// Create a new Multi-Tenant `FileSystem` keeping a directory per tenant in `filesystem`.
+    pub fn new<F: FileSystem>(filesystem: F) -> TenantFileSystem {
+        TenantFileSystem {
+            inner: Arc::new(filesystem),
+            tenants: Mutex::default(),
+        }
+    }
+
+    /// Open the view of tenant `id`, creating its directory if needed.
+    pub fn tenant(&self, id: &str) -> FileSystemResult<Tenant> {
+        Ok(Tenant {
+            inner: self.inner.clone(),
+            state: self.state(id)?,
+        })
+    }
+
+    /// Ids of the tenants with a directory in the backend, in order.
+    pub fn tenants(&self) -> FileSystemResult<Vec<String>> {
+        let mut tenants = DynamicFileSystem::list_directory(self.inner.as_ref(), "/")?;
+        tenants.retain(|id| {
+            DynamicFileSystem::is_directory(self.inner.as_ref(), &format!("/{id}")).unwrap_or(false)
+        });
+        tenants.sort();
+        Ok(tenants)
+    }
+
+    /// Limit the bytes tenant `id` may store, or lift the limit with `None`.
+    ///
+    /// Lowering a quota below the current usage doesn't remove anything, but fails any write
+    /// that would grow the tenant's files until enough is removed.
+    pub fn set_quota(&self, id: &str, quota: Option<u64>) -> FileSystemResult<()> {
+        self.state(id)?.usage.lock().expect("Poisoned Lock").quota = quota;
+        Ok(())
+    }
+
+    /// Get the quota of tenant `id`, if limited.
+    pub fn quota(&self, id: &str) -> FileSystemResult<Option<u64>> {
+        Ok(self.state(id)?.usage.lock().expect("Poisoned Lock").quota)
+    }
+
+    /// Get the bytes stored by tenant `id`.
+    pub fn usage(&self, id: &str) -> FileSystemResult<u64> {
+        Ok(self.state(id)?.usage.lock().expect("Poisoned Lock").used)
+    }
+
+    /// Remove tenant `id` and everything it stores.
+    pub fn remove_tenant(&self, id: &str) -> FileSystemResult<()> {
+        validate_id(id)?;
+        let mut tenants = self.tenants.lock().expect("Poisoned Lock");
+        let directory = format!("/{id}");
+        if DynamicFileSystem::exists(self.inner.as_ref(), &directory)? {
+            DynamicFileSystem::remove_directory_all(self.inner.as_ref(), &directory)?;
+        }
+        if let Some(state) = tenants.remove(id) {
+            state.usage.lock().expect("Poisoned Lock").used = 0;
+        }
+        Ok(())
+    }
+
+    /// Get the shared state of tenant `id`, measuring its usage the first time.
+    fn state(&self, id: &str) -> FileSystemResult<Arc<TenantState>> {
+        validate_id(id)?;
+        let mut tenants = self.tenants.lock().expect("Poisoned Lock");
+        if let Some(state) = tenants.get(id) {
+            return Ok(state.clone());
+        }
+        let directory = format!("/{id}");
+        DynamicFileSystem::create_directory_all(self.inner.as_ref(), &directory)?;
+        let state = Arc::new(TenantState {
+            id: id.to_string(),
+            usage: Mutex::new(TenantUsage {
+                quota: None,
+                used: directory_size(self.inner.as_ref(), &directory)?,
+            }),
+        });
+        tenants.insert(id.to_string(), state.clone());
+        Ok(state)
+    }
+}
+
+/// Reject tenant ids which aren't usable as a single directory name.
+fn validate_id(id: &str) -> FileSystemResult<()> {
+    if id.is_empty() || id == "." || id == ".." || id.contains(['/', '\\']) {
+        return Err(FileSystemError::invalid_path(id));
+    }
+    Ok(())
+}
+
+/// Total size of the files below `directory`, not following symbolic links.
+fn directory_size(inner: &dyn DynamicFileSystem, directory: &str) -> FileSystemResult<u64> {
+    let mut total = 0;
+    for name in inner.list_directory(directory)? {
+        let path = format!("{}/{name}", directory.trim_end_matches('/'));
+        total += entry_size(inner, &path)?;
+    }
+    Ok(total)
+}
+
+/// Size of the file or directory tree at `path`, not following symbolic links.
+fn entry_size(inner: &dyn DynamicFileSystem, path: &str) -> FileSystemResult<u64> {
+    match inner.file_type(path, SymlinkPolicy::NoFollow)? {
+        FileType::File => inner.filesize(path),
+        FileType::Directory => directory_size(inner, path),
+        FileType::Symlink => Ok(0),
+    }
+}
+
+#[derive(Debug)]
+struct TenantUsage {
+    quota: Option<u64>,
+    used: u64,
+}
+
+/// State shared by the views and handles of a tenant.
+#[derive(Debug)]
+struct TenantState {
+    id: String,
+    usage: Mutex<TenantUsage>,
+}
+
+impl TenantState {
+    /// Charge `bytes` to the tenant, failing if that would exceed its quota.
+    fn reserve(&self, bytes: u64) -> FileSystemResult<()> {
+        let mut usage = self.usage.lock().expect("Poisoned Lock");
+        if bytes > 0 && usage.quota.is_some_and(|quota| usage.used + bytes > quota) {
+            return Err(FileSystemError::QuotaExceeded);
+        }
+        usage.used += bytes;
+        Ok(())
+    }
+
+    /// Return `bytes` to the tenant.
+    fn release(&self, bytes: u64) {
+        let mut usage = self.usage.lock().expect("Poisoned Lock");
+        usage.used = usage.used.saturating_sub(bytes);
+    }
+
+    /// Replace a reservation of `reserved` bytes with the bytes actually gained, which were
+    /// `before` and are now `after`.
+    fn settle(&self, reserved: u64, before: u64, after: u64) {
+        let mut usage = self.usage.lock().expect("Poisoned Lock");
+        usage.used = (usage.used.saturating_sub(reserved) + after).saturating_sub(before);
+    }
+}
+
+/// Tenant of a [`TenantFileSystem`]
+///
+/// Exposes the tenant's directory as its root. Every component of a path is checked for
+/// symbolic links as it is resolved, so links can't lead out of the tenant's directory, even
+/// ones created in the backend directly.
+#[derive(Debug)]
+pub struct Tenant {
+    inner: Arc<dyn DynamicFileSystem>,
+    state: Arc<TenantState>,
+}
+
+impl Tenant {
+    /// Id of this tenant.
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.state.id
+    }
+
+    /// Get the bytes stored by this tenant.
+    #[must_use]
+    pub fn usage(&self) -> u64 {
+        self.state.usage.lock().expect("Poisoned Lock").used
+    }
+
+    /// Get the quota of this tenant, if limited.
+    #[must_use]
+    pub fn quota(&self) -> Option<u64> {
+        self.state.usage.lock().expect("Poisoned Lock").quota
+    }
+
+    /// Rewrite a tenant path into a path on the backend, following symbolic links in every
+    /// component but the last unless `follow` is set, and rejecting any that lead outside the
+    /// tenant's directory.
+    fn resolve(&self, path: &str, follow: bool) -> FileSystemResult<String> {
+        let root = [self.state.id.as_str()];
+        let mut pending = normalize_segments(path)?
+            .into_iter()
+            .map(ToString::to_string)
+            .collect::<VecDeque<_>>();
+        let mut resolved = vec![self.state.id.clone()];
+        let mut hops = 0;
+        while let Some(segment) = pending.pop_front() {
+            resolved.push(segment);
+            if pending.is_empty() && !follow {
+                break;
+            }
+            let current = join_segments(&resolved);
+            match self.inner.file_type(&current, SymlinkPolicy::NoFollow) {
+                Ok(FileType::Symlink) => {}
+                Ok(_) => continue,
+                Err(_) => {
+                    // Nothing below a missing entry can be a link.
+                    resolved.extend(pending.drain(..));
+                    break;
+                }
+            }
+            hops += 1;
+            if hops > MAX_SYMLINK_HOPS {
+                return Err(FileSystemError::invalid_path(path));
+            }
+            let target = self.inner.read_link(&current)?;
+            let target = if target.starts_with(['/', '\\']) {
+                target
+            } else {
+                let parent = join_segments(&resolved[..resolved.len() - 1]);
+                format!("{parent}/{target}")
+            };
+            let segments = normalize_segments(&target)?;
+            if segments.first() != root.first() {
+                return Err(FileSystemError::InvalidPath(target));
+            }
+            for segment in segments[1..].iter().rev() {
+                pending.push_front((*segment).to_string());
+            }
+            resolved.truncate(1);
+        }
+        Ok(join_segments(&resolved))
+    }
+
+    /// Size of the file at a resolved path, or zero if there isn't one.
+    fn file_size(&self, resolved: &str) -> FileSystemResult<u64> {
+        match self.inner.file_type(resolved, SymlinkPolicy::NoFollow) {
+            Ok(FileType::File) => self.inner.filesize(resolved),
+            _ => Ok(0),
+        }
+    }
+
+    /// Path of a file relative to the tenant's root.
+    fn relative(path: &str) -> FileSystemResult<String> {
+        Ok(join_segments(&normalize_segments(path)?))
+    }
+
+    fn wrap(
+        &self,
+        path: &str,
+        inner: Box<dyn FileHandle>,
+        append: bool,
+    ) -> FileSystemResult<TenantFileHandle> {
+        Ok(TenantFileHandle {
+            path: Self::relative(path)?,
+            inner,
+            state: self.state.clone(),
+            append,
+        })
+    }
+}
+
+impl FileSystem for Tenant {
+    type FileHandle = TenantFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::exists(self.inner.as_ref(), &self.resolve(path, true)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::is_file(self.inner.as_ref(), &self.resolve(path, true)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::is_directory(self.inner.as_ref(), &self.resolve(path, true)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        DynamicFileSystem::filesize(self.inner.as_ref(), &self.resolve(path, true)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_directory(self.inner.as_ref(), &self.resolve(path, false)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_directory_all(self.inner.as_ref(), &self.resolve(path, true)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        DynamicFileSystem::list_directory(self.inner.as_ref(), &self.resolve(path, true)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        if normalize_segments(path)?.is_empty() {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        DynamicFileSystem::remove_directory(self.inner.as_ref(), &self.resolve(path, false)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        if normalize_segments(path)?.is_empty() {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        let resolved = self.resolve(path, false)?;
+        let size = entry_size(self.inner.as_ref(), &resolved)?;
+        DynamicFileSystem::remove_directory_all(self.inner.as_ref(), &resolved)?;
+        self.state.release(size);
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let resolved = self.resolve(path, true)?;
+        let inner = DynamicFileSystem::create_file(self.inner.as_ref(), &resolved)?;
+        self.wrap(path, inner, false)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let resolved = self.resolve(path, true)?;
+        let inner = DynamicFileSystem::open_file(self.inner.as_ref(), &resolved)?;
+        self.wrap(path, inner, false)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        let resolved = self.resolve(path, false)?;
+        let size = entry_size(self.inner.as_ref(), &resolved)?;
+        DynamicFileSystem::remove_file(self.inner.as_ref(), &resolved)?;
+        self.state.release(size);
+        Ok(())
+    }
+
+    /// Moves the file without charging its size again, and returns the size of any file it
+    /// replaces to the tenant.
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        let (from, to) = (self.resolve(from, false)?, self.resolve(to, false)?);
+        let replaced = self.file_size(&to)?;
+        DynamicFileSystem::rename(self.inner.as_ref(), &from, &to)?;
+        self.state.release(replaced);
+        Ok(())
+    }
+
+    /// Fails with [`FileSystemError::QuotaExceeded`] before cloning if the new file doesn't fit.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        let (src, dst) = (self.resolve(src, true)?, self.resolve(dst, true)?);
+        let size = self.inner.filesize(&src)?;
+        let replaced = self.file_size(&dst)?;
+        let grown = size.saturating_sub(replaced);
+        self.state.reserve(grown)?;
+        match DynamicFileSystem::clone_file(self.inner.as_ref(), &src, &dst) {
+            Ok(method) => {
+                self.state.release(replaced.saturating_sub(size));
+                Ok(method)
+            }
+            Err(err) => {
+                self.state.release(grown);
+                Err(err)
+            }
+        }
+    }
+
+    /// Reserves every byte the batch writes before applying it as one batch, then settles the
+    /// tenant's usage to what the batch actually changed. A batch whose writes don't fit in
+    /// the quota together is applied one operation at a time instead, so only the writes that
+    /// don't fit fail. Operations whose paths can't be resolved fail on their own.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let written = batch
+            .iter()
+            .map(|operation| match operation {
+                BatchOperation::Write { contents, .. } => contents.len() as u64,
+                _ => 0,
+            })
+            .sum();
+        if self.state.reserve(written).is_err() {
+            return batch
+                .iter()
+                .map(|operation| apply_operation(self, operation))
+                .collect();
+        }
+        let mut resolved = Vec::with_capacity(batch.len());
+        let mut results: Vec<Option<FileSystemResult<()>>> = Vec::with_capacity(batch.len());
+        for operation in batch {
+            let follow = matches!(operation, BatchOperation::Write { .. });
+            match operation.map_paths(|path| self.resolve(path, follow)) {
+                Ok(operation) => {
+                    resolved.push(operation);
+                    results.push(None);
+                }
+                Err(error) => results.push(Some(Err(error))),
+            }
+        }
+        let paths = resolved
+            .iter()
+            .flat_map(BatchOperation::paths)
+            .collect::<BTreeSet<_>>();
+        let measure = || {
+            paths
+                .iter()
+                .map(|path| self.file_size(path).unwrap_or_default())
+                .sum::<u64>()
+        };
+        let before = measure();
+        let mut applied = DynamicFileSystem::apply(self.inner.as_ref(), &resolved).into_iter();
+        self.state.settle(written, before, measure());
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| applied.next().expect("Missing Batch Result")))
+            .collect()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        let follow = policy == SymlinkPolicy::Follow;
+        DynamicFileSystem::file_type(self.inner.as_ref(), &self.resolve(path, follow)?, policy)
+    }
+
+    /// Targets are confined to the tenant, with absolute targets taken relative to its root.
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        let link = self.resolve(path, false)?;
+        let target = if target.starts_with(['/', '\\']) {
+            let mut segments = vec![self.state.id.as_str()];
+            segments.extend(normalize_segments(target)?);
+            join_segments(&segments)
+        } else {
+            let parent = link.rsplit_once('/').map_or("", |(parent, _)| parent);
+            let resolved = format!("{parent}/{target}");
+            let segments = normalize_segments(&resolved)?;
+            if segments.first() != Some(&self.state.id.as_str()) {
+                return Err(FileSystemError::invalid_path(target));
+            }
+            target.to_string()
+        };
+        DynamicFileSystem::create_symlink(self.inner.as_ref(), &target, &link)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        let target =
+            DynamicFileSystem::read_link(self.inner.as_ref(), &self.resolve(path, false)?)?;
+        if !target.starts_with(['/', '\\']) {
+            return Ok(target);
+        }
+        let segments = normalize_segments(&target)?;
+        match segments.split_first() {
+            Some((id, rest)) if *id == self.state.id => Ok(join_segments(rest)),
+            _ => Err(FileSystemError::InvalidPath(target)),
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        DynamicFileSystem::permissions(self.inner.as_ref(), &self.resolve(path, true)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        DynamicFileSystem::modified(self.inner.as_ref(), &self.resolve(path, true)?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        let resolved = self.resolve(path, true)?;
+        DynamicFileSystem::set_permissions(self.inner.as_ref(), &resolved, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        let resolved = self.resolve(path, true)?;
+        let truncated = if options.is_truncate() {
+            match DynamicFileSystem::filesize(self.inner.as_ref(), &resolved) {
+                Ok(size) => size,
+                Err(FileSystemError::PathMissing) => 0,
+                Err(err) => return Err(err),
+            }
+        } else {
+            0
+        };
+        let inner = DynamicFileSystem::open_with(self.inner.as_ref(), &resolved, options)?;
+        self.state.release(truncated);
+        self.wrap(path, inner, options.is_append())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        let space = DynamicFileSystem::space(self.inner.as_ref())?;
+        let usage = self.state.usage.lock().expect("Poisoned Lock");
+        Ok(match usage.quota {
+            Some(quota) => {
+                let available = quota.saturating_sub(usage.used).min(space.available);
+                FileSystemSpace {
+                    total: quota.min(space.total),
+                    used: usage.used,
+                    available,
+                }
+            }
+            None => space,
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        DynamicFileSystem::read(self.inner.as_ref(), &self.resolve(path, true)?)
+    }
+
+    /// Fails with [`FileSystemError::QuotaExceeded`] before writing anything if the new
+    /// contents don't fit.
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        let options = OpenOptions::new().write(true).create(true).truncate(true);
+        FileSystem::open_with(self, path, options)?.write_fully(0, contents)
+    }
+
+    /// Fails with [`FileSystemError::QuotaExceeded`] before writing anything if the new
+    /// contents don't fit.
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn append(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        let options = OpenOptions::new().write(true).create(true).append(true);
+        let mut handle = FileSystem::open_with(self, path, options)?;
+        let end = handle.inner.get_size()?;
+        handle.write_fully(end, contents)
+    }
+}
+
+/// Tenant File Handle
+///
+/// Reports its path relative to the root of its [`Tenant`], and charges any growth of the file
+/// to the tenant's quota.
+pub struct TenantFileHandle {
+    path: String,
+    inner: Box<dyn FileHandle>,
+    state: Arc<TenantState>,
+    append: bool,
+}
+
+impl TenantFileHandle {
+    /// Write `len` bytes at `offset`, or at the end when appending, charging the growth of the
+    /// file to the tenant.
+    fn charged<T>(
+        &mut self,
+        offset: u64,
+        len: u64,
+        write: impl FnOnce(&mut dyn FileHandle) -> FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        let size = FileHandle::get_size(self.inner.as_ref())?;
+        let offset = if self.append { size } else { offset };
+        let reserved = offset.saturating_add(len).saturating_sub(size);
+        self.state.reserve(reserved)?;
+        let result = write(self.inner.as_mut());
+        let grown = FileHandle::get_size(self.inner.as_ref())
+            .map_or(reserved, |new_size| new_size.saturating_sub(size));
+        self.state.release(reserved.saturating_sub(grown));
+        result
+    }
+
+    /// Write all of `contents` at `offset`, charging the whole growth up front.
+    fn write_fully(&mut self, offset: u64, contents: &[u8]) -> FileSystemResult<()> {
+        let mut written = 0;
+        while written < contents.len() {
+            let offset = offset + written as u64;
+            match FileHandle::write_to_offset(self, offset, &contents[written..])? {
+                0 => return Err(FileSystemError::InvalidOperation),
+                count => written += count,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for TenantFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TenantFileHandle({}:{})", self.state.id, self.path)
+    }
+}
+
+impl Read for TenantFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Read::read(self.inner.as_mut(), buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        Read::read_vectored(self.inner.as_mut(), bufs)
+    }
+}
+
+impl Write for TenantFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let position = Seek::stream_position(self.inner.as_mut())?;
+        Ok(self.charged(position, buf.len() as u64, |inner| {
+            Write::write(inner, buf).map_err(FileSystemError::io_error)
+        })?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        let position = Seek::stream_position(self.inner.as_mut())?;
+        let len = bufs.iter().map(|buf| buf.len() as u64).sum();
+        Ok(self.charged(position, len, |inner| {
+            Write::write_vectored(inner, bufs).map_err(FileSystemError::io_error)
+        })?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Write::flush(self.inner.as_mut())
+    }
+}
+
+impl Seek for TenantFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        Seek::seek(self.inner.as_mut(), pos)
+    }
+}
+
+impl FileHandle for TenantFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        FileHandle::get_size(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        let size = FileHandle::get_size(self.inner.as_ref())?;
+        if new_size > size {
+            self.state.reserve(new_size - size)?;
+            FileHandle::set_size(self.inner.as_mut(), new_size)
+                .inspect_err(|_| self.state.release(new_size - size))
+        } else {
+            FileHandle::set_size(self.inner.as_mut(), new_size)?;
+            self.state.release(size - new_size);
+            Ok(())
+        }
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.charged(0, len, |inner| FileHandle::allocate(inner, len))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        FileHandle::sync_all(self.inner.as_mut())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        FileHandle::sync_data(self.inner.as_mut())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        FileHandle::get_lock_status(self.inner.as_ref())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::try_set_lock_status(self.inner.as_mut(), mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        FileHandle::read_at_offset(self.inner.as_mut(), offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.charged(offset, buffer.len() as u64, |inner| {
+            FileHandle::write_to_offset(inner, offset, buffer)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::lock_range(self.inner.as_mut(), offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        FileHandle::unlock_range(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        FileHandle::advise(self.inner.as_mut(), offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        FileHandle::read_at_vectored(self.inner.as_mut(), offset, buffers)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        let len = buffers.iter().map(|buffer| buffer.len() as u64).sum();
+        self.charged(offset, len, |inner| {
+            FileHandle::write_at_vectored(inner, offset, buffers)
+        })
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        FileHandle::map_readonly(self.inner.as_mut(), offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        FileHandle::alignment(self.inner.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_tenant_filesystem() {
+        use crate::TenantFileSystem;
+        use crate::{FileHandle, FileSystem, FileSystemError, MemoryFileSystem, OpenOptions};
+        use std::io::Write;
+
+        let backend = MemoryFileSystem::new();
+        backend.create_directory_all("/acme/existing").unwrap();
+        backend.write("/acme/existing/old.dat", &[0; 10]).unwrap();
+        let tenants = TenantFileSystem::new(backend.clone());
+        assert!(matches!(
+            tenants.tenant("../acme"),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+
+        // Existing files count towards usage, and tenants can't see each other
+        let acme = tenants.tenant("acme").unwrap();
+        let globex = tenants.tenant("globex").unwrap();
+        assert_eq!(acme.usage(), 10);
+        assert_eq!(tenants.tenants().unwrap(), ["acme", "globex"]);
+        globex.write("/secret.dat", b"secret").unwrap();
+        assert!(!acme.exists("/secret.dat").unwrap());
+        assert!(matches!(
+            acme.open_file("/../globex/secret.dat"),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+        assert_eq!(
+            acme.open_file("/existing/old.dat").unwrap().path(),
+            "/existing/old.dat"
+        );
+
+        // Writes that would grow past the quota fail, while overwrites and shrinking succeed
+        tenants.set_quota("acme", Some(16)).unwrap();
+        let mut file = acme.create_file("/data.dat").unwrap();
+        file.write_all(b"123456").unwrap();
+        assert!(matches!(
+            file.write_to_offset(6, b"7"),
+            Err(FileSystemError::QuotaExceeded)
+        ));
+        file.write_to_offset(0, b"abcdef").unwrap();
+        assert_eq!(acme.usage(), 16);
+        file.set_size(2).unwrap();
+        assert_eq!(acme.usage(), 12);
+        drop(file);
+        acme.remove_directory_all("/existing").unwrap();
+        assert_eq!(tenants.usage("acme").unwrap(), 2);
+        let options = OpenOptions::new().write(true).truncate(true);
+        acme.open_with("/data.dat", options).unwrap();
+        assert_eq!(acme.usage(), 0);
+        assert_eq!(acme.space().unwrap().available, 16);
+
+        // Links can't lead into another tenant, even when created in the backend directly
+        assert!(acme
+            .create_symlink("../globex/secret.dat", "/link")
+            .is_err());
+        acme.create_symlink("/data.dat", "/link").unwrap();
+        assert_eq!(acme.read_link("/link").unwrap(), "/data.dat");
+        backend
+            .create_symlink("/globex/secret.dat", "/acme/escape")
+            .unwrap();
+        assert!(matches!(
+            acme.read("/escape"),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+        assert_eq!(globex.read("/secret.dat").unwrap(), b"secret");
+
+        tenants.remove_tenant("globex").unwrap();
+        assert_eq!(tenants.tenants().unwrap(), ["acme"]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_tenant_rename() {
+        use crate::{FileSystem, MemoryFileSystem, TenantFileSystem};
+
+        let tenants = TenantFileSystem::new(MemoryFileSystem::new());
+        let acme = tenants.tenant("acme").unwrap();
+        tenants.set_quota("acme", Some(16)).unwrap();
+        acme.write("/data.tmp", &[1; 10]).unwrap();
+        acme.write("/data.dat", &[2; 4]).unwrap();
+        // Moving the file in place needs no room for a second copy
+        acme.rename("/data.tmp", "/data.dat").unwrap();
+        assert_eq!(acme.usage(), 10);
+        assert_eq!(acme.read("/data.dat").unwrap(), [1; 10]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_tenant_batch() {
+        use crate::TenantFileSystem;
+        use crate::{BatchOperation, FileSystem, FileSystemError, MemoryFileSystem};
+
+        let tenants = TenantFileSystem::new(MemoryFileSystem::new());
+        let acme = tenants.tenant("acme").unwrap();
+        tenants.set_quota("acme", Some(16)).unwrap();
+        acme.write("/old.dat", &[0; 6]).unwrap();
+        let results = acme.apply(&[
+            BatchOperation::write("/new.dat", &[1; 8]),
+            BatchOperation::rename("/new.dat", "/old.dat"),
+            BatchOperation::write("/../escape.dat", b"escape"),
+        ]);
+        assert!(results[0].is_ok() && results[1].is_ok());
+        assert!(matches!(results[2], Err(FileSystemError::InvalidPath(_))));
+        assert_eq!(acme.usage(), 8);
+
+        // Clones are charged, and writes that don't fit together fail one by one
+        acme.clone_file("/old.dat", "/copy.dat").unwrap();
+        assert_eq!(acme.usage(), 16);
+        assert!(matches!(
+            acme.clone_file("/old.dat", "/full.dat"),
+            Err(FileSystemError::QuotaExceeded)
+        ));
+        let results = acme.apply(&[
+            BatchOperation::remove_file("/copy.dat"),
+            BatchOperation::write("/fits.dat", &[2; 8]),
+            BatchOperation::write("/full.dat", &[3; 8]),
+        ]);
+        assert!(results[0].is_ok() && results[1].is_ok());
+        assert!(matches!(results[2], Err(FileSystemError::QuotaExceeded)));
+        assert_eq!(acme.usage(), 16);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_tenant_conformance() {
+        use crate::{MemoryFileSystem, TenantFileSystem};
+
+        crate::conformance::run(|| {
+            TenantFileSystem::new(MemoryFileSystem::new())
+                .tenant("tenant")
+                .unwrap()
+        });
+    }
+}
@@ -0,0 +1,349 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::utility::normalize_segments;
+use crate::{
+    BatchOperation, CloneMethod, ContentDigest, FileSystem, FileSystemError, FileSystemResult,
+    FileSystemSpace, FileType, HashAlgorithm, LocalFileSystem, MemoryFileSystem, OpenOptions,
+    Permissions, SymlinkPolicy, VirtualFileHandle, VirtualFileSystem,
+};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Temporary directories created by this process, keeping their names unique.
+static TEMP_DIRECTORIES: AtomicU64 = AtomicU64::new(0);
+
+/// Isolated Test Fixture `FileSystem`
+///
+/// Provides each test its own empty namespace, held in memory or in a fresh directory under
+/// the system temporary directory, with fluent methods to lay out fixtures before the test
+/// runs. The temporary directory is removed when the filesystem is dropped, which happens while
+/// unwinding too, so failing tests don't leave it behind.
+///
+/// Fixture methods panic if they can't set up the fixture, failing the test at the point it
+/// was declared.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, TestFileSystem};
+///
+/// let fs = TestFileSystem::memory()
+///     .with_directory("/empty")
+///     .with_file("/tables/users.dat", b"rows");
+/// assert_eq!(fs.read("/tables/users.dat").unwrap(), b"rows");
+///
+/// let root = {
+///     let fs = TestFileSystem::temp_dir().unwrap().with_file("/a/b.txt", b"b");
+///     assert!(fs.root().unwrap().join("a/b.txt").exists());
+///     fs.root().unwrap().to_path_buf()
+/// };
+/// assert!(!root.exists());
+/// ```
+#[derive(Debug)]
+pub struct TestFileSystem {
+    inner: VirtualFileSystem,
+    root: Option<PathBuf>,
+}
+
+impl TestFileSystem {
+    /// Create an empty Test `FileSystem` held in memory.
+    #[must_use]
+    pub fn memory() -> TestFileSystem {
+        TestFileSystem {
+            inner: VirtualFileSystem::new(MemoryFileSystem::new()),
+            root: None,
+        }
+    }
+
+    /// Create an empty Test `FileSystem` in a new directory under the system temporary
+    /// directory, removed again when it's dropped.
+    pub fn temp_dir() -> FileSystemResult<TestFileSystem> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let root = std::env::temp_dir().join(format!(
+            "minql-test-{}-{}-{nanos}",
+            std::process::id(),
+            TEMP_DIRECTORIES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&root).map_err(FileSystemError::io_error)?;
+        Ok(TestFileSystem {
+            inner: VirtualFileSystem::new(LocalFileSystem::new(&root)),
+            root: Some(root),
+        })
+    }
+
+    /// Add a file holding `contents`, creating any missing parent directories.
+    #[must_use]
+    pub fn with_file(self, path: &str, contents: &[u8]) -> TestFileSystem {
+        self.create_parent(path);
+        self.inner
+            .write(path, contents)
+            .unwrap_or_else(|err| panic!("Error creating fixture file {path}: {err}"));
+        self
+    }
+
+    /// Add a directory, creating any missing parent directories.
+    #[must_use]
+    pub fn with_directory(self, path: &str) -> TestFileSystem {
+        self.inner
+            .create_directory_all(path)
+            .unwrap_or_else(|err| panic!("Error creating fixture directory {path}: {err}"));
+        self
+    }
+
+    /// Add a symbolic link to `target`, creating any missing parent directories.
+    #[must_use]
+    pub fn with_symlink(self, target: &str, path: &str) -> TestFileSystem {
+        self.create_parent(path);
+        self.inner
+            .create_symlink(target, path)
+            .unwrap_or_else(|err| panic!("Error creating fixture symlink {path}: {err}"));
+        self
+    }
+
+    /// Directory holding the filesystem on disk, if it isn't held in memory.
+    #[must_use]
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Create the missing parent directories of a fixture.
+    fn create_parent(&self, path: &str) {
+        let segments = normalize_segments(path)
+            .unwrap_or_else(|err| panic!("Invalid fixture path {path}: {err}"));
+        if let Some((_, parents)) = segments.split_last() {
+            let parent = crate::utility::join_segments(parents);
+            self.inner
+                .create_directory_all(&parent)
+                .unwrap_or_else(|err| panic!("Error creating fixture directory {parent}: {err}"));
+        }
+    }
+}
+
+impl Drop for TestFileSystem {
+    fn drop(&mut self) {
+        if let Some(root) = &self.root {
+            if let Err(err) = std::fs::remove_dir_all(root) {
+                tracing::warn!(root = %root.display(), %err, "Error removing test directory");
+            }
+        }
+    }
+}
+
+impl FileSystem for TestFileSystem {
+    type FileHandle = VirtualFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        self.inner.exists(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        self.inner.is_file(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        self.inner.is_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.inner.filesize(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.inner.create_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.inner.create_directory_all(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        self.inner.list_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.inner.remove_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.inner.remove_directory_all(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.inner.create_file(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        self.inner.open_file(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        self.inner.remove_file(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.inner.file_type(path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        self.inner.create_symlink(target, path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.inner.read_link(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.inner.permissions(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.inner.modified(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.inner.set_permissions(path, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        self.inner.open_with(path, options)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        self.inner.space()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        self.inner.clone_file(src, dst)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        self.inner.rename(from, to)
+    }
+
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        self.inner.apply(batch)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn hash_file(&self, path: &str, algorithm: HashAlgorithm) -> FileSystemResult<ContentDigest> {
+        self.inner.hash_file(path, algorithm)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        self.inner.read(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_to_string(&self, path: &str) -> FileSystemResult<String> {
+        self.inner.read_to_string(path)
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        self.inner.write(path, contents)
+    }
+
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn append(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        self.inner.append(path, contents)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_fixture_cleanup() {
+        use crate::{FileSystem, FileType, SymlinkPolicy, TestFileSystem};
+
+        let fs = TestFileSystem::memory()
+            .with_file("/a/b.txt", b"b")
+            .with_symlink("/a/b.txt", "/links/b")
+            .with_directory("/empty");
+        assert!(fs.root().is_none());
+        assert_eq!(fs.list_directory("/").unwrap(), ["a", "empty", "links"]);
+        assert_eq!(
+            fs.file_type("/links/b", SymlinkPolicy::NoFollow).unwrap(),
+            FileType::Symlink
+        );
+
+        // Temporary directories are unique and removed on drop, even while unwinding
+        let first = TestFileSystem::temp_dir().unwrap();
+        let second = TestFileSystem::temp_dir()
+            .unwrap()
+            .with_file("a/b.txt", b"b");
+        assert_ne!(first.root(), second.root());
+        assert!(first.list_directory("/").unwrap().is_empty());
+        let root = second.root().unwrap().to_path_buf();
+        assert_eq!(std::fs::read(root.join("a/b.txt")).unwrap(), b"b");
+        drop(second);
+        assert!(!root.exists());
+
+        let root = std::panic::catch_unwind(|| {
+            let fs = TestFileSystem::temp_dir().unwrap().with_file("/x", b"x");
+            let root = fs.root().unwrap().to_path_buf();
+            assert!(root.exists());
+            std::panic::panic_any(root);
+        })
+        .unwrap_err()
+        .downcast::<std::path::PathBuf>()
+        .unwrap();
+        assert!(!root.exists());
+
+        let result = std::panic::catch_unwind(|| {
+            let _ = TestFileSystem::memory()
+                .with_file("/file", b"file")
+                .with_file("/file/nested", b"nested");
+        });
+        assert!(result.is_err(), "Impossible fixture didn't panic");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_fixture_conformance() {
+        use crate::TestFileSystem;
+
+        crate::conformance::run(TestFileSystem::memory);
+        crate::conformance::run(|| TestFileSystem::temp_dir().unwrap());
+    }
+}
@@ -0,0 +1,551 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemResult,
+    FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// I/O budgets enforced by a [`ThrottledFileSystem`].
+///
+/// Each budget is a token bucket refilled at its rate and holding up to one second of burst.
+/// Unset budgets are unlimited.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ThrottleLimits {
+    bytes_per_second: Option<u64>,
+    ops_per_second: Option<u64>,
+}
+
+impl ThrottleLimits {
+    /// Create unlimited budgets.
+    #[must_use]
+    pub fn new() -> ThrottleLimits {
+        ThrottleLimits::default()
+    }
+
+    /// Limit the bytes read and written per second.
+    #[must_use]
+    pub fn with_bytes_per_second(mut self, bytes_per_second: u64) -> ThrottleLimits {
+        self.bytes_per_second = Some(bytes_per_second);
+        self
+    }
+
+    /// Limit the read and write operations per second.
+    #[must_use]
+    pub fn with_ops_per_second(mut self, ops_per_second: u64) -> ThrottleLimits {
+        self.ops_per_second = Some(ops_per_second);
+        self
+    }
+
+    /// Bytes per second budget, if limited.
+    #[must_use]
+    pub fn bytes_per_second(&self) -> Option<u64> {
+        self.bytes_per_second
+    }
+
+    /// Operations per second budget, if limited.
+    #[must_use]
+    pub fn ops_per_second(&self) -> Option<u64> {
+        self.ops_per_second
+    }
+}
+
+/// Token bucket which may go into debt, making the caller wait until it is repaid.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+}
+
+impl TokenBucket {
+    #[allow(clippy::cast_precision_loss)]
+    fn new(rate: Option<u64>) -> Option<TokenBucket> {
+        rate.filter(|rate| *rate > 0).map(|rate| TokenBucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+        })
+    }
+
+    /// Take `amount` tokens, returning how long to wait for the bucket to cover them.
+    fn reserve(&mut self, elapsed: Duration, amount: f64) -> Duration {
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate) - amount;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ThrottleState {
+    refilled: Instant,
+    bytes: Option<TokenBucket>,
+    ops: Option<TokenBucket>,
+}
+
+/// Budgets shared by a [`ThrottledFileSystem`] and every handle it opens.
+#[derive(Debug)]
+struct Throttle {
+    state: Mutex<ThrottleState>,
+}
+
+impl Throttle {
+    fn new(limits: ThrottleLimits) -> Throttle {
+        Throttle {
+            state: Mutex::new(ThrottleState {
+                refilled: Instant::now(),
+                bytes: TokenBucket::new(limits.bytes_per_second),
+                ops: TokenBucket::new(limits.ops_per_second),
+            }),
+        }
+    }
+
+    /// Block until the budgets allow an operation transferring `bytes`.
+    #[allow(clippy::cast_precision_loss)]
+    fn acquire(&self, bytes: usize) {
+        let wait = {
+            let mut state = self.state.lock().expect("Poisoned Lock");
+            let now = Instant::now();
+            let elapsed = now - state.refilled;
+            state.refilled = now;
+            let bytes = state.bytes.as_mut().map_or(Duration::ZERO, |bucket| {
+                bucket.reserve(elapsed, bytes as f64)
+            });
+            let ops = state
+                .ops
+                .as_mut()
+                .map_or(Duration::ZERO, |bucket| bucket.reserve(elapsed, 1.0));
+            bytes.max(ops)
+        };
+        if !wait.is_zero() {
+            tracing::trace!(?wait, "Throttling I/O");
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// Throttled `FileSystem` Wrapper
+///
+/// Caps the bandwidth and operation rate of reads and writes through another [`FileSystem`].
+/// Budgets are shared by every handle opened through the wrapper, so a background task such as
+/// compaction can be given its own throttled view of a filesystem without starving foreground
+/// traffic. Metadata operations are never throttled.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, ThrottleLimits, ThrottledFileSystem};
+/// use std::io::Write;
+///
+/// let limits = ThrottleLimits::new()
+///     .with_bytes_per_second(64 * 1024 * 1024)
+///     .with_ops_per_second(1000);
+/// let fs = ThrottledFileSystem::new(MemoryFileSystem::new(), limits);
+///
+/// let mut file = fs.create_file("/compaction.tmp").unwrap();
+/// file.write_all(b"Hello, World!").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ThrottledFileSystem<F: FileSystem> {
+    throttle: Arc<Throttle>,
+    inner: F,
+}
+
+impl<F: FileSystem> ThrottledFileSystem<F> {
+    /// Create a new Throttled `FileSystem` enforcing `limits` on `filesystem`.
+    pub fn new(filesystem: F, limits: ThrottleLimits) -> ThrottledFileSystem<F> {
+        ThrottledFileSystem {
+            throttle: Arc::new(Throttle::new(limits)),
+            inner: filesystem,
+        }
+    }
+
+    /// Borrow the throttled filesystem.
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// Replace the budgets enforced on this filesystem and every open handle.
+    pub fn set_limits(&self, limits: ThrottleLimits) {
+        let mut state = self.throttle.state.lock().expect("Poisoned Lock");
+        state.bytes = TokenBucket::new(limits.bytes_per_second);
+        state.ops = TokenBucket::new(limits.ops_per_second);
+    }
+
+    fn wrap(&self, inner: F::FileHandle) -> ThrottledFileHandle<F::FileHandle> {
+        ThrottledFileHandle {
+            throttle: self.throttle.clone(),
+            inner,
+        }
+    }
+}
+
+impl<F: FileSystem> FileSystem for ThrottledFileSystem<F> {
+    type FileHandle = ThrottledFileHandle<F::FileHandle>;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        self.inner.exists(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        self.inner.is_file(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        self.inner.is_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.inner.filesize(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.inner.create_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.inner.create_directory_all(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        self.inner.list_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.inner.remove_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.inner.remove_directory_all(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        Ok(self.wrap(self.inner.create_file(path)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        Ok(self.wrap(self.inner.open_file(path)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        self.inner.remove_file(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.inner.file_type(path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        self.inner.create_symlink(target, path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.inner.read_link(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.inner.permissions(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.inner.modified(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.inner.set_permissions(path, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        Ok(self.wrap(self.inner.open_with(path, options)?))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        self.inner.space()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        self.inner.rename(from, to)
+    }
+
+    /// Charged as reading and writing the whole source, since the inner filesystem may copy it.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        let size = usize::try_from(self.inner.filesize(src)?).unwrap_or(usize::MAX);
+        self.throttle.acquire(size);
+        self.throttle.acquire(size);
+        self.inner.clone_file(src, dst)
+    }
+
+    /// Every write in the batch is charged before the batch is forwarded.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        for operation in batch {
+            if let BatchOperation::Write { contents, .. } = operation {
+                self.throttle.acquire(contents.len());
+            }
+        }
+        self.inner.apply(batch)
+    }
+}
+
+/// Throttled File Handle
+///
+/// Waits for the budgets of the [`ThrottledFileSystem`] that opened it before every read and
+/// write, charging the full size of the requested transfer.
+pub struct ThrottledFileHandle<H: FileHandle> {
+    throttle: Arc<Throttle>,
+    inner: H,
+}
+
+impl<H: FileHandle> std::fmt::Debug for ThrottledFileHandle<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<H: FileHandle> Read for ThrottledFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.throttle.acquire(buf.len());
+        self.inner.read(buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        self.throttle
+            .acquire(bufs.iter().map(|buf| buf.len()).sum());
+        self.inner.read_vectored(bufs)
+    }
+}
+
+impl<H: FileHandle> Write for ThrottledFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.throttle.acquire(buf.len());
+        self.inner.write(buf)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        self.throttle
+            .acquire(bufs.iter().map(|buf| buf.len()).sum());
+        self.inner.write_vectored(bufs)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<H: FileHandle> Seek for ThrottledFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<H: FileHandle> FileHandle for ThrottledFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        self.inner.path()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        self.inner.get_size()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.inner.set_size(new_size)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.inner.sync_all()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.inner.sync_data()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        self.inner.get_lock_status()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.try_set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        self.throttle.acquire(buffer.len());
+        self.inner.read_at_offset(offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.throttle.acquire(buffer.len());
+        self.inner.write_to_offset(offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.lock_range(offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.inner.unlock_range(offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        self.inner.advise(offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        self.throttle
+            .acquire(buffers.iter().map(|buffer| buffer.len()).sum());
+        self.inner.read_at_vectored(offset, buffers)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        self.throttle
+            .acquire(buffers.iter().map(|buffer| buffer.len()).sum());
+        self.inner.write_at_vectored(offset, buffers)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        self.throttle.acquire(len);
+        self.inner.map_readonly(offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        self.inner.alignment()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.inner.allocate(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{FileHandle, FileSystem, MemoryFileSystem, ThrottleLimits, ThrottledFileSystem};
+    use std::io::Write;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_throttled_filesystem() {
+        let fs = ThrottledFileSystem::new(MemoryFileSystem::new(), ThrottleLimits::new());
+        let mut file = fs.create_file("/unlimited.dat").unwrap();
+        file.write_all(&[0; 64]).unwrap();
+
+        // Operations are budgeted across every handle after the initial burst
+        fs.set_limits(ThrottleLimits::new().with_ops_per_second(100));
+        let mut other = fs.open_file("/unlimited.dat").unwrap();
+        let start = Instant::now();
+        for _ in 0..75 {
+            file.write_to_offset(0, &[1]).unwrap();
+            other.read_at_offset(0, &mut [0]).unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(400));
+
+        // Bytes are budgeted by the size of each transfer
+        fs.set_limits(ThrottleLimits::new().with_bytes_per_second(4096));
+        let start = Instant::now();
+        for _ in 0..6 {
+            file.write_to_offset(0, &[2; 1024]).unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_throttled_batch() {
+        use crate::BatchOperation;
+
+        let limits = ThrottleLimits::new().with_bytes_per_second(4096);
+        let fs = ThrottledFileSystem::new(MemoryFileSystem::new(), limits);
+        let batch = (0..6)
+            .map(|part| BatchOperation::write(&format!("/part-{part}"), &[0; 1024]))
+            .collect::<Vec<_>>();
+        let start = Instant::now();
+        assert!(fs.apply(&batch).iter().all(Result::is_ok));
+        assert!(start.elapsed() >= Duration::from_millis(400));
+
+        // Clones are charged for the bytes they may copy
+        let start = Instant::now();
+        fs.clone_file("/part-0", "/copy").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_throttled_conformance() {
+        crate::conformance::run(|| {
+            ThrottledFileSystem::new(MemoryFileSystem::new(), ThrottleLimits::new())
+        });
+    }
+}
@@ -0,0 +1,612 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::mpsc::{sync_channel, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Run an operation on a watchdog thread, giving up on it once `timeout` passes.
+///
+/// The operation is passed the deadline, after which it must not start doing anything, as its
+/// caller has already been told it timed out.
+fn run<T: Send + 'static>(
+    operation: &'static str,
+    timeout: Duration,
+    call: impl FnOnce(Instant) -> FileSystemResult<T> + Send + 'static,
+) -> FileSystemResult<T> {
+    let deadline = Instant::now() + timeout;
+    let (sender, receiver) = sync_channel(1);
+    std::thread::Builder::new()
+        .name(format!("minql-vfs-{operation}"))
+        .spawn(move || {
+            // The caller may have stopped waiting, in which case nobody needs the result.
+            let _ = sender.send(call(deadline));
+        })
+        .map_err(FileSystemError::io_error)?;
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => {
+            tracing::warn!(operation, ?timeout, "Operation timed out");
+            Err(FileSystemError::TimedOut)
+        }
+        Err(RecvTimeoutError::Disconnected) => Err(FileSystemError::internal_error(&format!(
+            "{operation} panicked"
+        ))),
+    }
+}
+
+/// Lock a handle for an operation, unless its caller gave up while waiting for the lock.
+fn acquire<H>(handle: &Mutex<H>, deadline: Instant) -> FileSystemResult<MutexGuard<'_, H>> {
+    let handle = handle.lock().expect("Poisoned Lock");
+    if Instant::now() >= deadline {
+        return Err(FileSystemError::TimedOut);
+    }
+    Ok(handle)
+}
+
+/// Copy `data` into consecutive buffers.
+fn scatter(data: &[u8], buffers: &mut [IoSliceMut<'_>]) {
+    let mut data = data;
+    for buffer in buffers {
+        let len = buffer.len().min(data.len());
+        buffer[..len].copy_from_slice(&data[..len]);
+        data = &data[len..];
+    }
+}
+
+/// Timeout `FileSystem` Wrapper
+///
+/// Bounds the wall-clock time of every operation on another [`FileSystem`] and its handles,
+/// failing those that overrun with [`FileSystemError::TimedOut`], so a hung network mount or
+/// object store stalls only the request that hit it.
+///
+/// Operations run on a watchdog thread each, since blocking calls can't be interrupted. An
+/// operation that times out is abandoned rather than cancelled, and may still take effect
+/// once the backend responds. Operations on a handle are serialized, and those still waiting
+/// on an abandoned one when their own deadline passes are skipped rather than run late.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, TimeoutFileSystem};
+/// use std::time::Duration;
+///
+/// let fs = TimeoutFileSystem::new(MemoryFileSystem::new(), Duration::from_secs(5));
+/// fs.write("/segment.dat", b"Hello, World!").unwrap();
+/// assert_eq!(fs.read("/segment.dat").unwrap(), b"Hello, World!");
+/// ```
+#[derive(Debug)]
+pub struct TimeoutFileSystem<F: FileSystem> {
+    inner: Arc<F>,
+    timeout: Duration,
+}
+
+impl<F: FileSystem> TimeoutFileSystem<F> {
+    /// Create a new Timeout `FileSystem` bounding every operation on `filesystem` to `timeout`.
+    pub fn new(filesystem: F, timeout: Duration) -> TimeoutFileSystem<F> {
+        TimeoutFileSystem {
+            inner: Arc::new(filesystem),
+            timeout,
+        }
+    }
+
+    /// Borrow the wrapped filesystem.
+    #[must_use]
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// Time each operation is allowed to take.
+    #[must_use]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Run an operation on the wrapped filesystem against the timeout.
+    fn call<T: Send + 'static>(
+        &self,
+        operation: &'static str,
+        call: impl FnOnce(&F) -> FileSystemResult<T> + Send + 'static,
+    ) -> FileSystemResult<T> {
+        let inner = self.inner.clone();
+        run(operation, self.timeout, move |_| call(&inner))
+    }
+
+    /// Run an operation on a path of the wrapped filesystem against the timeout.
+    fn call_path<T: Send + 'static>(
+        &self,
+        operation: &'static str,
+        path: &str,
+        call: impl FnOnce(&F, &str) -> FileSystemResult<T> + Send + 'static,
+    ) -> FileSystemResult<T> {
+        let path = path.to_string();
+        self.call(operation, move |inner| call(inner, &path))
+    }
+
+    fn wrap(&self, inner: F::FileHandle) -> TimeoutFileHandle<F::FileHandle> {
+        TimeoutFileHandle {
+            path: inner.path().to_string(),
+            inner: Arc::new(Mutex::new(inner)),
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl<F: FileSystem> FileSystem for TimeoutFileSystem<F> {
+    type FileHandle = TimeoutFileHandle<F::FileHandle>;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        self.call_path("exists", path, FileSystem::exists)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        self.call_path("is_file", path, FileSystem::is_file)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        self.call_path("is_directory", path, FileSystem::is_directory)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.call_path("filesize", path, FileSystem::filesize)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.call_path("create_directory", path, |inner, path| {
+            inner.create_directory(path)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.call_path("create_directory_all", path, |inner, path| {
+            inner.create_directory_all(path)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        self.call_path("list_directory", path, |inner, path| {
+            inner.list_directory(path)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.call_path("remove_directory", path, |inner, path| {
+            inner.remove_directory(path)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.call_path("remove_directory_all", path, |inner, path| {
+            inner.remove_directory_all(path)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let handle = self.call_path("create_file", path, FileSystem::create_file)?;
+        Ok(self.wrap(handle))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let handle = self.call_path("open_file", path, FileSystem::open_file)?;
+        Ok(self.wrap(handle))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        self.call_path("remove_file", path, FileSystem::remove_file)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.call_path("file_type", path, move |inner, path| {
+            inner.file_type(path, policy)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        let target = target.to_string();
+        self.call_path("create_symlink", path, move |inner, path| {
+            inner.create_symlink(&target, path)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.call_path("read_link", path, FileSystem::read_link)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.call_path("permissions", path, FileSystem::permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.call_path("modified", path, FileSystem::modified)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.call_path("set_permissions", path, move |inner, path| {
+            inner.set_permissions(path, permissions)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        let handle = self.call_path("open_with", path, move |inner, path| {
+            inner.open_with(path, options)
+        })?;
+        Ok(self.wrap(handle))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        self.call("space", FileSystem::space)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        let to = to.to_string();
+        self.call_path("rename", from, move |inner, from| inner.rename(from, &to))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        let dst = dst.to_string();
+        self.call_path("clone_file", src, move |inner, src| {
+            inner.clone_file(src, &dst)
+        })
+    }
+
+    /// The timeout bounds the whole batch, and every operation of a batch that timed out fails
+    /// with [`FileSystemError::TimedOut`], though some may still be applied.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let operations = batch.to_vec();
+        match self.call("apply", move |inner| Ok(inner.apply(&operations))) {
+            Ok(results) => results,
+            Err(FileSystemError::TimedOut) => batch
+                .iter()
+                .map(|_| Err(FileSystemError::TimedOut))
+                .collect(),
+            Err(err) => {
+                let message = err.to_string();
+                batch
+                    .iter()
+                    .map(|_| Err(FileSystemError::internal_error(&message)))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Timeout File Handle
+///
+/// Bounds every operation on a handle opened through a [`TimeoutFileSystem`] to its timeout.
+/// Reads go through a temporary buffer, which is only copied out if the read finishes in time.
+pub struct TimeoutFileHandle<H: FileHandle> {
+    path: String,
+    inner: Arc<Mutex<H>>,
+    timeout: Duration,
+}
+
+impl<H: FileHandle> TimeoutFileHandle<H> {
+    /// Run an operation on the wrapped handle against the timeout.
+    fn call<T: Send + 'static>(
+        &self,
+        operation: &'static str,
+        call: impl FnOnce(&mut H) -> FileSystemResult<T> + Send + 'static,
+    ) -> FileSystemResult<T> {
+        let inner = self.inner.clone();
+        run(operation, self.timeout, move |deadline| {
+            call(&mut *acquire(&inner, deadline)?)
+        })
+    }
+
+    /// Run a read of `len` bytes on the wrapped handle against the timeout.
+    fn call_read(
+        &self,
+        operation: &'static str,
+        len: usize,
+        call: impl FnOnce(&mut H, &mut [u8]) -> FileSystemResult<usize> + Send + 'static,
+    ) -> FileSystemResult<Vec<u8>> {
+        self.call(operation, move |inner| {
+            let mut data = vec![0; len];
+            let read = call(inner, &mut data)?;
+            data.truncate(read);
+            Ok(data)
+        })
+    }
+}
+
+impl<H: FileHandle> std::fmt::Debug for TimeoutFileHandle<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TimeoutFileHandle")
+            .field("path", &self.path)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<H: FileHandle> Read for TimeoutFileHandle<H> {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let data = self.call_read("read", buf.len(), |inner, data| {
+            inner.read(data).map_err(FileSystemError::io_error)
+        })?;
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+
+    #[tracing::instrument(level = "trace", skip(bufs))]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        let data = self.call_read("read_vectored", len, |inner, data| {
+            inner.read(data).map_err(FileSystemError::io_error)
+        })?;
+        scatter(&data, bufs);
+        Ok(data.len())
+    }
+}
+
+impl<H: FileHandle> Write for TimeoutFileHandle<H> {
+    #[tracing::instrument(level = "trace", skip(buf))]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let data = buf.to_vec();
+        Ok(self.call("write", move |inner| {
+            inner.write(&data).map_err(FileSystemError::io_error)
+        })?)
+    }
+
+    #[tracing::instrument(level = "trace", skip(bufs))]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        let data: Vec<u8> = bufs.iter().flat_map(|buf| buf.iter().copied()).collect();
+        Ok(self.call("write_vectored", move |inner| {
+            inner.write(&data).map_err(FileSystemError::io_error)
+        })?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(self.call("flush", |inner| {
+            inner.flush().map_err(FileSystemError::io_error)
+        })?)
+    }
+}
+
+impl<H: FileHandle> Seek for TimeoutFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        Ok(self.call("seek", move |inner| {
+            inner.seek(pos).map_err(FileSystemError::io_error)
+        })?)
+    }
+}
+
+impl<H: FileHandle> FileHandle for TimeoutFileHandle<H> {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        self.call("get_size", |inner| inner.get_size())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.call("set_size", move |inner| inner.set_size(new_size))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.call("sync_all", FileHandle::sync_all)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.call("sync_data", FileHandle::sync_data)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        self.call("get_lock_status", |inner| inner.get_lock_status())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.call("set_lock_status", move |inner| inner.set_lock_status(mode))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.call("try_set_lock_status", move |inner| {
+            inner.try_set_lock_status(mode)
+        })
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        let data = self.call_read("read_at_offset", buffer.len(), move |inner, data| {
+            inner.read_at_offset(offset, data)
+        })?;
+        buffer[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        let data = buffer.to_vec();
+        self.call("write_to_offset", move |inner| {
+            inner.write_to_offset(offset, &data)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.call("lock_range", move |inner| {
+            inner.lock_range(offset, len, mode)
+        })
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.call("unlock_range", move |inner| inner.unlock_range(offset, len))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        self.call("advise", move |inner| inner.advise(offset, len, advice))
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffers))]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        let len = buffers.iter().map(|buffer| buffer.len()).sum();
+        let data = self.call_read("read_at_vectored", len, move |inner, data| {
+            inner.read_at_vectored(offset, &mut [IoSliceMut::new(data)])
+        })?;
+        scatter(&data, buffers);
+        Ok(data.len())
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffers))]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        let data: Vec<u8> = buffers
+            .iter()
+            .flat_map(|buffer| buffer.iter().copied())
+            .collect();
+        self.call("write_at_vectored", move |inner| {
+            inner.write_at_vectored(offset, &[IoSlice::new(&data)])
+        })
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        self.call("map_readonly", move |inner| inner.map_readonly(offset, len))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        self.call("alignment", |inner| inner.alignment())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.call("allocate", move |inner| inner.allocate(len))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        FileHandle, FileSystem, FileSystemError, MemoryFileSystem, ThrottleLimits,
+        ThrottledFileSystem, TimeoutFileSystem,
+    };
+    use std::time::{Duration, Instant};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_timeout_filesystem() {
+        let fs = TimeoutFileSystem::new(MemoryFileSystem::new(), Duration::from_secs(5));
+        let mut file = fs.create_file("/data.bin").unwrap();
+        file.write_to_offset(0, b"Hello, World!").unwrap();
+        let mut buffer = [0; 5];
+        assert_eq!(file.read_at_offset(7, &mut buffer).unwrap(), 5);
+        assert_eq!(&buffer, b"World");
+        assert_eq!(fs.filesize("/data.bin").unwrap(), 13);
+        assert!(matches!(
+            fs.open_file("/missing.bin"),
+            Err(FileSystemError::PathMissing)
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_timeout_overrun() {
+        // After the first write, each write waits a second for the throttle.
+        let limits = ThrottleLimits::new().with_ops_per_second(1);
+        let throttled = ThrottledFileSystem::new(MemoryFileSystem::new(), limits);
+        let fs = TimeoutFileSystem::new(throttled, Duration::from_millis(50));
+        let mut file = fs.create_file("/slow.bin").unwrap();
+        file.write_to_offset(0, b"first").unwrap();
+
+        let start = Instant::now();
+        assert!(matches!(
+            file.write_to_offset(0, b"abandoned"),
+            Err(FileSystemError::TimedOut)
+        ));
+        assert!(matches!(
+            file.write_to_offset(0, b"skipped"),
+            Err(FileSystemError::TimedOut)
+        ));
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        // The abandoned write still lands, while the one queued behind it never runs.
+        std::thread::sleep(Duration::from_millis(1500));
+        assert_eq!(fs.inner().read("/slow.bin").unwrap(), b"abandoned");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_timeout_batch() {
+        use crate::BatchOperation;
+
+        let limits = ThrottleLimits::new().with_ops_per_second(1);
+        let throttled = ThrottledFileSystem::new(MemoryFileSystem::new(), limits);
+        let fs = TimeoutFileSystem::new(throttled, Duration::from_millis(50));
+        let results = fs.apply(&[
+            BatchOperation::write("/a.bin", b"a"),
+            BatchOperation::write("/b.bin", b"b"),
+        ]);
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, Err(FileSystemError::TimedOut))));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_timeout_conformance() {
+        crate::conformance::run(|| {
+            TimeoutFileSystem::new(MemoryFileSystem::new(), Duration::from_secs(5))
+        });
+    }
+}
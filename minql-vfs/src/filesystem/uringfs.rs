@@ -0,0 +1,706 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+#[cfg(feature = "mmap")]
+use crate::FileMapping;
+use crate::{
+    Advice, AsyncFileHandle, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, LocalFileHandle, LocalFileSystem, OpenOptions,
+    Permissions, SymlinkPolicy,
+};
+use io_uring::{opcode, squeue, types, IoUring};
+use nix::sys::eventfd::{EfdFlags, EventFd};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::SystemTime;
+
+/// `io_uring` `FileSystem`
+///
+/// A [`LocalFileSystem`] whose file handles read, write and sync through a shared `io_uring`
+/// submission queue instead of blocking system calls. Handles implement [`AsyncFileHandle`], so
+/// many reads can be in flight at once, and [`FileHandle`] as a synchronous shim which waits
+/// for each operation to complete. Directory and metadata operations go to the local
+/// filesystem directly.
+///
+/// A background thread reaps completions and wakes the waiting futures. Handles opened with
+/// [`OpenOptions::direct`] aren't supported, as operations use their own unaligned buffers.
+///
+/// ```rust,no_run
+/// use minql_vfs::{block_on, AsyncFileHandle, FileSystem, UringFileSystem};
+///
+/// let fs = UringFileSystem::new("/var/lib/minql").unwrap();
+/// let handle = fs.create_file("/table.dat").unwrap();
+/// block_on(async {
+///     handle.write_at(0, b"rows".to_vec()).await.unwrap();
+///     handle.sync_data().await.unwrap();
+///     assert_eq!(handle.read_at(0, 4).await.unwrap(), b"rows");
+/// });
+/// ```
+#[derive(Debug)]
+pub struct UringFileSystem {
+    local: LocalFileSystem,
+    ring: Arc<Ring>,
+}
+
+impl UringFileSystem {
+    /// Default number of submission queue entries.
+    pub const DEFAULT_QUEUE_DEPTH: u32 = 256;
+
+    /// Create a new `io_uring` `FileSystem` rooted at `root`.
+    pub fn new<T: AsRef<std::path::Path>>(root: T) -> FileSystemResult<UringFileSystem> {
+        Self::from_local(LocalFileSystem::new(root), Self::DEFAULT_QUEUE_DEPTH)
+    }
+
+    /// Create a new `io_uring` `FileSystem` over a configured [`LocalFileSystem`], with a
+    /// submission queue of `queue_depth` entries.
+    ///
+    /// Fails with [`FileSystemError::UnsupportedOperation`] if the kernel doesn't provide
+    /// `io_uring` or it is disabled.
+    pub fn from_local(
+        local: LocalFileSystem,
+        queue_depth: u32,
+    ) -> FileSystemResult<UringFileSystem> {
+        Ok(UringFileSystem {
+            local,
+            ring: Ring::new(queue_depth)?,
+        })
+    }
+
+    fn wrap(&self, local: LocalFileHandle, append: bool) -> UringFileHandle {
+        UringFileHandle {
+            fd: local.file().as_raw_fd(),
+            local,
+            ring: self.ring.clone(),
+            position: 0,
+            append,
+        }
+    }
+}
+
+impl FileSystem for UringFileSystem {
+    type FileHandle = UringFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        self.local.exists(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        self.local.is_file(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        self.local.is_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.local.filesize(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.local.create_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.local.create_directory_all(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        self.local.list_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.local.remove_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.local.remove_directory_all(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        Ok(self.wrap(self.local.create_file(path)?, false))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        Ok(self.wrap(self.local.open_file(path)?, false))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        self.local.remove_file(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.local.file_type(path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        self.local.create_symlink(target, path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.local.read_link(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.local.permissions(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.local.modified(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.local.set_permissions(path, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        if options.is_direct() {
+            return Err(FileSystemError::UnsupportedOperation);
+        }
+        Ok(self.wrap(self.local.open_with(path, options)?, options.is_append()))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        self.local.clone_file(src, dst)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        self.local.rename(from, to)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        self.local.space()
+    }
+}
+
+/// `io_uring` File Handle
+///
+/// Positional reads, writes and syncs go through the `io_uring` of its [`UringFileSystem`], while
+/// locking, sizing and hints go to the underlying [`LocalFileHandle`].
+#[derive(Debug)]
+pub struct UringFileHandle {
+    local: LocalFileHandle,
+    fd: RawFd,
+    ring: Arc<Ring>,
+    position: u64,
+    append: bool,
+}
+
+impl AsyncFileHandle for UringFileHandle {
+    fn read_at(
+        &self,
+        offset: u64,
+        len: usize,
+    ) -> impl Future<Output = FileSystemResult<Vec<u8>>> + Send {
+        let length = u32::try_from(len).unwrap_or(u32::MAX);
+        let operation = complete(self.ring.submit(vec![0; length as usize], |buffer| {
+            opcode::Read::new(types::Fd(self.fd), buffer, length)
+                .offset(offset)
+                .build()
+        }));
+        async move {
+            let (read, mut buffer) = operation.await?;
+            buffer.truncate(read);
+            Ok(buffer)
+        }
+    }
+
+    fn write_at(
+        &self,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> impl Future<Output = FileSystemResult<usize>> + Send {
+        let length = u32::try_from(data.len()).unwrap_or(u32::MAX);
+        let operation = complete(self.ring.submit(data, |buffer| {
+            opcode::Write::new(types::Fd(self.fd), buffer, length)
+                .offset(offset)
+                .build()
+        }));
+        async move { Ok(operation.await?.0) }
+    }
+
+    fn sync_data(&self) -> impl Future<Output = FileSystemResult<()>> + Send {
+        let operation = complete(self.ring.submit(Vec::new(), |_| {
+            opcode::Fsync::new(types::Fd(self.fd))
+                .flags(types::FsyncFlags::DATASYNC)
+                .build()
+        }));
+        async move { operation.await.map(|_| ()) }
+    }
+
+    fn sync_all(&self) -> impl Future<Output = FileSystemResult<()>> + Send {
+        let operation = complete(self.ring.submit(Vec::new(), |_| {
+            opcode::Fsync::new(types::Fd(self.fd)).build()
+        }));
+        async move { operation.await.map(|_| ()) }
+    }
+}
+
+impl Read for UringFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.read_at_offset(self.position, buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for UringFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.append {
+            self.position = self.get_size()?;
+        }
+        let written = self.write_to_offset(self.position, buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for UringFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(offset);
+            }
+            SeekFrom::Current(delta) => (self.position, delta),
+            SeekFrom::End(delta) => (self.get_size()?, delta),
+        };
+        self.position = base.checked_add_signed(delta).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+impl FileHandle for UringFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        self.local.path()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        self.local.get_size()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.local.set_size(new_size)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.local.allocate(len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        crate::block_on(AsyncFileHandle::sync_all(self))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        crate::block_on(AsyncFileHandle::sync_data(self))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        self.local.get_lock_status()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.local.set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.local.try_set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.local.lock_range(offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.local.unlock_range(offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        self.local.advise(offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        let data = crate::block_on(self.read_at(offset, buffer.len()))?;
+        buffer[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+
+    #[tracing::instrument(level = "trace", skip(buffer))]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        crate::block_on(self.write_at(offset, buffer.to_vec()))
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<FileMapping> {
+        self.local.map_readonly(offset, len)
+    }
+}
+
+/// Operation submitted to a [`Ring`], owning its buffer until the kernel is done with it.
+#[derive(Debug, Default)]
+struct Operation {
+    buffer: Vec<u8>,
+    result: Option<i32>,
+    waker: Option<Waker>,
+    abandoned: bool,
+}
+
+struct RingState {
+    ring: IoUring,
+    next: u64,
+    operations: HashMap<u64, Operation>,
+}
+
+impl std::fmt::Debug for RingState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RingState")
+            .field("next", &self.next)
+            .field("operations", &self.operations.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl RingState {
+    /// Record completed operations, waking their futures.
+    fn reap(&mut self) {
+        for entry in self.ring.completion() {
+            let id = entry.user_data();
+            let Some(operation) = self.operations.get_mut(&id) else {
+                continue;
+            };
+            if operation.abandoned {
+                self.operations.remove(&id);
+            } else {
+                operation.result = Some(entry.result());
+                if let Some(waker) = operation.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+/// `io_uring` shared by the handles of a [`UringFileSystem`].
+#[derive(Debug)]
+struct Ring {
+    state: Mutex<RingState>,
+    eventfd: Arc<EventFd>,
+}
+
+impl Ring {
+    fn new(queue_depth: u32) -> FileSystemResult<Arc<Ring>> {
+        let ring = IoUring::new(queue_depth).map_err(|err| match err.raw_os_error() {
+            Some(nix::libc::ENOSYS | nix::libc::EPERM) => FileSystemError::UnsupportedOperation,
+            _ => FileSystemError::io_error(err),
+        })?;
+        let eventfd = Arc::new(
+            EventFd::from_value_and_flags(0, EfdFlags::EFD_CLOEXEC)
+                .map_err(|errno| FileSystemError::io_error(errno.into()))?,
+        );
+        ring.submitter()
+            .register_eventfd(eventfd.as_raw_fd())
+            .map_err(FileSystemError::io_error)?;
+        let ring = Arc::new(Ring {
+            state: Mutex::new(RingState {
+                ring,
+                next: 0,
+                operations: HashMap::new(),
+            }),
+            eventfd: eventfd.clone(),
+        });
+        let weak = Arc::downgrade(&ring);
+        std::thread::Builder::new()
+            .name("minql-uring".to_string())
+            .spawn(move || Ring::reaper(&weak, &eventfd))
+            .map_err(FileSystemError::io_error)?;
+        Ok(ring)
+    }
+
+    /// Wake futures as their operations complete until the ring is dropped.
+    fn reaper(ring: &Weak<Ring>, eventfd: &EventFd) {
+        while eventfd.read().is_ok() {
+            let Some(ring) = ring.upgrade() else {
+                return;
+            };
+            ring.state.lock().expect("Poisoned Lock").reap();
+        }
+    }
+
+    /// Submit an operation built over the buffer it will own.
+    fn submit(
+        self: &Arc<Self>,
+        mut buffer: Vec<u8>,
+        build: impl FnOnce(*mut u8) -> squeue::Entry,
+    ) -> std::io::Result<UringOperation> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        let id = state.next;
+        state.next += 1;
+        let entry = build(buffer.as_mut_ptr()).user_data(id);
+        // The buffer's allocation doesn't move when the operation is moved into the table.
+        state.operations.insert(
+            id,
+            Operation {
+                buffer,
+                ..Operation::default()
+            },
+        );
+        loop {
+            // SAFETY: the buffer the entry points at is owned by the operation table, which only
+            // releases it once the kernel has posted the operation's completion.
+            #[allow(unsafe_code)]
+            let pushed = unsafe { state.ring.submission().push(&entry) };
+            if pushed.is_ok() {
+                break;
+            }
+            // The submission queue is full, hand it to the kernel to make room.
+            if let Err(err) = state.ring.submit() {
+                state.operations.remove(&id);
+                return Err(err);
+            }
+            state.reap();
+        }
+        state.ring.submit()?;
+        Ok(UringOperation {
+            ring: self.clone(),
+            id,
+            finished: false,
+        })
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        // Abandoned operations may still be in flight, and their buffers must outlive them.
+        let state = self.state.get_mut().expect("Poisoned Lock");
+        state.reap();
+        while state.operations.values().any(|op| op.result.is_none()) {
+            if let Err(err) = state.ring.submit_and_wait(1) {
+                tracing::warn!(?err, "Failed to drain io_uring on drop");
+                std::mem::forget(std::mem::take(&mut state.operations));
+                break;
+            }
+            state.reap();
+        }
+        if let Err(err) = self.eventfd.write(1) {
+            tracing::warn!(?err, "Failed to stop io_uring reaper on drop");
+        }
+    }
+}
+
+/// Await a submitted operation, or fail with the error submitting it.
+async fn complete(
+    operation: std::io::Result<UringOperation>,
+) -> FileSystemResult<(usize, Vec<u8>)> {
+    match operation {
+        Ok(operation) => operation.await,
+        Err(err) => Err(FileSystemError::io_error(err)),
+    }
+}
+
+/// Future of an operation submitted to a [`Ring`], resolving to its result and buffer.
+struct UringOperation {
+    ring: Arc<Ring>,
+    id: u64,
+    finished: bool,
+}
+
+impl Future for UringOperation {
+    type Output = FileSystemResult<(usize, Vec<u8>)>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.ring.state.lock().expect("Poisoned Lock");
+        state.reap();
+        let operation = state
+            .operations
+            .get_mut(&self.id)
+            .expect("Pending Operation");
+        let Some(result) = operation.result else {
+            operation.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        };
+        let operation = state
+            .operations
+            .remove(&self.id)
+            .expect("Pending Operation");
+        drop(state);
+        self.finished = true;
+        Poll::Ready(match usize::try_from(result) {
+            Ok(count) => Ok((count, operation.buffer)),
+            Err(_) => Err(FileSystemError::io_error(
+                std::io::Error::from_raw_os_error(-result),
+            )),
+        })
+    }
+}
+
+impl Drop for UringOperation {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let mut state = self.ring.state.lock().expect("Poisoned Lock");
+        if let Some(operation) = state.operations.get_mut(&self.id) {
+            if operation.result.is_some() {
+                state.operations.remove(&self.id);
+            } else {
+                operation.abandoned = true;
+                operation.waker = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_uring_filesystem() {
+        use crate::{block_on, AsyncFileHandle, FileHandle, FileSystem, FileSystemError};
+        use crate::{OpenOptions, UringFileSystem};
+        use std::io::{Read, Seek, SeekFrom, Write};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let root = std::env::temp_dir().join(format!(
+            "test-uring-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let fs = match UringFileSystem::new(&root) {
+            Ok(fs) => fs,
+            Err(FileSystemError::UnsupportedOperation) => return,
+            Err(err) => panic!("{err:?}"),
+        };
+
+        // The synchronous shim behaves like any other handle
+        let mut handle = fs.create_file("/table.dat").unwrap();
+        handle.write_all(b"hello world").unwrap();
+        FileHandle::sync_data(&mut handle).unwrap();
+        handle.seek(SeekFrom::Start(6)).unwrap();
+        let mut contents = String::new();
+        handle.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "world");
+        assert_eq!(handle.get_size().unwrap(), 11);
+
+        // Operations are in flight as soon as they are created
+        let reads = (0..4)
+            .map(|index| handle.read_at(index * 3, 3))
+            .collect::<Vec<_>>();
+        let parts = reads
+            .into_iter()
+            .map(|read| block_on(read).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(parts.concat(), b"hello world");
+        drop(handle.read_at(0, 11));
+        block_on(async {
+            assert_eq!(handle.write_at(11, b"!".to_vec()).await.unwrap(), 1);
+            handle.sync_all().await.unwrap();
+        });
+        drop(handle);
+        assert_eq!(fs.read("/table.dat").unwrap(), b"hello world!");
+
+        let options = OpenOptions::new().write(true).append(true);
+        let mut handle = fs.open_with("/table.dat", options).unwrap();
+        handle.write_all(b"?").unwrap();
+        assert_eq!(fs.read("/table.dat").unwrap(), b"hello world!?");
+        assert!(matches!(
+            fs.open_with("/table.dat", OpenOptions::new().read(true).direct(true)),
+            Err(FileSystemError::UnsupportedOperation)
+        ));
+        drop(handle);
+        drop(fs);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_uring_conformance() {
+        use crate::UringFileSystem;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let root = std::env::temp_dir().join(format!(
+            "test-uring-conformance-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        ));
+        let run = AtomicUsize::new(0);
+        crate::conformance::run(|| {
+            let root = root.join(run.fetch_add(1, Ordering::Relaxed).to_string());
+            std::fs::create_dir_all(&root).unwrap();
+            UringFileSystem::new(root).unwrap()
+        });
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,751 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::utility::normalize_path;
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+/// Contents of a file as seen by snapshots with ids after the previous version's `until` and up
+/// to and including its own.
+#[derive(Debug)]
+struct Version {
+    until: u64,
+    /// `None` if the file didn't exist.
+    contents: Option<Arc<[u8]>>,
+}
+
+#[derive(Debug, Default)]
+struct VersionState {
+    /// Id given to the next snapshot.
+    next_snapshot: u64,
+    /// Live snapshot ids and how many handles share each.
+    snapshots: BTreeMap<u64, usize>,
+    /// Preserved versions of each modified file, oldest first.
+    history: HashMap<String, Vec<Version>>,
+}
+
+impl VersionState {
+    /// Version of `path` seen by snapshot `id`, or `None` if it's the current file.
+    fn version(&self, id: u64, path: &str) -> Option<&Version> {
+        self.history
+            .get(path)?
+            .iter()
+            .find(|version| version.until >= id)
+    }
+}
+
+#[derive(Debug)]
+struct VersionShared<F: FileSystem> {
+    inner: F,
+    state: Mutex<VersionState>,
+}
+
+impl<F: FileSystem> VersionShared<F> {
+    fn lock(&self) -> MutexGuard<'_, VersionState> {
+        self.state.lock().expect("Poisoned Lock")
+    }
+
+    /// Copy the current contents of `path` aside if a live snapshot hasn't seen them preserved.
+    fn preserve(&self, state: &mut VersionState, path: &str) -> FileSystemResult<()> {
+        let Some(&newest) = state.snapshots.keys().next_back() else {
+            return Ok(());
+        };
+        let preserved = state
+            .history
+            .get(path)
+            .and_then(|history| history.last())
+            .is_some_and(|version| version.until >= newest);
+        if !preserved {
+            let contents = self.current(path)?;
+            state
+                .history
+                .entry(path.to_string())
+                .or_default()
+                .push(Version {
+                    until: newest,
+                    contents,
+                });
+        }
+        Ok(())
+    }
+
+    /// Preserve every file below `path`.
+    fn preserve_all(&self, state: &mut VersionState, path: &str) -> FileSystemResult<()> {
+        let mut files = BTreeSet::new();
+        collect_files(&self.inner, path, &mut files)?;
+        for file in files {
+            self.preserve(state, &file)?;
+        }
+        Ok(())
+    }
+
+    /// Current contents of the file at `path`, if there is one.
+    fn current(&self, path: &str) -> FileSystemResult<Option<Arc<[u8]>>> {
+        if !self.inner.exists(path)? || !self.inner.is_file(path)? {
+            return Ok(None);
+        }
+        let mut contents = Vec::new();
+        self.inner
+            .open_file(path)?
+            .read_to_end(&mut contents)
+            .map_err(FileSystemError::io_error)?;
+        Ok(Some(contents.into()))
+    }
+}
+
+/// Snapshotting `FileSystem` Wrapper
+///
+/// Forwards to another [`FileSystem`] while keeping the versions of files that live snapshots
+/// still need. [`VersionedFileSystem::snapshot`] is cheap: nothing is copied until a file is
+/// first modified after the snapshot, at which point its previous contents are kept in memory.
+/// Versions outlive the snapshots that need them until [`VersionedFileSystem::prune`] is called.
+///
+/// Snapshots version file contents only, and the check and copy before each modification are
+/// serialised so every snapshot sees a single consistent point in time.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, VersionedFileSystem};
+/// use std::io::Write;
+///
+/// let fs = VersionedFileSystem::new(MemoryFileSystem::new());
+/// fs.create_file("/data.txt").unwrap().write_all(b"before").unwrap();
+/// let snapshot = fs.snapshot();
+/// fs.remove_file("/data.txt").unwrap();
+/// assert_eq!(snapshot.read("/data.txt").unwrap(), b"before");
+/// assert!(!fs.exists("/data.txt").unwrap());
+/// ```
+#[derive(Debug)]
+pub struct VersionedFileSystem<F: FileSystem> {
+    shared: Arc<VersionShared<F>>,
+}
+
+impl<F: FileSystem> VersionedFileSystem<F> {
+    /// Create a new Versioned `FileSystem` over `filesystem`.
+    pub fn new(filesystem: F) -> VersionedFileSystem<F> {
+        VersionedFileSystem {
+            shared: Arc::new(VersionShared {
+                inner: filesystem,
+                state: Mutex::new(VersionState::default()),
+            }),
+        }
+    }
+
+    /// Take an immutable point-in-time view of every file.
+    #[must_use]
+    pub fn snapshot(&self) -> VersionedSnapshot<F> {
+        let mut state = self.shared.lock();
+        let id = state.next_snapshot;
+        state.next_snapshot += 1;
+        *state.snapshots.entry(id).or_default() += 1;
+        VersionedSnapshot {
+            id,
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Drop every preserved version that no live snapshot can see, returning how many were
+    /// dropped.
+    #[must_use]
+    pub fn prune(&self) -> usize {
+        let mut state = self.shared.lock();
+        let VersionState {
+            snapshots, history, ..
+        } = &mut *state;
+        let mut pruned = 0;
+        history.retain(|_, versions| {
+            let mut after = None;
+            versions.retain(|version| {
+                let start = after.map_or(0, |until| until + 1);
+                after = Some(version.until);
+                let live = snapshots.range(start..=version.until).next().is_some();
+                pruned += usize::from(!live);
+                live
+            });
+            !versions.is_empty()
+        });
+        pruned
+    }
+
+    /// Number of preserved versions across every file.
+    #[must_use]
+    pub fn versions(&self) -> usize {
+        self.shared.lock().history.values().map(Vec::len).sum()
+    }
+
+    /// Underlying `FileSystem`, where changes bypass versioning.
+    #[must_use]
+    pub fn inner(&self) -> &F {
+        &self.shared.inner
+    }
+
+    fn wrap(&self, path: String, inner: F::FileHandle) -> VersionedFileHandle<F> {
+        VersionedFileHandle {
+            path,
+            shared: self.shared.clone(),
+            inner,
+        }
+    }
+}
+
+impl<F: FileSystem> FileSystem for VersionedFileSystem<F> {
+    type FileHandle = VersionedFileHandle<F>;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        self.shared.inner.exists(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        self.shared.inner.is_file(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        self.shared.inner.is_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.shared.inner.filesize(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.shared.inner.create_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        self.shared.inner.create_directory_all(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        self.shared.inner.list_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        self.shared.inner.remove_directory(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        let mut state = self.shared.lock();
+        self.shared
+            .preserve_all(&mut state, &normalize_path(path)?)?;
+        self.shared.inner.remove_directory_all(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let normalized = normalize_path(path)?;
+        let mut state = self.shared.lock();
+        self.shared.preserve(&mut state, &normalized)?;
+        let handle = self.shared.inner.create_file(path)?;
+        drop(state);
+        Ok(self.wrap(normalized, handle))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        let handle = self.shared.inner.open_file(path)?;
+        Ok(self.wrap(normalize_path(path)?, handle))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        let mut state = self.shared.lock();
+        self.shared.preserve(&mut state, &normalize_path(path)?)?;
+        self.shared.inner.remove_file(path)
+    }
+
+    /// Snapshots keep seeing the file at `from` and whatever it replaced at `to`.
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        let mut state = self.shared.lock();
+        self.shared.preserve(&mut state, &normalize_path(from)?)?;
+        self.shared.preserve(&mut state, &normalize_path(to)?)?;
+        self.shared.inner.rename(from, to)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        let mut state = self.shared.lock();
+        self.shared.preserve(&mut state, &normalize_path(dst)?)?;
+        self.shared.inner.clone_file(src, dst)
+    }
+
+    /// Every file the batch changes is preserved before the batch is forwarded, so snapshots
+    /// see either none of it or, once taken afterwards, all of it. Operations on files that
+    /// can't be preserved fail on their own.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let mut state = self.shared.lock();
+        let mut preserved = Vec::with_capacity(batch.len());
+        let mut results: Vec<Option<FileSystemResult<()>>> = Vec::with_capacity(batch.len());
+        for operation in batch {
+            let files = match operation {
+                BatchOperation::Write { .. }
+                | BatchOperation::Rename { .. }
+                | BatchOperation::RemoveFile { .. } => operation.paths().collect(),
+                _ => Vec::new(),
+            };
+            match files
+                .into_iter()
+                .try_for_each(|path| self.shared.preserve(&mut state, &normalize_path(path)?))
+            {
+                Ok(()) => {
+                    preserved.push(operation.clone());
+                    results.push(None);
+                }
+                Err(error) => results.push(Some(Err(error))),
+            }
+        }
+        let mut applied = self.shared.inner.apply(&preserved).into_iter();
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| applied.next().expect("Missing Batch Result")))
+            .collect()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        self.shared.inner.file_type(path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        self.shared.inner.create_symlink(target, path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        self.shared.inner.read_link(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        self.shared.inner.permissions(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        self.shared.inner.modified(path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        self.shared.inner.set_permissions(path, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        let normalized = normalize_path(path)?;
+        let mut state = self.shared.lock();
+        if options.is_truncate() || options.is_create() || options.is_create_new() {
+            self.shared.preserve(&mut state, &normalized)?;
+        }
+        let handle = self.shared.inner.open_with(path, options)?;
+        drop(state);
+        Ok(self.wrap(normalized, handle))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        self.shared.inner.space()
+    }
+}
+
+/// Immutable point-in-time view of the files of a [`VersionedFileSystem`].
+///
+/// Keeps the versions it sees alive until it's dropped and the filesystem is pruned.
+pub struct VersionedSnapshot<F: FileSystem> {
+    id: u64,
+    shared: Arc<VersionShared<F>>,
+}
+
+impl<F: FileSystem> VersionedSnapshot<F> {
+    /// Id of this snapshot, increasing with each snapshot taken.
+    #[must_use]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Contents of the file at `path` when the snapshot was taken.
+    pub fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        self.contents(path)?
+            .map(|contents| contents.to_vec())
+            .ok_or(FileSystemError::PathMissing)
+    }
+
+    /// Check if a file existed at `path` when the snapshot was taken.
+    pub fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        Ok(self.contents(path)?.is_some())
+    }
+
+    /// Size of the file at `path` when the snapshot was taken.
+    pub fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        self.contents(path)?
+            .map(|contents| contents.len() as u64)
+            .ok_or(FileSystemError::PathMissing)
+    }
+
+    /// Paths of every file that existed when the snapshot was taken, in order.
+    pub fn files(&self) -> FileSystemResult<Vec<String>> {
+        let state = self.shared.lock();
+        let mut current = BTreeSet::new();
+        collect_files(&self.shared.inner, "/", &mut current)?;
+        let mut files = current
+            .iter()
+            .filter(|path| state.version(self.id, path).is_none())
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        for path in state.history.keys() {
+            if state
+                .version(self.id, path)
+                .is_some_and(|version| version.contents.is_some())
+            {
+                files.insert(path.clone());
+            }
+        }
+        Ok(files.into_iter().collect())
+    }
+
+    fn contents(&self, path: &str) -> FileSystemResult<Option<Arc<[u8]>>> {
+        let path = normalize_path(path)?;
+        // Hold the lock while reading so a writer can't slip in before the current file is read.
+        let state = self.shared.lock();
+        match state.version(self.id, &path) {
+            Some(version) => Ok(version.contents.clone()),
+            None => self.shared.current(&path),
+        }
+    }
+}
+
+impl<F: FileSystem> std::fmt::Debug for VersionedSnapshot<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VersionedSnapshot")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F: FileSystem> Drop for VersionedSnapshot<F> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        if let Some(count) = state.snapshots.get_mut(&self.id) {
+            *count -= 1;
+            if *count == 0 {
+                state.snapshots.remove(&self.id);
+            }
+        }
+    }
+}
+
+/// Versioned File Handle
+///
+/// Preserves the file for live snapshots before each modification.
+pub struct VersionedFileHandle<F: FileSystem> {
+    path: String,
+    shared: Arc<VersionShared<F>>,
+    inner: F::FileHandle,
+}
+
+impl<F: FileSystem> VersionedFileHandle<F> {
+    /// Run `operation` after preserving the file, holding the lock so no snapshot is taken
+    /// between the two.
+    fn modify<T>(
+        &mut self,
+        operation: impl FnOnce(&mut F::FileHandle) -> FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        let mut state = self.shared.lock();
+        self.shared.preserve(&mut state, &self.path)?;
+        operation(&mut self.inner)
+    }
+}
+
+impl<F: FileSystem> std::fmt::Debug for VersionedFileHandle<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<F: FileSystem> Read for VersionedFileHandle<F> {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<F: FileSystem> Write for VersionedFileHandle<F> {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(self.modify(|inner| inner.write(buf).map_err(FileSystemError::io_error))?)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<F: FileSystem> Seek for VersionedFileHandle<F> {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<F: FileSystem> FileHandle for VersionedFileHandle<F> {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        self.inner.path()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        self.inner.get_size()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.modify(|inner| inner.set_size(new_size))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.inner.sync_all()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.inner.sync_data()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        self.inner.get_lock_status()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.try_set_lock_status(mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        self.inner.read_at_offset(offset, buffer)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.modify(|inner| inner.write_to_offset(offset, buffer))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.inner.lock_range(offset, len, mode)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.inner.unlock_range(offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        self.inner.advise(offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        self.inner.read_at_vectored(offset, buffers)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        self.modify(|inner| inner.write_at_vectored(offset, buffers))
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        self.inner.map_readonly(offset, len)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        self.inner.alignment()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.modify(|inner| inner.allocate(len))
+    }
+}
+
+/// Collect the normalized path of every file at or below `path`.
+fn collect_files<F: FileSystem>(
+    filesystem: &F,
+    path: &str,
+    files: &mut BTreeSet<String>,
+) -> FileSystemResult<()> {
+    if !filesystem.exists(path)? {
+        return Ok(());
+    }
+    if filesystem.is_file(path)? {
+        files.insert(normalize_path(path)?);
+        return Ok(());
+    }
+    for name in filesystem.list_directory(path)? {
+        collect_files(
+            filesystem,
+            &format!("{}/{name}", path.trim_end_matches('/')),
+            files,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{FileHandle, FileSystem, MemoryFileSystem, OpenOptions, VersionedFileSystem};
+    use std::io::Write;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_versioned_filesystem() {
+        let fs = VersionedFileSystem::new(MemoryFileSystem::new());
+        fs.create_directory("/data").unwrap();
+        let mut file = fs.create_file("/data/a.txt").unwrap();
+        file.write_all(b"one").unwrap();
+
+        // No snapshots, so nothing is preserved
+        file.write_all(b" two").unwrap();
+        assert_eq!(fs.versions(), 0);
+
+        let first = fs.snapshot();
+        file.write_all(b" three").unwrap();
+        file.write_all(b" four").unwrap();
+        fs.create_file("/data/b.txt")
+            .unwrap()
+            .write_all(b"new")
+            .unwrap();
+        assert_eq!(fs.versions(), 2);
+
+        let second = fs.snapshot();
+        file.write_to_offset(0, b"ONE").unwrap();
+        fs.remove_directory_all("/data").unwrap();
+
+        assert_eq!(first.read("/data/a.txt").unwrap(), b"one two");
+        assert!(!first.exists("/data/b.txt").unwrap());
+        assert_eq!(first.files().unwrap(), vec!["/data/a.txt"]);
+        assert_eq!(second.read("/data/a.txt").unwrap(), b"one two three four");
+        assert_eq!(second.filesize("/data/b.txt").unwrap(), 3);
+        assert_eq!(second.files().unwrap(), vec!["/data/a.txt", "/data/b.txt"]);
+
+        // Versions only the dropped snapshot could see are pruned
+        drop(first);
+        assert_eq!(fs.prune(), 2);
+        assert_eq!(second.read("/data/a.txt").unwrap(), b"one two three four");
+        drop(second);
+        assert_eq!(fs.prune(), 2);
+        assert_eq!(fs.versions(), 0);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_versioned_snapshot_sees_current_files() {
+        let fs = VersionedFileSystem::new(MemoryFileSystem::new());
+        fs.create_file("/log").unwrap().write_all(b"abc").unwrap();
+        let snapshot = fs.snapshot();
+        assert_eq!(snapshot.read("/log").unwrap(), b"abc");
+
+        let options = OpenOptions::new().write(true).truncate(true);
+        let mut file = fs.open_with("/log", options).unwrap();
+        file.write_all(b"xyz").unwrap();
+        assert_eq!(snapshot.read("/log").unwrap(), b"abc");
+        assert_eq!(fs.versions(), 1);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_versioned_rename() {
+        let fs = VersionedFileSystem::new(MemoryFileSystem::new());
+        fs.write("/manifest", b"old").unwrap();
+        fs.write("/manifest.tmp", b"new").unwrap();
+        let snapshot = fs.snapshot();
+        fs.rename("/manifest.tmp", "/manifest").unwrap();
+        assert_eq!(fs.read("/manifest").unwrap(), b"new");
+        assert!(!fs.exists("/manifest.tmp").unwrap());
+        assert_eq!(snapshot.read("/manifest").unwrap(), b"old");
+        assert_eq!(snapshot.read("/manifest.tmp").unwrap(), b"new");
+        assert_eq!(fs.versions(), 2);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_versioned_batch() {
+        use crate::BatchOperation;
+
+        let fs = VersionedFileSystem::new(MemoryFileSystem::new());
+        fs.write("/a.txt", b"a").unwrap();
+        fs.write("/b.txt", b"b").unwrap();
+        let snapshot = fs.snapshot();
+        let results = fs.apply(&[
+            BatchOperation::write("/a.txt", b"A"),
+            BatchOperation::create_directory("/dir"),
+            BatchOperation::rename("/a.txt", "/dir/a.txt"),
+            BatchOperation::remove_file("/b.txt"),
+        ]);
+        assert!(results.iter().all(Result::is_ok));
+        fs.clone_file("/dir/a.txt", "/c.txt").unwrap();
+        assert_eq!(snapshot.read("/a.txt").unwrap(), b"a");
+        assert_eq!(snapshot.read("/b.txt").unwrap(), b"b");
+        assert!(!snapshot.exists("/dir/a.txt").unwrap());
+        assert!(!snapshot.exists("/c.txt").unwrap());
+        assert_eq!(fs.read("/c.txt").unwrap(), b"A");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_versioned_conformance() {
+        use crate::{MemoryFileSystem, VersionedFileSystem};
+
+        crate::conformance::run(|| VersionedFileSystem::new(MemoryFileSystem::new()));
+    }
+}
@@ -14,43 +14,393 @@
 // limitations under the License.
 //
 
+use crate::filesystem::metricfs::FileSystemMetrics;
+use crate::filesystem::mountfs::{MountFileSystem, MountTable};
 use crate::filesystem::{DynamicFileSystem, DynamicFileSystemProvider, FileSystemProvider};
-use crate::{FileHandle, FileLockMode, FileSystem, FileSystemError, FileSystemResult};
+use crate::utility::normalize_path;
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, LocalFileSystemProvider, ManagerMetrics,
+    MemoryFileSystemProvider, MetricFileSystem, OpenOptions, Permissions, SchemeMetrics,
+    SymlinkPolicy,
+};
 use minql_uri::URI;
-use std::collections::HashMap;
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+/// Filesystems provisioned by a [`VirtualFileSystemManager`] kept for reuse by default.
+const DEFAULT_PROVISION_CACHE: usize = 32;
+
+/// Virtual `FileSystem` Manager
+///
+/// Routes URIs to registered [`FileSystemProvider`]s by scheme and maintains a table of
+/// filesystems mounted at path prefixes, exposed as a single namespace.
+///
+/// Provisioned filesystems are cached by the scheme, authority, path and query of their URI, so
+/// repeated lookups reuse one filesystem, and its connections, instead of provisioning anew.
+/// The least recently used are evicted beyond the cache capacity.
+///
+/// Lookups are counted per scheme, and with [`VirtualFileSystemManager::with_metrics`] every
+/// provisioned and mounted filesystem is wrapped in a [`MetricFileSystem`], its operations
+/// gathered by scheme and by mount in [`VirtualFileSystemManager::metrics`].
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, VirtualFileSystemManager};
+///
+/// let manager = VirtualFileSystemManager::default();
+/// manager.mount("/data", MemoryFileSystem::new()).unwrap();
+/// manager.mount("/tmp", MemoryFileSystem::new()).unwrap();
+///
+/// let fs = manager.namespace();
+/// fs.create_file("/tmp/scratch.txt").unwrap();
+/// assert_eq!(fs.list_directory("/").unwrap(), vec!["data", "tmp"]);
+/// ```
+#[derive(Debug)]
+pub struct VirtualFileSystemManager {
+    providers: RwLock<HashMap<String, Arc<dyn DynamicFileSystemProvider>>>,
+    mounts: MountTable,
+    cache: Mutex<ProvisionCache>,
+    cache_capacity: usize,
+    collect_metrics: bool,
+    registry: Mutex<MetricRegistry>,
+}
+
+#[derive(Debug, Default)]
+struct ProvisionCache {
+    entries: HashMap<String, ProvisionedEntry>,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct ProvisionedEntry {
+    filesystem: Arc<dyn DynamicFileSystem>,
+    used: u64,
+}
+
+/// Lookup counters and shared filesystem metrics of each scheme and mount.
+#[derive(Debug, Default)]
+struct MetricRegistry {
+    schemes: BTreeMap<String, SchemeCounters>,
+    mounts: BTreeMap<String, FileSystemMetrics>,
+}
 
-/// Virtual FileSystem Manager
 #[derive(Debug, Default)]
-pub struct VirtualFileSystemManager(RwLock<HashMap<String, Arc<dyn DynamicFileSystemProvider>>>);
+struct SchemeCounters {
+    provisions: u64,
+    failures: u64,
+    cache_hits: u64,
+    metrics: FileSystemMetrics,
+}
+
+impl Default for VirtualFileSystemManager {
+    fn default() -> Self {
+        VirtualFileSystemManager {
+            providers: RwLock::default(),
+            mounts: MountTable::default(),
+            cache: Mutex::default(),
+            cache_capacity: DEFAULT_PROVISION_CACHE,
+            collect_metrics: false,
+            registry: Mutex::default(),
+        }
+    }
+}
 
 impl VirtualFileSystemManager {
+    /// Create a manager with providers for `file://` URIs, backed by [`LocalFileSystem`], and
+    /// `mem://` URIs, backed by [`MemoryFileSystem`], already registered.
+    ///
+    /// ```rust
+    /// use minql_vfs::{FileSystem, VirtualFileSystemManager};
+    ///
+    /// let manager = VirtualFileSystemManager::with_defaults();
+    /// assert_eq!(manager.providers(), vec!["file", "mem"]);
+    /// manager.get("mem://scratch").unwrap().create_file("/scratch.txt").unwrap();
+    /// assert!(manager.get("mem://scratch").unwrap().exists("/scratch.txt").unwrap());
+    /// ```
+    ///
+    /// [`LocalFileSystem`]: crate::LocalFileSystem
+    /// [`MemoryFileSystem`]: crate::MemoryFileSystem
+    #[must_use]
+    pub fn with_defaults() -> VirtualFileSystemManager {
+        let manager = VirtualFileSystemManager::default();
+        manager.insert_provider(LocalFileSystemProvider::default());
+        manager.insert_provider(MemoryFileSystemProvider::default());
+        manager
+    }
+
     /// Register a new Filesystem Provider
     #[tracing::instrument(level = "trace")]
     pub fn register<T: FileSystemProvider>(&self, provider: T) -> FileSystemResult<()> {
-        let mut lock = self.0.write().unwrap();
-        let provider = Arc::new(provider);
-        for scheme in provider.schemes().iter() {
-            lock.insert(scheme.to_string(), provider.clone());
+        self.insert_provider(provider);
+        Ok(())
+    }
+
+    /// Keep up to `capacity` provisioned filesystems for reuse, `0` disabling the cache.
+    #[must_use]
+    pub fn cache_capacity(mut self, capacity: usize) -> VirtualFileSystemManager {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    /// Wrap every filesystem provisioned or mounted afterwards in a [`MetricFileSystem`].
+    ///
+    /// Filesystems provisioned for a scheme share its metrics, and each mount has its own, so
+    /// a filesystem mounted from a URI counts toward both.
+    #[must_use]
+    pub fn with_metrics(mut self) -> VirtualFileSystemManager {
+        self.collect_metrics = true;
+        self
+    }
+
+    /// Get the lookup counters of every scheme, along with the metrics of the filesystems
+    /// provisioned for each and mounted at each prefix if collected.
+    #[tracing::instrument(level = "trace")]
+    pub fn metrics(&self) -> ManagerMetrics {
+        let registry = self.registry.lock().expect("Poisoned Lock");
+        ManagerMetrics {
+            schemes: registry
+                .schemes
+                .iter()
+                .map(|(scheme, counters)| {
+                    let metrics = SchemeMetrics {
+                        provisions: counters.provisions,
+                        failures: counters.failures,
+                        cache_hits: counters.cache_hits,
+                        metrics: counters.metrics.snapshot().aggregate().clone(),
+                    };
+                    (scheme.clone(), metrics)
+                })
+                .collect(),
+            mounts: registry
+                .mounts
+                .iter()
+                .map(|(prefix, metrics)| (prefix.clone(), metrics.snapshot().aggregate().clone()))
+                .collect(),
         }
+    }
+
+    /// Remove the Filesystem Provider registered for a scheme.
+    ///
+    /// Only the given scheme is removed, a provider registered for several keeps serving the
+    /// rest. Cached filesystems of the scheme are dropped, but those already handed out or
+    /// mounted are unaffected. Returns [`FileSystemError::UnknownFileSystem`] if no provider
+    /// handles the scheme.
+    #[tracing::instrument(level = "trace")]
+    pub fn unregister(&self, scheme: &str) -> FileSystemResult<()> {
+        let mut lock = self.providers.write().expect("Poisoned Lock");
+        lock.remove(scheme)
+            .ok_or(FileSystemError::UnknownFileSystem)?;
+        let prefix = format!("{scheme}://");
+        let mut cache = self.cache.lock().expect("Poisoned Lock");
+        cache.entries.retain(|key, _| !key.starts_with(&prefix));
         Ok(())
     }
 
+    /// Drop the cached filesystem provisioned for a URI, so the next lookup provisions a new one.
+    ///
+    /// Returns whether a filesystem was cached for the URI.
+    #[tracing::instrument(level = "trace")]
+    pub fn invalidate(&self, uri: &str) -> FileSystemResult<bool> {
+        let key = provision_key(uri)?;
+        let mut cache = self.cache.lock().expect("Poisoned Lock");
+        Ok(cache.entries.remove(&key).is_some())
+    }
+
+    /// Drop every cached filesystem.
+    #[tracing::instrument(level = "trace")]
+    pub fn invalidate_all(&self) {
+        let mut cache = self.cache.lock().expect("Poisoned Lock");
+        cache.entries.clear();
+    }
+
+    /// List the schemes with a registered Filesystem Provider, in sorted order.
+    #[tracing::instrument(level = "trace")]
+    pub fn providers(&self) -> Vec<String> {
+        let lock = self.providers.read().expect("Poisoned Lock");
+        let mut schemes: Vec<String> = lock.keys().cloned().collect();
+        schemes.sort();
+        schemes
+    }
+
     /// Get Filesystem for Path
     #[tracing::instrument(level = "trace")]
     pub fn get(&self, path: &str) -> FileSystemResult<VirtualFileSystem> {
-        let lock = self.0.read().unwrap();
-        let uri = URI::parse(path).map_err(|a| FileSystemError::WrappedError(Box::new(a)))?;
-        let provider = lock
-            .get(uri.scheme.to_string().as_str())
-            .ok_or(FileSystemError::UnknownFileSystem)?;
-        Ok(VirtualFileSystem(provider.provision(path)?))
+        Ok(VirtualFileSystem(self.provision(path)?))
+    }
+
+    /// Mount a Filesystem at a path prefix of the unified namespace.
+    ///
+    /// Returns [`FileSystemError::PathExists`] if something is already mounted at the prefix.
+    #[tracing::instrument(level = "trace")]
+    pub fn mount<F: FileSystem>(&self, prefix: &str, filesystem: F) -> FileSystemResult<()> {
+        self.mount_dynamic(prefix, Arc::new(filesystem))
+    }
+
+    /// Provision a Filesystem for a URI through its provider and mount it at a path prefix.
+    #[tracing::instrument(level = "trace")]
+    pub fn mount_uri(&self, prefix: &str, uri: &str) -> FileSystemResult<()> {
+        let filesystem = self.provision(uri)?;
+        self.mount_dynamic(prefix, filesystem)
+    }
+
+    /// Remove the Filesystem mounted at a path prefix.
+    #[tracing::instrument(level = "trace")]
+    pub fn unmount(&self, prefix: &str) -> FileSystemResult<()> {
+        let prefix = normalize_path(prefix)?;
+        let mut mounts = self.mounts.write().expect("Poisoned Lock");
+        match mounts.remove(&prefix) {
+            Some(_) => {
+                let mut registry = self.registry.lock().expect("Poisoned Lock");
+                registry.mounts.remove(&prefix);
+                Ok(())
+            }
+            None => Err(FileSystemError::PathMissing),
+        }
+    }
+
+    /// List the normalized prefixes of all mounted Filesystems.
+    #[tracing::instrument(level = "trace")]
+    pub fn mounts(&self) -> Vec<String> {
+        let mounts = self.mounts.read().expect("Poisoned Lock");
+        mounts.keys().cloned().collect()
+    }
+
+    /// Get a Filesystem presenting every mount as a single tree.
+    ///
+    /// Operations resolve against the longest mounted prefix of their path. The returned
+    /// filesystem shares the mount table, so later mounts and unmounts are visible through it.
+    #[tracing::instrument(level = "trace")]
+    pub fn namespace(&self) -> VirtualFileSystem {
+        VirtualFileSystem::new(MountFileSystem::new(self.mounts.clone()))
+    }
+
+    fn provision(&self, uri: &str) -> FileSystemResult<Arc<dyn DynamicFileSystem>> {
+        let key = provision_key(uri)?;
+        let scheme = key.split_once("://").map_or("", |(scheme, _)| scheme);
+        let cached = {
+            let mut cache = self.cache.lock().expect("Poisoned Lock");
+            cache.tick += 1;
+            let tick = cache.tick;
+            cache.entries.get_mut(&key).map(|entry| {
+                entry.used = tick;
+                entry.filesystem.clone()
+            })
+        };
+        if let Some(filesystem) = cached {
+            self.count(scheme, |counters| counters.cache_hits += 1);
+            return Ok(filesystem);
+        }
+
+        let provisioned = {
+            let lock = self.providers.read().expect("Poisoned Lock");
+            lock.get(scheme)
+                .cloned()
+                .ok_or(FileSystemError::UnknownFileSystem)
+        }
+        .and_then(|provider| provider.provision(uri));
+        let filesystem = match provisioned {
+            Ok(filesystem) => {
+                self.count(scheme, |counters| counters.provisions += 1);
+                filesystem
+            }
+            Err(err) => {
+                self.count(scheme, |counters| counters.failures += 1);
+                return Err(err);
+            }
+        };
+        let filesystem = if self.collect_metrics {
+            let metrics = self.registry.lock().expect("Poisoned Lock").schemes[scheme]
+                .metrics
+                .clone();
+            Arc::new(MetricFileSystem::shared(filesystem, metrics))
+        } else {
+            filesystem
+        };
+        if self.cache_capacity == 0 {
+            return Ok(filesystem);
+        }
+
+        let mut cache = self.cache.lock().expect("Poisoned Lock");
+        if let Some(entry) = cache.entries.get(&key) {
+            // Another thread provisioned the same URI meanwhile, share theirs
+            return Ok(entry.filesystem.clone());
+        }
+        while cache.entries.len() >= self.cache_capacity {
+            let victim = cache
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.clone())
+                .expect("Cached Entries");
+            cache.entries.remove(&victim);
+        }
+        let used = cache.tick;
+        cache.entries.insert(
+            key,
+            ProvisionedEntry {
+                filesystem: filesystem.clone(),
+                used,
+            },
+        );
+        Ok(filesystem)
+    }
+
+    /// Update the lookup counters of a scheme.
+    fn count(&self, scheme: &str, update: impl FnOnce(&mut SchemeCounters)) {
+        let mut registry = self.registry.lock().expect("Poisoned Lock");
+        update(registry.schemes.entry(scheme.to_string()).or_default());
+    }
+
+    fn insert_provider<T: FileSystemProvider>(&self, provider: T) {
+        let mut lock = self.providers.write().expect("Poisoned Lock");
+        let provider = Arc::new(provider);
+        for scheme in provider.schemes() {
+            lock.insert(scheme.to_string(), provider.clone());
+        }
+    }
+
+    fn mount_dynamic(
+        &self,
+        prefix: &str,
+        filesystem: Arc<dyn DynamicFileSystem>,
+    ) -> FileSystemResult<()> {
+        let prefix = normalize_path(prefix)?;
+        let mut mounts = self.mounts.write().expect("Poisoned Lock");
+        if mounts.contains_key(&prefix) {
+            return Err(FileSystemError::PathExists);
+        }
+        if self.collect_metrics {
+            let metrics = FileSystemMetrics::default();
+            let filesystem = MetricFileSystem::shared(filesystem, metrics.clone());
+            mounts.insert(prefix.clone(), Arc::new(filesystem));
+            let mut registry = self.registry.lock().expect("Poisoned Lock");
+            registry.mounts.insert(prefix, metrics);
+        } else {
+            mounts.insert(prefix, filesystem);
+        }
+        Ok(())
     }
 }
 
+/// Identify the filesystem a URI provisions by its scheme, authority, path and query.
+fn provision_key(uri: &str) -> FileSystemResult<String> {
+    let parsed = URI::parse(uri).map_err(|a| FileSystemError::WrappedError(Box::new(a)))?;
+    let mut key = format!("{}://", parsed.scheme);
+    if let Some(authority) = &parsed.authority {
+        key.push_str(&authority.to_string());
+    }
+    key.push_str(&parsed.path.to_string());
+    if let Some(query) = &parsed.query {
+        key.push('?');
+        key.push_str(&query.to_string());
+    }
+    Ok(key)
+}
+
 /// Virtual `FileSystem` Handle
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct VirtualFileSystem(Arc<dyn DynamicFileSystem>);
 
 impl VirtualFileSystem {
@@ -103,7 +453,7 @@ impl FileSystem for VirtualFileSystem {
 
     #[inline]
     #[tracing::instrument(level = "trace")]
-    fn list_directory<'a>(&self, path: &str) -> FileSystemResult<Vec<String>> {
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
         DynamicFileSystem::list_directory(self.0.as_ref(), path)
     }
 
@@ -142,10 +492,103 @@ impl FileSystem for VirtualFileSystem {
     fn remove_file(&self, path: &str) -> FileSystemResult<()> {
         DynamicFileSystem::remove_file(self.0.as_ref(), path)
     }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        DynamicFileSystem::file_type(self.0.as_ref(), path, policy)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_symlink(self.0.as_ref(), target, path)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        DynamicFileSystem::read_link(self.0.as_ref(), path)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        DynamicFileSystem::permissions(self.0.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        DynamicFileSystem::modified(self.0.as_ref(), path)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        DynamicFileSystem::set_permissions(self.0.as_ref(), path, permissions)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        Ok(VirtualFileHandle(DynamicFileSystem::open_with(
+            self.0.as_ref(),
+            path,
+            options,
+        )?))
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        DynamicFileSystem::clone_file(self.0.as_ref(), src, dst)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::rename(self.0.as_ref(), from, to)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        DynamicFileSystem::apply(self.0.as_ref(), batch)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        DynamicFileSystem::space(self.0.as_ref())
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn read(&self, path: &str) -> FileSystemResult<Vec<u8>> {
+        DynamicFileSystem::read(self.0.as_ref(), path)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn read_to_string(&self, path: &str) -> FileSystemResult<String> {
+        DynamicFileSystem::read_to_string(self.0.as_ref(), path)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn write(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        DynamicFileSystem::write(self.0.as_ref(), path, contents)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace", skip(contents))]
+    fn append(&self, path: &str, contents: &[u8]) -> FileSystemResult<()> {
+        DynamicFileSystem::append(self.0.as_ref(), path, contents)
+    }
 }
 
 /// Virtual File Handle
-pub struct VirtualFileHandle(Box<dyn FileHandle>);
+pub struct VirtualFileHandle(pub(crate) Box<dyn FileHandle>);
 
 impl std::fmt::Debug for VirtualFileHandle {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -159,6 +602,12 @@ impl Read for VirtualFileHandle {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         Read::read(self.0.as_mut(), buf)
     }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        Read::read_vectored(self.0.as_mut(), bufs)
+    }
 }
 
 impl Write for VirtualFileHandle {
@@ -169,6 +618,12 @@ impl Write for VirtualFileHandle {
         Write::write(self.0.as_mut(), buf)
     }
 
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        Write::write_vectored(self.0.as_mut(), bufs)
+    }
+
     #[inline]
     #[tracing::instrument(level = "trace")]
     fn flush(&mut self) -> std::io::Result<()> {
@@ -226,6 +681,69 @@ impl FileHandle for VirtualFileHandle {
     fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
         FileHandle::set_lock_status(self.0.as_mut(), mode)
     }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::try_set_lock_status(self.0.as_mut(), mode)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        FileHandle::lock_range(self.0.as_mut(), offset, len, mode)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        FileHandle::unlock_range(self.0.as_mut(), offset, len)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        FileHandle::advise(self.0.as_mut(), offset, len, advice)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        FileHandle::read_at_vectored(self.0.as_mut(), offset, buffers)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        FileHandle::write_at_vectored(self.0.as_mut(), offset, buffers)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        FileHandle::map_readonly(self.0.as_mut(), offset, len)
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        FileHandle::alignment(self.0.as_ref())
+    }
+
+    #[inline]
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        FileHandle::allocate(self.0.as_mut(), len)
+    }
 }
 
 #[cfg(test)]
@@ -313,4 +831,247 @@ mod test {
             .exists(filename.as_str())
             .expect("Error Checking File Existence"));
     }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_mount_namespace() {
+        use crate::{FileSystem, FileSystemError, VirtualFileSystemManager};
+        use std::io::Write;
+
+        let data = MemoryFileSystem::default();
+        let manager = VirtualFileSystemManager::default();
+        manager.mount("/", MemoryFileSystem::default()).unwrap();
+        manager.mount("/data", data.clone()).unwrap();
+        manager
+            .mount("/data/cold", MemoryFileSystem::default())
+            .unwrap();
+        assert!(matches!(
+            manager.mount("/data/", MemoryFileSystem::default()),
+            Err(FileSystemError::PathExists)
+        ));
+        assert_eq!(manager.mounts(), vec!["/", "/data", "/data/cold"]);
+
+        let fs = manager.namespace();
+        fs.create_file("/data/table.dat")
+            .unwrap()
+            .write_all(b"rows")
+            .unwrap();
+        fs.create_file("/database.dat").unwrap();
+
+        // Longest prefix wins and only on whole segments
+        assert!(data.exists("/table.dat").unwrap());
+        assert!(!data.exists("/base.dat").unwrap());
+        assert_eq!(fs.filesize("/data/table.dat").unwrap(), 4);
+        assert!(!fs.exists("/data/cold/table.dat").unwrap());
+
+        // Mount points show up as directories of their parents
+        assert!(fs.is_directory("/data").unwrap());
+        assert!(fs
+            .list_directory("/")
+            .unwrap()
+            .contains(&"data".to_string()));
+        assert!(fs
+            .list_directory("/data")
+            .unwrap()
+            .contains(&"cold".to_string()));
+
+        manager.unmount("/data").unwrap();
+        assert!(!fs.exists("/data/table.dat").unwrap());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_manager_lifecycle() {
+        use crate::{FileSystem, FileSystemError, VirtualFileSystemManager};
+        use std::io::Write;
+
+        let manager = VirtualFileSystemManager::default();
+        assert!(manager.providers().is_empty());
+
+        let manager = VirtualFileSystemManager::with_defaults();
+        assert_eq!(manager.providers(), vec!["file", "mem"]);
+
+        // Memory URIs with the same name share a tree
+        manager
+            .get("mem://cache")
+            .unwrap()
+            .create_file("/entry.bin")
+            .unwrap()
+            .write_all(b"cached")
+            .unwrap();
+        assert_eq!(
+            manager
+                .get("mem://cache/")
+                .unwrap()
+                .filesize("/entry.bin")
+                .unwrap(),
+            6
+        );
+        assert!(!manager
+            .get("mem://other")
+            .unwrap()
+            .exists("/entry.bin")
+            .unwrap());
+        assert!(matches!(
+            manager.get("mem://cache/nested"),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+
+        #[cfg(unix)]
+        {
+            let root = std::env::temp_dir();
+            let local = manager
+                .get(&format!("file://{}", root.to_str().unwrap()))
+                .unwrap();
+            assert!(local.is_directory("/").unwrap());
+        }
+
+        manager.unregister("mem").unwrap();
+        assert_eq!(manager.providers(), vec!["file"]);
+        assert!(matches!(
+            manager.get("mem://cache"),
+            Err(FileSystemError::UnknownFileSystem)
+        ));
+        assert!(matches!(
+            manager.unregister("mem"),
+            Err(FileSystemError::UnknownFileSystem)
+        ));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_provision_cache() {
+        use crate::{
+            FileSystem, FileSystemProvider, FileSystemResult, MemoryFileSystem,
+            VirtualFileSystemManager,
+        };
+        use std::collections::HashMap;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        #[derive(Debug, Default)]
+        struct CountingProvider(Arc<AtomicUsize>);
+
+        impl FileSystemProvider for CountingProvider {
+            type FileSystem = MemoryFileSystem;
+
+            fn schemes(&self) -> &[&str] {
+                &["count"]
+            }
+
+            fn configure(&self, configuration: &HashMap<String, String>) -> FileSystemResult<()> {
+                Ok(())
+            }
+
+            fn provision(&self, url: &str) -> FileSystemResult<MemoryFileSystem> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                Ok(MemoryFileSystem::new())
+            }
+        }
+
+        let provisioned = Arc::new(AtomicUsize::new(0));
+        let manager = VirtualFileSystemManager::default().cache_capacity(2);
+        manager
+            .register(CountingProvider(provisioned.clone()))
+            .unwrap();
+
+        // Repeated lookups share one filesystem, fragments aside
+        manager
+            .get("count://a/")
+            .unwrap()
+            .create_file("/x")
+            .unwrap();
+        assert!(manager
+            .get("count://a/#ignored")
+            .unwrap()
+            .exists("/x")
+            .unwrap());
+        assert_eq!(provisioned.load(Ordering::Relaxed), 1);
+        manager.get("count://a/?region=west").unwrap();
+        assert_eq!(provisioned.load(Ordering::Relaxed), 2);
+
+        // Touching a keeps it while the query variant is evicted
+        manager.get("count://a/").unwrap();
+        manager.get("count://b/").unwrap();
+        assert_eq!(provisioned.load(Ordering::Relaxed), 3);
+        assert!(manager.get("count://a/").unwrap().exists("/x").unwrap());
+        assert_eq!(provisioned.load(Ordering::Relaxed), 3);
+
+        assert!(manager.invalidate("count://a/").unwrap());
+        assert!(!manager.invalidate("count://a/").unwrap());
+        assert!(!manager.get("count://a/").unwrap().exists("/x").unwrap());
+        assert_eq!(provisioned.load(Ordering::Relaxed), 4);
+
+        manager.invalidate_all();
+        manager.get("count://b/").unwrap();
+        assert_eq!(provisioned.load(Ordering::Relaxed), 5);
+
+        manager.unregister("count").unwrap();
+        assert!(manager.get("count://b/").is_err());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_manager_metrics() {
+        use crate::{FileSystem, MemoryFileSystem, MetricOperation, VirtualFileSystemManager};
+
+        let manager = VirtualFileSystemManager::with_defaults().with_metrics();
+        manager.mount_uri("/scratch", "mem://scratch").unwrap();
+        manager.mount("/tables", MemoryFileSystem::new()).unwrap();
+        let fs = manager.namespace();
+        fs.write("/scratch/run-0", b"run").unwrap();
+        fs.write("/tables/users", b"users").unwrap();
+        manager
+            .get("mem://scratch")
+            .unwrap()
+            .read("/run-0")
+            .unwrap();
+        assert!(manager.get("mem://scratch/nested").is_err());
+        assert!(manager.get("ftp://host/").is_err());
+
+        let metrics = manager.metrics();
+        let scheme = metrics.scheme("mem").unwrap();
+        assert_eq!(scheme.provisions(), 1);
+        assert_eq!(scheme.cache_hits(), 1);
+        assert_eq!(scheme.failures(), 1);
+        assert_eq!(
+            scheme.metrics().operation(MetricOperation::Write).count(),
+            1
+        );
+        assert_eq!(scheme.metrics().bytes_written(), 3);
+        assert_eq!(scheme.metrics().bytes_read(), 3);
+        assert_eq!(metrics.scheme("ftp").unwrap().failures(), 1);
+        assert_eq!(
+            metrics.mounts().keys().collect::<Vec<_>>(),
+            ["/scratch", "/tables"]
+        );
+        let tables = metrics.mount("/tables").unwrap();
+        assert_eq!(tables.operation(MetricOperation::Write).count(), 1);
+        assert_eq!(tables.operation(MetricOperation::Read).count(), 0);
+
+        manager.unmount("/tables").unwrap();
+        assert!(manager.metrics().mount("/tables").is_none());
+
+        // Lookups are counted even without filesystem metrics
+        let manager = VirtualFileSystemManager::with_defaults();
+        manager.mount_uri("/scratch", "mem://scratch").unwrap();
+        manager.namespace().write("/scratch/run-0", b"run").unwrap();
+        let metrics = manager.metrics();
+        assert_eq!(metrics.scheme("mem").unwrap().provisions(), 1);
+        assert!(metrics
+            .scheme("mem")
+            .unwrap()
+            .metrics()
+            .operations()
+            .is_empty());
+        assert!(metrics.mounts().is_empty());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_virtual_conformance() {
+        use crate::{MemoryFileSystem, VirtualFileSystem};
+
+        crate::conformance::run(|| VirtualFileSystem::new(MemoryFileSystem::new()));
+    }
 }
@@ -0,0 +1,730 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::filesystem::DynamicFileSystem;
+use crate::{
+    Advice, BatchOperation, CloneMethod, FileHandle, FileLockMode, FileSystem, FileSystemError,
+    FileSystemResult, FileSystemSpace, FileType, OpenOptions, Permissions, SymlinkPolicy,
+};
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
+
+/// Buffering limits of a [`WriteBehindFileSystem`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct WriteBehindOptions {
+    handle_buffer: u64,
+    budget: u64,
+    flush_interval: Option<Duration>,
+}
+
+impl WriteBehindOptions {
+    /// Create the default options: 64 KiB per handle, 16 MiB in total, and no background flush.
+    #[must_use]
+    pub fn new() -> WriteBehindOptions {
+        WriteBehindOptions {
+            handle_buffer: 64 * 1024,
+            budget: 16 * 1024 * 1024,
+            flush_interval: None,
+        }
+    }
+
+    /// Flush a handle once it buffers this many bytes.
+    #[must_use]
+    pub fn with_handle_buffer(mut self, bytes: u64) -> WriteBehindOptions {
+        self.handle_buffer = bytes;
+        self
+    }
+
+    /// Flush a handle whenever the bytes buffered across every handle exceed this budget.
+    #[must_use]
+    pub fn with_budget(mut self, bytes: u64) -> WriteBehindOptions {
+        self.budget = bytes;
+        self
+    }
+
+    /// Flush every handle from a background thread at this interval.
+    #[must_use]
+    pub fn with_flush_interval(mut self, interval: Duration) -> WriteBehindOptions {
+        self.flush_interval = Some(interval);
+        self
+    }
+}
+
+impl Default for WriteBehindOptions {
+    fn default() -> Self {
+        WriteBehindOptions::new()
+    }
+}
+
+/// Writes buffered by a single handle, in the order they were issued.
+#[derive(Debug)]
+struct WriteBuffer {
+    inner: Box<dyn FileHandle>,
+    pending: Vec<(u64, Vec<u8>)>,
+    bytes: u64,
+    /// Failure of a background flush, reported by the next operation on the handle.
+    error: Option<std::io::Error>,
+}
+
+impl WriteBuffer {
+    /// Apply every pending write to the inner handle in order.
+    fn flush(&mut self, buffered: &AtomicU64) -> FileSystemResult<()> {
+        if let Some(err) = self.error.take() {
+            return Err(FileSystemError::io_error(err));
+        }
+        let mut applied = 0;
+        let mut result = Ok(());
+        for (offset, data) in &self.pending {
+            if let Err(err) = write_all_at(self.inner.as_mut(), *offset, data) {
+                result = Err(err);
+                break;
+            }
+            applied += 1;
+        }
+        let flushed = self
+            .pending
+            .drain(..applied)
+            .map(|(_, data)| data.len() as u64)
+            .sum::<u64>();
+        self.bytes -= flushed;
+        buffered.fetch_sub(flushed, Ordering::AcqRel);
+        result
+    }
+}
+
+#[derive(Debug)]
+struct WriteBehindShared {
+    options: WriteBehindOptions,
+    buffered: AtomicU64,
+    handles: Mutex<Vec<Weak<Mutex<WriteBuffer>>>>,
+}
+
+impl WriteBehindShared {
+    /// Flush every open handle on `path`, so the inner file holds every write made through it.
+    fn flush_path(&self, path: &str) -> FileSystemResult<()> {
+        let handles = {
+            let mut handles = self.handles.lock().expect("Poisoned Lock");
+            handles.retain(|handle| handle.strong_count() > 0);
+            handles.iter().filter_map(Weak::upgrade).collect::<Vec<_>>()
+        };
+        for handle in handles {
+            let mut buffer = handle.lock().expect("Poisoned Lock");
+            if buffer.inner.path() == path {
+                buffer.flush(&self.buffered)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush every open handle, keeping any failure to report on the handle's next operation.
+    fn flush_all(&self) {
+        let handles = {
+            let mut handles = self.handles.lock().expect("Poisoned Lock");
+            handles.retain(|handle| handle.strong_count() > 0);
+            handles.iter().filter_map(Weak::upgrade).collect::<Vec<_>>()
+        };
+        for handle in handles {
+            let mut buffer = handle.lock().expect("Poisoned Lock");
+            if buffer.error.is_none() {
+                if let Err(err) = buffer.flush(&self.buffered) {
+                    tracing::warn!(?err, "Background flush failed");
+                    buffer.error = Some(err.into());
+                }
+            }
+        }
+    }
+}
+
+/// Write-Behind Buffering `FileSystem` Wrapper
+///
+/// Collects writes in memory and applies them to another [`FileSystem`] in batches, turning
+/// streams of tiny writes into a few large ones. Contiguous writes are coalesced. A handle's
+/// buffer is flushed when it exceeds its own limit, when the bytes buffered across every handle
+/// exceed the shared budget, before any read or size query on the handle, on `sync_*`, on
+/// `flush`, when dropped, and periodically from a background thread if an interval is set.
+///
+/// Ordering guarantees:
+///
+/// * Writes through one handle reach the inner file in the order they were issued, and a
+///   handle always reads its own writes.
+/// * There is no ordering between handles: another handle on the same file only observes
+///   buffered writes once they are flushed.
+/// * A successful `sync_data` or `sync_all` means every earlier write through that handle is
+///   durable. A background flush failure is reported by the next operation on the handle.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, WriteBehindFileSystem, WriteBehindOptions};
+/// use std::io::Write;
+///
+/// let inner = MemoryFileSystem::new();
+/// let fs = WriteBehindFileSystem::new(inner.clone(), WriteBehindOptions::new());
+/// let mut file = fs.create_file("/metrics.log").unwrap();
+/// for _ in 0..100 {
+///     file.write_all(b"tiny").unwrap();
+/// }
+/// assert_eq!(inner.filesize("/metrics.log").unwrap(), 0);
+/// file.flush().unwrap();
+/// assert_eq!(inner.filesize("/metrics.log").unwrap(), 400);
+/// ```
+#[derive(Debug)]
+pub struct WriteBehindFileSystem {
+    shared: Arc<WriteBehindShared>,
+    inner: Arc<dyn DynamicFileSystem>,
+}
+
+impl WriteBehindFileSystem {
+    /// Create a new Write-Behind `FileSystem` over `filesystem`.
+    pub fn new<F: FileSystem>(filesystem: F, options: WriteBehindOptions) -> WriteBehindFileSystem {
+        let shared = Arc::new(WriteBehindShared {
+            options,
+            buffered: AtomicU64::new(0),
+            handles: Mutex::new(Vec::new()),
+        });
+        if let Some(interval) = options.flush_interval {
+            let shared = Arc::downgrade(&shared);
+            std::thread::spawn(move || loop {
+                std::thread::sleep(interval);
+                match shared.upgrade() {
+                    Some(shared) => shared.flush_all(),
+                    None => break,
+                }
+            });
+        }
+        WriteBehindFileSystem {
+            shared,
+            inner: Arc::new(filesystem),
+        }
+    }
+
+    /// Bytes currently buffered across every handle.
+    #[must_use]
+    pub fn buffered(&self) -> u64 {
+        self.shared.buffered.load(Ordering::Acquire)
+    }
+
+    /// Flush every open handle.
+    pub fn flush_all(&self) {
+        self.shared.flush_all();
+    }
+
+    fn wrap(&self, inner: Box<dyn FileHandle>, cursor: u64) -> WriteBehindFileHandle {
+        let path = inner.path().to_string();
+        let buffer = Arc::new(Mutex::new(WriteBuffer {
+            inner,
+            pending: Vec::new(),
+            bytes: 0,
+            error: None,
+        }));
+        self.shared
+            .handles
+            .lock()
+            .expect("Poisoned Lock")
+            .push(Arc::downgrade(&buffer));
+        WriteBehindFileHandle {
+            path,
+            cursor,
+            shared: self.shared.clone(),
+            buffer,
+        }
+    }
+}
+
+impl FileSystem for WriteBehindFileSystem {
+    type FileHandle = WriteBehindFileHandle;
+
+    #[tracing::instrument(level = "trace")]
+    fn exists(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::exists(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_file(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::is_file(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn is_directory(&self, path: &str) -> FileSystemResult<bool> {
+        DynamicFileSystem::is_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn filesize(&self, path: &str) -> FileSystemResult<u64> {
+        DynamicFileSystem::filesize(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_directory_all(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
+        DynamicFileSystem::list_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_directory(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_directory_all(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_directory_all(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        Ok(self.wrap(
+            DynamicFileSystem::create_file(self.inner.as_ref(), path)?,
+            0,
+        ))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_file(&self, path: &str) -> FileSystemResult<Self::FileHandle> {
+        Ok(self.wrap(DynamicFileSystem::open_file(self.inner.as_ref(), path)?, 0))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn remove_file(&self, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::remove_file(self.inner.as_ref(), path)
+    }
+
+    /// Flushes the handles open on `from` first, so the renamed file holds their writes.
+    #[tracing::instrument(level = "trace")]
+    fn rename(&self, from: &str, to: &str) -> FileSystemResult<()> {
+        self.shared.flush_path(from)?;
+        DynamicFileSystem::rename(self.inner.as_ref(), from, to)
+    }
+
+    /// Flushes the handles open on `src` first, so the new file holds their writes.
+    #[tracing::instrument(level = "trace")]
+    fn clone_file(&self, src: &str, dst: &str) -> FileSystemResult<CloneMethod> {
+        self.shared.flush_path(src)?;
+        DynamicFileSystem::clone_file(self.inner.as_ref(), src, dst)
+    }
+
+    /// Flushes the handles open on any path of the batch first, failing the operations on a
+    /// path whose handles couldn't be flushed without applying them.
+    #[tracing::instrument(level = "trace", skip(batch))]
+    fn apply(&self, batch: &[BatchOperation]) -> Vec<FileSystemResult<()>> {
+        let mut flushed = Vec::with_capacity(batch.len());
+        let mut results: Vec<Option<FileSystemResult<()>>> = Vec::with_capacity(batch.len());
+        for operation in batch {
+            match operation
+                .paths()
+                .try_for_each(|path| self.shared.flush_path(path))
+            {
+                Ok(()) => {
+                    flushed.push(operation.clone());
+                    results.push(None);
+                }
+                Err(error) => results.push(Some(Err(error))),
+            }
+        }
+        let mut applied = DynamicFileSystem::apply(self.inner.as_ref(), &flushed).into_iter();
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| applied.next().expect("Missing Batch Result")))
+            .collect()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn file_type(&self, path: &str, policy: SymlinkPolicy) -> FileSystemResult<FileType> {
+        DynamicFileSystem::file_type(self.inner.as_ref(), path, policy)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn create_symlink(&self, target: &str, path: &str) -> FileSystemResult<()> {
+        DynamicFileSystem::create_symlink(self.inner.as_ref(), target, path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_link(&self, path: &str) -> FileSystemResult<String> {
+        DynamicFileSystem::read_link(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn permissions(&self, path: &str) -> FileSystemResult<Permissions> {
+        DynamicFileSystem::permissions(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn modified(&self, path: &str) -> FileSystemResult<SystemTime> {
+        DynamicFileSystem::modified(self.inner.as_ref(), path)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_permissions(&self, path: &str, permissions: Permissions) -> FileSystemResult<()> {
+        DynamicFileSystem::set_permissions(self.inner.as_ref(), path, permissions)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn open_with(&self, path: &str, options: OpenOptions) -> FileSystemResult<Self::FileHandle> {
+        let mut inner = DynamicFileSystem::open_with(self.inner.as_ref(), path, options)?;
+        let cursor = inner.stream_position().map_err(FileSystemError::io_error)?;
+        Ok(self.wrap(inner, cursor))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn space(&self) -> FileSystemResult<FileSystemSpace> {
+        DynamicFileSystem::space(self.inner.as_ref())
+    }
+}
+
+/// Write-Behind File Handle
+///
+/// Buffers writes at its own cursor, applying them to the inner handle positionally when
+/// flushed.
+pub struct WriteBehindFileHandle {
+    path: String,
+    cursor: u64,
+    shared: Arc<WriteBehindShared>,
+    buffer: Arc<Mutex<WriteBuffer>>,
+}
+
+impl WriteBehindFileHandle {
+    /// Buffer a write, flushing if a limit is exceeded.
+    fn buffer_write(&mut self, offset: u64, data: &[u8]) -> FileSystemResult<()> {
+        let mut buffer = self.buffer.lock().expect("Poisoned Lock");
+        if let Some(err) = buffer.error.take() {
+            return Err(FileSystemError::io_error(err));
+        }
+        match buffer.pending.last_mut() {
+            Some((start, pending)) if *start + pending.len() as u64 == offset => {
+                pending.extend_from_slice(data);
+            }
+            _ => buffer.pending.push((offset, data.to_vec())),
+        }
+        buffer.bytes += data.len() as u64;
+        let total = self
+            .shared
+            .buffered
+            .fetch_add(data.len() as u64, Ordering::AcqRel)
+            + data.len() as u64;
+        if buffer.bytes >= self.shared.options.handle_buffer || total > self.shared.options.budget {
+            buffer.flush(&self.shared.buffered)?;
+        }
+        Ok(())
+    }
+
+    /// Flush pending writes and run `operation` against the inner handle.
+    fn flushed<T>(
+        &self,
+        operation: impl FnOnce(&mut dyn FileHandle) -> FileSystemResult<T>,
+    ) -> FileSystemResult<T> {
+        let mut buffer = self.buffer.lock().expect("Poisoned Lock");
+        buffer.flush(&self.shared.buffered)?;
+        operation(buffer.inner.as_mut())
+    }
+}
+
+impl std::fmt::Debug for WriteBehindFileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WriteBehindFileHandle")
+            .field("path", &self.path)
+            .field("cursor", &self.cursor)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Read for WriteBehindFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.read_at_offset(self.cursor, buf)?;
+        self.cursor += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for WriteBehindFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.write_to_offset(self.cursor, buf)?;
+        self.cursor += written as u64;
+        Ok(written)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(self.flushed(|_| Ok(()))?)
+    }
+}
+
+impl Seek for WriteBehindFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let cursor = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.get_size()?.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.cursor.checked_add_signed(offset),
+        };
+        self.cursor = cursor.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Seek before start of file",
+            )
+        })?;
+        Ok(self.cursor)
+    }
+}
+
+impl FileHandle for WriteBehindFileHandle {
+    #[tracing::instrument(level = "trace")]
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_size(&self) -> FileSystemResult<u64> {
+        self.flushed(|inner| FileHandle::get_size(inner))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_size(&mut self, new_size: u64) -> FileSystemResult<()> {
+        self.flushed(|inner| inner.set_size(new_size))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_all(&mut self) -> FileSystemResult<()> {
+        self.flushed(FileHandle::sync_all)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn sync_data(&mut self) -> FileSystemResult<()> {
+        self.flushed(FileHandle::sync_data)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn get_lock_status(&self) -> FileSystemResult<FileLockMode> {
+        self.buffer
+            .lock()
+            .expect("Poisoned Lock")
+            .inner
+            .get_lock_status()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.flushed(|inner| inner.set_lock_status(mode))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn try_set_lock_status(&mut self, mode: FileLockMode) -> FileSystemResult<()> {
+        self.flushed(|inner| inner.try_set_lock_status(mode))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_offset(&mut self, offset: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        self.flushed(|inner| inner.read_at_offset(offset, buffer))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_to_offset(&mut self, offset: u64, buffer: &[u8]) -> FileSystemResult<usize> {
+        self.buffer_write(offset, buffer)?;
+        Ok(buffer.len())
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn lock_range(&mut self, offset: u64, len: u64, mode: FileLockMode) -> FileSystemResult<()> {
+        self.flushed(|inner| inner.lock_range(offset, len, mode))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn unlock_range(&mut self, offset: u64, len: u64) -> FileSystemResult<()> {
+        self.flushed(|inner| inner.unlock_range(offset, len))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> FileSystemResult<()> {
+        let mut buffer = self.buffer.lock().expect("Poisoned Lock");
+        buffer.inner.advise(offset, len, advice)
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn read_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> FileSystemResult<usize> {
+        self.flushed(|inner| inner.read_at_vectored(offset, buffers))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn write_at_vectored(
+        &mut self,
+        offset: u64,
+        buffers: &[IoSlice<'_>],
+    ) -> FileSystemResult<usize> {
+        let data = buffers
+            .iter()
+            .flat_map(|buffer| buffer.iter().copied())
+            .collect::<Vec<_>>();
+        self.buffer_write(offset, &data)?;
+        Ok(data.len())
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tracing::instrument(level = "trace")]
+    fn map_readonly(&mut self, offset: u64, len: usize) -> FileSystemResult<crate::FileMapping> {
+        self.flushed(|inner| inner.map_readonly(offset, len))
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn alignment(&self) -> FileSystemResult<usize> {
+        self.buffer.lock().expect("Poisoned Lock").inner.alignment()
+    }
+
+    #[tracing::instrument(level = "trace")]
+    fn allocate(&mut self, len: u64) -> FileSystemResult<()> {
+        self.flushed(|inner| inner.allocate(len))
+    }
+}
+
+impl Drop for WriteBehindFileHandle {
+    fn drop(&mut self) {
+        if let Err(err) = self.flushed(|_| Ok(())) {
+            tracing::warn!(?err, "Failed to flush buffered writes on drop");
+        }
+    }
+}
+
+/// Write all of `data` at `offset`.
+fn write_all_at(handle: &mut dyn FileHandle, offset: u64, data: &[u8]) -> FileSystemResult<()> {
+    let mut written = 0;
+    while written < data.len() {
+        match handle.write_to_offset(offset + written as u64, &data[written..])? {
+            0 => return Err(FileSystemError::InvalidOperation),
+            count => written += count,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        FileHandle, FileSystem, MemoryFileSystem, WriteBehindFileSystem, WriteBehindOptions,
+    };
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::time::Duration;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_write_behind_filesystem() {
+        let inner = MemoryFileSystem::new();
+        let options = WriteBehindOptions::new()
+            .with_handle_buffer(64)
+            .with_budget(96);
+        let fs = WriteBehindFileSystem::new(inner.clone(), options);
+        let mut first = fs.create_file("/first.log").unwrap();
+        let mut second = fs.create_file("/second.log").unwrap();
+
+        for _ in 0..15 {
+            first.write_all(b"0123").unwrap();
+        }
+        assert_eq!(fs.buffered(), 60);
+        assert_eq!(inner.filesize("/first.log").unwrap(), 0);
+        first.write_all(b"4567").unwrap();
+        // The handle buffer filled and flushed as one write
+        assert_eq!(inner.filesize("/first.log").unwrap(), 64);
+
+        // The shared budget forces a flush before either handle buffer fills
+        first.write_all(&[1; 50]).unwrap();
+        second.write_all(&[2; 50]).unwrap();
+        assert_eq!(inner.filesize("/second.log").unwrap(), 50);
+        assert_eq!(fs.buffered(), 50);
+
+        // Handles read their own writes, in order
+        first.write_to_offset(0, b"ABCD").unwrap();
+        let mut contents = Vec::new();
+        first.seek(SeekFrom::Start(0)).unwrap();
+        first.read_to_end(&mut contents).unwrap();
+        assert_eq!(&contents[..8], b"ABCD0123");
+        assert_eq!(contents.len(), 114);
+        assert_eq!(fs.buffered(), 0);
+
+        second.write_all(b"tail").unwrap();
+        drop(second);
+        assert_eq!(inner.filesize("/second.log").unwrap(), 54);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_write_behind_background_flush() {
+        let inner = MemoryFileSystem::new();
+        let options = WriteBehindOptions::new().with_flush_interval(Duration::from_millis(10));
+        let fs = WriteBehindFileSystem::new(inner.clone(), options);
+        let mut file = fs.create_file("/async.log").unwrap();
+        file.write_all(b"eventually").unwrap();
+        for _ in 0..100 {
+            if fs.buffered() == 0 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(inner.filesize("/async.log").unwrap(), 10);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_write_behind_rename() {
+        let inner = MemoryFileSystem::new();
+        let fs = WriteBehindFileSystem::new(inner.clone(), WriteBehindOptions::new());
+        let mut file = fs.create_file("/manifest.tmp").unwrap();
+        file.write_all(b"manifest").unwrap();
+        assert_eq!(fs.buffered(), 8);
+        fs.rename("/manifest.tmp", "/manifest").unwrap();
+        assert_eq!(fs.buffered(), 0);
+        assert_eq!(inner.read("/manifest").unwrap(), b"manifest");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_write_behind_batch() {
+        use crate::BatchOperation;
+
+        let inner = MemoryFileSystem::new();
+        let fs = WriteBehindFileSystem::new(inner.clone(), WriteBehindOptions::new());
+        let mut file = fs.create_file("/segment.tmp").unwrap();
+        file.write_all(b"segment").unwrap();
+        fs.clone_file("/segment.tmp", "/segment.bak").unwrap();
+        assert_eq!(inner.read("/segment.bak").unwrap(), b"segment");
+
+        file.write_all(b" tail").unwrap();
+        let results = fs.apply(&[BatchOperation::rename("/segment.tmp", "/segment.dat")]);
+        assert!(results[0].is_ok());
+        assert_eq!(fs.buffered(), 0);
+        assert_eq!(inner.read("/segment.dat").unwrap(), b"segment tail");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_write_behind_conformance() {
+        use crate::{MemoryFileSystem, WriteBehindFileSystem, WriteBehindOptions};
+
+        crate::conformance::run(|| {
+            WriteBehindFileSystem::new(MemoryFileSystem::new(), WriteBehindOptions::new())
+        });
+    }
+}
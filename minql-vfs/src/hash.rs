@@ -0,0 +1,235 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{FileHandle, FileSystemResult};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+
+/// Size of the chunks files are read in while hashing.
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Algorithm used to hash the contents of a file.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum HashAlgorithm {
+    /// SHA-256, producing a 32 byte digest
+    Sha256,
+    /// BLAKE3, producing a 32 byte digest
+    Blake3,
+    /// CRC-32 (IEEE), producing a 4 byte big-endian digest
+    Crc32,
+}
+
+/// Digest of the contents of a file, produced by a [`ContentHasher`].
+///
+/// Displays as lowercase hex.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ContentDigest {
+    algorithm: HashAlgorithm,
+    bytes: Vec<u8>,
+}
+
+impl ContentDigest {
+    /// Algorithm that produced this digest.
+    #[must_use]
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Raw bytes of the digest.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Parse a digest displayed as hex, or `None` if it isn't the hex of a digest of `algorithm`.
+    pub(crate) fn from_hex(algorithm: HashAlgorithm, hex: &str) -> Option<ContentDigest> {
+        let length = match algorithm {
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 32,
+            HashAlgorithm::Crc32 => 4,
+        };
+        if hex.len() != length * 2 || !hex.is_ascii() {
+            return None;
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        Some(ContentDigest { algorithm, bytes })
+    }
+}
+
+impl std::fmt::Display for ContentDigest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in &self.bytes {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Incremental hasher fed the contents of a file in chunks.
+///
+/// Implements [`Write`], so it can be the destination of [`std::io::copy`] or sit behind a
+/// wrapper that hashes data as it passes through.
+///
+/// ```rust
+/// use minql_vfs::{ContentHasher, HashAlgorithm};
+///
+/// let mut hasher = ContentHasher::new(HashAlgorithm::Crc32);
+/// hasher.update(b"Hello, ");
+/// hasher.update(b"World!");
+/// assert_eq!(hasher.finalize().to_string(), "ec4ac3d0");
+/// ```
+#[derive(Clone)]
+pub struct ContentHasher {
+    state: HasherState,
+}
+
+#[derive(Clone)]
+enum HasherState {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+    Crc32(crc32fast::Hasher),
+}
+
+impl ContentHasher {
+    /// Create a hasher using the provided algorithm.
+    #[must_use]
+    pub fn new(algorithm: HashAlgorithm) -> ContentHasher {
+        let state = match algorithm {
+            HashAlgorithm::Sha256 => HasherState::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => HasherState::Blake3(Box::default()),
+            HashAlgorithm::Crc32 => HasherState::Crc32(crc32fast::Hasher::new()),
+        };
+        ContentHasher { state }
+    }
+
+    /// Algorithm this hasher uses.
+    #[must_use]
+    pub fn algorithm(&self) -> HashAlgorithm {
+        match self.state {
+            HasherState::Sha256(_) => HashAlgorithm::Sha256,
+            HasherState::Blake3(_) => HashAlgorithm::Blake3,
+            HasherState::Crc32(_) => HashAlgorithm::Crc32,
+        }
+    }
+
+    /// Feed the next chunk of data to the hasher.
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.state {
+            HasherState::Sha256(hasher) => hasher.update(data),
+            HasherState::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            HasherState::Crc32(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Consume the hasher, returning the digest of all the data fed to it.
+    #[must_use]
+    pub fn finalize(self) -> ContentDigest {
+        let algorithm = self.algorithm();
+        let bytes = match self.state {
+            HasherState::Sha256(hasher) => hasher.finalize().to_vec(),
+            HasherState::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+            HasherState::Crc32(hasher) => hasher.finalize().to_be_bytes().to_vec(),
+        };
+        ContentDigest { algorithm, bytes }
+    }
+}
+
+impl std::fmt::Debug for ContentHasher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ContentHasher")
+            .field("algorithm", &self.algorithm())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Write for ContentHasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash the whole contents of the file behind a handle in chunks, leaving its cursor untouched.
+pub(crate) fn hash_handle<H: FileHandle + ?Sized>(
+    handle: &mut H,
+    algorithm: HashAlgorithm,
+) -> FileSystemResult<ContentDigest> {
+    let mut hasher = ContentHasher::new(algorithm);
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    let mut offset = 0;
+    loop {
+        let read = handle.read_at_offset(offset, &mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&buffer[..read]);
+        offset += read as u64;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_hash_algorithms() {
+        use crate::{FileHandle, FileSystem, HashAlgorithm, MemoryFileSystem};
+        use sha2::Digest;
+        use std::io::{Read, Seek, SeekFrom};
+
+        let fs = MemoryFileSystem::new();
+        let contents = vec![7u8; 64 * 1024 + 7];
+        fs.write("/blob.bin", &contents).unwrap();
+
+        let digest = fs.hash_file("/blob.bin", HashAlgorithm::Sha256).unwrap();
+        assert_eq!(digest.algorithm(), HashAlgorithm::Sha256);
+        assert_eq!(
+            digest.as_bytes(),
+            sha2::Sha256::digest(&contents).as_slice()
+        );
+        let digest = fs.hash_file("/blob.bin", HashAlgorithm::Blake3).unwrap();
+        assert_eq!(digest.as_bytes(), blake3::hash(&contents).as_bytes());
+        let digest = fs.hash_file("/blob.bin", HashAlgorithm::Crc32).unwrap();
+        assert_eq!(digest.as_bytes(), crc32fast::hash(&contents).to_be_bytes());
+
+        let mut handle = fs.open_file("/blob.bin").unwrap();
+        handle.seek(SeekFrom::Start(10)).unwrap();
+        assert_eq!(
+            handle.hash(HashAlgorithm::Crc32).unwrap(),
+            fs.hash_file("/blob.bin", HashAlgorithm::Crc32).unwrap()
+        );
+        let mut rest = Vec::new();
+        handle.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest.len(), contents.len() - 10);
+
+        let empty = fs.hash_file("/empty.bin", HashAlgorithm::Sha256);
+        assert!(empty.is_err());
+        fs.write("/empty.bin", b"").unwrap();
+        assert_eq!(
+            fs.hash_file("/empty.bin", HashAlgorithm::Sha256)
+                .unwrap()
+                .to_string(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{
+    CachingFileSystem, ChecksumFileSystem, FileSystem, MetricFileSystem, ScopedFileSystem,
+    SyncFileSystem, SyncPolicy, ThrottleLimits, ThrottledFileSystem, TimeoutFileSystem,
+    WriteBehindFileSystem, WriteBehindOptions,
+};
+use std::time::Duration;
+
+/// Middleware wrapping a [`FileSystem`] in another, applied by a [`Stack`].
+///
+/// Closures from a filesystem to its wrapper are layers too, so wrappers needing more
+/// configuration than their layer type offers can be stacked in place.
+pub trait FileSystemLayer<F: FileSystem> {
+    /// Resulting `FileSystem`.
+    type FileSystem: FileSystem;
+
+    /// Wrap `filesystem`.
+    fn layer(self, filesystem: F) -> Self::FileSystem;
+}
+
+impl<F: FileSystem, W: FileSystem, L: FnOnce(F) -> W> FileSystemLayer<F> for L {
+    type FileSystem = W;
+
+    fn layer(self, filesystem: F) -> W {
+        self(filesystem)
+    }
+}
+
+/// `FileSystem` Middleware Stack
+///
+/// Builds a filesystem by wrapping a backend in a sequence of [`FileSystemLayer`]s, where each
+/// layer wraps everything beneath it, so the last layer added sees operations first.
+///
+/// ```rust
+/// use minql_vfs::{
+///     AclEffect, AclFileSystem, AclOperation, AclRule, FileSystem, FileSystemError,
+///     MemoryFileSystem, Metrics, Stack, ThrottleLimits,
+/// };
+///
+/// let fs = Stack::new(MemoryFileSystem::new())
+///     .layer(ThrottleLimits::new().with_ops_per_second(10_000))
+///     .layer(|fs| {
+///         AclFileSystem::new(fs)
+///             .with_default(AclEffect::Allow)
+///             .with_rule(AclRule::new(AclEffect::Deny, "/secrets/**", &[AclOperation::Read]))
+///     })
+///     .layer(Metrics)
+///     .build();
+/// fs.write("/table.dat", b"rows").unwrap();
+/// assert!(matches!(fs.read("/secrets/key"), Err(FileSystemError::PermissionDenied)));
+/// assert_eq!(fs.filesystem_metrics().bytes_written(), 4);
+/// ```
+#[derive(Debug)]
+pub struct Stack<F: FileSystem> {
+    filesystem: F,
+}
+
+impl<F: FileSystem> Stack<F> {
+    /// Start a stack over a backend `filesystem`.
+    pub fn new(filesystem: F) -> Stack<F> {
+        Stack { filesystem }
+    }
+
+    /// Wrap the stack so far in `layer`.
+    pub fn layer<L: FileSystemLayer<F>>(self, layer: L) -> Stack<L::FileSystem> {
+        Stack {
+            filesystem: layer.layer(self.filesystem),
+        }
+    }
+
+    /// Finish the stack, returning its outermost `FileSystem`.
+    pub fn build(self) -> F {
+        self.filesystem
+    }
+}
+
+/// Layer recording operation metrics with a [`MetricFileSystem`].
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Metrics;
+
+impl<F: FileSystem> FileSystemLayer<F> for Metrics {
+    type FileSystem = MetricFileSystem;
+
+    fn layer(self, filesystem: F) -> MetricFileSystem {
+        MetricFileSystem::new(filesystem)
+    }
+}
+
+/// Layer verifying block checksums with a [`ChecksumFileSystem`].
+#[derive(Copy, Clone, Debug)]
+pub struct Checksums {
+    block_size: u64,
+}
+
+impl Checksums {
+    /// Checksum blocks of [`ChecksumFileSystem::DEFAULT_BLOCK_SIZE`] bytes.
+    #[must_use]
+    pub fn new() -> Checksums {
+        Checksums {
+            block_size: ChecksumFileSystem::DEFAULT_BLOCK_SIZE,
+        }
+    }
+
+    /// Set the number of bytes covered by each checksum.
+    #[must_use]
+    pub fn with_block_size(mut self, block_size: u64) -> Checksums {
+        self.block_size = block_size;
+        self
+    }
+}
+
+impl Default for Checksums {
+    fn default() -> Self {
+        Checksums::new()
+    }
+}
+
+impl<F: FileSystem> FileSystemLayer<F> for Checksums {
+    type FileSystem = ChecksumFileSystem;
+
+    fn layer(self, filesystem: F) -> ChecksumFileSystem {
+        ChecksumFileSystem::new(filesystem).with_block_size(self.block_size)
+    }
+}
+
+/// Layer confining paths beneath a prefix with a [`ScopedFileSystem`].
+#[derive(Clone, Debug)]
+pub struct Scope {
+    prefix: String,
+}
+
+impl Scope {
+    /// Confine paths beneath `prefix`.
+    #[must_use]
+    pub fn new(prefix: &str) -> Scope {
+        Scope {
+            prefix: prefix.to_string(),
+        }
+    }
+}
+
+impl<F: FileSystem> FileSystemLayer<F> for Scope {
+    type FileSystem = ScopedFileSystem;
+
+    fn layer(self, filesystem: F) -> ScopedFileSystem {
+        ScopedFileSystem::new(filesystem, &self.prefix)
+    }
+}
+
+/// Layer bounding the duration of operations with a [`TimeoutFileSystem`].
+#[derive(Copy, Clone, Debug)]
+pub struct Timeout {
+    timeout: Duration,
+}
+
+impl Timeout {
+    /// Fail operations taking longer than `timeout`.
+    #[must_use]
+    pub fn new(timeout: Duration) -> Timeout {
+        Timeout { timeout }
+    }
+}
+
+impl<F: FileSystem> FileSystemLayer<F> for Timeout {
+    type FileSystem = TimeoutFileSystem<F>;
+
+    fn layer(self, filesystem: F) -> TimeoutFileSystem<F> {
+        TimeoutFileSystem::new(filesystem, self.timeout)
+    }
+}
+
+/// Layer caching files on a faster `FileSystem` with a [`CachingFileSystem`].
+#[derive(Debug)]
+pub struct Cache<C: FileSystem> {
+    fast: C,
+    capacity: u64,
+}
+
+impl<C: FileSystem> Cache<C> {
+    /// Cache up to `capacity` bytes of files on `fast`.
+    pub fn new(fast: C, capacity: u64) -> Cache<C> {
+        Cache { fast, capacity }
+    }
+}
+
+impl<F: FileSystem, C: FileSystem> FileSystemLayer<F> for Cache<C> {
+    type FileSystem = CachingFileSystem<F, C>;
+
+    fn layer(self, filesystem: F) -> CachingFileSystem<F, C> {
+        CachingFileSystem::new(filesystem, self.fast, self.capacity)
+    }
+}
+
+/// Throttles operations with a [`ThrottledFileSystem`].
+impl<F: FileSystem> FileSystemLayer<F> for ThrottleLimits {
+    type FileSystem = ThrottledFileSystem<F>;
+
+    fn layer(self, filesystem: F) -> ThrottledFileSystem<F> {
+        ThrottledFileSystem::new(filesystem, self)
+    }
+}
+
+/// Syncs handles according to the policy with a [`SyncFileSystem`].
+impl<F: FileSystem> FileSystemLayer<F> for SyncPolicy {
+    type FileSystem = SyncFileSystem;
+
+    fn layer(self, filesystem: F) -> SyncFileSystem {
+        SyncFileSystem::new(filesystem, self)
+    }
+}
+
+/// Buffers writes with a [`WriteBehindFileSystem`].
+impl<F: FileSystem> FileSystemLayer<F> for WriteBehindOptions {
+    type FileSystem = WriteBehindFileSystem;
+
+    fn layer(self, filesystem: F) -> WriteBehindFileSystem {
+        WriteBehindFileSystem::new(filesystem, self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_stack_order() {
+        use crate::{
+            Cache, Checksums, FileSystem, FileSystemError, MemoryFileSystem, Metrics, Scope, Stack,
+            SyncPolicy, Timeout,
+        };
+        use std::time::Duration;
+
+        let backend = MemoryFileSystem::new();
+        backend.create_directory("/scoped").unwrap();
+        let fs = Stack::new(backend.clone())
+            .layer(Checksums::new().with_block_size(16))
+            .layer(Scope::new("/scoped"))
+            .layer(Cache::new(MemoryFileSystem::new(), 1024))
+            .layer(SyncPolicy::Never)
+            .layer(Timeout::new(Duration::from_secs(5)))
+            .layer(Metrics)
+            .build();
+        fs.write("/table.dat", b"rows").unwrap();
+        assert_eq!(fs.read("/table.dat").unwrap(), b"rows");
+        assert_eq!(fs.filesystem_metrics().bytes_written(), 4);
+
+        // Layers added first sit closest to the backend, so checksums are kept within the scope
+        assert_eq!(backend.read("/scoped/table.dat").unwrap(), b"rows");
+        assert!(backend.is_file("/scoped/table.dat.crc").unwrap());
+        assert!(matches!(
+            fs.read("/../table.dat"),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+    }
+}
@@ -0,0 +1,577 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{
+    FileSystem, FileSystemError, FileSystemResult, LockManager, ObjectStore, PutCondition,
+};
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// File extension of lease records.
+const LEASE_EXTENSION: &str = ".lease";
+
+/// Longest wait for another thread or process updating a lease record kept in a file.
+const RECORD_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Holder of a lease as recorded by its [`LeaseStore`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LeaseInfo {
+    /// Id of the [`LeaseManager`] holding the lease
+    pub holder: String,
+    /// Fencing token, incremented every time the lease changes hands
+    pub token: u64,
+    /// When the lease lapses unless renewed
+    pub expires: SystemTime,
+}
+
+impl LeaseInfo {
+    /// Whether the lease has lapsed.
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.expires <= SystemTime::now()
+    }
+
+    fn encode(&self) -> String {
+        let millis = self
+            .expires
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        format!("{}\n{}\n{millis}\n", self.holder, self.token)
+    }
+
+    fn decode(contents: &str) -> Option<LeaseInfo> {
+        let mut lines = contents.lines();
+        let holder = lines.next()?.to_string();
+        let token = lines.next()?.parse().ok()?;
+        let millis = lines.next()?.parse().ok()?;
+        Some(LeaseInfo {
+            holder,
+            token,
+            expires: UNIX_EPOCH + Duration::from_millis(millis),
+        })
+    }
+}
+
+/// Storage for lease records supporting conditional writes.
+///
+/// Every record carries an opaque version, and a record is only replaced if it still has the
+/// version it was read with, so racing holders can't both claim a lease.
+pub trait LeaseStore: Debug + Send + Sync + 'static {
+    /// Load the record of the lease `name` along with its version, or `None` if it has never
+    /// been held.
+    fn load(&self, name: &str) -> FileSystemResult<Option<(LeaseInfo, String)>>;
+    /// Store the record of the lease `name` if it is still at `version`, or still absent if
+    /// `version` is `None`, returning whether it was stored.
+    fn store(&self, name: &str, info: &LeaseInfo, version: Option<&str>) -> FileSystemResult<bool>;
+}
+
+/// Lease records kept as objects, updated with conditional writes.
+#[derive(Debug)]
+pub struct ObjectLeaseStore {
+    store: Arc<dyn ObjectStore>,
+    prefix: String,
+}
+
+impl ObjectLeaseStore {
+    /// Create a lease store keeping its records beneath `prefix` within the store.
+    pub fn new<S: ObjectStore>(store: S, prefix: &str) -> ObjectLeaseStore {
+        Self::from_arc(Arc::new(store), prefix)
+    }
+
+    /// Create a lease store over a shared store client.
+    pub fn from_arc(store: Arc<dyn ObjectStore>, prefix: &str) -> ObjectLeaseStore {
+        let mut prefix = prefix
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join("/");
+        if !prefix.is_empty() {
+            prefix.push('/');
+        }
+        ObjectLeaseStore { store, prefix }
+    }
+
+    fn key(&self, name: &str) -> FileSystemResult<String> {
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+            return Err(FileSystemError::invalid_path(name));
+        }
+        Ok(format!("{}{name}{LEASE_EXTENSION}", self.prefix))
+    }
+}
+
+impl LeaseStore for ObjectLeaseStore {
+    fn load(&self, name: &str) -> FileSystemResult<Option<(LeaseInfo, String)>> {
+        let Some((data, version)) = self.store.get_versioned(&self.key(name)?)? else {
+            return Ok(None);
+        };
+        let info = LeaseInfo::decode(&String::from_utf8_lossy(&data))
+            .ok_or_else(|| FileSystemError::internal_error("Malformed lease record"))?;
+        Ok(Some((info, version)))
+    }
+
+    fn store(&self, name: &str, info: &LeaseInfo, version: Option<&str>) -> FileSystemResult<bool> {
+        let condition = version.map_or(PutCondition::Absent, |version| {
+            PutCondition::Matches(version.to_string())
+        });
+        let stored = self
+            .store
+            .put_if(&self.key(name)?, info.encode().as_bytes(), &condition)?;
+        Ok(stored.is_some())
+    }
+}
+
+/// Lease records kept in files, updated while holding the record's lock file.
+///
+/// The record's contents serve as its version.
+#[derive(Debug)]
+pub struct FileLeaseStore<F: FileSystem> {
+    locks: LockManager<F>,
+}
+
+impl<F: FileSystem> FileLeaseStore<F> {
+    /// Create a lease store keeping its records and lock files in `directory`.
+    pub fn new(fs: F, directory: &str) -> FileSystemResult<FileLeaseStore<F>> {
+        Ok(FileLeaseStore {
+            locks: LockManager::new(fs, directory)?,
+        })
+    }
+
+    fn read(&self, path: &str) -> FileSystemResult<Option<String>> {
+        match self.locks.filesystem().read_to_string(path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(FileSystemError::PathMissing) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<F: FileSystem> LeaseStore for FileLeaseStore<F> {
+    fn load(&self, name: &str) -> FileSystemResult<Option<(LeaseInfo, String)>> {
+        let path = self.locks.sibling_path(name, LEASE_EXTENSION)?;
+        let _guard = self.locks.lock_timeout(name, RECORD_LOCK_TIMEOUT)?;
+        let Some(contents) = self.read(&path)? else {
+            return Ok(None);
+        };
+        let info = LeaseInfo::decode(&contents)
+            .ok_or_else(|| FileSystemError::internal_error("Malformed lease record"))?;
+        Ok(Some((info, contents)))
+    }
+
+    fn store(&self, name: &str, info: &LeaseInfo, version: Option<&str>) -> FileSystemResult<bool> {
+        let path = self.locks.sibling_path(name, LEASE_EXTENSION)?;
+        let _guard = self.locks.lock_timeout(name, RECORD_LOCK_TIMEOUT)?;
+        if self.read(&path)?.as_deref() != version {
+            return Ok(false);
+        }
+        self.locks
+            .filesystem()
+            .write(&path, info.encode().as_bytes())?;
+        Ok(true)
+    }
+}
+
+/// Lease Manager
+///
+/// Grants time limited, exclusive leases on names in a [`LeaseStore`], giving single writer
+/// guarantees on backends without advisory locks such as object stores. A lease lapses unless
+/// renewed before its time to live runs out, either explicitly with [`Lease::renew`] or from a
+/// background thread started by [`Lease::with_heartbeat`], after which another holder may take
+/// it over.
+///
+/// Every change of hands increments the lease's fencing token. Since a holder can't know it
+/// has lost a lapsed lease until it next renews, writers should pass [`Lease::token`] along
+/// with their writes so the resources they guard can reject a stale holder's writes. Expiry is
+/// judged by each participant's wall clock, so those clocks must be reasonably synchronized.
+///
+/// ```rust
+/// use minql_vfs::{FileLeaseStore, FileSystemError, LeaseManager, MemoryFileSystem};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let store = Arc::new(FileLeaseStore::new(MemoryFileSystem::new(), "/leases").unwrap());
+/// let primary = LeaseManager::from_arc(store.clone());
+/// let standby = LeaseManager::from_arc(store);
+///
+/// let lease = primary.try_acquire("writer", Duration::from_secs(30)).unwrap();
+/// assert_eq!(lease.token(), 1);
+/// assert!(matches!(
+///     standby.try_acquire("writer", Duration::from_secs(30)),
+///     Err(FileSystemError::AlreadyLocked)
+/// ));
+/// lease.release().unwrap();
+/// assert_eq!(standby.try_acquire("writer", Duration::from_secs(30)).unwrap().token(), 2);
+/// ```
+#[derive(Debug)]
+pub struct LeaseManager {
+    store: Arc<dyn LeaseStore>,
+    holder: String,
+}
+
+impl LeaseManager {
+    /// Create a Lease Manager with a unique holder id.
+    pub fn new<S: LeaseStore>(store: S) -> LeaseManager {
+        Self::from_arc(Arc::new(store))
+    }
+
+    /// Create a Lease Manager over a shared lease store.
+    pub fn from_arc(store: Arc<dyn LeaseStore>) -> LeaseManager {
+        static MANAGERS: AtomicU64 = AtomicU64::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let holder = format!(
+            "{}-{nanos:x}-{}",
+            std::process::id(),
+            MANAGERS.fetch_add(1, Ordering::Relaxed)
+        );
+        LeaseManager { store, holder }
+    }
+
+    /// Identify this manager's leases with `holder` rather than a generated id.
+    #[must_use]
+    pub fn with_holder(mut self, holder: &str) -> LeaseManager {
+        self.holder = holder.to_string();
+        self
+    }
+
+    /// Id recorded as the holder of this manager's leases.
+    #[must_use]
+    pub fn holder(&self) -> &str {
+        &self.holder
+    }
+
+    /// Acquire the lease `name` for `ttl`, failing with [`FileSystemError::AlreadyLocked`] if
+    /// it is held and hasn't lapsed.
+    #[tracing::instrument(level = "trace")]
+    pub fn try_acquire(&self, name: &str, ttl: Duration) -> FileSystemResult<Lease> {
+        let current = self.store.load(name)?;
+        let token = match &current {
+            Some((info, _)) if !info.is_expired() => return Err(FileSystemError::AlreadyLocked),
+            Some((info, _)) => info.token + 1,
+            None => 1,
+        };
+        let info = LeaseInfo {
+            holder: self.holder.clone(),
+            token,
+            expires: SystemTime::now() + ttl,
+        };
+        let version = current.as_ref().map(|(_, version)| version.as_str());
+        if !self.store.store(name, &info, version)? {
+            return Err(FileSystemError::AlreadyLocked);
+        }
+        Ok(Lease {
+            shared: Arc::new(LeaseShared {
+                store: self.store.clone(),
+                name: name.to_string(),
+                ttl,
+                state: Mutex::new(LeaseState {
+                    info,
+                    lost: false,
+                    released: false,
+                }),
+            }),
+            heartbeat: None,
+        })
+    }
+
+    /// Get the current holder of the lease `name`, or `None` if it isn't held.
+    pub fn current(&self, name: &str) -> FileSystemResult<Option<LeaseInfo>> {
+        Ok(self
+            .store
+            .load(name)?
+            .map(|(info, _)| info)
+            .filter(|info| !info.is_expired()))
+    }
+}
+
+/// Exclusive, time limited hold on a lease of a [`LeaseManager`], released when dropped.
+#[derive(Debug)]
+pub struct Lease {
+    shared: Arc<LeaseShared>,
+    heartbeat: Option<Heartbeat>,
+}
+
+#[derive(Debug)]
+struct LeaseShared {
+    store: Arc<dyn LeaseStore>,
+    name: String,
+    ttl: Duration,
+    state: Mutex<LeaseState>,
+}
+
+#[derive(Debug)]
+struct LeaseState {
+    info: LeaseInfo,
+    lost: bool,
+    released: bool,
+}
+
+#[derive(Debug)]
+struct Heartbeat {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: JoinHandle<()>,
+}
+
+impl Lease {
+    /// Name of the held lease.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.shared.name
+    }
+
+    /// Fencing token of this hold on the lease.
+    #[must_use]
+    pub fn token(&self) -> u64 {
+        self.shared.state.lock().expect("Poisoned Lock").info.token
+    }
+
+    /// When the lease lapses unless renewed.
+    #[must_use]
+    pub fn expires(&self) -> SystemTime {
+        self.shared
+            .state
+            .lock()
+            .expect("Poisoned Lock")
+            .info
+            .expires
+    }
+
+    /// Whether the lease is still held: it hasn't lapsed and no renewal has found it taken over.
+    #[must_use]
+    pub fn is_held(&self) -> bool {
+        let state = self.shared.state.lock().expect("Poisoned Lock");
+        !state.lost && !state.released && !state.info.is_expired()
+    }
+
+    /// Extend the lease by its time to live, failing with [`FileSystemError::AlreadyLocked`]
+    /// if it has been taken over.
+    #[tracing::instrument(level = "trace")]
+    pub fn renew(&self) -> FileSystemResult<()> {
+        self.shared.renew()
+    }
+
+    /// Renew the lease every `interval` from a background thread until it is released.
+    ///
+    /// The interval should be comfortably shorter than the time to live. The heartbeat stops
+    /// once a renewal fails, after which [`Lease::is_held`] reports the lease lost or lapsed.
+    #[must_use]
+    pub fn with_heartbeat(mut self, interval: Duration) -> Lease {
+        self.stop_heartbeat();
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let shared = self.shared.clone();
+        let signal = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let (stopped, wake) = &*signal;
+            let mut stopped = stopped.lock().expect("Poisoned Lock");
+            loop {
+                stopped = wake
+                    .wait_timeout(stopped, interval)
+                    .expect("Poisoned Lock")
+                    .0;
+                if *stopped {
+                    return;
+                }
+                if let Err(err) = shared.renew() {
+                    tracing::warn!(?err, name = shared.name, "Lease heartbeat failed");
+                    return;
+                }
+            }
+        });
+        self.heartbeat = Some(Heartbeat { stop, thread });
+        self
+    }
+
+    /// Release the lease so it can be acquired immediately by another holder.
+    #[tracing::instrument(level = "trace")]
+    pub fn release(mut self) -> FileSystemResult<()> {
+        self.stop_heartbeat();
+        self.shared.release()
+    }
+
+    fn stop_heartbeat(&mut self) {
+        if let Some(heartbeat) = self.heartbeat.take() {
+            let (stopped, wake) = &*heartbeat.stop;
+            *stopped.lock().expect("Poisoned Lock") = true;
+            wake.notify_all();
+            let _ = heartbeat.thread.join();
+        }
+    }
+}
+
+impl LeaseShared {
+    fn renew(&self) -> FileSystemResult<()> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        if state.lost || state.released {
+            return Err(FileSystemError::AlreadyLocked);
+        }
+        let renewed = match self.store.load(&self.name)? {
+            Some((current, version))
+                if current.holder == state.info.holder && current.token == state.info.token =>
+            {
+                let info = LeaseInfo {
+                    expires: SystemTime::now() + self.ttl,
+                    ..current
+                };
+                self.store
+                    .store(&self.name, &info, Some(&version))?
+                    .then_some(info)
+            }
+            _ => None,
+        };
+        if let Some(info) = renewed {
+            state.info = info;
+            Ok(())
+        } else {
+            state.lost = true;
+            Err(FileSystemError::AlreadyLocked)
+        }
+    }
+
+    fn release(&self) -> FileSystemResult<()> {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        if state.released {
+            return Ok(());
+        }
+        state.released = true;
+        if state.lost {
+            return Ok(());
+        }
+        // The record is kept, lapsed, so the next holder's fencing token follows on from ours.
+        match self.store.load(&self.name)? {
+            Some((current, version))
+                if current.holder == state.info.holder && current.token == state.info.token =>
+            {
+                let info = LeaseInfo {
+                    expires: UNIX_EPOCH,
+                    ..current
+                };
+                self.store.store(&self.name, &info, Some(&version))?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.stop_heartbeat();
+        if let Err(err) = self.shared.release() {
+            tracing::warn!(
+                ?err,
+                name = self.shared.name,
+                "Failed to release lease on drop"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_file_lease_takeover() {
+        use crate::{FileLeaseStore, FileSystemError, LeaseManager, MemoryFileSystem};
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let store = Arc::new(FileLeaseStore::new(MemoryFileSystem::new(), "/leases").unwrap());
+        let first = LeaseManager::from_arc(store.clone()).with_holder("first");
+        let second = LeaseManager::from_arc(store);
+
+        // A short lease lapses and is taken over with the next fencing token
+        let lapsed = first
+            .try_acquire("writer", Duration::from_millis(50))
+            .unwrap();
+        assert_eq!(second.current("writer").unwrap().unwrap().holder, "first");
+        assert!(matches!(
+            second.try_acquire("writer", Duration::from_secs(30)),
+            Err(FileSystemError::AlreadyLocked)
+        ));
+        std::thread::sleep(Duration::from_millis(80));
+        assert!(!lapsed.is_held());
+        assert!(second.current("writer").unwrap().is_none());
+        let lease = second
+            .try_acquire("writer", Duration::from_secs(30))
+            .unwrap();
+        assert_eq!(lease.token(), 2);
+
+        // The previous holder learns it lost the lease when renewing, and releasing it then
+        // leaves the new holder untouched
+        assert!(matches!(
+            lapsed.renew(),
+            Err(FileSystemError::AlreadyLocked)
+        ));
+        lapsed.release().unwrap();
+        assert!(lease.is_held());
+        lease.renew().unwrap();
+        drop(lease);
+        assert_eq!(
+            first
+                .try_acquire("writer", Duration::from_secs(30))
+                .unwrap()
+                .token(),
+            3
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_object_lease_heartbeat() {
+        use crate::filesystem::TestObjectStore;
+        use crate::{FileSystemError, LeaseManager, ObjectLeaseStore};
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let objects = TestObjectStore::default();
+        let store = Arc::new(ObjectLeaseStore::new(objects.clone(), "/locks/"));
+        let first = LeaseManager::from_arc(store.clone());
+        let second = LeaseManager::from_arc(store);
+
+        // Heartbeats keep a short lease alive well past its time to live
+        let lease = first
+            .try_acquire("compaction", Duration::from_millis(100))
+            .unwrap()
+            .with_heartbeat(Duration::from_millis(20));
+        assert!(objects
+            .objects
+            .lock()
+            .unwrap()
+            .contains_key("locks/compaction.lease"));
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(lease.is_held());
+        assert!(matches!(
+            second.try_acquire("compaction", Duration::from_secs(30)),
+            Err(FileSystemError::AlreadyLocked)
+        ));
+
+        // Dropping the lease releases it without resetting the fencing token
+        drop(lease);
+        let lease = second
+            .try_acquire("compaction", Duration::from_secs(30))
+            .unwrap();
+        assert_eq!(lease.token(), 2);
+        assert!(matches!(
+            second.try_acquire("../escape", Duration::from_secs(30)),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+    }
+}
@@ -19,7 +19,7 @@
 //!
 //!
 
-#![forbid(unsafe_code)]
+#![deny(unsafe_code)]
 #![warn(
     clippy::cargo,
     missing_docs,
@@ -30,21 +30,108 @@
 #![allow(
     clippy::option_if_let_else,
     clippy::module_name_repetitions,
-    clippy::missing_errors_doc
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
 )]
 // TODO: Remove These before 1.0
 #![allow(unused_imports, unused_variables, dead_code, unused_mut)]
 
+#[cfg(feature = "archive")]
+mod archive;
+mod bufferpool;
+mod bulk;
+mod cas;
+mod clock;
+#[cfg(any(test, feature = "conformance"))]
+pub mod conformance;
+mod content_type;
+mod diff;
 mod filesystem;
+mod hash;
+mod layer;
+mod lease;
+mod lockmanager;
+mod manifest;
+mod paged;
+mod path;
+mod progress;
 mod result;
+mod segmented;
+mod simulation;
+mod sync;
+mod tempgc;
+mod utility;
+mod wal;
 
+pub use self::bufferpool::{BufferPool, ClockPolicy, EvictionPolicy, LruPolicy, PinnedPage};
+pub use self::bulk::{
+    copy, copy_parallel, copy_with_progress, remove_directory_all_parallel,
+    remove_directory_all_with_progress, BulkReport, ParallelOptions,
+};
+pub use self::cas::{CasReader, CasStore, CasWriter, ContentHash, GcStats};
+pub use self::clock::{Clock, ManualClock, SystemClock};
+pub use self::content_type::detect_content_type;
+pub use self::diff::{diff, diff_with, DiffChange, DiffEntry, DiffOptions, DiffReport};
 pub use self::filesystem::{
-    FileHandle, FileLockMode, FileSystem, FileSystemProvider, LocalFileHandle, LocalFileSystem,
-    MemoryFileHandle, MemoryFileSystem, MetricFileSystem, MetricsFileHandle, VirtualFileHandle,
-    VirtualFileSystem, VirtualFileSystemManager,
+    block_on, is_connectivity_error, AclEffect, AclFileHandle, AclFileSystem, AclOperation,
+    AclRule, Advice, AsyncFileHandle, BatchOperation, BufferedFileHandle, CacheStats,
+    CachingFileHandle, CachingFileSystem, ChecksumFileHandle, ChecksumFileSystem, CloneMethod,
+    CrashFileHandle, CrashFileSystem, Divergence, EmbeddedFileHandle, EmbeddedFileSystem,
+    FailoverBackend, FailoverEvent, FailoverFileHandle, FailoverFileSystem, FailoverObserver,
+    FileHandle, FileLockMode, FileSystem, FileSystemProvider, FileSystemSpace, FileType,
+    GroupCommit, IoPriority, LatencyHistogram, LocalFileHandle, LocalFileSystem,
+    LocalFileSystemBuilder, LocalFileSystemProvider, ManagerMetrics, MemoryFileHandle,
+    MemoryFileSystem, MemoryFileSystemProvider, MetricFileSystem, MetricOperation, MetricsData,
+    MetricsFileHandle, MetricsSnapshot, MirrorCheckFileHandle, MirrorCheckFileSystem, MirrorPolicy,
+    ObjectListing, ObjectMeta, ObjectStore, ObjectStoreFileHandle, ObjectStoreFileSystem,
+    OpenOptions, OperationMetrics, Permissions, PriorityFileHandle, PriorityFileSystem,
+    PriorityStats, PriorityView, PutCondition, ReadAt, RecordFileHandle, RecordFileSystem,
+    ReplayMismatch, ReplayReport, ReplicatedFileHandle, ReplicatedFileSystem, ReplicationPolicy,
+    SchemeMetrics, ScopedFileHandle, ScopedFileSystem, SimulatedFileHandle, SimulatedFileSystem,
+    StripePolicy, StripedFileSystem, SymlinkPolicy, SyncFileHandle, SyncFileSystem, SyncPolicy,
+    Tenant, TenantFileHandle, TenantFileSystem, TestFileSystem, ThrottleLimits,
+    ThrottledFileHandle, ThrottledFileSystem, TimeoutFileHandle, TimeoutFileSystem, TraceOperation,
+    TraceRecord, TraceReplayer, TraceValue, VersionedFileHandle, VersionedFileSystem,
+    VersionedSnapshot, VirtualFileHandle, VirtualFileSystem, VirtualFileSystemManager, WriteAt,
+    WriteBehindFileHandle, WriteBehindFileSystem, WriteBehindOptions,
 };
+pub use self::hash::{ContentDigest, ContentHasher, HashAlgorithm};
+
+#[cfg(feature = "archive")]
+pub use self::archive::{pack, pack_with_progress, unpack, unpack_with_progress, ArchiveFormat};
+#[cfg(feature = "mmap")]
+pub use self::filesystem::FileMapping;
+#[cfg(feature = "azure")]
+pub use self::filesystem::{AzureFileSystemProvider, AzureObjectStore};
+#[cfg(feature = "gcs")]
+pub use self::filesystem::{GcsFileSystemProvider, GcsObjectStore};
+#[cfg(feature = "s3")]
+pub use self::filesystem::{S3FileSystemProvider, S3ObjectStore};
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub use self::filesystem::{UringFileHandle, UringFileSystem};
 
+pub use self::layer::{Cache, Checksums, FileSystemLayer, Metrics, Scope, Stack, Timeout};
+pub use self::lease::{
+    FileLeaseStore, Lease, LeaseInfo, LeaseManager, LeaseStore, ObjectLeaseStore,
+};
+pub use self::lockmanager::{LockGuard, LockInfo, LockManager};
+pub use self::manifest::{
+    manifest, manifest_with, verify, Manifest, ManifestEntry, ManifestMismatch, MismatchKind,
+};
+pub use self::paged::{Page, PageId, PagedFile};
+pub use self::path::VfsPath;
+pub use self::progress::{Progress, ProgressObserver};
 pub use self::result::{FileSystemError, FileSystemResult};
+pub use self::segmented::{SegmentOptions, SegmentPosition, SegmentedWriter};
+pub use self::simulation::{LatencyModel, SimEvent, SimRng, Simulation};
+pub use self::sync::{
+    sync, sync_parallel, sync_with_progress, SyncAction, SyncCompare, SyncOptions, SyncPlan,
+};
+pub use self::tempgc::{
+    collect_temp_files, is_temp_name, temp_name, TempCollector, TempFile, TempGcOptions,
+    TempGcReport, TEMP_MARKER,
+};
+pub use self::wal::{Lsn, WalIterator, WalOptions, WalSyncPolicy, WriteAheadLog};
 
 #[cfg(test)]
 mod tests {
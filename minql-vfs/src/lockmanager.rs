@@ -0,0 +1,438 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{FileHandle, FileLockMode, FileSystem, FileSystemError, FileSystemResult, OpenOptions};
+use std::collections::HashSet;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// File extension of lock files.
+const LOCK_EXTENSION: &str = ".lock";
+
+/// Longest wait between attempts on a lock held by another process.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Holder of a lock as recorded in its lock file.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct LockInfo {
+    /// Id of the process holding the lock
+    pub pid: u32,
+    /// When the holder last refreshed the lock
+    pub heartbeat: SystemTime,
+}
+
+/// Named Lock Manager
+///
+/// Provides named exclusive locks shared by every thread and process using the same directory
+/// of a [`FileSystem`]. Threads of one process are coordinated through an in-memory table, and
+/// processes through an advisory lock on a lock file per name, recording the holder's process
+/// id and a heartbeat. Locks are released when their [`LockGuard`] is dropped.
+///
+/// Filesystems without advisory range locks fall back to creating the lock file exclusively, in
+/// which case a lock whose heartbeat is older than the stale timeout, because its holder died
+/// without releasing it, is taken over.
+///
+/// ```rust
+/// use minql_vfs::{FileSystemError, LockManager, MemoryFileSystem};
+///
+/// let locks = LockManager::new(MemoryFileSystem::new(), "/locks").unwrap();
+/// let guard = locks.try_lock("compaction").unwrap();
+/// assert!(matches!(locks.try_lock("compaction"), Err(FileSystemError::AlreadyLocked)));
+/// assert_eq!(locks.holder("compaction").unwrap().unwrap().pid, std::process::id());
+/// drop(guard);
+/// assert!(locks.try_lock("compaction").is_ok());
+/// ```
+#[derive(Debug)]
+pub struct LockManager<F: FileSystem> {
+    shared: Arc<LockManagerShared<F>>,
+    stale_after: Duration,
+}
+
+#[derive(Debug)]
+struct LockManagerShared<F: FileSystem> {
+    fs: F,
+    directory: String,
+    held: Mutex<HashSet<String>>,
+    released: Condvar,
+}
+
+impl<F: FileSystem> LockManager<F> {
+    /// Default age after which an exclusively created lock file is considered abandoned.
+    pub const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(30);
+
+    /// Create a Lock Manager keeping its lock files in `directory`.
+    pub fn new(fs: F, directory: &str) -> FileSystemResult<LockManager<F>> {
+        let directory = directory.trim_end_matches('/').to_string();
+        fs.create_directory_all(&directory)?;
+        Ok(LockManager {
+            shared: Arc::new(LockManagerShared {
+                fs,
+                directory,
+                held: Mutex::default(),
+                released: Condvar::new(),
+            }),
+            stale_after: Self::DEFAULT_STALE_AFTER,
+        })
+    }
+
+    /// Take over exclusively created lock files whose heartbeat is older than `stale_after`.
+    ///
+    /// Holders on such filesystems must call [`LockGuard::heartbeat`] more often than this.
+    #[must_use]
+    pub fn with_stale_after(mut self, stale_after: Duration) -> LockManager<F> {
+        self.stale_after = stale_after;
+        self
+    }
+
+    /// Acquire the lock `name`, failing with [`FileSystemError::AlreadyLocked`] if it is held.
+    #[tracing::instrument(level = "trace")]
+    pub fn try_lock(&self, name: &str) -> FileSystemResult<LockGuard<F>> {
+        let path = self.lock_path(name)?;
+        {
+            let mut held = self.shared.held.lock().expect("Poisoned Lock");
+            if !held.insert(name.to_string()) {
+                return Err(FileSystemError::AlreadyLocked);
+            }
+        }
+        match self.acquire(&path) {
+            Ok((handle, exclusive_file)) => {
+                let mut guard = LockGuard {
+                    shared: self.shared.clone(),
+                    name: name.to_string(),
+                    path,
+                    handle: Some(handle),
+                    exclusive_file,
+                };
+                guard.heartbeat()?;
+                Ok(guard)
+            }
+            Err(err) => {
+                self.shared.release(name);
+                Err(err)
+            }
+        }
+    }
+
+    /// Acquire the lock `name`, waiting up to `timeout` for it to be released before failing
+    /// with [`FileSystemError::AlreadyLocked`].
+    #[tracing::instrument(level = "trace")]
+    pub fn lock_timeout(&self, name: &str, timeout: Duration) -> FileSystemResult<LockGuard<F>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.try_lock(name) {
+                Err(FileSystemError::AlreadyLocked) => {}
+                result => return result,
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(FileSystemError::AlreadyLocked);
+            }
+            // Wake as soon as a thread of this process releases, and poll for other processes.
+            let held = self.shared.held.lock().expect("Poisoned Lock");
+            if held.contains(name) {
+                drop(
+                    self.shared
+                        .released
+                        .wait_timeout(held, remaining.min(LOCK_POLL_INTERVAL))
+                        .expect("Poisoned Lock"),
+                );
+            } else {
+                drop(held);
+                std::thread::sleep(remaining.min(LOCK_POLL_INTERVAL));
+            }
+        }
+    }
+
+    /// Get the holder recorded for the lock `name`, or `None` if it isn't held.
+    pub fn holder(&self, name: &str) -> FileSystemResult<Option<LockInfo>> {
+        match self.shared.fs.read_to_string(&self.lock_path(name)?) {
+            Ok(contents) => Ok(parse_lock_info(&contents)),
+            Err(FileSystemError::PathMissing) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Filesystem holding the lock files.
+    pub(crate) fn filesystem(&self) -> &F {
+        &self.shared.fs
+    }
+
+    /// Path of the file with `extension` kept alongside the lock file for `name`.
+    pub(crate) fn sibling_path(&self, name: &str, extension: &str) -> FileSystemResult<String> {
+        self.lock_path(name)?;
+        Ok(format!("{}/{name}{extension}", self.shared.directory))
+    }
+
+    /// Path of the lock file for `name`, which must be usable as a file name.
+    fn lock_path(&self, name: &str) -> FileSystemResult<String> {
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+            return Err(FileSystemError::invalid_path(name));
+        }
+        Ok(format!("{}/{name}{LOCK_EXTENSION}", self.shared.directory))
+    }
+
+    /// Lock the lock file for this process, returning whether it was created exclusively.
+    fn acquire(&self, path: &str) -> FileSystemResult<(F::FileHandle, bool)> {
+        let fs = &self.shared.fs;
+        let options = OpenOptions::new().read(true).write(true).create(true);
+        let mut handle = fs.open_with(path, options)?;
+        match handle.lock_range(0, 0, FileLockMode::Exclusive) {
+            Ok(()) => return Ok((handle, false)),
+            Err(FileSystemError::FileAlreadyLocked) => return Err(FileSystemError::AlreadyLocked),
+            Err(FileSystemError::UnsupportedOperation) => drop(handle),
+            Err(err) => return Err(err),
+        }
+
+        // Without range locks the lock file only exists while the lock is held.
+        let fresh = fs.filesize(path)? == 0;
+        if fresh {
+            fs.remove_file(path)?;
+        }
+        match fs.create_file(path) {
+            Ok(handle) => Ok((handle, true)),
+            Err(FileSystemError::PathExists) => {
+                let stale = fs
+                    .read_to_string(path)
+                    .ok()
+                    .and_then(|contents| parse_lock_info(&contents))
+                    .and_then(|info| info.heartbeat.elapsed().ok())
+                    .is_some_and(|age| age > self.stale_after);
+                if !stale {
+                    return Err(FileSystemError::AlreadyLocked);
+                }
+                tracing::warn!(path, "Taking over stale lock");
+                fs.remove_file(path)?;
+                match fs.create_file(path) {
+                    Ok(handle) => Ok((handle, true)),
+                    Err(FileSystemError::PathExists) => Err(FileSystemError::AlreadyLocked),
+                    Err(err) => Err(err),
+                }
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<F: FileSystem> LockManagerShared<F> {
+    fn release(&self, name: &str) {
+        self.held.lock().expect("Poisoned Lock").remove(name);
+        self.released.notify_all();
+    }
+}
+
+/// Exclusive hold on a named lock of a [`LockManager`], released when dropped.
+#[derive(Debug)]
+pub struct LockGuard<F: FileSystem> {
+    shared: Arc<LockManagerShared<F>>,
+    name: String,
+    path: String,
+    handle: Option<F::FileHandle>,
+    exclusive_file: bool,
+}
+
+impl<F: FileSystem> LockGuard<F> {
+    /// Name of the held lock.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Record this process and the current time as the holder of the lock.
+    #[tracing::instrument(level = "trace")]
+    pub fn heartbeat(&mut self) -> FileSystemResult<()> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let contents = format!("{} {millis}\n", std::process::id());
+        let handle = self.handle.as_mut().expect("Lock Handle");
+        handle.set_size(0)?;
+        let mut written = 0;
+        while written < contents.len() {
+            match handle.write_to_offset(written as u64, &contents.as_bytes()[written..])? {
+                0 => return Err(FileSystemError::InvalidOperation),
+                count => written += count,
+            }
+        }
+        handle.sync_data()
+    }
+}
+
+impl<F: FileSystem> Drop for LockGuard<F> {
+    fn drop(&mut self) {
+        if let Some(mut handle) = self.handle.take() {
+            let result = if self.exclusive_file {
+                drop(handle);
+                self.shared.fs.remove_file(&self.path)
+            } else {
+                // Clear the holder before unlocking, the file itself is left for the next holder
+                handle.set_size(0).and_then(|()| handle.unlock_range(0, 0))
+            };
+            if let Err(err) = result {
+                tracing::warn!(?err, name = self.name, "Failed to release lock on drop");
+            }
+        }
+        self.shared.release(&self.name);
+    }
+}
+
+/// Parse the `pid millis` contents of a lock file.
+fn parse_lock_info(contents: &str) -> Option<LockInfo> {
+    let (pid, millis) = contents.trim().split_once(' ')?;
+    Some(LockInfo {
+        pid: pid.parse().ok()?,
+        heartbeat: UNIX_EPOCH + Duration::from_millis(millis.parse().ok()?),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_lock_manager() {
+        use crate::{FileSystemError, LockManager, MemoryFileSystem};
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        let locks = Arc::new(LockManager::new(MemoryFileSystem::new(), "/locks/").unwrap());
+        assert!(locks.holder("table").unwrap().is_none());
+        assert!(matches!(
+            locks.try_lock("../table"),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+
+        let guard = locks.try_lock("table").unwrap();
+        assert_eq!(guard.name(), "table");
+        assert_eq!(
+            locks.holder("table").unwrap().unwrap().pid,
+            std::process::id()
+        );
+        let other = locks.try_lock("index").unwrap();
+        assert!(matches!(
+            locks.try_lock("table"),
+            Err(FileSystemError::AlreadyLocked)
+        ));
+        let start = Instant::now();
+        assert!(matches!(
+            locks.lock_timeout("table", Duration::from_millis(30)),
+            Err(FileSystemError::AlreadyLocked)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+
+        // A waiter wakes once the holder releases
+        let waiter = {
+            let locks = locks.clone();
+            std::thread::spawn(move || {
+                locks
+                    .lock_timeout("table", Duration::from_secs(10))
+                    .map(|guard| guard.name().to_string())
+                    .ok()
+            })
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        drop(guard);
+        assert_eq!(waiter.join().unwrap().as_deref(), Some("table"));
+        assert!(locks.holder("table").unwrap().is_none());
+        drop(other);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_lock_manager_local() {
+        use crate::{FileHandle, FileLockMode, FileSystem, FileSystemError, LocalFileSystem};
+        use crate::{LockManager, OpenOptions};
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let root = std::env::temp_dir().join(format!(
+            "test-locks-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_nanos()
+        ));
+        let fs = LocalFileSystem::new(&root);
+        let locks = LockManager::new(LocalFileSystem::new(&root), "/locks").unwrap();
+        let guard = locks.try_lock("writer").unwrap();
+
+        // Another process sees the advisory lock on the lock file
+        let mut handle = fs
+            .open_with(
+                "/locks/writer.lock",
+                OpenOptions::new().read(true).write(true),
+            )
+            .unwrap();
+        assert!(matches!(
+            handle.lock_range(0, 0, FileLockMode::Exclusive),
+            Err(FileSystemError::FileAlreadyLocked)
+        ));
+        drop(guard);
+        handle.lock_range(0, 0, FileLockMode::Exclusive).unwrap();
+        assert!(matches!(
+            locks.try_lock("writer"),
+            Err(FileSystemError::AlreadyLocked)
+        ));
+        drop(handle);
+        assert!(locks.try_lock("writer").is_ok());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_lock_manager_stale() {
+        use crate::filesystem::TestObjectStore;
+        use crate::{FileSystem, FileSystemError, LockManager, ObjectStoreFileSystem};
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        // Object stores lack range locks, so lock files are created exclusively
+        let store = TestObjectStore::default();
+        let first = LockManager::new(ObjectStoreFileSystem::new(store.clone(), "data"), "/locks")
+            .unwrap()
+            .with_stale_after(Duration::from_mins(1));
+        let second = LockManager::new(ObjectStoreFileSystem::new(store.clone(), "data"), "/locks")
+            .unwrap()
+            .with_stale_after(Duration::from_mins(1));
+        let guard = first.try_lock("job").unwrap();
+        assert!(matches!(
+            second.try_lock("job"),
+            Err(FileSystemError::AlreadyLocked)
+        ));
+        drop(guard);
+        drop(second.try_lock("job").unwrap());
+
+        // A holder that stopped heartbeating is taken over, a live one isn't
+        let fs = ObjectStoreFileSystem::new(store, "data");
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        fs.write("/locks/job.lock", format!("1 {millis}\n").as_bytes())
+            .unwrap();
+        assert!(matches!(
+            first.try_lock("job"),
+            Err(FileSystemError::AlreadyLocked)
+        ));
+        fs.write("/locks/job.lock", b"1 0\n").unwrap();
+        let guard = first.try_lock("job").unwrap();
+        assert_eq!(
+            first.holder("job").unwrap().unwrap().pid,
+            std::process::id()
+        );
+        drop(guard);
+        assert!(!fs.exists("/locks/job.lock").unwrap());
+    }
+}
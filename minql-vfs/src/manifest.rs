@@ -0,0 +1,475 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::utility::{child_path, normalize_path};
+use crate::{
+    ContentDigest, FileSystem, FileSystemError, FileSystemResult, FileType, HashAlgorithm,
+    SymlinkPolicy,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// First line of a saved manifest, followed by the name of its hash algorithm.
+const MANIFEST_HEADER: &str = "minql-manifest 1";
+/// Prefix of the optional last line of a saved manifest, holding its signature.
+const SIGNATURE_PREFIX: &str = "signature ";
+
+/// File recorded in a [`Manifest`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ManifestEntry {
+    /// Path of the file relative to the manifest root, without a leading `/`
+    pub path: String,
+    /// Size of the file in bytes
+    pub size: u64,
+    /// Digest of the contents of the file
+    pub digest: ContentDigest,
+}
+
+/// Sizes and digests of every file below a directory, built by [`manifest`] and checked
+/// against a tree by [`verify`].
+///
+/// Manifests are saved as text with a line per file, and may be signed with a secret key so a
+/// receiver can tell the manifest itself wasn't tampered with. Signatures are keyed BLAKE3
+/// hashes of everything but the signature line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Manifest {
+    algorithm: HashAlgorithm,
+    entries: Vec<ManifestEntry>,
+    signature: Option<[u8; 32]>,
+}
+
+impl Manifest {
+    /// Algorithm the digests of the entries were computed with.
+    #[must_use]
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Recorded files, ordered by path.
+    #[must_use]
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    /// Sign the manifest with `key`, replacing any previous signature.
+    pub fn sign(&mut self, key: &[u8; 32]) {
+        self.signature = Some(*blake3::keyed_hash(key, self.body().as_bytes()).as_bytes());
+    }
+
+    /// Check if the manifest carries a signature.
+    #[must_use]
+    pub fn is_signed(&self) -> bool {
+        self.signature.is_some()
+    }
+
+    /// Check if the manifest is signed with `key` and unchanged since, in constant time.
+    #[must_use]
+    pub fn verify_signature(&self, key: &[u8; 32]) -> bool {
+        self.signature.is_some_and(|signature| {
+            blake3::keyed_hash(key, self.body().as_bytes()) == blake3::Hash::from(signature)
+        })
+    }
+
+    /// Save the manifest as text to the file at `path`, replacing it.
+    pub fn save<F: FileSystem + ?Sized>(&self, fs: &F, path: &str) -> FileSystemResult<()> {
+        fs.write(path, self.to_string().as_bytes())
+    }
+
+    /// Load a manifest saved by [`Manifest::save`].
+    ///
+    /// Fails with [`FileSystemError::CorruptData`] at the start of the first malformed line.
+    pub fn load<F: FileSystem + ?Sized>(fs: &F, path: &str) -> FileSystemResult<Manifest> {
+        let text = fs.read_to_string(path)?;
+        let corrupt = |offset: usize| FileSystemError::CorruptData {
+            path: path.to_string(),
+            offset: offset as u64,
+        };
+        let mut lines = text.split_inclusive('\n').scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line.strip_suffix('\n').unwrap_or(line)))
+        });
+        let algorithm = lines
+            .next()
+            .and_then(|(_, line)| line.strip_prefix(MANIFEST_HEADER)?.strip_prefix(' '))
+            .and_then(algorithm_from_name)
+            .ok_or_else(|| corrupt(0))?;
+        let mut manifest = Manifest {
+            algorithm,
+            entries: Vec::new(),
+            signature: None,
+        };
+        for (offset, line) in lines {
+            if manifest.signature.is_some() {
+                return Err(corrupt(offset));
+            }
+            if let Some(signature) = line.strip_prefix(SIGNATURE_PREFIX) {
+                let signature = blake3::Hash::from_hex(signature).map_err(|_| corrupt(offset))?;
+                manifest.signature = Some(*signature.as_bytes());
+                continue;
+            }
+            let mut fields = line.splitn(3, ' ');
+            let entry = (|| {
+                let digest = ContentDigest::from_hex(algorithm, fields.next()?)?;
+                let size = fields.next()?.parse().ok()?;
+                let path = unescape(fields.next()?)?;
+                Some(ManifestEntry { path, size, digest })
+            })()
+            .ok_or_else(|| corrupt(offset))?;
+            if manifest
+                .entries
+                .last()
+                .is_some_and(|last| last.path >= entry.path)
+            {
+                return Err(corrupt(offset));
+            }
+            manifest.entries.push(entry);
+        }
+        Ok(manifest)
+    }
+
+    /// Text of the manifest without its signature, which is what gets signed.
+    fn body(&self) -> String {
+        let mut body = format!("{MANIFEST_HEADER} {}\n", algorithm_name(self.algorithm));
+        for entry in &self.entries {
+            let _ = writeln!(
+                body,
+                "{} {} {}",
+                entry.digest,
+                entry.size,
+                escape(&entry.path)
+            );
+        }
+        body
+    }
+}
+
+impl std::fmt::Display for Manifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.body())?;
+        if let Some(signature) = &self.signature {
+            writeln!(
+                f,
+                "{SIGNATURE_PREFIX}{}",
+                blake3::Hash::from(*signature).to_hex()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How a tree differs from a [`Manifest`] at one path.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MismatchKind {
+    /// File recorded in the manifest is missing from the tree
+    Missing,
+    /// File in the tree isn't recorded in the manifest
+    Unexpected,
+    /// File has a different size than recorded
+    Size {
+        /// Recorded size
+        expected: u64,
+        /// Size in the tree
+        actual: u64,
+    },
+    /// File has the recorded size but different contents
+    Digest {
+        /// Recorded digest
+        expected: ContentDigest,
+        /// Digest of the file in the tree
+        actual: ContentDigest,
+    },
+}
+
+/// Difference between a tree and a [`Manifest`], returned by [`verify`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ManifestMismatch {
+    /// Path of the file relative to the manifest root, without a leading `/`
+    pub path: String,
+    /// How the file differs
+    pub kind: MismatchKind,
+}
+
+/// Record the size and SHA-256 digest of every file below the directory at `path`.
+///
+/// Symbolic links aren't followed and aren't recorded.
+///
+/// ```rust
+/// use minql_vfs::{manifest, verify, FileSystem, Manifest, MemoryFileSystem, MismatchKind};
+///
+/// let source = MemoryFileSystem::new();
+/// source.create_directory_all("/dataset/tables").unwrap();
+/// source.write("/dataset/tables/users.db", b"rows").unwrap();
+/// let key = [7; 32];
+/// let mut shipped = manifest(&source, "/dataset").unwrap();
+/// shipped.sign(&key);
+/// shipped.save(&source, "/dataset.manifest").unwrap();
+///
+/// let received = Manifest::load(&source, "/dataset.manifest").unwrap();
+/// assert!(received.verify_signature(&key));
+/// assert!(verify(&source, "/dataset", &received).unwrap().is_empty());
+///
+/// source.write("/dataset/tables/users.db", b"ROWS").unwrap();
+/// let mismatches = verify(&source, "/dataset", &received).unwrap();
+/// assert_eq!(mismatches[0].path, "tables/users.db");
+/// assert!(matches!(mismatches[0].kind, MismatchKind::Digest { .. }));
+/// ```
+pub fn manifest<F: FileSystem>(fs: &F, path: &str) -> FileSystemResult<Manifest> {
+    manifest_with(fs, path, HashAlgorithm::Sha256)
+}
+
+/// Record the size and digest of every file below the directory at `path` like [`manifest`],
+/// hashing with `algorithm`.
+pub fn manifest_with<F: FileSystem>(
+    fs: &F,
+    path: &str,
+    algorithm: HashAlgorithm,
+) -> FileSystemResult<Manifest> {
+    let entries = files(fs, path)?
+        .into_iter()
+        .map(|(relative, absolute)| {
+            Ok(ManifestEntry {
+                path: relative,
+                size: fs.filesize(&absolute)?,
+                digest: fs.hash_file(&absolute, algorithm)?,
+            })
+        })
+        .collect::<FileSystemResult<Vec<_>>>()?;
+    Ok(Manifest {
+        algorithm,
+        entries,
+        signature: None,
+    })
+}
+
+/// Compare the files below the directory at `path` with a manifest, returning the mismatches
+/// ordered by path.
+///
+/// Files are only hashed when their size matches. The signature of the manifest isn't
+/// checked, see [`Manifest::verify_signature`].
+pub fn verify<F: FileSystem>(
+    fs: &F,
+    path: &str,
+    manifest: &Manifest,
+) -> FileSystemResult<Vec<ManifestMismatch>> {
+    let mut present = files(fs, path)?;
+    let mut mismatches = Vec::new();
+    for entry in &manifest.entries {
+        let kind = match present.remove(&entry.path) {
+            None => Some(MismatchKind::Missing),
+            Some(absolute) => {
+                let size = fs.filesize(&absolute)?;
+                if size == entry.size {
+                    let digest = fs.hash_file(&absolute, manifest.algorithm)?;
+                    (digest != entry.digest).then(|| MismatchKind::Digest {
+                        expected: entry.digest.clone(),
+                        actual: digest,
+                    })
+                } else {
+                    Some(MismatchKind::Size {
+                        expected: entry.size,
+                        actual: size,
+                    })
+                }
+            }
+        };
+        if let Some(kind) = kind {
+            mismatches.push(ManifestMismatch {
+                path: entry.path.clone(),
+                kind,
+            });
+        }
+    }
+    mismatches.extend(present.into_keys().map(|path| ManifestMismatch {
+        path,
+        kind: MismatchKind::Unexpected,
+    }));
+    mismatches.sort_by(|first, second| first.path.cmp(&second.path));
+    Ok(mismatches)
+}
+
+/// Find every file below the directory at `path`, keyed by relative path with their absolute
+/// paths.
+fn files<F: FileSystem>(fs: &F, path: &str) -> FileSystemResult<BTreeMap<String, String>> {
+    let root = normalize_path(path)?;
+    if !fs.is_directory(&root)? {
+        return Err(if fs.exists(&root)? {
+            FileSystemError::InvalidOperation
+        } else {
+            FileSystemError::PathMissing
+        });
+    }
+    let mut files = BTreeMap::new();
+    let mut pending = vec![(root, String::new())];
+    while let Some((directory, relative)) = pending.pop() {
+        for name in fs.list_directory(&directory)? {
+            let absolute = child_path(&directory, &name);
+            let relative = if relative.is_empty() {
+                name
+            } else {
+                format!("{relative}/{name}")
+            };
+            match fs.file_type(&absolute, SymlinkPolicy::NoFollow)? {
+                FileType::Directory => pending.push((absolute, relative)),
+                FileType::File => {
+                    files.insert(relative, absolute);
+                }
+                FileType::Symlink => {}
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Name of a hash algorithm in a saved manifest.
+fn algorithm_name(algorithm: HashAlgorithm) -> &'static str {
+    match algorithm {
+        HashAlgorithm::Sha256 => "sha256",
+        HashAlgorithm::Blake3 => "blake3",
+        HashAlgorithm::Crc32 => "crc32",
+    }
+}
+
+/// Hash algorithm named in a saved manifest.
+fn algorithm_from_name(name: &str) -> Option<HashAlgorithm> {
+    match name {
+        "sha256" => Some(HashAlgorithm::Sha256),
+        "blake3" => Some(HashAlgorithm::Blake3),
+        "crc32" => Some(HashAlgorithm::Crc32),
+        _ => None,
+    }
+}
+
+/// Escape the backslashes and line breaks of a path so it fits on one line.
+fn escape(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for character in path.chars() {
+        match character {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// Reverse [`escape`], or `None` if the path has an unknown escape.
+fn unescape(escaped: &str) -> Option<String> {
+    let mut path = String::with_capacity(escaped.len());
+    let mut characters = escaped.chars();
+    while let Some(character) = characters.next() {
+        path.push(match character {
+            '\\' => match characters.next()? {
+                '\\' => '\\',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            },
+            '\r' => return None,
+            character => character,
+        });
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_manifest() {
+        use super::{manifest_with, verify, Manifest, ManifestMismatch, MismatchKind};
+        use crate::{FileSystem, FileSystemError, HashAlgorithm, MemoryFileSystem};
+
+        let fs = MemoryFileSystem::new();
+        fs.create_directory_all("/data/a/empty").unwrap();
+        fs.write("/data/a/one", b"one").unwrap();
+        fs.write("/data/a/line\nbreak", b"odd").unwrap();
+        fs.write("/data/two", b"two").unwrap();
+        fs.create_symlink("two", "/data/link").unwrap();
+
+        let mut manifest = manifest_with(&fs, "/data", HashAlgorithm::Blake3).unwrap();
+        let paths = manifest
+            .entries()
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["a/line\nbreak", "a/one", "two"]);
+        assert!(!manifest.is_signed());
+        assert!(!manifest.verify_signature(&[1; 32]));
+        manifest.sign(&[1; 32]);
+        assert!(manifest.verify_signature(&[1; 32]));
+        assert!(!manifest.verify_signature(&[2; 32]));
+
+        manifest.save(&fs, "/data.manifest").unwrap();
+        let loaded = Manifest::load(&fs, "/data.manifest").unwrap();
+        assert_eq!(loaded, manifest);
+        assert!(loaded.verify_signature(&[1; 32]));
+
+        // Tampering with an entry invalidates the signature
+        let text = fs.read_to_string("/data.manifest").unwrap();
+        fs.write(
+            "/data.manifest",
+            text.replacen(" 3 a/one", " 4 a/one", 1).as_bytes(),
+        )
+        .unwrap();
+        assert!(!Manifest::load(&fs, "/data.manifest")
+            .unwrap()
+            .verify_signature(&[1; 32]));
+        fs.write("/data.manifest", text.replacen(" 3 ", " x ", 1).as_bytes())
+            .unwrap();
+        let header = text.find('\n').unwrap() as u64 + 1;
+        assert!(matches!(
+            Manifest::load(&fs, "/data.manifest"),
+            Err(FileSystemError::CorruptData { offset, .. }) if offset == header
+        ));
+
+        assert!(verify(&fs, "/data", &manifest).unwrap().is_empty());
+        fs.write("/data/two", b"TWO").unwrap();
+        fs.write("/data/a/one", b"longer").unwrap();
+        fs.remove_file("/data/a/line\nbreak").unwrap();
+        fs.write("/data/a/empty/new", b"new").unwrap();
+        let mismatches = verify(&fs, "/data", &manifest).unwrap();
+        assert_eq!(
+            mismatches[..3],
+            [
+                ManifestMismatch {
+                    path: "a/empty/new".to_string(),
+                    kind: MismatchKind::Unexpected,
+                },
+                ManifestMismatch {
+                    path: "a/line\nbreak".to_string(),
+                    kind: MismatchKind::Missing,
+                },
+                ManifestMismatch {
+                    path: "a/one".to_string(),
+                    kind: MismatchKind::Size {
+                        expected: 3,
+                        actual: 6
+                    },
+                },
+            ]
+        );
+        assert_eq!(mismatches.len(), 4);
+        assert_eq!(mismatches[3].path, "two");
+        assert!(matches!(mismatches[3].kind, MismatchKind::Digest { .. }));
+
+        assert!(matches!(
+            verify(&fs, "/data/two", &manifest),
+            Err(FileSystemError::InvalidOperation)
+        ));
+    }
+}
@@ -0,0 +1,377 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{FileHandle, FileSystemError, FileSystemResult, Lsn};
+
+/// Index of a page within a [`PagedFile`].
+pub type PageId = u64;
+
+/// Number of bytes at the end of every page reserved for its checksum.
+const CHECKSUM_SIZE: usize = 4;
+
+/// Number of bytes at the start of every page reserved for its LSN, when enabled.
+const LSN_HEADER_SIZE: usize = 16;
+
+/// Contents of a single page, excluding its checksum trailer and LSN header.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Page {
+    data: Box<[u8]>,
+    lsn: Lsn,
+}
+
+impl Page {
+    /// Create a zeroed page holding `len` bytes.
+    #[must_use]
+    pub fn new(len: usize) -> Page {
+        Page {
+            data: vec![0; len].into_boxed_slice(),
+            lsn: Lsn::default(),
+        }
+    }
+
+    /// Borrow the contents of this page.
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Mutably borrow the contents of this page.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Number of bytes held by this page.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Check if this page holds no bytes.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Position in the log of the last record applied to this page.
+    #[must_use]
+    pub fn lsn(&self) -> Lsn {
+        self.lsn
+    }
+
+    /// Record that the log record at `lsn` has been applied to this page.
+    ///
+    /// Only stored by a [`PagedFile`] with [`PagedFile::with_page_lsn`] enabled.
+    pub fn set_lsn(&mut self, lsn: Lsn) {
+        self.lsn = lsn;
+    }
+}
+
+impl From<Vec<u8>> for Page {
+    fn from(data: Vec<u8>) -> Self {
+        Page {
+            data: data.into_boxed_slice(),
+            lsn: Lsn::default(),
+        }
+    }
+}
+
+impl std::fmt::Debug for Page {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Page({} bytes)", self.data.len())
+    }
+}
+
+/// Paged File
+///
+/// Divides a [`FileHandle`] into fixed size pages addressed by [`PageId`]. The last four bytes of
+/// every page on storage hold a CRC32 of the rest of the page, which is verified on every read,
+/// so each [`Page`] carries `page_size - 4` bytes of payload. Pages that have been allocated but
+/// never written read back as zeroes.
+///
+/// Files holding pages changed under a write-ahead log can also reserve the first sixteen bytes
+/// of every page for the [`Lsn`] of the last record applied to it, with
+/// [`PagedFile::with_page_lsn`], so recovery knows which records a page already reflects.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, PagedFile};
+///
+/// let fs = MemoryFileSystem::new();
+/// let mut file = PagedFile::new(fs.create_file("/pages.dat").unwrap(), 4096).unwrap();
+///
+/// let id = file.extend(1).unwrap();
+/// let mut page = file.read_page(id).unwrap();
+/// page.data_mut()[..5].copy_from_slice(b"Hello");
+/// file.write_page(id, &page).unwrap();
+/// assert_eq!(&file.read_page(id).unwrap().data()[..5], b"Hello");
+/// ```
+#[derive(Debug)]
+pub struct PagedFile<H: FileHandle> {
+    handle: H,
+    page_size: usize,
+    page_lsn: bool,
+}
+
+impl<H: FileHandle> PagedFile<H> {
+    /// Wrap a `FileHandle` using pages of `page_size` bytes, including the checksum trailer.
+    pub fn new(handle: H, page_size: usize) -> FileSystemResult<PagedFile<H>> {
+        if page_size <= CHECKSUM_SIZE {
+            return Err(FileSystemError::InternalError(format!(
+                "page size {page_size} must be larger than the {CHECKSUM_SIZE} byte checksum"
+            )));
+        }
+        Ok(PagedFile {
+            handle,
+            page_size,
+            page_lsn: false,
+        })
+    }
+
+    /// Store the [`Lsn`] of every page in a header before its payload.
+    ///
+    /// Fails if the page size leaves no room for a payload.
+    pub fn with_page_lsn(mut self) -> FileSystemResult<PagedFile<H>> {
+        if self.page_size <= CHECKSUM_SIZE + LSN_HEADER_SIZE {
+            return Err(FileSystemError::InternalError(format!(
+                "page size {} must be larger than the {} byte header and checksum",
+                self.page_size,
+                CHECKSUM_SIZE + LSN_HEADER_SIZE
+            )));
+        }
+        self.page_lsn = true;
+        Ok(self)
+    }
+
+    /// Size of each page on storage, including the checksum trailer and any LSN header.
+    #[must_use]
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Whether every page stores its [`Lsn`] in a header, see [`PagedFile::with_page_lsn`].
+    #[must_use]
+    pub fn has_page_lsn(&self) -> bool {
+        self.page_lsn
+    }
+
+    /// Number of payload bytes held by each page.
+    #[must_use]
+    pub fn payload_size(&self) -> usize {
+        self.page_size - CHECKSUM_SIZE - self.header_size()
+    }
+
+    /// Create an empty page sized for this file.
+    #[must_use]
+    pub fn new_page(&self) -> Page {
+        Page::new(self.payload_size())
+    }
+
+    /// Number of whole pages in the file.
+    pub fn page_count(&self) -> FileSystemResult<u64> {
+        Ok(self.handle.get_size()? / self.page_size as u64)
+    }
+
+    /// Read and verify a page.
+    #[tracing::instrument(level = "trace")]
+    pub fn read_page(&mut self, id: PageId) -> FileSystemResult<Page> {
+        if id >= self.page_count()? {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        let mut buffer = vec![0; self.page_size];
+        let mut filled = 0;
+        while filled < buffer.len() {
+            let offset = self.offset(id) + filled as u64;
+            match self.handle.read_at_offset(offset, &mut buffer[filled..])? {
+                0 => return Err(FileSystemError::InvalidOperation),
+                read => filled += read,
+            }
+        }
+        let (payload, trailer) = buffer.split_at(self.page_size - CHECKSUM_SIZE);
+        let stored = u32::from_le_bytes(trailer.try_into().expect("Checksum Trailer"));
+        // Pages extended but never written are all zeroes, including their checksum.
+        if stored != crc32fast::hash(payload) && !buffer.iter().all(|byte| *byte == 0) {
+            return Err(FileSystemError::CorruptData {
+                path: self.handle.path().to_string(),
+                offset: self.offset(id),
+            });
+        }
+        let lsn = if self.page_lsn {
+            Lsn {
+                segment: u64::from_le_bytes(buffer[..8].try_into().expect("Page Lsn")),
+                offset: u64::from_le_bytes(buffer[8..16].try_into().expect("Page Lsn")),
+            }
+        } else {
+            Lsn::default()
+        };
+        buffer.truncate(self.page_size - CHECKSUM_SIZE);
+        buffer.drain(..self.header_size());
+        let mut page = Page::from(buffer);
+        page.set_lsn(lsn);
+        Ok(page)
+    }
+
+    /// Checksum and write a page, extending the file if `id` is the next page.
+    #[tracing::instrument(level = "trace")]
+    pub fn write_page(&mut self, id: PageId, page: &Page) -> FileSystemResult<()> {
+        if page.len() != self.payload_size() || id > self.page_count()? {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        let mut buffer = Vec::with_capacity(self.page_size);
+        if self.page_lsn {
+            buffer.extend_from_slice(&page.lsn().segment.to_le_bytes());
+            buffer.extend_from_slice(&page.lsn().offset.to_le_bytes());
+        }
+        buffer.extend_from_slice(page.data());
+        let checksum = crc32fast::hash(&buffer);
+        buffer.extend_from_slice(&checksum.to_le_bytes());
+        let mut written = 0;
+        while written < buffer.len() {
+            let offset = self.offset(id) + written as u64;
+            match self.handle.write_to_offset(offset, &buffer[written..])? {
+                0 => return Err(FileSystemError::InvalidOperation),
+                count => written += count,
+            }
+        }
+        Ok(())
+    }
+
+    /// Append `count` zeroed pages, returning the id of the first.
+    #[tracing::instrument(level = "trace")]
+    pub fn extend(&mut self, count: u64) -> FileSystemResult<PageId> {
+        let first = self.page_count()?;
+        self.handle
+            .allocate((first + count) * self.page_size as u64)?;
+        Ok(first)
+    }
+
+    /// Discard every page from `count` onwards.
+    #[tracing::instrument(level = "trace")]
+    pub fn truncate_pages(&mut self, count: u64) -> FileSystemResult<()> {
+        self.handle.set_size(count * self.page_size as u64)
+    }
+
+    /// Flush written pages to storage.
+    #[tracing::instrument(level = "trace")]
+    pub fn sync(&mut self) -> FileSystemResult<()> {
+        self.handle.sync_data()
+    }
+
+    /// Borrow the underlying `FileHandle`.
+    #[must_use]
+    pub fn handle(&self) -> &H {
+        &self.handle
+    }
+
+    /// Unwrap the underlying `FileHandle`.
+    #[must_use]
+    pub fn into_inner(self) -> H {
+        self.handle
+    }
+
+    fn offset(&self, id: PageId) -> u64 {
+        id * self.page_size as u64
+    }
+
+    fn header_size(&self) -> usize {
+        if self.page_lsn {
+            LSN_HEADER_SIZE
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{FileHandle, FileSystem, FileSystemError, Lsn, MemoryFileSystem, PagedFile};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_paged_file() {
+        let fs = MemoryFileSystem::new();
+        let mut file = PagedFile::new(fs.create_file("/pages.dat").unwrap(), 64).unwrap();
+        assert_eq!(file.payload_size(), 60);
+        assert_eq!(file.page_count().unwrap(), 0);
+        assert!(matches!(
+            file.read_page(0),
+            Err(FileSystemError::InvalidOperation)
+        ));
+
+        // Extended pages read back as zeroes
+        assert_eq!(file.extend(2).unwrap(), 0);
+        assert_eq!(file.page_count().unwrap(), 2);
+        assert!(file.read_page(1).unwrap().data().iter().all(|b| *b == 0));
+
+        // Writing the next page appends it
+        let mut page = file.new_page();
+        page.data_mut()[..5].copy_from_slice(b"Hello");
+        file.write_page(2, &page).unwrap();
+        assert_eq!(file.page_count().unwrap(), 3);
+        assert_eq!(file.read_page(2).unwrap(), page);
+        assert!(matches!(
+            file.write_page(4, &page),
+            Err(FileSystemError::InvalidOperation)
+        ));
+        assert!(file.write_page(0, &vec![0; 10].into()).is_err());
+
+        // Corruption is caught by the checksum
+        let mut handle = file.into_inner();
+        handle.write_to_offset(2 * 64 + 1, b"J").unwrap();
+        let mut file = PagedFile::new(handle, 64).unwrap();
+        assert!(matches!(
+            file.read_page(2),
+            Err(FileSystemError::CorruptData { offset: 128, .. })
+        ));
+
+        file.truncate_pages(1).unwrap();
+        assert_eq!(file.page_count().unwrap(), 1);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_paged_file_lsn() {
+        let fs = MemoryFileSystem::new();
+        let handle = fs.create_file("/pages.dat").unwrap();
+        assert!(PagedFile::new(handle.clone(), 20)
+            .unwrap()
+            .with_page_lsn()
+            .is_err());
+        let mut file = PagedFile::new(handle, 64).unwrap().with_page_lsn().unwrap();
+        assert_eq!(file.payload_size(), 44);
+
+        file.extend(1).unwrap();
+        assert_eq!(file.read_page(0).unwrap().lsn(), Lsn::default());
+        let mut page = file.new_page();
+        page.data_mut()[..5].copy_from_slice(b"Hello");
+        let lsn = Lsn {
+            segment: 3,
+            offset: 1024,
+        };
+        page.set_lsn(lsn);
+        file.write_page(0, &page).unwrap();
+        let read = file.read_page(0).unwrap();
+        assert_eq!(read.lsn(), lsn);
+        assert_eq!(read, page);
+
+        // The header is covered by the checksum
+        let mut handle = file.into_inner();
+        handle.write_to_offset(0, &[4]).unwrap();
+        let mut file = PagedFile::new(handle, 64).unwrap().with_page_lsn().unwrap();
+        assert!(matches!(
+            file.read_page(0),
+            Err(FileSystemError::CorruptData { offset: 0, .. })
+        ));
+    }
+}
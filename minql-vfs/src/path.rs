@@ -0,0 +1,267 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::utility::{join_segments, normalize_segments};
+use crate::{FileSystemError, FileSystemResult};
+use minql_uri::{Path, PathBuilder};
+use std::fmt::Write;
+
+/// Normalized, absolute path within a `FileSystem`.
+///
+/// Both `/` and `\` are accepted as separators, empty and `.` segments are dropped and `..`
+/// removes the preceding segment, so a `VfsPath` is always `/` separated, starts at the root
+/// and can never climb above it. Dereferences to `str`, so it can be passed straight to any
+/// [`FileSystem`](crate::FileSystem) method.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, VfsPath};
+///
+/// let data = VfsPath::new(r"data\.\tables").unwrap();
+/// assert_eq!(data.as_str(), "/data/tables");
+///
+/// let table = data.join("users.tbl").unwrap();
+/// assert_eq!(table.file_name(), Some("users.tbl"));
+/// assert_eq!(table.extension(), Some("tbl"));
+/// assert_eq!(table.parent(), Some(data.clone()));
+/// assert!(data.join("../../..").is_err());
+///
+/// let fs = MemoryFileSystem::new();
+/// fs.create_directory_all(&data).unwrap();
+/// fs.write(&table, b"rows").unwrap();
+/// assert_eq!(fs.read(&table).unwrap(), b"rows");
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct VfsPath {
+    path: String,
+}
+
+impl VfsPath {
+    /// Path of the root directory.
+    #[must_use]
+    pub fn root() -> VfsPath {
+        VfsPath {
+            path: String::from("/"),
+        }
+    }
+
+    /// Normalize a path, resolving it from the root even if it doesn't start with a separator.
+    ///
+    /// Fails with [`FileSystemError::InvalidPath`] if a `..` would climb above the root.
+    pub fn new(path: &str) -> FileSystemResult<VfsPath> {
+        Ok(VfsPath {
+            path: join_segments(&normalize_segments(path)?),
+        })
+    }
+
+    /// Convert the path of a URI, decoding its percent-encoded segments.
+    ///
+    /// Fails with [`FileSystemError::InvalidPath`] if a decoded segment holds a separator or a
+    /// `..` would climb above the root.
+    pub fn from_uri_path(path: &Path<'_>) -> FileSystemResult<VfsPath> {
+        let segments = path.builder().segments();
+        if segments.iter().any(|segment| segment.contains(['/', '\\'])) {
+            return Err(FileSystemError::invalid_path(&path.to_string()));
+        }
+        VfsPath::new(&segments.join("/"))
+    }
+
+    /// Convert into the absolute path of a URI, percent-encoding each segment.
+    #[must_use]
+    pub fn to_uri_path(&self) -> PathBuilder {
+        let segments = self
+            .segments()
+            .map(|segment| {
+                let mut encoded = String::with_capacity(segment.len());
+                for byte in segment.bytes() {
+                    match byte {
+                        b'0'..=b'9' | b'A'..=b'Z' | b'a'..=b'z' | b'-' | b'.' | b'_' | b'~' => {
+                            encoded.push(char::from(byte));
+                        }
+                        byte => {
+                            let _ = write!(encoded, "%{byte:02X}");
+                        }
+                    }
+                }
+                encoded
+            })
+            .collect();
+        PathBuilder::Absolute { segments }
+    }
+
+    /// The path as a `/` separated string starting with `/`.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    /// Whether this is the root directory.
+    #[must_use]
+    pub fn is_root(&self) -> bool {
+        self.path == "/"
+    }
+
+    /// Names of the entries along the path, from the root down.
+    #[must_use]
+    pub fn segments(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.path.split('/').filter(|segment| !segment.is_empty())
+    }
+
+    /// Resolve `path` relative to this one, even if it starts with a separator.
+    ///
+    /// `..` segments may climb back up towards the root, but fail with
+    /// [`FileSystemError::InvalidPath`] if they would climb above it.
+    pub fn join(&self, path: &str) -> FileSystemResult<VfsPath> {
+        VfsPath::new(&format!("{}/{path}", self.path))
+    }
+
+    /// Path of the directory holding this entry, or `None` for the root.
+    #[must_use]
+    pub fn parent(&self) -> Option<VfsPath> {
+        let (parent, _) = self.path.rsplit_once('/').filter(|_| !self.is_root())?;
+        Some(if parent.is_empty() {
+            VfsPath::root()
+        } else {
+            VfsPath {
+                path: parent.to_string(),
+            }
+        })
+    }
+
+    /// Name of the last segment, or `None` for the root.
+    #[must_use]
+    pub fn file_name(&self) -> Option<&str> {
+        self.segments().next_back()
+    }
+
+    /// Extension of the last segment, without the `.`, if it has one after a non-empty stem.
+    #[must_use]
+    pub fn extension(&self) -> Option<&str> {
+        let (stem, extension) = self.file_name()?.rsplit_once('.')?;
+        (!stem.is_empty()).then_some(extension)
+    }
+
+    /// Whether `base` is this path or one of its ancestors, comparing whole segments.
+    #[must_use]
+    pub fn starts_with(&self, base: &VfsPath) -> bool {
+        base.is_root()
+            || self.path == base.path
+            || self
+                .path
+                .strip_prefix(&base.path)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+impl Default for VfsPath {
+    fn default() -> Self {
+        VfsPath::root()
+    }
+}
+
+impl std::fmt::Display for VfsPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+impl std::ops::Deref for VfsPath {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.path
+    }
+}
+
+impl AsRef<str> for VfsPath {
+    fn as_ref(&self) -> &str {
+        &self.path
+    }
+}
+
+impl std::str::FromStr for VfsPath {
+    type Err = FileSystemError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        VfsPath::new(path)
+    }
+}
+
+impl TryFrom<&str> for VfsPath {
+    type Error = FileSystemError;
+
+    fn try_from(path: &str) -> Result<Self, Self::Error> {
+        VfsPath::new(path)
+    }
+}
+
+impl From<VfsPath> for String {
+    fn from(path: VfsPath) -> String {
+        path.path
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_vfs_path() {
+        use crate::{FileSystemError, VfsPath};
+        use minql_uri::Path;
+
+        assert_eq!(VfsPath::new("").unwrap(), VfsPath::root());
+        assert_eq!(VfsPath::new("//a/./b//").unwrap().as_str(), "/a/b");
+        assert_eq!(VfsPath::new("a/b/../c").unwrap().as_str(), "/a/c");
+        assert!(matches!(
+            VfsPath::new("/a/../.."),
+            Err(FileSystemError::InvalidPath(_))
+        ));
+
+        let root = VfsPath::root();
+        assert!(root.is_root());
+        assert_eq!(root.parent(), None);
+        assert_eq!(root.file_name(), None);
+        assert_eq!(root.segments().count(), 0);
+
+        let path = root.join("logs").unwrap().join(r"2024\wal.log").unwrap();
+        assert_eq!(path.to_string(), "/logs/2024/wal.log");
+        assert_eq!(
+            path.segments().collect::<Vec<_>>(),
+            ["logs", "2024", "wal.log"]
+        );
+        assert_eq!(path.parent().unwrap().as_str(), "/logs/2024");
+        assert_eq!(
+            path.parent().unwrap().parent().unwrap().parent(),
+            Some(root.clone())
+        );
+        assert_eq!(path.join("..").unwrap().as_str(), "/logs/2024");
+        assert_eq!(VfsPath::new("/.profile").unwrap().extension(), None);
+        assert_eq!(VfsPath::new("/a.tar.gz").unwrap().extension(), Some("gz"));
+
+        let logs: VfsPath = "/logs".parse().unwrap();
+        assert!(path.starts_with(&logs));
+        assert!(path.starts_with(&root));
+        assert!(logs.starts_with(&logs));
+        assert!(!VfsPath::new("/logsheet").unwrap().starts_with(&logs));
+
+        let named = VfsPath::new("/my data/naïve.txt").unwrap();
+        let uri_path = named.to_uri_path();
+        assert_eq!(uri_path.segments(), ["my data", "naïve.txt"]);
+        let parsed = Path::parse("/my%20data/na%C3%AFve.txt").unwrap();
+        assert_eq!(VfsPath::from_uri_path(&parsed).unwrap(), named);
+        let escaped = Path::parse("/a%2F..%2F..").unwrap();
+        assert!(VfsPath::from_uri_path(&escaped).is_err());
+    }
+}
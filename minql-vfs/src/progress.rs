@@ -0,0 +1,111 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::io::Read;
+
+/// Running totals of a bulk operation, reported to a [`ProgressObserver`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Progress {
+    /// Number of entries processed
+    pub entries: u64,
+    /// Number of bytes of file contents processed
+    pub bytes: u64,
+}
+
+/// Observer of the progress of a long-running operation, like copying, synchronizing, removing
+/// or archiving a tree.
+///
+/// Implemented for closures taking the path being processed and the totals so far.
+pub trait ProgressObserver {
+    /// Report the totals after an entry completes, and while a large file is in flight.
+    fn progress(&mut self, path: &str, progress: Progress);
+}
+
+impl<F: FnMut(&str, Progress)> ProgressObserver for F {
+    fn progress(&mut self, path: &str, progress: Progress) {
+        self(path, progress);
+    }
+}
+
+/// Observer discarding all progress, used by the variants of operations without one.
+pub(crate) struct NoProgress;
+
+impl ProgressObserver for NoProgress {
+    fn progress(&mut self, path: &str, progress: Progress) {}
+}
+
+/// Totals of an operation in flight along with their observer.
+pub(crate) struct ProgressTracker<'a> {
+    progress: Progress,
+    observer: &'a mut dyn ProgressObserver,
+}
+
+impl<'a> ProgressTracker<'a> {
+    /// Start tracking an operation.
+    pub(crate) fn new(observer: &'a mut dyn ProgressObserver) -> ProgressTracker<'a> {
+        ProgressTracker {
+            progress: Progress::default(),
+            observer,
+        }
+    }
+
+    /// Count a completed entry.
+    pub(crate) fn entry(&mut self, path: &str) {
+        self.progress.entries += 1;
+        self.observer.progress(path, self.progress);
+    }
+
+    /// Count bytes of an entry in flight.
+    pub(crate) fn bytes(&mut self, path: &str, bytes: u64) {
+        self.progress.bytes += bytes;
+        self.observer.progress(path, self.progress);
+    }
+
+    /// Wrap a reader of the contents of `path`, counting bytes as they're read.
+    pub(crate) fn reader<'t, R: Read>(
+        &'t mut self,
+        path: &'t str,
+        inner: R,
+    ) -> ProgressReader<'t, 'a, R> {
+        ProgressReader {
+            inner,
+            path,
+            tracker: self,
+        }
+    }
+
+    /// Totals so far.
+    pub(crate) fn progress(&self) -> Progress {
+        self.progress
+    }
+}
+
+/// Reader counting the bytes read through it towards a [`ProgressTracker`].
+pub(crate) struct ProgressReader<'t, 'a, R: Read> {
+    inner: R,
+    path: &'t str,
+    tracker: &'t mut ProgressTracker<'a>,
+}
+
+impl<R: Read> Read for ProgressReader<'_, '_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            self.tracker.bytes(self.path, read as u64);
+        }
+        Ok(read)
+    }
+}
@@ -22,7 +22,7 @@ pub type FileSystemResult<T> = Result<T, FileSystemError>;
 /// Error Type for VFS Library
 #[derive(Debug)]
 pub enum FileSystemError {
-    /// Path is not valid in this FileSystem
+    /// Path is not valid in this `FileSystem`
     InvalidPath(String),
     /// Attempt to create an object that already exists.
     PathExists,
@@ -38,18 +38,29 @@ pub enum FileSystemError {
     AlreadyLocked,
     /// Operation Not supported on Path
     InvalidOperation,
+    /// Operation would exceed a storage quota
+    QuotaExceeded,
+    /// Operation didn't complete in time
+    TimedOut,
     /// Virtual File System doesn't support an operation.
     UnsupportedOperation,
-    /// FileSystemError Error
+    /// `FileSystemError` Error
     InternalError(String),
-    /// Unknown FileSystem Protocol Scheme
+    /// Stored data failed integrity verification
+    CorruptData {
+        /// Path of the corrupt file
+        path: String,
+        /// Offset of the first corrupt byte range within the file
+        offset: u64,
+    },
+    /// Unknown `FileSystem` Protocol Scheme
     UnknownFileSystem,
     /// IO Error
     IOError(std::io::Error),
     /// Parsing Error
     ParsingError(URIError),
     /// Wrapped Error
-    WrappedError(Box<dyn std::error::Error>),
+    WrappedError(Box<dyn std::error::Error + Send + Sync>),
 }
 
 impl FileSystemError {
@@ -73,7 +84,7 @@ impl FileSystemError {
 
     /// Create a new Wrapper Error from an Error
     #[must_use]
-    pub fn wrap_error<E: std::error::Error + 'static>(err: E) -> FileSystemError {
+    pub fn wrap_error<E: std::error::Error + Send + Sync + 'static>(err: E) -> FileSystemError {
         FileSystemError::WrappedError(Box::new(err))
     }
 }
@@ -91,3 +102,33 @@ impl From<URIError> for FileSystemError {
         FileSystemError::ParsingError(err)
     }
 }
+
+impl From<FileSystemError> for std::io::Error {
+    fn from(err: FileSystemError) -> Self {
+        match err {
+            FileSystemError::IOError(err) => err,
+            FileSystemError::PathMissing | FileSystemError::ParentMissing => {
+                std::io::Error::new(std::io::ErrorKind::NotFound, err.to_string())
+            }
+            FileSystemError::PathExists => {
+                std::io::Error::new(std::io::ErrorKind::AlreadyExists, err.to_string())
+            }
+            FileSystemError::PermissionDenied => {
+                std::io::Error::new(std::io::ErrorKind::PermissionDenied, err.to_string())
+            }
+            FileSystemError::UnsupportedOperation => {
+                std::io::Error::new(std::io::ErrorKind::Unsupported, err.to_string())
+            }
+            FileSystemError::QuotaExceeded => {
+                std::io::Error::new(std::io::ErrorKind::QuotaExceeded, err.to_string())
+            }
+            FileSystemError::TimedOut => {
+                std::io::Error::new(std::io::ErrorKind::TimedOut, err.to_string())
+            }
+            FileSystemError::CorruptData { .. } => {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string())
+            }
+            err => std::io::Error::other(err.to_string()),
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{FileHandle, FileSystem, FileSystemError, FileSystemResult, OpenOptions};
+use std::time::{Duration, Instant};
+
+/// Position of data written by a [`SegmentedWriter`], ordered by position in the sequence.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct SegmentPosition {
+    /// Segment holding the data
+    pub segment: u64,
+    /// Byte offset of the data within its segment
+    pub offset: u64,
+}
+
+/// Configuration of a [`SegmentedWriter`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SegmentOptions {
+    max_size: u64,
+    max_age: Option<Duration>,
+    extension: String,
+}
+
+impl SegmentOptions {
+    /// Default size at which segments are rotated.
+    pub const DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+    /// Create the default options, rotating at 64 MiB with no age limit and naming segments
+    /// with a `.seg` extension.
+    #[must_use]
+    pub fn new() -> SegmentOptions {
+        SegmentOptions {
+            max_size: Self::DEFAULT_MAX_SIZE,
+            max_age: None,
+            extension: String::from(".seg"),
+        }
+    }
+
+    /// Rotate to a new segment once a write would take the current one past `max_size` bytes.
+    ///
+    /// A single write larger than `max_size` still goes to one segment of its own.
+    #[must_use]
+    pub fn with_max_size(mut self, max_size: u64) -> SegmentOptions {
+        self.max_size = max_size.max(1);
+        self
+    }
+
+    /// Rotate to a new segment on the first write once the current one is older than
+    /// `max_age`.
+    #[must_use]
+    pub fn with_max_age(mut self, max_age: Duration) -> SegmentOptions {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Name segment files with this extension, such as `.log`.
+    #[must_use]
+    pub fn with_extension(mut self, extension: &str) -> SegmentOptions {
+        self.extension = extension.to_string();
+        self
+    }
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        SegmentOptions::new()
+    }
+}
+
+/// Rotating Segment File Writer
+///
+/// Appends data to numbered segment files within a directory of any [`FileSystem`], moving on
+/// to the next segment once the current one reaches the configured size or age. A single write
+/// never spans two segments, and finished segments are synced before the next is started.
+///
+/// Opening a directory that already holds segments resumes appending at the end of the newest.
+/// Segments are opened, listed and deleted by their number. The age of a resumed segment is
+/// counted from when it was reopened.
+///
+/// ```rust
+/// use minql_vfs::{FileSystem, MemoryFileSystem, SegmentOptions, SegmentedWriter};
+///
+/// let fs = MemoryFileSystem::new();
+/// let options = SegmentOptions::new().with_max_size(8);
+/// let mut writer = SegmentedWriter::open(fs.clone(), "/logs", options.clone()).unwrap();
+/// writer.append(b"first").unwrap();
+/// writer.append(b"second").unwrap();
+/// assert_eq!(writer.segments().unwrap(), vec![0, 1]);
+/// drop(writer);
+///
+/// let writer = SegmentedWriter::open(fs.clone(), "/logs", options).unwrap();
+/// assert_eq!(writer.position().segment, 1);
+/// assert_eq!(fs.read(&writer.segment_path(0)).unwrap(), b"first");
+/// ```
+#[derive(Debug)]
+pub struct SegmentedWriter<F: FileSystem> {
+    fs: F,
+    directory: String,
+    options: SegmentOptions,
+    segment: u64,
+    handle: F::FileHandle,
+    offset: u64,
+    opened: Instant,
+}
+
+impl<F: FileSystem> SegmentedWriter<F> {
+    /// Open or create the segments stored in `directory`, resuming at the end of the newest.
+    pub fn open(
+        fs: F,
+        directory: &str,
+        options: SegmentOptions,
+    ) -> FileSystemResult<SegmentedWriter<F>> {
+        let directory = directory.trim_end_matches('/').to_string();
+        fs.create_directory_all(&directory)?;
+        let segment = list_segments(&fs, &directory, &options.extension)?
+            .last()
+            .copied()
+            .unwrap_or_default();
+        let path = segment_path(&directory, &options.extension, segment);
+        let handle = fs.open_with(
+            &path,
+            OpenOptions::new().read(true).write(true).create(true),
+        )?;
+        let offset = handle.get_size()?;
+        Ok(SegmentedWriter {
+            fs,
+            directory,
+            options,
+            segment,
+            handle,
+            offset,
+            opened: Instant::now(),
+        })
+    }
+
+    /// Append `data` to the current segment, rotating first if it is full or too old, and
+    /// return where it was written.
+    #[tracing::instrument(level = "trace", skip(data))]
+    pub fn append(&mut self, data: &[u8]) -> FileSystemResult<SegmentPosition> {
+        let expired = self
+            .options
+            .max_age
+            .is_some_and(|max_age| self.opened.elapsed() >= max_age);
+        if self.offset > 0 && (self.offset + data.len() as u64 > self.options.max_size || expired) {
+            self.rotate()?;
+        }
+        let mut written = 0;
+        while written < data.len() {
+            match self
+                .handle
+                .write_to_offset(self.offset + written as u64, &data[written..])?
+            {
+                0 => return Err(FileSystemError::InvalidOperation),
+                count => written += count,
+            }
+        }
+        let position = self.position();
+        self.offset += data.len() as u64;
+        Ok(position)
+    }
+
+    /// Sync the current segment and start writing to the next one, returning its number.
+    #[tracing::instrument(level = "trace")]
+    pub fn rotate(&mut self) -> FileSystemResult<u64> {
+        self.handle.sync_data()?;
+        let segment = self.segment + 1;
+        self.handle = self.fs.create_file(&self.segment_path(segment))?;
+        self.segment = segment;
+        self.offset = 0;
+        self.opened = Instant::now();
+        Ok(segment)
+    }
+
+    /// Flush everything appended to the current segment to storage.
+    #[tracing::instrument(level = "trace")]
+    pub fn sync(&mut self) -> FileSystemResult<()> {
+        self.handle.sync_data()
+    }
+
+    /// Position the next append will be written at, unless it rotates.
+    #[must_use]
+    pub fn position(&self) -> SegmentPosition {
+        SegmentPosition {
+            segment: self.segment,
+            offset: self.offset,
+        }
+    }
+
+    /// Path of the file holding a segment.
+    #[must_use]
+    pub fn segment_path(&self, segment: u64) -> String {
+        segment_path(&self.directory, &self.options.extension, segment)
+    }
+
+    /// Numbers of every segment, oldest first.
+    pub fn segments(&self) -> FileSystemResult<Vec<u64>> {
+        list_segments(&self.fs, &self.directory, &self.options.extension)
+    }
+
+    /// Open a segment for reading.
+    pub fn open_segment(&self, segment: u64) -> FileSystemResult<F::FileHandle> {
+        self.fs
+            .open_with(&self.segment_path(segment), OpenOptions::new().read(true))
+    }
+
+    /// Delete a segment, failing with [`FileSystemError::InvalidOperation`] for the one being
+    /// written.
+    #[tracing::instrument(level = "trace")]
+    pub fn delete_segment(&mut self, segment: u64) -> FileSystemResult<()> {
+        if segment == self.segment {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        self.fs.remove_file(&self.segment_path(segment))
+    }
+
+    /// Delete every segment older than `segment`, never including the one being written.
+    #[tracing::instrument(level = "trace")]
+    pub fn delete_segments_before(&mut self, segment: u64) -> FileSystemResult<()> {
+        for existing in self.segments()? {
+            if existing >= segment.min(self.segment) {
+                break;
+            }
+            self.fs.remove_file(&self.segment_path(existing))?;
+        }
+        Ok(())
+    }
+}
+
+fn segment_path(directory: &str, extension: &str, segment: u64) -> String {
+    format!("{directory}/{segment:020}{extension}")
+}
+
+fn list_segments<F: FileSystem>(
+    fs: &F,
+    directory: &str,
+    extension: &str,
+) -> FileSystemResult<Vec<u64>> {
+    let mut segments = fs
+        .list_directory(directory)?
+        .iter()
+        .filter_map(|name| name.strip_suffix(extension)?.parse().ok())
+        .collect::<Vec<u64>>();
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        FileSystem, FileSystemError, MemoryFileSystem, SegmentOptions, SegmentPosition,
+        SegmentedWriter,
+    };
+    use std::io::Read;
+    use std::time::Duration;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_segment_rotation() {
+        let fs = MemoryFileSystem::new();
+        let options = SegmentOptions::new()
+            .with_max_size(10)
+            .with_extension(".log");
+        let mut writer = SegmentedWriter::open(fs.clone(), "/data/logs/", options.clone()).unwrap();
+        let positions = (0..5u8)
+            .map(|i| writer.append(&[i; 4]).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions[3],
+            SegmentPosition {
+                segment: 1,
+                offset: 4
+            }
+        );
+        // Oversized writes get a segment of their own
+        assert_eq!(writer.append(&[9; 32]).unwrap().segment, 3);
+        assert_eq!(writer.segments().unwrap(), vec![0, 1, 2, 3]);
+        assert!(fs.exists("/data/logs/00000000000000000001.log").unwrap());
+
+        let mut contents = Vec::new();
+        writer
+            .open_segment(1)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, [[2; 4], [3; 4]].concat());
+
+        assert_eq!(writer.rotate().unwrap(), 4);
+        drop(writer);
+
+        // Reopening resumes at the end of the newest segment
+        let mut writer = SegmentedWriter::open(fs.clone(), "/data/logs", options).unwrap();
+        assert_eq!(
+            writer.position(),
+            SegmentPosition {
+                segment: 4,
+                offset: 0
+            }
+        );
+        writer.append(b"resumed").unwrap();
+        assert_eq!(writer.position().offset, 7);
+
+        writer.delete_segment(0).unwrap();
+        assert!(matches!(
+            writer.delete_segment(4),
+            Err(FileSystemError::InvalidOperation)
+        ));
+        writer.delete_segments_before(u64::MAX).unwrap();
+        assert_eq!(writer.segments().unwrap(), vec![4]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_segment_age() {
+        let fs = MemoryFileSystem::new();
+        let options = SegmentOptions::new().with_max_age(Duration::ZERO);
+        let mut writer = SegmentedWriter::open(fs, "/logs", options).unwrap();
+        writer.append(b"one").unwrap();
+        writer.append(b"two").unwrap();
+        assert_eq!(writer.segments().unwrap(), vec![0, 1]);
+    }
+}
@@ -0,0 +1,468 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::Clock;
+use std::cell::Cell;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, SystemTime};
+
+thread_local! {
+    /// Simulated task running on this thread, if any.
+    static CURRENT_TASK: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Small deterministic pseudo-random generator (`SplitMix64`) driving simulation choices.
+#[derive(Clone, Debug)]
+pub struct SimRng(u64);
+
+impl SimRng {
+    /// Create a generator from a seed.
+    #[must_use]
+    pub fn new(seed: u64) -> SimRng {
+        SimRng(seed)
+    }
+
+    /// Next pseudo-random value.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Pseudo-random value in `0..bound`, or zero if `bound` is zero.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        if bound == 0 {
+            0
+        } else {
+            self.next_u64() % bound
+        }
+    }
+}
+
+/// Simulated cost of operations against a backend.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct LatencyModel {
+    operation: Duration,
+    per_kib: Duration,
+    sync: Duration,
+    jitter: Duration,
+}
+
+impl LatencyModel {
+    /// Create a model where every operation is free.
+    #[must_use]
+    pub fn new() -> LatencyModel {
+        LatencyModel::default()
+    }
+
+    /// Fixed cost of every operation.
+    #[must_use]
+    pub fn with_operation(mut self, latency: Duration) -> LatencyModel {
+        self.operation = latency;
+        self
+    }
+
+    /// Additional cost per KiB read or written.
+    #[must_use]
+    pub fn with_per_kib(mut self, latency: Duration) -> LatencyModel {
+        self.per_kib = latency;
+        self
+    }
+
+    /// Additional cost of syncing a file.
+    #[must_use]
+    pub fn with_sync(mut self, latency: Duration) -> LatencyModel {
+        self.sync = latency;
+        self
+    }
+
+    /// Maximum random latency added to every operation.
+    #[must_use]
+    pub fn with_jitter(mut self, jitter: Duration) -> LatencyModel {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sample the latency of an operation transferring `bytes`.
+    pub fn sample(&self, rng: &mut SimRng, bytes: usize, sync: bool) -> Duration {
+        let per_byte = self.per_kib.as_nanos() * bytes as u128 / 1024;
+        let mut latency = self.operation
+            + Duration::from_nanos(u64::try_from(per_byte).unwrap_or(u64::MAX))
+            + Duration::from_nanos(rng.below(u64::try_from(self.jitter.as_nanos()).unwrap_or(0)));
+        if sync {
+            latency += self.sync;
+        }
+        latency
+    }
+}
+
+/// Task queued by [`Simulation::spawn`].
+type SimTaskFn = Box<dyn FnOnce() + Send>;
+
+/// Operation recorded by a [`Simulation`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SimEvent {
+    /// Virtual time the operation started at
+    pub time: Duration,
+    /// Task performing the operation, or `None` outside of [`Simulation::run`]
+    pub task: Option<usize>,
+    /// Description of the operation
+    pub operation: String,
+}
+
+#[derive(Debug)]
+struct SimTask {
+    wake: Duration,
+    done: bool,
+}
+
+#[derive(Debug)]
+struct SimState {
+    now: Duration,
+    rng: SimRng,
+    running: Option<usize>,
+    tasks: Vec<SimTask>,
+    events: Vec<SimEvent>,
+}
+
+impl SimState {
+    /// Choose the next task to run: the earliest to wake, with ties broken by the seeded rng.
+    fn schedule(&mut self) {
+        let wake = self
+            .tasks
+            .iter()
+            .filter(|task| !task.done)
+            .map(|task| task.wake)
+            .min();
+        self.running = wake.map(|wake| {
+            let ready = (0..self.tasks.len())
+                .filter(|id| !self.tasks[*id].done && self.tasks[*id].wake == wake)
+                .collect::<Vec<_>>();
+            self.now = self.now.max(wake);
+            ready[usize::try_from(self.rng.below(ready.len() as u64)).expect("Task Index")]
+        });
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct SimShared {
+    state: Mutex<SimState>,
+    turn: Condvar,
+}
+
+impl SimShared {
+    /// Block until `task` is scheduled to run.
+    fn wait_turn(&self, task: usize) {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        while state.running != Some(task) {
+            state = self.turn.wait(state).expect("Poisoned Lock");
+        }
+    }
+
+    /// Record an operation and charge its latency to the virtual clock.
+    ///
+    /// Inside a running simulation this is a scheduling point: the calling task sleeps until the
+    /// operation completes in virtual time, letting any task that wakes earlier run first.
+    pub(crate) fn advance(&self, operation: &str, latency: impl FnOnce(&mut SimRng) -> Duration) {
+        let mut state = self.state.lock().expect("Poisoned Lock");
+        let latency = latency(&mut state.rng);
+        let task = CURRENT_TASK
+            .get()
+            .filter(|task| state.running == Some(*task));
+        let event = SimEvent {
+            time: state.now,
+            task,
+            operation: operation.to_string(),
+        };
+        state.events.push(event);
+        match task {
+            None => state.now += latency,
+            Some(task) => {
+                state.tasks[task].wake = state.now + latency;
+                state.schedule();
+                self.turn.notify_all();
+                while state.running != Some(task) {
+                    state = self.turn.wait(state).expect("Poisoned Lock");
+                }
+            }
+        }
+    }
+}
+
+/// Marks a task finished and schedules the next when dropped, even while panicking.
+struct TaskGuard<'a> {
+    shared: &'a SimShared,
+    task: usize,
+}
+
+impl Drop for TaskGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().expect("Poisoned Lock");
+        state.tasks[self.task].done = true;
+        state.schedule();
+        self.shared.turn.notify_all();
+        CURRENT_TASK.set(None);
+    }
+}
+
+/// Deterministic Simulation
+///
+/// Runs concurrent tasks against simulated backends with a virtual clock, so multi-threaded
+/// workloads replay identically for a given seed. Each task runs on its own thread, but only one
+/// ever runs at a time: every operation through a
+/// [`SimulatedFileSystem`](crate::SimulatedFileSystem) advances that task's virtual time by the
+/// latency of its backend and hands control to whichever task wakes next, with ties broken by
+/// the seeded generator.
+///
+/// Tasks must not block on one another outside of simulated operations, for example on a
+/// blocking file lock, as the task holding it will never be scheduled.
+///
+/// ```rust
+/// use minql_vfs::{
+///     FileSystem, LatencyModel, MemoryFileSystem, SimulatedFileSystem, Simulation,
+/// };
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let mut sim = Simulation::new(42);
+/// let latency = LatencyModel::new().with_operation(Duration::from_millis(1));
+/// let fs = Arc::new(SimulatedFileSystem::new(MemoryFileSystem::new(), &sim, latency));
+/// for task in 0..4 {
+///     let fs = fs.clone();
+///     sim.spawn(move || {
+///         fs.create_file(&format!("/task-{task}.dat")).unwrap();
+///     });
+/// }
+/// sim.run();
+/// assert_eq!(sim.now(), Duration::from_millis(1));
+/// ```
+#[derive(Clone)]
+pub struct Simulation {
+    shared: Arc<SimShared>,
+    pending: Arc<Mutex<Vec<SimTaskFn>>>,
+}
+
+impl Clock for Simulation {
+    /// Virtual time as an offset from the Unix epoch.
+    fn now(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Simulation::now(self)
+    }
+}
+
+impl std::fmt::Debug for Simulation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Simulation")
+            .field("now", &self.now())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Simulation {
+    /// Create a new Simulation whose scheduling and jitter are driven by `seed`.
+    #[must_use]
+    pub fn new(seed: u64) -> Simulation {
+        Simulation {
+            shared: Arc::new(SimShared {
+                state: Mutex::new(SimState {
+                    now: Duration::ZERO,
+                    rng: SimRng::new(seed),
+                    running: None,
+                    tasks: Vec::new(),
+                    events: Vec::new(),
+                }),
+                turn: Condvar::new(),
+            }),
+            pending: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Current virtual time.
+    #[must_use]
+    pub fn now(&self) -> Duration {
+        self.shared.state.lock().expect("Poisoned Lock").now
+    }
+
+    /// Sleep the current task for `duration` of virtual time.
+    pub fn sleep(&self, duration: Duration) {
+        self.shared.advance("sleep", |_| duration);
+    }
+
+    /// Every operation recorded so far, in the order they were issued.
+    #[must_use]
+    pub fn events(&self) -> Vec<SimEvent> {
+        self.shared
+            .state
+            .lock()
+            .expect("Poisoned Lock")
+            .events
+            .clone()
+    }
+
+    /// Queue a task to run on the next call to [`Simulation::run`].
+    pub fn spawn<T: FnOnce() + Send + 'static>(&self, task: T) {
+        self.pending
+            .lock()
+            .expect("Poisoned Lock")
+            .push(Box::new(task));
+    }
+
+    /// Run every queued task to completion, resuming the first panic raised by any of them.
+    pub fn run(&self) {
+        let tasks = std::mem::take(&mut *self.pending.lock().expect("Poisoned Lock"));
+        let first = {
+            let mut state = self.shared.state.lock().expect("Poisoned Lock");
+            let first = state.tasks.len();
+            let now = state.now;
+            state.tasks.extend(tasks.iter().map(|_| SimTask {
+                wake: now,
+                done: false,
+            }));
+            first
+        };
+        let threads = tasks
+            .into_iter()
+            .enumerate()
+            .map(|(index, task)| {
+                let shared = self.shared.clone();
+                std::thread::spawn(move || {
+                    let id = first + index;
+                    shared.wait_turn(id);
+                    CURRENT_TASK.set(Some(id));
+                    let _guard = TaskGuard {
+                        shared: &shared,
+                        task: id,
+                    };
+                    task();
+                })
+            })
+            .collect::<Vec<_>>();
+        {
+            let mut state = self.shared.state.lock().expect("Poisoned Lock");
+            state.schedule();
+            self.shared.turn.notify_all();
+        }
+        let mut panic = None;
+        for thread in threads {
+            if let Err(err) = thread.join() {
+                panic.get_or_insert(err);
+            }
+        }
+        if let Some(panic) = panic {
+            std::panic::resume_unwind(panic);
+        }
+    }
+
+    pub(crate) fn shared(&self) -> Arc<SimShared> {
+        self.shared.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BatchOperation, FileSystem, LatencyModel, MemoryFileSystem};
+    use crate::{SimulatedFileSystem, Simulation};
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn workload(seed: u64) -> (Duration, Vec<(Option<usize>, String)>) {
+        let sim = Simulation::new(seed);
+        let latency = LatencyModel::new()
+            .with_operation(Duration::from_micros(100))
+            .with_per_kib(Duration::from_micros(10))
+            .with_jitter(Duration::from_micros(50));
+        let fs = Arc::new(SimulatedFileSystem::new(
+            MemoryFileSystem::new(),
+            &sim,
+            latency,
+        ));
+        for task in 0..4 {
+            let fs = fs.clone();
+            sim.spawn(move || {
+                let mut file = fs.create_file(&format!("/task-{task}.dat")).unwrap();
+                for _ in 0..5 {
+                    file.write_all(&[0; 512]).unwrap();
+                }
+            });
+        }
+        sim.run();
+        let order = sim
+            .events()
+            .into_iter()
+            .map(|event| (event.task, event.operation))
+            .collect();
+        (sim.now(), order)
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_simulation_deterministic() {
+        let (time, events) = workload(7);
+        assert_eq!(events.len(), 24);
+        assert!(time >= Duration::from_micros(600));
+        assert_eq!(workload(7), (time, events.clone()));
+        assert_ne!(workload(8).1, events);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_simulation_virtual_time() {
+        let sim = Simulation::new(1);
+        let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+        for (task, delay) in [(0, 30), (1, 10), (2, 20)] {
+            let (inner, log) = (sim.clone(), log.clone());
+            sim.spawn(move || {
+                inner.sleep(Duration::from_secs(delay));
+                log.lock().unwrap().push(task);
+            });
+        }
+        sim.run();
+        assert_eq!(*log.lock().unwrap(), vec![1, 2, 0]);
+        assert_eq!(sim.now(), Duration::from_secs(30));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_simulation_batch() {
+        let sim = Simulation::new(5);
+        let latency = LatencyModel::new()
+            .with_operation(Duration::from_millis(1))
+            .with_per_kib(Duration::from_millis(1));
+        let fs = SimulatedFileSystem::new(MemoryFileSystem::new(), &sim, latency);
+        let results = fs.apply(&[
+            BatchOperation::create_directory("/data"),
+            BatchOperation::write("/data/a", &[0; 1024]),
+            BatchOperation::write("/data/b", &[0; 1024]),
+        ]);
+        assert!(results.iter().all(Result::is_ok));
+        // The batch is a single operation, charged for everything it wrote
+        assert_eq!(sim.events().len(), 1);
+        assert_eq!(sim.now(), Duration::from_millis(3));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_simulation_conformance() {
+        let sim = Simulation::new(3);
+        let latency = LatencyModel::new().with_operation(Duration::from_micros(100));
+        crate::conformance::run(|| {
+            SimulatedFileSystem::new(MemoryFileSystem::new(), &sim, latency)
+        });
+        assert!(sim.now() > Duration::ZERO);
+    }
+}
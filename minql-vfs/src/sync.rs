@@ -0,0 +1,607 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::bulk::{copy_contents, run_parallel};
+use crate::progress::{NoProgress, ProgressTracker};
+use crate::utility::{child_path, glob_segments, match_segments, normalize_segments};
+use crate::{
+    BulkReport, FileHandle, FileSystem, FileSystemError, FileSystemResult, HashAlgorithm,
+    OpenOptions, ParallelOptions, ProgressObserver,
+};
+use std::collections::HashSet;
+
+/// How [`sync`] decides whether a file present on both sides needs copying.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum SyncCompare {
+    /// Copy when the sizes differ
+    Size,
+    /// Copy when the sizes differ or the source was modified after the destination
+    #[default]
+    Modified,
+    /// Copy when the contents differ
+    Hash,
+}
+
+/// Options of a [`sync`].
+#[derive(Clone, Debug, Default)]
+pub struct SyncOptions {
+    compare: SyncCompare,
+    delete: bool,
+    dry_run: bool,
+    include: Vec<Vec<String>>,
+    exclude: Vec<Vec<String>>,
+}
+
+impl SyncOptions {
+    /// Create options comparing by size and modification time, without deletion or filters.
+    #[must_use]
+    pub fn new() -> SyncOptions {
+        SyncOptions::default()
+    }
+
+    /// Set how files present on both sides are compared.
+    #[must_use]
+    pub fn with_compare(mut self, compare: SyncCompare) -> SyncOptions {
+        self.compare = compare;
+        self
+    }
+
+    /// Set whether entries missing from the source are removed from the destination.
+    #[must_use]
+    pub fn with_delete(mut self, delete: bool) -> SyncOptions {
+        self.delete = delete;
+        self
+    }
+
+    /// Set whether to only plan the synchronization, leaving the destination untouched.
+    #[must_use]
+    pub fn with_dry_run(mut self, dry_run: bool) -> SyncOptions {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Only synchronize paths matching `pattern`. Without any include patterns every path is
+    /// included.
+    ///
+    /// Patterns are matched a path segment at a time, where `*` matches any run of characters
+    /// and `?` any single character within a segment, and a `**` segment matches any number of
+    /// segments, including none.
+    #[must_use]
+    pub fn include(mut self, pattern: &str) -> SyncOptions {
+        self.include.push(glob_segments(pattern));
+        self
+    }
+
+    /// Skip paths matching `pattern`, along with everything below them, on both sides.
+    #[must_use]
+    pub fn exclude(mut self, pattern: &str) -> SyncOptions {
+        self.exclude.push(glob_segments(pattern));
+        self
+    }
+
+    /// How files present on both sides are compared.
+    #[must_use]
+    pub fn compare(&self) -> SyncCompare {
+        self.compare
+    }
+
+    /// Whether entries missing from the source are removed from the destination.
+    #[must_use]
+    pub fn is_delete(&self) -> bool {
+        self.delete
+    }
+
+    /// Whether only a plan is made.
+    #[must_use]
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Check if `segments` are excluded.
+    fn excluded(&self, segments: &[&str]) -> bool {
+        self.exclude
+            .iter()
+            .any(|pattern| match_segments(pattern, segments))
+    }
+
+    /// Check if `segments` are included, ignoring exclusions.
+    fn included(&self, segments: &[&str]) -> bool {
+        self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| match_segments(pattern, segments))
+    }
+}
+
+/// Step of a [`SyncPlan`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SyncAction {
+    /// Create a directory on the destination
+    CreateDirectory(String),
+    /// Copy a file of the given size from the source, replacing any destination file
+    CopyFile(String, u64),
+    /// Remove a file from the destination
+    RemoveFile(String),
+    /// Remove a directory and everything below it from the destination
+    RemoveDirectory(String),
+}
+
+impl SyncAction {
+    /// Path the action applies to.
+    #[must_use]
+    pub fn path(&self) -> &str {
+        match self {
+            SyncAction::CreateDirectory(path)
+            | SyncAction::CopyFile(path, _)
+            | SyncAction::RemoveFile(path)
+            | SyncAction::RemoveDirectory(path) => path,
+        }
+    }
+}
+
+/// Ordered actions bringing a destination in line with a source, returned by [`sync`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SyncPlan {
+    actions: Vec<SyncAction>,
+}
+
+impl SyncPlan {
+    /// Actions in the order they are applied.
+    #[must_use]
+    pub fn actions(&self) -> &[SyncAction] {
+        &self.actions
+    }
+
+    /// Check if the destination was already in sync.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Total number of bytes copied.
+    #[must_use]
+    pub fn bytes(&self) -> u64 {
+        self.actions
+            .iter()
+            .map(|action| match action {
+                SyncAction::CopyFile(_, size) => *size,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+/// One-way synchronize the `destination` `FileSystem` with the `source`.
+///
+/// Directories and files of the source missing from the destination are created, and files
+/// differing according to [`SyncOptions::compare`] are copied over. When deletion is enabled,
+/// entries of the destination missing from the source are removed. An entry whose type differs
+/// between both sides is always replaced. Excluded paths are left alone on both sides, so they
+/// are neither copied nor deleted.
+///
+/// Returns the plan of actions taken, or in a dry run the actions that would be taken. To
+/// synchronize a subdirectory, wrap both sides in a [`ScopedFileSystem`](crate::ScopedFileSystem).
+///
+/// ```rust
+/// use minql_vfs::{sync, FileSystem, MemoryFileSystem, SyncAction, SyncOptions};
+///
+/// let source = MemoryFileSystem::new();
+/// source.create_directory("/data").unwrap();
+/// source.write("/data/table.db", b"rows").unwrap();
+/// source.write("/data/scratch.tmp", b"junk").unwrap();
+/// let destination = MemoryFileSystem::new();
+/// destination.write("/stale.db", b"old").unwrap();
+///
+/// let options = SyncOptions::new().with_delete(true).exclude("**/*.tmp");
+/// let plan = sync(&source, &destination, &options.clone().with_dry_run(true)).unwrap();
+/// assert_eq!(plan.actions(), [
+///     SyncAction::CreateDirectory("/data".to_string()),
+///     SyncAction::CopyFile("/data/table.db".to_string(), 4),
+///     SyncAction::RemoveFile("/stale.db".to_string()),
+/// ]);
+/// assert!(destination.exists("/stale.db").unwrap());
+///
+/// assert_eq!(sync(&source, &destination, &options).unwrap(), plan);
+/// assert_eq!(destination.read("/data/table.db").unwrap(), b"rows");
+/// assert!(!destination.exists("/stale.db").unwrap());
+/// assert!(sync(&source, &destination, &options).unwrap().is_empty());
+/// ```
+pub fn sync<S: FileSystem, D: FileSystem>(
+    source: &S,
+    destination: &D,
+    options: &SyncOptions,
+) -> FileSystemResult<SyncPlan> {
+    sync_with_progress(source, destination, options, &mut NoProgress)
+}
+
+/// One-way synchronize the `destination` `FileSystem` with the `source` like [`sync`],
+/// reporting the progress of applied actions to `observer`.
+pub fn sync_with_progress<S: FileSystem, D: FileSystem>(
+    source: &S,
+    destination: &D,
+    options: &SyncOptions,
+    observer: &mut dyn ProgressObserver,
+) -> FileSystemResult<SyncPlan> {
+    let mut sync = Sync {
+        source,
+        destination,
+        options,
+        created: HashSet::new(),
+        plan: SyncPlan::default(),
+        tracker: ProgressTracker::new(observer),
+    };
+    sync.directory("/", true)?;
+    Ok(sync.plan)
+}
+
+/// One-way synchronize the `destination` `FileSystem` with the `source` like [`sync`], copying
+/// and removing files on a pool of worker threads.
+///
+/// The plan is made first, as by a dry run. Runs of file copies and removals are then applied
+/// at once, while directories are created and removed in plan order between them. Actions
+/// which fail are reported in the [`BulkReport`] without stopping the others.
+///
+/// Returns the plan along with the report of applying it, which is empty in a dry run.
+pub fn sync_parallel<S: FileSystem, D: FileSystem>(
+    source: &S,
+    destination: &D,
+    options: &SyncOptions,
+    parallel: &ParallelOptions,
+) -> FileSystemResult<(SyncPlan, BulkReport)> {
+    let plan = sync(source, destination, &options.clone().with_dry_run(true))?;
+    let mut report = BulkReport::default();
+    if options.dry_run {
+        return Ok((plan, report));
+    }
+    let files = |action: &SyncAction| {
+        matches!(action, SyncAction::CopyFile(..) | SyncAction::RemoveFile(_))
+    };
+    let mut actions = plan.actions();
+    while let Some(first) = actions.first() {
+        let count = if files(first) {
+            actions.iter().take_while(|action| files(action)).count()
+        } else {
+            1
+        };
+        let (wave, rest) = actions.split_at(count);
+        let results = run_parallel(parallel.workers(), wave, |action| {
+            tracing::debug!(?action, "sync");
+            match action {
+                SyncAction::CreateDirectory(path) => destination.create_directory(path).map(|()| 0),
+                SyncAction::CopyFile(path, _) => copy_contents(source, path, destination, path),
+                SyncAction::RemoveFile(path) => destination.remove_file(path).map(|()| 0),
+                SyncAction::RemoveDirectory(path) => {
+                    destination.remove_directory_all(path).map(|()| 0)
+                }
+            }
+        });
+        for (action, result) in wave.iter().zip(results) {
+            report.record(action.path(), result);
+        }
+        actions = rest;
+    }
+    Ok((plan, report))
+}
+
+/// State of a running [`sync`].
+struct Sync<'a, S: FileSystem, D: FileSystem> {
+    source: &'a S,
+    destination: &'a D,
+    options: &'a SyncOptions,
+    /// Destination directories created by this sync
+    created: HashSet<String>,
+    plan: SyncPlan,
+    tracker: ProgressTracker<'a>,
+}
+
+impl<S: FileSystem, D: FileSystem> Sync<'_, S, D> {
+    /// Synchronize the children of a directory present on the source, which is present on the
+    /// destination unless `exists` is false.
+    fn directory(&mut self, directory: &str, exists: bool) -> FileSystemResult<()> {
+        let mut children = self.source.list_directory(directory)?;
+        children.sort();
+        for name in &children {
+            let path = child_path(directory, name);
+            let segments = normalize_segments(&path)?;
+            if self.options.excluded(&segments) {
+                continue;
+            }
+            let included = self.options.included(&segments);
+            let is_directory = self.source.is_directory(&path)?;
+            let (dst_file, dst_directory) = if exists {
+                (
+                    self.destination.is_file(&path)?,
+                    self.destination.is_directory(&path)?,
+                )
+            } else {
+                (false, false)
+            };
+            if is_directory {
+                if dst_file {
+                    self.apply(SyncAction::RemoveFile(path.clone()))?;
+                }
+                if included && !dst_directory {
+                    self.create_parents(&path)?;
+                }
+                self.directory(&path, dst_directory)?;
+            } else if included {
+                if dst_directory {
+                    self.apply(SyncAction::RemoveDirectory(path.clone()))?;
+                }
+                if !dst_file || self.differs(&path)? {
+                    self.create_parents(directory)?;
+                    let size = self.source.filesize(&path)?;
+                    self.apply(SyncAction::CopyFile(path, size))?;
+                }
+            }
+        }
+        if self.options.delete && exists {
+            let mut extra = self.destination.list_directory(directory)?;
+            extra.retain(|name| !children.contains(name));
+            extra.sort();
+            for name in extra {
+                self.remove(&child_path(directory, &name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove a destination entry missing from the source, unless filtered out. Directories
+    /// are emptied first, and only removed if nothing filtered out remains in them.
+    ///
+    /// Returns whether the entry was removed.
+    fn remove(&mut self, path: &str) -> FileSystemResult<bool> {
+        let segments = normalize_segments(path)?;
+        if self.options.excluded(&segments) {
+            return Ok(false);
+        }
+        if !self.destination.is_directory(path)? {
+            if !self.options.included(&segments) {
+                return Ok(false);
+            }
+            self.apply(SyncAction::RemoveFile(path.to_string()))?;
+            return Ok(true);
+        }
+        let mut children = self.destination.list_directory(path)?;
+        children.sort();
+        let mut empty = true;
+        for name in children {
+            empty &= self.remove(&child_path(path, &name))?;
+        }
+        if empty && self.options.included(&segments) {
+            self.apply(SyncAction::RemoveDirectory(path.to_string()))?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Create a destination directory and any missing parents, unless already present.
+    fn create_parents(&mut self, directory: &str) -> FileSystemResult<()> {
+        let segments = normalize_segments(directory)?;
+        let mut path = String::new();
+        for segment in segments {
+            path.push('/');
+            path.push_str(segment);
+            if self.created.contains(&path) || self.destination.is_directory(&path)? {
+                continue;
+            }
+            self.created.insert(path.clone());
+            self.apply(SyncAction::CreateDirectory(path.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// Check if a file present on both sides differs.
+    fn differs(&self, path: &str) -> FileSystemResult<bool> {
+        if self.source.filesize(path)? != self.destination.filesize(path)? {
+            return Ok(true);
+        }
+        match self.options.compare {
+            SyncCompare::Size => Ok(false),
+            SyncCompare::Modified => {
+                match (self.source.modified(path), self.destination.modified(path)) {
+                    (Ok(source), Ok(destination)) => Ok(source > destination),
+                    (Err(FileSystemError::UnsupportedOperation), _)
+                    | (_, Err(FileSystemError::UnsupportedOperation)) => {
+                        Ok(self.source.hash_file(path, HashAlgorithm::Sha256)?
+                            != self.destination.hash_file(path, HashAlgorithm::Sha256)?)
+                    }
+                    (Err(err), _) | (_, Err(err)) => Err(err),
+                }
+            }
+            SyncCompare::Hash => Ok(self.source.hash_file(path, HashAlgorithm::Sha256)?
+                != self.destination.hash_file(path, HashAlgorithm::Sha256)?),
+        }
+    }
+
+    /// Record an action, and apply it to the destination unless this is a dry run.
+    fn apply(&mut self, action: SyncAction) -> FileSystemResult<()> {
+        tracing::debug!(?action, dry_run = self.options.dry_run, "sync");
+        if !self.options.dry_run {
+            match &action {
+                SyncAction::CreateDirectory(path) => self.destination.create_directory(path)?,
+                SyncAction::CopyFile(path, _) => {
+                    let reader = self.source.open_file(path)?;
+                    let mut writer = self.destination.open_with(
+                        path,
+                        OpenOptions::new().write(true).create(true).truncate(true),
+                    )?;
+                    std::io::copy(&mut self.tracker.reader(path, reader), &mut writer)
+                        .map_err(FileSystemError::io_error)?;
+                    writer.sync_all()?;
+                }
+                SyncAction::RemoveFile(path) => self.destination.remove_file(path)?,
+                SyncAction::RemoveDirectory(path) => self.destination.remove_directory_all(path)?,
+            }
+            self.tracker.entry(action.path());
+        }
+        self.plan.actions.push(action);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sync, sync_parallel, sync_with_progress, SyncAction, SyncCompare, SyncOptions};
+    use crate::{FileSystem, MemoryFileSystem, ParallelOptions, Progress};
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_sync() {
+        let source = MemoryFileSystem::new();
+        source.create_directory_all("/a/b").unwrap();
+        source.write("/a/b/same.txt", b"same").unwrap();
+        source.write("/a/changed.txt", b"new!").unwrap();
+        source.write("/a/skip.log", b"log").unwrap();
+        source.write("/conflict", b"file").unwrap();
+
+        let destination = MemoryFileSystem::new();
+        destination.create_directory_all("/a/b").unwrap();
+        destination.write("/a/changed.txt", b"old!").unwrap();
+        destination.write("/a/keep.log", b"kept").unwrap();
+        destination.create_directory_all("/conflict/x").unwrap();
+        destination.create_directory_all("/gone/deeper").unwrap();
+        destination.write("/gone/deeper/file", b"x").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        destination.write("/a/b/same.txt", b"same").unwrap();
+
+        let options = SyncOptions::new()
+            .with_compare(SyncCompare::Hash)
+            .with_delete(true)
+            .exclude("**/*.log");
+        let plan = sync(&source, &destination, &options).unwrap();
+        assert_eq!(
+            plan.actions(),
+            [
+                SyncAction::CopyFile("/a/changed.txt".to_string(), 4),
+                SyncAction::RemoveDirectory("/conflict".to_string()),
+                SyncAction::CopyFile("/conflict".to_string(), 4),
+                SyncAction::RemoveFile("/gone/deeper/file".to_string()),
+                SyncAction::RemoveDirectory("/gone/deeper".to_string()),
+                SyncAction::RemoveDirectory("/gone".to_string()),
+            ]
+        );
+        assert_eq!(plan.bytes(), 8);
+        assert_eq!(destination.read("/a/changed.txt").unwrap(), b"new!");
+        assert_eq!(destination.read("/conflict").unwrap(), b"file");
+        assert_eq!(destination.read("/a/keep.log").unwrap(), b"kept");
+        assert!(!destination.exists("/a/skip.log").unwrap());
+        assert!(!destination.exists("/gone").unwrap());
+
+        // Size comparison misses same-size edits, modification times catch them.
+        source.write("/a/b/same.txt", b"SAME").unwrap();
+        let size = options.clone().with_compare(SyncCompare::Size);
+        assert!(sync(&source, &destination, &size).unwrap().is_empty());
+        let plan = sync(&source, &destination, &SyncOptions::new()).unwrap();
+        assert_eq!(
+            plan.actions(),
+            [
+                SyncAction::CopyFile("/a/b/same.txt".to_string(), 4),
+                SyncAction::CopyFile("/a/skip.log".to_string(), 3),
+            ]
+        );
+        assert!(sync(&source, &destination, &SyncOptions::new())
+            .unwrap()
+            .is_empty());
+
+        source.write("/a/changed.txt", b"newer").unwrap();
+        let mut updates = Vec::new();
+        let plan = sync_with_progress(
+            &source,
+            &destination,
+            &SyncOptions::new(),
+            &mut |path: &str, progress: Progress| updates.push((path.to_string(), progress)),
+        )
+        .unwrap();
+        assert_eq!(plan.bytes(), 5);
+        assert_eq!(
+            updates.last().unwrap(),
+            &(
+                "/a/changed.txt".to_string(),
+                Progress {
+                    entries: 1,
+                    bytes: 5
+                }
+            )
+        );
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_sync_include() {
+        let source = MemoryFileSystem::new();
+        source.create_directory_all("/a/b").unwrap();
+        source.create_directory_all("/empty").unwrap();
+        source.write("/a/b/table.db", b"rows").unwrap();
+        source.write("/a/notes.txt", b"notes").unwrap();
+        let destination = MemoryFileSystem::new();
+        destination.write("/other.txt", b"other").unwrap();
+        destination.write("/old.db", b"old").unwrap();
+
+        let options = SyncOptions::new()
+            .include("**/*.db")
+            .with_delete(true)
+            .with_dry_run(true);
+        let plan = sync(&source, &destination, &options).unwrap();
+        assert_eq!(
+            plan.actions(),
+            [
+                SyncAction::CreateDirectory("/a".to_string()),
+                SyncAction::CreateDirectory("/a/b".to_string()),
+                SyncAction::CopyFile("/a/b/table.db".to_string(), 4),
+                SyncAction::RemoveFile("/old.db".to_string()),
+            ]
+        );
+        assert!(!destination.exists("/a").unwrap());
+        assert_eq!(
+            sync(&source, &destination, &options.with_dry_run(false)).unwrap(),
+            plan
+        );
+        assert_eq!(destination.read("/a/b/table.db").unwrap(), b"rows");
+        assert!(destination.exists("/other.txt").unwrap());
+        assert!(!destination.exists("/empty").unwrap());
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_sync_parallel() {
+        let source = MemoryFileSystem::new();
+        let destination = MemoryFileSystem::new();
+        source.create_directory_all("/data/nested").unwrap();
+        for index in 0..32 {
+            source
+                .write(&format!("/data/nested/{index:02}.db"), &[1; 64])
+                .unwrap();
+        }
+        source.write("/data/conflict", b"file").unwrap();
+        destination.create_directory_all("/data/conflict").unwrap();
+        destination.write("/data/stale.db", b"old").unwrap();
+
+        let options = SyncOptions::new().with_delete(true);
+        let expected = sync(&source, &destination, &options.clone().with_dry_run(true)).unwrap();
+        let parallel = ParallelOptions::new().with_workers(4);
+        let (plan, report) = sync_parallel(&source, &destination, &options, &parallel).unwrap();
+        assert_eq!(plan, expected);
+        assert!(report.is_ok());
+        assert_eq!(report.progress().entries, plan.actions().len() as u64);
+        assert_eq!(report.progress().bytes, plan.bytes());
+        assert_eq!(destination.read("/data/conflict").unwrap(), b"file");
+        assert_eq!(destination.read("/data/nested/31.db").unwrap(), vec![1; 64]);
+        assert!(!destination.exists("/data/stale.db").unwrap());
+        assert!(sync(&source, &destination, &options).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,435 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::utility::child_path;
+use crate::{FileSystem, FileSystemError, FileSystemResult, FileType, SymlinkPolicy};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+/// Marker separating the name a temporary file was derived from and its unique suffix.
+pub const TEMP_MARKER: &str = ".minql-tmp-";
+
+/// How old a temporary file must be before it's collected by default.
+const DEFAULT_MIN_AGE: Duration = Duration::from_hours(1);
+
+/// Source of unique temporary file names within this process.
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Derive a unique temporary file name from `name`, following the naming convention
+/// [`collect_temp_files`] recognizes.
+///
+/// Names are `{name}.minql-tmp-{pid}-{counter}`, unique within this process and across processes
+/// running at the same time.
+///
+/// ```rust
+/// use minql_vfs::{is_temp_name, temp_name};
+///
+/// let name = temp_name("sort-run");
+/// assert!(name.starts_with("sort-run.minql-tmp-"));
+/// assert!(is_temp_name(&name));
+/// assert_ne!(temp_name("sort-run"), name);
+/// ```
+#[must_use]
+pub fn temp_name(name: &str) -> String {
+    format!(
+        "{name}{TEMP_MARKER}{}-{}",
+        std::process::id(),
+        NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Check if a file name follows the temporary file naming convention of [`temp_name`].
+#[must_use]
+pub fn is_temp_name(name: &str) -> bool {
+    let Some((_, suffix)) = name.rsplit_once(TEMP_MARKER) else {
+        return false;
+    };
+    let Some((pid, counter)) = suffix.split_once('-') else {
+        return false;
+    };
+    [pid, counter]
+        .iter()
+        .all(|part| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit()))
+}
+
+/// Options of a [`collect_temp_files`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TempGcOptions {
+    min_age: Duration,
+    dry_run: bool,
+}
+
+impl Default for TempGcOptions {
+    fn default() -> Self {
+        TempGcOptions {
+            min_age: DEFAULT_MIN_AGE,
+            dry_run: false,
+        }
+    }
+}
+
+impl TempGcOptions {
+    /// Create options collecting temporary files untouched for an hour.
+    #[must_use]
+    pub fn new() -> TempGcOptions {
+        TempGcOptions::default()
+    }
+
+    /// Set how long a temporary file must have gone unmodified before it's collected.
+    ///
+    /// Temporary files of running processes are told apart from orphaned ones by age alone, so
+    /// this must comfortably exceed the time any process keeps a temporary file around.
+    #[must_use]
+    pub fn with_min_age(mut self, min_age: Duration) -> TempGcOptions {
+        self.min_age = min_age;
+        self
+    }
+
+    /// Set whether to only report orphaned temporary files, leaving them in place.
+    #[must_use]
+    pub fn with_dry_run(mut self, dry_run: bool) -> TempGcOptions {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// How long a temporary file must have gone unmodified before it's collected.
+    #[must_use]
+    pub fn min_age(&self) -> Duration {
+        self.min_age
+    }
+
+    /// Whether only a report is made.
+    #[must_use]
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+}
+
+/// Orphaned temporary file found by [`collect_temp_files`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TempFile {
+    path: String,
+    size: u64,
+    age: Duration,
+}
+
+impl TempFile {
+    /// Path of the file
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    /// Size of the file in bytes
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+    /// Time since the file was last modified
+    #[must_use]
+    pub fn age(&self) -> Duration {
+        self.age
+    }
+}
+
+/// Temporary files found by [`collect_temp_files`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TempGcReport {
+    orphaned: Vec<TempFile>,
+    retained: u64,
+}
+
+impl TempGcReport {
+    /// Orphaned temporary files, which were removed unless this was a dry run.
+    #[must_use]
+    pub fn orphaned(&self) -> &[TempFile] {
+        &self.orphaned
+    }
+
+    /// Temporary files left in place because they're too recent to be orphaned.
+    #[must_use]
+    pub fn retained(&self) -> u64 {
+        self.retained
+    }
+
+    /// Total size of the orphaned temporary files in bytes.
+    #[must_use]
+    pub fn bytes(&self) -> u64 {
+        self.orphaned.iter().map(TempFile::size).sum()
+    }
+}
+
+/// Remove orphaned temporary files below `root`.
+///
+/// Every file named following the convention of [`temp_name`] which hasn't been modified for at
+/// least [`TempGcOptions::min_age`] is considered orphaned by a crashed process and removed.
+/// Symbolic links aren't followed, and files removed concurrently are skipped. Files whose
+/// modification time is unknown are always retained.
+///
+/// Returns the orphaned files, or in a dry run the files that would be removed. Run it at
+/// startup, or periodically through a [`TempCollector`].
+///
+/// ```rust
+/// use minql_vfs::{collect_temp_files, temp_name, FileSystem, MemoryFileSystem, TempGcOptions};
+/// use std::time::Duration;
+///
+/// let fs = MemoryFileSystem::new();
+/// fs.create_directory("/spill").unwrap();
+/// let spill = format!("/spill/{}", temp_name("join"));
+/// fs.write(&spill, b"partition").unwrap();
+/// fs.write("/spill/table.db", b"rows").unwrap();
+///
+/// let options = TempGcOptions::new().with_min_age(Duration::ZERO);
+/// let report = collect_temp_files(&fs, "/", &options.with_dry_run(true)).unwrap();
+/// assert_eq!(report.orphaned()[0].path(), spill);
+/// assert_eq!(report.bytes(), 9);
+/// assert!(fs.exists(&spill).unwrap());
+///
+/// assert_eq!(collect_temp_files(&fs, "/", &options).unwrap().orphaned().len(), 1);
+/// assert_eq!(fs.list_directory("/spill").unwrap(), vec!["table.db"]);
+/// ```
+pub fn collect_temp_files<F: FileSystem + ?Sized>(
+    fs: &F,
+    root: &str,
+    options: &TempGcOptions,
+) -> FileSystemResult<TempGcReport> {
+    let mut report = TempGcReport::default();
+    let now = SystemTime::now();
+    let mut pending = vec![root.to_string()];
+    while let Some(directory) = pending.pop() {
+        let mut children = match fs.list_directory(&directory) {
+            Err(FileSystemError::PathMissing) if directory != root => continue,
+            children => children?,
+        };
+        children.sort();
+        for name in children.iter().rev() {
+            let path = child_path(&directory, name);
+            match fs.file_type(&path, SymlinkPolicy::NoFollow) {
+                Ok(FileType::Directory) => pending.push(path),
+                Ok(FileType::File) if is_temp_name(name) => {
+                    collect(fs, path, now, options, &mut report)?;
+                }
+                Ok(_) | Err(FileSystemError::PathMissing) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    report.orphaned.sort_by(|a, b| a.path.cmp(&b.path));
+    tracing::debug!(
+        orphaned = report.orphaned.len(),
+        bytes = report.bytes(),
+        retained = report.retained,
+        dry_run = options.dry_run,
+        "Collected temporary files"
+    );
+    Ok(report)
+}
+
+/// Collect a single temporary file if it's old enough.
+fn collect<F: FileSystem + ?Sized>(
+    fs: &F,
+    path: String,
+    now: SystemTime,
+    options: &TempGcOptions,
+    report: &mut TempGcReport,
+) -> FileSystemResult<()> {
+    let age = match fs.modified(&path) {
+        Ok(modified) => now.duration_since(modified).unwrap_or_default(),
+        Err(FileSystemError::PathMissing) => return Ok(()),
+        Err(FileSystemError::UnsupportedOperation) => {
+            report.retained += 1;
+            return Ok(());
+        }
+        Err(err) => return Err(err),
+    };
+    if age < options.min_age {
+        report.retained += 1;
+        return Ok(());
+    }
+    let size = match fs.filesize(&path) {
+        Err(FileSystemError::PathMissing) => return Ok(()),
+        size => size?,
+    };
+    if !options.dry_run {
+        match fs.remove_file(&path) {
+            Err(FileSystemError::PathMissing) => return Ok(()),
+            result => result?,
+        }
+    }
+    report.orphaned.push(TempFile { path, size, age });
+    Ok(())
+}
+
+/// Periodic Temporary File Collector
+///
+/// Runs [`collect_temp_files`] on a background thread, once when spawned and then at a fixed
+/// interval until dropped. Failed collections are logged and retried at the next interval.
+///
+/// ```rust
+/// use minql_vfs::{temp_name, FileSystem, MemoryFileSystem, TempCollector, TempGcOptions};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let fs = Arc::new(MemoryFileSystem::new());
+/// fs.write(&format!("/{}", temp_name("sort")), b"run").unwrap();
+///
+/// let options = TempGcOptions::new().with_min_age(Duration::ZERO);
+/// let collector = TempCollector::spawn(fs.clone(), "/", options, Duration::from_secs(60));
+/// while collector.collections() == 0 {
+///     std::thread::sleep(Duration::from_millis(1));
+/// }
+/// assert_eq!(collector.last_report().unwrap().bytes(), 3);
+/// assert!(fs.list_directory("/").unwrap().is_empty());
+/// ```
+#[derive(Debug)]
+pub struct TempCollector {
+    shared: Arc<CollectorShared>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Results shared between a [`TempCollector`] and its thread.
+#[derive(Debug, Default)]
+struct CollectorShared {
+    collections: AtomicU64,
+    last_report: Mutex<Option<TempGcReport>>,
+}
+
+impl TempCollector {
+    /// Spawn a thread collecting orphaned temporary files below `root` every `interval`.
+    pub fn spawn<F: FileSystem>(
+        fs: Arc<F>,
+        root: &str,
+        options: TempGcOptions,
+        interval: Duration,
+    ) -> TempCollector {
+        let shared = Arc::new(CollectorShared::default());
+        let (stop, stopped) = channel();
+        let thread = {
+            let shared = shared.clone();
+            let root = root.to_string();
+            std::thread::spawn(move || loop {
+                match collect_temp_files(fs.as_ref(), &root, &options) {
+                    Ok(report) => *shared.last_report.lock().expect("Poisoned Lock") = Some(report),
+                    Err(err) => tracing::warn!(?err, root, "Failed to collect temporary files"),
+                }
+                shared.collections.fetch_add(1, Ordering::AcqRel);
+                if stopped.recv_timeout(interval) != Err(RecvTimeoutError::Timeout) {
+                    break;
+                }
+            })
+        };
+        TempCollector {
+            shared,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Collections run so far, whether they succeeded or not.
+    #[must_use]
+    pub fn collections(&self) -> u64 {
+        self.shared.collections.load(Ordering::Acquire)
+    }
+
+    /// Report of the last successful collection.
+    #[must_use]
+    pub fn last_report(&self) -> Option<TempGcReport> {
+        self.shared
+            .last_report
+            .lock()
+            .expect("Poisoned Lock")
+            .clone()
+    }
+}
+
+impl Drop for TempCollector {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                tracing::warn!("Temporary file collector panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        collect_temp_files, is_temp_name, temp_name, FileSystem, MemoryFileSystem, TempCollector,
+        TempFile, TempGcOptions,
+    };
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_collect_temp_files() {
+        assert!(is_temp_name("run.minql-tmp-12-0"));
+        assert!(!is_temp_name("run.minql-tmp-12"));
+        assert!(!is_temp_name("run.minql-tmp-12-x"));
+        assert!(!is_temp_name("run.tmp"));
+
+        let fs = MemoryFileSystem::new();
+        fs.create_directory_all("/data/spill/deep").unwrap();
+        let orphans = [
+            format!("/data/spill/{}", temp_name("hash-join")),
+            format!("/data/spill/deep/{}", temp_name("sort")),
+        ];
+        for orphan in &orphans {
+            fs.write(orphan, b"spill").unwrap();
+        }
+        fs.write("/data/table.db", b"rows").unwrap();
+        fs.create_symlink("/data/spill", "/link").unwrap();
+
+        // Recent temporary files are retained
+        let options = TempGcOptions::new();
+        assert_eq!(options.min_age(), Duration::from_hours(1));
+        let report = collect_temp_files(&fs, "/", &options).unwrap();
+        assert!(report.orphaned().is_empty());
+        assert_eq!(report.retained(), 2);
+
+        // A dry run reports without removing
+        let options = options.with_min_age(Duration::ZERO);
+        let report = collect_temp_files(&fs, "/", &options.with_dry_run(true)).unwrap();
+        let paths: Vec<_> = report.orphaned().iter().map(TempFile::path).collect();
+        let mut expected: Vec<_> = orphans.iter().map(String::as_str).collect();
+        expected.sort_unstable();
+        assert_eq!(paths, expected);
+        assert_eq!(report.bytes(), 10);
+        assert!(orphans.iter().all(|orphan| fs.exists(orphan).unwrap()));
+
+        // Scoped to a directory and removing
+        let report = collect_temp_files(&fs, "/data/spill/deep", &options).unwrap();
+        assert_eq!(report.orphaned()[0].path(), orphans[1]);
+        assert!(!fs.exists(&orphans[1]).unwrap());
+        assert!(fs.exists(&orphans[0]).unwrap());
+        assert!(collect_temp_files(&fs, "/missing", &options).is_err());
+
+        // Periodically in the background
+        let fs = Arc::new(fs);
+        let collector = TempCollector::spawn(fs.clone(), "/", options, Duration::from_millis(10));
+        while collector.collections() < 2 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        drop(collector);
+        assert!(!fs.exists(&orphans[0]).unwrap());
+        assert_eq!(fs.read("/data/table.db").unwrap(), b"rows");
+    }
+}
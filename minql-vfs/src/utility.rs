@@ -0,0 +1,148 @@
+//
+// Copyright 2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{BatchOperation, FileSystem, FileSystemError, FileSystemResult, OpenOptions};
+use std::io::Write;
+
+/// Lexically normalize a `FileSystem` path into its segments.
+///
+/// Empty and `.` segments are dropped and `..` removes the preceding segment. A `..` that would
+/// climb above the root is rejected with [`FileSystemError::InvalidPath`] rather than clamped.
+pub(crate) fn normalize_segments(path: &str) -> FileSystemResult<Vec<&str>> {
+    let mut segments = Vec::new();
+    for segment in path.split(['/', '\\']) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if segments.pop().is_none() {
+                    return Err(FileSystemError::invalid_path(path));
+                }
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Ok(segments)
+}
+
+/// Lexically normalize a `FileSystem` path into an absolute `/` separated path.
+pub(crate) fn normalize_path(path: &str) -> FileSystemResult<String> {
+    Ok(join_segments(&normalize_segments(path)?))
+}
+
+/// Join path segments into an absolute `/` separated path.
+pub(crate) fn join_segments<S: AsRef<str>>(segments: &[S]) -> String {
+    let mut path = String::new();
+    for segment in segments {
+        path.push('/');
+        path.push_str(segment.as_ref());
+    }
+    if path.is_empty() {
+        path.push('/');
+    }
+    path
+}
+
+/// Join a directory path and the name of one of its children.
+pub(crate) fn child_path(directory: &str, name: &str) -> String {
+    format!("{}/{name}", directory.trim_end_matches('/'))
+}
+
+/// Stream the contents of the file at `src` into a new file at `dst`, returning the bytes copied.
+pub(crate) fn copy_file<F: FileSystem + ?Sized>(
+    fs: &F,
+    src: &str,
+    dst: &str,
+) -> FileSystemResult<u64> {
+    let mut reader = fs.open_with(src, OpenOptions::new().read(true))?;
+    let options = OpenOptions::new().write(true).create_new(true);
+    let mut writer = fs.open_with(dst, options)?;
+    let copied = std::io::copy(&mut reader, &mut writer).map_err(FileSystemError::io_error)?;
+    writer.flush().map_err(FileSystemError::io_error)?;
+    Ok(copied)
+}
+
+/// Move a file by copying it and removing the original, replacing any file at `dst`.
+pub(crate) fn move_file<F: FileSystem + ?Sized>(
+    fs: &F,
+    src: &str,
+    dst: &str,
+) -> FileSystemResult<()> {
+    if !fs.is_file(src)? {
+        return Err(if fs.exists(src)? {
+            FileSystemError::InvalidOperation
+        } else {
+            FileSystemError::PathMissing
+        });
+    }
+    if fs.is_directory(dst)? {
+        return Err(FileSystemError::InvalidOperation);
+    }
+    if fs.is_file(dst)? {
+        fs.remove_file(dst)?;
+    }
+    copy_file(fs, src, dst)?;
+    fs.remove_file(src)
+}
+
+/// Apply a single batch operation through the regular `FileSystem` methods.
+pub(crate) fn apply_operation<F: FileSystem + ?Sized>(
+    fs: &F,
+    operation: &BatchOperation,
+) -> FileSystemResult<()> {
+    match operation {
+        BatchOperation::CreateDirectory { path } => fs.create_directory(path),
+        BatchOperation::CreateDirectoryAll { path } => fs.create_directory_all(path),
+        BatchOperation::Write { path, contents } => fs.write(path, contents),
+        BatchOperation::Rename { from, to } => fs.rename(from, to),
+        BatchOperation::RemoveFile { path } => fs.remove_file(path),
+        BatchOperation::RemoveDirectory { path } => fs.remove_directory(path),
+    }
+}
+
+/// Split a glob pattern into segments for [`match_segments`].
+pub(crate) fn glob_segments(pattern: &str) -> Vec<String> {
+    pattern
+        .split(['/', '\\'])
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Match path segments against glob pattern segments.
+pub(crate) fn match_segments(pattern: &[String], segments: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => segments.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=segments.len()).any(|skip| match_segments(rest, &segments[skip..]))
+        }
+        Some((first, rest)) => match segments.split_first() {
+            Some((segment, remaining)) => {
+                match_glob(first.as_bytes(), segment.as_bytes()) && match_segments(rest, remaining)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Match a single segment against a glob pattern of `*` and `?` wildcards.
+fn match_glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| match_glob(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && match_glob(rest, &text[1..]),
+        Some((byte, rest)) => text.first() == Some(byte) && match_glob(rest, &text[1..]),
+    }
+}
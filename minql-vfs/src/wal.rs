@@ -0,0 +1,553 @@
+//
+// Copyright 2019-2024 Hans W. Uhlig. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{FileHandle, FileSystem, FileSystemError, FileSystemResult, OpenOptions};
+
+/// Size of the length and checksum header framing every record.
+const RECORD_HEADER_SIZE: usize = 8;
+
+/// File extension of log segments.
+const SEGMENT_EXTENSION: &str = ".wal";
+
+/// Position of a record within a [`WriteAheadLog`], ordered by position in the log.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Lsn {
+    /// Segment holding the record
+    pub segment: u64,
+    /// Byte offset of the record within its segment
+    pub offset: u64,
+}
+
+/// When a [`WriteAheadLog`] flushes appended records to storage.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WalSyncPolicy {
+    /// Sync after every appended record.
+    Always,
+    /// Sync once at least this many bytes have been appended since the last sync.
+    Batch(u64),
+    /// Only sync when [`WriteAheadLog::sync`] is called or a segment is rotated.
+    Manual,
+}
+
+/// Configuration of a [`WriteAheadLog`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct WalOptions {
+    segment_size: u64,
+    sync_policy: WalSyncPolicy,
+}
+
+impl WalOptions {
+    /// Default size at which segments are rotated.
+    pub const DEFAULT_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+    /// Create the default options, rotating at 64 MiB and syncing every record.
+    #[must_use]
+    pub fn new() -> WalOptions {
+        WalOptions {
+            segment_size: Self::DEFAULT_SEGMENT_SIZE,
+            sync_policy: WalSyncPolicy::Always,
+        }
+    }
+
+    /// Rotate to a new segment once the current one would exceed `segment_size` bytes.
+    #[must_use]
+    pub fn with_segment_size(mut self, segment_size: u64) -> WalOptions {
+        self.segment_size = segment_size.max(RECORD_HEADER_SIZE as u64 + 1);
+        self
+    }
+
+    /// Set when appended records are synced to storage.
+    #[must_use]
+    pub fn with_sync_policy(mut self, sync_policy: WalSyncPolicy) -> WalOptions {
+        self.sync_policy = sync_policy;
+        self
+    }
+}
+
+impl Default for WalOptions {
+    fn default() -> Self {
+        WalOptions::new()
+    }
+}
+
+/// Write-Ahead Log
+///
+/// An append-only log of opaque records stored in numbered segment files within a directory of
+/// any [`FileSystem`]. Each record is framed by its length and a CRC32 covering both the length
+/// and the payload. Segments are rotated once they reach the configured size.
+///
+/// Opening a log recovers from a crash by scanning the newest segment and truncating it after the
+/// last intact record, discarding any torn write. Records are replayed with
+/// [`WriteAheadLog::iter`].
+///
+/// ```rust
+/// use minql_vfs::{MemoryFileSystem, WalOptions, WriteAheadLog};
+///
+/// let fs = MemoryFileSystem::new();
+/// let mut wal = WriteAheadLog::open(fs.clone(), "/wal", WalOptions::new()).unwrap();
+/// wal.append(b"first").unwrap();
+/// wal.append(b"second").unwrap();
+/// drop(wal);
+///
+/// let wal = WriteAheadLog::open(fs, "/wal", WalOptions::new()).unwrap();
+/// let records = wal
+///     .iter(Default::default())
+///     .map(|record| record.unwrap().1)
+///     .collect::<Vec<_>>();
+/// assert_eq!(records, vec![b"first".to_vec(), b"second".to_vec()]);
+/// ```
+#[derive(Debug)]
+pub struct WriteAheadLog<F: FileSystem> {
+    fs: F,
+    directory: String,
+    options: WalOptions,
+    segment: u64,
+    handle: F::FileHandle,
+    offset: u64,
+    unsynced: u64,
+}
+
+impl<F: FileSystem> WriteAheadLog<F> {
+    /// Open or create the log stored in `directory`, recovering from any torn final write.
+    pub fn open(fs: F, directory: &str, options: WalOptions) -> FileSystemResult<WriteAheadLog<F>> {
+        let directory = directory.trim_end_matches('/').to_string();
+        fs.create_directory_all(&directory)?;
+        let segments = list_segments(&fs, &directory)?;
+        let segment = segments.last().copied().unwrap_or_default();
+
+        // Find the end of the last intact record in the newest segment.
+        let mut offset = 0;
+        if segments.is_empty() {
+            fs.create_file(&segment_path(&directory, segment))?;
+        } else {
+            let mut reader = WalIterator::new(&fs, &directory, vec![segment], 0);
+            for record in &mut reader {
+                record?;
+            }
+            offset = reader.offset;
+        }
+
+        let mut handle = fs.open_with(
+            &segment_path(&directory, segment),
+            OpenOptions::new().read(true).write(true),
+        )?;
+        if handle.get_size()? > offset {
+            tracing::warn!(segment, offset, "Truncating torn write-ahead log tail");
+            handle.set_size(offset)?;
+            handle.sync_data()?;
+        }
+        Ok(WriteAheadLog {
+            fs,
+            directory,
+            options,
+            segment,
+            handle,
+            offset,
+            unsynced: 0,
+        })
+    }
+
+    /// Append a record, returning its position in the log.
+    #[tracing::instrument(level = "trace", skip(payload))]
+    pub fn append(&mut self, payload: &[u8]) -> FileSystemResult<Lsn> {
+        let length = u32::try_from(payload.len()).map_err(FileSystemError::wrap_error)?;
+        let frame_size = (RECORD_HEADER_SIZE + payload.len()) as u64;
+        if self.offset > 0 && self.offset + frame_size > self.options.segment_size {
+            self.rotate()?;
+        }
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&length.to_le_bytes());
+        hasher.update(payload);
+        let mut frame = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len());
+        frame.extend_from_slice(&length.to_le_bytes());
+        frame.extend_from_slice(&hasher.finalize().to_le_bytes());
+        frame.extend_from_slice(payload);
+        let mut written = 0;
+        while written < frame.len() {
+            match self
+                .handle
+                .write_to_offset(self.offset + written as u64, &frame[written..])?
+            {
+                0 => return Err(FileSystemError::InvalidOperation),
+                count => written += count,
+            }
+        }
+
+        let lsn = Lsn {
+            segment: self.segment,
+            offset: self.offset,
+        };
+        self.offset += frame_size;
+        self.unsynced += frame_size;
+        match self.options.sync_policy {
+            WalSyncPolicy::Always => self.sync()?,
+            WalSyncPolicy::Batch(bytes) if self.unsynced >= bytes => self.sync()?,
+            WalSyncPolicy::Batch(_) | WalSyncPolicy::Manual => {}
+        }
+        Ok(lsn)
+    }
+
+    /// Flush every appended record to storage.
+    #[tracing::instrument(level = "trace")]
+    pub fn sync(&mut self) -> FileSystemResult<()> {
+        if self.unsynced > 0 {
+            self.handle.sync_data()?;
+            self.unsynced = 0;
+        }
+        Ok(())
+    }
+
+    /// Position the next appended record will be written at.
+    #[must_use]
+    pub fn next_lsn(&self) -> Lsn {
+        Lsn {
+            segment: self.segment,
+            offset: self.offset,
+        }
+    }
+
+    /// Ids of every segment in the log, oldest first.
+    pub fn segments(&self) -> FileSystemResult<Vec<u64>> {
+        list_segments(&self.fs, &self.directory)
+    }
+
+    /// Delete every segment older than the one holding `lsn`, once its records are no longer
+    /// needed for recovery.
+    #[tracing::instrument(level = "trace")]
+    pub fn remove_segments_before(&mut self, lsn: Lsn) -> FileSystemResult<()> {
+        for segment in self.segments()? {
+            if segment >= lsn.segment.min(self.segment) {
+                break;
+            }
+            self.fs
+                .remove_file(&segment_path(&self.directory, segment))?;
+        }
+        Ok(())
+    }
+
+    /// Copy the segments holding the records from `from` up to `to` into `directory` of
+    /// `target`, as a log whose records keep their positions and which ends at `to`.
+    ///
+    /// `to` should be the position of a record or the end of the log, such as
+    /// [`next_lsn`](WriteAheadLog::next_lsn). Records before `from` in its segment are copied
+    /// too. Returns the number of bytes copied.
+    #[tracing::instrument(level = "trace", skip(target))]
+    pub fn copy_to<G: FileSystem>(
+        &self,
+        target: &G,
+        directory: &str,
+        from: Lsn,
+        to: Lsn,
+    ) -> FileSystemResult<u64> {
+        let directory = directory.trim_end_matches('/');
+        target.create_directory_all(directory)?;
+        let mut copied = 0;
+        for segment in self.segments()? {
+            if segment < from.segment || segment > to.segment {
+                continue;
+            }
+            let mut bytes = self.fs.read(&segment_path(&self.directory, segment))?;
+            if segment == to.segment {
+                bytes.truncate(usize::try_from(to.offset).unwrap_or(usize::MAX));
+            }
+            target.write(&segment_path(directory, segment), &bytes)?;
+            copied += bytes.len() as u64;
+        }
+        Ok(copied)
+    }
+
+    /// Replay every record at or after `from`, oldest first.
+    ///
+    /// Iteration ends quietly at a torn record in the newest segment, while a damaged record in
+    /// any older segment is reported as an error.
+    pub fn iter(&self, from: Lsn) -> WalIterator<'_, F> {
+        let segments = list_segments(&self.fs, &self.directory)
+            .map(|segments| {
+                segments
+                    .into_iter()
+                    .filter(|segment| *segment >= from.segment)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let offset = segments
+            .first()
+            .filter(|segment| **segment == from.segment)
+            .map_or(0, |_| from.offset);
+        WalIterator::new(&self.fs, &self.directory, segments, offset)
+    }
+
+    /// Sync the current segment and start writing to the next one.
+    fn rotate(&mut self) -> FileSystemResult<()> {
+        self.unsynced = 1;
+        self.sync()?;
+        let segment = self.segment + 1;
+        self.handle = self
+            .fs
+            .create_file(&segment_path(&self.directory, segment))?;
+        self.segment = segment;
+        self.offset = 0;
+        Ok(())
+    }
+}
+
+/// Iterator replaying records from a [`WriteAheadLog`].
+#[derive(Debug)]
+pub struct WalIterator<'a, F: FileSystem> {
+    fs: &'a F,
+    directory: String,
+    segments: Vec<u64>,
+    index: usize,
+    handle: Option<F::FileHandle>,
+    offset: u64,
+}
+
+impl<'a, F: FileSystem> WalIterator<'a, F> {
+    fn new(fs: &'a F, directory: &str, segments: Vec<u64>, offset: u64) -> WalIterator<'a, F> {
+        WalIterator {
+            fs,
+            directory: directory.to_string(),
+            segments,
+            index: 0,
+            handle: None,
+            offset,
+        }
+    }
+
+    /// Read the record at the current offset, or `None` if there isn't an intact one.
+    fn read_record(&mut self) -> FileSystemResult<Option<Vec<u8>>> {
+        let segment = self.segments[self.index];
+        let handle = match &mut self.handle {
+            Some(handle) => handle,
+            handle => handle.insert(self.fs.open_file(&segment_path(&self.directory, segment))?),
+        };
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        if !read_exact_at(handle, self.offset, &mut header)? {
+            return Ok(None);
+        }
+        let length = u32::from_le_bytes(header[..4].try_into().expect("Record Length"));
+        let checksum = u32::from_le_bytes(header[4..].try_into().expect("Record Checksum"));
+        let available = handle.get_size()?.saturating_sub(self.offset) - RECORD_HEADER_SIZE as u64;
+        if u64::from(length) > available {
+            return Ok(None);
+        }
+        let mut payload = vec![0; length as usize];
+        if !read_exact_at(
+            handle,
+            self.offset + RECORD_HEADER_SIZE as u64,
+            &mut payload,
+        )? {
+            return Ok(None);
+        }
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header[..4]);
+        hasher.update(&payload);
+        if hasher.finalize() != checksum {
+            return Ok(None);
+        }
+        Ok(Some(payload))
+    }
+}
+
+impl<F: FileSystem> Iterator for WalIterator<'_, F> {
+    type Item = FileSystemResult<(Lsn, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.segments.len() {
+            let lsn = Lsn {
+                segment: self.segments[self.index],
+                offset: self.offset,
+            };
+            match self.read_record() {
+                Ok(Some(payload)) => {
+                    self.offset += (RECORD_HEADER_SIZE + payload.len()) as u64;
+                    return Some(Ok((lsn, payload)));
+                }
+                Ok(None) if self.index + 1 == self.segments.len() => return None,
+                Ok(None) => {
+                    // Older segments are synced before rotating, so anything left over past the
+                    // last record is damage rather than a torn write.
+                    let size = match self.handle.as_ref().map(FileHandle::get_size) {
+                        Some(Ok(size)) => size,
+                        Some(Err(err)) => return Some(Err(err)),
+                        None => 0,
+                    };
+                    if size > self.offset {
+                        self.index = self.segments.len();
+                        return Some(Err(FileSystemError::CorruptData {
+                            path: segment_path(&self.directory, lsn.segment),
+                            offset: lsn.offset,
+                        }));
+                    }
+                    self.index += 1;
+                    self.handle = None;
+                    self.offset = 0;
+                }
+                Err(err) => {
+                    self.index = self.segments.len();
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Fill `buffer` from `offset`, returning `false` if the file ends first.
+fn read_exact_at<H: FileHandle>(
+    handle: &mut H,
+    offset: u64,
+    buffer: &mut [u8],
+) -> FileSystemResult<bool> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match handle.read_at_offset(offset + filled as u64, &mut buffer[filled..])? {
+            0 => return Ok(false),
+            read => filled += read,
+        }
+    }
+    Ok(true)
+}
+
+fn segment_path(directory: &str, segment: u64) -> String {
+    format!("{directory}/{segment:020}{SEGMENT_EXTENSION}")
+}
+
+fn list_segments<F: FileSystem>(fs: &F, directory: &str) -> FileSystemResult<Vec<u64>> {
+    let mut segments = fs
+        .list_directory(directory)?
+        .iter()
+        .filter_map(|name| name.strip_suffix(SEGMENT_EXTENSION)?.parse().ok())
+        .collect::<Vec<u64>>();
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        FileHandle, FileSystem, FileSystemError, Lsn, MemoryFileSystem, WalOptions, WalSyncPolicy,
+        WriteAheadLog,
+    };
+
+    fn replay(wal: &WriteAheadLog<MemoryFileSystem>, from: Lsn) -> Vec<Vec<u8>> {
+        wal.iter(from).map(|record| record.unwrap().1).collect()
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_wal_append_and_replay() {
+        let fs = MemoryFileSystem::new();
+        let options = WalOptions::new()
+            .with_segment_size(64)
+            .with_sync_policy(WalSyncPolicy::Batch(32));
+        let mut wal = WriteAheadLog::open(fs.clone(), "/data/wal", options).unwrap();
+        let mut lsns = Vec::new();
+        for i in 0..10u8 {
+            lsns.push(wal.append(&[i; 20]).unwrap());
+        }
+        wal.sync().unwrap();
+
+        // 28 byte frames rotate every two records
+        assert_eq!(wal.segments().unwrap(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(
+            lsns[3],
+            Lsn {
+                segment: 1,
+                offset: 28
+            }
+        );
+        assert_eq!(replay(&wal, Lsn::default()).len(), 10);
+        assert_eq!(replay(&wal, lsns[5])[0], vec![5; 20]);
+        drop(wal);
+
+        // Reopening resumes appending after the last record
+        let mut wal = WriteAheadLog::open(fs.clone(), "/data/wal", options).unwrap();
+        assert_eq!(
+            wal.next_lsn(),
+            Lsn {
+                segment: 4,
+                offset: 56
+            }
+        );
+        wal.append(b"after restart").unwrap();
+        let records = replay(&wal, Lsn::default());
+        assert_eq!(records.len(), 11);
+        assert_eq!(records[10], b"after restart");
+
+        let end = wal.next_lsn();
+        wal.append(b"after copy").unwrap();
+        assert_eq!(
+            wal.copy_to(&fs, "/copy", lsns[5], end).unwrap(),
+            56 + 56 + 77
+        );
+        let copy = WriteAheadLog::open(fs.clone(), "/copy", options).unwrap();
+        assert_eq!(copy.next_lsn(), end);
+        let records = replay(&copy, lsns[5]);
+        assert_eq!(records.len(), 6);
+        assert_eq!(records[5], b"after restart");
+
+        wal.remove_segments_before(lsns[6]).unwrap();
+        assert_eq!(wal.segments().unwrap(), vec![3, 4, 5]);
+        assert_eq!(replay(&wal, Lsn::default())[0], vec![6; 20]);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_wal_recovery() {
+        let fs = MemoryFileSystem::new();
+        let mut wal = WriteAheadLog::open(fs.clone(), "/wal", WalOptions::new()).unwrap();
+        wal.append(b"committed").unwrap();
+        let torn = wal.append(b"torn record").unwrap();
+        drop(wal);
+
+        // Simulate a crash part way through writing the second record
+        let path = "/wal/00000000000000000000.wal";
+        let mut file = fs.open_file(path).unwrap();
+        file.set_size(torn.offset + 12).unwrap();
+        drop(file);
+
+        let mut wal = WriteAheadLog::open(fs.clone(), "/wal", WalOptions::new()).unwrap();
+        assert_eq!(wal.next_lsn(), torn);
+        assert_eq!(fs.filesize(path).unwrap(), torn.offset);
+        wal.append(b"recovered").unwrap();
+        assert_eq!(
+            replay(&wal, Lsn::default()),
+            vec![b"committed".to_vec(), b"recovered".to_vec()]
+        );
+
+        // Once a later segment exists, damage to an older one is reported rather than skipped
+        drop(wal);
+        let options = WalOptions::new().with_segment_size(32);
+        let mut wal = WriteAheadLog::open(fs.clone(), "/wal", options).unwrap();
+        assert_eq!(
+            wal.append(b"rotated").unwrap(),
+            Lsn {
+                segment: 1,
+                offset: 0
+            }
+        );
+        let mut file = fs.open_file(path).unwrap();
+        file.write_to_offset(10, b"X").unwrap();
+        drop(file);
+        let results = wal.iter(Lsn::default()).collect::<Vec<_>>();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            results[0],
+            Err(FileSystemError::CorruptData { offset: 0, .. })
+        ));
+    }
+}